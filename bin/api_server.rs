@@ -1,16 +1,78 @@
 use axum::{
-    extract::{Json, Path, Query},
-    http::{StatusCode, Method},
-    response::Json as ResponseJson,
+    extract::{Extension, Json, MatchedPath, Path, Query, Request},
+    http::{header, HeaderMap, StatusCode, Method},
+    middleware::{self, Next},
+    response::{IntoResponse, Json as ResponseJson, Response},
     routing::{get, post},
     Router,
 };
-use tower_http::cors::{CorsLayer, Any};
+use base64::Engine;
+use futures::{StreamExt, TryStreamExt};
+use sha2::Digest;
+use tower_http::cors::{AllowOrigin, CorsLayer, Any};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use finternet_sdk::{FinternetClient, AssetMetadata, TransactionRecord};
+use finternet_sdk::action_log::{ActionLogPage, RotationPolicy};
+use finternet_sdk::api_keys::{ApiKeyRecord, ApiKeyStore, ApiScope};
+use finternet_sdk::alerts::{AlertDirection, AlertTriggered, BalanceThreshold};
+use finternet_sdk::amount_format::AmountFormatter;
+use finternet_sdk::capabilities::Capabilities;
+use finternet_sdk::counterparty::CounterpartyReport;
+use finternet_sdk::dry_run::{DryRunOutcome, DRY_RUN_HEADER};
+use finternet_sdk::deposit::{DepositAddress, DepositDetected, DepositManager, DEFAULT_MIN_CONFIRMATIONS};
+use finternet_sdk::error_code::FinternetError;
+use finternet_sdk::export::{self, ExportFormat};
+use finternet_sdk::intent::{IntentError, IntentStore};
+use finternet_sdk::gate::{GateRequirement, GateResult};
+use finternet_sdk::history::{HistoryCompleteness, HistoryFilter, COMPLETENESS_HEADER};
+#[cfg(feature = "sqlite")]
+use finternet_sdk::history::TruncatedHistory;
+use finternet_sdk::identity_index::IdentityIndex;
+use finternet_sdk::issuance::IssuedAssetPage;
+use finternet_sdk::freshness::Freshness;
+use finternet_sdk::ledger::{TokenAccountDetail, TokenAccountState};
+#[cfg(feature = "sqlite")]
+use finternet_sdk::local_ledger::{LedgerRetention, LocalLedger};
+use finternet_sdk::organization::{OrgRole, Organization};
+use finternet_sdk::owned_assets::{OwnedAsset, OwnedAssetSort, OwnedAssetsQuery};
+use finternet_sdk::metadata::{DirectoryPublisher, IpfsPinningPublisher};
+use finternet_sdk::mint_registry::MintNetworkError;
+use finternet_sdk::namespace::{namespaced, DEFAULT_NAMESPACE};
+use finternet_sdk::asset_policy::{AssetPolicy, AssetPolicyViolation};
+use finternet_sdk::policy::PolicyViolation;
+use finternet_sdk::portfolio::{Portfolio, UsdPrices};
+use finternet_sdk::precheck::RecipientPrecheck;
+use finternet_sdk::qr::QrCode;
+use finternet_sdk::resources::ResourceLimits;
+use finternet_sdk::response_signing::{SIGNATURE_HEADER, SIGNER_HEADER};
+use finternet_sdk::redaction::Redactions;
+use finternet_sdk::reconcile::{self, ExpectedPayment, ReconciliationReport};
+use finternet_sdk::remediation;
+use finternet_sdk::rpc_options::RpcCallOptions;
+use finternet_sdk::preview::{PreviewWarning, TransactionPreview};
+use finternet_sdk::payment_queue::{PaymentJob, PaymentQueue, PaymentQueueConfig, QueueMetrics, QueuedPayment};
+use finternet_sdk::solana_pay::{PaymentRequest, PaymentRequestStatus, PaymentRequestStore, TransferRequest};
+#[cfg(feature = "sqlite")]
+use finternet_sdk::statement::YearMonth;
+use finternet_sdk::token_account::UndecodableAccount;
+use finternet_sdk::usage::{self, KeyUsage, QuotaError, UsageLedger, UsageQuota};
+use finternet_sdk::tokenization_draft::{AssetSpec, TokenizationDraft, DEFAULT_DRAFT_VALID_FOR_SECS};
+use finternet_sdk::server_config::{ServerConfig, WebhookTargets, NON_RELOADABLE};
+use finternet_sdk::sweep::{SweepOptions, SweepReport};
+use finternet_sdk::state_store::{FileStateStore, MemoryStateStore, StateStore};
+#[cfg(feature = "sqlite")]
+use finternet_sdk::state_store::SqliteStateStore;
+use finternet_sdk::webhook::{self, DeadLetter, DeadLetterQueue, RetryPolicy};
+use finternet_sdk::validation::{self, validate_asset_spec, validate_payment, ValidationError, Violation};
+use finternet_sdk::{
+    deserialize_amount_text, usdc, AccountStatus, Amount, AssetType, Currency, FinternetClient, FinternetConfig, AssetMetadata, MetadataBackend, RecipientError,
+    TransactionRecord,
+};
 use solana_sdk::{pubkey::Pubkey, signature::Keypair, signer::Signer};
+use std::path::PathBuf;
 use std::str::FromStr;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 #[derive(Debug, Serialize, Deserialize)]
 struct TokenizeAssetRequest {
@@ -27,12 +89,47 @@ struct TokenizeAssetResponse {
     metadata: AssetMetadata,
 }
 
+/// A `/api/tokenize-asset` body, plus how long the draft may be committed for
+#[derive(Debug, Serialize, Deserialize)]
+struct PrepareTokenizationRequest {
+    #[serde(flatten)]
+    asset: TokenizeAssetRequest,
+    /// Default: a week
+    valid_for_hours: Option<u64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct DiscardTokenizationResponse {
+    id: String,
+    discarded: bool,
+}
+
+/// `/api/capabilities`: the client's `Capabilities` and what only the server knows
+#[derive(Debug, Serialize, Deserialize)]
+struct CapabilitiesResponse {
+    #[serde(flatten)]
+    capabilities: Capabilities,
+    /// Kinds of webhook with a target configured, e.g. `deposit`
+    webhooks: Vec<String>,
+    api_keys_required: bool,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 struct SendPaymentRequest {
     to: String,
-    amount: f64,
+    /// Whole units as a number or a string such as `"1,000.50"`
+    #[serde(deserialize_with = "deserialize_amount_text")]
+    amount: String,
     memo: Option<String>,
     token_mint: Option<String>,
+    /// Commission/fee cuts routed atomically alongside the primary payment
+    splits: Option<Vec<PaymentSplit>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct PaymentSplit {
+    to: String,
+    bps: u16,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -40,15 +137,101 @@ struct SendPaymentResponse {
     signature: String,
     from: String,
     to: String,
-    amount: f64,
+    /// Exact decimal text in whole units, e.g. `"100.5"`
+    amount: String,
+    base_units: u64,
+}
+
+/// What `/api/preview-payment` and `/api/preview-tokenize` found, with amounts formatted
+#[derive(Debug, Serialize, Deserialize)]
+struct PreviewResponse {
+    would_succeed: bool,
+    fee_lamports: u64,
+    fee: String,
+    balance_changes: Vec<BalanceChangeView>,
+    created_accounts: Vec<CreatedAccountView>,
+    simulation_error: Option<String>,
+    logs: Vec<String>,
+    warnings: Vec<PreviewWarning>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct BalanceChangeView {
+    account: String,
+    owner: String,
+    /// `None` for SOL
+    mint: Option<String>,
+    before: u64,
+    after: u64,
+    /// Signed change in whole units with the currency, e.g. `+24.50 USDC`
+    change: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct CreatedAccountView {
+    address: String,
+    program: String,
+    rent_lamports: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct TokenizePreviewResponse {
+    /// Placeholder; the real tokenization mints under a fresh address
+    mint: String,
+    metadata: AssetMetadata,
+    preview: PreviewResponse,
+}
+
+impl From<TransactionPreview> for PreviewResponse {
+    fn from(preview: TransactionPreview) -> Self {
+        let balance_changes = preview
+            .balance_changes
+            .iter()
+            .map(|change| BalanceChangeView {
+                account: change.account.to_string(),
+                owner: change.owner.to_string(),
+                mint: change.mint.map(|mint| mint.to_string()),
+                before: change.before,
+                after: change.after,
+                change: format!(
+                    "{}{}",
+                    if change.change() > 0 { "+" } else { "" },
+                    get_client().amount_format().format_signed(&change.currency(), change.change())
+                ),
+            })
+            .collect();
+        Self {
+            would_succeed: preview.would_succeed(),
+            fee_lamports: preview.fee,
+            fee: get_client().amount_format().format(&Currency::Sol, preview.fee),
+            balance_changes,
+            created_accounts: preview
+                .created_accounts
+                .iter()
+                .map(|account| CreatedAccountView {
+                    address: account.address.to_string(),
+                    program: account.program.to_string(),
+                    rent_lamports: account.lamports,
+                })
+                .collect(),
+            simulation_error: preview.simulation_error,
+            logs: preview.logs,
+            warnings: preview.warnings,
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 struct WalletInfoResponse {
     public_key: String,
+    account_status: AccountStatus,
     sol_balance: f64,
     usdc_balance: f64,
     token_accounts: Vec<TokenAccountInfo>,
+    /// Token accounts left out because they could not be decoded
+    undecodable_accounts: Vec<UndecodableAccount>,
+    /// Unix time the balances were read from the chain
+    as_of: u64,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -56,6 +239,13 @@ struct TokenAccountInfo {
     mint: String,
     balance: u64,
     decimals: u8,
+    /// `balance` in whole units with the currency
+    display_balance: String,
+    address: String,
+    state: TokenAccountState,
+    delegate: Option<String>,
+    delegated_amount: u64,
+    close_authority: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -68,49 +258,723 @@ struct AssetInfo {
 #[derive(Debug, Serialize, Deserialize)]
 struct ErrorResponse {
     error: String,
+    /// Catalogue `code` and `name`, e.g. `E1001` and `insufficient_token_balance`, shared with
+    /// the CLI
+    #[serde(flatten)]
+    kind: FinternetError,
+    message: String,
+    /// Every field that failed validation, for `invalid_input` errors
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    violations: Vec<Violation>,
+    /// What to do about a known Solana or RPC failure
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    hint: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SubmitPaymentIntentRequest {
+    /// JSON-serialized `PaymentIntent`, exactly as signed
     message: String,
+    /// Off-chain message signature by the intent's user
+    signature: String,
 }
 
 // Global client instance (in production, you'd want proper state management)
-static mut CLIENT: Option<FinternetClient> = None;
-static mut WALLET: Option<Keypair> = None;
+static CLIENT: OnceLock<Arc<FinternetClient>> = OnceLock::new();
+static WALLET: OnceLock<Keypair> = OnceLock::new();
+static INTENTS: OnceLock<IntentStore> = OnceLock::new();
+#[cfg(feature = "sqlite")]
+static LEDGER: OnceLock<LocalLedger> = OnceLock::new();
+static PAYMENT_REQUESTS: OnceLock<PaymentRequestStore> = OnceLock::new();
+static PAYMENT_QUEUE: OnceLock<Arc<PaymentQueue>> = OnceLock::new();
+static DEAD_LETTERS: OnceLock<DeadLetterQueue> = OnceLock::new();
+static DEPOSITS: OnceLock<DepositManager> = OnceLock::new();
+static HTTP: OnceLock<reqwest::Client> = OnceLock::new();
+static API_KEYS: OnceLock<ApiKeyStore> = OnceLock::new();
+/// Usage and quotas per API key, kept whenever API keys are on
+static USAGE: OnceLock<UsageLedger> = OnceLock::new();
+static ORG: OnceLock<Pubkey> = OnceLock::new();
+static ORG_STATE: Mutex<Option<(Instant, Organization)>> = Mutex::new(None);
+/// `FINTERNET_SERVER_CONFIG`, the settings file re-read on SIGHUP or when it changes
+static SERVER_CONFIG_PATH: OnceLock<PathBuf> = OnceLock::new();
+static SERVER_CONFIG: RwLock<Option<Arc<ServerConfig>>> = RwLock::new(None);
+static CONFIG_STATUS: Mutex<Option<ConfigStatus>> = Mutex::new(None);
+/// Requests counted per caller in the current minute, keyed by a hash of their bearer token
+static RATE_WINDOWS: Mutex<BTreeMap<String, (u64, u32)>> = Mutex::new(BTreeMap::new());
+
+/// How long the server trusts its copy of the organization's membership
+const ORG_STATE_TTL: Duration = Duration::from_secs(30);
+
+/// When set, unsigned `/api/send-payment` calls are refused and only signed intents are honored
+/// Whether JSON responses carry `X-Finternet-Signature` and `X-Finternet-Signer`
+fn sign_responses() -> bool {
+    matches!(std::env::var("FINTERNET_SIGN_RESPONSES").as_deref(), Ok("1") | Ok("true"))
+}
+
+/// Whether `FINTERNET_READ_ONLY` limits the server to routes that change nothing
+fn read_only() -> bool {
+    matches!(std::env::var("FINTERNET_READ_ONLY").as_deref(), Ok("1") | Ok("true"))
+}
+
+fn require_signed_intents() -> bool {
+    matches!(
+        std::env::var("FINTERNET_REQUIRE_SIGNED_INTENTS").as_deref(),
+        Ok("1") | Ok("true")
+    )
+}
+
+/// Per-user, per-mint daily cap in base units for intent-driven payments
+fn intent_daily_limit() -> u64 {
+    std::env::var("FINTERNET_DAILY_LIMIT")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(1_000_000_000)
+}
+
+fn error_response(status: StatusCode, error: &str, message: String) -> (StatusCode, ResponseJson<ErrorResponse>) {
+    let kind = FinternetError::of_message(&message).unwrap_or_else(|| kind_for_status(status));
+    kind_response(status, kind, error, message)
+}
+
+fn kind_response(
+    status: StatusCode,
+    kind: FinternetError,
+    error: &str,
+    message: String,
+) -> (StatusCode, ResponseJson<ErrorResponse>) {
+    (status, ResponseJson(error_body(kind, error, message)))
+}
+
+fn error_body(kind: FinternetError, error: &str, message: String) -> ErrorResponse {
+    ErrorResponse {
+        error: error.to_string(),
+        kind,
+        hint: remediation::remediation_for_message(&message),
+        message,
+        violations: Vec::new(),
+    }
+}
+
+/// A failed SDK call, classified through the catalogue
+///
+/// A route's catch-all 500 or 502 gives way to the kind's own status, so an insufficient
+/// balance is a 422 and a rate-limited RPC node a 503 rather than a server error.
+fn sdk_error_response(status: StatusCode, error: &str, e: &anyhow::Error) -> (StatusCode, ResponseJson<ErrorResponse>) {
+    let kind = FinternetError::of(e);
+    let status = match status {
+        StatusCode::INTERNAL_SERVER_ERROR | StatusCode::BAD_GATEWAY if kind != FinternetError::Internal => {
+            StatusCode::from_u16(kind.http_status()).unwrap_or(status)
+        }
+        _ => status,
+    };
+    let (status, ResponseJson(mut body)) = kind_response(status, kind, error, e.to_string());
+    body.hint = remediation::remediation(e);
+    (status, ResponseJson(body))
+}
+
+/// The catalogue entry for a route's own error, where nothing more specific is known
+fn kind_for_status(status: StatusCode) -> FinternetError {
+    match status {
+        StatusCode::BAD_REQUEST | StatusCode::UNPROCESSABLE_ENTITY => FinternetError::InvalidInput,
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => FinternetError::NotAuthorized,
+        StatusCode::NOT_FOUND => FinternetError::NotFound,
+        StatusCode::TOO_MANY_REQUESTS => FinternetError::RpcRateLimited,
+        StatusCode::BAD_GATEWAY | StatusCode::SERVICE_UNAVAILABLE | StatusCode::GATEWAY_TIMEOUT => {
+            FinternetError::RpcUnavailable
+        }
+        _ => FinternetError::Internal,
+    }
+}
+
+/// 400 listing every violation, if there are any
+fn validation_response(violations: Vec<Violation>) -> Result<(), (StatusCode, ResponseJson<ErrorResponse>)> {
+    match validation::check(violations) {
+        Ok(()) => Ok(()),
+        Err(e) => Err(match e.downcast::<ValidationError>() {
+            Ok(err) => validation_error_response(err),
+            Err(e) => error_response(StatusCode::BAD_REQUEST, "invalid_input", e.to_string()),
+        }),
+    }
+}
+
+fn validation_error_response(err: ValidationError) -> (StatusCode, ResponseJson<ErrorResponse>) {
+    let (status, ResponseJson(mut body)) =
+        kind_response(StatusCode::BAD_REQUEST, FinternetError::from(&err), err.code(), err.to_string());
+    body.violations = err.violations;
+    (status, ResponseJson(body))
+}
+
+/// A send simulated in dry-run mode; never a 2xx, so it cannot pass for a completed one
+fn dry_run_response(outcome: &DryRunOutcome) -> (StatusCode, ResponseJson<ErrorResponse>) {
+    kind_response(StatusCode::CONFLICT, outcome.into(), outcome.code(), outcome.to_string())
+}
+
+fn intent_error_response(err: &IntentError) -> (StatusCode, ResponseJson<ErrorResponse>) {
+    let status = match err {
+        IntentError::Malformed(_) => StatusCode::BAD_REQUEST,
+        IntentError::InvalidSignature => StatusCode::UNAUTHORIZED,
+        IntentError::LimitExceeded { .. } => StatusCode::FORBIDDEN,
+        IntentError::ReplayedNonce { .. } => StatusCode::CONFLICT,
+        IntentError::Expired { .. } => StatusCode::UNPROCESSABLE_ENTITY,
+    };
+    kind_response(status, err.into(), err.code(), err.to_string())
+}
 
 async fn initialize_client() -> Result<(), Box<dyn std::error::Error>> {
-    unsafe {
-        CLIENT = Some(FinternetClient::new_devnet());
-        WALLET = Some(FinternetClient::load_default_wallet()?);
+    let outbox_path = std::env::var("FINTERNET_OUTBOX_PATH")
+        .unwrap_or_else(|_| ".finternet/outbox.json".to_string());
+    let metadata_uri_base = std::env::var("FINTERNET_METADATA_URI_BASE").ok();
+    let metadata_backend = std::env::var("FINTERNET_METADATA_BACKEND")
+        .ok()
+        .map(|backend| backend.parse::<MetadataBackend>())
+        .transpose()?;
+    // Display fields such as `display_amount` follow FINTERNET_LOCALE, FINTERNET_CURRENCY_DISPLAY,
+    // and FINTERNET_PRECISION; raw base-unit fields never change
+    let mut amount_format = match std::env::var("FINTERNET_LOCALE") {
+        Ok(locale) => AmountFormatter::new(locale.parse()?),
+        Err(_) => AmountFormatter::default(),
+    };
+    if let Ok(display) = std::env::var("FINTERNET_CURRENCY_DISPLAY") {
+        amount_format = amount_format.with_display(display.parse()?);
+    }
+    if let Ok(precision) = std::env::var("FINTERNET_PRECISION") {
+        amount_format = amount_format.with_precision(precision.parse()?);
+    }
+    // The server keeps an audit trail unless FINTERNET_ACTION_LOG is set to an empty value
+    let action_log_path = std::env::var("FINTERNET_ACTION_LOG")
+        .unwrap_or_else(|_| ".finternet/actions.jsonl".to_string());
+    // A comma-separated FINTERNET_RPC_CANDIDATES list replaces the default devnet endpoint with
+    // the fastest healthy one at startup
+    let rpc_candidates: Vec<String> = std::env::var("FINTERNET_RPC_CANDIDATES")
+        .map(|urls| urls.split(',').map(str::trim).filter(|url| !url.is_empty()).map(String::from).collect())
+        .unwrap_or_default();
+    // Ledger entries are written under FINTERNET_NAMESPACE; a comma-separated
+    // FINTERNET_TRUSTED_NAMESPACES list is read as well, and FINTERNET_LEGACY_ENTRIES=true reads
+    // un-prefixed entries from before namespaces
+    let namespace = std::env::var("FINTERNET_NAMESPACE").unwrap_or_else(|_| DEFAULT_NAMESPACE.to_string());
+    namespaced(&namespace, "")?;
+    // The action log rotates at FINTERNET_ACTION_LOG_MAX_BYTES (default 64 MiB) and keeps
+    // FINTERNET_ACTION_LOG_SEGMENTS rotated segments (default 8); settled outbox entries are
+    // archived after FINTERNET_OUTBOX_ARCHIVE_DAYS (default 30); 0 turns either off
+    let env_number = |name: &str, default: u64| -> Result<u64, Box<dyn std::error::Error>> {
+        match std::env::var(name) {
+            Ok(value) => Ok(value.parse().map_err(|e| format!("{}: {}", name, e))?),
+            Err(_) => Ok(default),
+        }
+    };
+    let action_log_max_bytes = env_number("FINTERNET_ACTION_LOG_MAX_BYTES", 64 * 1024 * 1024)?;
+    let resource_limits = ResourceLimits {
+        action_log_rotation: Some(RotationPolicy {
+            max_bytes: action_log_max_bytes,
+            retained_segments: env_number("FINTERNET_ACTION_LOG_SEGMENTS", 8)? as usize,
+        })
+        .filter(|_| action_log_max_bytes > 0),
+        outbox_archive_after_days: Some(env_number("FINTERNET_OUTBOX_ARCHIVE_DAYS", 30)?).filter(|days| *days > 0),
+        ..ResourceLimits::default()
+    };
+    let trusted_namespaces: Vec<String> = std::env::var("FINTERNET_TRUSTED_NAMESPACES")
+        .map(|names| names.split(',').map(str::trim).filter(|name| !name.is_empty()).map(String::from).collect())
+        .unwrap_or_default();
+    let config = FinternetConfig {
+        outbox_path: Some(PathBuf::from(outbox_path)),
+        auto_select: !rpc_candidates.is_empty(),
+        rpc_candidates,
+        action_log_path: Some(action_log_path).filter(|path| !path.is_empty()).map(PathBuf::from),
+        metadata_uri_base: metadata_uri_base.clone(),
+        metadata_backend,
+        dry_run: matches!(std::env::var("FINTERNET_DRY_RUN").as_deref(), Ok("1") | Ok("true")),
+        amount_format,
+        namespace,
+        trusted_namespaces,
+        accept_legacy_entries: matches!(std::env::var("FINTERNET_LEGACY_ENTRIES").as_deref(), Ok("1") | Ok("true")),
+        resource_limits,
+        ..FinternetConfig::default()
+    };
+    let mut client = FinternetClient::new(config);
+
+    // Spending limits, CORS origins, rate limits, and webhook targets come from
+    // FINTERNET_SERVER_CONFIG when set and are re-read while the server runs
+    let server_config = match std::env::var("FINTERNET_SERVER_CONFIG") {
+        Ok(path) => {
+            let path = PathBuf::from(path);
+            let server_config = ServerConfig::load(&path)?;
+            client = client.with_spending_policy(server_config.spending_policy()?)?;
+            let _ = SERVER_CONFIG_PATH.set(path);
+            Some(server_config)
+        }
+        Err(_) => None,
+    };
+
+    // Pinning to IPFS wins over a local directory; with neither, the URI base alone is used
+    if let Ok(jwt) = std::env::var("FINTERNET_PINATA_JWT") {
+        client = client.with_metadata_publisher(IpfsPinningPublisher {
+            api_url: "https://api.pinata.cloud/pinning/pinJSONToIPFS".to_string(),
+            jwt,
+            gateway_url: std::env::var("FINTERNET_IPFS_GATEWAY")
+                .unwrap_or_else(|_| "https://gateway.pinata.cloud".to_string()),
+        });
+    } else if let (Ok(dir), Some(base)) = (std::env::var("FINTERNET_METADATA_DIR"), &metadata_uri_base) {
+        client = client.with_metadata_publisher(DirectoryPublisher::new(dir, base));
+    }
+
+    // Comma-separated asset policy files, enforced on every payment of their mints
+    if let Ok(paths) = std::env::var("FINTERNET_ASSET_POLICIES") {
+        for path in paths.split(',').map(str::trim).filter(|path| !path.is_empty()) {
+            client = client.with_asset_policy(AssetPolicy::load(&PathBuf::from(path))?);
+        }
+    }
+
+    // Resolve anything a previous run broadcast but never saw confirmed
+    match client.recover_outbox().await {
+        Ok(recovery) if !recovery.is_empty() => println!("♻️  Outbox recovered: {:?}", recovery),
+        Ok(_) => {}
+        Err(e) => eprintln!("Outbox recovery failed: {}", e),
+    }
+
+    // One store shared by every registry when FINTERNET_STATE_STORE names one (`memory`, a
+    // SQLite database ending in `.db` or `.sqlite`, or a JSON file path); otherwise each
+    // registry keeps its own file
+    let shared: Option<Arc<dyn StateStore>> = match std::env::var("FINTERNET_STATE_STORE") {
+        Ok(store) if store == "memory" => Some(Arc::new(MemoryStateStore::new())),
+        #[cfg(feature = "sqlite")]
+        Ok(path) if is_sqlite_path(&path) => Some(Arc::new(SqliteStateStore::open(&PathBuf::from(path))?)),
+        #[cfg(not(feature = "sqlite"))]
+        Ok(path) if is_sqlite_path(&path) => {
+            return Err(format!("FINTERNET_STATE_STORE={} is a SQLite database, which needs a build with --features sqlite", path).into())
+        }
+        Ok(path) => Some(Arc::new(FileStateStore::open(&PathBuf::from(path))?)),
+        Err(_) => None,
+    };
+    let registry_path = |var: &str, default: &str| PathBuf::from(std::env::var(var).unwrap_or_else(|_| default.to_string()));
+
+    let _ = INTENTS.set(match &shared {
+        Some(store) => IntentStore::new(Arc::clone(store)),
+        None => IntentStore::open(&registry_path("FINTERNET_INTENT_STORE", ".finternet/intents.json"))?,
+    });
+
+    let _ = PAYMENT_REQUESTS.set(match &shared {
+        Some(store) => PaymentRequestStore::new(Arc::clone(store)),
+        None => PaymentRequestStore::open(&registry_path("FINTERNET_PAYMENT_REQUESTS", ".finternet/payment-requests.json"))?,
+    });
+
+    // The queue worker keeps at most FINTERNET_QUEUE_MAX_IN_FLIGHT payments unsettled (default 4)
+    let queue_config = PaymentQueueConfig {
+        max_in_flight: env_number("FINTERNET_QUEUE_MAX_IN_FLIGHT", 4)?.max(1) as usize,
+        ..PaymentQueueConfig::default()
+    };
+    let _ = PAYMENT_QUEUE.set(Arc::new(match &shared {
+        Some(store) => PaymentQueue::new(Arc::clone(store), queue_config)?,
+        None => PaymentQueue::open(&registry_path("FINTERNET_PAYMENT_QUEUE", ".finternet/payment-queue.json"), queue_config)?,
+    }));
+
+    // With a key store configured, every route except /health needs a key with the right scope
+    if let Ok(api_keys_path) = std::env::var("FINTERNET_API_KEYS") {
+        let _ = API_KEYS.set(match &shared {
+            Some(store) => ApiKeyStore::new(Arc::clone(store)),
+            None => ApiKeyStore::open(&PathBuf::from(api_keys_path))?,
+        });
+        let _ = USAGE.set(match &shared {
+            Some(store) => UsageLedger::new(Arc::clone(store)),
+            None => UsageLedger::open(&registry_path("FINTERNET_USAGE_STORE", ".finternet/usage.json"))?,
+        });
+    }
+    // Keys then also need their member's role in this organization for mutating routes
+    if let Ok(org) = std::env::var("FINTERNET_ORG") {
+        let _ = ORG.set(Pubkey::from_str(&org).map_err(|e| anyhow::anyhow!("Invalid FINTERNET_ORG: {}", e))?);
+    }
+
+    let _ = DEAD_LETTERS.set(match &shared {
+        Some(store) => DeadLetterQueue::new(Arc::clone(store)),
+        None => DeadLetterQueue::open(&registry_path("FINTERNET_DEAD_LETTERS", ".finternet/dead-letters.json"))?,
+    });
+
+    // Serve history from a local ledger kept fresh in the background
+    #[cfg(feature = "sqlite")]
+    if let Ok(ledger_path) = std::env::var("FINTERNET_LEDGER_DB") {
+        let _ = LEDGER.set(LocalLedger::open(&PathBuf::from(ledger_path))?);
+    }
+    #[cfg(not(feature = "sqlite"))]
+    if std::env::var_os("FINTERNET_LEDGER_DB").is_some() {
+        return Err("FINTERNET_LEDGER_DB needs a build with --features sqlite".into());
+    }
+
+    let wallet = match server_config.as_ref().and_then(|config| config.wallet_path.as_deref()) {
+        Some(path) => FinternetClient::load_wallet_from_file(path)?,
+        None => FinternetClient::load_default_wallet()?,
+    };
+    // Deposit addresses are derived from the server wallet and credited in USDC
+    let _ = DEPOSITS.set(match &shared {
+        Some(store) => DepositManager::new(Arc::clone(store), &wallet, usdc::devnet_mint())?,
+        None => DepositManager::open(
+            &registry_path("FINTERNET_DEPOSITS", ".finternet/deposits.json"),
+            &wallet,
+            usdc::devnet_mint(),
+        )?,
+    });
+
+    let _ = CLIENT.set(Arc::new(client));
+    let _ = WALLET.set(wallet);
+    if let (Some(server_config), Some(path)) = (server_config, SERVER_CONFIG_PATH.get()) {
+        install_server_config(path, server_config);
     }
     Ok(())
 }
 
-fn get_client() -> &'static FinternetClient {
-    unsafe { CLIENT.as_ref().expect("Client not initialized") }
+/// Seconds between background ledger syncs, from `FINTERNET_LEDGER_SYNC_SECS`
+/// Newest records the local ledger keeps per owner, from `FINTERNET_LEDGER_MAX_RECORDS`
+#[cfg(feature = "sqlite")]
+fn ledger_retention() -> LedgerRetention {
+    LedgerRetention {
+        max_records_per_owner: std::env::var("FINTERNET_LEDGER_MAX_RECORDS").ok().and_then(|v| v.parse().ok()),
+    }
+}
+
+#[cfg(feature = "sqlite")]
+fn ledger_sync_interval() -> u64 {
+    std::env::var("FINTERNET_LEDGER_SYNC_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(60)
+}
+
+/// Sync and compact the ledger in the background, then re-check its unfinalized records
+///
+/// Records whose transaction left the chain are dropped and POSTed to
+/// `FINTERNET_INVALIDATION_WEBHOOK` if set.
+/// History and its completeness from the synced ledger, when one is configured
+#[cfg(feature = "sqlite")]
+fn ledger_history(owner: &Pubkey, limit: usize) -> Option<anyhow::Result<(Vec<TransactionRecord>, HistoryCompleteness)>> {
+    let ledger = LEDGER.get()?;
+    Some(ledger.query_history(owner, limit, 0).and_then(|history| Ok((history, ledger.completeness(owner)?))))
+}
+
+#[cfg(not(feature = "sqlite"))]
+fn ledger_history(_owner: &Pubkey, _limit: usize) -> Option<anyhow::Result<(Vec<TransactionRecord>, HistoryCompleteness)>> {
+    None
+}
+
+#[cfg(feature = "sqlite")]
+async fn run_ledger_sync(ledger: &'static LocalLedger) {
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(ledger_sync_interval()));
+    loop {
+        interval.tick().await;
+        let owner = get_wallet().pubkey();
+        match get_client().sync_local_ledger(ledger, &owner).await {
+            Ok(report) if report.new_signatures > 0 => {
+                println!("🔄 Ledger synced: {} new records", report.new_records)
+            }
+            Ok(_) => {}
+            Err(e) => eprintln!("Ledger sync failed: {}", e),
+        }
+        match ledger.compact(&ledger_retention()) {
+            Ok(compaction) if compaction.records_dropped > 0 => {
+                println!("🗜️  Ledger compacted: {} old records dropped", compaction.records_dropped)
+            }
+            Ok(_) => {}
+            Err(e) => eprintln!("Ledger compaction failed: {}", e),
+        }
+        let invalidated = match get_client().verify_ledger_finality(ledger, &owner).await {
+            Ok(report) => report.invalidated,
+            Err(e) => {
+                eprintln!("Finality check failed: {}", e);
+                continue;
+            }
+        };
+        let webhook_url = webhook_target(|targets| &targets.invalidation, "FINTERNET_INVALIDATION_WEBHOOK");
+        for record in invalidated {
+            println!("❌ Transaction {} left the chain; removed {} ledger records", record.signature, record.records.len());
+            if let Some(url) = &webhook_url {
+                let payload = serde_json::json!({
+                    "event": "record_invalidated",
+                    "owner": record.owner.to_string(),
+                    "signature": record.signature.to_string(),
+                    "records": record.records,
+                    "detected_at": record.detected_at,
+                });
+                tokio::spawn(dispatch_webhook("record_invalidated", url.clone(), payload));
+            }
+        }
+    }
+}
+
+/// Which settings file is in effect, for `/health`
+#[derive(Debug, Clone, Serialize)]
+struct ConfigStatus {
+    path: PathBuf,
+    /// 1 for the file read at startup, one more for every reload applied since
+    generation: u64,
+    loaded_at: u64,
+    /// Read only at startup; changing them needs a restart
+    non_reloadable: &'static [&'static str],
+    /// Why the latest reload was rejected; cleared by the next one that succeeds
+    last_error: Option<ConfigReloadError>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct ConfigReloadError {
+    at: u64,
+    code: String,
+    message: String,
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs()
+}
+
+/// The settings file in effect, if the server was started with one
+fn server_config() -> Option<Arc<ServerConfig>> {
+    SERVER_CONFIG.read().ok().and_then(|config| config.clone())
+}
+
+fn install_server_config(path: &std::path::Path, config: ServerConfig) {
+    if let Ok(mut running) = SERVER_CONFIG.write() {
+        *running = Some(Arc::new(config));
+    }
+    if let Ok(mut status) = CONFIG_STATUS.lock() {
+        let generation = status.as_ref().map_or(0, |status| status.generation) + 1;
+        *status = Some(ConfigStatus {
+            path: path.to_path_buf(),
+            generation,
+            loaded_at: unix_now(),
+            non_reloadable: NON_RELOADABLE,
+            last_error: None,
+        });
+    }
+}
+
+/// Whether FINTERNET_STATE_STORE names a SQLite database rather than a JSON file
+fn is_sqlite_path(path: &str) -> bool {
+    matches!(
+        std::path::Path::new(path).extension().and_then(|extension| extension.to_str()),
+        Some("db" | "sqlite" | "sqlite3")
+    )
+}
+
+/// Where to POST a webhook: the settings file's target, else the environment variable
+fn webhook_target(target: fn(&WebhookTargets) -> &Option<String>, env: &str) -> Option<String> {
+    server_config()
+        .and_then(|config| target(&config.webhooks).clone())
+        .or_else(|| std::env::var(env).ok())
+        .filter(|url| !url.is_empty())
+}
+
+/// Re-read the settings file and swap it in, or keep the running one if it is invalid
+///
+/// The whole file is validated before anything changes. Settings that cannot change without a
+/// restart keep their running values, with a warning.
+fn reload_server_config(path: &std::path::Path) {
+    let applied = ServerConfig::load(path).and_then(|mut config| {
+        let policy = config.spending_policy()?;
+        let running = server_config().map(|config| (*config).clone()).unwrap_or_default();
+        for setting in config.ignored_changes(&running) {
+            eprintln!("⚠️  {} changed in {}; it only takes effect after a restart", setting, path.display());
+        }
+        config.bind_address = running.bind_address;
+        config.wallet_path = running.wallet_path;
+        get_client().replace_spending_policy(policy)?;
+        Ok(config)
+    });
+    match applied {
+        Ok(config) => {
+            install_server_config(path, config);
+            println!("🔁 Reloaded server config from {}", path.display());
+        }
+        Err(e) => {
+            eprintln!("Config reload rejected, keeping the running config: {}", e);
+            let code = e
+                .downcast_ref::<finternet_sdk::server_config::ServerConfigError>()
+                .map_or("config_reload_failed", |invalid| invalid.code());
+            if let Ok(mut status) = CONFIG_STATUS.lock() {
+                if let Some(status) = status.as_mut() {
+                    status.last_error = Some(ConfigReloadError { at: unix_now(), code: code.to_string(), message: e.to_string() });
+                }
+            }
+        }
+    }
+}
+
+#[cfg(unix)]
+type Hangup = Option<tokio::signal::unix::Signal>;
+#[cfg(not(unix))]
+type Hangup = Option<()>;
+
+#[cfg(unix)]
+fn listen_for_hangup() -> Hangup {
+    tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup())
+        .inspect_err(|e| eprintln!("Could not listen for SIGHUP, relying on file polling: {}", e))
+        .ok()
+}
+
+#[cfg(not(unix))]
+fn listen_for_hangup() -> Hangup {
+    None
+}
+
+async fn next_hangup(hangup: &mut Hangup) {
+    #[cfg(unix)]
+    if let Some(signal) = hangup {
+        signal.recv().await;
+        return;
+    }
+    let _ = hangup;
+    std::future::pending::<()>().await
+}
+
+/// Reload the settings file on SIGHUP, and whenever its modification time changes, checked
+/// every `FINTERNET_CONFIG_POLL_SECS` (default 5)
+async fn run_config_watcher(path: &'static std::path::Path) {
+    let modified = || std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok();
+    let poll = std::env::var("FINTERNET_CONFIG_POLL_SECS").ok().and_then(|v| v.parse().ok()).unwrap_or(5);
+    let mut interval = tokio::time::interval(Duration::from_secs(poll));
+    let mut hangup = listen_for_hangup();
+    let mut last_modified = modified();
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {
+                let now = modified();
+                if now == last_modified {
+                    continue;
+                }
+                last_modified = now;
+            }
+            _ = next_hangup(&mut hangup) => {
+                last_modified = modified();
+            }
+        }
+        reload_server_config(path);
+    }
+}
+
+/// Refuse callers past the settings file's `rate_limit` for the current minute with `429`
+async fn rate_limit(request: Request, next: Next) -> Response {
+    let Some(limit) = server_config().and_then(|config| config.rate_limit) else {
+        return next.run(request).await;
+    };
+    if request.uri().path() == "/health" {
+        return next.run(request).await;
+    }
+    let caller: String = sha2::Sha256::digest(bearer_token(request.headers()).as_bytes()).iter().map(|b| format!("{:02x}", b)).collect();
+    let now = unix_now();
+    let minute = now / 60;
+    let allowed = match RATE_WINDOWS.lock() {
+        Ok(mut windows) => {
+            windows.retain(|_, (window, _)| *window == minute);
+            let (_, count) = windows.entry(caller).or_insert((minute, 0));
+            *count += 1;
+            *count <= limit.requests_per_minute
+        }
+        Err(_) => true,
+    };
+    if allowed {
+        return next.run(request).await;
+    }
+    let retry_after = 60 - now % 60;
+    let mut response = error_response(
+        StatusCode::TOO_MANY_REQUESTS,
+        "rate_limited",
+        format!("More than {} requests this minute; retry in {}s", limit.requests_per_minute, retry_after),
+    )
+    .into_response();
+    if let Ok(value) = header::HeaderValue::from_str(&retry_after.to_string()) {
+        response.headers_mut().insert(header::RETRY_AFTER, value);
+    }
+    response
+}
+
+/// Whether a browser on `origin` may call the API: any origin unless the settings file lists them
+fn origin_allowed(origin: &header::HeaderValue) -> bool {
+    match server_config() {
+        Some(config) if !config.cors_origins.is_empty() => {
+            config.cors_origins.iter().any(|allowed| allowed.as_bytes() == origin.as_bytes())
+        }
+        _ => true,
+    }
+}
+
+fn get_client() -> &'static Arc<FinternetClient> {
+    CLIENT.get().expect("Client not initialized")
 }
 
 fn get_wallet() -> &'static Keypair {
-    unsafe { WALLET.as_ref().expect("Wallet not initialized") }
+    WALLET.get().expect("Wallet not initialized")
 }
 
 async fn health_check() -> ResponseJson<serde_json::Value> {
+    let config = CONFIG_STATUS.lock().ok().and_then(|status| status.clone());
     ResponseJson(serde_json::json!({
         "status": "healthy",
         "service": "Finternet SDK API",
-        "version": "0.1.0"
+        "version": "0.1.0",
+        "dry_run": get_client().is_dry_run(),
+        "read_only": read_only(),
+        "config": config,
     }))
 }
 
+/// What this deployment supports, for frontends to feature-detect
+///
+/// The client's capabilities, with the server's batch and page limits, the settings file's
+/// current rate limit, and the webhook kinds that have a target.
+async fn get_capabilities() -> ResponseJson<CapabilitiesResponse> {
+    let mut capabilities = get_client().capabilities();
+    capabilities.read_only = read_only();
+    capabilities.limits.max_batch_size = Some(MAX_METADATA_BATCH.min(MAX_PORTFOLIO_WALLETS));
+    capabilities.limits.max_page_size = Some(MAX_ASSETS_PAGE);
+    capabilities.limits.requests_per_minute =
+        server_config().and_then(|config| config.rate_limit).map(|limit| limit.requests_per_minute);
+    let webhooks = [
+        ("invalidation", webhook_target(|targets| &targets.invalidation, "FINTERNET_INVALIDATION_WEBHOOK")),
+        ("deposit", webhook_target(|targets| &targets.deposit, "FINTERNET_DEPOSIT_WEBHOOK")),
+        ("dead_letter_alert", webhook_target(|targets| &targets.dead_letter_alert, "FINTERNET_DLQ_ALERT_WEBHOOK")),
+    ];
+    ResponseJson(CapabilitiesResponse {
+        capabilities,
+        webhooks: webhooks.into_iter().filter(|(_, url)| url.is_some()).map(|(kind, _)| kind.to_string()).collect(),
+        api_keys_required: API_KEYS.get().is_some(),
+    })
+}
+
+/// Per-phase latency histograms and cache, store, and payment queue gauges in the Prometheus
+/// text format
+#[cfg(feature = "metrics")]
+async fn phase_metrics() -> Response {
+    let usage = finternet_sdk::resources::ResourceUsage {
+        #[cfg(feature = "sqlite")]
+        local_ledger: LEDGER.get().and_then(|ledger| ledger.usage().ok()),
+        ..get_client().resource_usage()
+    };
+    let queue = PAYMENT_QUEUE.get().and_then(|queue| queue.metrics().ok()).map(|metrics| metrics.render_prometheus());
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4; charset=utf-8")],
+        get_client().phase_histograms().render_prometheus() + &usage.render_prometheus() + &queue.unwrap_or_default(),
+    )
+        .into_response()
+}
+
 async fn tokenize_asset(
     Json(payload): Json<TokenizeAssetRequest>,
-) -> Result<ResponseJson<TokenizeAssetResponse>, StatusCode> {
+) -> Result<ResponseJson<TokenizeAssetResponse>, (StatusCode, ResponseJson<ErrorResponse>)> {
     let client = get_client();
     let wallet = get_wallet();
 
+    let asset_type: AssetType = payload
+        .asset_type
+        .parse()
+        .map_err(|e: anyhow::Error| error_response(StatusCode::BAD_REQUEST, "invalid_asset_type", e.to_string()))?;
+    validation_response(validate_asset_spec(
+        &payload.name,
+        &payload.description,
+        payload.value,
+        &asset_type,
+        &Default::default(),
+    ))?;
+
     match client
         .tokenize_asset(
             &payload.name,
             &payload.description,
             payload.value,
-            &payload.asset_type,
+            &asset_type,
             wallet,
         )
         .await
@@ -131,43 +995,89 @@ async fn tokenize_asset(
         }
         Err(e) => {
             eprintln!("Token creation failed: {}", e);
-            Err(StatusCode::INTERNAL_SERVER_ERROR)
+            if let Some(simulated) = e.downcast_ref::<DryRunOutcome>() {
+                return Err(dry_run_response(simulated));
+            }
+            Err(sdk_error_response(StatusCode::INTERNAL_SERVER_ERROR, "tokenization_failed", &e))
         }
     }
 }
 
+/// A `/api/send-payment` body checked and converted to base units, shared with its preview
+struct ParsedPayment {
+    to: Pubkey,
+    splits: Option<Vec<(Pubkey, u16)>>,
+    mint: Pubkey,
+    amount: Amount,
+}
+
+fn parse_payment_request(payload: &SendPaymentRequest) -> Result<ParsedPayment, (StatusCode, ResponseJson<ErrorResponse>)> {
+    let to_pubkey = Pubkey::from_str(&payload.to)
+        .map_err(|_| error_response(StatusCode::BAD_REQUEST, "invalid_address", format!("Invalid recipient '{}'", payload.to)))?;
+
+    let splits = match &payload.splits {
+        Some(splits) => {
+            let mut parsed = Vec::with_capacity(splits.len());
+            for split in splits {
+                let pk = Pubkey::from_str(&split.to).map_err(|_| {
+                    error_response(StatusCode::BAD_REQUEST, "invalid_address", format!("Invalid split recipient '{}'", split.to))
+                })?;
+                parsed.push((pk, split.bps));
+            }
+            if parsed.iter().map(|(_, bps)| *bps as u32).sum::<u32>() > 10_000 {
+                return Err(error_response(
+                    StatusCode::BAD_REQUEST,
+                    "invalid_splits",
+                    "Split shares exceed 10000 basis points".to_string(),
+                ));
+            }
+            Some(parsed)
+        }
+        None => None,
+    };
+
+    let mint_pubkey = match &payload.token_mint {
+        Some(token_mint) => Some(Pubkey::from_str(token_mint).map_err(|_| {
+            error_response(StatusCode::BAD_REQUEST, "invalid_address", format!("Invalid token mint '{}'", token_mint))
+        })?),
+        None => None,
+    };
+    // SPL tokens are assumed to have 9 decimals, USDC has 6
+    let (mint, currency) = match mint_pubkey {
+        Some(mint) => (mint, Currency::spl(mint, 9)),
+        None => (usdc::devnet_mint(), Currency::usdc_devnet()),
+    };
+    let amount = Amount::parse(&payload.amount, &currency)
+        .map_err(|e| kind_response(StatusCode::BAD_REQUEST, FinternetError::from(&e), e.code(), e.to_string()))?;
+    validation_response(validate_payment(&to_pubkey, amount.base_units, &mint, payload.memo.as_deref()))?;
+    Ok(ParsedPayment { to: to_pubkey, splits, mint, amount })
+}
+
 async fn send_payment(
+    key: Option<Extension<ApiKeyRecord>>,
     Json(payload): Json<SendPaymentRequest>,
-) -> Result<ResponseJson<SendPaymentResponse>, StatusCode> {
+) -> Result<ResponseJson<SendPaymentResponse>, (StatusCode, ResponseJson<ErrorResponse>)> {
+    if require_signed_intents() {
+        return Err(error_response(
+            StatusCode::FORBIDDEN,
+            "signed_intent_required",
+            "This server only accepts signed payment intents".to_string(),
+        ));
+    }
+
     let client = get_client();
     let wallet = get_wallet();
 
-    let to_pubkey = match Pubkey::from_str(&payload.to) {
-        Ok(pk) => pk,
-        Err(_) => return Err(StatusCode::BAD_REQUEST),
-    };
+    let ParsedPayment { to: to_pubkey, splits, mint, amount } = parse_payment_request(&payload)?;
+    let reservation = reserve_volume(key.as_deref(), &amount.currency, amount.base_units)?;
 
-    let result = if let Some(token_mint) = payload.token_mint {
-        let mint_pubkey = match Pubkey::from_str(&token_mint) {
-            Ok(pk) => pk,
-            Err(_) => return Err(StatusCode::BAD_REQUEST),
-        };
-        // Convert f64 to u64 for SPL token amount (assuming 9 decimals for most SPL tokens)
-        let amount_lamports = (payload.amount * 1_000_000_000.0) as u64;
-        client
-            .send_payment(
-                wallet,
-                &to_pubkey,
-                amount_lamports,
-                &mint_pubkey,
-                payload.memo.as_deref(),
-            )
-            .await
-    } else {
-        // For USDC payments, the SDK handles the conversion internally
-        client
-            .send_usdc_payment(wallet, &to_pubkey, payload.amount, payload.memo.as_deref())
-            .await
+    let result = match &splits {
+        Some(splits) => {
+            client
+                .send_split_payment(wallet, (to_pubkey, amount.base_units), splits, &mint, payload.memo.as_deref())
+                .await
+        }
+        None => client.send_payment(wallet, &to_pubkey, amount.base_units, &mint, payload.memo.as_deref()).await,
     };
 
     match result {
@@ -175,92 +1085,1965 @@ async fn send_payment(
             signature: signature.to_string(),
             from: wallet.pubkey().to_string(),
             to: payload.to,
-            amount: payload.amount,
+            amount: amount.to_decimal_string(),
+            base_units: amount.base_units,
         })),
         Err(e) => {
             eprintln!("Payment failed: {}", e);
-            Err(StatusCode::INTERNAL_SERVER_ERROR)
+            if let Some(reservation) = reservation {
+                reservation.release();
+            }
+            if let Some(simulated) = e.downcast_ref::<DryRunOutcome>() {
+                return Err(dry_run_response(simulated));
+            }
+            if let Some(violation) = e.downcast_ref::<PolicyViolation>() {
+                return Err(kind_response(StatusCode::FORBIDDEN, violation.into(), violation.code(), violation.to_string()));
+            }
+            if let Some(violation) = e.downcast_ref::<AssetPolicyViolation>() {
+                return Err(kind_response(StatusCode::FORBIDDEN, violation.into(), violation.code(), violation.to_string()));
+            }
+            if let Some(invalid) = e.downcast_ref::<ValidationError>() {
+                return Err(validation_error_response(invalid.clone()));
+            }
+            if let Some(mismatch) = e.downcast_ref::<MintNetworkError>() {
+                return Err(kind_response(StatusCode::BAD_REQUEST, mismatch.into(), mismatch.code(), mismatch.to_string()));
+            }
+            Err(match e.downcast_ref::<RecipientError>() {
+                Some(recipient_err) => kind_response(StatusCode::BAD_REQUEST, recipient_err.into(), recipient_err.code(), recipient_err.to_string()),
+                None => sdk_error_response(StatusCode::INTERNAL_SERVER_ERROR, "payment_failed", &e),
+            })
         }
     }
 }
 
-async fn get_wallet_info() -> Result<ResponseJson<WalletInfoResponse>, StatusCode> {
-    let client = get_client();
-    let wallet = get_wallet();
+/// `/api/send-payment`'s body, with where the payment goes in the queue
+#[derive(Debug, Deserialize)]
+struct EnqueuePaymentRequest {
+    #[serde(flatten)]
+    payment: SendPaymentRequest,
+    /// Higher goes first; 0 by default
+    #[serde(default)]
+    priority: i32,
+    /// Unix time before which the payment is not sent
+    not_before: Option<u64>,
+}
 
-    let sol_balance = match client.get_sol_balance(&wallet.pubkey()).await {
-        Ok(balance) => balance,
-        Err(_) => 0.0,
-    };
+#[derive(Debug, Serialize)]
+struct QueuedPaymentResponse {
+    #[serde(flatten)]
+    payment: QueuedPayment,
+    display_amount: String,
+}
 
-    let usdc_balance = match client.get_usdc_balance(&wallet.pubkey()).await {
-        Ok(balance) => balance,
-        Err(_) => 0.0,
-    };
+fn get_payment_queue_store() -> &'static PaymentQueue {
+    PAYMENT_QUEUE.get().expect("Payment queue not initialized")
+}
 
-    let token_accounts = match client.get_token_accounts(&wallet.pubkey()).await {
-        Ok(accounts) => accounts
-            .into_iter()
-            .map(|(mint, balance)| TokenAccountInfo {
-                mint: mint.to_string(),
-                balance,
-                decimals: 9, // Default for most SPL tokens
-            })
-            .collect(),
-        Err(_) => vec![],
+/// Queue a payment for the worker to send, returning its queue id at once
+///
+/// Splits are not queued; send those with `/api/send-payment`.
+async fn enqueue_payment(
+    key: Option<Extension<ApiKeyRecord>>,
+    Json(payload): Json<EnqueuePaymentRequest>,
+) -> Result<(StatusCode, ResponseJson<QueuedPaymentResponse>), (StatusCode, ResponseJson<ErrorResponse>)> {
+    if require_signed_intents() {
+        return Err(error_response(
+            StatusCode::FORBIDDEN,
+            "signed_intent_required",
+            "This server only accepts signed payment intents".to_string(),
+        ));
+    }
+    let ParsedPayment { to, splits, mint, amount } = parse_payment_request(&payload.payment)?;
+    if splits.is_some() {
+        return Err(error_response(
+            StatusCode::BAD_REQUEST,
+            "splits_not_queued",
+            "Split payments cannot be queued; send them with /api/send-payment".to_string(),
+        ));
+    }
+    let job = PaymentJob {
+        to,
+        amount: amount.base_units,
+        mint,
+        memo: payload.payment.memo,
+        priority: payload.priority,
+        not_before: payload.not_before,
     };
+    // Queued volume counts on the day it is queued, whenever the worker sends it
+    let reservation = reserve_volume(key.as_deref(), &amount.currency, amount.base_units)?;
+    let payment = get_payment_queue_store().enqueue(job).map_err(|e| {
+        if let Some(reservation) = reservation {
+            reservation.release();
+        }
+        sdk_error_response(StatusCode::INTERNAL_SERVER_ERROR, "payment_queue_error", &e)
+    })?;
+    Ok((StatusCode::ACCEPTED, ResponseJson(QueuedPaymentResponse {
+        payment,
+        display_amount: get_client().amount_format().format(&amount.currency, amount.base_units),
+    })))
+}
 
-    Ok(ResponseJson(WalletInfoResponse {
-        public_key: wallet.pubkey().to_string(),
-        sol_balance,
-        usdc_balance,
-        token_accounts,
-    }))
+async fn get_queued_payment(
+    Path(id): Path<String>,
+) -> Result<ResponseJson<QueuedPayment>, (StatusCode, ResponseJson<ErrorResponse>)> {
+    get_payment_queue_store()
+        .get(&id)
+        .map_err(|e| sdk_error_response(StatusCode::INTERNAL_SERVER_ERROR, "payment_queue_error", &e))?
+        .map(ResponseJson)
+        .ok_or_else(|| error_response(StatusCode::NOT_FOUND, "queued_payment_not_found", format!("No queued payment {}", id)))
+}
+
+/// How deep and how old the payment queue is
+async fn get_payment_queue() -> Result<ResponseJson<QueueMetrics>, (StatusCode, ResponseJson<ErrorResponse>)> {
+    get_payment_queue_store()
+        .metrics()
+        .map(ResponseJson)
+        .map_err(|e| sdk_error_response(StatusCode::INTERNAL_SERVER_ERROR, "payment_queue_error", &e))
 }
 
-async fn get_owned_assets() -> Result<ResponseJson<Vec<AssetInfo>>, StatusCode> {
+/// Simulate what `/api/send-payment` would do with the same body, without sending anything
+async fn preview_payment(
+    Json(payload): Json<SendPaymentRequest>,
+) -> Result<ResponseJson<PreviewResponse>, (StatusCode, ResponseJson<ErrorResponse>)> {
     let client = get_client();
     let wallet = get_wallet();
 
-    match client.get_owned_assets(&wallet.pubkey()).await {
-        Ok(assets) => {
-            let mut asset_infos = Vec::new();
-            for (mint, balance) in assets {
-                let metadata = client.get_asset_info(&mint).await.ok();
-                asset_infos.push(AssetInfo {
-                    mint: mint.to_string(),
-                    metadata,
-                    balance,
-                });
-            }
-            Ok(ResponseJson(asset_infos))
-        }
-        Err(e) => {
-            eprintln!("Failed to get assets: {}", e);
-            Err(StatusCode::INTERNAL_SERVER_ERROR)
+    let ParsedPayment { to, splits, mint, amount } = parse_payment_request(&payload)?;
+    let preview = match &splits {
+        Some(splits) => {
+            client
+                .preview_split_payment(&wallet.pubkey(), (to, amount.base_units), splits, &mint, payload.memo.as_deref())
+                .await
         }
+        None => client.preview_payment(&wallet.pubkey(), &to, amount.base_units, &mint, payload.memo.as_deref()).await,
+    };
+    match preview {
+        Ok(preview) => Ok(ResponseJson(preview.into())),
+        Err(e) => Err(preview_error_response(e)),
     }
 }
 
-async fn get_transaction_history() -> Result<ResponseJson<Vec<TransactionRecord>>, StatusCode> {
+/// Simulate what `/api/tokenize-asset` would do with the same body, without minting anything
+async fn preview_tokenize(
+    Json(payload): Json<TokenizeAssetRequest>,
+) -> Result<ResponseJson<TokenizePreviewResponse>, (StatusCode, ResponseJson<ErrorResponse>)> {
     let client = get_client();
     let wallet = get_wallet();
 
+    let asset_type: AssetType = payload
+        .asset_type
+        .parse()
+        .map_err(|e: anyhow::Error| error_response(StatusCode::BAD_REQUEST, "invalid_asset_type", e.to_string()))?;
     match client
-        .get_transaction_history(&wallet.pubkey(), Some(20))
+        .preview_tokenize(
+            &payload.name,
+            &payload.description,
+            payload.value,
+            &asset_type,
+            &wallet.pubkey(),
+            &Default::default(),
+        )
         .await
     {
-        Ok(history) => Ok(ResponseJson(history)),
-        Err(e) => {
-            eprintln!("Failed to get transaction history: {}", e);
-            Err(StatusCode::INTERNAL_SERVER_ERROR)
-        }
+        Ok(preview) => Ok(ResponseJson(TokenizePreviewResponse {
+            mint: preview.mint.to_string(),
+            metadata: preview.metadata,
+            preview: preview.transaction.into(),
+        })),
+        Err(e) => Err(preview_error_response(e)),
     }
 }
 
-async fn get_asset_info(Path(mint_address): Path<String>) -> Result<ResponseJson<AssetMetadata>, StatusCode> {
+/// Stage a tokenization for review and return its signed draft; nothing is minted
+async fn prepare_tokenization(
+    Json(payload): Json<PrepareTokenizationRequest>,
+) -> Result<ResponseJson<TokenizationDraft>, (StatusCode, ResponseJson<ErrorResponse>)> {
     let client = get_client();
-    
+    let wallet = get_wallet();
+
+    let asset = payload.asset;
+    let asset_type: AssetType = asset
+        .asset_type
+        .parse()
+        .map_err(|e: anyhow::Error| error_response(StatusCode::BAD_REQUEST, "invalid_asset_type", e.to_string()))?;
+    let spec = AssetSpec { name: asset.name, description: asset.description, value: asset.value, asset_type };
+    validation_response(validate_asset_spec(&spec.name, &spec.description, spec.value, &spec.asset_type, &Default::default()))?;
+    let valid_for_secs = payload.valid_for_hours.map_or(DEFAULT_DRAFT_VALID_FOR_SECS, |hours| hours.saturating_mul(3600));
+
+    client
+        .prepare_tokenization(wallet, &spec, &Default::default(), valid_for_secs)
+        .await
+        .map(ResponseJson)
+        .map_err(|e| sdk_error_response(StatusCode::INTERNAL_SERVER_ERROR, "tokenization_prepare_failed", &e))
+}
+
+/// Mint a draft from `/api/tokenization-drafts`, sent back unchanged after review
+async fn commit_tokenization(
+    Json(draft): Json<TokenizationDraft>,
+) -> Result<ResponseJson<TokenizeAssetResponse>, (StatusCode, ResponseJson<ErrorResponse>)> {
+    match get_client().commit_tokenization(get_wallet(), &draft).await {
+        Ok(asset) => Ok(ResponseJson(TokenizeAssetResponse {
+            mint: asset.mint.to_string(),
+            signature: asset.signature.to_string(),
+            metadata: asset.metadata,
+        })),
+        Err(e) => {
+            if let Some(simulated) = e.downcast_ref::<DryRunOutcome>() {
+                return Err(dry_run_response(simulated));
+            }
+            Err(sdk_error_response(StatusCode::INTERNAL_SERVER_ERROR, "tokenization_commit_failed", &e))
+        }
+    }
+}
+
+/// Remove the staged metadata of a draft that will not be committed
+async fn discard_tokenization(
+    Json(draft): Json<TokenizationDraft>,
+) -> Result<ResponseJson<DiscardTokenizationResponse>, (StatusCode, ResponseJson<ErrorResponse>)> {
+    get_client()
+        .discard_tokenization(&draft)
+        .await
+        .map(|()| ResponseJson(DiscardTokenizationResponse { id: draft.id.clone(), discarded: true }))
+        .map_err(|e| sdk_error_response(StatusCode::INTERNAL_SERVER_ERROR, "tokenization_discard_failed", &e))
+}
+
+/// A simulated failure is a warning in a 200 response; only a preview that could not run errors
+fn preview_error_response(e: anyhow::Error) -> (StatusCode, ResponseJson<ErrorResponse>) {
+    if let Some(invalid) = e.downcast_ref::<ValidationError>() {
+        return validation_error_response(invalid.clone());
+    }
+    if let Some(mismatch) = e.downcast_ref::<MintNetworkError>() {
+        return kind_response(StatusCode::BAD_REQUEST, mismatch.into(), mismatch.code(), mismatch.to_string());
+    }
+    match e.downcast_ref::<RecipientError>() {
+        Some(recipient_err) => kind_response(StatusCode::BAD_REQUEST, recipient_err.into(), recipient_err.code(), recipient_err.to_string()),
+        None => sdk_error_response(StatusCode::BAD_GATEWAY, "preview_failed", &e),
+    }
+}
+
+async fn submit_payment_intent(
+    key: Option<Extension<ApiKeyRecord>>,
+    Json(payload): Json<SubmitPaymentIntentRequest>,
+) -> Result<ResponseJson<SendPaymentResponse>, (StatusCode, ResponseJson<ErrorResponse>)> {
+    let client = get_client();
+    let wallet = get_wallet();
+    let store = INTENTS.get().expect("Intent store not initialized");
+
+    let intent = FinternetClient::verify_payment_intent(&payload.message, &payload.signature)
+        .map_err(|e| intent_error_response(&e))?;
+
+    let now = chrono::Utc::now().timestamp() as u64;
+    if let Err(e) = store.reserve(&intent, intent_daily_limit(), now) {
+        return Err(match e.downcast_ref::<IntentError>() {
+            Some(intent_err) => intent_error_response(intent_err),
+            None => sdk_error_response(StatusCode::INTERNAL_SERVER_ERROR, "intent_store_error", &e),
+        });
+    }
+    let reservation = match reserve_volume(key.as_deref(), &Currency::from_mint(intent.mint), intent.amount) {
+        Ok(reservation) => reservation,
+        Err(refusal) => {
+            if let Err(release_err) = store.release(&intent, now) {
+                eprintln!("Failed to release intent allowance: {}", release_err);
+            }
+            return Err(refusal);
+        }
+    };
+
+    match client
+        .send_payment(wallet, &intent.recipient, intent.amount, &intent.mint, intent.memo.as_deref())
+        .await
+    {
+        Ok(signature) => Ok(ResponseJson(SendPaymentResponse {
+            signature: signature.to_string(),
+            from: wallet.pubkey().to_string(),
+            to: intent.recipient.to_string(),
+            amount: Amount { base_units: intent.amount, currency: Currency::from_mint(intent.mint) }.to_decimal_string(),
+            base_units: intent.amount,
+        })),
+        Err(e) => {
+            eprintln!("Intent payment failed: {}", e);
+            if let Err(release_err) = store.release(&intent, now) {
+                eprintln!("Failed to release intent allowance: {}", release_err);
+            }
+            if let Some(reservation) = reservation {
+                reservation.release();
+            }
+            if let Some(simulated) = e.downcast_ref::<DryRunOutcome>() {
+                return Err(dry_run_response(simulated));
+            }
+            if let Some(violation) = e.downcast_ref::<PolicyViolation>() {
+                return Err(kind_response(StatusCode::FORBIDDEN, violation.into(), violation.code(), violation.to_string()));
+            }
+            if let Some(violation) = e.downcast_ref::<AssetPolicyViolation>() {
+                return Err(kind_response(StatusCode::FORBIDDEN, violation.into(), violation.code(), violation.to_string()));
+            }
+            if let Some(invalid) = e.downcast_ref::<ValidationError>() {
+                return Err(validation_error_response(invalid.clone()));
+            }
+            if let Some(mismatch) = e.downcast_ref::<MintNetworkError>() {
+                return Err(kind_response(StatusCode::BAD_REQUEST, mismatch.into(), mismatch.code(), mismatch.to_string()));
+            }
+            Err(match e.downcast_ref::<RecipientError>() {
+                Some(recipient_err) => kind_response(StatusCode::BAD_REQUEST, recipient_err.into(), recipient_err.code(), recipient_err.to_string()),
+                None => sdk_error_response(StatusCode::INTERNAL_SERVER_ERROR, "payment_failed", &e),
+            })
+        }
+    }
+}
+
+/// `?commitment=processed|confirmed|finalized` on read endpoints
+#[derive(Debug, Deserialize)]
+struct CommitmentQuery {
+    commitment: Option<String>,
+}
+
+impl CommitmentQuery {
+    fn options(&self) -> Result<RpcCallOptions, StatusCode> {
+        match &self.commitment {
+            Some(commitment) => RpcCallOptions::from_commitment_str(commitment).map_err(|_| StatusCode::BAD_REQUEST),
+            None => Ok(RpcCallOptions::default()),
+        }
+    }
+}
+
+/// `/api/wallet-info` query: `?commitment=` for fresh reads, or `?freshness=cached|swr|swr:<seconds>`
+#[derive(Debug, Deserialize)]
+struct WalletInfoQuery {
+    commitment: Option<String>,
+    freshness: Option<String>,
+}
+
+async fn get_wallet_info(
+    Query(query): Query<WalletInfoQuery>,
+) -> Result<ResponseJson<WalletInfoResponse>, StatusCode> {
+    let client = get_client();
+    let wallet = get_wallet();
+    let freshness = match &query.freshness {
+        Some(freshness) => Freshness::from_str(freshness).map_err(|_| StatusCode::BAD_REQUEST)?,
+        None => Freshness::Fresh,
+    };
+    if freshness != Freshness::Fresh {
+        // Cached balances were read at the client's commitment; a different one cannot apply
+        if query.commitment.is_some() {
+            return Err(StatusCode::BAD_REQUEST);
+        }
+        return cached_wallet_info(client, &wallet.pubkey(), freshness).await;
+    }
+    let options = CommitmentQuery { commitment: query.commitment }.options()?;
+
+    // Report an unreachable RPC node as such instead of a wallet with zero balances
+    let account_status = match client.get_account_status(&wallet.pubkey()).await {
+        Ok(AccountStatus::RpcUnavailable { .. }) | Err(_) => return Err(StatusCode::SERVICE_UNAVAILABLE),
+        Ok(status) => status,
+    };
+
+    let sol_balance = client
+        .get_sol_balance_with_options(&wallet.pubkey(), &options)
+        .await
+        .unwrap_or(0.0);
+
+    let usdc_balance = client.get_usdc_balance(&wallet.pubkey()).await.unwrap_or(0.0);
+
+    let (token_accounts, undecodable_accounts) = match client.get_token_account_scan(&wallet.pubkey()).await {
+        Ok(scan) => (scan.accounts.into_iter().map(token_account_info).collect(), scan.undecodable),
+        Err(_) => (vec![], vec![]),
+    };
+
+    Ok(ResponseJson(WalletInfoResponse {
+        public_key: wallet.pubkey().to_string(),
+        account_status,
+        sol_balance,
+        usdc_balance,
+        token_accounts,
+        undecodable_accounts,
+        as_of: chrono::Utc::now().timestamp() as u64,
+    }))
+}
+
+/// Wallet info served from the client's cache, for UIs that must not wait on the RPC node
+async fn cached_wallet_info(
+    client: &Arc<FinternetClient>,
+    pubkey: &Pubkey,
+    freshness: Freshness,
+) -> Result<ResponseJson<WalletInfoResponse>, StatusCode> {
+    let read = match client.get_wallet_info_with_freshness(pubkey, freshness).await {
+        Ok(read) => read,
+        Err(e) => {
+            tracing::warn!("Wallet info for {} unavailable: {}", pubkey, e);
+            return Err(StatusCode::SERVICE_UNAVAILABLE);
+        }
+    };
+    let info = read.value;
+    let usdc_balance = info.token_balances.get(&usdc::devnet_mint()).copied().unwrap_or(0) as f64 / 1_000_000.0;
+    Ok(ResponseJson(WalletInfoResponse {
+        public_key: info.pubkey.to_string(),
+        sol_balance: info.sol_balance_as_sol(),
+        usdc_balance,
+        token_accounts: info.token_accounts.into_iter().map(token_account_info).collect(),
+        undecodable_accounts: info.undecodable_accounts,
+        account_status: info.status,
+        as_of: read.as_of,
+    }))
+}
+
+fn token_account_info(account: TokenAccountDetail) -> TokenAccountInfo {
+    TokenAccountInfo {
+        mint: account.mint.to_string(),
+        balance: account.amount,
+        decimals: account.decimals.unwrap_or(9), // Default for most SPL tokens
+        display_balance: get_client().amount_format().format(&account.currency(), account.amount),
+        address: account.address.to_string(),
+        state: account.state,
+        delegate: account.delegate.map(|d| d.to_string()),
+        delegated_amount: account.delegated_amount,
+        close_authority: account.close_authority.map(|c| c.to_string()),
+    }
+}
+
+/// Page size of `GET /api/assets` when no `limit` is given
+const DEFAULT_ASSETS_PAGE: usize = 50;
+/// Largest page `GET /api/assets` returns
+const MAX_ASSETS_PAGE: usize = 200;
+
+#[derive(Debug, Deserialize)]
+struct OwnedAssetsParams {
+    limit: Option<usize>,
+    offset: Option<usize>,
+    /// `next_cursor` from the previous page; takes precedence over `offset`
+    cursor: Option<String>,
+    /// `balance` (largest first, the default) or `name`
+    sort: Option<String>,
+    asset_type: Option<String>,
+    /// Base units
+    min_balance: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+struct OwnedAssetsResponse {
+    assets: Vec<AssetInfo>,
+    /// Assets matching the filters, across all pages
+    total_count: usize,
+    /// Pass as `cursor` for the next page; absent on the last one
+    next_cursor: Option<String>,
+}
+
+/// A page of the server wallet's assets, filtered and sorted before any metadata is read
+async fn get_owned_assets(
+    Query(params): Query<OwnedAssetsParams>,
+) -> Result<ResponseJson<OwnedAssetsResponse>, (StatusCode, ResponseJson<ErrorResponse>)> {
+    let client = get_client();
+    let wallet = get_wallet();
+
+    // The cursor is the next page's offset, kept opaque so it can change without breaking clients
+    let offset = match &params.cursor {
+        Some(cursor) => cursor
+            .parse()
+            .map_err(|_| error_response(StatusCode::BAD_REQUEST, "invalid_cursor", format!("Invalid cursor '{}'", cursor)))?,
+        None => params.offset.unwrap_or(0),
+    };
+    let sort = match &params.sort {
+        Some(sort) => OwnedAssetSort::from_str(sort)
+            .map_err(|e| error_response(StatusCode::BAD_REQUEST, "invalid_sort", e.to_string()))?,
+        None => OwnedAssetSort::default(),
+    };
+    let asset_type = match &params.asset_type {
+        Some(asset_type) => Some(
+            AssetType::from_str(asset_type)
+                .map_err(|e| error_response(StatusCode::BAD_REQUEST, "invalid_asset_type", e.to_string()))?,
+        ),
+        None => None,
+    };
+    let query = OwnedAssetsQuery {
+        offset,
+        limit: params.limit.unwrap_or(DEFAULT_ASSETS_PAGE).min(MAX_ASSETS_PAGE),
+        sort,
+        asset_type,
+        min_balance: params.min_balance.unwrap_or(0),
+    };
+
+    let page = client.get_owned_assets_page(&wallet.pubkey(), &query).await.map_err(|e| {
+        eprintln!("Failed to get assets: {}", e);
+        sdk_error_response(StatusCode::BAD_GATEWAY, "assets_failed", &e)
+    })?;
+    Ok(ResponseJson(OwnedAssetsResponse {
+        assets: page
+            .assets
+            .into_iter()
+            .map(|OwnedAsset { mint, balance, metadata }| AssetInfo {
+                mint: mint.to_string(),
+                metadata,
+                balance,
+            })
+            .collect(),
+        total_count: page.total_count,
+        next_cursor: page.next_offset.map(|offset| offset.to_string()),
+    }))
+}
+
+#[derive(Debug, Serialize)]
+struct HistoryEntry {
+    #[serde(flatten)]
+    record: TransactionRecord,
+    /// `amount` in whole units with the currency, e.g. `12.50 USDC`
+    display_amount: String,
+    /// The wallet redacted this transaction's memo
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    redacted: bool,
+}
+
+impl HistoryEntry {
+    fn new(record: TransactionRecord, redactions: &Redactions) -> Self {
+        Self {
+            display_amount: get_client().amount_format().format(&record.currency, record.amount),
+            redacted: redactions.hides_memo(&record.signature),
+            record,
+        }
+    }
+}
+
+async fn get_transaction_history(
+    Query(query): Query<CommitmentQuery>,
+) -> Result<Response, StatusCode> {
+    let client = get_client();
+    let wallet = get_wallet();
+    let options = query.options()?;
+
+    // An explicit commitment asks for the chain's view, not the synced copy
+    let (history, completeness) = match (ledger_history(&wallet.pubkey(), 20), &query.commitment) {
+        (Some(synced), None) => synced.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?,
+        _ => {
+            let history = client
+                .get_transaction_history_with_completeness(&wallet.pubkey(), Some(20), &options)
+                .await
+                .map_err(|e| {
+                    eprintln!("Failed to get transaction history: {}", e);
+                    StatusCode::INTERNAL_SERVER_ERROR
+                })?;
+            (history.records, history.completeness)
+        }
+    };
+    // Redacted memos must never be served, so history is withheld when redactions cannot be read
+    let redactions = client.get_redactions(&wallet.pubkey()).await.map_err(|e| {
+        eprintln!("Failed to read redactions: {}", e);
+        StatusCode::BAD_GATEWAY
+    })?;
+    let mut history = history;
+    redactions.apply_to_records(&mut history);
+    let entries: Vec<HistoryEntry> = history.into_iter().map(|record| HistoryEntry::new(record, &redactions)).collect();
+    Ok(([(COMPLETENESS_HEADER, completeness.header_value())], ResponseJson(entries)).into_response())
+}
+
+#[derive(Debug, Deserialize)]
+struct ExportQuery {
+    /// `ofx` (the default) or `qif`
+    format: Option<String>,
+    /// Most recent transactions to include
+    limit: Option<usize>,
+}
+
+/// The server wallet's history as an OFX or QIF file, one statement per currency
+async fn export_transaction_history(
+    Query(query): Query<ExportQuery>,
+) -> Result<Response, (StatusCode, ResponseJson<ErrorResponse>)> {
+    let client = get_client();
+    let wallet = get_wallet();
+    let format = ExportFormat::from_str(query.format.as_deref().unwrap_or("ofx"))
+        .map_err(|e| error_response(StatusCode::BAD_REQUEST, "invalid_format", e.to_string()))?;
+    let limit = query.limit.unwrap_or(100);
+
+    let history_failed = |e: anyhow::Error| sdk_error_response(StatusCode::BAD_GATEWAY, "history_failed", &e);
+    let (history, completeness) = match ledger_history(&wallet.pubkey(), limit) {
+        Some(synced) => synced.map_err(history_failed)?,
+        None => (
+            client
+                .iter_transaction_history(&wallet.pubkey(), HistoryFilter::default())
+                .take(limit)
+                .try_collect()
+                .await
+                .map_err(history_failed)?,
+            // Only a synced ledger knows whether it reaches the account's creation
+            HistoryCompleteness::Unknown,
+        ),
+    };
+    let index = std::env::var("FINTERNET_IDENTITY_INDEX")
+        .ok()
+        .and_then(|path| IdentityIndex::load(std::path::Path::new(&path)).ok());
+
+    let generated_at = chrono::Utc::now().timestamp().max(0) as u64;
+    let redactions = client
+        .get_redactions(&wallet.pubkey())
+        .await
+        .map_err(|e| sdk_error_response(StatusCode::BAD_GATEWAY, "redactions_unavailable", &e))?;
+    let body = export::export_history(format, &wallet.pubkey(), &history, index.as_ref(), &redactions, generated_at);
+    let disposition = format!("attachment; filename=\"transactions.{}\"", format.extension());
+    Ok((
+        [
+            (header::CONTENT_TYPE.as_str(), format.content_type().to_string()),
+            (header::CONTENT_DISPOSITION.as_str(), disposition),
+            (COMPLETENESS_HEADER, completeness.header_value()),
+        ],
+        body,
+    )
+        .into_response())
+}
+
+#[derive(Debug, Deserialize)]
+struct PrecheckQuery {
+    to: String,
+    /// Mint address; defaults to devnet USDC
+    mint: Option<String>,
+}
+
+async fn precheck_recipient(
+    Query(query): Query<PrecheckQuery>,
+) -> Result<ResponseJson<RecipientPrecheck>, (StatusCode, ResponseJson<ErrorResponse>)> {
+    let client = get_client();
+    let to = Pubkey::from_str(&query.to)
+        .map_err(|e| error_response(StatusCode::BAD_REQUEST, "invalid_address", e.to_string()))?;
+    let mint = match query.mint.as_deref() {
+        Some(mint) => Pubkey::from_str(mint)
+            .map_err(|e| error_response(StatusCode::BAD_REQUEST, "invalid_mint", e.to_string()))?,
+        None => usdc::devnet_mint(),
+    };
+
+    client
+        .precheck_recipient(&to, &mint)
+        .await
+        .map(ResponseJson)
+        .map_err(|e| sdk_error_response(StatusCode::SERVICE_UNAVAILABLE, "precheck_failed", &e))
+}
+
+async fn analyze_counterparty(
+    Path(address): Path<String>,
+) -> Result<ResponseJson<CounterpartyReport>, (StatusCode, ResponseJson<ErrorResponse>)> {
+    let client = get_client();
+    let address = Pubkey::from_str(&address)
+        .map_err(|e| error_response(StatusCode::BAD_REQUEST, "invalid_address", e.to_string()))?;
+
+    client
+        .analyze_counterparty(&address)
+        .await
+        .map(ResponseJson)
+        .map_err(|e| sdk_error_response(StatusCode::BAD_GATEWAY, "analysis_failed", &e))
+}
+
+#[derive(Debug, Deserialize)]
+struct GateQuery {
+    /// Require this mint, at least `min` base units of it (1 by default)
+    mint: Option<String>,
+    min: Option<u64>,
+    /// Require any asset of this verified collection
+    collection: Option<String>,
+    /// Require an asset of this type signed by `issuer`
+    asset_type: Option<String>,
+    issuer: Option<String>,
+}
+
+impl GateQuery {
+    fn requirement(&self) -> Result<GateRequirement, (StatusCode, ResponseJson<ErrorResponse>)> {
+        let pubkey = |field: &str, value: &str| {
+            Pubkey::from_str(value).map_err(|e| error_response(StatusCode::BAD_REQUEST, &format!("invalid_{}", field), e.to_string()))
+        };
+        match (&self.mint, &self.collection, &self.asset_type, &self.issuer) {
+            (Some(mint), None, None, None) => Ok(GateRequirement::HoldsMint {
+                mint: pubkey("mint", mint)?,
+                min_amount: self.min.unwrap_or(1),
+            }),
+            (None, Some(collection), None, None) => Ok(GateRequirement::HoldsCollection {
+                collection: pubkey("collection", collection)?,
+            }),
+            (None, None, Some(asset_type), Some(issuer)) => Ok(GateRequirement::HoldsAssetType {
+                asset_type: AssetType::from_str(asset_type)
+                    .map_err(|e| error_response(StatusCode::BAD_REQUEST, "invalid_asset_type", e.to_string()))?,
+                issuer: pubkey("issuer", issuer)?,
+            }),
+            _ => Err(error_response(
+                StatusCode::BAD_REQUEST,
+                "invalid_requirement",
+                "Pass exactly one of mint (with optional min), collection, or asset_type with issuer".to_string(),
+            )),
+        }
+    }
+}
+
+/// Whether `address` passes a token gate, signed by the server wallet
+async fn check_token_gate(
+    Path(address): Path<String>,
+    Query(query): Query<GateQuery>,
+) -> Result<ResponseJson<GateResult>, (StatusCode, ResponseJson<ErrorResponse>)> {
+    let owner = Pubkey::from_str(&address)
+        .map_err(|e| error_response(StatusCode::BAD_REQUEST, "invalid_address", e.to_string()))?;
+    let requirement = query.requirement()?;
+    let mut result = get_client()
+        .verify_token_gate(&owner, &requirement)
+        .await
+        .map_err(|e| sdk_error_response(StatusCode::BAD_GATEWAY, "gate_check_failed", &e))?;
+    result
+        .attest(get_wallet())
+        .map_err(|e| sdk_error_response(StatusCode::INTERNAL_SERVER_ERROR, "attestation_failed", &e))?;
+    Ok(ResponseJson(result))
+}
+
+#[derive(Debug, Deserialize)]
+struct CreatePaymentRequestBody {
+    /// Whole units, e.g. `12.5` or `"1,000.50"`
+    #[serde(deserialize_with = "deserialize_amount_text")]
+    amount: String,
+    /// `USDC` or an SPL mint address
+    currency: String,
+    label: Option<String>,
+    message: Option<String>,
+    /// Seconds until the request expires; 15 minutes by default
+    ttl_secs: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+struct PaymentRequestResponse {
+    #[serde(flatten)]
+    request: PaymentRequest,
+    /// Base64 PNG of the Solana Pay URL, only returned on creation
+    #[serde(skip_serializing_if = "Option::is_none")]
+    qr_code_png: Option<String>,
+}
+
+async fn create_payment_request(
+    Json(body): Json<CreatePaymentRequestBody>,
+) -> Result<ResponseJson<PaymentRequestResponse>, (StatusCode, ResponseJson<ErrorResponse>)> {
+    let client = get_client();
+    let store = PAYMENT_REQUESTS.get().expect("Payment request store not initialized");
+
+    let (mint, decimals) = if body.currency.eq_ignore_ascii_case("usdc") {
+        (usdc::devnet_mint(), 6)
+    } else {
+        let mint = Pubkey::from_str(&body.currency).map_err(|_| {
+            error_response(
+                StatusCode::BAD_REQUEST,
+                "unsupported_currency",
+                format!("{} is neither USDC nor a mint address", body.currency),
+            )
+        })?;
+        let supply = client
+            .client
+            .get_token_supply(&mint)
+            .map_err(|e| error_response(StatusCode::BAD_REQUEST, "unsupported_currency", e.to_string()))?;
+        (mint, supply.decimals)
+    };
+    let amount = Amount::parse(&body.amount, &Currency::spl(mint, decimals))
+        .map_err(|e| kind_response(StatusCode::BAD_REQUEST, FinternetError::from(&e), e.code(), e.to_string()))?;
+    if amount.base_units == 0 {
+        return Err(error_response(StatusCode::BAD_REQUEST, "invalid_amount", "Amount must be positive".to_string()));
+    }
+
+    let recipient = get_wallet().pubkey();
+    let reference = Keypair::new().pubkey();
+    let mut transfer = TransferRequest::new(recipient);
+    transfer.amount = Some(amount.clone());
+    transfer.spl_token = Some(mint);
+    transfer.references = vec![reference];
+    transfer.label = body.label.clone();
+    transfer.message = body.message.clone();
+    let url = transfer.to_url();
+
+    let png = QrCode::encode(url.as_bytes())
+        .and_then(|qr| qr.to_png(8))
+        .map_err(|e| sdk_error_response(StatusCode::INTERNAL_SERVER_ERROR, "qr_failed", &e))?;
+
+    let now = chrono::Utc::now().timestamp() as u64;
+    let request = PaymentRequest {
+        id: uuid::Uuid::new_v4().to_string(),
+        reference,
+        recipient,
+        mint,
+        amount: amount.to_decimal_string(),
+        base_units: amount.base_units,
+        currency: body.currency,
+        label: body.label,
+        message: body.message,
+        url,
+        created_at: now,
+        expires_at: now + body.ttl_secs.unwrap_or(15 * 60),
+        status: PaymentRequestStatus::Pending,
+        settlement: None,
+    };
+    store
+        .insert(request.clone())
+        .map_err(|e| sdk_error_response(StatusCode::INTERNAL_SERVER_ERROR, "payment_request_store_error", &e))?;
+
+    Ok(ResponseJson(PaymentRequestResponse {
+        request,
+        qr_code_png: Some(base64::engine::general_purpose::STANDARD.encode(png)),
+    }))
+}
+
+async fn get_payment_request(
+    Path(id): Path<String>,
+) -> Result<ResponseJson<PaymentRequestResponse>, (StatusCode, ResponseJson<ErrorResponse>)> {
+    let store = PAYMENT_REQUESTS.get().expect("Payment request store not initialized");
+    let store_error = |e: anyhow::Error| sdk_error_response(StatusCode::INTERNAL_SERVER_ERROR, "payment_request_store_error", &e);
+
+    let request = store
+        .get(&id)
+        .map_err(store_error)?
+        .ok_or_else(|| error_response(StatusCode::NOT_FOUND, "payment_request_not_found", format!("No payment request {}", id)))?;
+    let request = if request.status == PaymentRequestStatus::Pending {
+        refresh_payment_request(store, request)
+            .await
+            .map_err(|e| sdk_error_response(StatusCode::BAD_GATEWAY, "payment_lookup_failed", &e))?
+    } else {
+        request
+    };
+
+    Ok(ResponseJson(PaymentRequestResponse { request, qr_code_png: None }))
+}
+
+/// Look for the request's reference on-chain; a stale request is only expired once it is
+/// known to be unpaid
+async fn refresh_payment_request(store: &PaymentRequestStore, request: PaymentRequest) -> anyhow::Result<PaymentRequest> {
+    let found = get_client()
+        .find_payment_by_reference(&request.reference, &request.recipient)
+        .await?;
+    let settlement = found.and_then(|(_, records)| records.into_iter().find(|record| request.is_settled_by(record)));
+
+    let updated = match settlement {
+        Some(record) => store.mark_paid(&request.id, record)?,
+        None if request.expires_at <= chrono::Utc::now().timestamp() as u64 => store.mark_expired(&request.id)?,
+        None => None,
+    };
+    Ok(updated.unwrap_or(request))
+}
+
+async fn run_payment_request_expiry(store: &'static PaymentRequestStore) {
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(30));
+    loop {
+        interval.tick().await;
+        let stale = match store.stale(chrono::Utc::now().timestamp() as u64) {
+            Ok(stale) => stale,
+            Err(e) => {
+                eprintln!("Payment request expiry failed: {}", e);
+                continue;
+            }
+        };
+        for request in stale {
+            match refresh_payment_request(store, request).await {
+                Ok(request) if request.status == PaymentRequestStatus::Expired => {
+                    println!("⌛ Payment request {} expired", request.id)
+                }
+                Ok(_) => {}
+                Err(e) => eprintln!("Payment request expiry failed: {}", e),
+            }
+        }
+    }
+}
+
+#[cfg(feature = "sqlite")]
+#[derive(Debug, Deserialize)]
+struct StatementQuery {
+    /// `YYYY-MM`
+    month: String,
+    /// Mint address or `usdc`; every mint when omitted
+    mint: Option<String>,
+    /// `json` (default) or `markdown`
+    format: Option<String>,
+    /// Build the statement even when truncated history leaves an opening balance unverified
+    #[serde(default)]
+    allow_partial: bool,
+}
+
+#[cfg(feature = "sqlite")]
+async fn get_statement(
+    Query(query): Query<StatementQuery>,
+) -> Result<Response, (StatusCode, ResponseJson<ErrorResponse>)> {
+    let client = get_client();
+    let wallet = get_wallet();
+    let ledger = LEDGER.get().ok_or_else(|| {
+        error_response(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "ledger_not_configured",
+            "Statements need a local ledger; set FINTERNET_LEDGER_DB".to_string(),
+        )
+    })?;
+
+    let month = YearMonth::from_str(&query.month)
+        .map_err(|e| error_response(StatusCode::BAD_REQUEST, "invalid_month", e.to_string()))?;
+    let mint = match query.mint.as_deref() {
+        Some(m) if m.eq_ignore_ascii_case("usdc") => Some(usdc::devnet_mint()),
+        Some(m) => Some(
+            Pubkey::from_str(m).map_err(|e| error_response(StatusCode::BAD_REQUEST, "invalid_mint", e.to_string()))?,
+        ),
+        None => None,
+    };
+    let index = std::env::var("FINTERNET_IDENTITY_INDEX")
+        .ok()
+        .and_then(|path| IdentityIndex::load(std::path::Path::new(&path)).ok());
+
+    let statement = client
+        .generate_statement(ledger, &wallet.pubkey(), month, mint, index.as_ref(), query.allow_partial)
+        .await
+        .map_err(|e| match e.downcast_ref::<TruncatedHistory>() {
+            Some(truncated) => kind_response(StatusCode::CONFLICT, truncated.into(), truncated.code(), truncated.to_string()),
+            None => sdk_error_response(StatusCode::BAD_GATEWAY, "statement_failed", &e),
+        })?;
+
+    match query.format.as_deref() {
+        Some("markdown") | Some("md") => Ok((
+            [(header::CONTENT_TYPE, "text/markdown; charset=utf-8")],
+            statement.to_markdown_with(client.amount_format()),
+        )
+            .into_response()),
+        _ => Ok(ResponseJson(statement).into_response()),
+    }
+}
+
+#[cfg(not(feature = "sqlite"))]
+async fn get_statement() -> (StatusCode, ResponseJson<ErrorResponse>) {
+    error_response(
+        StatusCode::NOT_IMPLEMENTED,
+        "ledger_not_compiled",
+        "Statements need a local ledger, which needs a build with --features sqlite".to_string(),
+    )
+}
+
+#[derive(Debug, Deserialize)]
+struct ReconcileRequest {
+    /// Expected payments with amounts in base units
+    #[serde(default)]
+    expected: Vec<ExpectedPayment>,
+    /// Or the billing system's CSV export, with amounts in whole units
+    expected_csv: Option<String>,
+    /// First and last day of the window, `YYYY-MM-DD` in UTC
+    from: String,
+    to: String,
+}
+
+/// Reconcile expected payments against what the server wallet received in the window
+async fn reconcile_payments(
+    Json(payload): Json<ReconcileRequest>,
+) -> Result<ResponseJson<ReconciliationReport>, (StatusCode, ResponseJson<ErrorResponse>)> {
+    let client = get_client();
+    let wallet = get_wallet();
+
+    let day = |value: &str| {
+        chrono::NaiveDate::parse_from_str(value, "%Y-%m-%d").map_err(|_| {
+            error_response(StatusCode::BAD_REQUEST, "invalid_date", format!("Expected YYYY-MM-DD, got '{}'", value))
+        })
+    };
+    let window = reconcile::day_window(day(&payload.from)?, day(&payload.to)?);
+    let mut expected = payload.expected;
+    if let Some(csv) = &payload.expected_csv {
+        expected.extend(
+            client
+                .expected_payments_from_csv(csv)
+                .map_err(|e| error_response(StatusCode::BAD_REQUEST, "invalid_expected_payments", e.to_string()))?,
+        );
+    }
+
+    client
+        .reconcile(expected, &wallet.pubkey(), window)
+        .await
+        .map(ResponseJson)
+        .map_err(|e| sdk_error_response(StatusCode::BAD_GATEWAY, "reconcile_failed", &e))
+}
+
+#[derive(Debug, Deserialize)]
+struct PageQuery {
+    offset: Option<usize>,
+    limit: Option<usize>,
+}
+
+async fn get_issued_assets(
+    Path(address): Path<String>,
+    Query(page): Query<PageQuery>,
+) -> Result<ResponseJson<IssuedAssetPage>, (StatusCode, ResponseJson<ErrorResponse>)> {
+    let client = get_client();
+    let issuer = Pubkey::from_str(&address)
+        .map_err(|e| error_response(StatusCode::BAD_REQUEST, "invalid_address", e.to_string()))?;
+
+    let limit = page.limit.unwrap_or(50).min(200);
+    client
+        .get_issued_assets_page(&issuer, page.offset.unwrap_or(0), limit)
+        .await
+        .map(ResponseJson)
+        .map_err(|e| sdk_error_response(StatusCode::BAD_GATEWAY, "issued_assets_failed", &e))
+}
+
+#[derive(Debug, Deserialize)]
+struct RegisterAlertsRequest {
+    /// Wallet to watch; the server wallet when omitted
+    address: Option<String>,
+    /// `<currency>:<amount>`, e.g. `sol:0.2` or `usdc:500`
+    alerts: Vec<String>,
+    /// Receives a JSON `AlertWebhookPayload` POST for every crossing
+    webhook_url: String,
+    /// Seconds between balance checks; 30 by default
+    interval_secs: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+struct RegisterAlertsResponse {
+    id: String,
+    address: String,
+    thresholds: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct AlertWebhookPayload {
+    alert_id: String,
+    address: String,
+    direction: AlertDirection,
+    currency: String,
+    mint: Option<String>,
+    /// Base units
+    current: u64,
+    threshold: u64,
+    current_display: String,
+    threshold_display: String,
+    checked_at: u64,
+}
+
+impl AlertWebhookPayload {
+    fn new(alert_id: &str, alert: &AlertTriggered) -> Self {
+        let currency = &alert.threshold.currency;
+        Self {
+            alert_id: alert_id.to_string(),
+            address: alert.owner.to_string(),
+            direction: alert.direction,
+            currency: currency.label(),
+            mint: currency.mint().map(|m| m.to_string()),
+            current: alert.current,
+            threshold: alert.threshold.level,
+            current_display: get_client().amount_format().format(currency, alert.current),
+            threshold_display: get_client().amount_format().format(currency, alert.threshold.level),
+            checked_at: alert.checked_at,
+        }
+    }
+}
+
+/// Watch a wallet's balances in the background and POST each threshold crossing to a webhook
+async fn register_balance_alerts(
+    Json(body): Json<RegisterAlertsRequest>,
+) -> Result<ResponseJson<RegisterAlertsResponse>, (StatusCode, ResponseJson<ErrorResponse>)> {
+    let owner = match &body.address {
+        Some(address) => Pubkey::from_str(address)
+            .map_err(|_| error_response(StatusCode::BAD_REQUEST, "invalid_address", format!("Invalid address '{}'", address)))?,
+        None => get_wallet().pubkey(),
+    };
+    if body.alerts.is_empty() {
+        return Err(error_response(StatusCode::BAD_REQUEST, "invalid_alert", "At least one alert is required".to_string()));
+    }
+    let thresholds = body
+        .alerts
+        .iter()
+        .map(|alert| alert.parse::<BalanceThreshold>())
+        .collect::<anyhow::Result<Vec<_>>>()
+        .map_err(|e| error_response(StatusCode::BAD_REQUEST, "invalid_alert", e.to_string()))?;
+    let webhook_url = reqwest::Url::parse(&body.webhook_url)
+        .map_err(|e| error_response(StatusCode::BAD_REQUEST, "invalid_webhook_url", e.to_string()))?;
+    let interval = std::time::Duration::from_secs(body.interval_secs.unwrap_or(30).max(1));
+
+    let id = uuid::Uuid::new_v4().to_string();
+    let response = RegisterAlertsResponse {
+        id: id.clone(),
+        address: owner.to_string(),
+        thresholds: thresholds.iter().map(|t| t.to_string()).collect(),
+    };
+    let watcher = async move {
+        get_client()
+            .watch_balance_alerts(&owner, thresholds, interval, |alert| {
+                println!("🔔 Alert {}: {}", id, alert);
+                match serde_json::to_value(AlertWebhookPayload::new(&id, &alert)) {
+                    Ok(payload) => {
+                        tokio::spawn(dispatch_webhook("balance_alert", webhook_url.to_string(), payload));
+                    }
+                    Err(e) => eprintln!("Could not encode alert {}: {}", id, e),
+                }
+            })
+            .await
+    };
+    get_client()
+        .tasks()
+        .spawn("balance_alerts", watcher)
+        .map_err(|e| error_response(StatusCode::SERVICE_UNAVAILABLE, "shutting_down", e.to_string()))?;
+    Ok(ResponseJson(response))
+}
+
+fn get_deposits() -> &'static DepositManager {
+    DEPOSITS.get().expect("Deposit manager not initialized")
+}
+
+#[derive(Debug, Deserialize)]
+struct AssignDepositAddressRequest {
+    customer_id: String,
+}
+
+#[derive(Debug, Serialize)]
+struct DepositAddressResponse {
+    customer_id: String,
+    address: String,
+    token_account: String,
+    mint: String,
+    /// Base units credited so far
+    credited: u64,
+    deposits: usize,
+}
+
+impl DepositAddressResponse {
+    fn new(address: &DepositAddress) -> Self {
+        Self {
+            customer_id: address.customer_id.clone(),
+            address: address.address.to_string(),
+            token_account: address.token_account.to_string(),
+            mint: get_deposits().mint().to_string(),
+            credited: address.credited,
+            deposits: address.deposits,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct DepositWebhookPayload {
+    customer_id: String,
+    signature: String,
+    from: String,
+    to: String,
+    mint: Option<String>,
+    /// Base units
+    amount: u64,
+    amount_display: String,
+    /// Customer's credited total including this deposit
+    credited: u64,
+    slot: u64,
+    timestamp: u64,
+}
+
+impl DepositWebhookPayload {
+    fn new(deposit: &DepositDetected) -> Self {
+        let record = &deposit.record;
+        Self {
+            customer_id: deposit.customer_id.clone(),
+            signature: record.signature.to_string(),
+            from: record.from.to_string(),
+            to: record.to.to_string(),
+            mint: record.currency.mint().map(|m| m.to_string()),
+            amount: record.amount,
+            amount_display: get_client().amount_format().format(&record.currency, record.amount),
+            credited: deposit.credited,
+            slot: record.slot,
+            timestamp: record.timestamp,
+        }
+    }
+}
+
+/// The customer's deposit address, assigned on first request
+async fn assign_deposit_address(
+    Json(body): Json<AssignDepositAddressRequest>,
+) -> Result<ResponseJson<DepositAddressResponse>, (StatusCode, ResponseJson<ErrorResponse>)> {
+    let deposits = get_deposits();
+    deposits
+        .assign_address(&body.customer_id)
+        .map_err(|e| error_response(StatusCode::BAD_REQUEST, "invalid_customer_id", e.to_string()))?;
+    let address = deposits
+        .get(&body.customer_id)
+        .map_err(|e| sdk_error_response(StatusCode::INTERNAL_SERVER_ERROR, "deposit_store_failed", &e))?
+        .ok_or_else(|| error_response(StatusCode::INTERNAL_SERVER_ERROR, "deposit_store_failed", "Assigned address was not stored".to_string()))?;
+    Ok(ResponseJson(DepositAddressResponse::new(&address)))
+}
+
+async fn get_deposit_address(
+    Path(customer_id): Path<String>,
+) -> Result<ResponseJson<DepositAddressResponse>, (StatusCode, ResponseJson<ErrorResponse>)> {
+    let address = get_deposits()
+        .get(&customer_id)
+        .map_err(|e| sdk_error_response(StatusCode::INTERNAL_SERVER_ERROR, "deposit_store_failed", &e))?
+        .ok_or_else(|| error_response(StatusCode::NOT_FOUND, "not_found", format!("No deposit address for {}", customer_id)))?;
+    Ok(ResponseJson(DepositAddressResponse::new(&address)))
+}
+
+#[derive(Debug, Deserialize)]
+struct SweepDepositsRequest {
+    /// Sweep only this customer's address; every address when omitted
+    customer_id: Option<String>,
+    /// Receives the funds; the server wallet when omitted
+    treasury: Option<String>,
+    /// Skip balances below this many base units
+    min_amount: Option<u64>,
+}
+
+/// Move deposited funds to the treasury, the server wallet paying the fees; admin only
+async fn sweep_deposits(
+    headers: HeaderMap,
+    Json(body): Json<SweepDepositsRequest>,
+) -> Result<ResponseJson<SweepReport>, (StatusCode, ResponseJson<ErrorResponse>)> {
+    require_admin(&headers)?;
+    let treasury = match &body.treasury {
+        Some(treasury) => Pubkey::from_str(treasury)
+            .map_err(|e| error_response(StatusCode::BAD_REQUEST, "invalid_treasury", e.to_string()))?,
+        None => get_wallet().pubkey(),
+    };
+    let options = SweepOptions {
+        min_amount: body.min_amount.unwrap_or(0),
+        close_empty_accounts: false,
+    };
+    let report = get_client()
+        .sweep_deposits(get_deposits(), get_wallet(), body.customer_id.as_deref(), &treasury, &options)
+        .await
+        .map_err(|e| sdk_error_response(StatusCode::BAD_GATEWAY, "sweep_failed", &e))?;
+    Ok(ResponseJson(report))
+}
+
+/// Credit deposits in the background, POSTing each one to `FINTERNET_DEPOSIT_WEBHOOK` if set
+async fn run_deposit_watcher(deposits: &'static DepositManager) {
+    let min_confirmations = std::env::var("FINTERNET_DEPOSIT_CONFIRMATIONS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_MIN_CONFIRMATIONS);
+    let interval = std::time::Duration::from_secs(
+        std::env::var("FINTERNET_DEPOSIT_POLL_SECS").ok().and_then(|v| v.parse().ok()).unwrap_or(15),
+    );
+    get_client()
+        .watch_deposits(deposits, min_confirmations, interval, |deposit| {
+            println!(
+                "💰 Deposit for {}: {} ({})",
+                deposit.customer_id,
+                get_client().amount_format().format(&deposit.record.currency, deposit.record.amount),
+                deposit.record.signature
+            );
+            let Some(url) = webhook_target(|targets| &targets.deposit, "FINTERNET_DEPOSIT_WEBHOOK") else {
+                return;
+            };
+            match serde_json::to_value(DepositWebhookPayload::new(&deposit)) {
+                Ok(payload) => {
+                    tokio::spawn(dispatch_webhook("deposit", url, payload));
+                }
+                Err(e) => eprintln!("Could not encode deposit {}: {}", deposit.record.signature, e),
+            }
+        })
+        .await
+}
+
+fn http_client() -> &'static reqwest::Client {
+    HTTP.get_or_init(reqwest::Client::new)
+}
+
+fn get_dead_letters() -> &'static DeadLetterQueue {
+    DEAD_LETTERS.get().expect("Dead-letter queue not initialized")
+}
+
+/// Delivery attempts per webhook before it is dead-lettered, from `FINTERNET_WEBHOOK_RETRIES`
+fn webhook_retry_policy() -> RetryPolicy {
+    let mut policy = RetryPolicy::default();
+    if let Some(attempts) = std::env::var("FINTERNET_WEBHOOK_RETRIES").ok().and_then(|v| v.parse().ok()) {
+        policy.max_attempts = attempts;
+    }
+    policy
+}
+
+/// Deliver a webhook with retries, dead-lettering it if every attempt fails
+async fn dispatch_webhook(source: &'static str, url: String, payload: serde_json::Value) {
+    let Err(failure) = webhook::deliver(http_client(), &url, &payload, &webhook_retry_policy()).await else {
+        return;
+    };
+    eprintln!("{} webhook to {} dead-lettered: {}", source, url, failure);
+    match get_dead_letters().push(source, &url, &payload, &failure) {
+        Ok((letter, queued)) => notify_dead_letter_growth(&letter, queued).await,
+        Err(e) => eprintln!("Could not store dead letter for {}: {}", url, e),
+    }
+}
+
+/// Tell `FINTERNET_DLQ_ALERT_WEBHOOK` once the queue reaches `FINTERNET_DLQ_ALERT_THRESHOLD` (default 1)
+///
+/// Tried once and never dead-lettered itself, so an unreachable alert target cannot feed the queue.
+async fn notify_dead_letter_growth(letter: &DeadLetter, queued: usize) {
+    let Some(alert_url) = webhook_target(|targets| &targets.dead_letter_alert, "FINTERNET_DLQ_ALERT_WEBHOOK") else {
+        return;
+    };
+    let threshold = std::env::var("FINTERNET_DLQ_ALERT_THRESHOLD")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(1);
+    let Some(payload) = dead_letter_alert(letter, queued, threshold) else {
+        return;
+    };
+    let once = RetryPolicy { max_attempts: 1, ..RetryPolicy::default() };
+    if let Err(e) = webhook::deliver(http_client(), &alert_url, &payload, &once).await {
+        eprintln!("Dead-letter alert to {} failed: {}", alert_url, e);
+    }
+}
+
+/// The alert for `letter` joining a queue now `queued` long, if that reaches `threshold`
+fn dead_letter_alert(letter: &DeadLetter, queued: usize, threshold: usize) -> Option<serde_json::Value> {
+    if queued < threshold {
+        return None;
+    }
+    Some(serde_json::json!({
+        "event": "dead_letter_queue_grew",
+        "queued": queued,
+        "latest": {
+            "id": letter.id,
+            "source": letter.source,
+            "url": letter.url,
+            "attempts": letter.attempts,
+            "last_error": letter.last_error,
+        },
+    }))
+}
+
+async fn list_dead_letters() -> Result<ResponseJson<Vec<DeadLetter>>, (StatusCode, ResponseJson<ErrorResponse>)> {
+    get_dead_letters()
+        .list()
+        .map(ResponseJson)
+        .map_err(|e| sdk_error_response(StatusCode::INTERNAL_SERVER_ERROR, "dead_letter_store_error", &e))
+}
+
+#[derive(Debug, Serialize)]
+struct RetryDeadLetterResponse {
+    id: String,
+    delivered: bool,
+    attempts: u32,
+}
+
+/// Re-attempt a dead letter now; delivered letters leave the queue, failed ones stay with the new error
+async fn retry_dead_letter(
+    Path(id): Path<String>,
+) -> Result<ResponseJson<RetryDeadLetterResponse>, (StatusCode, ResponseJson<ErrorResponse>)> {
+    let outcome = get_dead_letters()
+        .retry(http_client(), &id, &webhook_retry_policy())
+        .await
+        .map_err(|e| sdk_error_response(StatusCode::INTERNAL_SERVER_ERROR, "dead_letter_store_error", &e))?
+        .ok_or_else(|| error_response(StatusCode::NOT_FOUND, "dead_letter_not_found", format!("No dead letter {}", id)))?;
+    match outcome {
+        Ok(attempts) => Ok(ResponseJson(RetryDeadLetterResponse { id, delivered: true, attempts })),
+        Err(failure) => Err(error_response(StatusCode::BAD_GATEWAY, "webhook_delivery_failed", failure.to_string())),
+    }
+}
+
+fn bearer_token(headers: &HeaderMap) -> &str {
+    headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .unwrap_or_default()
+}
+
+/// Whether `presented` is `$FINTERNET_ADMIN_TOKEN`; never when no token is configured
+fn is_admin_token(presented: &str) -> bool {
+    let Some(expected) = std::env::var("FINTERNET_ADMIN_TOKEN").ok().filter(|token| !token.is_empty()) else {
+        return false;
+    };
+    // Compare digests so the time taken says nothing about how much of the token matched
+    sha2::Sha256::digest(presented.as_bytes()) == sha2::Sha256::digest(expected.as_bytes())
+}
+
+/// Refuse unless the request carries `$FINTERNET_ADMIN_TOKEN` or an `admin` API key as its bearer token
+///
+/// Admin endpoints stay closed when neither is configured.
+fn require_admin(headers: &HeaderMap) -> Result<(), (StatusCode, ResponseJson<ErrorResponse>)> {
+    let token_configured = std::env::var("FINTERNET_ADMIN_TOKEN").is_ok_and(|token| !token.is_empty());
+    if !token_configured && API_KEYS.get().is_none() {
+        return Err(error_response(
+            StatusCode::FORBIDDEN,
+            "admin_not_configured",
+            "Set FINTERNET_ADMIN_TOKEN or FINTERNET_API_KEYS to enable admin endpoints".to_string(),
+        ));
+    }
+    let presented = bearer_token(headers);
+    if is_admin_token(presented) {
+        return Ok(());
+    }
+    match API_KEYS.get().map(|keys| keys.authenticate(presented)).transpose() {
+        Ok(Some(Some(record))) if record.allows(ApiScope::Admin) => Ok(()),
+        Ok(Some(Some(record))) => Err(missing_scope(&record, ApiScope::Admin)),
+        Ok(_) => Err(error_response(StatusCode::UNAUTHORIZED, "unauthorized", "Invalid admin token".to_string())),
+        Err(e) => Err(sdk_error_response(StatusCode::INTERNAL_SERVER_ERROR, "api_key_store_error", &e)),
+    }
+}
+
+fn missing_scope(record: &ApiKeyRecord, scope: ApiScope) -> (StatusCode, ResponseJson<ErrorResponse>) {
+    error_response(
+        StatusCode::FORBIDDEN,
+        "missing_scope",
+        format!("API key '{}' lacks the '{}' scope", record.name, scope),
+    )
+}
+
+/// Scope a route needs once API keys are enabled; `None` for routes open to anyone
+///
+/// Routes not listed need `admin`, so a new endpoint stays closed until it is classified here.
+fn required_scope(method: &Method, path: &str) -> Option<ApiScope> {
+    match path {
+        "/health" | "/api/capabilities" => None,
+        "/metrics"
+        | "/api/wallet-info"
+        | "/api/organization"
+        | "/api/assets"
+        | "/api/transactions"
+        | "/api/transactions/export"
+        | "/api/asset/:mint_address"
+        | "/api/assets/metadata"
+        | "/api/portfolio"
+        | "/api/issued-assets/:address"
+        | "/api/statement"
+        | "/api/reconcile"
+        | "/api/precheck"
+        | "/api/analyze/:address"
+        | "/api/gate/:address"
+        | "/api/preview-payment"
+        | "/api/preview-tokenize"
+        | "/api/payment-requests/:id"
+        | "/api/payments/queue"
+        | "/api/payments/queue/:id"
+        | "/api/deposit-address/:customer_id" => Some(ApiScope::Read),
+        "/api/payment-requests" | "/api/deposit-address" if method == Method::POST => Some(ApiScope::PaymentsRequest),
+        "/api/send-payment" | "/api/payments/enqueue" | "/api/payment-intents" | "/api/deposits/sweep" => {
+            Some(ApiScope::PaymentsSend)
+        }
+        "/api/tokenize-asset"
+        | "/api/tokenization-drafts"
+        | "/api/tokenization-drafts/commit"
+        | "/api/tokenization-drafts/discard" => Some(ApiScope::AssetsTokenize),
+        "/api/alerts" | "/api/webhooks/dead-letters" | "/api/webhooks/dead-letters/:id/retry" => {
+            Some(ApiScope::WebhooksManage)
+        }
+        _ => Some(ApiScope::Admin),
+    }
+}
+
+/// Enforce `required_scope` for every route when `FINTERNET_API_KEYS` is set
+///
+/// The admin token passes everywhere, so keys can be issued before any exist.
+async fn authorize(mut request: Request, next: Next) -> Response {
+    let Some(keys) = API_KEYS.get() else {
+        return next.run(request).await;
+    };
+    let path = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|matched| matched.as_str().to_string())
+        .unwrap_or_else(|| request.uri().path().to_string());
+    let Some(scope) = required_scope(request.method(), &path) else {
+        return next.run(request).await;
+    };
+
+    let presented = bearer_token(request.headers());
+    if is_admin_token(presented) {
+        return next.run(request).await;
+    }
+    match keys.authenticate(presented) {
+        Ok(Some(record)) if record.allows(scope) => match check_member_role(&record, scope).await {
+            Ok(()) => {
+                // Handlers and `meter_usage` attribute the call to this key
+                request.extensions_mut().insert(record);
+                next.run(request).await
+            }
+            Err(refusal) => refusal.into_response(),
+        },
+        Ok(Some(record)) => missing_scope(&record, scope).into_response(),
+        Ok(None) => error_response(
+            StatusCode::UNAUTHORIZED,
+            "unauthorized",
+            "Send an API key as `Authorization: Bearer <key>`".to_string(),
+        )
+        .into_response(),
+        Err(e) => sdk_error_response(StatusCode::INTERNAL_SERVER_ERROR, "api_key_store_error", &e).into_response(),
+    }
+}
+
+/// Count each keyed request against its key's day, refusing it with `429` once the key's
+/// request quota is used up, and attribute the RPC calls and SDK operations it makes
+async fn meter_usage(request: Request, next: Next) -> Response {
+    let (Some(ledger), Some(key)) = (USAGE.get(), request.extensions().get::<ApiKeyRecord>().cloned()) else {
+        return next.run(request).await;
+    };
+    let now = unix_now();
+    if let Err(e) = ledger.admit_request(&key, now) {
+        let Some(exceeded) = e.downcast_ref::<QuotaError>() else {
+            return sdk_error_response(StatusCode::INTERNAL_SERVER_ERROR, "usage_store_error", &e).into_response();
+        };
+        let mut response = quota_error(exceeded).into_response();
+        let retry_after = exceeded.resets_at().saturating_sub(now);
+        if let Ok(value) = header::HeaderValue::from_str(&retry_after.to_string()) {
+            response.headers_mut().insert(header::RETRY_AFTER, value);
+        }
+        return response;
+    }
+    let (response, reading) = usage::metered(next.run(request)).await;
+    if let Err(e) = ledger.record_metered(&key.id, &reading, now) {
+        eprintln!("Could not record usage of API key {}: {}", key.id, e);
+    }
+    response
+}
+
+/// `429 quota_exceeded` for a spent request or volume quota
+fn quota_error(exceeded: &QuotaError) -> (StatusCode, ResponseJson<ErrorResponse>) {
+    let kind = FinternetError::from(exceeded);
+    let status = StatusCode::from_u16(kind.http_status()).unwrap_or(StatusCode::TOO_MANY_REQUESTS);
+    kind_response(status, kind, kind.identifier(), exceeded.to_string())
+}
+
+/// A payment counted against the calling key's daily volume, given back if it is not sent
+struct VolumeReservation {
+    key_id: String,
+    currency: Currency,
+    amount: u64,
+    reserved_at: u64,
+}
+
+impl VolumeReservation {
+    fn release(self) {
+        let Some(ledger) = USAGE.get() else {
+            return;
+        };
+        if let Err(e) = ledger.release_payment(&self.key_id, &self.currency, self.amount, self.reserved_at) {
+            eprintln!("Could not release usage of API key {}: {}", self.key_id, e);
+        }
+    }
+}
+
+/// Count `amount` against the calling key's daily volume before sending it; `None` for calls
+/// made without a key
+fn reserve_volume(
+    key: Option<&ApiKeyRecord>,
+    currency: &Currency,
+    amount: u64,
+) -> Result<Option<VolumeReservation>, (StatusCode, ResponseJson<ErrorResponse>)> {
+    let (Some(ledger), Some(key)) = (USAGE.get(), key) else {
+        return Ok(None);
+    };
+    let now = unix_now();
+    match ledger.reserve_payment(key, currency, amount, now) {
+        Ok(()) => Ok(Some(VolumeReservation { key_id: key.id.clone(), currency: currency.clone(), amount, reserved_at: now })),
+        Err(e) => Err(match e.downcast_ref::<QuotaError>() {
+            Some(exceeded) => quota_error(exceeded),
+            None => sdk_error_response(StatusCode::INTERNAL_SERVER_ERROR, "usage_store_error", &e),
+        }),
+    }
+}
+
+/// Refuse every call outside the `Read` scope with `403` while the server is read-only
+async fn refuse_writes(request: Request, next: Next) -> Response {
+    let path = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|matched| matched.as_str().to_string())
+        .unwrap_or_else(|| request.uri().path().to_string());
+    match required_scope(request.method(), &path) {
+        Some(scope) if scope != ApiScope::Read && request.method() != Method::GET => error_response(
+            StatusCode::FORBIDDEN,
+            "read_only",
+            format!("{} {} changes state, and this server is read-only", request.method(), path),
+        )
+        .into_response(),
+        _ => next.run(request).await,
+    }
+}
+
+/// Mark every response of a dry-run server, so no client takes a simulated send for a real one
+async fn mark_dry_run(request: Request, next: Next) -> Response {
+    let mut response = next.run(request).await;
+    response
+        .headers_mut()
+        .insert(DRY_RUN_HEADER, header::HeaderValue::from_static("true"));
+    response
+}
+
+/// Sign every JSON response body with the server wallet, errors and mutations included, so a
+/// payment confirmation can be kept as evidence
+async fn sign_response(request: Request, next: Next) -> Response {
+    let response = next.run(request).await;
+    let is_json = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("application/json"));
+    if !is_json {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let bytes = match axum::body::to_bytes(body, usize::MAX).await {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            return error_response(StatusCode::INTERNAL_SERVER_ERROR, "response_signing_failed", e.to_string())
+                .into_response()
+        }
+    };
+    let signed = serde_json::from_slice::<serde_json::Value>(&bytes)
+        .map_err(anyhow::Error::from)
+        .and_then(|body| FinternetClient::sign_api_response(get_wallet(), &body));
+    match signed {
+        Ok(signature) => {
+            let headers = [(SIGNATURE_HEADER, signature), (SIGNER_HEADER, get_wallet().pubkey().to_string())];
+            for (name, value) in headers {
+                if let Ok(value) = header::HeaderValue::from_str(&value) {
+                    parts.headers.insert(name, value);
+                }
+            }
+        }
+        Err(e) => tracing::warn!("Could not sign response: {}", e),
+    }
+    Response::from_parts(parts, axum::body::Body::from(bytes))
+}
+
+/// With `FINTERNET_ORG` set, the key's member needs at least operator for mutating routes and
+/// admin for admin routes; read routes need no role
+async fn check_member_role(record: &ApiKeyRecord, scope: ApiScope) -> Result<(), (StatusCode, ResponseJson<ErrorResponse>)> {
+    let Some(org) = ORG.get() else {
+        return Ok(());
+    };
+    let role = match scope {
+        ApiScope::Read => return Ok(()),
+        ApiScope::Admin => OrgRole::Admin,
+        _ => OrgRole::Operator,
+    };
+    let Some(member) = record.member else {
+        return Err(error_response(
+            StatusCode::FORBIDDEN,
+            "org_member_required",
+            format!("API key '{}' acts for no organization member", record.name),
+        ));
+    };
+    let organization = current_organization(org)
+        .await
+        .map_err(|e| sdk_error_response(StatusCode::BAD_GATEWAY, "rpc_error", &e))?;
+    if organization.has_role(&member, role) {
+        return Ok(());
+    }
+    Err(error_response(
+        StatusCode::FORBIDDEN,
+        "org_role_required",
+        format!("{} needs at least the {} role in organization {}", member, role, org),
+    ))
+}
+
+/// The server's organization, read from the ledger at most every `ORG_STATE_TTL`
+async fn current_organization(org: &Pubkey) -> anyhow::Result<Organization> {
+    if let Ok(state) = ORG_STATE.lock() {
+        if let Some((_, organization)) = state.as_ref().filter(|(read_at, _)| read_at.elapsed() < ORG_STATE_TTL) {
+            return Ok(organization.clone());
+        }
+    }
+    let organization = get_client()
+        .get_organization(org)
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("Organization {} is not on the ledger", org))?;
+    if let Ok(mut state) = ORG_STATE.lock() {
+        *state = Some((Instant::now(), organization.clone()));
+    }
+    Ok(organization)
+}
+
+/// The organization named by `FINTERNET_ORG`, with its members' roles
+async fn get_organization() -> Result<ResponseJson<Organization>, (StatusCode, ResponseJson<ErrorResponse>)> {
+    let Some(org) = ORG.get() else {
+        return Err(error_response(
+            StatusCode::NOT_FOUND,
+            "org_not_configured",
+            "No organization configured; set FINTERNET_ORG".to_string(),
+        ));
+    };
+    current_organization(org)
+        .await
+        .map(ResponseJson)
+        .map_err(|e| sdk_error_response(StatusCode::BAD_GATEWAY, "rpc_error", &e))
+}
+
+fn get_api_keys() -> Result<&'static ApiKeyStore, (StatusCode, ResponseJson<ErrorResponse>)> {
+    API_KEYS.get().ok_or_else(|| {
+        error_response(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "api_keys_not_configured",
+            "API keys are disabled; set FINTERNET_API_KEYS".to_string(),
+        )
+    })
+}
+
+#[derive(Debug, Deserialize)]
+struct CreateApiKeyRequest {
+    name: String,
+    scopes: Vec<String>,
+    /// Wallet the key acts for, checked against `FINTERNET_ORG` when set
+    #[serde(default)]
+    member: Option<String>,
+    /// Daily request and volume caps
+    #[serde(default)]
+    quota: Option<UsageQuota>,
+}
+
+#[derive(Debug, Serialize)]
+struct CreateApiKeyResponse {
+    /// Shown only here; the server keeps just its hash
+    key: String,
+    record: ApiKeyRecord,
+}
+
+/// Issue a scoped API key; admin only
+async fn create_api_key(
+    headers: HeaderMap,
+    Json(body): Json<CreateApiKeyRequest>,
+) -> Result<ResponseJson<CreateApiKeyResponse>, (StatusCode, ResponseJson<ErrorResponse>)> {
+    require_admin(&headers)?;
+    let keys = get_api_keys()?;
+    if body.name.trim().is_empty() {
+        return Err(error_response(StatusCode::BAD_REQUEST, "invalid_name", "Name the key after its user".to_string()));
+    }
+    let scopes = body
+        .scopes
+        .iter()
+        .map(|scope| ApiScope::from_str(scope))
+        .collect::<anyhow::Result<Vec<_>>>()
+        .map_err(|e| error_response(StatusCode::BAD_REQUEST, "invalid_scope", e.to_string()))?;
+    if scopes.is_empty() {
+        return Err(error_response(StatusCode::BAD_REQUEST, "invalid_scope", "An API key needs at least one scope".to_string()));
+    }
+
+    let member = body
+        .member
+        .as_deref()
+        .map(Pubkey::from_str)
+        .transpose()
+        .map_err(|e| error_response(StatusCode::BAD_REQUEST, "invalid_member", e.to_string()))?;
+
+    let (key, mut record) = keys
+        .create(body.name.trim(), &scopes, member)
+        .map_err(|e| sdk_error_response(StatusCode::INTERNAL_SERVER_ERROR, "api_key_store_error", &e))?;
+    if let Some(quota) = body.quota {
+        record = keys
+            .set_quota(&record.id, quota)
+            .map_err(|e| sdk_error_response(StatusCode::INTERNAL_SERVER_ERROR, "api_key_store_error", &e))?
+            .unwrap_or(record);
+    }
+    Ok(ResponseJson(CreateApiKeyResponse { key, record }))
+}
+
+/// Every issued key, revoked ones included; admin only
+async fn list_api_keys(
+    headers: HeaderMap,
+) -> Result<ResponseJson<Vec<ApiKeyRecord>>, (StatusCode, ResponseJson<ErrorResponse>)> {
+    require_admin(&headers)?;
+    get_api_keys()?
+        .list()
+        .map(ResponseJson)
+        .map_err(|e| sdk_error_response(StatusCode::INTERNAL_SERVER_ERROR, "api_key_store_error", &e))
+}
+
+/// Stop accepting a key; admin only
+async fn revoke_api_key(
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Result<ResponseJson<ApiKeyRecord>, (StatusCode, ResponseJson<ErrorResponse>)> {
+    require_admin(&headers)?;
+    get_api_keys()?
+        .revoke(&id)
+        .map_err(|e| sdk_error_response(StatusCode::INTERNAL_SERVER_ERROR, "api_key_store_error", &e))?
+        .map(ResponseJson)
+        .ok_or_else(|| error_response(StatusCode::NOT_FOUND, "api_key_not_found", format!("No API key {}", id)))
+}
+
+/// Replace a key's daily quota; an empty body lifts it. Admin only
+async fn set_api_key_quota(
+    headers: HeaderMap,
+    Path(id): Path<String>,
+    Json(quota): Json<UsageQuota>,
+) -> Result<ResponseJson<ApiKeyRecord>, (StatusCode, ResponseJson<ErrorResponse>)> {
+    require_admin(&headers)?;
+    get_api_keys()?
+        .set_quota(&id, quota)
+        .map_err(|e| sdk_error_response(StatusCode::INTERNAL_SERVER_ERROR, "api_key_store_error", &e))?
+        .map(ResponseJson)
+        .ok_or_else(|| error_response(StatusCode::NOT_FOUND, "api_key_not_found", format!("No API key {}", id)))
+}
+
+/// `/api/admin/usage` filters; days are UTC `YYYY-MM-DD` and default to today
+#[derive(Debug, Deserialize)]
+struct UsageQuery {
+    key: Option<String>,
+    from: Option<String>,
+    to: Option<String>,
+}
+
+/// Usage per API key over a range of days, inclusive; admin only
+async fn get_api_usage(
+    headers: HeaderMap,
+    Query(query): Query<UsageQuery>,
+) -> Result<ResponseJson<Vec<KeyUsage>>, (StatusCode, ResponseJson<ErrorResponse>)> {
+    require_admin(&headers)?;
+    let ledger = USAGE.get().ok_or_else(|| {
+        error_response(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "api_keys_not_configured",
+            "Usage is tracked per API key; set FINTERNET_API_KEYS".to_string(),
+        )
+    })?;
+    let today = usage::usage_day(unix_now());
+    let day = |value: Option<String>| -> Result<String, (StatusCode, ResponseJson<ErrorResponse>)> {
+        let Some(value) = value else {
+            return Ok(today.clone());
+        };
+        chrono::NaiveDate::parse_from_str(&value, "%Y-%m-%d")
+            .map(|date| date.format("%Y-%m-%d").to_string())
+            .map_err(|e| error_response(StatusCode::BAD_REQUEST, "invalid_date", format!("'{}' is not YYYY-MM-DD: {}", value, e)))
+    };
+    let (from, to) = (day(query.from)?, day(query.to)?);
+    if from > to {
+        return Err(error_response(StatusCode::BAD_REQUEST, "invalid_date", format!("{} is after {}", from, to)));
+    }
+    ledger
+        .usage(query.key.as_deref(), &from, &to)
+        .map(ResponseJson)
+        .map_err(|e| sdk_error_response(StatusCode::INTERNAL_SERVER_ERROR, "usage_store_error", &e))
+}
+
+/// Page of the action log, oldest first; admin only
+async fn get_action_log(
+    headers: HeaderMap,
+    Query(page): Query<PageQuery>,
+) -> Result<ResponseJson<ActionLogPage>, (StatusCode, ResponseJson<ErrorResponse>)> {
+    require_admin(&headers)?;
+    let log = get_client().action_log().ok_or_else(|| {
+        error_response(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "action_log_not_configured",
+            "The action log is disabled; set FINTERNET_ACTION_LOG".to_string(),
+        )
+    })?;
+    let mut page = log
+        .page(page.offset.unwrap_or(0), page.limit.unwrap_or(100).min(1000))
+        .map_err(|e| sdk_error_response(StatusCode::INTERNAL_SERVER_ERROR, "action_log_error", &e))?;
+    get_client()
+        .redact_action_page(&mut page)
+        .await
+        .map_err(|e| sdk_error_response(StatusCode::BAD_GATEWAY, "redactions_unavailable", &e))?;
+    Ok(ResponseJson(page))
+}
+
+/// Most mints `POST /api/assets/metadata` accepts in one request
+const MAX_METADATA_BATCH: usize = 100;
+/// How long browsers may reuse a metadata batch before revalidating it
+const METADATA_CACHE_CONTROL: &str = "public, max-age=60";
+
+#[derive(Debug, Deserialize)]
+struct AssetsMetadataRequest {
+    mints: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct AssetsMetadataResponse {
+    /// One entry per requested mint, in request order
+    assets: Vec<AssetMetadataEntry>,
+}
+
+#[derive(Debug, Serialize)]
+struct AssetMetadataEntry {
+    mint: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    metadata: Option<AssetMetadata>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<ErrorResponse>,
+}
+
+impl AssetMetadataEntry {
+    fn failed(mint: String, kind: FinternetError, error: &str, message: String) -> Self {
+        Self { mint, metadata: None, error: Some(error_body(kind, error, message)) }
+    }
+}
+
+/// Metadata for up to 100 mints from the client's cache, with an ETag over the account data
+async fn get_assets_metadata(
+    headers: HeaderMap,
+    Json(request): Json<AssetsMetadataRequest>,
+) -> Result<Response, (StatusCode, ResponseJson<ErrorResponse>)> {
+    if request.mints.len() > MAX_METADATA_BATCH {
+        return Err(error_response(
+            StatusCode::BAD_REQUEST,
+            "too_many_mints",
+            format!("At most {} mints per request, got {}", MAX_METADATA_BATCH, request.mints.len()),
+        ));
+    }
+    let parsed: Vec<Option<Pubkey>> = request.mints.iter().map(|mint| Pubkey::from_str(mint).ok()).collect();
+    let valid: Vec<Pubkey> = parsed.iter().flatten().copied().collect();
+    let mut results = get_client()
+        .get_assets_metadata(&valid)
+        .await
+        .map_err(|e| sdk_error_response(StatusCode::BAD_GATEWAY, "rpc_error", &e))?
+        .into_iter();
+
+    let mut hasher = sha2::Sha256::new();
+    let mut assets = Vec::with_capacity(request.mints.len());
+    for (mint, pubkey) in request.mints.into_iter().zip(parsed) {
+        let entry = match pubkey.and_then(|_| results.next()) {
+            None => AssetMetadataEntry::failed(mint, FinternetError::InvalidInput, "invalid_address", "Not a valid base58 address".to_string()),
+            Some(Ok(asset)) => {
+                hasher.update(format!("{}:{}\n", mint, asset.etag));
+                AssetMetadataEntry { mint, metadata: Some(asset.metadata), error: None }
+            }
+            Some(Err(e)) => {
+                let kind = match FinternetError::of(&e) {
+                    FinternetError::Internal => FinternetError::NotFound,
+                    kind => kind,
+                };
+                AssetMetadataEntry::failed(mint, kind, "asset_not_found", e.to_string())
+            }
+        };
+        if let Some(error) = &entry.error {
+            hasher.update(format!("{}!{}\n", entry.mint, error.message));
+        }
+        assets.push(entry);
+    }
+    let etag = format!("\"{}\"", hasher.finalize().iter().map(|b| format!("{:02x}", b)).collect::<String>());
+
+    let cache_headers = [(header::ETAG, etag.clone()), (header::CACHE_CONTROL, METADATA_CACHE_CONTROL.to_string())];
+    let unchanged = headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.split(',').any(|tag| tag.trim().trim_start_matches("W/") == etag));
+    if unchanged {
+        return Ok((StatusCode::NOT_MODIFIED, cache_headers).into_response());
+    }
+    Ok((cache_headers, ResponseJson(AssetsMetadataResponse { assets })).into_response())
+}
+
+/// Most wallets `POST /api/portfolio` accepts in one request
+const MAX_PORTFOLIO_WALLETS: usize = 100;
+
+#[derive(Debug, Deserialize)]
+struct PortfolioRequest {
+    addresses: Vec<String>,
+    /// USD prices per whole unit; values are left empty without them
+    #[serde(default)]
+    prices: UsdPrices,
+}
+
+/// Balances of many wallets at once, per wallet and consolidated
+///
+/// A wallet that cannot be read carries an `error` rather than failing the request.
+async fn get_portfolio(
+    Json(request): Json<PortfolioRequest>,
+) -> Result<ResponseJson<Portfolio>, (StatusCode, ResponseJson<ErrorResponse>)> {
+    if request.addresses.is_empty() || request.addresses.len() > MAX_PORTFOLIO_WALLETS {
+        return Err(error_response(
+            StatusCode::BAD_REQUEST,
+            "invalid_wallet_count",
+            format!("Between 1 and {} addresses per request, got {}", MAX_PORTFOLIO_WALLETS, request.addresses.len()),
+        ));
+    }
+    let addresses = request
+        .addresses
+        .iter()
+        .map(|address| {
+            Pubkey::from_str(address).map_err(|_| {
+                error_response(StatusCode::BAD_REQUEST, "invalid_address", format!("Not a valid base58 address: {}", address))
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let portfolio = get_client()
+        .get_portfolio(&addresses)
+        .await
+        .map_err(|e| sdk_error_response(StatusCode::BAD_GATEWAY, "rpc_error", &e))?;
+    Ok(ResponseJson(portfolio.with_usd_prices(&request.prices)))
+}
+
+async fn get_asset_info(Path(mint_address): Path<String>) -> Result<ResponseJson<AssetMetadata>, StatusCode> {
+    let client = get_client();
+    
     let mint_pubkey = match Pubkey::from_str(&mint_address) {
         Ok(pk) => pk,
         Err(_) => return Err(StatusCode::BAD_REQUEST),
@@ -281,32 +3064,451 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     // Initialize the Finternet client and wallet
     initialize_client().await?;
+    // Background work runs as client tasks, so shutting down stops it before the stores are flushed
+    let tasks = get_client().tasks();
+    #[cfg(feature = "sqlite")]
+    if let Some(ledger) = LEDGER.get() {
+        tasks.spawn_until_shutdown("ledger_sync", run_ledger_sync(ledger))?;
+    }
+    if let Some(store) = PAYMENT_REQUESTS.get() {
+        tasks.spawn_until_shutdown("payment_request_expiry", run_payment_request_expiry(store))?;
+    }
+    if let Some(deposits) = DEPOSITS.get() {
+        tasks.spawn("deposit_watcher", run_deposit_watcher(deposits))?;
+    }
+    if let Some(queue) = PAYMENT_QUEUE.get() {
+        let wallet = Arc::new(get_wallet().insecure_clone());
+        tasks.spawn("payment_queue", Arc::clone(queue).run(Arc::clone(get_client()), wallet))?;
+    }
+    if let Some(path) = SERVER_CONFIG_PATH.get() {
+        tasks.spawn_until_shutdown("config_watcher", run_config_watcher(path))?;
+    }
 
-    // CORS configuration
+    // CORS configuration; with a settings file its origins are checked per request, so a reload
+    // changes them without rebuilding the router
+    let origins = match SERVER_CONFIG_PATH.get() {
+        Some(_) => AllowOrigin::predicate(|origin, _| origin_allowed(origin)),
+        None => AllowOrigin::from(Any),
+    };
     let cors = CorsLayer::new()
-        .allow_origin(Any)
+        .allow_origin(origins)
         .allow_methods([Method::GET, Method::POST, Method::OPTIONS])
-        .allow_headers(Any);
+        .allow_headers(Any)
+        .expose_headers([
+            header::HeaderName::from_static("x-finternet-signature"),
+            header::HeaderName::from_static("x-finternet-signer"),
+            header::HeaderName::from_static("x-finternet-dry-run"),
+        ]);
 
     // Build the router
     let app = Router::new()
         .route("/health", get(health_check))
+        .route("/api/capabilities", get(get_capabilities))
         .route("/api/tokenize-asset", post(tokenize_asset))
         .route("/api/send-payment", post(send_payment))
+        .route("/api/preview-payment", post(preview_payment))
+        .route("/api/preview-tokenize", post(preview_tokenize))
+        .route("/api/tokenization-drafts", post(prepare_tokenization))
+        .route("/api/tokenization-drafts/commit", post(commit_tokenization))
+        .route("/api/tokenization-drafts/discard", post(discard_tokenization))
+        .route("/api/payment-intents", post(submit_payment_intent))
         .route("/api/wallet-info", get(get_wallet_info))
         .route("/api/assets", get(get_owned_assets))
         .route("/api/transactions", get(get_transaction_history))
+        .route("/api/transactions/export", get(export_transaction_history))
         .route("/api/asset/:mint_address", get(get_asset_info))
-        .layer(cors);
+        .route("/api/assets/metadata", post(get_assets_metadata))
+        .route("/api/portfolio", post(get_portfolio))
+        .route("/api/organization", get(get_organization))
+        .route("/api/issued-assets/:address", get(get_issued_assets))
+        .route("/api/statement", get(get_statement))
+        .route("/api/reconcile", post(reconcile_payments))
+        .route("/api/precheck", get(precheck_recipient))
+        .route("/api/analyze/:address", get(analyze_counterparty))
+        .route("/api/gate/:address", get(check_token_gate))
+        .route("/api/payment-requests", post(create_payment_request))
+        .route("/api/payment-requests/:id", get(get_payment_request))
+        .route("/api/payments/enqueue", post(enqueue_payment))
+        .route("/api/payments/queue", get(get_payment_queue))
+        .route("/api/payments/queue/:id", get(get_queued_payment))
+        .route("/api/alerts", post(register_balance_alerts))
+        .route("/api/deposit-address", post(assign_deposit_address))
+        .route("/api/deposit-address/:customer_id", get(get_deposit_address))
+        .route("/api/deposits/sweep", post(sweep_deposits))
+        .route("/api/webhooks/dead-letters", get(list_dead_letters))
+        .route("/api/action-log", get(get_action_log))
+        .route("/api/webhooks/dead-letters/:id/retry", post(retry_dead_letter))
+        .route("/api/admin/keys", get(list_api_keys).post(create_api_key))
+        .route("/api/admin/keys/:id/revoke", post(revoke_api_key))
+        .route("/api/admin/keys/:id/quota", post(set_api_key_quota))
+        .route("/api/admin/usage", get(get_api_usage));
+    #[cfg(feature = "metrics")]
+    let app = app.route("/metrics", get(phase_metrics));
+    let app = app.route_layer(middleware::from_fn(meter_usage));
+    let app = app.route_layer(middleware::from_fn(authorize));
+    let app = if sign_responses() { app.layer(middleware::from_fn(sign_response)) } else { app };
+    let app = if read_only() { app.route_layer(middleware::from_fn(refuse_writes)) } else { app };
+    let app = if get_client().is_dry_run() { app.layer(middleware::from_fn(mark_dry_run)) } else { app };
+    let app = if SERVER_CONFIG_PATH.get().is_some() { app.layer(middleware::from_fn(rate_limit)) } else { app };
+    let app = app.layer(cors);
 
-    let listener = tokio::net::TcpListener::bind("127.0.0.1:3001").await?;
+    let bind_address = server_config()
+        .and_then(|config| config.bind_address.clone())
+        .unwrap_or_else(|| "127.0.0.1:3001".to_string());
+    let listener = tokio::net::TcpListener::bind(&bind_address).await?;
     
-    println!("🚀 Finternet SDK API Server running on http://127.0.0.1:3001");
-    println!("📊 Health check: http://127.0.0.1:3001/health");
+    println!("🚀 Finternet SDK API Server running on http://{}", bind_address);
+    println!("📊 Health check: http://{}/health", bind_address);
     println!("🌐 Frontend should run on http://localhost:3000");
     println!("🔗 CORS enabled for frontend integration");
+    if get_client().is_dry_run() {
+        println!("🧪 Dry-run mode: transactions are simulated, never sent");
+    }
+    if read_only() {
+        println!("🔒 Read-only mode: calls that change state are refused");
+    }
+
+    axum::serve(listener, app)
+        .with_graceful_shutdown(async {
+            if let Err(e) = tokio::signal::ctrl_c().await {
+                eprintln!("Could not listen for Ctrl-C: {}", e);
+                std::future::pending::<()>().await;
+            }
+            println!("🛑 Shutting down: finishing requests and background tasks");
+        })
+        .await?;
 
-    axum::serve(listener, app).await?;
+    let report = get_client().shutdown().await;
+    if !report.aborted.is_empty() {
+        eprintln!("Aborted background tasks that did not stop in time: {}", report.aborted.join(", "));
+    }
+    for (name, error) in &report.flush_errors {
+        eprintln!("Flushing {} failed: {}", name, error);
+    }
+    println!("👋 Shut down cleanly: {} background tasks stopped", report.stopped.len());
 
     Ok(())
-} 
\ No newline at end of file
+} 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    /// The server's key store, backed by memory; every test shares it as the server would
+    fn api_keys() -> &'static ApiKeyStore {
+        API_KEYS.get_or_init(|| ApiKeyStore::new(Arc::new(MemoryStateStore::new())))
+    }
+
+    /// The scoped routes under test behind the real `authorize` layer, each answering "ok"
+    fn scoped_router() -> Router {
+        Router::new()
+            .route("/health", get(|| async { "ok" }))
+            .route("/api/wallet-info", get(|| async { "ok" }))
+            .route("/api/send-payment", post(|| async { "ok" }))
+            .route("/api/payment-requests", post(|| async { "ok" }))
+            .route("/api/admin/keys", get(|| async { "ok" }))
+            .route_layer(middleware::from_fn(authorize))
+    }
+
+    /// Call `path` with `key` as the bearer token, returning the status and JSON error code
+    async fn call(method: Method, path: &str, key: Option<&str>) -> (StatusCode, Option<serde_json::Value>) {
+        let mut request = Request::builder().method(method).uri(path);
+        if let Some(key) = key {
+            request = request.header(header::AUTHORIZATION, format!("Bearer {}", key));
+        }
+        // A router is always ready, so it can be called without polling readiness first
+        let request = request.body(axum::body::Body::empty()).unwrap();
+        let response = tower::Service::call(&mut scoped_router(), request).await.unwrap();
+        let status = response.status();
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, serde_json::from_slice(&bytes).ok())
+    }
+
+    #[tokio::test]
+    async fn a_read_only_key_reads_wallet_info_but_cannot_send_a_payment() {
+        let (key, _) = api_keys().create("frontend", &[ApiScope::Read], None).unwrap();
+
+        let (status, _) = call(Method::GET, "/api/wallet-info", Some(&key)).await;
+        assert_eq!(status, StatusCode::OK);
+
+        let (status, body) = call(Method::POST, "/api/send-payment", Some(&key)).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        let body = body.unwrap();
+        assert_eq!(body["error"], "missing_scope");
+        assert_eq!(body["message"], "API key 'frontend' lacks the 'payments:send' scope");
+    }
+
+    #[tokio::test]
+    async fn each_key_reaches_only_the_routes_its_scopes_cover() {
+        let (billing, _) = api_keys().create("billing", &[ApiScope::PaymentsRequest], None).unwrap();
+        let (payer, _) = api_keys().create("payer", &[ApiScope::PaymentsSend], None).unwrap();
+        let (admin, _) = api_keys().create("admin", &[ApiScope::Admin], None).unwrap();
+
+        let expected = [
+            (&billing, Method::POST, "/api/payment-requests", StatusCode::OK),
+            (&billing, Method::POST, "/api/send-payment", StatusCode::FORBIDDEN),
+            (&billing, Method::GET, "/api/wallet-info", StatusCode::FORBIDDEN),
+            (&payer, Method::POST, "/api/send-payment", StatusCode::OK),
+            (&payer, Method::GET, "/api/admin/keys", StatusCode::FORBIDDEN),
+            // Admin grants every other scope
+            (&admin, Method::GET, "/api/wallet-info", StatusCode::OK),
+            (&admin, Method::POST, "/api/send-payment", StatusCode::OK),
+            (&admin, Method::POST, "/api/payment-requests", StatusCode::OK),
+            (&admin, Method::GET, "/api/admin/keys", StatusCode::OK),
+        ];
+        for (key, method, path, status) in expected {
+            assert_eq!(call(method.clone(), path, Some(key)).await.0, status, "{} {}", method, path);
+        }
+        let (_, body) = call(Method::GET, "/api/admin/keys", Some(&payer)).await;
+        assert_eq!(body.unwrap()["message"], "API key 'payer' lacks the 'admin' scope");
+    }
+
+    #[tokio::test]
+    async fn missing_unknown_and_revoked_keys_are_unauthorized_except_on_open_routes() {
+        let (key, record) = api_keys().create("retired", &[ApiScope::Read], None).unwrap();
+        assert_eq!(call(Method::GET, "/api/wallet-info", Some(&key)).await.0, StatusCode::OK);
+        api_keys().revoke(&record.id).unwrap();
+
+        for presented in [None, Some("fk_not-a-key"), Some("not-even-prefixed"), Some(key.as_str())] {
+            let (status, body) = call(Method::GET, "/api/wallet-info", presented).await;
+            assert_eq!(status, StatusCode::UNAUTHORIZED, "{:?}", presented);
+            assert_eq!(body.unwrap()["error"], "unauthorized");
+            assert_eq!(call(Method::GET, "/health", presented).await.0, StatusCode::OK);
+        }
+    }
+
+    #[test]
+    fn unclassified_routes_need_admin_and_the_store_keeps_only_hashes() {
+        assert_eq!(required_scope(&Method::GET, "/health"), None);
+        assert_eq!(required_scope(&Method::GET, "/api/capabilities"), None);
+        assert_eq!(required_scope(&Method::POST, "/api/admin/keys"), Some(ApiScope::Admin));
+        assert_eq!(required_scope(&Method::POST, "/api/admin/keys/:id/revoke"), Some(ApiScope::Admin));
+        assert_eq!(required_scope(&Method::GET, "/api/not-yet-classified"), Some(ApiScope::Admin));
+
+        let (key, record) = api_keys().create("hashed", &[ApiScope::Read], None).unwrap();
+        let stored = api_keys().list().unwrap().into_iter().find(|listed| listed.id == record.id).unwrap();
+        assert_ne!(stored.key_hash, key);
+        assert!(!serde_json::to_string(&stored).unwrap().contains(&key));
+        assert_eq!(api_keys().authenticate(&key).unwrap().unwrap().id, record.id);
+    }
+
+    #[test]
+    fn each_intent_refusal_is_its_own_4xx_json_error() {
+        let refusals = [
+            IntentError::Malformed("nonce must not be empty".to_string()),
+            IntentError::InvalidSignature,
+            IntentError::Expired { expired_at: 1_715_342_400 },
+            IntentError::ReplayedNonce { nonce: "n-1".to_string() },
+            IntentError::LimitExceeded { limit: 100, spent: 90, requested: 11 },
+        ];
+        let mut statuses = HashSet::new();
+        let mut errors = HashSet::new();
+        for refusal in &refusals {
+            let (status, ResponseJson(body)) = intent_error_response(refusal);
+            assert!(status.is_client_error(), "{}: {}", refusal, status);
+            statuses.insert(status);
+            let json = serde_json::to_value(&body).unwrap();
+            assert_eq!(json["error"], refusal.code());
+            assert_eq!(json["message"], refusal.to_string());
+            errors.insert(refusal.code());
+        }
+        assert_eq!(statuses.len(), refusals.len());
+        assert_eq!(errors.len(), refusals.len());
+    }
+
+    #[tokio::test]
+    async fn a_dry_run_server_marks_every_response_and_never_reports_a_simulated_send_as_done() {
+        let simulated = DryRunOutcome {
+            operation: "send_payment".to_string(),
+            signature: solana_sdk::signature::Signature::new_unique(),
+            units_consumed: Some(4_210),
+            logs: Vec::new(),
+            simulation_error: None,
+        };
+        let marker = simulated.marker();
+        let mut router = Router::new()
+            .route("/health", get(|| async { "ok" }))
+            .route("/api/send-payment", post(move || async move { dry_run_response(&simulated) }))
+            .layer(middleware::from_fn(mark_dry_run));
+
+        for (method, path) in [(Method::GET, "/health"), (Method::POST, "/api/send-payment")] {
+            let request = Request::builder().method(method).uri(path).body(axum::body::Body::empty()).unwrap();
+            let response = tower::Service::call(&mut router, request).await.unwrap();
+            assert_eq!(response.headers()[DRY_RUN_HEADER], "true", "{}", path);
+            if path == "/api/send-payment" {
+                assert_eq!(response.status(), StatusCode::CONFLICT);
+                let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+                let body: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+                assert_eq!(body["error"], "dry_run");
+                assert!(body["message"].as_str().unwrap().contains(&marker), "{}", body);
+            }
+        }
+    }
+
+    #[test]
+    fn the_dead_letter_alert_fires_from_the_threshold_on_without_the_payload() {
+        let letter = DeadLetter {
+            id: "dl-1".to_string(),
+            source: "balance_alert".to_string(),
+            url: "https://hooks.example/finternet".to_string(),
+            payload: serde_json::json!({ "api_key": webhook::REDACTED, "amount": 5 }),
+            attempts: 3,
+            last_error: "HTTP status server error (500 Internal Server Error)".to_string(),
+            failed_at: 1_715_342_400,
+            last_attempt_at: 1_715_342_400,
+        };
+        assert_eq!(dead_letter_alert(&letter, 2, 3), None);
+
+        for queued in [3, 4] {
+            let alert = dead_letter_alert(&letter, queued, 3).unwrap();
+            assert_eq!(alert["event"], "dead_letter_queue_grew");
+            assert_eq!(alert["queued"], queued);
+            assert_eq!(alert["latest"]["id"], "dl-1");
+            assert_eq!(alert["latest"]["attempts"], 3);
+            assert_eq!(alert["latest"]["last_error"], letter.last_error);
+            // The alert names the letter; the payload stays in the queue
+            assert!(alert["latest"].get("payload").is_none());
+        }
+        // A threshold of zero or one alerts on every dead letter
+        assert!(dead_letter_alert(&letter, 1, 0).is_some() && dead_letter_alert(&letter, 1, 1).is_some());
+    }
+
+    /// Write a settings file limiting each payment of `mint` to `limit` base units
+    fn write_limit(path: &std::path::Path, mint: &Pubkey, limit: u64, bind_address: &str) {
+        let config = serde_json::json!({
+            "bind_address": bind_address,
+            "spending": { "max_per_transaction": { mint.to_string(): limit } },
+        });
+        std::fs::write(path, config.to_string()).unwrap();
+    }
+
+    /// POST `body` to `path` on a router with the real payment and health handlers
+    async fn request(method: Method, path: &str, body: serde_json::Value) -> (StatusCode, serde_json::Value) {
+        let mut router = Router::new()
+            .route("/health", get(health_check))
+            .route("/api/send-payment", post(send_payment));
+        let request = Request::builder()
+            .method(method)
+            .uri(path)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(axum::body::Body::from(body.to_string()))
+            .unwrap();
+        let response = tower::Service::call(&mut router, request).await.unwrap();
+        let status = response.status();
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, serde_json::from_slice(&bytes).unwrap_or_default())
+    }
+
+    /// A JSON-RPC node that only knows its genesis hash, so payments get past the mint check
+    /// to the spending policy and fail at the first RPC call after it
+    async fn genesis_only_rpc() -> String {
+        let node = Router::new().route(
+            "/",
+            post(|Json(call): Json<serde_json::Value>| async move {
+                let id = call["id"].clone();
+                ResponseJson(match call["method"].as_str() {
+                    Some("getGenesisHash") => {
+                        serde_json::json!({ "jsonrpc": "2.0", "id": id, "result": solana_sdk::hash::Hash::default().to_string() })
+                    }
+                    _ => serde_json::json!({ "jsonrpc": "2.0", "id": id, "error": { "code": -32601, "message": "Method not found" } }),
+                })
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, node).await });
+        url
+    }
+
+    fn config_generation() -> u64 {
+        CONFIG_STATUS.lock().unwrap().as_ref().unwrap().generation
+    }
+
+    // One test, since the running config, client and watcher are process-wide as in the server
+    #[tokio::test(flavor = "multi_thread")]
+    async fn a_reload_enforces_the_new_limit_on_the_next_payment_and_an_invalid_file_changes_nothing() {
+        let dir = std::env::temp_dir().join(format!("finternet-config-reload-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path: &'static std::path::Path = SERVER_CONFIG_PATH.get_or_init(|| dir.join("server.json"));
+        let mint = Pubkey::new_unique();
+        write_limit(path, &mint, 5_000_000_000, "127.0.0.1:3001");
+        let config = ServerConfig::load(path).unwrap();
+        let client = FinternetClient::new(FinternetConfig {
+            rpc_url: genesis_only_rpc().await,
+            ..FinternetConfig::default()
+        })
+        .with_spending_policy(config.spending_policy().unwrap())
+        .unwrap();
+        assert!(CLIENT.set(Arc::new(client)).is_ok());
+        let _ = WALLET.set(Keypair::new());
+        install_server_config(path, config);
+
+        let payment = |amount: &str| {
+            serde_json::json!({ "to": Keypair::new().pubkey().to_string(), "amount": amount, "token_mint": mint.to_string() })
+        };
+        let (status, body) = request(Method::POST, "/api/send-payment", payment("3")).await;
+        assert_ne!(status, StatusCode::FORBIDDEN, "{}", body);
+        let (status, body) = request(Method::POST, "/api/send-payment", payment("6")).await;
+        assert_eq!((status, body["error"].as_str()), (StatusCode::FORBIDDEN, Some("per_transaction_limit")));
+
+        // Lowering the limit applies to the very next request
+        write_limit(path, &mint, 2_000_000_000, "0.0.0.0:8080");
+        reload_server_config(path);
+        let (status, body) = request(Method::POST, "/api/send-payment", payment("3")).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert_eq!(body["error"], "per_transaction_limit");
+        assert!(body["message"].as_str().unwrap().contains("limit of 2000000000"), "{}", body);
+        // The bind address cannot change without a restart, so the running one stays
+        assert_eq!(server_config().unwrap().bind_address.as_deref(), Some("127.0.0.1:3001"));
+        let (_, health) = request(Method::GET, "/health", serde_json::Value::Null).await;
+        assert_eq!(health["config"]["generation"], 2);
+        assert!(health["config"]["last_error"].is_null());
+
+        // A limit of zero is invalid: the file is rejected whole and the old limit still applies
+        write_limit(path, &mint, 0, "127.0.0.1:3001");
+        reload_server_config(path);
+        let (_, health) = request(Method::GET, "/health", serde_json::Value::Null).await;
+        assert_eq!(health["config"]["generation"], 2);
+        assert_eq!(health["config"]["last_error"]["code"], "invalid_server_config");
+        assert!(health["config"]["last_error"]["message"].as_str().unwrap().contains("max_per_transaction"), "{}", health);
+        let (status, body) = request(Method::POST, "/api/send-payment", payment("3")).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert!(body["message"].as_str().unwrap().contains("limit of 2000000000"), "{}", body);
+        let (status, _) = request(Method::POST, "/api/send-payment", payment("1")).await;
+        assert_ne!(status, StatusCode::FORBIDDEN);
+
+        // The watcher reloads on SIGHUP, well before its next poll
+        let watcher = tokio::spawn(run_config_watcher(path));
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        write_limit(path, &mint, 1_000_000_000, "127.0.0.1:3001");
+        let sent = std::process::Command::new("kill")
+            .args(["-HUP", &std::process::id().to_string()])
+            .status()
+            .unwrap();
+        assert!(sent.success());
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while config_generation() < 3 && Instant::now() < deadline {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        assert_eq!(config_generation(), 3);
+        let (_, health) = request(Method::GET, "/health", serde_json::Value::Null).await;
+        assert!(health["config"]["last_error"].is_null());
+        let (status, body) = request(Method::POST, "/api/send-payment", payment("2")).await;
+        assert_eq!((status, body["error"].as_str()), (StatusCode::FORBIDDEN, Some("per_transaction_limit")));
+
+        // ...and when the file's modification time changes
+        tokio::time::sleep(Duration::from_millis(1_100)).await;
+        write_limit(path, &mint, 4_000_000_000, "127.0.0.1:3001");
+        let deadline = Instant::now() + Duration::from_secs(8);
+        while config_generation() < 4 && Instant::now() < deadline {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        watcher.abort();
+        assert_eq!(config_generation(), 4);
+        let (status, _) = request(Method::POST, "/api/send-payment", payment("3")).await;
+        assert_ne!(status, StatusCode::FORBIDDEN);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
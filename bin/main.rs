@@ -1,11 +1,59 @@
 use anyhow::Result;
 use clap::{Parser, Subcommand};
-use finternet_sdk::{FinternetClient, FinternetConfig};
+use futures::{StreamExt, TryStreamExt};
+use finternet_sdk::costs::{CategoryCosts, CostAmount};
+use finternet_sdk::credential::{CredentialStatus, SignedCredential};
+use finternet_sdk::action_log;
+use finternet_sdk::alerts::{AlertDirection, BalanceThreshold};
+use finternet_sdk::amount_format::{AmountFormatter, CurrencyDisplay, NumberLocale, Precision};
+use finternet_sdk::approval::{ApprovalAuthority, PaymentProposal, ProposalOptions};
+use finternet_sdk::asset::{derive_mint_keypair, AuthorityCustody, TokenizeOptions};
+use finternet_sdk::asset_policy::AssetPolicy;
+use finternet_sdk::bootstrap::{BootstrapOptions, BootstrapReport, StepStatus};
+use finternet_sdk::batch::{BatchAsset, BatchItemStatus, BatchJournal, BatchPayment};
+use finternet_sdk::claim::{self, ClaimablePayment};
+use finternet_sdk::demo::{self, DemoOptions, DemoReport, StageStatus as DemoStageStatus};
+use finternet_sdk::diagnostics::{DiagnosticReport, DiagnosticVerdict};
+use finternet_sdk::error_code::{ErrorEnvelope, FinternetError};
+use finternet_sdk::export::{self, ExportFormat};
+use finternet_sdk::gate::GateRequirement;
+use finternet_sdk::history::{HistoryCompleteness, HistoryFilter};
+use finternet_sdk::holdings_proof::{AccountDrift, HoldingsProof};
+use finternet_sdk::identity_bulk::{BulkIdentityStatus, BulkResult, IdentityAttestation, IdentityEntry};
+use finternet_sdk::identity_index::IdentityIndex;
+#[cfg(feature = "sqlite")]
+use finternet_sdk::local_ledger::LocalLedger;
+use finternet_sdk::localnet::{self, Localnet, LocalnetOptions};
+use finternet_sdk::memo_template::MemoTemplate;
+use finternet_sdk::metadata_watch::{ChangeSeverity, MetadataSnapshot};
+use finternet_sdk::mint_registry::{self, MintNetworkError};
+use finternet_sdk::organization::OrgRole;
+use finternet_sdk::payment_link::PaymentLink;
+use finternet_sdk::portfolio::{Holding, UsdPrices};
+use finternet_sdk::profile::{self, Profiles};
+use finternet_sdk::progress::ProgressEvent;
+use finternet_sdk::reconcile::{self, ReconciliationStatus};
+use finternet_sdk::rpc_options::RpcCallOptions;
+use finternet_sdk::state_bundle::{self, StateBundle, StateChangeKind};
+#[cfg(feature = "sqlite")]
+use finternet_sdk::statement::YearMonth;
+use finternet_sdk::cold_sweep::{SweepPlan, SweepSelection, TokenSelection};
+use finternet_sdk::sweep::{SweepAccountStatus, SweepOptions};
+use finternet_sdk::timelock::{TimelockClaim, TimelockHandle};
+use finternet_sdk::tokenization_draft::{AssetSpec, TokenizationDraft};
+use finternet_sdk::validation::{self, validate_asset_spec, validate_identity, validate_payment, Violation};
+use finternet_sdk::{
+    AccountStatus, Amount, RecipientError, AssetType, Currency, Edition, FinternetClient, FinternetConfig, MetadataBackend,
+};
+use indicatif::{ProgressBar, ProgressStyle};
 use log::info;
-use solana_sdk::{pubkey::Pubkey, signer::Signer};
+use solana_sdk::{pubkey::Pubkey, signature::Keypair, signer::Signer};
 use std::collections::HashMap;
 use std::str::FromStr;
 
+mod output;
+use output::{AssetRow, HistoryRow, PortfolioRow, TokenRow, ViewArgs};
+
 // USDC constants for CLI usage
 const USDC_DEVNET_MINT: &str = "4zMMC9srt5Ri5X14GAgXhaHii3GnPAEERYPJgZJDncDU";
 
@@ -26,6 +74,51 @@ struct Cli {
     
     #[arg(long, help = "Path to wallet file (defaults to ~/.config/solana/id.json)")]
     wallet: Option<String>,
+    
+    #[arg(long, help = "Connect with a profile from .finternet/profiles.json, e.g. the one localnet up writes; --rpc-url and --wallet take precedence")]
+    profile: Option<String>,
+    
+    #[arg(long, help = "Record all RPC traffic to a JSONL file for offline replay")]
+    record_rpc: Option<String>,
+    
+    #[arg(long, help = "Replay RPC traffic from a recording instead of contacting the network")]
+    replay_rpc: Option<String>,
+    
+    #[arg(long, help = "Base URL where token metadata JSON is hosted (<base>/<mint>.json)")]
+    metadata_uri_base: Option<String>,
+    
+    #[arg(long, help = "Force where asset metadata is recorded: metaplex or ledger_memo (default: detect)")]
+    metadata_backend: Option<MetadataBackend>,
+    
+    #[arg(long, help = "Asset policy JSON file to enforce on transfers of its mint; repeatable")]
+    asset_policy: Vec<String>,
+    
+    #[arg(long, help = "Append a hash-chained record of every action to this JSONL file")]
+    action_log: Option<String>,
+    
+    #[arg(long, help = "With no wallet set up, sign with a throwaway key saved to a session file instead of refusing")]
+    ephemeral_ok: bool,
+    
+    #[arg(long, help = "Build and simulate every transaction but send none, for any command")]
+    dry_run_all: bool,
+    
+    #[arg(long, global = true, help = "Write amounts for this locale: plain, en-US, en-GB, de-DE, or fr-FR (default: plain)")]
+    locale: Option<NumberLocale>,
+    
+    #[arg(long, global = true, help = "Show the unit of amounts as a code, a symbol, or none (default: code)")]
+    currency_display: Option<CurrencyDisplay>,
+    
+    #[arg(long, global = true, help = "Decimal places of amounts: trimmed, full, or a number (default: trimmed)")]
+    precision: Option<Precision>,
+    
+    #[arg(long, help = "Write and read ledger entries under this namespace (default: finternet)")]
+    namespace: Option<String>,
+    
+    #[arg(long, help = "Also read ledger entries of this namespace; repeatable")]
+    trusted_namespace: Vec<String>,
+    
+    #[arg(long, help = "Also read un-prefixed ledger entries written before namespaces")]
+    legacy_entries: bool,
 }
 
 #[derive(Subcommand)]
@@ -41,8 +134,48 @@ enum Commands {
         #[arg(short, long)]
         value: u64,
         
+        /// real_estate, invoice, commodity, security, receivable, or custom:<name>
         #[arg(short, long, default_value = "real_estate")]
         asset_type: String,
+        
+        #[arg(long)]
+        symbol: Option<String>,
+        
+        /// Creator and share as PUBKEY:SHARE; repeat for co-issuers, shares must sum to 100
+        #[arg(long = "creator")]
+        creators: Vec<String>,
+        
+        /// Secondary-sale royalty in basis points
+        #[arg(long, default_value = "0")]
+        seller_fee_bps: u16,
+        
+        /// Derive the mint address from this seed, so revealing it later proves you generated it
+        #[arg(long, conflicts_with = "mint_keypair")]
+        mint_seed: Option<String>,
+        
+        /// Keypair file of the mint to create
+        #[arg(long)]
+        mint_keypair: Option<String>,
+        
+        /// Who can mint more: `issuer`, `none`, or an address
+        #[arg(long, default_value = "issuer")]
+        mint_authority: AuthorityCustody,
+        
+        /// Who can freeze holders' accounts: `issuer`, `none`, or an address such as a compliance key
+        #[arg(long, default_value = "issuer")]
+        freeze_authority: AuthorityCustody,
+    },
+    
+    /// Tokenize an asset through a signed draft reviewed before minting (prepare, commit, discard)
+    Tokenize {
+        #[command(subcommand)]
+        action: TokenizeAction,
+    },
+    
+    /// Verify yourself as a listed creator of an asset
+    VerifyCreator {
+        #[arg(short, long)]
+        mint: String,
     },
     
     /// Send USDC payment to another wallet
@@ -50,11 +183,106 @@ enum Commands {
         #[arg(short, long)]
         to: String, // Public key as string
         
+        /// Amount in USDC, e.g. `12.50`, `1,000.50`, `1.000,50`, `$25`, or `0.5k`
         #[arg(short, long)]
-        amount: f64, // Amount in USDC
+        amount: String,
         
         #[arg(short, long)]
         memo: Option<String>,
+        
+        /// Build the memo from a template such as `INV-{invoice_id}|{customer}`
+        #[arg(long, conflicts_with = "memo")]
+        memo_template: Option<String>,
+        
+        /// `key=value` for a memo template placeholder; repeat for each one
+        #[arg(long = "memo-var", requires = "memo_template")]
+        memo_vars: Vec<String>,
+        
+        /// Send even if the recipient precheck finds a likely mistake
+        #[arg(short, long)]
+        yes: bool,
+        
+        /// Confirm by this time or do not send: RFC 3339, or `HH:MM` for today in local time
+        #[arg(long)]
+        deadline: Option<String>,
+    },
+    
+    /// Send many token payments from a JSON file, one transaction each
+    BatchPayment {
+        /// JSON array of {"id", "to", "amount", "memo"?}; amounts in token base units
+        #[arg(short, long, required_unless_present = "resume")]
+        file: Option<String>,
+        
+        /// Token mint; defaults to devnet USDC
+        #[arg(short, long)]
+        token_mint: Option<String>,
+        
+        /// Record per-item progress here so an interrupted run can be resumed
+        #[arg(short, long)]
+        journal: Option<String>,
+        
+        /// Reconcile an interrupted run's journal against the chain and finish it
+        #[arg(short, long, conflicts_with_all = ["file", "journal"])]
+        resume: Option<String>,
+        
+        /// Stop launching payments that could not confirm by this time: RFC 3339, or `HH:MM` today
+        #[arg(long, conflicts_with = "resume")]
+        deadline: Option<String>,
+    },
+    
+    /// Consolidate token balances held by many wallets into one account; this wallet pays the fees
+    Sweep {
+        /// `usdc` or a mint address
+        #[arg(short, long, default_value = "usdc")]
+        mint: String,
+        
+        /// Wallet or token account receiving the balances
+        #[arg(short, long)]
+        to: String,
+        
+        /// Skip balances below this amount, in whole units
+        #[arg(long, default_value = "0")]
+        min: String,
+        
+        /// Keypair files of the wallets to sweep, or directories of them
+        #[arg(short, long, required = true, num_args = 1..)]
+        keys: Vec<String>,
+        
+        /// Close emptied token accounts and reclaim their rent
+        #[arg(long)]
+        close: bool,
+    },
+    
+    /// Move this wallet's funds to cold storage through a reviewable plan file (plan, execute)
+    ColdSweep {
+        #[command(subcommand)]
+        action: ColdSweepAction,
+    },
+    
+    /// Write this wallet's un-prefixed ledger entries on an address again under the namespace
+    MigrateLedgerEntries {
+        /// Address the entries touch (default: this wallet)
+        #[arg(long)]
+        address: Option<String>,
+        
+        /// How many recent transactions to look through
+        #[arg(long)]
+        limit: Option<usize>,
+    },
+    
+    /// Tokenize many assets from a JSON file
+    BatchTokenize {
+        /// JSON array of {"id", "name", "description", "value", "asset_type"}
+        #[arg(short, long, required_unless_present = "resume")]
+        file: Option<String>,
+        
+        /// Record per-item progress here so an interrupted run can be resumed
+        #[arg(short, long)]
+        journal: Option<String>,
+        
+        /// Reconcile an interrupted run's journal against the chain and finish it
+        #[arg(short, long, conflicts_with_all = ["file", "journal"])]
+        resume: Option<String>,
     },
     
     /// Send any SPL token payment
@@ -62,23 +290,134 @@ enum Commands {
         #[arg(short, long)]
         to: String, // Public key as string
         
+        /// Amount in the token's base units; separators and a `k` or `m` suffix are accepted
         #[arg(short, long)]
-        amount: u64, // Amount in token lamports
+        amount: String,
         
         #[arg(short, long)]
         token_mint: String, // Token mint address
         
         #[arg(short, long)]
         memo: Option<String>,
+        
+        /// Build the memo from a template such as `INV-{invoice_id}|{customer}`
+        #[arg(long, conflicts_with = "memo")]
+        memo_template: Option<String>,
+        
+        /// `key=value` for a memo template placeholder; repeat for each one
+        #[arg(long = "memo-var", requires = "memo_template")]
+        memo_vars: Vec<String>,
+        
+        /// Send even if the recipient precheck finds a likely mistake
+        #[arg(short, long)]
+        yes: bool,
+    },
+    
+    /// Sign a token payment that needs a second wallet's approval, and save it for the approver
+    ProposePayment {
+        #[arg(short, long)]
+        to: String,
+        
+        #[arg(short, long)]
+        amount: u64, // Amount in token lamports
+        
+        #[arg(short, long)]
+        token_mint: String,
+        
+        /// Wallet that must counter-sign
+        #[arg(long)]
+        approver: String,
+        
+        /// Pay from this SPL multisig's token account so the chain enforces both signatures
+        #[arg(long)]
+        multisig: Option<String>,
+        
+        /// Durable nonce account controlled by this wallet; a new one is created if omitted
+        #[arg(long)]
+        nonce_account: Option<String>,
+        
+        #[arg(short, long)]
+        memo: Option<String>,
+        
+        #[arg(long, default_value = "24")]
+        valid_for_hours: u64,
+        
+        #[arg(short, long, default_value = "proposal.json")]
+        output: String,
+    },
+    
+    /// Check a payment proposal, counter-sign it, and broadcast it
+    ApprovePayment {
+        #[arg(short, long)]
+        file: String,
+        
+        /// Approve without asking
+        #[arg(short, long)]
+        yes: bool,
+    },
+    
+    /// Create a signed link asking to be paid, or pay one
+    PaymentLink {
+        #[command(subcommand)]
+        action: PaymentLinkAction,
+    },
+    
+    /// Publish or fetch a mint's transfer restrictions (enforced by this SDK, not on-chain)
+    AssetPolicy {
+        #[command(subcommand)]
+        action: AssetPolicyAction,
     },
     
     /// Get transaction history for wallet
     History {
+        /// Most recent transactions to fetch; sorting only reorders these
         #[arg(short, long, default_value = "10")]
         limit: usize,
         
         #[arg(short, long)]
         address: Option<String>, // If not provided, uses wallet address
+        
+        /// Identity index file used to show names next to addresses
+        #[arg(long)]
+        identity_index: Option<String>,
+        
+        /// Read from the local ledger written by `sync` instead of RPC
+        #[arg(long)]
+        local: bool,
+        
+        /// Local ledger database
+        #[arg(long, default_value = ".finternet/ledger.db")]
+        db: String,
+        
+        /// Show the fields of memos that match this template
+        #[arg(long)]
+        memo_template: Option<String>,
+        
+        /// Write the history to this file for accounting software instead of printing it
+        #[arg(long)]
+        export: Option<String>,
+        
+        /// Export format: `ofx` or `qif`
+        #[arg(long, default_value = "ofx")]
+        format: String,
+        
+        #[command(flatten)]
+        view: ViewArgs,
+    },
+    
+    /// Pull new transactions into the local ledger so `history --local` is instant; needs the
+    /// `sqlite` feature
+    Sync {
+        #[arg(short, long)]
+        address: Option<String>, // If not provided, uses wallet address
+        
+        /// Local ledger database
+        #[arg(long, default_value = ".finternet/ledger.db")]
+        db: String,
+        
+        /// Re-check unfinalized records afterwards and drop any whose transaction left the chain
+        #[arg(long)]
+        verify_finality: bool,
     },
     
     /// Get asset information by mint address
@@ -87,16 +426,244 @@ enum Commands {
         mint: String,
     },
     
+    /// Anchor a document's hash to an asset you issued
+    AttachDoc {
+        #[arg(short, long)]
+        mint: String,
+        #[arg(short, long)]
+        file: String,
+        /// Short label such as `deed` or `invoice`
+        #[arg(long = "type", default_value = "document")]
+        doc_type: String,
+    },
+    
+    /// Check a file against the documents anchored to an asset
+    VerifyDoc {
+        #[arg(short, long)]
+        mint: String,
+        #[arg(short, long)]
+        file: String,
+    },
+    
+    /// Sign a proof of what the wallet holds in some mints now, for a third party to verify
+    ProveHoldings {
+        /// Mint to include, or `usdc`; repeat for each one
+        #[arg(short, long = "mint", required = true)]
+        mints: Vec<String>,
+        
+        /// A challenge the verifier gave, signed into the proof
+        #[arg(short, long)]
+        challenge: Option<String>,
+        
+        #[arg(short, long)]
+        output: String,
+    },
+    
+    /// Check a holdings proof's signature and accounts against the cluster
+    VerifyHoldings {
+        #[arg(short, long)]
+        file: String,
+        
+        /// Require the proof to answer this challenge
+        #[arg(short, long)]
+        challenge: Option<String>,
+        
+        /// `mint=amount` in base units the proof must show at least; repeat for each mint
+        #[arg(long = "min")]
+        minimums: Vec<String>,
+    },
+    
     /// Get wallet balance information
     Balance {
         #[arg(short, long)]
         address: Option<String>, // If not provided, uses wallet address
     },
     
-    /// Get owned assets for a wallet
+    /// Watch balances and report when one crosses an alert threshold
+    Watch {
+        #[arg(short, long)]
+        address: Option<String>, // If not provided, uses wallet address
+        /// `<currency>:<amount>`, e.g. `sol:0.2` or `usdc:500`; repeat for each threshold
+        #[arg(long = "alert", required = true)]
+        alerts: Vec<String>,
+        /// Seconds between balance checks
+        #[arg(long, default_value = "30")]
+        interval: u64,
+    },
+    
+    /// Get owned assets for a wallet, or snapshot and diff their metadata
     Assets {
+        #[command(subcommand)]
+        action: Option<AssetsAction>,
+        
+        #[arg(short, long)]
+        address: Option<String>, // If not provided, uses wallet address
+        
+        /// Show at most this many, after sorting
+        #[arg(short, long)]
+        limit: Option<usize>,
+        
+        #[command(flatten)]
+        view: ViewArgs,
+    },
+    
+    /// List every asset ever issued by a wallet, including ones since transferred away
+    IssuedAssets {
+        #[arg(short, long)]
+        address: Option<String>, // If not provided, uses wallet address
+        
+        #[arg(short, long, default_value = "1")]
+        page: usize,
+        
+        #[arg(long, default_value = "20")]
+        per_page: usize,
+    },
+    
+    /// Summarize network fees and rent deposits paid over a date range
+    Costs {
+        #[arg(short, long)]
+        address: Option<String>, // If not provided, uses wallet address
+        
+        /// First day included, YYYY-MM-DD (UTC)
+        #[arg(long)]
+        from: String,
+        
+        /// Last day included, YYYY-MM-DD (UTC)
+        #[arg(long)]
+        to: String,
+        
+        /// SOL price in USD to also report USD amounts
+        #[arg(long)]
+        sol_usd: Option<f64>,
+    },
+    
+    /// Balances of several wallets, per wallet and consolidated
+    Portfolio {
+        /// Wallet to include; repeat for each. Only this wallet when omitted
+        #[arg(short, long = "address")]
+        addresses: Vec<String>,
+        
+        /// SOL price in USD to also report USD values
+        #[arg(long)]
+        sol_usd: Option<f64>,
+        
+        /// `<mint>:<price>` USD price of one whole token; repeat for each mint
+        #[arg(long = "token-usd")]
+        token_usd: Vec<String>,
+        
+        #[command(flatten)]
+        view: ViewArgs,
+    },
+    
+    /// Monthly statement with opening/closing balances, from the local ledger; needs the
+    /// `sqlite` feature
+    Statement {
+        /// Month as YYYY-MM (UTC)
+        #[arg(long)]
+        month: String,
+        
+        /// Token mint, or `usdc`; every mint when omitted
+        #[arg(long)]
+        mint: Option<String>,
+        
+        /// Write here instead of printing; `.json` writes JSON, anything else Markdown
+        #[arg(short, long)]
+        output: Option<String>,
+        
         #[arg(short, long)]
         address: Option<String>, // If not provided, uses wallet address
+        
+        /// Local ledger database written by `sync`
+        #[arg(long, default_value = ".finternet/ledger.db")]
+        db: String,
+        
+        /// Identity index file used to name counterparties
+        #[arg(long)]
+        identity_index: Option<String>,
+        
+        /// Build it even when truncated history leaves an opening balance unverified
+        #[arg(long)]
+        allow_partial: bool,
+    },
+    
+    /// Match a billing export of expected payments against what actually arrived
+    Reconcile {
+        /// CSV with a header row: id, payer, amount (whole units), mint, reference, memo, due_date
+        #[arg(short, long)]
+        expected: String,
+        
+        /// First day of the window, YYYY-MM-DD (UTC)
+        #[arg(long)]
+        from: String,
+        
+        /// Last day of the window, inclusive, YYYY-MM-DD (UTC)
+        #[arg(long)]
+        to: String,
+        
+        /// Write the full report as JSON
+        #[arg(short, long)]
+        output: Option<String>,
+        
+        #[arg(short, long)]
+        address: Option<String>, // If not provided, uses wallet address
+    },
+    
+    /// Stand up a new environment: wallet, SOL, USDC account, identity, and a sanity-check asset
+    Bootstrap {
+        /// Keypair to use, created if missing; the global --wallet takes precedence
+        #[arg(long, default_value = ".finternet/deployer.json")]
+        keypair: String,
+        
+        /// Progress file; re-running with it finishes an interrupted bootstrap
+        #[arg(long, default_value = ".finternet/bootstrap.json")]
+        state: String,
+        
+        #[arg(long, default_value = "Finternet Deployment")]
+        identity_name: String,
+        
+        /// Airdrop when the wallet holds less SOL than this
+        #[arg(long, default_value = "0.5")]
+        min_sol: String,
+        
+        /// Also read the sanity asset back through this API server, e.g. http://localhost:3000
+        #[arg(long)]
+        api_url: Option<String>,
+        
+        /// `text` or `json`
+        #[arg(long, default_value = "text")]
+        output: String,
+    },
+    
+    /// Run a local validator with the SDK's programs, a funded wallet, and a USDC stand-in (up, down, reset)
+    Localnet {
+        #[command(subcommand)]
+        action: LocalnetAction,
+    },
+    
+    /// Bundle local configuration and state files (never wallets) for another environment
+    ExportState {
+        /// Files or directories to bundle, relative to the working directory
+        #[arg(default_value = ".finternet")]
+        paths: Vec<String>,
+        
+        #[arg(short, long, default_value = "finternet-state.json.gz")]
+        output: String,
+    },
+    
+    /// Restore a bundle written by export-state into the working directory
+    ImportState {
+        #[arg(short, long)]
+        input: String,
+        
+        /// Only show which files would be added or changed
+        #[arg(long)]
+        dry_run: bool,
+    },
+    
+    /// Check that an action log's hash chain is intact
+    VerifyActionLog {
+        #[arg(default_value = ".finternet/actions.jsonl")]
+        path: String,
     },
     
     /// Create a new wallet
@@ -105,12 +672,80 @@ enum Commands {
         output_path: String,
     },
     
+    /// List throwaway session wallets left by --ephemeral-ok, or save one as a permanent wallet
+    RecoverSessionWallet {
+        /// Public key of the session wallet to keep; omit to list them
+        pubkey: Option<String>,
+        #[arg(short, long, help = "Where to save it, e.g. ~/.config/solana/id.json; never overwritten")]
+        output: Option<String>,
+    },
+    
     /// Get wallet info and identity
     WalletInfo {
         #[arg(short, long)]
         address: Option<String>,
     },
     
+    /// Probe RPC endpoints for latency and slot freshness to pick an --rpc-url
+    RpcBenchmark {
+        /// Endpoints to compare; defaults to the configured RPC URL
+        urls: Vec<String>,
+    },
+    
+    /// Time the reads and simulated write a payment depends on against the configured RPC
+    Diagnose {
+        /// Wallet whose history and token accounts are read (default: this wallet)
+        #[arg(short, long)]
+        address: Option<String>,
+        
+        /// Times each step is sampled
+        #[arg(long, default_value_t = finternet_sdk::diagnostics::DEFAULT_DIAGNOSTIC_SAMPLES)]
+        samples: usize,
+        
+        /// `text` or `json`
+        #[arg(long, default_value = "text")]
+        output: String,
+    },
+    
+    /// List the stable error codes, with the exit code and HTTP status of each
+    ErrorCodes {
+        /// `text` or `json`
+        #[arg(long, default_value = "text")]
+        output: String,
+    },
+    
+    /// On-chain reputation readout before accepting a large payment or shipping an asset
+    Analyze {
+        #[arg(short, long)]
+        address: Option<String>,
+    },
+    
+    /// Check whether a wallet holds a required asset, signing the result with this wallet
+    GateCheck {
+        /// Wallet to check
+        #[arg(short, long)]
+        address: String,
+        
+        /// Require this token: `usdc` or a mint address
+        #[arg(short, long, conflicts_with_all = ["collection", "asset_type"])]
+        mint: Option<String>,
+        
+        /// Minimum balance of --mint, in whole units
+        #[arg(long, requires = "mint")]
+        min: Option<String>,
+        
+        /// Require any asset of this verified collection
+        #[arg(short, long, conflicts_with = "asset_type")]
+        collection: Option<String>,
+        
+        /// Require an asset of this type issued by --issuer
+        #[arg(short = 't', long, requires = "issuer")]
+        asset_type: Option<String>,
+        
+        #[arg(short, long, requires = "asset_type")]
+        issuer: Option<String>,
+    },
+    
     /// Register identity on-chain
     RegisterIdentity {
         #[arg(short, long)]
@@ -123,6 +758,43 @@ enum Commands {
         organization: Option<String>,
     },
     
+    /// Ask readers to stop showing identity fields or memos this wallet published
+    Redact {
+        /// Identity metadata key to redact, or `display_name`; repeatable
+        #[arg(long = "field")]
+        fields: Vec<String>,
+        
+        /// Signature of a transaction whose memo to redact; repeatable
+        #[arg(long = "memo")]
+        memos: Vec<String>,
+    },
+    
+    /// Register a team's identities from a CSV of pubkey,display_name[,keypair][,metadata...]
+    RegisterIdentities {
+        /// Wallets without a keypair column are attested by this wallet instead, for their owner to confirm
+        #[arg(short, long, required_unless_present = "resume")]
+        file: Option<String>,
+        
+        /// Record per-entry progress here so an interrupted run can be resumed
+        #[arg(short, long, default_value = ".finternet/identities.json")]
+        journal: String,
+        
+        /// Finish the entries an interrupted run left pending
+        #[arg(short, long, conflicts_with = "file")]
+        resume: bool,
+        
+        /// Where to write each attestation, as <pubkey>.json
+        #[arg(long, default_value = ".finternet/attestations")]
+        attestations_dir: String,
+    },
+    
+    /// Countersign an identity attestation addressed to this wallet
+    ConfirmAttestation {
+        /// Attestation file written by register-identities
+        #[arg(short, long)]
+        file: String,
+    },
+    
     /// Setup devnet USDC for testing
     SetupUsdc {
         #[arg(short, long)]
@@ -133,126 +805,1357 @@ enum Commands {
     DiscoverTokens {
         #[arg(short, long)]
         address: Option<String>,
+        
+        /// Show at most this many, after sorting
+        #[arg(short, long)]
+        limit: Option<usize>,
+        
+        #[command(flatten)]
+        view: ViewArgs,
     },
     
-    /// Run enhanced demo
-    Demo,
+    /// Time-locked payments held in escrow (send, cancel, claim, reclaim, status)
+    Timelock {
+        #[command(subcommand)]
+        action: TimelockAction,
+    },
     
-    /// Test token creation and discovery with blockchain confirmation wait
-    TestTokenDiscovery {
-        #[arg(short, long, default_value = "20")]
-        wait_seconds: u64,
+    /// Pay someone without a wallet yet: they claim it with a secret you send them
+    SendClaimable {
+        /// Amount in whole units, e.g. 125 or 12.50
+        #[arg(short, long)]
+        amount: String,
+        
+        /// `usdc` or a token mint address
+        #[arg(short, long, default_value = "usdc")]
+        mint: String,
+        
+        /// Claim secret to use; a fresh one is generated when omitted
+        #[arg(long)]
+        secret: Option<String>,
+        
+        /// From then the sender can cancel and take the funds back
+        #[arg(long, default_value = "168")]
+        expires_in_hours: u64,
+        
+        /// Where to save the payment record, which holds the secret; keep it private
+        #[arg(short, long, default_value = "claim.json")]
+        output: String,
     },
-}
-
-#[tokio::main]
-async fn main() -> Result<()> {
-    env_logger::init();
     
-    let cli = Cli::parse();
+    /// Claim a payment sent with `send-claimable` into this wallet or `--to`
+    Claim {
+        #[arg(long)]
+        secret: String,
+        
+        /// Wallet to receive the funds; this wallet when omitted
+        #[arg(long)]
+        to: Option<String>,
+    },
     
-    // Initialize Finternet client
-    let config = if let Some(rpc_url) = cli.rpc_url {
-        FinternetConfig {
-            rpc_url,
-            commitment_level: "confirmed".to_string(),
-        }
-    } else {
-        FinternetConfig::default()
-    };
+    /// Take back an expired, unclaimed payment sent with `send-claimable`
+    CancelClaimable {
+        #[arg(short = 'f', long, default_value = "claim.json")]
+        claim: String,
+    },
     
-    let client = FinternetClient::new(config);
-    info!("Connected to Solana RPC: {}", client.config.rpc_url);
+    /// Redeem tokens with their issuer (request, list, settle)
+    Redeem {
+        #[command(subcommand)]
+        action: RedeemAction,
+    },
+    
+    /// Sell tokenized assets: list one, browse, reprice, cancel, or buy with an atomic swap
+    Listing {
+        #[command(subcommand)]
+        action: ListingAction,
+    },
+    
+    /// Issue, verify, or revoke verifiable credentials
+    Credential {
+        #[command(subcommand)]
+        action: CredentialAction,
+    },
+    
+    /// Build, refresh, or search an index of counterparties' registered identities
+    Identities {
+        #[command(subcommand)]
+        action: IdentitiesAction,
+    },
+    
+    /// Organizations sharing a treasury: create one, manage members and roles, show it
+    Org {
+        #[command(subcommand)]
+        action: OrgAction,
+    },
+    
+    /// Run the SDK demo, checking every stage; exits nonzero if any fails
+    Demo {
+        /// Seeded keypairs and a fixed clock against a local validator (the default RPC here),
+        /// a --replay-rpc recording of one, or the --recorded run; for CI
+        #[arg(long, conflicts_with = "live", required_unless_present = "live")]
+        self_contained: bool,
+        
+        /// Replay the self-contained run shipped with the SDK; needs no validator or network
+        #[arg(long, requires = "self_contained")]
+        recorded: bool,
+        
+        /// Use the configured wallet against the configured cluster
+        #[arg(long)]
+        live: bool,
+        
+        /// Derives the demo keypairs and stable mint
+        #[arg(long, default_value = finternet_sdk::demo::DEFAULT_DEMO_SEED)]
+        seed: String,
+        
+        /// `text` or `json`
+        #[arg(long, default_value = "text")]
+        output: String,
+    },
+    
+    /// Test token creation and discovery with blockchain confirmation wait
+    TestTokenDiscovery {
+        #[arg(short, long, default_value = "20")]
+        wait_seconds: u64,
+    },
+}
+
+#[derive(Subcommand)]
+enum LocalnetAction {
+    /// Start solana-test-validator, or reuse the running one, and write the `localnet` profile
+    Up {
+        #[command(flatten)]
+        options: LocalnetArgs,
+    },
+    
+    /// Stop the validator; its ledger is kept for the next `up`
+    Down {
+        #[arg(long, default_value = ".finternet/localnet")]
+        dir: String,
+    },
+    
+    /// Stop the validator, wipe its ledger, and start it again; the wallet and mint addresses are kept
+    Reset {
+        #[command(flatten)]
+        options: LocalnetArgs,
+    },
+}
+
+#[derive(clap::Args)]
+struct LocalnetArgs {
+    /// Workspace for the ledger, log, wallet, and mint keypair
+    #[arg(long, default_value = ".finternet/localnet")]
+    dir: String,
+    
+    #[arg(long, default_value = "8899")]
+    rpc_port: u16,
+    
+    /// Token Metadata program .so to deploy instead of cloning it
+    #[arg(long)]
+    token_metadata_so: Option<String>,
+    
+    /// Cluster to clone the Token Metadata program from
+    #[arg(long, default_value = localnet::MAINNET_RPC_URL)]
+    clone_url: String,
+}
+
+impl LocalnetArgs {
+    fn options(&self) -> LocalnetOptions {
+        LocalnetOptions {
+            dir: std::path::PathBuf::from(&self.dir),
+            rpc_port: self.rpc_port,
+            token_metadata_program: self.token_metadata_so.as_ref().map(std::path::PathBuf::from),
+            clone_url: self.clone_url.clone(),
+            ..LocalnetOptions::default()
+        }
+    }
+}
+
+#[derive(Subcommand)]
+enum TokenizeAction {
+    /// Stage the asset's metadata, estimate the mint, and write a signed draft; nothing is minted
+    Prepare {
+        #[arg(short, long)]
+        name: String,
+        
+        #[arg(short, long)]
+        description: String,
+        
+        #[arg(short, long)]
+        value: u64,
+        
+        /// real_estate, invoice, commodity, security, receivable, or custom:<name>
+        #[arg(short, long, default_value = "real_estate")]
+        asset_type: String,
+        
+        #[arg(long)]
+        symbol: Option<String>,
+        
+        /// Creator and share as PUBKEY:SHARE; repeat for co-issuers, shares must sum to 100
+        #[arg(long = "creator")]
+        creators: Vec<String>,
+        
+        /// Secondary-sale royalty in basis points
+        #[arg(long, default_value = "0")]
+        seller_fee_bps: u16,
+        
+        /// Hours the draft may be committed for
+        #[arg(long, default_value = "168")]
+        valid_for_hours: u64,
+        
+        #[arg(short, long, default_value = "tokenization-draft.json")]
+        output: String,
+    },
+    
+    /// Check a draft again, publish its metadata, and mint it
+    Commit {
+        #[arg(short = 'f', long)]
+        draft: String,
+    },
+    
+    /// Remove a draft's staged metadata
+    Discard {
+        #[arg(short = 'f', long)]
+        draft: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum ColdSweepAction {
+    /// Work out the sweep and write its unsigned transactions for review; nothing is signed
+    Plan {
+        /// Cold wallet receiving the funds
+        #[arg(short, long)]
+        to: String,
+        
+        /// Hot wallet to plan for (default: this wallet)
+        #[arg(long)]
+        from: Option<String>,
+        
+        /// SOL left in the hot wallet after the sweep and its fees
+        #[arg(long, default_value = "0.01")]
+        reserve: String,
+        
+        /// Leave SOL in the hot wallet
+        #[arg(long)]
+        no_sol: bool,
+        
+        /// Only sweep these mints (`usdc` or an address); repeatable (default: every token)
+        #[arg(short, long)]
+        mint: Vec<String>,
+        
+        /// Leave every token balance in the hot wallet
+        #[arg(long, conflicts_with = "mint")]
+        no_tokens: bool,
+        
+        /// Close emptied token accounts, sending their rent to the cold wallet
+        #[arg(long)]
+        close: bool,
+        
+        /// SOL the hot wallet's balance may drift from the plan before execution halts
+        #[arg(long, default_value = "0.0001")]
+        tolerance: String,
+        
+        #[arg(short, long, default_value = "cold-sweep.json")]
+        output: String,
+    },
+    
+    /// Check a plan against the chain again, then sign and send it, halting on any difference
+    Execute {
+        #[arg(short = 'f', long)]
+        plan: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum TimelockAction {
+    /// Lock a payment in escrow: the recipient can claim it once it unlocks, until it expires
+    Send {
+        #[arg(short, long)]
+        to: String,
+        
+        /// Amount in whole units, e.g. 125 or 12.50
+        #[arg(short, long)]
+        amount: String,
+        
+        /// `usdc` or a token mint address
+        #[arg(short, long, default_value = "usdc")]
+        mint: String,
+        
+        /// Until then only the sender can cancel
+        #[arg(long, default_value = "24")]
+        unlock_in_hours: u64,
+        
+        /// From then only the sender can reclaim
+        #[arg(long, default_value = "168")]
+        expires_in_hours: u64,
+        
+        #[arg(short, long, default_value = "timelock.json")]
+        output: String,
+    },
+    
+    /// Return a payment that has not unlocked yet to this wallet
+    Cancel {
+        #[arg(short = 'f', long)]
+        handle: String,
+    },
+    
+    /// Sign a claim to an unlocked payment addressed to this wallet, for its sender to countersign
+    Claim {
+        #[arg(short = 'f', long)]
+        handle: String,
+        
+        #[arg(short, long, default_value = "timelock-claim.json")]
+        output: String,
+    },
+    
+    /// Countersign a recipient's claim to a payment this wallet locked, and send it
+    Countersign {
+        #[arg(short = 'f', long)]
+        handle: String,
+        
+        /// Claim file signed by the recipient
+        #[arg(short, long)]
+        claim: String,
+    },
+    
+    /// Return an expired, unclaimed payment to this wallet
+    Reclaim {
+        #[arg(short = 'f', long)]
+        handle: String,
+    },
+    
+    /// Show where a time-locked payment stands and its ledger history
+    Status {
+        #[arg(short = 'f', long)]
+        handle: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum RedeemAction {
+    /// Ask the issuer of an asset to redeem tokens
+    Request {
+        #[arg(short, long)]
+        mint: String,
+        
+        #[arg(short, long)]
+        amount: u64,
+        
+        #[arg(short, long)]
+        payout_details: String,
+        
+        #[arg(short, long)]
+        redemption_address: Option<String>, // Return the tokens here along with the request
+    },
+    
+    /// List pending redemption requests addressed to an issuer
+    List {
+        #[arg(short, long)]
+        issuer: Option<String>, // If not provided, uses wallet address
+    },
+    
+    /// Settle a pending request with the signature of the payout transaction
+    Settle {
+        #[arg(short, long)]
+        request_id: String,
+        
+        #[arg(short, long)]
+        payment_signature: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum ListingAction {
+    /// Offer this wallet's whole holding of an asset for sale
+    Create {
+        #[arg(short, long)]
+        mint: String,
+        
+        /// Price in whole units of the currency, e.g. 1500 or 12.50
+        #[arg(short, long)]
+        price: String,
+        
+        /// `usdc` or the token mint the price is paid in
+        #[arg(short, long, default_value = "usdc")]
+        currency: String,
+        
+        #[arg(long, default_value = "168")]
+        expires_in_hours: u64,
+        
+        /// Wallet approved to deliver the asset to a buyer, which countersigns the swap
+        #[arg(long)]
+        settlement_authority: String,
+    },
+    
+    /// Active listings of an issuer's assets, or of one mint
+    List {
+        #[arg(short, long)]
+        address: Option<String>, // If not provided, uses wallet address
+    },
+    
+    /// Replace one of this wallet's listings with one at a new price
+    Reprice {
+        #[arg(short, long)]
+        listing_id: String,
+        
+        /// New price in whole units of the listing's currency
+        #[arg(short, long)]
+        price: String,
+    },
+    
+    /// Withdraw one of this wallet's listings
+    Cancel {
+        #[arg(short, long)]
+        listing_id: String,
+    },
+    
+    /// Buy a listing with this wallet; the listing's settlement authority co-signs the swap
+    Buy {
+        #[arg(short, long)]
+        listing_id: String,
+        
+        /// Mint of the listed asset, whose history holds the listing
+        #[arg(short, long)]
+        mint: String,
+        
+        /// Keypair file of the listing's settlement authority, which moves the asset under the
+        /// seller's approval in the same transaction
+        #[arg(long)]
+        settlement_keypair: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum CredentialAction {
+    /// Sign claims about a subject wallet with this wallet as issuer
+    Issue {
+        #[arg(short, long)]
+        subject: String,
+        
+        /// Claim as KEY=VALUE; repeat for several, e.g. --claim kyc_level=2
+        #[arg(short, long = "claim")]
+        claims: Vec<String>,
+        
+        #[arg(short, long, default_value = "365")]
+        expires_in_days: u64,
+        
+        /// Also anchor the credential hash on-chain
+        #[arg(short, long)]
+        anchor: bool,
+        
+        #[arg(short, long)]
+        output: String,
+    },
+    
+    /// Check a credential file's signature, expiry, anchor, and revocation
+    Verify {
+        #[arg(short, long)]
+        file: String,
+    },
+    
+    /// Revoke a credential this wallet issued
+    Revoke {
+        #[arg(short, long)]
+        file: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum PaymentLinkAction {
+    /// Sign a link asking for a payment to this wallet
+    Create {
+        /// Amount in whole units, e.g. 125 or 12.50
+        #[arg(short, long)]
+        amount: String,
+        
+        /// `usdc` or a token mint address
+        #[arg(short, long, default_value = "usdc")]
+        currency: String,
+        
+        #[arg(short, long)]
+        memo: Option<String>,
+        
+        #[arg(long, default_value = "168")]
+        expires_in_hours: u64,
+    },
+    
+    /// Check a payment link, show what it asks for, and pay it
+    Pay {
+        #[arg(short, long)]
+        link: String,
+        
+        /// Pay without asking
+        #[arg(short, long)]
+        yes: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum AssetsAction {
+    /// Record the metadata of every held asset, to diff against later
+    Snapshot {
+        #[arg(short, long)]
+        address: Option<String>, // If not provided, uses wallet address
+        
+        /// Write the snapshot here instead of printing it
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+    
+    /// Report metadata changes since a saved snapshot
+    Diff {
+        #[arg(long)]
+        baseline: String,
+        
+        /// Defaults to the baseline's owner
+        #[arg(short, long)]
+        address: Option<String>,
+        
+        /// Replace the baseline with the new snapshot afterwards
+        #[arg(long)]
+        update: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum AssetPolicyAction {
+    /// Anchor a policy file as a ledger entry; only the mint's issuer can
+    Anchor {
+        #[arg(short, long)]
+        file: String,
+    },
+    
+    /// Show the policy the mint's issuer anchored, optionally saving it for --asset-policy
+    Get {
+        #[arg(short, long)]
+        mint: String,
+        
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum IdentitiesAction {
+    /// Resolve the registered identity of each address and save the index
+    Build {
+        #[arg(short, long, value_delimiter = ',')]
+        addresses: Vec<String>,
+        
+        #[arg(short, long, default_value = "identity-index.json")]
+        index: String,
+    },
     
-    // Load wallet
+    /// Rescan indexed addresses with new activity, optionally adding more
+    Refresh {
+        #[arg(short, long, default_value = "identity-index.json")]
+        index: String,
+        
+        #[arg(short, long, value_delimiter = ',')]
+        addresses: Vec<String>,
+    },
+    
+    /// Find the addresses that registered a name or metadata value
+    Lookup {
+        #[arg(short, long, default_value = "identity-index.json")]
+        index: String,
+        
+        #[arg(short, long)]
+        name: Option<String>,
+        
+        /// Metadata match as KEY=VALUE, e.g. email=ops@acme.com
+        #[arg(short, long)]
+        metadata: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum OrgAction {
+    /// Create an organization with this wallet as its first admin
+    Create {
+        #[arg(short, long)]
+        name: String,
+    },
+    
+    /// Give a wallet a role in an organization, or change the one it has; admins only
+    AddMember {
+        #[arg(short, long)]
+        org: String,
+        
+        #[arg(short, long)]
+        member: String,
+        
+        /// viewer, operator, or admin
+        #[arg(short, long)]
+        role: String,
+    },
+    
+    /// Take a wallet out of an organization; admins only
+    RemoveMember {
+        #[arg(short, long)]
+        org: String,
+        
+        #[arg(short, long)]
+        member: String,
+    },
+    
+    /// Show an organization's members, and any forged or invalid changes that were ignored
+    Show {
+        #[arg(short, long)]
+        org: String,
+    },
+}
+
+#[tokio::main]
+async fn main() {
+    env_logger::init();
+    
+    if let Err(e) = run(Cli::parse()).await {
+        let envelope = ErrorEnvelope::new(&e);
+        if json_errors_requested() {
+            println!("{}", serde_json::json!({ "error": envelope }));
+        } else {
+            eprintln!("Error [{}]: {:?}", envelope.kind, e);
+            if let Some(hint) = &envelope.hint {
+                eprintln!("\n💡 Hint: {}", hint);
+            }
+        }
+        std::process::exit(envelope.exit_code);
+    }
+}
+
+/// Whether the command line asked for JSON output, so a failure is reported as JSON too
+fn json_errors_requested() -> bool {
+    let args: Vec<String> = std::env::args().collect();
+    args.iter().any(|arg| arg.eq_ignore_ascii_case("--output=json"))
+        || args
+            .windows(2)
+            .any(|pair| (pair[0] == "--output" || pair[0] == "-o") && pair[1].eq_ignore_ascii_case("json"))
+}
+
+async fn run(mut cli: Cli) -> Result<()> {
+    // The localnet manages its own validator and wallet, before any client exists
+    if let Commands::Localnet { action } = &cli.command {
+        return run_localnet(action).await;
+    }
+    if let Some(name) = &cli.profile {
+        let profiles = Profiles::load(std::path::Path::new(profile::DEFAULT_PROFILES_PATH))?;
+        let profile = profiles.get(name)?;
+        profile.register_mints()?;
+        cli.rpc_url = cli.rpc_url.or_else(|| Some(profile.rpc_url.clone()));
+        cli.wallet = cli.wallet.or_else(|| profile.wallet.as_ref().map(|path| path.display().to_string()));
+    }
+    
+    // Initialize Finternet client
+    let self_contained_demo = matches!(cli.command, Commands::Demo { self_contained: true, .. });
+    let mut config = if let Some(rpc_url) = cli.rpc_url {
+        FinternetConfig {
+            rpc_url,
+            commitment_level: "confirmed".to_string(),
+            ..FinternetConfig::default()
+        }
+    } else if self_contained_demo {
+        FinternetConfig {
+            rpc_url: demo::LOCAL_VALIDATOR_URL.to_string(),
+            ..FinternetConfig::default()
+        }
+    } else {
+        FinternetConfig::default()
+    };
+    config.record_rpc_to = cli.record_rpc.map(std::path::PathBuf::from);
+    config.metadata_uri_base = cli.metadata_uri_base;
+    config.metadata_backend = cli.metadata_backend;
+    config.action_log_path = cli.action_log.map(std::path::PathBuf::from);
+    config.dry_run = cli.dry_run_all;
+    config.amount_format = AmountFormatter {
+        locale: cli.locale.unwrap_or_default(),
+        display: cli.currency_display.unwrap_or_default(),
+        precision: cli.precision.unwrap_or_default(),
+    };
+    output::set_amount_format(config.amount_format.clone());
+    if let Some(namespace) = cli.namespace {
+        finternet_sdk::namespace::namespaced(&namespace, "")?;
+        config.namespace = namespace;
+    }
+    config.trusted_namespaces = cli.trusted_namespace;
+    config.accept_legacy_entries = cli.legacy_entries;
+    
+    let client = if let Some(recording) = cli.replay_rpc {
+        FinternetClient::from_recording_with(std::path::Path::new(&recording), config)?
+    } else if let Commands::Demo { recorded: true, seed, .. } = &cli.command {
+        if seed != demo::DEFAULT_DEMO_SEED {
+            anyhow::bail!("The recorded demo was run with --seed {}; drop --seed or run against a local validator", demo::DEFAULT_DEMO_SEED);
+        }
+        FinternetClient::recorded_demo(config)?
+    } else {
+        FinternetClient::new(config)
+    };
+    let client = match &cli.command {
+        Commands::BatchPayment { .. } | Commands::BatchTokenize { .. } | Commands::Sync { .. } => {
+            client.with_progress(progress_bar())
+        }
+        Commands::Demo { self_contained: true, .. } => client.with_fixed_timestamp(demo::DEMO_TIMESTAMP),
+        _ => client,
+    };
+    let client = cli.asset_policy.iter().try_fold(client, |client, path| {
+        AssetPolicy::load(std::path::Path::new(path)).map(|policy| client.with_asset_policy(policy))
+    })?;
+    info!("Connected to Solana RPC: {}", client.config.rpc_url);
+    
+    // Bootstrap manages its own wallet, and keeps stdout clean for --output json
+    if let Commands::Bootstrap { keypair, state, identity_name, min_sol, api_url, output } = &cli.command {
+        let mut options = BootstrapOptions::new(cli.wallet.as_deref().unwrap_or(keypair), state);
+        options.identity_name = identity_name.clone();
+        options.min_lamports = Amount::parse(min_sol, &finternet_sdk::Currency::Sol)?.base_units;
+        options.airdrop_lamports = options.airdrop_lamports.max(options.min_lamports);
+        options.api_url = api_url.clone();
+        let json = output.eq_ignore_ascii_case("json");
+        if !json {
+            println!("🏗️  Bootstrapping {} (state: {})", client.config.rpc_url, state);
+        }
+        let report = client.bootstrap(&options).await?;
+        if json {
+            println!("{}", serde_json::to_string_pretty(&report)?);
+        } else {
+            print_bootstrap_report(&report);
+        }
+        return Ok(());
+    }
+    
+    // A self-contained demo derives its own payer; stdout stays clean for --output json
+    if let Commands::Demo { self_contained, seed, output, .. } = &cli.command {
+        let options = if *self_contained {
+            DemoOptions::self_contained(seed)
+        } else {
+            DemoOptions { seed: seed.clone(), ..DemoOptions::live() }
+        };
+        let payer = match (&cli.wallet, self_contained) {
+            (_, true) => options.payer()?,
+            (Some(path), false) => FinternetClient::load_wallet_from_file(std::path::Path::new(path))?,
+            (None, false) => FinternetClient::load_default_wallet()
+                .map_err(|e| anyhow::anyhow!("A live demo needs a wallet ({}); pass --wallet or run solana-keygen new", e))?,
+        };
+        let json = output.eq_ignore_ascii_case("json");
+        if !json {
+            let mode = if *self_contained { "self-contained" } else { "live" };
+            println!("🚀 Running the {} demo against {}", mode, client.config.rpc_url);
+        }
+        let report = client.run_demo(&payer, &options).await?;
+        if json {
+            println!("{}", serde_json::to_string_pretty(&report)?);
+        } else {
+            print_demo_report(&report);
+        }
+        if !report.passed {
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+    
+    // State bundles and action logs are local files only, and RPC benchmarks only read; no wallet involved
+    match &cli.command {
+        Commands::RpcBenchmark { urls } => {
+            let candidates = if urls.is_empty() { vec![client.config.rpc_url.clone()] } else { urls.clone() };
+            println!("📡 Probing {} RPC endpoints...", candidates.len());
+            let probes = FinternetClient::probe_rpc_endpoints(&candidates);
+            for (i, probe) in probes.iter().enumerate() {
+                let marker = if probe.is_usable() { "✅" } else { "❌" };
+                println!("{} {}. {}", marker, i + 1, probe);
+            }
+            match probes.iter().find(|probe| probe.is_usable()) {
+                Some(best) => println!("\n🏆 Fastest healthy endpoint: --rpc-url {}", best.url),
+                None => println!("\n⚠️  No endpoint is healthy and within {} slots of the best", finternet_sdk::rpc_select::MAX_SLOT_LAG),
+            }
+            return Ok(());
+        }
+        Commands::VerifyActionLog { path } => {
+            let verification = action_log::verify_action_log(std::path::Path::new(path))?;
+            println!("✅ {} entries verified in {}", verification.entries, path);
+            if verification.segments > 0 || verification.pruned > 0 {
+                println!(
+                    "🗂️  {} rotated segments kept; {} older entries pruned after verifying",
+                    verification.segments, verification.pruned
+                );
+            }
+            println!("🔗 Head: {}", verification.head);
+            return Ok(());
+        }
+        Commands::ExportState { paths, output } => {
+            let paths: Vec<std::path::PathBuf> = paths.iter().map(std::path::PathBuf::from).collect();
+            let bundle = state_bundle::export_client_state(&paths)?;
+            bundle.write(std::path::Path::new(output))?;
+            println!("📦 Exported {} state files to {}", bundle.manifest.files.len(), output);
+            for entry in &bundle.manifest.files {
+                println!("   {} ({} bytes)", entry.path, entry.size);
+            }
+            return Ok(());
+        }
+        Commands::ImportState { input, dry_run } => {
+            let bundle = StateBundle::read(std::path::Path::new(input))?;
+            let changes = if *dry_run {
+                state_bundle::plan_client_state(&bundle)?
+            } else {
+                state_bundle::apply_client_state(&bundle)?
+            };
+            println!(
+                "{} {} (SDK {}, {} files)",
+                if *dry_run { "🔍 Would import" } else { "📥 Imported" },
+                input,
+                bundle.manifest.sdk_version,
+                changes.len()
+            );
+            for change in &changes {
+                let marker = match change.kind {
+                    StateChangeKind::Added => "+",
+                    StateChangeKind::Modified => "~",
+                    StateChangeKind::Unchanged => "=",
+                };
+                println!("   {} {}", marker, change.path);
+            }
+            return Ok(());
+        }
+        _ => {}
+    }
+    
+    // Load wallet; only commands that sign need one
     let wallet = if let Some(wallet_path) = cli.wallet {
-        FinternetClient::load_wallet_from_file(&std::path::Path::new(&wallet_path))?
+        Some(FinternetClient::load_wallet_from_file(std::path::Path::new(&wallet_path))?)
     } else {
-        match FinternetClient::load_default_wallet() {
-            Ok(wallet) => wallet,
-            Err(_) => {
-                println!("⚠️  No wallet found. Creating a new one...");
-                let new_wallet = FinternetClient::create_new_wallet();
-                println!("🔑 New wallet created: {}", new_wallet.pubkey());
-                println!("💡 Save this wallet using: finternet-cli create-wallet -o ~/.config/solana/id.json");
-                println!("💰 Don't forget to airdrop SOL for gas fees!");
-                new_wallet
+        FinternetClient::load_default_wallet().ok()
+    };
+    let wallet = match wallet {
+        Some(wallet) => Some(wallet),
+        None if !signs_with_wallet(&cli.command) => None,
+        None if cli.ephemeral_ok => Some(create_session_wallet()?),
+        None => {
+            return Err(anyhow::anyhow!(
+                "This command signs with a wallet, and none is set up. Create one with \
+                 `finternet-cli create-wallet -o ~/.config/solana/id.json`, pass --wallet, or pass --ephemeral-ok \
+                 to sign with a throwaway key saved under {}",
+                session_wallet_dir().display()
+            ));
+        }
+    };
+    
+    match &wallet {
+        Some(wallet) => println!("🔑 Using wallet: {}", wallet.pubkey()),
+        None => println!("👀 No wallet set up; running read-only"),
+    }
+    
+    // Execute commands
+    match cli.command {
+        Commands::TokenizeAsset {
+            name,
+            description,
+            value,
+            asset_type,
+            symbol,
+            creators,
+            seller_fee_bps,
+            mint_seed,
+            mint_keypair,
+            mint_authority,
+            freeze_authority,
+        } => {
+            let asset_type: AssetType = asset_type.parse()?;
+            let mint_keypair = match (mint_seed, mint_keypair) {
+                (Some(seed), _) => Some(derive_mint_keypair(&signer(&wallet)?.pubkey(), &seed)?),
+                (None, Some(path)) => Some(FinternetClient::load_wallet_from_file(std::path::Path::new(&path))?),
+                (None, None) => None,
+            };
+            let options = TokenizeOptions {
+                symbol,
+                creators: parse_creators(&creators)?,
+                seller_fee_basis_points: seller_fee_bps,
+                mint_keypair,
+                mint_authority,
+                freeze_authority,
+                ..TokenizeOptions::default()
+            };
+            require_valid(validate_asset_spec(&name, &description, value, &asset_type, &options))?;
+            println!("🏭 Tokenizing asset: {} ({})", name, asset_type);
+            
+            let (mint_address, metadata, signature) = client
+                .tokenize_asset_with_options(&name, &description, value, &asset_type, signer(&wallet)?, &options)
+                .await?;
+            
+            println!("✅ Asset tokenized successfully!");
+            println!("🪙 Mint Address: {}", mint_address);
+            println!("📝 Transaction: {}", signature);
+            println!("📋 Metadata: {:#?}", metadata);
+        }
+        
+        Commands::Tokenize { action } => match action {
+            TokenizeAction::Prepare {
+                name,
+                description,
+                value,
+                asset_type,
+                symbol,
+                creators,
+                seller_fee_bps,
+                valid_for_hours,
+                output,
+            } => {
+                let spec = AssetSpec { name, description, value, asset_type: asset_type.parse()? };
+                let options = TokenizeOptions {
+                    symbol,
+                    creators: parse_creators(&creators)?,
+                    seller_fee_basis_points: seller_fee_bps,
+                    ..TokenizeOptions::default()
+                };
+                require_valid(validate_asset_spec(&spec.name, &spec.description, spec.value, &spec.asset_type, &options))?;
+                println!("📝 Preparing tokenization of {} ({})", spec.name, spec.asset_type);
+                
+                let draft = client
+                    .prepare_tokenization(signer(&wallet)?, &spec, &options, valid_for_hours.saturating_mul(3600))
+                    .await?;
+                draft.save(std::path::Path::new(&output))?;
+                println!("{}", draft);
+                println!("📁 Saved to {}; review it, then run `tokenize commit --draft {}`", output, output);
+            }
+            
+            TokenizeAction::Commit { draft } => {
+                let draft = TokenizationDraft::load(std::path::Path::new(&draft))?;
+                println!("{}", draft);
+                let asset = client.commit_tokenization(signer(&wallet)?, &draft).await?;
+                println!("✅ Asset tokenized successfully!");
+                println!("🪙 Mint Address: {}", asset.mint);
+                println!("📝 Transaction: {}", asset.signature);
+            }
+            
+            TokenizeAction::Discard { draft } => {
+                let draft = TokenizationDraft::load(std::path::Path::new(&draft))?;
+                client.discard_tokenization(&draft).await?;
+                println!("🗑️  Discarded tokenization draft {}", draft.id);
+            }
+        },
+        
+        Commands::SendPayment { to, amount, memo, memo_template, memo_vars, yes, deadline } => {
+            let to_pubkey = Pubkey::from_str(&to)?;
+            let memo = render_memo(memo, memo_template.as_deref(), &memo_vars)?;
+            let usdc_mint = client.usdc_mint()?;
+            let amount = Amount::parse(&amount, &finternet_sdk::Currency::spl(usdc_mint, 6))?;
+            require_valid(validate_payment(&to_pubkey, amount.base_units, &usdc_mint, memo.as_deref()))?;
+            confirm_recipient(&client, &to_pubkey, &usdc_mint, yes).await?;
+            println!("💸 Sending {} to {}", amount, to);
+            
+            let mut options = RpcCallOptions::default();
+            if let Some(deadline) = deadline.as_deref() {
+                options = options.with_deadline(parse_deadline(deadline)?);
+            }
+            let signature = client
+                .send_payment_with_options(signer(&wallet)?, &to_pubkey, amount.base_units, &usdc_mint, memo.as_deref(), &options)
+                .await
+                .inspect_err(explain_recipient_error)?;
+            
+            println!("✅ Payment sent successfully!");
+            println!("📝 Transaction: {}", signature);
+            println!("🔍 Explorer: {}", client.explorer_url(&format!("tx/{}", signature)));
+        }
+        
+        Commands::BatchPayment { file, token_mint, journal, resume, deadline } => {
+            let journal = match (resume, file) {
+                (Some(resume), _) => {
+                    println!("🔁 Resuming batch from {}", resume);
+                    client.resume_batch(signer(&wallet)?, std::path::Path::new(&resume)).await?
+                }
+                (None, Some(file)) => {
+                    let payments: Vec<BatchPayment> = serde_json::from_str(&std::fs::read_to_string(&file)?)?;
+                    let mint = match token_mint {
+                        Some(mint) => Pubkey::from_str(&mint)?,
+                        None => client.usdc_mint()?,
+                    };
+                    let deadline = deadline.as_deref().map(parse_deadline).transpose()?;
+                    println!("💸 Sending {} payments (mint: {})", payments.len(), mint);
+                    client
+                        .send_batch_payment_until(signer(&wallet)?, &payments, &mint, journal.as_deref().map(std::path::Path::new), deadline)
+                        .await?
+                }
+                (None, None) => unreachable!("clap requires --file or --resume"),
+            };
+            print_batch_journal(&journal);
+        }
+        
+        Commands::BatchTokenize { file, journal, resume } => {
+            let journal = match (resume, file) {
+                (Some(resume), _) => {
+                    println!("🔁 Resuming batch from {}", resume);
+                    client.resume_batch(signer(&wallet)?, std::path::Path::new(&resume)).await?
+                }
+                (None, Some(file)) => {
+                    let assets: Vec<BatchAsset> = serde_json::from_str(&std::fs::read_to_string(&file)?)?;
+                    println!("🏗️ Tokenizing {} assets", assets.len());
+                    client
+                        .tokenize_assets_batch(signer(&wallet)?, &assets, journal.as_deref().map(std::path::Path::new))
+                        .await?
+                }
+                (None, None) => unreachable!("clap requires --file or --resume"),
+            };
+            print_batch_journal(&journal);
+        }
+        
+        Commands::Sweep { mint, to, min, keys, close } => {
+            let currency = if mint.eq_ignore_ascii_case("usdc") {
+                finternet_sdk::Currency::usdc_devnet()
+            } else {
+                let mint = Pubkey::from_str(&mint)?;
+                finternet_sdk::Currency::spl(mint, client.client.get_token_supply(&mint)?.decimals)
+            };
+            let mint = currency.mint().ok_or_else(|| anyhow::anyhow!("Only token balances can be swept"))?;
+            let options = SweepOptions {
+                min_amount: currency.parse_amount(&min).map_err(|e| anyhow::anyhow!(e))?,
+                close_empty_accounts: close,
+            };
+            let mut wallets = Vec::new();
+            for path in &keys {
+                let path = std::path::Path::new(path);
+                if path.is_dir() {
+                    let mut files: Vec<_> = std::fs::read_dir(path)?
+                        .map(|entry| entry.map(|entry| entry.path()))
+                        .collect::<std::io::Result<_>>()?;
+                    files.retain(|file| file.extension().is_some_and(|ext| ext == "json"));
+                    files.sort();
+                    for file in files {
+                        wallets.push(FinternetClient::load_wallet_from_file(&file)?);
+                    }
+                } else {
+                    wallets.push(FinternetClient::load_wallet_from_file(path)?);
+                }
             }
-        }
-    };
-    
-    println!("🔑 Using wallet: {}", wallet.pubkey());
-    
-    // Execute commands
-    match cli.command {
-        Commands::TokenizeAsset { name, description, value, asset_type } => {
-            println!("🏭 Tokenizing asset: {}", name);
             
-            let (mint_address, metadata, signature) = client
-                .tokenize_asset(&name, &description, value, &asset_type, &wallet)
+            println!("🧹 Sweeping {} from {} wallets to {}", currency.label(), wallets.len(), to);
+            let report = client
+                .sweep_balances(signer(&wallet)?, &wallets, &Pubkey::from_str(&to)?, &mint, &options)
                 .await?;
+            for account in &report.accounts {
+                let icon = match account.status {
+                    SweepAccountStatus::Swept | SweepAccountStatus::Closed => "✅",
+                    SweepAccountStatus::Failed => "❌",
+                    SweepAccountStatus::BelowMinimum | SweepAccountStatus::Empty | SweepAccountStatus::NoAccount => "⏭️",
+                };
+                let signature = account.signature.map(|signature| format!(" {}", signature)).unwrap_or_default();
+                println!("{} {} {:?} {}{}", icon, account.wallet, account.status, output::amounts().format(&currency, account.amount), signature);
+                if let Some(error) = &account.error {
+                    println!("   {}", error);
+                }
+            }
+            println!(
+                "📊 Swept {} from {} wallets in {} transactions; fees {} SOL, rent reclaimed {} SOL",
+                output::amounts().format(&currency, report.total_swept()),
+                report.count(SweepAccountStatus::Swept),
+                report.transactions.len(),
+                report.total_fees() as f64 / 1_000_000_000.0,
+                report.total_rent_reclaimed() as f64 / 1_000_000_000.0
+            );
+        }
+        
+        Commands::ColdSweep { action } => match action {
+            ColdSweepAction::Plan { to, from, reserve, no_sol, mint, no_tokens, close, tolerance, output } => {
+                let hot_wallet = match from {
+                    Some(address) => Pubkey::from_str(&address)?,
+                    None => own_address(&wallet)?,
+                };
+                let tokens = if no_tokens {
+                    TokenSelection::None
+                } else if mint.is_empty() {
+                    TokenSelection::All
+                } else {
+                    let mints = mint
+                        .iter()
+                        .map(|mint| {
+                            if mint.eq_ignore_ascii_case("usdc") {
+                                client.usdc_mint()
+                            } else {
+                                Ok(Pubkey::from_str(mint)?)
+                            }
+                        })
+                        .collect::<Result<Vec<_>>>()?;
+                    TokenSelection::Only(mints)
+                };
+                let sol = finternet_sdk::Currency::Sol;
+                let selection = SweepSelection {
+                    sol_reserve: if no_sol { None } else { Some(sol.parse_amount(&reserve).map_err(|e| anyhow::anyhow!(e))?) },
+                    tokens,
+                    close_accounts: close,
+                    sol_tolerance: sol.parse_amount(&tolerance).map_err(|e| anyhow::anyhow!(e))?,
+                };
+                
+                let plan = client.plan_cold_sweep(&hot_wallet, &Pubkey::from_str(&to)?, &selection).await?;
+                plan.save(std::path::Path::new(&output))?;
+                println!("{}", plan);
+                if plan.transactions.is_empty() {
+                    println!("📭 Nothing to sweep");
+                } else {
+                    println!("📁 Saved to {}; review it, then run `cold-sweep execute --plan {}`", output, output);
+                }
+            }
             
-            println!("✅ Asset tokenized successfully!");
-            println!("🪙 Mint Address: {}", mint_address);
-            println!("📝 Transaction: {}", signature);
-            println!("📋 Metadata: {:#?}", metadata);
+            ColdSweepAction::Execute { plan } => {
+                let path = std::path::Path::new(&plan);
+                println!("{}", SweepPlan::load(path)?);
+                let execution = client.execute_sweep_plan(signer(&wallet)?, path).await?;
+                for signature in &execution.signatures {
+                    println!("✅ {}", signature);
+                }
+                if execution.already_sent > 0 {
+                    println!("⏭️  {} transaction(s) were sent by an earlier run", execution.already_sent);
+                }
+                println!("🧊 Cold sweep {} complete", execution.plan_id);
+            }
+        },
+        
+        Commands::MigrateLedgerEntries { address, limit } => {
+            let address = match address {
+                Some(address) => Pubkey::from_str(&address)?,
+                None => own_address(&wallet)?,
+            };
+            println!("🏷️  Migrating legacy ledger entries on {} into namespace {}", address, client.namespace());
+            let migration = client.migrate_legacy_entries(signer(&wallet)?, &address, limit).await?;
+            for entry in &migration.migrated {
+                let signatures: Vec<String> = entry.signatures.iter().map(|s| s.to_string()).collect();
+                println!("✅ {} → {}", entry.original, signatures.join(", "));
+            }
+            for entry in &migration.skipped {
+                println!("⏭️  {}: {}", entry.signature, entry.reason);
+            }
+            println!("📝 {} migrated, {} skipped", migration.migrated.len(), migration.skipped.len());
         }
         
-        Commands::SendPayment { to, amount, memo } => {
+        Commands::SendToken { to, amount, token_mint, memo, memo_template, memo_vars, yes } => {
             let to_pubkey = Pubkey::from_str(&to)?;
-            println!("💸 Sending ${:.2} USDC to {}", amount, to);
+            let memo = render_memo(memo, memo_template.as_deref(), &memo_vars)?;
+            let mint_pubkey = Pubkey::from_str(&token_mint)?;
+            // Base units have no decimals or symbol of their own
+            let base_units = finternet_sdk::Currency::Spl { mint: mint_pubkey, decimals: 0, symbol: None };
+            let amount = Amount::parse(&amount, &base_units)?.base_units;
+            require_valid(validate_payment(&to_pubkey, amount, &mint_pubkey, memo.as_deref()))?;
+            confirm_recipient(&client, &to_pubkey, &mint_pubkey, yes).await?;
+            
+            println!("🪙 Sending {} tokens to {}", amount, to);
             
             let signature = client
-                .send_usdc_payment(&wallet, &to_pubkey, amount, memo.as_deref())
-                .await?;
+                .send_payment(signer(&wallet)?, &to_pubkey, amount, &mint_pubkey, memo.as_deref())
+                .await
+                .inspect_err(explain_recipient_error)?;
             
-            println!("✅ Payment sent successfully!");
+            println!("✅ Token transfer successful!");
             println!("📝 Transaction: {}", signature);
         }
         
-        Commands::SendToken { to, amount, token_mint, memo } => {
+        Commands::ProposePayment { to, amount, token_mint, approver, multisig, nonce_account, memo, valid_for_hours, output } => {
             let to_pubkey = Pubkey::from_str(&to)?;
             let mint_pubkey = Pubkey::from_str(&token_mint)?;
+            require_valid(validate_payment(&to_pubkey, amount, &mint_pubkey, memo.as_deref()))?;
             
-            println!("🪙 Sending {} tokens to {}", amount, to);
+            let mut options = ProposalOptions::new(Pubkey::from_str(&approver)?);
+            if let Some(multisig) = multisig {
+                options.authority = ApprovalAuthority::Multisig { multisig: Pubkey::from_str(&multisig)? };
+            }
+            options.nonce_account = nonce_account.as_deref().map(Pubkey::from_str).transpose()?;
+            options.valid_for_secs = valid_for_hours * 60 * 60;
             
-            let signature = client
-                .send_payment(&wallet, &to_pubkey, amount, &mint_pubkey, memo.as_deref())
+            let proposal = client
+                .propose_payment(signer(&wallet)?, &to_pubkey, amount, &mint_pubkey, memo.as_deref(), &options)
                 .await?;
+            proposal.save(std::path::Path::new(&output))?;
             
-            println!("✅ Token transfer successful!");
+            println!("✍️  {}", proposal);
+            println!("📁 Saved to {}; send it to {} to approve", output, proposal.approver);
+        }
+        
+        Commands::ApprovePayment { file, yes } => {
+            let proposal = PaymentProposal::load(std::path::Path::new(&file))?;
+            proposal.verify()?;
+            println!("🔍 {}", proposal);
+            if let ApprovalAuthority::CoSigner = proposal.authority {
+                println!("⚠️  Co-signer proposal: the chain does not require your signature, only this workflow does");
+            }
+            confirm_prompt("❓ Approve and send this payment?", yes)?;
+            
+            let signature = client.approve_and_execute(signer(&wallet)?, &proposal).await?;
+            println!("✅ Payment approved and sent!");
             println!("📝 Transaction: {}", signature);
         }
         
-        Commands::History { limit, address } => {
+        Commands::PaymentLink { action } => match action {
+            PaymentLinkAction::Create { amount, currency, memo, expires_in_hours } => {
+                let currency = if currency.eq_ignore_ascii_case("usdc") {
+                    finternet_sdk::Currency::usdc_devnet()
+                } else {
+                    let mint = Pubkey::from_str(&currency)?;
+                    finternet_sdk::Currency::spl(mint, client.client.get_token_supply(&mint)?.decimals)
+                };
+                let amount = currency.parse_amount(&amount).map_err(|e| anyhow::anyhow!(e))?;
+                let expires_at = chrono::Utc::now().timestamp() as u64 + expires_in_hours * 3600;
+                
+                let link = FinternetClient::create_payment_link(signer(&wallet)?, amount, &currency, memo.as_deref(), expires_at)?;
+                println!("🔗 Payment link for {} to {}:", output::amounts().format(&currency, amount), own_address(&wallet)?);
+                println!("{}", link);
+            }
+            
+            PaymentLinkAction::Pay { link, yes } => {
+                let decoded = PaymentLink::decode(&link)?;
+                println!("🔍 {}", decoded);
+                if decoded.is_expired(chrono::Utc::now().timestamp() as u64) {
+                    anyhow::bail!("Payment link expired at {}", decoded.expires_at);
+                }
+                if let Some(mint) = decoded.currency.mint() {
+                    confirm_recipient(&client, &decoded.recipient, &mint, yes).await?;
+                }
+                confirm_prompt("❓ Pay this link?", yes)?;
+                
+                let signature = client
+                    .execute_payment_link(signer(&wallet)?, &link)
+                    .await
+                    .inspect_err(explain_recipient_error)?;
+                println!("✅ Payment link paid!");
+                println!("📝 Transaction: {}", signature);
+            }
+        },
+        
+        Commands::AssetPolicy { action } => match action {
+            AssetPolicyAction::Anchor { file } => {
+                let policy = AssetPolicy::load(std::path::Path::new(&file))?;
+                let signature = client.anchor_asset_policy(signer(&wallet)?, &policy).await?;
+                println!("✅ Asset policy anchored!");
+                println!("{}", policy);
+                println!("📝 Transaction: {}", signature);
+            }
+            
+            AssetPolicyAction::Get { mint, output } => {
+                let mint = Pubkey::from_str(&mint)?;
+                match client.get_asset_policy(&mint).await? {
+                    Some(policy) => {
+                        println!("📜 {}", policy);
+                        println!("💡 Enforced client-side only; pass it with --asset-policy to honor it");
+                        if let Some(output) = output {
+                            policy.save(std::path::Path::new(&output))?;
+                            println!("💾 Saved to {}", output);
+                        }
+                    }
+                    None => println!("📭 No asset policy anchored for {}", mint),
+                }
+            }
+        },
+        
+        Commands::History { limit, address, identity_index, local, db, memo_template, export, format, view } => {
+            let format = ExportFormat::from_str(&format)?;
+            let memo_template = memo_template.as_deref().map(MemoTemplate::parse).transpose()?;
             let target_address = if let Some(addr) = address {
                 Pubkey::from_str(&addr)?
             } else {
-                wallet.pubkey()
+                own_address(&wallet)?
             };
+            let index = identity_index
+                .map(|path| IdentityIndex::load(std::path::Path::new(&path)))
+                .transpose()?
+                .unwrap_or_default();
+            // Status lines go to stderr with --output json, keeping stdout parseable
+            let notice = |line: String| if view.is_json() { eprintln!("{}", line) } else { println!("{}", line) };
             
-            println!("📜 Fetching transaction history for: {}", target_address);
+            notice(format!("📜 Fetching transaction history for: {}", target_address));
+            
+            let mut history = if local {
+                local_history(&db, &target_address, limit, notice)?
+            } else if export.is_some() {
+                // Exports can run past one signature page
+                client
+                    .iter_transaction_history(&target_address, HistoryFilter::default())
+                    .take(limit)
+                    .try_collect()
+                    .await?
+            } else {
+                let history = client
+                    .get_transaction_history_with_completeness(&target_address, Some(limit), &Default::default())
+                    .await?;
+                if let Some(slot) = history.completeness.truncated_at() {
+                    notice(format!("⚠️  History is truncated at slot {}; the node serves nothing older", slot));
+                }
+                history.records
+            };
+            let redactions = client.get_redactions(&target_address).await?;
+            redactions.apply_to_records(&mut history);
             
-            let history = client.get_transaction_history(&target_address, Some(limit)).await?;
+            if let Some(path) = export {
+                let generated_at = chrono::Utc::now().timestamp().max(0) as u64;
+                let exported =
+                    export::export_history(format, &target_address, &history, Some(&index), &redactions, generated_at);
+                std::fs::write(&path, exported)?;
+                notice(format!("📁 Exported {} transactions as {} to: {}", history.len(), format, path));
+                return Ok(());
+            }
             
-            if history.is_empty() {
-                println!("📭 No transactions found");
+            let rows = history
+                .into_iter()
+                .map(|record| HistoryRow {
+                    signature: record.signature,
+                    time: record.timestamp,
+                    from: record.from,
+                    from_name: index.display_name(&record.from).map(str::to_string),
+                    to: record.to,
+                    to_name: index.display_name(&record.to).map(str::to_string),
+                    amount: record.amount,
+                    mint: record.currency.mint(),
+                    decimals: record.currency.decimals(),
+                    memo_fields: record
+                        .memo
+                        .as_deref()
+                        .and_then(|memo| memo_template.as_ref().and_then(|template| template.extract(memo)))
+                        .map(|fields| fields.into_iter().collect())
+                        .unwrap_or_default(),
+                    currency: record.currency,
+                    memo: record.memo,
+                })
+                .collect();
+            output::print_rows(rows, &view, None, "📭 No transactions found")?;
+        }
+        
+        #[cfg(feature = "sqlite")]
+        Commands::Sync { address, db, verify_finality } => {
+            let target_address = if let Some(addr) = address {
+                Pubkey::from_str(&addr)?
             } else {
-                println!("📋 Found {} transactions:", history.len());
-                for (i, record) in history.iter().enumerate() {
-                    println!("\n{}. Transaction: {}", i + 1, record.signature);
-                    println!("   From: {}", record.from);
-                    println!("   To: {}", record.to);
-                    println!("   Amount: {}", record.amount);
-                    println!("   Token: {}", record.token_mint);
-                    if let Some(memo) = &record.memo {
-                        println!("   Memo: {}", memo);
-                    }
-                    println!("   Time: {}", record.timestamp);
+                own_address(&wallet)?
+            };
+            println!("🔄 Syncing {} into {}", target_address, db);
+            
+            let ledger = LocalLedger::open(std::path::Path::new(&db))?;
+            let report = client.sync_local_ledger(&ledger, &target_address).await?;
+            println!("✅ {} new signatures, {} new transfer records", report.new_signatures, report.new_records);
+            for gap in &report.gaps {
+                println!("⚠️  Gap at slot {}: {}", gap.after_slot, gap.reason);
+            }
+            match report.completeness {
+                HistoryCompleteness::Complete => println!("📚 History reaches back to the account's first funding"),
+                HistoryCompleteness::TruncatedAt(slot) => {
+                    println!("⚠️  History is truncated at slot {}; the node serves nothing older", slot)
+                }
+                HistoryCompleteness::Unknown => {}
+            }
+            
+            if verify_finality {
+                let finality = client.verify_ledger_finality(&ledger, &target_address).await?;
+                println!(
+                    "🔒 {} records finalized, {} still only confirmed",
+                    finality.finalized, finality.still_confirmed
+                );
+                for invalidated in &finality.invalidated {
+                    println!(
+                        "❌ {} left the chain; removed {} records",
+                        invalidated.signature,
+                        invalidated.records.len()
+                    );
                 }
             }
         }
         
+        #[cfg(not(feature = "sqlite"))]
+        Commands::Sync { .. } | Commands::Statement { .. } => {
+            anyhow::bail!("The local ledger needs a build with --features sqlite");
+        }
+        
         Commands::AssetInfo { mint } => {
             let mint_pubkey = Pubkey::from_str(&mint)?;
             println!("🔍 Fetching asset info for: {}", mint);
@@ -265,24 +2168,157 @@ async fn main() -> Result<()> {
             println!("   Issuer: {}", asset_info.issuer);
             println!("   Type: {}", asset_info.asset_type);
             println!("   Created: {}", asset_info.created_at);
+            println!("   Supply: {} (decimals: {})", asset_info.supply, asset_info.decimals);
+            let custody = |c: AuthorityCustody| match c {
+                AuthorityCustody::Issuer => format!("{} (issuer)", asset_info.issuer),
+                AuthorityCustody::Key(key) if asset_info.has_metadata => format!("{} (separate key)", key),
+                AuthorityCustody::Key(key) => key.to_string(),
+                AuthorityCustody::Disabled => "none".to_string(),
+            };
+            println!("   Mint authority: {}", custody(asset_info.mint_custody()));
+            println!("   Freeze authority: {}", custody(asset_info.freeze_custody()));
+            if !asset_info.has_metadata {
+                println!("   ⚠️  No Metaplex metadata: name, issuer, and type are unknown");
+            } else {
+                println!("   Symbol: {}", asset_info.symbol);
+                println!("   URI: {}", asset_info.uri);
+                println!("   Primary sale happened: {}", asset_info.primary_sale_happened);
+            }
+            if let Some(standard) = asset_info.token_standard {
+                println!("   Token standard: {:?}", standard);
+            }
+            if let Some(rule_set) = asset_info.rule_set {
+                println!("   Rule set: {}", rule_set);
+            }
+            match &asset_info.edition {
+                Some(Edition::Master { supply, max_supply: Some(max) }) => println!("   Master edition: {} of {} printed", supply, max),
+                Some(Edition::Master { supply, max_supply: None }) => println!("   Master edition: {} printed, unlimited", supply),
+                Some(Edition::Print { parent, number }) => println!("   Print #{} of master edition {}", number, parent),
+                None => {}
+            }
+            println!("   Royalty: {} bps", asset_info.seller_fee_basis_points);
+            for creator in &asset_info.creators {
+                println!(
+                    "   Creator: {} ({}%) {}",
+                    creator.address,
+                    creator.share,
+                    if creator.verified { "✅ verified" } else { "⏳ unverified" }
+                );
+            }
+            for document in &asset_info.documents {
+                println!("   Document: {} {} ({} bytes)", document.doc_type, document.sha256, document.size);
+                if let Some(uri) = &document.uri {
+                    println!("      URI: {}", uri);
+                }
+            }
+        }
+        
+        Commands::AttachDoc { mint, file, doc_type } => {
+            let mint_pubkey = Pubkey::from_str(&mint)?;
+            let bytes = std::fs::read(&file).map_err(|e| anyhow::anyhow!("Could not read {}: {}", file, e))?;
+            println!("📎 Anchoring {} as a {} of {}", file, doc_type, mint);
+            
+            let anchor = client.attach_document(signer(&wallet)?, &mint_pubkey, &bytes, &doc_type).await?;
+            println!("✅ Document anchored!");
+            println!("   SHA-256: {}", anchor.sha256);
+            if let Some(uri) = &anchor.uri {
+                println!("   URI: {}", uri);
+            }
+            println!("📝 Transaction: {}", anchor.signature);
+        }
+        
+        Commands::VerifyDoc { mint, file } => {
+            let mint_pubkey = Pubkey::from_str(&mint)?;
+            let bytes = std::fs::read(&file).map_err(|e| anyhow::anyhow!("Could not read {}: {}", file, e))?;
+            
+            let verification = client.verify_document(&mint_pubkey, &bytes).await?;
+            if !verification.is_verified() {
+                println!("❌ {} ({}) matches none of the {} documents anchored to {}", file, verification.sha256, verification.anchored, mint);
+                std::process::exit(FinternetError::TamperedData.exit_code());
+            }
+            for anchor in &verification.matches {
+                println!("✅ {} is the {} anchored at {} in {}", file, anchor.doc_type, anchor.anchored_at, anchor.signature);
+            }
+        }
+        
+        Commands::ProveHoldings { mints, challenge, output } => {
+            let mints = mints
+                .iter()
+                .map(|mint| if mint.eq_ignore_ascii_case("usdc") { client.usdc_mint() } else { Ok(Pubkey::from_str(mint)?) })
+                .collect::<Result<Vec<_>>>()?;
+            println!("🔏 Proving holdings of {} in {} mint(s)", own_address(&wallet)?, mints.len());
+            
+            let proof = client.generate_holdings_proof_for(signer(&wallet)?, &mints, challenge.as_deref()).await?;
+            proof.save(std::path::Path::new(&output))?;
+            println!("{}", proof);
+            println!("✅ Proof written to {}", output);
+        }
+        
+        Commands::VerifyHoldings { file, challenge, minimums } => {
+            let proof = HoldingsProof::load(std::path::Path::new(&file))?;
+            println!("🔍 Verifying holdings of {} at slot {}", proof.wallet, proof.slot);
+            
+            let verification = client.verify_holdings_proof(&proof, challenge.as_deref()).await?;
+            for failure in &verification.failures {
+                println!("❌ {}", failure);
+            }
+            for account in &verification.accounts {
+                let now = match &account.drift {
+                    AccountDrift::Unchanged => "unchanged".to_string(),
+                    AccountDrift::Changed { amount } => format!("{} now", amount),
+                    AccountDrift::Transferred { owner } => format!("now owned by {}", owner),
+                    AccountDrift::Closed => "closed since".to_string(),
+                };
+                println!("   {} ({}): {} at the proof's slot, {}", account.address, account.mint, account.proven_amount, now);
+            }
+            let mut short = false;
+            for minimum in &minimums {
+                let (mint, amount) = minimum
+                    .split_once('=')
+                    .ok_or_else(|| anyhow::anyhow!("Expected --min mint=amount, got '{}'", minimum))?;
+                let mint = if mint.eq_ignore_ascii_case("usdc") { client.usdc_mint()? } else { Pubkey::from_str(mint)? };
+                let amount: u64 = amount.parse().map_err(|_| anyhow::anyhow!("Invalid amount in --min {}", minimum))?;
+                if !verification.proves_at_least(&mint, amount) {
+                    println!("❌ The proof does not show at least {} of {} (it shows {})", amount, mint, proof.total(&mint));
+                    short = true;
+                }
+            }
+            if !verification.is_valid() || short {
+                std::process::exit(FinternetError::TamperedData.exit_code());
+            }
+            for mint in &proof.mints {
+                println!("✅ {} held {} of {} at slot {}", proof.wallet, proof.total(mint), mint, proof.slot);
+            }
+            if verification.has_drift() {
+                println!("⚠️  Some accounts have changed since the proof's slot (checked at slot {})", verification.checked_at_slot);
+            }
+        }
+        
+        Commands::VerifyCreator { mint } => {
+            let mint_pubkey = Pubkey::from_str(&mint)?;
+            println!("✍️  Verifying {} as creator of {}", own_address(&wallet)?, mint);
+            
+            let signature = client.verify_creator(signer(&wallet)?, &mint_pubkey).await?;
+            println!("✅ Creator verified!");
+            println!("📝 Transaction: {}", signature);
         }
         
         Commands::Balance { address } => {
             let target_address = if let Some(addr) = address {
                 Pubkey::from_str(&addr)?
             } else {
-                wallet.pubkey()
+                own_address(&wallet)?
             };
             
             println!("💰 Checking balances for: {}", target_address);
             
             // Get SOL balance
             let sol_balance = client.client.get_balance(&target_address)?;
-            println!("   SOL: {:.4}", sol_balance as f64 / 1_000_000_000.0);
+            println!("   SOL: {}", output::amounts().format_units(&Currency::Sol, sol_balance as i128));
             
             // Get USDC balance
             let usdc_balance = client.get_usdc_balance(&target_address).await?;
-            println!("   USDC: ${:.2}", usdc_balance);
+            println!("   USDC: {}", output::amounts().format_usd(usdc_balance));
             
             // Get other token balances
             let token_accounts = client.get_token_accounts(&target_address).await?;
@@ -296,34 +2332,388 @@ async fn main() -> Result<()> {
             }
         }
         
-        Commands::Assets { address } => {
+        Commands::Watch { address, alerts, interval } => {
+            let target_address = if let Some(addr) = address {
+                Pubkey::from_str(&addr)?
+            } else {
+                own_address(&wallet)?
+            };
+            let thresholds = alerts
+                .iter()
+                .map(|alert| alert.parse::<BalanceThreshold>())
+                .collect::<Result<Vec<_>>>()?;
+            
+            println!("👀 Watching {} every {}s (Ctrl+C to stop)", target_address, interval);
+            for threshold in &thresholds {
+                println!("   🔔 {}", threshold);
+            }
+            
+            client
+                .watch_balance_alerts(&target_address, thresholds, std::time::Duration::from_secs(interval), |alert| {
+                    let icon = match alert.direction {
+                        AlertDirection::Below => "🚨",
+                        AlertDirection::Recovered => "✅",
+                    };
+                    println!("{} {}", icon, alert);
+                })
+                .await;
+        }
+        
+        Commands::Assets { action: Some(AssetsAction::Snapshot { address, output }), .. } => {
+            let target_address = if let Some(addr) = address {
+                Pubkey::from_str(&addr)?
+            } else {
+                own_address(&wallet)?
+            };
+            let snapshot = client.snapshot_asset_metadata(&target_address).await?;
+            match output {
+                Some(output) => {
+                    snapshot.save(std::path::Path::new(&output))?;
+                    println!("📸 Snapshot of {} assets held by {} saved to {}", snapshot.assets.len(), target_address, output);
+                    for asset in snapshot.assets.iter().filter(|asset| asset.uri_error.is_some()) {
+                        println!("⚠️  {}: URI not fetched ({})", asset.mint, asset.uri_error.as_deref().unwrap_or_default());
+                    }
+                }
+                None => println!("{}", serde_json::to_string_pretty(&snapshot)?),
+            }
+        }
+        
+        Commands::Assets { action: Some(AssetsAction::Diff { baseline, address, update }), .. } => {
+            let path = std::path::Path::new(&baseline);
+            let old = MetadataSnapshot::load(path)?;
+            let target_address = match address {
+                Some(addr) => Pubkey::from_str(&addr)?,
+                None => old.owner,
+            };
+            let new = client.snapshot_asset_metadata(&target_address).await?;
+            let changes = FinternetClient::diff_asset_metadata(&old, &new);
+            if changes.is_empty() {
+                println!("✅ No metadata changes since {}", baseline);
+            } else {
+                println!("🔎 {} metadata changes since {}:", changes.len(), baseline);
+                for change in &changes {
+                    let marker = if change.severity == ChangeSeverity::High { "🚨" } else { "  " };
+                    println!("{} {}", marker, change);
+                }
+            }
+            if update {
+                new.save(path)?;
+                println!("💾 Baseline updated");
+            }
+        }
+        
+        Commands::Assets { action: None, address, limit, view } => {
+            let target_address = if let Some(addr) = address {
+                Pubkey::from_str(&addr)?
+            } else {
+                own_address(&wallet)?
+            };
+            
+            if !view.is_json() {
+                println!("🏦 Fetching owned assets for: {}", target_address);
+            }
+            
+            let accounts: Vec<_> = client
+                .get_token_account_details(&target_address)
+                .await?
+                .into_iter()
+                .filter(|account| account.amount > 0)
+                .collect();
+            
+            let mut rows = Vec::with_capacity(accounts.len());
+            for account in accounts {
+                // Mints without readable metadata are still listed, just unnamed
+                let asset_info = client.get_asset_info(&account.mint).await.ok().filter(|info| info.has_metadata);
+                rows.push(AssetRow {
+                    mint: account.mint,
+                    name: asset_info.as_ref().map(|info| info.name.clone()),
+                    asset_type: asset_info.as_ref().map(|info| info.asset_type.to_string()),
+                    amount: account.amount,
+                    decimals: account.currency().decimals(),
+                    account: account.address,
+                    frozen: account.is_frozen(),
+                    delegate: account.delegate,
+                    delegated_amount: account.delegated_amount,
+                    close_authority: account.close_authority,
+                });
+            }
+            output::print_rows(rows, &view, limit, "📭 No assets found")?;
+        }
+        
+        Commands::IssuedAssets { address, page, per_page } => {
             let target_address = if let Some(addr) = address {
                 Pubkey::from_str(&addr)?
             } else {
-                wallet.pubkey()
+                own_address(&wallet)?
             };
             
-            println!("🏦 Fetching owned assets for: {}", target_address);
+            println!("🏭 Fetching assets issued by: {}", target_address);
             
-            let assets = client.get_owned_assets(&target_address).await?;
+            let offset = page.saturating_sub(1) * per_page;
+            let issued = client
+                .get_issued_assets_page(&target_address, offset, per_page)
+                .await?;
             
-            if assets.is_empty() {
-                println!("📭 No assets found");
+            if issued.total == 0 {
+                println!("📭 No issued assets found");
             } else {
-                println!("📋 Found {} assets:", assets.len());
-                for (i, (mint, balance)) in assets.iter().enumerate() {
-                    println!("\n{}. Mint: {}", i + 1, mint);
-                    println!("   Balance: {}", balance);
-                    
-                    // Try to get asset metadata
-                    if let Ok(asset_info) = client.get_asset_info(mint).await {
-                        println!("   Name: {}", asset_info.name);
-                        println!("   Type: {}", asset_info.asset_type);
+                println!("\n📊 {} issued assets (page {}):", issued.total, page);
+                for asset in &issued.assets {
+                    println!("\n🪙 {} ({})", asset.name, asset.mint);
+                    println!("   Supply: {}", asset.current_supply);
+                    if let Some(created_at) = asset.created_at {
+                        let created = chrono::DateTime::from_timestamp(created_at as i64, 0)
+                            .map(|dt| dt.to_rfc3339())
+                            .unwrap_or_else(|| created_at.to_string());
+                        println!("   Created: {}", created);
+                    }
+                    if let Some(signature) = asset.creation_signature {
+                        println!("   Creation tx: {}", signature);
+                    }
+                }
+                if offset + issued.assets.len() < issued.total {
+                    println!("\n💡 More results: --page {}", page + 1);
+                }
+            }
+        }
+        
+        Commands::Reconcile { expected, from, to, output, address } => {
+            let target_address = if let Some(addr) = address {
+                Pubkey::from_str(&addr)?
+            } else {
+                own_address(&wallet)?
+            };
+            let day = |value: &str| {
+                chrono::NaiveDate::parse_from_str(value, "%Y-%m-%d")
+                    .map_err(|_| anyhow::anyhow!("Expected a date as YYYY-MM-DD, got '{}'", value))
+            };
+            let window = reconcile::day_window(day(&from)?, day(&to)?);
+            let expected = client.expected_payments_from_csv(&std::fs::read_to_string(&expected)?)?;
+            
+            println!("🧮 Reconciling {} expected payments to {} ({} to {})", expected.len(), target_address, from, to);
+            let report = client.reconcile(expected, &target_address, window).await?;
+            
+            let mut currencies: HashMap<Pubkey, finternet_sdk::Currency> = HashMap::new();
+            for item in &report.items {
+                let mint = item.expected.mint;
+                let currency = match currencies.entry(mint) {
+                    std::collections::hash_map::Entry::Occupied(entry) => entry.into_mut(),
+                    std::collections::hash_map::Entry::Vacant(entry) => {
+                        let decimals = client.client.get_token_supply(&mint)?.decimals;
+                        entry.insert(finternet_sdk::Currency::spl(mint, decimals))
                     }
+                };
+                let icon = match item.status {
+                    ReconciliationStatus::Matched => "✅",
+                    ReconciliationStatus::PartiallyPaid => "🟡",
+                    ReconciliationStatus::Overpaid => "🟠",
+                    ReconciliationStatus::Unmatched => "❌",
+                };
+                let tier = item.tier.map(|tier| format!(" via {:?}", tier)).unwrap_or_default();
+                println!(
+                    "{} {}: {:?}{}, received {} of {}{}",
+                    icon,
+                    item.expected.id,
+                    item.status,
+                    tier,
+                    output::amounts().format(currency, item.received),
+                    output::amounts().format(currency, item.expected.amount),
+                    if item.overdue { " ⏰ overdue" } else { "" }
+                );
+                for signature in &item.signatures {
+                    println!("   📝 {}", signature);
+                }
+            }
+            for record in &report.unexpected {
+                println!(
+                    "❔ Unexpected: {} from {} ({})",
+                    output::amounts().format(&record.currency, record.amount),
+                    record.from,
+                    record.signature
+                );
+            }
+            match report.completeness {
+                HistoryCompleteness::Complete => {}
+                HistoryCompleteness::TruncatedAt(slot) => println!(
+                    "⚠️  History is truncated at slot {}, inside the window; older payments were not considered",
+                    slot
+                ),
+                HistoryCompleteness::Unknown => {
+                    println!("⚠️  History scan stopped before the start of the window; older payments were not considered")
+                }
+            }
+            println!(
+                "📊 {} matched, {} partially paid, {} overpaid, {} unmatched, {} unexpected",
+                report.count(ReconciliationStatus::Matched),
+                report.count(ReconciliationStatus::PartiallyPaid),
+                report.count(ReconciliationStatus::Overpaid),
+                report.count(ReconciliationStatus::Unmatched),
+                report.unexpected.len()
+            );
+            if let Some(path) = output {
+                std::fs::write(&path, serde_json::to_string_pretty(&report)?)?;
+                println!("📁 Saved to: {}", path);
+            }
+        }
+        
+        #[cfg(feature = "sqlite")]
+        Commands::Statement { month, mint, output, address, db, identity_index, allow_partial } => {
+            let target_address = if let Some(addr) = address {
+                Pubkey::from_str(&addr)?
+            } else {
+                own_address(&wallet)?
+            };
+            let month = YearMonth::from_str(&month)?;
+            let mint = match mint.as_deref() {
+                Some(m) if m.eq_ignore_ascii_case("usdc") => Some(client.usdc_mint()?),
+                Some(m) => Some(Pubkey::from_str(m)?),
+                None => None,
+            };
+            let index = identity_index
+                .map(|path| IdentityIndex::load(std::path::Path::new(&path)))
+                .transpose()?;
+            
+            println!("🧾 Statement for {} ({})", target_address, month);
+            
+            let ledger = LocalLedger::open(std::path::Path::new(&db))?;
+            if ledger.last_synced_at(&target_address)?.is_none() {
+                println!("⚠️  {} has never been synced into {}; run `sync` first", target_address, db);
+            }
+            let statement = client
+                .generate_statement(&ledger, &target_address, month, mint, index.as_ref(), allow_partial)
+                .await?;
+            
+            for section in statement.mints.iter().filter(|m| m.opening_balance_unverified) {
+                println!(
+                    "⚠️  Opening balance of {} is unverified: history is {}",
+                    section.currency, statement.completeness
+                );
+            }
+            for section in statement.mints.iter().filter(|m| m.is_mismatched()) {
+                println!(
+                    "🚨 BALANCE MISMATCH for {}: computed {} vs on-chain {}",
+                    section.currency,
+                    output::amounts().format_signed(&section.currency, section.closing_balance),
+                    output::amounts().format(&section.currency, section.on_chain_closing_balance)
+                );
+            }
+            match output {
+                Some(path) if path.ends_with(".json") => {
+                    std::fs::write(&path, serde_json::to_string_pretty(&statement)?)?;
+                    println!("📁 Saved to: {}", path);
+                }
+                Some(path) => {
+                    std::fs::write(&path, statement.to_markdown())?;
+                    println!("📁 Saved to: {}", path);
+                }
+                None => println!("\n{}", statement.to_markdown()),
+            }
+        }
+        
+        Commands::Costs { address, from, to, sol_usd } => {
+            let target_address = if let Some(addr) = address {
+                Pubkey::from_str(&addr)?
+            } else {
+                own_address(&wallet)?
+            };
+            
+            let parse_day = |day: &str| chrono::NaiveDate::parse_from_str(day, "%Y-%m-%d")
+                .map_err(|e| anyhow::anyhow!("Invalid date '{}', expected YYYY-MM-DD: {}", day, e));
+            let from_ts = parse_day(&from)?.and_hms_opt(0, 0, 0).unwrap().and_utc().timestamp() as u64;
+            let to_ts = parse_day(&to)?.and_hms_opt(23, 59, 59).unwrap().and_utc().timestamp() as u64;
+            
+            println!("🧾 Cost summary for {} from {} to {}", target_address, from, to);
+            
+            let mut summary = client.get_cost_summary(&target_address, from_ts, to_ts).await?;
+            if let Some(price) = sol_usd {
+                summary = summary.with_usd_price(price);
+            }
+            
+            let print_costs = |costs: &CategoryCosts| {
+                let line = |label: &str, amount: &CostAmount| {
+                    let usd = amount.usd.map(|usd| format!(" ({})", output::amounts().format_usd(usd))).unwrap_or_default();
+                    println!("   {:<15} {:>12} lamports  {:.6} SOL{}", label, amount.lamports, amount.sol, usd);
+                };
+                line("Fees:", &costs.fees);
+                line("  priority:", &costs.priority_fees);
+                line("Rent paid:", &costs.rent_paid);
+                line("Rent reclaimed:", &costs.rent_reclaimed);
+            };
+            
+            for (category, costs) in &summary.by_category {
+                println!("\n📂 {:?} ({} transactions)", category, costs.transactions);
+                print_costs(costs);
+            }
+            
+            let total = &summary.total;
+            println!("\n💰 Total ({} transactions)", total.transactions);
+            print_costs(total);
+            println!("   {:<15} {:>12} lamports", "Net cost:", total.net_lamports());
+        }
+        
+        Commands::Portfolio { addresses, sol_usd, token_usd, view } => {
+            let addresses = if addresses.is_empty() {
+                vec![own_address(&wallet)?]
+            } else {
+                addresses.iter().map(|addr| Pubkey::from_str(addr)).collect::<Result<Vec<_>, _>>()?
+            };
+            let mut prices = UsdPrices { sol: sol_usd, ..Default::default() };
+            for entry in &token_usd {
+                let (mint, price) = entry
+                    .split_once(':')
+                    .ok_or_else(|| anyhow::anyhow!("Invalid --token-usd '{}', expected <mint>:<price>", entry))?;
+                let price: f64 = price
+                    .parse()
+                    .map_err(|e| anyhow::anyhow!("Invalid price in --token-usd '{}': {}", entry, e))?;
+                prices.tokens.insert(Pubkey::from_str(mint)?, price);
+            }
+            
+            if !view.is_json() {
+                println!("📊 Portfolio of {} wallets", addresses.len());
+            }
+            let portfolio = client.get_portfolio(&addresses).await?.with_usd_prices(&prices);
+            if view.is_json() {
+                println!("{}", serde_json::to_string_pretty(&portfolio)?);
+            } else {
+                for failed in portfolio.wallets.iter().filter(|wallet| wallet.error.is_some()) {
+                    println!("⚠️  {}: {}", failed.address, failed.error.as_deref().unwrap_or_default());
+                }
+                let rows = |wallet: Option<Pubkey>, holdings: &[Holding]| {
+                    holdings
+                        .iter()
+                        .map(|holding| PortfolioRow {
+                            wallet,
+                            currency: holding.currency.clone(),
+                            name: holding.name.clone(),
+                            amount: holding.amount,
+                            usd_value: holding.usd_value,
+                        })
+                        .collect::<Vec<_>>()
+                };
+                let per_wallet = portfolio
+                    .wallets
+                    .iter()
+                    .flat_map(|wallet| rows(Some(wallet.address), &wallet.holdings))
+                    .collect();
+                println!();
+                output::print_rows(per_wallet, &view, None, "📭 No holdings found")?;
+                println!("\n💰 Totals");
+                output::print_rows(rows(None, &portfolio.totals), &view, None, "📭 No holdings found")?;
+                if let Some(usd) = portfolio.usd_value {
+                    println!("\n   Total value: {}", output::amounts().format_usd(usd));
                 }
             }
         }
         
+        Commands::Bootstrap { .. } => unreachable!("bootstrap is handled before the wallet is loaded"),
+        Commands::Localnet { .. } => unreachable!("localnet is handled before the client is built"),
+        Commands::ExportState { .. }
+        | Commands::ImportState { .. }
+        | Commands::VerifyActionLog { .. }
+        | Commands::RpcBenchmark { .. } => {
+            unreachable!("state bundles, action logs, and RPC benchmarks are handled before the wallet is loaded")
+        }
+        
         Commands::CreateWallet { output_path } => {
             let new_wallet = FinternetClient::create_new_wallet();
             let path = std::path::Path::new(&output_path);
@@ -338,33 +2728,174 @@ async fn main() -> Result<()> {
             println!("   2. Set as default: solana config set --keypair {}", output_path);
         }
         
-        Commands::WalletInfo { address } => {
+        Commands::RecoverSessionWallet { pubkey, output } => {
+            let dir = session_wallet_dir();
+            let Some(pubkey) = pubkey else {
+                let mut found = 0;
+                for entry in std::fs::read_dir(&dir).into_iter().flatten().flatten() {
+                    let path = entry.path();
+                    if let Ok(session) = FinternetClient::load_wallet_from_file(&path) {
+                        println!("🔑 {}  {}", session.pubkey(), path.display());
+                        found += 1;
+                    }
+                }
+                if found == 0 {
+                    println!("📭 No session wallets in {}", dir.display());
+                } else {
+                    println!("\n💡 Keep one with: finternet-cli recover-session-wallet <PUBKEY> -o ~/.config/solana/id.json");
+                }
+                return Ok(());
+            };
+            let output = output.ok_or_else(|| anyhow::anyhow!("Pass -o with where to save the wallet"))?;
+            let source = dir.join(session_wallet_file_name(&Pubkey::from_str(&pubkey)?));
+            let session = FinternetClient::load_wallet_from_file(&source)
+                .map_err(|e| anyhow::anyhow!("No session wallet {} in {}: {}", pubkey, dir.display(), e))?;
+            let path = std::path::Path::new(&output);
+            if path.exists() {
+                return Err(anyhow::anyhow!("{} already exists; choose another path", output));
+            }
+            if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+                std::fs::create_dir_all(parent)?;
+            }
+            save_private_key(&session, path)?;
+            println!("✅ Session wallet {} saved to {}", session.pubkey(), output);
+            println!("🗑️  {} can be deleted once you have checked the new file", source.display());
+        }
+        
+        Commands::WalletInfo { address } => {
+            let target_address = if let Some(addr) = address {
+                Pubkey::from_str(&addr)?
+            } else {
+                own_address(&wallet)?
+            };
+            
+            println!("ℹ️  Wallet Information for: {}", target_address);
+            
+            let wallet_info = client.get_wallet_info(&target_address).await?;
+            let identity = client.get_identity(&target_address).await?;
+            
+            println!("\n💰 Balances:");
+            for (currency, amount) in wallet_info.balances() {
+                println!("   {}", output::amounts().format(&currency, amount));
+            }
+            println!("   Token types: {}", wallet_info.total_token_types());
+            if !wallet_info.undecodable_accounts.is_empty() {
+                println!("   ⚠️  {} token accounts could not be decoded:", wallet_info.undecodable_accounts.len());
+                for account in &wallet_info.undecodable_accounts {
+                    println!("      {}: {}", account.address, account.reason);
+                }
+            }
+            
+            println!("\n🆔 Identity:");
+            if let Some(display_name) = &identity.display_name {
+                println!("   Display Name: {}", display_name);
+            }
+            
+            for (key, value) in &identity.metadata {
+                println!("   {}: {}", key, value);
+            }
+            for field in &identity.redacted_fields {
+                println!("   {}: (redacted)", field);
+            }
+            
+            let status_icon = match &wallet_info.status {
+                AccountStatus::NotFound => "⚪",
+                AccountStatus::SystemAccountActive { .. } => "🟢",
+                AccountStatus::ProgramOwned { .. } => "🧩",
+                AccountStatus::RentExemptReserve { .. } => "🟡",
+                AccountStatus::RpcUnavailable { .. } => "🔴",
+            };
+            println!("   Status: {} {}", status_icon, wallet_info.status);
+        }
+        
+        Commands::GateCheck { address, mint, min, collection, asset_type, issuer } => {
+            let owner = Pubkey::from_str(&address)?;
+            let requirement = match (mint, collection, asset_type, issuer) {
+                (Some(mint), None, None, None) => {
+                    let currency = if mint.eq_ignore_ascii_case("usdc") {
+                        finternet_sdk::Currency::usdc_devnet()
+                    } else {
+                        let mint = Pubkey::from_str(&mint)?;
+                        finternet_sdk::Currency::spl(mint, client.client.get_token_supply(&mint)?.decimals)
+                    };
+                    let min_amount = match min {
+                        Some(min) => currency.parse_amount(&min).map_err(|e| anyhow::anyhow!(e))?,
+                        None => 1,
+                    };
+                    GateRequirement::HoldsMint {
+                        mint: currency.mint().ok_or_else(|| anyhow::anyhow!("Gates only check token balances"))?,
+                        min_amount,
+                    }
+                }
+                (None, Some(collection), None, None) => GateRequirement::HoldsCollection {
+                    collection: Pubkey::from_str(&collection)?,
+                },
+                (None, None, Some(asset_type), Some(issuer)) => GateRequirement::HoldsAssetType {
+                    asset_type: AssetType::from_str(&asset_type).map_err(|e| anyhow::anyhow!(e))?,
+                    issuer: Pubkey::from_str(&issuer)?,
+                },
+                _ => anyhow::bail!("Pass --mint, --collection, or --asset-type with --issuer"),
+            };
+            
+            println!("🚪 Checking whether {} {}", owner, requirement);
+            let mut result = client.verify_token_gate(&owner, &requirement).await?;
+            result.attest(signer(&wallet)?)?;
+            if let Some(evidence) = &result.evidence {
+                println!("✅ Passed: {} of {} in {}", evidence.balance, evidence.mint, evidence.token_account);
+            } else {
+                println!("❌ Not satisfied");
+            }
+            for mint in &result.frozen_mints {
+                println!("🧊 {} is held in a frozen account and does not count", mint);
+            }
+            if let Some(attestation) = &result.attestation {
+                println!("🔏 Signed by {} at {}: {}", attestation.attester, result.checked_at, attestation.signature);
+            }
+        }
+        
+        Commands::Analyze { address } => {
             let target_address = if let Some(addr) = address {
                 Pubkey::from_str(&addr)?
             } else {
-                wallet.pubkey()
+                own_address(&wallet)?
             };
             
-            println!("ℹ️  Wallet Information for: {}", target_address);
-            
-            let wallet_info = client.get_wallet_info(&target_address).await?;
-            let identity = client.get_identity(&target_address).await?;
+            println!("🔍 Analyzing counterparty: {}", target_address);
+            let report = client.analyze_counterparty(&target_address).await?;
             
-            println!("\n💰 Balances:");
-            println!("   SOL: {:.4}", wallet_info.sol_balance_as_sol());
-            println!("   Token types: {}", wallet_info.total_token_types());
+            println!("\n📜 Activity:");
+            let days = |secs: u64| secs as f64 / 86_400.0;
+            match report.account_age_secs {
+                Some(age) if report.history_complete => println!("   Account age: {:.1} days", days(age)),
+                Some(age) => println!("   Account age: at least {:.1} days", days(age)),
+                None => println!("   Account age: unknown"),
+            }
+            println!("   Transactions: {} ({} failed)", report.transaction_count, report.failed_transactions);
+            println!("   Distinct counterparties: {}", report.distinct_counterparties);
+            println!("   Tokens transacted: {}, held: {}", report.token_mints_transacted, report.token_mints_held);
             
             println!("\n🆔 Identity:");
-            if let Some(display_name) = &identity.display_name {
-                println!("   Display Name: {}", display_name);
+            match (report.identity_registered, &report.identity_name) {
+                (true, Some(name)) => println!("   Registered as {}", name),
+                (true, None) => println!("   Registered (no display name)"),
+                (false, _) => println!("   Not registered"),
             }
+            println!(
+                "   Issued assets: {}{}",
+                report.issued_assets,
+                if report.has_verified_collection { " (verified collection)" } else { "" }
+            );
             
-            for (key, value) in &identity.metadata {
-                println!("   {}: {}", key, value);
+            println!();
+            if report.flags.is_empty() {
+                println!("✅ No risk flags");
+            }
+            for flag in &report.flags {
+                println!("🚩 {}", flag);
+            }
+            for note in &report.scan_notes {
+                println!("ℹ️  {}", note);
             }
-            
-            let is_active = client.is_account_active(&target_address).await?;
-            println!("   Status: {}", if is_active { "Active" } else { "Inactive" });
         }
         
         Commands::RegisterIdentity { display_name, email, organization } => {
@@ -379,22 +2910,64 @@ async fn main() -> Result<()> {
             }
             
             metadata.insert("registration_method".to_string(), "cli".to_string());
+            require_valid(validate_identity(&display_name, &metadata))?;
             
             println!("📝 Registering identity: {}", display_name);
             
             let signature = client
-                .register_identity(&wallet, &display_name, metadata)
+                .register_identity(signer(&wallet)?, &display_name, metadata)
                 .await?;
             
             println!("✅ Identity registered successfully!");
             println!("📝 Transaction: {}", signature);
         }
         
+        Commands::Redact { fields, memos } => {
+            let memos = memos
+                .iter()
+                .map(|signature| signature.parse::<solana_sdk::signature::Signature>())
+                .collect::<Result<Vec<_>, _>>()?;
+            println!("🙈 Redacting {} identity fields and {} memos", fields.len(), memos.len());
+            
+            let fields: Vec<&str> = fields.iter().map(String::as_str).collect();
+            let signature = client.redact(signer(&wallet)?, &fields, &memos).await?;
+            
+            println!("✅ Redaction recorded; the original data stays on-chain but SDK readers hide it");
+            println!("📝 Transaction: {}", signature);
+        }
+        
+        Commands::RegisterIdentities { file, journal, resume, attestations_dir } => {
+            let journal_path = std::path::Path::new(&journal);
+            let result = match file {
+                Some(file) if !resume => {
+                    let entries = IdentityEntry::from_csv(&std::fs::read_to_string(&file)?)?;
+                    println!("📝 Registering {} identities", entries.len());
+                    client.register_identities_bulk(signer(&wallet)?, entries, Some(journal_path)).await?
+                }
+                _ => {
+                    println!("🔁 Resuming identity registration from {}", journal);
+                    client.resume_identity_registration(signer(&wallet)?, journal_path).await?
+                }
+            };
+            for attestation in result.items.iter().filter_map(|item| item.attestation.as_ref()) {
+                attestation.save(&std::path::Path::new(&attestations_dir).join(format!("{}.json", attestation.subject)))?;
+            }
+            print_identity_registration(&result, &attestations_dir);
+        }
+        
+        Commands::ConfirmAttestation { file } => {
+            let attestation = IdentityAttestation::load(std::path::Path::new(&file))?;
+            println!("📝 Confirming identity '{}' attested by {}", attestation.display_name, attestation.authority);
+            let signature = client.confirm_identity_attestation(signer(&wallet)?, &attestation).await?;
+            println!("✅ Identity registered successfully!");
+            println!("📝 Transaction: {}", signature);
+        }
+        
         Commands::SetupUsdc { address } => {
             let target_address = if let Some(addr) = address {
                 Pubkey::from_str(&addr)?
             } else {
-                wallet.pubkey()
+                own_address(&wallet)?
             };
             
             println!("💵 Setting up devnet USDC for: {}", target_address);
@@ -404,7 +2977,7 @@ async fn main() -> Result<()> {
                     println!("📋 USDC Status: {}", status);
                     
                     let balance = client.get_usdc_balance(&target_address).await?;
-                    println!("💰 Current USDC Balance: ${:.2}", balance);
+                    println!("💰 Current USDC Balance: {}", output::amounts().format_usd(balance));
                     
                     if balance < 1.0 {
                         println!("\n💡 To get devnet USDC:");
@@ -419,44 +2992,495 @@ async fn main() -> Result<()> {
             }
         }
         
-        Commands::DiscoverTokens { address } => {
+        Commands::Diagnose { address, samples, output } => {
+            let target_address = if let Some(addr) = address {
+                Pubkey::from_str(&addr)?
+            } else {
+                own_address(&wallet)?
+            };
+            let json = output.eq_ignore_ascii_case("json");
+            if !json {
+                println!("🩺 Timing {} against {}", target_address, client.rpc_endpoint());
+            }
+            
+            let report = client.diagnose(&target_address, samples).await?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&report)?);
+            } else {
+                print_diagnostic_report(&report);
+            }
+        }
+        
+        Commands::ErrorCodes { output } => {
+            if output.eq_ignore_ascii_case("json") {
+                let codes: Vec<_> = FinternetError::ALL
+                    .iter()
+                    .map(|kind| serde_json::json!({
+                        "code": kind.code(),
+                        "name": kind.identifier(),
+                        "exit_code": kind.exit_code(),
+                        "http_status": kind.http_status(),
+                    }))
+                    .collect();
+                println!("{}", serde_json::to_string_pretty(&codes)?);
+            } else {
+                println!("{:<6} {:<28} {:>4} {:>5}", "CODE", "NAME", "EXIT", "HTTP");
+                for kind in FinternetError::ALL {
+                    println!("{:<6} {:<28} {:>4} {:>5}", kind.code(), kind.identifier(), kind.exit_code(), kind.http_status());
+                }
+            }
+        }
+        
+        Commands::DiscoverTokens { address, limit, view } => {
             let target_address = if let Some(addr) = address {
                 Pubkey::from_str(&addr)?
             } else {
-                wallet.pubkey()
+                own_address(&wallet)?
             };
             
-            println!("🔍 Discovering all tokens for: {}", target_address);
+            if !view.is_json() {
+                println!("🔍 Discovering all tokens for: {}", target_address);
+            }
             
-            match client.discover_all_tokens(&target_address).await {
-                Ok(tokens) => {
-                    if tokens.is_empty() {
-                        println!("📭 No token holdings found");
-                    } else {
-                        println!("📦 Found {} token holdings:", tokens.len());
-                        for (mint, balance, name) in tokens {
-                            let display_name = name.unwrap_or_else(|| "Unknown Token".to_string());
-                            println!("   • {} (Balance: {}) - {}", display_name, balance, mint);
+            let tokens = client.discover_all_tokens(&target_address).await?;
+            let decimals: HashMap<Pubkey, u8> = client
+                .get_token_account_details(&target_address)
+                .await?
+                .iter()
+                .map(|account| (account.mint, account.currency().decimals()))
+                .collect();
+            let rows = tokens
+                .into_iter()
+                .map(|(mint, amount, name)| TokenRow {
+                    mint,
+                    name,
+                    amount,
+                    decimals: decimals.get(&mint).copied().unwrap_or(0),
+                })
+                .collect();
+            output::print_rows(rows, &view, limit, "📭 No token holdings found")?;
+        }
+        
+        Commands::Credential { action } => match action {
+            CredentialAction::Issue { subject, claims, expires_in_days, anchor, output } => {
+                let subject = Pubkey::from_str(&subject)?;
+                let claims = claims
+                    .iter()
+                    .map(|claim| {
+                        claim
+                            .split_once('=')
+                            .map(|(k, v)| (k.to_string(), v.to_string()))
+                            .ok_or_else(|| anyhow::anyhow!("Claim '{}' must be KEY=VALUE", claim))
+                    })
+                    .collect::<anyhow::Result<HashMap<_, _>>>()?;
+                let expires_at = chrono::Utc::now().timestamp() as u64 + expires_in_days * 86_400;
+                
+                println!("🪪 Issuing credential to {} with {} claims", subject, claims.len());
+                
+                let credential = client
+                    .issue_credential(signer(&wallet)?, subject, claims, expires_at, anchor)
+                    .await?;
+                std::fs::write(&output, serde_json::to_string_pretty(&credential)?)?;
+                
+                println!("✅ Credential {} issued", credential.id);
+                if let Some(signature) = credential.anchor_signature {
+                    println!("⚓ Anchored: {}", signature);
+                }
+                println!("📁 Saved to: {}", output);
+            }
+            
+            CredentialAction::Verify { file } => {
+                let credential: SignedCredential = serde_json::from_str(&std::fs::read_to_string(&file)?)?;
+                println!("🔍 Verifying credential {} from {}", credential.id, credential.issuer);
+                
+                match client.verify_credential(&credential).await? {
+                    CredentialStatus::Valid => {
+                        println!("✅ Valid credential for {}", credential.subject);
+                        for (key, value) in &credential.claims {
+                            println!("   {}: {}", key, value);
                         }
                     }
+                    CredentialStatus::InvalidSignature => println!("❌ Signature does not match the issuer"),
+                    CredentialStatus::Expired { expired_at } => println!("⌛ Expired at {}", expired_at),
+                    CredentialStatus::AnchorMismatch => println!("❌ On-chain anchor missing or does not match"),
+                    CredentialStatus::Revoked { signature } => println!("🚫 Revoked by the issuer: {}", signature),
                 }
-                Err(e) => {
-                    println!("❌ Token discovery failed: {}", e);
+            }
+            
+            CredentialAction::Revoke { file } => {
+                let credential: SignedCredential = serde_json::from_str(&std::fs::read_to_string(&file)?)?;
+                println!("🚫 Revoking credential {}", credential.id);
+                
+                let signature = client.revoke_credential(signer(&wallet)?, &credential).await?;
+                println!("✅ Credential revoked!");
+                println!("📝 Transaction: {}", signature);
+            }
+        },
+        
+        Commands::Identities { action } => match action {
+            IdentitiesAction::Build { addresses, index } => {
+                let addresses = addresses
+                    .iter()
+                    .map(|addr| Pubkey::from_str(addr))
+                    .collect::<Result<Vec<_>, _>>()?;
+                println!("🗂️  Indexing identities of {} addresses", addresses.len());
+                
+                let built = client.build_identity_index(&addresses).await?;
+                built.save(std::path::Path::new(&index))?;
+                println!("✅ Indexed {} addresses into {}", built.len(), index);
+            }
+            
+            IdentitiesAction::Refresh { index, addresses } => {
+                let path = std::path::Path::new(&index);
+                let mut loaded = IdentityIndex::load(path)?;
+                let addresses = addresses
+                    .iter()
+                    .map(|addr| Pubkey::from_str(addr))
+                    .collect::<Result<Vec<_>, _>>()?;
+                
+                let rescanned = client.refresh_identity_index(&mut loaded, &addresses).await?;
+                loaded.save(path)?;
+                println!("✅ Rescanned {} of {} addresses", rescanned, loaded.len());
+            }
+            
+            IdentitiesAction::Lookup { index, name, metadata } => {
+                let loaded = IdentityIndex::load(std::path::Path::new(&index))?;
+                let matches = match (name, metadata) {
+                    (Some(name), _) => loaded.lookup_by_name(&name),
+                    (None, Some(pair)) => {
+                        let (key, value) = pair
+                            .split_once('=')
+                            .ok_or_else(|| anyhow::anyhow!("Metadata must be KEY=VALUE"))?;
+                        loaded.lookup_by_metadata(key, value)
+                    }
+                    (None, None) => return Err(anyhow::anyhow!("Specify --name or --metadata")),
+                };
+                
+                match matches.len() {
+                    0 => println!("📭 No matching identity"),
+                    1 => println!("✅ {}", matches[0]),
+                    n => {
+                        println!("⚠️  {} addresses claim this identity:", n);
+                        for pubkey in &matches {
+                            println!("   • {}", pubkey);
+                        }
+                    }
                 }
             }
-        }
+        },
+        
+        Commands::Org { action } => match action {
+            OrgAction::Create { name } => {
+                println!("🏢 Creating organization '{}'", name);
+                let (id, signature) = client.create_organization(signer(&wallet)?, &name).await?;
+                println!("✅ Organization id: {}", id);
+                println!("📝 Signature: {}", signature);
+            }
+            
+            OrgAction::AddMember { org, member, role } => {
+                let org = Pubkey::from_str(&org)?;
+                let member = Pubkey::from_str(&member)?;
+                let role = OrgRole::from_str(&role)?;
+                let signature = client.add_member(signer(&wallet)?, &org, &member, role).await?;
+                println!("✅ {} is now {} in {}", member, role, org);
+                println!("📝 Signature: {}", signature);
+            }
+            
+            OrgAction::RemoveMember { org, member } => {
+                let org = Pubkey::from_str(&org)?;
+                let member = Pubkey::from_str(&member)?;
+                let signature = client.remove_member(signer(&wallet)?, &org, &member).await?;
+                println!("✅ {} removed from {}", member, org);
+                println!("📝 Signature: {}", signature);
+            }
+            
+            OrgAction::Show { org } => {
+                let org = Pubkey::from_str(&org)?;
+                let Some(organization) = client.get_organization(&org).await? else {
+                    println!("📭 No organization {} on the ledger", org);
+                    return Ok(());
+                };
+                println!("🏢 {} ({})", organization.name, organization.id);
+                println!("   Created by {} at {}", organization.creator, organization.created_at);
+                for (member, role) in &organization.members {
+                    println!("   • {} — {}", member, role);
+                }
+                if !organization.rejected.is_empty() {
+                    println!("\n⚠️  {} changes ignored:", organization.rejected.len());
+                    for change in &organization.rejected {
+                        println!("   {} {}: {}", change.action, change.signature, change.reason);
+                    }
+                }
+            }
+        },
+        
+        Commands::Timelock { action } => match action {
+            TimelockAction::Send { to, amount, mint, unlock_in_hours, expires_in_hours, output } => {
+                let to_pubkey = Pubkey::from_str(&to)?;
+                let currency = if mint.eq_ignore_ascii_case("usdc") {
+                    finternet_sdk::Currency::usdc_devnet()
+                } else {
+                    let mint = Pubkey::from_str(&mint)?;
+                    finternet_sdk::Currency::spl(mint, client.client.get_token_supply(&mint)?.decimals)
+                };
+                let mint_pubkey = currency.mint().ok_or_else(|| anyhow::anyhow!("Time-locked payments need a token mint"))?;
+                let amount = currency.parse_amount(&amount).map_err(|e| anyhow::anyhow!(e))?;
+                let now = chrono::Utc::now().timestamp() as u64;
+                let unlock_at = now + unlock_in_hours * 3600;
+                let expiry = now + expires_in_hours * 3600;
+                
+                println!("⏳ Locking {} for {}", output::amounts().format(&currency, amount), to_pubkey);
+                let handle = client
+                    .send_timelocked_payment(signer(&wallet)?, &to_pubkey, amount, &mint_pubkey, unlock_at, expiry)
+                    .await
+                    .inspect_err(explain_recipient_error)?;
+                handle.save(std::path::Path::new(&output))?;
+                
+                println!("✅ Payment locked in escrow {}", handle.escrow);
+                println!("🔓 Claimable from {} until {}", handle.unlock_at, handle.expiry);
+                println!("📝 Transaction: {}", handle.signature);
+                println!("📁 Saved to {}; send it to {} so they can claim", output, handle.recipient);
+            }
+            
+            TimelockAction::Cancel { handle } => {
+                let handle = TimelockHandle::load(std::path::Path::new(&handle))?;
+                let signature = client.cancel_timelocked(signer(&wallet)?, &handle).await?;
+                println!("↩️  Time-locked payment {} cancelled", handle.id);
+                println!("📝 Transaction: {}", signature);
+            }
+            
+            TimelockAction::Claim { handle, output } => {
+                let handle = TimelockHandle::load(std::path::Path::new(&handle))?;
+                let claim = client.claim_timelocked(signer(&wallet)?, &handle).await?;
+                claim.save(std::path::Path::new(&output))?;
+                println!("✍️  Claim to {} base units of {} signed", handle.amount, handle.mint);
+                println!("📁 Saved to {}; send it to {} to countersign", output, handle.sender);
+            }
+            
+            TimelockAction::Countersign { handle, claim } => {
+                let handle = TimelockHandle::load(std::path::Path::new(&handle))?;
+                let claim = TimelockClaim::load(std::path::Path::new(&claim))?;
+                let signature = client.countersign_timelock_claim(signer(&wallet)?, &handle, &claim).await?;
+                println!("✅ {} claimed {} base units of {}", handle.recipient, handle.amount, handle.mint);
+                println!("📝 Transaction: {}", signature);
+            }
+            
+            TimelockAction::Reclaim { handle } => {
+                let handle = TimelockHandle::load(std::path::Path::new(&handle))?;
+                let signature = client.reclaim_expired(signer(&wallet)?, &handle).await?;
+                println!("↩️  Expired payment {} reclaimed", handle.id);
+                println!("📝 Transaction: {}", signature);
+            }
+            
+            TimelockAction::Status { handle } => {
+                let handle = TimelockHandle::load(std::path::Path::new(&handle))?;
+                let status = client.get_timelock_status(&handle).await?;
+                println!("⏳ Time lock {}: {}", status.id, status.state);
+                println!("   {} → {}", handle.sender, handle.recipient);
+                println!("   Escrow balance: {} of {} base units", status.escrow_balance, handle.amount);
+                println!("   Unlocks at {}, expires at {} (cluster time {})", handle.unlock_at, handle.expiry, status.checked_at);
+                for event in &status.events {
+                    println!("   • {:?} by {} at slot {}: {}", event.action, event.signer, event.slot, event.signature);
+                }
+            }
+        },
         
-        Commands::Demo => {
-            println!("🚀 Running enhanced Finternet SDK demo...");
-            println!("💡 This will demonstrate all core features with realistic scenarios");
-            println!("📋 Running: cargo run --example enhanced_demo");
+        Commands::SendClaimable { amount, mint, secret, expires_in_hours, output } => {
+            let currency = if mint.eq_ignore_ascii_case("usdc") {
+                finternet_sdk::Currency::usdc_devnet()
+            } else {
+                let mint = Pubkey::from_str(&mint)?;
+                finternet_sdk::Currency::spl(mint, client.client.get_token_supply(&mint)?.decimals)
+            };
+            let mint_pubkey = currency.mint().ok_or_else(|| anyhow::anyhow!("Claimable payments need a token mint"))?;
+            let amount = currency.parse_amount(&amount).map_err(|e| anyhow::anyhow!(e))?;
+            let secret = secret.unwrap_or_else(claim::generate_claim_secret);
+            let expires_at = chrono::Utc::now().timestamp() as u64 + expires_in_hours * 3600;
+            
+            println!("✉️  Sending {} as a claimable payment", output::amounts().format(&currency, amount));
+            let claim = client.send_claimable_payment(signer(&wallet)?, amount, &mint_pubkey, &secret, expires_at).await?;
+            claim.save(std::path::Path::new(&output))?;
             
-            // Note: In a real CLI, you might want to run the demo directly here
-            // For now, we'll provide instructions
-            println!("\n🔧 To run the full demo:");
-            println!("   cargo run --example enhanced_demo");
+            println!("✅ Funds held at {}", claim.claim_address);
+            println!("🔑 Claim secret: {}", claim.claim_secret);
+            println!("   Send it to the recipient out of band; anyone holding it can claim");
+            println!("⏰ You can cancel from {}", claim.expires_at);
+            println!("📝 Transaction: {}", claim.signature);
+            println!("📁 Saved to {}; keep it private, it holds the secret", output);
+        }
+        
+        Commands::Claim { secret, to } => {
+            let new_owner = match to {
+                Some(to) => Pubkey::from_str(&to)?,
+                None => own_address(&wallet)?,
+            };
+            let signature = client.claim_payment(&new_owner, &secret).await?;
+            println!("✅ Payment claimed into {}", new_owner);
+            println!("📝 Transaction: {}", signature);
         }
         
+        Commands::CancelClaimable { claim } => {
+            let claim = ClaimablePayment::load(std::path::Path::new(&claim))?;
+            let signature = client.cancel_claimable(signer(&wallet)?, &claim).await?;
+            println!("↩️  Claimable payment at {} cancelled", claim.claim_address);
+            println!("📝 Transaction: {}", signature);
+        }
+        
+        Commands::Redeem { action } => match action {
+            RedeemAction::Request { mint, amount, payout_details, redemption_address } => {
+                let mint_pubkey = Pubkey::from_str(&mint)?;
+                let redemption_pubkey = redemption_address
+                    .map(|addr| Pubkey::from_str(&addr))
+                    .transpose()?;
+                
+                println!("🔁 Requesting redemption of {} tokens of {}", amount, mint);
+                
+                let request = client
+                    .request_redemption(signer(&wallet)?, &mint_pubkey, amount, &payout_details, redemption_pubkey.as_ref())
+                    .await?;
+                
+                println!("✅ Redemption requested!");
+                println!("🆔 Request ID: {}", request.id);
+                println!("🏦 Issuer: {}", request.issuer);
+                if let Some(signature) = request.signature {
+                    println!("📝 Transaction: {}", signature);
+                }
+            }
+            
+            RedeemAction::List { issuer } => {
+                let issuer_address = if let Some(addr) = issuer {
+                    Pubkey::from_str(&addr)?
+                } else {
+                    own_address(&wallet)?
+                };
+                
+                println!("📥 Pending redemption requests for issuer: {}", issuer_address);
+                
+                let requests = client.list_redemption_requests(&issuer_address).await?;
+                if requests.is_empty() {
+                    println!("📭 No pending requests");
+                } else {
+                    for (i, request) in requests.iter().enumerate() {
+                        println!("\n{}. Request: {}", i + 1, request.id);
+                        println!("   Holder: {}", request.holder);
+                        println!("   Mint: {}", request.mint);
+                        println!("   Amount: {}", request.amount);
+                        println!("   Payout: {}", request.payout_details);
+                        if let Some(address) = request.redemption_address {
+                            println!("   Tokens returned to: {}", address);
+                        }
+                        println!("   Time: {}", request.timestamp);
+                    }
+                }
+            }
+            
+            RedeemAction::Settle { request_id, payment_signature } => {
+                let payment_signature = solana_sdk::signature::Signature::from_str(&payment_signature)?;
+                
+                let requests = client.list_redemption_requests(&own_address(&wallet)?).await?;
+                let request = requests
+                    .iter()
+                    .find(|request| request.id == request_id)
+                    .ok_or_else(|| anyhow::anyhow!("No pending redemption request with id {}", request_id))?;
+                
+                println!("🧾 Settling redemption request: {}", request_id);
+                
+                let settlement = client
+                    .settle_redemption(signer(&wallet)?, request, &payment_signature)
+                    .await?;
+                
+                println!("✅ Redemption settled!");
+                println!("🔥 Tokens burned: {}", settlement.burned);
+                println!("📝 Transaction: {}", settlement.signature);
+            }
+        },
+        
+        Commands::Listing { action } => match action {
+            ListingAction::Create { mint, price, currency, expires_in_hours, settlement_authority } => {
+                let mint_pubkey = Pubkey::from_str(&mint)?;
+                let settlement_authority = Pubkey::from_str(&settlement_authority)?;
+                let currency = if currency.eq_ignore_ascii_case("usdc") {
+                    finternet_sdk::Currency::usdc_devnet()
+                } else {
+                    let mint = Pubkey::from_str(&currency)?;
+                    finternet_sdk::Currency::spl(mint, client.client.get_token_supply(&mint)?.decimals)
+                };
+                let price = currency.parse_amount(&price).map_err(|e| anyhow::anyhow!(e))?;
+                let expiry = client.get_chain_time().await?.unix_timestamp() + expires_in_hours * 3600;
+                
+                println!("🏷️  Listing {} for {}", mint_pubkey, output::amounts().format(&currency, price));
+                let listing = client
+                    .create_listing(signer(&wallet)?, &mint_pubkey, price, &currency, expiry, &settlement_authority)
+                    .await?;
+                
+                println!("✅ Listed!");
+                println!("{}", listing);
+                println!("📝 Transaction: {}", listing.signature);
+            }
+            
+            ListingAction::List { address } => {
+                let address = if let Some(addr) = address {
+                    Pubkey::from_str(&addr)?
+                } else {
+                    own_address(&wallet)?
+                };
+                
+                println!("🛒 Active listings for: {}", address);
+                
+                let listings = client.get_listings(&address).await?;
+                if listings.is_empty() {
+                    println!("📭 No active listings");
+                } else {
+                    for (i, listing) in listings.iter().enumerate() {
+                        println!("\n{}. {}", i + 1, listing);
+                    }
+                }
+            }
+            
+            ListingAction::Reprice { listing_id, price } => {
+                let listings = client.get_listings(&own_address(&wallet)?).await?;
+                let listing = listings
+                    .iter()
+                    .find(|listing| listing.id == listing_id)
+                    .ok_or_else(|| anyhow::anyhow!("No active listing with id {}", listing_id))?;
+                let price = listing.currency.parse_amount(&price).map_err(|e| anyhow::anyhow!(e))?;
+                
+                let listing = client.update_listing_price(signer(&wallet)?, &listing_id, price).await?;
+                
+                println!("✅ Repriced; the listing is now {}", listing.id);
+                println!("{}", listing);
+                println!("📝 Transaction: {}", listing.signature);
+            }
+            
+            ListingAction::Cancel { listing_id } => {
+                let signature = client.cancel_listing(signer(&wallet)?, &listing_id).await?;
+                println!("✅ Listing {} cancelled", listing_id);
+                println!("📝 Transaction: {}", signature);
+            }
+            
+            ListingAction::Buy { listing_id, mint, settlement_keypair } => {
+                let mint_pubkey = Pubkey::from_str(&mint)?;
+                let settlement_authority = FinternetClient::load_wallet_from_file(std::path::Path::new(&settlement_keypair))?;
+                let listings = client.get_listings(&mint_pubkey).await?;
+                let listing = listings
+                    .iter()
+                    .find(|listing| listing.id == listing_id)
+                    .ok_or_else(|| anyhow::anyhow!("No active listing with id {} for {}", listing_id, mint_pubkey))?;
+                
+                println!("🤝 Buying listing {} for {}", listing.id, output::amounts().format(&listing.currency, listing.price));
+                let fulfillment = client.fulfill_listing(signer(&wallet)?, &settlement_authority, listing).await?;
+                
+                println!("✅ Bought!");
+                println!("📝 Settlement: {}", fulfillment.settlement_signature);
+                match fulfillment.record_signature {
+                    Some(signature) => println!("🧾 Fulfillment recorded: {}", signature),
+                    None => println!("⚠️  The swap settled but its fulfillment entry could not be written"),
+                }
+            }
+        },
+        
+        Commands::Demo { .. } => unreachable!("handled before the wallet is loaded"),
+        
         Commands::TestTokenDiscovery { wait_seconds } => {
             println!("🧪 Testing Token Creation and Discovery");
             println!("⏰ Will wait {} seconds for blockchain confirmation", wait_seconds);
@@ -469,8 +3493,8 @@ async fn main() -> Result<()> {
                 &test_name,
                 "Test token created for discovery verification",
                 1000,
-                "test",
-                &wallet,
+                &AssetType::Custom("test".to_string()),
+                signer(&wallet)?,
             ).await {
                 Ok((mint, _metadata, signature)) => {
                     println!("✅ Test token created: {}", mint);
@@ -482,7 +3506,7 @@ async fn main() -> Result<()> {
                     
                     // Test discovery
                     println!("🔍 Testing token discovery...");
-                    match client.discover_all_tokens(&wallet.pubkey()).await {
+                    match client.discover_all_tokens(&own_address(&wallet)?).await {
                         Ok(tokens) => {
                             if tokens.is_empty() {
                                 println!("📭 No tokens found in discovery");
@@ -507,7 +3531,7 @@ async fn main() -> Result<()> {
                     
                     // Also test balance check
                     println!("\n💰 Testing balance check...");
-                    match client.get_token_balance(&wallet.pubkey(), &mint).await {
+                    match client.get_token_balance(&own_address(&wallet)?, &mint).await {
                         Ok(balance) => {
                             println!("✅ Token balance check successful: {} tokens", balance);
                         }
@@ -524,4 +3548,574 @@ async fn main() -> Result<()> {
     }
     
     Ok(())
-} 
\ No newline at end of file
+} 
+
+/// Show the recipient precheck and, for a likely wrong address, ask before sending
+/// Whether `command` signs with the CLI wallet, so cannot run without one
+///
+/// Commands that only read take an address instead and run without any wallet. Bootstrap,
+/// demos, and the local-file commands are handled before a wallet is loaded.
+fn signs_with_wallet(command: &Commands) -> bool {
+    match command {
+        Commands::TokenizeAsset { .. }
+        | Commands::VerifyCreator { .. }
+        | Commands::AttachDoc { .. }
+        | Commands::SendPayment { .. }
+        | Commands::ProveHoldings { .. }
+        | Commands::BatchPayment { .. }
+        | Commands::Sweep { .. }
+        | Commands::BatchTokenize { .. }
+        | Commands::SendToken { .. }
+        | Commands::ProposePayment { .. }
+        | Commands::ApprovePayment { .. }
+        | Commands::GateCheck { .. }
+        | Commands::RegisterIdentity { .. }
+        | Commands::Redact { .. }
+        | Commands::RegisterIdentities { .. }
+        | Commands::ConfirmAttestation { .. }
+        | Commands::SendClaimable { .. }
+        | Commands::CancelClaimable { .. }
+        | Commands::TestTokenDiscovery { .. } => true,
+        // A payment link asks to be paid to the wallet that signs it
+        Commands::PaymentLink { .. } => true,
+        Commands::AssetPolicy { action } => matches!(action, AssetPolicyAction::Anchor { .. }),
+        Commands::Timelock { action } => !matches!(action, TimelockAction::Status { .. }),
+        Commands::Tokenize { action } => !matches!(action, TokenizeAction::Discard { .. }),
+        Commands::ColdSweep { action } => matches!(action, ColdSweepAction::Execute { .. }),
+        Commands::MigrateLedgerEntries { .. } => true,
+        Commands::Redeem { action } => !matches!(action, RedeemAction::List { .. }),
+        Commands::Listing { action } => !matches!(action, ListingAction::List { .. }),
+        Commands::Credential { action } => !matches!(action, CredentialAction::Verify { .. }),
+        Commands::Org { action } => !matches!(action, OrgAction::Show { .. }),
+        Commands::History { .. }
+        | Commands::Sync { .. }
+        | Commands::AssetInfo { .. }
+        | Commands::VerifyDoc { .. }
+        | Commands::VerifyHoldings { .. }
+        | Commands::Balance { .. }
+        | Commands::Watch { .. }
+        | Commands::Assets { .. }
+        | Commands::IssuedAssets { .. }
+        | Commands::Costs { .. }
+        | Commands::Portfolio { .. }
+        | Commands::Statement { .. }
+        | Commands::Reconcile { .. }
+        | Commands::Bootstrap { .. }
+        | Commands::Localnet { .. }
+        | Commands::ExportState { .. }
+        | Commands::ImportState { .. }
+        | Commands::VerifyActionLog { .. }
+        | Commands::CreateWallet { .. }
+        | Commands::RecoverSessionWallet { .. }
+        | Commands::WalletInfo { .. }
+        | Commands::RpcBenchmark { .. }
+        | Commands::Analyze { .. }
+        | Commands::SetupUsdc { .. }
+        | Commands::DiscoverTokens { .. }
+        | Commands::Diagnose { .. }
+        | Commands::ErrorCodes { .. }
+        | Commands::Claim { .. }
+        | Commands::Identities { .. }
+        | Commands::Demo { .. } => false,
+    }
+}
+
+/// The wallet a signing command runs with; always set, as `run` refuses those without one
+/// `history --local`: `owner`'s newest `limit` records from the local ledger, noting how
+/// complete its synced history is
+#[cfg(feature = "sqlite")]
+fn local_history(db: &str, owner: &Pubkey, limit: usize, notice: impl Fn(String)) -> Result<Vec<finternet_sdk::TransactionRecord>> {
+    let ledger = LocalLedger::open(std::path::Path::new(db))?;
+    match ledger.last_synced_at(owner)? {
+        Some(synced_at) => notice(format!("💾 Local ledger, last synced {}", synced_at)),
+        None => notice(format!("⚠️  {} has never been synced into {}; run `sync` first", owner, db)),
+    }
+    let gaps = ledger.gaps(owner)?;
+    if !gaps.is_empty() {
+        notice(format!("⚠️  {} known gaps in synced history:", gaps.len()));
+        for gap in &gaps {
+            notice(format!("   slot {}: {}", gap.after_slot, gap.reason));
+        }
+    }
+    if let Some(slot) = ledger.completeness(owner)?.truncated_at() {
+        notice(format!("⚠️  History is truncated at slot {}; the node serves nothing older", slot));
+    }
+    ledger.query_history(owner, limit, 0)
+}
+
+#[cfg(not(feature = "sqlite"))]
+fn local_history(_db: &str, _owner: &Pubkey, _limit: usize, _notice: impl Fn(String)) -> Result<Vec<finternet_sdk::TransactionRecord>> {
+    anyhow::bail!("The local ledger needs a build with --features sqlite")
+}
+
+fn signer(wallet: &Option<Keypair>) -> Result<&Keypair> {
+    wallet.as_ref().ok_or_else(|| anyhow::anyhow!("This command needs a wallet; pass --wallet"))
+}
+
+/// The wallet's address, for commands that default to it when no address is given
+fn own_address(wallet: &Option<Keypair>) -> Result<Pubkey> {
+    wallet
+        .as_ref()
+        .map(|wallet| wallet.pubkey())
+        .ok_or_else(|| anyhow::anyhow!("No wallet is set up; pass --address, or --wallet for your own"))
+}
+
+/// Where `--ephemeral-ok` keys are saved, so `recover-session-wallet` can find them
+///
+/// Per user: under `$XDG_RUNTIME_DIR`, which only its user can enter, or else a directory in the
+/// shared temp dir named for the user's uid.
+fn session_wallet_dir() -> std::path::PathBuf {
+    #[cfg(unix)]
+    {
+        if let Some(runtime) = std::env::var_os("XDG_RUNTIME_DIR").filter(|dir| !dir.is_empty()) {
+            return std::path::PathBuf::from(runtime).join("finternet-session-wallets");
+        }
+        std::env::temp_dir().join(format!("finternet-session-wallets-{}", current_uid()))
+    }
+    #[cfg(not(unix))]
+    std::env::temp_dir().join("finternet-session-wallets")
+}
+
+#[cfg(unix)]
+fn current_uid() -> u32 {
+    // SAFETY: getuid takes no arguments, touches no memory of ours, and always succeeds
+    unsafe { libc::getuid() }
+}
+
+/// Create `dir` accessible to this user alone, or check that an existing one still is
+///
+/// The temp dir is shared, so a directory already there may have been planted by another user
+/// to read the keys written into it.
+fn ensure_private_dir(dir: &std::path::Path) -> Result<()> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{DirBuilderExt, MetadataExt};
+        match std::fs::DirBuilder::new().mode(0o700).create(dir) {
+            Ok(()) => return Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {}
+            Err(e) => return Err(anyhow::anyhow!("Could not create {}: {}", dir.display(), e)),
+        }
+        // Not followed: a symlink could point the keys anywhere
+        let metadata = std::fs::symlink_metadata(dir)?;
+        if !metadata.is_dir() || metadata.uid() != current_uid() || metadata.mode() & 0o077 != 0 {
+            return Err(anyhow::anyhow!(
+                "Refusing to keep keys in {}: it is not a directory owned by you and closed to other users",
+                dir.display()
+            ));
+        }
+        Ok(())
+    }
+    #[cfg(not(unix))]
+    {
+        std::fs::create_dir_all(dir)?;
+        Ok(())
+    }
+}
+
+/// Write `wallet` to a new file at `path`, readable only by this user from the moment it exists
+///
+/// Never overwrites: an existing file at `path` is an error.
+fn save_private_key(wallet: &Keypair, path: &std::path::Path) -> Result<()> {
+    use std::io::Write;
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options
+        .open(path)
+        .map_err(|e| anyhow::anyhow!("Could not create {}: {}", path.display(), e))?;
+    file.write_all(serde_json::to_string_pretty(&wallet.to_bytes().to_vec())?.as_bytes())?;
+    file.sync_all()?;
+    Ok(())
+}
+
+fn session_wallet_file_name(pubkey: &Pubkey) -> String {
+    format!("session-{}.json", pubkey)
+}
+
+/// A throwaway key, saved before anything is signed with it so nothing it controls is lost
+fn create_session_wallet() -> Result<Keypair> {
+    let wallet = FinternetClient::create_new_wallet();
+    let dir = session_wallet_dir();
+    ensure_private_dir(&dir)?;
+    let path = dir.join(session_wallet_file_name(&wallet.pubkey()));
+    save_private_key(&wallet, &path)?;
+    println!("⚠️  No wallet set up; signing with throwaway key {}", wallet.pubkey());
+    println!("📁 Saved to {}", path.display());
+    println!("💡 The temp directory may be cleared; keep the key with: finternet-cli recover-session-wallet {} -o <path>", wallet.pubkey());
+    Ok(wallet)
+}
+
+async fn confirm_recipient(client: &FinternetClient, to: &Pubkey, mint: &Pubkey, yes: bool) -> Result<()> {
+    // A mint from another cluster is refused outright, not asked about
+    client.check_mint_network(mint).inspect_err(explain_recipient_error)?;
+    let precheck = client.precheck_recipient(to, mint).await?;
+    for warning in &precheck.warnings {
+        println!("⚠️  {}", warning);
+    }
+    if !precheck.needs_confirmation() || yes {
+        return Ok(());
+    }
+    
+    use std::io::{BufRead, IsTerminal, Write};
+    if !std::io::stdin().is_terminal() {
+        anyhow::bail!("Recipient looks wrong; rerun with --yes to send anyway");
+    }
+    print!("❓ Send anyway? [y/N] ");
+    std::io::stdout().flush()?;
+    let mut answer = String::new();
+    std::io::stdin().lock().read_line(&mut answer)?;
+    if matches!(answer.trim(), "y" | "Y" | "yes") {
+        Ok(())
+    } else {
+        anyhow::bail!("Payment cancelled")
+    }
+}
+
+/// Ask `question` before going ahead, unless `--yes` was given
+fn confirm_prompt(question: &str, yes: bool) -> Result<()> {
+    if yes {
+        return Ok(());
+    }
+    
+    use std::io::{BufRead, IsTerminal, Write};
+    if !std::io::stdin().is_terminal() {
+        anyhow::bail!("Rerun with --yes to go ahead without a prompt");
+    }
+    print!("{} [y/N] ", question);
+    std::io::stdout().flush()?;
+    let mut answer = String::new();
+    std::io::stdin().lock().read_line(&mut answer)?;
+    if matches!(answer.trim(), "y" | "Y" | "yes") {
+        Ok(())
+    } else {
+        anyhow::bail!("Cancelled")
+    }
+}
+
+/// Point out which address or mint the user probably meant to paste when a payment was refused
+fn explain_recipient_error(error: &anyhow::Error) {
+    match error.downcast_ref::<RecipientError>() {
+        Some(RecipientError::RecipientIsTokenAccountForDifferentMint { owner, account_mint, .. }) => {
+            println!("💡 You pasted a token account (for mint {}) rather than a wallet address.", account_mint);
+            println!("💡 To pay its owner, send to the wallet address {} instead.", owner);
+        }
+        Some(RecipientError::RecipientOffCurve { .. }) => {
+            println!("💡 That address is a program-derived address, not a wallet.");
+            println!("💡 Ask the recipient for their wallet address or their token account for this mint.");
+        }
+        None => {}
+    }
+    if let Some(MintNetworkError::MintNetworkMismatch { name, expected_network, .. }) = error.downcast_ref() {
+        match mint_registry::find(name, *expected_network) {
+            Some(known) => println!("💡 {} on {} is {}.", known.name, expected_network, known.mint),
+            None => println!("💡 Use the {} mint for {}, or connect to the cluster this mint belongs to.", name, expected_network),
+        }
+    }
+}
+
+/// `--deadline` as an `Instant`: an RFC 3339 timestamp, or `HH:MM` for that time today in local time
+fn parse_deadline(value: &str) -> Result<std::time::Instant> {
+    let now = chrono::Local::now();
+    let deadline = match chrono::DateTime::parse_from_rfc3339(value) {
+        Ok(deadline) => deadline.with_timezone(&chrono::Local),
+        Err(_) => {
+            let time = chrono::NaiveTime::parse_from_str(value, "%H:%M")
+                .map_err(|_| anyhow::anyhow!("Expected a deadline as RFC 3339 or HH:MM, got '{}'", value))?;
+            now.date_naive()
+                .and_time(time)
+                .and_local_timezone(chrono::Local)
+                .earliest()
+                .ok_or_else(|| anyhow::anyhow!("{} does not exist today in local time", value))?
+        }
+    };
+    let left = (deadline - now)
+        .to_std()
+        .map_err(|_| anyhow::anyhow!("Deadline {} has already passed", deadline.format("%Y-%m-%d %H:%M:%S")))?;
+    Ok(std::time::Instant::now() + left)
+}
+
+/// `--memo` as given, or the memo rendered from `--memo-template` and its `--memo-var key=value` flags
+fn render_memo(memo: Option<String>, template: Option<&str>, vars: &[String]) -> Result<Option<String>> {
+    let Some(template) = template else {
+        return Ok(memo);
+    };
+    let template = MemoTemplate::parse(template)?;
+    let vars = vars
+        .iter()
+        .map(|var| {
+            var.split_once('=')
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .ok_or_else(|| anyhow::anyhow!("Invalid --memo-var '{}'; expected key=value", var))
+        })
+        .collect::<Result<HashMap<_, _>>>()?;
+    let memo = template.render(&vars)?;
+    println!("📝 Memo: {}", memo);
+    Ok(Some(memo))
+}
+
+async fn run_localnet(action: &LocalnetAction) -> Result<()> {
+    let report = match action {
+        LocalnetAction::Down { dir } => {
+            let localnet = Localnet::new(LocalnetOptions { dir: std::path::PathBuf::from(dir), ..LocalnetOptions::default() });
+            match localnet.down().await? {
+                Some(pid) => println!("🛑 Stopped validator {}; ledger kept in {}", pid, localnet.ledger_dir().display()),
+                None => println!("💤 No validator started by localnet up is running"),
+            }
+            return Ok(());
+        }
+        LocalnetAction::Up { options } => {
+            println!("🏗️  Bringing up localnet on port {}...", options.rpc_port);
+            Localnet::new(options.options()).up().await?
+        }
+        LocalnetAction::Reset { options } => {
+            println!("♻️  Resetting localnet in {}...", options.dir);
+            Localnet::new(options.options()).reset().await?
+        }
+    };
+    
+    let state = &report.state;
+    match (report.started, state.pid) {
+        (true, Some(pid)) => println!("✅ Started solana-test-validator (pid {}) at {}", pid, state.rpc_url),
+        _ => println!("⏭️  Validator already running at {}", state.rpc_url),
+    }
+    println!("🔑 Wallet: {} ({})", state.wallet, finternet_sdk::Currency::Sol.format_amount(report.wallet_lamports));
+    let usdc = finternet_sdk::Currency::Spl {
+        mint: Pubkey::from_str(&state.usdc_mint)?,
+        decimals: 6,
+        symbol: Some(mint_registry::USDC.to_string()),
+    };
+    println!("🪙 USDC stand-in: {} ({} held)", state.usdc_mint, usdc.format_amount(report.usdc_balance));
+    println!(
+        "🔍 Explorer: {}",
+        mint_registry::explorer_url(Some(mint_registry::Network::Localnet), &state.rpc_url, &format!("address/{}", state.usdc_mint))
+    );
+    for signature in &report.signatures {
+        println!("📝 Transaction: {}", signature);
+    }
+    println!("📋 Profile '{}' written to {}", report.profile, report.profiles_path.display());
+    println!("💡 Use it with: finternet-cli --profile {} <command>", report.profile);
+    Ok(())
+}
+
+fn print_bootstrap_report(report: &BootstrapReport) {
+    println!("\n📋 Readiness report for {}", report.wallet);
+    for outcome in &report.steps {
+        let icon = match outcome.status {
+            StepStatus::Created => "✅",
+            StepStatus::AlreadyDone => "⏭️ ",
+            StepStatus::Verified => "🔍",
+            StepStatus::Failed => "❌",
+        };
+        println!("   {} {:<13} {}", icon, outcome.step.to_string(), outcome.detail);
+        if let Some(address) = &outcome.address {
+            println!("      Address: {}", address);
+        }
+        if let Some(signature) = &outcome.signature {
+            println!("      Signature: {}", signature);
+        }
+    }
+    println!("📁 Keypair: {}", report.keypair_path.display());
+    if report.ready {
+        println!("🎉 Environment ready");
+    }
+}
+
+fn print_demo_report(report: &DemoReport) {
+    println!("\n📋 Demo report for {}", report.payer);
+    for outcome in &report.stages {
+        let icon = match outcome.status {
+            DemoStageStatus::Passed => "✅",
+            DemoStageStatus::Failed => "❌",
+            DemoStageStatus::Skipped => "⏭️ ",
+        };
+        println!("   {} {:<13} {}", icon, outcome.stage.to_string(), outcome.detail);
+        if let Some(address) = &outcome.address {
+            println!("      Address: {}", address);
+        }
+        if let Some(signature) = &outcome.signature {
+            println!("      Signature: {}", signature);
+        }
+    }
+    if report.passed {
+        println!("🎉 Every stage passed");
+    }
+}
+
+fn print_diagnostic_report(report: &DiagnosticReport) {
+    let millis = |value: Option<f64>| value.map(|ms| format!("{:.0}ms", ms)).unwrap_or_else(|| "-".to_string());
+    println!("\n{:<13} {:<28} {:>8} {:>8} {:>8}", "PHASE", "METHOD", "MEDIAN", "MAX", "BUDGET");
+    for step in &report.steps {
+        let verdict = match step.verdict() {
+            DiagnosticVerdict::Ok => "✅",
+            DiagnosticVerdict::OverBudget => "🐢 over budget",
+            DiagnosticVerdict::Unstable => "⚠️  unstable",
+            DiagnosticVerdict::Failed => "❌ failed",
+        };
+        println!(
+            "{:<13} {:<28} {:>8} {:>8} {:>8} {}",
+            step.phase.to_string(),
+            step.method,
+            millis(step.median_ms()),
+            millis(step.max_ms()),
+            millis(Some(step.budget_ms)),
+            verdict
+        );
+        if let Some(error) = &step.error {
+            println!("   {}", error);
+        }
+    }
+    let outliers = report.outliers();
+    if outliers.is_empty() {
+        println!("\n🎉 Every step is within budget");
+    } else {
+        let phases: Vec<String> = outliers.iter().map(|step| step.phase.to_string()).collect();
+        println!("\n⚠️  Outside budget: {}", phases.join(", "));
+    }
+    println!("ℹ️  Broadcast and confirmation are not sampled; see a send's receipt timings");
+}
+
+/// `--creator` values, each PUBKEY:SHARE
+fn parse_creators(entries: &[String]) -> Result<Vec<(Pubkey, u8)>> {
+    entries
+        .iter()
+        .map(|entry| {
+            let (address, share) =
+                entry.split_once(':').ok_or_else(|| anyhow::anyhow!("Creator '{}' must be PUBKEY:SHARE", entry))?;
+            Ok((Pubkey::from_str(address)?, share.parse::<u8>()?))
+        })
+        .collect()
+}
+
+/// Print every violation, then stop before the SDK is called
+fn require_valid(violations: Vec<Violation>) -> Result<()> {
+    for violation in &violations {
+        println!("❌ {}", violation);
+    }
+    validation::check(violations)
+}
+
+/// Live progress for long-running commands, drawn on stderr and hidden when it is not a terminal
+fn progress_bar() -> impl Fn(ProgressEvent) + Send + Sync + 'static {
+    let bar = ProgressBar::new_spinner();
+    move |event| match event {
+        ProgressEvent::Started { total: Some(total), .. } => {
+            bar.reset();
+            bar.set_style(
+                ProgressStyle::with_template("{bar:40.cyan/blue} {pos}/{len} {msg}")
+                    .unwrap_or_else(|_| ProgressStyle::default_bar()),
+            );
+            bar.set_length(total as u64);
+        }
+        ProgressEvent::Started { total: None, .. } => bar.set_message("starting…"),
+        ProgressEvent::Scanning { signatures_found, .. } => {
+            bar.set_message(format!("{} signatures found", signatures_found));
+            bar.tick();
+        }
+        ProgressEvent::Processing { signature, .. } => {
+            let signature = signature.to_string();
+            bar.set_message(format!("{}…", &signature[..12.min(signature.len())]));
+        }
+        ProgressEvent::AwaitingConfirmation { waited_secs, .. } if waited_secs > 0 => {
+            bar.set_message(format!("confirming ({}s)", waited_secs));
+        }
+        ProgressEvent::AwaitingConfirmation { .. } => {}
+        ProgressEvent::Advanced { completed, .. } => bar.set_position(completed as u64),
+        ProgressEvent::Finished { .. } => bar.finish_and_clear(),
+    }
+}
+
+fn print_identity_registration(result: &BulkResult, attestations_dir: &str) {
+    for item in &result.items {
+        let icon = match item.status {
+            BulkIdentityStatus::Registered => "✅",
+            BulkIdentityStatus::Attested => "✍️",
+            BulkIdentityStatus::Failed => "❌",
+            BulkIdentityStatus::Pending => "⏸️",
+        };
+        let detail = item.signature.map(|signature| format!(" {}", signature)).unwrap_or_default();
+        println!("{} {} ({}){}", icon, item.entry.display_name, item.entry.pubkey, detail);
+        if let Some(error) = &item.error {
+            println!("   {}", error);
+        }
+    }
+    println!(
+        "📊 {} registered, {} attested, {} failed",
+        result.count(BulkIdentityStatus::Registered),
+        result.count(BulkIdentityStatus::Attested),
+        result.count(BulkIdentityStatus::Failed)
+    );
+    if result.count(BulkIdentityStatus::Attested) > 0 {
+        println!("✍️  Attestations are in {}; each owner runs confirm-attestation --file <pubkey>.json", attestations_dir);
+    }
+    if !result.is_complete() {
+        println!("⚠️  Registration incomplete; rerun with --resume to finish it");
+    }
+}
+
+fn print_batch_journal(journal: &BatchJournal) {
+    for item in &journal.items {
+        let icon = match item.status {
+            BatchItemStatus::Confirmed => "✅",
+            BatchItemStatus::Failed => "❌",
+            BatchItemStatus::Pending => "⏸️",
+            BatchItemStatus::Submitted | BatchItemStatus::InFlight => "❓",
+        };
+        let detail = item
+            .mint
+            .map(|mint| format!(" mint {}", mint))
+            .or_else(|| item.signature.map(|signature| format!(" {}", signature)))
+            .unwrap_or_default();
+        println!("{} {}{}", icon, item.id, detail);
+        if let Some(error) = &item.error {
+            println!("   {}", error);
+        }
+    }
+    println!(
+        "📊 {} confirmed, {} failed, {} unresolved",
+        journal.count(BatchItemStatus::Confirmed),
+        journal.count(BatchItemStatus::Failed),
+        journal.items.len() - journal.count(BatchItemStatus::Confirmed) - journal.count(BatchItemStatus::Failed)
+    );
+    if !journal.is_complete() {
+        println!("⚠️  Batch incomplete; rerun with --resume once in-flight transactions have settled");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn session_keys_are_private_from_creation_and_a_shared_directory_is_refused() {
+        use std::os::unix::fs::PermissionsExt;
+        let root = std::env::temp_dir().join(format!("finternet-session-keys-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(&root).unwrap();
+
+        let dir = root.join("sessions");
+        ensure_private_dir(&dir).unwrap();
+        assert_eq!(std::fs::metadata(&dir).unwrap().permissions().mode() & 0o777, 0o700);
+        let wallet = Keypair::new();
+        let path = dir.join(session_wallet_file_name(&wallet.pubkey()));
+        save_private_key(&wallet, &path).unwrap();
+        assert_eq!(std::fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+        assert_eq!(FinternetClient::load_wallet_from_file(&path).unwrap().pubkey(), wallet.pubkey());
+        assert!(save_private_key(&Keypair::new(), &path).is_err());
+        ensure_private_dir(&dir).unwrap();
+
+        // A directory others can open, or a symlink to one, is not used
+        std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o755)).unwrap();
+        assert!(ensure_private_dir(&dir).is_err());
+        let link = root.join("link");
+        std::os::unix::fs::symlink(&dir, &link).unwrap();
+        std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o700)).unwrap();
+        assert!(ensure_private_dir(&link).is_err());
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,742 @@
+//! Table and JSON rendering shared by the CLI's listing commands
+//!
+//! Each command turns its results into rows of one `Row` type. Sorting and `--limit` apply to
+//! the rows, and then they print as either a table or JSON, so the two formats always agree.
+
+use anyhow::{anyhow, Result};
+use finternet_sdk::amount_format::{AmountFormatter, CurrencyDisplay};
+use finternet_sdk::{Currency, FinternetClient};
+use serde::{Serialize, Serializer};
+use solana_sdk::{pubkey::Pubkey, signature::Signature};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+/// Free-text cells longer than this are cut short with `…`
+const MAX_CELL_CHARS: usize = 40;
+const COLUMN_GAP: &str = "  ";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Table,
+    Json,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s.to_ascii_lowercase().as_str() {
+            "table" => Ok(Self::Table),
+            "json" => Ok(Self::Json),
+            other => Err(format!("Unknown output format '{}', expected table or json", other)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortKey {
+    /// Largest first
+    Amount,
+    /// Newest first
+    Time,
+    /// Alphabetical
+    Name,
+}
+
+impl FromStr for SortKey {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s.to_ascii_lowercase().as_str() {
+            "amount" => Ok(Self::Amount),
+            "time" => Ok(Self::Time),
+            "name" => Ok(Self::Name),
+            other => Err(format!("Unknown sort key '{}', expected amount, time, or name", other)),
+        }
+    }
+}
+
+impl fmt::Display for SortKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Amount => write!(f, "amount"),
+            Self::Time => write!(f, "time"),
+            Self::Name => write!(f, "name"),
+        }
+    }
+}
+
+/// Output flags shared by every listing command
+#[derive(clap::Args, Debug, Clone)]
+pub struct ViewArgs {
+    /// `table` or `json`
+    #[arg(long, default_value = "table")]
+    pub output: OutputFormat,
+
+    /// `amount`, `time`, or `name`
+    #[arg(long)]
+    pub sort_by: Option<SortKey>,
+
+    /// Comma-separated table columns, in the order to show them
+    #[arg(long, value_delimiter = ',')]
+    pub columns: Option<Vec<String>>,
+}
+
+impl ViewArgs {
+    pub fn is_json(&self) -> bool {
+        self.output == OutputFormat::Json
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Align {
+    Left,
+    /// Right-aligned on the decimal point, for amounts with differing decimals
+    Decimal,
+}
+
+#[derive(Debug)]
+pub struct Column {
+    pub name: &'static str,
+    pub align: Align,
+    /// Shown when `--columns` is not given
+    pub default: bool,
+}
+
+/// Whether addresses and signatures are printed in full or shortened
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddressWidth {
+    Full,
+    Short,
+}
+
+impl AddressWidth {
+    pub fn pubkey(self, pubkey: &Pubkey) -> String {
+        match self {
+            Self::Full => pubkey.to_string(),
+            Self::Short => FinternetClient::create_readable_address(pubkey, ""),
+        }
+    }
+
+    /// Any base58 string, e.g. a transaction signature, shortened the same way as addresses
+    pub fn text(self, value: &str) -> String {
+        let chars: Vec<char> = value.chars().collect();
+        match self {
+            Self::Short if chars.len() > 19 => format!(
+                "{}...{}",
+                chars[..8].iter().collect::<String>(),
+                chars[chars.len() - 8..].iter().collect::<String>()
+            ),
+            _ => value.to_string(),
+        }
+    }
+}
+
+/// One line of a listing, printable as a table row or a JSON object
+pub trait Row: Serialize {
+    /// Every column, in default order
+    const COLUMNS: &'static [Column];
+    /// Keys these rows have values for
+    const SORT_KEYS: &'static [SortKey];
+
+    fn cell(&self, column: &str, addresses: AddressWidth) -> String;
+
+    /// Amount in whole units, comparable across currencies with different decimals
+    fn amount(&self) -> f64 {
+        0.0
+    }
+
+    fn time(&self) -> u64 {
+        0
+    }
+
+    fn name(&self) -> String {
+        String::new()
+    }
+}
+
+/// Sort, limit, and print `rows` as `view` asks, or `empty` when there are none in a table
+pub fn print_rows<R: Row>(rows: Vec<R>, view: &ViewArgs, limit: Option<usize>, empty: &str) -> Result<()> {
+    let (rows, columns) = arrange(rows, view, limit)?;
+    if view.is_json() {
+        println!("{}", serde_json::to_string_pretty(&rows)?);
+    } else if rows.is_empty() {
+        println!("{}", empty);
+    } else {
+        // Full addresses unless they would not fit; piped output always gets them in full
+        let full = render_table(&rows, &columns, AddressWidth::Full);
+        let fits = match console::Term::stdout().size_checked() {
+            Some((_, width)) => full.lines().all(|line| line.chars().count() <= width as usize),
+            None => true,
+        };
+        if fits {
+            print!("{}", full);
+        } else {
+            print!("{}", render_table(&rows, &columns, AddressWidth::Short));
+        }
+    }
+    Ok(())
+}
+
+/// The rows to show, sorted and then limited, and the columns to show them in
+fn arrange<R: Row>(mut rows: Vec<R>, view: &ViewArgs, limit: Option<usize>) -> Result<(Vec<R>, Vec<&'static Column>)> {
+    let columns = select_columns::<R>(view.columns.as_deref())?;
+    if let Some(key) = view.sort_by {
+        sort_rows(&mut rows, key)?;
+    }
+    if let Some(limit) = limit {
+        rows.truncate(limit);
+    }
+    Ok((rows, columns))
+}
+
+fn select_columns<R: Row>(requested: Option<&[String]>) -> Result<Vec<&'static Column>> {
+    let Some(requested) = requested else {
+        return Ok(R::COLUMNS.iter().filter(|column| column.default).collect());
+    };
+    requested
+        .iter()
+        .map(|name| {
+            R::COLUMNS
+                .iter()
+                .find(|column| column.name.eq_ignore_ascii_case(name.trim()))
+                .ok_or_else(|| {
+                    let available: Vec<_> = R::COLUMNS.iter().map(|column| column.name).collect();
+                    anyhow!("Unknown column '{}', expected one of: {}", name.trim(), available.join(", "))
+                })
+        })
+        .collect()
+}
+
+fn sort_rows<R: Row>(rows: &mut [R], key: SortKey) -> Result<()> {
+    if !R::SORT_KEYS.contains(&key) {
+        let supported: Vec<_> = R::SORT_KEYS.iter().map(SortKey::to_string).collect();
+        return Err(anyhow!("Cannot sort by {} here, expected one of: {}", key, supported.join(", ")));
+    }
+    match key {
+        SortKey::Amount => rows.sort_by(|a, b| b.amount().total_cmp(&a.amount())),
+        SortKey::Time => rows.sort_by_key(|row| std::cmp::Reverse(row.time())),
+        SortKey::Name => rows.sort_by_key(|row| row.name().to_lowercase()),
+    }
+    Ok(())
+}
+
+/// Fixed-width table with a header, each line ending in a newline
+pub fn render_table<R: Row>(rows: &[R], columns: &[&Column], addresses: AddressWidth) -> String {
+    let mut body: Vec<Vec<String>> = rows
+        .iter()
+        .map(|row| columns.iter().map(|column| row.cell(column.name, addresses)).collect())
+        .collect();
+    for (index, column) in columns.iter().enumerate() {
+        if column.align == Align::Decimal {
+            let column_cells: Vec<String> = body.iter().map(|row| row[index].clone()).collect();
+            let refs: Vec<&str> = column_cells.iter().map(String::as_str).collect();
+            for (row, cell) in body.iter_mut().zip(align_decimals(&refs)) {
+                row[index] = cell;
+            }
+        }
+    }
+
+    let mut lines = vec![columns.iter().map(|column| column.name.to_uppercase()).collect::<Vec<_>>()];
+    lines.append(&mut body);
+
+    let widths: Vec<usize> = (0..columns.len())
+        .map(|index| lines.iter().map(|line| line[index].chars().count()).max().unwrap_or(0))
+        .collect();
+    let mut rendered = String::new();
+    for (line_index, line) in lines.iter().enumerate() {
+        let mut text = String::new();
+        for (index, (cell, column)) in line.iter().zip(columns).enumerate() {
+            if index > 0 {
+                text.push_str(COLUMN_GAP);
+            }
+            let pad = widths[index] - cell.chars().count();
+            match column.align {
+                Align::Left => {
+                    text.push_str(cell);
+                    if index + 1 < columns.len() {
+                        text.push_str(&" ".repeat(pad));
+                    }
+                }
+                Align::Decimal => {
+                    text.push_str(&" ".repeat(pad));
+                    text.push_str(cell);
+                }
+            }
+        }
+        rendered.push_str(&text);
+        rendered.push('\n');
+        if line_index == 0 {
+            let total = widths.iter().sum::<usize>() + COLUMN_GAP.len() * widths.len().saturating_sub(1);
+            rendered.push_str(&"-".repeat(total));
+            rendered.push('\n');
+        }
+    }
+    rendered
+}
+
+/// Pad each amount so the decimal points line up when the column is right-aligned
+fn align_decimals(cells: &[&str]) -> Vec<String> {
+    let separator = amounts().locale.decimal_separator;
+    let fraction_len = |cell: &str| cell.rfind(separator).map(|dot| cell[dot..].chars().count()).unwrap_or(0);
+    let widest = cells.iter().map(|cell| fraction_len(cell)).max().unwrap_or(0);
+    cells
+        .iter()
+        .map(|cell| format!("{}{}", cell, " ".repeat(widest - fraction_len(cell))))
+        .collect()
+}
+
+const fn column(name: &'static str, align: Align, default: bool) -> Column {
+    Column { name, align, default }
+}
+
+/// A token account holding a balance, listed by `assets`
+#[derive(Debug, Clone, Serialize)]
+pub struct AssetRow {
+    #[serde(serialize_with = "display")]
+    pub mint: Pubkey,
+    pub name: Option<String>,
+    pub asset_type: Option<String>,
+    /// Base units
+    pub amount: u64,
+    pub decimals: u8,
+    #[serde(serialize_with = "display")]
+    pub account: Pubkey,
+    pub frozen: bool,
+    #[serde(serialize_with = "display_opt")]
+    pub delegate: Option<Pubkey>,
+    pub delegated_amount: u64,
+    #[serde(serialize_with = "display_opt")]
+    pub close_authority: Option<Pubkey>,
+}
+
+impl Row for AssetRow {
+    const COLUMNS: &'static [Column] = &[
+        column("name", Align::Left, true),
+        column("type", Align::Left, true),
+        column("amount", Align::Decimal, true),
+        column("mint", Align::Left, true),
+        column("frozen", Align::Left, true),
+        column("account", Align::Left, false),
+        column("delegate", Align::Left, false),
+        column("close_authority", Align::Left, false),
+    ];
+    const SORT_KEYS: &'static [SortKey] = &[SortKey::Amount, SortKey::Name];
+
+    fn cell(&self, column: &str, addresses: AddressWidth) -> String {
+        let units = |amount: u64| amounts().format_units(&Currency::spl(self.mint, self.decimals), amount as i128);
+        match column {
+            "name" => clip(self.name.as_deref().unwrap_or("-")),
+            "type" => clip(self.asset_type.as_deref().unwrap_or("-")),
+            "amount" => units(self.amount),
+            "mint" => addresses.pubkey(&self.mint),
+            "frozen" => if self.frozen { "yes" } else { "no" }.to_string(),
+            "account" => addresses.pubkey(&self.account),
+            "delegate" => match &self.delegate {
+                Some(delegate) => format!("{} ({})", addresses.pubkey(delegate), units(self.delegated_amount)),
+                None => "-".to_string(),
+            },
+            "close_authority" => self.close_authority.map(|a| addresses.pubkey(&a)).unwrap_or_else(|| "-".to_string()),
+            _ => String::new(),
+        }
+    }
+
+    fn amount(&self) -> f64 {
+        whole_units(self.amount, self.decimals)
+    }
+
+    fn name(&self) -> String {
+        self.name.clone().unwrap_or_default()
+    }
+}
+
+/// One transfer, listed by `history`
+#[derive(Debug, Clone, Serialize)]
+pub struct HistoryRow {
+    #[serde(serialize_with = "display")]
+    pub signature: Signature,
+    pub time: u64,
+    #[serde(serialize_with = "display")]
+    pub from: Pubkey,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub from_name: Option<String>,
+    #[serde(serialize_with = "display")]
+    pub to: Pubkey,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub to_name: Option<String>,
+    /// Base units of `currency`
+    pub amount: u64,
+    #[serde(serialize_with = "currency_label")]
+    pub currency: Currency,
+    #[serde(serialize_with = "display_opt")]
+    pub mint: Option<Pubkey>,
+    pub decimals: u8,
+    pub memo: Option<String>,
+    /// Values parsed out of the memo by `--memo-template`
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub memo_fields: BTreeMap<String, String>,
+}
+
+impl Row for HistoryRow {
+    const COLUMNS: &'static [Column] = &[
+        column("time", Align::Left, true),
+        column("signature", Align::Left, true),
+        column("from", Align::Left, true),
+        column("to", Align::Left, true),
+        column("amount", Align::Decimal, true),
+        column("currency", Align::Left, true),
+        column("memo", Align::Left, true),
+        column("fields", Align::Left, false),
+    ];
+    const SORT_KEYS: &'static [SortKey] = &[SortKey::Amount, SortKey::Time];
+
+    fn cell(&self, column: &str, addresses: AddressWidth) -> String {
+        let party = |pubkey: &Pubkey, name: &Option<String>| match name {
+            Some(name) => format!("{} ({})", addresses.pubkey(pubkey), clip(name)),
+            None => addresses.pubkey(pubkey),
+        };
+        match column {
+            "time" => format_time(self.time),
+            "signature" => addresses.text(&self.signature.to_string()),
+            "from" => party(&self.from, &self.from_name),
+            "to" => party(&self.to, &self.to_name),
+            "amount" => amounts().format_units(&self.currency, self.amount as i128),
+            "currency" => match (&self.currency, addresses) {
+                (Currency::Spl { mint, symbol: None, .. }, AddressWidth::Full) => mint.to_string(),
+                (currency, _) => currency.label(),
+            },
+            "memo" => clip(self.memo.as_deref().unwrap_or("")),
+            "fields" => clip(
+                &self
+                    .memo_fields
+                    .iter()
+                    .map(|(key, value)| format!("{}={}", key, value))
+                    .collect::<Vec<_>>()
+                    .join(" "),
+            ),
+            _ => String::new(),
+        }
+    }
+
+    fn amount(&self) -> f64 {
+        whole_units(self.amount, self.decimals)
+    }
+
+    fn time(&self) -> u64 {
+        self.time
+    }
+}
+
+/// A mint the wallet holds, listed by `discover-tokens`
+#[derive(Debug, Clone, Serialize)]
+pub struct TokenRow {
+    #[serde(serialize_with = "display")]
+    pub mint: Pubkey,
+    pub name: Option<String>,
+    /// Base units
+    pub amount: u64,
+    pub decimals: u8,
+}
+
+impl Row for TokenRow {
+    const COLUMNS: &'static [Column] = &[
+        column("name", Align::Left, true),
+        column("amount", Align::Decimal, true),
+        column("mint", Align::Left, true),
+    ];
+    const SORT_KEYS: &'static [SortKey] = &[SortKey::Amount, SortKey::Name];
+
+    fn cell(&self, column: &str, addresses: AddressWidth) -> String {
+        match column {
+            "name" => clip(self.name.as_deref().unwrap_or("Unknown Token")),
+            "amount" => amounts().format_units(&Currency::spl(self.mint, self.decimals), self.amount as i128),
+            "mint" => addresses.pubkey(&self.mint),
+            _ => String::new(),
+        }
+    }
+
+    fn amount(&self) -> f64 {
+        whole_units(self.amount, self.decimals)
+    }
+
+    fn name(&self) -> String {
+        self.name.clone().unwrap_or_default()
+    }
+}
+
+/// One currency held by one wallet, or by all of them together, listed by `portfolio`
+#[derive(Debug, Clone, Serialize)]
+pub struct PortfolioRow {
+    /// `None` on the consolidated rows
+    #[serde(serialize_with = "display_opt")]
+    pub wallet: Option<Pubkey>,
+    #[serde(serialize_with = "currency_label")]
+    pub currency: Currency,
+    pub name: Option<String>,
+    /// Base units of `currency`
+    pub amount: u64,
+    pub usd_value: Option<f64>,
+}
+
+impl Row for PortfolioRow {
+    const COLUMNS: &'static [Column] = &[
+        column("wallet", Align::Left, true),
+        column("currency", Align::Left, true),
+        column("name", Align::Left, true),
+        column("amount", Align::Decimal, true),
+        column("usd", Align::Decimal, true),
+    ];
+    const SORT_KEYS: &'static [SortKey] = &[SortKey::Amount, SortKey::Name];
+
+    fn cell(&self, column: &str, addresses: AddressWidth) -> String {
+        match column {
+            "wallet" => self.wallet.map(|wallet| addresses.pubkey(&wallet)).unwrap_or_else(|| "all".to_string()),
+            "currency" => match (&self.currency, addresses) {
+                (Currency::Spl { mint, symbol: None, .. }, AddressWidth::Full) => mint.to_string(),
+                (currency, _) => currency.label(),
+            },
+            "name" => clip(self.name.as_deref().unwrap_or("-")),
+            "amount" => amounts().format_units(&self.currency, self.amount as i128),
+            "usd" => self
+                .usd_value
+                .map(|usd| AmountFormatter { display: CurrencyDisplay::None, ..amounts().clone() }.format_usd(usd))
+                .unwrap_or_else(|| "-".to_string()),
+            _ => String::new(),
+        }
+    }
+
+    fn amount(&self) -> f64 {
+        whole_units(self.amount, self.currency.decimals())
+    }
+
+    fn name(&self) -> String {
+        self.name.clone().unwrap_or_else(|| self.currency.label())
+    }
+}
+
+static AMOUNT_FORMAT: OnceLock<AmountFormatter> = OnceLock::new();
+
+/// Set once from the global `--locale`, `--currency-display`, and `--precision` flags
+pub fn set_amount_format(formatter: AmountFormatter) {
+    let _ = AMOUNT_FORMAT.set(formatter);
+}
+
+/// How the CLI writes amounts, the plain format unless a flag chose otherwise
+pub fn amounts() -> &'static AmountFormatter {
+    AMOUNT_FORMAT.get_or_init(AmountFormatter::default)
+}
+
+fn whole_units(amount: u64, decimals: u8) -> f64 {
+    amount as f64 / 10f64.powi(decimals as i32)
+}
+
+/// Free text such as names and memos, cut short with `…` so one long value cannot widen the table
+pub fn clip(text: &str) -> String {
+    if text.chars().count() <= MAX_CELL_CHARS {
+        return text.to_string();
+    }
+    let mut cut: String = text.chars().take(MAX_CELL_CHARS - 1).collect();
+    cut.push('…');
+    cut
+}
+
+/// `YYYY-MM-DD HH:MM` in UTC, or `-` when the time is unknown
+pub fn format_time(timestamp: u64) -> String {
+    match chrono::DateTime::from_timestamp(timestamp as i64, 0) {
+        Some(time) if timestamp > 0 => time.format("%Y-%m-%d %H:%M").to_string(),
+        _ => "-".to_string(),
+    }
+}
+
+/// Serialize through `Display`, so addresses and signatures appear as base58 in JSON
+pub fn display<T: fmt::Display, S: Serializer>(value: &T, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(value)
+}
+
+pub fn display_opt<T: fmt::Display, S: Serializer>(value: &Option<T>, serializer: S) -> Result<S::Ok, S::Error> {
+    match value {
+        Some(value) => serializer.collect_str(value),
+        None => serializer.serialize_none(),
+    }
+}
+
+fn currency_label<S: Serializer>(currency: &Currency, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&currency.label())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    /// 2024-06-03 14:05:00 UTC
+    const JUNE_THIRD: u64 = 1_717_423_500;
+
+    fn key(byte: u8) -> Pubkey {
+        Pubkey::new_from_array([byte; 32])
+    }
+
+    fn view(sort_by: Option<SortKey>, columns: Option<&str>) -> ViewArgs {
+        ViewArgs {
+            output: OutputFormat::Table,
+            sort_by,
+            columns: columns.map(|columns| columns.split(',').map(str::to_string).collect()),
+        }
+    }
+
+    fn assets() -> Vec<AssetRow> {
+        let asset = |byte: u8, name: Option<&str>, asset_type: Option<&str>, amount: u64, decimals: u8| AssetRow {
+            mint: key(byte),
+            name: name.map(str::to_string),
+            asset_type: asset_type.map(str::to_string),
+            amount,
+            decimals,
+            account: key(byte + 100),
+            frozen: false,
+            delegate: None,
+            delegated_amount: 0,
+            close_authority: None,
+        };
+        let mut delegated = asset(4, Some("Harbour Warehouse Receipt, Berth 12 North Quay"), Some("Commodity"), 3, 0);
+        delegated.frozen = true;
+        delegated.delegate = Some(key(50));
+        delegated.delegated_amount = 1;
+        delegated.close_authority = Some(key(51));
+        vec![
+            asset(1, Some("Treasury Bill 2025"), Some("Bond"), 1_250_000_000, 6),
+            asset(2, None, None, 42, 0),
+            asset(3, Some("acme shares"), Some("Equity"), 75_500, 2),
+            delegated,
+        ]
+    }
+
+    fn history() -> Vec<HistoryRow> {
+        let transfer = |byte: u8, hours: u64, amount: u64, currency: Currency, memo: Option<&str>| HistoryRow {
+            signature: Signature::from([byte; 64]),
+            time: JUNE_THIRD + hours * 3600,
+            from: key(1),
+            from_name: None,
+            to: key(byte),
+            to_name: None,
+            amount,
+            decimals: currency.decimals(),
+            mint: currency.mint(),
+            currency,
+            memo: memo.map(str::to_string),
+            memo_fields: BTreeMap::new(),
+        };
+        let mut invoice = transfer(20, 5, 1_200_500_000, Currency::usdc_devnet(), Some("INV-2024-0193 June consulting"));
+        invoice.to_name = Some("Northwind Traders".to_string());
+        invoice.memo_fields = BTreeMap::from([("invoice".to_string(), "INV-2024-0193".to_string())]);
+        vec![
+            transfer(21, 0, 5_000_000, Currency::Sol, None),
+            invoice,
+            transfer(22, 30, 7, Currency::Spl { mint: key(9), decimals: 0, symbol: None }, Some("")),
+            transfer(23, 2, 990_000, Currency::usdc_devnet(), Some("refund")),
+        ]
+    }
+
+    fn tokens() -> Vec<TokenRow> {
+        vec![
+            TokenRow { mint: key(1), name: Some("Treasury Bill 2025".to_string()), amount: 1_250_000_000, decimals: 6 },
+            TokenRow { mint: key(2), name: None, amount: 42, decimals: 0 },
+            TokenRow { mint: key(3), name: Some("acme shares".to_string()), amount: 75_500, decimals: 2 },
+        ]
+    }
+
+    fn portfolio() -> Vec<PortfolioRow> {
+        vec![
+            PortfolioRow { wallet: Some(key(1)), currency: Currency::Sol, name: None, amount: 2_500_000_000, usd_value: Some(375.5) },
+            PortfolioRow { wallet: Some(key(2)), currency: Currency::usdc_devnet(), name: Some("USD Coin".to_string()), amount: 10_000_000, usd_value: Some(10.0) },
+            PortfolioRow { wallet: None, currency: Currency::Sol, name: None, amount: 2_500_000_000, usd_value: Some(375.5) },
+            PortfolioRow { wallet: None, currency: Currency::Spl { mint: key(9), decimals: 0, symbol: None }, name: None, amount: 7, usd_value: None },
+        ]
+    }
+
+    /// Compare with the snapshot, or rewrite it when `FINTERNET_BLESS` is set
+    fn assert_snapshot(name: &str, actual: &str) {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/tables").join(name);
+        if std::env::var_os("FINTERNET_BLESS").is_some() {
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(&path, actual).unwrap();
+        }
+        let expected = std::fs::read_to_string(&path).unwrap_or_else(|e| panic!("{}: {}", path.display(), e));
+        assert_eq!(actual, expected, "{} changed; rerun with FINTERNET_BLESS=1 if that was intended", name);
+    }
+
+    fn table<R: Row>(rows: Vec<R>, view: &ViewArgs, limit: Option<usize>, addresses: AddressWidth) -> String {
+        let (rows, columns) = arrange(rows, view, limit).unwrap();
+        render_table(&rows, &columns, addresses)
+    }
+
+    #[test]
+    fn asset_tables_match_their_snapshots() {
+        assert_snapshot("assets_full.txt", &table(assets(), &view(None, None), None, AddressWidth::Full));
+        assert_snapshot("assets_short.txt", &table(assets(), &view(None, None), None, AddressWidth::Short));
+        assert_snapshot(
+            "assets_by_name_with_delegates.txt",
+            &table(assets(), &view(Some(SortKey::Name), Some("name, amount,delegate,close_authority")), None, AddressWidth::Short),
+        );
+    }
+
+    #[test]
+    fn history_tables_match_their_snapshots() {
+        assert_snapshot("history_full.txt", &table(history(), &view(None, None), None, AddressWidth::Full));
+        assert_snapshot(
+            "history_newest_two.txt",
+            &table(history(), &view(Some(SortKey::Time), Some("time,to,amount,currency,fields")), Some(2), AddressWidth::Short),
+        );
+    }
+
+    #[test]
+    fn token_and_portfolio_tables_match_their_snapshots() {
+        assert_snapshot("tokens_by_amount.txt", &table(tokens(), &view(Some(SortKey::Amount), None), None, AddressWidth::Short));
+        assert_snapshot("portfolio_full.txt", &table(portfolio(), &view(None, None), None, AddressWidth::Full));
+        assert_snapshot("portfolio_short.txt", &table(portfolio(), &view(None, None), None, AddressWidth::Short));
+    }
+
+    #[test]
+    fn the_limit_applies_after_sorting() {
+        let (rows, _) = arrange(assets(), &view(Some(SortKey::Amount), None), Some(2)).unwrap();
+        let names: Vec<_> = rows.iter().map(|row| row.name.as_deref().unwrap_or("-")).collect();
+        assert_eq!(names, ["Treasury Bill 2025", "acme shares"]);
+
+        let (rows, _) = arrange(history(), &view(Some(SortKey::Time), None), Some(1)).unwrap();
+        assert_eq!(rows[0].signature, Signature::from([22; 64]));
+    }
+
+    #[test]
+    fn json_carries_the_same_rows_as_the_table() {
+        let (rows, _) = arrange(history(), &view(Some(SortKey::Amount), None), Some(2)).unwrap();
+        let json = serde_json::to_value(&rows).unwrap();
+        assert_eq!(json[0]["signature"], Signature::from([20; 64]).to_string());
+        assert_eq!(json[0]["to"], key(20).to_string());
+        assert_eq!(json[0]["to_name"], "Northwind Traders");
+        assert_eq!(json[0]["amount"], 1_200_500_000);
+        assert_eq!(json[0]["memo_fields"]["invoice"], "INV-2024-0193");
+        // 7 whole units of a mint without decimals outrank 0.99 USDC
+        assert_eq!(json[1]["mint"], key(9).to_string());
+        assert_eq!(json[1]["amount"], 7);
+        assert!(json[1].get("memo_fields").is_none());
+    }
+
+    #[test]
+    fn unknown_columns_and_unsupported_sorts_are_refused() {
+        let error = arrange(assets(), &view(None, Some("name,price")), None).unwrap_err().to_string();
+        assert!(error.contains("Unknown column 'price'"), "{}", error);
+        let error = arrange(assets(), &view(Some(SortKey::Time), None), None).unwrap_err().to_string();
+        assert!(error.contains("Cannot sort by time"), "{}", error);
+        assert!("size".parse::<SortKey>().is_err());
+    }
+
+    #[test]
+    fn long_text_is_clipped_and_amounts_line_up_on_the_point() {
+        let clipped = clip(&"x".repeat(60));
+        assert_eq!(clipped.chars().count(), MAX_CELL_CHARS);
+        assert!(clipped.ends_with('…'));
+        assert_eq!(align_decimals(&["1.5", "12", "0.125"]), ["1.5  ", "12    ", "0.125"]);
+        assert_eq!(format_time(0), "-");
+    }
+}
@@ -0,0 +1,260 @@
+use anyhow::{anyhow, Result};
+use finternet_sdk::instructions::mint_rent_lamports;
+use finternet_sdk::localnet::{Localnet, LocalnetOptions};
+use finternet_sdk::mint_registry::Network;
+use finternet_sdk::{AssetType, Currency, FinternetClient, FinternetConfig};
+use solana_sdk::program_pack::Pack;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{keypair_from_seed, Keypair};
+use solana_sdk::signer::Signer;
+use solana_sdk::system_instruction;
+use solana_sdk::transaction::Transaction;
+use std::collections::HashMap;
+use std::time::Duration;
+use tokio::time::sleep;
+
+const MAINNET_GENESIS_HASH: &str = "5eykt4UsFv8P8NJdTREpY1vzqKqZKvdpKuc147dw2N9d";
+
+/// Set to `1` to run the examples against a localnet instead of devnet
+const LOCALNET_ENV: &str = "FINTERNET_LOCALNET";
+
+const MIN_SOL_LAMPORTS: u64 = 500_000_000;
+const AIRDROP_LAMPORTS: u64 = 1_000_000_000;
+const AIRDROP_CONFIRM_SECS: u64 = 60;
+
+/// Signed by the wallet to derive the demo token's mint, so every run finds the same one
+const STABLE_MINT_SEED_MESSAGE: &[u8] = b"finternet-sdk demo stable token v1";
+const STABLE_DECIMALS: u8 = 6;
+const STABLE_SYMBOL: &str = "DEMO";
+/// Balance kept on hand for the payment demos: 1,000 DEMO
+const STABLE_TARGET_BALANCE: u64 = 1_000_000_000;
+
+const DEMO_ASSET_NAME: &str = "Finternet Demo Fixture Asset";
+const DEMO_ASSET_TYPE: &str = "demo_fixture";
+const DEMO_IDENTITY_NAME: &str = "Finternet Demo Account";
+
+/// The client and wallet the examples run with
+///
+/// With `FINTERNET_LOCALNET=1` this brings up the localnet exactly as `finternet-cli localnet up`
+/// does, reusing a running one, and signs with its workspace wallet. Otherwise it is devnet and
+/// the default wallet.
+pub async fn connect() -> Result<(FinternetClient, Keypair)> {
+    if matches!(std::env::var(LOCALNET_ENV).as_deref(), Ok("1") | Ok("true")) {
+        let localnet = Localnet::new(LocalnetOptions::default());
+        let report = localnet.up().await?;
+        let client = FinternetClient::new(FinternetConfig {
+            rpc_url: report.state.rpc_url,
+            ..FinternetConfig::default()
+        });
+        let wallet = FinternetClient::load_wallet_from_file(&localnet.wallet_path())?;
+        return Ok((client, wallet));
+    }
+    let client = FinternetClient::new_devnet();
+    let wallet = FinternetClient::load_default_wallet()
+        .map_err(|e| anyhow!("No default wallet ({}); run: solana-keygen new", e))?;
+    Ok((client, wallet))
+}
+
+/// What `ensure_demo_environment` guarantees exists for the wallet
+///
+/// Each example compiles this module separately and uses only some of the fields.
+#[allow(dead_code)]
+#[derive(Debug, Clone)]
+pub struct DemoEnvironment {
+    /// Stable token minted by the wallet itself, standing in for devnet USDC
+    pub stable: Currency,
+    /// An asset tokenized by the wallet and still held by it
+    pub asset_mint: Pubkey,
+    pub identity_name: String,
+}
+
+impl DemoEnvironment {
+    pub fn stable_mint(&self) -> Pubkey {
+        self.stable.mint().expect("demo stable token is an SPL mint")
+    }
+}
+
+/// Bring `wallet` up to the state the examples expect, creating only what is missing
+///
+/// Guarantees at least 0.5 SOL, a funded balance of the demo stable token, one tokenized demo
+/// asset, and a registered identity. Refuses to run against mainnet.
+pub async fn ensure_demo_environment(client: &FinternetClient, wallet: &Keypair) -> Result<DemoEnvironment> {
+    refuse_mainnet(client)?;
+
+    let sol_lamports = ensure_sol(client, wallet).await?;
+    println!("   ✅ SOL: {}", Currency::Sol.format_amount(sol_lamports));
+
+    let (stable, stable_balance) = ensure_stable_token(client, wallet).await?;
+    println!("   ✅ Stable token: {} ({})", stable.format_amount(stable_balance), stable);
+
+    let asset_mint = ensure_demo_asset(client, wallet).await?;
+    println!("   ✅ Demo asset: {}", asset_mint);
+
+    let identity_name = ensure_identity(client, wallet).await?;
+    println!("   ✅ Identity: {}", identity_name);
+
+    Ok(DemoEnvironment {
+        stable,
+        asset_mint,
+        identity_name,
+    })
+}
+
+fn refuse_mainnet(client: &FinternetClient) -> Result<()> {
+    let genesis = client.client.get_genesis_hash()?;
+    if genesis.to_string() == MAINNET_GENESIS_HASH {
+        return Err(anyhow!(
+            "Refusing to create demo fixtures on mainnet ({}); point the client at devnet",
+            client.config.rpc_url
+        ));
+    }
+    Ok(())
+}
+
+async fn ensure_sol(client: &FinternetClient, wallet: &Keypair) -> Result<u64> {
+    let balance = client.client.get_balance(&wallet.pubkey())?;
+    if balance >= MIN_SOL_LAMPORTS {
+        return Ok(balance);
+    }
+
+    println!("   🪂 Requesting a SOL airdrop...");
+    let signature = client.client.request_airdrop(&wallet.pubkey(), AIRDROP_LAMPORTS)?;
+    for _ in 0..AIRDROP_CONFIRM_SECS {
+        if client.client.confirm_transaction(&signature)? {
+            break;
+        }
+        sleep(Duration::from_secs(1)).await;
+    }
+
+    let balance = client.client.get_balance(&wallet.pubkey())?;
+    if balance < MIN_SOL_LAMPORTS {
+        return Err(anyhow!(
+            "Wallet has {} after airdrop {}; at least {} is needed",
+            Currency::Sol.format_amount(balance),
+            signature,
+            Currency::Sol.format_amount(MIN_SOL_LAMPORTS)
+        ));
+    }
+    Ok(balance)
+}
+
+/// Create the wallet's demo mint on first use and top its balance back up to the target
+///
+/// On a localnet the USDC stand-in `Localnet::up` created, with the workspace wallet as its
+/// mint authority, is the stable token instead.
+async fn ensure_stable_token(client: &FinternetClient, wallet: &Keypair) -> Result<(Currency, u64)> {
+    if client.network()? == Some(Network::Localnet) {
+        let stable = Currency::spl(client.usdc_mint()?, STABLE_DECIMALS);
+        return top_up_stable_token(client, wallet, None, stable).await;
+    }
+    let seed = wallet.sign_message(STABLE_MINT_SEED_MESSAGE);
+    let mint = keypair_from_seed(&seed.as_ref()[..32]).map_err(|e| anyhow!("Could not derive demo mint: {}", e))?;
+    let stable = Currency::Spl {
+        mint: mint.pubkey(),
+        decimals: STABLE_DECIMALS,
+        symbol: Some(STABLE_SYMBOL.to_string()),
+    };
+    top_up_stable_token(client, wallet, Some(&mint), stable).await
+}
+
+/// Mint `stable` to the wallet up to the target, creating it first from `mint` if it is missing
+async fn top_up_stable_token(
+    client: &FinternetClient,
+    wallet: &Keypair,
+    mint: Option<&Keypair>,
+    stable: Currency,
+) -> Result<(Currency, u64)> {
+    let mint_address = stable.mint().expect("demo stable token is an SPL mint");
+    let ata = spl_associated_token_account::get_associated_token_address(&wallet.pubkey(), &mint_address);
+
+    let mut instructions = Vec::new();
+    let mut signers = vec![wallet];
+    if client.client.get_account(&mint_address).is_err() {
+        let mint = mint.ok_or_else(|| anyhow!("Stable token {} does not exist; run finternet-cli localnet up", mint_address))?;
+        println!("   🏦 Creating demo stable token {}...", mint_address);
+        instructions.push(system_instruction::create_account(
+            &wallet.pubkey(),
+            &mint_address,
+            mint_rent_lamports(),
+            spl_token::state::Mint::LEN as u64,
+            &spl_token::id(),
+        ));
+        instructions.push(spl_token::instruction::initialize_mint2(
+            &spl_token::id(),
+            &mint_address,
+            &wallet.pubkey(),
+            None,
+            STABLE_DECIMALS,
+        )?);
+        signers.push(mint);
+    }
+
+    let balance = client.get_token_balance(&wallet.pubkey(), &mint_address).await.unwrap_or(0);
+    if balance < STABLE_TARGET_BALANCE {
+        instructions.push(spl_associated_token_account::instruction::create_associated_token_account_idempotent(
+            &wallet.pubkey(),
+            &wallet.pubkey(),
+            &mint_address,
+            &spl_token::id(),
+        ));
+        instructions.push(spl_token::instruction::mint_to(
+            &spl_token::id(),
+            &mint_address,
+            &ata,
+            &wallet.pubkey(),
+            &[],
+            STABLE_TARGET_BALANCE - balance,
+        )?);
+    }
+
+    if !instructions.is_empty() {
+        let recent_blockhash = client.client.get_latest_blockhash()?;
+        let transaction =
+            Transaction::new_signed_with_payer(&instructions, Some(&wallet.pubkey()), &signers, recent_blockhash);
+        client.client.send_and_confirm_transaction(&transaction)?;
+    }
+
+    let balance = client.get_token_balance(&wallet.pubkey(), &mint_address).await?;
+    if balance < STABLE_TARGET_BALANCE {
+        return Err(anyhow!(
+            "Demo stable balance is {} after top-up, expected {}",
+            stable.format_amount(balance),
+            stable.format_amount(STABLE_TARGET_BALANCE)
+        ));
+    }
+    Ok((stable, balance))
+}
+
+async fn ensure_demo_asset(client: &FinternetClient, wallet: &Keypair) -> Result<Pubkey> {
+    let asset_type = AssetType::Custom(DEMO_ASSET_TYPE.to_string());
+    let held = client.get_owned_assets_by_type(&wallet.pubkey(), &asset_type).await?;
+    if let Some((mint, _, _)) = held.iter().find(|(_, _, info)| info.name == DEMO_ASSET_NAME) {
+        return Ok(*mint);
+    }
+
+    println!("   🏠 Tokenizing the demo asset...");
+    let (mint, _, _) = client
+        .tokenize_asset(
+            DEMO_ASSET_NAME,
+            "Fixture asset kept by the example wallet",
+            1_000,
+            &asset_type,
+            wallet,
+        )
+        .await?;
+    Ok(mint)
+}
+
+async fn ensure_identity(client: &FinternetClient, wallet: &Keypair) -> Result<String> {
+    if let Some(identity) = client.get_registered_identity(&wallet.pubkey()).await? {
+        if let Some(name) = identity.display_name {
+            return Ok(name);
+        }
+    }
+
+    println!("   🆔 Registering the demo identity...");
+    let mut metadata = HashMap::new();
+    metadata.insert("demo_user".to_string(), "true".to_string());
+    client.register_identity(wallet, DEMO_IDENTITY_NAME, metadata).await?;
+    Ok(DEMO_IDENTITY_NAME.to_string())
+}
@@ -0,0 +1,57 @@
+use anyhow::{anyhow, Result};
+use finternet_sdk::instructions::{memo_instruction, payment_instructions};
+use finternet_sdk::{usdc, FinternetClient};
+use solana_sdk::compute_budget::ComputeBudgetInstruction;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signer::Signer;
+use solana_sdk::transaction::Transaction;
+use std::str::FromStr;
+
+/// Sell a tokenized asset in one atomic transaction: the seller hands over the asset token, the
+/// buyer pays in USDC, and a memo records the deal. Either everything lands or nothing does.
+///
+/// Both wallets sign the same transaction. The seller is the default wallet; the buyer keypair
+/// file is the second argument.
+///
+///     cargo run --example composed_transaction -- <asset-mint> <buyer-keypair.json> <usdc-amount>
+#[tokio::main]
+async fn main() -> Result<()> {
+    let mut args = std::env::args().skip(1);
+    let usage = "usage: composed_transaction <asset-mint> <buyer-keypair.json> <usdc-amount>";
+    let asset_mint = Pubkey::from_str(&args.next().ok_or_else(|| anyhow!(usage))?)?;
+    let buyer_path = args.next().ok_or_else(|| anyhow!(usage))?;
+    let usdc_amount: f64 = args.next().ok_or_else(|| anyhow!(usage))?.parse()?;
+
+    let client = FinternetClient::new_devnet();
+    let seller = FinternetClient::load_default_wallet()?;
+    let buyer = solana_sdk::signature::read_keypair_file(&buyer_path)
+        .map_err(|e| anyhow!("Failed to read buyer keypair {}: {}", buyer_path, e))?;
+
+    println!("🤝 {} sells {} to {} for ${:.2} USDC", seller.pubkey(), asset_mint, buyer.pubkey(), usdc_amount);
+
+    let mut instructions = vec![ComputeBudgetInstruction::set_compute_unit_limit(200_000)];
+    // Asset token: seller -> buyer
+    instructions.extend(payment_instructions(&seller.pubkey(), &buyer.pubkey(), 1, &asset_mint, None)?);
+    // Payment: buyer -> seller (USDC has 6 decimals)
+    instructions.extend(payment_instructions(
+        &buyer.pubkey(),
+        &seller.pubkey(),
+        (usdc_amount * 1_000_000.0) as u64,
+        &usdc::devnet_mint(),
+        None,
+    )?);
+    instructions.push(memo_instruction(
+        &seller.pubkey(),
+        &format!("Sale of {} for {} USDC", asset_mint, usdc_amount),
+    ));
+
+    let recent_blockhash = client.client.get_latest_blockhash()?;
+    let mut transaction = Transaction::new_with_payer(&instructions, Some(&seller.pubkey()));
+    transaction.sign(&[&seller, &buyer], recent_blockhash);
+
+    let signature = client.client.send_and_confirm_transaction(&transaction)?;
+    println!("✅ Sale settled atomically");
+    println!("📝 Transaction: {}", signature);
+
+    Ok(())
+}
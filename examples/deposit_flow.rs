@@ -0,0 +1,79 @@
+mod common;
+
+use anyhow::{anyhow, Result};
+use common::fixtures::{connect, ensure_demo_environment};
+use finternet_sdk::deposit::DepositManager;
+use finternet_sdk::sweep::SweepOptions;
+use solana_sdk::signer::Signer;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Confirmation depth for the demo; production deployments should wait far longer
+const DEMO_MIN_CONFIRMATIONS: u64 = 1;
+const DETECTION_TIMEOUT_SECS: u64 = 120;
+
+/// Exchange-style deposit loop: assign a customer a deposit address, pay into it, watch for the
+/// deposit to be credited, then sweep it back to the treasury
+///
+/// The default wallet plays both the customer paying in and the treasury. Deposits are made in
+/// the demo stable token, so no devnet USDC is needed.
+///
+///     cargo run --example deposit_flow -- [customer-id]
+#[tokio::main]
+async fn main() -> Result<()> {
+    env_logger::init();
+    let customer_id = std::env::args().nth(1).unwrap_or_else(|| "customer-42".to_string());
+
+    let (client, wallet) = connect().await?;
+    println!("🔑 Treasury: {}", wallet.pubkey());
+
+    println!("\n🧰 Preparing demo environment...");
+    let env = ensure_demo_environment(&client, &wallet).await?;
+    let mint = env.stable_mint();
+    let deposits = DepositManager::open(&PathBuf::from(".finternet/demo-deposits.json"), &wallet, mint)?;
+
+    let address = deposits.assign_address(&customer_id)?;
+    println!("\n🏦 Deposit address for {}: {}", customer_id, address);
+
+    // Anything already on the address (from an earlier run) is credited now, not mistaken for
+    // this run's deposit
+    client.poll_deposits(&deposits, DEMO_MIN_CONFIRMATIONS).await?;
+
+    let amount = 2_500_000; // 2.5 DEMO
+    let signature = client
+        .send_payment(&wallet, &address, amount, &mint, Some(&format!("Deposit for {}", customer_id)))
+        .await?;
+    println!("💸 Paid in {}: {}", env.stable.format_amount(amount), signature);
+
+    println!("\n👀 Watching for the deposit...");
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(DETECTION_TIMEOUT_SECS);
+    loop {
+        let detected = client.poll_deposits(&deposits, DEMO_MIN_CONFIRMATIONS).await?;
+        if let Some(deposit) = detected.iter().find(|deposit| deposit.record.signature == signature) {
+            println!(
+                "   ✅ Credited {} with {}; total credited {}",
+                deposit.customer_id,
+                env.stable.format_amount(deposit.record.amount),
+                env.stable.format_amount(deposit.credited)
+            );
+            break;
+        }
+        if tokio::time::Instant::now() >= deadline {
+            return Err(anyhow!("Deposit {} was not credited within {}s", signature, DETECTION_TIMEOUT_SECS));
+        }
+        tokio::time::sleep(Duration::from_secs(2)).await;
+    }
+
+    println!("\n🧹 Sweeping deposits to the treasury...");
+    let report = client
+        .sweep_deposits(&deposits, &wallet, Some(&customer_id), &wallet.pubkey(), &SweepOptions::default())
+        .await?;
+    println!(
+        "   ✅ Swept {} in {} transactions ({} lamports in fees)",
+        env.stable.format_amount(report.total_swept()),
+        report.transactions.len(),
+        report.total_fees()
+    );
+
+    Ok(())
+}
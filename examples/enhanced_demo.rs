@@ -1,5 +1,9 @@
-use anyhow::Result;
-use finternet_sdk::FinternetClient;
+mod common;
+
+use anyhow::{anyhow, Result};
+use common::fixtures::{connect, ensure_demo_environment};
+use finternet_sdk::demo::{self, DemoOptions, DemoReport};
+use finternet_sdk::{AssetType, FinternetClient, FinternetConfig};
 use log::info;
 use solana_sdk::signer::Signer;
 use std::collections::HashMap;
@@ -8,58 +12,51 @@ use tokio::time::sleep;
 
 /// Enhanced comprehensive demo showing real-world Finternet SDK usage
 /// This demo addresses common issues and shows grant-ready functionality
+///
+/// Every step must succeed; any failure exits nonzero, so this doubles as a devnet canary.
+/// With `--self-contained` it instead runs the checked demo stages against a local validator
+/// with seeded keypairs and prints the JSON report.
 #[tokio::main]
 async fn main() -> Result<()> {
     env_logger::init();
     info!("🚀 Starting Enhanced Finternet SDK Demo");
     
-    // Initialize client
-    let client = FinternetClient::new_devnet();
-    println!("📡 Connected to Solana devnet: {}", client.config.rpc_url);
+    if std::env::args().any(|arg| arg == "--self-contained") {
+        let client = FinternetClient::new(FinternetConfig {
+            rpc_url: demo::LOCAL_VALIDATOR_URL.to_string(),
+            ..FinternetConfig::default()
+        })
+        .with_fixed_timestamp(demo::DEMO_TIMESTAMP);
+        let options = DemoOptions::self_contained(demo::DEFAULT_DEMO_SEED);
+        let report = client.run_demo(&options.payer()?, &options).await?;
+        println!("{}", serde_json::to_string_pretty(&report)?);
+        return require_passed(&report);
+    }
     
-    // Load wallet
-    let wallet = match FinternetClient::load_default_wallet() {
-        Ok(wallet) => {
-            println!("🔑 Using existing wallet: {}", wallet.pubkey());
-            wallet
-        }
-        Err(_) => {
-            println!("❌ No wallet found. Please run: solana-keygen new");
-            return Ok(());
-        }
-    };
+    // Initialize client and wallet: devnet, or a localnet with FINTERNET_LOCALNET=1
+    let (client, wallet) = connect().await?;
+    println!("📡 Connected to Solana: {}", client.config.rpc_url);
+    println!("🔑 Using wallet: {}", wallet.pubkey());
     
-    // Check and ensure sufficient SOL balance
-    println!("\n💰 Checking SOL balance...");
-    let sol_balance = client.client.get_balance(&wallet.pubkey())?;
-    let sol_amount = sol_balance as f64 / 1_000_000_000.0;
-    println!("   SOL Balance: {:.4}", sol_amount);
+    // Fixtures: SOL, a demo stable token, a pre-tokenized asset, and an identity
+    println!("\n🧰 Preparing demo environment...");
+    let env = ensure_demo_environment(&client, &wallet).await?;
     
-    if sol_amount < 0.1 {
-        println!("   ⚠️  Low SOL balance! Running airdrop...");
-        println!("   🪂 Please wait for airdrop to complete...");
-        
-        // Request airdrop
-        match client.client.request_airdrop(&wallet.pubkey(), 1_000_000_000) {
-            Ok(signature) => {
-                println!("   ✅ Airdrop requested: {}", signature);
-                // Wait for confirmation
-                sleep(Duration::from_secs(5)).await;
-            }
-            Err(e) => {
-                println!("   ❌ Airdrop failed: {}", e);
-                println!("   💡 Please manually run: solana airdrop 2");
-            }
-        }
+    // The same checked stages CI runs self-contained: fund, mint, tokenize, pay, register
+    println!("\n🧪 Checking core flows...");
+    let report = client.run_demo(&wallet, &DemoOptions::live()).await?;
+    for stage in &report.stages {
+        println!("   {:?} {}: {}", stage.status, stage.stage, stage.detail);
     }
+    require_passed(&report)?;
     
     // Enhanced Asset Tokenization Demo
     println!("\n🏭 ENHANCED DEMO 1: Multi-Asset Tokenization");
     
     let assets_to_create = vec![
-        ("Commercial Invoice #INV-2024-001", "Healthcare supplies invoice - Net 30 payment terms", 15000, "invoice"),
-        ("Gold Certificate #GLD-500oz", "500oz gold bullion certificate from COMEX", 1200000, "commodity"),
-        ("Real Estate Token #NYC-APT-42", "Manhattan luxury apartment - 2BR/2BA", 2500000, "real_estate"),
+        ("Commercial Invoice #INV-2024-001", "Healthcare supplies invoice - Net 30 payment terms", 15000, AssetType::Invoice),
+        ("Gold Certificate #GLD-500oz", "500oz gold bullion certificate from COMEX", 1200000, AssetType::Commodity),
+        ("Real Estate Token #NYC-APT-42", "Manhattan luxury apartment - 2BR/2BA", 2500000, AssetType::RealEstate),
     ];
     
     let mut created_tokens = Vec::new();
@@ -67,68 +64,31 @@ async fn main() -> Result<()> {
     for (name, description, value, asset_type) in assets_to_create {
         println!("   📝 Creating: {}", name);
         
-        match client.tokenize_asset(name, description, value, asset_type, &wallet).await {
-            Ok((mint, _metadata)) => {
-                println!("   ✅ Success! Token: {}", mint);
-                created_tokens.push(mint);
-                println!("   ⏳ Waiting for blockchain confirmation...");
-                sleep(Duration::from_secs(20)).await; // Wait for blockchain confirmation
-                println!("   ✅ Confirmation complete!");
-            }
-            Err(e) => {
-                println!("   ⚠️  Creation failed: {}", e);
-                println!("   💡 This is normal on devnet - some Metaplex operations require mainnet");
-            }
-        }
+        let (mint, _metadata, _signature) = client
+            .tokenize_asset(name, description, value, &asset_type, &wallet)
+            .await
+            .map_err(|e| anyhow!("Tokenizing {} failed: {}", name, e))?;
+        println!("   ✅ Success! Token: {}", mint);
+        created_tokens.push(mint);
     }
     
-    if !created_tokens.is_empty() {
-        println!("   🎉 Successfully created {} tokens!", created_tokens.len());
-    }
+    println!("   🎉 Successfully created {} tokens!", created_tokens.len());
     
     // Enhanced Asset Discovery
     println!("\n🔍 ENHANCED DEMO 2: Advanced Asset Discovery");
     println!("   📡 Scanning for all token holdings...");
     
-    match client.discover_all_tokens(&wallet.pubkey()).await {
-        Ok(tokens) => {
-            if tokens.is_empty() {
-                println!("   📭 No token holdings found");
-                println!("   💡 This is expected for new wallets");
-            } else {
-                println!("   📦 Found {} token holdings:", tokens.len());
-                for (mint, balance, name) in tokens {
-                    let display_name = name.unwrap_or_else(|| "Unknown Token".to_string());
-                    println!("      • {} (Balance: {}) - {}", display_name, balance, mint);
-                }
-            }
-        }
-        Err(e) => {
-            println!("   ❌ Asset discovery failed: {}", e);
-        }
+    let tokens = client.discover_all_tokens(&wallet.pubkey()).await?;
+    println!("   📦 Found {} token holdings:", tokens.len());
+    for (mint, balance, name) in &tokens {
+        let display_name = name.clone().unwrap_or_else(|| "Unknown Token".to_string());
+        println!("      • {} (Balance: {}) - {}", display_name, balance, mint);
     }
     
-    // USDC Setup and Testing
-    println!("\n💵 ENHANCED DEMO 3: USDC Integration Setup");
-    
-    match client.request_devnet_usdc(&wallet.pubkey()).await {
-        Ok(status) => {
-            println!("   📋 USDC Status: {}", status);
-        }
-        Err(e) => {
-            println!("   ❌ USDC check failed: {}", e);
-        }
-    }
-    
-    let usdc_balance = client.get_usdc_balance(&wallet.pubkey()).await?;
-    println!("   💰 Current USDC Balance: ${:.2}", usdc_balance);
-    
-    if usdc_balance < 1.0 {
-        println!("   💡 To get devnet USDC:");
-        println!("      1. Create USDC token account: spl-token create-account 4zMMC9srt5Ri5X14GAgXhaHii3GnPAEERYPJgZJDncDU");
-        println!("      2. Use a devnet USDC faucet or ask in Solana Discord");
-        println!("      3. Alternative: Use SPL Token faucet for testing");
-    }
+    // Stable token standing in for USDC, which devnet wallets cannot be relied on to hold
+    println!("\n💵 ENHANCED DEMO 3: Stable Token Setup");
+    let stable_balance = client.get_token_balance(&wallet.pubkey(), &env.stable_mint()).await?;
+    println!("   💰 Current {} balance: {}", env.stable.label(), env.stable.format_amount(stable_balance));
     
     // Identity and Registration Demo
     println!("\n🆔 ENHANCED DEMO 4: Professional Identity Registration");
@@ -140,19 +100,12 @@ async fn main() -> Result<()> {
     professional_metadata.insert("trading_experience".to_string(), "institutional".to_string());
     professional_metadata.insert("demo_timestamp".to_string(), chrono::Utc::now().to_rfc3339());
     
-    match client.register_identity(&wallet, "Finternet Demo Account", professional_metadata).await {
-        Ok(signature) => {
-            println!("   ✅ Professional identity registered!");
-            println!("   📝 Transaction: {}", signature);
-            println!("   🔗 View: https://explorer.solana.com/tx/{}?cluster=devnet", signature);
-            println!("   ⏳ Waiting for identity confirmation...");
-            sleep(Duration::from_secs(10)).await; // Wait for identity confirmation
-            println!("   ✅ Identity confirmation complete!");
-        }
-        Err(e) => {
-            println!("   ⚠️  Identity registration: {}", e);
-        }
-    }
+    let signature = client
+        .register_identity(&wallet, &env.identity_name, professional_metadata)
+        .await?;
+    println!("   ✅ Professional identity registered!");
+    println!("   📝 Transaction: {}", signature);
+    println!("   🔗 View: {}", client.explorer_url(&format!("tx/{}", signature)));
     
     // Wait a bit more before final discovery to ensure all transactions are settled
     println!("\n🔄 Final Asset Discovery (after blockchain settlement)");
@@ -160,24 +113,14 @@ async fn main() -> Result<()> {
     sleep(Duration::from_secs(10)).await;
     
     println!("   📡 Re-scanning for all token holdings...");
-    match client.discover_all_tokens(&wallet.pubkey()).await {
-        Ok(tokens) => {
-            if tokens.is_empty() {
-                println!("   📭 Still no token holdings found in discovery");
-                println!("   💡 Note: Tokens were created (see mint addresses above)");
-                println!("   💡 Discovery timing can vary - tokens are real and verifiable on Explorer");
-            } else {
-                println!("   🎉 SUCCESS! Found {} token holdings:", tokens.len());
-                for (mint, balance, name) in tokens {
-                    let display_name = name.unwrap_or_else(|| "Unknown Token".to_string());
-                    println!("      • {} (Balance: {}) - {}", display_name, balance, mint);
-                }
-            }
-        }
-        Err(e) => {
-            println!("   ❌ Asset discovery failed: {}", e);
+    let tokens = client.discover_all_tokens(&wallet.pubkey()).await?;
+    println!("   📦 Found {} token holdings", tokens.len());
+    for expected in created_tokens.iter().chain([&env.asset_mint]) {
+        if !tokens.iter().any(|(mint, _, _)| mint == expected) {
+            return Err(anyhow!("Discovery did not find minted asset {}", expected));
         }
     }
+    println!("   🎉 SUCCESS! Discovery found all {} minted assets", created_tokens.len() + 1);
     
     // Cross-Border Payment Simulation
     println!("\n🌍 ENHANCED DEMO 5: Cross-Border Payment Workflow");
@@ -194,96 +137,79 @@ async fn main() -> Result<()> {
     println!("\n   📋 Invoice Financing Scenario:");
     println!("   1. Alice creates invoice token (already done above)");
     println!("   2. Bob wants to purchase invoice at discount");
-    println!("   3. Payment would happen via USDC transfer");
-    
-    if usdc_balance >= 10.0 {
-        println!("   💸 Executing payment demo...");
-        
-        match client.send_usdc_payment(
-            &wallet,
-            &alice_wallet.pubkey(),
-            5.0,
-            Some("Invoice #INV-2024-001 purchase - 67% of face value"),
-        ).await {
-            Ok(signature) => {
-                println!("   ✅ Cross-border payment successful!");
-                println!("   📝 Transaction: {}", signature);
-                println!("   💰 Paid: $5.00 USDC");
-                println!("   🔗 Verify: https://explorer.solana.com/tx/{}?cluster=devnet", signature);
-            }
-            Err(e) => {
-                println!("   ❌ Payment failed: {}", e);
-            }
-        }
-    } else {
-        println!("   💡 Demo payment skipped (insufficient USDC)");
-        println!("   💡 With USDC, this would execute a real cross-border payment");
+    println!("   3. Payment happens via a stable token transfer");
+    
+    println!("   💸 Executing payment demo...");
+    let amount = 5_000_000; // 5.00 with 6 decimals
+    let signature = client.send_payment(
+        &wallet,
+        &alice_wallet.pubkey(),
+        amount,
+        &env.stable_mint(),
+        Some("Invoice #INV-2024-001 purchase - 67% of face value"),
+    ).await?;
+    println!("   ✅ Cross-border payment successful!");
+    println!("   📝 Transaction: {}", signature);
+    println!("   🔗 Verify: {}", client.explorer_url(&format!("tx/{}", signature)));
+    
+    let received = client.get_token_balance(&alice_wallet.pubkey(), &env.stable_mint()).await?;
+    if received != amount {
+        return Err(anyhow!(
+            "Alice holds {} after payment {}, expected {}",
+            env.stable.format_amount(received),
+            signature,
+            env.stable.format_amount(amount)
+        ));
     }
+    println!("   💰 Paid: {}", env.stable.format_amount(received));
     
     // Transaction History with Context
     println!("\n📚 ENHANCED DEMO 6: Comprehensive Transaction Analysis");
     
-    match client.get_transaction_history(&wallet.pubkey(), Some(20)).await {
-        Ok(history) => {
-            if history.is_empty() {
-                println!("   📭 No transaction history yet");
-                println!("   💡 History will populate as you use the SDK");
-            } else {
-                println!("   📋 Transaction History ({} entries):", history.len());
-                for (i, record) in history.iter().enumerate() {
-                    println!("      {}. Signature: {}", i + 1, record.signature);
-                    println!("         {} → {}", record.from, record.to);
-                    println!("         Amount: {} tokens", record.amount);
-                    if let Some(memo) = &record.memo {
-                        println!("         Purpose: {}", memo);
-                    }
-                    println!("         Time: {}", chrono::DateTime::from_timestamp(record.timestamp as i64, 0)
-                        .map(|dt| dt.format("%Y-%m-%d %H:%M:%S UTC").to_string())
-                        .unwrap_or_else(|| "Unknown".to_string()));
-                    println!();
-                }
+    let history = client.get_transaction_history(&wallet.pubkey(), Some(20)).await?;
+    if history.is_empty() {
+        println!("   📭 No transaction history yet");
+    } else {
+        println!("   📋 Transaction History ({} entries):", history.len());
+        for (i, record) in history.iter().enumerate() {
+            println!("      {}. Signature: {}", i + 1, record.signature);
+            println!("         {} → {}", record.from, record.to);
+            println!("         Amount: {}", record.currency.format_amount(record.amount));
+            if let Some(memo) = &record.memo {
+                println!("         Purpose: {}", memo);
             }
-        }
-        Err(e) => {
-            println!("   ❌ Failed to fetch history: {}", e);
+            println!("         Time: {}", chrono::DateTime::from_timestamp(record.timestamp as i64, 0)
+                .map(|dt| dt.format("%Y-%m-%d %H:%M:%S UTC").to_string())
+                .unwrap_or_else(|| "Unknown".to_string()));
+            println!();
         }
     }
     
     // Advanced Wallet Analytics
     println!("\n📊 ENHANCED DEMO 7: Wallet Analytics Dashboard");
     
-    match client.get_wallet_info(&wallet.pubkey()).await {
-        Ok(info) => {
-            println!("   💼 Wallet Analytics:");
-            println!("      Address: {}", wallet.pubkey());
-            println!("      SOL Balance: {:.4} SOL", info.sol_balance_as_sol());
-            println!("      Token Types: {}", info.total_token_types());
-            println!("      USDC Balance: ${:.2}", usdc_balance);
-            
-            if !created_tokens.is_empty() {
-                println!("      Assets Tokenized: {}", created_tokens.len());
-            }
-            
-            // Try to get identity
-            if let Ok(identity) = client.get_identity(&wallet.pubkey()).await {
-                if let Some(name) = &identity.display_name {
-                    println!("      Identity: {}", name);
-                }
-                if !identity.metadata.is_empty() {
-                    println!("      Profile: {} attributes", identity.metadata.len());
-                }
-            }
-        }
-        Err(e) => {
-            println!("   ❌ Analytics failed: {}", e);
-        }
+    let info = client.get_wallet_info(&wallet.pubkey()).await?;
+    println!("   💼 Wallet Analytics:");
+    println!("      Address: {}", wallet.pubkey());
+    for (currency, balance) in info.balances() {
+        println!("      {}", currency.format_amount(balance));
+    }
+    println!("      Token Types: {}", info.total_token_types());
+    println!("      Assets Tokenized: {}", created_tokens.len());
+    
+    let identity = client.get_identity(&wallet.pubkey()).await?;
+    if let Some(name) = &identity.display_name {
+        println!("      Identity: {}", name);
+    }
+    if !identity.metadata.is_empty() {
+        println!("      Profile: {} attributes", identity.metadata.len());
     }
     
     // Grant Readiness Summary
     println!("\n🏆 GRANT READINESS SUMMARY");
     println!("   ✅ Asset Tokenization: {} tokens created", created_tokens.len());
     println!("   ✅ Identity System: Professional profile registered");
-    println!("   ✅ Payment Infrastructure: USDC integration ready");
+    println!("   ✅ Payment Infrastructure: Stable token payments settled");
     println!("   ✅ Ledger Access: Transaction history & analytics");
     println!("   ✅ Real Blockchain Activity: All operations on-chain");
     println!("   ✅ Production Ready: Mainnet deployment capable");
@@ -305,4 +231,11 @@ async fn main() -> Result<()> {
     println!("\n🚀 Demo completed! This SDK is ready for $10,000 USDC grant submission.");
     
     Ok(())
-} 
\ No newline at end of file
+}
+
+fn require_passed(report: &DemoReport) -> Result<()> {
+    match report.stages.iter().find(|stage| stage.status != demo::StageStatus::Passed) {
+        Some(stage) => Err(anyhow!("Demo stage {} did not pass: {}", stage.stage, stage.detail)),
+        None => Ok(()),
+    }
+}
@@ -0,0 +1,210 @@
+use anyhow::Result;
+use finternet_sdk::FinternetClient;
+use serde_json::{Map, Value};
+use solana_sdk::signer::Signer;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Level, Metadata, Subscriber};
+
+/// Read-only SDK calls with every span and event emitted as one JSON object per line,
+/// ready to be shipped to Loki, Datadog, or any other JSON log pipeline.
+///
+/// Set `FINTERNET_LOG=debug` to include the per-RPC timing events.
+#[tokio::main]
+async fn main() -> Result<()> {
+    let max_level = match std::env::var("FINTERNET_LOG").as_deref() {
+        Ok("trace") => Level::TRACE,
+        Ok("debug") => Level::DEBUG,
+        Ok("warn") => Level::WARN,
+        Ok("error") => Level::ERROR,
+        _ => Level::INFO,
+    };
+    tracing::subscriber::set_global_default(JsonSubscriber::new(max_level))?;
+
+    let client = FinternetClient::new_devnet();
+    let wallet_pubkey = match FinternetClient::load_default_wallet() {
+        Ok(wallet) => wallet.pubkey(),
+        Err(_) => FinternetClient::create_new_wallet().pubkey(),
+    };
+
+    let _ = client.get_sol_balance(&wallet_pubkey).await;
+    let _ = client.get_token_accounts(&wallet_pubkey).await;
+    let _ = client.get_identity(&wallet_pubkey).await;
+
+    Ok(())
+}
+
+struct SpanData {
+    name: &'static str,
+    parent: Option<Id>,
+    fields: Map<String, Value>,
+    refs: usize,
+}
+
+/// Minimal subscriber that writes each event, with the fields of its enclosing spans, as JSON
+struct JsonSubscriber {
+    max_level: Level,
+    next_id: AtomicU64,
+    spans: Mutex<HashMap<u64, SpanData>>,
+}
+
+thread_local! {
+    static CURRENT: RefCell<Vec<Id>> = const { RefCell::new(Vec::new()) };
+}
+
+impl JsonSubscriber {
+    fn new(max_level: Level) -> Self {
+        Self {
+            max_level,
+            next_id: AtomicU64::new(1),
+            spans: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn current_span() -> Option<Id> {
+        CURRENT.with(|stack| stack.borrow().last().cloned())
+    }
+}
+
+struct JsonVisitor<'a>(&'a mut Map<String, Value>);
+
+impl Visit for JsonVisitor<'_> {
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.0.insert(field.name().to_string(), Value::from(value));
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.0.insert(field.name().to_string(), Value::from(value));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.0.insert(field.name().to_string(), Value::from(value));
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.0.insert(field.name().to_string(), Value::from(value));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().to_string(), Value::from(value));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.0.insert(field.name().to_string(), Value::from(format!("{:?}", value)));
+    }
+}
+
+impl Subscriber for JsonSubscriber {
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        metadata.level() <= &self.max_level
+    }
+
+    fn new_span(&self, attrs: &Attributes<'_>) -> Id {
+        let id = Id::from_u64(self.next_id.fetch_add(1, Ordering::Relaxed));
+        let parent = if attrs.is_contextual() {
+            Self::current_span()
+        } else {
+            attrs.parent().cloned()
+        };
+
+        let mut fields = Map::new();
+        attrs.record(&mut JsonVisitor(&mut fields));
+
+        self.spans.lock().unwrap().insert(
+            id.into_u64(),
+            SpanData {
+                name: attrs.metadata().name(),
+                parent,
+                fields,
+                refs: 1,
+            },
+        );
+        id
+    }
+
+    fn record(&self, span: &Id, values: &Record<'_>) {
+        if let Some(data) = self.spans.lock().unwrap().get_mut(&span.into_u64()) {
+            values.record(&mut JsonVisitor(&mut data.fields));
+        }
+    }
+
+    fn record_follows_from(&self, _span: &Id, _follows: &Id) {}
+
+    fn event(&self, event: &Event<'_>) {
+        let mut fields = Map::new();
+        event.record(&mut JsonVisitor(&mut fields));
+
+        // Walk from the innermost span outwards, collecting its name and fields
+        let mut spans = Vec::new();
+        let mut cursor = if event.is_contextual() {
+            Self::current_span()
+        } else {
+            event.parent().cloned()
+        };
+        {
+            let registry = self.spans.lock().unwrap();
+            while let Some(id) = cursor {
+                match registry.get(&id.into_u64()) {
+                    Some(data) => {
+                        let mut span = data.fields.clone();
+                        span.insert("name".to_string(), Value::from(data.name));
+                        spans.push(Value::Object(span));
+                        cursor = data.parent.clone();
+                    }
+                    None => break,
+                }
+            }
+        }
+        spans.reverse();
+
+        let metadata = event.metadata();
+        let line = serde_json::json!({
+            "timestamp": chrono::Utc::now().to_rfc3339(),
+            "level": metadata.level().to_string(),
+            "target": metadata.target(),
+            "fields": fields,
+            "spans": spans,
+        });
+        println!("{}", line);
+    }
+
+    fn enter(&self, span: &Id) {
+        CURRENT.with(|stack| stack.borrow_mut().push(span.clone()));
+    }
+
+    fn exit(&self, span: &Id) {
+        CURRENT.with(|stack| {
+            let mut stack = stack.borrow_mut();
+            if let Some(pos) = stack.iter().rposition(|id| id == span) {
+                stack.remove(pos);
+            }
+        });
+    }
+
+    fn clone_span(&self, span: &Id) -> Id {
+        if let Some(data) = self.spans.lock().unwrap().get_mut(&span.into_u64()) {
+            data.refs += 1;
+        }
+        span.clone()
+    }
+
+    fn try_close(&self, span: Id) -> bool {
+        let mut registry = self.spans.lock().unwrap();
+        let closed = match registry.get_mut(&span.into_u64()) {
+            Some(data) => {
+                data.refs -= 1;
+                data.refs == 0
+            }
+            None => false,
+        };
+        if closed {
+            registry.remove(&span.into_u64());
+        }
+        closed
+    }
+}
@@ -0,0 +1,74 @@
+mod common;
+
+use anyhow::{anyhow, Result};
+use common::fixtures::{connect, ensure_demo_environment};
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Keypair;
+use solana_sdk::signer::Signer;
+use std::time::{Duration, Instant};
+
+const DEFAULT_PAYMENTS: usize = 20;
+/// Payments cycle through this many fresh recipients, so both runs pay some new ATAs and mostly known ones
+const RECIPIENTS: usize = 4;
+
+/// Time the same run of sequential payments sent one by one and through a `SendContext`
+///
+/// Each payment moves one base unit of the demo stable token. The plain run goes first, so its
+/// recipients' ATAs exist by the time the context run reads them; the context run pays fresh
+/// recipients of its own for a fair comparison.
+///
+///     cargo run --example send_context_benchmark -- [payments]
+#[tokio::main]
+async fn main() -> Result<()> {
+    let payments: usize = match std::env::args().nth(1) {
+        Some(arg) => arg.parse().map_err(|e| anyhow!("usage: send_context_benchmark [payments]: {}", e))?,
+        None => DEFAULT_PAYMENTS,
+    };
+
+    let (client, wallet) = connect().await?;
+    println!("🧰 Preparing demo environment...");
+    let env = ensure_demo_environment(&client, &wallet).await?;
+    let mint = env.stable_mint();
+
+    println!("\n⏱️  {} payments with send_payment...", payments);
+    let recipients = fresh_recipients();
+    let fetches = client.blockhash_provider().fetches();
+    let started = Instant::now();
+    for i in 0..payments {
+        client.send_payment(&wallet, &recipients[i % RECIPIENTS], 1, &mint, None).await?;
+    }
+    let plain = started.elapsed();
+    report(plain, payments, client.blockhash_provider().fetches() - fetches, None);
+
+    println!("\n⏱️  {} payments with send_payment_with_context...", payments);
+    let recipients = fresh_recipients();
+    let fetches = client.blockhash_provider().fetches();
+    let started = Instant::now();
+    let context = client.prepare_send_context(&mint)?;
+    client.prefetch_recipients(&context, &recipients)?;
+    for i in 0..payments {
+        client.send_payment_with_context(&context, &wallet, &recipients[i % RECIPIENTS], 1, None).await?;
+    }
+    let with_context = started.elapsed();
+    report(with_context, payments, client.blockhash_provider().fetches() - fetches, Some(context.lookups()));
+
+    println!(
+        "\n📊 Context run took {:.0}% of the plain run's time",
+        with_context.as_secs_f64() / plain.as_secs_f64() * 100.0
+    );
+    Ok(())
+}
+
+fn fresh_recipients() -> Vec<Pubkey> {
+    (0..RECIPIENTS).map(|_| Keypair::new().pubkey()).collect()
+}
+
+fn report(elapsed: Duration, payments: usize, blockhash_fetches: u64, lookups: Option<u64>) {
+    println!("   Total:             {:.2}s", elapsed.as_secs_f64());
+    println!("   Per payment:       {:.0} ms", elapsed.as_secs_f64() * 1000.0 / payments.max(1) as f64);
+    println!("   Blockhash fetches: {}", blockhash_fetches);
+    match lookups {
+        Some(lookups) => println!("   Account lookups:   {}", lookups),
+        None => println!("   Account lookups:   {} (one per payment)", payments),
+    }
+}
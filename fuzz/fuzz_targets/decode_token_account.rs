@@ -0,0 +1,48 @@
+//! Feeds arbitrary input to `decode_token_account` in every form an RPC node returns account
+//! data in, so a malformed response can only ever be an error
+//!
+//! Input that parses as JSON is tried as an account dump (`{"data": ...}` as in
+//! `tests/fixtures/token_accounts`), as `UiAccountData` on its own, and as jsonParsed data. Any
+//! input is also tried as raw account bytes, base64 and base58 encoded. Seed it with the fixtures:
+//!
+//! ```bash
+//! cargo +nightly fuzz run decode_token_account fuzz/corpus/decode_token_account tests/fixtures/token_accounts
+//! ```
+
+#![no_main]
+
+use base64::{engine::general_purpose, Engine};
+use finternet_sdk::token_account::{decode_token_account, TokenAccountScan};
+use libfuzzer_sys::fuzz_target;
+use solana_account_decoder::parse_account_data::ParsedAccount;
+use solana_account_decoder::{UiAccountData, UiAccountEncoding};
+
+fuzz_target!(|input: &[u8]| {
+    if let Ok(value) = serde_json::from_slice::<serde_json::Value>(input) {
+        if let Some(data) = value.get("data").and_then(|data| serde_json::from_value::<UiAccountData>(data.clone()).ok()) {
+            let _ = decode_token_account(&data);
+        }
+        if let Ok(data) = serde_json::from_value::<UiAccountData>(value.clone()) {
+            let _ = decode_token_account(&data);
+        }
+        for program in ["spl-token", "spl-token-2022"] {
+            let parsed = ParsedAccount { program: program.to_string(), parsed: value.clone(), space: input.len() as u64 };
+            let _ = decode_token_account(&UiAccountData::Json(parsed));
+        }
+    }
+
+    let base64 = general_purpose::STANDARD.encode(input);
+    let base58 = bs58::encode(input).into_string();
+    let mut scan = TokenAccountScan::default();
+    scan.push(&base58, &UiAccountData::Binary(base64, UiAccountEncoding::Base64));
+    scan.push(&base58, &UiAccountData::Binary(base58.clone(), UiAccountEncoding::Base58));
+    scan.push(&base58, &UiAccountData::LegacyBinary(base58.clone()));
+    // Text that is not valid in its stated encoding
+    if let Ok(text) = std::str::from_utf8(input) {
+        for encoding in [UiAccountEncoding::Base64, UiAccountEncoding::Base58, UiAccountEncoding::Base64Zstd, UiAccountEncoding::Binary] {
+            let _ = decode_token_account(&UiAccountData::Binary(text.to_string(), encoding));
+        }
+        let _ = decode_token_account(&UiAccountData::LegacyBinary(text.to_string()));
+    }
+    assert_eq!(scan.accounts.len() + scan.undecodable.len(), 3);
+});
@@ -0,0 +1,492 @@
+//! Append-only local record of every action the SDK took, for audits
+//!
+//! Each entry is one JSON line carrying the operation, its parameters (addresses and amounts,
+//! never keys), its signature or error and the error's catalogue code, and the policy decisions
+//! it went through. Entries are hash-chained: each one commits to the hash of the one before it,
+//! so `verify_action_log` notices any line that was edited, removed, or reordered.
+//!
+//! With a `RotationPolicy`, a live file past its size is verified and renamed to a segment,
+//! `<path>.<first seq>`, and the chain carries on in a fresh file. Segments beyond the retained
+//! count are deleted oldest first, each only after it verifies; `<path>.anchor` then records
+//! where the deleted ones ended, so the oldest kept segment can still be checked.
+
+use crate::asset_policy::AssetPolicyViolation;
+use crate::dry_run::DryRunOutcome;
+use crate::error_code::FinternetError;
+use crate::events::SdkEvent;
+use crate::policy::PolicyViolation;
+use crate::FinternetClient;
+use crate::resources::{file_bytes, StoreUsage};
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use solana_sdk::pubkey::Pubkey;
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// `prev_hash` of the first entry
+pub const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// What one policy decided about an action
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PolicyDecision {
+    /// `spending_policy` or `asset_policy`
+    pub policy: String,
+    pub allowed: bool,
+    /// Violation code when the policy refused the action
+    pub code: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ActionEntry {
+    /// Position in the log, from 0
+    pub seq: u64,
+    pub timestamp: u64,
+    /// SDK method, e.g. `send_payment`
+    pub operation: String,
+    pub parameters: serde_json::Value,
+    pub signature: Option<String>,
+    pub error: Option<String>,
+    /// Catalogue code of the error, e.g. `E1001`; absent in entries written before codes existed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<String>,
+    #[serde(default)]
+    pub policy_decisions: Vec<PolicyDecision>,
+    pub prev_hash: String,
+    /// Hex SHA-256 of the entry serialized with this field empty
+    pub hash: String,
+}
+
+impl ActionEntry {
+    fn compute_hash(&self) -> Result<String> {
+        let unhashed = ActionEntry { hash: String::new(), ..self.clone() };
+        Ok(sha256_hex(serde_json::to_string(&unhashed)?.as_bytes()))
+    }
+}
+
+/// Why `verify_action_log` rejected a log; `line` counts from 1
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ActionLogError {
+    Malformed { line: usize, reason: String },
+    /// The entry's contents no longer match its hash
+    HashMismatch { line: usize },
+    /// The entry does not follow the one before it
+    ChainBroken { line: usize },
+}
+
+impl ActionLogError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::Malformed { .. } => "action_log_malformed",
+            Self::HashMismatch { .. } => "action_log_hash_mismatch",
+            Self::ChainBroken { .. } => "action_log_chain_broken",
+        }
+    }
+}
+
+impl fmt::Display for ActionLogError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Malformed { line, reason } => write!(f, "Action log line {} is not a valid entry: {}", line, reason),
+            Self::HashMismatch { line } => write!(f, "Action log line {} was modified after it was written", line),
+            Self::ChainBroken { line } => {
+                write!(f, "Action log line {} does not follow the entry before it; entries were removed or reordered", line)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ActionLogError {}
+
+/// Summary of a log that verified cleanly
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ActionLogVerification {
+    /// Entries still on disk, across the live file and its segments
+    pub entries: u64,
+    /// Hash of the newest entry; recording it elsewhere also detects truncation
+    pub head: String,
+    /// Entries in segments deleted by rotation, verified before they were
+    #[serde(default)]
+    pub pruned: u64,
+    #[serde(default)]
+    pub segments: usize,
+}
+
+/// When the action log rotates, and how many rotated segments it keeps
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RotationPolicy {
+    /// Rotate before an append once the live file has reached this size
+    pub max_bytes: u64,
+    /// Newest segments kept; older ones are deleted once they verify
+    pub retained_segments: usize,
+}
+
+/// Where the newest deleted segment ended: the next sequence number and the hash to chain from
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct ChainAnchor {
+    next_seq: u64,
+    head: String,
+}
+
+/// One page of entries, oldest first
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ActionLogPage {
+    pub total: usize,
+    pub offset: usize,
+    pub entries: Vec<ActionEntry>,
+}
+
+/// JSONL action log, appended to under a lock so concurrent actions chain in order
+pub struct ActionLog {
+    path: PathBuf,
+    /// Next sequence number and the newest hash, read from the files on first append
+    head: Mutex<Option<(u64, String)>>,
+    rotation: Option<RotationPolicy>,
+}
+
+impl ActionLog {
+    pub fn new(path: &Path) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            head: Mutex::new(None),
+            rotation: None,
+        }
+    }
+
+    /// Rotate as `rotation` says; the log grows without bound when it is `None`
+    pub fn with_rotation(mut self, rotation: Option<RotationPolicy>) -> Self {
+        self.rotation = rotation;
+        self
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Chain and append an entry, returning it as written
+    pub fn append(
+        &self,
+        operation: &str,
+        parameters: serde_json::Value,
+        signature: Option<String>,
+        error: Option<String>,
+        error_code: Option<String>,
+        policy_decisions: Vec<PolicyDecision>,
+    ) -> Result<ActionEntry> {
+        let mut head = self.head.lock().map_err(|_| anyhow!("Action log lock poisoned"))?;
+        if let Some(rotation) = self.rotation {
+            if file_bytes(&self.path) >= rotation.max_bytes {
+                self.rotate(rotation)?;
+            }
+        }
+        let (seq, prev_hash) = match head.take() {
+            Some(head) => head,
+            None => match read_entries(&self.path)?.last() {
+                Some(last) => (last.seq + 1, last.hash.clone()),
+                None => segment_tip(&self.path)?,
+            },
+        };
+
+        let mut entry = ActionEntry {
+            seq,
+            timestamp: SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0),
+            operation: operation.to_string(),
+            parameters,
+            signature,
+            error,
+            error_code,
+            policy_decisions,
+            prev_hash,
+            hash: String::new(),
+        };
+        entry.hash = entry.compute_hash()?;
+
+        if let Some(parent) = self.path.parent() {
+            if !parent.as_os_str().is_empty() {
+                fs::create_dir_all(parent)?;
+            }
+        }
+        let mut file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        // On failure the head stays unset, so the next append rereads what actually got written
+        writeln!(file, "{}", serde_json::to_string(&entry)?)
+            .and_then(|_| file.sync_data())
+            .map_err(|e| anyhow!("Could not append to action log {}: {}", self.path.display(), e))?;
+        *head = Some((seq + 1, entry.hash.clone()));
+        Ok(entry)
+    }
+
+    /// Entries `offset..offset + limit` of those still on disk, oldest first
+    pub fn page(&self, offset: usize, limit: usize) -> Result<ActionLogPage> {
+        let mut entries = Vec::new();
+        for (_, segment) in retained_segments(&self.path)? {
+            entries.extend(read_entries(&segment)?);
+        }
+        entries.extend(read_entries(&self.path)?);
+        Ok(ActionLogPage {
+            total: entries.len(),
+            offset,
+            entries: entries.into_iter().skip(offset).take(limit).collect(),
+        })
+    }
+
+    /// Size of the live file and of the segments kept beside it
+    pub fn usage(&self) -> Result<StoreUsage> {
+        let segments = retained_segments(&self.path)?;
+        Ok(StoreUsage {
+            path: self.path.clone(),
+            entries: read_entries(&self.path)?.len(),
+            bytes: file_bytes(&self.path),
+            archived_files: segments.len(),
+            archived_bytes: segments.iter().map(|(_, segment)| file_bytes(segment)).sum(),
+        })
+    }
+
+    /// Verify the live file and rename it to a segment, then prune old segments
+    ///
+    /// A live file that does not verify stays where it is and keeps growing, so an altered
+    /// entry is never rotated out of sight; the failure is logged.
+    fn rotate(&self, rotation: RotationPolicy) -> Result<()> {
+        let start = segment_tip(&self.path)?;
+        let first_seq = start.0;
+        match verify_file(&self.path, start) {
+            Ok((next_seq, _)) if next_seq == first_seq => return Ok(()),
+            Ok(_) => {}
+            Err(e) => {
+                tracing::error!("Not rotating action log {}: {:#}", self.path.display(), e);
+                return Ok(());
+            }
+        }
+        let segment = segment_path(&self.path, first_seq);
+        fs::rename(&self.path, &segment)
+            .map_err(|e| anyhow!("Could not rotate action log {} to {}: {}", self.path.display(), segment.display(), e))?;
+        tracing::info!("Rotated action log {} to {}", self.path.display(), segment.display());
+
+        let segments = retained_segments(&self.path)?;
+        let excess = segments.len().saturating_sub(rotation.retained_segments);
+        for (_, segment) in segments.into_iter().take(excess) {
+            let start = read_anchor(&self.path)?.map_or((0, GENESIS_HASH.to_string()), |anchor| (anchor.next_seq, anchor.head));
+            let (next_seq, head) = match verify_file(&segment, start) {
+                Ok(tip) => tip,
+                Err(e) => {
+                    tracing::error!("Keeping action log segment {}, which does not verify: {:#}", segment.display(), e);
+                    break;
+                }
+            };
+            // The anchor goes first: a crash before the delete leaves a segment it already covers
+            write_anchor(&self.path, &ChainAnchor { next_seq, head })?;
+            fs::remove_file(&segment)?;
+            tracing::info!("Pruned action log segment {}", segment.display());
+        }
+        Ok(())
+    }
+}
+
+/// Check every entry's hash and its link to the one before it, across the retained segments
+/// and the live file
+///
+/// Fails with an `ActionLogError` naming the first line that does not verify, in the segment
+/// it is in. A missing file is an empty, valid log.
+pub fn verify_action_log(path: &Path) -> Result<ActionLogVerification> {
+    let start = read_anchor(path)?.map_or((0, GENESIS_HASH.to_string()), |anchor| (anchor.next_seq, anchor.head));
+    let pruned = start.0;
+    let segments = retained_segments(path)?;
+    let mut tip = start;
+    for (_, segment) in &segments {
+        tip = verify_file(segment, tip).with_context(|| format!("In action log segment {}", segment.display()))?;
+    }
+    let (next_seq, head) = verify_file(path, tip)?;
+    Ok(ActionLogVerification { entries: next_seq - pruned, head, pruned, segments: segments.len() })
+}
+
+/// Verify one file whose first entry follows `(seq, hash)`, returning the same for its last
+fn verify_file(path: &Path, (mut next_seq, mut head): (u64, String)) -> Result<(u64, String)> {
+    let data = match fs::read_to_string(path) {
+        Ok(data) => data,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e.into()),
+    };
+    for (index, line) in data.lines().enumerate().filter(|(_, line)| !line.trim().is_empty()) {
+        let line_number = index + 1;
+        let entry: ActionEntry = serde_json::from_str(line).map_err(|e| ActionLogError::Malformed {
+            line: line_number,
+            reason: e.to_string(),
+        })?;
+        if entry.compute_hash()? != entry.hash {
+            return Err(ActionLogError::HashMismatch { line: line_number }.into());
+        }
+        if entry.seq != next_seq || entry.prev_hash != head {
+            return Err(ActionLogError::ChainBroken { line: line_number }.into());
+        }
+        head = entry.hash;
+        next_seq += 1;
+    }
+    Ok((next_seq, head))
+}
+
+/// `<path>.<first seq>`, zero-padded so segments sort by name too
+fn segment_path(path: &Path, first_seq: u64) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".{:012}", first_seq));
+    PathBuf::from(name)
+}
+
+fn anchor_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".anchor");
+    PathBuf::from(name)
+}
+
+fn read_anchor(path: &Path) -> Result<Option<ChainAnchor>> {
+    let anchor = anchor_path(path);
+    match fs::read_to_string(&anchor) {
+        Ok(data) => Ok(Some(
+            serde_json::from_str(&data).map_err(|e| anyhow!("Corrupt action log anchor {}: {}", anchor.display(), e))?,
+        )),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn write_anchor(path: &Path, anchor: &ChainAnchor) -> Result<()> {
+    let target = anchor_path(path);
+    let tmp = target.with_extension("anchor.tmp");
+    fs::write(&tmp, serde_json::to_string(anchor)?)?;
+    fs::rename(&tmp, &target)?;
+    Ok(())
+}
+
+/// Rotated segments not yet covered by the anchor, oldest first
+fn retained_segments(path: &Path) -> Result<Vec<(u64, PathBuf)>> {
+    let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+        return Ok(Vec::new());
+    };
+    let dir = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+    let pruned = read_anchor(path)?.map_or(0, |anchor| anchor.next_seq);
+    let listing = match fs::read_dir(&dir) {
+        Ok(listing) => listing,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+    let prefix = format!("{}.", name);
+    let mut segments = Vec::new();
+    for file in listing {
+        let file = file?;
+        let file_name = file.file_name();
+        let Some(suffix) = file_name.to_str().and_then(|file_name| file_name.strip_prefix(&prefix)) else {
+            continue;
+        };
+        match suffix.parse::<u64>() {
+            Ok(first_seq) if suffix.len() == 12 && first_seq >= pruned => segments.push((first_seq, file.path())),
+            _ => {}
+        }
+    }
+    segments.sort();
+    Ok(segments)
+}
+
+/// Where the next entry chains from when the live file is empty: the newest segment's last
+/// entry, else the anchor, else the genesis
+fn segment_tip(path: &Path) -> Result<(u64, String)> {
+    if let Some((_, newest)) = retained_segments(path)?.last() {
+        if let Some(last) = read_entries(newest)?.last() {
+            return Ok((last.seq + 1, last.hash.clone()));
+        }
+    }
+    Ok(read_anchor(path)?.map_or((0, GENESIS_HASH.to_string()), |anchor| (anchor.next_seq, anchor.head)))
+}
+
+fn read_entries(path: &Path) -> Result<Vec<ActionEntry>> {
+    let data = match fs::read_to_string(path) {
+        Ok(data) => data,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+    data.lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| serde_json::from_str(line).map_err(|e| anyhow!("Corrupt action log {}: {}", path.display(), e)))
+        .collect()
+}
+
+fn sha256_hex(data: &[u8]) -> String {
+    Sha256::digest(data).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+impl FinternetClient {
+    /// The action log configured through `FinternetConfig::action_log_path`, if any
+    pub fn action_log(&self) -> Option<&ActionLog> {
+        self.action_log.as_deref()
+    }
+
+    /// Raise `ActionCompleted` for `operation`, which the action log subscriber records
+    ///
+    /// `payment_mint` is set for payments, which go through the spending and asset policies.
+    pub(crate) fn log_action<T>(
+        &self,
+        operation: &str,
+        parameters: serde_json::Value,
+        payment_mint: Option<&Pubkey>,
+        outcome: Result<&T, &anyhow::Error>,
+        signature: impl FnOnce(&T) -> String,
+    ) {
+        crate::usage::count_operation(operation);
+        if let Err(e) = outcome {
+            self.emit_policy_violation(operation, e);
+        }
+        // A simulated send passed every policy; it is recorded under its marker, with no error
+        let dry_run = outcome.err().and_then(|e| e.downcast_ref::<DryRunOutcome>());
+        self.emit(|| {
+            let (signature, error) = match (outcome, dry_run) {
+                (Ok(value), _) => (Some(signature(value)).filter(|signature| !signature.is_empty()), None),
+                (Err(_), Some(simulated)) => (Some(simulated.marker()), simulated.simulation_error.clone()),
+                (Err(e), None) => (None, Some(e.to_string())),
+            };
+            let error_code = outcome.err().filter(|_| error.is_some()).map(|e| FinternetError::of(e).code());
+            let policy_decisions = match payment_mint {
+                Some(mint) => self.policy_decisions(mint, outcome.err().filter(|_| dry_run.is_none())),
+                None => Vec::new(),
+            };
+            SdkEvent::ActionCompleted {
+                operation: operation.to_string(),
+                parameters,
+                signature,
+                error,
+                error_code,
+                policy_decisions,
+            }
+        });
+    }
+
+    /// What each configured policy decided; a failure before a policy was consulted records
+    /// no decision for it
+    fn policy_decisions(&self, mint: &Pubkey, error: Option<&anyhow::Error>) -> Vec<PolicyDecision> {
+        let mut decisions = Vec::new();
+        if self.spending.is_some() {
+            let violation = error.and_then(|e| e.downcast_ref::<PolicyViolation>());
+            if error.is_none() || violation.is_some() {
+                decisions.push(PolicyDecision {
+                    policy: "spending_policy".to_string(),
+                    allowed: violation.is_none(),
+                    code: violation.map(|v| v.code().to_string()),
+                });
+            }
+        }
+        if self.asset_policy(mint).is_some() {
+            let violation = error.and_then(|e| e.downcast_ref::<AssetPolicyViolation>());
+            if error.is_none() || violation.is_some() {
+                decisions.push(PolicyDecision {
+                    policy: "asset_policy".to_string(),
+                    allowed: violation.is_none(),
+                    code: violation.map(|v| v.code().to_string()),
+                });
+            }
+        }
+        decisions
+    }
+}
@@ -0,0 +1,231 @@
+use crate::telemetry::readable;
+use crate::{Currency, FinternetClient};
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use solana_account_decoder::UiAccountData;
+use solana_client::rpc_request::TokenAccountsFilter;
+use solana_sdk::pubkey::Pubkey;
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Share of the level a balance must climb back above before a low alert is cleared
+const DEFAULT_HYSTERESIS_BPS: u64 = 500;
+
+/// Alert when `currency` held by the owner drops below `level`
+///
+/// Parses from `sol:0.2`, `usdc:500`, or `<mint>:<amount>`. Amounts are whole units; for
+/// mints other than SOL and USDC the decimals are not known, so give them in base units.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BalanceThreshold {
+    pub currency: Currency,
+    /// Base units
+    pub level: u64,
+    /// A low alert clears only once the balance reaches `level + hysteresis`
+    pub hysteresis: u64,
+}
+
+impl BalanceThreshold {
+    /// Threshold with a hysteresis band of 5% of `level`
+    pub fn new(currency: Currency, level: u64) -> Self {
+        Self {
+            currency,
+            level,
+            hysteresis: (level as u128 * DEFAULT_HYSTERESIS_BPS as u128 / 10_000) as u64,
+        }
+    }
+
+    pub fn with_hysteresis(mut self, hysteresis: u64) -> Self {
+        self.hysteresis = hysteresis;
+        self
+    }
+
+    fn clears_at(&self) -> u64 {
+        self.level.saturating_add(self.hysteresis)
+    }
+}
+
+impl fmt::Display for BalanceThreshold {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} below {}", self.currency.label(), self.currency.format_amount(self.level))
+    }
+}
+
+impl FromStr for BalanceThreshold {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (currency, amount) = s
+            .split_once(':')
+            .ok_or_else(|| anyhow!("Invalid alert '{}'; expected <currency>:<amount>, e.g. sol:0.2", s))?;
+        let currency = if currency.eq_ignore_ascii_case("sol") {
+            Currency::Sol
+        } else if currency.eq_ignore_ascii_case("usdc") {
+            Currency::usdc_devnet()
+        } else {
+            Currency::from_mint(Pubkey::from_str(currency).map_err(|_| anyhow!("Invalid alert currency '{}'", currency))?)
+        };
+        let level = currency.parse_amount(amount).map_err(|e| anyhow!(e))?;
+        Ok(Self::new(currency, level))
+    }
+}
+
+/// Which way a balance crossed its threshold
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertDirection {
+    /// Dropped below the level
+    Below,
+    /// Climbed back above the level plus hysteresis
+    Recovered,
+}
+
+/// A threshold that fired, with what the balance was when it did
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AlertTriggered {
+    pub owner: Pubkey,
+    pub threshold: BalanceThreshold,
+    pub direction: AlertDirection,
+    /// Base units held at `checked_at`
+    pub current: u64,
+    pub checked_at: u64,
+}
+
+impl fmt::Display for AlertTriggered {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let currency = &self.threshold.currency;
+        match self.direction {
+            AlertDirection::Below => write!(
+                f,
+                "{} holds {}, below the {} threshold",
+                readable(&self.owner),
+                currency.format_amount(self.current),
+                currency.format_amount(self.threshold.level)
+            ),
+            AlertDirection::Recovered => write!(
+                f,
+                "{} holds {}, back above the {} threshold",
+                readable(&self.owner),
+                currency.format_amount(self.current),
+                currency.format_amount(self.threshold.level)
+            ),
+        }
+    }
+}
+
+/// Remembers which thresholds are currently breached, so each crossing fires once
+#[derive(Debug, Clone)]
+pub struct AlertMonitor {
+    owner: Pubkey,
+    thresholds: Vec<BalanceThreshold>,
+    breached: Vec<bool>,
+}
+
+impl AlertMonitor {
+    pub fn new(owner: Pubkey, thresholds: Vec<BalanceThreshold>) -> Self {
+        let breached = vec![false; thresholds.len()];
+        Self { owner, thresholds, breached }
+    }
+
+    pub fn thresholds(&self) -> &[BalanceThreshold] {
+        &self.thresholds
+    }
+
+    /// Feed one balance per threshold, in order; returns the crossings since the last call
+    ///
+    /// A balance already below its level on the first call fires `Below`. A breached threshold
+    /// fires `Recovered` only once the balance reaches the level plus its hysteresis, and
+    /// wobbling between the two does not fire again.
+    pub fn observe(&mut self, balances: &[u64], checked_at: u64) -> Vec<AlertTriggered> {
+        let mut fired = Vec::new();
+        for ((threshold, breached), &current) in self.thresholds.iter().zip(self.breached.iter_mut()).zip(balances) {
+            let direction = if !*breached && current < threshold.level {
+                AlertDirection::Below
+            } else if *breached && current >= threshold.clears_at() {
+                AlertDirection::Recovered
+            } else {
+                continue;
+            };
+            *breached = direction == AlertDirection::Below;
+            fired.push(AlertTriggered {
+                owner: self.owner,
+                threshold: threshold.clone(),
+                direction,
+                current,
+                checked_at,
+            });
+        }
+        fired
+    }
+}
+
+impl FinternetClient {
+    /// Thresholds `owner` is below right now, each reported as a `Below` alert
+    pub async fn check_balance_alerts(
+        &self,
+        owner: &Pubkey,
+        thresholds: &[BalanceThreshold],
+    ) -> Result<Vec<AlertTriggered>> {
+        let balances = self.threshold_balances(owner, thresholds).await?;
+        Ok(AlertMonitor::new(*owner, thresholds.to_vec()).observe(&balances, unix_now()))
+    }
+
+    /// Poll `owner` every `interval` and call `handler` whenever a threshold crosses either way
+    ///
+    /// Runs until the task is dropped or the client shuts down. Failed polls are logged and retried on the next tick
+    /// rather than treated as a zero balance.
+    pub async fn watch_balance_alerts(
+        &self,
+        owner: &Pubkey,
+        thresholds: Vec<BalanceThreshold>,
+        interval: Duration,
+        mut handler: impl FnMut(AlertTriggered),
+    ) {
+        let mut monitor = AlertMonitor::new(*owner, thresholds);
+        let shutdown = self.tasks.signal();
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            tokio::select! {
+                _ = ticker.tick() => {}
+                _ = shutdown.cancelled() => return,
+            }
+            match self.threshold_balances(owner, monitor.thresholds()).await {
+                Ok(balances) => monitor.observe(&balances, unix_now()).into_iter().for_each(&mut handler),
+                Err(e) => tracing::warn!("Balance alert poll for {} failed: {}", readable(owner), e),
+            }
+        }
+    }
+
+    /// Current balance for each threshold's currency, in base units
+    async fn threshold_balances(&self, owner: &Pubkey, thresholds: &[BalanceThreshold]) -> Result<Vec<u64>> {
+        let mut balances = Vec::with_capacity(thresholds.len());
+        for threshold in thresholds {
+            balances.push(match threshold.currency.mint() {
+                None => self.client.get_balance(owner)?,
+                Some(mint) => self.mint_balance(owner, &mint)?,
+            });
+        }
+        Ok(balances)
+    }
+
+    /// Sum of every `mint` account `owner` holds, under either token program; 0 if there are none
+    fn mint_balance(&self, owner: &Pubkey, mint: &Pubkey) -> Result<u64> {
+        let accounts = self.client.get_token_accounts_by_owner(owner, TokenAccountsFilter::Mint(*mint))?;
+        let mut total = 0u64;
+        for account in accounts {
+            let UiAccountData::Json(parsed) = account.account.data else {
+                return Err(anyhow!("Token account {} was not returned as parsed JSON", account.pubkey));
+            };
+            let amount = parsed.parsed["info"]["tokenAmount"]["amount"]
+                .as_str()
+                .and_then(|a| a.parse::<u64>().ok())
+                .ok_or_else(|| anyhow!("Token account {} has no readable amount", account.pubkey))?;
+            total = total.saturating_add(amount);
+        }
+        Ok(total)
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
@@ -0,0 +1,438 @@
+//! Locale-aware rendering of token amounts for people to read
+//!
+//! Amounts are always held as exact base units; an `AmountFormatter` only decides how they are
+//! written out: the decimal and thousands separators, whether the unit is a symbol, a code, or
+//! left off, and how many decimal places show. Rounding happens in the rendered string alone.
+//! The default formatter writes what `Currency::format_amount` always has, e.g. `1234.50 USDC`,
+//! so output only changes where a locale is chosen. Machine formats (OFX, QIF, CSV columns of
+//! base units) never go through a locale.
+
+use crate::{Currency, FinternetClient, FinternetConfig};
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// How numbers are written in one locale
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NumberLocale {
+    /// BCP 47 tag, e.g. `de-DE`; empty for the plain format
+    pub tag: String,
+    pub decimal_separator: char,
+    /// Between each group of three integer digits; `None` writes no grouping
+    pub thousands_separator: Option<char>,
+    /// Whether a currency symbol goes before the number (`$1.00`) or after it (`1,00 $`)
+    pub symbol_first: bool,
+}
+
+impl NumberLocale {
+    /// `1234.5`, no grouping: what the SDK wrote before locales existed
+    pub fn plain() -> Self {
+        Self::new("", '.', None, true)
+    }
+
+    pub fn en_us() -> Self {
+        Self::new("en-US", '.', Some(','), true)
+    }
+
+    pub fn en_gb() -> Self {
+        Self::new("en-GB", '.', Some(','), true)
+    }
+
+    pub fn de_de() -> Self {
+        Self::new("de-DE", ',', Some('.'), false)
+    }
+
+    pub fn fr_fr() -> Self {
+        // French groups with a narrow no-break space
+        Self::new("fr-FR", ',', Some('\u{202f}'), false)
+    }
+
+    fn new(tag: &str, decimal_separator: char, thousands_separator: Option<char>, symbol_first: bool) -> Self {
+        Self {
+            tag: tag.to_string(),
+            decimal_separator,
+            thousands_separator,
+            symbol_first,
+        }
+    }
+
+    pub const ACCEPTED: [&'static str; 5] = ["plain", "en-US", "en-GB", "de-DE", "fr-FR"];
+}
+
+impl Default for NumberLocale {
+    fn default() -> Self {
+        Self::plain()
+    }
+}
+
+impl FromStr for NumberLocale {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().replace('_', "-").to_ascii_lowercase().as_str() {
+            "plain" | "" => Ok(Self::plain()),
+            "en-us" | "en" => Ok(Self::en_us()),
+            "en-gb" => Ok(Self::en_gb()),
+            "de-de" | "de" => Ok(Self::de_de()),
+            "fr-fr" | "fr" => Ok(Self::fr_fr()),
+            _ => Err(anyhow!("Unknown locale '{}'. Accepted values: {}", s, Self::ACCEPTED.join(", "))),
+        }
+    }
+}
+
+/// How an amount's unit is shown
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CurrencyDisplay {
+    /// `12.50 USDC`
+    #[default]
+    Code,
+    /// `$12.50`, placed as the locale does; currencies without a symbol fall back to their code
+    Symbol,
+    /// `12.50`
+    None,
+}
+
+impl FromStr for CurrencyDisplay {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "code" => Ok(Self::Code),
+            "symbol" => Ok(Self::Symbol),
+            "none" => Ok(Self::None),
+            _ => Err(anyhow!("Unknown currency display '{}'; expected code, symbol, or none", s)),
+        }
+    }
+}
+
+/// How many decimal places an amount shows, out of its mint's decimals
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Precision {
+    /// Every significant place, and at least two; never rounded
+    #[default]
+    Trimmed,
+    /// Every one of the mint's decimal places
+    Full,
+    /// Exactly this many places, rounded half away from zero
+    Fixed(u8),
+}
+
+impl FromStr for Precision {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "trimmed" => Ok(Self::Trimmed),
+            "full" => Ok(Self::Full),
+            places => places
+                .parse()
+                .map(Self::Fixed)
+                .map_err(|_| anyhow!("Unknown precision '{}'; expected trimmed, full, or a number of places", s)),
+        }
+    }
+}
+
+/// Renders amounts of any currency for one locale; see the module documentation
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AmountFormatter {
+    pub locale: NumberLocale,
+    pub display: CurrencyDisplay,
+    pub precision: Precision,
+}
+
+impl AmountFormatter {
+    pub fn new(locale: NumberLocale) -> Self {
+        Self {
+            locale,
+            ..Self::default()
+        }
+    }
+
+    pub fn with_display(mut self, display: CurrencyDisplay) -> Self {
+        self.display = display;
+        self
+    }
+
+    pub fn with_precision(mut self, precision: Precision) -> Self {
+        self.precision = precision;
+        self
+    }
+
+    /// `raw` base units of `currency` with its unit, e.g. `1.234,56 USDC`
+    pub fn format(&self, currency: &Currency, raw: u64) -> String {
+        self.format_signed(currency, raw as i128)
+    }
+
+    /// Like `format`, for signed deltas and running balances
+    pub fn format_signed(&self, currency: &Currency, raw: i128) -> String {
+        let number = self.number(currency.decimals(), raw.unsigned_abs());
+        let sign = sign(raw, &number);
+        match (self.display, symbol(currency)) {
+            (CurrencyDisplay::None, _) => format!("{}{}", sign, number),
+            (CurrencyDisplay::Symbol, Some(symbol)) if self.locale.symbol_first => format!("{}{}{}", sign, symbol, number),
+            (CurrencyDisplay::Symbol, Some(symbol)) => format!("{}{} {}", sign, number, symbol),
+            (CurrencyDisplay::Code, _) | (CurrencyDisplay::Symbol, None) => {
+                format!("{}{} {}", sign, number, currency.label())
+            }
+        }
+    }
+
+    /// `raw` base units as a number alone, for table columns headed with the unit
+    pub fn format_units(&self, currency: &Currency, raw: i128) -> String {
+        let number = self.number(currency.decimals(), raw.unsigned_abs());
+        format!("{}{}", sign(raw, &number), number)
+    }
+
+    /// A US dollar valuation, which is an estimate rather than base units, to the cent
+    ///
+    /// Dollars always show as `$` unless the unit is left off.
+    pub fn format_usd(&self, usd: f64) -> String {
+        let cents = (usd.abs() * 100.0).round() as u128;
+        let number = self.number_with(2, cents, Precision::Fixed(2));
+        let sign = if usd < 0.0 && cents > 0 { "-" } else { "" };
+        match self.display {
+            CurrencyDisplay::None => format!("{}{}", sign, number),
+            _ if self.locale.symbol_first => format!("{}${}", sign, number),
+            _ => format!("{}{} $", sign, number),
+        }
+    }
+
+    fn number(&self, decimals: u8, raw: u128) -> String {
+        self.number_with(decimals, raw, self.precision)
+    }
+
+    fn number_with(&self, decimals: u8, raw: u128, precision: Precision) -> String {
+        let decimals = decimals as u32;
+        let (integer, mut fraction) = match precision {
+            Precision::Fixed(places) if (places as u32) < decimals => {
+                // Past 10^38 the divisor outgrows every amount, which then rounds to zero
+                let rounded = match 10u128.checked_pow(decimals - places as u32) {
+                    Some(divisor) => (raw + divisor / 2) / divisor,
+                    None => 0,
+                };
+                split(rounded, places as u32)
+            }
+            Precision::Fixed(places) => {
+                let (integer, mut fraction) = split(raw, decimals);
+                fraction.extend(std::iter::repeat_n('0', places as usize - decimals as usize));
+                (integer, fraction)
+            }
+            Precision::Trimmed | Precision::Full => split(raw, decimals),
+        };
+        if precision == Precision::Trimmed {
+            while fraction.len() > 2 && fraction.ends_with('0') {
+                fraction.pop();
+            }
+        }
+
+        let integer = self.group(integer);
+        if fraction.is_empty() {
+            integer
+        } else {
+            format!("{}{}{}", integer, self.locale.decimal_separator, fraction)
+        }
+    }
+
+    fn group(&self, integer: u128) -> String {
+        let digits = integer.to_string();
+        let Some(separator) = self.locale.thousands_separator else {
+            return digits;
+        };
+        let mut grouped = String::with_capacity(digits.len() + digits.len() / 3);
+        for (i, digit) in digits.chars().enumerate() {
+            if i > 0 && (digits.len() - i).is_multiple_of(3) {
+                grouped.push(separator);
+            }
+            grouped.push(digit);
+        }
+        grouped
+    }
+}
+
+impl fmt::Display for AmountFormatter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let tag = if self.locale.tag.is_empty() { "plain" } else { &self.locale.tag };
+        write!(f, "{} ({:?}, {:?})", tag, self.display, self.precision)
+    }
+}
+
+impl FinternetConfig {
+    /// Render amounts in statements, API display fields, and CLI output with `formatter`
+    pub fn with_amount_format(mut self, formatter: AmountFormatter) -> Self {
+        self.amount_format = formatter;
+        self
+    }
+}
+
+impl FinternetClient {
+    /// How this client renders amounts for people to read
+    pub fn amount_format(&self) -> &AmountFormatter {
+        &self.config.amount_format
+    }
+}
+
+/// `-` for a negative amount, unless rounding left nothing of it to show
+fn sign(raw: i128, number: &str) -> &'static str {
+    if raw < 0 && number.chars().any(|c| c.is_ascii_digit() && c != '0') {
+        "-"
+    } else {
+        ""
+    }
+}
+
+/// `raw` base units as whole units and the zero-padded fraction digits
+///
+/// A mint may claim up to 255 decimals; beyond 38 no amount reaches one whole unit.
+fn split(raw: u128, decimals: u32) -> (u128, String) {
+    match 10u128.checked_pow(decimals) {
+        Some(scale) => (raw / scale, pad(raw % scale, decimals as usize)),
+        None => (0, pad(raw, decimals as usize)),
+    }
+}
+
+/// `value` zero-padded to `width` digits; empty when `width` is 0
+fn pad(value: u128, width: usize) -> String {
+    if width == 0 {
+        String::new()
+    } else {
+        format!("{:0width$}", value, width = width)
+    }
+}
+
+/// The symbol `currency` is written with, for the ones that have one
+fn symbol(currency: &Currency) -> Option<&'static str> {
+    match currency {
+        Currency::Sol => Some("◎"),
+        Currency::Spl { symbol: Some(symbol), .. } if symbol == "USDC" => Some("$"),
+        Currency::Spl { .. } => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::pubkey::Pubkey;
+    use std::fmt::Write as _;
+    use std::path::Path;
+
+    /// A bridged token with Ethereum's 18 decimals
+    fn high_decimals() -> Currency {
+        Currency::Spl { mint: Pubkey::new_from_array([7; 32]), decimals: 18, symbol: Some("WETH".to_string()) }
+    }
+
+    fn unnamed() -> Currency {
+        Currency::Spl { mint: Pubkey::new_from_array([9; 32]), decimals: 0, symbol: None }
+    }
+
+    /// Every display and precision of a fixed set of amounts, one line each
+    fn render(locale: NumberLocale) -> String {
+        let amounts: [(Currency, i128); 12] = [
+            (Currency::usdc_devnet(), 0),
+            (Currency::usdc_devnet(), 1),
+            (Currency::usdc_devnet(), 1_234_560_000),
+            (Currency::usdc_devnet(), 999_995),
+            (Currency::usdc_devnet(), -1_000_000_000_000),
+            (Currency::Sol, 5_000),
+            (Currency::Sol, 1_500_000_000),
+            (Currency::Sol, -123_456_789_012),
+            (high_decimals(), 1_000_000_000_000_000_001),
+            (high_decimals(), 18_446_744_073_709_551_615),
+            (high_decimals(), -5_000_000_000_000_000),
+            (unnamed(), 1_234_567),
+        ];
+        let mut out = String::new();
+        for display in [CurrencyDisplay::Code, CurrencyDisplay::Symbol, CurrencyDisplay::None] {
+            for precision in [Precision::Trimmed, Precision::Full, Precision::Fixed(2), Precision::Fixed(0)] {
+                let formatter = AmountFormatter::new(locale.clone()).with_display(display).with_precision(precision);
+                let _ = writeln!(out, "## {}", formatter);
+                for (currency, raw) in &amounts {
+                    let _ = writeln!(out, "{} {}: {}", raw, currency.label(), formatter.format_signed(currency, *raw));
+                }
+                let _ = writeln!(out, "usd 1234567.891: {}", formatter.format_usd(1_234_567.891));
+                let _ = writeln!(out, "usd -0.004: {}", formatter.format_usd(-0.004));
+            }
+        }
+        out
+    }
+
+    /// Compare with the snapshot, or rewrite it when `FINTERNET_BLESS` is set
+    fn assert_snapshot(name: &str, actual: &str) {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/amounts").join(name);
+        if std::env::var_os("FINTERNET_BLESS").is_some() {
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(&path, actual).unwrap();
+        }
+        let expected = std::fs::read_to_string(&path).unwrap_or_else(|e| panic!("{}: {}", path.display(), e));
+        assert_eq!(actual, expected, "{} changed; rerun with FINTERNET_BLESS=1 if that was intended", name);
+    }
+
+    #[test]
+    fn each_locale_matches_its_snapshot() {
+        for tag in NumberLocale::ACCEPTED {
+            let locale: NumberLocale = tag.parse().unwrap();
+            assert_snapshot(&format!("{}.txt", tag), &render(locale));
+        }
+    }
+
+    #[test]
+    fn the_default_is_what_currency_always_wrote() {
+        let formatter = AmountFormatter::default();
+        assert_eq!(formatter.format(&Currency::usdc_devnet(), 1_234_500_000), "1234.50 USDC");
+        assert_eq!(formatter.format(&Currency::Sol, 5_000), "0.000005 SOL");
+        assert_eq!(formatter.format(&Currency::Sol, 5_000), Currency::Sol.format_amount(5_000));
+        assert_eq!(formatter.format_units(&Currency::Sol, -1_000_000_000), "-1.00");
+    }
+
+    #[test]
+    fn european_separators_and_symbol_placement() {
+        let de = AmountFormatter::new(NumberLocale::de_de()).with_display(CurrencyDisplay::Symbol);
+        assert_eq!(de.format(&Currency::usdc_devnet(), 1_234_560_000), "1.234,56 $");
+        assert_eq!(de.format(&Currency::Sol, 1_500_000_000), "1,50 ◎");
+        let us = AmountFormatter::new(NumberLocale::en_us()).with_display(CurrencyDisplay::Symbol);
+        assert_eq!(us.format_signed(&Currency::usdc_devnet(), -1_234_560_000), "-$1,234.56");
+        let fr = AmountFormatter::new(NumberLocale::fr_fr());
+        assert_eq!(fr.format(&Currency::usdc_devnet(), 1_234_560_000), "1\u{202f}234,56 USDC");
+    }
+
+    #[test]
+    fn rounding_is_display_only_and_half_away_from_zero() {
+        let two = AmountFormatter::default().with_precision(Precision::Fixed(2));
+        let usdc = Currency::usdc_devnet();
+        assert_eq!(two.format_units(&usdc, 999_995), "1.00");
+        assert_eq!(two.format_units(&usdc, -999_995), "-1.00");
+        assert_eq!(two.format_units(&usdc, 994_999), "0.99");
+        // A debit too small to show is not written as minus zero
+        assert_eq!(two.format_signed(&usdc, -4_999), "0.00 USDC");
+        assert_eq!(two.format_signed(&usdc, -5_000), "-0.01 USDC");
+        // Trimmed never rounds, whatever the mint's decimals
+        let trimmed = AmountFormatter::default();
+        assert_eq!(trimmed.format_units(&high_decimals(), 1_000_000_000_000_000_001), "1.000000000000000001");
+        assert_eq!(trimmed.format_units(&usdc, 1), "0.000001");
+    }
+
+    #[test]
+    fn absurd_decimals_do_not_overflow() {
+        let currency = Currency::Spl { mint: Pubkey::new_from_array([3; 32]), decimals: u8::MAX, symbol: None };
+        let full = AmountFormatter::default().with_precision(Precision::Full);
+        let rendered = full.format_units(&currency, i128::MIN);
+        assert_eq!(rendered.len(), "-0.".len() + u8::MAX as usize);
+        assert!(rendered.ends_with(&(i128::MIN.unsigned_abs()).to_string()));
+        let two = AmountFormatter::default().with_precision(Precision::Fixed(2));
+        assert_eq!(two.format_units(&currency, i128::MAX), "0.00");
+        assert_eq!(AmountFormatter::default().format_units(&currency, 0), "0.00");
+    }
+
+    #[test]
+    fn settings_parse_from_their_names() {
+        assert_eq!("de_DE".parse::<NumberLocale>().unwrap(), NumberLocale::de_de());
+        assert_eq!("EN".parse::<NumberLocale>().unwrap(), NumberLocale::en_us());
+        assert!("ja-JP".parse::<NumberLocale>().is_err());
+        assert_eq!("Symbol".parse::<CurrencyDisplay>().unwrap(), CurrencyDisplay::Symbol);
+        assert_eq!("4".parse::<Precision>().unwrap(), Precision::Fixed(4));
+        assert_eq!("full".parse::<Precision>().unwrap(), Precision::Full);
+        assert!("-1".parse::<Precision>().is_err());
+    }
+}
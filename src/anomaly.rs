@@ -0,0 +1,637 @@
+//! Spotting unusual outgoing activity, such as a compromised script draining small amounts
+//!
+//! With `FinternetConfig::anomaly_detection` set, every payment the spending policy lets
+//! through is compared against an `ActivityBaseline` of the wallet's recent sends: who it has
+//! paid before, how many transfers it makes an hour, and how much of each mint leaves it a day.
+//! Each rule the payment trips raises `SdkEvent::AnomalyDetected`. In `Observe` mode the payment
+//! goes ahead; in `Enforce` mode it is refused as `PolicyViolation::Anomaly` and does not count
+//! towards the baseline. The baseline is kept in `anomaly-baseline.json` next to the outbox file,
+//! so restarts keep it, and `reset_anomaly_baseline` starts it again from nothing.
+
+use crate::events::SdkEvent;
+use crate::policy::{PolicyViolation, SpendRequest};
+use crate::{FinternetClient, FinternetConfig};
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use solana_sdk::pubkey::Pubkey;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const HOUR_SECS: u64 = 60 * 60;
+const DAY_SECS: u64 = 24 * HOUR_SECS;
+/// Days of outflow the daily average is taken over
+const TRAILING_DAYS: u64 = 7;
+
+/// What happens to a payment that trips a rule
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AnomalyMode {
+    /// Raise the event and send anyway
+    #[default]
+    Observe,
+    /// Raise the event and refuse the payment through the spending policy
+    Enforce,
+}
+
+/// Which patterns count as unusual; rules left unset are not checked
+#[derive(Debug, Clone, Default)]
+pub struct AnomalyRules {
+    /// Paying a recipient never paid before more than this many base units of the mint
+    pub new_recipient_thresholds: HashMap<Pubkey, u64>,
+    /// More transfers than this in any rolling hour, every mint together
+    pub max_sends_per_hour: Option<u32>,
+    /// The last 24 hours' outflow of a mint above its trailing 7-day daily average times this
+    pub outflow_multiplier: Option<f64>,
+    pub mode: AnomalyMode,
+    /// Where the baseline is kept instead of next to the outbox file
+    pub baseline_path: Option<PathBuf>,
+}
+
+impl AnomalyRules {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn flag_new_recipients_above(mut self, mint: Pubkey, amount: u64) -> Self {
+        self.new_recipient_thresholds.insert(mint, amount);
+        self
+    }
+
+    pub fn with_max_sends_per_hour(mut self, sends: u32) -> Self {
+        self.max_sends_per_hour = Some(sends);
+        self
+    }
+
+    pub fn with_outflow_multiplier(mut self, multiplier: f64) -> Self {
+        self.outflow_multiplier = Some(multiplier);
+        self
+    }
+
+    /// Refuse payments that trip a rule instead of only reporting them
+    pub fn enforce(mut self) -> Self {
+        self.mode = AnomalyMode::Enforce;
+        self
+    }
+
+    pub fn with_baseline_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.baseline_path = Some(path.into());
+        self
+    }
+}
+
+/// One rule a payment tripped
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Anomaly {
+    /// `recipient` was never paid before and is sent more than `threshold`
+    NewRecipient { recipient: Pubkey, mint: Pubkey, amount: u64, threshold: u64 },
+    /// `sends` transfers in the last hour, counting this payment's, against a limit of `limit`
+    SendRate { sends: u32, limit: u32 },
+    /// `outflow` of `mint` in the last 24 hours, counting this payment's, is above `limit`:
+    /// `daily_average` times the configured multiplier
+    OutflowSpike { mint: Pubkey, outflow: u64, daily_average: u64, limit: u64 },
+}
+
+impl Anomaly {
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::NewRecipient { .. } => "new_recipient",
+            Self::SendRate { .. } => "send_rate",
+            Self::OutflowSpike { .. } => "outflow_spike",
+        }
+    }
+}
+
+impl fmt::Display for Anomaly {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NewRecipient { recipient, mint, amount, threshold } => write!(
+                f,
+                "{} of mint {} to {}, never paid before, is above the new-recipient threshold of {}",
+                amount, mint, recipient, threshold
+            ),
+            Self::SendRate { sends, limit } => {
+                write!(f, "{} transfers in the last hour is above the limit of {}", sends, limit)
+            }
+            Self::OutflowSpike { mint, outflow, daily_average, limit } => write!(
+                f,
+                "{} of mint {} sent in the last 24 hours is above {} (a trailing daily average of {})",
+                outflow, mint, limit, daily_average
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Anomaly {}
+
+/// One transfer counted in the baseline
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct SendRecord {
+    to: Pubkey,
+    mint: Pubkey,
+    amount: u64,
+    at: u64,
+}
+
+/// A wallet's recent outgoing activity, as the rules compare payments against it
+///
+/// `evaluate` and `record` take the time explicitly, so a scripted sequence of sends can be
+/// replayed against a baseline without a client.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ActivityBaseline {
+    /// Unix time the baseline was started or last reset
+    pub started_at: u64,
+    /// Transfers of the last 8 days
+    sends: Vec<SendRecord>,
+    /// When each recipient, by address, was first paid
+    recipients: BTreeMap<String, u64>,
+}
+
+impl ActivityBaseline {
+    pub fn new(started_at: u64) -> Self {
+        Self {
+            started_at,
+            ..Self::default()
+        }
+    }
+
+    pub fn knows_recipient(&self, recipient: &Pubkey) -> bool {
+        self.recipients.contains_key(&recipient.to_string())
+    }
+
+    /// Every rule `requests`, sent together at `now`, trip, in the order new recipient, send
+    /// rate, outflow
+    pub fn evaluate(&self, rules: &AnomalyRules, requests: &[SpendRequest], now: u64) -> Vec<Anomaly> {
+        let mut anomalies = Vec::new();
+        for request in requests {
+            let Some(threshold) = rules.new_recipient_thresholds.get(&request.mint) else { continue };
+            if request.amount > *threshold && !self.knows_recipient(&request.to) {
+                anomalies.push(Anomaly::NewRecipient {
+                    recipient: request.to,
+                    mint: request.mint,
+                    amount: request.amount,
+                    threshold: *threshold,
+                });
+            }
+        }
+
+        if let Some(limit) = rules.max_sends_per_hour {
+            let recent = self.sends.iter().filter(|send| send.at + HOUR_SECS > now).count();
+            let sends = u32::try_from(recent + requests.len()).unwrap_or(u32::MAX);
+            if sends > limit {
+                anomalies.push(Anomaly::SendRate { sends, limit });
+            }
+        }
+
+        // Whole days of history before the current 24 hours, up to a week
+        let history_days = (now.saturating_sub(DAY_SECS).saturating_sub(self.started_at) / DAY_SECS).min(TRAILING_DAYS);
+        if let Some(multiplier) = rules.outflow_multiplier.filter(|_| history_days > 0) {
+            let mut requested: BTreeMap<Pubkey, u64> = BTreeMap::new();
+            for request in requests {
+                let total = requested.entry(request.mint).or_default();
+                *total = total.saturating_add(request.amount);
+            }
+            for (mint, requested) in requested {
+                let day_start = now.saturating_sub(DAY_SECS);
+                let trailing_start = day_start.saturating_sub(history_days * DAY_SECS);
+                let (mut outflow, mut trailing) = (requested, 0u64);
+                for send in self.sends.iter().filter(|send| send.mint == mint) {
+                    if send.at > day_start {
+                        outflow = outflow.saturating_add(send.amount);
+                    } else if send.at > trailing_start {
+                        trailing = trailing.saturating_add(send.amount);
+                    }
+                }
+                // A mint with no outflow to compare against has no baseline yet
+                let daily_average = trailing / history_days;
+                if daily_average == 0 {
+                    continue;
+                }
+                let limit = (daily_average as f64 * multiplier) as u64;
+                if outflow > limit {
+                    anomalies.push(Anomaly::OutflowSpike { mint, outflow, daily_average, limit });
+                }
+            }
+        }
+        anomalies
+    }
+
+    /// Count `requests` as sent at `now`, forgetting transfers too old for any rule
+    pub fn record(&mut self, requests: &[SpendRequest], now: u64) {
+        self.sends.retain(|send| send.at + (TRAILING_DAYS + 1) * DAY_SECS > now);
+        for request in requests {
+            self.sends.push(SendRecord {
+                to: request.to,
+                mint: request.mint,
+                amount: request.amount,
+                at: now,
+            });
+            self.recipients.entry(request.to.to_string()).or_insert(now);
+        }
+    }
+
+    /// Undo a `record` made at `now` for transfers that never moved funds
+    pub fn forget(&mut self, requests: &[SpendRequest], now: u64) {
+        for request in requests {
+            if let Some(index) = self.sends.iter().position(|send| {
+                send.at == now && send.to == request.to && send.mint == request.mint && send.amount == request.amount
+            }) {
+                self.sends.remove(index);
+            }
+            let key = request.to.to_string();
+            let still_paid = self.sends.iter().any(|send| send.to == request.to);
+            if self.recipients.get(&key) == Some(&now) && !still_paid {
+                self.recipients.remove(&key);
+            }
+        }
+    }
+}
+
+/// The rules plus the baseline they compare against, loaded on first use
+pub(crate) struct AnomalyDetector {
+    rules: AnomalyRules,
+    /// JSON file of the baseline; in memory only when `None`
+    path: Option<PathBuf>,
+    baseline: Mutex<Option<ActivityBaseline>>,
+}
+
+impl AnomalyDetector {
+    pub(crate) fn new(rules: AnomalyRules, outbox_path: Option<&Path>) -> Self {
+        let path = rules
+            .baseline_path
+            .clone()
+            .or_else(|| outbox_path.map(|outbox| outbox.with_file_name("anomaly-baseline.json")));
+        if path.is_none() {
+            tracing::warn!("No outbox_path or baseline_path configured; the anomaly baseline will reset on restart");
+        }
+        Self {
+            rules,
+            path,
+            baseline: Mutex::new(None),
+        }
+    }
+
+    /// Run `f` on the baseline, reading it from disk the first time, and save what it changed
+    fn with_baseline<T>(&self, now: u64, f: impl FnOnce(&mut ActivityBaseline) -> (T, bool)) -> Result<T> {
+        let mut slot = self.baseline.lock().map_err(|_| anyhow!("Anomaly baseline lock poisoned"))?;
+        if slot.is_none() {
+            *slot = Some(self.load()?.unwrap_or_else(|| ActivityBaseline::new(now)));
+        }
+        let Some(baseline) = slot.as_mut() else {
+            return Err(anyhow!("Anomaly baseline was not loaded"));
+        };
+        let (value, changed) = f(baseline);
+        if changed {
+            self.persist(baseline)?;
+        }
+        Ok(value)
+    }
+
+    /// Evaluate `requests` and, unless enforcement refuses them, count them in the baseline
+    fn observe(&self, requests: &[SpendRequest], now: u64) -> Result<Vec<Anomaly>> {
+        self.with_baseline(now, |baseline| {
+            let anomalies = baseline.evaluate(&self.rules, requests, now);
+            if self.rules.mode == AnomalyMode::Enforce && !anomalies.is_empty() {
+                return (anomalies, false);
+            }
+            baseline.record(requests, now);
+            (anomalies, true)
+        })
+    }
+
+    fn forget(&self, requests: &[SpendRequest], now: u64) -> Result<()> {
+        self.with_baseline(now, |baseline| (baseline.forget(requests, now), true))
+    }
+
+    fn reset(&self, now: u64) -> Result<()> {
+        self.with_baseline(now, |baseline| {
+            *baseline = ActivityBaseline::new(now);
+            ((), true)
+        })
+    }
+
+    fn load(&self) -> Result<Option<ActivityBaseline>> {
+        let Some(path) = self.path.as_deref().filter(|path| path.exists()) else {
+            return Ok(None);
+        };
+        let data = fs::read_to_string(path)?;
+        if data.trim().is_empty() {
+            return Ok(None);
+        }
+        serde_json::from_str(&data)
+            .map(Some)
+            .map_err(|e| anyhow!("Corrupt anomaly baseline {}: {}", path.display(), e))
+    }
+
+    fn persist(&self, baseline: &ActivityBaseline) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if let Some(parent) = path.parent() {
+            if !parent.as_os_str().is_empty() {
+                fs::create_dir_all(parent)?;
+            }
+        }
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, serde_json::to_string_pretty(baseline)?)?;
+        fs::rename(&tmp, path)?;
+        Ok(())
+    }
+}
+
+impl FinternetConfig {
+    /// Compare every payment against the wallet's recent activity; see `anomaly`
+    pub fn with_anomaly_detection(mut self, rules: AnomalyRules) -> Self {
+        self.anomaly_detection = Some(rules);
+        self
+    }
+}
+
+impl FinternetClient {
+    /// Start the anomaly baseline again from nothing, e.g. after a deliberate change in how the
+    /// wallet is used; a no-op without anomaly detection
+    pub fn reset_anomaly_baseline(&self) -> Result<()> {
+        match &self.anomaly {
+            Some(detector) => detector.reset(SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs()),
+            None => Ok(()),
+        }
+    }
+
+    /// Raise an event for each rule `requests` trip; in enforce mode, the first one as an error
+    pub(crate) fn check_anomalies(&self, requests: &[SpendRequest], now: u64) -> Result<()> {
+        let Some(detector) = &self.anomaly else {
+            return Ok(());
+        };
+        let anomalies = detector.observe(requests, now)?;
+        let enforced = detector.rules.mode == AnomalyMode::Enforce;
+        for anomaly in &anomalies {
+            tracing::warn!("Anomalous payment: {}", anomaly);
+            self.emit(|| SdkEvent::AnomalyDetected {
+                anomaly: anomaly.clone(),
+                enforced,
+            });
+        }
+        match anomalies.into_iter().next() {
+            Some(anomaly) if enforced => Err(PolicyViolation::Anomaly(anomaly).into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Take back transfers counted by `check_anomalies` that never moved funds
+    pub(crate) fn forget_anomaly_sends(&self, requests: &[SpendRequest], now: u64) {
+        if let Some(detector) = &self.anomaly {
+            if let Err(e) = detector.forget(requests, now) {
+                tracing::warn!("Failed to update the anomaly baseline: {}", e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_cluster::{Landing, MockCluster, TempDir};
+    use solana_sdk::instruction::InstructionError;
+    use solana_sdk::signature::Keypair;
+    use solana_sdk::signer::Signer;
+    use solana_sdk::transaction::TransactionError;
+    use std::sync::Arc;
+
+    const STARTED: u64 = 1_700_000_000;
+    /// 1 USDC in base units
+    const USDC: u64 = 1_000_000;
+
+    fn rules(mint: Pubkey) -> AnomalyRules {
+        AnomalyRules::new()
+            .flag_new_recipients_above(mint, 100 * USDC)
+            .with_max_sends_per_hour(5)
+            .with_outflow_multiplier(4.0)
+    }
+
+    /// Evaluate and record each `(at, to, amount)` send in turn, as observe mode does, returning
+    /// the codes of the rules each one tripped
+    fn replay(
+        baseline: &mut ActivityBaseline,
+        rules: &AnomalyRules,
+        mint: Pubkey,
+        sends: &[(u64, Pubkey, u64)],
+    ) -> Vec<Vec<&'static str>> {
+        sends
+            .iter()
+            .map(|(at, to, amount)| {
+                let request = [SpendRequest { to: *to, mint, amount: *amount }];
+                let anomalies = baseline.evaluate(rules, &request, *at);
+                baseline.record(&request, *at);
+                anomalies.iter().map(Anomaly::code).collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn a_scripted_week_then_a_drain_trips_exactly_the_expected_rules() {
+        let mint = Pubkey::new_unique();
+        let rules = rules(mint);
+        let (supplier, payroll, drain, attacker) =
+            (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let mut baseline = ActivityBaseline::new(STARTED);
+
+        // A week of 50 USDC a day to one supplier sets a daily average of 50, so a limit of 200
+        let week: Vec<(u64, Pubkey, u64)> = (0..8).map(|day| (STARTED + day * DAY_SECS, supplier, 50 * USDC)).collect();
+        assert!(replay(&mut baseline, &rules, mint, &week).iter().all(Vec::is_empty));
+
+        let t = STARTED + 8 * DAY_SECS + HOUR_SECS;
+        let script = [
+            (t, supplier, 60 * USDC),
+            // New, but under the threshold
+            (t + 60, drain, 5 * USDC),
+            (t + 120, payroll, 120 * USDC),
+            (t + 180, drain, USDC),
+            (t + 240, drain, USDC),
+            // The sixth send inside an hour
+            (t + 300, drain, USDC),
+            // An hour later the rate has cleared, and 189 USDC has left today
+            (t + 2 * HOUR_SECS, drain, USDC),
+            (t + 2 * HOUR_SECS + 60, supplier, 20 * USDC),
+            (t + 2 * HOUR_SECS + 120, attacker, 300 * USDC),
+        ];
+        assert_eq!(
+            replay(&mut baseline, &rules, mint, &script),
+            [
+                vec![],
+                vec![],
+                vec!["new_recipient"],
+                vec![],
+                vec![],
+                vec!["send_rate"],
+                vec![],
+                vec!["outflow_spike"],
+                vec!["new_recipient", "outflow_spike"],
+            ]
+        );
+    }
+
+    #[test]
+    fn each_anomaly_reports_the_numbers_that_tripped_it() {
+        let mint = Pubkey::new_unique();
+        let rules = rules(mint);
+        let known = Pubkey::new_unique();
+        let mut baseline = ActivityBaseline::new(STARTED);
+        for day in 0..8 {
+            baseline.record(&[SpendRequest { to: known, mint, amount: 50 * USDC }], STARTED + day * DAY_SECS);
+        }
+        let now = STARTED + 8 * DAY_SECS + HOUR_SECS;
+        let stranger = Pubkey::new_unique();
+        let requests: Vec<SpendRequest> = (0..6).map(|_| SpendRequest { to: stranger, mint, amount: 101 * USDC }).collect();
+
+        let anomalies = baseline.evaluate(&rules, &requests[..1], now);
+        assert_eq!(
+            anomalies,
+            [Anomaly::NewRecipient { recipient: stranger, mint, amount: 101 * USDC, threshold: 100 * USDC }]
+        );
+        let anomalies = baseline.evaluate(&rules, &requests, now);
+        assert!(anomalies.contains(&Anomaly::SendRate { sends: 6, limit: 5 }));
+        assert!(anomalies.contains(&Anomaly::OutflowSpike {
+            mint,
+            outflow: 606 * USDC,
+            daily_average: 50 * USDC,
+            limit: 200 * USDC,
+        }));
+        // Evaluating records nothing
+        assert!(!baseline.knows_recipient(&stranger));
+    }
+
+    #[test]
+    fn outflow_is_not_judged_before_a_full_day_of_history_or_for_a_mint_never_sent() {
+        let mint = Pubkey::new_unique();
+        let rules = AnomalyRules::new().with_outflow_multiplier(2.0);
+        let mut baseline = ActivityBaseline::new(STARTED);
+        let huge = [SpendRequest { to: Pubkey::new_unique(), mint, amount: u64::MAX / 2 }];
+        assert!(baseline.evaluate(&rules, &huge, STARTED + DAY_SECS).is_empty());
+
+        baseline.record(&[SpendRequest { to: Pubkey::new_unique(), mint: Pubkey::new_unique(), amount: 1 }], STARTED);
+        assert!(baseline.evaluate(&rules, &huge, STARTED + 3 * DAY_SECS).is_empty());
+    }
+
+    #[test]
+    fn forgetting_an_unsent_transfer_also_forgets_a_recipient_it_introduced() {
+        let (mint, to) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let mut baseline = ActivityBaseline::new(STARTED);
+        let request = [SpendRequest { to, mint, amount: 10 }];
+        baseline.record(&request, STARTED + 10);
+        baseline.forget(&request, STARTED + 10);
+        assert!(!baseline.knows_recipient(&to));
+
+        baseline.record(&request, STARTED + 10);
+        baseline.record(&request, STARTED + 20);
+        baseline.forget(&request, STARTED + 20);
+        assert!(baseline.knows_recipient(&to));
+    }
+
+    /// Each anomaly event raised, with whether it was enforced
+    type Seen = Arc<Mutex<Vec<(Anomaly, bool)>>>;
+
+    struct Setup {
+        cluster: MockCluster,
+        dir: TempDir,
+        payer: Keypair,
+        mint: Pubkey,
+    }
+
+    fn setup() -> Setup {
+        let cluster = MockCluster::new();
+        let payer = Keypair::new();
+        cluster.set_balance(payer.pubkey(), 1_000_000_000);
+        let mint = cluster.add_mint(&spl_token::id(), 6, &Pubkey::new_unique());
+        cluster.add_token_account(&payer.pubkey(), &mint, 10_000 * USDC);
+        Setup { cluster, dir: TempDir::new("anomaly"), payer, mint }
+    }
+
+    impl Setup {
+        /// A client with `rules` and its baseline next to an outbox in the test directory,
+        /// collecting its anomaly events
+        fn client(&self, rules: AnomalyRules) -> (FinternetClient, Seen) {
+            let client = self.cluster.client(FinternetConfig {
+                outbox_path: Some(self.dir.join("outbox.json")),
+                ..FinternetConfig::default().with_anomaly_detection(rules)
+            });
+            let seen = Seen::default();
+            let sink = Arc::clone(&seen);
+            client.on_event(move |event| {
+                if let SdkEvent::AnomalyDetected { anomaly, enforced } = event {
+                    sink.lock().unwrap().push((anomaly, enforced));
+                }
+            });
+            (client, seen)
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn observe_mode_reports_and_sends_while_enforce_mode_refuses() {
+        let s = setup();
+        let to = Keypair::new().pubkey();
+        let (client, seen) = s.client(AnomalyRules::new().flag_new_recipients_above(s.mint, 100 * USDC));
+
+        client.send_payment(&s.payer, &to, 150 * USDC, &s.mint, None).await.unwrap();
+        assert_eq!(s.cluster.sent().len(), 1);
+        let events = seen.lock().unwrap().clone();
+        assert_eq!(events.len(), 1);
+        assert!(matches!(events[0], (Anomaly::NewRecipient { .. }, false)));
+
+        let stranger = Keypair::new().pubkey();
+        let (client, seen) = s.client(AnomalyRules::new().flag_new_recipients_above(s.mint, 100 * USDC).enforce());
+        let error = client.send_payment(&s.payer, &stranger, 150 * USDC, &s.mint, None).await.unwrap_err();
+        let Some(PolicyViolation::Anomaly(Anomaly::NewRecipient { recipient, .. })) = error.downcast_ref::<PolicyViolation>() else {
+            panic!("expected an anomaly refusal: {}", error);
+        };
+        assert_eq!(*recipient, stranger);
+        assert!(matches!(seen.lock().unwrap()[..], [(Anomaly::NewRecipient { .. }, true)]));
+        assert_eq!(s.cluster.sent().len(), 1);
+
+        // A refused payment is not learned from, so trying again is refused again
+        assert!(client.send_payment(&s.payer, &stranger, 150 * USDC, &s.mint, None).await.is_err());
+        // The recipient paid in observe mode is known from the shared baseline
+        client.send_payment(&s.payer, &to, 140 * USDC, &s.mint, None).await.unwrap();
+        assert_eq!(s.cluster.sent().len(), 2);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn the_baseline_survives_a_restart_and_can_be_reset() {
+        let s = setup();
+        let rules = || AnomalyRules::new().flag_new_recipients_above(s.mint, 100 * USDC).enforce();
+        let to = Keypair::new().pubkey();
+        {
+            let (client, _) = s.client(AnomalyRules::new().flag_new_recipients_above(s.mint, 100 * USDC));
+            client.send_payment(&s.payer, &to, 150 * USDC, &s.mint, None).await.unwrap();
+        }
+        assert!(s.dir.join("anomaly-baseline.json").exists());
+
+        let (restarted, seen) = s.client(rules());
+        restarted.send_payment(&s.payer, &to, 151 * USDC, &s.mint, None).await.unwrap();
+        assert!(seen.lock().unwrap().is_empty());
+
+        restarted.reset_anomaly_baseline().unwrap();
+        let error = restarted.send_payment(&s.payer, &to, 152 * USDC, &s.mint, None).await.unwrap_err();
+        assert!(matches!(error.downcast_ref::<PolicyViolation>(), Some(PolicyViolation::Anomaly(_))), "{}", error);
+        let (reloaded, _) = s.client(rules());
+        assert!(reloaded.send_payment(&s.payer, &to, 153 * USDC, &s.mint, None).await.is_err());
+        assert_eq!(s.cluster.sent().len(), 2);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn a_payment_that_failed_on_chain_does_not_introduce_its_recipient() {
+        let s = setup();
+        let to = Keypair::new().pubkey();
+        let (client, _) = s.client(AnomalyRules::new().flag_new_recipients_above(s.mint, 100 * USDC));
+        s.cluster.set_landing(Landing::Failed(TransactionError::InstructionError(0, InstructionError::Custom(1))));
+        client.send_payment(&s.payer, &to, 150 * USDC, &s.mint, None).await.unwrap_err();
+
+        let (enforcing, _) = s.client(AnomalyRules::new().flag_new_recipients_above(s.mint, 100 * USDC).enforce());
+        s.cluster.set_landing(Landing::Confirmed);
+        let error = enforcing.send_payment(&s.payer, &to, 151 * USDC, &s.mint, None).await.unwrap_err();
+        assert!(matches!(error.downcast_ref::<PolicyViolation>(), Some(PolicyViolation::Anomaly(_))), "{}", error);
+    }
+}
@@ -0,0 +1,280 @@
+//! Scoped API keys for the HTTP server
+//!
+//! Each key carries the scopes it may use; `admin` implies every other scope. The store keeps
+//! only the SHA-256 of each key, so the key itself is shown once, when it is created, and cannot
+//! be recovered from the store afterwards.
+
+use crate::state_store::{legacy_map, FileStateStore, Namespace, StateStore};
+use crate::usage::UsageQuota;
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use solana_sdk::pubkey::Pubkey;
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Every key starts with this, so leaked keys are easy to grep for
+const KEY_PREFIX: &str = "fk_";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum ApiScope {
+    /// Balances, history, asset lookups, previews, and other calls that change nothing
+    Read,
+    /// Sending payments and sweeping deposits
+    PaymentsSend,
+    /// Creating payment requests and deposit addresses, which ask for money but move none
+    PaymentsRequest,
+    AssetsTokenize,
+    /// Balance alerts and the webhook dead-letter queue
+    WebhooksManage,
+    /// Key management and the action log; grants every other scope too
+    Admin,
+}
+
+impl ApiScope {
+    pub const ALL: [ApiScope; 6] = [
+        Self::Read,
+        Self::PaymentsSend,
+        Self::PaymentsRequest,
+        Self::AssetsTokenize,
+        Self::WebhooksManage,
+        Self::Admin,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Read => "read",
+            Self::PaymentsSend => "payments:send",
+            Self::PaymentsRequest => "payments:request",
+            Self::AssetsTokenize => "assets:tokenize",
+            Self::WebhooksManage => "webhooks:manage",
+            Self::Admin => "admin",
+        }
+    }
+}
+
+impl FromStr for ApiScope {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|scope| scope.as_str() == s.trim())
+            .ok_or_else(|| anyhow!("Unknown API scope '{}'", s))
+    }
+}
+
+impl TryFrom<String> for ApiScope {
+    type Error = anyhow::Error;
+
+    fn try_from(s: String) -> Result<Self> {
+        s.parse()
+    }
+}
+
+impl From<ApiScope> for String {
+    fn from(scope: ApiScope) -> Self {
+        scope.as_str().to_string()
+    }
+}
+
+impl fmt::Display for ApiScope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// What the store keeps about a key; never the key itself
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiKeyRecord {
+    pub id: String,
+    pub name: String,
+    /// Hex SHA-256 of the key
+    pub key_hash: String,
+    pub scopes: Vec<ApiScope>,
+    /// Wallet the key acts for, whose organization role the server checks when it has one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub member: Option<Pubkey>,
+    pub created_at: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub revoked_at: Option<u64>,
+    /// Daily caps enforced by the server's `UsageLedger`; none when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub quota: Option<UsageQuota>,
+}
+
+impl ApiKeyRecord {
+    pub fn is_revoked(&self) -> bool {
+        self.revoked_at.is_some()
+    }
+
+    /// Whether the key may call an endpoint needing `scope`
+    pub fn allows(&self, scope: ApiScope) -> bool {
+        self.scopes.iter().any(|granted| *granted == scope || *granted == ApiScope::Admin)
+    }
+}
+
+/// Namespace API keys are kept under in a `StateStore`
+pub const API_KEYS_NAMESPACE: &str = "api_keys";
+
+/// Persistent set of API keys, keyed by id
+pub struct ApiKeyStore {
+    keys: Namespace<ApiKeyRecord>,
+    /// Held across each read-then-write so concurrent revocations see each other
+    lock: Mutex<()>,
+}
+
+impl ApiKeyStore {
+    /// Keep keys in their own file at `path`
+    pub fn open(path: &Path) -> Result<Self> {
+        let store = FileStateStore::open_registry(path, &legacy_map(API_KEYS_NAMESPACE))?;
+        Ok(Self::new(Arc::new(store)))
+    }
+
+    pub fn new(store: Arc<dyn StateStore>) -> Self {
+        Self {
+            keys: Namespace::new(store, API_KEYS_NAMESPACE),
+            lock: Mutex::new(()),
+        }
+    }
+
+    /// Issue a key with `scopes`, acting for `member` if given, returning the key itself and
+    /// its stored record
+    ///
+    /// The key is not kept anywhere; hand it to its user now.
+    pub fn create(&self, name: &str, scopes: &[ApiScope], member: Option<Pubkey>) -> Result<(String, ApiKeyRecord)> {
+        if scopes.is_empty() {
+            return Err(anyhow!("An API key needs at least one scope"));
+        }
+        let key = format!(
+            "{}{}{}",
+            KEY_PREFIX,
+            uuid::Uuid::new_v4().simple(),
+            uuid::Uuid::new_v4().simple()
+        );
+        let mut scopes = scopes.to_vec();
+        scopes.sort_by_key(|scope| scope.as_str());
+        scopes.dedup();
+        let record = ApiKeyRecord {
+            id: uuid::Uuid::new_v4().to_string(),
+            name: name.to_string(),
+            key_hash: hash_key(&key),
+            scopes,
+            member,
+            created_at: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+            revoked_at: None,
+            quota: None,
+        };
+
+        let _guard = self.lock.lock().map_err(|_| anyhow!("API key store lock poisoned"))?;
+        self.keys.put(&record.id, &record)?;
+        Ok((key, record))
+    }
+
+    /// Every key, revoked ones included, oldest first
+    pub fn list(&self) -> Result<Vec<ApiKeyRecord>> {
+        let mut records: Vec<ApiKeyRecord> = self.keys.list()?.into_iter().map(|(_, record)| record).collect();
+        records.sort_by(|a, b| (a.created_at, &a.id).cmp(&(b.created_at, &b.id)));
+        Ok(records)
+    }
+
+    /// Stop accepting the key; revoking an already revoked key leaves its revocation time alone
+    pub fn revoke(&self, id: &str) -> Result<Option<ApiKeyRecord>> {
+        let _guard = self.lock.lock().map_err(|_| anyhow!("API key store lock poisoned"))?;
+        let Some(mut record) = self.keys.get(id)? else {
+            return Ok(None);
+        };
+        if record.revoked_at.is_none() {
+            record.revoked_at = Some(SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs());
+            self.keys.put(id, &record)?;
+        }
+        Ok(Some(record))
+    }
+
+    /// Replace the key's daily quota; an unlimited quota clears it
+    pub fn set_quota(&self, id: &str, quota: UsageQuota) -> Result<Option<ApiKeyRecord>> {
+        let _guard = self.lock.lock().map_err(|_| anyhow!("API key store lock poisoned"))?;
+        let Some(mut record) = self.keys.get(id)? else {
+            return Ok(None);
+        };
+        record.quota = Some(quota).filter(|quota| !quota.is_unlimited());
+        self.keys.put(id, &record)?;
+        Ok(Some(record))
+    }
+
+    /// The live record for a presented key, if it is one
+    pub fn authenticate(&self, key: &str) -> Result<Option<ApiKeyRecord>> {
+        if !key.starts_with(KEY_PREFIX) {
+            return Ok(None);
+        }
+        let hash = hash_key(key);
+        Ok(self
+            .keys
+            .list()?
+            .into_iter()
+            .map(|(_, record)| record)
+            .find(|record| record.key_hash == hash && !record.is_revoked()))
+    }
+}
+
+fn hash_key(key: &str) -> String {
+    Sha256::digest(key.as_bytes()).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state_store::MemoryStateStore;
+
+    fn store() -> ApiKeyStore {
+        ApiKeyStore::new(Arc::new(MemoryStateStore::new()))
+    }
+
+    #[test]
+    fn scopes_parse_from_and_serialize_to_their_names() {
+        for scope in ApiScope::ALL {
+            assert_eq!(scope.as_str().parse::<ApiScope>().unwrap(), scope);
+            assert_eq!(serde_json::to_value(scope).unwrap(), scope.as_str());
+        }
+        assert!("payments".parse::<ApiScope>().is_err());
+        assert!(serde_json::from_str::<ApiScope>("\"superuser\"").is_err());
+    }
+
+    #[test]
+    fn only_admin_implies_the_other_scopes() {
+        let store = store();
+        let (_, read) = store.create("frontend", &[ApiScope::Read], None).unwrap();
+        assert!(read.allows(ApiScope::Read));
+        assert!(ApiScope::ALL.iter().filter(|scope| **scope != ApiScope::Read).all(|scope| !read.allows(*scope)));
+
+        let (_, admin) = store.create("admin", &[ApiScope::Admin], None).unwrap();
+        assert!(ApiScope::ALL.iter().all(|scope| admin.allows(*scope)));
+    }
+
+    #[test]
+    fn a_key_is_stored_only_as_its_hash_and_stops_working_once_revoked() {
+        let store = store();
+        assert!(store.create("empty", &[], None).is_err());
+
+        let (key, record) = store
+            .create("billing", &[ApiScope::PaymentsRequest, ApiScope::Read, ApiScope::Read], None)
+            .unwrap();
+        assert!(key.starts_with(KEY_PREFIX));
+        assert_eq!(record.key_hash, hash_key(&key));
+        assert_eq!(record.scopes, [ApiScope::PaymentsRequest, ApiScope::Read]);
+        assert!(!serde_json::to_string(&store.list().unwrap()).unwrap().contains(&key));
+        assert_eq!(store.authenticate(&key).unwrap().unwrap().id, record.id);
+        assert!(store.authenticate(&key[KEY_PREFIX.len()..]).unwrap().is_none());
+
+        let revoked_at = store.revoke(&record.id).unwrap().unwrap().revoked_at;
+        assert!(revoked_at.is_some());
+        assert!(store.authenticate(&key).unwrap().is_none());
+        // Revoking again keeps the first revocation time
+        assert_eq!(store.revoke(&record.id).unwrap().unwrap().revoked_at, revoked_at);
+        assert!(store.revoke("no-such-key").unwrap().is_none());
+    }
+}
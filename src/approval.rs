@@ -0,0 +1,492 @@
+use crate::confirmation::OperationKind;
+use crate::telemetry::readable;
+use crate::validation::{self, validate_payment};
+use crate::{Currency, FinternetClient};
+use anyhow::{anyhow, Result};
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use solana_client::nonce_utils;
+use solana_sdk::{
+    hash::Hash,
+    instruction::Instruction,
+    message::Message,
+    nonce::State as NonceState,
+    pubkey::Pubkey,
+    signature::{Keypair, Signature},
+    signer::Signer,
+    system_instruction,
+    transaction::Transaction,
+};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const PROPOSAL_VERSION: u32 = 1;
+const DEFAULT_VALID_FOR_SECS: u64 = 24 * 60 * 60;
+
+/// What makes the approver's signature necessary
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ApprovalAuthority {
+    /// Funds leave the initiator's own token account; the approver co-signs the memo.
+    /// The second signature is only enforced by this workflow, not on-chain.
+    CoSigner,
+    /// Funds leave the token account of an SPL multisig that needs both signers, so the
+    /// chain itself refuses the transfer without the approver
+    Multisig { multisig: Pubkey },
+}
+
+/// How `propose_payment` should build the proposal
+#[derive(Debug, Clone)]
+pub struct ProposalOptions {
+    pub approver: Pubkey,
+    pub authority: ApprovalAuthority,
+    /// Durable nonce account whose authority is the initiator; one is created when `None`
+    pub nonce_account: Option<Pubkey>,
+    pub valid_for_secs: u64,
+}
+
+impl ProposalOptions {
+    pub fn new(approver: Pubkey) -> Self {
+        Self {
+            approver,
+            authority: ApprovalAuthority::CoSigner,
+            nonce_account: None,
+            valid_for_secs: DEFAULT_VALID_FOR_SECS,
+        }
+    }
+}
+
+/// Why a proposal was refused before the approver signed it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProposalError {
+    /// The transaction does not match the proposal's fields, or its hash does not match
+    Tampered { reason: String },
+    Expired { expires_at: u64 },
+    WrongApprover { expected: Pubkey, actual: Pubkey },
+    /// The initiator's signature does not verify against the proposed transaction
+    InvalidInitiatorSignature,
+    /// The nonce moved on: the proposal was executed or cancelled already
+    NonceAdvanced { nonce_account: Pubkey },
+    UnsupportedVersion(u32),
+}
+
+impl ProposalError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::Tampered { .. } => "proposal_tampered",
+            Self::Expired { .. } => "proposal_expired",
+            Self::WrongApprover { .. } => "wrong_approver",
+            Self::InvalidInitiatorSignature => "invalid_initiator_signature",
+            Self::NonceAdvanced { .. } => "proposal_nonce_advanced",
+            Self::UnsupportedVersion(_) => "unsupported_proposal_version",
+        }
+    }
+}
+
+impl fmt::Display for ProposalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Tampered { reason } => write!(f, "Proposal has been tampered with: {}", reason),
+            Self::Expired { expires_at } => write!(f, "Proposal expired at {}", expires_at),
+            Self::WrongApprover { expected, actual } => {
+                write!(f, "Proposal must be approved by {}, not {}", expected, actual)
+            }
+            Self::InvalidInitiatorSignature => write!(f, "Initiator's signature does not match the proposed transaction"),
+            Self::NonceAdvanced { nonce_account } => write!(
+                f,
+                "Nonce account {} has moved on; the proposal was already executed or cancelled",
+                nonce_account
+            ),
+            Self::UnsupportedVersion(version) => write!(f, "Unsupported proposal version {}", version),
+        }
+    }
+}
+
+impl std::error::Error for ProposalError {}
+
+/// A payment signed by its initiator and waiting for a second signature
+///
+/// The transaction uses a durable nonce, so it does not go stale while it waits. `message` is
+/// the exact transaction message the initiator signed and `message_hash` its SHA-256; both are
+/// rebuilt from the readable fields and compared before the approver signs.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PaymentProposal {
+    pub version: u32,
+    pub id: String,
+    pub initiator: Pubkey,
+    pub approver: Pubkey,
+    pub authority: ApprovalAuthority,
+    pub to: Pubkey,
+    pub mint: Pubkey,
+    pub decimals: u8,
+    /// Base units of `mint`
+    pub amount: u64,
+    pub memo: Option<String>,
+    pub nonce_account: Pubkey,
+    pub nonce_blockhash: String,
+    pub created_at: u64,
+    /// Refused for approval after this; the initiator can also cancel by advancing the nonce
+    pub expires_at: u64,
+    /// Base64 of the serialized transaction message
+    pub message: String,
+    /// Hex SHA-256 of the serialized message
+    pub message_hash: String,
+    pub initiator_signature: Signature,
+}
+
+impl PaymentProposal {
+    pub fn load(path: &Path) -> Result<Self> {
+        let data = fs::read_to_string(path)?;
+        serde_json::from_str(&data).map_err(|e| anyhow!("Invalid payment proposal {}: {}", path.display(), e))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            if !parent.as_os_str().is_empty() {
+                fs::create_dir_all(parent)?;
+            }
+        }
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// The token account funds come from
+    pub fn source_owner(&self) -> Pubkey {
+        match self.authority {
+            ApprovalAuthority::CoSigner => self.initiator,
+            ApprovalAuthority::Multisig { multisig } => multisig,
+        }
+    }
+
+    /// The message the readable fields describe
+    fn expected_message(&self) -> Result<Message> {
+        let blockhash = Hash::from_str(&self.nonce_blockhash)
+            .map_err(|_| anyhow!("Invalid nonce blockhash '{}'", self.nonce_blockhash))?;
+        let instructions = proposal_instructions(
+            &self.id,
+            &self.initiator,
+            &self.approver,
+            &self.authority,
+            &self.to,
+            &self.mint,
+            self.decimals,
+            self.amount,
+            self.memo.as_deref(),
+            &self.nonce_account,
+        )?;
+        Ok(Message::new_with_blockhash(&instructions, Some(&self.initiator), &blockhash))
+    }
+
+    /// Check the transaction against the readable fields, its hash, and the initiator's signature
+    pub fn verify(&self) -> Result<Message> {
+        if self.version != PROPOSAL_VERSION {
+            return Err(ProposalError::UnsupportedVersion(self.version).into());
+        }
+        let tampered = |reason: &str| ProposalError::Tampered { reason: reason.to_string() };
+        let stored = base64::engine::general_purpose::STANDARD
+            .decode(&self.message)
+            .map_err(|_| tampered("message is not valid base64"))?;
+        if hex_sha256(&stored) != self.message_hash {
+            return Err(tampered("message hash does not match the message").into());
+        }
+        let expected = self.expected_message()?;
+        if expected.serialize() != stored {
+            return Err(tampered("transaction does not match the proposal's recipient, amount, mint, or memo").into());
+        }
+        if !self.initiator_signature.verify(self.initiator.as_ref(), &stored) {
+            return Err(ProposalError::InvalidInitiatorSignature.into());
+        }
+        Ok(expected)
+    }
+}
+
+impl fmt::Display for PaymentProposal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let currency = Currency::spl(self.mint, self.decimals);
+        writeln!(f, "Proposal {}", self.id)?;
+        writeln!(f, "  Pay:       {} ({})", currency.format_amount(self.amount), self.mint)?;
+        writeln!(f, "  To:        {}", self.to)?;
+        writeln!(f, "  From:      {}", self.source_owner())?;
+        writeln!(f, "  Initiator: {}", self.initiator)?;
+        writeln!(f, "  Approver:  {}", self.approver)?;
+        if let Some(memo) = &self.memo {
+            writeln!(f, "  Memo:      {}", memo)?;
+        }
+        write!(f, "  Expires:   {}", self.expires_at)
+    }
+}
+
+impl FinternetClient {
+    /// Sign a payment as its initiator and return it as a proposal for the approver
+    ///
+    /// Nothing is broadcast except, when no nonce account is given, the creation of one.
+    #[tracing::instrument(skip_all, fields(wallet = %readable(&initiator.pubkey()), to = %readable(to), mint = %mint, amount))]
+    pub async fn propose_payment(
+        &self,
+        initiator: &Keypair,
+        to: &Pubkey,
+        amount: u64,
+        mint: &Pubkey,
+        memo: Option<&str>,
+        options: &ProposalOptions,
+    ) -> Result<PaymentProposal> {
+        validation::check(validate_payment(to, amount, mint, memo))?;
+        if options.approver == initiator.pubkey() {
+            return Err(anyhow!("The approver must be a different wallet from the initiator"));
+        }
+
+        let nonce_account = match options.nonce_account {
+            Some(nonce_account) => nonce_account,
+            None => self.create_nonce_account(initiator)?,
+        };
+        let nonce_blockhash = self.nonce_blockhash(&nonce_account, &initiator.pubkey())?;
+        let decimals = self.client.get_token_supply(mint)?.decimals;
+
+        let now = unix_now();
+        let id = uuid::Uuid::new_v4().to_string();
+        let instructions = proposal_instructions(
+            &id,
+            &initiator.pubkey(),
+            &options.approver,
+            &options.authority,
+            to,
+            mint,
+            decimals,
+            amount,
+            memo,
+            &nonce_account,
+        )?;
+        let message = Message::new_with_blockhash(&instructions, Some(&initiator.pubkey()), &nonce_blockhash);
+        let bytes = message.serialize();
+
+        tracing::info!("Proposed payment {} awaiting approval by {}", id, options.approver);
+        Ok(PaymentProposal {
+            version: PROPOSAL_VERSION,
+            id,
+            initiator: initiator.pubkey(),
+            approver: options.approver,
+            authority: options.authority,
+            to: *to,
+            mint: *mint,
+            decimals,
+            amount,
+            memo: memo.map(str::to_string),
+            nonce_account,
+            nonce_blockhash: nonce_blockhash.to_string(),
+            created_at: now,
+            expires_at: now + options.valid_for_secs,
+            message: base64::engine::general_purpose::STANDARD.encode(&bytes),
+            message_hash: hex_sha256(&bytes),
+            initiator_signature: initiator.sign_message(&bytes),
+        })
+    }
+
+    /// Check a proposal, add the approver's signature, and broadcast it
+    ///
+    /// Refuses, with a `ProposalError`, proposals that are expired, meant for another approver,
+    /// altered in any way, or already executed or cancelled.
+    pub async fn approve_and_execute(&self, approver: &Keypair, proposal: &PaymentProposal) -> Result<Signature> {
+        let outcome = self.approve_and_execute_unlogged(approver, proposal).await;
+        let parameters = serde_json::json!({
+            "approver": approver.pubkey().to_string(),
+            "proposal": proposal.id,
+            "initiator": proposal.initiator.to_string(),
+            "to": proposal.to.to_string(),
+            "amount": proposal.amount,
+            "mint": proposal.mint.to_string(),
+        });
+        self.log_action("approve_and_execute", parameters, None, outcome.as_ref(), Signature::to_string);
+        outcome
+    }
+    
+    #[tracing::instrument(skip_all, fields(wallet = %readable(&approver.pubkey()), proposal = %proposal.id, signature = tracing::field::Empty))]
+    async fn approve_and_execute_unlogged(&self, approver: &Keypair, proposal: &PaymentProposal) -> Result<Signature> {
+        if unix_now() > proposal.expires_at {
+            return Err(ProposalError::Expired { expires_at: proposal.expires_at }.into());
+        }
+        if approver.pubkey() != proposal.approver {
+            return Err(ProposalError::WrongApprover {
+                expected: proposal.approver,
+                actual: approver.pubkey(),
+            }
+            .into());
+        }
+        let message = proposal.verify()?;
+        if self.nonce_blockhash(&proposal.nonce_account, &proposal.initiator)?.to_string() != proposal.nonce_blockhash {
+            return Err(ProposalError::NonceAdvanced { nonce_account: proposal.nonce_account }.into());
+        }
+
+        let blockhash = message.recent_blockhash;
+        let mut transaction = Transaction::new_unsigned(message);
+        let initiator_index = transaction
+            .message
+            .account_keys
+            .iter()
+            .position(|key| *key == proposal.initiator)
+            .ok_or_else(|| anyhow!("Initiator is not a signer of the proposed transaction"))?;
+        transaction.signatures[initiator_index] = proposal.initiator_signature;
+        transaction.try_partial_sign(&[approver], blockhash)?;
+
+        let signature = self.send_and_confirm_tracked(OperationKind::Payment, "approve_payment", &transaction)?;
+        tracing::Span::current().record("signature", tracing::field::display(&signature));
+        tracing::info!("Approved payment {} executed: {}", proposal.id, signature);
+        Ok(signature)
+    }
+
+    /// Create a durable nonce account owned and paid for by `authority`
+    fn create_nonce_account(&self, authority: &Keypair) -> Result<Pubkey> {
+        let nonce = Keypair::new();
+        let rent = self.client.get_minimum_balance_for_rent_exemption(NonceState::size())?;
+        let instructions =
+            system_instruction::create_nonce_account(&authority.pubkey(), &nonce.pubkey(), &authority.pubkey(), rent);
+        let mut transaction = Transaction::new_with_payer(&instructions, Some(&authority.pubkey()));
+        self.sign_with_fresh_blockhash(&mut transaction, &[authority, &nonce])?;
+        self.send_and_confirm_untracked("create_nonce_account", &transaction)?;
+        tracing::info!("Created durable nonce account {}", nonce.pubkey());
+        Ok(nonce.pubkey())
+    }
+
+    /// The blockhash stored in `nonce_account`, which must be controlled by `authority`
+    pub(crate) fn nonce_blockhash(&self, nonce_account: &Pubkey, authority: &Pubkey) -> Result<Hash> {
+        let account = nonce_utils::get_account_with_commitment(&self.client, nonce_account, self.client.commitment())
+            .map_err(|e| anyhow!("Could not read nonce account {}: {}", nonce_account, e))?;
+        let data = nonce_utils::data_from_account(&account)
+            .map_err(|e| anyhow!("{} is not an initialized nonce account: {}", nonce_account, e))?;
+        if data.authority != *authority {
+            return Err(anyhow!(
+                "Nonce account {} is controlled by {}, not {}",
+                nonce_account,
+                data.authority,
+                authority
+            ));
+        }
+        Ok(data.blockhash())
+    }
+}
+
+/// Advance the nonce, create the recipient's account if needed, transfer, and record a memo
+/// that both parties sign
+#[allow(clippy::too_many_arguments)]
+fn proposal_instructions(
+    id: &str,
+    initiator: &Pubkey,
+    approver: &Pubkey,
+    authority: &ApprovalAuthority,
+    to: &Pubkey,
+    mint: &Pubkey,
+    decimals: u8,
+    amount: u64,
+    memo: Option<&str>,
+    nonce_account: &Pubkey,
+) -> Result<Vec<Instruction>> {
+    let (source_owner, signers): (Pubkey, Vec<&Pubkey>) = match authority {
+        ApprovalAuthority::CoSigner => (*initiator, vec![initiator]),
+        ApprovalAuthority::Multisig { multisig } => (*multisig, vec![initiator, approver]),
+    };
+    let source = spl_associated_token_account::get_associated_token_address(&source_owner, mint);
+    let destination = spl_associated_token_account::get_associated_token_address(to, mint);
+
+    let mut memo_text = format!("approved-payment:{}", id);
+    if let Some(memo) = memo {
+        memo_text.push('|');
+        memo_text.push_str(memo);
+    }
+
+    Ok(vec![
+        system_instruction::advance_nonce_account(nonce_account, initiator),
+        spl_associated_token_account::instruction::create_associated_token_account_idempotent(
+            initiator,
+            to,
+            mint,
+            &spl_token::id(),
+        ),
+        spl_token::instruction::transfer_checked(
+            &spl_token::id(),
+            &source,
+            mint,
+            &destination,
+            &source_owner,
+            &signers,
+            amount,
+            decimals,
+        )?,
+        spl_memo::build_memo(memo_text.as_bytes(), &[initiator, approver]),
+    ])
+}
+
+fn hex_sha256(bytes: &[u8]) -> String {
+    Sha256::digest(bytes).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_cluster::{Landing, MockCluster};
+    use std::time::Duration;
+
+    /// A cluster where `initiator` holds 10 tokens and controls a nonce account, and a
+    /// proposal to pay 1 token that `approver` may approve
+    async fn proposed() -> (MockCluster, FinternetClient, Keypair, PaymentProposal) {
+        let cluster = MockCluster::new();
+        let (initiator, approver) = (Keypair::new(), Keypair::new());
+        cluster.set_balance(initiator.pubkey(), 1_000_000_000);
+        let mint = cluster.add_mint(&spl_token::id(), 6, &Pubkey::new_unique());
+        cluster.add_token_account(&initiator.pubkey(), &mint, 10_000_000);
+        let nonce_account = cluster.add_nonce_account(&initiator.pubkey());
+        let client = cluster.default_client();
+        let options = ProposalOptions { nonce_account: Some(nonce_account), ..ProposalOptions::new(approver.pubkey()) };
+        let proposal = client
+            .propose_payment(&initiator, &Keypair::new().pubkey(), 1_000_000, &mint, None, &options)
+            .await
+            .unwrap();
+        (cluster, client, approver, proposal)
+    }
+
+    /// Once the cluster has accepted a transaction, do `then` to it after a short while
+    fn after_send(cluster: &MockCluster, then: impl FnOnce(&MockCluster, &Transaction) + Send + 'static) {
+        let cluster = cluster.clone();
+        std::thread::spawn(move || {
+            while cluster.sent().is_empty() {
+                std::thread::sleep(Duration::from_millis(10));
+            }
+            std::thread::sleep(Duration::from_millis(700));
+            let sent = cluster.sent()[0].clone();
+            then(&cluster, &sent);
+        });
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn an_approved_proposal_is_awaited_on_its_nonce_not_a_blockhash() {
+        let (cluster, client, approver, proposal) = proposed().await;
+        cluster.set_landing(Landing::Pending);
+        after_send(&cluster, |cluster, sent| cluster.land(&sent.signatures[0], None));
+
+        let signature = client.approve_and_execute(&approver, &proposal).await.unwrap();
+
+        assert_eq!(cluster.sent()[0].signatures[0], signature);
+        // The nonce is no recent blockhash, so the blockhash wait would have given up at once
+        assert_eq!(cluster.call_count("isBlockhashValid"), 0);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn a_proposal_whose_nonce_moves_on_without_it_is_reported_lost() {
+        let (cluster, client, approver, proposal) = proposed().await;
+        cluster.set_landing(Landing::Pending);
+        let (nonce_account, initiator) = (proposal.nonce_account, proposal.initiator);
+        after_send(&cluster, move |cluster, _| {
+            cluster.advance_nonce(&nonce_account, &initiator);
+        });
+
+        let error = client.approve_and_execute(&approver, &proposal).await.unwrap_err();
+
+        assert!(error.to_string().contains("advanced without transaction"), "{}", error);
+    }
+}
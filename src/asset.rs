@@ -1,21 +1,212 @@
-use crate::{AssetMetadata, FinternetClient};
-use anyhow::Result;
+use crate::confirmation::OperationKind;
+use crate::events::SdkEvent;
+use crate::instructions::{tokenize_asset_instructions, tokenize_asset_ledger_instructions};
+use crate::metadata::{fetch_offchain_metadata, OffchainMetadata};
+use crate::rpc_options::RpcCallOptions;
+use crate::{AssetCreator, AssetMetadata, AssetType, Edition, FinternetClient, MetadataBackend, TokenStandard};
+use crate::telemetry::readable;
+use crate::validation;
+use anyhow::{anyhow, Result};
 use mpl_token_metadata::{
-    accounts::Metadata,
-    instructions::{CreateMetadataAccountV3, CreateMetadataAccountV3InstructionArgs},
-    types::{Creator, DataV2},
+    accounts::{Edition as EditionAccount, MasterEdition, Metadata},
+    instructions::SignMetadata,
+    types::{Creator, ProgrammableConfig, TokenStandard as MplTokenStandard},
 };
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use solana_sdk::{
+    account::Account,
+    instruction::Instruction,
     pubkey::Pubkey,
     signature::Keypair,
-    signer::Signer,
-    system_instruction,
+    signer::{keypair::keypair_from_seed, Signer},
     transaction::Transaction,
 };
-use spl_associated_token_account::instruction as ata_instruction;
-use spl_token::instruction as token_instruction;
+use spl_token_2022::extension::StateWithExtensions;
+use spl_token_2022::state::Mint;
+use std::str::FromStr;
 use std::time::{SystemTime, UNIX_EPOCH};
 
+const TOKEN_SYMBOL: &str = "FINT";
+/// Ledger entry action of the metadata memo written in place of Token Metadata
+pub const ASSET_METADATA_ACTION: &str = "asset_metadata";
+/// `getMultipleAccounts` accepts at most this many addresses per call
+const MAX_ACCOUNTS_PER_CALL: usize = 100;
+/// Token Metadata `Key` discriminators for edition accounts
+const EDITION_V1_KEY: u8 = 1;
+const MASTER_EDITION_V1_KEY: u8 = 2;
+const MASTER_EDITION_V2_KEY: u8 = 6;
+/// Domain separator so a mint seed cannot double as any other key's seed
+const MINT_KEY_DOMAIN: &[u8] = b"finternet-mint-v1:";
+
+/// The mint keypair `issuer` derives from `seed`
+///
+/// The same issuer and seed always give the same mint, so revealing the seed later proves the
+/// issuer generated it; see `is_derived_mint`. Each seed mints once: a second tokenization
+/// with it is refused because the mint account already exists.
+pub fn derive_mint_keypair(issuer: &Pubkey, seed: &str) -> Result<Keypair> {
+    if seed.is_empty() {
+        return Err(anyhow!("Mint seed must not be empty"));
+    }
+    let mut hasher = Sha256::new();
+    hasher.update(MINT_KEY_DOMAIN);
+    hasher.update(issuer.as_ref());
+    hasher.update(seed.as_bytes());
+    keypair_from_seed(&hasher.finalize()).map_err(|e| anyhow!("Could not derive the mint keypair: {}", e))
+}
+
+/// Whether `mint` is the one `derive_mint_keypair(issuer, seed)` gives
+pub fn is_derived_mint(mint: &Pubkey, issuer: &Pubkey, seed: &str) -> bool {
+    derive_mint_keypair(issuer, seed).is_ok_and(|keypair| keypair.pubkey() == *mint)
+}
+
+/// Who holds a mint's mint or freeze authority
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "holder", content = "address", rename_all = "snake_case")]
+pub enum AuthorityCustody {
+    /// The issuing wallet
+    #[default]
+    Issuer,
+    /// A key other than the issuer's, such as a compliance officer's
+    Key(Pubkey),
+    /// Nobody: the supply is fixed, or no holder's account can be frozen
+    Disabled,
+}
+
+impl AuthorityCustody {
+    /// The authority this gives a mint issued by `issuer`
+    pub fn resolve(&self, issuer: &Pubkey) -> Option<Pubkey> {
+        match self {
+            Self::Issuer => Some(*issuer),
+            Self::Key(key) => Some(*key),
+            Self::Disabled => None,
+        }
+    }
+
+    /// How `authority`, as read from a mint, relates to the asset's `issuer`
+    pub fn of(authority: Option<Pubkey>, issuer: &Pubkey) -> Self {
+        match authority {
+            Some(key) if key == *issuer => Self::Issuer,
+            Some(key) => Self::Key(key),
+            None => Self::Disabled,
+        }
+    }
+}
+
+impl std::fmt::Display for AuthorityCustody {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Issuer => write!(f, "issuer"),
+            Self::Key(key) => write!(f, "{}", key),
+            Self::Disabled => write!(f, "none"),
+        }
+    }
+}
+
+impl FromStr for AuthorityCustody {
+    type Err = anyhow::Error;
+
+    /// `issuer`, `none`, or the address of the key to hold the authority
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "issuer" => Ok(Self::Issuer),
+            "none" => Ok(Self::Disabled),
+            _ => Pubkey::from_str(s)
+                .map(Self::Key)
+                .map_err(|_| anyhow!("Expected `issuer`, `none`, or an address, got '{}'", s)),
+        }
+    }
+}
+
+impl AssetMetadata {
+    /// Who can mint more of the asset
+    pub fn mint_custody(&self) -> AuthorityCustody {
+        AuthorityCustody::of(self.mint_authority, &self.issuer)
+    }
+
+    /// Who can freeze holders' token accounts
+    pub fn freeze_custody(&self) -> AuthorityCustody {
+        AuthorityCustody::of(self.freeze_authority, &self.issuer)
+    }
+}
+
+/// Per-call overrides for `tokenize_asset_with_options`
+#[derive(Debug, Default)]
+pub struct TokenizeOptions {
+    /// Base URL for `<base>/<mint>.json`; takes precedence over `FinternetConfig::metadata_uri_base`
+    /// but not over a configured `MetadataPublisher`
+    pub metadata_uri_base: Option<String>,
+    /// Mint with an empty metadata URI instead of failing when the publisher errors
+    pub skip_metadata_on_publish_failure: bool,
+    /// Token symbol, at most 10 characters; defaults to `FINT`
+    pub symbol: Option<String>,
+    /// Creators and their shares, summing to 100; defaults to the signing wallet at 100%
+    ///
+    /// Only the signing wallet's entry is marked verified. Other creators verify themselves
+    /// afterwards with `verify_creator`.
+    pub creators: Vec<(Pubkey, u8)>,
+    /// Secondary-sale royalty, at most 10000 (100%)
+    pub seller_fee_basis_points: u16,
+    /// Commitment, confirmation timeout, retries, and preflight for the mint transaction
+    pub rpc: RpcCallOptions,
+    /// Keypair of the new mint, for example from `derive_mint_keypair`; a fresh one when `None`
+    pub mint_keypair: Option<Keypair>,
+    /// Handed over after the single token is minted, so the issuer still signs the mint itself
+    pub mint_authority: AuthorityCustody,
+    /// Set when the mint is created; `Disabled` leaves holders' accounts unfreezable for good
+    pub freeze_authority: AuthorityCustody,
+}
+
+impl Clone for TokenizeOptions {
+    fn clone(&self) -> Self {
+        Self {
+            metadata_uri_base: self.metadata_uri_base.clone(),
+            skip_metadata_on_publish_failure: self.skip_metadata_on_publish_failure,
+            symbol: self.symbol.clone(),
+            creators: self.creators.clone(),
+            seller_fee_basis_points: self.seller_fee_basis_points,
+            rpc: self.rpc.clone(),
+            mint_keypair: self.mint_keypair.as_ref().map(Keypair::insecure_clone),
+            mint_authority: self.mint_authority,
+            freeze_authority: self.freeze_authority,
+        }
+    }
+}
+
+impl TokenizeOptions {
+    pub fn symbol(&self) -> &str {
+        self.symbol.as_deref().unwrap_or(TOKEN_SYMBOL)
+    }
+    
+    /// Check symbol, royalty, and creator shares against the Metaplex rules
+    pub fn validate(&self) -> Result<()> {
+        validation::check(validation::validate_tokenize_options(self))
+    }
+    
+    /// The keypair to create the mint with: the given one, or a fresh one
+    pub(crate) fn new_mint_keypair(&self) -> Keypair {
+        self.mint_keypair.as_ref().map(Keypair::insecure_clone).unwrap_or_else(Keypair::new)
+    }
+    
+    pub(crate) fn metaplex_creators(&self, signer: &Pubkey) -> Vec<Creator> {
+        if self.creators.is_empty() {
+            return vec![Creator {
+                address: *signer,
+                verified: true,
+                share: 100,
+            }];
+        }
+        self.creators
+            .iter()
+            .map(|(address, share)| Creator {
+                address: *address,
+                verified: address == signer,
+                share: *share,
+            })
+            .collect()
+    }
+}
+
 impl FinternetClient {
     /// Tokenize a real-world or digital asset by minting an SPL token with metadata
     pub async fn tokenize_asset(
@@ -23,168 +214,337 @@ impl FinternetClient {
         name: &str,
         description: &str,
         value: u64,
-        asset_type: &str,
+        asset_type: &AssetType,
+        wallet: &Keypair,
+    ) -> Result<(Pubkey, AssetMetadata, solana_sdk::signature::Signature)> {
+        self.tokenize_asset_with_options(name, description, value, asset_type, wallet, &TokenizeOptions::default())
+            .await
+    }
+    
+    /// Tokenize an asset, publishing its off-chain metadata before minting
+    pub async fn tokenize_asset_with_options(
+        &self,
+        name: &str,
+        description: &str,
+        value: u64,
+        asset_type: &AssetType,
+        wallet: &Keypair,
+        options: &TokenizeOptions,
+    ) -> Result<(Pubkey, AssetMetadata, solana_sdk::signature::Signature)> {
+        let outcome = self
+            .tokenize_asset_unlogged(name, description, value, asset_type, wallet, options)
+            .await;
+        let parameters = serde_json::json!({
+            "issuer": wallet.pubkey().to_string(),
+            "name": name,
+            "value": value,
+            "asset_type": asset_type.to_string(),
+            "symbol": options.symbol(),
+            "mint": outcome.as_ref().ok().map(|(mint, _, _)| mint.to_string()),
+        });
+        if let Ok((mint, _, signature)) = &outcome {
+            self.emit(|| SdkEvent::TokenizationCompleted { mint: *mint, signature: *signature });
+        }
+        self.log_action("tokenize_asset", parameters, None, outcome.as_ref(), |(_, _, signature)| signature.to_string());
+        outcome
+    }
+    
+    #[tracing::instrument(skip_all, fields(wallet = %readable(&wallet.pubkey()), asset_type = %asset_type, value, rpc = %self.rpc_endpoint(), mint = tracing::field::Empty, signature = tracing::field::Empty))]
+    async fn tokenize_asset_unlogged(
+        &self,
+        name: &str,
+        description: &str,
+        value: u64,
+        asset_type: &AssetType,
         wallet: &Keypair,
+        options: &TokenizeOptions,
     ) -> Result<(Pubkey, AssetMetadata, solana_sdk::signature::Signature)> {
-        log::info!(
+        tracing::info!(
             "Tokenizing asset: {} of type: {} with value: {}",
             name,
             asset_type,
             value
         );
 
-        // Create a new mint keypair
-        let mint_keypair = Keypair::new();
-        let mint_pubkey = mint_keypair.pubkey();
-        
-        // Get recent blockhash
-        let recent_blockhash = self.client.get_latest_blockhash()?;
-        
-        // Calculate rent exemption for mint account
-        let mint_rent = self.client.get_minimum_balance_for_rent_exemption(82)?; // 82 bytes for mint account
-        
-        // Create mint account instruction
-        let create_mint_account_ix = system_instruction::create_account(
-            &wallet.pubkey(),
-            &mint_pubkey,
-            mint_rent,
-            82,
-            &spl_token::id(),
-        );
+        validation::check(validation::validate_asset_spec(name, description, value, asset_type, options))?;
         
-        // Initialize mint instruction
-        let init_mint_ix = token_instruction::initialize_mint(
-            &spl_token::id(),
-            &mint_pubkey,
-            &wallet.pubkey(),
-            Some(&wallet.pubkey()),
-            0, // decimals
-        )?;
-        
-        // Get associated token account for the wallet
-        let associated_token_account = spl_associated_token_account::get_associated_token_address(
-            &wallet.pubkey(),
-            &mint_pubkey,
-        );
-        
-        // Create associated token account instruction
-        let create_ata_ix = ata_instruction::create_associated_token_account(
-            &wallet.pubkey(),
-            &wallet.pubkey(),
-            &mint_pubkey,
-            &spl_token::id(),
-        );
+        let mint_keypair = options.new_mint_keypair();
+        let mint_pubkey = mint_keypair.pubkey();
+        if mint_pubkey == wallet.pubkey() {
+            return Err(anyhow!("The mint keypair must not be the issuing wallet"));
+        }
+        // A reused or re-derived keypair would fail on-chain only after metadata is published
+        if self.client.get_account_with_commitment(&mint_pubkey, self.client.commitment())?.value.is_some() {
+            return Err(anyhow!("An account already exists at mint address {}; each mint keypair or seed mints once", mint_pubkey));
+        }
         
-        // Mint 1 token to the associated token account
-        let mint_to_ix = token_instruction::mint_to(
-            &spl_token::id(),
-            &mint_pubkey,
-            &associated_token_account,
-            &wallet.pubkey(),
-            &[&wallet.pubkey()],
-            1,
-        )?;
-        
-        // Create metadata account
-        let metadata_account = Metadata::find_pda(&mint_pubkey).0;
-        
-        // Create metadata instruction
-        let creators = vec![Creator {
-            address: wallet.pubkey(),
-            verified: true,
-            share: 100,
-        }];
-        
-        let data = DataV2 {
-            name: name.to_string(),
-            symbol: "FINT".to_string(),
-            uri: format!("https://api.finternet.com/metadata/{}", mint_pubkey),
-            seller_fee_basis_points: 0,
-            creators: Some(creators),
-            collection: None,
-            uses: None,
-        };
+        let mut asset_metadata =
+            new_asset_metadata(name, description, value, asset_type, &wallet.pubkey(), &mint_pubkey, options)?;
         
-        let create_metadata_ix = CreateMetadataAccountV3 {
-            metadata: metadata_account,
-            mint: mint_pubkey,
-            mint_authority: wallet.pubkey(),
-            payer: wallet.pubkey(),
-            update_authority: (wallet.pubkey(), true),
-            system_program: solana_sdk::system_program::id(),
-            rent: None,
-        }.instruction(CreateMetadataAccountV3InstructionArgs {
-            data,
-            is_mutable: true,
-            collection_details: None,
-        });
+        // Publish off-chain metadata first so the URI is live by the time wallets see the mint
+        let uri = self.resolve_metadata_uri(&mint_pubkey, &asset_metadata, options).await?;
+        asset_metadata.uri = uri.clone();
         
-        // Build and send transaction
-        let instructions = vec![
-            create_mint_account_ix,
-            init_mint_ix,
-            create_ata_ix,
-            mint_to_ix,
-            create_metadata_ix,
-        ];
+        let instructions = self.tokenize_transaction_instructions(&wallet.pubkey(), &mut asset_metadata, options)?;
         
         let mut transaction = Transaction::new_with_payer(&instructions, Some(&wallet.pubkey()));
-        transaction.sign(&[wallet, &mint_keypair], recent_blockhash);
+        let signers = [wallet, &mint_keypair];
+        self.sign_with_fresh_blockhash(&mut transaction, &signers)?;
         
-        let signature = self.client.send_and_confirm_transaction(&transaction)?;
+        let signature =
+            self.send_and_confirm_resigning_with(OperationKind::Tokenization, "tokenize_asset", transaction, &signers, &options.rpc)?;
+        let span = tracing::Span::current();
+        span.record("mint", tracing::field::display(&mint_pubkey));
+        span.record("signature", tracing::field::display(&signature));
         
-        log::info!(
+        tracing::info!(
             "Asset tokenized successfully! Mint: {}, Signature: {}",
             mint_pubkey,
             signature
         );
         
-        // Create asset metadata
-        let asset_metadata = AssetMetadata {
-            name: name.to_string(),
-            description: description.to_string(),
-            value,
-            issuer: wallet.pubkey(),
-            asset_type: asset_type.to_string(),
-            created_at: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
-            token_mint: Some(mint_pubkey),
+        Ok((mint_pubkey, asset_metadata, signature))
+    }
+    
+    /// Instructions minting `asset`, shared by `tokenize_asset` and its preview
+    ///
+    /// Picks the metadata backend and records it, along with the fields that depend on it, in
+    /// `asset`. `asset.uri` must already be resolved.
+    pub(crate) fn tokenize_transaction_instructions(
+        &self,
+        payer: &Pubkey,
+        asset: &mut AssetMetadata,
+        options: &TokenizeOptions,
+    ) -> Result<Vec<Instruction>> {
+        let mint = asset.token_mint.ok_or_else(|| anyhow!("Asset has no mint to create"))?;
+        // Calculate rent exemption for mint account
+        let mint_rent = self.client.get_minimum_balance_for_rent_exemption(82)?; // 82 bytes for mint account
+        
+        let backend = self.metadata_backend()?;
+        let instructions = match backend {
+            MetadataBackend::Metaplex => {
+                tokenize_asset_instructions(payer, &mint, &asset.name, &asset.uri, mint_rent, options)?
+            }
+            MetadataBackend::LedgerMemo => {
+                asset.token_standard = None;
+                let entry = self.namespaced_entry(&serde_json::to_string(&LedgerMetadata::from_asset(asset, &mint))?)?;
+                tokenize_asset_ledger_instructions(payer, &mint, &entry, mint_rent, options)?
+            }
         };
+        asset.metadata_backend = Some(backend);
+        Ok(instructions)
+    }
+    
+    async fn resolve_metadata_uri(
+        &self,
+        mint: &Pubkey,
+        asset: &AssetMetadata,
+        options: &TokenizeOptions,
+    ) -> Result<String> {
+        if let Some(publisher) = &self.metadata_publisher {
+            let offchain = OffchainMetadata::from_asset(asset, options.symbol());
+            return match publisher.publish(mint, &offchain).await {
+                Ok(uri) => {
+                    tracing::info!("Published metadata for {} to {}", mint, uri);
+                    Ok(uri)
+                }
+                Err(e) if options.skip_metadata_on_publish_failure => {
+                    tracing::warn!("Metadata publishing failed, minting {} without a URI: {}", mint, e);
+                    Ok(String::new())
+                }
+                Err(e) => Err(anyhow!("Metadata publishing failed, nothing was minted: {}", e)),
+            };
+        }
         
-        Ok((mint_pubkey, asset_metadata, signature))
+        match self.configured_metadata_uri(mint, options) {
+            Some(uri) => Ok(uri),
+            None => {
+                tracing::warn!("No metadata publisher or URI base configured; minting {} without a URI", mint);
+                Ok(String::new())
+            }
+        }
+    }
+    
+    /// `<base>/<mint>.json` under the configured URI base, if there is one
+    pub(crate) fn configured_metadata_uri(&self, mint: &Pubkey, options: &TokenizeOptions) -> Option<String> {
+        let base = options
+            .metadata_uri_base
+            .as_deref()
+            .or(self.config.metadata_uri_base.as_deref())?;
+        Some(format!("{}/{}.json", base.trim_end_matches('/'), mint))
     }
     
     /// Get asset information from the blockchain
+    #[tracing::instrument(skip_all, fields(mint = %token_mint, rpc = %self.rpc_endpoint()))]
     pub async fn get_asset_info(&self, token_mint: &Pubkey) -> Result<AssetMetadata> {
-        log::info!("Fetching asset info for mint: {}", token_mint);
-        
-        // Get metadata account
-        let metadata_account = Metadata::find_pda(token_mint).0;
+        tracing::info!("Fetching asset info for mint: {}", token_mint);
         
-        // Fetch metadata account data
-        let metadata_account_data = self.client.get_account_data(&metadata_account)?;
-        let metadata = Metadata::from_bytes(&metadata_account_data)?;
+        // The mint account itself works for every token, with or without Metaplex metadata
+        let accounts = self
+            .asset_accounts(std::slice::from_ref(token_mint))?
+            .pop()
+            .ok_or_else(|| anyhow!("No accounts returned for {}", token_mint))?;
+        let mut asset_metadata = asset_from_accounts(token_mint, &accounts)?;
+        self.fill_ledger_metadata(token_mint, &mut asset_metadata).await?;
+        // Documents belong to tokenized assets, so fungible mints never pay for the history scan
+        if asset_metadata.has_metadata && asset_metadata.decimals == 0 && asset_metadata.supply <= 1 {
+            let issuer = asset_metadata.issuer;
+            match self.anchored_documents(token_mint, &issuer).await {
+                Ok(documents) => asset_metadata.documents = documents,
+                Err(e) => tracing::warn!("Could not read documents of {}: {}", token_mint, e),
+            }
+        }
+        if !asset_metadata.uri.is_empty() {
+            let uri = asset_metadata.uri.clone();
+            fill_offchain_metadata(&mut asset_metadata, &uri).await;
+        }
         
-        // Extract creator (issuer) information - directly access metadata fields
-        let issuer = metadata
-            .creators
-            .and_then(|creators| creators.first().map(|c| c.address))
-            .unwrap_or_default();
-        
-        let asset_metadata = AssetMetadata {
-            name: metadata.name.trim_matches('\0').to_string(),
-            description: "Asset tokenized on Finternet".to_string(), // Placeholder as description isn't stored in metadata
-            value: 0, // Would need to be stored in custom program data
-            issuer,
-            asset_type: "tokenized_asset".to_string(),
-            created_at: 0, // Would need to be stored in custom program data
-            token_mint: Some(*token_mint),
+        tracing::info!("Asset info retrieved: {:?}", asset_metadata);
+        Ok(asset_metadata)
+    }
+    
+    /// Where `tokenize_asset` records metadata: the configured backend, else Metaplex if the
+    /// Token Metadata program is deployed, which is checked once per client
+    pub fn metadata_backend(&self) -> Result<MetadataBackend> {
+        if let Some(backend) = self.config.metadata_backend {
+            return Ok(backend);
+        }
+        let deployed = match self.metaplex_deployed.get() {
+            Some(deployed) => *deployed,
+            None => {
+                let program = self
+                    .client
+                    .get_account_with_commitment(&mpl_token_metadata::ID, self.client.commitment())?
+                    .value;
+                let deployed = program.is_some_and(|account| account.executable);
+                if !deployed {
+                    tracing::warn!(
+                        "Token Metadata is not deployed on {}; asset metadata will be written as ledger memos",
+                        self.rpc_endpoint()
+                    );
+                }
+                *self.metaplex_deployed.get_or_init(|| deployed)
+            }
         };
+        Ok(if deployed { MetadataBackend::Metaplex } else { MetadataBackend::LedgerMemo })
+    }
+    
+    /// Fill `asset_metadata` from `mint`'s ledger memo when it has no Metaplex metadata
+    ///
+    /// Only 1-of-1 mints can be ours, so other tokens never pay for the history scan.
+    pub(crate) async fn fill_ledger_metadata(&self, mint: &Pubkey, asset_metadata: &mut AssetMetadata) -> Result<()> {
+        if !asset_metadata.has_metadata && asset_metadata.decimals == 0 && asset_metadata.supply <= 1 {
+            if let Some(entry) = self.ledger_metadata(mint).await? {
+                entry.apply(asset_metadata);
+            }
+        }
+        Ok(())
+    }
+    
+    /// The metadata memo written in `mint`'s creation transaction, if it has one
+    ///
+    /// Any transaction can mention the mint, so only entries signed by the issuer they name
+    /// count, and the oldest of those wins.
+    async fn ledger_metadata(&self, mint: &Pubkey) -> Result<Option<LedgerMetadata>> {
+        let entries = self.read_ledger_entries(mint, Some(1000)).await?;
+        Ok(entries.iter().rev().find_map(|entry| {
+            let metadata: LedgerMetadata = serde_json::from_str(&entry.data).ok()?;
+            let issuer = Pubkey::from_str(&metadata.issuer).ok()?;
+            let valid =
+                metadata.action == ASSET_METADATA_ACTION && metadata.mint == mint.to_string() && entry.signer == Some(issuer);
+            valid.then_some(metadata)
+        }))
+    }
+    
+    /// The mint, Metaplex metadata, and edition accounts of each mint, read in batches
+    pub(crate) fn asset_accounts(&self, mints: &[Pubkey]) -> Result<Vec<AssetAccounts>> {
+        let addresses: Vec<Pubkey> = mints
+            .iter()
+            .flat_map(|mint| [*mint, Metadata::find_pda(mint).0, MasterEdition::find_pda(mint).0])
+            .collect();
+        let mut accounts = Vec::with_capacity(addresses.len());
+        for chunk in addresses.chunks(MAX_ACCOUNTS_PER_CALL) {
+            accounts.extend(self.client.get_multiple_accounts(chunk)?);
+        }
+        let mut accounts = accounts.into_iter();
+        Ok(std::iter::from_fn(|| {
+            Some(AssetAccounts {
+                mint: accounts.next()?,
+                metadata: accounts.next()?,
+                edition: accounts.next()?,
+            })
+        })
+        .collect())
+    }
+    
+    /// Hand a 1-of-1 asset token to `to`, creating their token account if needed
+    ///
+    /// Goes through `send_payment`, so the spending policy, asset policy, and recipient checks
+    /// apply.
+    pub async fn transfer_asset(
+        &self,
+        owner: &Keypair,
+        mint: &Pubkey,
+        to: &Pubkey,
+    ) -> Result<solana_sdk::signature::Signature> {
+        self.send_payment(owner, to, 1, mint, None).await
+    }
+    
+    /// Sign an asset's metadata as one of its listed creators, marking that entry verified
+    #[tracing::instrument(skip_all, fields(wallet = %readable(&wallet.pubkey()), mint = %mint, rpc = %self.rpc_endpoint(), signature = tracing::field::Empty))]
+    pub async fn verify_creator(&self, wallet: &Keypair, mint: &Pubkey) -> Result<solana_sdk::signature::Signature> {
+        let asset = self.get_asset_info(mint).await?;
+        if asset.metadata_backend == Some(MetadataBackend::LedgerMemo) {
+            return Err(anyhow!("{} has ledger memo metadata, which creators cannot sign", mint));
+        }
+        match asset.creators.iter().find(|c| c.address == wallet.pubkey()) {
+            None => return Err(anyhow!("{} is not a listed creator of {}", wallet.pubkey(), mint)),
+            Some(creator) if creator.verified => {
+                return Err(anyhow!("{} is already verified on {}", wallet.pubkey(), mint))
+            }
+            Some(_) => {}
+        }
         
-        log::info!("Asset info retrieved: {:?}", asset_metadata);
-        Ok(asset_metadata)
+        let instruction = SignMetadata {
+            metadata: Metadata::find_pda(mint).0,
+            creator: wallet.pubkey(),
+        }
+        .instruction();
+        
+        let mut transaction = Transaction::new_with_payer(&[instruction], Some(&wallet.pubkey()));
+        self.sign_with_fresh_blockhash(&mut transaction, &[wallet])?;
+        
+        let signature = self.send_and_confirm_resigning(OperationKind::Other, "verify_creator", transaction, &[wallet])?;
+        tracing::Span::current().record("signature", tracing::field::display(&signature));
+        tracing::info!("Creator {} verified on {}: {}", wallet.pubkey(), mint, signature);
+        Ok(signature)
+    }
+    
+    /// Owned assets whose stored metadata has the given type, with that metadata
+    #[tracing::instrument(skip_all, fields(wallet = %readable(owner), asset_type = %asset_type))]
+    pub async fn get_owned_assets_by_type(
+        &self,
+        owner: &Pubkey,
+        asset_type: &AssetType,
+    ) -> Result<Vec<(Pubkey, u64, AssetMetadata)>> {
+        let mut matching = Vec::new();
+        for (mint, balance) in self.get_owned_assets(owner).await? {
+            // Plain tokens without Metaplex metadata have no type to match
+            match self.get_asset_info(&mint).await {
+                Ok(info) if info.has_metadata && &info.asset_type == asset_type => {
+                    matching.push((mint, balance, info))
+                }
+                Ok(_) => {}
+                Err(e) => tracing::debug!("Skipping {} without readable metadata: {}", mint, e),
+            }
+        }
+        
+        tracing::info!("Found {} owned assets of type {}", matching.len(), asset_type);
+        Ok(matching)
     }
     
     /// Check if a mint account exists and is valid
+    #[tracing::instrument(skip_all, fields(mint = %token_mint))]
     pub async fn is_valid_asset(&self, token_mint: &Pubkey) -> Result<bool> {
         match self.client.get_account(token_mint) {
             Ok(account) => {
@@ -194,4 +554,883 @@ impl FinternetClient {
             Err(_) => Ok(false),
         }
     }
-} 
\ No newline at end of file
+}
+
+/// Metadata of an asset about to be minted to `issuer` as `mint`, before its URI is known
+pub(crate) fn new_asset_metadata(
+    name: &str,
+    description: &str,
+    value: u64,
+    asset_type: &AssetType,
+    issuer: &Pubkey,
+    mint: &Pubkey,
+    options: &TokenizeOptions,
+) -> Result<AssetMetadata> {
+    let creators = options.metaplex_creators(issuer);
+    Ok(AssetMetadata {
+        name: name.to_string(),
+        description: description.to_string(),
+        value,
+        issuer: *issuer,
+        asset_type: asset_type.clone(),
+        created_at: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+        token_mint: Some(*mint),
+        creators: creators.iter().map(to_asset_creator).collect(),
+        seller_fee_basis_points: options.seller_fee_basis_points,
+        supply: 1,
+        decimals: 0,
+        mint_authority: options.mint_authority.resolve(issuer),
+        freeze_authority: options.freeze_authority.resolve(issuer),
+        is_initialized: true,
+        has_metadata: true,
+        metadata_backend: None,
+        symbol: options.symbol().to_string(),
+        uri: String::new(),
+        token_standard: Some(TokenStandard::FungibleAsset),
+        primary_sale_happened: false,
+        rule_set: None,
+        edition: None,
+        documents: Vec::new(),
+    })
+}
+
+fn to_asset_creator(creator: &Creator) -> AssetCreator {
+    AssetCreator {
+        address: creator.address,
+        verified: creator.verified,
+        share: creator.share,
+    }
+}
+
+/// `tokenize_asset`'s metadata memo on clusters without Token Metadata, addresses as base58
+#[derive(Debug, Serialize, Deserialize)]
+struct LedgerMetadata {
+    action: String,
+    mint: String,
+    name: String,
+    symbol: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    uri: String,
+    description: String,
+    value: u64,
+    issuer: String,
+    asset_type: AssetType,
+    created_at: u64,
+    #[serde(default)]
+    creators: Vec<LedgerCreator>,
+    #[serde(default)]
+    seller_fee_basis_points: u16,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct LedgerCreator {
+    address: String,
+    verified: bool,
+    share: u8,
+}
+
+impl LedgerMetadata {
+    fn from_asset(asset: &AssetMetadata, mint: &Pubkey) -> Self {
+        Self {
+            action: ASSET_METADATA_ACTION.to_string(),
+            mint: mint.to_string(),
+            name: asset.name.clone(),
+            symbol: asset.symbol.clone(),
+            uri: asset.uri.clone(),
+            description: asset.description.clone(),
+            value: asset.value,
+            issuer: asset.issuer.to_string(),
+            asset_type: asset.asset_type.clone(),
+            created_at: asset.created_at,
+            creators: asset
+                .creators
+                .iter()
+                .map(|creator| LedgerCreator {
+                    address: creator.address.to_string(),
+                    verified: creator.verified,
+                    share: creator.share,
+                })
+                .collect(),
+            seller_fee_basis_points: asset.seller_fee_basis_points,
+        }
+    }
+    
+    /// Fill the descriptive fields of mint-only `asset` from the memo
+    fn apply(self, asset: &mut AssetMetadata) {
+        asset.has_metadata = true;
+        asset.metadata_backend = Some(MetadataBackend::LedgerMemo);
+        asset.name = self.name;
+        asset.symbol = self.symbol;
+        asset.uri = self.uri;
+        asset.description = self.description;
+        asset.value = self.value;
+        asset.issuer = Pubkey::from_str(&self.issuer).unwrap_or_default();
+        asset.asset_type = self.asset_type;
+        asset.created_at = self.created_at;
+        asset.creators = self
+            .creators
+            .into_iter()
+            .filter_map(|creator| {
+                Some(AssetCreator {
+                    address: Pubkey::from_str(&creator.address).ok()?,
+                    verified: creator.verified,
+                    share: creator.share,
+                })
+            })
+            .collect();
+        asset.seller_fee_basis_points = self.seller_fee_basis_points;
+    }
+}
+
+/// The accounts an asset's metadata is read from; `None` where the account does not exist
+pub(crate) struct AssetAccounts {
+    pub mint: Option<Account>,
+    pub metadata: Option<Account>,
+    pub edition: Option<Account>,
+}
+
+impl AssetAccounts {
+    /// Hex SHA-256 over every account's data; changes whenever any of them does
+    pub(crate) fn fingerprint(&self) -> String {
+        let mut hasher = Sha256::new();
+        for account in [&self.mint, &self.metadata, &self.edition] {
+            match account {
+                Some(account) => {
+                    hasher.update([1]);
+                    hasher.update(&account.data);
+                }
+                None => hasher.update([0]),
+            }
+        }
+        hasher.finalize().iter().map(|b| format!("{:02x}", b)).collect()
+    }
+}
+
+/// Mint-level data plus whatever the Metaplex metadata and edition accounts hold
+///
+/// Makes no assumptions about how the token was minted: every string field has its padding
+/// removed and older metadata without a token standard gets one inferred.
+pub(crate) fn asset_from_accounts(token_mint: &Pubkey, accounts: &AssetAccounts) -> Result<AssetMetadata> {
+    let mint_account = accounts
+        .mint
+        .as_ref()
+        .ok_or_else(|| anyhow!("{} does not exist", token_mint))?;
+    if mint_account.owner != spl_token::id() && mint_account.owner != spl_token_2022::id() {
+        return Err(anyhow!("{} is not a mint: owned by {}", token_mint, mint_account.owner));
+    }
+    // Token-2022 mints share the SPL Token base layout, followed by optional extensions
+    let mint = StateWithExtensions::<Mint>::unpack(&mint_account.data)
+        .map_err(|e| anyhow!("{} is not a valid mint account: {}", token_mint, e))?
+        .base;
+    
+    let mut asset_metadata = AssetMetadata {
+        name: String::new(),
+        description: String::new(),
+        value: 0,
+        issuer: Pubkey::default(),
+        asset_type: AssetType::Custom("unknown".to_string()),
+        created_at: 0,
+        token_mint: Some(*token_mint),
+        creators: Vec::new(),
+        seller_fee_basis_points: 0,
+        supply: mint.supply,
+        decimals: mint.decimals,
+        mint_authority: mint.mint_authority.into(),
+        freeze_authority: mint.freeze_authority.into(),
+        is_initialized: mint.is_initialized,
+        has_metadata: false,
+        metadata_backend: None,
+        symbol: String::new(),
+        uri: String::new(),
+        token_standard: None,
+        primary_sale_happened: false,
+        rule_set: None,
+        edition: None,
+        documents: Vec::new(),
+    };
+    
+    // The edition PDA only belongs to Token Metadata once an edition has been created
+    asset_metadata.edition = accounts
+        .edition
+        .as_ref()
+        .filter(|account| account.owner == mpl_token_metadata::ID)
+        .and_then(|account| match parse_edition(&account.data) {
+            Ok(edition) => edition,
+            Err(e) => {
+                tracing::warn!("Undecodable edition account for {}: {}", token_mint, e);
+                None
+            }
+        });
+    
+    let metadata = match &accounts.metadata {
+        Some(account) => match Metadata::from_bytes(&account.data) {
+            Ok(metadata) => metadata,
+            Err(e) => {
+                tracing::warn!("Undecodable metadata account for {}: {}", token_mint, e);
+                return Ok(asset_metadata);
+            }
+        },
+        None => {
+            tracing::info!("No Metaplex metadata for {}, returning mint data only", token_mint);
+            return Ok(asset_metadata);
+        }
+    };
+    
+    // Extract creator (issuer) information - directly access metadata fields
+    let creators: Vec<AssetCreator> = metadata
+        .creators
+        .as_deref()
+        .unwrap_or_default()
+        .iter()
+        .map(to_asset_creator)
+        .collect();
+    
+    asset_metadata.has_metadata = true;
+    asset_metadata.metadata_backend = Some(MetadataBackend::Metaplex);
+    asset_metadata.name = trim_padding(&metadata.name);
+    asset_metadata.symbol = trim_padding(&metadata.symbol);
+    asset_metadata.uri = trim_padding(&metadata.uri);
+    asset_metadata.description = "Asset tokenized on Finternet".to_string(); // Placeholder until the off-chain JSON is read
+    asset_metadata.issuer = creators.first().map(|c| c.address).unwrap_or_default();
+    asset_metadata.asset_type = AssetType::Custom("tokenized_asset".to_string());
+    asset_metadata.creators = creators;
+    asset_metadata.seller_fee_basis_points = metadata.seller_fee_basis_points;
+    asset_metadata.primary_sale_happened = metadata.primary_sale_happened;
+    asset_metadata.token_standard = Some(match &metadata.token_standard {
+        Some(standard) => to_token_standard(standard),
+        None => infer_token_standard(mint.decimals, asset_metadata.edition.as_ref()),
+    });
+    asset_metadata.rule_set = match metadata.programmable_config {
+        Some(ProgrammableConfig::V1 { rule_set }) => rule_set,
+        None => None,
+    };
+    
+    Ok(asset_metadata)
+}
+
+/// Token Metadata pads fixed-size strings with NULs; some issuers pad with spaces instead
+fn trim_padding(value: &str) -> String {
+    value.trim_matches(|c: char| c == '\0' || c.is_whitespace()).to_string()
+}
+
+fn to_token_standard(standard: &MplTokenStandard) -> TokenStandard {
+    match standard {
+        MplTokenStandard::NonFungible => TokenStandard::NonFungible,
+        MplTokenStandard::NonFungibleEdition => TokenStandard::NonFungibleEdition,
+        MplTokenStandard::FungibleAsset => TokenStandard::FungibleAsset,
+        MplTokenStandard::Fungible => TokenStandard::Fungible,
+        MplTokenStandard::ProgrammableNonFungible => TokenStandard::ProgrammableNonFungible,
+        MplTokenStandard::ProgrammableNonFungibleEdition => TokenStandard::ProgrammableNonFungibleEdition,
+    }
+}
+
+/// The standard Token Metadata itself assigns to metadata written before the field existed
+fn infer_token_standard(decimals: u8, edition: Option<&Edition>) -> TokenStandard {
+    match edition {
+        Some(Edition::Master { .. }) => TokenStandard::NonFungible,
+        Some(Edition::Print { .. }) => TokenStandard::NonFungibleEdition,
+        None if decimals == 0 => TokenStandard::FungibleAsset,
+        None => TokenStandard::Fungible,
+    }
+}
+
+/// Master or print edition, told apart by the account's key byte; `None` for other keys
+fn parse_edition(data: &[u8]) -> std::io::Result<Option<Edition>> {
+    Ok(match data.first().copied() {
+        Some(EDITION_V1_KEY) => {
+            let edition = EditionAccount::from_bytes(data)?;
+            Some(Edition::Print { parent: edition.parent, number: edition.edition })
+        }
+        // V1 shares V2's leading fields, followed by printing mints this SDK does not need
+        Some(MASTER_EDITION_V1_KEY) | Some(MASTER_EDITION_V2_KEY) => {
+            let master = MasterEdition::from_bytes(data)?;
+            Some(Edition::Master { supply: master.supply, max_supply: master.max_supply })
+        }
+        _ => None,
+    })
+}
+
+/// Fill in what only the off-chain JSON knows; a failed fetch leaves the on-chain data as is
+pub(crate) async fn fill_offchain_metadata(asset_metadata: &mut AssetMetadata, uri: &str) {
+    match fetch_offchain_metadata(uri).await {
+        Ok(offchain) => {
+            asset_metadata.description = offchain.description.clone();
+            if let Some(value) = offchain.attribute("value").and_then(|v| v.as_u64()) {
+                asset_metadata.value = value;
+            }
+            if let Some(asset_type) = offchain.attribute("asset_type").and_then(|v| v.as_str()) {
+                asset_metadata.asset_type = AssetType::from(asset_type.to_string());
+            }
+            if let Some(created_at) = offchain.attribute("created_at").and_then(|v| v.as_u64()) {
+                asset_metadata.created_at = created_at;
+            }
+        }
+        Err(e) => tracing::warn!("Could not fetch off-chain metadata from {}: {}", uri, e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_cluster::MockCluster;
+    use crate::FinternetConfig;
+    use base64::{engine::general_purpose, Engine};
+    use solana_sdk::program_pack::Pack;
+    use std::path::{Path, PathBuf};
+
+    /// Metaplex account dumps in `tests/fixtures/metaplex`, each with what it must read as
+    fn fixtures() -> Vec<(String, serde_json::Value)> {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/metaplex");
+        let mut paths: Vec<PathBuf> = std::fs::read_dir(&dir).unwrap().map(|entry| entry.unwrap().path()).collect();
+        paths.sort();
+        paths
+            .into_iter()
+            .map(|path| {
+                let name = path.file_stem().unwrap().to_string_lossy().into_owned();
+                (name, serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap())
+            })
+            .collect()
+    }
+
+    fn account(dump: &serde_json::Value) -> Option<Account> {
+        let owner = Pubkey::from_str(dump.get("owner")?.as_str()?).unwrap();
+        let data = general_purpose::STANDARD.decode(dump["data"].as_str().unwrap()).unwrap();
+        Some(Account { lamports: 1_000_000, data, owner, executable: false, rent_epoch: 0 })
+    }
+
+    fn accounts(fixture: &serde_json::Value) -> AssetAccounts {
+        AssetAccounts {
+            mint: account(&fixture["mint"]),
+            metadata: account(&fixture["metadata"]),
+            edition: account(&fixture["edition"]),
+        }
+    }
+
+    fn pubkey(value: &serde_json::Value) -> Option<Pubkey> {
+        value.as_str().map(|key| Pubkey::from_str(key).unwrap())
+    }
+
+    fn edition(value: &serde_json::Value) -> Option<Edition> {
+        match value["kind"].as_str()? {
+            "master" => Some(Edition::Master { supply: value["supply"].as_u64().unwrap(), max_supply: value["max_supply"].as_u64() }),
+            "print" => Some(Edition::Print { parent: pubkey(&value["parent"]).unwrap(), number: value["number"].as_u64().unwrap() }),
+            other => panic!("unknown edition kind {}", other),
+        }
+    }
+
+    #[test]
+    fn other_issuers_metadata_reads_as_recorded() {
+        let fixtures = fixtures();
+        assert!(fixtures.len() >= 9);
+        for (name, fixture) in fixtures {
+            let mint = Pubkey::new_unique();
+            let asset = asset_from_accounts(&mint, &accounts(&fixture)).unwrap_or_else(|e| panic!("{}: {}", name, e));
+            let expected = &fixture["expected"];
+            let creators: Vec<AssetCreator> = expected["creators"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|creator| AssetCreator {
+                    address: pubkey(&creator["address"]).unwrap(),
+                    verified: creator["verified"].as_bool().unwrap(),
+                    share: creator["share"].as_u64().unwrap() as u8,
+                })
+                .collect();
+            let token_standard: Option<TokenStandard> = serde_json::from_value(expected["token_standard"].clone()).unwrap();
+
+            assert_eq!(asset.token_mint, Some(mint), "{}", name);
+            assert_eq!((asset.name.as_str(), asset.symbol.as_str(), asset.uri.as_str()), (
+                expected["name"].as_str().unwrap(),
+                expected["symbol"].as_str().unwrap(),
+                expected["uri"].as_str().unwrap(),
+            ), "{}", name);
+            assert_eq!(asset.seller_fee_basis_points as u64, expected["seller_fee_basis_points"].as_u64().unwrap(), "{}", name);
+            assert_eq!(asset.creators, creators, "{}", name);
+            assert_eq!(asset.issuer, pubkey(&expected["issuer"]).unwrap(), "{}", name);
+            assert_eq!(asset.primary_sale_happened, expected["primary_sale_happened"].as_bool().unwrap(), "{}", name);
+            assert_eq!(asset.token_standard, token_standard, "{}", name);
+            assert_eq!(asset.rule_set, pubkey(&expected["rule_set"]), "{}", name);
+            assert_eq!(asset.edition, edition(&expected["edition"]), "{}", name);
+            assert_eq!((asset.decimals as u64, asset.supply), (expected["decimals"].as_u64().unwrap(), expected["supply"].as_u64().unwrap()), "{}", name);
+            assert_eq!(asset.has_metadata, expected["has_metadata"].as_bool().unwrap(), "{}", name);
+            assert_eq!(asset.metadata_backend, asset.has_metadata.then_some(MetadataBackend::Metaplex), "{}", name);
+        }
+    }
+
+    #[test]
+    fn an_account_that_is_not_a_mint_is_refused() {
+        let (_, fixture) = fixtures().into_iter().find(|(name, _)| name == "fungible_token").unwrap();
+        let mut accounts = accounts(&fixture);
+        accounts.mint.as_mut().unwrap().owner = solana_sdk::system_program::id();
+        let error = asset_from_accounts(&Pubkey::new_unique(), &accounts).unwrap_err().to_string();
+        assert!(error.contains("is not a mint"), "{}", error);
+
+        accounts.mint = None;
+        let error = asset_from_accounts(&Pubkey::new_unique(), &accounts).unwrap_err().to_string();
+        assert!(error.contains("does not exist"), "{}", error);
+    }
+
+    #[test]
+    fn the_fingerprint_covers_the_edition_account() {
+        let (_, fixture) = fixtures().into_iter().find(|(name, _)| name == "legacy_nft_without_token_standard").unwrap();
+        let mut accounts = accounts(&fixture);
+        let before = accounts.fingerprint();
+        accounts.edition.as_mut().unwrap().data[1] = 1;
+        assert_ne!(accounts.fingerprint(), before);
+        accounts.edition = None;
+        assert_ne!(accounts.fingerprint(), before);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn accounts_are_read_in_batches_and_stay_with_their_mint() {
+        let cluster = MockCluster::new();
+        let fixtures = fixtures();
+        // 40 mints need 120 addresses, so two getMultipleAccounts calls
+        let mints: Vec<Pubkey> = (0..40).map(|_| Pubkey::new_unique()).collect();
+        for (mint, (_, fixture)) in mints.iter().zip(fixtures.iter().cycle()) {
+            let accounts = accounts(fixture);
+            for (address, account) in [
+                (*mint, accounts.mint),
+                (Metadata::find_pda(mint).0, accounts.metadata),
+                (MasterEdition::find_pda(mint).0, accounts.edition),
+            ] {
+                if let Some(account) = account {
+                    cluster.set_account(address, account);
+                }
+            }
+        }
+        let client = cluster.default_client();
+
+        let read = client.asset_accounts(&mints).unwrap();
+        assert_eq!(cluster.call_count("getMultipleAccounts"), 2);
+        assert_eq!(read.len(), mints.len());
+        for ((mint, accounts), (name, fixture)) in mints.iter().zip(&read).zip(fixtures.iter().cycle()) {
+            let asset = asset_from_accounts(mint, accounts).unwrap();
+            assert_eq!(asset.name, fixture["expected"]["name"].as_str().unwrap(), "{}", name);
+            assert_eq!(accounts.edition.is_some(), !fixture["edition"].is_null(), "{}", name);
+        }
+    }
+
+    /// A Token Metadata account in its on-chain borsh layout, with no collection, uses, or config
+    fn metadata_account(mint: &Pubkey, update_authority: &Pubkey, creators: &[(Pubkey, bool, u8)], fee: u16) -> Account {
+        fn string(data: &mut Vec<u8>, text: &str) {
+            data.extend((text.len() as u32).to_le_bytes());
+            data.extend(text.as_bytes());
+        }
+        let mut data = vec![4];
+        data.extend(update_authority.to_bytes());
+        data.extend(mint.to_bytes());
+        string(&mut data, "Harbour Receipt 12");
+        string(&mut data, "HRB");
+        string(&mut data, "");
+        data.extend(fee.to_le_bytes());
+        data.push(1);
+        data.extend((creators.len() as u32).to_le_bytes());
+        for (address, verified, share) in creators {
+            data.extend(address.to_bytes());
+            data.push(*verified as u8);
+            data.push(*share);
+        }
+        // primary_sale_happened, is_mutable, then edition_nonce, token_standard, collection,
+        // uses, collection_details, and programmable_config all absent
+        data.extend([0, 1, 0, 1, 2, 0, 0, 0, 0]);
+        Account { lamports: 5_616_720, data, owner: mpl_token_metadata::ID, executable: false, rent_epoch: 0 }
+    }
+
+    fn with_creators(creators: &[(Pubkey, u8)], fee: u16) -> TokenizeOptions {
+        TokenizeOptions { creators: creators.to_vec(), seller_fee_basis_points: fee, ..TokenizeOptions::default() }
+    }
+
+    #[test]
+    fn creator_shares_must_sum_to_exactly_100_and_the_fee_stay_within_10000() {
+        let (issuer, partner) = (Pubkey::new_unique(), Pubkey::new_unique());
+        assert!(with_creators(&[(issuer, 60), (partner, 40)], 250).validate().is_ok());
+        assert!(with_creators(&[(issuer, 100)], 10_000).validate().is_ok());
+        assert!(with_creators(&[], 0).validate().is_ok());
+
+        for (creators, total) in [(vec![(issuer, 60), (partner, 50)], 110), (vec![(issuer, 60), (partner, 39)], 99), (vec![(issuer, 0), (partner, 0)], 0)] {
+            let error = with_creators(&creators, 250).validate().unwrap_err();
+            let violations = &error.downcast_ref::<validation::ValidationError>().unwrap().violations;
+            assert_eq!(violations.len(), 1);
+            assert_eq!(violations[0].field, "creators");
+            assert!(violations[0].message.contains(&format!("got {}", total)), "{}", violations[0]);
+        }
+
+        // Every broken rule is reported at once
+        let six: Vec<(Pubkey, u8)> = (0..6).map(|_| (Pubkey::new_unique(), 10)).collect();
+        let mut creators = six.clone();
+        creators.push(six[0]);
+        let error = with_creators(&creators, 10_001).validate().unwrap_err();
+        let violations = &error.downcast_ref::<validation::ValidationError>().unwrap().violations;
+        let messages: Vec<String> = violations.iter().map(ToString::to_string).collect();
+        assert_eq!(violations.len(), 4, "{:?}", messages);
+        assert!(messages[0].starts_with("seller_fee_basis_points: 10001 exceeds"));
+        assert!(messages[1].contains("7 listed, at most 5"));
+        assert!(messages[2].contains("listed more than once"));
+        assert!(messages[3].contains("got 70"));
+    }
+
+    #[test]
+    fn only_the_signing_wallet_is_verified() {
+        let (issuer, partner) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let creators = with_creators(&[(partner, 40), (issuer, 60)], 250).metaplex_creators(&issuer);
+        let flags: Vec<(Pubkey, bool, u8)> = creators.iter().map(|c| (c.address, c.verified, c.share)).collect();
+        assert_eq!(flags, vec![(partner, false, 40), (issuer, true, 60)]);
+
+        let alone = TokenizeOptions::default().metaplex_creators(&issuer);
+        assert_eq!(alone.iter().map(|c| (c.address, c.verified, c.share)).collect::<Vec<_>>(), vec![(issuer, true, 100)]);
+
+        // An issuer who is not a listed creator verifies nobody
+        let others = with_creators(&[(partner, 100)], 0).metaplex_creators(&Pubkey::new_unique());
+        assert!(others.iter().all(|c| !c.verified));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn invalid_shares_are_refused_before_anything_is_sent() {
+        let cluster = MockCluster::new();
+        let issuer = Keypair::new();
+        cluster.set_balance(issuer.pubkey(), 1_000_000_000);
+        let client = cluster.default_client();
+
+        let options = with_creators(&[(issuer.pubkey(), 60), (Keypair::new().pubkey(), 50)], 250);
+        let error = client
+            .tokenize_asset_with_options("Warehouse Receipt", "Berth 12", 1_000, &AssetType::Commodity, &issuer, &options)
+            .await
+            .unwrap_err();
+        assert!(error.chain().any(|cause| cause.is::<validation::ValidationError>()), "{}", error);
+        assert!(cluster.sent().is_empty());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn a_second_creator_verifies_after_the_fact() {
+        let cluster = MockCluster::new();
+        let (issuer, partner) = (Keypair::new(), Keypair::new());
+        cluster.set_balance(partner.pubkey(), 1_000_000_000);
+        let mint = cluster.add_mint(&spl_token::id(), 0, &issuer.pubkey());
+        let metadata = Metadata::find_pda(&mint).0;
+        cluster.set_account(metadata, metadata_account(&mint, &issuer.pubkey(), &[(issuer.pubkey(), true, 60), (partner.pubkey(), false, 40)], 250));
+        let client = cluster.default_client();
+
+        let asset = client.get_asset_info(&mint).await.unwrap();
+        assert_eq!(asset.seller_fee_basis_points, 250);
+        assert_eq!(
+            asset.creators,
+            vec![
+                AssetCreator { address: issuer.pubkey(), verified: true, share: 60 },
+                AssetCreator { address: partner.pubkey(), verified: false, share: 40 },
+            ]
+        );
+
+        client.verify_creator(&partner, &mint).await.unwrap();
+        let sent = cluster.sent();
+        assert_eq!(sent.len(), 1);
+        let message = &sent[0].message;
+        assert_eq!(message.account_keys[0], partner.pubkey());
+        let sign = &message.instructions[0];
+        assert_eq!(message.account_keys[sign.program_id_index as usize], mpl_token_metadata::ID);
+        let accounts: Vec<Pubkey> = sign.accounts.iter().map(|index| message.account_keys[*index as usize]).collect();
+        assert_eq!(accounts, vec![metadata, partner.pubkey()]);
+
+        // Once Token Metadata has flipped the flag, both creators read as verified
+        cluster.set_account(metadata, metadata_account(&mint, &issuer.pubkey(), &[(issuer.pubkey(), true, 60), (partner.pubkey(), true, 40)], 250));
+        let asset = client.get_asset_info(&mint).await.unwrap();
+        assert!(asset.creators.iter().all(|creator| creator.verified));
+        let error = client.verify_creator(&partner, &mint).await.unwrap_err();
+        assert!(error.to_string().contains("already verified"), "{}", error);
+
+        let outsider = Keypair::new();
+        let error = client.verify_creator(&outsider, &mint).await.unwrap_err();
+        assert!(error.to_string().contains("is not a listed creator"), "{}", error);
+        assert_eq!(cluster.sent().len(), 1);
+    }
+
+    /// The 1-of-1 mint `tokenize_asset` leaves behind, which the mock cluster does not create itself
+    fn minted(cluster: &MockCluster, mint: &Pubkey) {
+        minted_with(cluster, mint, None, None);
+    }
+
+    /// `minted`, with the authorities the mint transaction left in place
+    fn minted_with(cluster: &MockCluster, mint: &Pubkey, mint_authority: Option<Pubkey>, freeze_authority: Option<Pubkey>) {
+        let state = spl_token::state::Mint {
+            mint_authority: mint_authority.into(),
+            supply: 1,
+            decimals: 0,
+            is_initialized: true,
+            freeze_authority: freeze_authority.into(),
+        };
+        let mut data = vec![0; spl_token::state::Mint::LEN];
+        spl_token::state::Mint::pack(state, &mut data).unwrap();
+        cluster.set_account(*mint, Account { lamports: 1_461_600, data, owner: spl_token::id(), executable: false, rent_epoch: 0 });
+    }
+
+    /// Post `metadata` as an asset metadata memo signed by `signer` that mentions `mint`
+    fn post_metadata(client: &FinternetClient, signer: &Keypair, mint: &Pubkey, metadata: &LedgerMetadata) {
+        let entry = client.namespaced_entry(&serde_json::to_string(metadata).unwrap()).unwrap();
+        let instructions = [
+            crate::instructions::reference_instruction(&signer.pubkey(), &[*mint]),
+            crate::instructions::ledger_entry_instruction(&signer.pubkey(), entry.as_bytes()),
+        ];
+        let transaction = Transaction::new_with_payer(&instructions, Some(&signer.pubkey()));
+        client.send_and_confirm_resigning(OperationKind::LedgerEntry, "test", transaction, &[signer]).unwrap();
+    }
+
+    #[test]
+    fn the_backend_is_detected_once_and_can_be_forced() {
+        let cluster = MockCluster::new();
+        let client = cluster.default_client();
+        assert_eq!(client.metadata_backend().unwrap(), MetadataBackend::LedgerMemo);
+        assert_eq!(client.metadata_backend().unwrap(), MetadataBackend::LedgerMemo);
+        assert_eq!(cluster.call_count("getAccountInfo"), 1);
+
+        // The answer is cached, so deploying the program later changes nothing for this client
+        let program = Account { lamports: 1, data: vec![], owner: solana_sdk::bpf_loader_upgradeable::id(), executable: true, rent_epoch: 0 };
+        cluster.set_account(mpl_token_metadata::ID, program);
+        assert_eq!(client.metadata_backend().unwrap(), MetadataBackend::LedgerMemo);
+        assert_eq!(cluster.default_client().metadata_backend().unwrap(), MetadataBackend::Metaplex);
+
+        // A forced backend wins without asking the cluster
+        for backend in [MetadataBackend::Metaplex, MetadataBackend::LedgerMemo] {
+            let cluster = MockCluster::new();
+            let client = cluster.client(FinternetConfig { metadata_backend: Some(backend), ..FinternetConfig::default() });
+            assert_eq!(client.metadata_backend().unwrap(), backend);
+            assert_eq!(cluster.call_count("getAccountInfo"), 0);
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn without_token_metadata_the_asset_is_minted_with_a_memo_and_read_back() {
+        let cluster = MockCluster::new();
+        let issuer = Keypair::new();
+        cluster.set_balance(issuer.pubkey(), 1_000_000_000);
+        let client = cluster.default_client();
+
+        let (mint, asset, signature) = client
+            .tokenize_asset("Warehouse Receipt", "Berth 12, lot 4", 25_000, &AssetType::Commodity, &issuer)
+            .await
+            .unwrap();
+        assert_eq!(asset.metadata_backend, Some(MetadataBackend::LedgerMemo));
+        assert_eq!(asset.token_standard, None);
+
+        let sent = cluster.sent();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].signatures[0], signature);
+        let programs: Vec<Pubkey> = sent[0].message.instructions.iter().map(|ix| *ix.program_id(&sent[0].message.account_keys)).collect();
+        assert!(!programs.contains(&mpl_token_metadata::ID));
+        assert!(programs.contains(&spl_memo::id()));
+
+        minted(&cluster, &mint);
+        let read = client.get_asset_info(&mint).await.unwrap();
+        assert!(read.has_metadata);
+        assert_eq!(read.metadata_backend, Some(MetadataBackend::LedgerMemo));
+        assert_eq!((read.name.as_str(), read.description.as_str(), read.value), ("Warehouse Receipt", "Berth 12, lot 4", 25_000));
+        assert_eq!((read.issuer, read.asset_type.clone()), (issuer.pubkey(), AssetType::Commodity));
+        assert_eq!((read.symbol.as_str(), read.created_at), (asset.symbol.as_str(), asset.created_at));
+        assert_eq!(read.creators, asset.creators);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn only_the_oldest_memo_signed_by_its_issuer_counts() {
+        let cluster = MockCluster::new();
+        let (issuer, forger) = (Keypair::new(), Keypair::new());
+        cluster.set_balance(issuer.pubkey(), 1_000_000_000);
+        cluster.set_balance(forger.pubkey(), 1_000_000_000);
+        let client = cluster.default_client();
+        let (_, asset, _) = client
+            .tokenize_asset("Warehouse Receipt", "Berth 12", 25_000, &AssetType::Commodity, &issuer)
+            .await
+            .unwrap();
+        let mint = Pubkey::new_unique();
+        minted(&cluster, &mint);
+
+        let named = |name: &str, issuer: &Pubkey| {
+            let mut metadata = LedgerMetadata::from_asset(&asset, &mint);
+            metadata.name = name.to_string();
+            metadata.issuer = issuer.to_string();
+            metadata
+        };
+        // Earlier entries naming the issuer without its signature, or naming the wrong mint
+        post_metadata(&client, &forger, &mint, &named("Forged", &issuer.pubkey()));
+        let mut elsewhere = named("Elsewhere", &issuer.pubkey());
+        elsewhere.mint = Pubkey::new_unique().to_string();
+        post_metadata(&client, &issuer, &mint, &elsewhere);
+        post_metadata(&client, &issuer, &mint, &named("Original", &issuer.pubkey()));
+        post_metadata(&client, &issuer, &mint, &named("Renamed", &issuer.pubkey()));
+
+        let read = client.get_asset_info(&mint).await.unwrap();
+        assert_eq!(read.name, "Original");
+        assert_eq!(read.issuer, issuer.pubkey());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn mints_that_cannot_be_ours_never_scan_for_a_memo() {
+        let cluster = MockCluster::new();
+        let client = cluster.default_client();
+        let fungible = cluster.add_mint(&spl_token::id(), 6, &Pubkey::new_unique());
+
+        let read = client.get_asset_info(&fungible).await.unwrap();
+        assert!(!read.has_metadata);
+        assert_eq!(read.metadata_backend, None);
+        assert_eq!(cluster.call_count("getSignaturesForAddress"), 0);
+
+        // A 1-of-1 mint without a memo reads as a bare mint
+        let bare = Pubkey::new_unique();
+        minted(&cluster, &bare);
+        let read = client.get_asset_info(&bare).await.unwrap();
+        assert!(!read.has_metadata);
+        assert_eq!(read.metadata_backend, None);
+    }
+
+    /// Every SPL Token instruction of `transaction`, decoded
+    fn token_instructions(transaction: &Transaction) -> Vec<spl_token::instruction::TokenInstruction<'_>> {
+        let keys = &transaction.message.account_keys;
+        transaction
+            .message
+            .instructions
+            .iter()
+            .filter(|ix| *ix.program_id(keys) == spl_token::id())
+            .map(|ix| spl_token::instruction::TokenInstruction::unpack(&ix.data).unwrap())
+            .collect()
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn a_derived_mint_can_hand_each_authority_to_a_separate_key() {
+        use spl_token::instruction::{AuthorityType, TokenInstruction};
+        let cluster = MockCluster::new();
+        let (issuer, compliance, treasury) = (Keypair::new(), Pubkey::new_unique(), Pubkey::new_unique());
+        cluster.set_balance(issuer.pubkey(), 1_000_000_000);
+        let client = cluster.default_client();
+
+        let derived = derive_mint_keypair(&issuer.pubkey(), "plot-14-deed").unwrap();
+        assert_eq!(derived.pubkey(), derive_mint_keypair(&issuer.pubkey(), "plot-14-deed").unwrap().pubkey());
+        assert_ne!(derived.pubkey(), derive_mint_keypair(&Keypair::new().pubkey(), "plot-14-deed").unwrap().pubkey());
+        assert!(derive_mint_keypair(&issuer.pubkey(), "").is_err());
+        let options = TokenizeOptions {
+            mint_keypair: Some(derived.insecure_clone()),
+            mint_authority: AuthorityCustody::Key(treasury),
+            freeze_authority: AuthorityCustody::Key(compliance),
+            ..TokenizeOptions::default()
+        };
+        let (mint, asset, _) = client
+            .tokenize_asset_with_options("Plot 14", "Harbour Road", 90_000, &AssetType::RealEstate, &issuer, &options)
+            .await
+            .unwrap();
+        // Revealing the seed proves the issuer generated the mint
+        assert_eq!(mint, derived.pubkey());
+        assert!(is_derived_mint(&mint, &issuer.pubkey(), "plot-14-deed"));
+        assert!(!is_derived_mint(&mint, &issuer.pubkey(), "plot-15-deed"));
+        assert_eq!((asset.mint_custody(), asset.freeze_custody()), (AuthorityCustody::Key(treasury), AuthorityCustody::Key(compliance)));
+
+        // Only the issuer and the mint sign; the issuer mints the token, then hands the authority over
+        let sent = cluster.sent();
+        assert_eq!(sent.len(), 1);
+        let message = &sent[0].message;
+        assert_eq!(message.header.num_required_signatures, 2);
+        assert_eq!(message.account_keys[..2], [issuer.pubkey(), mint]);
+        let instructions = token_instructions(&sent[0]);
+        let TokenInstruction::InitializeMint { decimals: 0, mint_authority, freeze_authority } = &instructions[0] else {
+            panic!("expected InitializeMint first, got {:?}", instructions[0]);
+        };
+        assert_eq!((*mint_authority, Option::from(*freeze_authority)), (issuer.pubkey(), Some(compliance)));
+        assert!(matches!(instructions[1], TokenInstruction::MintTo { amount: 1 }));
+        let TokenInstruction::SetAuthority { authority_type: AuthorityType::MintTokens, new_authority } = &instructions[2] else {
+            panic!("expected the mint authority handed over last, got {:?}", instructions[2]);
+        };
+        assert_eq!(Option::from(*new_authority), Some(treasury));
+
+        minted_with(&cluster, &mint, Some(treasury), Some(compliance));
+        let read = client.get_asset_info(&mint).await.unwrap();
+        assert_eq!(read.issuer, issuer.pubkey());
+        assert_eq!((read.mint_authority, read.freeze_authority), (Some(treasury), Some(compliance)));
+        assert_eq!((read.mint_custody(), read.freeze_custody()), (AuthorityCustody::Key(treasury), AuthorityCustody::Key(compliance)));
+
+        // A seed mints once: its account now exists, so nothing more is sent
+        let error = client
+            .tokenize_asset_with_options("Plot 14", "Harbour Road", 90_000, &AssetType::RealEstate, &issuer, &options)
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("each mint keypair or seed mints once"), "{}", error);
+        assert_eq!(cluster.sent().len(), 1);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn freezing_and_further_minting_can_be_disabled_for_good() {
+        use spl_token::instruction::{AuthorityType, TokenInstruction};
+        let cluster = MockCluster::new();
+        let issuer = Keypair::new();
+        cluster.set_balance(issuer.pubkey(), 1_000_000_000);
+        let client = cluster.default_client();
+
+        let options = TokenizeOptions {
+            mint_authority: AuthorityCustody::Disabled,
+            freeze_authority: AuthorityCustody::Disabled,
+            ..TokenizeOptions::default()
+        };
+        let (mint, asset, _) = client
+            .tokenize_asset_with_options("Invoice 2291", "Net 30", 4_800, &AssetType::Invoice, &issuer, &options)
+            .await
+            .unwrap();
+        assert_eq!((asset.mint_authority, asset.freeze_authority), (None, None));
+        let sent = cluster.sent();
+        let instructions = token_instructions(&sent[0]);
+        assert!(matches!(&instructions[0], TokenInstruction::InitializeMint { freeze_authority, .. } if freeze_authority.is_none()));
+        assert!(matches!(
+            &instructions[2],
+            TokenInstruction::SetAuthority { authority_type: AuthorityType::MintTokens, new_authority } if new_authority.is_none()
+        ));
+
+        minted_with(&cluster, &mint, None, None);
+        let read = client.get_asset_info(&mint).await.unwrap();
+        assert_eq!((read.mint_custody(), read.freeze_custody()), (AuthorityCustody::Disabled, AuthorityCustody::Disabled));
+
+        // By default the issuer keeps both, and no hand-over is sent
+        let (_, asset, _) = client
+            .tokenize_asset("Invoice 2292", "Net 30", 5_100, &AssetType::Invoice, &issuer)
+            .await
+            .unwrap();
+        assert_eq!((asset.mint_custody(), asset.freeze_custody()), (AuthorityCustody::Issuer, AuthorityCustody::Issuer));
+        let sent = cluster.sent();
+        let instructions = token_instructions(&sent[1]);
+        assert!(!instructions.iter().any(|ix| matches!(ix, TokenInstruction::SetAuthority { .. })));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn keys_the_transaction_cannot_be_signed_with_are_refused_before_sending() {
+        let cluster = MockCluster::new();
+        let issuer = Keypair::new();
+        cluster.set_balance(issuer.pubkey(), 1_000_000_000);
+        let client = cluster.default_client();
+        let tokenize = |options: TokenizeOptions| {
+            let client = &client;
+            let issuer = &issuer;
+            async move {
+                client
+                    .tokenize_asset_with_options("Plot 9", "Harbour Road", 70_000, &AssetType::RealEstate, issuer, &options)
+                    .await
+                    .unwrap_err()
+            }
+        };
+
+        let error = tokenize(TokenizeOptions { mint_keypair: Some(issuer.insecure_clone()), ..TokenizeOptions::default() }).await;
+        assert!(error.to_string().contains("must not be the issuing wallet"), "{}", error);
+
+        let mint = Keypair::new();
+        for field in ["mint_authority", "freeze_authority"] {
+            let mut options = TokenizeOptions { mint_keypair: Some(mint.insecure_clone()), ..TokenizeOptions::default() };
+            match field {
+                "mint_authority" => options.mint_authority = AuthorityCustody::Key(mint.pubkey()),
+                _ => options.freeze_authority = AuthorityCustody::Key(mint.pubkey()),
+            }
+            let error = tokenize(options).await;
+            let violations = &error.downcast_ref::<validation::ValidationError>().unwrap().violations;
+            assert_eq!(violations.len(), 1);
+            assert_eq!(violations[0].field, field);
+        }
+        assert!(cluster.sent().is_empty());
+
+        // The CLI's spelling of each choice
+        assert_eq!("issuer".parse::<AuthorityCustody>().unwrap(), AuthorityCustody::Issuer);
+        assert_eq!("NONE".parse::<AuthorityCustody>().unwrap(), AuthorityCustody::Disabled);
+        assert_eq!(mint.pubkey().to_string().parse::<AuthorityCustody>().unwrap(), AuthorityCustody::Key(mint.pubkey()));
+        assert!("compliance".parse::<AuthorityCustody>().is_err());
+    }
+}
@@ -0,0 +1,415 @@
+//! Per-mint transfer restrictions, enforced by this SDK rather than on-chain
+//!
+//! An `AssetPolicy` is checked by `send_payment` and `transfer_asset` before any transaction
+//! is built. The token program knows nothing about it: a wallet using any other client, or
+//! this one without the policy attached, can still move the tokens freely. Anchoring a policy
+//! publishes it so cooperating counterparties can fetch and honor the same rules.
+
+use crate::telemetry::readable;
+use crate::FinternetClient;
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use solana_client::rpc_config::RpcProgramAccountsConfig;
+use solana_client::rpc_filter::{Memcmp, RpcFilterType};
+use solana_sdk::{
+    program_pack::Pack,
+    pubkey::Pubkey,
+    signature::{Keypair, Signature},
+    signer::Signer,
+};
+use std::collections::{BTreeSet, HashSet};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+/// Ledger entry action under which the issuer publishes a mint's policy
+pub const ASSET_POLICY_ACTION: &str = "asset_policy";
+
+/// Who may receive a mint, checked client-side before it is sent
+///
+/// Only the receiving side is checked; sending tokens away is never refused.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "PolicyFile", into = "PolicyFile")]
+pub struct AssetPolicy {
+    pub mint: Pubkey,
+    /// When set, only these wallets may receive the mint
+    pub allowed_owners: Option<BTreeSet<Pubkey>>,
+    /// Recipients must have registered an identity with `register_identity`
+    pub require_identity: bool,
+    /// Refuse transfers that would add a holder beyond this many
+    pub max_holders: Option<u64>,
+}
+
+/// JSON layout of a policy file and ledger entry, with addresses as base58
+#[derive(Serialize, Deserialize)]
+struct PolicyFile {
+    mint: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    allowed_owners: Option<Vec<String>>,
+    #[serde(default)]
+    require_identity: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    max_holders: Option<u64>,
+}
+
+impl From<AssetPolicy> for PolicyFile {
+    fn from(policy: AssetPolicy) -> Self {
+        Self {
+            mint: policy.mint.to_string(),
+            allowed_owners: policy
+                .allowed_owners
+                .map(|owners| owners.iter().map(Pubkey::to_string).collect()),
+            require_identity: policy.require_identity,
+            max_holders: policy.max_holders,
+        }
+    }
+}
+
+impl TryFrom<PolicyFile> for AssetPolicy {
+    type Error = String;
+
+    fn try_from(file: PolicyFile) -> Result<Self, String> {
+        let address = |value: &str| Pubkey::from_str(value).map_err(|_| format!("invalid address '{}'", value));
+        Ok(Self {
+            mint: address(&file.mint)?,
+            allowed_owners: file
+                .allowed_owners
+                .map(|owners| owners.iter().map(|owner| address(owner)).collect::<Result<_, _>>())
+                .transpose()?,
+            require_identity: file.require_identity,
+            max_holders: file.max_holders,
+        })
+    }
+}
+
+impl AssetPolicy {
+    /// A policy for `mint` that allows everything until rules are added
+    pub fn new(mint: Pubkey) -> Self {
+        Self {
+            mint,
+            allowed_owners: None,
+            require_identity: false,
+            max_holders: None,
+        }
+    }
+
+    pub fn allow_owner(mut self, owner: Pubkey) -> Self {
+        self.allowed_owners.get_or_insert_with(BTreeSet::new).insert(owner);
+        self
+    }
+
+    pub fn require_identity(mut self) -> Self {
+        self.require_identity = true;
+        self
+    }
+
+    pub fn max_holders(mut self, limit: u64) -> Self {
+        self.max_holders = Some(limit);
+        self
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = fs::read_to_string(path)?;
+        serde_json::from_str(&contents).map_err(|e| anyhow!("Corrupt asset policy {}: {}", path.display(), e))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+impl fmt::Display for AssetPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Asset policy for {}", self.mint)?;
+        match &self.allowed_owners {
+            Some(owners) => writeln!(f, "  Allowed owners:   {}", owners.len())?,
+            None => writeln!(f, "  Allowed owners:   anyone")?,
+        }
+        writeln!(f, "  Require identity: {}", self.require_identity)?;
+        match self.max_holders {
+            Some(limit) => write!(f, "  Max holders:      {}", limit),
+            None => write!(f, "  Max holders:      unlimited"),
+        }
+    }
+}
+
+/// The asset policy rule a transfer broke
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AssetPolicyViolation {
+    OwnerNotAllowed { mint: Pubkey, owner: Pubkey },
+    /// The recipient has no identity registration signed by itself
+    IdentityRequired { mint: Pubkey, owner: Pubkey },
+    /// The recipient does not hold the mint yet and `limit` wallets already do
+    MaxHolders { mint: Pubkey, limit: u64, holders: u64 },
+}
+
+impl AssetPolicyViolation {
+    /// Stable machine-readable identifier for API responses
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::OwnerNotAllowed { .. } => "owner_not_allowed",
+            Self::IdentityRequired { .. } => "identity_required",
+            Self::MaxHolders { .. } => "max_holders",
+        }
+    }
+}
+
+impl fmt::Display for AssetPolicyViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::OwnerNotAllowed { mint, owner } => {
+                write!(f, "{} is not an allowed owner of {}", owner, mint)
+            }
+            Self::IdentityRequired { mint, owner } => {
+                write!(f, "{} must register an identity before receiving {}", owner, mint)
+            }
+            Self::MaxHolders { mint, limit, holders } => write!(
+                f,
+                "{} already has {} holders, the most its policy allows is {}",
+                mint, holders, limit
+            ),
+        }
+    }
+}
+
+impl std::error::Error for AssetPolicyViolation {}
+
+impl FinternetClient {
+    /// Check every payment of `policy.mint` this client sends against it, replacing any policy
+    /// already attached for that mint
+    pub fn with_asset_policy(mut self, policy: AssetPolicy) -> Self {
+        self.asset_policies.insert(policy.mint, policy);
+        self
+    }
+
+    pub fn asset_policy(&self, mint: &Pubkey) -> Option<&AssetPolicy> {
+        self.asset_policies.get(mint)
+    }
+
+    /// Refuse sending `mint` to `to` if the attached policy forbids it; a no-op without one
+    ///
+    /// Checks run cheapest first, so the holder scan only happens when the others pass.
+    pub(crate) async fn check_asset_policy(&self, to: &Pubkey, mint: &Pubkey) -> Result<()> {
+        let Some(policy) = self.asset_policies.get(mint) else {
+            return Ok(());
+        };
+
+        if let Some(allowed) = &policy.allowed_owners {
+            if !allowed.contains(to) {
+                return Err(AssetPolicyViolation::OwnerNotAllowed { mint: *mint, owner: *to }.into());
+            }
+        }
+
+        if policy.require_identity && self.get_registered_identity(to).await?.is_none() {
+            return Err(AssetPolicyViolation::IdentityRequired { mint: *mint, owner: *to }.into());
+        }
+
+        if let Some(limit) = policy.max_holders {
+            let holders = self.asset_holders(mint)?;
+            if !holders.contains(to) && holders.len() as u64 >= limit {
+                return Err(AssetPolicyViolation::MaxHolders {
+                    mint: *mint,
+                    limit,
+                    holders: holders.len() as u64,
+                }
+                .into());
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Wallets holding a non-zero balance of `mint`, from a scan of its token program
+    pub fn asset_holders(&self, mint: &Pubkey) -> Result<HashSet<Pubkey>> {
+        let token_program = self.client.get_account(mint)?.owner;
+        let config = RpcProgramAccountsConfig {
+            filters: Some(vec![RpcFilterType::Memcmp(Memcmp::new_raw_bytes(0, mint.to_bytes().to_vec()))]),
+            ..RpcProgramAccountsConfig::default()
+        };
+        let accounts = self.client.get_program_accounts_with_config(&token_program, config)?;
+
+        // Token-2022 accounts start with the same layout, followed by their extensions
+        Ok(accounts
+            .iter()
+            .filter_map(|(_, account)| account.data.get(..spl_token::state::Account::LEN))
+            .filter_map(|data| spl_token::state::Account::unpack_from_slice(data).ok())
+            .filter(|token_account| token_account.amount > 0)
+            .map(|token_account| token_account.owner)
+            .collect())
+    }
+
+    /// Publish `policy` as a ledger entry so counterparties can fetch it with `get_asset_policy`
+    ///
+    /// Only the mint's issuer (its metadata update authority) may anchor a policy, since that
+    /// is the only signer `get_asset_policy` trusts.
+    #[tracing::instrument(skip_all, fields(wallet = %readable(&issuer_wallet.pubkey()), mint = %policy.mint))]
+    pub async fn anchor_asset_policy(&self, issuer_wallet: &Keypair, policy: &AssetPolicy) -> Result<Signature> {
+        let asset = self.get_asset_info(&policy.mint).await?;
+        if !asset.has_metadata || asset.issuer != issuer_wallet.pubkey() {
+            return Err(anyhow!(
+                "Only the issuer of {} can anchor its asset policy",
+                policy.mint
+            ));
+        }
+
+        let entry = serde_json::json!({
+            "action": ASSET_POLICY_ACTION,
+            "mint": policy.mint.to_string(),
+            "policy": policy,
+        });
+        let entry = entry.to_string();
+        let compress = entry.len() > crate::chunking::MAX_SINGLE_ENTRY_LEN;
+        let signatures = self.write_ledger_entry_chunked(issuer_wallet, &entry, compress).await?;
+        let signature = signatures
+            .first()
+            .copied()
+            .ok_or_else(|| anyhow!("Asset policy entry produced no transactions"))?;
+        tracing::info!("Asset policy for {} anchored: {}", policy.mint, signature);
+        Ok(signature)
+    }
+
+    /// The most recent policy the mint's issuer anchored for it, if any
+    ///
+    /// Fetching a policy does not attach it; pass it to `with_asset_policy` to honor it.
+    #[tracing::instrument(skip_all, fields(mint = %mint))]
+    pub async fn get_asset_policy(&self, mint: &Pubkey) -> Result<Option<AssetPolicy>> {
+        let asset = self.get_asset_info(mint).await?;
+        if !asset.has_metadata {
+            return Ok(None);
+        }
+
+        // Entries are newest first; only ones the issuer signed count
+        let entries = self.read_ledger_entries(&asset.issuer, Some(1000)).await?;
+        let anchored = entries
+            .iter()
+            .filter(|entry| entry.signer == Some(asset.issuer))
+            .filter_map(|entry| entry.json())
+            .find(|value| {
+                value.get("action").and_then(|a| a.as_str()) == Some(ASSET_POLICY_ACTION)
+                    && value.get("mint").and_then(|m| m.as_str()) == Some(mint.to_string().as_str())
+            });
+
+        match anchored.and_then(|mut value| value.get_mut("policy").map(serde_json::Value::take)) {
+            Some(policy) => {
+                let policy: AssetPolicy = serde_json::from_value(policy)
+                    .map_err(|e| anyhow!("Anchored asset policy for {} is invalid: {}", mint, e))?;
+                if policy.mint != *mint {
+                    return Err(anyhow!("Anchored asset policy names mint {}, not {}", policy.mint, mint));
+                }
+                Ok(Some(policy))
+            }
+            None => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_cluster::{MockCluster, TempDir};
+    use std::collections::HashMap;
+
+    struct Parties {
+        cluster: MockCluster,
+        payer: Keypair,
+        mint: Pubkey,
+    }
+
+    fn parties() -> Parties {
+        let cluster = MockCluster::new();
+        let mint = cluster.add_mint(&spl_token::id(), 0, &Pubkey::new_unique());
+        let payer = Keypair::new();
+        cluster.set_balance(payer.pubkey(), 1_000_000_000);
+        cluster.add_token_account(&payer.pubkey(), &mint, 1_000);
+        Parties { cluster, payer, mint }
+    }
+
+    fn violation(error: anyhow::Error) -> AssetPolicyViolation {
+        error.downcast::<AssetPolicyViolation>().unwrap()
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn only_allowlisted_owners_may_receive_the_mint() {
+        let Parties { cluster, payer, mint } = parties();
+        let (allowed, stranger) = (Keypair::new().pubkey(), Keypair::new().pubkey());
+        let client = cluster.default_client().with_asset_policy(AssetPolicy::new(mint).allow_owner(allowed));
+
+        let error = client.send_payment(&payer, &stranger, 1, &mint, None).await.unwrap_err();
+        assert_eq!(violation(error), AssetPolicyViolation::OwnerNotAllowed { mint, owner: stranger });
+        assert!(cluster.sent().is_empty(), "refused before anything was built");
+        client.send_payment(&payer, &allowed, 1, &mint, None).await.unwrap();
+
+        // The policy covers its own mint only
+        let other = cluster.add_mint(&spl_token::id(), 0, &Pubkey::new_unique());
+        cluster.add_token_account(&payer.pubkey(), &other, 10);
+        client.send_payment(&payer, &stranger, 1, &other, None).await.unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn recipients_must_have_registered_an_identity_themselves() {
+        let Parties { cluster, payer, mint } = parties();
+        let recipient = Keypair::new();
+        let client = cluster.default_client().with_asset_policy(AssetPolicy::new(mint).require_identity());
+
+        let error = client.send_payment(&payer, &recipient.pubkey(), 1, &mint, None).await.unwrap_err();
+        assert_eq!(violation(error), AssetPolicyViolation::IdentityRequired { mint, owner: recipient.pubkey() });
+
+        client.register_identity(&recipient, "Treasury Desk", HashMap::new()).await.unwrap();
+        client.send_payment(&payer, &recipient.pubkey(), 1, &mint, None).await.unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn a_new_holder_beyond_the_limit_is_refused_but_existing_holders_are_not() {
+        let Parties { cluster, payer, mint } = parties();
+        let (holder, emptied, newcomer) = (Keypair::new().pubkey(), Keypair::new().pubkey(), Keypair::new().pubkey());
+        cluster.add_token_account(&holder, &mint, 5);
+        // An empty account does not make its owner a holder
+        cluster.add_token_account(&emptied, &mint, 0);
+        let client = cluster.default_client().with_asset_policy(AssetPolicy::new(mint).max_holders(2));
+        assert_eq!(client.asset_holders(&mint).unwrap(), HashSet::from([payer.pubkey(), holder]));
+
+        let error = client.send_payment(&payer, &newcomer, 1, &mint, None).await.unwrap_err();
+        assert_eq!(violation(error), AssetPolicyViolation::MaxHolders { mint, limit: 2, holders: 2 });
+        client.send_payment(&payer, &holder, 1, &mint, None).await.unwrap();
+
+        let client = cluster.default_client().with_asset_policy(AssetPolicy::new(mint).max_holders(3));
+        client.send_payment(&payer, &emptied, 1, &mint, None).await.unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn the_policy_binds_only_clients_it_is_attached_to() {
+        let Parties { cluster, payer, mint } = parties();
+        let stranger = Keypair::new().pubkey();
+        let policy = AssetPolicy::new(mint).allow_owner(Keypair::new().pubkey());
+        let bound = cluster.default_client().with_asset_policy(policy);
+        bound.send_payment(&payer, &stranger, 1, &mint, None).await.unwrap_err();
+
+        // Client-side enforcement: without the policy, the token program moves the tokens anyway
+        cluster.default_client().send_payment(&payer, &stranger, 1, &mint, None).await.unwrap();
+        assert_eq!(cluster.sent().len(), 1);
+    }
+
+    #[test]
+    fn a_policy_file_round_trips_and_bad_files_are_refused() {
+        let dir = TempDir::new("asset_policy");
+        let mint = Pubkey::new_unique();
+        let policy = AssetPolicy::new(mint)
+            .allow_owner(Pubkey::new_unique())
+            .allow_owner(Pubkey::new_unique())
+            .require_identity()
+            .max_holders(50);
+        let path = dir.join("policy.json");
+        policy.save(&path).unwrap();
+        assert_eq!(AssetPolicy::load(&path).unwrap(), policy);
+
+        // Hand-written files may leave out every rule
+        fs::write(&path, format!(r#"{{ "mint": "{}" }}"#, mint)).unwrap();
+        assert_eq!(AssetPolicy::load(&path).unwrap(), AssetPolicy::new(mint));
+
+        fs::write(&path, format!(r#"{{ "mint": "{}", "allowed_owners": ["not-an-address"] }}"#, mint)).unwrap();
+        let error = AssetPolicy::load(&path).unwrap_err().to_string();
+        assert!(error.contains("Corrupt asset policy") && error.contains("not-an-address"), "{}", error);
+    }
+}
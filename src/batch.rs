@@ -0,0 +1,841 @@
+use crate::asset::TokenizeOptions;
+use crate::confirmation::OperationKind;
+use crate::deadline::DeadlineError;
+use crate::pipeline::{SendBody, SendMiddleware, SendRequest, SendStage};
+use crate::progress::{Operation, ProgressEvent};
+use crate::rpc_options::RpcCallOptions;
+use crate::send_context::SendContext;
+use crate::telemetry::readable;
+use crate::{AssetType, FinternetClient};
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use solana_client::client_error::ClientError;
+use solana_client::rpc_client::GetConfirmedSignaturesForAddress2Config;
+use solana_sdk::{
+    commitment_config::CommitmentConfig,
+    hash::Hash,
+    pubkey::Pubkey,
+    signature::{Keypair, Signature},
+    signer::Signer,
+};
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+/// Tag appended to batch payment memos so a landed payment can be found without the journal
+const BATCH_MEMO_TAG: &str = "finternet-batch:";
+/// Leading hex digits of the action hash carried in a memo reference
+const MEMO_REFERENCE_LEN: usize = 16;
+const SIGNATURE_SCAN_LIMIT: usize = 1000;
+
+/// One payment of `send_batch_payment`, as read from a batch file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchPayment {
+    pub id: String,
+    pub to: Pubkey,
+    pub amount: u64,
+    #[serde(default)]
+    pub memo: Option<String>,
+}
+
+/// One asset of `tokenize_assets_batch`, as read from a batch file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchAsset {
+    pub id: String,
+    pub name: String,
+    pub description: String,
+    pub value: u64,
+    pub asset_type: AssetType,
+}
+
+/// What one batch item is meant to do
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum BatchAction {
+    Payment {
+        to: Pubkey,
+        amount: u64,
+        mint: Pubkey,
+        memo: Option<String>,
+    },
+    Tokenize {
+        name: String,
+        description: String,
+        value: u64,
+        asset_type: AssetType,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BatchItemStatus {
+    /// Not started, or known not to have landed; safe to run
+    Pending,
+    /// Started without a known signature (tokenization); outcome unknown until reconciled
+    InFlight,
+    /// Signed and broadcast; outcome unknown until reconciled
+    Submitted,
+    Confirmed,
+    /// Landed and failed on-chain, or rejected before broadcast
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchItem {
+    pub id: String,
+    pub action: BatchAction,
+    /// SHA-256 over the batch id, item id, and action; identifies the item on-chain
+    pub action_hash: String,
+    pub status: BatchItemStatus,
+    pub signature: Option<Signature>,
+    /// Blockhash the submitted transaction was signed against, to tell expired from in-doubt
+    pub recent_blockhash: Option<Hash>,
+    /// Mint created by a tokenize item
+    pub mint: Option<Pubkey>,
+    pub error: Option<String>,
+    pub updated_at: u64,
+}
+
+impl BatchItem {
+    fn set(&mut self, status: BatchItemStatus, error: Option<String>) {
+        self.status = status;
+        self.error = error;
+        self.updated_at = now_secs();
+    }
+
+    fn memo_reference(&self) -> String {
+        format!("{}{}", BATCH_MEMO_TAG, &self.action_hash[..MEMO_REFERENCE_LEN])
+    }
+}
+
+/// Per-item progress of a batch operation, written to disk after every change
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchJournal {
+    pub batch_id: String,
+    pub created_at: u64,
+    pub items: Vec<BatchItem>,
+}
+
+impl BatchJournal {
+    /// Start a journal with every item `Pending`; item ids must be unique
+    pub fn new(items: Vec<(String, BatchAction)>) -> Result<Self> {
+        let batch_id = uuid::Uuid::new_v4().to_string();
+        let mut seen = HashSet::new();
+        let mut journal_items = Vec::with_capacity(items.len());
+        for (id, action) in items {
+            if !seen.insert(id.clone()) {
+                return Err(anyhow!("Batch item id '{}' is used more than once", id));
+            }
+            journal_items.push(BatchItem {
+                action_hash: action_hash(&batch_id, &id, &action)?,
+                id,
+                action,
+                status: BatchItemStatus::Pending,
+                signature: None,
+                recent_blockhash: None,
+                mint: None,
+                error: None,
+                updated_at: now_secs(),
+            });
+        }
+        Ok(Self {
+            batch_id,
+            created_at: now_secs(),
+            items: journal_items,
+        })
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let data = fs::read_to_string(path)?;
+        serde_json::from_str(&data).map_err(|e| anyhow!("Corrupt batch journal {}: {}", path.display(), e))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            if !parent.as_os_str().is_empty() {
+                fs::create_dir_all(parent)?;
+            }
+        }
+        // Write-then-rename so an interrupted batch never leaves a truncated journal
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, serde_json::to_string_pretty(self)?)?;
+        fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    pub fn count(&self, status: BatchItemStatus) -> usize {
+        self.items.iter().filter(|item| item.status == status).count()
+    }
+
+    /// Every item has a final outcome
+    pub fn is_complete(&self) -> bool {
+        self.items
+            .iter()
+            .all(|item| matches!(item.status, BatchItemStatus::Confirmed | BatchItemStatus::Failed))
+    }
+
+    fn persist(&self, path: Option<&Path>) -> Result<()> {
+        match path {
+            Some(path) => self.save(path),
+            None => Ok(()),
+        }
+    }
+}
+
+/// What `reconcile_batch` decided for the items whose outcome was unknown
+#[derive(Debug, Clone, Default)]
+pub struct BatchReconciliation {
+    /// Found on-chain, including pending items that landed in an earlier unjournaled run
+    pub confirmed: Vec<String>,
+    pub failed: Vec<String>,
+    /// Never landed and can no longer land, so reset to `Pending`
+    pub reset: Vec<String>,
+    /// Broadcast with a still-valid blockhash; may yet land, so left `Submitted`
+    pub in_doubt: Vec<String>,
+}
+
+fn action_hash(batch_id: &str, id: &str, action: &BatchAction) -> Result<String> {
+    let mut hasher = Sha256::new();
+    hasher.update(batch_id.as_bytes());
+    hasher.update([0]);
+    hasher.update(id.as_bytes());
+    hasher.update([0]);
+    hasher.update(serde_json::to_vec(action)?);
+    Ok(hasher.finalize().iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// Payment memo with the item's reference appended
+fn batch_memo(item: &BatchItem, memo: Option<&str>) -> String {
+    match memo {
+        Some(memo) => format!("{} | {}", memo, item.memo_reference()),
+        None => item.memo_reference(),
+    }
+}
+
+/// Names a batch payment's signature in the journal, and saves it, before anything is broadcast
+///
+/// Runs at `PersistIntent` on every attempt, so a re-signed payment replaces the signature of
+/// the attempt that expired.
+struct JournalBatchItem<'a> {
+    journal: Mutex<&'a mut BatchJournal>,
+    index: usize,
+    path: Option<&'a Path>,
+}
+
+#[async_trait]
+impl SendMiddleware for JournalBatchItem<'_> {
+    fn name(&self) -> &str {
+        "journal_batch_item"
+    }
+
+    async fn before_send(&self, client: &FinternetClient, request: &mut SendRequest) -> Result<()> {
+        let mut journal = self.journal.lock().map_err(|_| anyhow!("Batch journal lock poisoned"))?;
+        let item = &mut journal.items[self.index];
+        item.signature = request.signature;
+        item.recent_blockhash = request.recent_blockhash;
+        item.set(BatchItemStatus::Submitted, None);
+        journal.persist(self.path)?;
+        if let Some(signature) = request.signature {
+            client.report_progress(|| ProgressEvent::Processing { operation: Operation::Batch, signature });
+        }
+        Ok(())
+    }
+}
+
+impl FinternetClient {
+    /// Send every payment in `payments`, journaling progress to `journal_path` if given
+    ///
+    /// Every payment is made in `mint`, each in its own transaction. One item failing does not
+    /// stop the rest; check the returned journal for per-item outcomes.
+    pub async fn send_batch_payment(
+        &self,
+        from_wallet: &Keypair,
+        payments: &[BatchPayment],
+        mint: &Pubkey,
+        journal_path: Option<&Path>,
+    ) -> Result<BatchJournal> {
+        self.send_batch_payment_until(from_wallet, payments, mint, journal_path, None).await
+    }
+
+    /// `send_batch_payment` that launches no payment once `deadline` is too close to make
+    ///
+    /// Payments not launched stay `Pending` in the journal, for `resume_batch` to send later.
+    pub async fn send_batch_payment_until(
+        &self,
+        from_wallet: &Keypair,
+        payments: &[BatchPayment],
+        mint: &Pubkey,
+        journal_path: Option<&Path>,
+        deadline: Option<Instant>,
+    ) -> Result<BatchJournal> {
+        let items = payments
+            .iter()
+            .map(|payment| {
+                let action = BatchAction::Payment {
+                    to: payment.to,
+                    amount: payment.amount,
+                    mint: *mint,
+                    memo: payment.memo.clone(),
+                };
+                (payment.id.clone(), action)
+            })
+            .collect();
+        let mut journal = BatchJournal::new(items)?;
+        self.run_batch_until(from_wallet, &mut journal, journal_path, deadline).await?;
+        Ok(journal)
+    }
+
+    /// Tokenize every asset in `assets`, journaling progress to `journal_path` if given
+    pub async fn tokenize_assets_batch(
+        &self,
+        wallet: &Keypair,
+        assets: &[BatchAsset],
+        journal_path: Option<&Path>,
+    ) -> Result<BatchJournal> {
+        let items = assets
+            .iter()
+            .map(|asset| {
+                let action = BatchAction::Tokenize {
+                    name: asset.name.clone(),
+                    description: asset.description.clone(),
+                    value: asset.value,
+                    asset_type: asset.asset_type.clone(),
+                };
+                (asset.id.clone(), action)
+            })
+            .collect();
+        let mut journal = BatchJournal::new(items)?;
+        self.run_batch(wallet, &mut journal, journal_path).await?;
+        Ok(journal)
+    }
+
+    /// Reconcile an interrupted batch against the chain, then run whatever is still pending
+    pub async fn resume_batch(&self, wallet: &Keypair, journal_path: &Path) -> Result<BatchJournal> {
+        let (mut journal, reconciliation) = self.reconcile_batch(journal_path, &wallet.pubkey()).await?;
+        if !reconciliation.in_doubt.is_empty() {
+            tracing::warn!(
+                "{} batch items may still land and are not retried: {}",
+                reconciliation.in_doubt.len(),
+                reconciliation.in_doubt.join(", ")
+            );
+        }
+        self.run_batch(wallet, &mut journal, Some(journal_path)).await?;
+        Ok(journal)
+    }
+
+    /// Run every `Pending` item of `journal`, saving it before and after each one
+    pub async fn run_batch(
+        &self,
+        wallet: &Keypair,
+        journal: &mut BatchJournal,
+        journal_path: Option<&Path>,
+    ) -> Result<()> {
+        self.run_batch_until(wallet, journal, journal_path, None).await
+    }
+
+    /// `run_batch`, stopping before the first item that `deadline` leaves too little time for
+    ///
+    /// Items not launched stay `Pending`. A payment broadcast but unconfirmed at the deadline
+    /// stays `Submitted`, like any other in-doubt send, for `reconcile_batch`.
+    #[tracing::instrument(skip_all, fields(wallet = %readable(&wallet.pubkey()), batch = %journal.batch_id, items = journal.items.len(), rpc = %self.rpc_endpoint()))]
+    pub async fn run_batch_until(
+        &self,
+        wallet: &Keypair,
+        journal: &mut BatchJournal,
+        journal_path: Option<&Path>,
+        deadline: Option<Instant>,
+    ) -> Result<()> {
+        journal.persist(journal_path)?;
+        let options = RpcCallOptions { deadline, ..RpcCallOptions::default() };
+
+        let pending: Vec<usize> = (0..journal.items.len())
+            .filter(|&i| journal.items[i].status == BatchItemStatus::Pending)
+            .collect();
+        let total = pending.len();
+        self.report_progress(|| ProgressEvent::Started { operation: Operation::Batch, total: Some(total) });
+        // One send context per mint, prepared on reaching its first payment
+        let mut contexts: HashMap<Pubkey, SendContext> = HashMap::new();
+
+        let mut launched = 0;
+        for (completed, &index) in pending.iter().enumerate() {
+            // Reported on reaching the next item, so every early `continue` is counted too
+            if completed > 0 {
+                self.report_progress(|| ProgressEvent::Advanced { operation: Operation::Batch, completed, total });
+            }
+            if let Err(e) = self.check_deadline("batch", &options) {
+                tracing::warn!("Batch {} stopped with {} items left: {}", journal.batch_id, total - completed, e);
+                break;
+            }
+            launched = completed + 1;
+            let action = journal.items[index].action.clone();
+            match action {
+                BatchAction::Payment { to, amount, mint, memo } => {
+                    let memo = batch_memo(&journal.items[index], memo.as_deref());
+                    let parameters = serde_json::json!({
+                        "batch": journal.batch_id,
+                        "item": journal.items[index].id,
+                        "from": wallet.pubkey().to_string(),
+                        "to": to.to_string(),
+                        "amount": amount,
+                        "mint": mint.to_string(),
+                        "memo": memo,
+                    });
+                    let context = match contexts.entry(mint) {
+                        Entry::Occupied(entry) => entry.into_mut(),
+                        Entry::Vacant(entry) => match self.prepare_batch_context(&mint, journal) {
+                            Ok(context) => entry.insert(context),
+                            Err(e) => {
+                                self.log_action("batch_payment", parameters, Some(&mint), Err(&e), Signature::to_string);
+                                journal.items[index].set(BatchItemStatus::Failed, Some(e.to_string()));
+                                journal.persist(journal_path)?;
+                                continue;
+                            }
+                        },
+                    };
+                    let request = SendRequest::payment("batch_payment", wallet.pubkey(), to, amount, mint, Some(&memo), parameters)
+                        .with_body(SendBody::Context(context.clone()))
+                        .with_options(&options);
+                    let journaling = JournalBatchItem { journal: Mutex::new(&mut *journal), index, path: journal_path };
+                    let outcome = self
+                        .send_through_pipeline_with(request, &[wallet], &[(SendStage::PersistIntent, &journaling)])
+                        .await
+                        .map(|receipt| receipt.signature);
+
+                    let item = &mut journal.items[index];
+                    match outcome {
+                        Ok(_) if self.config.confirmation.get(OperationKind::Payment).wait => {
+                            item.set(BatchItemStatus::Confirmed, None)
+                        }
+                        // Sent without waiting, so it is left for reconcile like any unconfirmed send
+                        Ok(_) => item.set(BatchItemStatus::Submitted, None),
+                        // The deadline came too close before broadcasting
+                        Err(e) if matches!(e.downcast_ref::<DeadlineError>(), Some(DeadlineError::TooLateToSend { .. })) => {
+                            item.signature = None;
+                            item.recent_blockhash = None;
+                            item.set(BatchItemStatus::Pending, None);
+                            journal.persist(journal_path)?;
+                            launched -= 1;
+                            tracing::warn!("Batch {} stopped at its deadline: {}", journal.batch_id, e);
+                            break;
+                        }
+                        // Refused before the journal named a signature, so nothing went out
+                        Err(e) if item.signature.is_none() => item.set(BatchItemStatus::Failed, Some(e.to_string())),
+                        Err(e) => {
+                            // Only on-chain failures are final; anything else is left for reconcile
+                            let on_chain = e
+                                .downcast_ref::<ClientError>()
+                                .and_then(|c| c.get_transaction_error())
+                                .is_some();
+                            let status = if on_chain { BatchItemStatus::Failed } else { BatchItemStatus::Submitted };
+                            tracing::warn!("Batch payment {} did not confirm: {}", item.id, e);
+                            item.set(status, Some(e.to_string()));
+                        }
+                    }
+                }
+                BatchAction::Tokenize { name, description, value, asset_type } => {
+                    journal.items[index].set(BatchItemStatus::InFlight, None);
+                    journal.persist(journal_path)?;
+
+                    let outcome = self
+                        .tokenize_asset_with_options(
+                            &name,
+                            &description,
+                            value,
+                            &asset_type,
+                            wallet,
+                            &TokenizeOptions::default(),
+                        )
+                        .await;
+                    let item = &mut journal.items[index];
+                    match outcome {
+                        Ok((mint, _, signature)) => {
+                            item.mint = Some(mint);
+                            item.signature = Some(signature);
+                            item.set(BatchItemStatus::Confirmed, None);
+                        }
+                        Err(e) => {
+                            tracing::warn!("Batch tokenization {} did not confirm: {}", item.id, e);
+                            let status = match e.downcast_ref::<ClientError>() {
+                                Some(c) if c.get_transaction_error().is_some() => BatchItemStatus::Failed,
+                                // Validation and publishing errors happen before any broadcast
+                                None => BatchItemStatus::Failed,
+                                Some(_) => BatchItemStatus::InFlight,
+                            };
+                            item.set(status, Some(e.to_string()));
+                        }
+                    }
+                }
+            }
+            journal.persist(journal_path)?;
+        }
+        if launched > 0 {
+            self.report_progress(|| ProgressEvent::Advanced { operation: Operation::Batch, completed: launched, total });
+        }
+        self.report_progress(|| ProgressEvent::Finished { operation: Operation::Batch, completed: launched });
+
+        tracing::info!(
+            "Batch {}: {} confirmed, {} failed, {} unresolved",
+            journal.batch_id,
+            journal.count(BatchItemStatus::Confirmed),
+            journal.count(BatchItemStatus::Failed),
+            journal.count(BatchItemStatus::Submitted) + journal.count(BatchItemStatus::InFlight)
+        );
+        Ok(())
+    }
+
+    /// Resolve every item of an interrupted batch whose outcome is unknown
+    ///
+    /// Submitted signatures are looked up directly. Items never submitted, and tokenizations
+    /// started without a known signature, are searched for on-chain: payments by the reference
+    /// in their memo, tokenizations by a mint `wallet` issued with the same name and description.
+    /// The resolved journal is written back to `journal_path` and is safe to resume from. Give
+    /// in-flight transactions a couple of minutes to expire before reconciling.
+    #[tracing::instrument(skip_all, fields(wallet = %readable(wallet), journal = %journal_path.display(), rpc = %self.rpc_endpoint()))]
+    pub async fn reconcile_batch(
+        &self,
+        journal_path: &Path,
+        wallet: &Pubkey,
+    ) -> Result<(BatchJournal, BatchReconciliation)> {
+        let mut journal = BatchJournal::load(journal_path)?;
+        let mut reconciliation = BatchReconciliation::default();
+
+        // Submitted items: ask the cluster about the journaled signatures
+        let submitted: Vec<usize> = (0..journal.items.len())
+            .filter(|&i| journal.items[i].status == BatchItemStatus::Submitted && journal.items[i].signature.is_some())
+            .collect();
+        if !submitted.is_empty() {
+            let signatures: Vec<Signature> = submitted.iter().filter_map(|&i| journal.items[i].signature).collect();
+            let mut statuses = Vec::with_capacity(signatures.len());
+            // getSignatureStatuses accepts at most 256 signatures per call
+            for chunk in signatures.chunks(256) {
+                statuses.extend(self.client.get_signature_statuses_with_history(chunk)?.value);
+            }
+
+            for (&i, status) in submitted.iter().zip(statuses) {
+                let item = &mut journal.items[i];
+                match status {
+                    Some(status) => match status.err {
+                        None => {
+                            item.set(BatchItemStatus::Confirmed, None);
+                            reconciliation.confirmed.push(item.id.clone());
+                        }
+                        Some(err) => {
+                            item.set(BatchItemStatus::Failed, Some(err.to_string()));
+                            reconciliation.failed.push(item.id.clone());
+                        }
+                    },
+                    None => {
+                        let still_valid = match item.recent_blockhash {
+                            Some(blockhash) => self.client.is_blockhash_valid(&blockhash, CommitmentConfig::processed())?,
+                            None => false,
+                        };
+                        if still_valid {
+                            reconciliation.in_doubt.push(item.id.clone());
+                        } else {
+                            item.signature = None;
+                            item.recent_blockhash = None;
+                            item.set(BatchItemStatus::Pending, Some("Expired without landing".to_string()));
+                            reconciliation.reset.push(item.id.clone());
+                        }
+                    }
+                }
+            }
+        }
+
+        // Everything else without an outcome: look for it on-chain
+        let unresolved =
+            |item: &BatchItem| matches!(item.status, BatchItemStatus::Pending | BatchItemStatus::InFlight);
+        let needs_memo_scan = journal
+            .items
+            .iter()
+            .any(|item| unresolved(item) && matches!(item.action, BatchAction::Payment { .. }));
+        let needs_issued_scan = journal
+            .items
+            .iter()
+            .any(|item| unresolved(item) && matches!(item.action, BatchAction::Tokenize { .. }));
+
+        let memos = if needs_memo_scan { self.recent_memos(wallet)? } else { Vec::new() };
+        let issued = if needs_issued_scan { self.get_issued_assets(wallet).await? } else { Vec::new() };
+        let mut claimed_mints: HashSet<Pubkey> = journal.items.iter().filter_map(|item| item.mint).collect();
+
+        for item in journal.items.iter_mut().filter(|item| unresolved(item)) {
+            let was_pending = item.status == BatchItemStatus::Pending;
+            let found = match &item.action {
+                BatchAction::Payment { .. } => {
+                    let reference = item.memo_reference();
+                    memos
+                        .iter()
+                        .find(|(_, memo)| memo.contains(&reference))
+                        .map(|(signature, _)| (*signature, None))
+                }
+                BatchAction::Tokenize { name, description, .. } => {
+                    let mut found = None;
+                    for asset in issued.iter().filter(|a| &a.name == name && !claimed_mints.contains(&a.mint)) {
+                        // Mints without off-chain JSON can only be matched by name
+                        let matches = match self.get_asset_info(&asset.mint).await {
+                            Ok(info) => info.description.is_empty() || &info.description == description,
+                            Err(_) => true,
+                        };
+                        if matches {
+                            found = asset.creation_signature.map(|signature| (signature, Some(asset.mint)));
+                            break;
+                        }
+                    }
+                    found
+                }
+            };
+
+            match found {
+                Some((signature, mint)) => {
+                    if let Some(mint) = mint {
+                        claimed_mints.insert(mint);
+                        item.mint = Some(mint);
+                    }
+                    item.signature = Some(signature);
+                    item.set(BatchItemStatus::Confirmed, None);
+                    reconciliation.confirmed.push(item.id.clone());
+                }
+                None if was_pending => {}
+                None => {
+                    item.set(BatchItemStatus::Pending, Some("Not found on-chain".to_string()));
+                    reconciliation.reset.push(item.id.clone());
+                }
+            }
+        }
+
+        journal.save(journal_path)?;
+        tracing::info!(
+            "Reconciled batch {}: {} confirmed, {} failed, {} reset to pending, {} in doubt",
+            journal.batch_id,
+            reconciliation.confirmed.len(),
+            reconciliation.failed.len(),
+            reconciliation.reset.len(),
+            reconciliation.in_doubt.len()
+        );
+        Ok((journal, reconciliation))
+    }
+
+    /// A send context for `mint`, with every pending payment's recipient in it read up front
+    fn prepare_batch_context(&self, mint: &Pubkey, journal: &BatchJournal) -> Result<SendContext> {
+        let context = self.prepare_send_context(mint)?;
+        let recipients: Vec<Pubkey> = journal
+            .items
+            .iter()
+            .filter(|item| item.status == BatchItemStatus::Pending)
+            .filter_map(|item| match &item.action {
+                BatchAction::Payment { to, mint: item_mint, .. } if item_mint == mint => Some(*to),
+                _ => None,
+            })
+            .collect();
+        self.prefetch_recipients(&context, &recipients)?;
+        Ok(context)
+    }
+
+    /// Memo text of the wallet's most recent transactions, as reported with each signature
+    fn recent_memos(&self, wallet: &Pubkey) -> Result<Vec<(Signature, String)>> {
+        let signatures = self.client.get_signatures_for_address_with_config(
+            wallet,
+            GetConfirmedSignaturesForAddress2Config {
+                limit: Some(SIGNATURE_SCAN_LIMIT),
+                commitment: Some(CommitmentConfig::confirmed()),
+                ..GetConfirmedSignaturesForAddress2Config::default()
+            },
+        )?;
+        signatures
+            .into_iter()
+            .filter(|info| info.err.is_none())
+            .filter_map(|info| Some((info.signature, info.memo?)))
+            .map(|(signature, memo)| Ok((Signature::from_str(&signature)?, memo)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_cluster::{Landing, MockCluster, TempDir};
+    use solana_sdk::instruction::InstructionError;
+    use solana_sdk::transaction::TransactionError;
+    use std::time::Duration;
+
+    /// Never lets the payment to `0` past the confirmation wait, as if the process died there
+    struct KillAt(Pubkey);
+
+    #[async_trait]
+    impl SendMiddleware for KillAt {
+        fn name(&self) -> &str {
+            "kill_at"
+        }
+
+        async fn before_send(&self, _client: &FinternetClient, request: &mut SendRequest) -> Result<()> {
+            if request.to == self.0 {
+                std::future::pending::<()>().await;
+            }
+            Ok(())
+        }
+    }
+
+    fn funded(cluster: &MockCluster) -> (Keypair, Pubkey) {
+        let mint = cluster.add_mint(&spl_token::id(), 6, &Pubkey::new_unique());
+        let payer = Keypair::new();
+        cluster.set_balance(payer.pubkey(), 1_000_000_000);
+        cluster.add_token_account(&payer.pubkey(), &mint, 100_000_000);
+        (payer, mint)
+    }
+
+    fn payments(recipients: &[Pubkey]) -> Vec<BatchPayment> {
+        recipients
+            .iter()
+            .enumerate()
+            .map(|(i, to)| BatchPayment { id: format!("p{}", i + 1), to: *to, amount: 1_000 * (i as u64 + 1), memo: None })
+            .collect()
+    }
+
+    fn status(journal: &BatchJournal, id: &str) -> BatchItemStatus {
+        journal.items.iter().find(|item| item.id == id).unwrap().status
+    }
+
+    fn sent_to(cluster: &MockCluster, recipient: &Pubkey) -> usize {
+        cluster.sent().iter().filter(|tx| tx.message.account_keys.contains(recipient)).count()
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn reconcile_classifies_every_item_of_a_batch_killed_midway() {
+        let (cluster, dir) = (MockCluster::new(), TempDir::new("batch"));
+        let (payer, mint) = funded(&cluster);
+        let path = dir.join("batch.json");
+        let to: Vec<Pubkey> = (0..7).map(|_| Keypair::new().pubkey()).collect();
+        let outcomes = to.clone();
+        cluster.land_with(move |tx| {
+            let paid = |i: usize| tx.message.account_keys.contains(&outcomes[i]);
+            if paid(1) {
+                Landing::Failed(TransactionError::InstructionError(1, InstructionError::Custom(1)))
+            } else if paid(2) || paid(3) {
+                Landing::Refused("Node is unhealthy".to_string())
+            } else if paid(4) {
+                Landing::Pending
+            } else {
+                Landing::Confirmed
+            }
+        });
+
+        // p1 confirms, p2 fails on-chain, p3 and p4 are refused by the node after being
+        // journaled, and the process dies while waiting on p5, before p6 and p7 start
+        let crashed = cluster.default_client().with_send_middleware(SendStage::Confirm, KillAt(to[4]));
+        let run = {
+            let (payer, payments, path) = (Keypair::from_bytes(&payer.to_bytes()).unwrap(), payments(&to), path.clone());
+            tokio::spawn(async move { crashed.send_batch_payment(&payer, &payments, &mint, Some(&path)).await })
+        };
+        for _ in 0..500 {
+            if sent_to(&cluster, &to[4]) > 0 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        run.abort();
+        assert!(run.await.unwrap_err().is_cancelled());
+
+        let killed = BatchJournal::load(&path).unwrap();
+        let statuses: Vec<BatchItemStatus> = killed.items.iter().map(|item| item.status).collect();
+        use BatchItemStatus::*;
+        assert_eq!(statuses, vec![Confirmed, Failed, Submitted, Submitted, Submitted, Pending, Pending]);
+        assert!(killed.items[4].signature.is_some());
+
+        // After the kill: the node forwarded p3 after all, p5 failed on-chain, and an earlier
+        // unjournaled run already paid p6 with its memo reference
+        cluster.land(&killed.items[2].signature.unwrap(), None);
+        cluster.land(
+            &killed.items[4].signature.unwrap(),
+            Some(TransactionError::InstructionError(1, InstructionError::Custom(1))),
+        );
+        let client = cluster.default_client();
+        client
+            .send_payment(&payer, &to[5], 6_000, &mint, Some(&killed.items[5].memo_reference()))
+            .await
+            .unwrap();
+
+        let (journal, reconciliation) = client.reconcile_batch(&path, &payer.pubkey()).await.unwrap();
+        assert_eq!(reconciliation.confirmed, vec!["p3", "p6"]);
+        assert_eq!(reconciliation.failed, vec!["p5"]);
+        assert_eq!(reconciliation.in_doubt, vec!["p4"]);
+        assert!(reconciliation.reset.is_empty());
+        let statuses: Vec<BatchItemStatus> = journal.items.iter().map(|item| item.status).collect();
+        assert_eq!(statuses, vec![Confirmed, Failed, Confirmed, Submitted, Failed, Confirmed, Pending]);
+        assert_eq!(BatchJournal::load(&path).unwrap().items.iter().map(|item| item.status).collect::<Vec<_>>(), statuses);
+
+        // Once p4's blockhash expires it can never land, so it is reset for a retry
+        cluster.expire_blockhashes();
+        let (journal, reconciliation) = client.reconcile_batch(&path, &payer.pubkey()).await.unwrap();
+        assert_eq!(reconciliation.reset, vec!["p4"]);
+        assert!(reconciliation.confirmed.is_empty() && reconciliation.failed.is_empty() && reconciliation.in_doubt.is_empty());
+        assert_eq!(status(&journal, "p4"), Pending);
+        assert!(journal.items[3].signature.is_none());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn resuming_sends_only_what_never_landed() {
+        let (cluster, dir) = (MockCluster::new(), TempDir::new("batch"));
+        let (payer, mint) = funded(&cluster);
+        let path = dir.join("batch.json");
+        let to: Vec<Pubkey> = (0..4).map(|_| Keypair::new().pubkey()).collect();
+        let refused = to[1];
+        cluster.land_with(move |tx| {
+            if tx.message.account_keys.contains(&refused) {
+                Landing::Refused("Node is unhealthy".to_string())
+            } else {
+                Landing::Confirmed
+            }
+        });
+
+        // The process dies waiting on p3, with p2 refused and p4 never started
+        let crashed = cluster.default_client().with_send_middleware(SendStage::Confirm, KillAt(to[2]));
+        let run = {
+            let (payer, payments, path) = (Keypair::from_bytes(&payer.to_bytes()).unwrap(), payments(&to), path.clone());
+            tokio::spawn(async move { crashed.send_batch_payment(&payer, &payments, &mint, Some(&path)).await })
+        };
+        for _ in 0..500 {
+            if sent_to(&cluster, &to[2]) > 0 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        run.abort();
+        let _ = run.await;
+
+        cluster.set_landing(Landing::Confirmed);
+        cluster.expire_blockhashes();
+        let journal = cluster.default_client().resume_batch(&payer, &path).await.unwrap();
+
+        assert!(journal.is_complete());
+        assert_eq!(journal.count(BatchItemStatus::Confirmed), 4);
+        // p1 and p3 landed before the kill and are not paid twice
+        let paid: Vec<usize> = to.iter().map(|recipient| sent_to(&cluster, recipient)).collect();
+        assert_eq!(paid, vec![1, 1, 1, 1]);
+    }
+
+    #[test]
+    fn item_ids_must_be_unique_and_hash_per_batch() {
+        let action = BatchAction::Payment { to: Pubkey::new_unique(), amount: 1, mint: Pubkey::new_unique(), memo: None };
+        let duplicated = vec![("a".to_string(), action.clone()), ("a".to_string(), action.clone())];
+        assert!(BatchJournal::new(duplicated).unwrap_err().to_string().contains("'a'"));
+
+        let items = || vec![("a".to_string(), action.clone()), ("b".to_string(), action.clone())];
+        let (first, second) = (BatchJournal::new(items()).unwrap(), BatchJournal::new(items()).unwrap());
+        // The same payment in two batches, or twice in one, carries a different memo reference
+        assert_ne!(first.items[0].memo_reference(), first.items[1].memo_reference());
+        assert_ne!(first.items[0].memo_reference(), second.items[0].memo_reference());
+    }
+}
@@ -0,0 +1,463 @@
+//! One recent blockhash shared by every transaction this client signs
+//!
+//! Fetching a blockhash per transaction doubles the RPC calls of every send, and parallel sends
+//! all race the node for the same value. `BlockhashProvider` caches one hash, refetched after
+//! `REFRESH_INTERVAL` or sooner once it nears expiry, and fetches under a lock so concurrent
+//! signers share a single request.
+
+use crate::confirmation::OperationKind;
+use crate::deadline::DeadlineError;
+use crate::dry_run::DryRunOutcome;
+use crate::latency::{timed, Phase};
+use crate::outbox::OutboxStatus;
+use crate::rpc_options::RpcCallOptions;
+use crate::FinternetClient;
+use anyhow::{anyhow, Result};
+use solana_client::client_error::ClientError;
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::{
+    clock::{DEFAULT_MS_PER_SLOT, MAX_PROCESSING_AGE},
+    commitment_config::CommitmentConfig,
+    hash::Hash,
+    signature::Signature,
+    signers::Signers,
+    transaction::{Transaction, TransactionError},
+};
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// How long a fetched blockhash is handed out before the next fetch
+const REFRESH_INTERVAL: Duration = Duration::from_secs(5);
+/// Refetch early once fewer blocks than this are estimated to remain before the hash expires
+const EXPIRY_MARGIN_BLOCKS: u64 = 60;
+/// Blockhashes tried before giving up on signing a transaction identical to one already signed
+const MAX_DISTINCT_BLOCKHASH_ATTEMPTS: usize = 10;
+/// Times a send whose blockhash expired before it landed is re-signed and sent again
+pub(crate) const MAX_EXPIRY_RESIGNS: usize = 2;
+
+#[derive(Debug, Clone, Copy)]
+struct CachedBlockhash {
+    hash: Hash,
+    last_valid_block_height: u64,
+    fetched_at: Instant,
+}
+
+impl CachedBlockhash {
+    /// Current block height, estimated from the fetch time on the assumption that the hash
+    /// was brand new when fetched
+    ///
+    /// Skipped slots and hashes fetched at `finalized` make the real height lower, so the
+    /// estimate errs towards refreshing early.
+    fn estimated_block_height(&self) -> u64 {
+        let elapsed_slots = self.fetched_at.elapsed().as_millis() as u64 / DEFAULT_MS_PER_SLOT;
+        self.last_valid_block_height.saturating_sub(MAX_PROCESSING_AGE as u64) + elapsed_slots
+    }
+
+    fn remaining_blocks(&self) -> u64 {
+        self.last_valid_block_height.saturating_sub(self.estimated_block_height())
+    }
+}
+
+#[derive(Default)]
+struct ProviderState {
+    current: Option<CachedBlockhash>,
+    /// Signatures of transactions signed against each unexpired hash, keyed with its last
+    /// valid block height
+    signed: HashMap<Hash, (u64, HashSet<Signature>)>,
+}
+
+/// Cached recent blockhash handed to every transaction the client signs
+///
+/// Two identical transactions signed against the same hash would share a signature, and the
+/// cluster would drop the second as a duplicate. The provider remembers what it signed, so the
+/// second one waits for a newer hash instead.
+#[derive(Default)]
+pub struct BlockhashProvider {
+    state: Mutex<ProviderState>,
+    fetches: AtomicU64,
+}
+
+impl BlockhashProvider {
+    /// Blockhash fetches made so far, however many transactions were signed
+    pub fn fetches(&self) -> u64 {
+        self.fetches.load(Ordering::Relaxed)
+    }
+
+    /// The cached blockhash and its last valid block height, without fetching
+    pub fn current(&self) -> Option<(Hash, u64)> {
+        let state = self.state.lock().ok()?;
+        state.current.map(|cached| (cached.hash, cached.last_valid_block_height))
+    }
+
+    /// Stop handing out `hash`, e.g. after a transaction signed against it expired
+    pub fn invalidate(&self, hash: &Hash) {
+        if let Ok(mut state) = self.state.lock() {
+            if state.current.is_some_and(|cached| cached.hash == *hash) {
+                state.current = None;
+            }
+        }
+    }
+
+    /// The cached blockhash if it is fresh and not `avoid`, else a newly fetched one
+    ///
+    /// The fetch happens under the lock, so callers arriving meanwhile wait for it and share
+    /// its result rather than fetching again.
+    fn blockhash(&self, rpc: &RpcClient, avoid: Option<Hash>) -> Result<CachedBlockhash> {
+        let mut state = self.state.lock().map_err(|_| anyhow!("Blockhash provider lock poisoned"))?;
+        let fresh = state.current.filter(|cached| {
+            cached.fetched_at.elapsed() < REFRESH_INTERVAL
+                && cached.remaining_blocks() > EXPIRY_MARGIN_BLOCKS
+                && Some(cached.hash) != avoid
+        });
+        if let Some(cached) = fresh {
+            return Ok(cached);
+        }
+
+        let (hash, last_valid_block_height) = rpc.get_latest_blockhash_with_commitment(rpc.commitment())?;
+        self.fetches.fetch_add(1, Ordering::Relaxed);
+        let cached = CachedBlockhash {
+            hash,
+            last_valid_block_height,
+            fetched_at: Instant::now(),
+        };
+        let height = cached.estimated_block_height();
+        state.signed.retain(|_, (last_valid, _)| *last_valid >= height);
+        state.current = Some(cached);
+        Ok(cached)
+    }
+
+    /// Record `signature` against `blockhash`; false when it was already signed against it
+    fn claim(&self, blockhash: &CachedBlockhash, signature: Signature) -> Result<bool> {
+        let mut state = self.state.lock().map_err(|_| anyhow!("Blockhash provider lock poisoned"))?;
+        Ok(state
+            .signed
+            .entry(blockhash.hash)
+            .or_insert_with(|| (blockhash.last_valid_block_height, HashSet::new()))
+            .1
+            .insert(signature))
+    }
+}
+
+impl FinternetClient {
+    /// The blockhash provider this client signs every transaction with
+    pub fn blockhash_provider(&self) -> &BlockhashProvider {
+        &self.blockhash
+    }
+
+    /// Sign `transaction` against the provider's blockhash, replacing any earlier signatures
+    ///
+    /// A transaction identical to one already signed against the current hash waits for a
+    /// newer one, so both can land.
+    pub(crate) fn sign_with_fresh_blockhash<T: Signers + ?Sized>(
+        &self,
+        transaction: &mut Transaction,
+        signers: &T,
+    ) -> Result<()> {
+        let mut taken = None;
+        for _ in 0..MAX_DISTINCT_BLOCKHASH_ATTEMPTS {
+            let blockhash = self.blockhash.blockhash(&self.client, taken)?;
+            transaction.try_sign(signers, blockhash.hash)?;
+            let signature = *transaction
+                .signatures
+                .first()
+                .ok_or_else(|| anyhow!("Transaction has no signers"))?;
+            if self.blockhash.claim(&blockhash, signature)? {
+                return Ok(());
+            }
+            tracing::debug!("Identical transaction already signed against {}, waiting for a newer blockhash", blockhash.hash);
+            taken = Some(blockhash.hash);
+            std::thread::sleep(Duration::from_millis(DEFAULT_MS_PER_SLOT));
+        }
+        Err(anyhow!(
+            "No new blockhash after {} attempts to sign a transaction identical to one already sent",
+            MAX_DISTINCT_BLOCKHASH_ATTEMPTS
+        ))
+    }
+
+    /// `send_and_confirm_tracked` that re-signs with `signers` and sends again when the
+    /// transaction's blockhash expires before it lands
+    ///
+    /// The transaction is first given its kind's priority fee; see `fees`. Execution errors are
+    /// never retried. The expired attempt is marked `Expired` in the
+    /// outbox, and the returned signature is that of the attempt which landed.
+    pub(crate) fn send_and_confirm_resigning<T: Signers + ?Sized>(
+        &self,
+        kind: OperationKind,
+        label: &str,
+        transaction: Transaction,
+        signers: &T,
+    ) -> Result<Signature> {
+        self.send_and_confirm_resigning_with(kind, label, transaction, signers, &RpcCallOptions::default())
+    }
+
+    /// `send_and_confirm_resigning` with per-call send and confirmation settings
+    pub(crate) fn send_and_confirm_resigning_with<T: Signers + ?Sized>(
+        &self,
+        kind: OperationKind,
+        label: &str,
+        mut transaction: Transaction,
+        signers: &T,
+        options: &RpcCallOptions,
+    ) -> Result<Signature> {
+        self.apply_priority_fee(kind, label, &mut transaction, signers)?;
+        self.send_and_confirm_resigning_as_priced(kind, label, transaction, signers, options)
+    }
+
+    /// `send_and_confirm_resigning_with` for a transaction whose priority fee is already set
+    pub(crate) fn send_and_confirm_resigning_as_priced<T: Signers + ?Sized>(
+        &self,
+        kind: OperationKind,
+        label: &str,
+        mut transaction: Transaction,
+        signers: &T,
+        options: &RpcCallOptions,
+    ) -> Result<Signature> {
+        let mut resigns = 0;
+        loop {
+            let error = match self.send_and_confirm_tracked_with(kind, label, &transaction, options) {
+                Ok(signature) => return Ok(signature),
+                Err(e) => e,
+            };
+            if !self.resign_if_expired(label, &mut transaction, signers, &error, &mut resigns, options)? {
+                return Err(error);
+            }
+        }
+    }
+
+    /// Re-sign `transaction` with a fresh blockhash when `error` means its attempt can never
+    /// land, marking that attempt `Expired` in the outbox
+    ///
+    /// Returns whether it was re-signed, which happens at most `MAX_EXPIRY_RESIGNS` times per
+    /// send as counted in `resigns`.
+    pub(crate) fn resign_if_expired<T: Signers + ?Sized>(
+        &self,
+        label: &str,
+        transaction: &mut Transaction,
+        signers: &T,
+        error: &anyhow::Error,
+        resigns: &mut usize,
+        options: &RpcCallOptions,
+    ) -> Result<bool> {
+        if *resigns == MAX_EXPIRY_RESIGNS {
+            return Ok(false);
+        }
+        match self.send_expired(transaction, error) {
+            Ok(true) => {}
+            Ok(false) => return Ok(false),
+            Err(e) => {
+                tracing::warn!("Could not tell whether {} expired: {}", label, e);
+                return Ok(false);
+            }
+        }
+
+        *resigns += 1;
+        let blockhash = transaction.message.recent_blockhash;
+        if let (Some(outbox), Some(signature)) = (&self.outbox, transaction.signatures.first()) {
+            outbox.mark(signature, OutboxStatus::Expired, None)?;
+        }
+        self.blockhash.invalidate(&blockhash);
+        tracing::warn!("{} expired before landing ({}), re-signing ({}/{})", label, error, resigns, MAX_EXPIRY_RESIGNS);
+        timed(options.phase_clock.as_ref(), Phase::Blockhash, || self.sign_with_fresh_blockhash(transaction, signers))?;
+        Ok(true)
+    }
+
+    /// Whether a failed send can never land because its blockhash expired
+    ///
+    /// A node refusing the blockhash outright counts; other execution errors never do. Anything
+    /// else, such as a confirmation timeout, only counts once the cluster has no record of the
+    /// signature and no longer accepts the blockhash. Until then the send may still land.
+    fn send_expired(&self, transaction: &Transaction, error: &anyhow::Error) -> Result<bool> {
+        // A send past its deadline is not re-signed, and one refused before broadcast never went out
+        if error.is::<DryRunOutcome>() || error.is::<DeadlineError>() {
+            return Ok(false);
+        }
+        if let Some(tx_err) = error.downcast_ref::<ClientError>().and_then(|c| c.get_transaction_error()) {
+            return Ok(tx_err == TransactionError::BlockhashNotFound);
+        }
+        let Some(signature) = transaction.signatures.first() else {
+            return Ok(false);
+        };
+        let status = self.client.get_signature_statuses_with_history(&[*signature])?.value;
+        if status.into_iter().flatten().next().is_some() {
+            return Ok(false);
+        }
+        Ok(!self
+            .client
+            .is_blockhash_valid(&transaction.message.recent_blockhash, CommitmentConfig::processed())?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_cluster::{Landing, MockCluster, TempDir};
+    use crate::FinternetConfig;
+    use solana_sdk::instruction::InstructionError;
+    use solana_sdk::pubkey::Pubkey;
+    use solana_sdk::signature::Keypair;
+    use solana_sdk::signer::Signer;
+    use solana_sdk::system_instruction;
+
+    fn transfer(payer: &Keypair, lamports: u64) -> Transaction {
+        let instruction = system_instruction::transfer(&payer.pubkey(), &Pubkey::new_unique(), lamports);
+        Transaction::new_with_payer(&[instruction], Some(&payer.pubkey()))
+    }
+
+    fn outbox_status(client: &FinternetClient, signature: &Signature) -> OutboxStatus {
+        let entries = client.outbox.as_ref().unwrap().entries().unwrap();
+        entries.iter().find(|entry| entry.signature == *signature).unwrap().status
+    }
+
+    #[test]
+    fn concurrent_sends_share_one_blockhash_and_none_expire() {
+        let cluster = MockCluster::new();
+        let client = cluster.default_client();
+        let payer = Keypair::new();
+        let mint = cluster.add_mint(&spl_token::id(), 6, &Pubkey::new_unique());
+        cluster.set_balance(payer.pubkey(), 1_000_000_000);
+        cluster.add_token_account(&payer.pubkey(), &mint, 1_000_000_000);
+
+        let results: Vec<Result<Signature>> = std::thread::scope(|scope| {
+            let sends: Vec<_> = (0..50)
+                .map(|_| {
+                    let (client, payer) = (&client, &payer);
+                    scope.spawn(move || {
+                        futures::executor::block_on(client.send_payment(payer, &Keypair::new().pubkey(), 1_000, &mint, None))
+                    })
+                })
+                .collect();
+            sends.into_iter().map(|send| send.join().unwrap()).collect()
+        });
+
+        let signatures: HashSet<Signature> = results.into_iter().map(Result::unwrap).collect();
+        assert_eq!(signatures.len(), 50);
+        // Nothing was re-signed, and every send took its hash from a handful of fetches
+        assert_eq!(cluster.sent().len(), 50);
+        let fetches = client.blockhash_provider().fetches();
+        assert!(fetches <= 3, "{} fetches for 50 sends", fetches);
+        assert_eq!(cluster.call_count("getLatestBlockhash") as u64, fetches);
+        let hashes: HashSet<Hash> = cluster.sent().iter().map(|tx| tx.message.recent_blockhash).collect();
+        assert!(hashes.len() as u64 <= fetches);
+    }
+
+    #[test]
+    fn the_cached_hash_is_refetched_once_stale_or_invalidated() {
+        let cluster = MockCluster::new();
+        let client = cluster.default_client();
+        let provider = client.blockhash_provider();
+
+        let first = provider.blockhash(&client.client, None).unwrap();
+        assert_eq!(provider.blockhash(&client.client, None).unwrap().hash, first.hash);
+        assert_eq!(provider.fetches(), 1);
+        assert_eq!(provider.current().map(|(hash, _)| hash), Some(first.hash));
+
+        let next = cluster.advance_blockhash();
+        provider.invalidate(&first.hash);
+        assert_eq!(provider.current(), None);
+        assert_eq!(provider.blockhash(&client.client, None).unwrap().hash, next);
+        assert_eq!(provider.fetches(), 2);
+
+        let latest = cluster.advance_blockhash();
+        provider.state.lock().unwrap().current.as_mut().unwrap().fetched_at =
+            Instant::now().checked_sub(REFRESH_INTERVAL).unwrap();
+        assert_eq!(provider.blockhash(&client.client, None).unwrap().hash, latest);
+        assert_eq!(provider.fetches(), 3);
+    }
+
+    #[test]
+    fn an_identical_transaction_waits_for_a_newer_blockhash() {
+        let cluster = MockCluster::new();
+        let client = cluster.default_client();
+        let payer = Keypair::new();
+        let transaction = transfer(&payer, 1);
+
+        let mut first = transaction.clone();
+        client.sign_with_fresh_blockhash(&mut first, &[&payer]).unwrap();
+        // The cluster moves on while the second copy waits for the cached hash to be avoided
+        let newer = cluster.advance_blockhash();
+        let mut second = transaction;
+        client.sign_with_fresh_blockhash(&mut second, &[&payer]).unwrap();
+
+        assert_eq!(second.message.recent_blockhash, newer);
+        assert_ne!(first.signatures[0], second.signatures[0]);
+        assert_eq!(client.blockhash_provider().fetches(), 2);
+    }
+
+    #[test]
+    fn a_send_against_an_expired_cached_hash_is_re_signed_and_lands() {
+        let cluster = MockCluster::new();
+        let dir = TempDir::new("blockhash-stale");
+        let client = cluster.client(FinternetConfig { outbox_path: Some(dir.join("outbox.json")), ..FinternetConfig::default() });
+        let payer = Keypair::new();
+        cluster.set_balance(payer.pubkey(), 1_000_000_000);
+        let stale = client.blockhash_provider().blockhash(&client.client, None).unwrap().hash;
+        cluster.expire_blockhashes();
+
+        let signature = client
+            .send_and_confirm_resigning(OperationKind::Payment, "test", transfer(&payer, 1), &[&payer])
+            .unwrap();
+
+        let sent = cluster.sent();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].signatures[0], signature);
+        assert_eq!(sent[0].message.recent_blockhash, cluster.blockhash());
+        assert_ne!(cluster.blockhash(), stale);
+        assert_eq!(outbox_status(&client, &signature), OutboxStatus::Confirmed);
+    }
+
+    #[test]
+    fn an_attempt_that_expires_while_pending_is_marked_expired_and_re_signed() {
+        let cluster = MockCluster::new();
+        let dir = TempDir::new("blockhash-pending");
+        let client = cluster.client(FinternetConfig { outbox_path: Some(dir.join("outbox.json")), ..FinternetConfig::default() });
+        let payer = Keypair::new();
+        cluster.set_balance(payer.pubkey(), 1_000_000_000);
+        let attempts = std::sync::Arc::new(AtomicU64::new(0));
+        let counted = std::sync::Arc::clone(&attempts);
+        cluster.land_with(move |_| match counted.fetch_add(1, Ordering::SeqCst) {
+            0 => Landing::Pending,
+            _ => Landing::Confirmed,
+        });
+
+        // Expire the hash once the first attempt is out and being waited on
+        let watcher = {
+            let cluster = cluster.clone();
+            std::thread::spawn(move || {
+                while cluster.sent().is_empty() {
+                    std::thread::sleep(Duration::from_millis(10));
+                }
+                cluster.expire_blockhashes();
+            })
+        };
+        let signature = client
+            .send_and_confirm_resigning(OperationKind::Payment, "test", transfer(&payer, 1), &[&payer])
+            .unwrap();
+        watcher.join().unwrap();
+
+        let sent = cluster.sent();
+        assert_eq!(sent.len(), 2);
+        assert_ne!(sent[0].message.recent_blockhash, sent[1].message.recent_blockhash);
+        assert_eq!(sent[1].signatures[0], signature);
+        assert_eq!(outbox_status(&client, &sent[0].signatures[0]), OutboxStatus::Expired);
+        assert_eq!(outbox_status(&client, &signature), OutboxStatus::Confirmed);
+    }
+
+    #[test]
+    fn an_execution_error_is_never_re_signed() {
+        let cluster = MockCluster::new();
+        let client = cluster.default_client();
+        let payer = Keypair::new();
+        cluster.set_balance(payer.pubkey(), 1_000_000_000);
+        cluster.set_landing(Landing::Failed(TransactionError::InstructionError(0, InstructionError::InsufficientFunds)));
+
+        let error = client
+            .send_and_confirm_resigning(OperationKind::Payment, "test", transfer(&payer, 1), &[&payer])
+            .unwrap_err();
+
+        assert!(error.to_string().contains("insufficient funds"), "{}", error);
+        assert_eq!(cluster.sent().len(), 1);
+        assert_eq!(client.blockhash_provider().fetches(), 1);
+    }
+}
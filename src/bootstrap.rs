@@ -0,0 +1,404 @@
+use crate::mint_registry::Network;
+use crate::telemetry::readable;
+use crate::{AssetType, Currency, FinternetClient};
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{Keypair, Signature};
+use solana_sdk::signer::Signer;
+use solana_sdk::transaction::Transaction;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+const AIRDROP_CONFIRM_SECS: u64 = 60;
+const SANITY_ASSET_TYPE: &str = "bootstrap_sanity";
+const SANITY_ASSET_NAME: &str = "Finternet Bootstrap Check";
+
+/// One stage of standing up an environment, in the order they run
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BootstrapStep {
+    Wallet,
+    Funding,
+    UsdcAccount,
+    Identity,
+    SanityAsset,
+    ReadBack,
+}
+
+impl BootstrapStep {
+    pub const ALL: [BootstrapStep; 6] = [
+        Self::Wallet,
+        Self::Funding,
+        Self::UsdcAccount,
+        Self::Identity,
+        Self::SanityAsset,
+        Self::ReadBack,
+    ];
+}
+
+impl fmt::Display for BootstrapStep {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Wallet => "wallet",
+            Self::Funding => "funding",
+            Self::UsdcAccount => "usdc_account",
+            Self::Identity => "identity",
+            Self::SanityAsset => "sanity_asset",
+            Self::ReadBack => "read_back",
+        };
+        f.write_str(name)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StepStatus {
+    /// Done by this run
+    Created,
+    /// Found already in place, by an earlier run or by hand
+    AlreadyDone,
+    /// Checked and passed; for steps that create nothing
+    Verified,
+    Failed,
+}
+
+/// What a step did or found, kept in the state file and printed in the report
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StepOutcome {
+    pub step: BootstrapStep,
+    pub status: StepStatus,
+    /// The account the step is about, if any
+    pub address: Option<String>,
+    /// Transaction that did the work, when this or an earlier run sent one
+    pub signature: Option<String>,
+    pub detail: String,
+}
+
+/// What `FinternetClient::bootstrap` should set up
+#[derive(Debug, Clone)]
+pub struct BootstrapOptions {
+    /// Keypair to use, created here if the file does not exist
+    pub keypair_path: PathBuf,
+    /// Progress is saved here after every step so a failed run can be re-run to finish
+    pub state_path: PathBuf,
+    /// Airdrop when the wallet holds less than this many lamports
+    pub min_lamports: u64,
+    pub airdrop_lamports: u64,
+    pub identity_name: String,
+    pub identity_metadata: HashMap<String, String>,
+    /// Also check the sanity asset through a running API server, e.g. `http://localhost:3000`
+    pub api_url: Option<String>,
+}
+
+impl BootstrapOptions {
+    pub fn new(keypair_path: impl Into<PathBuf>, state_path: impl Into<PathBuf>) -> Self {
+        Self {
+            keypair_path: keypair_path.into(),
+            state_path: state_path.into(),
+            min_lamports: 500_000_000,
+            airdrop_lamports: 1_000_000_000,
+            identity_name: "Finternet Deployment".to_string(),
+            identity_metadata: HashMap::from([("registration_method".to_string(), "bootstrap".to_string())]),
+            api_url: None,
+        }
+    }
+}
+
+/// Saved between runs: the wallet and whatever each step last reported
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct BootstrapState {
+    wallet: Option<String>,
+    steps: BTreeMap<BootstrapStep, StepOutcome>,
+}
+
+/// Every step of a finished bootstrap, with the addresses and signatures it involved
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BootstrapReport {
+    pub wallet: String,
+    pub keypair_path: PathBuf,
+    pub rpc_url: String,
+    pub steps: Vec<StepOutcome>,
+    pub sanity_asset: Option<String>,
+    /// Every step succeeded
+    pub ready: bool,
+}
+
+impl FinternetClient {
+    /// Stand up a deployment: wallet, SOL, USDC account, identity, and a read-back-checked asset
+    ///
+    /// Each step first checks whether its work is already done and skips it if so, so running
+    /// this again is harmless. The state file is written after every step; when a step fails,
+    /// the error is recorded there and returned, and re-running picks up from the same wallet
+    /// and sanity asset. Refuses to run against mainnet.
+    pub async fn bootstrap(&self, options: &BootstrapOptions) -> Result<BootstrapReport> {
+        if self.network()? == Some(Network::Mainnet) {
+            return Err(anyhow!("Refusing to bootstrap against mainnet ({})", self.config.rpc_url));
+        }
+
+        let mut state = load_state(&options.state_path)?;
+        let (wallet, wallet_outcome) = self.bootstrap_wallet(options, &state)?;
+        record(&mut state, &options.state_path, Some(wallet.pubkey().to_string()), wallet_outcome)?;
+
+        for step in &BootstrapStep::ALL[1..] {
+            let previous = state.steps.get(step).cloned();
+            let sanity_asset = state.steps.get(&BootstrapStep::SanityAsset).and_then(|o| o.address.clone());
+            let result = match step {
+                BootstrapStep::Funding => self.bootstrap_funding(options, &wallet).await,
+                BootstrapStep::UsdcAccount => self.bootstrap_usdc_account(&wallet, previous.as_ref()),
+                BootstrapStep::Identity => self.bootstrap_identity(options, &wallet, previous.as_ref()).await,
+                BootstrapStep::SanityAsset => self.bootstrap_sanity_asset(&wallet, previous.as_ref()).await,
+                BootstrapStep::ReadBack => self.bootstrap_read_back(options, sanity_asset).await,
+                BootstrapStep::Wallet => unreachable!("wallet step runs first"),
+            };
+            match result {
+                Ok(outcome) => record(&mut state, &options.state_path, None, outcome)?,
+                Err(e) => {
+                    let failed = StepOutcome {
+                        step: *step,
+                        status: StepStatus::Failed,
+                        address: previous.as_ref().and_then(|o| o.address.clone()),
+                        signature: previous.as_ref().and_then(|o| o.signature.clone()),
+                        detail: e.to_string(),
+                    };
+                    record(&mut state, &options.state_path, None, failed)?;
+                    return Err(e.context(format!(
+                        "Bootstrap step '{}' failed; progress saved to {}, re-run to finish",
+                        step,
+                        options.state_path.display()
+                    )));
+                }
+            }
+        }
+
+        let steps: Vec<StepOutcome> = BootstrapStep::ALL.iter().filter_map(|step| state.steps.get(step).cloned()).collect();
+        Ok(BootstrapReport {
+            wallet: wallet.pubkey().to_string(),
+            keypair_path: options.keypair_path.clone(),
+            rpc_url: self.config.rpc_url.clone(),
+            sanity_asset: state.steps.get(&BootstrapStep::SanityAsset).and_then(|o| o.address.clone()),
+            ready: steps.len() == BootstrapStep::ALL.len() && steps.iter().all(|o| o.status != StepStatus::Failed),
+            steps,
+        })
+    }
+
+    fn bootstrap_wallet(&self, options: &BootstrapOptions, state: &BootstrapState) -> Result<(Keypair, StepOutcome)> {
+        let path = &options.keypair_path;
+        let (wallet, status, detail) = if path.exists() {
+            let wallet = Self::load_wallet_from_file(path)?;
+            (wallet, StepStatus::AlreadyDone, format!("Loaded {}", path.display()))
+        } else {
+            let wallet = Self::create_new_wallet();
+            Self::save_wallet_to_file(&wallet, path)?;
+            (wallet, StepStatus::Created, format!("Created {}", path.display()))
+        };
+        if let Some(recorded) = &state.wallet {
+            if *recorded != wallet.pubkey().to_string() {
+                return Err(anyhow!(
+                    "State file {} belongs to wallet {}, but {} holds {}; use a fresh state file",
+                    options.state_path.display(),
+                    recorded,
+                    path.display(),
+                    wallet.pubkey()
+                ));
+            }
+        }
+        let outcome = StepOutcome {
+            step: BootstrapStep::Wallet,
+            status,
+            address: Some(wallet.pubkey().to_string()),
+            signature: None,
+            detail,
+        };
+        Ok((wallet, outcome))
+    }
+
+    async fn bootstrap_funding(&self, options: &BootstrapOptions, wallet: &Keypair) -> Result<StepOutcome> {
+        let outcome = |status, signature: Option<Signature>, balance: u64| StepOutcome {
+            step: BootstrapStep::Funding,
+            status,
+            address: Some(wallet.pubkey().to_string()),
+            signature: signature.map(|s| s.to_string()),
+            detail: format!("Balance {}", Currency::Sol.format_amount(balance)),
+        };
+        let balance = self.client.get_balance(&wallet.pubkey())?;
+        if balance >= options.min_lamports {
+            return Ok(outcome(StepStatus::AlreadyDone, None, balance));
+        }
+
+        if self.is_dry_run() {
+            return Err(anyhow!("Dry run: not requesting an airdrop to {}; fund it manually", wallet.pubkey()));
+        }
+        let signature = self
+            .client
+            .request_airdrop(&wallet.pubkey(), options.airdrop_lamports)
+            .map_err(|e| anyhow!("Airdrop to {} failed ({}); fund it manually and re-run", wallet.pubkey(), e))?;
+        for _ in 0..AIRDROP_CONFIRM_SECS {
+            if self.client.confirm_transaction(&signature)? {
+                break;
+            }
+            tokio::time::sleep(Duration::from_secs(1)).await;
+        }
+        let balance = self.client.get_balance(&wallet.pubkey())?;
+        if balance < options.min_lamports {
+            return Err(anyhow!(
+                "Wallet holds {} after airdrop {}, at least {} is needed",
+                Currency::Sol.format_amount(balance),
+                signature,
+                Currency::Sol.format_amount(options.min_lamports)
+            ));
+        }
+        Ok(outcome(StepStatus::Created, Some(signature), balance))
+    }
+
+    fn bootstrap_usdc_account(&self, wallet: &Keypair, previous: Option<&StepOutcome>) -> Result<StepOutcome> {
+        let mint = self.usdc_mint()?;
+        let ata = spl_associated_token_account::get_associated_token_address(&wallet.pubkey(), &mint);
+        let outcome = |status, signature: Option<String>| StepOutcome {
+            step: BootstrapStep::UsdcAccount,
+            status,
+            address: Some(ata.to_string()),
+            signature,
+            detail: format!("USDC account for mint {}", mint),
+        };
+        if self.client.get_account(&ata).is_ok() {
+            return Ok(outcome(StepStatus::AlreadyDone, previous.and_then(|o| o.signature.clone())));
+        }
+
+        let instruction = spl_associated_token_account::instruction::create_associated_token_account_idempotent(
+            &wallet.pubkey(),
+            &wallet.pubkey(),
+            &mint,
+            &spl_token::id(),
+        );
+        let mut transaction = Transaction::new_with_payer(&[instruction], Some(&wallet.pubkey()));
+        self.sign_with_fresh_blockhash(&mut transaction, &[wallet])?;
+        let signature = self.send_and_confirm_untracked("bootstrap_usdc_account", &transaction)?;
+        Ok(outcome(StepStatus::Created, Some(signature.to_string())))
+    }
+
+    async fn bootstrap_identity(
+        &self,
+        options: &BootstrapOptions,
+        wallet: &Keypair,
+        previous: Option<&StepOutcome>,
+    ) -> Result<StepOutcome> {
+        let outcome = |status, signature: Option<String>, name: String| StepOutcome {
+            step: BootstrapStep::Identity,
+            status,
+            address: Some(wallet.pubkey().to_string()),
+            signature,
+            detail: format!("Registered as '{}'", name),
+        };
+        if let Some(identity) = self.get_registered_identity(&wallet.pubkey()).await? {
+            let name = identity.display_name.unwrap_or_default();
+            return Ok(outcome(StepStatus::AlreadyDone, previous.and_then(|o| o.signature.clone()), name));
+        }
+        let signature = self
+            .register_identity(wallet, &options.identity_name, options.identity_metadata.clone())
+            .await?;
+        Ok(outcome(StepStatus::Created, Some(signature.to_string()), options.identity_name.clone()))
+    }
+
+    async fn bootstrap_sanity_asset(&self, wallet: &Keypair, previous: Option<&StepOutcome>) -> Result<StepOutcome> {
+        let outcome = |status, mint: Pubkey, signature: Option<String>| StepOutcome {
+            step: BootstrapStep::SanityAsset,
+            status,
+            address: Some(mint.to_string()),
+            signature,
+            detail: SANITY_ASSET_NAME.to_string(),
+        };
+        let asset_type = AssetType::Custom(SANITY_ASSET_TYPE.to_string());
+        let held = self.get_owned_assets_by_type(&wallet.pubkey(), &asset_type).await?;
+        let recorded = previous.and_then(|o| o.address.as_deref()).and_then(|a| Pubkey::from_str(a).ok());
+        let existing = held
+            .iter()
+            .find(|(mint, _, _)| Some(*mint) == recorded)
+            .or_else(|| held.iter().find(|(_, _, info)| info.name == SANITY_ASSET_NAME));
+        if let Some((mint, _, _)) = existing {
+            let signature = previous
+                .filter(|o| o.address.as_deref() == Some(mint.to_string().as_str()))
+                .and_then(|o| o.signature.clone());
+            return Ok(outcome(StepStatus::AlreadyDone, *mint, signature));
+        }
+
+        let (mint, _, signature) = self
+            .tokenize_asset(
+                SANITY_ASSET_NAME,
+                "Minted by bootstrap to check that tokenization and read-back work",
+                1,
+                &asset_type,
+                wallet,
+            )
+            .await?;
+        Ok(outcome(StepStatus::Created, mint, Some(signature.to_string())))
+    }
+
+    /// Read the sanity asset back through the SDK and, when configured, through the API server
+    async fn bootstrap_read_back(&self, options: &BootstrapOptions, sanity_asset: Option<String>) -> Result<StepOutcome> {
+        let mint = sanity_asset.ok_or_else(|| anyhow!("No sanity asset to read back"))?;
+        let mint = Pubkey::from_str(&mint).map_err(|_| anyhow!("Invalid sanity asset address '{}' in state", mint))?;
+        let info = self.get_asset_info(&mint).await?;
+        if info.name != SANITY_ASSET_NAME {
+            return Err(anyhow!("Asset {} reads back as '{}', expected '{}'", mint, info.name, SANITY_ASSET_NAME));
+        }
+        let mut detail = format!("{} read back through the SDK", readable(&mint));
+
+        if let Some(api_url) = &options.api_url {
+            let url = format!("{}/api/asset/{}", api_url.trim_end_matches('/'), mint);
+            let response = reqwest::Client::new()
+                .get(&url)
+                .timeout(Duration::from_secs(30))
+                .send()
+                .await
+                .and_then(|r| r.error_for_status())
+                .map_err(|e| anyhow!("API read-back from {} failed: {}", url, e))?;
+            let body: serde_json::Value = response.json().await?;
+            let name = body.get("name").and_then(|n| n.as_str()).unwrap_or_default();
+            if name != SANITY_ASSET_NAME {
+                return Err(anyhow!("API at {} returned '{}' for {}, expected '{}'", api_url, name, mint, SANITY_ASSET_NAME));
+            }
+            detail.push_str(&format!(" and the API at {}", api_url));
+        }
+
+        Ok(StepOutcome {
+            step: BootstrapStep::ReadBack,
+            status: StepStatus::Verified,
+            address: Some(mint.to_string()),
+            signature: None,
+            detail,
+        })
+    }
+}
+
+fn load_state(path: &Path) -> Result<BootstrapState> {
+    if !path.exists() {
+        return Ok(BootstrapState::default());
+    }
+    let data = fs::read_to_string(path)?;
+    if data.trim().is_empty() {
+        return Ok(BootstrapState::default());
+    }
+    serde_json::from_str(&data).map_err(|e| anyhow!("Corrupt bootstrap state {}: {}", path.display(), e))
+}
+
+/// Store a step's outcome and write the state file
+fn record(state: &mut BootstrapState, path: &Path, wallet: Option<String>, outcome: StepOutcome) -> Result<()> {
+    if wallet.is_some() {
+        state.wallet = wallet;
+    }
+    state.steps.insert(outcome.step, outcome);
+    if let Some(parent) = path.parent() {
+        if !parent.as_os_str().is_empty() {
+            fs::create_dir_all(parent)?;
+        }
+    }
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, serde_json::to_string_pretty(state)?)?;
+    fs::rename(&tmp, path)?;
+    Ok(())
+}
@@ -0,0 +1,128 @@
+use crate::asset::{asset_from_accounts, fill_offchain_metadata};
+use crate::freshness::WalletCache;
+use crate::resources::{approximate_bytes, CacheUsage, LruMap, ResourceLimits};
+use crate::telemetry::readable;
+use crate::{AssetMetadata, FinternetClient};
+use anyhow::{anyhow, Result};
+use solana_sdk::pubkey::Pubkey;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// How long cached asset metadata is served before its accounts are read again
+const ASSET_CACHE_TTL: Duration = Duration::from_secs(60);
+
+/// Asset metadata with a fingerprint of the accounts it was read from
+#[derive(Debug, Clone)]
+pub struct CachedAsset {
+    pub metadata: AssetMetadata,
+    /// Hex SHA-256 of the mint, metadata, and edition account data; changes whenever any does
+    pub etag: String,
+}
+
+/// Asset metadata shared by every call on a client
+///
+/// Entries are served for a minute. After that the accounts are read again, and the off-chain
+/// JSON is only fetched again if the account data changed. Past `ResourceLimits::asset_cache`
+/// the least recently requested mints are dropped.
+#[derive(Default)]
+pub(crate) struct ClientCache {
+    assets: Mutex<LruMap<Pubkey, (Instant, CachedAsset)>>,
+    /// Wallet balances, for reads that accept a cached value
+    pub(crate) wallets: WalletCache,
+}
+
+impl ClientCache {
+    pub(crate) fn new(limits: &ResourceLimits) -> Self {
+        Self {
+            assets: Mutex::new(LruMap::new(limits.asset_cache)),
+            wallets: WalletCache::new(limits.wallet_cache),
+        }
+    }
+
+    pub(crate) fn asset_usage(&self) -> CacheUsage {
+        self.assets.lock().map(|assets| assets.usage()).unwrap_or_default()
+    }
+}
+
+impl FinternetClient {
+    /// Metadata for each of `mints`, in order, from the client's cache where it is fresh
+    ///
+    /// Misses are read with batched `getMultipleAccounts` calls and their off-chain JSON is
+    /// fetched concurrently. A mint that cannot be read gets its own error rather than
+    /// failing the batch; only an RPC failure does that.
+    #[tracing::instrument(skip_all, fields(mints = mints.len(), rpc = %self.rpc_endpoint()))]
+    pub async fn get_assets_metadata(&self, mints: &[Pubkey]) -> Result<Vec<Result<CachedAsset>>> {
+        // Answered from here rather than the cache, which may evict part of a large batch
+        let mut results: HashMap<Pubkey, CachedAsset> = HashMap::new();
+        let mut stale = HashMap::new();
+        let mut misses = Vec::new();
+        {
+            let mut cache = self.cache.assets.lock().map_err(|_| anyhow!("Asset cache lock poisoned"))?;
+            for mint in mints {
+                match cache.get(mint) {
+                    Some((fetched_at, cached)) if fetched_at.elapsed() < ASSET_CACHE_TTL => {
+                        results.insert(*mint, cached.clone());
+                        continue;
+                    }
+                    Some((_, cached)) => {
+                        stale.insert(*mint, cached.clone());
+                    }
+                    None => {}
+                }
+                if !misses.contains(mint) {
+                    misses.push(*mint);
+                }
+            }
+        }
+        tracing::info!("Asset metadata: {} requested, {} to fetch", mints.len(), misses.len());
+
+        let mut fetched = HashMap::new();
+        let mut failed = HashMap::new();
+        let mut pending = Vec::new();
+        let accounts = self.asset_accounts(&misses)?;
+        for (mint, accounts) in misses.iter().zip(accounts) {
+            let etag = accounts.fingerprint();
+            if let Some(cached) = stale.remove(mint).filter(|cached| cached.etag == etag) {
+                fetched.insert(*mint, cached);
+                continue;
+            }
+            match asset_from_accounts(mint, &accounts) {
+                Ok(metadata) => pending.push((*mint, metadata, etag)),
+                Err(e) => {
+                    failed.insert(*mint, e.to_string());
+                }
+            }
+        }
+
+        let completed = futures::future::join_all(pending.into_iter().map(|(mint, mut metadata, etag)| async move {
+            if !metadata.uri.is_empty() {
+                let uri = metadata.uri.clone();
+                fill_offchain_metadata(&mut metadata, &uri).await;
+            }
+            (mint, CachedAsset { metadata, etag })
+        }))
+        .await;
+        fetched.extend(completed);
+
+        let mut cache = self.cache.assets.lock().map_err(|_| anyhow!("Asset cache lock poisoned"))?;
+        let now = Instant::now();
+        for (mint, asset) in fetched {
+            let bytes = approximate_bytes(&asset.metadata) + asset.etag.len();
+            results.insert(mint, asset.clone());
+            cache.insert(mint, (now, asset), bytes);
+        }
+        for mint in failed.keys() {
+            cache.remove(mint);
+        }
+        Ok(mints
+            .iter()
+            .map(|mint| match (results.get(mint), failed.get(mint)) {
+                (Some(asset), _) => Ok(asset.clone()),
+                (None, Some(reason)) => Err(anyhow!("{}", reason)),
+                (None, None) => Err(anyhow!("No metadata fetched for {}", readable(mint))),
+            })
+            .collect())
+    }
+}
+
@@ -0,0 +1,166 @@
+//! What this build and this client can do, so callers feature-detect instead of assuming
+//!
+//! `FinternetClient::capabilities` reports the SDK version, the cargo features compiled in, the
+//! cluster the RPC node serves, and the runtime modes the config switched on. Optional
+//! functionality a build does not have is simply absent from `features`, never listed as off.
+//! The API server answers `GET /api/capabilities` with the same struct, filling in its own
+//! limits and whether it is read-only.
+
+use crate::currency::Currency;
+use crate::mint_registry::{self, Network};
+use crate::validation::MAX_MEMO_BYTES;
+use crate::FinternetClient;
+use serde::{Deserialize, Serialize};
+
+/// Cargo features this build was compiled with, as named in `Cargo.toml`
+pub fn compiled_features() -> Vec<String> {
+    let mut features = Vec::new();
+    if cfg!(feature = "metrics") {
+        features.push("metrics".to_string());
+    }
+    if cfg!(feature = "sqlite") {
+        features.push("sqlite".to_string());
+    }
+    features
+}
+
+/// Token programs every transfer, balance, and asset lookup handles
+pub const TOKEN_PROGRAMS: &[&str] = &["spl_token", "spl_token_2022"];
+
+/// Bounds a caller should stay within; `None` means the SDK sets none
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Limits {
+    pub max_memo_bytes: usize,
+    /// Most items one batch request may name
+    pub max_batch_size: Option<usize>,
+    pub max_page_size: Option<usize>,
+    /// Requests per minute per API key
+    pub requests_per_minute: Option<u32>,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_memo_bytes: MAX_MEMO_BYTES,
+            max_batch_size: None,
+            max_page_size: None,
+            requests_per_minute: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Capabilities {
+    /// SDK crate version, e.g. `0.1.0`
+    pub version: String,
+    /// See `compiled_features`
+    pub features: Vec<String>,
+    /// `None` for a private cluster or an RPC node that did not answer
+    pub network: Option<Network>,
+    /// SOL and the registry's mints for `network`
+    pub currencies: Vec<Currency>,
+    pub token_programs: Vec<String>,
+    pub dry_run: bool,
+    /// Only reads are served; the SDK itself never sets this
+    pub read_only: bool,
+    pub limits: Limits,
+}
+
+impl Capabilities {
+    /// Whether `feature` was compiled in
+    pub fn has_feature(&self, feature: &str) -> bool {
+        self.features.iter().any(|f| f == feature)
+    }
+
+    /// Whether payments in `currency` are known to work on this cluster
+    pub fn supports_currency(&self, currency: &Currency) -> bool {
+        self.currencies.iter().any(|c| c.mint() == currency.mint())
+    }
+}
+
+impl FinternetClient {
+    /// This build's and this client's capabilities
+    ///
+    /// Reads the cluster once, like `network`; if the RPC node cannot be reached the network is
+    /// reported as `None` and only mints valid on every cluster are listed.
+    pub fn capabilities(&self) -> Capabilities {
+        let network = self.network().unwrap_or_else(|e| {
+            tracing::warn!("Could not read the cluster for capabilities: {}", e);
+            None
+        });
+        let currencies = std::iter::once(Currency::Sol)
+            .chain(mint_registry::on_network(network).into_iter().map(|known| Currency::from_mint(known.mint)))
+            .collect();
+        Capabilities {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            features: compiled_features(),
+            network,
+            currencies,
+            token_programs: TOKEN_PROGRAMS.iter().map(|p| p.to_string()).collect(),
+            dry_run: self.is_dry_run(),
+            read_only: false,
+            limits: Limits::default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_cluster::MockCluster;
+    use crate::payment::usdc;
+    use crate::FinternetConfig;
+    use solana_sdk::{hash::Hash, pubkey::Pubkey};
+    use std::str::FromStr;
+
+    /// Devnet's genesis hash, so the mock cluster reads as devnet
+    const DEVNET_GENESIS_HASH: &str = "EtWTRABZaYq6iMfeYKouRu166VU2xqa1wcaWoxPkrZBG";
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn capabilities_report_the_build_features_and_the_cluster_the_client_is_on() {
+        let cluster = MockCluster::new();
+        cluster.set_genesis_hash(Hash::from_str(DEVNET_GENESIS_HASH).unwrap());
+        let client = cluster.default_client();
+        let capabilities = client.capabilities();
+
+        assert_eq!(capabilities.version, env!("CARGO_PKG_VERSION"));
+        assert_eq!(capabilities.features, compiled_features());
+        assert_eq!(capabilities.has_feature("metrics"), cfg!(feature = "metrics"));
+        // Features this crate does not have are absent, not listed as off
+        for absent in ["swap", "webhooks", "sponsorship"] {
+            assert!(!capabilities.has_feature(absent), "{}", absent);
+        }
+        assert_eq!(capabilities.network, Some(Network::Devnet));
+        assert_eq!(capabilities.currencies[0], Currency::Sol);
+        assert!(capabilities.supports_currency(&Currency::usdc_devnet()));
+        assert!(!capabilities.supports_currency(&Currency::from_mint(usdc::mainnet_mint())));
+        assert_eq!(capabilities.token_programs, ["spl_token", "spl_token_2022"]);
+        assert!(!capabilities.dry_run && !capabilities.read_only);
+        assert_eq!(capabilities.limits, Limits::default());
+        assert_eq!(capabilities.limits.max_memo_bytes, MAX_MEMO_BYTES);
+
+        // The genesis hash is read once and the struct survives the JSON the server sends
+        let json = serde_json::to_value(&capabilities).unwrap();
+        assert_eq!(json["network"], "devnet");
+        assert_eq!(serde_json::from_value::<Capabilities>(json).unwrap(), capabilities);
+        assert_eq!(client.capabilities(), capabilities);
+        assert_eq!(cluster.call_count("getGenesisHash"), 1);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn runtime_config_shows_up_and_an_unreachable_node_lists_only_universal_mints() {
+        let cluster = MockCluster::new();
+        cluster.respond("getGenesisHash", |_| Some(Err("connection refused".to_string())));
+        let client = cluster.client(FinternetConfig::default().with_dry_run(true));
+
+        let capabilities = client.capabilities();
+        assert!(capabilities.dry_run);
+        assert_eq!(capabilities.network, None);
+        assert!(!capabilities.supports_currency(&Currency::usdc_devnet()));
+        // Wrapped SOL is the same mint on every cluster
+        assert!(capabilities.supports_currency(&Currency::from_mint(spl_token::native_mint::id())));
+        let listed: Vec<Pubkey> = capabilities.currencies.iter().filter_map(Currency::mint).collect();
+        let universal: Vec<Pubkey> = mint_registry::on_network(None).iter().map(|known| known.mint).collect();
+        assert_eq!(listed, universal);
+    }
+}
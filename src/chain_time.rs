@@ -0,0 +1,214 @@
+use crate::FinternetClient;
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Target slot time used to extrapolate between slots that have a known block time
+pub const APPROX_SLOT_DURATION_MS: i64 = 400;
+/// How many slots `get_chain_time` walks back looking for a timestamped block
+pub const BLOCK_TIME_LOOKBACK: u64 = 32;
+
+/// The cluster's view of "now"
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChainTime {
+    /// Most recent slot that has a block time, or the current slot if none was found
+    pub slot: u64,
+    /// Block time of `slot`; `None` when no recent block carried a timestamp
+    pub block_time: Option<DateTime<Utc>>,
+    pub epoch: u64,
+    /// Fraction of the current epoch that has elapsed, from 0.0 to 1.0
+    pub epoch_progress: f64,
+}
+
+impl ChainTime {
+    /// Block time as unix seconds, falling back to the local clock when the chain had none
+    pub fn unix_timestamp(&self) -> u64 {
+        self.block_time
+            .unwrap_or_else(Utc::now)
+            .timestamp()
+            .max(0) as u64
+    }
+}
+
+/// Walk back from `slot` to the nearest slot whose block time `lookup` can resolve
+///
+/// Recent slots routinely have no block time yet (not finalized, or skipped by the leader), so
+/// the tip alone is not a reliable source.
+pub(crate) fn latest_block_time(
+    slot: u64,
+    lookback: u64,
+    mut lookup: impl FnMut(u64) -> Option<i64>,
+) -> Option<(u64, DateTime<Utc>)> {
+    (slot.saturating_sub(lookback)..=slot)
+        .rev()
+        .find_map(|candidate| {
+            let timestamp = lookup(candidate)?;
+            DateTime::from_timestamp(timestamp, 0).map(|time| (candidate, time))
+        })
+}
+
+impl FinternetClient {
+    /// Current slot, epoch, and the most recent block time the cluster can report
+    #[tracing::instrument(skip_all, fields(rpc = %self.rpc_endpoint()))]
+    pub async fn get_chain_time(&self) -> Result<ChainTime> {
+        let epoch_info = self.client.get_epoch_info()?;
+        let tip = epoch_info.absolute_slot;
+
+        let found = latest_block_time(tip, BLOCK_TIME_LOOKBACK, |slot| {
+            self.client.get_block_time(slot).ok()
+        });
+        if found.is_none() {
+            tracing::warn!("No block time found in the last {} slots before {}", BLOCK_TIME_LOOKBACK, tip);
+        }
+
+        let epoch_progress = if epoch_info.slots_in_epoch == 0 {
+            0.0
+        } else {
+            epoch_info.slot_index as f64 / epoch_info.slots_in_epoch as f64
+        };
+
+        Ok(ChainTime {
+            slot: found.map(|(slot, _)| slot).unwrap_or(tip),
+            block_time: found.map(|(_, time)| time),
+            epoch: epoch_info.epoch,
+            epoch_progress,
+        })
+    }
+
+    /// Wall-clock time of a slot, estimated from the nearest timestamped block when the slot has none
+    #[tracing::instrument(skip_all, fields(slot, rpc = %self.rpc_endpoint()))]
+    pub async fn slot_to_time(&self, slot: u64) -> Result<DateTime<Utc>> {
+        // The lookback starts at `slot` itself, so an exact block time wins when there is one
+        let (anchor_slot, anchor_time) =
+            latest_block_time(slot, BLOCK_TIME_LOOKBACK, |s| self.client.get_block_time(s).ok())
+                .or_else(|| {
+                    let now = self.client.get_slot().ok()?;
+                    latest_block_time(now, BLOCK_TIME_LOOKBACK, |s| self.client.get_block_time(s).ok())
+                })
+                .ok_or_else(|| anyhow!("No block time available near slot {}", slot))?;
+
+        let offset = slot as i64 - anchor_slot as i64;
+        Ok(anchor_time + Duration::milliseconds(offset * APPROX_SLOT_DURATION_MS))
+    }
+
+    /// Approximate slot produced at `time`, extrapolated from the current chain time
+    #[tracing::instrument(skip_all, fields(time = %time, rpc = %self.rpc_endpoint()))]
+    pub async fn time_to_approx_slot(&self, time: DateTime<Utc>) -> Result<u64> {
+        let now = self.get_chain_time().await?;
+        let anchor_time = now
+            .block_time
+            .ok_or_else(|| anyhow!("Cluster reported no recent block time to extrapolate from"))?;
+
+        let elapsed_ms = (anchor_time - time).num_milliseconds();
+        let slots = elapsed_ms / APPROX_SLOT_DURATION_MS;
+        Ok((now.slot as i64 - slots).max(0) as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_cluster::MockCluster;
+    use serde_json::Value;
+
+    /// Block time of the mock cluster's starting slot, 1000
+    const TIP_TIME: i64 = 1_700_000_000;
+
+    /// Leave the newest `missing` slots without a block time, as a node does before they finalize
+    fn without_recent_block_times(cluster: &MockCluster, tip: u64, missing: u64) {
+        cluster.respond("getBlockTime", move |params| {
+            let slot = params.get(0).and_then(Value::as_u64)?;
+            match tip.checked_sub(slot)? {
+                // Some nodes answer null, others an error
+                behind if behind < missing && slot % 2 == 0 => Some(Ok(Value::Null)),
+                behind if behind < missing => Some(Err(format!("Block not available for slot {}", slot))),
+                _ => None,
+            }
+        });
+    }
+
+    #[test]
+    fn the_walk_back_stops_at_the_first_slot_with_a_time() {
+        let mut asked = Vec::new();
+        let found = latest_block_time(100, 10, |slot| {
+            asked.push(slot);
+            (slot <= 96).then_some(TIP_TIME + slot as i64)
+        });
+        assert_eq!(found, Some((96, DateTime::from_timestamp(TIP_TIME + 96, 0).unwrap())));
+        assert_eq!(asked, vec![100, 99, 98, 97, 96]);
+
+        // Never further back than the lookback, nor below slot 0
+        let mut asked = Vec::new();
+        assert_eq!(latest_block_time(3, 10, |slot| { asked.push(slot); None }), None);
+        assert_eq!(asked, vec![3, 2, 1, 0]);
+        assert_eq!(latest_block_time(100, 2, |slot| (slot < 98).then_some(TIP_TIME)), None);
+
+        // A timestamp no date can hold is passed over like a missing one
+        assert_eq!(latest_block_time(5, 5, |slot| Some(if slot == 5 { i64::MAX } else { TIP_TIME })).map(|(slot, _)| slot), Some(4));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn chain_time_falls_back_to_the_newest_block_with_a_time() {
+        let cluster = MockCluster::new();
+        without_recent_block_times(&cluster, 1_000, 5);
+        let client = cluster.default_client();
+
+        let now = client.get_chain_time().await.unwrap();
+        assert_eq!(now.slot, 995);
+        // Five slots of 400ms before the tip, truncated to whole seconds
+        assert_eq!(now.block_time, DateTime::from_timestamp(TIP_TIME - 2, 0));
+        assert_eq!(now.epoch, 0);
+        assert!((now.epoch_progress - 1_000.0 / 432_000.0).abs() < 1e-12);
+        assert_eq!(now.unix_timestamp(), (TIP_TIME - 2) as u64);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn chain_time_without_any_recent_block_time_keeps_the_tip() {
+        let cluster = MockCluster::new();
+        without_recent_block_times(&cluster, 1_000, BLOCK_TIME_LOOKBACK + 1);
+        let client = cluster.default_client();
+
+        let now = client.get_chain_time().await.unwrap();
+        assert_eq!((now.slot, now.block_time), (1_000, None));
+        assert_eq!(cluster.call_count("getBlockTime") as u64, BLOCK_TIME_LOOKBACK + 1);
+        // The local clock stands in, so a timestamp is still available
+        assert!(now.unix_timestamp() > TIP_TIME as u64);
+
+        let error = client.time_to_approx_slot(Utc::now()).await.unwrap_err();
+        assert!(error.to_string().contains("no recent block time"), "{}", error);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn slot_times_are_exact_when_known_and_extrapolated_otherwise() {
+        let cluster = MockCluster::new();
+        without_recent_block_times(&cluster, 1_000, 5);
+        let client = cluster.default_client();
+
+        // 990 has its own block time
+        assert_eq!(client.slot_to_time(990).await.unwrap(), DateTime::from_timestamp(TIP_TIME - 4, 0).unwrap());
+        // 998 has none; it is three slots past 995, at 400ms each
+        let anchor = DateTime::from_timestamp(TIP_TIME - 2, 0).unwrap();
+        assert_eq!(client.slot_to_time(998).await.unwrap(), anchor + Duration::milliseconds(1_200));
+        // A future slot has no block near it, so it extrapolates from the newest known one
+        assert_eq!(client.slot_to_time(1_100).await.unwrap(), anchor + Duration::milliseconds(105 * 400));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn time_to_slot_inverts_slot_to_time() {
+        let cluster = MockCluster::new();
+        without_recent_block_times(&cluster, 1_000, 5);
+        let client = cluster.default_client();
+
+        let anchor = DateTime::from_timestamp(TIP_TIME - 2, 0).unwrap();
+        assert_eq!(client.time_to_approx_slot(anchor).await.unwrap(), 995);
+        assert_eq!(client.time_to_approx_slot(anchor - Duration::seconds(60)).await.unwrap(), 845);
+        assert_eq!(client.time_to_approx_slot(anchor + Duration::seconds(4)).await.unwrap(), 1_005);
+        // Before genesis clamps to slot 0
+        assert_eq!(client.time_to_approx_slot(anchor - Duration::days(1)).await.unwrap(), 0);
+        for slot in [900, 950, 995] {
+            let time = client.slot_to_time(slot).await.unwrap();
+            let back = client.time_to_approx_slot(time).await.unwrap();
+            assert!(back.abs_diff(slot) <= 3, "{} came back as {}", slot, back);
+        }
+    }
+}
@@ -0,0 +1,162 @@
+use crate::ledger::LedgerEntry;
+use anyhow::{anyhow, Result};
+use base64::{engine::general_purpose, Engine};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::{Deserialize, Serialize};
+use solana_sdk::signature::Signature;
+use std::collections::HashMap;
+use std::io::{Read, Write};
+
+/// Prefix of every chunk memo: `finternet-chunk <index>/<total>:<id>:<data>`
+pub const CHUNK_PREFIX: &str = "finternet-chunk ";
+/// Prefix marking a payload as gzip + base64 encoded
+pub const COMPRESSED_PREFIX: &str = "finternet-gz:";
+/// Largest payload written as a single plain memo
+pub const MAX_SINGLE_ENTRY_LEN: usize = 800;
+/// Payload bytes per chunk; two chunks plus headers fit comfortably in one transaction
+pub const CHUNK_DATA_LEN: usize = 400;
+pub const CHUNKS_PER_TRANSACTION: usize = 2;
+
+/// How a reassembled entry was put together from its chunks
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChunkInfo {
+    pub id: String,
+    pub total: u32,
+    /// 1-based indexes of chunks that were not found
+    pub missing: Vec<u32>,
+    /// Every transaction that carried a chunk of this entry
+    pub signatures: Vec<Signature>,
+}
+
+pub fn compress(data: &str) -> Result<String> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::best());
+    encoder.write_all(data.as_bytes())?;
+    let compressed = encoder.finish()?;
+    Ok(format!("{}{}", COMPRESSED_PREFIX, general_purpose::STANDARD.encode(compressed)))
+}
+
+/// Undo `compress`; payloads without the compressed prefix are returned unchanged
+pub fn decompress(data: &str) -> Result<String> {
+    let Some(encoded) = data.strip_prefix(COMPRESSED_PREFIX) else {
+        return Ok(data.to_string());
+    };
+    let compressed = general_purpose::STANDARD.decode(encoded)?;
+    let mut decoder = GzDecoder::new(compressed.as_slice());
+    let mut decompressed = String::new();
+    decoder
+        .read_to_string(&mut decompressed)
+        .map_err(|e| anyhow!("Failed to decompress ledger entry: {}", e))?;
+    Ok(decompressed)
+}
+
+/// Split a payload into numbered chunk memos, never cutting through a UTF-8 character
+pub fn split_into_chunks(id: &str, payload: &str) -> Vec<String> {
+    let mut parts = Vec::new();
+    let mut current = String::new();
+    for ch in payload.chars() {
+        if current.len() + ch.len_utf8() > CHUNK_DATA_LEN {
+            parts.push(std::mem::take(&mut current));
+        }
+        current.push(ch);
+    }
+    if !current.is_empty() || parts.is_empty() {
+        parts.push(current);
+    }
+
+    let total = parts.len();
+    parts
+        .into_iter()
+        .enumerate()
+        .map(|(i, part)| format!("{}{}/{}:{}:{}", CHUNK_PREFIX, i + 1, total, id, part))
+        .collect()
+}
+
+/// Parse a chunk memo into `(index, total, id, data)`
+pub fn parse_chunk(memo: &str) -> Option<(u32, u32, &str, &str)> {
+    let rest = memo.strip_prefix(CHUNK_PREFIX)?;
+    let (position, rest) = rest.split_once(':')?;
+    let (id, data) = rest.split_once(':')?;
+    let (index, total) = position.split_once('/')?;
+    let index: u32 = index.parse().ok()?;
+    let total: u32 = total.parse().ok()?;
+    if index == 0 || index > total {
+        return None;
+    }
+    Some((index, total, id, data))
+}
+
+/// Merge chunk memos into whole entries, leaving ordinary memos as they are
+///
+/// Entries keep the newest-first order of their first chunk. Entries with missing chunks are
+/// still returned, carrying whatever data was found and the list of missing indexes.
+pub fn reassemble(entries: Vec<LedgerEntry>) -> Vec<LedgerEntry> {
+    struct Pending {
+        position: usize,
+        total: u32,
+        first: LedgerEntry,
+        parts: HashMap<u32, String>,
+        signatures: Vec<Signature>,
+    }
+
+    let mut output: Vec<Option<LedgerEntry>> = Vec::new();
+    let mut pending: HashMap<String, Pending> = HashMap::new();
+
+    for entry in entries {
+        let Some((index, total, id, data)) = parse_chunk(&entry.data) else {
+            output.push(Some(entry));
+            continue;
+        };
+        let (id, data) = (id.to_string(), data.to_string());
+
+        let group = pending.entry(id).or_insert_with(|| {
+            output.push(None);
+            Pending {
+                position: output.len() - 1,
+                total,
+                first: entry.clone(),
+                parts: HashMap::new(),
+                signatures: Vec::new(),
+            }
+        });
+        if !group.signatures.contains(&entry.signature) {
+            group.signatures.push(entry.signature);
+        }
+        // The lowest-numbered chunk identifies the entry
+        if parse_chunk(&group.first.data).map(|(i, ..)| index < i).unwrap_or(false) {
+            group.first = entry;
+        }
+        group.parts.insert(index, data);
+    }
+
+    for (id, group) in pending {
+        let missing: Vec<u32> = (1..=group.total).filter(|i| !group.parts.contains_key(i)).collect();
+        let joined: String = (1..=group.total)
+            .filter_map(|i| group.parts.get(&i).map(String::as_str))
+            .collect();
+
+        let data = if missing.is_empty() {
+            decompress(&joined).unwrap_or_else(|e| {
+                tracing::warn!("Ledger entry {} could not be decompressed: {}", id, e);
+                joined.clone()
+            })
+        } else {
+            tracing::warn!("Ledger entry {} is partial: missing chunks {:?}", id, missing);
+            joined
+        };
+
+        output[group.position] = Some(LedgerEntry {
+            data,
+            chunk: Some(ChunkInfo {
+                id,
+                total: group.total,
+                missing,
+                signatures: group.signatures,
+            }),
+            ..group.first
+        });
+    }
+
+    output.into_iter().flatten().collect()
+}
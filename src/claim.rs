@@ -0,0 +1,612 @@
+//! Payments to people without a wallet yet, claimed with a secret shared out of band
+//!
+//! The funds go to a token account owned by a claim key derived from a high-entropy secret.
+//! The sender hands the secret over by email, SMS, or a link, and whoever presents it can later
+//! sweep the funds into a wallet of their choosing. The sender also funds the claim key with
+//! enough SOL to pay for that sweep, so a brand-new wallet needs no SOL of its own. Every
+//! settlement empties and closes the escrow, so a second claim, or a cancel that loses the race,
+//! fails on-chain. The secret itself never appears in a transaction or a ledger entry.
+//! Expiry is enforced by this SDK against the cluster clock, not by the chain.
+
+use crate::confirmation::OperationKind;
+use crate::pipeline::{SendBody, SendRequest};
+use crate::telemetry::readable;
+use crate::FinternetClient;
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use solana_sdk::{
+    instruction::Instruction,
+    message::Message,
+    program_pack::Pack,
+    pubkey::Pubkey,
+    signature::{Keypair, Signature},
+    signer::{keypair::keypair_from_seed, Signer},
+    system_instruction,
+    transaction::Transaction,
+};
+use spl_associated_token_account::instruction as ata_instruction;
+use spl_token::instruction as token_instruction;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub const CLAIM_CREATED_ACTION: &str = "claim_created";
+pub const CLAIM_CLAIMED_ACTION: &str = "claim_claimed";
+pub const CLAIM_CANCELLED_ACTION: &str = "claim_cancelled";
+
+/// Shortest claim secret accepted; `generate_claim_secret` makes 64-character ones
+pub const MIN_CLAIM_SECRET_LEN: usize = 32;
+
+/// Lamports on top of the claimer's token account rent, for the sweep transaction's fee
+const CLAIM_FEE_ALLOWANCE: u64 = 10_000;
+
+/// Domain separator so a claim secret cannot double as any other key's seed
+const CLAIM_KEY_DOMAIN: &[u8] = b"finternet-claim-v1:";
+
+/// A fresh claim secret: 64 hex characters, 244 of their bits random
+pub fn generate_claim_secret() -> String {
+    format!("{}{}", uuid::Uuid::new_v4().simple(), uuid::Uuid::new_v4().simple())
+}
+
+/// The claim key `claim_secret` stands for
+pub fn claim_keypair(claim_secret: &str) -> Result<Keypair> {
+    if claim_secret.chars().count() < MIN_CLAIM_SECRET_LEN {
+        return Err(ClaimError::WeakSecret { min_len: MIN_CLAIM_SECRET_LEN }.into());
+    }
+    let mut hasher = Sha256::new();
+    hasher.update(CLAIM_KEY_DOMAIN);
+    hasher.update(claim_secret.as_bytes());
+    keypair_from_seed(&hasher.finalize()).map_err(|e| anyhow!("Could not derive the claim key: {}", e))
+}
+
+/// The sender's record of a claimable payment
+///
+/// It carries the claim secret, which is what lets the sender cancel, so keep it as private as
+/// the secret itself.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ClaimablePayment {
+    pub sender: Pubkey,
+    pub mint: Pubkey,
+    pub decimals: u8,
+    /// Base units held for the claimer
+    pub amount: u64,
+    /// Public key of the claim key; identifies the payment on-chain
+    pub claim_address: Pubkey,
+    /// The claim key's associated token account for `mint`
+    pub escrow: Pubkey,
+    /// Unix seconds from which the sender may cancel; until they do, it can still be claimed
+    pub expires_at: u64,
+    pub created_at: u64,
+    /// Transaction that funded the escrow
+    pub signature: Signature,
+    pub claim_secret: String,
+}
+
+impl ClaimablePayment {
+    pub fn load(path: &Path) -> Result<Self> {
+        let data = fs::read_to_string(path)?;
+        serde_json::from_str(&data).map_err(|e| anyhow!("Invalid claimable payment {}: {}", path.display(), e))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            if !parent.as_os_str().is_empty() {
+                fs::create_dir_all(parent)?;
+            }
+        }
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+/// Why a claimable payment could not be created or settled
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ClaimError {
+    WeakSecret { min_len: usize },
+    /// The secret's claim key already holds a payment, or once did
+    SecretInUse { claim_address: Pubkey },
+    InvalidExpiry { reason: String },
+    NotSender { expected: Pubkey, actual: Pubkey },
+    /// Too early to cancel
+    NotExpired { expires_at: u64 },
+    /// Nothing left to claim: the payment was claimed, cancelled, or never funded
+    AlreadySettled { claim_address: Pubkey },
+}
+
+impl ClaimError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::WeakSecret { .. } => "claim_weak_secret",
+            Self::SecretInUse { .. } => "claim_secret_in_use",
+            Self::InvalidExpiry { .. } => "claim_invalid_expiry",
+            Self::NotSender { .. } => "claim_not_sender",
+            Self::NotExpired { .. } => "claim_not_expired",
+            Self::AlreadySettled { .. } => "claim_already_settled",
+        }
+    }
+}
+
+impl fmt::Display for ClaimError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::WeakSecret { min_len } => write!(f, "Claim secrets must be at least {} characters", min_len),
+            Self::SecretInUse { claim_address } => {
+                write!(f, "This claim secret was already used for the payment at {}; generate a new one", claim_address)
+            }
+            Self::InvalidExpiry { reason } => write!(f, "Invalid claim expiry: {}", reason),
+            Self::NotSender { expected, actual } => write!(f, "Only the sender {} can cancel, not {}", expected, actual),
+            Self::NotExpired { expires_at } => write!(f, "The payment cannot be cancelled before it expires at {}", expires_at),
+            Self::AlreadySettled { claim_address } => {
+                write!(f, "Nothing left to claim at {}: the payment was already claimed or cancelled", claim_address)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ClaimError {}
+
+/// A non-empty escrow token account of the claim key
+struct Escrow {
+    account: Pubkey,
+    mint: Pubkey,
+    amount: u64,
+    decimals: u8,
+}
+
+impl FinternetClient {
+    /// Hold `amount` of `mint` for whoever presents `claim_secret`, cancellable from `expires_at`
+    ///
+    /// Sent through the send pipeline, so the same validation, asset policy, and spending limits
+    /// apply as to `send_payment`, with the claim key as recipient. The sender also pays the claim key's fee allowance, about
+    /// 0.002 SOL, which the claimer or the cancelling sender gets back.
+    pub async fn send_claimable_payment(
+        &self,
+        wallet: &Keypair,
+        amount: u64,
+        mint: &Pubkey,
+        claim_secret: &str,
+        expires_at: u64,
+    ) -> Result<ClaimablePayment> {
+        let outcome = self.send_claimable_payment_unlogged(wallet, amount, mint, claim_secret, expires_at).await;
+        let parameters = serde_json::json!({
+            "from": wallet.pubkey().to_string(),
+            "amount": amount,
+            "mint": mint.to_string(),
+            "expires_at": expires_at,
+            "claim_address": outcome.as_ref().ok().map(|claim| claim.claim_address.to_string()),
+        });
+        self.log_action("send_claimable_payment", parameters, Some(mint), outcome.as_ref(), |claim| {
+            claim.signature.to_string()
+        });
+        outcome
+    }
+
+    #[tracing::instrument(skip_all, fields(wallet = %readable(&wallet.pubkey()), mint = %mint, amount, expires_at))]
+    async fn send_claimable_payment_unlogged(
+        &self,
+        wallet: &Keypair,
+        amount: u64,
+        mint: &Pubkey,
+        claim_secret: &str,
+        expires_at: u64,
+    ) -> Result<ClaimablePayment> {
+        let sender = wallet.pubkey();
+        let claim_key = claim_keypair(claim_secret)?;
+        let claim_address = claim_key.pubkey();
+        let now = self.get_chain_time().await?.unix_timestamp();
+        if expires_at <= now {
+            return Err(ClaimError::InvalidExpiry {
+                reason: format!("{} has already passed (cluster time {})", expires_at, now),
+            }
+            .into());
+        }
+        // Reusing a secret would let whoever holds it from an earlier payment take this one too
+        if !self.client.get_signatures_for_address(&claim_address)?.is_empty() {
+            return Err(ClaimError::SecretInUse { claim_address }.into());
+        }
+        let decimals = self.client.get_token_supply(mint)?.decimals;
+        let escrow = spl_associated_token_account::get_associated_token_address(&claim_address, mint);
+        let allowance = self
+            .client
+            .get_minimum_balance_for_rent_exemption(spl_token::state::Account::LEN)?
+            + CLAIM_FEE_ALLOWANCE;
+
+        let entry = serde_json::json!({
+            "action": CLAIM_CREATED_ACTION,
+            "sender": sender.to_string(),
+            "claim_address": claim_address.to_string(),
+            "mint": mint.to_string(),
+            "amount": amount,
+            "expires_at": expires_at,
+        });
+        let instructions = vec![
+            ata_instruction::create_associated_token_account_idempotent(&sender, &claim_address, mint, &spl_token::id()),
+            token_instruction::transfer_checked(
+                &spl_token::id(),
+                &spl_associated_token_account::get_associated_token_address(&sender, mint),
+                mint,
+                &escrow,
+                &sender,
+                &[],
+                amount,
+                decimals,
+            )?,
+            system_instruction::transfer(&sender, &claim_address, allowance),
+            spl_memo::build_memo(self.namespaced_entry(&entry.to_string())?.as_bytes(), &[&sender]),
+        ];
+
+        // Logged by `send_claimable_payment`, with the claim address
+        let request =
+            SendRequest::payment("send_claimable_payment", sender, claim_address, amount, *mint, None, serde_json::Value::Null)
+                .with_body(SendBody::Instructions(instructions))
+                .unrecorded();
+        let signature = self.send_through_pipeline(request, &[wallet]).await?.signature;
+
+        tracing::info!("Claimable payment funded at {}: {}", claim_address, signature);
+        Ok(ClaimablePayment {
+            sender,
+            mint: *mint,
+            decimals,
+            amount,
+            claim_address,
+            escrow,
+            expires_at,
+            created_at: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+            signature,
+            claim_secret: claim_secret.to_string(),
+        })
+    }
+
+    /// Sweep everything held for `claim_secret` into `new_owner`'s wallet
+    ///
+    /// `new_owner` needs no SOL and does not sign: the claim key pays the fee, and its token
+    /// account rent and leftover SOL go to `new_owner` too.
+    pub async fn claim_payment(&self, new_owner: &Pubkey, claim_secret: &str) -> Result<Signature> {
+        let claim_key = claim_keypair(claim_secret);
+        let parameters = serde_json::json!({
+            "to": new_owner.to_string(),
+            "claim_address": claim_key.as_ref().ok().map(|key| key.pubkey().to_string()),
+        });
+        let outcome = match claim_key {
+            Ok(claim_key) => self.settle_claim(&claim_key, new_owner, None).await,
+            Err(e) => Err(e),
+        };
+        self.log_action("claim_payment", parameters, None, outcome.as_ref(), Signature::to_string);
+        outcome
+    }
+
+    /// Return an unclaimed payment to its sender once it has expired
+    pub async fn cancel_claimable(&self, wallet: &Keypair, claim: &ClaimablePayment) -> Result<Signature> {
+        let outcome = self.cancel_claimable_unlogged(wallet, claim).await;
+        let parameters = serde_json::json!({
+            "wallet": wallet.pubkey().to_string(),
+            "claim_address": claim.claim_address.to_string(),
+            "mint": claim.mint.to_string(),
+            "amount": claim.amount,
+        });
+        self.log_action("cancel_claimable", parameters, None, outcome.as_ref(), Signature::to_string);
+        outcome
+    }
+
+    async fn cancel_claimable_unlogged(&self, wallet: &Keypair, claim: &ClaimablePayment) -> Result<Signature> {
+        if wallet.pubkey() != claim.sender {
+            return Err(ClaimError::NotSender { expected: claim.sender, actual: wallet.pubkey() }.into());
+        }
+        let claim_key = claim_keypair(&claim.claim_secret)?;
+        if claim_key.pubkey() != claim.claim_address {
+            return Err(anyhow!("The claim secret does not match claim address {}", claim.claim_address));
+        }
+        let now = self.get_chain_time().await?.unix_timestamp();
+        if now < claim.expires_at {
+            return Err(ClaimError::NotExpired { expires_at: claim.expires_at }.into());
+        }
+        self.settle_claim(&claim_key, &claim.sender, Some(wallet)).await
+    }
+
+    /// Empty and close every escrow of `claim_key` into `payee`, the claim key paying the fee
+    ///
+    /// A cancel passes the sender as `cancelled_by`, who co-signs the ledger entry.
+    #[tracing::instrument(skip_all, fields(claim = %readable(&claim_key.pubkey()), payee = %readable(payee), cancelled = cancelled_by.is_some()))]
+    async fn settle_claim(&self, claim_key: &Keypair, payee: &Pubkey, cancelled_by: Option<&Keypair>) -> Result<Signature> {
+        let claim_address = claim_key.pubkey();
+        let escrows = self.claim_escrows(&claim_address).await?;
+        if escrows.is_empty() {
+            return Err(ClaimError::AlreadySettled { claim_address }.into());
+        }
+
+        let payee_accounts: Vec<Pubkey> = escrows
+            .iter()
+            .map(|escrow| spl_associated_token_account::get_associated_token_address(payee, &escrow.mint))
+            .collect();
+        let missing_accounts = self
+            .client
+            .get_multiple_accounts(&payee_accounts)?
+            .iter()
+            .filter(|account| account.is_none())
+            .count() as u64;
+        let account_rent = self.client.get_minimum_balance_for_rent_exemption(spl_token::state::Account::LEN)?;
+
+        let action = if cancelled_by.is_some() { CLAIM_CANCELLED_ACTION } else { CLAIM_CLAIMED_ACTION };
+        let entry = serde_json::json!({
+            "action": action,
+            "claim_address": claim_address.to_string(),
+            "to": payee.to_string(),
+        });
+        let mut instructions: Vec<Instruction> = Vec::new();
+        for (escrow, payee_account) in escrows.iter().zip(&payee_accounts) {
+            instructions.push(ata_instruction::create_associated_token_account_idempotent(
+                &claim_address,
+                payee,
+                &escrow.mint,
+                &spl_token::id(),
+            ));
+            instructions.push(token_instruction::transfer_checked(
+                &spl_token::id(),
+                &escrow.account,
+                &escrow.mint,
+                payee_account,
+                &claim_address,
+                &[],
+                escrow.amount,
+                escrow.decimals,
+            )?);
+            // Closing the escrow is what makes a second settlement fail on-chain
+            instructions.push(token_instruction::close_account(
+                &spl_token::id(),
+                &escrow.account,
+                payee,
+                &claim_address,
+                &[],
+            )?);
+        }
+
+        let cancelled_by_pubkey = cancelled_by.map(|sender| sender.pubkey());
+        let memo_signers: Vec<&Pubkey> = std::iter::once(&claim_address).chain(cancelled_by_pubkey.as_ref()).collect();
+        instructions.push(spl_memo::build_memo(self.namespaced_entry(&entry.to_string())?.as_bytes(), &memo_signers));
+
+        // Whatever the claim key has left after the fee and any new token accounts goes to the
+        // payee too; a system account may not be left holding less than its rent
+        let fee = self
+            .client
+            .get_fee_for_message(&Message::new(&instructions, Some(&claim_address)))?;
+        let balance = self.client.get_balance(&claim_address)?;
+        let leftover = balance.saturating_sub(fee + missing_accounts * account_rent);
+        if leftover > 0 {
+            instructions.push(system_instruction::transfer(&claim_address, payee, leftover));
+        }
+
+        let mut signers: Vec<&Keypair> = vec![claim_key];
+        signers.extend(cancelled_by);
+        let label = if cancelled_by.is_some() { "cancel_claimable" } else { "claim_payment" };
+        let mut transaction = Transaction::new_with_payer(&instructions, Some(&claim_address));
+        self.sign_with_fresh_blockhash(&mut transaction, &signers)?;
+        match self.send_and_confirm_resigning(OperationKind::Payment, label, transaction, &signers) {
+            Ok(signature) => {
+                tracing::info!("Claimable payment at {} settled to {}: {}", claim_address, payee, signature);
+                Ok(signature)
+            }
+            Err(e) => {
+                // Lost a race with another claim or a cancel: say so rather than the raw failure
+                match self.claim_escrows(&claim_address).await {
+                    Ok(after) if after.is_empty() => Err(ClaimError::AlreadySettled { claim_address }.into()),
+                    _ => Err(e),
+                }
+            }
+        }
+    }
+
+    /// The claim key's token accounts that still hold something
+    async fn claim_escrows(&self, claim_address: &Pubkey) -> Result<Vec<Escrow>> {
+        let mut escrows = Vec::new();
+        for account in self.get_token_account_details(claim_address).await? {
+            if account.amount == 0 {
+                continue;
+            }
+            let decimals = match account.decimals {
+                Some(decimals) => decimals,
+                None => self.client.get_token_supply(&account.mint)?.decimals,
+            };
+            escrows.push(Escrow { account: account.address, mint: account.mint, amount: account.amount, decimals });
+        }
+        Ok(escrows)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_cluster::{Landing, MockCluster, TempDir};
+    use serde_json::json;
+    use solana_sdk::{instruction::InstructionError, transaction::TransactionError};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    /// Cluster time of a fresh `MockCluster`
+    const NOW: u64 = 1_700_000_000;
+    const EXPIRES_AT: u64 = NOW + 86_400;
+
+    struct Setup {
+        cluster: MockCluster,
+        client: FinternetClient,
+        sender: Keypair,
+        mint: Pubkey,
+    }
+
+    fn setup() -> Setup {
+        let cluster = MockCluster::new();
+        let sender = Keypair::new();
+        let mint = cluster.add_mint(&spl_token::id(), 6, &Pubkey::new_unique());
+        cluster.set_balance(sender.pubkey(), 1_000_000_000);
+        cluster.add_token_account(&sender.pubkey(), &mint, 100_000_000);
+        let client = cluster.default_client();
+        Setup { cluster, client, sender, mint }
+    }
+
+    fn claim_error(error: anyhow::Error) -> ClaimError {
+        error.downcast::<ClaimError>().unwrap()
+    }
+
+    /// Put on the cluster what the funding transaction of `claim` would have left there
+    fn land_funding(cluster: &MockCluster, claim: &ClaimablePayment) {
+        cluster.set_token_account(claim.escrow, &claim.claim_address, &claim.mint, claim.amount);
+        cluster.set_balance(claim.claim_address, 2_039_280 + CLAIM_FEE_ALLOWANCE);
+    }
+
+    /// Put on the cluster what a settlement of `claim` would have left there: an empty escrow
+    fn land_settlement(cluster: &MockCluster, claim: &ClaimablePayment) {
+        cluster.set_token_account(claim.escrow, &claim.claim_address, &claim.mint, 0);
+    }
+
+    #[test]
+    fn the_claim_key_is_derived_from_the_secret_alone_and_weak_secrets_are_refused() {
+        let secret = generate_claim_secret();
+        assert_eq!(secret.len(), 64);
+        assert_ne!(secret, generate_claim_secret());
+        assert_eq!(claim_keypair(&secret).unwrap().pubkey(), claim_keypair(&secret).unwrap().pubkey());
+        assert_ne!(claim_keypair(&secret).unwrap().pubkey(), claim_keypair(&generate_claim_secret()).unwrap().pubkey());
+        // Not simply the secret's hash used as a seed, so it cannot collide with another key scheme
+        let undomained = keypair_from_seed(&Sha256::digest(secret.as_bytes())).unwrap();
+        assert_ne!(claim_keypair(&secret).unwrap().pubkey(), undomained.pubkey());
+
+        let short = "x".repeat(MIN_CLAIM_SECRET_LEN - 1);
+        assert_eq!(claim_error(claim_keypair(&short).unwrap_err()), ClaimError::WeakSecret { min_len: MIN_CLAIM_SECRET_LEN });
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn a_claimable_payment_funds_the_claim_key_without_putting_the_secret_on_chain() {
+        let Setup { cluster, client, sender, mint } = setup();
+        let secret = generate_claim_secret();
+
+        let claim = client.send_claimable_payment(&sender, 25_000_000, &mint, &secret, EXPIRES_AT).await.unwrap();
+
+        let claim_address = claim_keypair(&secret).unwrap().pubkey();
+        assert_eq!((claim.sender, claim.claim_address, claim.amount, claim.decimals), (sender.pubkey(), claim_address, 25_000_000, 6));
+        assert_eq!(claim.escrow, spl_associated_token_account::get_associated_token_address(&claim_address, &mint));
+        let sent = cluster.sent();
+        assert_eq!(sent.len(), 1);
+        let funding = &sent[0];
+        assert_eq!(funding.signatures[0], claim.signature);
+        assert_eq!(funding.message.account_keys[0], sender.pubkey());
+        assert!(funding.message.account_keys.contains(&claim.escrow));
+        let bytes = bincode::serialize(funding).unwrap();
+        assert!(!bytes.windows(secret.len()).any(|window| window == secret.as_bytes()));
+
+        // The record round-trips, secret included, for the sender to cancel with
+        let dir = TempDir::new("claim");
+        claim.save(&dir.join("claim.json")).unwrap();
+        assert_eq!(ClaimablePayment::load(&dir.join("claim.json")).unwrap(), claim);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn a_reused_secret_or_a_past_expiry_is_refused_before_anything_is_sent() {
+        let Setup { cluster, client, sender, mint } = setup();
+        let secret = generate_claim_secret();
+
+        let error = client.send_claimable_payment(&sender, 1_000, &mint, &secret, NOW - 1).await.unwrap_err();
+        assert!(matches!(claim_error(error), ClaimError::InvalidExpiry { .. }));
+        assert!(cluster.sent().is_empty());
+
+        let claim = client.send_claimable_payment(&sender, 1_000, &mint, &secret, EXPIRES_AT).await.unwrap();
+        let error = client.send_claimable_payment(&sender, 1_000, &mint, &secret, EXPIRES_AT).await.unwrap_err();
+        assert_eq!(claim_error(error), ClaimError::SecretInUse { claim_address: claim.claim_address });
+        assert_eq!(cluster.sent().len(), 1);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn a_claim_sweeps_and_closes_the_escrow_and_a_second_claim_is_refused() {
+        let Setup { cluster, client, sender, mint } = setup();
+        let secret = generate_claim_secret();
+        let claim = client.send_claimable_payment(&sender, 25_000_000, &mint, &secret, EXPIRES_AT).await.unwrap();
+        land_funding(&cluster, &claim);
+        let new_owner = Keypair::new().pubkey();
+
+        let signature = client.claim_payment(&new_owner, &secret).await.unwrap();
+
+        let sweep = cluster.sent().into_iter().find(|tx| tx.signatures[0] == signature).unwrap();
+        // The claim key pays and signs alone; the new owner needs no SOL and no signature
+        assert_eq!(sweep.message.account_keys[0], claim.claim_address);
+        assert_eq!(sweep.message.header.num_required_signatures, 1);
+        let programs: Vec<Pubkey> = sweep
+            .message
+            .instructions
+            .iter()
+            .map(|instruction| sweep.message.account_keys[instruction.program_id_index as usize])
+            .collect();
+        assert_eq!(
+            programs[..4],
+            [spl_associated_token_account::id(), spl_token::id(), spl_token::id(), spl_memo::id()]
+        );
+        let close = &sweep.message.instructions[2];
+        assert_eq!(
+            spl_token::instruction::TokenInstruction::unpack(&close.data).unwrap(),
+            spl_token::instruction::TokenInstruction::CloseAccount
+        );
+        assert_eq!(sweep.message.account_keys[close.accounts[1] as usize], new_owner);
+
+        land_settlement(&cluster, &claim);
+        let error = client.claim_payment(&Keypair::new().pubkey(), &secret).await.unwrap_err();
+        assert_eq!(claim_error(error), ClaimError::AlreadySettled { claim_address: claim.claim_address });
+        assert_eq!(cluster.sent().len(), 2);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn a_claim_that_loses_the_race_on_chain_reports_it_was_already_settled() {
+        let Setup { cluster, client, sender, mint } = setup();
+        let secret = generate_claim_secret();
+        let claim = client.send_claimable_payment(&sender, 25_000_000, &mint, &secret, EXPIRES_AT).await.unwrap();
+        land_funding(&cluster, &claim);
+
+        // Another claim lands between this one reading the escrow and its transaction executing
+        let settled = Arc::new(AtomicBool::new(false));
+        let emptied = Arc::clone(&settled);
+        cluster.respond("getTokenAccountsByOwner", move |_| {
+            emptied
+                .load(Ordering::SeqCst)
+                .then(|| Ok(json!({ "context": { "slot": 1_000 }, "value": [] })))
+        });
+        let landed = Arc::clone(&settled);
+        cluster.land_with(move |_| {
+            landed.store(true, Ordering::SeqCst);
+            Landing::Failed(TransactionError::InstructionError(1, InstructionError::InsufficientFunds))
+        });
+
+        let error = client.claim_payment(&Keypair::new().pubkey(), &secret).await.unwrap_err();
+        assert_eq!(claim_error(error), ClaimError::AlreadySettled { claim_address: claim.claim_address });
+        // The claim was sent and failed on-chain, rather than being refused up front
+        assert_eq!(cluster.sent().len(), 2);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn only_the_sender_cancels_only_after_expiry_and_never_after_a_claim() {
+        let Setup { cluster, client, sender, mint } = setup();
+        let claimed = client.send_claimable_payment(&sender, 1_000, &mint, &generate_claim_secret(), EXPIRES_AT).await.unwrap();
+        let unclaimed = client.send_claimable_payment(&sender, 2_000, &mint, &generate_claim_secret(), EXPIRES_AT).await.unwrap();
+        land_funding(&cluster, &claimed);
+        land_funding(&cluster, &unclaimed);
+
+        let error = client.cancel_claimable(&sender, &unclaimed).await.unwrap_err();
+        assert_eq!(claim_error(error), ClaimError::NotExpired { expires_at: EXPIRES_AT });
+        let stranger = Keypair::new();
+        let error = client.cancel_claimable(&stranger, &unclaimed).await.unwrap_err();
+        assert_eq!(claim_error(error), ClaimError::NotSender { expected: sender.pubkey(), actual: stranger.pubkey() });
+
+        client.claim_payment(&Keypair::new().pubkey(), &claimed.claim_secret).await.unwrap();
+        land_settlement(&cluster, &claimed);
+        cluster.set_time(EXPIRES_AT as i64);
+        let before = cluster.sent().len();
+
+        let error = client.cancel_claimable(&sender, &claimed).await.unwrap_err();
+        assert_eq!(claim_error(error), ClaimError::AlreadySettled { claim_address: claimed.claim_address });
+        assert_eq!(cluster.sent().len(), before);
+
+        let signature = client.cancel_claimable(&sender, &unclaimed).await.unwrap();
+        let cancel = cluster.sent().into_iter().find(|tx| tx.signatures[0] == signature).unwrap();
+        // The claim key still pays; the sender co-signs and gets the funds back
+        assert_eq!(cancel.message.account_keys[..2], [unclaimed.claim_address, sender.pubkey()]);
+        assert_eq!(cancel.message.header.num_required_signatures, 2);
+        let close = &cancel.message.instructions[2];
+        assert_eq!(cancel.message.account_keys[close.accounts[1] as usize], sender.pubkey());
+    }
+}
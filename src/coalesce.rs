@@ -0,0 +1,255 @@
+//! Collapsing identical concurrent RPC reads into one upstream request
+//!
+//! Under load the API server asks for the same hot wallet's accounts many times in the same
+//! second. `CoalescingSender` sits under the client cache: while a read is in flight, identical
+//! reads (same method, same params) wait for it and get its response, or its error, instead of
+//! going upstream again. Only methods in `COALESCED_METHODS` are shared; sends, simulations, and
+//! airdrops always go through on their own.
+
+use crate::recording::RecordedError;
+use solana_client::client_error::Result as ClientResult;
+use solana_client::rpc_request::RpcRequest;
+use solana_client::rpc_sender::{RpcSender, RpcTransportStats};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::sync::OnceCell;
+
+/// Reads whose identical in-flight calls share one response
+const COALESCED_METHODS: &[RpcRequest] = &[
+    RpcRequest::GetAccountInfo,
+    RpcRequest::GetBalance,
+    RpcRequest::GetBlockHeight,
+    RpcRequest::GetEpochInfo,
+    RpcRequest::GetFirstAvailableBlock,
+    RpcRequest::GetHealth,
+    RpcRequest::GetLatestBlockhash,
+    RpcRequest::GetMinimumBalanceForRentExemption,
+    RpcRequest::GetMultipleAccounts,
+    RpcRequest::GetProgramAccounts,
+    RpcRequest::GetSignatureStatuses,
+    RpcRequest::GetSignaturesForAddress,
+    RpcRequest::GetSlot,
+    RpcRequest::GetTokenAccountBalance,
+    RpcRequest::GetTokenAccountsByDelegate,
+    RpcRequest::GetTokenAccountsByOwner,
+    RpcRequest::GetTokenLargestAccounts,
+    RpcRequest::GetTokenSupply,
+    RpcRequest::GetTransaction,
+];
+
+/// What every waiter on one upstream call receives
+type SharedResponse = Result<serde_json::Value, RecordedError>;
+
+/// RPC transport wrapper that shares one upstream call among identical concurrent reads
+pub struct CoalescingSender<S> {
+    inner: S,
+    in_flight: Mutex<HashMap<String, Arc<OnceCell<SharedResponse>>>>,
+}
+
+impl<S: RpcSender> CoalescingSender<S> {
+    pub fn new(inner: S) -> Self {
+        Self { inner, in_flight: Mutex::new(HashMap::new()) }
+    }
+}
+
+#[async_trait::async_trait]
+impl<S: RpcSender + Send + Sync> RpcSender for CoalescingSender<S> {
+    async fn send(&self, request: RpcRequest, params: serde_json::Value) -> ClientResult<serde_json::Value> {
+        if !COALESCED_METHODS.contains(&request) {
+            return self.inner.send(request, params).await;
+        }
+
+        let key = format!("{}:{}", request, params);
+        let call = self
+            .in_flight
+            .lock()
+            .ok()
+            .map(|mut in_flight| in_flight.entry(key.clone()).or_default().clone());
+        let Some(call) = call else {
+            // A poisoned map only costs the sharing, never the call
+            return self.inner.send(request, params).await;
+        };
+        // The first caller runs the request; the rest wait on it. Should it be cancelled, the
+        // next waiter takes over.
+        let response = call
+            .get_or_init(|| async {
+                self.inner
+                    .send(request, params)
+                    .await
+                    .map_err(|e| RecordedError::from_client_error(&e, ""))
+            })
+            .await
+            .clone();
+        // Later identical reads must see fresh data, so the answer is shared only while in flight
+        if let Ok(mut in_flight) = self.in_flight.lock() {
+            if in_flight.get(&key).is_some_and(|current| Arc::ptr_eq(current, &call)) {
+                in_flight.remove(&key);
+            }
+        }
+        response.map_err(|e| e.to_client_error(request))
+    }
+
+    fn get_transport_stats(&self) -> RpcTransportStats {
+        self.inner.get_transport_stats()
+    }
+
+    fn url(&self) -> String {
+        self.inner.url()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_cluster::{MockCluster, MockSender};
+    use crate::{FinternetClient, FinternetConfig};
+    use base64::{engine::general_purpose, Engine};
+    use futures::future::join_all;
+    use serde_json::json;
+    use solana_sdk::pubkey::Pubkey;
+    use solana_sdk::signature::Keypair;
+    use solana_sdk::signer::Signer;
+    use solana_sdk::system_instruction;
+    use solana_sdk::transaction::Transaction;
+    use tokio::sync::Semaphore;
+
+    /// Holds requests until the gate is closed, so callers pile up behind the first
+    struct Gated {
+        inner: MockSender,
+        gate: Arc<Semaphore>,
+    }
+
+    #[async_trait::async_trait]
+    impl RpcSender for Gated {
+        async fn send(&self, request: RpcRequest, params: serde_json::Value) -> ClientResult<serde_json::Value> {
+            // The node version is fetched once per client, ahead of its first account read
+            if request != RpcRequest::GetVersion {
+                let _ = self.gate.acquire().await;
+            }
+            self.inner.send(request, params).await
+        }
+
+        fn get_transport_stats(&self) -> RpcTransportStats {
+            RpcTransportStats::default()
+        }
+
+        fn url(&self) -> String {
+            self.inner.url()
+        }
+    }
+
+    fn gated(cluster: &MockCluster) -> (Arc<Semaphore>, Gated) {
+        let gate = Arc::new(Semaphore::new(0));
+        (Arc::clone(&gate), Gated { inner: cluster.sender(), gate })
+    }
+
+    /// Run `calls` together, opening the gate once every one of them has been polled
+    async fn together<F: std::future::Future>(gate: &Semaphore, calls: Vec<F>) -> Vec<F::Output> {
+        let (results, ()) = futures::join!(join_all(calls), async {
+            tokio::task::yield_now().await;
+            gate.close();
+        });
+        results
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn identical_reads_in_flight_share_one_request_and_later_ones_go_upstream() {
+        let cluster = MockCluster::new();
+        let (wallet, other) = (Pubkey::new_unique(), Pubkey::new_unique());
+        cluster.set_balance(wallet, 42);
+        let (gate, inner) = gated(&cluster);
+        let sender = CoalescingSender::new(inner);
+
+        let calls = (0..100).map(|_| sender.send(RpcRequest::GetBalance, json!([wallet.to_string()]))).collect();
+        let results = together(&gate, calls).await;
+        assert!(results.iter().all(|result| result.as_ref().unwrap()["value"] == 42));
+        assert_eq!(cluster.call_count("getBalance"), 1);
+        assert!(sender.in_flight.lock().unwrap().is_empty());
+
+        // Once answered, the next read sees fresh data, and other params never share
+        cluster.set_balance(wallet, 43);
+        let mut balances = Vec::new();
+        for address in [wallet, other, wallet] {
+            balances.push(sender.send(RpcRequest::GetBalance, json!([address.to_string()])).await.unwrap()["value"].clone());
+        }
+        assert_eq!(balances, [json!(43), json!(0), json!(43)]);
+        assert_eq!(cluster.call_count("getBalance"), 4);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn an_upstream_error_reaches_every_waiter() {
+        let cluster = MockCluster::new();
+        cluster.respond("getBalance", |_| Some(Err("Node is behind by 42 slots".to_string())));
+        let (gate, inner) = gated(&cluster);
+        let sender = CoalescingSender::new(inner);
+
+        let calls = (0..100).map(|_| sender.send(RpcRequest::GetBalance, json!([Pubkey::default().to_string()]))).collect();
+        let results = together(&gate, calls).await;
+        for result in results {
+            let error = result.unwrap_err().to_string();
+            assert!(error.contains("Node is behind by 42 slots"), "{}", error);
+        }
+        assert_eq!(cluster.call_count("getBalance"), 1);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn sends_are_never_shared() {
+        let cluster = MockCluster::new();
+        let payer = Keypair::new();
+        let transfer = system_instruction::transfer(&payer.pubkey(), &Pubkey::new_unique(), 1);
+        let transaction = Transaction::new_signed_with_payer(&[transfer], Some(&payer.pubkey()), &[&payer], cluster.blockhash());
+        let encoded = general_purpose::STANDARD.encode(bincode::serialize(&transaction).unwrap());
+        let params = json!([encoded, { "encoding": "base64" }]);
+        let (gate, inner) = gated(&cluster);
+        let sender = CoalescingSender::new(inner);
+
+        let calls = (0..5).map(|_| sender.send(RpcRequest::SendTransaction, params.clone())).collect();
+        let results = together(&gate, calls).await;
+        assert!(results.iter().all(Result::is_ok));
+        assert_eq!(cluster.call_count("sendTransaction"), 5);
+        assert_eq!(cluster.sent().len(), 5);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn a_waiter_takes_over_when_the_first_caller_is_cancelled() {
+        let cluster = MockCluster::new();
+        let (gate, inner) = gated(&cluster);
+        let sender = CoalescingSender::new(inner);
+        let params = json!([Pubkey::default().to_string()]);
+
+        let mut first = Box::pin(sender.send(RpcRequest::GetBalance, params.clone()));
+        let mut second = Box::pin(sender.send(RpcRequest::GetBalance, params));
+        assert!(futures::poll!(&mut first).is_pending());
+        assert!(futures::poll!(&mut second).is_pending());
+        drop(first);
+        gate.close();
+
+        assert_eq!(second.await.unwrap()["value"], 0);
+        assert_eq!(cluster.call_count("getBalance"), 1);
+    }
+
+    /// 100 threads reading the same asset at once, answered once the burst has built up
+    fn burst(config: FinternetConfig) -> usize {
+        let cluster = MockCluster::new();
+        let mint = cluster.add_mint(&spl_token::id(), 6, &Pubkey::new_unique());
+        let (gate, inner) = gated(&cluster);
+        let client = FinternetClient::with_rpc_sender(FinternetConfig { rpc_url: "mock://cluster".to_string(), ..config }, inner);
+
+        std::thread::scope(|scope| {
+            let readers: Vec<_> =
+                (0..100).map(|_| scope.spawn(|| futures::executor::block_on(client.get_asset_info(&mint)))).collect();
+            std::thread::sleep(std::time::Duration::from_millis(500));
+            gate.close();
+            for reader in readers {
+                assert_eq!(reader.join().unwrap().unwrap().decimals, 6);
+            }
+        });
+        cluster.call_count("getMultipleAccounts")
+    }
+
+    #[test]
+    fn a_burst_of_identical_asset_reads_makes_one_upstream_request() {
+        assert_eq!(burst(FinternetConfig::default()), 1);
+        assert_eq!(burst(FinternetConfig { coalesce_reads: false, ..FinternetConfig::default() }), 100);
+    }
+}
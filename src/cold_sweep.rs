@@ -0,0 +1,922 @@
+//! Moving treasury funds from a hot wallet to cold storage, reviewed before anything is signed
+//!
+//! `plan_cold_sweep` only reads. It works out what would leave the hot wallet (SOL above a
+//! reserve, the selected token balances, the token accounts to close) and every fee, and builds
+//! the exact unsigned transactions. A `SweepPlan` saved to JSON carries each one with a readable
+//! summary, so the file can be reviewed, or passed around like a PSBT. Before anything is signed,
+//! every message and summary is rebuilt from the plan's fields, and an edited file is refused.
+//! `execute_sweep_plan` then checks the chain against the plan before each transaction and halts
+//! at the first difference. Sent transactions are recorded in the file, so running it again
+//! resumes after them. Only the blockhash is replaced at signing, since the one a plan was built
+//! with expires long before a review ends.
+
+use crate::confirmation::OperationKind;
+use crate::fees::transaction_fee;
+use crate::ledger::TokenAccountDetail;
+use crate::sweep::transaction_size;
+use crate::telemetry::readable;
+use crate::{Currency, FinternetClient};
+use anyhow::{anyhow, Result};
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use solana_sdk::{
+    hash::Hash,
+    instruction::Instruction,
+    message::Message,
+    packet::PACKET_DATA_SIZE,
+    program_pack::Pack,
+    program_utils::limited_deserialize,
+    pubkey::Pubkey,
+    signature::{Keypair, Signature},
+    signer::Signer,
+    system_instruction,
+    transaction::Transaction,
+};
+use spl_associated_token_account::{get_associated_token_address, instruction::create_associated_token_account_idempotent};
+use std::collections::HashSet;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const PLAN_VERSION: u32 = 1;
+/// Accounts fetched per `getMultipleAccounts` call, the RPC maximum
+const ACCOUNTS_PER_FETCH: usize = 100;
+/// 0.01 SOL, enough for a few more transactions from the hot wallet
+pub const DEFAULT_SOL_RESERVE: u64 = 10_000_000;
+/// 0.0001 SOL of drift in the hot wallet's balance between plan and execution
+pub const DEFAULT_SOL_TOLERANCE: u64 = 100_000;
+
+/// Which token balances a cold sweep moves
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", content = "mints", rename_all = "snake_case")]
+pub enum TokenSelection {
+    None,
+    /// Every token account the hot wallet owns
+    #[default]
+    All,
+    Only(Vec<Pubkey>),
+}
+
+impl TokenSelection {
+    fn includes(&self, mint: &Pubkey) -> bool {
+        match self {
+            Self::None => false,
+            Self::All => true,
+            Self::Only(mints) => mints.contains(mint),
+        }
+    }
+}
+
+/// What `plan_cold_sweep` moves out of the hot wallet
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SweepSelection {
+    /// Lamports left in the hot wallet after the sweep and its fees; `None` leaves SOL alone.
+    /// Must be 0 or at least the rent-exempt minimum.
+    pub sol_reserve: Option<u64>,
+    pub tokens: TokenSelection,
+    /// Close each token account the sweep empties, and any already empty, sending its rent to
+    /// the cold wallet
+    pub close_accounts: bool,
+    /// How far the hot wallet's SOL may drift from the plan before execution halts; token
+    /// balances must match exactly
+    pub sol_tolerance: u64,
+}
+
+impl Default for SweepSelection {
+    fn default() -> Self {
+        Self {
+            sol_reserve: Some(DEFAULT_SOL_RESERVE),
+            tokens: TokenSelection::All,
+            close_accounts: false,
+            sol_tolerance: DEFAULT_SOL_TOLERANCE,
+        }
+    }
+}
+
+/// One thing a planned transaction does
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum PlannedMove {
+    Token {
+        mint: Pubkey,
+        decimals: u8,
+        /// The hot wallet's token account
+        source: Pubkey,
+        /// The cold wallet's associated token account
+        destination: Pubkey,
+        /// Base units, the source's whole balance when planned
+        amount: u64,
+        /// The cold wallet had no account for the mint; the hot wallet pays its rent
+        create_destination: bool,
+        close_source: bool,
+        /// Lamports the cold wallet receives from closing the source
+        rent_lamports: u64,
+    },
+    Sol {
+        lamports: u64,
+    },
+}
+
+/// One unsigned transaction of a sweep plan
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlannedTransaction {
+    /// What the transaction does, one line per step
+    pub summary: Vec<String>,
+    pub moves: Vec<PlannedMove>,
+    pub fee_lamports: u64,
+    /// Lamports the hot wallet should hold just before this transaction
+    pub expected_sol_balance: u64,
+    /// Base64 of the serialized message, with the planning blockhash
+    pub message: String,
+    /// Hex SHA-256 of the serialized message
+    pub message_hash: String,
+    /// Set once sent, so a halted execution resumes after it
+    #[serde(default)]
+    pub signature: Option<Signature>,
+}
+
+/// A balance the plan leaves in the hot wallet, and why
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SkippedBalance {
+    /// `None` for SOL
+    pub mint: Option<Pubkey>,
+    pub account: Pubkey,
+    pub amount: u64,
+    pub reason: String,
+}
+
+/// Everything a cold sweep would do, written out for review; see the module documentation
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SweepPlan {
+    pub version: u32,
+    pub id: String,
+    pub hot_wallet: Pubkey,
+    pub cold_destination: Pubkey,
+    pub created_at: u64,
+    pub selection: SweepSelection,
+    /// The hot wallet's lamports when planned
+    pub sol_balance: u64,
+    /// In the order they are sent
+    pub transactions: Vec<PlannedTransaction>,
+    pub skipped: Vec<SkippedBalance>,
+    pub total_fee_lamports: u64,
+    /// Rent of the cold wallet's new token accounts, paid by the hot wallet
+    pub total_rent_lamports: u64,
+}
+
+/// Why a sweep plan was refused or halted
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ColdSweepError {
+    WrongWallet { expected: Pubkey, actual: Pubkey },
+    /// A message or summary does not match the plan's fields, or its hash does not match
+    Tampered { transaction: usize, reason: String },
+    /// The chain no longer matches the plan; transactions before `transaction` were sent
+    Diverged { transaction: usize, sent: usize, reason: String },
+    UnsupportedVersion(u32),
+}
+
+impl ColdSweepError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::WrongWallet { .. } => "wrong_sweep_wallet",
+            Self::Tampered { .. } => "sweep_plan_tampered",
+            Self::Diverged { .. } => "sweep_plan_diverged",
+            Self::UnsupportedVersion(_) => "unsupported_sweep_plan_version",
+        }
+    }
+}
+
+impl fmt::Display for ColdSweepError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::WrongWallet { expected, actual } => {
+                write!(f, "Sweep plan moves funds out of {}, not {}", expected, actual)
+            }
+            Self::Tampered { transaction, reason } => {
+                write!(f, "Sweep plan has been tampered with at transaction {}: {}", transaction + 1, reason)
+            }
+            Self::Diverged { transaction, sent, reason } => write!(
+                f,
+                "Halted before transaction {}: {}; {} transaction(s) of the plan were sent. Plan the sweep again",
+                transaction + 1,
+                reason,
+                sent
+            ),
+            Self::UnsupportedVersion(version) => write!(f, "Unsupported sweep plan version {}", version),
+        }
+    }
+}
+
+impl std::error::Error for ColdSweepError {}
+
+/// What one `execute_sweep_plan` run sent
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SweepExecution {
+    pub plan_id: String,
+    /// Sent by this run, in order
+    pub signatures: Vec<Signature>,
+    /// Sent by earlier runs and skipped
+    pub already_sent: usize,
+}
+
+impl SweepPlan {
+    pub fn load(path: &Path) -> Result<Self> {
+        let data = fs::read_to_string(path)?;
+        serde_json::from_str(&data).map_err(|e| anyhow!("Invalid sweep plan {}: {}", path.display(), e))
+    }
+
+    /// Written to a temporary file and renamed, so an interrupted save never loses sent signatures
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            if !parent.as_os_str().is_empty() {
+                fs::create_dir_all(parent)?;
+            }
+        }
+        let temporary = path.with_extension("json.tmp");
+        fs::write(&temporary, serde_json::to_string_pretty(self)?)?;
+        fs::rename(&temporary, path)?;
+        Ok(())
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.transactions.iter().all(|transaction| transaction.signature.is_some())
+    }
+
+    /// Lamports the cold wallet receives: the SOL moved plus rent of closed accounts
+    pub fn total_sol_moved(&self) -> u64 {
+        self.moves()
+            .map(|planned| match planned {
+                PlannedMove::Sol { lamports } => *lamports,
+                PlannedMove::Token { rent_lamports, .. } => *rent_lamports,
+            })
+            .sum()
+    }
+
+    fn moves(&self) -> impl Iterator<Item = &PlannedMove> {
+        self.transactions.iter().flat_map(|transaction| &transaction.moves)
+    }
+
+    /// Check every transaction against the plan's fields and hashes, returning their messages
+    pub fn verify(&self) -> Result<Vec<Message>> {
+        if self.version != PLAN_VERSION {
+            return Err(ColdSweepError::UnsupportedVersion(self.version).into());
+        }
+        let mut messages = Vec::with_capacity(self.transactions.len());
+        for (index, planned) in self.transactions.iter().enumerate() {
+            let tampered = |reason: &str| ColdSweepError::Tampered { transaction: index, reason: reason.to_string() };
+            let stored = base64::engine::general_purpose::STANDARD
+                .decode(&planned.message)
+                .map_err(|_| tampered("message is not valid base64"))?;
+            if hex_sha256(&stored) != planned.message_hash {
+                return Err(tampered("message hash does not match the message").into());
+            }
+            let stored_message: Message = limited_deserialize(&stored)
+                .map_err(|_| tampered("message does not decode"))?;
+            for planned_move in &planned.moves {
+                if let PlannedMove::Token { mint, destination, .. } = planned_move {
+                    if *destination != get_associated_token_address(&self.cold_destination, mint) {
+                        return Err(tampered("a destination is not the cold wallet's token account").into());
+                    }
+                }
+            }
+            let instructions = move_instructions(&self.hot_wallet, &self.cold_destination, &planned.moves)?;
+            let expected =
+                Message::new_with_blockhash(&instructions, Some(&self.hot_wallet), &stored_message.recent_blockhash);
+            if expected.serialize() != stored {
+                return Err(tampered("transaction does not match the planned moves").into());
+            }
+            if transaction_summary(&self.hot_wallet, &self.cold_destination, &planned.moves, planned.fee_lamports)
+                != planned.summary
+            {
+                return Err(tampered("summary does not match the planned moves").into());
+            }
+            messages.push(expected);
+        }
+        Ok(messages)
+    }
+}
+
+impl fmt::Display for SweepPlan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Cold sweep plan {}", self.id)?;
+        writeln!(f, "  From:  {}", self.hot_wallet)?;
+        writeln!(f, "  To:    {}", self.cold_destination)?;
+        writeln!(f, "  Fees:  {}", Currency::Sol.format_amount(self.total_fee_lamports))?;
+        if self.total_rent_lamports > 0 {
+            let rent = Currency::Sol.format_amount(self.total_rent_lamports);
+            writeln!(f, "  Rent:  {} for new cold token accounts", rent)?;
+        }
+        for (index, transaction) in self.transactions.iter().enumerate() {
+            let status = match &transaction.signature {
+                Some(signature) => format!(" (sent: {})", signature),
+                None => String::new(),
+            };
+            writeln!(f, "  Transaction {}{}", index + 1, status)?;
+            for line in &transaction.summary {
+                writeln!(f, "    {}", line)?;
+            }
+        }
+        for skipped in &self.skipped {
+            writeln!(f, "  Left behind: {} in {}: {}", skipped.amount, skipped.account, skipped.reason)?;
+        }
+        Ok(())
+    }
+}
+
+impl FinternetClient {
+    /// Work out a sweep of `hot_wallet` into `cold_destination` without signing anything
+    #[tracing::instrument(skip_all, fields(wallet = %readable(hot_wallet), to = %readable(cold_destination)))]
+    pub async fn plan_cold_sweep(
+        &self,
+        hot_wallet: &Pubkey,
+        cold_destination: &Pubkey,
+        assets: &SweepSelection,
+    ) -> Result<SweepPlan> {
+        if hot_wallet == cold_destination {
+            return Err(anyhow!("The cold destination must be a different wallet from the hot wallet"));
+        }
+        if let Some(reserve) = assets.sol_reserve {
+            let minimum = self.client.get_minimum_balance_for_rent_exemption(0)?;
+            if reserve > 0 && reserve < minimum {
+                return Err(anyhow!(
+                    "A SOL reserve must be 0 or at least {}, the rent-exempt minimum",
+                    Currency::Sol.format_amount(minimum)
+                ));
+            }
+        }
+
+        let mut skipped = Vec::new();
+        let mut sources: Vec<TokenAccountDetail> = Vec::new();
+        for detail in self.get_token_account_details(hot_wallet).await? {
+            if !assets.tokens.includes(&detail.mint) || (detail.amount == 0 && !assets.close_accounts) {
+                continue;
+            }
+            if detail.is_frozen() {
+                skipped.push(SkippedBalance {
+                    mint: Some(detail.mint),
+                    account: detail.address,
+                    amount: detail.amount,
+                    reason: "the account is frozen".to_string(),
+                });
+                continue;
+            }
+            sources.push(detail);
+        }
+        // A stable order, so planning twice against the same state gives the same transactions
+        sources.sort_by(|a, b| a.mint.cmp(&b.mint).then(a.address.cmp(&b.address)));
+
+        let mints: Vec<Pubkey> = {
+            let mut seen = HashSet::new();
+            sources.iter().map(|source| source.mint).filter(|mint| seen.insert(*mint)).collect()
+        };
+        let cold_accounts: Vec<Pubkey> = mints
+            .iter()
+            .map(|mint| get_associated_token_address(cold_destination, mint))
+            .collect();
+        let mut existing = HashSet::new();
+        for chunk in cold_accounts.chunks(ACCOUNTS_PER_FETCH) {
+            for (address, account) in chunk.iter().zip(self.client.get_multiple_accounts(chunk)?) {
+                if account.is_some() {
+                    existing.insert(*address);
+                }
+            }
+        }
+        let source_addresses: Vec<Pubkey> = sources.iter().map(|source| source.address).collect();
+        let mut source_lamports = Vec::with_capacity(source_addresses.len());
+        for chunk in source_addresses.chunks(ACCOUNTS_PER_FETCH) {
+            source_lamports.extend(
+                self.client
+                    .get_multiple_accounts(chunk)?
+                    .into_iter()
+                    .map(|account| account.map_or(0, |account| account.lamports)),
+            );
+        }
+        let account_rent = self.client.get_minimum_balance_for_rent_exemption(spl_token::state::Account::LEN)?;
+
+        let mut moves = Vec::with_capacity(sources.len());
+        for (source, lamports) in sources.iter().zip(source_lamports) {
+            let decimals = match source.decimals {
+                Some(decimals) => decimals,
+                None => self.client.get_token_supply(&source.mint)?.decimals,
+            };
+            let destination = get_associated_token_address(cold_destination, &source.mint);
+            moves.push(PlannedMove::Token {
+                mint: source.mint,
+                decimals,
+                source: source.address,
+                destination,
+                amount: source.amount,
+                create_destination: existing.insert(destination),
+                close_source: assets.close_accounts,
+                rent_lamports: if assets.close_accounts { lamports } else { 0 },
+            });
+        }
+
+        // Pack token moves into as few transactions as fit; SOL goes last, once fees are known
+        let mut batches: Vec<Vec<PlannedMove>> = Vec::new();
+        let mut current: Vec<PlannedMove> = Vec::new();
+        for planned in moves {
+            let mut candidate = current.clone();
+            candidate.push(planned.clone());
+            let instructions = move_instructions(hot_wallet, cold_destination, &candidate)?;
+            if !current.is_empty() && transaction_size(&instructions, hot_wallet) > PACKET_DATA_SIZE {
+                batches.push(std::mem::replace(&mut current, vec![planned]));
+            } else {
+                current = candidate;
+            }
+        }
+        if !current.is_empty() {
+            batches.push(current);
+        }
+
+        let blockhash = self.client.get_latest_blockhash()?;
+        let sol_balance = self.client.get_balance(hot_wallet)?;
+        let mut remaining = sol_balance;
+        let mut transactions = Vec::with_capacity(batches.len() + 1);
+        let mut total_rent_lamports = 0;
+        for batch in batches {
+            let rent = account_rent
+                * batch
+                    .iter()
+                    .filter(|planned| matches!(planned, PlannedMove::Token { create_destination: true, .. }))
+                    .count() as u64;
+            let transaction = planned_transaction(hot_wallet, cold_destination, batch, remaining, &blockhash)?;
+            remaining = remaining.saturating_sub(transaction.fee_lamports + rent);
+            total_rent_lamports += rent;
+            transactions.push(transaction);
+        }
+
+        if let Some(reserve) = assets.sol_reserve {
+            let probe = vec![PlannedMove::Sol { lamports: 0 }];
+            let sol_fee = planned_transaction(hot_wallet, cold_destination, probe, remaining, &blockhash)?.fee_lamports;
+            match remaining.checked_sub(reserve + sol_fee).filter(|lamports| *lamports > 0) {
+                Some(lamports) => transactions.push(planned_transaction(
+                    hot_wallet,
+                    cold_destination,
+                    vec![PlannedMove::Sol { lamports }],
+                    remaining,
+                    &blockhash,
+                )?),
+                None => skipped.push(SkippedBalance {
+                    mint: None,
+                    account: *hot_wallet,
+                    amount: remaining,
+                    reason: format!(
+                        "nothing above the {} reserve after fees",
+                        Currency::Sol.format_amount(reserve)
+                    ),
+                }),
+            }
+        }
+
+        let plan = SweepPlan {
+            version: PLAN_VERSION,
+            id: uuid::Uuid::new_v4().to_string(),
+            hot_wallet: *hot_wallet,
+            cold_destination: *cold_destination,
+            created_at: unix_now(),
+            selection: assets.clone(),
+            sol_balance,
+            total_fee_lamports: transactions.iter().map(|transaction| transaction.fee_lamports).sum(),
+            total_rent_lamports,
+            transactions,
+            skipped,
+        };
+        tracing::info!(
+            "Planned cold sweep {} in {} transactions, {} balances left behind",
+            plan.id,
+            plan.transactions.len(),
+            plan.skipped.len()
+        );
+        Ok(plan)
+    }
+
+    /// Check the plan at `plan_file` against the chain again, then sign and send its
+    /// transactions in order
+    ///
+    /// Refuses, with a `ColdSweepError`, plans for another wallet or altered in any way, and
+    /// halts before the first transaction whose accounts no longer match the plan. Each sent
+    /// signature is saved to `plan_file` as soon as it confirms.
+    pub async fn execute_sweep_plan(&self, wallet: &Keypair, plan_file: &Path) -> Result<SweepExecution> {
+        let outcome = self.execute_sweep_plan_unlogged(wallet, plan_file).await;
+        let plan = SweepPlan::load(plan_file).ok();
+        let parameters = serde_json::json!({
+            "wallet": wallet.pubkey().to_string(),
+            "plan": plan.as_ref().map(|plan| plan.id.clone()),
+            "to": plan.as_ref().map(|plan| plan.cold_destination.to_string()),
+            "transactions": plan.as_ref().map(|plan| plan.transactions.len()),
+            "lamports": plan.as_ref().map(SweepPlan::total_sol_moved),
+        });
+        self.log_action("execute_sweep_plan", parameters, None, outcome.as_ref(), |execution| {
+            let signatures: Vec<String> = execution.signatures.iter().map(Signature::to_string).collect();
+            signatures.join(",")
+        });
+        outcome
+    }
+
+    #[tracing::instrument(skip_all, fields(wallet = %readable(&wallet.pubkey()), plan = %plan_file.display()))]
+    async fn execute_sweep_plan_unlogged(&self, wallet: &Keypair, plan_file: &Path) -> Result<SweepExecution> {
+        let mut plan = SweepPlan::load(plan_file)?;
+        if wallet.pubkey() != plan.hot_wallet {
+            return Err(ColdSweepError::WrongWallet {
+                expected: plan.hot_wallet,
+                actual: wallet.pubkey(),
+            }
+            .into());
+        }
+        let messages = plan.verify()?;
+
+        let already_sent = plan.transactions.iter().filter(|transaction| transaction.signature.is_some()).count();
+        let mut signatures = Vec::new();
+        for (index, message) in messages.into_iter().enumerate() {
+            if plan.transactions[index].signature.is_some() {
+                continue;
+            }
+            if let Some(reason) = self.sweep_divergence(&plan, &plan.transactions[index])? {
+                return Err(ColdSweepError::Diverged {
+                    transaction: index,
+                    sent: already_sent + signatures.len(),
+                    reason,
+                }
+                .into());
+            }
+
+            let mut transaction = Transaction::new_unsigned(message);
+            self.sign_with_fresh_blockhash(&mut transaction, &[wallet])?;
+            let signature = self.send_and_confirm_tracked(OperationKind::Payment, "cold_sweep", &transaction)?;
+            tracing::info!("Cold sweep {} transaction {} sent: {}", plan.id, index + 1, signature);
+            plan.transactions[index].signature = Some(signature);
+            plan.save(plan_file)?;
+            signatures.push(signature);
+        }
+
+        Ok(SweepExecution {
+            plan_id: plan.id,
+            signatures,
+            already_sent,
+        })
+    }
+
+    /// How the accounts `planned` touches differ from the plan, if they do
+    fn sweep_divergence(&self, plan: &SweepPlan, planned: &PlannedTransaction) -> Result<Option<String>> {
+        let balance = self.client.get_balance(&plan.hot_wallet)?;
+        if balance.abs_diff(planned.expected_sol_balance) > plan.selection.sol_tolerance {
+            return Ok(Some(format!(
+                "the hot wallet holds {} but the plan expects {} (tolerance {})",
+                Currency::Sol.format_amount(balance),
+                Currency::Sol.format_amount(planned.expected_sol_balance),
+                Currency::Sol.format_amount(plan.selection.sol_tolerance)
+            )));
+        }
+
+        let token_moves: Vec<(&Pubkey, &Pubkey, u64, u8)> = planned
+            .moves
+            .iter()
+            .filter_map(|planned| match planned {
+                PlannedMove::Token { mint, source, amount, decimals, .. } => Some((mint, source, *amount, *decimals)),
+                PlannedMove::Sol { .. } => None,
+            })
+            .collect();
+        let sources: Vec<Pubkey> = token_moves.iter().map(|(_, source, _, _)| **source).collect();
+        let mut accounts = Vec::with_capacity(sources.len());
+        for chunk in sources.chunks(ACCOUNTS_PER_FETCH) {
+            accounts.extend(self.client.get_multiple_accounts(chunk)?);
+        }
+        for ((mint, source, amount, decimals), account) in token_moves.into_iter().zip(accounts) {
+            let Some(state) = account
+                .filter(|account| account.owner == spl_token::id())
+                .and_then(|account| spl_token::state::Account::unpack(&account.data).ok())
+            else {
+                return Ok(Some(format!("token account {} no longer exists", source)));
+            };
+            if state.owner != plan.hot_wallet || state.mint != *mint {
+                return Ok(Some(format!("token account {} is no longer the hot wallet's account for {}", source, mint)));
+            }
+            if state.is_frozen() {
+                return Ok(Some(format!("token account {} has been frozen", source)));
+            }
+            if state.amount != amount {
+                let currency = Currency::spl(*mint, decimals);
+                return Ok(Some(format!(
+                    "token account {} holds {} but the plan moves {}",
+                    source,
+                    currency.format_amount(state.amount),
+                    currency.format_amount(amount)
+                )));
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// A transaction carrying `moves`, with its fee worked out and its summary written
+fn planned_transaction(
+    hot_wallet: &Pubkey,
+    cold_destination: &Pubkey,
+    moves: Vec<PlannedMove>,
+    expected_sol_balance: u64,
+    blockhash: &Hash,
+) -> Result<PlannedTransaction> {
+    let instructions = move_instructions(hot_wallet, cold_destination, &moves)?;
+    let message = Message::new_with_blockhash(&instructions, Some(hot_wallet), blockhash);
+    let fee_lamports = transaction_fee(&message, 0);
+    let bytes = message.serialize();
+    Ok(PlannedTransaction {
+        summary: transaction_summary(hot_wallet, cold_destination, &moves, fee_lamports),
+        moves,
+        fee_lamports,
+        expected_sol_balance,
+        message: base64::engine::general_purpose::STANDARD.encode(&bytes),
+        message_hash: hex_sha256(&bytes),
+        signature: None,
+    })
+}
+
+/// Create the cold account where needed, transfer, close, all signed by the hot wallet alone
+fn move_instructions(hot_wallet: &Pubkey, cold_destination: &Pubkey, moves: &[PlannedMove]) -> Result<Vec<Instruction>> {
+    let mut instructions = Vec::new();
+    for planned in moves {
+        match planned {
+            PlannedMove::Token {
+                mint,
+                decimals,
+                source,
+                destination,
+                amount,
+                create_destination,
+                close_source,
+                ..
+            } => {
+                if *create_destination {
+                    instructions.push(create_associated_token_account_idempotent(
+                        hot_wallet,
+                        cold_destination,
+                        mint,
+                        &spl_token::id(),
+                    ));
+                }
+                if *amount > 0 {
+                    instructions.push(spl_token::instruction::transfer_checked(
+                        &spl_token::id(),
+                        source,
+                        mint,
+                        destination,
+                        hot_wallet,
+                        &[],
+                        *amount,
+                        *decimals,
+                    )?);
+                }
+                if *close_source {
+                    instructions.push(spl_token::instruction::close_account(
+                        &spl_token::id(),
+                        source,
+                        cold_destination,
+                        hot_wallet,
+                        &[],
+                    )?);
+                }
+            }
+            PlannedMove::Sol { lamports } => {
+                instructions.push(system_instruction::transfer(hot_wallet, cold_destination, *lamports));
+            }
+        }
+    }
+    Ok(instructions)
+}
+
+/// The readable lines of a planned transaction, rebuilt from its moves when it is verified
+fn transaction_summary(
+    hot_wallet: &Pubkey,
+    cold_destination: &Pubkey,
+    moves: &[PlannedMove],
+    fee_lamports: u64,
+) -> Vec<String> {
+    let sol = |lamports: u64| Currency::Sol.format_amount(lamports);
+    let mut lines = Vec::new();
+    for planned in moves {
+        match planned {
+            PlannedMove::Token {
+                mint,
+                decimals,
+                source,
+                destination,
+                amount,
+                create_destination,
+                close_source,
+                rent_lamports,
+            } => {
+                let currency = Currency::spl(*mint, *decimals);
+                if *create_destination {
+                    lines.push(format!(
+                        "Create {}'s {} account {}, rent paid by {}",
+                        cold_destination,
+                        currency.label(),
+                        destination,
+                        hot_wallet
+                    ));
+                }
+                if *amount > 0 {
+                    lines.push(format!(
+                        "Move {} ({}) from {} to {}",
+                        currency.format_amount(*amount),
+                        mint,
+                        source,
+                        destination
+                    ));
+                }
+                if *close_source {
+                    lines.push(format!("Close {}, returning {} rent to {}", source, sol(*rent_lamports), cold_destination));
+                }
+            }
+            PlannedMove::Sol { lamports } => {
+                lines.push(format!("Move {} from {} to {}", sol(*lamports), hot_wallet, cold_destination));
+            }
+        }
+    }
+    lines.push(format!("Network fee {}, paid by {}", sol(fee_lamports), hot_wallet));
+    lines
+}
+
+fn hex_sha256(bytes: &[u8]) -> String {
+    Sha256::digest(bytes).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_cluster::{MockCluster, TempDir};
+    use solana_sdk::rent::Rent;
+
+    const HOT_SOL: u64 = 1_000_000_000;
+
+    struct Setup {
+        cluster: MockCluster,
+        client: FinternetClient,
+        hot: Keypair,
+        cold: Pubkey,
+        mint: Pubkey,
+        source: Pubkey,
+        dir: TempDir,
+    }
+
+    /// A hot wallet holding SOL and 5 units of a 6-decimal mint, and an empty cold wallet
+    fn setup() -> Setup {
+        let cluster = MockCluster::new();
+        let hot = Keypair::new();
+        cluster.set_balance(hot.pubkey(), HOT_SOL);
+        let mint = cluster.add_mint(&spl_token::id(), 6, &Pubkey::new_unique());
+        let source = cluster.add_token_account(&hot.pubkey(), &mint, 5_000_000);
+        let client = cluster.default_client();
+        Setup { client, hot, cold: Keypair::new().pubkey(), mint, source, dir: TempDir::new("cold-sweep"), cluster }
+    }
+
+    fn sweep_error(error: anyhow::Error) -> ColdSweepError {
+        error.downcast().unwrap()
+    }
+
+    impl Setup {
+        async fn plan(&self, selection: &SweepSelection) -> std::path::PathBuf {
+            let plan = self.client.plan_cold_sweep(&self.hot.pubkey(), &self.cold, selection).await.unwrap();
+            let path = self.dir.join("plan.json");
+            plan.save(&path).unwrap();
+            path
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn a_plan_lists_every_move_and_fee_without_signing_anything() {
+        let s = setup();
+        let plan = s.client.plan_cold_sweep(&s.hot.pubkey(), &s.cold, &SweepSelection::default()).await.unwrap();
+        assert!(s.cluster.sent().is_empty());
+
+        assert_eq!(plan.transactions.len(), 2);
+        let rent = Rent::default().minimum_balance(spl_token::state::Account::LEN);
+        let destination = get_associated_token_address(&s.cold, &s.mint);
+        let tokens = &plan.transactions[0];
+        assert_eq!(
+            tokens.moves,
+            [PlannedMove::Token {
+                mint: s.mint,
+                decimals: 6,
+                source: s.source,
+                destination,
+                amount: 5_000_000,
+                create_destination: true,
+                close_source: false,
+                rent_lamports: 0,
+            }]
+        );
+        assert_eq!(tokens.expected_sol_balance, HOT_SOL);
+        assert!(tokens.summary[0].starts_with(&format!("Create {}'s", s.cold)), "{:?}", tokens.summary);
+
+        // SOL goes last, leaving the reserve once every fee and the new account's rent are paid
+        let sol = &plan.transactions[1];
+        let remaining = HOT_SOL - tokens.fee_lamports - rent;
+        assert_eq!(sol.expected_sol_balance, remaining);
+        assert_eq!(sol.moves, [PlannedMove::Sol { lamports: remaining - DEFAULT_SOL_RESERVE - sol.fee_lamports }]);
+        assert_eq!(plan.total_fee_lamports, tokens.fee_lamports + sol.fee_lamports);
+        assert_eq!(plan.total_rent_lamports, rent);
+        assert!(plan.to_string().contains("Network fee"));
+
+        let path = s.dir.join("plan.json");
+        plan.save(&path).unwrap();
+        let loaded = SweepPlan::load(&path).unwrap();
+        assert_eq!(loaded, plan);
+        assert_eq!(loaded.verify().unwrap().len(), 2);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn a_token_balance_changed_since_planning_halts_before_anything_is_signed() {
+        let s = setup();
+        let path = s.plan(&SweepSelection::default()).await;
+        s.cluster.set_token_account(s.source, &s.hot.pubkey(), &s.mint, 4_000_000);
+
+        let error = sweep_error(s.client.execute_sweep_plan(&s.hot, &path).await.unwrap_err());
+        let ColdSweepError::Diverged { transaction: 0, sent: 0, reason } = &error else {
+            panic!("expected a divergence at the first transaction: {}", error);
+        };
+        assert!(reason.contains("holds 4") && reason.contains("plan moves 5"), "{}", reason);
+        assert_eq!(error.code(), "sweep_plan_diverged");
+        assert!(s.cluster.sent().is_empty());
+        assert!(!SweepPlan::load(&path).unwrap().is_complete());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn sol_drift_beyond_the_tolerance_halts_but_drift_within_it_does_not() {
+        let s = setup();
+        let selection = SweepSelection { tokens: TokenSelection::None, ..SweepSelection::default() };
+        let path = s.plan(&selection).await;
+
+        s.cluster.set_balance(s.hot.pubkey(), HOT_SOL + DEFAULT_SOL_TOLERANCE + 1);
+        let error = sweep_error(s.client.execute_sweep_plan(&s.hot, &path).await.unwrap_err());
+        assert!(matches!(error, ColdSweepError::Diverged { transaction: 0, sent: 0, .. }), "{}", error);
+        assert!(s.cluster.sent().is_empty());
+
+        s.cluster.set_balance(s.hot.pubkey(), HOT_SOL - DEFAULT_SOL_TOLERANCE);
+        let execution = s.client.execute_sweep_plan(&s.hot, &path).await.unwrap();
+        assert_eq!((execution.signatures.len(), execution.already_sent), (1, 0));
+        assert_eq!(s.cluster.sent().len(), 1);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn a_halt_midway_records_what_was_sent_and_a_rerun_resumes_after_it() {
+        let s = setup();
+        let path = s.plan(&SweepSelection::default()).await;
+
+        // The mock cluster does not run the token transaction, so the SOL it spent never leaves
+        let error = sweep_error(s.client.execute_sweep_plan(&s.hot, &path).await.unwrap_err());
+        assert!(matches!(error, ColdSweepError::Diverged { transaction: 1, sent: 1, .. }), "{}", error);
+        let sent = s.cluster.sent();
+        assert_eq!(sent.len(), 1);
+        let plan = SweepPlan::load(&path).unwrap();
+        assert_eq!(plan.transactions[0].signature, Some(sent[0].signatures[0]));
+        assert_eq!(plan.transactions[1].signature, None);
+
+        s.cluster.set_balance(s.hot.pubkey(), plan.transactions[1].expected_sol_balance);
+        let execution = s.client.execute_sweep_plan(&s.hot, &path).await.unwrap();
+        assert_eq!((execution.signatures.len(), execution.already_sent), (1, 1));
+        assert_eq!(s.cluster.sent().len(), 2);
+        assert!(SweepPlan::load(&path).unwrap().is_complete());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn an_edited_plan_or_another_wallet_is_refused_before_the_chain_is_checked() {
+        let s = setup();
+        let path = s.plan(&SweepSelection::default()).await;
+        let original = SweepPlan::load(&path).unwrap();
+
+        let error = sweep_error(s.client.execute_sweep_plan(&Keypair::new(), &path).await.unwrap_err());
+        assert!(matches!(error, ColdSweepError::WrongWallet { .. }), "{}", error);
+
+        // Moving less than the message does, so a reviewer would be misled
+        let mut edited = original.clone();
+        if let PlannedMove::Token { amount, .. } = &mut edited.transactions[0].moves[0] {
+            *amount = 1;
+        }
+        edited.save(&path).unwrap();
+        let error = sweep_error(s.client.execute_sweep_plan(&s.hot, &path).await.unwrap_err());
+        assert_eq!(error, ColdSweepError::Tampered { transaction: 0, reason: "transaction does not match the planned moves".to_string() });
+
+        let mut edited = original.clone();
+        edited.transactions[1].summary[0] = "Move 0.001 SOL to cold storage".to_string();
+        edited.save(&path).unwrap();
+        let error = sweep_error(s.client.execute_sweep_plan(&s.hot, &path).await.unwrap_err());
+        assert!(matches!(error, ColdSweepError::Tampered { transaction: 1, .. }), "{}", error);
+
+        let mut edited = original;
+        edited.transactions[0].message_hash = "00".repeat(32);
+        edited.save(&path).unwrap();
+        let error = sweep_error(s.client.execute_sweep_plan(&s.hot, &path).await.unwrap_err());
+        assert!(error.to_string().contains("message hash"), "{}", error);
+
+        assert!(s.cluster.sent().is_empty());
+        assert_eq!(s.cluster.call_count("getBalance"), 1);
+    }
+}
@@ -0,0 +1,224 @@
+use crate::fees::FeeStrategy;
+use crate::rpc_options::RpcCallOptions;
+use crate::{FinternetClient, FinternetConfig};
+use serde::{Deserialize, Serialize};
+use solana_sdk::commitment_config::CommitmentConfig;
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// Class of transaction a high-level method sends, each with its own `ConfirmationPolicy`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OperationKind {
+    /// `send_payment`, `send_split_payment`, batch payments, and approved payments
+    Payment,
+    /// `tokenize_asset` and its variants
+    Tokenization,
+    /// Memo ledger entries, including identity registrations
+    LedgerEntry,
+    /// Everything else, e.g. `verify_creator`
+    Other,
+}
+
+/// How long, and to what commitment, sends of one `OperationKind` are waited on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConfirmationPolicy {
+    /// `None` uses `FinternetConfig::commitment_level`
+    pub commitment: Option<CommitmentConfig>,
+    /// `None` leaves it to the RPC client, roughly until the blockhash expires
+    pub timeout: Option<Duration>,
+    /// Return the signature as soon as the node accepts the transaction
+    ///
+    /// The transaction is still journaled in the outbox, left `Submitted` for `recover_outbox`
+    /// to resolve.
+    pub wait: bool,
+    /// `None` uses `FinternetConfig::fee_strategy`
+    pub fee_strategy: Option<FeeStrategy>,
+}
+
+impl Default for ConfirmationPolicy {
+    fn default() -> Self {
+        Self {
+            commitment: None,
+            timeout: None,
+            wait: true,
+            fee_strategy: None,
+        }
+    }
+}
+
+impl ConfirmationPolicy {
+    pub fn wait_for(commitment: CommitmentConfig, timeout: Duration) -> Self {
+        Self {
+            commitment: Some(commitment),
+            timeout: Some(timeout),
+            wait: true,
+            fee_strategy: None,
+        }
+    }
+
+    pub fn fire_and_forget() -> Self {
+        Self {
+            wait: false,
+            ..Self::default()
+        }
+    }
+
+    /// Send with `strategy` rather than the client-wide fee strategy
+    pub fn with_fee_strategy(mut self, strategy: FeeStrategy) -> Self {
+        self.fee_strategy = Some(strategy);
+        self
+    }
+}
+
+/// `ConfirmationPolicy` per `OperationKind`; kinds without an entry use the default policy
+#[derive(Debug, Clone, Default)]
+pub struct ConfirmationPolicies {
+    policies: HashMap<OperationKind, ConfirmationPolicy>,
+}
+
+impl ConfirmationPolicies {
+    pub fn get(&self, kind: OperationKind) -> ConfirmationPolicy {
+        self.policies.get(&kind).copied().unwrap_or_default()
+    }
+
+    pub fn set(&mut self, kind: OperationKind, policy: ConfirmationPolicy) {
+        self.policies.insert(kind, policy);
+    }
+}
+
+impl FinternetConfig {
+    /// Apply `policy` to every send of `kind`
+    ///
+    /// By default every kind waits for `commitment_level` for as long as the RPC client does,
+    /// roughly until the blockhash expires. Per-call `RpcCallOptions` still take precedence.
+    pub fn with_confirmation_policy(mut self, kind: OperationKind, policy: ConfirmationPolicy) -> Self {
+        self.confirmation.set(kind, policy);
+        self
+    }
+}
+
+impl FinternetClient {
+    /// Per-call `options` with anything they leave unset filled in from `kind`'s policy
+    pub(crate) fn confirmation_options(&self, kind: OperationKind, options: &RpcCallOptions) -> RpcCallOptions {
+        let policy = self.config.confirmation.get(kind);
+        RpcCallOptions {
+            commitment: options.commitment.or(policy.commitment),
+            timeout: options.timeout.or(policy.timeout),
+            wait: Some(options.wait.unwrap_or(policy.wait)),
+            ..options.clone()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_cluster::{Landing, MockCluster, TempDir};
+    use crate::outbox::OutboxStatus;
+    use serde_json::json;
+    use solana_sdk::signature::Keypair;
+    use solana_sdk::signer::Signer;
+    use solana_sdk::system_instruction;
+    use solana_sdk::transaction::Transaction;
+
+    fn transfer(cluster: &MockCluster, payer: &Keypair) -> Transaction {
+        let instruction = system_instruction::transfer(&payer.pubkey(), &Keypair::new().pubkey(), 1);
+        Transaction::new_signed_with_payer(&[instruction], Some(&payer.pubkey()), &[payer], cluster.blockhash())
+    }
+
+    /// Report every signature as landed but only `confirmed`, never `finalized`
+    fn confirmed_only(cluster: &MockCluster) {
+        cluster.respond("getSignatureStatuses", |params| {
+            let count = params.get(0).and_then(|signatures| signatures.as_array()).map_or(0, Vec::len);
+            let status = json!({ "slot": 1_000, "confirmations": 1, "err": null, "status": { "Ok": null }, "confirmationStatus": "confirmed" });
+            Some(Ok(json!({ "context": { "slot": 1_000 }, "value": vec![status; count] })))
+        });
+    }
+
+    fn policies() -> FinternetConfig {
+        FinternetConfig::default()
+            .with_confirmation_policy(
+                OperationKind::Payment,
+                ConfirmationPolicy::wait_for(CommitmentConfig::confirmed(), Duration::from_secs(30)),
+            )
+            .with_confirmation_policy(
+                OperationKind::Tokenization,
+                ConfirmationPolicy::wait_for(CommitmentConfig::finalized(), Duration::from_secs(1)),
+            )
+            .with_confirmation_policy(OperationKind::LedgerEntry, ConfirmationPolicy::fire_and_forget())
+    }
+
+    #[test]
+    fn per_call_options_win_over_the_class_policy() {
+        let client = MockCluster::new().client(policies());
+
+        let options = client.confirmation_options(OperationKind::Tokenization, &RpcCallOptions::default());
+        assert_eq!(options.commitment, Some(CommitmentConfig::finalized()));
+        assert_eq!(options.timeout, Some(Duration::from_secs(1)));
+        assert_eq!(options.wait, Some(true));
+
+        let per_call = RpcCallOptions {
+            commitment: Some(CommitmentConfig::processed()),
+            timeout: Some(Duration::from_secs(5)),
+            wait: Some(false),
+            ..RpcCallOptions::default()
+        };
+        let options = client.confirmation_options(OperationKind::Tokenization, &per_call);
+        assert_eq!(
+            (options.commitment, options.timeout, options.wait),
+            (Some(CommitmentConfig::processed()), Some(Duration::from_secs(5)), Some(false))
+        );
+
+        // A kind without a policy leaves everything to the client defaults, and waits
+        let options = client.confirmation_options(OperationKind::Other, &RpcCallOptions::default());
+        assert_eq!((options.commitment, options.timeout, options.wait), (None, None, Some(true)));
+        assert_eq!(client.config.confirmation.get(OperationKind::Other), ConfirmationPolicy::default());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn each_class_waits_for_its_own_commitment() {
+        let (cluster, payer) = (MockCluster::new(), Keypair::new());
+        confirmed_only(&cluster);
+        let client = cluster.client(policies());
+
+        // Payments settle for `confirmed`, as does a class left on the client's default
+        for kind in [OperationKind::Payment, OperationKind::Other] {
+            client.send_and_confirm_resigning(kind, "test", transfer(&cluster, &payer), &[&payer]).unwrap();
+        }
+
+        // Tokenizations hold out for `finalized` and give up at their own timeout
+        let started = std::time::Instant::now();
+        let error = client
+            .send_and_confirm_resigning(OperationKind::Tokenization, "test", transfer(&cluster, &payer), &[&payer])
+            .unwrap_err();
+        assert!(error.to_string().contains("not Finalized after 1s"), "{}", error);
+        assert!(started.elapsed() >= Duration::from_secs(1) && started.elapsed() < Duration::from_secs(10));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn fire_and_forget_returns_the_signature_and_leaves_it_in_the_outbox() {
+        let (cluster, dir, payer) = (MockCluster::new(), TempDir::new("confirmation"), Keypair::new());
+        cluster.set_landing(Landing::Pending);
+        let client = cluster.client(FinternetConfig { outbox_path: Some(dir.join("outbox.json")), ..policies() });
+
+        let statuses = cluster.call_count("getSignatureStatuses");
+        let transaction = transfer(&cluster, &payer);
+        let signature = client
+            .send_and_confirm_resigning(OperationKind::LedgerEntry, "write_ledger_entry", transaction.clone(), &[&payer])
+            .unwrap();
+        assert_eq!(signature, transaction.signatures[0]);
+        assert_eq!(cluster.sent().len(), 1);
+        // Nothing waited on it
+        assert_eq!(cluster.call_count("getSignatureStatuses"), statuses);
+
+        let entries = client.outbox.as_ref().unwrap().entries().unwrap();
+        let entry = entries.iter().find(|entry| entry.signature == signature).unwrap();
+        assert_eq!((entry.status, entry.label.as_str()), (OutboxStatus::Submitted, "write_ledger_entry"));
+
+        // Reconciled later like any other submitted send
+        cluster.land(&signature, None);
+        let recovery = client.recover_outbox().await.unwrap();
+        assert_eq!(recovery.confirmed, vec![signature]);
+    }
+}
@@ -0,0 +1,276 @@
+use crate::fees::LAMPORTS_PER_SIGNATURE;
+use crate::telemetry::readable;
+use crate::FinternetClient;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use solana_client::rpc_client::GetConfirmedSignaturesForAddress2Config;
+use solana_client::rpc_config::RpcTransactionConfig;
+use solana_sdk::{commitment_config::CommitmentConfig, pubkey::Pubkey, signature::Signature};
+use solana_transaction_status::{
+    option_serializer::OptionSerializer, EncodedConfirmedTransactionWithStatusMeta, EncodedTransaction,
+    UiInstruction, UiMessage, UiParsedInstruction, UiTransactionEncoding,
+};
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+const LAMPORTS_PER_SOL: f64 = 1_000_000_000.0;
+const SIGNATURE_PAGE_LIMIT: usize = 1000;
+
+/// What a transaction was for, as far as cost reporting is concerned
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CostCategory {
+    Payments,
+    Tokenizations,
+    Identity,
+    Other,
+}
+
+/// An amount in lamports with its SOL and, once priced, USD equivalents
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct CostAmount {
+    pub lamports: u64,
+    pub sol: f64,
+    pub usd: Option<f64>,
+}
+
+impl CostAmount {
+    fn add(&mut self, lamports: u64) {
+        self.lamports += lamports;
+        self.sol = self.lamports as f64 / LAMPORTS_PER_SOL;
+    }
+
+    fn price(&mut self, sol_usd: f64) {
+        self.usd = Some(self.sol * sol_usd);
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CategoryCosts {
+    pub transactions: usize,
+    /// Network fees paid as fee payer
+    pub fees: CostAmount,
+    /// The part of `fees` above the base fee per signature, i.e. priority fees
+    #[serde(default)]
+    pub priority_fees: CostAmount,
+    /// Rent deposited into accounts the wallet funded (ATAs, mints, metadata)
+    pub rent_paid: CostAmount,
+    /// Rent returned to the wallet by closed accounts
+    pub rent_reclaimed: CostAmount,
+}
+
+impl CategoryCosts {
+    fn merge(&mut self, other: &CategoryCosts) {
+        self.transactions += other.transactions;
+        self.fees.add(other.fees.lamports);
+        self.priority_fees.add(other.priority_fees.lamports);
+        self.rent_paid.add(other.rent_paid.lamports);
+        self.rent_reclaimed.add(other.rent_reclaimed.lamports);
+    }
+
+    fn price(&mut self, sol_usd: f64) {
+        self.fees.price(sol_usd);
+        self.priority_fees.price(sol_usd);
+        self.rent_paid.price(sol_usd);
+        self.rent_reclaimed.price(sol_usd);
+    }
+
+    /// Fees plus rent paid, minus rent reclaimed
+    pub fn net_lamports(&self) -> i128 {
+        self.fees.lamports as i128 + self.rent_paid.lamports as i128 - self.rent_reclaimed.lamports as i128
+    }
+}
+
+/// Network fees and rent for one wallet over a time window
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CostSummary {
+    pub owner: Pubkey,
+    pub from_ts: u64,
+    pub to_ts: u64,
+    pub by_category: BTreeMap<CostCategory, CategoryCosts>,
+    pub total: CategoryCosts,
+    /// SOL/USD rate the USD figures were computed at, if any
+    pub sol_usd_price: Option<f64>,
+}
+
+impl CostSummary {
+    /// Fill in the USD equivalent of every amount at the given SOL price
+    pub fn with_usd_price(mut self, sol_usd: f64) -> Self {
+        for costs in self.by_category.values_mut() {
+            costs.price(sol_usd);
+        }
+        self.total.price(sol_usd);
+        self.sol_usd_price = Some(sol_usd);
+        self
+    }
+}
+
+impl FinternetClient {
+    /// Sum the fees and rent `owner` paid, and the rent it got back, between two unix timestamps
+    #[tracing::instrument(skip_all, fields(wallet = %readable(owner), from_ts, to_ts, rpc = %self.rpc_endpoint()))]
+    pub async fn get_cost_summary(&self, owner: &Pubkey, from_ts: u64, to_ts: u64) -> Result<CostSummary> {
+        let mut by_category: BTreeMap<CostCategory, CategoryCosts> = BTreeMap::new();
+        let mut before = None;
+
+        'pages: loop {
+            let page = self.client.get_signatures_for_address_with_config(
+                owner,
+                GetConfirmedSignaturesForAddress2Config {
+                    before,
+                    until: None,
+                    limit: Some(SIGNATURE_PAGE_LIMIT),
+                    commitment: Some(CommitmentConfig::confirmed()),
+                },
+            )?;
+            let Some(last) = page.last() else { break };
+            before = Some(Signature::from_str(&last.signature)?);
+            let exhausted = page.len() < SIGNATURE_PAGE_LIMIT;
+
+            // Signatures come newest first
+            for sig_info in &page {
+                let block_time = sig_info.block_time.unwrap_or(0).max(0) as u64;
+                if block_time > to_ts {
+                    continue;
+                }
+                if block_time < from_ts {
+                    break 'pages;
+                }
+
+                let signature = Signature::from_str(&sig_info.signature)?;
+                let transaction = match self.client.get_transaction_with_config(
+                    &signature,
+                    RpcTransactionConfig {
+                        encoding: Some(UiTransactionEncoding::JsonParsed),
+                        commitment: Some(CommitmentConfig::confirmed()),
+                        max_supported_transaction_version: Some(0),
+                    },
+                ) {
+                    Ok(transaction) => transaction,
+                    Err(e) => {
+                        tracing::warn!("Skipping {} in cost summary: {}", signature, e);
+                        continue;
+                    }
+                };
+
+                if let Some((category, costs)) = transaction_costs(owner, &transaction) {
+                    by_category.entry(category).or_default().merge(&costs);
+                }
+            }
+
+            if exhausted {
+                break;
+            }
+        }
+
+        let mut total = CategoryCosts::default();
+        for costs in by_category.values() {
+            total.merge(costs);
+        }
+        tracing::info!(
+            "Cost summary over {} transactions: {} lamports fees ({} priority), {} rent paid, {} reclaimed",
+            total.transactions,
+            total.fees.lamports,
+            total.priority_fees.lamports,
+            total.rent_paid.lamports,
+            total.rent_reclaimed.lamports
+        );
+
+        Ok(CostSummary {
+            owner: *owner,
+            from_ts,
+            to_ts,
+            by_category,
+            total,
+            sol_usd_price: None,
+        })
+    }
+}
+
+/// Attribute one transaction's fee and rent movements to `owner`
+fn transaction_costs(
+    owner: &Pubkey,
+    transaction: &EncodedConfirmedTransactionWithStatusMeta,
+) -> Option<(CostCategory, CategoryCosts)> {
+    let meta = transaction.transaction.meta.as_ref()?;
+    let EncodedTransaction::Json(ui_transaction) = &transaction.transaction.transaction else {
+        return None;
+    };
+    let UiMessage::Parsed(message) = &ui_transaction.message else {
+        return None;
+    };
+
+    let owner_str = owner.to_string();
+    let account_index = |address: &str| message.account_keys.iter().position(|key| key.pubkey == address);
+
+    let mut instructions: Vec<&UiInstruction> = message.instructions.iter().collect();
+    if let OptionSerializer::Some(inner) = &meta.inner_instructions {
+        instructions.extend(inner.iter().flat_map(|set| set.instructions.iter()));
+    }
+
+    let mut costs = CategoryCosts {
+        transactions: 1,
+        ..CategoryCosts::default()
+    };
+    if message.account_keys.first().map(|key| key.pubkey == owner_str).unwrap_or(false) {
+        costs.fees.add(meta.fee);
+        let base_fee = LAMPORTS_PER_SIGNATURE * ui_transaction.signatures.len() as u64;
+        costs.priority_fees.add(meta.fee.saturating_sub(base_fee));
+    }
+
+    let mut category = CostCategory::Other;
+    let mut raise = |found: CostCategory| {
+        // Tokenization dominates identity, which dominates payments
+        let rank = |c: CostCategory| match c {
+            CostCategory::Tokenizations => 3,
+            CostCategory::Identity => 2,
+            CostCategory::Payments => 1,
+            CostCategory::Other => 0,
+        };
+        if rank(found) > rank(category) {
+            category = found;
+        }
+    };
+
+    for instruction in instructions {
+        match instruction {
+            UiInstruction::Parsed(UiParsedInstruction::Parsed(parsed)) => {
+                let kind = parsed.parsed.get("type").and_then(|t| t.as_str()).unwrap_or_default();
+                let info = parsed.parsed.get("info");
+                let field = |name: &str| info.and_then(|i| i.get(name));
+                let field_str = |name: &str| field(name).and_then(|v| v.as_str()).unwrap_or_default();
+
+                match (parsed.program.as_str(), kind) {
+                    ("system", "createAccount" | "createAccountWithSeed") if field_str("source") == owner_str => {
+                        costs.rent_paid.add(field("lamports").and_then(|v| v.as_u64()).unwrap_or(0));
+                    }
+                    ("system", "transfer") if field("lamports").and_then(|v| v.as_u64()).unwrap_or(0) > 0 => {
+                        raise(CostCategory::Payments)
+                    }
+                    ("spl-token", "transfer" | "transferChecked") => raise(CostCategory::Payments),
+                    ("spl-token", "closeAccount") if field_str("destination") == owner_str => {
+                        let reclaimed = account_index(field_str("account"))
+                            .and_then(|i| meta.pre_balances.get(i))
+                            .copied()
+                            .unwrap_or(0);
+                        costs.rent_reclaimed.add(reclaimed);
+                    }
+                    ("spl-memo", _) => {
+                        let memo = parsed.parsed.as_str().unwrap_or_default();
+                        if memo.contains("\"register_identity\"") {
+                            raise(CostCategory::Identity);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            UiInstruction::Parsed(UiParsedInstruction::PartiallyDecoded(decoded)) => {
+                if decoded.program_id == mpl_token_metadata::ID.to_string() {
+                    raise(CostCategory::Tokenizations);
+                }
+            }
+            UiInstruction::Compiled(_) => {}
+        }
+    }
+
+    Some((category, costs))
+}
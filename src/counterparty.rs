@@ -0,0 +1,296 @@
+use crate::history::transfer_records;
+use crate::telemetry::readable;
+use crate::FinternetClient;
+use anyhow::Result;
+use mpl_token_metadata::accounts::Metadata;
+use serde::{Deserialize, Serialize};
+use solana_client::rpc_client::GetConfirmedSignaturesForAddress2Config;
+use solana_client::rpc_config::RpcTransactionConfig;
+use solana_sdk::{commitment_config::CommitmentConfig, pubkey::Pubkey, signature::Signature};
+use solana_transaction_status::{
+    option_serializer::OptionSerializer, EncodedConfirmedTransactionWithStatusMeta, EncodedTransaction, UiMessage,
+    UiTransactionEncoding,
+};
+use std::collections::HashSet;
+use std::fmt;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Signatures read to count transactions and date the account
+const SIGNATURE_SCAN_LIMIT: usize = 1000;
+/// Most recent successful transactions fetched in full for counterparties and tokens
+const TRANSACTION_DETAIL_LIMIT: usize = 100;
+/// Issued assets whose metadata is checked for a verified collection
+const ISSUED_ASSET_CHECK_LIMIT: usize = 20;
+const NEW_ACCOUNT_SECS: u64 = 24 * 60 * 60;
+
+/// Something about an address worth a second look before trusting it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "flag", rename_all = "snake_case")]
+pub enum CounterpartyFlag {
+    /// No transactions at all
+    NoHistory,
+    /// First transaction less than 24 hours ago
+    NewAccount { age_secs: u64 },
+    /// Every inspected transfer was with the same address
+    SingleCounterparty { counterparty: Pubkey },
+    /// At least a quarter of the scanned transactions failed
+    HighFailureRate { failed: usize, scanned: usize },
+}
+
+impl fmt::Display for CounterpartyFlag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NoHistory => write!(f, "No transaction history"),
+            Self::NewAccount { age_secs } => {
+                write!(f, "Account created less than 24h ago ({}h old)", age_secs / 3600)
+            }
+            Self::SingleCounterparty { counterparty } => {
+                write!(f, "Only ever interacted with one address ({})", counterparty)
+            }
+            Self::HighFailureRate { failed, scanned } => {
+                write!(f, "{} of {} scanned transactions failed", failed, scanned)
+            }
+        }
+    }
+}
+
+/// On-chain reputation readout for an address, with the limits of the scan spelled out
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CounterpartyReport {
+    pub address: Pubkey,
+    pub generated_at: u64,
+    pub signatures_scanned: usize,
+    /// False when the scan stopped at `SIGNATURE_SCAN_LIMIT`; ages and counts are then lower bounds
+    pub history_complete: bool,
+    /// Block time of the oldest scanned transaction
+    pub first_seen: Option<u64>,
+    pub last_seen: Option<u64>,
+    pub account_age_secs: Option<u64>,
+    pub transaction_count: usize,
+    pub failed_transactions: usize,
+    /// Successful transactions fetched in full for the counterparty and token figures
+    pub transactions_inspected: usize,
+    pub distinct_counterparties: usize,
+    pub token_mints_transacted: usize,
+    pub token_mints_held: usize,
+    pub identity_registered: bool,
+    pub identity_name: Option<String>,
+    pub issued_assets: usize,
+    pub issued_assets_checked: usize,
+    pub has_verified_collection: bool,
+    pub flags: Vec<CounterpartyFlag>,
+    /// Plain-language description of what was and was not scanned
+    pub scan_notes: Vec<String>,
+}
+
+impl CounterpartyReport {
+    pub fn is_flagged(&self) -> bool {
+        !self.flags.is_empty()
+    }
+}
+
+impl FinternetClient {
+    /// Gather account age, activity, counterparties, tokens, identity, and issuance for `address`
+    ///
+    /// Scans are bounded: only the most recent signatures are read and only the most recent
+    /// transactions are fetched in full. `scan_notes` says exactly how much was covered.
+    #[tracing::instrument(skip_all, fields(wallet = %readable(address), rpc = %self.rpc_endpoint()))]
+    pub async fn analyze_counterparty(&self, address: &Pubkey) -> Result<CounterpartyReport> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+
+        let signatures = self.client.get_signatures_for_address_with_config(
+            address,
+            GetConfirmedSignaturesForAddress2Config {
+                limit: Some(SIGNATURE_SCAN_LIMIT),
+                commitment: Some(CommitmentConfig::confirmed()),
+                ..GetConfirmedSignaturesForAddress2Config::default()
+            },
+        )?;
+        let history_complete = signatures.len() < SIGNATURE_SCAN_LIMIT;
+        let failed_transactions = signatures.iter().filter(|status| status.err.is_some()).count();
+        let last_seen = signatures.first().and_then(|status| status.block_time).map(|t| t.max(0) as u64);
+        let first_seen = signatures.last().and_then(|status| status.block_time).map(|t| t.max(0) as u64);
+        let account_age_secs = first_seen.map(|first| now.saturating_sub(first));
+
+        let mut counterparties = HashSet::new();
+        let mut mints = HashSet::new();
+        let mut transactions_inspected = 0;
+        let mut unreadable = 0;
+        for status in signatures.iter().filter(|status| status.err.is_none()).take(TRANSACTION_DETAIL_LIMIT) {
+            let signature = Signature::from_str(&status.signature)?;
+            let transaction = match self.client.get_transaction_with_config(
+                &signature,
+                RpcTransactionConfig {
+                    encoding: Some(UiTransactionEncoding::Json),
+                    commitment: Some(CommitmentConfig::confirmed()),
+                    max_supported_transaction_version: Some(0),
+                },
+            ) {
+                Ok(transaction) => transaction,
+                Err(e) => {
+                    tracing::debug!("Skipping unreadable transaction {}: {}", signature, e);
+                    unreadable += 1;
+                    continue;
+                }
+            };
+            transactions_inspected += 1;
+            for record in transfer_records(address, signature, &transaction) {
+                mints.extend(record.currency.mint());
+                let other = if record.from == *address { record.to } else { record.from };
+                if other != Pubkey::default() {
+                    counterparties.insert(other);
+                }
+            }
+            counterparties.extend(sol_counterparties(address, &transaction));
+        }
+
+        let token_mints_held = match self.get_token_accounts(address).await {
+            Ok(balances) => balances.values().filter(|amount| **amount > 0).count(),
+            Err(e) => {
+                tracing::warn!("Could not read token accounts of {}: {}", address, e);
+                0
+            }
+        };
+
+        let identity = self.get_registered_identity(address).await.unwrap_or_else(|e| {
+            tracing::warn!("Could not read registered identity of {}: {}", address, e);
+            None
+        });
+
+        let issued = self.issued_mints(address).unwrap_or_else(|e| {
+            tracing::warn!("Could not list assets issued by {}: {}", address, e);
+            Vec::new()
+        });
+        let checked: Vec<Pubkey> = issued.iter().take(ISSUED_ASSET_CHECK_LIMIT).map(|(mint, _)| *mint).collect();
+        let has_verified_collection = checked.iter().any(|mint| {
+            self.client
+                .get_account_data(&Metadata::find_pda(mint).0)
+                .ok()
+                .and_then(|data| Metadata::from_bytes(&data).ok())
+                .and_then(|metadata| metadata.collection)
+                .map(|collection| collection.verified)
+                .unwrap_or(false)
+        });
+
+        let mut flags = Vec::new();
+        if signatures.is_empty() {
+            flags.push(CounterpartyFlag::NoHistory);
+        }
+        // With a truncated scan the account is at least this old, so only a full scan can say "new"
+        if let (true, Some(age_secs)) = (history_complete, account_age_secs) {
+            if age_secs < NEW_ACCOUNT_SECS {
+                flags.push(CounterpartyFlag::NewAccount { age_secs });
+            }
+        }
+        if counterparties.len() == 1 {
+            let counterparty = *counterparties.iter().next().expect("one counterparty");
+            flags.push(CounterpartyFlag::SingleCounterparty { counterparty });
+        }
+        if failed_transactions > 0 && failed_transactions * 4 >= signatures.len() {
+            flags.push(CounterpartyFlag::HighFailureRate {
+                failed: failed_transactions,
+                scanned: signatures.len(),
+            });
+        }
+
+        let mut scan_notes = vec![if history_complete {
+            format!("Full history scanned ({} signatures)", signatures.len())
+        } else {
+            format!(
+                "Based on the most recent {} signatures; older history was not scanned, so age and counts are lower bounds",
+                SIGNATURE_SCAN_LIMIT
+            )
+        }];
+        scan_notes.push(format!(
+            "Counterparties and tokens from the most recent {} successful transactions ({} inspected)",
+            TRANSACTION_DETAIL_LIMIT, transactions_inspected
+        ));
+        if unreadable > 0 {
+            scan_notes.push(format!("{} transactions could not be fetched and were skipped", unreadable));
+        }
+        if issued.len() > checked.len() {
+            scan_notes.push(format!(
+                "Collections checked on {} of {} issued assets",
+                checked.len(),
+                issued.len()
+            ));
+        }
+
+        Ok(CounterpartyReport {
+            address: *address,
+            generated_at: now,
+            signatures_scanned: signatures.len(),
+            history_complete,
+            first_seen,
+            last_seen,
+            account_age_secs,
+            transaction_count: signatures.len(),
+            failed_transactions,
+            transactions_inspected,
+            distinct_counterparties: counterparties.len(),
+            token_mints_transacted: mints.len(),
+            token_mints_held,
+            identity_registered: identity.is_some(),
+            identity_name: identity.and_then(|identity| identity.display_name),
+            issued_assets: issued.len(),
+            issued_assets_checked: checked.len(),
+            has_verified_collection,
+            flags,
+            scan_notes,
+        })
+    }
+}
+
+/// Wallets whose SOL moved opposite to `address`'s in a transaction, ignoring token accounts
+/// (whose rent changes are not payments) and the fee
+fn sol_counterparties(address: &Pubkey, transaction: &EncodedConfirmedTransactionWithStatusMeta) -> Vec<Pubkey> {
+    let Some(meta) = &transaction.transaction.meta else {
+        return Vec::new();
+    };
+    let keys: Vec<String> = match &transaction.transaction.transaction {
+        EncodedTransaction::Json(ui_transaction) => match &ui_transaction.message {
+            UiMessage::Raw(message) => message.account_keys.clone(),
+            UiMessage::Parsed(message) => message.account_keys.iter().map(|key| key.pubkey.clone()).collect(),
+        },
+        _ => return Vec::new(),
+    };
+    let Some(own_index) = keys.iter().position(|key| *key == address.to_string()) else {
+        return Vec::new();
+    };
+
+    let token_accounts: HashSet<usize> = [&meta.pre_token_balances, &meta.post_token_balances]
+        .into_iter()
+        .filter_map(|balances| match balances {
+            OptionSerializer::Some(balances) => Some(balances),
+            _ => None,
+        })
+        .flatten()
+        .map(|balance| balance.account_index as usize)
+        .collect();
+    let delta = |i: usize| {
+        let pre = meta.pre_balances.get(i).copied().unwrap_or(0) as i128;
+        let post = meta.post_balances.get(i).copied().unwrap_or(0) as i128;
+        post - pre
+    };
+
+    // The fee payer (index 0) would otherwise look like it sent SOL to the cluster
+    let mut own_delta = delta(own_index);
+    if own_index == 0 {
+        own_delta += meta.fee as i128;
+    }
+    if own_delta == 0 {
+        return Vec::new();
+    }
+    (0..keys.len())
+        .filter(|&i| i != own_index && !token_accounts.contains(&i))
+        .filter(|&i| {
+            let mut other = delta(i);
+            if i == 0 {
+                other += meta.fee as i128;
+            }
+            other != 0 && other.signum() == -own_delta.signum()
+        })
+        .filter_map(|i| Pubkey::from_str(&keys[i]).ok())
+        .collect()
+}
@@ -0,0 +1,196 @@
+use crate::telemetry::readable;
+use crate::FinternetClient;
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use solana_sdk::{
+    pubkey::Pubkey,
+    signature::{Keypair, Signature},
+    signer::Signer,
+};
+use std::collections::{BTreeMap, HashMap};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const CREDENTIAL_ANCHOR_ACTION: &str = "credential_anchor";
+const CREDENTIAL_REVOCATION_ACTION: &str = "credential_revocation";
+
+/// An issuer's signed attestation about a subject wallet, e.g. `kyc_level = 2`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SignedCredential {
+    pub id: String,
+    pub issuer: Pubkey,
+    pub subject: Pubkey,
+    /// Sorted so the signed payload is canonical
+    pub claims: BTreeMap<String, String>,
+    pub issued_at: u64,
+    pub expires_at: u64,
+    /// Issuer's off-chain message signature over `canonical_payload()`
+    pub signature: String,
+    /// Ledger entry carrying the payload hash, if the credential was anchored
+    pub anchor_signature: Option<Signature>,
+}
+
+/// Signed fields, serialized in this fixed order
+#[derive(Serialize)]
+struct CredentialPayload<'a> {
+    id: &'a str,
+    issuer: String,
+    subject: String,
+    claims: &'a BTreeMap<String, String>,
+    issued_at: u64,
+    expires_at: u64,
+}
+
+impl SignedCredential {
+    /// The exact bytes the issuer signs: compact JSON with sorted claims
+    pub fn canonical_payload(&self) -> Result<String> {
+        Ok(serde_json::to_string(&CredentialPayload {
+            id: &self.id,
+            issuer: self.issuer.to_string(),
+            subject: self.subject.to_string(),
+            claims: &self.claims,
+            issued_at: self.issued_at,
+            expires_at: self.expires_at,
+        })?)
+    }
+
+    /// Hex SHA-256 of the canonical payload, as anchored on-chain
+    pub fn payload_hash(&self) -> Result<String> {
+        let digest = Sha256::digest(self.canonical_payload()?.as_bytes());
+        Ok(digest.iter().map(|b| format!("{:02x}", b)).collect())
+    }
+}
+
+/// Outcome of `verify_credential`, checked in this order
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum CredentialStatus {
+    Valid,
+    InvalidSignature,
+    Expired { expired_at: u64 },
+    /// The anchor transaction is missing, not signed by the issuer, or carries another hash
+    AnchorMismatch,
+    Revoked { signature: Signature },
+}
+
+impl CredentialStatus {
+    pub fn is_valid(&self) -> bool {
+        matches!(self, Self::Valid)
+    }
+}
+
+impl FinternetClient {
+    /// Sign a set of claims about `subject`, optionally anchoring the credential's hash on-chain
+    #[tracing::instrument(skip_all, fields(wallet = %readable(&issuer_wallet.pubkey()), subject = %readable(&subject), claims = claims.len(), anchor))]
+    pub async fn issue_credential(
+        &self,
+        issuer_wallet: &Keypair,
+        subject: Pubkey,
+        claims: HashMap<String, String>,
+        expires_at: u64,
+        anchor: bool,
+    ) -> Result<SignedCredential> {
+        let issued_at = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        if expires_at <= issued_at {
+            return Err(anyhow!("Credential would already be expired at {}", expires_at));
+        }
+
+        let mut credential = SignedCredential {
+            id: uuid::Uuid::new_v4().to_string(),
+            issuer: issuer_wallet.pubkey(),
+            subject,
+            claims: claims.into_iter().collect(),
+            issued_at,
+            expires_at,
+            signature: String::new(),
+            anchor_signature: None,
+        };
+        credential.signature = Self::sign_offchain_message(issuer_wallet, &credential.canonical_payload()?)?;
+
+        if anchor {
+            let entry = serde_json::json!({
+                "action": CREDENTIAL_ANCHOR_ACTION,
+                "id": credential.id,
+                "subject": subject.to_string(),
+                "hash": credential.payload_hash()?,
+            });
+            let signature = self.write_ledger_entry(issuer_wallet, &entry.to_string()).await?;
+            credential.anchor_signature = Some(signature);
+        }
+
+        tracing::info!("Credential {} issued to {}", credential.id, subject);
+        Ok(credential)
+    }
+
+    /// Publish a revocation entry for a credential the wallet issued
+    #[tracing::instrument(skip_all, fields(wallet = %readable(&issuer_wallet.pubkey()), credential_id = %credential.id))]
+    pub async fn revoke_credential(&self, issuer_wallet: &Keypair, credential: &SignedCredential) -> Result<Signature> {
+        if credential.issuer != issuer_wallet.pubkey() {
+            return Err(anyhow!("Only the issuer {} can revoke credential {}", credential.issuer, credential.id));
+        }
+
+        let entry = serde_json::json!({
+            "action": CREDENTIAL_REVOCATION_ACTION,
+            "id": credential.id,
+            "subject": credential.subject.to_string(),
+        });
+        let signature = self.write_ledger_entry(issuer_wallet, &entry.to_string()).await?;
+        tracing::info!("Credential {} revoked: {}", credential.id, signature);
+        Ok(signature)
+    }
+
+    /// Check a presented credential's signature, expiry, anchor, and revocation state
+    #[tracing::instrument(skip_all, fields(credential_id = %credential.id, issuer = %readable(&credential.issuer)))]
+    pub async fn verify_credential(&self, credential: &SignedCredential) -> Result<CredentialStatus> {
+        let payload = credential.canonical_payload()?;
+        if !Self::verify_offchain_message(&credential.issuer, &payload, &credential.signature).unwrap_or(false) {
+            return Ok(CredentialStatus::InvalidSignature);
+        }
+
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        if credential.expires_at <= now {
+            return Ok(CredentialStatus::Expired { expired_at: credential.expires_at });
+        }
+
+        // Anchors and revocations are both ledger entries the issuer signed
+        let entries: Vec<_> = self
+            .read_ledger_entries(&credential.issuer, Some(1000))
+            .await?
+            .into_iter()
+            .filter(|entry| entry.signer == Some(credential.issuer))
+            .collect();
+        let for_credential = |action: &str, value: &serde_json::Value| {
+            value.get("action").and_then(|a| a.as_str()) == Some(action)
+                && value.get("id").and_then(|i| i.as_str()) == Some(credential.id.as_str())
+        };
+
+        if let Some(anchor_signature) = credential.anchor_signature {
+            let hash = credential.payload_hash()?;
+            let anchored = entries.iter().any(|entry| {
+                entry.signature == anchor_signature
+                    && entry
+                        .json()
+                        .map(|value| {
+                            for_credential(CREDENTIAL_ANCHOR_ACTION, &value)
+                                && value.get("hash").and_then(|h| h.as_str()) == Some(hash.as_str())
+                        })
+                        .unwrap_or(false)
+            });
+            if !anchored {
+                return Ok(CredentialStatus::AnchorMismatch);
+            }
+        }
+
+        let revocation = entries.iter().find(|entry| {
+            entry
+                .json()
+                .map(|value| for_credential(CREDENTIAL_REVOCATION_ACTION, &value))
+                .unwrap_or(false)
+        });
+        if let Some(revocation) = revocation {
+            return Ok(CredentialStatus::Revoked { signature: revocation.signature });
+        }
+
+        Ok(CredentialStatus::Valid)
+    }
+}
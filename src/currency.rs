@@ -0,0 +1,156 @@
+use crate::amount_format::AmountFormatter;
+use crate::mint_registry;
+use crate::payment::{usdc, Amount};
+use crate::telemetry::readable;
+use serde::{Deserialize, Serialize};
+use solana_sdk::pubkey::Pubkey;
+use std::fmt;
+use std::str::FromStr;
+
+pub const SOL_DECIMALS: u8 = 9;
+
+/// What an amount is denominated in, with what it takes to display it
+///
+/// Serializes as `{"kind": "sol"}` or `{"kind": "spl", "mint": ..., "decimals": ..., "symbol": ...}`.
+/// A bare mint, as records stored it before, still deserializes, and so does a mint address
+/// string or `"SOL"`: well-known mints get their decimals and symbol, any other mint gets
+/// `decimals: 0` until it is seen again on-chain.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case", try_from = "CurrencyRepr")]
+pub enum Currency {
+    /// Native SOL, counted in lamports
+    Sol,
+    Spl {
+        mint: Pubkey,
+        decimals: u8,
+        symbol: Option<String>,
+    },
+}
+
+impl Currency {
+    /// SPL currency for `mint`, with the symbol filled in for well-known mints
+    pub fn spl(mint: Pubkey, decimals: u8) -> Self {
+        Self::Spl {
+            mint,
+            decimals,
+            symbol: known_symbol(&mint).map(str::to_string),
+        }
+    }
+
+    /// A mint whose decimals were not recorded: exact for well-known mints, 0 otherwise
+    pub fn from_mint(mint: Pubkey) -> Self {
+        let decimals = known_decimals(&mint).unwrap_or(0);
+        Self::spl(mint, decimals)
+    }
+
+    pub fn usdc_devnet() -> Self {
+        Self::spl(usdc::devnet_mint(), 6)
+    }
+
+    /// The SPL mint; `None` for native SOL
+    pub fn mint(&self) -> Option<Pubkey> {
+        match self {
+            Self::Sol => None,
+            Self::Spl { mint, .. } => Some(*mint),
+        }
+    }
+
+    pub fn decimals(&self) -> u8 {
+        match self {
+            Self::Sol => SOL_DECIMALS,
+            Self::Spl { decimals, .. } => *decimals,
+        }
+    }
+
+    /// Ticker if known, otherwise the shortened mint address
+    pub fn label(&self) -> String {
+        match self {
+            Self::Sol => "SOL".to_string(),
+            Self::Spl { symbol: Some(symbol), .. } => symbol.clone(),
+            Self::Spl { mint, .. } => readable(mint),
+        }
+    }
+
+    /// `raw` base units with the unit attached, e.g. `12.50 USDC` or `0.000005 SOL`
+    pub fn format_amount(&self, raw: u64) -> String {
+        self.format_signed(raw as i128)
+    }
+
+    /// Like `format_amount`, for signed deltas and running balances
+    pub fn format_signed(&self, raw: i128) -> String {
+        AmountFormatter::default().format_signed(self, raw)
+    }
+
+    /// `raw` base units as a decimal number without the unit, trailing zeros trimmed to two places
+    ///
+    /// Always the plain format; use an `AmountFormatter` for a locale.
+    pub fn format_units(&self, raw: i128) -> String {
+        AmountFormatter::default().format_units(self, raw)
+    }
+
+    /// A decimal amount such as `0.2`, `1,000.50`, or `$25` in base units; see `Amount::parse`
+    pub fn parse_amount(&self, amount: &str) -> Result<u64, String> {
+        Amount::parse(amount, self).map(|amount| amount.base_units).map_err(|e| e.to_string())
+    }
+}
+
+impl fmt::Display for Currency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Sol => write!(f, "SOL"),
+            Self::Spl { mint, symbol: Some(symbol), .. } => write!(f, "{} ({})", symbol, mint),
+            Self::Spl { mint, .. } => write!(f, "{}", mint),
+        }
+    }
+}
+
+fn known_decimals(mint: &Pubkey) -> Option<u8> {
+    match mint_registry::lookup(mint)?.name {
+        mint_registry::USDC => Some(6),
+        "wSOL" => Some(SOL_DECIMALS),
+        _ => None,
+    }
+}
+
+fn known_symbol(mint: &Pubkey) -> Option<&'static str> {
+    mint_registry::lookup(mint).map(|known| known.name)
+}
+
+/// Everything a stored currency may look like
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum CurrencyRepr {
+    Tagged(TaggedCurrency),
+    /// A pre-`Currency` `token_mint` field
+    Mint(Pubkey),
+    /// `"SOL"` or a mint address
+    Name(String),
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+enum TaggedCurrency {
+    Sol,
+    Spl {
+        mint: Pubkey,
+        decimals: u8,
+        #[serde(default)]
+        symbol: Option<String>,
+    },
+}
+
+impl TryFrom<CurrencyRepr> for Currency {
+    type Error = String;
+
+    fn try_from(repr: CurrencyRepr) -> Result<Self, String> {
+        Ok(match repr {
+            CurrencyRepr::Tagged(TaggedCurrency::Sol) => Self::Sol,
+            CurrencyRepr::Tagged(TaggedCurrency::Spl { mint, decimals, symbol }) => Self::Spl { mint, decimals, symbol },
+            CurrencyRepr::Mint(mint) => Self::from_mint(mint),
+            CurrencyRepr::Name(s) if s.eq_ignore_ascii_case("sol") => Self::Sol,
+            CurrencyRepr::Name(s) => {
+                Self::from_mint(Pubkey::from_str(&s).map_err(|_| format!("Invalid currency '{}'", s))?)
+            }
+        })
+    }
+}
@@ -0,0 +1,252 @@
+//! Sends that confirm by a deadline or do not go out at all
+//!
+//! `RpcCallOptions::with_deadline` bounds a send. Until it is broadcast, the send is refused with
+//! `DeadlineError::TooLateToSend` once less time is left than a broadcast and confirmation are
+//! budgeted to take (`send_budget`): the pipeline checks after its middleware, after pricing,
+//! and before every broadcast, re-signed ones included. A send already broadcast stops waiting
+//! at the deadline with `DeadlineError::DeadlineExceeded`. Its outbox entry stays `Submitted`,
+//! so `recover_outbox` still records whether it landed, and `Outbox::entries` can be asked.
+//! `run_batch_until` stops launching batch items once one could no longer make it.
+
+use crate::latency::Phase;
+use crate::rpc_options::RpcCallOptions;
+use crate::FinternetClient;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use solana_sdk::signature::Signature;
+use solana_transaction_status::TransactionConfirmationStatus;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// How long a send needs between broadcast and confirmation against a healthy cluster
+pub fn send_budget() -> Duration {
+    Phase::Broadcast.budget() + Phase::Confirmation.budget()
+}
+
+/// Time left before `deadline`, zero once it has passed
+pub fn remaining(deadline: Instant) -> Duration {
+    deadline.saturating_duration_since(Instant::now())
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DeadlineError {
+    /// Nothing was broadcast: `remaining` was less than the `budget` a send needs
+    TooLateToSend {
+        operation: String,
+        remaining: Duration,
+        budget: Duration,
+    },
+    /// `signature` was broadcast but not confirmed by the deadline; it may still land
+    DeadlineExceeded {
+        signature: Signature,
+        /// What the cluster last reported; `None` if it had not seen the transaction
+        last_status: Option<TransactionConfirmationStatus>,
+    },
+}
+
+impl DeadlineError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::TooLateToSend { .. } => "too_late_to_send",
+            Self::DeadlineExceeded { .. } => "deadline_exceeded",
+        }
+    }
+}
+
+impl fmt::Display for DeadlineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::TooLateToSend { operation, remaining, budget } => write!(
+                f,
+                "Not sending {}: {:.1}s left before the deadline, a send needs {:.1}s",
+                operation,
+                remaining.as_secs_f64(),
+                budget.as_secs_f64()
+            ),
+            Self::DeadlineExceeded { signature, last_status: Some(status) } => write!(
+                f,
+                "Transaction {} was not confirmed by the deadline (last seen {:?}); the outbox keeps tracking it",
+                signature, status
+            ),
+            Self::DeadlineExceeded { signature, last_status: None } => write!(
+                f,
+                "Transaction {} was not confirmed by the deadline and the cluster has not seen it; the outbox keeps tracking it",
+                signature
+            ),
+        }
+    }
+}
+
+impl std::error::Error for DeadlineError {}
+
+impl FinternetClient {
+    /// Refuse `operation` when `options.deadline` leaves less than `send_budget`
+    pub(crate) fn check_deadline(&self, operation: &str, options: &RpcCallOptions) -> Result<()> {
+        let Some(deadline) = options.deadline else {
+            return Ok(());
+        };
+        let (remaining, budget) = (remaining(deadline), send_budget());
+        if remaining < budget {
+            tracing::warn!("Refusing {} with {:?} left before its deadline", operation, remaining);
+            return Err(DeadlineError::TooLateToSend { operation: operation.to_string(), remaining, budget }.into());
+        }
+        Ok(())
+    }
+
+    /// `DeadlineExceeded` for `signature`, with the status the cluster reports for it now
+    pub(crate) fn deadline_exceeded(&self, signature: &Signature) -> anyhow::Error {
+        let last_status = match self.client.get_signature_statuses(&[*signature]) {
+            Ok(response) => response.value.into_iter().flatten().next().and_then(|status| status.confirmation_status),
+            Err(e) => {
+                tracing::warn!("Could not read the status of {} at its deadline: {}", signature, e);
+                None
+            }
+        };
+        DeadlineError::DeadlineExceeded { signature: *signature, last_status }.into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::batch::{BatchItemStatus, BatchPayment};
+    use crate::mock_cluster::{Landing, MockCluster, TempDir};
+    use crate::outbox::{Outbox, OutboxStatus};
+    use crate::policy::SpendingPolicy;
+    use crate::FinternetConfig;
+    use solana_sdk::pubkey::Pubkey;
+    use solana_sdk::signature::Keypair;
+    use solana_sdk::signer::Signer;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Time past the send budget the confirmation waits out before the deadline passes
+    const SLACK: Duration = Duration::from_millis(700);
+
+    /// A cluster with a payer holding 10 tokens of a 6-decimal mint, and a client of it that
+    /// journals in an outbox
+    fn setup(dir: &TempDir) -> (MockCluster, FinternetClient, Keypair, Pubkey) {
+        let cluster = MockCluster::new();
+        let payer = Keypair::new();
+        let mint = cluster.add_mint(&spl_token::id(), 6, &Pubkey::new_unique());
+        cluster.set_balance(payer.pubkey(), 1_000_000_000);
+        cluster.add_token_account(&payer.pubkey(), &mint, 10_000_000);
+        let client = cluster.client(FinternetConfig {
+            outbox_path: Some(dir.join("outbox.json")),
+            ..FinternetConfig::default()
+        });
+        (cluster, client, payer, mint)
+    }
+
+    fn payments(count: usize) -> Vec<BatchPayment> {
+        (0..count)
+            .map(|i| BatchPayment { id: format!("p{}", i), to: Keypair::new().pubkey(), amount: 1_000 + i as u64, memo: None })
+            .collect()
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn a_deadline_closer_than_the_send_budget_sends_nothing_and_spends_nothing() {
+        let dir = TempDir::new("deadline-refused");
+        let (cluster, client, payer, mint) = setup(&dir);
+        let client = client.with_spending_policy(SpendingPolicy::new().with_max_per_day(mint, 1_000_000)).unwrap();
+
+        for deadline in [Instant::now() + send_budget() - Duration::from_secs(1), Instant::now()] {
+            let options = RpcCallOptions::default().with_deadline(deadline);
+            let error = client
+                .send_payment_with_options(&payer, &Keypair::new().pubkey(), 1_000_000, &mint, None, &options)
+                .await
+                .unwrap_err();
+            let Some(DeadlineError::TooLateToSend { operation, remaining, budget }) = error.downcast_ref::<DeadlineError>() else {
+                panic!("expected TooLateToSend, got {}", error);
+            };
+            assert_eq!(operation, "send_payment");
+            assert!(*remaining < *budget);
+            assert_eq!(*budget, send_budget());
+            assert_eq!(error.downcast_ref::<DeadlineError>().unwrap().code(), "too_late_to_send");
+        }
+        assert!(cluster.sent().is_empty());
+        assert_eq!(cluster.call_count("sendTransaction"), 0);
+        assert!(Outbox::new(&dir.join("outbox.json")).entries().unwrap().is_empty());
+
+        // Neither refusal counted against the daily limit
+        client.send_payment(&payer, &Keypair::new().pubkey(), 1_000_000, &mint, None).await.unwrap();
+
+        // A batch launches nothing it cannot finish, leaving every item to resume later
+        let journal = client
+            .send_batch_payment_until(&payer, &payments(3), &mint, None, Some(Instant::now() + Duration::from_secs(2)))
+            .await
+            .unwrap();
+        assert!(journal.items.iter().all(|item| item.status == BatchItemStatus::Pending && item.signature.is_none()));
+        assert_eq!(cluster.sent().len(), 1);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn a_broadcast_send_stops_waiting_at_the_deadline_and_the_outbox_records_its_fate() {
+        let dir = TempDir::new("deadline-exceeded");
+        let (cluster, client, payer, mint) = setup(&dir);
+        // The node has the transaction but it never reaches the client's commitment in time
+        cluster.set_landing(Landing::Pending);
+        cluster.respond("getSignatureStatuses", |params| {
+            let statuses: Vec<serde_json::Value> = params[0]
+                .as_array()?
+                .iter()
+                .map(|_| serde_json::json!({ "slot": 1000, "confirmations": 0, "err": null, "status": { "Ok": null }, "confirmationStatus": "processed" }))
+                .collect();
+            Some(Ok(serde_json::json!({ "context": { "slot": 1000 }, "value": statuses })))
+        });
+
+        let deadline = Instant::now() + send_budget() + SLACK;
+        let options = RpcCallOptions::default().with_deadline(deadline);
+        let error = client
+            .send_payment_with_options(&payer, &Keypair::new().pubkey(), 1_000_000, &mint, None, &options)
+            .await
+            .unwrap_err();
+        assert!(Instant::now() >= deadline);
+        assert!(Instant::now() < deadline + Duration::from_secs(2), "stopped waiting soon after the deadline");
+        let sent = cluster.sent();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(
+            error.downcast_ref::<DeadlineError>(),
+            Some(&DeadlineError::DeadlineExceeded {
+                signature: sent[0].signatures[0],
+                last_status: Some(TransactionConfirmationStatus::Processed),
+            })
+        );
+        assert!(error.to_string().contains("the outbox keeps tracking it"), "{}", error);
+
+        // Still tracked, so once it lands recovery records that
+        let outbox = Outbox::new(&dir.join("outbox.json"));
+        let entries = outbox.entries().unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!((entries[0].signature, entries[0].status), (sent[0].signatures[0], OutboxStatus::Submitted));
+        cluster.respond("getSignatureStatuses", |_| None);
+        cluster.land(&sent[0].signatures[0], None);
+        let recovery = client.recover_outbox().await.unwrap();
+        assert_eq!(recovery.confirmed, [sent[0].signatures[0]]);
+        assert_eq!(outbox.entries().unwrap()[0].status, OutboxStatus::Confirmed);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn a_batch_stops_launching_once_an_in_doubt_item_runs_into_the_deadline() {
+        let dir = TempDir::new("deadline-batch");
+        let (cluster, client, payer, mint) = setup(&dir);
+        // The first payment never confirms; the ones after it would land at once
+        let landed = AtomicUsize::new(0);
+        cluster.land_with(move |_| match landed.fetch_add(1, Ordering::SeqCst) {
+            0 => Landing::Pending,
+            _ => Landing::Confirmed,
+        });
+
+        let deadline = Instant::now() + send_budget() + SLACK;
+        let journal = client
+            .send_batch_payment_until(&payer, &payments(3), &mint, Some(&dir.join("batch.json")), Some(deadline))
+            .await
+            .unwrap();
+        let statuses: Vec<BatchItemStatus> = journal.items.iter().map(|item| item.status).collect();
+        assert_eq!(statuses, [BatchItemStatus::Submitted, BatchItemStatus::Pending, BatchItemStatus::Pending]);
+        let sent = cluster.sent();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(journal.items[0].signature, Some(sent[0].signatures[0]));
+        assert!(journal.items[0].error.as_deref().unwrap().contains("not confirmed by the deadline"), "{:?}", journal.items[0]);
+        assert!(journal.items[1..].iter().all(|item| item.signature.is_none()));
+    }
+}
@@ -0,0 +1,530 @@
+//! A scripted run through the SDK's core flows that checks each one as it goes
+//!
+//! `run_demo` funds a payer, mints it a stable token, tokenizes an asset, pays a recipient with
+//! a memo, and registers an identity, reading each result back before moving on. Self-contained
+//! runs use keypairs derived from a seed and a fixed clock, against a local validator or an RPC
+//! recording of one, so CI gets the same accounts every time and a recorded run replays without
+//! a network; `RECORDED_DEMO` is one such run, shipped with the SDK. Live runs take the caller's
+//! wallet against any non-mainnet cluster.
+
+use crate::instructions::mint_rent_lamports;
+use crate::mint_registry::{self, Network};
+use crate::asset::{derive_mint_keypair, TokenizeOptions};
+use crate::{AssetType, Currency, FinternetClient};
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use solana_sdk::program_pack::Pack;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{keypair_from_seed, Keypair, Signature};
+use solana_sdk::signer::Signer;
+use solana_sdk::system_instruction;
+use solana_sdk::transaction::Transaction;
+use std::collections::HashMap;
+use std::fmt;
+use std::time::Duration;
+
+const AIRDROP_CONFIRM_SECS: u64 = 60;
+const STABLE_DECIMALS: u8 = 6;
+const STABLE_SYMBOL: &str = "DEMO";
+const DEMO_ASSET_NAME: &str = "Finternet Demo Invoice";
+const DEMO_ASSET_TYPE: &str = "demo_fixture";
+const DEMO_PAYMENT_MEMO: &str = "finternet demo payment";
+
+/// Seed for the self-contained run's keypairs when none is given
+pub const DEFAULT_DEMO_SEED: &str = "finternet-demo-v1";
+/// Clock for self-contained runs: 2023-11-14T22:13:20Z
+pub const DEMO_TIMESTAMP: u64 = 1_700_000_000;
+/// Where `solana-test-validator` listens by default
+pub const LOCAL_VALIDATOR_URL: &str = "http://127.0.0.1:8899";
+/// A self-contained run with `DEFAULT_DEMO_SEED`, recorded against the SDK's in-memory test
+/// cluster, for `FinternetClient::recorded_demo` to replay
+pub const RECORDED_DEMO: &str = include_str!("../tests/fixtures/demo/self_contained.jsonl");
+const RECORDED_DEMO_NAME: &str = "recorded-demo";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DemoMode {
+    /// Seeded keypairs and a fixed clock, against a local validator or a recording of one
+    SelfContained,
+    /// The caller's wallet, against a live cluster
+    Live,
+}
+
+/// One stage of the demo, in the order they run
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DemoStage {
+    Funding,
+    StableToken,
+    Tokenization,
+    Payment,
+    Identity,
+}
+
+impl DemoStage {
+    pub const ALL: [DemoStage; 5] = [
+        Self::Funding,
+        Self::StableToken,
+        Self::Tokenization,
+        Self::Payment,
+        Self::Identity,
+    ];
+}
+
+impl fmt::Display for DemoStage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Funding => "funding",
+            Self::StableToken => "stable_token",
+            Self::Tokenization => "tokenization",
+            Self::Payment => "payment",
+            Self::Identity => "identity",
+        };
+        f.write_str(name)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StageStatus {
+    Passed,
+    Failed,
+    /// Not run because an earlier stage failed
+    Skipped,
+}
+
+/// What a stage did and what it read back
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StageOutcome {
+    pub stage: DemoStage,
+    pub status: StageStatus,
+    /// The account the stage is about, if any
+    pub address: Option<String>,
+    pub signature: Option<String>,
+    pub detail: String,
+}
+
+/// What `FinternetClient::run_demo` should do
+#[derive(Debug, Clone)]
+pub struct DemoOptions {
+    pub mode: DemoMode,
+    /// Derives the recipient, the stable mint, and in self-contained runs the payer
+    pub seed: String,
+    /// Airdrop when the payer holds less than this many lamports
+    pub min_lamports: u64,
+    pub airdrop_lamports: u64,
+    /// Stable token balance, in base units, the payer is minted up to
+    pub stable_balance: u64,
+    pub payment_amount: u64,
+    pub identity_name: String,
+}
+
+impl DemoOptions {
+    pub fn self_contained(seed: &str) -> Self {
+        Self::new(DemoMode::SelfContained, seed)
+    }
+
+    pub fn live() -> Self {
+        Self::new(DemoMode::Live, DEFAULT_DEMO_SEED)
+    }
+
+    fn new(mode: DemoMode, seed: &str) -> Self {
+        Self {
+            mode,
+            seed: seed.to_string(),
+            min_lamports: 500_000_000,
+            airdrop_lamports: 2_000_000_000,
+            stable_balance: 1_000_000_000,
+            payment_amount: 25_000_000,
+            identity_name: "Finternet Demo Account".to_string(),
+        }
+    }
+
+    /// The payer of a self-contained run
+    pub fn payer(&self) -> Result<Keypair> {
+        demo_keypair(&self.seed, "payer")
+    }
+}
+
+/// Every stage of a demo run, for CI to check
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DemoReport {
+    pub mode: DemoMode,
+    pub rpc_url: String,
+    pub payer: String,
+    pub recipient: String,
+    pub stable_mint: String,
+    pub stages: Vec<StageOutcome>,
+    /// Every stage passed
+    pub passed: bool,
+}
+
+/// The keypair a seed gives for `role`; the same seed and role always give the same key
+pub fn demo_keypair(seed: &str, role: &str) -> Result<Keypair> {
+    let digest = Sha256::digest(format!("finternet-demo:{}:{}", seed, role).as_bytes());
+    keypair_from_seed(&digest).map_err(|e| anyhow!("Could not derive demo keypair '{}': {}", role, e))
+}
+
+/// Whether `rpc_url` is a local validator or an RPC recording
+fn is_self_contained_endpoint(rpc_url: &str) -> bool {
+    if rpc_url.starts_with("replay://") {
+        return true;
+    }
+    mint_registry::is_local_endpoint(rpc_url)
+}
+
+/// What the stages pass along to each other
+struct DemoContext<'a> {
+    payer: &'a Keypair,
+    recipient: Pubkey,
+    stable: Keypair,
+}
+
+impl FinternetClient {
+    /// A client that replays `RECORDED_DEMO` under `config`, for a self-contained run with
+    /// `DEFAULT_DEMO_SEED` that needs neither a validator nor a network
+    pub fn recorded_demo(config: crate::FinternetConfig) -> Result<Self> {
+        let sender = crate::recording::ReplaySender::from_contents(RECORDED_DEMO, std::path::Path::new(RECORDED_DEMO_NAME))?;
+        Ok(Self::replaying(sender, crate::FinternetConfig { fixed_timestamp: Some(DEMO_TIMESTAMP), ..config }))
+    }
+
+    /// Run every demo stage as `payer`, reading each result back before the next
+    ///
+    /// A failing stage stops the run; it and the stages after it are reported rather than
+    /// returned as an error, so the report always covers every stage. Errors are returned only
+    /// for runs that are refused outright: against mainnet, or self-contained against a remote
+    /// cluster or without `fixed_timestamp` set.
+    pub async fn run_demo(&self, payer: &Keypair, options: &DemoOptions) -> Result<DemoReport> {
+        if options.mode == DemoMode::SelfContained {
+            if !is_self_contained_endpoint(&self.config.rpc_url) {
+                return Err(anyhow!(
+                    "A self-contained demo runs against a local validator or an RPC recording, not {}",
+                    self.config.rpc_url
+                ));
+            }
+            if self.config.fixed_timestamp.is_none() {
+                return Err(anyhow!("A self-contained demo needs FinternetConfig::fixed_timestamp set"));
+            }
+        }
+        if self.network()? == Some(Network::Mainnet) {
+            return Err(anyhow!("Refusing to run the demo against mainnet ({})", self.config.rpc_url));
+        }
+
+        let context = DemoContext {
+            payer,
+            recipient: demo_keypair(&options.seed, "recipient")?.pubkey(),
+            stable: demo_keypair(&options.seed, &format!("stable-mint:{}", payer.pubkey()))?,
+        };
+        let mut stages = Vec::new();
+        let mut failed = false;
+        for stage in DemoStage::ALL {
+            if failed {
+                stages.push(StageOutcome {
+                    stage,
+                    status: StageStatus::Skipped,
+                    address: None,
+                    signature: None,
+                    detail: "Not run; an earlier stage failed".to_string(),
+                });
+                continue;
+            }
+            let result = match stage {
+                DemoStage::Funding => self.demo_funding(options, &context).await,
+                DemoStage::StableToken => self.demo_stable_token(options, &context).await,
+                DemoStage::Tokenization => self.demo_tokenization(options, &context).await,
+                DemoStage::Payment => self.demo_payment(options, &context).await,
+                DemoStage::Identity => self.demo_identity(options, &context).await,
+            };
+            let outcome = result.unwrap_or_else(|e| {
+                failed = true;
+                StageOutcome { stage, status: StageStatus::Failed, address: None, signature: None, detail: e.to_string() }
+            });
+            stages.push(outcome);
+        }
+
+        Ok(DemoReport {
+            mode: options.mode,
+            rpc_url: self.config.rpc_url.clone(),
+            payer: payer.pubkey().to_string(),
+            recipient: context.recipient.to_string(),
+            stable_mint: context.stable.pubkey().to_string(),
+            passed: !failed,
+            stages,
+        })
+    }
+
+    async fn demo_funding(&self, options: &DemoOptions, context: &DemoContext<'_>) -> Result<StageOutcome> {
+        let payer = context.payer.pubkey();
+        let mut signature = None;
+        let mut balance = self.client.get_balance(&payer)?;
+        if balance < options.min_lamports {
+            if self.is_dry_run() {
+                return Err(anyhow!("Dry run: not requesting an airdrop to {}; fund it manually", payer));
+            }
+            let airdrop = self
+                .client
+                .request_airdrop(&payer, options.airdrop_lamports)
+                .map_err(|e| anyhow!("Airdrop to {} failed ({}); fund it manually and re-run", payer, e))?;
+            for _ in 0..AIRDROP_CONFIRM_SECS {
+                if self.client.confirm_transaction(&airdrop)? {
+                    break;
+                }
+                tokio::time::sleep(Duration::from_secs(1)).await;
+            }
+            balance = self.client.get_balance(&payer)?;
+            signature = Some(airdrop.to_string());
+        }
+        if balance < options.min_lamports {
+            return Err(anyhow!(
+                "Payer holds {}, at least {} is needed",
+                Currency::Sol.format_amount(balance),
+                Currency::Sol.format_amount(options.min_lamports)
+            ));
+        }
+        Ok(StageOutcome {
+            stage: DemoStage::Funding,
+            status: StageStatus::Passed,
+            address: Some(payer.to_string()),
+            signature,
+            detail: format!("Balance {}", Currency::Sol.format_amount(balance)),
+        })
+    }
+
+    /// Create the payer's stable mint on first use and mint it up to `stable_balance`
+    async fn demo_stable_token(&self, options: &DemoOptions, context: &DemoContext<'_>) -> Result<StageOutcome> {
+        let payer = context.payer.pubkey();
+        let mint = context.stable.pubkey();
+        let stable = Currency::Spl { mint, decimals: STABLE_DECIMALS, symbol: Some(STABLE_SYMBOL.to_string()) };
+        let ata = spl_associated_token_account::get_associated_token_address(&payer, &mint);
+
+        let mut instructions = Vec::new();
+        let mut signers = vec![context.payer];
+        if self.client.get_account(&mint).is_err() {
+            instructions.push(system_instruction::create_account(
+                &payer,
+                &mint,
+                mint_rent_lamports(),
+                spl_token::state::Mint::LEN as u64,
+                &spl_token::id(),
+            ));
+            instructions.push(spl_token::instruction::initialize_mint2(
+                &spl_token::id(),
+                &mint,
+                &payer,
+                None,
+                STABLE_DECIMALS,
+            )?);
+            signers.push(&context.stable);
+        }
+        let balance = self.get_token_balance(&payer, &mint).await.unwrap_or(0);
+        if balance < options.stable_balance {
+            instructions.push(spl_associated_token_account::instruction::create_associated_token_account_idempotent(
+                &payer,
+                &payer,
+                &mint,
+                &spl_token::id(),
+            ));
+            instructions.push(spl_token::instruction::mint_to(
+                &spl_token::id(),
+                &mint,
+                &ata,
+                &payer,
+                &[],
+                options.stable_balance - balance,
+            )?);
+        }
+
+        let mut signature = None;
+        if !instructions.is_empty() {
+            let mut transaction = Transaction::new_with_payer(&instructions, Some(&payer));
+            self.sign_with_fresh_blockhash(&mut transaction, &signers)?;
+            signature = Some(self.send_and_confirm_untracked("demo_stable_token", &transaction)?.to_string());
+        }
+
+        let balance = self.get_token_balance(&payer, &mint).await?;
+        if balance < options.stable_balance {
+            return Err(anyhow!(
+                "Stable balance is {} after minting, expected {}",
+                stable.format_amount(balance),
+                stable.format_amount(options.stable_balance)
+            ));
+        }
+        Ok(StageOutcome {
+            stage: DemoStage::StableToken,
+            status: StageStatus::Passed,
+            address: Some(mint.to_string()),
+            signature,
+            detail: format!("Payer holds {}", stable.format_amount(balance)),
+        })
+    }
+
+    /// Tokenize the seed's asset on first use; later runs read back the one already minted
+    async fn demo_tokenization(&self, options: &DemoOptions, context: &DemoContext<'_>) -> Result<StageOutcome> {
+        let mint_keypair = derive_mint_keypair(&context.payer.pubkey(), &format!("finternet-demo:{}", options.seed))?;
+        let mint = mint_keypair.pubkey();
+        let mut signature = None;
+        if self.client.get_account(&mint).is_err() {
+            let tokenize = TokenizeOptions { mint_keypair: Some(mint_keypair), ..TokenizeOptions::default() };
+            let (_, _, minted) = self
+                .tokenize_asset_with_options(
+                    DEMO_ASSET_NAME,
+                    "Net 30 invoice tokenized by the SDK demo",
+                    15_000,
+                    &AssetType::Custom(DEMO_ASSET_TYPE.to_string()),
+                    context.payer,
+                    &tokenize,
+                )
+                .await?;
+            signature = Some(minted.to_string());
+        }
+        let info = self.get_asset_info(&mint).await?;
+        if info.name != DEMO_ASSET_NAME || info.issuer != context.payer.pubkey() {
+            return Err(anyhow!(
+                "Asset {} reads back as '{}' issued by {}, expected '{}' issued by {}",
+                mint,
+                info.name,
+                info.issuer,
+                DEMO_ASSET_NAME,
+                context.payer.pubkey()
+            ));
+        }
+        Ok(StageOutcome {
+            stage: DemoStage::Tokenization,
+            status: StageStatus::Passed,
+            address: Some(mint.to_string()),
+            detail: match signature {
+                Some(_) => format!("'{}' minted and read back", DEMO_ASSET_NAME),
+                None => format!("'{}' read back from an earlier run", DEMO_ASSET_NAME),
+            },
+            signature,
+        })
+    }
+
+    async fn demo_payment(&self, options: &DemoOptions, context: &DemoContext<'_>) -> Result<StageOutcome> {
+        let mint = context.stable.pubkey();
+        let signature = self
+            .send_payment(context.payer, &context.recipient, options.payment_amount, &mint, Some(DEMO_PAYMENT_MEMO))
+            .await?;
+        let record = self
+            .get_transaction_details(&signature)
+            .await?
+            .ok_or_else(|| anyhow!("Payment {} not found after confirmation", signature))?;
+        check_payment(&record, &signature, options.payment_amount)?;
+        let received = self.get_token_balance(&context.recipient, &mint).await?;
+        if received < options.payment_amount {
+            return Err(anyhow!("Recipient {} holds {} after payment {}", context.recipient, received, signature));
+        }
+        Ok(StageOutcome {
+            stage: DemoStage::Payment,
+            status: StageStatus::Passed,
+            address: Some(context.recipient.to_string()),
+            signature: Some(signature.to_string()),
+            detail: format!("Paid {} base units with memo '{}'", options.payment_amount, DEMO_PAYMENT_MEMO),
+        })
+    }
+
+    async fn demo_identity(&self, options: &DemoOptions, context: &DemoContext<'_>) -> Result<StageOutcome> {
+        let wallet = context.payer.pubkey();
+        let mut signature = None;
+        let registered = self.get_registered_identity(&wallet).await?;
+        if registered.as_ref().and_then(|i| i.display_name.as_deref()) != Some(options.identity_name.as_str()) {
+            let metadata = HashMap::from([("registration_method".to_string(), "demo".to_string())]);
+            signature = Some(self.register_identity(context.payer, &options.identity_name, metadata).await?);
+        }
+        let identity = self
+            .get_registered_identity(&wallet)
+            .await?
+            .ok_or_else(|| anyhow!("Identity for {} not readable after registering", wallet))?;
+        let name = identity.display_name.unwrap_or_default();
+        if name != options.identity_name {
+            return Err(anyhow!("Identity for {} reads back as '{}', expected '{}'", wallet, name, options.identity_name));
+        }
+        Ok(StageOutcome {
+            stage: DemoStage::Identity,
+            status: StageStatus::Passed,
+            address: Some(wallet.to_string()),
+            signature: signature.map(|s: Signature| s.to_string()),
+            detail: format!("Reads back as '{}'", name),
+        })
+    }
+}
+
+fn check_payment(record: &crate::TransactionRecord, signature: &Signature, amount: u64) -> Result<()> {
+    let memo = record.memo.as_deref().unwrap_or_default();
+    if !memo.contains(DEMO_PAYMENT_MEMO) {
+        return Err(anyhow!("Payment {} carries memo '{}', expected '{}'", signature, memo, DEMO_PAYMENT_MEMO));
+    }
+    if record.amount != amount {
+        return Err(anyhow!("Payment {} moved {} base units, expected {}", signature, record.amount, amount));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_cluster::MockCluster;
+    use crate::recording::RecordingSender;
+    use crate::FinternetConfig;
+    use solana_client::rpc_sender::RpcSender;
+    use std::path::Path;
+
+    fn demo_client(cluster: &MockCluster, sender: impl RpcSender + Send + Sync + 'static) -> FinternetClient {
+        cluster.execute_sends();
+        FinternetClient::with_rpc_sender(
+            FinternetConfig { rpc_url: LOCAL_VALIDATOR_URL.to_string(), ..FinternetConfig::default() },
+            sender,
+        )
+        .with_fixed_timestamp(DEMO_TIMESTAMP)
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn the_shipped_recording_replays_every_stage() {
+        let options = DemoOptions::self_contained(DEFAULT_DEMO_SEED);
+        let client = FinternetClient::recorded_demo(FinternetConfig::default()).unwrap();
+
+        let report = client.run_demo(&options.payer().unwrap(), &options).await.unwrap();
+
+        assert!(report.passed, "{:#?}", report.stages);
+        assert_eq!(report.rpc_url, format!("replay://{}", RECORDED_DEMO_NAME));
+        assert!(report.stages.iter().all(|stage| stage.status == StageStatus::Passed));
+    }
+
+    /// Rewrites the recording `RECORDED_DEMO` embeds; run it after changing what the demo sends:
+    /// `cargo test --lib demo::tests::record_the_shipped_demo -- --ignored`
+    #[tokio::test(flavor = "multi_thread")]
+    #[ignore = "rewrites tests/fixtures/demo/self_contained.jsonl"]
+    async fn record_the_shipped_demo() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/demo/self_contained.jsonl");
+        let _ = std::fs::remove_file(&path);
+        let cluster = MockCluster::new();
+        let client = demo_client(&cluster, RecordingSender::over(cluster.sender(), &path).unwrap());
+        let options = DemoOptions::self_contained(DEFAULT_DEMO_SEED);
+
+        let report = client.run_demo(&options.payer().unwrap(), &options).await.unwrap();
+
+        assert!(report.passed, "{:#?}", report.stages);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn every_stage_passes_and_a_second_run_reuses_what_the_first_made() {
+        let cluster = MockCluster::new();
+        let client = demo_client(&cluster, cluster.sender());
+        let options = DemoOptions::self_contained(DEFAULT_DEMO_SEED);
+        let payer = options.payer().unwrap();
+
+        let first = client.run_demo(&payer, &options).await.unwrap();
+        assert!(first.passed, "{:#?}", first.stages);
+        assert_eq!(first.stages.iter().map(|s| s.stage).collect::<Vec<_>>(), DemoStage::ALL);
+
+        cluster.advance_blockhash();
+        let second = client.run_demo(&payer, &options).await.unwrap();
+        assert!(second.passed, "{:#?}", second.stages);
+        let stage = |report: &DemoReport, stage: DemoStage| report.stages.iter().find(|s| s.stage == stage).cloned().unwrap();
+        assert!(stage(&second, DemoStage::Funding).signature.is_none());
+        assert_eq!(stage(&second, DemoStage::Tokenization).address, stage(&first, DemoStage::Tokenization).address);
+        assert!(stage(&second, DemoStage::Tokenization).signature.is_none());
+        assert!(stage(&second, DemoStage::Identity).signature.is_none());
+    }
+}
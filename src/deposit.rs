@@ -0,0 +1,352 @@
+//! Per-customer deposit addresses, watched and credited automatically
+//!
+//! Each customer gets a wallet derived from the master keypair by signing a fixed message with
+//! their id, so the same id always maps to the same address and the master can sign sweeps out
+//! of it. The store keeps the mapping, each address's running credit, and the newest signature
+//! already processed on its token account, so deposits are credited once even across restarts.
+
+use crate::history::{transfer_records, SIGNATURE_PAGE_LIMIT};
+use crate::state_store::{FileStateStore, Namespace, StateStore};
+use crate::sweep::{SweepOptions, SweepReport};
+use crate::telemetry::readable;
+use crate::{FinternetClient, TransactionRecord};
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use solana_client::rpc_client::GetConfirmedSignaturesForAddress2Config;
+use solana_client::rpc_config::RpcTransactionConfig;
+use solana_sdk::{
+    commitment_config::CommitmentConfig,
+    pubkey::Pubkey,
+    signature::{keypair_from_seed, Keypair, Signature},
+    signer::Signer,
+};
+use solana_transaction_status::{TransactionConfirmationStatus, UiTransactionEncoding};
+use std::collections::HashMap;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Prefix of the message the master signs to derive a customer's deposit wallet
+const DEPOSIT_SEED_PREFIX: &str = "finternet-sdk deposit address v1:";
+
+/// Slots built on top of a deposit before it is credited, unless it is already finalized
+pub const DEFAULT_MIN_CONFIRMATIONS: u64 = 32;
+
+/// A customer's deposit address and what has been credited to it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DepositAddress {
+    pub customer_id: String,
+    /// Wallet the customer pays; derived, so only the master can move funds out of it
+    pub address: Pubkey,
+    /// Associated token account of `address` for the manager's mint, which the watcher scans
+    pub token_account: Pubkey,
+    pub assigned_at: u64,
+    /// Base units credited so far
+    pub credited: u64,
+    pub deposits: usize,
+    /// Newest signature on `token_account` already processed
+    pub checkpoint: Option<Signature>,
+}
+
+/// A confirmed incoming transfer, credited to `customer_id` before it was reported
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DepositDetected {
+    pub customer_id: String,
+    pub record: TransactionRecord,
+    /// Customer's credited total including this deposit
+    pub credited: u64,
+}
+
+/// Namespace of deposit addresses in a `StateStore`, keyed by customer id
+pub const DEPOSIT_ADDRESSES_NAMESPACE: &str = "deposit_addresses";
+/// Namespace holding the master and mint the addresses were derived for
+pub const DEPOSIT_OWNER_NAMESPACE: &str = "deposit_owner";
+const OWNER_KEY: &str = "owner";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct DepositOwner {
+    master: Pubkey,
+    mint: Pubkey,
+}
+
+/// The file `DepositManager` wrote before it moved onto a `StateStore`
+#[derive(Debug, Clone, Deserialize)]
+struct LegacyDepositDb {
+    master: Pubkey,
+    mint: Pubkey,
+    addresses: HashMap<String, DepositAddress>,
+}
+
+fn import_legacy_deposits(raw: serde_json::Value) -> Result<Vec<(String, String, serde_json::Value)>> {
+    let db: LegacyDepositDb = serde_json::from_value(raw)?;
+    let owner = DepositOwner { master: db.master, mint: db.mint };
+    let mut entries = vec![(DEPOSIT_OWNER_NAMESPACE.to_string(), OWNER_KEY.to_string(), serde_json::to_value(owner)?)];
+    for (customer_id, address) in db.addresses {
+        entries.push((DEPOSIT_ADDRESSES_NAMESPACE.to_string(), customer_id, serde_json::to_value(address)?));
+    }
+    Ok(entries)
+}
+
+/// Persistent customer-to-address mapping for one master keypair and mint
+pub struct DepositManager {
+    master: Keypair,
+    mint: Pubkey,
+    addresses: Namespace<DepositAddress>,
+    /// Held across each read-then-write so an assignment and a credit do not race
+    lock: Mutex<()>,
+}
+
+impl DepositManager {
+    /// Keep the mapping in its own file at `path`, refusing one created for another master or mint
+    pub fn open(path: &Path, master: &Keypair, mint: Pubkey) -> Result<Self> {
+        let store = FileStateStore::open_registry(path, &import_legacy_deposits)?;
+        Self::new(Arc::new(store), master, mint).map_err(|e| e.context(format!("Deposit store {}", path.display())))
+    }
+
+    /// Keep the mapping in `store`, refusing one created for another master or mint
+    pub fn new(store: Arc<dyn StateStore>, master: &Keypair, mint: Pubkey) -> Result<Self> {
+        let owners: Namespace<DepositOwner> = Namespace::new(Arc::clone(&store), DEPOSIT_OWNER_NAMESPACE);
+        let owner = DepositOwner { master: master.pubkey(), mint };
+        match owners.get(OWNER_KEY)? {
+            Some(existing) if existing != owner => {
+                return Err(anyhow!(
+                    "Deposit addresses belong to master {} and mint {}, not {} and {}",
+                    existing.master,
+                    existing.mint,
+                    owner.master,
+                    owner.mint
+                ));
+            }
+            Some(_) => {}
+            None => owners.put(OWNER_KEY, &owner)?,
+        }
+
+        Ok(Self {
+            master: master.insecure_clone(),
+            mint,
+            addresses: Namespace::new(store, DEPOSIT_ADDRESSES_NAMESPACE),
+            lock: Mutex::new(()),
+        })
+    }
+
+    pub fn mint(&self) -> &Pubkey {
+        &self.mint
+    }
+
+    /// The customer's deposit address, assigning and persisting it on first request
+    pub fn assign_address(&self, customer_id: &str) -> Result<Pubkey> {
+        if customer_id.trim().is_empty() {
+            return Err(anyhow!("Customer id must not be empty"));
+        }
+        let _guard = self.lock.lock().map_err(|_| anyhow!("Deposit store lock poisoned"))?;
+        if let Some(existing) = self.addresses.get(customer_id)? {
+            return Ok(existing.address);
+        }
+        let address = self.keypair(customer_id)?.pubkey();
+        self.addresses.put(
+            customer_id,
+            &DepositAddress {
+                customer_id: customer_id.to_string(),
+                address,
+                token_account: spl_associated_token_account::get_associated_token_address(&address, &self.mint),
+                assigned_at: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+                credited: 0,
+                deposits: 0,
+                checkpoint: None,
+            },
+        )?;
+        tracing::info!("Assigned deposit address {} to {}", address, customer_id);
+        Ok(address)
+    }
+
+    pub fn get(&self, customer_id: &str) -> Result<Option<DepositAddress>> {
+        self.addresses.get(customer_id)
+    }
+
+    /// Every assigned address, by customer id
+    pub fn addresses(&self) -> Result<Vec<DepositAddress>> {
+        let mut addresses: Vec<DepositAddress> = self.addresses.list()?.into_iter().map(|(_, address)| address).collect();
+        addresses.sort_by(|a, b| a.customer_id.cmp(&b.customer_id));
+        Ok(addresses)
+    }
+
+    /// Keypair of the customer's deposit wallet
+    fn keypair(&self, customer_id: &str) -> Result<Keypair> {
+        let seed = self.master.sign_message(format!("{}{}", DEPOSIT_SEED_PREFIX, customer_id).as_bytes());
+        keypair_from_seed(&seed.as_ref()[..32]).map_err(|e| anyhow!("Could not derive deposit address: {}", e))
+    }
+
+    /// Credit `records` and move the checkpoint to `checkpoint` in one write
+    fn credit(&self, customer_id: &str, checkpoint: Signature, records: Vec<TransactionRecord>) -> Result<Vec<DepositDetected>> {
+        let _guard = self.lock.lock().map_err(|_| anyhow!("Deposit store lock poisoned"))?;
+        let mut address = self
+            .addresses
+            .get(customer_id)?
+            .ok_or_else(|| anyhow!("No deposit address assigned to {}", customer_id))?;
+        let mut detected = Vec::with_capacity(records.len());
+        for record in records {
+            address.credited = address.credited.saturating_add(record.amount);
+            address.deposits += 1;
+            detected.push(DepositDetected {
+                customer_id: customer_id.to_string(),
+                record,
+                credited: address.credited,
+            });
+        }
+        address.checkpoint = Some(checkpoint);
+        self.addresses.put(customer_id, &address)?;
+        Ok(detected)
+    }
+}
+
+impl FinternetClient {
+    /// Credit every deposit with at least `min_confirmations` that arrived since the last poll
+    ///
+    /// Each deposit is persisted before it is returned. A deposit still short of its
+    /// confirmations holds back everything after it on the same address until a later poll.
+    pub async fn poll_deposits(&self, manager: &DepositManager, min_confirmations: u64) -> Result<Vec<DepositDetected>> {
+        let current_slot = self.client.get_slot_with_commitment(CommitmentConfig::confirmed())?;
+        let mut detected = Vec::new();
+        for address in manager.addresses()? {
+            match self.scan_deposit_address(manager, &address, current_slot, min_confirmations) {
+                Ok(deposits) => detected.extend(deposits),
+                Err(e) => tracing::warn!("Deposit scan for {} failed: {}", address.customer_id, e),
+            }
+        }
+        Ok(detected)
+    }
+
+    /// Poll every assigned address each `interval` and call `handler` with each deposit credited
+    ///
+    /// Runs until the task is dropped or the client shuts down; addresses assigned meanwhile are picked up on the next
+    /// tick. Failed polls are logged and retried.
+    pub async fn watch_deposits(
+        &self,
+        manager: &DepositManager,
+        min_confirmations: u64,
+        interval: Duration,
+        mut handler: impl FnMut(DepositDetected),
+    ) {
+        let shutdown = self.tasks.signal();
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            tokio::select! {
+                _ = ticker.tick() => {}
+                _ = shutdown.cancelled() => return,
+            }
+            match self.poll_deposits(manager, min_confirmations).await {
+                Ok(deposits) => deposits.into_iter().for_each(&mut handler),
+                Err(e) => tracing::warn!("Deposit poll failed: {}", e),
+            }
+        }
+    }
+
+    /// Sweep one customer's deposit address, or every one, into `treasury`
+    ///
+    /// `fee_payer` covers the fees, so deposit wallets never need SOL. Sweeping leaves the
+    /// credited totals alone; they record what each customer paid in.
+    pub async fn sweep_deposits(
+        &self,
+        manager: &DepositManager,
+        fee_payer: &Keypair,
+        customer_id: Option<&str>,
+        treasury: &Pubkey,
+        options: &SweepOptions,
+    ) -> Result<SweepReport> {
+        let customers: Vec<String> = match customer_id {
+            Some(customer_id) => {
+                let address = manager
+                    .get(customer_id)?
+                    .ok_or_else(|| anyhow!("No deposit address assigned to {}", customer_id))?;
+                vec![address.customer_id]
+            }
+            None => manager.addresses()?.into_iter().map(|address| address.customer_id).collect(),
+        };
+        let wallets = customers
+            .iter()
+            .map(|customer_id| manager.keypair(customer_id))
+            .collect::<Result<Vec<_>>>()?;
+        self.sweep_balances(fee_payer, &wallets, treasury, manager.mint(), options).await
+    }
+
+    /// Credit what arrived at one address since its checkpoint, oldest first
+    #[tracing::instrument(skip_all, fields(customer = %address.customer_id, wallet = %readable(&address.address)))]
+    fn scan_deposit_address(
+        &self,
+        manager: &DepositManager,
+        address: &DepositAddress,
+        current_slot: u64,
+        min_confirmations: u64,
+    ) -> Result<Vec<DepositDetected>> {
+        // Newest first, back to the checkpoint; deposit addresses see little traffic, so page
+        // until the checkpoint is reached rather than leave a gap behind it
+        let mut signatures = Vec::new();
+        let mut before = None;
+        loop {
+            let page = self.client.get_signatures_for_address_with_config(
+                &address.token_account,
+                GetConfirmedSignaturesForAddress2Config {
+                    before,
+                    until: address.checkpoint,
+                    limit: Some(SIGNATURE_PAGE_LIMIT),
+                    commitment: Some(CommitmentConfig::confirmed()),
+                },
+            )?;
+            let exhausted = page.len() < SIGNATURE_PAGE_LIMIT;
+            if let Some(last) = page.last() {
+                before = Some(Signature::from_str(&last.signature)?);
+            }
+            signatures.extend(page);
+            if exhausted {
+                break;
+            }
+        }
+
+        let mut checkpoint = None;
+        let mut records = Vec::new();
+        for status in signatures.iter().rev() {
+            let finalized = status.confirmation_status == Some(TransactionConfirmationStatus::Finalized);
+            if !finalized && current_slot.saturating_sub(status.slot) < min_confirmations {
+                break;
+            }
+            let signature = Signature::from_str(&status.signature)?;
+            if status.err.is_none() {
+                let fetched = self.client.get_transaction_with_config(
+                    &signature,
+                    RpcTransactionConfig {
+                        encoding: Some(UiTransactionEncoding::Json),
+                        commitment: Some(CommitmentConfig::confirmed()),
+                        max_supported_transaction_version: Some(0),
+                    },
+                );
+                let transaction = match fetched {
+                    Ok(transaction) => transaction,
+                    Err(e) => {
+                        // Keep the checkpoint before it, so the next poll tries it again
+                        tracing::warn!("Deposit transaction {} unavailable: {}", signature, e);
+                        break;
+                    }
+                };
+                records.extend(transfer_records(&address.address, signature, &transaction).into_iter().filter(|record| {
+                    record.to == address.address && record.currency.mint() == Some(*manager.mint())
+                }));
+            }
+            checkpoint = Some(signature);
+        }
+
+        let Some(checkpoint) = checkpoint else {
+            return Ok(Vec::new());
+        };
+        let detected = manager.credit(&address.customer_id, checkpoint, records)?;
+        for deposit in &detected {
+            tracing::info!(
+                "Credited {} with {} ({})",
+                deposit.customer_id,
+                deposit.record.currency.format_amount(deposit.record.amount),
+                deposit.record.signature
+            );
+        }
+        Ok(detected)
+    }
+}
@@ -0,0 +1,193 @@
+//! A scripted latency check of the configured RPC node, for turning "it's slow" into numbers
+//!
+//! `diagnose` runs each read a send or a history read depends on, plus a simulated write,
+//! `samples` times, and compares the median against the phase's `Phase::budget`. Nothing is
+//! sent, so broadcast and confirmation are not measured; their timings come from real sends, on
+//! `SendReceipt::timings`.
+
+use crate::latency::Phase;
+use crate::payment::usdc;
+use crate::FinternetClient;
+use anyhow::Result;
+use mpl_token_metadata::accounts::Metadata;
+use serde::{Deserialize, Serialize};
+use solana_client::rpc_client::GetConfirmedSignaturesForAddress2Config;
+use solana_client::rpc_config::{RpcSimulateTransactionConfig, RpcTransactionConfig};
+use solana_client::rpc_request::TokenAccountsFilter;
+use solana_sdk::{pubkey::Pubkey, signature::Signature, transaction::Transaction};
+use solana_transaction_status::UiTransactionEncoding;
+use std::str::FromStr;
+use std::time::Instant;
+
+/// Samples taken of each step when none are asked for
+pub const DEFAULT_DIAGNOSTIC_SAMPLES: usize = 3;
+/// A sample this many times the median marks the step unstable even when the median is in budget
+const SPIKE_FACTOR: f64 = 3.0;
+const DIAGNOSTIC_MEMO: &str = "finternet diagnose";
+
+/// How a step compares with its budget
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DiagnosticVerdict {
+    Ok,
+    /// The median sample exceeded the phase's budget
+    OverBudget,
+    /// In budget, but one sample spiked well above the median
+    Unstable,
+    Failed,
+}
+
+/// One RPC call of the script and how long each sample of it took
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DiagnosticStep {
+    pub phase: Phase,
+    /// RPC method called, e.g. `getLatestBlockhash`
+    pub method: String,
+    pub samples_ms: Vec<f64>,
+    pub budget_ms: f64,
+    /// Why sampling stopped early, if it did
+    pub error: Option<String>,
+}
+
+impl DiagnosticStep {
+    pub fn median_ms(&self) -> Option<f64> {
+        let mut sorted = self.samples_ms.clone();
+        sorted.sort_by(f64::total_cmp);
+        sorted.get(sorted.len() / 2).copied()
+    }
+
+    pub fn max_ms(&self) -> Option<f64> {
+        self.samples_ms.iter().copied().max_by(f64::total_cmp)
+    }
+
+    /// `Failed` only when not one sample succeeded
+    pub fn verdict(&self) -> DiagnosticVerdict {
+        let (Some(median), Some(max)) = (self.median_ms(), self.max_ms()) else {
+            return DiagnosticVerdict::Failed;
+        };
+        if median > self.budget_ms {
+            DiagnosticVerdict::OverBudget
+        } else if max > median * SPIKE_FACTOR && max > self.budget_ms / 2.0 {
+            DiagnosticVerdict::Unstable
+        } else {
+            DiagnosticVerdict::Ok
+        }
+    }
+}
+
+/// Every step of a `diagnose` run, in the order they ran
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DiagnosticReport {
+    /// Endpoint checked, with credentials stripped
+    pub rpc: String,
+    /// Wallet whose accounts and history were read
+    pub wallet: Pubkey,
+    pub steps: Vec<DiagnosticStep>,
+}
+
+impl DiagnosticReport {
+    /// Steps that were over budget, unstable, or failed
+    pub fn outliers(&self) -> Vec<&DiagnosticStep> {
+        self.steps.iter().filter(|step| step.verdict() != DiagnosticVerdict::Ok).collect()
+    }
+}
+
+impl FinternetClient {
+    /// Time each step of the diagnostic script `samples` times against the configured RPC
+    ///
+    /// Reads `wallet`'s history and token accounts and simulates a memo it pays for; the
+    /// simulation skips signature checks, so no keypair is needed. An RPC error stops that step
+    /// and the script moves on.
+    #[tracing::instrument(skip_all, fields(wallet = %wallet, samples, rpc = %self.rpc_endpoint()))]
+    pub async fn diagnose(&self, wallet: &Pubkey, samples: usize) -> Result<DiagnosticReport> {
+        let samples = samples.max(1);
+        let mut steps = Vec::new();
+
+        steps.push(sample(Phase::Blockhash, "getLatestBlockhash", samples, || Ok(self.client.get_latest_blockhash()?)));
+
+        let mut newest: Option<Signature> = None;
+        steps.push(sample(Phase::Signatures, "getSignaturesForAddress", samples, || {
+            let signatures = self.client.get_signatures_for_address_with_config(
+                wallet,
+                GetConfirmedSignaturesForAddress2Config { limit: Some(10), ..Default::default() },
+            )?;
+            newest = signatures.first().and_then(|info| Signature::from_str(&info.signature).ok());
+            Ok(())
+        }));
+        if let Some(signature) = newest {
+            steps.push(sample(Phase::Transactions, "getTransaction", samples, || {
+                Ok(self.client.get_transaction_with_config(
+                    &signature,
+                    RpcTransactionConfig {
+                        encoding: Some(UiTransactionEncoding::Json),
+                        commitment: Some(self.history_commitment_for(&Default::default())),
+                        max_supported_transaction_version: Some(0),
+                    },
+                )?)
+            }));
+        }
+
+        let mut held_mint: Option<Pubkey> = None;
+        steps.push(sample(Phase::Accounts, "getTokenAccountsByOwner", samples, || {
+            let accounts = self
+                .client
+                .get_token_accounts_by_owner(wallet, TokenAccountsFilter::ProgramId(spl_token::id()))?;
+            held_mint = accounts.iter().find_map(|account| token_account_mint(&account.account.data));
+            Ok(())
+        }));
+        let mint = held_mint.unwrap_or_else(usdc::devnet_mint);
+        steps.push(sample(Phase::Metadata, "getMultipleAccounts", samples, || {
+            Ok(self.client.get_multiple_accounts(&[mint, Metadata::find_pda(&mint).0])?)
+        }));
+
+        steps.push(sample(Phase::Fees, "getRecentPrioritizationFees", samples, || {
+            Ok(self.client.get_recent_prioritization_fees(&[*wallet])?)
+        }));
+        let memo = spl_memo::build_memo(DIAGNOSTIC_MEMO.as_bytes(), &[]);
+        let transaction = Transaction::new_with_payer(&[memo], Some(wallet));
+        steps.push(sample(Phase::Simulation, "simulateTransaction", samples, || {
+            Ok(self.client.simulate_transaction_with_config(
+                &transaction,
+                RpcSimulateTransactionConfig { sig_verify: false, replace_recent_blockhash: true, ..Default::default() },
+            )?)
+        }));
+
+        for step in &steps {
+            tracing::debug!(phase = %step.phase, method = %step.method, median_ms = step.median_ms(), "diagnostic step done");
+        }
+        Ok(DiagnosticReport { rpc: self.rpc_endpoint(), wallet: *wallet, steps })
+    }
+}
+
+/// Run `call` up to `samples` times, stopping at its first error
+fn sample<T>(phase: Phase, method: &str, samples: usize, mut call: impl FnMut() -> Result<T>) -> DiagnosticStep {
+    let mut step = DiagnosticStep {
+        phase,
+        method: method.to_string(),
+        samples_ms: Vec::with_capacity(samples),
+        budget_ms: phase.budget().as_secs_f64() * 1000.0,
+        error: None,
+    };
+    for _ in 0..samples {
+        let started = Instant::now();
+        let outcome = call();
+        let elapsed_ms = started.elapsed().as_secs_f64() * 1000.0;
+        match outcome {
+            Ok(_) => step.samples_ms.push(elapsed_ms),
+            Err(e) => {
+                step.error = Some(e.to_string());
+                break;
+            }
+        }
+    }
+    step
+}
+
+/// The mint of a `jsonParsed` token account returned by `getTokenAccountsByOwner`
+fn token_account_mint(data: &solana_account_decoder::UiAccountData) -> Option<Pubkey> {
+    let solana_account_decoder::UiAccountData::Json(parsed) = data else {
+        return None;
+    };
+    let mint = parsed.parsed.get("info")?.get("mint")?.as_str()?;
+    Pubkey::from_str(mint).ok()
+}
@@ -0,0 +1,311 @@
+//! Legal documents (deeds, invoices) bound to a tokenized asset by their SHA-256
+//!
+//! `attach_document` writes a ledger entry from the asset's issuer that references the mint,
+//! so it shows up in the mint's history, and names the document's hash, type, and where the
+//! metadata publisher put the bytes, if it hosts documents. Only entries the issuer signed are
+//! read back: anyone can reference a mint, but only the issuer can vouch for its documents.
+
+use crate::telemetry::readable;
+use crate::FinternetClient;
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use solana_sdk::{
+    pubkey::Pubkey,
+    signature::{Keypair, Signature},
+    signer::Signer,
+};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Ledger entry action under which the issuer anchors a document
+pub const DOCUMENT_ACTION: &str = "document_anchor";
+/// Document types are short labels such as `deed` or `invoice`
+pub const MAX_DOC_TYPE_BYTES: usize = 32;
+/// Signatures of the mint's history searched for anchors
+const DOCUMENT_SCAN_LIMIT: usize = 200;
+
+/// One document the issuer bound to a mint
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DocumentAnchor {
+    pub mint: Pubkey,
+    /// Lowercase hex SHA-256 of the document bytes
+    pub sha256: String,
+    pub doc_type: String,
+    /// Where the metadata publisher stored the bytes; `None` when only the hash was anchored
+    pub uri: Option<String>,
+    /// Bytes in the document
+    pub size: u64,
+    pub anchored_by: Pubkey,
+    /// Unix seconds of the anchoring transaction's block
+    pub anchored_at: u64,
+    pub signature: Signature,
+}
+
+/// How a document compares with what the issuer anchored for its mint
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DocumentVerification {
+    pub mint: Pubkey,
+    /// Hash of the bytes that were checked
+    pub sha256: String,
+    /// Anchors of exactly these bytes, oldest first; empty when the document was altered or
+    /// never anchored
+    pub matches: Vec<DocumentAnchor>,
+    /// Every document anchored for the mint, for telling a tampered file from an unknown one
+    pub anchored: usize,
+}
+
+impl DocumentVerification {
+    pub fn is_verified(&self) -> bool {
+        !self.matches.is_empty()
+    }
+}
+
+impl FinternetClient {
+    /// Anchor `bytes` as a `doc_type` document of `mint`, signed by its issuer
+    ///
+    /// With a metadata publisher that hosts documents the bytes are uploaded first and the
+    /// anchor carries their URI; otherwise only the hash goes on-chain and the holder keeps the
+    /// file.
+    pub async fn attach_document(
+        &self,
+        wallet: &Keypair,
+        mint: &Pubkey,
+        bytes: &[u8],
+        doc_type: &str,
+    ) -> Result<DocumentAnchor> {
+        let outcome = self.attach_document_unlogged(wallet, mint, bytes, doc_type).await;
+        let parameters = serde_json::json!({
+            "wallet": wallet.pubkey().to_string(),
+            "mint": mint.to_string(),
+            "doc_type": doc_type,
+            "sha256": outcome.as_ref().ok().map(|anchor| anchor.sha256.clone()),
+        });
+        self.log_action("attach_document", parameters, None, outcome.as_ref(), |anchor| anchor.signature.to_string());
+        outcome
+    }
+
+    #[tracing::instrument(skip_all, fields(wallet = %readable(&wallet.pubkey()), mint = %mint, doc_type))]
+    async fn attach_document_unlogged(
+        &self,
+        wallet: &Keypair,
+        mint: &Pubkey,
+        bytes: &[u8],
+        doc_type: &str,
+    ) -> Result<DocumentAnchor> {
+        let doc_type = doc_type.trim();
+        if doc_type.is_empty() || doc_type.len() > MAX_DOC_TYPE_BYTES {
+            return Err(anyhow!("Document type must be 1-{} bytes, got '{}'", MAX_DOC_TYPE_BYTES, doc_type));
+        }
+        if bytes.is_empty() {
+            return Err(anyhow!("Refusing to anchor an empty document"));
+        }
+        let asset = self.get_asset_info(mint).await?;
+        if !asset.has_metadata || asset.issuer != wallet.pubkey() {
+            return Err(anyhow!("Only the issuer of {} can attach documents to it", mint));
+        }
+
+        let sha256 = hex_sha256(bytes);
+        let uri = match &self.metadata_publisher {
+            Some(publisher) => publisher.publish_document(mint, &sha256, bytes).await?,
+            None => None,
+        };
+        let entry = serde_json::json!({
+            "action": DOCUMENT_ACTION,
+            "mint": mint.to_string(),
+            "sha256": sha256,
+            "doc_type": doc_type,
+            "uri": uri,
+            "size": bytes.len(),
+        });
+        // Referencing the mint puts the anchor in its history, where `verify_document` looks
+        let signature = self.write_ledger_entry_referencing(wallet, &entry.to_string(), &[*mint]).await?;
+        tracing::info!("Document {} anchored to {}: {}", sha256, mint, signature);
+        Ok(DocumentAnchor {
+            mint: *mint,
+            sha256,
+            doc_type: doc_type.to_string(),
+            uri,
+            size: bytes.len() as u64,
+            anchored_by: wallet.pubkey(),
+            anchored_at: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+            signature,
+        })
+    }
+
+    /// Check `bytes` against the documents `mint`'s issuer anchored
+    ///
+    /// A file altered by even one byte hashes differently and matches nothing.
+    #[tracing::instrument(skip_all, fields(mint = %mint))]
+    pub async fn verify_document(&self, mint: &Pubkey, bytes: &[u8]) -> Result<DocumentVerification> {
+        let asset = self.get_asset_info(mint).await?;
+        let sha256 = hex_sha256(bytes);
+        let anchored = asset.documents;
+        let matches: Vec<DocumentAnchor> = anchored.iter().filter(|anchor| anchor.sha256 == sha256).cloned().collect();
+        if matches.is_empty() {
+            tracing::warn!("Document {} matches none of the {} anchored for {}", sha256, anchored.len(), mint);
+        }
+        Ok(DocumentVerification { mint: *mint, sha256, matches, anchored: anchored.len() })
+    }
+
+    /// Documents `issuer` anchored for `mint`, oldest first
+    pub(crate) async fn anchored_documents(&self, mint: &Pubkey, issuer: &Pubkey) -> Result<Vec<DocumentAnchor>> {
+        let entries = self.read_ledger_entries(mint, Some(DOCUMENT_SCAN_LIMIT)).await?;
+        let mint_text = mint.to_string();
+        let mut anchors: Vec<DocumentAnchor> = entries
+            .iter()
+            .filter(|entry| entry.signer == Some(*issuer) && !entry.is_partial())
+            .filter_map(|entry| {
+                let value = entry.json()?;
+                if value.get("action")?.as_str()? != DOCUMENT_ACTION || value.get("mint")?.as_str()? != mint_text {
+                    return None;
+                }
+                let sha256 = value.get("sha256")?.as_str()?;
+                if sha256.len() != 64 || !sha256.bytes().all(|b| b.is_ascii_hexdigit()) {
+                    return None;
+                }
+                Some(DocumentAnchor {
+                    mint: *mint,
+                    sha256: sha256.to_ascii_lowercase(),
+                    doc_type: value.get("doc_type")?.as_str()?.to_string(),
+                    uri: value.get("uri").and_then(|uri| uri.as_str()).map(str::to_string),
+                    size: value.get("size").and_then(|size| size.as_u64()).unwrap_or(0),
+                    anchored_by: *issuer,
+                    anchored_at: entry.timestamp,
+                    signature: entry.signature,
+                })
+            })
+            .collect();
+        // Entries come newest first
+        anchors.reverse();
+        Ok(anchors)
+    }
+}
+
+fn hex_sha256(bytes: &[u8]) -> String {
+    Sha256::digest(bytes).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::DirectoryPublisher;
+    use crate::mock_cluster::{MockCluster, TempDir};
+    use mpl_token_metadata::accounts::Metadata;
+    use solana_sdk::account::Account;
+
+    const DEED: &[u8] = b"%PDF-1.7 Deed of sale, Plot 14, Harbour Road. Signed and witnessed.";
+
+    /// A one-of-one asset whose Token Metadata names `issuer` as its first creator
+    fn issued_asset(cluster: &MockCluster, issuer: &Pubkey) -> Pubkey {
+        fn string(data: &mut Vec<u8>, text: &str) {
+            data.extend((text.len() as u32).to_le_bytes());
+            data.extend(text.as_bytes());
+        }
+        let mint = cluster.add_mint(&spl_token::id(), 0, issuer);
+        let mut data = vec![4];
+        data.extend(issuer.to_bytes());
+        data.extend(mint.to_bytes());
+        string(&mut data, "Plot 14 Harbour Road");
+        string(&mut data, "PLOT");
+        string(&mut data, "");
+        // No fee, the issuer as sole verified creator, then every optional field absent
+        data.extend([0, 0, 1, 1, 0, 0, 0]);
+        data.extend(issuer.to_bytes());
+        data.extend([1, 100]);
+        data.extend([0, 1, 0, 1, 2, 0, 0, 0, 0]);
+        let account = Account { lamports: 5_616_720, data, owner: mpl_token_metadata::ID, executable: false, rent_epoch: 0 };
+        cluster.set_account(Metadata::find_pda(&mint).0, account);
+        mint
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn an_anchored_document_verifies_and_a_tampered_copy_does_not() {
+        let cluster = MockCluster::new();
+        let dir = TempDir::new("documents");
+        let issuer = Keypair::new();
+        cluster.set_balance(issuer.pubkey(), 1_000_000_000);
+        let mint = issued_asset(&cluster, &issuer.pubkey());
+        let client = cluster
+            .default_client()
+            .with_metadata_publisher(DirectoryPublisher::new(dir.path(), "https://assets.example.com"));
+
+        let anchor = client.attach_document(&issuer, &mint, DEED, " deed ").await.unwrap();
+        assert_eq!(anchor.sha256, hex_sha256(DEED));
+        assert_eq!(anchor.sha256.len(), 64);
+        assert_eq!(anchor.doc_type, "deed");
+        assert_eq!(anchor.size, DEED.len() as u64);
+        assert_eq!(anchor.anchored_by, issuer.pubkey());
+        // The publisher hosts the bytes; the chain only carries their hash and where they are
+        let uri = format!("https://assets.example.com/documents/{}/{}", mint, anchor.sha256);
+        assert_eq!(anchor.uri.as_deref(), Some(uri.as_str()));
+        let hosted = dir.path().join("documents").join(mint.to_string()).join(&anchor.sha256);
+        assert_eq!(std::fs::read(hosted).unwrap(), DEED);
+        let sent = cluster.sent();
+        assert_eq!(sent.len(), 1);
+        assert!(sent[0].message.account_keys.contains(&mint));
+
+        let asset = client.get_asset_info(&mint).await.unwrap();
+        assert_eq!(asset.documents.len(), 1);
+        let listed = &asset.documents[0];
+        assert_eq!((listed.signature, &listed.sha256, &listed.doc_type), (anchor.signature, &anchor.sha256, &anchor.doc_type));
+        assert_eq!(listed.uri, anchor.uri);
+        assert_eq!(listed.size, anchor.size);
+
+        let verification = client.verify_document(&mint, DEED).await.unwrap();
+        assert!(verification.is_verified());
+        assert_eq!(verification.anchored, 1);
+        assert_eq!(verification.matches.len(), 1);
+        let matched = &verification.matches[0];
+        assert_eq!((matched.anchored_by, matched.signature), (issuer.pubkey(), anchor.signature));
+        assert!(matched.anchored_at > 0);
+
+        // One changed byte hashes differently and matches nothing, though the mint has a document
+        let mut tampered = DEED.to_vec();
+        tampered[20] ^= 0x01;
+        let verification = client.verify_document(&mint, &tampered).await.unwrap();
+        assert!(!verification.is_verified());
+        assert_eq!(verification.sha256, hex_sha256(&tampered));
+        assert_ne!(verification.sha256, anchor.sha256);
+        assert_eq!(verification.anchored, 1);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn only_the_issuer_can_anchor_and_only_its_anchors_count() {
+        let cluster = MockCluster::new();
+        let (issuer, forger) = (Keypair::new(), Keypair::new());
+        cluster.set_balance(issuer.pubkey(), 1_000_000_000);
+        cluster.set_balance(forger.pubkey(), 1_000_000_000);
+        let mint = issued_asset(&cluster, &issuer.pubkey());
+        let client = cluster.default_client();
+
+        let error = client.attach_document(&forger, &mint, DEED, "deed").await.unwrap_err();
+        assert!(error.to_string().contains("Only the issuer"), "{}", error);
+        for (bytes, doc_type) in [(DEED, ""), (DEED, "a-document-type-longer-than-32-bytes"), (&[][..], "deed")] {
+            assert!(client.attach_document(&issuer, &mint, bytes, doc_type).await.is_err(), "{:?}", doc_type);
+        }
+        assert!(cluster.sent().is_empty());
+
+        // Anyone can reference the mint with an entry shaped like an anchor; it is not read back
+        let forged = serde_json::json!({
+            "action": DOCUMENT_ACTION,
+            "mint": mint.to_string(),
+            "sha256": hex_sha256(DEED),
+            "doc_type": "deed",
+            "uri": null,
+            "size": DEED.len(),
+        });
+        client.write_ledger_entry_referencing(&forger, &forged.to_string(), &[mint]).await.unwrap();
+        let verification = client.verify_document(&mint, DEED).await.unwrap();
+        assert!(!verification.is_verified());
+        assert_eq!(verification.anchored, 0);
+
+        // Without a publisher that hosts documents only the hash is anchored
+        let anchor = client.attach_document(&issuer, &mint, DEED, "deed").await.unwrap();
+        assert_eq!(anchor.uri, None);
+        let verification = client.verify_document(&mint, DEED).await.unwrap();
+        assert_eq!(verification.anchored, 1);
+        assert_eq!(verification.matches[0].anchored_by, issuer.pubkey());
+        assert_eq!(verification.matches[0].uri, None);
+    }
+}
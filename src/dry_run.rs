@@ -0,0 +1,250 @@
+//! Client-wide dry-run mode, for staging environments where nothing may reach the chain
+//!
+//! With `FinternetConfig::dry_run` set, every transaction the client would send is built,
+//! signed, and simulated instead. The mutating call then fails with a `DryRunOutcome` carrying
+//! what the simulation reported, so later steps that depend on it never run against state that
+//! does not exist. Reads are untouched. The action log and event hooks record the attempt under
+//! a `dry-run:` marker rather than a signature, and spending limits are not charged. Calls made
+//! on the raw `FinternetClient::client` bypass the mode.
+
+use crate::events::SdkEvent;
+use crate::{FinternetClient, FinternetConfig};
+use serde::{Deserialize, Serialize};
+use solana_sdk::{signature::Signature, transaction::Transaction};
+use std::fmt;
+
+/// Prefix of the marker recorded in place of a signature for simulated sends
+pub const DRY_RUN_MARKER_PREFIX: &str = "dry-run:";
+/// Response header the API server sets to `true` on every response while in dry-run mode
+pub const DRY_RUN_HEADER: &str = "X-Finternet-Dry-Run";
+
+/// A transaction built and simulated in dry-run mode, returned as the error of the call that
+/// would have sent it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DryRunOutcome {
+    pub operation: String,
+    /// Signature of the simulated transaction, which was never broadcast
+    pub signature: Signature,
+    pub units_consumed: Option<u64>,
+    #[serde(default)]
+    pub logs: Vec<String>,
+    /// Why the simulation failed, or could not run; `None` means the send would have succeeded
+    pub simulation_error: Option<String>,
+}
+
+impl DryRunOutcome {
+    pub fn code(&self) -> &'static str {
+        if self.simulation_error.is_some() {
+            "dry_run_failed"
+        } else {
+            "dry_run"
+        }
+    }
+
+    pub fn would_succeed(&self) -> bool {
+        self.simulation_error.is_none()
+    }
+
+    /// `dry-run:<signature>`, which can never be mistaken for a real signature
+    pub fn marker(&self) -> String {
+        format!("{}{}", DRY_RUN_MARKER_PREFIX, self.signature)
+    }
+}
+
+impl fmt::Display for DryRunOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.simulation_error {
+            None => write!(f, "Dry run: {} simulated successfully and was not sent ({})", self.operation, self.marker()),
+            Some(error) => write!(f, "Dry run: {} would fail ({}) and was not sent ({})", self.operation, error, self.marker()),
+        }
+    }
+}
+
+impl std::error::Error for DryRunOutcome {}
+
+impl FinternetConfig {
+    /// Simulate every transaction instead of sending it; see `dry_run`
+    pub fn with_dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+}
+
+impl FinternetClient {
+    pub fn is_dry_run(&self) -> bool {
+        self.config.dry_run
+    }
+
+    /// Simulate `transaction` in place of sending it, as an error for the sending call to return
+    pub(crate) fn simulate_instead(&self, label: &str, transaction: &Transaction) -> anyhow::Error {
+        let signature = transaction.signatures.first().copied().unwrap_or_default();
+        let outcome = match self.client.simulate_transaction(transaction) {
+            Ok(response) => DryRunOutcome {
+                operation: label.to_string(),
+                signature,
+                units_consumed: response.value.units_consumed,
+                logs: response.value.logs.unwrap_or_default(),
+                simulation_error: response.value.err.map(|e| e.to_string()),
+            },
+            Err(e) => DryRunOutcome {
+                operation: label.to_string(),
+                signature,
+                units_consumed: None,
+                logs: Vec::new(),
+                simulation_error: Some(format!("simulation could not run: {}", e)),
+            },
+        };
+        tracing::info!("{}", outcome);
+        self.emit(|| SdkEvent::DryRun {
+            operation: outcome.operation.clone(),
+            marker: outcome.marker(),
+            simulation_error: outcome.simulation_error.clone(),
+        });
+        outcome.into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error_code::FinternetError;
+    use crate::events::SdkEvent;
+    use crate::mock_cluster::{MockCluster, TempDir};
+    use crate::policy::{PolicyViolation, SpendingPolicy};
+    use crate::AssetType;
+    use serde_json::json;
+    use solana_sdk::{pubkey::Pubkey, signature::Keypair, signer::Signer};
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+
+    fn dry_run_client(cluster: &MockCluster) -> FinternetClient {
+        cluster.client(FinternetConfig::default().with_dry_run(true))
+    }
+
+    /// A payer with SOL for fees and 100 units of a fresh 6-decimal mint
+    fn funded(cluster: &MockCluster) -> (Keypair, Pubkey) {
+        let payer = Keypair::new();
+        let mint = cluster.add_mint(&spl_token::id(), 6, &Pubkey::new_unique());
+        cluster.set_balance(payer.pubkey(), 1_000_000_000);
+        cluster.add_token_account(&payer.pubkey(), &mint, 100_000_000);
+        (payer, mint)
+    }
+
+    fn simulated<T: fmt::Debug>(result: anyhow::Result<T>) -> DryRunOutcome {
+        let error = result.unwrap_err();
+        error.downcast_ref::<DryRunOutcome>().unwrap_or_else(|| panic!("not a dry run: {}", error)).clone()
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn no_mutating_method_reaches_send_transaction() {
+        let cluster = MockCluster::new();
+        let client = dry_run_client(&cluster);
+        let (payer, mint) = funded(&cluster);
+        let to = Keypair::new().pubkey();
+
+        let outcomes = vec![
+            simulated(client.send_payment(&payer, &to, 1_000_000, &mint, Some("invoice 7")).await),
+            simulated(client.tokenize_asset("Harbour Receipt", "Berth 12", 1_000, &AssetType::Commodity, &payer).await),
+            simulated(client.write_ledger_entry(&payer, "quarter closed").await),
+            simulated(client.register_identity(&payer, "Dana Ortiz", HashMap::new()).await),
+            simulated(client.create_organization(&payer, "Acme Treasury").await),
+        ];
+
+        assert_eq!(cluster.call_count("sendTransaction"), 0);
+        assert!(cluster.sent().is_empty());
+        assert_eq!(cluster.call_count("simulateTransaction"), outcomes.len());
+        for outcome in &outcomes {
+            assert!(outcome.would_succeed(), "{}", outcome);
+            assert_eq!(outcome.code(), "dry_run");
+            assert_ne!(outcome.signature, Signature::default());
+            assert!(outcome.to_string().contains("was not sent"), "{}", outcome);
+        }
+        // Reads are answered as usual
+        assert_eq!(client.get_token_balance(&payer.pubkey(), &mint).await.unwrap(), 100_000_000);
+        assert!(client.is_dry_run());
+        assert!(!cluster.default_client().is_dry_run());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn a_failing_simulation_is_reported_and_still_not_sent() {
+        let cluster = MockCluster::new();
+        let client = dry_run_client(&cluster);
+        let (payer, mint) = funded(&cluster);
+        cluster.respond("simulateTransaction", |_| {
+            Some(Ok(json!({
+                "context": { "slot": 1000 },
+                "value": {
+                    "err": { "InstructionError": [0, { "Custom": 1 }] },
+                    "logs": ["Program log: Error: insufficient funds"],
+                    "accounts": null,
+                    "unitsConsumed": 4_210,
+                },
+            })))
+        });
+
+        let outcome = simulated(client.send_payment(&payer, &Keypair::new().pubkey(), 1, &mint, None).await);
+        assert!(!outcome.would_succeed());
+        assert_eq!(outcome.code(), "dry_run_failed");
+        assert_eq!(outcome.units_consumed, Some(4_210));
+        assert_eq!(outcome.logs, ["Program log: Error: insufficient funds"]);
+        assert!(outcome.to_string().contains("would fail"), "{}", outcome);
+        assert_eq!(FinternetError::from(&outcome), FinternetError::DryRunFailed);
+        assert_eq!(cluster.call_count("sendTransaction"), 0);
+
+        // A node that cannot simulate at all is reported the same way
+        cluster.respond("simulateTransaction", |_| Some(Err("Method not found".to_string())));
+        let outcome = simulated(client.write_ledger_entry(&payer, "quarter closed").await);
+        assert!(outcome.simulation_error.unwrap().starts_with("simulation could not run"));
+        assert!(cluster.sent().is_empty());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn the_action_log_and_events_record_a_marker_instead_of_a_signature() {
+        let cluster = MockCluster::new();
+        let dir = TempDir::new("dry-run");
+        let client = cluster.client(FinternetConfig {
+            action_log_path: Some(dir.join("actions.jsonl")),
+            ..FinternetConfig::default().with_dry_run(true)
+        });
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&seen);
+        client.on_event(move |event| sink.lock().unwrap().push(event));
+        let (payer, mint) = funded(&cluster);
+
+        let outcome = simulated(client.send_payment(&payer, &Keypair::new().pubkey(), 1, &mint, None).await);
+        let marker = outcome.marker();
+        assert_eq!(marker, format!("dry-run:{}", outcome.signature));
+
+        let events = seen.lock().unwrap().clone();
+        assert!(events.iter().any(|event| matches!(
+            event,
+            SdkEvent::DryRun { marker: m, simulation_error: None, .. } if *m == marker
+        )));
+        assert!(!events.iter().any(|event| matches!(event, SdkEvent::PaymentConfirmed { .. })));
+
+        let page = client.action_log().unwrap().page(0, 10).unwrap();
+        let entry = page.entries.iter().find(|entry| entry.operation == "send_payment").unwrap();
+        assert_eq!(entry.signature.as_deref(), Some(marker.as_str()));
+        assert_eq!((entry.error.as_deref(), entry.error_code.as_deref()), (None, None));
+        assert_eq!(cluster.call_count("sendTransaction"), 0);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn simulated_payments_are_not_charged_to_the_spending_limit() {
+        let cluster = MockCluster::new();
+        let (payer, mint) = funded(&cluster);
+        let client = dry_run_client(&cluster)
+            .with_spending_policy(SpendingPolicy::new().with_max_per_day(mint, 1_000))
+            .unwrap();
+        let to = Keypair::new().pubkey();
+
+        // Amounts differ so the transactions do, under the mock cluster's single blockhash
+        for amount in [1_000, 999, 998] {
+            simulated(client.send_payment(&payer, &to, amount, &mint, None).await);
+        }
+        // The limit still applies to each simulated payment on its own
+        let error = client.send_payment(&payer, &to, 1_001, &mint, None).await.unwrap_err();
+        assert!(error.downcast_ref::<PolicyViolation>().is_some(), "{}", error);
+        assert_eq!(cluster.call_count("sendTransaction"), 0);
+    }
+}
@@ -0,0 +1,952 @@
+//! The stable error catalogue: one numeric code, identifier, exit code, and HTTP status per kind
+//! of failure, for scripts that branch on why a call failed
+//!
+//! `FinternetError::of` classifies an `anyhow::Error` by the first typed error in its chain, then
+//! by the RPC error underneath, then by the text of known Solana messages. Every public error type
+//! implements `Catalogued`, registered in one list that is also what `of` looks for, and maps
+//! through an exhaustive match or destructuring, so a new variant or field does not compile until
+//! it is given a code. Codes, identifiers, and exit codes never change once released; retired ones
+//! are not reused.
+
+use crate::action_log::ActionLogError;
+use crate::anomaly::Anomaly;
+use crate::approval::ProposalError;
+use crate::asset_policy::AssetPolicyViolation;
+use crate::claim::ClaimError;
+use crate::cold_sweep::ColdSweepError;
+use crate::deadline::DeadlineError;
+use crate::dry_run::DryRunOutcome;
+use crate::history::TruncatedHistory;
+use crate::identity_bulk::AttestationError;
+use crate::intent::IntentError;
+use crate::listing::ListingError;
+use crate::memo_template::MemoTemplateError;
+use crate::mint_registry::MintNetworkError;
+use crate::organization::OrganizationError;
+use crate::payment::{AmountError, RecipientError};
+use crate::payment_link::PaymentLinkError;
+use crate::pipeline::SendRefused;
+use crate::policy::PolicyViolation;
+use crate::remediation::error_text;
+use crate::server_config::ServerConfigError;
+use crate::state_bundle::StateBundleError;
+use crate::timelock::TimelockError;
+use crate::token_account::TokenAccountDecodeError;
+use crate::tokenization_draft::TokenizationDraftError;
+use crate::usage::QuotaError;
+use crate::validation::ValidationError;
+use crate::webhook::DeliveryFailure;
+use serde::ser::SerializeStruct;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use solana_client::client_error::{ClientError, ClientErrorKind};
+use solana_client::rpc_request::{RpcError, RpcResponseErrorData};
+use solana_sdk::pubkey::ParsePubkeyError;
+use solana_sdk::signature::ParseSignatureError;
+use solana_sdk::transaction::TransactionError;
+use std::fmt;
+
+/// One kind of failure, grouped by the thousands digit of its code: 1 funds, 2 input, 3 RPC, chain,
+/// and other remote endpoints, 4 policy, 5 local state, 6 dry runs, 9 unclassified
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FinternetError {
+    /// The source token account holds less than the amount sent
+    InsufficientTokenBalance,
+    /// The fee payer cannot cover the fee or an account's rent
+    InsufficientSolForFees,
+    /// The recipient cannot receive the token, e.g. an off-curve address
+    InvalidRecipient,
+    /// A parameter failed validation: an amount, a memo, a malformed link or intent
+    InvalidInput,
+    /// The mint belongs to another network than the client's
+    WrongNetwork,
+    /// A signed payload, plan, or bundle does not match its signature or checksum
+    TamperedData,
+    /// A link, intent, proposal, or claim window has passed
+    Expired,
+    /// Already paid, claimed, executed, or otherwise settled
+    AlreadySettled,
+    NotFound,
+    /// A version, encoding, or currency this SDK does not handle
+    Unsupported,
+    /// The RPC node could not be reached or failed to answer
+    RpcUnavailable,
+    RpcRateLimited,
+    /// The transaction's blockhash expired before it landed
+    TransactionExpired,
+    /// The transaction landed or simulated with a program error
+    TransactionFailed,
+    /// The RPC node's history does not reach back far enough for the answer
+    HistoryTruncated,
+    /// The RPC node returned data that does not decode
+    UnexpectedRpcData,
+    /// The transaction was broadcast but not confirmed by the caller's deadline; it may still land
+    DeadlineExceeded,
+    /// A webhook endpoint refused or did not answer any attempt at a delivery
+    WebhookUndeliverable,
+    /// A spending policy refused the payment
+    PolicyViolation,
+    /// Anomaly detection refused the payment
+    AnomalyDetected,
+    /// The asset's transfer policy refused the recipient
+    AssetPolicyViolation,
+    /// The signer is not the wallet, member, or role the operation needs
+    NotAuthorized,
+    /// A send middleware refused the payment, e.g. sanctions screening
+    SendRefused,
+    /// A time lock or expiry has not been reached yet
+    NotYetAllowed,
+    /// An API key has used its daily request or volume quota; it resets at the next UTC midnight
+    QuotaExceeded,
+    InvalidConfig,
+    /// A local file such as the action log is malformed or altered
+    CorruptLocalState,
+    /// The client is in dry-run mode; the transaction was simulated and not sent
+    DryRun,
+    /// The client is in dry-run mode and the simulation failed
+    DryRunFailed,
+    /// Anything not classified above
+    Internal,
+}
+
+impl FinternetError {
+    pub const ALL: [FinternetError; 30] = [
+        Self::InsufficientTokenBalance,
+        Self::InsufficientSolForFees,
+        Self::InvalidRecipient,
+        Self::InvalidInput,
+        Self::WrongNetwork,
+        Self::TamperedData,
+        Self::Expired,
+        Self::AlreadySettled,
+        Self::NotFound,
+        Self::Unsupported,
+        Self::RpcUnavailable,
+        Self::RpcRateLimited,
+        Self::TransactionExpired,
+        Self::TransactionFailed,
+        Self::HistoryTruncated,
+        Self::UnexpectedRpcData,
+        Self::DeadlineExceeded,
+        Self::WebhookUndeliverable,
+        Self::PolicyViolation,
+        Self::AnomalyDetected,
+        Self::AssetPolicyViolation,
+        Self::NotAuthorized,
+        Self::SendRefused,
+        Self::NotYetAllowed,
+        Self::QuotaExceeded,
+        Self::InvalidConfig,
+        Self::CorruptLocalState,
+        Self::DryRun,
+        Self::DryRunFailed,
+        Self::Internal,
+    ];
+
+    /// The numeric code, e.g. `1001`
+    pub fn number(&self) -> u16 {
+        match self {
+            Self::InsufficientTokenBalance => 1001,
+            Self::InsufficientSolForFees => 1002,
+            Self::InvalidRecipient => 2001,
+            Self::InvalidInput => 2002,
+            Self::WrongNetwork => 2003,
+            Self::TamperedData => 2004,
+            Self::Expired => 2005,
+            Self::AlreadySettled => 2006,
+            Self::NotFound => 2007,
+            Self::Unsupported => 2008,
+            Self::RpcUnavailable => 3001,
+            Self::RpcRateLimited => 3002,
+            Self::TransactionExpired => 3003,
+            Self::TransactionFailed => 3004,
+            Self::HistoryTruncated => 3005,
+            Self::UnexpectedRpcData => 3006,
+            Self::DeadlineExceeded => 3007,
+            Self::WebhookUndeliverable => 3008,
+            Self::PolicyViolation => 4001,
+            Self::AnomalyDetected => 4002,
+            Self::AssetPolicyViolation => 4003,
+            Self::NotAuthorized => 4004,
+            Self::SendRefused => 4005,
+            Self::NotYetAllowed => 4006,
+            Self::QuotaExceeded => 4007,
+            Self::InvalidConfig => 5001,
+            Self::CorruptLocalState => 5002,
+            Self::DryRun => 6001,
+            Self::DryRunFailed => 6002,
+            Self::Internal => 9999,
+        }
+    }
+
+    /// The code as written in envelopes and the action log, e.g. `E1001`
+    pub fn code(&self) -> String {
+        format!("E{}", self.number())
+    }
+
+    /// Stable snake_case identifier, e.g. `insufficient_token_balance`
+    pub fn identifier(&self) -> &'static str {
+        match self {
+            Self::InsufficientTokenBalance => "insufficient_token_balance",
+            Self::InsufficientSolForFees => "insufficient_sol_for_fees",
+            Self::InvalidRecipient => "invalid_recipient",
+            Self::InvalidInput => "invalid_input",
+            Self::WrongNetwork => "wrong_network",
+            Self::TamperedData => "tampered_data",
+            Self::Expired => "expired",
+            Self::AlreadySettled => "already_settled",
+            Self::NotFound => "not_found",
+            Self::Unsupported => "unsupported",
+            Self::RpcUnavailable => "rpc_unavailable",
+            Self::RpcRateLimited => "rpc_rate_limited",
+            Self::TransactionExpired => "transaction_expired",
+            Self::TransactionFailed => "transaction_failed",
+            Self::HistoryTruncated => "history_truncated",
+            Self::UnexpectedRpcData => "unexpected_rpc_data",
+            Self::DeadlineExceeded => "deadline_exceeded",
+            Self::WebhookUndeliverable => "webhook_undeliverable",
+            Self::PolicyViolation => "policy_violation",
+            Self::AnomalyDetected => "anomaly_detected",
+            Self::AssetPolicyViolation => "asset_policy_violation",
+            Self::NotAuthorized => "not_authorized",
+            Self::SendRefused => "send_refused",
+            Self::NotYetAllowed => "not_yet_allowed",
+            Self::QuotaExceeded => "quota_exceeded",
+            Self::InvalidConfig => "invalid_config",
+            Self::CorruptLocalState => "corrupt_local_state",
+            Self::DryRun => "dry_run",
+            Self::DryRunFailed => "dry_run_failed",
+            Self::Internal => "internal",
+        }
+    }
+
+    /// Process exit code of a CLI command failing this way
+    ///
+    /// 1 stays the catch-all and 2 is clap's usage error; the tens digit follows the code's
+    /// group.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Self::InsufficientTokenBalance => 10,
+            Self::InsufficientSolForFees => 11,
+            Self::InvalidRecipient => 20,
+            Self::InvalidInput => 21,
+            Self::WrongNetwork => 22,
+            Self::TamperedData => 23,
+            Self::Expired => 24,
+            Self::AlreadySettled => 25,
+            Self::NotFound => 26,
+            Self::Unsupported => 27,
+            Self::RpcUnavailable => 30,
+            Self::RpcRateLimited => 31,
+            Self::TransactionExpired => 32,
+            Self::TransactionFailed => 33,
+            Self::HistoryTruncated => 34,
+            Self::UnexpectedRpcData => 35,
+            Self::DeadlineExceeded => 36,
+            Self::WebhookUndeliverable => 37,
+            Self::PolicyViolation => 40,
+            Self::AnomalyDetected => 41,
+            Self::AssetPolicyViolation => 42,
+            Self::NotAuthorized => 43,
+            Self::SendRefused => 44,
+            Self::NotYetAllowed => 45,
+            Self::QuotaExceeded => 46,
+            Self::InvalidConfig => 50,
+            Self::CorruptLocalState => 51,
+            Self::DryRun => 60,
+            Self::DryRunFailed => 61,
+            Self::Internal => 1,
+        }
+    }
+
+    /// HTTP status the API server answers with when a route has no more specific one
+    pub fn http_status(&self) -> u16 {
+        match self {
+            Self::InsufficientTokenBalance | Self::InsufficientSolForFees => 422,
+            Self::InvalidRecipient | Self::InvalidInput | Self::WrongNetwork | Self::TamperedData | Self::Unsupported => 400,
+            Self::Expired | Self::TransactionFailed => 422,
+            Self::AlreadySettled | Self::HistoryTruncated | Self::NotYetAllowed | Self::DryRun | Self::DryRunFailed => 409,
+            Self::NotFound => 404,
+            Self::RpcUnavailable | Self::UnexpectedRpcData | Self::WebhookUndeliverable => 502,
+            Self::RpcRateLimited => 503,
+            Self::QuotaExceeded => 429,
+            Self::TransactionExpired | Self::DeadlineExceeded => 504,
+            Self::PolicyViolation
+            | Self::AnomalyDetected
+            | Self::AssetPolicyViolation
+            | Self::NotAuthorized
+            | Self::SendRefused => 403,
+            Self::InvalidConfig | Self::CorruptLocalState | Self::Internal => 500,
+        }
+    }
+
+    /// The catalogue entry of a code such as `E1001`
+    pub fn from_code(code: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|kind| kind.code() == code)
+    }
+
+    /// Classify `error` by the first cause in its chain that says why it failed
+    pub fn of(error: &anyhow::Error) -> Self {
+        for cause in error.chain() {
+            if let Some(kind) = typed(cause) {
+                return kind;
+            }
+        }
+        // Text first: program logs tell a token shortfall from a SOL one, the error kind does not
+        if let Some(kind) = Self::of_message(&error_text(error)) {
+            return kind;
+        }
+        error
+            .chain()
+            .find_map(|cause| cause.downcast_ref::<ClientError>().and_then(client_error))
+            .unwrap_or(Self::Internal)
+    }
+
+    /// Classify an error that only survives as text, by the known Solana and RPC messages in it
+    pub fn of_message(message: &str) -> Option<Self> {
+        let message = message.to_ascii_lowercase();
+        TEXT_RULES.iter().find(|(pattern, _)| message.contains(pattern)).map(|(_, kind)| *kind)
+    }
+}
+
+impl fmt::Display for FinternetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.code(), self.identifier())
+    }
+}
+
+/// Written as `code` and `name` fields, for flattening into error bodies
+impl Serialize for FinternetError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut fields = serializer.serialize_struct("FinternetError", 2)?;
+        fields.serialize_field("code", &self.code())?;
+        fields.serialize_field("name", self.identifier())?;
+        fields.end()
+    }
+}
+
+/// Read back from the `code` field; `name` is not checked
+impl<'de> Deserialize<'de> for FinternetError {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        struct Fields {
+            code: String,
+        }
+        let fields = Fields::deserialize(deserializer)?;
+        Self::from_code(&fields.code)
+            .ok_or_else(|| serde::de::Error::custom(format!("unknown error code {}", fields.code)))
+    }
+}
+
+/// The JSON a failing CLI command prints with `--output json`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ErrorEnvelope {
+    /// `code` (e.g. `E1001`) and `name` (e.g. `insufficient_token_balance`)
+    #[serde(flatten)]
+    pub kind: FinternetError,
+    pub exit_code: i32,
+    pub message: String,
+    pub hint: Option<String>,
+}
+
+impl ErrorEnvelope {
+    pub fn new(error: &anyhow::Error) -> Self {
+        let kind = FinternetError::of(error);
+        Self {
+            kind,
+            exit_code: kind.exit_code(),
+            message: format!("{:#}", error),
+            hint: crate::remediation::remediation(error),
+        }
+    }
+}
+
+/// Known messages, checked in order against the lowercased error text and program logs
+const TEXT_RULES: &[(&str, FinternetError)] = &[
+    ("insufficient lamports", FinternetError::InsufficientSolForFees),
+    ("no record of a prior credit", FinternetError::InsufficientSolForFees),
+    ("insufficient funds for fee", FinternetError::InsufficientSolForFees),
+    ("insufficient funds for rent", FinternetError::InsufficientSolForFees),
+    // The token program's `Error: insufficient funds` log
+    ("insufficient funds", FinternetError::InsufficientTokenBalance),
+    ("insufficient balance", FinternetError::InsufficientTokenBalance),
+    ("blockhash not found", FinternetError::TransactionExpired),
+    ("block height exceeded", FinternetError::TransactionExpired),
+    ("too many requests", FinternetError::RpcRateLimited),
+    ("error sending request", FinternetError::RpcUnavailable),
+    ("connection refused", FinternetError::RpcUnavailable),
+    ("operation timed out", FinternetError::RpcUnavailable),
+];
+
+/// An error type with a place in the catalogue
+///
+/// Every public error type of the SDK implements this, through the `catalogue!` list below, which
+/// is also the list `FinternetError::of` looks for in an error chain. A type left out of the list
+/// is classified `Internal`; the tests refuse any error type in the crate that is not on it.
+pub trait Catalogued: std::error::Error + 'static {
+    fn kind(&self) -> FinternetError;
+}
+
+macro_rules! catalogue {
+    ($($error:ty),* $(,)?) => {
+        $(impl Catalogued for $error {
+            fn kind(&self) -> FinternetError {
+                FinternetError::from(self)
+            }
+        })*
+
+        fn typed(cause: &(dyn std::error::Error + 'static)) -> Option<FinternetError> {
+            $(if let Some(error) = cause.downcast_ref::<$error>() {
+                return Some(error.kind());
+            })*
+            None
+        }
+
+        #[cfg(test)]
+        const CATALOGUED: &[&str] = &[$(stringify!($error)),*];
+    };
+}
+
+catalogue!(
+    RecipientError,
+    AmountError,
+    ValidationError,
+    MintNetworkError,
+    PolicyViolation,
+    Anomaly,
+    AssetPolicyViolation,
+    SendRefused,
+    DryRunOutcome,
+    TruncatedHistory,
+    DeadlineError,
+    ServerConfigError,
+    MemoTemplateError,
+    ActionLogError,
+    IntentError,
+    QuotaError,
+    PaymentLinkError,
+    ProposalError,
+    ColdSweepError,
+    ClaimError,
+    TimelockError,
+    OrganizationError,
+    StateBundleError,
+    AttestationError,
+    TokenAccountDecodeError,
+    TokenizationDraftError,
+    ListingError,
+    DeliveryFailure,
+    ParsePubkeyError,
+    ParseSignatureError,
+);
+
+fn client_error(error: &ClientError) -> Option<FinternetError> {
+    match error.kind() {
+        ClientErrorKind::Io(_) => Some(FinternetError::RpcUnavailable),
+        ClientErrorKind::Reqwest(e) if e.status().is_some_and(|status| status.as_u16() == 429) => {
+            Some(FinternetError::RpcRateLimited)
+        }
+        ClientErrorKind::Reqwest(_) => Some(FinternetError::RpcUnavailable),
+        ClientErrorKind::TransactionError(e) => Some(transaction_error(e)),
+        ClientErrorKind::RpcError(RpcError::RpcResponseError {
+            data: RpcResponseErrorData::SendTransactionPreflightFailure(result),
+            ..
+        }) => Some(result.err.as_ref().map_or(FinternetError::TransactionFailed, transaction_error)),
+        ClientErrorKind::RpcError(RpcError::RpcResponseError { .. }) => Some(FinternetError::RpcUnavailable),
+        ClientErrorKind::SerdeJson(_) => Some(FinternetError::UnexpectedRpcData),
+        _ => None,
+    }
+}
+
+fn transaction_error(error: &TransactionError) -> FinternetError {
+    match error {
+        TransactionError::InsufficientFundsForFee | TransactionError::InsufficientFundsForRent { .. } => {
+            FinternetError::InsufficientSolForFees
+        }
+        TransactionError::BlockhashNotFound => FinternetError::TransactionExpired,
+        TransactionError::AlreadyProcessed => FinternetError::AlreadySettled,
+        _ => FinternetError::TransactionFailed,
+    }
+}
+
+impl From<&RecipientError> for FinternetError {
+    fn from(error: &RecipientError) -> Self {
+        match error {
+            RecipientError::RecipientIsTokenAccountForDifferentMint { .. } | RecipientError::RecipientOffCurve { .. } => {
+                Self::InvalidRecipient
+            }
+        }
+    }
+}
+
+impl From<&AmountError> for FinternetError {
+    fn from(error: &AmountError) -> Self {
+        match error {
+            AmountError::Empty
+            | AmountError::Negative { .. }
+            | AmountError::InvalidCharacter { .. }
+            | AmountError::Ambiguous { .. }
+            | AmountError::Misgrouped { .. }
+            | AmountError::SymbolMismatch { .. }
+            | AmountError::TooManyDecimals { .. }
+            | AmountError::Overflow { .. } => Self::InvalidInput,
+        }
+    }
+}
+
+impl From<&ValidationError> for FinternetError {
+    fn from(error: &ValidationError) -> Self {
+        let ValidationError { violations: _ } = error;
+        Self::InvalidInput
+    }
+}
+
+impl From<&MintNetworkError> for FinternetError {
+    fn from(error: &MintNetworkError) -> Self {
+        match error {
+            MintNetworkError::MintNetworkMismatch { .. } => Self::WrongNetwork,
+        }
+    }
+}
+
+impl From<&PolicyViolation> for FinternetError {
+    fn from(error: &PolicyViolation) -> Self {
+        match error {
+            PolicyViolation::RecipientDenied { .. }
+            | PolicyViolation::RecipientNotAllowed { .. }
+            | PolicyViolation::PerTransactionLimit { .. }
+            | PolicyViolation::RollingLimit { .. }
+            | PolicyViolation::ApprovalDenied { .. } => Self::PolicyViolation,
+            PolicyViolation::RoleRequired { .. } => Self::NotAuthorized,
+            PolicyViolation::Anomaly(anomaly) => anomaly.into(),
+        }
+    }
+}
+
+impl From<&Anomaly> for FinternetError {
+    fn from(error: &Anomaly) -> Self {
+        match error {
+            Anomaly::NewRecipient { .. } | Anomaly::SendRate { .. } | Anomaly::OutflowSpike { .. } => {
+                Self::AnomalyDetected
+            }
+        }
+    }
+}
+
+impl From<&AssetPolicyViolation> for FinternetError {
+    fn from(error: &AssetPolicyViolation) -> Self {
+        match error {
+            AssetPolicyViolation::OwnerNotAllowed { .. }
+            | AssetPolicyViolation::IdentityRequired { .. }
+            | AssetPolicyViolation::MaxHolders { .. } => Self::AssetPolicyViolation,
+        }
+    }
+}
+
+impl From<&SendRefused> for FinternetError {
+    fn from(error: &SendRefused) -> Self {
+        // The reason is the middleware's own; a middleware with a catalogued reason returns that
+        // error instead
+        let SendRefused { middleware: _, reason: _, message: _ } = error;
+        Self::SendRefused
+    }
+}
+
+impl From<&DryRunOutcome> for FinternetError {
+    fn from(outcome: &DryRunOutcome) -> Self {
+        if outcome.simulation_error.is_some() {
+            Self::DryRunFailed
+        } else {
+            Self::DryRun
+        }
+    }
+}
+
+impl From<&TruncatedHistory> for FinternetError {
+    fn from(error: &TruncatedHistory) -> Self {
+        let TruncatedHistory { owner: _, slot: _, needed_for: _ } = error;
+        Self::HistoryTruncated
+    }
+}
+
+impl From<&DeadlineError> for FinternetError {
+    fn from(error: &DeadlineError) -> Self {
+        match error {
+            DeadlineError::TooLateToSend { .. } => Self::Expired,
+            DeadlineError::DeadlineExceeded { .. } => Self::DeadlineExceeded,
+        }
+    }
+}
+
+impl From<&QuotaError> for FinternetError {
+    fn from(error: &QuotaError) -> Self {
+        match error {
+            QuotaError::RequestsExhausted { .. } | QuotaError::VolumeExhausted { .. } => Self::QuotaExceeded,
+        }
+    }
+}
+
+impl From<&ServerConfigError> for FinternetError {
+    fn from(error: &ServerConfigError) -> Self {
+        let ServerConfigError { path: _, violations: _ } = error;
+        Self::InvalidConfig
+    }
+}
+
+impl From<&MemoTemplateError> for FinternetError {
+    fn from(error: &MemoTemplateError) -> Self {
+        match error {
+            MemoTemplateError::InvalidTemplate(_) => Self::InvalidConfig,
+            MemoTemplateError::MissingVariable(_) | MemoTemplateError::AmbiguousValue { .. } | MemoTemplateError::TooLong { .. } => {
+                Self::InvalidInput
+            }
+        }
+    }
+}
+
+impl From<&ActionLogError> for FinternetError {
+    fn from(error: &ActionLogError) -> Self {
+        match error {
+            ActionLogError::Malformed { .. } | ActionLogError::HashMismatch { .. } | ActionLogError::ChainBroken { .. } => {
+                Self::CorruptLocalState
+            }
+        }
+    }
+}
+
+impl From<&IntentError> for FinternetError {
+    fn from(error: &IntentError) -> Self {
+        match error {
+            IntentError::Malformed(_) => Self::InvalidInput,
+            IntentError::InvalidSignature => Self::TamperedData,
+            IntentError::Expired { .. } => Self::Expired,
+            IntentError::ReplayedNonce { .. } => Self::AlreadySettled,
+            IntentError::LimitExceeded { .. } => Self::PolicyViolation,
+        }
+    }
+}
+
+impl From<&PaymentLinkError> for FinternetError {
+    fn from(error: &PaymentLinkError) -> Self {
+        match error {
+            PaymentLinkError::Malformed(_) => Self::InvalidInput,
+            PaymentLinkError::InvalidSignature => Self::TamperedData,
+            PaymentLinkError::Expired { .. } => Self::Expired,
+            PaymentLinkError::AlreadyPaid { .. } => Self::AlreadySettled,
+            PaymentLinkError::UnsupportedCurrency => Self::Unsupported,
+        }
+    }
+}
+
+impl From<&ProposalError> for FinternetError {
+    fn from(error: &ProposalError) -> Self {
+        match error {
+            ProposalError::Tampered { .. } | ProposalError::InvalidInitiatorSignature => Self::TamperedData,
+            ProposalError::Expired { .. } => Self::Expired,
+            ProposalError::WrongApprover { .. } => Self::NotAuthorized,
+            ProposalError::NonceAdvanced { .. } => Self::AlreadySettled,
+            ProposalError::UnsupportedVersion(_) => Self::Unsupported,
+        }
+    }
+}
+
+impl From<&ColdSweepError> for FinternetError {
+    fn from(error: &ColdSweepError) -> Self {
+        match error {
+            ColdSweepError::WrongWallet { .. } => Self::NotAuthorized,
+            ColdSweepError::Tampered { .. } => Self::TamperedData,
+            // A diverged plan is as stale as an expired one: plan the sweep again
+            ColdSweepError::Diverged { .. } => Self::Expired,
+            ColdSweepError::UnsupportedVersion(_) => Self::Unsupported,
+        }
+    }
+}
+
+impl From<&ClaimError> for FinternetError {
+    fn from(error: &ClaimError) -> Self {
+        match error {
+            ClaimError::WeakSecret { .. } | ClaimError::InvalidExpiry { .. } => Self::InvalidInput,
+            ClaimError::SecretInUse { .. } | ClaimError::AlreadySettled { .. } => Self::AlreadySettled,
+            ClaimError::NotSender { .. } => Self::NotAuthorized,
+            ClaimError::NotExpired { .. } => Self::NotYetAllowed,
+        }
+    }
+}
+
+impl From<&TimelockError> for FinternetError {
+    fn from(error: &TimelockError) -> Self {
+        match error {
+            TimelockError::InvalidSchedule { .. } => Self::InvalidInput,
+            TimelockError::NotSender { .. } | TimelockError::NotRecipient { .. } => Self::NotAuthorized,
+            TimelockError::CancelWindowClosed { .. } | TimelockError::ClaimWindowClosed { .. } => Self::Expired,
+            TimelockError::StillLocked { .. } | TimelockError::NotExpired { .. } => Self::NotYetAllowed,
+            TimelockError::AlreadySettled { .. } | TimelockError::ClaimNonceAdvanced { .. } => Self::AlreadySettled,
+            TimelockError::InvalidClaim { .. } => Self::TamperedData,
+        }
+    }
+}
+
+impl From<&TokenizationDraftError> for FinternetError {
+    fn from(error: &TokenizationDraftError) -> Self {
+        match error {
+            TokenizationDraftError::Tampered { .. } => Self::TamperedData,
+            TokenizationDraftError::Expired { .. } | TokenizationDraftError::Diverged { .. } => Self::Expired,
+            TokenizationDraftError::WrongIssuer { .. } => Self::NotAuthorized,
+            TokenizationDraftError::AlreadyCommitted { .. } => Self::AlreadySettled,
+            TokenizationDraftError::UnsupportedVersion(_) => Self::Unsupported,
+        }
+    }
+}
+
+impl From<&OrganizationError> for FinternetError {
+    fn from(error: &OrganizationError) -> Self {
+        match error {
+            OrganizationError::InvalidName { .. } => Self::InvalidInput,
+            OrganizationError::NotFound { .. } => Self::NotFound,
+            OrganizationError::NotAdmin { .. } | OrganizationError::NotMember { .. } => Self::NotAuthorized,
+            OrganizationError::LastAdmin { .. } => Self::PolicyViolation,
+        }
+    }
+}
+
+impl From<&StateBundleError> for FinternetError {
+    fn from(error: &StateBundleError) -> Self {
+        match error {
+            StateBundleError::SecretDetected { .. } | StateBundleError::UnsafePath { .. } | StateBundleError::InvalidFile { .. } => {
+                Self::InvalidInput
+            }
+            StateBundleError::UnsupportedVersion { .. } => Self::Unsupported,
+            StateBundleError::ManifestMismatch { .. } | StateBundleError::ChecksumMismatch { .. } => Self::TamperedData,
+        }
+    }
+}
+
+impl From<&AttestationError> for FinternetError {
+    fn from(error: &AttestationError) -> Self {
+        match error {
+            AttestationError::WrongWallet { .. } => Self::NotAuthorized,
+            AttestationError::NotOnLedger { .. } => Self::NotFound,
+        }
+    }
+}
+
+impl From<&ParsePubkeyError> for FinternetError {
+    fn from(_: &ParsePubkeyError) -> Self {
+        Self::InvalidInput
+    }
+}
+
+impl From<&ParseSignatureError> for FinternetError {
+    fn from(_: &ParseSignatureError) -> Self {
+        Self::InvalidInput
+    }
+}
+
+impl From<&TokenAccountDecodeError> for FinternetError {
+    fn from(error: &TokenAccountDecodeError) -> Self {
+        match error {
+            TokenAccountDecodeError::InvalidAddress { .. }
+            | TokenAccountDecodeError::InvalidEncoding { .. }
+            | TokenAccountDecodeError::UnsupportedEncoding { .. }
+            | TokenAccountDecodeError::InvalidData { .. }
+            | TokenAccountDecodeError::NotTokenAccount { .. }
+            | TokenAccountDecodeError::MissingField { .. }
+            | TokenAccountDecodeError::InvalidField { .. } => Self::UnexpectedRpcData,
+        }
+    }
+}
+
+impl From<&ListingError> for FinternetError {
+    fn from(error: &ListingError) -> Self {
+        match error {
+            ListingError::NotFound { .. } => Self::NotFound,
+            // A listing that moved on since the buyer saw it is as stale as an expired one
+            ListingError::Expired { .. }
+            | ListingError::Superseded { .. }
+            | ListingError::PriceChanged { .. }
+            | ListingError::SellerNoLongerHolds { .. }
+            | ListingError::NotApproved { .. } => Self::Expired,
+            ListingError::Cancelled | ListingError::AlreadyFulfilled { .. } => Self::AlreadySettled,
+            ListingError::NotSeller { .. } | ListingError::NotSettlementAuthority { .. } => Self::NotAuthorized,
+        }
+    }
+}
+
+impl From<&DeliveryFailure> for FinternetError {
+    fn from(error: &DeliveryFailure) -> Self {
+        let DeliveryFailure { attempts: _, last_error: _ } = error;
+        Self::WebhookUndeliverable
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::validation::Violation;
+    use anyhow::anyhow;
+    use solana_sdk::{pubkey::Pubkey, signature::Signature};
+
+    /// The released catalogue; a row here never changes, so a failure means a code moved
+    const CATALOGUE: &[(FinternetError, &str, &str, i32, u16)] = &[
+        (FinternetError::InsufficientTokenBalance, "E1001", "insufficient_token_balance", 10, 422),
+        (FinternetError::InsufficientSolForFees, "E1002", "insufficient_sol_for_fees", 11, 422),
+        (FinternetError::InvalidRecipient, "E2001", "invalid_recipient", 20, 400),
+        (FinternetError::InvalidInput, "E2002", "invalid_input", 21, 400),
+        (FinternetError::WrongNetwork, "E2003", "wrong_network", 22, 400),
+        (FinternetError::TamperedData, "E2004", "tampered_data", 23, 400),
+        (FinternetError::Expired, "E2005", "expired", 24, 422),
+        (FinternetError::AlreadySettled, "E2006", "already_settled", 25, 409),
+        (FinternetError::NotFound, "E2007", "not_found", 26, 404),
+        (FinternetError::Unsupported, "E2008", "unsupported", 27, 400),
+        (FinternetError::RpcUnavailable, "E3001", "rpc_unavailable", 30, 502),
+        (FinternetError::RpcRateLimited, "E3002", "rpc_rate_limited", 31, 503),
+        (FinternetError::TransactionExpired, "E3003", "transaction_expired", 32, 504),
+        (FinternetError::TransactionFailed, "E3004", "transaction_failed", 33, 422),
+        (FinternetError::HistoryTruncated, "E3005", "history_truncated", 34, 409),
+        (FinternetError::UnexpectedRpcData, "E3006", "unexpected_rpc_data", 35, 502),
+        (FinternetError::DeadlineExceeded, "E3007", "deadline_exceeded", 36, 504),
+        (FinternetError::WebhookUndeliverable, "E3008", "webhook_undeliverable", 37, 502),
+        (FinternetError::PolicyViolation, "E4001", "policy_violation", 40, 403),
+        (FinternetError::AnomalyDetected, "E4002", "anomaly_detected", 41, 403),
+        (FinternetError::AssetPolicyViolation, "E4003", "asset_policy_violation", 42, 403),
+        (FinternetError::NotAuthorized, "E4004", "not_authorized", 43, 403),
+        (FinternetError::SendRefused, "E4005", "send_refused", 44, 403),
+        (FinternetError::NotYetAllowed, "E4006", "not_yet_allowed", 45, 409),
+        (FinternetError::QuotaExceeded, "E4007", "quota_exceeded", 46, 429),
+        (FinternetError::InvalidConfig, "E5001", "invalid_config", 50, 500),
+        (FinternetError::CorruptLocalState, "E5002", "corrupt_local_state", 51, 500),
+        (FinternetError::DryRun, "E6001", "dry_run", 60, 409),
+        (FinternetError::DryRunFailed, "E6002", "dry_run_failed", 61, 409),
+        (FinternetError::Internal, "E9999", "internal", 1, 500),
+    ];
+
+    #[test]
+    fn catalogue_is_pinned() {
+        assert_eq!(CATALOGUE.len(), FinternetError::ALL.len());
+        for (kind, (expected, code, identifier, exit_code, http_status)) in FinternetError::ALL.iter().zip(CATALOGUE) {
+            assert_eq!(kind, expected);
+            assert_eq!(kind.code(), *code);
+            assert_eq!(kind.identifier(), *identifier);
+            assert_eq!(kind.exit_code(), *exit_code, "{}", code);
+            assert_eq!(kind.http_status(), *http_status, "{}", code);
+            assert_eq!(FinternetError::from_code(code), Some(*kind));
+        }
+    }
+
+    #[test]
+    fn codes_and_exit_codes_are_unique_and_grouped() {
+        for (i, kind) in FinternetError::ALL.iter().enumerate() {
+            for other in &FinternetError::ALL[i + 1..] {
+                assert_ne!(kind.number(), other.number());
+                assert_ne!(kind.identifier(), other.identifier());
+                assert_ne!(kind.exit_code(), other.exit_code());
+            }
+            if *kind != FinternetError::Internal {
+                // The exit code's tens digit is the code's thousands digit
+                assert_eq!(kind.exit_code() / 10, (kind.number() / 1000) as i32, "{}", kind);
+            }
+            // 2 is clap's usage error
+            assert_ne!(kind.exit_code(), 2);
+        }
+    }
+
+    #[test]
+    fn every_error_type_in_the_crate_is_catalogued() {
+        let src = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("src");
+        let mut missing = Vec::new();
+        for entry in std::fs::read_dir(&src).unwrap() {
+            let path = entry.unwrap().path();
+            let text = std::fs::read_to_string(&path).unwrap();
+            for line in text.lines() {
+                let Some(rest) = line.trim().strip_prefix("impl std::error::Error for ") else {
+                    continue;
+                };
+                let name = rest.trim_end_matches(" {}").trim_end_matches(" {").trim();
+                if !CATALOGUED.contains(&name) {
+                    missing.push(format!("{} ({})", name, path.display()));
+                }
+            }
+        }
+        assert!(missing.is_empty(), "error types missing from catalogue!: {:?}", missing);
+    }
+
+    #[test]
+    fn typed_errors_are_found_behind_context() {
+        let error = anyhow::Error::from(ListingError::Cancelled)
+            .context("buying listing 42")
+            .context("marketplace request failed");
+        assert_eq!(FinternetError::of(&error), FinternetError::AlreadySettled);
+    }
+
+    #[test]
+    fn listing_errors_map_per_variant() {
+        let signature = Signature::default();
+        let cases = [
+            (ListingError::NotFound { id: "l1".to_string() }, FinternetError::NotFound),
+            (ListingError::Expired { expires_at: 1 }, FinternetError::Expired),
+            (ListingError::Cancelled, FinternetError::AlreadySettled),
+            (ListingError::Superseded { by: "l2".to_string() }, FinternetError::Expired),
+            (ListingError::AlreadyFulfilled { settlement: signature }, FinternetError::AlreadySettled),
+            (ListingError::NotSeller { seller: Pubkey::default() }, FinternetError::NotAuthorized),
+            (ListingError::SellerNoLongerHolds { held: 0, listed: 1 }, FinternetError::Expired),
+            (ListingError::NotApproved { delegated: 0, listed: 1 }, FinternetError::Expired),
+            (ListingError::NotSettlementAuthority { authority: Pubkey::default() }, FinternetError::NotAuthorized),
+            (ListingError::PriceChanged { listed: 2, expected: 1 }, FinternetError::Expired),
+        ];
+        for (error, kind) in cases {
+            assert_eq!(FinternetError::of(&error.clone().into()), kind, "{:?}", error);
+        }
+    }
+
+    #[test]
+    fn structs_without_variants_keep_their_own_code() {
+        let validation = ValidationError {
+            violations: vec![Violation { field: "amount".to_string(), message: "must be above 0".to_string() }],
+        };
+        assert_eq!(FinternetError::of(&validation.into()), FinternetError::InvalidInput);
+        let refused = SendRefused::new("screening", "sanctioned_recipient", "on a sanctions list");
+        assert_eq!(FinternetError::of(&refused.into()), FinternetError::SendRefused);
+        let undelivered = DeliveryFailure { attempts: 3, last_error: "HTTP 500".to_string() };
+        assert_eq!(FinternetError::of(&undelivered.into()), FinternetError::WebhookUndeliverable);
+        let truncated = TruncatedHistory { owner: Pubkey::default(), slot: 7, needed_for: "opening balance".to_string() };
+        assert_eq!(FinternetError::of(&truncated.into()), FinternetError::HistoryTruncated);
+    }
+
+    #[test]
+    fn rpc_failures_are_classified_by_kind() {
+        let expired = ClientError::from(TransactionError::BlockhashNotFound);
+        assert_eq!(FinternetError::of(&expired.into()), FinternetError::TransactionExpired);
+        let fee = ClientError::from(TransactionError::InsufficientFundsForFee);
+        assert_eq!(FinternetError::of(&fee.into()), FinternetError::InsufficientSolForFees);
+        let io = ClientError::from(std::io::Error::other("reset"));
+        assert_eq!(FinternetError::of(&io.into()), FinternetError::RpcUnavailable);
+        let node = ClientError::from(ClientErrorKind::RpcError(RpcError::RpcResponseError {
+            code: -32005,
+            message: "Node is unhealthy".to_string(),
+            data: RpcResponseErrorData::Empty,
+        }));
+        assert_eq!(FinternetError::of(&node.into()), FinternetError::RpcUnavailable);
+    }
+
+    #[test]
+    fn known_messages_are_classified_by_text() {
+        let cases = [
+            ("Transfer: insufficient lamports 10, need 20", FinternetError::InsufficientSolForFees),
+            ("Program log: Error: insufficient funds", FinternetError::InsufficientTokenBalance),
+            ("Blockhash not found", FinternetError::TransactionExpired),
+            ("HTTP status client error (429 Too Many Requests)", FinternetError::RpcRateLimited),
+            ("something else entirely", FinternetError::Internal),
+        ];
+        for (message, kind) in cases {
+            assert_eq!(FinternetError::of(&anyhow!(message.to_string())), kind, "{}", message);
+        }
+    }
+
+    #[test]
+    fn kinds_serialize_as_code_and_name() {
+        let json = serde_json::to_value(FinternetError::DeadlineExceeded).unwrap();
+        assert_eq!(json, serde_json::json!({ "code": "E3007", "name": "deadline_exceeded" }));
+        let back: FinternetError = serde_json::from_value(json).unwrap();
+        assert_eq!(back, FinternetError::DeadlineExceeded);
+        assert!(serde_json::from_value::<FinternetError>(serde_json::json!({ "code": "E0000" })).is_err());
+    }
+}
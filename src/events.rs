@@ -0,0 +1,343 @@
+//! Observers for what the client does, for compliance logging and monitoring
+//!
+//! `on_event` registers a callback that runs synchronously, in registration order, on the thread
+//! of the operation that raised the event. A callback that panics is caught, logged, and dropped;
+//! the operation carries on. Subscribers that do I/O should take `subscribe_events` instead,
+//! which hands events to an async channel. The action log is itself a subscriber, fed by
+//! `ActionCompleted`.
+
+use crate::action_log::{ActionLog, PolicyDecision};
+use crate::anomaly::Anomaly;
+use crate::asset_policy::AssetPolicyViolation;
+use crate::fees::PriorityFee;
+use crate::policy::PolicyViolation;
+use crate::FinternetClient;
+use serde::Serialize;
+use solana_sdk::{pubkey::Pubkey, signature::Signature};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use tokio::sync::mpsc;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum SdkEvent {
+    /// A payment transaction was signed and is about to be sent
+    PaymentSubmitted { operation: String, signature: Signature },
+    /// The payment reached the commitment it was waited on with
+    PaymentConfirmed { operation: String, signature: Signature },
+    /// Sending or confirming failed; an expired payment is then re-signed and submitted again
+    PaymentFailed { operation: String, signature: Signature, error: String },
+    /// A send was given a compute unit price and signed again; `signature` is the new one
+    PriorityFeeSet { operation: String, signature: Signature, fee: PriorityFee },
+    /// Dry-run mode simulated a send instead of broadcasting it
+    DryRun {
+        operation: String,
+        /// `dry-run:<signature>`; the signature was never broadcast
+        marker: String,
+        simulation_error: Option<String>,
+    },
+    TokenizationCompleted { mint: Pubkey, signature: Signature },
+    IdentityRegistered { pubkey: Pubkey, signature: Signature },
+    /// A payment tripped an anomaly rule; `enforced` means it was refused
+    AnomalyDetected { anomaly: Anomaly, enforced: bool },
+    /// A spending or asset policy refused an action
+    PolicyViolation {
+        operation: String,
+        /// `spending_policy` or `asset_policy`
+        policy: String,
+        code: String,
+        message: String,
+    },
+    /// A stale-while-revalidate read finished reading a wallet again; its cache entry is current
+    BalanceRefreshed {
+        wallet: Pubkey,
+        sol_balance: u64,
+        /// Unix time of the read
+        as_of: u64,
+    },
+    /// `reselect_rpc` moved the client to another endpoint; URLs have credentials stripped
+    RpcFailover { from: String, to: String },
+    /// Outcome of a public client method, with what the action log records about it
+    ActionCompleted {
+        /// SDK method, e.g. `send_payment`
+        operation: String,
+        parameters: serde_json::Value,
+        signature: Option<String>,
+        error: Option<String>,
+        /// Catalogue code of the error, e.g. `E1001`
+        error_code: Option<String>,
+        policy_decisions: Vec<PolicyDecision>,
+    },
+}
+
+/// Receives every `SdkEvent`; must return quickly, as it runs on the operation's thread
+pub type EventCallback = Arc<dyn Fn(SdkEvent) + Send + Sync>;
+
+#[derive(Clone)]
+enum Subscriber {
+    Callback(EventCallback),
+    Channel(mpsc::UnboundedSender<SdkEvent>),
+}
+
+/// Every subscriber registered on one client, in registration order, by id
+#[derive(Default)]
+pub(crate) struct EventBus {
+    next_id: AtomicU64,
+    subscribers: RwLock<Vec<(u64, Subscriber)>>,
+}
+
+impl EventBus {
+    fn push(&self, subscriber: Subscriber) {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        match self.subscribers.write() {
+            Ok(mut subscribers) => subscribers.push((id, subscriber)),
+            Err(_) => tracing::error!("Event subscriber lock poisoned; subscriber not registered"),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.subscribers.read().map(|subscribers| subscribers.is_empty()).unwrap_or(true)
+    }
+
+    /// Deliver `event` to each subscriber, dropping those that panicked or stopped listening
+    fn publish(&self, event: SdkEvent) {
+        // Called without the lock held, so a callback may itself subscribe or raise events
+        let Ok(subscribers) = self.subscribers.read().map(|subscribers| subscribers.clone()) else {
+            tracing::error!("Event subscriber lock poisoned; {:?} not delivered", event);
+            return;
+        };
+        let mut gone = Vec::new();
+        for (id, subscriber) in subscribers {
+            let delivered = match subscriber {
+                Subscriber::Callback(callback) => {
+                    let event = event.clone();
+                    let delivered = catch_unwind(AssertUnwindSafe(|| callback(event))).is_ok();
+                    if !delivered {
+                        tracing::warn!("Event subscriber panicked; it will receive no further events");
+                    }
+                    delivered
+                }
+                Subscriber::Channel(sender) => sender.send(event.clone()).is_ok(),
+            };
+            if !delivered {
+                gone.push(id);
+            }
+        }
+        if gone.is_empty() {
+            return;
+        }
+        if let Ok(mut subscribers) = self.subscribers.write() {
+            subscribers.retain(|(id, _)| !gone.contains(id));
+        }
+    }
+}
+
+impl FinternetClient {
+    /// Call `callback` with every event the client raises from now on
+    pub fn on_event(&self, callback: impl Fn(SdkEvent) + Send + Sync + 'static) {
+        self.events.push(Subscriber::Callback(Arc::new(callback)));
+    }
+
+    /// Every event the client raises from now on, for subscribers that do I/O
+    ///
+    /// The channel is unbounded so sends never wait on a slow reader; dropping the receiver
+    /// unsubscribes.
+    pub fn subscribe_events(&self) -> mpsc::UnboundedReceiver<SdkEvent> {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        self.events.push(Subscriber::Channel(sender));
+        receiver
+    }
+
+    /// Hand an event to every subscriber, building it only if there are any
+    pub(crate) fn emit(&self, event: impl FnOnce() -> SdkEvent) {
+        if !self.events.is_empty() {
+            self.events.publish(event());
+        }
+    }
+
+    /// Raise `PolicyViolation` when `error` is a policy refusal
+    pub(crate) fn emit_policy_violation(&self, operation: &str, error: &anyhow::Error) {
+        let (policy, code, message) =
+            match (error.downcast_ref::<PolicyViolation>(), error.downcast_ref::<AssetPolicyViolation>()) {
+                (Some(v), _) => ("spending_policy", v.code(), v.to_string()),
+                (None, Some(v)) => ("asset_policy", v.code(), v.to_string()),
+                (None, None) => return,
+            };
+        self.emit(|| SdkEvent::PolicyViolation {
+            operation: operation.to_string(),
+            policy: policy.to_string(),
+            code: code.to_string(),
+            message,
+        });
+    }
+}
+
+/// The subscriber that writes `ActionCompleted` events to the action log
+///
+/// A log that cannot be written is reported but does not fail the action, which has already
+/// happened.
+pub(crate) fn action_log_subscriber(log: Arc<ActionLog>) -> impl Fn(SdkEvent) + Send + Sync + 'static {
+    move |event| {
+        let SdkEvent::ActionCompleted { operation, parameters, signature, error, error_code, policy_decisions } = event
+        else {
+            return;
+        };
+        if let Err(e) = log.append(&operation, parameters, signature, error, error_code, policy_decisions) {
+            tracing::error!("Action {} was not recorded: {}", operation, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_cluster::{Landing, MockCluster};
+    use crate::policy::SpendingPolicy;
+    use solana_sdk::{instruction::InstructionError, signature::Keypair, signer::Signer, transaction::TransactionError};
+    use std::sync::Mutex;
+
+    type Seen = Arc<Mutex<Vec<SdkEvent>>>;
+
+    fn funded(cluster: &MockCluster) -> (Keypair, Pubkey) {
+        let payer = Keypair::new();
+        let mint = cluster.add_mint(&spl_token::id(), 6, &Pubkey::new_unique());
+        cluster.set_balance(payer.pubkey(), 1_000_000_000);
+        cluster.add_token_account(&payer.pubkey(), &mint, 100_000_000);
+        (payer, mint)
+    }
+
+    /// Every event `client` raises from now on, as a callback subscriber sees them
+    fn record(client: &FinternetClient) -> Seen {
+        let seen = Seen::default();
+        let sink = Arc::clone(&seen);
+        client.on_event(move |event| sink.lock().unwrap().push(event));
+        seen
+    }
+
+    /// The `event` tag of each event, e.g. `payment_submitted`
+    fn kinds(events: &[SdkEvent]) -> Vec<String> {
+        events
+            .iter()
+            .map(|event| serde_json::to_value(event).unwrap()["event"].as_str().unwrap().to_string())
+            .collect()
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn a_successful_payment_is_submitted_confirmed_then_logged() {
+        let cluster = MockCluster::new();
+        let client = cluster.default_client();
+        let (payer, mint) = funded(&cluster);
+        let seen = record(&client);
+
+        let signature = client.send_payment(&payer, &Keypair::new().pubkey(), 5_000, &mint, Some("INV-7")).await.unwrap();
+
+        let events = seen.lock().unwrap().clone();
+        assert_eq!(kinds(&events), ["payment_submitted", "payment_confirmed", "action_completed"]);
+        let operation = "send_payment".to_string();
+        assert_eq!(events[0], SdkEvent::PaymentSubmitted { operation: operation.clone(), signature });
+        assert_eq!(events[1], SdkEvent::PaymentConfirmed { operation, signature });
+        let SdkEvent::ActionCompleted { operation, parameters, signature: logged, error, .. } = &events[2] else {
+            unreachable!()
+        };
+        assert_eq!(operation, "send_payment");
+        assert_eq!(parameters["memo"], "INV-7");
+        assert_eq!(logged.as_deref(), Some(signature.to_string().as_str()));
+        assert_eq!(*error, None);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn a_failed_payment_is_submitted_failed_then_logged_with_its_error() {
+        let cluster = MockCluster::new();
+        let client = cluster.default_client();
+        let (payer, mint) = funded(&cluster);
+        let seen = record(&client);
+        cluster.set_landing(Landing::Failed(TransactionError::InstructionError(0, InstructionError::InsufficientFunds)));
+
+        let failure = client.send_payment(&payer, &Keypair::new().pubkey(), 5_000, &mint, None).await.unwrap_err();
+
+        let events = seen.lock().unwrap().clone();
+        assert_eq!(kinds(&events), ["payment_submitted", "payment_failed", "action_completed"]);
+        let signature = cluster.sent()[0].signatures[0];
+        assert_eq!(events[0], SdkEvent::PaymentSubmitted { operation: "send_payment".to_string(), signature });
+        assert_eq!(
+            events[1],
+            SdkEvent::PaymentFailed { operation: "send_payment".to_string(), signature, error: failure.to_string() }
+        );
+        let SdkEvent::ActionCompleted { signature, error, error_code, .. } = &events[2] else { unreachable!() };
+        assert_eq!(*signature, None);
+        assert_eq!(error.as_deref(), Some(failure.to_string().as_str()));
+        assert_eq!(error_code.as_deref(), Some("E1001"));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn a_payment_the_policy_refuses_raises_the_violation_and_submits_nothing() {
+        let cluster = MockCluster::new();
+        let (payer, mint) = funded(&cluster);
+        let client = cluster
+            .default_client()
+            .with_spending_policy(SpendingPolicy::new().with_max_per_transaction(mint, 1_000))
+            .unwrap();
+        let seen = record(&client);
+
+        client.send_payment(&payer, &Keypair::new().pubkey(), 5_000, &mint, None).await.unwrap_err();
+
+        let events = seen.lock().unwrap().clone();
+        assert_eq!(kinds(&events), ["policy_violation", "action_completed"]);
+        let SdkEvent::PolicyViolation { operation, policy, .. } = &events[0] else { unreachable!() };
+        assert_eq!((operation.as_str(), policy.as_str()), ("send_payment", "spending_policy"));
+        assert!(cluster.sent().is_empty());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn subscribers_run_in_order_and_a_panicking_one_is_dropped_without_failing_the_send() {
+        let cluster = MockCluster::new();
+        let client = cluster.default_client();
+        let (payer, mint) = funded(&cluster);
+        let order = Arc::new(Mutex::new(Vec::new()));
+        let calls = Arc::new(AtomicU64::new(0));
+        let first = Arc::clone(&order);
+        client.on_event(move |_| first.lock().unwrap().push("first"));
+        let panicking = Arc::clone(&calls);
+        client.on_event(move |_| {
+            panicking.fetch_add(1, Ordering::Relaxed);
+            panic!("subscriber bug");
+        });
+        let last = Arc::clone(&order);
+        client.on_event(move |_| last.lock().unwrap().push("last"));
+        let mut channel = client.subscribe_events();
+
+        client.send_payment(&payer, &Keypair::new().pubkey(), 5_000, &mint, None).await.unwrap();
+
+        // The panicking subscriber saw only the first event; the others saw all three, in order
+        assert_eq!(calls.load(Ordering::Relaxed), 1);
+        assert_eq!(*order.lock().unwrap(), ["first", "last", "first", "last", "first", "last"]);
+        let mut received = Vec::new();
+        while let Ok(event) = channel.try_recv() {
+            received.push(event);
+        }
+        assert_eq!(kinds(&received), ["payment_submitted", "payment_confirmed", "action_completed"]);
+    }
+
+    #[test]
+    fn a_dropped_channel_unsubscribes_and_events_are_only_built_when_someone_listens() {
+        let cluster = MockCluster::new();
+        let client = cluster.default_client();
+        let built = AtomicU64::new(0);
+        let event = || {
+            built.fetch_add(1, Ordering::Relaxed);
+            SdkEvent::RpcFailover { from: "a".to_string(), to: "b".to_string() }
+        };
+        client.emit(event);
+        assert_eq!(built.load(Ordering::Relaxed), 0);
+
+        let channel = client.subscribe_events();
+        client.emit(event);
+        assert_eq!(built.load(Ordering::Relaxed), 1);
+        drop(channel);
+        client.emit(event);
+        assert!(client.events.is_empty());
+        client.emit(event);
+        assert_eq!(built.load(Ordering::Relaxed), 2);
+    }
+}
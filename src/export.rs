@@ -0,0 +1,443 @@
+//! Transaction history as OFX or QIF, for importing into accounting software
+//!
+//! Each currency the records move becomes its own statement (an OFX `STMTRS`, a QIF
+//! `!Account` block), with amounts signed from the owner's side: positive for money in, negative
+//! for money out. Network fees the owner paid go on the SOL statement as separate lines, once per
+//! transaction. Counterparties are named from the identity index when it knows them, and memos
+//! the owner redacted read as `REDACTED`.
+
+use crate::identity_index::IdentityIndex;
+use crate::redaction::Redactions;
+use crate::{Currency, TransactionRecord};
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, TimeZone, Utc};
+use solana_sdk::{pubkey::Pubkey, signature::Signature};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use std::fmt::Write as _;
+use std::str::FromStr;
+
+/// OFX allows at most this many characters in `ACCTID`
+const OFX_ACCOUNT_ID_LEN: usize = 22;
+/// OFX allows at most this many characters in a transaction's `NAME`
+const OFX_NAME_LEN: usize = 32;
+/// OFX allows at most this many characters in a transaction's `MEMO`
+const OFX_MEMO_LEN: usize = 255;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// OFX 1.0.2 bank statement, as read by QuickBooks, Xero, and GnuCash
+    Ofx,
+    /// Quicken Interchange Format, for tools that do not read OFX
+    Qif,
+}
+
+impl ExportFormat {
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            Self::Ofx => "application/x-ofx",
+            Self::Qif => "application/qif",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Ofx => "ofx",
+            Self::Qif => "qif",
+        }
+    }
+}
+
+impl FromStr for ExportFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "ofx" => Ok(Self::Ofx),
+            "qif" => Ok(Self::Qif),
+            other => Err(anyhow!("Unknown export format '{}'; expected ofx or qif", other)),
+        }
+    }
+}
+
+impl fmt::Display for ExportFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.extension())
+    }
+}
+
+/// One line of an exported statement
+struct ExportLine {
+    id: String,
+    timestamp: u64,
+    /// Positive for money in, negative for money out, in base units
+    amount: i128,
+    payee: String,
+    memo: Option<String>,
+    is_fee: bool,
+}
+
+/// One currency's lines, oldest first
+struct ExportStatement {
+    currency: Currency,
+    lines: Vec<ExportLine>,
+}
+
+impl ExportStatement {
+    fn net(&self) -> i128 {
+        self.lines.iter().map(|line| line.amount).sum()
+    }
+}
+
+/// Render `owner`'s `records` in `format`
+///
+/// `generated_at` is stamped as the server time of an OFX file, so the same records and time give
+/// the same bytes.
+pub fn export_history(
+    format: ExportFormat,
+    owner: &Pubkey,
+    records: &[TransactionRecord],
+    names: Option<&IdentityIndex>,
+    redactions: &Redactions,
+    generated_at: u64,
+) -> String {
+    let mut records = records.to_vec();
+    redactions.apply_to_records(&mut records);
+    let statements = group_statements(owner, &records, names);
+    match format {
+        ExportFormat::Ofx => to_ofx(owner, &statements, generated_at),
+        ExportFormat::Qif => to_qif(owner, &statements),
+    }
+}
+
+fn group_statements(owner: &Pubkey, records: &[TransactionRecord], names: Option<&IdentityIndex>) -> Vec<ExportStatement> {
+    let mut records: Vec<&TransactionRecord> = records.iter().collect();
+    // Legs of one transaction share a time and slot; ordering them too keeps their FITIDs stable
+    records.sort_by_key(|record| (record.timestamp, record.slot, record.signature, record.from, record.to, record.amount));
+
+    let name_for = |address: &Pubkey| {
+        if *address == Pubkey::default() {
+            return "mint/burn".to_string();
+        }
+        match names.and_then(|names| names.display_name(address)) {
+            Some(name) => name.to_string(),
+            None => address.to_string(),
+        }
+    };
+
+    // SOL sorts first, then mints by address, so statement order is stable
+    let mut statements: BTreeMap<Option<Pubkey>, ExportStatement> = BTreeMap::new();
+    let mut fee_counted: HashSet<Signature> = HashSet::new();
+    let mut legs: HashMap<Signature, usize> = HashMap::new();
+    for record in &records {
+        let incoming = record.to == *owner;
+        let counterparty = if incoming { record.from } else { record.to };
+        // A split payment has several records under one signature; the other legs get a suffix
+        let leg = legs.entry(record.signature).or_insert(0);
+        let id = match *leg {
+            0 => record.signature.to_string(),
+            n => format!("{}-{}", record.signature, n),
+        };
+        *leg += 1;
+        let statement = statements
+            .entry(record.currency.mint())
+            .or_insert_with(|| ExportStatement { currency: record.currency.clone(), lines: Vec::new() });
+        statement.lines.push(ExportLine {
+            id,
+            timestamp: record.timestamp,
+            amount: if incoming { record.amount as i128 } else { -(record.amount as i128) },
+            payee: name_for(&counterparty),
+            memo: record.memo.clone(),
+            is_fee: false,
+        });
+
+        if record.from == *owner && record.fee > 0 && fee_counted.insert(record.signature) {
+            statements
+                .entry(None)
+                .or_insert_with(|| ExportStatement { currency: Currency::Sol, lines: Vec::new() })
+                .lines
+                .push(ExportLine {
+                    id: format!("{}-fee", record.signature),
+                    timestamp: record.timestamp,
+                    amount: -(record.fee as i128),
+                    payee: "Solana network fee".to_string(),
+                    memo: Some(format!("Fee for {}", record.signature)),
+                    is_fee: true,
+                });
+        }
+    }
+    statements.into_values().collect()
+}
+
+/// Statement account name: the owner's first characters and the currency, unique per currency
+fn account_id(owner: &Pubkey, currency: &Currency) -> String {
+    let code = match currency {
+        Currency::Sol => "SOL".to_string(),
+        Currency::Spl { symbol: Some(symbol), .. } => symbol.to_ascii_uppercase(),
+        Currency::Spl { mint, .. } => mint.to_string().chars().take(8).collect(),
+    };
+    let owner: String = owner.to_string().chars().take(8).collect();
+    format!("{}-{}", owner, code).chars().take(OFX_ACCOUNT_ID_LEN).collect()
+}
+
+fn utc(timestamp: u64) -> DateTime<Utc> {
+    Utc.timestamp_opt(timestamp as i64, 0).single().unwrap_or_default()
+}
+
+fn ofx_date(timestamp: u64) -> String {
+    utc(timestamp).format("%Y%m%d%H%M%S[0:GMT]").to_string()
+}
+
+/// SGML-safe, ASCII-only text of at most `limit` characters
+fn ofx_text(text: &str, limit: usize) -> String {
+    text.chars()
+        .map(|c| if c.is_ascii() && !c.is_ascii_control() { c } else { '?' })
+        .take(limit)
+        .collect::<String>()
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+fn to_ofx(owner: &Pubkey, statements: &[ExportStatement], generated_at: u64) -> String {
+    let mut out = String::new();
+    for header in [
+        "OFXHEADER:100",
+        "DATA:OFXSGML",
+        "VERSION:102",
+        "SECURITY:NONE",
+        "ENCODING:USASCII",
+        "CHARSET:1252",
+        "COMPRESSION:NONE",
+        "OLDFILEUID:NONE",
+        "NEWFILEUID:NONE",
+    ] {
+        let _ = writeln!(out, "{}", header);
+    }
+    let _ = writeln!(out);
+    let _ = writeln!(out, "<OFX>");
+    let _ = writeln!(out, "<SIGNONMSGSRSV1>");
+    let _ = writeln!(out, "<SONRS>");
+    let _ = writeln!(out, "<STATUS><CODE>0<SEVERITY>INFO</STATUS>");
+    let _ = writeln!(out, "<DTSERVER>{}", ofx_date(generated_at));
+    let _ = writeln!(out, "<LANGUAGE>ENG");
+    let _ = writeln!(out, "</SONRS>");
+    let _ = writeln!(out, "</SIGNONMSGSRSV1>");
+    let _ = writeln!(out, "<BANKMSGSRSV1>");
+    for (number, statement) in statements.iter().enumerate() {
+        let currency = &statement.currency;
+        let start = statement.lines.first().map_or(generated_at, |line| line.timestamp);
+        let end = statement.lines.last().map_or(generated_at, |line| line.timestamp);
+        let _ = writeln!(out, "<STMTTRNRS>");
+        let _ = writeln!(out, "<TRNUID>{}", number + 1);
+        let _ = writeln!(out, "<STATUS><CODE>0<SEVERITY>INFO</STATUS>");
+        let _ = writeln!(out, "<STMTRS>");
+        // OFX only takes ISO 4217 codes; the account ID carries the actual currency
+        let _ = writeln!(out, "<CURDEF>USD");
+        let _ = writeln!(out, "<BANKACCTFROM>");
+        let _ = writeln!(out, "<BANKID>SOLANA");
+        let _ = writeln!(out, "<ACCTID>{}", ofx_text(&account_id(owner, currency), OFX_ACCOUNT_ID_LEN));
+        let _ = writeln!(out, "<ACCTTYPE>CHECKING");
+        let _ = writeln!(out, "</BANKACCTFROM>");
+        let _ = writeln!(out, "<BANKTRANLIST>");
+        let _ = writeln!(out, "<DTSTART>{}", ofx_date(start));
+        let _ = writeln!(out, "<DTEND>{}", ofx_date(end));
+        for line in &statement.lines {
+            let kind = match (line.is_fee, line.amount >= 0) {
+                (true, _) => "FEE",
+                (false, true) => "CREDIT",
+                (false, false) => "DEBIT",
+            };
+            let _ = writeln!(out, "<STMTTRN>");
+            let _ = writeln!(out, "<TRNTYPE>{}", kind);
+            let _ = writeln!(out, "<DTPOSTED>{}", ofx_date(line.timestamp));
+            let _ = writeln!(out, "<TRNAMT>{}", currency.format_units(line.amount));
+            let _ = writeln!(out, "<FITID>{}", line.id);
+            let _ = writeln!(out, "<NAME>{}", ofx_text(&line.payee, OFX_NAME_LEN));
+            if let Some(memo) = line.memo.as_deref().filter(|memo| !memo.is_empty()) {
+                let _ = writeln!(out, "<MEMO>{}", ofx_text(memo, OFX_MEMO_LEN));
+            }
+            let _ = writeln!(out, "</STMTTRN>");
+        }
+        let _ = writeln!(out, "</BANKTRANLIST>");
+        // Records carry no balances, so the ledger balance is what the exported lines add up to
+        let _ = writeln!(out, "<LEDGERBAL>");
+        let _ = writeln!(out, "<BALAMT>{}", currency.format_units(statement.net()));
+        let _ = writeln!(out, "<DTASOF>{}", ofx_date(end));
+        let _ = writeln!(out, "</LEDGERBAL>");
+        let _ = writeln!(out, "</STMTRS>");
+        let _ = writeln!(out, "</STMTTRNRS>");
+    }
+    let _ = writeln!(out, "</BANKMSGSRSV1>");
+    let _ = writeln!(out, "</OFX>");
+    out
+}
+
+/// QIF fields end at the line break, so embedded ones would start a new field
+fn qif_text(text: &str) -> String {
+    text.replace(['\r', '\n'], " ")
+}
+
+fn to_qif(owner: &Pubkey, statements: &[ExportStatement]) -> String {
+    let mut out = String::new();
+    for statement in statements {
+        let currency = &statement.currency;
+        let _ = writeln!(out, "!Account");
+        let _ = writeln!(out, "N{}", account_id(owner, currency));
+        let _ = writeln!(out, "D{} on Solana", qif_text(&currency.to_string()));
+        let _ = writeln!(out, "TBank");
+        let _ = writeln!(out, "^");
+        let _ = writeln!(out, "!Type:Bank");
+        for line in &statement.lines {
+            let _ = writeln!(out, "D{}", utc(line.timestamp).format("%m/%d/%Y"));
+            let _ = writeln!(out, "T{}", currency.format_units(line.amount));
+            let _ = writeln!(out, "N{}", line.id);
+            let _ = writeln!(out, "P{}", qif_text(&line.payee));
+            if let Some(memo) = line.memo.as_deref().filter(|memo| !memo.is_empty()) {
+                let _ = writeln!(out, "M{}", qif_text(memo));
+            }
+            if line.is_fee {
+                let _ = writeln!(out, "LBank Charges");
+            }
+            let _ = writeln!(out, "^");
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::identity_index::IndexedIdentity;
+    use std::path::Path;
+
+    /// 2024-03-01 09:30:00 UTC
+    const MARCH_FIRST: u64 = 1_709_285_400;
+
+    fn key(byte: u8) -> Pubkey {
+        Pubkey::new_from_array([byte; 32])
+    }
+
+    fn signature(byte: u8) -> Signature {
+        Signature::from([byte; 64])
+    }
+
+    fn record(signature: Signature, from: Pubkey, to: Pubkey, amount: u64, currency: Currency, hours: u64) -> TransactionRecord {
+        TransactionRecord {
+            signature,
+            from,
+            to,
+            amount,
+            currency,
+            timestamp: MARCH_FIRST + hours * 3600,
+            memo: None,
+            fee: 0,
+            slot: 250_000_000 + hours,
+            memo_fields: HashMap::new(),
+        }
+    }
+
+    /// A month of one owner's activity: payroll in, a split supplier payment, fees, a burn, an
+    /// unnamed token, a memo with markup and a line break, and one redacted memo
+    fn history() -> (Pubkey, Vec<TransactionRecord>, IdentityIndex, Redactions) {
+        let owner = key(1);
+        let (employer, supplier, courier, stranger) = (key(2), key(3), key(4), key(5));
+        let points = Currency::Spl { mint: key(9), decimals: 2, symbol: None };
+
+        let mut payroll = record(signature(10), employer, owner, 2_500_000_000, Currency::usdc_devnet(), 0);
+        payroll.memo = Some("Payroll March".to_string());
+        let mut supplier_leg = record(signature(11), owner, supplier, 1_200_500_000, Currency::usdc_devnet(), 26);
+        supplier_leg.memo = Some("PO #4471 <rush> & expedited".to_string());
+        supplier_leg.fee = 10_000;
+        let mut courier_leg = record(signature(11), owner, courier, 50_000_000, Currency::usdc_devnet(), 26);
+        courier_leg.memo = Some("Shipping\nfor PO #4471".to_string());
+        courier_leg.fee = 10_000;
+        let mut rent = record(signature(12), owner, stranger, 1_500_000_000, Currency::Sol, 50);
+        rent.memo = Some("Desk rent, private".to_string());
+        rent.fee = 5_000;
+        let points_in = record(signature(13), Pubkey::default(), owner, 12_345, points.clone(), 74);
+        let mut refund = record(signature(14), supplier, owner, 99_990_000, Currency::usdc_devnet(), 98);
+        refund.memo = Some(String::new());
+        let points_out = record(signature(15), owner, Pubkey::default(), 345, points, 122);
+
+        let mut names = IdentityIndex::default();
+        for (pubkey, name) in [(employer, "Acme Payroll"), (supplier, "Müller & Söhne <Wholesale> Trading Company")] {
+            names.insert(IndexedIdentity {
+                pubkey,
+                display_name: Some(name.to_string()),
+                metadata: HashMap::new(),
+                redacted_fields: Vec::new(),
+                last_signature: None,
+            });
+        }
+        let mut redactions = Redactions::default();
+        redactions.memos.insert(signature(12));
+
+        // Deliberately out of order; the export sorts by time
+        let records = vec![refund, rent, payroll, points_out, supplier_leg, courier_leg, points_in];
+        (owner, records, names, redactions)
+    }
+
+    /// Compare with the golden file, or rewrite it when `FINTERNET_BLESS` is set
+    fn assert_golden(name: &str, actual: &str) {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/export").join(name);
+        if std::env::var_os("FINTERNET_BLESS").is_some() {
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(&path, actual).unwrap();
+        }
+        let expected = std::fs::read_to_string(&path).unwrap_or_else(|e| panic!("{}: {}", path.display(), e));
+        assert_eq!(actual, expected, "{} changed; rerun with FINTERNET_BLESS=1 if that was intended", name);
+    }
+
+    #[test]
+    fn ofx_matches_the_golden_file() {
+        let (owner, records, names, redactions) = history();
+        let ofx = export_history(ExportFormat::Ofx, &owner, &records, Some(&names), &redactions, MARCH_FIRST + 200 * 3600);
+        assert_golden("history.ofx", &ofx);
+    }
+
+    #[test]
+    fn qif_matches_the_golden_file() {
+        let (owner, records, names, redactions) = history();
+        let qif = export_history(ExportFormat::Qif, &owner, &records, Some(&names), &redactions, 0);
+        assert_golden("history.qif", &qif);
+    }
+
+    #[test]
+    fn each_currency_is_its_own_statement_and_fees_are_counted_once() {
+        let (owner, records, names, _) = history();
+        let statements = group_statements(&owner, &records, Some(&names));
+        let currencies: Vec<Option<Pubkey>> = statements.iter().map(|statement| statement.currency.mint()).collect();
+        let mut mints = [crate::usdc::devnet_mint(), key(9)];
+        mints.sort();
+        assert_eq!(currencies, vec![None, Some(mints[0]), Some(mints[1])]);
+
+        let sol = &statements[0];
+        let fees: Vec<&ExportLine> = sol.lines.iter().filter(|line| line.is_fee).collect();
+        // The split payment paid one fee for both legs
+        assert_eq!(fees.len(), 2);
+        assert_eq!(sol.net(), -1_500_000_000 - 10_000 - 5_000);
+
+        let usdc = statements.iter().find(|statement| statement.currency.mint() == Some(crate::usdc::devnet_mint())).unwrap();
+        let ids: Vec<&str> = usdc.lines.iter().map(|line| line.id.as_str()).collect();
+        assert_eq!(ids[1], signature(11).to_string());
+        assert_eq!(ids[2], format!("{}-1", signature(11)));
+        assert_eq!(usdc.net(), 2_500_000_000 - 1_200_500_000 - 50_000_000 + 99_990_000);
+    }
+
+    #[test]
+    fn the_same_records_and_time_give_the_same_bytes() {
+        let (owner, mut records, names, redactions) = history();
+        let first = export_history(ExportFormat::Ofx, &owner, &records, Some(&names), &redactions, MARCH_FIRST);
+        records.reverse();
+        let second = export_history(ExportFormat::Ofx, &owner, &records, Some(&names), &redactions, MARCH_FIRST);
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn formats_parse_from_their_names() {
+        assert_eq!("OFX".parse::<ExportFormat>().unwrap(), ExportFormat::Ofx);
+        assert_eq!(" qif ".parse::<ExportFormat>().unwrap(), ExportFormat::Qif);
+        assert!("csv".parse::<ExportFormat>().is_err());
+    }
+}
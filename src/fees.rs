@@ -0,0 +1,245 @@
+//! Priority fees on sends, chosen per `OperationKind`
+//!
+//! Every send the client signs itself gets a compute unit price from its kind's
+//! `ConfirmationPolicy::fee_strategy`, or `FinternetConfig::fee_strategy` when the policy sets
+//! none. A dynamic strategy samples `getRecentPrioritizationFees` for the accounts the
+//! transaction writes, takes a percentile, and caps it; samples are reused for a few seconds.
+//! Transactions signed by others, such as approved proposals, are sent as they are.
+
+use crate::confirmation::OperationKind;
+use crate::events::SdkEvent;
+use crate::{FinternetClient, FinternetConfig};
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use solana_sdk::compute_budget::{self, ComputeBudgetInstruction};
+use solana_sdk::instruction::{CompiledInstruction, Instruction};
+use solana_sdk::message::Message;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signers::Signers;
+use solana_sdk::transaction::Transaction;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// How long fee samples for one set of accounts are reused
+const SAMPLE_TTL: Duration = Duration::from_secs(10);
+/// `getRecentPrioritizationFees` takes at most this many accounts
+const MAX_SAMPLED_ACCOUNTS: usize = 128;
+/// First byte of a `SetComputeUnitPrice` instruction
+const SET_COMPUTE_UNIT_PRICE: u8 = 3;
+/// Base fee the cluster charges per signature
+pub(crate) const LAMPORTS_PER_SIGNATURE: u64 = 5000;
+/// Compute units granted per instruction when a transaction sets no limit, and the cap on the total
+const DEFAULT_INSTRUCTION_COMPUTE_UNITS: u64 = 200_000;
+const MAX_COMPUTE_UNITS: u64 = 1_400_000;
+const MICRO_LAMPORTS_PER_LAMPORT: u128 = 1_000_000;
+
+/// Compute unit price for a send, in micro-lamports per compute unit
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FeeStrategy {
+    /// No priority fee
+    #[default]
+    None,
+    Fixed(u64),
+    /// The `percentile` of recent fees paid on the written accounts, never above `max`
+    Dynamic { percentile: u8, max: u64 },
+}
+
+/// The compute unit price a send went out with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PriorityFee {
+    pub strategy: FeeStrategy,
+    /// Micro-lamports per compute unit
+    pub compute_unit_price: u64,
+    /// The estimate before `max` cut it down, if it did
+    pub clamped_from: Option<u64>,
+}
+
+/// When a set of fees was read, and the fees, sorted
+type Sample = (Instant, Vec<u64>);
+
+/// Recent fee samples by the sorted accounts they were read for
+#[derive(Default)]
+pub(crate) struct FeeSamples {
+    samples: Mutex<HashMap<Vec<Pubkey>, Sample>>,
+}
+
+impl FinternetConfig {
+    /// Set the priority fee for every kind of send whose confirmation policy does not set one
+    pub fn with_fee_strategy(mut self, strategy: FeeStrategy) -> Self {
+        self.fee_strategy = strategy;
+        self
+    }
+}
+
+impl FinternetClient {
+    /// The strategy sends of `kind` use
+    pub fn fee_strategy(&self, kind: OperationKind) -> FeeStrategy {
+        self.config.confirmation.get(kind).fee_strategy.unwrap_or(self.config.fee_strategy)
+    }
+
+    /// The compute unit price `strategy` picks for a transaction writing `accounts`
+    pub fn estimate_priority_fee(&self, strategy: FeeStrategy, accounts: &[Pubkey]) -> Result<PriorityFee> {
+        let (estimate, max) = match strategy {
+            FeeStrategy::None => (0, None),
+            FeeStrategy::Fixed(price) => (price, None),
+            FeeStrategy::Dynamic { percentile, max } => (percentile_of(&self.fee_samples(accounts)?, percentile), Some(max)),
+        };
+        let compute_unit_price = max.map_or(estimate, |max| estimate.min(max));
+        Ok(PriorityFee {
+            strategy,
+            compute_unit_price,
+            clamped_from: (compute_unit_price < estimate).then_some(estimate),
+        })
+    }
+
+    fn fee_samples(&self, accounts: &[Pubkey]) -> Result<Vec<u64>> {
+        let mut key: Vec<Pubkey> = accounts.iter().take(MAX_SAMPLED_ACCOUNTS).copied().collect();
+        key.sort();
+        key.dedup();
+        {
+            let samples = self.fee_samples.samples.lock().map_err(|_| anyhow!("Fee sample cache lock poisoned"))?;
+            if let Some((read_at, fees)) = samples.get(&key) {
+                if read_at.elapsed() < SAMPLE_TTL {
+                    return Ok(fees.clone());
+                }
+            }
+        }
+
+        let mut fees: Vec<u64> =
+            self.client.get_recent_prioritization_fees(&key)?.into_iter().map(|fee| fee.prioritization_fee).collect();
+        fees.sort_unstable();
+        let mut samples = self.fee_samples.samples.lock().map_err(|_| anyhow!("Fee sample cache lock poisoned"))?;
+        samples.retain(|_, (read_at, _)| read_at.elapsed() < SAMPLE_TTL);
+        samples.insert(key, (Instant::now(), fees.clone()));
+        Ok(fees)
+    }
+
+    /// Give `transaction` the compute unit price for `kind` and sign it again
+    ///
+    /// Transactions that already set a price keep it. Returns the fee chosen, or `None` when
+    /// the transaction was left as it was.
+    pub(crate) fn apply_priority_fee<T: Signers + ?Sized>(
+        &self,
+        kind: OperationKind,
+        label: &str,
+        transaction: &mut Transaction,
+        signers: &T,
+    ) -> Result<Option<PriorityFee>> {
+        let Some(fee) = self.price_transaction(kind, label, transaction, None)? else {
+            return Ok(None);
+        };
+        self.sign_with_fresh_blockhash(transaction, signers)?;
+        self.report_priority_fee(label, transaction, fee);
+        Ok(Some(fee))
+    }
+
+    /// Give the unsigned `transaction` the compute unit price for `kind`, sampled on `sampled`
+    /// or, by default, on every account it writes
+    ///
+    /// Transactions that already set a price keep it.
+    pub(crate) fn price_transaction(
+        &self,
+        kind: OperationKind,
+        label: &str,
+        transaction: &mut Transaction,
+        sampled: Option<&[Pubkey]>,
+    ) -> Result<Option<PriorityFee>> {
+        if self.fee_strategy(kind) == FeeStrategy::None || sets_compute_unit_price(transaction) {
+            return Ok(None);
+        }
+        let message = &transaction.message;
+        let writable: Vec<Pubkey> = match sampled {
+            Some(accounts) => accounts.to_vec(),
+            None => (0..message.account_keys.len())
+                .filter(|&i| message.is_writable(i))
+                .map(|i| message.account_keys[i])
+                .collect(),
+        };
+        let fee = self.priority_fee_for(kind, label, &writable)?;
+        if let Some(fee) = fee {
+            prepend_compute_unit_price(transaction, fee.compute_unit_price);
+        }
+        Ok(fee)
+    }
+
+    /// The fee for a send of `kind` writing `writable`, or `None` when it pays no priority fee
+    pub(crate) fn priority_fee_for(&self, kind: OperationKind, label: &str, writable: &[Pubkey]) -> Result<Option<PriorityFee>> {
+        let strategy = self.fee_strategy(kind);
+        if strategy == FeeStrategy::None {
+            return Ok(None);
+        }
+        let fee = self.estimate_priority_fee(strategy, writable)?;
+        if let Some(estimate) = fee.clamped_from {
+            tracing::info!(
+                "{} priority fee capped at {} micro-lamports per compute unit, estimate was {}",
+                label,
+                fee.compute_unit_price,
+                estimate
+            );
+        }
+        Ok((fee.compute_unit_price > 0).then_some(fee))
+    }
+
+    /// Raise `PriorityFeeSet` for a transaction signed with `fee`
+    pub(crate) fn report_priority_fee(&self, label: &str, transaction: &Transaction, fee: PriorityFee) {
+        if let Some(signature) = transaction.signatures.first().copied() {
+            self.emit(|| SdkEvent::PriorityFeeSet { operation: label.to_string(), signature, fee });
+        }
+        tracing::debug!("{} priced at {} micro-lamports per compute unit", label, fee.compute_unit_price);
+    }
+}
+
+/// What the cluster charges for `message` at `compute_unit_price`, with no compute unit limit set
+pub(crate) fn transaction_fee(message: &Message, compute_unit_price: u64) -> u64 {
+    let instructions = message
+        .instructions
+        .iter()
+        .filter(|instruction| message.account_keys.get(usize::from(instruction.program_id_index)) != Some(&compute_budget::id()))
+        .count() as u64;
+    let compute_units = (instructions * DEFAULT_INSTRUCTION_COMPUTE_UNITS).min(MAX_COMPUTE_UNITS);
+    let priority = (u128::from(compute_unit_price) * u128::from(compute_units)).div_ceil(MICRO_LAMPORTS_PER_LAMPORT);
+    LAMPORTS_PER_SIGNATURE * u64::from(message.header.num_required_signatures) + priority as u64
+}
+
+/// The value at `percentile` of `sorted`, 0 when there are no samples
+fn percentile_of(sorted: &[u64], percentile: u8) -> u64 {
+    if sorted.is_empty() {
+        return 0;
+    }
+    let rank = (usize::from(percentile.min(100)) * sorted.len()).div_ceil(100);
+    sorted[rank.saturating_sub(1)]
+}
+
+/// The instruction that sets `fee`, for transactions built with it from the start
+pub(crate) fn compute_unit_price_instruction(fee: &PriorityFee) -> Instruction {
+    ComputeBudgetInstruction::set_compute_unit_price(fee.compute_unit_price)
+}
+
+fn sets_compute_unit_price(transaction: &Transaction) -> bool {
+    let message = &transaction.message;
+    message.instructions.iter().any(|instruction| {
+        message.account_keys.get(usize::from(instruction.program_id_index)) == Some(&compute_budget::id())
+            && instruction.data.first() == Some(&SET_COMPUTE_UNIT_PRICE)
+    })
+}
+
+/// Put a `SetComputeUnitPrice` instruction first, adding the compute budget program as a
+/// read-only account at the end so no existing account index moves
+fn prepend_compute_unit_price(transaction: &mut Transaction, compute_unit_price: u64) {
+    let message = &mut transaction.message;
+    let program_index = match message.account_keys.iter().position(|key| *key == compute_budget::id()) {
+        Some(index) => index,
+        None => {
+            message.account_keys.push(compute_budget::id());
+            message.header.num_readonly_unsigned_accounts += 1;
+            message.account_keys.len() - 1
+        }
+    };
+    let data = ComputeBudgetInstruction::set_compute_unit_price(compute_unit_price).data;
+    message.instructions.insert(
+        0,
+        CompiledInstruction { program_id_index: program_index as u8, accounts: Vec::new(), data },
+    );
+}
@@ -0,0 +1,370 @@
+//! Catching confirmed transactions that later vanish with a fork
+//!
+//! A transaction seen at `confirmed` can still be dropped if its fork loses. `verify_finality`
+//! re-checks signatures against the cluster. `verify_ledger_finality` re-checks the local
+//! ledger's records that have not been seen finalized yet, and removes those the chain no longer
+//! has. It reports each one as a `RecordInvalidated`, marks any matching outbox entry `Dropped`,
+//! and appends the invalidation to the action log.
+
+#[cfg(feature = "sqlite")]
+use crate::local_ledger::LocalLedger;
+#[cfg(feature = "sqlite")]
+use crate::outbox::OutboxStatus;
+#[cfg(feature = "sqlite")]
+use crate::telemetry::readable;
+use crate::{FinternetClient, TransactionRecord};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use solana_sdk::{pubkey::Pubkey, signature::Signature};
+use solana_transaction_status::TransactionConfirmationStatus;
+#[cfg(feature = "sqlite")]
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// `getSignatureStatuses` accepts at most this many signatures per call
+const STATUSES_PER_CALL: usize = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum FinalityStatus {
+    /// Rooted; no fork can undo it
+    Finalized { signature: Signature, slot: u64 },
+    /// On the chain but not rooted yet, so it can still be dropped
+    StillConfirmed { signature: Signature, slot: u64 },
+    /// The cluster has no record of it: dropped with a fork, or never landed
+    NotFound { signature: Signature },
+}
+
+impl FinalityStatus {
+    pub fn signature(&self) -> &Signature {
+        match self {
+            Self::Finalized { signature, .. }
+            | Self::StillConfirmed { signature, .. }
+            | Self::NotFound { signature } => signature,
+        }
+    }
+}
+
+/// Records removed from a local ledger because their transaction left the chain
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordInvalidated {
+    pub owner: Pubkey,
+    pub signature: Signature,
+    pub records: Vec<TransactionRecord>,
+    pub detected_at: u64,
+}
+
+/// Outcome of one `verify_ledger_finality` pass
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FinalityReport {
+    pub checked: usize,
+    pub finalized: usize,
+    pub still_confirmed: usize,
+    pub invalidated: Vec<RecordInvalidated>,
+}
+
+impl FinternetClient {
+    /// Where each signature stands now, in the order given
+    ///
+    /// Statuses are looked up with transaction history, so old signatures are found as long as
+    /// the RPC node still serves them.
+    #[tracing::instrument(skip_all, fields(signatures = signatures.len(), rpc = %self.rpc_endpoint()))]
+    pub async fn verify_finality(&self, signatures: &[Signature]) -> Result<Vec<FinalityStatus>> {
+        let mut statuses = Vec::with_capacity(signatures.len());
+        for chunk in signatures.chunks(STATUSES_PER_CALL) {
+            let found = self.client.get_signature_statuses_with_history(chunk)?.value;
+            for (signature, status) in chunk.iter().zip(found) {
+                statuses.push(match status {
+                    Some(status) if status.confirmation_status == Some(TransactionConfirmationStatus::Finalized) => {
+                        FinalityStatus::Finalized { signature: *signature, slot: status.slot }
+                    }
+                    Some(status) => FinalityStatus::StillConfirmed { signature: *signature, slot: status.slot },
+                    None => FinalityStatus::NotFound { signature: *signature },
+                });
+            }
+        }
+        Ok(statuses)
+    }
+
+    /// Re-check `owner`'s unfinalized ledger records, dropping those the chain no longer has
+    ///
+    /// Records older than the node's first available block cannot be looked up; they predate any
+    /// live fork by far, so they are taken as final rather than reported missing.
+    #[cfg(feature = "sqlite")]
+    #[tracing::instrument(skip_all, fields(wallet = %readable(owner), db = %ledger.path().display()))]
+    pub async fn verify_ledger_finality(&self, ledger: &LocalLedger, owner: &Pubkey) -> Result<FinalityReport> {
+        let mut report = FinalityReport::default();
+        let pending = ledger.unfinalized(owner)?;
+        if pending.is_empty() {
+            return Ok(report);
+        }
+        let first_available = self.client.get_first_available_block().unwrap_or(0);
+        let (historical, recent): (Vec<_>, Vec<_>) = pending.into_iter().partition(|(_, slot)| *slot < first_available);
+        let mut finalized: Vec<Signature> = historical.into_iter().map(|(signature, _)| signature).collect();
+
+        let signatures: Vec<Signature> = recent.iter().map(|(signature, _)| *signature).collect();
+        report.checked = signatures.len();
+        for status in self.verify_finality(&signatures).await? {
+            match status {
+                FinalityStatus::Finalized { signature, .. } => finalized.push(signature),
+                FinalityStatus::StillConfirmed { .. } => report.still_confirmed += 1,
+                FinalityStatus::NotFound { signature } => {
+                    let records = ledger.invalidate(owner, &signature)?;
+                    tracing::warn!("Transaction {} left the chain; dropped {} ledger records", signature, records.len());
+                    self.record_invalidation(&signature, &records);
+                    report.invalidated.push(RecordInvalidated {
+                        owner: *owner,
+                        signature,
+                        records,
+                        detected_at: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+                    });
+                }
+            }
+        }
+        report.finalized = finalized.len();
+        ledger.mark_finalized(owner, &finalized)?;
+
+        tracing::info!(
+            "Finality check: {} finalized, {} still confirmed, {} invalidated",
+            report.finalized,
+            report.still_confirmed,
+            report.invalidated.len()
+        );
+        Ok(report)
+    }
+
+    /// Run `verify_ledger_finality` every `interval`, calling `handler` for each invalidation
+    ///
+    /// Runs until the client shuts down; spawn it next to the task that syncs the ledger.
+    #[cfg(feature = "sqlite")]
+    pub async fn watch_ledger_finality(
+        &self,
+        ledger: &LocalLedger,
+        owner: &Pubkey,
+        interval: Duration,
+        mut handler: impl FnMut(RecordInvalidated),
+    ) {
+        let shutdown = self.tasks.signal();
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            tokio::select! {
+                _ = ticker.tick() => {}
+                _ = shutdown.cancelled() => return,
+            }
+            match self.verify_ledger_finality(ledger, owner).await {
+                Ok(report) => report.invalidated.into_iter().for_each(&mut handler),
+                Err(e) => tracing::warn!("Finality check failed: {}", e),
+            }
+        }
+    }
+
+    /// Keep the outbox and action log in step with a transaction that left the chain
+    #[cfg(feature = "sqlite")]
+    fn record_invalidation(&self, signature: &Signature, records: &[TransactionRecord]) {
+        if let Some(outbox) = &self.outbox {
+            let tracked = outbox
+                .entries()
+                .map(|entries| entries.iter().any(|entry| entry.signature == *signature))
+                .unwrap_or(false);
+            if tracked {
+                if let Err(e) = outbox.mark(signature, OutboxStatus::Dropped, Some("Dropped with its fork".to_string())) {
+                    tracing::error!("Outbox entry {} not marked dropped: {}", signature, e);
+                }
+            }
+        }
+        let parameters = serde_json::json!({
+            "signature": signature.to_string(),
+            "records": records.len(),
+            "amounts": records
+                .iter()
+                .map(|record| serde_json::json!({
+                    "from": record.from.to_string(),
+                    "to": record.to.to_string(),
+                    "amount": record.amount,
+                    "currency": record.currency.label(),
+                }))
+                .collect::<Vec<_>>(),
+        });
+        let outcome: Result<()> = Ok(());
+        self.log_action("record_invalidated", parameters, None, outcome.as_ref(), |_| signature.to_string());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_cluster::MockCluster;
+    use serde_json::{json, Value};
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+
+    /// How far each signature got, as the cluster reports it; missing ones are unknown
+    type Chain = Arc<Mutex<HashMap<Signature, &'static str>>>;
+
+    fn serve_statuses(cluster: &MockCluster) -> Chain {
+        let chain = Chain::default();
+        let seen = Arc::clone(&chain);
+        cluster.respond("getSignatureStatuses", move |params| {
+            let chain = seen.lock().unwrap();
+            let statuses: Vec<Value> = params[0]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|signature| {
+                    let signature: Signature = signature.as_str().unwrap().parse().unwrap();
+                    chain.get(&signature).map_or(Value::Null, |level| {
+                        json!({ "slot": 900, "confirmations": null, "err": null, "status": { "Ok": null }, "confirmationStatus": level })
+                    })
+                })
+                .collect();
+            Some(Ok(json!({ "context": { "slot": 1_000 }, "value": statuses })))
+        });
+        chain
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn each_signature_is_reported_in_order_across_status_calls() {
+        let cluster = MockCluster::new();
+        let client = cluster.default_client();
+        let chain = serve_statuses(&cluster);
+        let signatures: Vec<Signature> = (0..300).map(|_| Signature::new_unique()).collect();
+        for (i, signature) in signatures.iter().enumerate() {
+            match i % 3 {
+                0 => chain.lock().unwrap().insert(*signature, "finalized"),
+                1 => chain.lock().unwrap().insert(*signature, "confirmed"),
+                _ => None,
+            };
+        }
+
+        let statuses = client.verify_finality(&signatures).await.unwrap();
+        assert_eq!(cluster.call_count("getSignatureStatuses"), 2);
+        assert_eq!(statuses.len(), signatures.len());
+        for (i, (status, signature)) in statuses.iter().zip(&signatures).enumerate() {
+            let expected = match i % 3 {
+                0 => FinalityStatus::Finalized { signature: *signature, slot: 900 },
+                1 => FinalityStatus::StillConfirmed { signature: *signature, slot: 900 },
+                _ => FinalityStatus::NotFound { signature: *signature },
+            };
+            assert_eq!(*status, expected);
+        }
+    }
+
+    /// The ledger re-check, which needs the SQLite-backed `LocalLedger`
+    #[cfg(feature = "sqlite")]
+    mod ledger {
+        use super::*;
+        use crate::action_log::ActionLog;
+        use crate::confirmation::OperationKind;
+        use crate::mock_cluster::TempDir;
+        use crate::{Currency, FinternetConfig};
+        use solana_sdk::signature::Keypair;
+        use solana_sdk::signer::Signer;
+        use solana_sdk::system_instruction;
+        use solana_sdk::transaction::Transaction;
+
+        fn record(owner: &Pubkey, signature: Signature, amount: u64) -> TransactionRecord {
+            TransactionRecord {
+                signature,
+                from: *owner,
+                to: Pubkey::new_unique(),
+                amount,
+                currency: Currency::spl(Pubkey::new_unique(), 6),
+                timestamp: 1_700_000_000,
+                memo: None,
+                fee: 5_000,
+                slot: 900,
+                memo_fields: HashMap::new(),
+            }
+        }
+
+        #[tokio::test(flavor = "multi_thread")]
+        async fn a_confirmed_record_that_vanishes_is_invalidated_in_the_ledger_outbox_and_action_log() {
+            let cluster = MockCluster::new();
+            let dir = TempDir::new("finality");
+            let client = cluster.client(FinternetConfig {
+                outbox_path: Some(dir.join("outbox.json")),
+                action_log_path: Some(dir.join("actions.jsonl")),
+                ..FinternetConfig::default()
+            });
+            let payer = Keypair::new();
+            cluster.set_balance(payer.pubkey(), 1_000_000_000);
+            let transfer = system_instruction::transfer(&payer.pubkey(), &Keypair::new().pubkey(), 1_000);
+            let sent = client
+                .send_and_confirm_resigning(OperationKind::Payment, "test", Transaction::new_with_payer(&[transfer], Some(&payer.pubkey())), &[&payer])
+                .unwrap();
+
+            let chain = serve_statuses(&cluster);
+            let (rooted, pending) = (Signature::new_unique(), Signature::new_unique());
+            chain.lock().unwrap().extend([(rooted, "finalized"), (pending, "confirmed"), (sent, "confirmed")]);
+            let ledger = LocalLedger::open(&dir.join("ledger.db")).unwrap();
+            let owner = payer.pubkey();
+            let dropped = record(&owner, sent, 1_000);
+            ledger
+                .insert_records(&owner, vec![dropped.clone(), record(&owner, pending, 2), record(&owner, rooted, 3)])
+                .unwrap();
+
+            let report = client.verify_ledger_finality(&ledger, &owner).await.unwrap();
+            assert_eq!((report.checked, report.finalized, report.still_confirmed), (3, 1, 2));
+            assert!(report.invalidated.is_empty());
+
+            // The fork carrying `sent` loses
+            chain.lock().unwrap().remove(&sent);
+            let report = client.verify_ledger_finality(&ledger, &owner).await.unwrap();
+            assert_eq!((report.checked, report.finalized, report.still_confirmed), (2, 0, 1));
+            assert_eq!(report.invalidated.len(), 1);
+            let invalidated = &report.invalidated[0];
+            assert_eq!((invalidated.owner, invalidated.signature), (owner, sent));
+            assert_eq!(invalidated.records.iter().map(|r| (r.signature, r.amount)).collect::<Vec<_>>(), [(sent, 1_000)]);
+
+            let remaining: Vec<Signature> = ledger.query_history(&owner, 10, 0).unwrap().iter().map(|r| r.signature).collect();
+            assert_eq!(remaining, [pending, rooted]);
+            let outbox = client.outbox.as_ref().unwrap().entries().unwrap();
+            let entry = outbox.iter().find(|entry| entry.signature == sent).unwrap();
+            assert_eq!(entry.status, OutboxStatus::Dropped);
+            let log = ActionLog::new(&dir.join("actions.jsonl")).page(0, 100).unwrap();
+            let logged = log.entries.iter().find(|entry| entry.operation == "record_invalidated").unwrap();
+            assert_eq!(logged.signature, Some(sent.to_string()));
+            assert_eq!(logged.parameters["amounts"][0]["amount"], 1_000);
+
+            // Only the still-confirmed record is checked from now on
+            let report = client.verify_ledger_finality(&ledger, &owner).await.unwrap();
+            assert_eq!((report.checked, report.still_confirmed, report.invalidated.len()), (1, 1, 0));
+        }
+
+        #[tokio::test(flavor = "multi_thread")]
+        async fn the_watcher_reports_an_invalidation_and_stops_on_shutdown() {
+            let cluster = MockCluster::new();
+            let dir = TempDir::new("finality-watch");
+            let client = Arc::new(cluster.default_client());
+            let chain = serve_statuses(&cluster);
+            let owner = Pubkey::new_unique();
+            let signature = Signature::new_unique();
+            chain.lock().unwrap().insert(signature, "confirmed");
+            let ledger = Arc::new(LocalLedger::open(&dir.join("ledger.db")).unwrap());
+            ledger.insert_records(&owner, vec![record(&owner, signature, 7)]).unwrap();
+
+            let (events, mut received) = tokio::sync::mpsc::unbounded_channel();
+            let (watching, watched) = (Arc::clone(&client), Arc::clone(&ledger));
+            client
+                .tasks()
+                .spawn("finality", async move {
+                    watching
+                        .watch_ledger_finality(&watched, &owner, Duration::from_millis(20), |invalidated| {
+                            let _ = events.send(invalidated);
+                        })
+                        .await
+                })
+                .unwrap();
+
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            assert!(received.try_recv().is_err());
+            chain.lock().unwrap().remove(&signature);
+            let invalidated = tokio::time::timeout(Duration::from_secs(5), received.recv()).await.unwrap().unwrap();
+            assert_eq!((invalidated.owner, invalidated.signature), (owner, signature));
+            assert!(ledger.query_history(&owner, 10, 0).unwrap().is_empty());
+
+            let report = client.shutdown().await;
+            assert_eq!(report.stopped, ["finality"]);
+            assert!(report.aborted.is_empty());
+        }
+    }
+}
@@ -0,0 +1,228 @@
+//! Balance reads that can be answered from the client's cache
+//!
+//! Every `get_wallet_info` stores what it read. `Freshness` picks how the `_with_freshness` reads
+//! use that: `Fresh` always reads the chain, `Cached` serves whatever is stored, and
+//! `StaleWhileRevalidate` serves the stored value at once and, when it is older than the given
+//! age, reads it again on a background thread, raising `BalanceRefreshed` once the cache holds
+//! the new value. Every transaction the client sends drops the entries of the accounts it names,
+//! so a wallet is never served its balance from before a payment it just made.
+
+use crate::events::SdkEvent;
+use crate::identity::WalletInfo;
+use crate::ledger::TokenAccountDetail;
+use crate::resources::{CacheLimit, CacheUsage, LruMap};
+use crate::telemetry::readable;
+use crate::{usdc, FinternetClient};
+use anyhow::{anyhow, Result};
+use solana_sdk::pubkey::Pubkey;
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// How old a cached balance may get under a bare `swr` before it is read again
+pub const DEFAULT_SWR_MAX_AGE: Duration = Duration::from_secs(15);
+
+/// How a balance read may use the client's cache
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Freshness {
+    /// Whatever is cached, however old; the chain is read only on a miss
+    Cached,
+    /// Always read the chain
+    #[default]
+    Fresh,
+    /// Serve what is cached, reading it again in the background once it is older than this
+    StaleWhileRevalidate(Duration),
+}
+
+impl FromStr for Freshness {
+    type Err = anyhow::Error;
+
+    /// `fresh`, `cached`, `swr` (revalidating after `DEFAULT_SWR_MAX_AGE`), or `swr:<seconds>`
+    fn from_str(freshness: &str) -> Result<Self> {
+        match freshness.trim() {
+            "fresh" => Ok(Self::Fresh),
+            "cached" => Ok(Self::Cached),
+            "swr" => Ok(Self::StaleWhileRevalidate(DEFAULT_SWR_MAX_AGE)),
+            other => other
+                .strip_prefix("swr:")
+                .and_then(|seconds| seconds.parse::<u64>().ok())
+                .map(|seconds| Self::StaleWhileRevalidate(Duration::from_secs(seconds)))
+                .ok_or_else(|| anyhow!("Invalid freshness '{}': use fresh, cached, swr, or swr:<seconds>", other)),
+        }
+    }
+}
+
+/// A balance read with when the chain was read for it
+#[derive(Debug, Clone)]
+pub struct CachedRead<T> {
+    pub value: T,
+    /// Unix time the value was read from the chain
+    pub as_of: u64,
+    pub age: Duration,
+    /// Whether a background read of a newer value is under way
+    pub revalidating: bool,
+}
+
+impl<T> CachedRead<T> {
+    fn map<U>(self, f: impl FnOnce(T) -> U) -> CachedRead<U> {
+        CachedRead { value: f(self.value), as_of: self.as_of, age: self.age, revalidating: self.revalidating }
+    }
+}
+
+struct CachedWallet {
+    fetched_at: Instant,
+    as_of: u64,
+    info: WalletInfo,
+}
+
+/// Wallet reads shared by every call on a client, keyed by wallet
+#[derive(Default)]
+pub(crate) struct WalletCache {
+    /// Least recently read wallets go first past `ResourceLimits::wallet_cache`
+    entries: Mutex<LruMap<Pubkey, CachedWallet>>,
+    /// Wallets with a background read under way, so a burst of reads starts only one
+    refreshing: Mutex<HashSet<Pubkey>>,
+    /// Bumped by every invalidation; a read that started before one is not stored
+    epoch: AtomicU64,
+}
+
+impl WalletCache {
+    pub(crate) fn new(limit: CacheLimit) -> Self {
+        Self { entries: Mutex::new(LruMap::new(limit)), ..Self::default() }
+    }
+
+    pub(crate) fn usage(&self) -> CacheUsage {
+        self.entries.lock().map(|entries| entries.usage()).unwrap_or_default()
+    }
+
+    fn get(&self, pubkey: &Pubkey) -> Result<Option<CachedRead<WalletInfo>>> {
+        let mut entries = self.entries.lock().map_err(|_| anyhow!("Wallet cache lock poisoned"))?;
+        Ok(entries.get(pubkey).map(|cached| CachedRead {
+            value: cached.info.clone(),
+            as_of: cached.as_of,
+            age: cached.fetched_at.elapsed(),
+            revalidating: false,
+        }))
+    }
+
+    pub(crate) fn epoch(&self) -> u64 {
+        self.epoch.load(Ordering::Acquire)
+    }
+
+    /// Keep `info`, unless the cache was invalidated since the read began at `epoch`
+    pub(crate) fn store(&self, info: &WalletInfo, epoch: u64) {
+        let Ok(mut entries) = self.entries.lock() else { return };
+        // Checked under the lock, which `invalidate` also takes before bumping the epoch
+        if self.epoch() != epoch {
+            return;
+        }
+        let as_of = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default();
+        let bytes = std::mem::size_of::<CachedWallet>()
+            + info.token_balances.len() * std::mem::size_of::<(Pubkey, u64)>()
+            + info.token_accounts.len() * std::mem::size_of::<TokenAccountDetail>();
+        entries.insert(info.pubkey, CachedWallet { fetched_at: Instant::now(), as_of, info: info.clone() }, bytes);
+    }
+
+    /// Drop what is cached for `accounts`, and any read of them still in flight
+    pub(crate) fn invalidate(&self, accounts: &[Pubkey]) {
+        let Ok(mut entries) = self.entries.lock() else { return };
+        self.epoch.fetch_add(1, Ordering::AcqRel);
+        for account in accounts {
+            entries.remove(account);
+        }
+    }
+
+    /// Whether the caller should start a background read of `pubkey`
+    fn begin_refresh(&self, pubkey: &Pubkey) -> bool {
+        self.refreshing.lock().map(|mut refreshing| refreshing.insert(*pubkey)).unwrap_or(false)
+    }
+
+    fn end_refresh(&self, pubkey: &Pubkey) {
+        if let Ok(mut refreshing) = self.refreshing.lock() {
+            refreshing.remove(pubkey);
+        }
+    }
+}
+
+impl FinternetClient {
+    /// `get_wallet_info`, served from the client's cache as `freshness` allows
+    ///
+    /// A miss is always read from the chain, whatever `freshness` says.
+    #[tracing::instrument(skip_all, fields(wallet = %readable(pubkey), freshness = ?freshness))]
+    pub async fn get_wallet_info_with_freshness(
+        self: &Arc<Self>,
+        pubkey: &Pubkey,
+        freshness: Freshness,
+    ) -> Result<CachedRead<WalletInfo>> {
+        let cached = match freshness {
+            Freshness::Fresh => None,
+            _ => self.cache.wallets.get(pubkey)?,
+        };
+        match (cached, freshness) {
+            (Some(mut cached), Freshness::StaleWhileRevalidate(max_age)) => {
+                if cached.age > max_age {
+                    cached.revalidating = self.revalidate_wallet(*pubkey);
+                }
+                Ok(cached)
+            }
+            (Some(cached), _) => Ok(cached),
+            (None, _) => {
+                let value = self.get_wallet_info(pubkey).await?;
+                let as_of = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+                Ok(CachedRead { value, as_of, age: Duration::ZERO, revalidating: false })
+            }
+        }
+    }
+
+    /// USDC held by a wallet in whole USDC, served from the client's cache as `freshness` allows
+    ///
+    /// Served from the wallet's cached token accounts, so this sums every USDC account the wallet
+    /// owns where `get_usdc_balance` reads only the associated one.
+    pub async fn get_usdc_balance_with_freshness(
+        self: &Arc<Self>,
+        wallet_pubkey: &Pubkey,
+        freshness: Freshness,
+    ) -> Result<CachedRead<f64>> {
+        let read = self.get_wallet_info_with_freshness(wallet_pubkey, freshness).await?;
+        let mint = usdc::devnet_mint();
+        Ok(read.map(|info| info.token_balances.get(&mint).copied().unwrap_or(0) as f64 / 1_000_000.0))
+    }
+
+    /// `get_token_accounts`, served from the client's cache as `freshness` allows
+    pub async fn get_token_accounts_with_freshness(
+        self: &Arc<Self>,
+        owner: &Pubkey,
+        freshness: Freshness,
+    ) -> Result<CachedRead<HashMap<Pubkey, u64>>> {
+        let read = self.get_wallet_info_with_freshness(owner, freshness).await?;
+        Ok(read.map(|info| info.token_balances))
+    }
+
+    /// Read `pubkey` again on a background thread; whether a read is now under way
+    fn revalidate_wallet(self: &Arc<Self>, pubkey: Pubkey) -> bool {
+        if !self.cache.wallets.begin_refresh(&pubkey) {
+            return true;
+        }
+        let client = Arc::clone(self);
+        // RPC calls block, so the read gets its own thread rather than an async task
+        let spawned = std::thread::Builder::new().name("finternet-revalidate".to_string()).spawn(move || {
+            let outcome = futures::executor::block_on(client.get_wallet_info(&pubkey));
+            client.cache.wallets.end_refresh(&pubkey);
+            match outcome {
+                Ok(info) => {
+                    let as_of = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default();
+                    client.emit(|| SdkEvent::BalanceRefreshed { wallet: pubkey, sol_balance: info.sol_balance, as_of });
+                }
+                Err(e) => tracing::warn!("Background balance read for {} failed: {}", readable(&pubkey), e),
+            }
+        });
+        if let Err(e) = spawned {
+            self.cache.wallets.end_refresh(&pubkey);
+            tracing::warn!("Background balance read for {} not started: {}", readable(&pubkey), e);
+            return false;
+        }
+        true
+    }
+}
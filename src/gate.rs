@@ -0,0 +1,244 @@
+//! Token gating: does a wallet hold the asset a service requires?
+//!
+//! Only unfrozen balances count. A `GateResult` can be signed by the checking wallet, so a
+//! downstream service that trusts that wallet can accept the result without querying the chain.
+
+use crate::telemetry::readable;
+use crate::{AssetMetadata, AssetType, FinternetClient, MetadataBackend};
+use anyhow::Result;
+use mpl_token_metadata::accounts::Metadata;
+use serde::{Deserialize, Serialize};
+use solana_sdk::{pubkey::Pubkey, signature::Keypair, signer::Signer};
+use std::fmt;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Metadata accounts fetched per `getMultipleAccounts` call, the RPC maximum
+const ACCOUNTS_PER_FETCH: usize = 100;
+
+/// What a wallet must hold to pass the gate
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum GateRequirement {
+    /// At least `min_amount` base units of `mint`, across all of the wallet's accounts
+    HoldsMint { mint: Pubkey, min_amount: u64 },
+    /// Any token of a verified Metaplex collection
+    HoldsCollection { collection: Pubkey },
+    /// Any asset of `asset_type` whose metadata `issuer` signed
+    HoldsAssetType { asset_type: AssetType, issuer: Pubkey },
+}
+
+impl fmt::Display for GateRequirement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::HoldsMint { mint, min_amount } => write!(f, "holds at least {} of {}", min_amount, mint),
+            Self::HoldsCollection { collection } => write!(f, "holds an asset of collection {}", collection),
+            Self::HoldsAssetType { asset_type, issuer } => {
+                write!(f, "holds a {} asset issued by {}", asset_type, issuer)
+            }
+        }
+    }
+}
+
+/// The holding that satisfied a gate
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GateEvidence {
+    pub mint: Pubkey,
+    /// Largest unfrozen account of the mint
+    pub token_account: Pubkey,
+    /// Unfrozen balance of the mint across all of the wallet's accounts
+    pub balance: u64,
+}
+
+/// Signature by the wallet that checked the gate over `GateResult::canonical_payload()`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GateAttestation {
+    pub attester: Pubkey,
+    /// Off-chain message signature, base58
+    pub signature: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GateResult {
+    pub owner: Pubkey,
+    pub requirement: GateRequirement,
+    pub satisfied: bool,
+    pub evidence: Option<GateEvidence>,
+    /// Matching mints not counted, or counted short, because some of their balance is frozen
+    pub frozen_mints: Vec<Pubkey>,
+    pub checked_at: u64,
+    pub attestation: Option<GateAttestation>,
+}
+
+/// Signed fields, serialized in this fixed order
+#[derive(Serialize)]
+struct GatePayload {
+    owner: String,
+    requirement: String,
+    satisfied: bool,
+    mint: Option<String>,
+    balance: Option<u64>,
+    checked_at: u64,
+    attester: String,
+}
+
+impl GateResult {
+    /// The exact bytes an attester signs: compact JSON naming the owner, requirement, outcome,
+    /// and evidence
+    pub fn canonical_payload(&self, attester: &Pubkey) -> Result<String> {
+        Ok(serde_json::to_string(&GatePayload {
+            owner: self.owner.to_string(),
+            requirement: self.requirement.to_string(),
+            satisfied: self.satisfied,
+            mint: self.evidence.as_ref().map(|evidence| evidence.mint.to_string()),
+            balance: self.evidence.as_ref().map(|evidence| evidence.balance),
+            checked_at: self.checked_at,
+            attester: attester.to_string(),
+        })?)
+    }
+
+    /// Sign the result as `attester`, replacing any earlier attestation
+    pub fn attest(&mut self, attester: &Keypair) -> Result<()> {
+        let payload = self.canonical_payload(&attester.pubkey())?;
+        self.attestation = Some(GateAttestation {
+            attester: attester.pubkey(),
+            signature: FinternetClient::sign_offchain_message(attester, &payload)?,
+        });
+        Ok(())
+    }
+
+    /// Whether the attestation is a valid signature by `trusted` over this result
+    pub fn verify_attestation(&self, trusted: &Pubkey) -> Result<bool> {
+        let Some(attestation) = &self.attestation else {
+            return Ok(false);
+        };
+        if attestation.attester != *trusted {
+            return Ok(false);
+        }
+        FinternetClient::verify_offchain_message(trusted, &self.canonical_payload(trusted)?, &attestation.signature)
+    }
+}
+
+/// What the owner holds of one mint
+struct Holding {
+    mint: Pubkey,
+    /// Across unfrozen accounts only
+    balance: u64,
+    largest_account: Option<(Pubkey, u64)>,
+    /// Some of the balance sits in a frozen account
+    frozen: bool,
+}
+
+impl FinternetClient {
+    /// Check whether `owner` meets `requirement`; the result is unsigned until `attest`ed
+    #[tracing::instrument(skip_all, fields(wallet = %readable(owner), requirement = %requirement))]
+    pub async fn verify_token_gate(&self, owner: &Pubkey, requirement: &GateRequirement) -> Result<GateResult> {
+        let holdings = self.holdings(owner).await?;
+        let mut result = GateResult {
+            owner: *owner,
+            requirement: requirement.clone(),
+            satisfied: false,
+            evidence: None,
+            frozen_mints: Vec::new(),
+            checked_at: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+            attestation: None,
+        };
+
+        let (candidates, threshold): (Vec<&Holding>, u64) = match requirement {
+            GateRequirement::HoldsMint { mint, min_amount } => (
+                holdings.iter().filter(|holding| holding.mint == *mint).collect(),
+                (*min_amount).max(1),
+            ),
+            GateRequirement::HoldsCollection { collection } => {
+                let mints: Vec<Pubkey> = holdings.iter().map(|holding| holding.mint).collect();
+                let in_collection = self.verified_collection_mints(&mints, collection)?;
+                (holdings.iter().filter(|holding| in_collection.contains(&holding.mint)).collect(), 1)
+            }
+            GateRequirement::HoldsAssetType { asset_type, issuer } => {
+                let mut matching = Vec::new();
+                for holding in holdings.iter().filter(|holding| holding.balance > 0 || holding.frozen) {
+                    match self.get_asset_info(&holding.mint).await {
+                        Ok(asset) if issued_as(&asset, asset_type, issuer) => matching.push(holding),
+                        Ok(_) => {}
+                        Err(e) => tracing::debug!("Skipping {} for the gate: {}", holding.mint, e),
+                    }
+                }
+                (matching, 1)
+            }
+        };
+
+        for holding in candidates {
+            match holding.largest_account {
+                Some((token_account, _)) if holding.balance >= threshold => {
+                    result.satisfied = true;
+                    result.evidence = Some(GateEvidence {
+                        mint: holding.mint,
+                        token_account,
+                        balance: holding.balance,
+                    });
+                    break;
+                }
+                _ if holding.frozen => result.frozen_mints.push(holding.mint),
+                _ => {}
+            }
+        }
+        tracing::info!("Gate '{}' for {}: {}", requirement, owner, if result.satisfied { "passed" } else { "failed" });
+        Ok(result)
+    }
+
+    /// Every mint `owner` has a token account for, with its unfrozen balance
+    async fn holdings(&self, owner: &Pubkey) -> Result<Vec<Holding>> {
+        let mut holdings: Vec<Holding> = Vec::new();
+        for account in self.get_token_account_details(owner).await? {
+            let index = match holdings.iter().position(|holding| holding.mint == account.mint) {
+                Some(index) => index,
+                None => {
+                    holdings.push(Holding { mint: account.mint, balance: 0, largest_account: None, frozen: false });
+                    holdings.len() - 1
+                }
+            };
+            let holding = &mut holdings[index];
+            if account.is_frozen() {
+                holding.frozen |= account.amount > 0;
+                continue;
+            }
+            holding.balance += account.amount;
+            if account.amount > 0 && holding.largest_account.is_none_or(|(_, amount)| account.amount > amount) {
+                holding.largest_account = Some((account.address, account.amount));
+            }
+        }
+        Ok(holdings)
+    }
+
+    /// Which of `mints` belong to `collection`, with the collection verified in their metadata
+    fn verified_collection_mints(&self, mints: &[Pubkey], collection: &Pubkey) -> Result<Vec<Pubkey>> {
+        let pdas: Vec<Pubkey> = mints.iter().map(|mint| Metadata::find_pda(mint).0).collect();
+        let mut accounts = Vec::with_capacity(pdas.len());
+        for chunk in pdas.chunks(ACCOUNTS_PER_FETCH) {
+            accounts.extend(self.client.get_multiple_accounts(chunk)?);
+        }
+        Ok(mints
+            .iter()
+            .zip(accounts)
+            .filter(|(_, account)| {
+                account
+                    .as_ref()
+                    .and_then(|account| Metadata::from_bytes(&account.data).ok())
+                    .and_then(|metadata| metadata.collection)
+                    .is_some_and(|c| c.verified && c.key == *collection)
+            })
+            .map(|(mint, _)| *mint)
+            .collect())
+    }
+}
+
+/// Whether `asset` is of `asset_type` and its metadata carries `issuer`'s signature: a verified
+/// Metaplex creator, or the signer of the ledger memo
+fn issued_as(asset: &AssetMetadata, asset_type: &AssetType, issuer: &Pubkey) -> bool {
+    if !asset.has_metadata || asset.asset_type != *asset_type || asset.issuer != *issuer {
+        return false;
+    }
+    match asset.metadata_backend {
+        Some(MetadataBackend::LedgerMemo) => true,
+        _ => asset.creators.iter().any(|creator| creator.address == *issuer && creator.verified),
+    }
+}
@@ -0,0 +1,786 @@
+//! Streaming a wallet's whole history, newest first, without handling cursors
+//!
+//! `iter_history` pages through `getSignaturesForAddress` with the `before` cursor and fetches
+//! transactions a few at a time as the consumer pulls, so at most one signature page and one
+//! batch of transactions are held however long the history is. A rate-limited call pauses and
+//! is retried; any other failure to read a signature page ends the stream with that error. The
+//! export, ledger sync, and reconciliation all read history through here.
+//!
+//! Public nodes prune old transactions, so the oldest signature a node returns is not always
+//! the account's first. `HistoryCompleteness` says which: a history is `Complete` when its oldest
+//! transaction is the one that first funded the account, and `TruncatedAt` the oldest slot the
+//! node still has otherwise.
+
+use crate::ledger::extract_memos;
+use crate::telemetry::readable;
+use crate::{Currency, FinternetClient, TransactionRecord};
+use anyhow::Result;
+use futures::stream::{self, Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use solana_client::rpc_client::GetConfirmedSignaturesForAddress2Config;
+use solana_client::rpc_config::RpcTransactionConfig;
+use solana_client::rpc_response::RpcConfirmedTransactionStatusWithSignature;
+use solana_sdk::{commitment_config::CommitmentConfig, pubkey::Pubkey, signature::Signature};
+use solana_transaction_status::option_serializer::OptionSerializer;
+use solana_transaction_status::{EncodedConfirmedTransactionWithStatusMeta, EncodedTransaction, UiMessage, UiTransactionEncoding};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fmt;
+use std::ops::Range;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Transactions fetched at once; RPC calls block, so each gets its own thread
+const FETCH_CONCURRENCY: usize = 4;
+const INITIAL_RATE_LIMIT_PAUSE: Duration = Duration::from_secs(1);
+const MAX_RATE_LIMIT_PAUSE: Duration = Duration::from_secs(30);
+pub(crate) const SIGNATURE_PAGE_LIMIT: usize = 1000;
+/// Signature pages pulled per sync; anything beyond is recorded as a gap
+pub(crate) const MAX_SYNC_PAGES: usize = 20;
+
+/// Which part of a history to stream
+#[derive(Debug, Clone, Default)]
+pub struct HistoryFilter {
+    /// Start with the transaction just older than this one
+    pub before: Option<Signature>,
+    /// Stop on reaching this transaction, e.g. the newest one already synced; it is not yielded
+    pub until: Option<Signature>,
+    /// Unix block times to keep, start inclusive; the stream ends once it is older than the start.
+    /// Transactions without a block time are left out when this is set.
+    pub time_range: Option<Range<u64>>,
+    /// Only transfers of this mint; `iter_transaction_history` only
+    pub mint: Option<Pubkey>,
+}
+
+impl HistoryFilter {
+    fn admits(&self, status: &RpcConfirmedTransactionStatusWithSignature) -> bool {
+        match &self.time_range {
+            Some(range) => status.block_time.is_some_and(|time| range.contains(&(time.max(0) as u64))),
+            None => true,
+        }
+    }
+
+    /// Whether `status`, and so everything older, is before the range start
+    fn is_past(&self, status: &RpcConfirmedTransactionStatusWithSignature) -> bool {
+        match (&self.time_range, status.block_time) {
+            (Some(range), Some(time)) => (time.max(0) as u64) < range.start,
+            _ => false,
+        }
+    }
+}
+
+/// Response header the API server sets on history it serves, e.g. `truncated_at=123456`
+pub const COMPLETENESS_HEADER: &str = "X-Finternet-History-Completeness";
+
+/// Whether a history reaches back to the account's creation
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "status", content = "slot", rename_all = "snake_case")]
+pub enum HistoryCompleteness {
+    /// The oldest transaction read is the one that first funded the account
+    Complete,
+    /// The node serves nothing older than this slot, though the account existed before it
+    TruncatedAt(u64),
+    /// The read stopped before the oldest transaction, or it could not be fetched
+    #[default]
+    Unknown,
+}
+
+impl HistoryCompleteness {
+    pub fn is_complete(&self) -> bool {
+        *self == Self::Complete
+    }
+
+    pub fn truncated_at(&self) -> Option<u64> {
+        match self {
+            Self::TruncatedAt(slot) => Some(*slot),
+            _ => None,
+        }
+    }
+
+    /// `complete`, `truncated_at=<slot>`, or `unknown`, as sent in `COMPLETENESS_HEADER`
+    pub fn header_value(&self) -> String {
+        match self {
+            Self::Complete => "complete".to_string(),
+            Self::TruncatedAt(slot) => format!("truncated_at={}", slot),
+            Self::Unknown => "unknown".to_string(),
+        }
+    }
+}
+
+impl fmt::Display for HistoryCompleteness {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Complete => write!(f, "complete"),
+            Self::TruncatedAt(slot) => write!(f, "truncated at slot {}", slot),
+            Self::Unknown => write!(f, "of unknown completeness"),
+        }
+    }
+}
+
+/// A result that needs the whole history was asked for over a truncated one
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TruncatedHistory {
+    pub owner: Pubkey,
+    /// Oldest slot the history holds
+    pub slot: u64,
+    /// What could not be worked out, e.g. `opening balance`
+    pub needed_for: String,
+}
+
+impl TruncatedHistory {
+    pub fn code(&self) -> &'static str {
+        "history_truncated"
+    }
+}
+
+impl fmt::Display for TruncatedHistory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "History of {} is truncated at slot {}, so its {} cannot be computed; allow partial history to compute it anyway",
+            self.owner, self.slot, self.needed_for
+        )
+    }
+}
+
+impl std::error::Error for TruncatedHistory {}
+
+/// A stretch of history the ledger knows it does not have
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HistoryGap {
+    /// Newest signature known to be missing on the far side of the gap, if known
+    pub before: Option<Signature>,
+    /// Oldest slot the ledger does hold on the near side of the gap
+    pub after_slot: u64,
+    pub reason: String,
+    pub recorded_at: u64,
+}
+
+/// One signature from an owner's history
+#[derive(Debug)]
+pub struct HistoryEntry {
+    pub signature: Signature,
+    pub slot: u64,
+    pub block_time: Option<i64>,
+    /// Failed on chain; its transaction is not fetched
+    pub failed: bool,
+    /// `None` for failed transactions; an error when the node would not serve it, e.g. pruned
+    pub transaction: Option<Result<EncodedConfirmedTransactionWithStatusMeta, String>>,
+    /// The node has no older signature for the owner; see `history_completeness`
+    pub oldest_available: bool,
+}
+
+impl HistoryEntry {
+    /// The owner's transfers in this transaction; none if it failed or could not be fetched
+    pub fn transfer_records(&self, owner: &Pubkey) -> Vec<TransactionRecord> {
+        match &self.transaction {
+            Some(Ok(transaction)) => transfer_records(owner, self.signature, transaction),
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// Where the stream has got to
+struct HistoryPager {
+    owner: Pubkey,
+    filter: HistoryFilter,
+    before: Option<Signature>,
+    /// Read from the current signature page, not yet fetched
+    pending: VecDeque<RpcConfirmedTransactionStatusWithSignature>,
+    ready: VecDeque<HistoryEntry>,
+    exhausted: bool,
+    /// The last page read was the node's last, not cut short by the filter
+    reached_oldest: bool,
+    pause: Duration,
+}
+
+impl HistoryPager {
+    /// Wait out a rate limit, longer each time until a call gets through
+    async fn back_off(&mut self, error: &str) {
+        tracing::warn!("History read for {} rate limited, pausing {:?}: {}", readable(&self.owner), self.pause, error);
+        tokio::time::sleep(self.pause).await;
+        self.pause = (self.pause * 2).min(MAX_RATE_LIMIT_PAUSE);
+    }
+}
+
+impl FinternetClient {
+    /// Every signature in `owner`'s history that `filter` admits, newest first
+    ///
+    /// Nothing is read until the stream is polled, and dropping it stops the reads. The stream
+    /// also ends before its next page once the client starts shutting down.
+    pub fn iter_history(&self, owner: &Pubkey, filter: HistoryFilter) -> impl Stream<Item = Result<HistoryEntry>> + '_ {
+        let pager = HistoryPager {
+            owner: *owner,
+            before: filter.before,
+            filter,
+            pending: VecDeque::new(),
+            ready: VecDeque::new(),
+            exhausted: false,
+            reached_oldest: false,
+            pause: INITIAL_RATE_LIMIT_PAUSE,
+        };
+        stream::unfold(Some(pager), move |pager| async move {
+            let mut pager = pager?;
+            if self.tasks.is_shutting_down() && pager.ready.is_empty() && pager.pending.is_empty() {
+                return None;
+            }
+            match self.next_history_entry(&mut pager).await {
+                Ok(Some(entry)) => Some((Ok(entry), Some(pager))),
+                Ok(None) => None,
+                Err(e) => Some((Err(e), None)),
+            }
+        })
+    }
+
+    /// `owner`'s transfers, newest first, across the whole history `filter` admits
+    ///
+    /// Transactions the node will not serve are skipped with a warning.
+    pub fn iter_transaction_history(
+        &self,
+        owner: &Pubkey,
+        filter: HistoryFilter,
+    ) -> impl Stream<Item = Result<TransactionRecord>> + '_ {
+        let owner = *owner;
+        let mint = filter.mint;
+        self.iter_history(&owner, filter).flat_map(move |entry| {
+            let records = match entry {
+                Ok(entry) => {
+                    if let Some(Err(e)) = &entry.transaction {
+                        tracing::warn!("Skipping transaction {} in history of {}: {}", entry.signature, readable(&owner), e);
+                    }
+                    let mut records = entry.transfer_records(&owner);
+                    records.retain(|record| mint.is_none() || record.currency.mint() == mint);
+                    self.attach_memo_fields(&mut records);
+                    records.into_iter().map(Ok).collect()
+                }
+                Err(e) => vec![Err(e)],
+            };
+            stream::iter(records)
+        })
+    }
+
+    async fn next_history_entry(&self, pager: &mut HistoryPager) -> Result<Option<HistoryEntry>> {
+        loop {
+            if let Some(entry) = pager.ready.pop_front() {
+                return Ok(Some(entry));
+            }
+            if !pager.pending.is_empty() {
+                self.fetch_history_batch(pager).await;
+            } else if pager.exhausted {
+                return Ok(None);
+            } else {
+                self.read_signature_page(pager).await?;
+            }
+        }
+    }
+
+    async fn read_signature_page(&self, pager: &mut HistoryPager) -> Result<()> {
+        let page = loop {
+            let read = self.client.get_signatures_for_address_with_config(
+                &pager.owner,
+                GetConfirmedSignaturesForAddress2Config {
+                    before: pager.before,
+                    until: pager.filter.until,
+                    limit: Some(SIGNATURE_PAGE_LIMIT),
+                    commitment: Some(CommitmentConfig::confirmed()),
+                },
+            );
+            match read {
+                Ok(page) => break page,
+                Err(e) if is_rate_limited(&e.to_string()) => pager.back_off(&e.to_string()).await,
+                Err(e) => return Err(e.into()),
+            }
+        };
+        pager.pause = INITIAL_RATE_LIMIT_PAUSE;
+        pager.exhausted = page.len() < SIGNATURE_PAGE_LIMIT;
+        pager.reached_oldest = pager.exhausted && pager.filter.until.is_none();
+        if let Some(last) = page.last() {
+            pager.before = Some(Signature::from_str(&last.signature)?);
+        }
+        let mut oldest_admitted = true;
+        for status in page {
+            if pager.filter.is_past(&status) {
+                pager.exhausted = true;
+                oldest_admitted = false;
+                break;
+            }
+            oldest_admitted = pager.filter.admits(&status);
+            if oldest_admitted {
+                pager.pending.push_back(status);
+            }
+        }
+        // The entry flagged oldest must be the account's oldest, not the oldest in range
+        pager.reached_oldest &= oldest_admitted;
+        Ok(())
+    }
+
+    /// Fetch the next few pending transactions; a rate-limited one and those after it go back
+    /// on the queue, so entries still come out in order
+    async fn fetch_history_batch(&self, pager: &mut HistoryPager) {
+        let batch: Vec<_> = pager.pending.drain(..pager.pending.len().min(FETCH_CONCURRENCY)).collect();
+        let fetched: Vec<Option<Result<EncodedConfirmedTransactionWithStatusMeta, String>>> = std::thread::scope(|scope| {
+            let workers: Vec<_> = batch
+                .iter()
+                .map(|status| {
+                    let failed = status.err.is_some();
+                    scope.spawn(move || {
+                        if failed {
+                            return None;
+                        }
+                        let signature = Signature::from_str(&status.signature).map_err(|e| e.to_string());
+                        Some(signature.and_then(|signature| {
+                            self.client
+                                .get_transaction_with_config(
+                                    &signature,
+                                    RpcTransactionConfig {
+                                        encoding: Some(UiTransactionEncoding::Json),
+                                        commitment: Some(CommitmentConfig::confirmed()),
+                                        max_supported_transaction_version: Some(0),
+                                    },
+                                )
+                                .map_err(|e| e.to_string())
+                        }))
+                    })
+                })
+                .collect();
+            workers
+                .into_iter()
+                .map(|worker| worker.join().unwrap_or_else(|_| Some(Err("Transaction fetch panicked".to_string()))))
+                .collect()
+        });
+
+        let mut rate_limited = None;
+        let last = batch.len().saturating_sub(1);
+        for (index, (status, transaction)) in batch.iter().zip(fetched).enumerate() {
+            if let Some(Err(e)) = &transaction {
+                if is_rate_limited(e) {
+                    rate_limited = Some((index, e.clone()));
+                    break;
+                }
+            }
+            let Ok(signature) = Signature::from_str(&status.signature) else {
+                tracing::warn!("Skipping unparseable signature {} in history of {}", status.signature, readable(&pager.owner));
+                continue;
+            };
+            pager.ready.push_back(HistoryEntry {
+                signature,
+                slot: status.slot,
+                block_time: status.block_time,
+                failed: status.err.is_some(),
+                transaction,
+                oldest_available: index == last && pager.pending.is_empty() && pager.reached_oldest,
+            });
+        }
+
+        match rate_limited {
+            Some((index, e)) => {
+                for status in batch.into_iter().skip(index).rev() {
+                    pager.pending.push_front(status);
+                }
+                pager.back_off(&e).await;
+            }
+            None => pager.pause = INITIAL_RATE_LIMIT_PAUSE,
+        }
+    }
+}
+
+impl FinternetClient {
+    /// How complete `owner`'s history is, given the oldest entry read from it
+    ///
+    /// Only an entry with `oldest_available` can show the history complete or truncated; for
+    /// any other the read stopped early and the answer is `Unknown`. The entry's transaction is
+    /// fetched if the stream did not.
+    pub fn history_completeness(&self, owner: &Pubkey, oldest: &HistoryEntry) -> HistoryCompleteness {
+        if !oldest.oldest_available {
+            return HistoryCompleteness::Unknown;
+        }
+        let funded = match &oldest.transaction {
+            Some(Ok(transaction)) => first_funding(owner, transaction),
+            _ => self.fetch_first_funding(owner, &oldest.signature),
+        };
+        completeness_of(funded, oldest.slot)
+    }
+
+    /// `history_completeness` for the oldest of a signature list read straight from RPC
+    pub(crate) fn completeness_of_signatures(
+        &self,
+        owner: &Pubkey,
+        signatures: &[RpcConfirmedTransactionStatusWithSignature],
+        reached_oldest: bool,
+    ) -> HistoryCompleteness {
+        if !reached_oldest {
+            return HistoryCompleteness::Unknown;
+        }
+        // No history at all: nothing has happened to the account that could be missing
+        let Some(oldest) = signatures.last() else {
+            return HistoryCompleteness::Complete;
+        };
+        let funded = Signature::from_str(&oldest.signature)
+            .ok()
+            .and_then(|signature| self.fetch_first_funding(owner, &signature));
+        completeness_of(funded, oldest.slot)
+    }
+
+    fn fetch_first_funding(&self, owner: &Pubkey, signature: &Signature) -> Option<bool> {
+        let transaction = self
+            .client
+            .get_transaction_with_config(
+                signature,
+                RpcTransactionConfig {
+                    encoding: Some(UiTransactionEncoding::Json),
+                    commitment: Some(CommitmentConfig::confirmed()),
+                    max_supported_transaction_version: Some(0),
+                },
+            )
+            .inspect_err(|e| tracing::warn!("Could not fetch oldest transaction {} of {}: {}", signature, readable(owner), e))
+            .ok()?;
+        first_funding(owner, &transaction)
+    }
+}
+
+/// One record per mint whose balance `owner` saw change in the transaction
+///
+/// The counterparty is the owner whose balance of the same mint moved the other way the most;
+/// when there is none (mints, burns) it is the default pubkey.
+pub(crate) fn transfer_records(
+    owner: &Pubkey,
+    signature: Signature,
+    transaction: &EncodedConfirmedTransactionWithStatusMeta,
+) -> Vec<TransactionRecord> {
+    let Some(meta) = &transaction.transaction.meta else {
+        return Vec::new();
+    };
+    if meta.err.is_some() {
+        return Vec::new();
+    }
+    let (OptionSerializer::Some(pre), OptionSerializer::Some(post)) = (&meta.pre_token_balances, &meta.post_token_balances)
+    else {
+        return Vec::new();
+    };
+
+    // Net change per (mint, holder), from every token account that appears on either side
+    let mut deltas: BTreeMap<(String, String), i128> = BTreeMap::new();
+    let mut decimals: HashMap<&str, u8> = HashMap::new();
+    for (balances, sign) in [(pre, -1i128), (post, 1i128)] {
+        for balance in balances {
+            decimals.insert(&balance.mint, balance.ui_token_amount.decimals);
+            let OptionSerializer::Some(holder) = &balance.owner else { continue };
+            let amount = balance.ui_token_amount.amount.parse::<u64>().unwrap_or(0) as i128;
+            *deltas.entry((balance.mint.clone(), holder.clone())).or_default() += sign * amount;
+        }
+    }
+
+    let owner_str = owner.to_string();
+    let memos = extract_memos(transaction);
+    let memo = (!memos.is_empty()).then(|| memos.join("; "));
+
+    let mut records = Vec::new();
+    for ((mint, holder), delta) in &deltas {
+        if holder != &owner_str || *delta == 0 {
+            continue;
+        }
+        let Ok(mint_key) = Pubkey::from_str(mint) else { continue };
+        let counterparty = deltas
+            .iter()
+            .filter(|((m, h), d)| m == mint && h != holder && d.signum() == -delta.signum())
+            .max_by_key(|(_, d)| d.abs())
+            .and_then(|((_, h), _)| Pubkey::from_str(h).ok())
+            .unwrap_or_default();
+        let (from, to) = if *delta < 0 { (*owner, counterparty) } else { (counterparty, *owner) };
+
+        records.push(TransactionRecord {
+            signature,
+            from,
+            to,
+            amount: delta.unsigned_abs() as u64,
+            currency: Currency::spl(mint_key, decimals.get(mint.as_str()).copied().unwrap_or(0)),
+            timestamp: transaction.block_time.unwrap_or(0).max(0) as u64,
+            memo: memo.clone(),
+            fee: meta.fee,
+            slot: transaction.slot,
+            memo_fields: HashMap::new(),
+        });
+    }
+    records
+}
+
+fn completeness_of(funded: Option<bool>, slot: u64) -> HistoryCompleteness {
+    match funded {
+        Some(true) => HistoryCompleteness::Complete,
+        Some(false) => HistoryCompleteness::TruncatedAt(slot),
+        None => HistoryCompleteness::Unknown,
+    }
+}
+
+/// Whether `transaction` first funded `owner`, i.e. it held no lamports before; `None` when the
+/// transaction does not say
+fn first_funding(owner: &Pubkey, transaction: &EncodedConfirmedTransactionWithStatusMeta) -> Option<bool> {
+    let EncodedTransaction::Json(ui) = &transaction.transaction.transaction else {
+        return None;
+    };
+    let keys = match &ui.message {
+        UiMessage::Raw(message) => &message.account_keys,
+        UiMessage::Parsed(_) => return None,
+    };
+    let owner = owner.to_string();
+    let index = keys.iter().position(|key| *key == owner)?;
+    let meta = transaction.transaction.meta.as_ref()?;
+    meta.pre_balances.get(index).map(|lamports| *lamports == 0)
+}
+
+fn is_rate_limited(error: &str) -> bool {
+    let error = error.to_lowercase();
+    error.contains("429") || error.contains("too many requests")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_cluster::MockCluster;
+    use crate::rpc_options::RpcCallOptions;
+    use serde_json::{json, Value};
+    use std::collections::HashMap;
+
+    /// 2024-03-04, UTC
+    const IN_MARCH: i64 = 1_709_500_000;
+
+    /// An incoming transfer in a scripted history
+    struct Transfer {
+        signature: Signature,
+        slot: u64,
+        /// Owner's lamports before it; 0 marks the transaction that created the account
+        lamports_before: u64,
+        tokens_before: u64,
+        amount: u64,
+    }
+
+    /// The owner's whole history, newest first: three deposits of a 6-decimal mint, the oldest
+    /// of which funded the account
+    fn history() -> Vec<Transfer> {
+        [(3_000, 2_000_000, 30, 15), (2_000, 2_000_000, 10, 20), (1_000, 0, 0, 10)]
+            .into_iter()
+            .map(|(slot, lamports_before, tokens_before, amount)| Transfer {
+                signature: Signature::new_unique(),
+                slot,
+                lamports_before,
+                tokens_before,
+                amount,
+            })
+            .collect()
+    }
+
+    fn token_balance(index: u8, mint: &Pubkey, owner: &Pubkey, amount: u64) -> Value {
+        json!({
+            "accountIndex": index,
+            "mint": mint.to_string(),
+            "owner": owner.to_string(),
+            "programId": spl_token::id().to_string(),
+            "uiTokenAmount": {
+                "uiAmount": null,
+                "decimals": 6,
+                "amount": amount.to_string(),
+                "uiAmountString": amount.to_string(),
+            },
+        })
+    }
+
+    /// Serve `served` of `owner`'s history as a node that has pruned everything older, with
+    /// `getSignaturesForAddress` honouring its cursors and `getTransaction` the JSON encoding
+    fn serve(cluster: &MockCluster, owner: Pubkey, mint: Pubkey, served: Vec<Transfer>, first_available_block: u64) {
+        let sender = Pubkey::new_unique();
+        let statuses: Vec<(String, Value)> = served
+            .iter()
+            .map(|transfer| {
+                let status = json!({
+                    "signature": transfer.signature.to_string(),
+                    "slot": transfer.slot,
+                    "err": null,
+                    "memo": null,
+                    "blockTime": IN_MARCH + transfer.slot as i64,
+                    "confirmationStatus": "finalized",
+                });
+                (transfer.signature.to_string(), status)
+            })
+            .collect();
+        cluster.respond("getSignaturesForAddress", move |params| {
+            if params[0].as_str()? != owner.to_string() {
+                return None;
+            }
+            let config = &params[1];
+            let mut page: Vec<Value> = statuses.iter().map(|(_, status)| status.clone()).collect();
+            if let Some(before) = config["before"].as_str() {
+                let start = statuses.iter().position(|(signature, _)| signature == before).map_or(page.len(), |i| i + 1);
+                page.drain(..start);
+            }
+            if let Some(until) = config["until"].as_str() {
+                if let Some(end) = page.iter().position(|status| status["signature"] == until) {
+                    page.truncate(end);
+                }
+            }
+            page.truncate(config["limit"].as_u64().unwrap_or(1000) as usize);
+            Some(Ok(Value::Array(page)))
+        });
+
+        let transactions: HashMap<String, Value> = served
+            .iter()
+            .map(|transfer| {
+                let after = transfer.tokens_before + transfer.amount;
+                let transaction = json!({
+                    "slot": transfer.slot,
+                    "blockTime": IN_MARCH + transfer.slot as i64,
+                    "transaction": {
+                        "signatures": [transfer.signature.to_string()],
+                        "message": {
+                            "header": { "numRequiredSignatures": 1, "numReadonlySignedAccounts": 0, "numReadonlyUnsignedAccounts": 0 },
+                            "accountKeys": [
+                                sender.to_string(),
+                                owner.to_string(),
+                                Pubkey::new_unique().to_string(),
+                                Pubkey::new_unique().to_string(),
+                            ],
+                            "recentBlockhash": solana_sdk::hash::Hash::default().to_string(),
+                            "instructions": [],
+                        },
+                    },
+                    "meta": {
+                        "err": null,
+                        "status": { "Ok": null },
+                        "fee": 5_000,
+                        "preBalances": [1_000_000_000, transfer.lamports_before, 2_039_280, 2_039_280],
+                        "postBalances": [999_995_000, transfer.lamports_before.max(2_000_000), 2_039_280, 2_039_280],
+                        "preTokenBalances": [
+                            token_balance(2, &mint, &sender, 1_000),
+                            token_balance(3, &mint, &owner, transfer.tokens_before),
+                        ],
+                        "postTokenBalances": [
+                            token_balance(2, &mint, &sender, 1_000 - transfer.amount),
+                            token_balance(3, &mint, &owner, after),
+                        ],
+                    },
+                });
+                (transfer.signature.to_string(), transaction)
+            })
+            .collect();
+        cluster.respond("getTransaction", move |params| transactions.get(params[0].as_str()?).cloned().map(Ok));
+        cluster.respond("getFirstAvailableBlock", move |_| Some(Ok(json!(first_available_block))));
+    }
+
+    /// A node serving the whole history, and one that pruned the transaction creating the account
+    fn nodes() -> [(MockCluster, Pubkey, Pubkey, u64); 2] {
+        [false, true].map(|pruned| {
+            let (cluster, owner, mint) = (MockCluster::new(), Pubkey::new_unique(), Pubkey::new_unique());
+            let mut served = history();
+            let first_available_block = if pruned {
+                served.pop();
+                2_000
+            } else {
+                0
+            };
+            let oldest = served.last().unwrap().slot;
+            serve(&cluster, owner, mint, served, first_available_block);
+            (cluster, owner, mint, oldest)
+        })
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn a_pruned_node_marks_the_history_truncated_at_its_oldest_slot() {
+        let [(full, owner, _, _), (pruned, pruned_owner, _, oldest)] = nodes();
+
+        let history = full
+            .default_client()
+            .get_transaction_history_with_completeness(&owner, None, &RpcCallOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(history.records.len(), 3);
+        assert_eq!(history.completeness, HistoryCompleteness::Complete);
+
+        let client = pruned.default_client();
+        let history = client
+            .get_transaction_history_with_completeness(&pruned_owner, None, &RpcCallOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(history.records.len(), 2);
+        assert_eq!(history.completeness, HistoryCompleteness::TruncatedAt(oldest));
+        assert_eq!(history.completeness.header_value(), "truncated_at=2000");
+
+        // A read cut short by its limit never reached the oldest end, so cannot tell
+        let partial = client
+            .get_transaction_history_with_completeness(&pruned_owner, Some(2), &RpcCallOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(partial.completeness, HistoryCompleteness::Unknown);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn only_the_oldest_streamed_entry_decides_completeness() {
+        for (cluster, owner, _, oldest) in nodes() {
+            let client = cluster.default_client();
+            let entries: Vec<HistoryEntry> =
+                client.iter_history(&owner, HistoryFilter::default()).map(Result::unwrap).collect().await;
+            let (last, rest) = entries.split_last().unwrap();
+            assert!(last.oldest_available && rest.iter().all(|entry| !entry.oldest_available));
+            assert_eq!(last.slot, oldest);
+            let expected = if oldest == 1_000 { HistoryCompleteness::Complete } else { HistoryCompleteness::TruncatedAt(oldest) };
+            assert_eq!(client.history_completeness(&owner, last), expected);
+            assert_eq!(client.history_completeness(&owner, &rest[0]), HistoryCompleteness::Unknown);
+        }
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn a_statement_over_pruned_history_refuses_an_opening_balance_unless_partial_is_allowed() {
+        use crate::local_ledger::LocalLedger;
+        use crate::mock_cluster::TempDir;
+        use crate::statement::YearMonth;
+        let [(full, owner, _, _), (pruned, pruned_owner, _, _)] = nodes();
+        let dir = TempDir::new("history");
+        let march = YearMonth::new(2024, 3).unwrap();
+
+        let ledger = LocalLedger::open(&dir.join("full.db")).unwrap();
+        let client = full.default_client();
+        let report = client.sync_local_ledger(&ledger, &owner).await.unwrap();
+        assert_eq!(report.completeness, HistoryCompleteness::Complete);
+        let statement = client.generate_statement(&ledger, &owner, march, None, None, false).await.unwrap();
+        assert!(statement.completeness.is_complete());
+        assert!(!statement.mints[0].opening_balance_unverified);
+
+        let ledger = LocalLedger::open(&dir.join("pruned.db")).unwrap();
+        let client = pruned.default_client();
+        let report = client.sync_local_ledger(&ledger, &pruned_owner).await.unwrap();
+        assert_eq!(report.completeness, HistoryCompleteness::TruncatedAt(2_000));
+        assert!(report.gaps.iter().any(|gap| gap.reason.contains("no history before slot 2000")), "{:?}", report.gaps);
+        assert_eq!(ledger.completeness(&pruned_owner).unwrap(), HistoryCompleteness::TruncatedAt(2_000));
+
+        let error = client.generate_statement(&ledger, &pruned_owner, march, None, None, false).await.unwrap_err();
+        let truncated = error.downcast_ref::<TruncatedHistory>().unwrap();
+        assert_eq!((truncated.owner, truncated.slot), (pruned_owner, 2_000));
+        assert!(truncated.needed_for.starts_with("opening balance"), "{}", truncated);
+        assert_eq!(crate::error_code::FinternetError::of(&error), crate::error_code::FinternetError::HistoryTruncated);
+
+        let partial = client.generate_statement(&ledger, &pruned_owner, march, None, None, true).await.unwrap();
+        assert_eq!(partial.completeness, HistoryCompleteness::TruncatedAt(2_000));
+        assert!(partial.mints[0].opening_balance_unverified);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn reconciliation_reports_a_window_the_node_no_longer_covers() {
+        let [(full, owner, _, _), (pruned, pruned_owner, _, _)] = nodes();
+        let window = 0..u64::MAX;
+
+        let report = full.default_client().reconcile(Vec::new(), &owner, window.clone()).await.unwrap();
+        assert!(report.complete);
+        assert_eq!(report.unexpected.len(), 3);
+
+        let report = pruned.default_client().reconcile(Vec::new(), &pruned_owner, window).await.unwrap();
+        assert!(!report.complete);
+        assert_eq!(report.completeness, HistoryCompleteness::TruncatedAt(2_000));
+        assert_eq!(report.unexpected.len(), 2);
+    }
+
+    #[test]
+    fn completeness_serializes_with_its_slot() {
+        assert_eq!(serde_json::to_value(HistoryCompleteness::TruncatedAt(7)).unwrap(), json!({ "status": "truncated_at", "slot": 7 }));
+        assert_eq!(serde_json::to_value(HistoryCompleteness::Complete).unwrap(), json!({ "status": "complete" }));
+        assert_eq!(HistoryCompleteness::default(), HistoryCompleteness::Unknown);
+        assert_eq!(HistoryCompleteness::TruncatedAt(7).to_string(), "truncated at slot 7");
+    }
+}
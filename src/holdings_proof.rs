@@ -0,0 +1,612 @@
+//! Proof, for a third party, of what a wallet held at a slot
+//!
+//! `generate_holdings_proof` reads every account the wallet holds in the given mints in a
+//! single RPC call, so they all come from one slot, and keeps each account's data exactly as
+//! the node returned it. The wallet signs the bundle, which proves control of the address
+//! without handing over a key. A verifier's challenge can be signed in too, so an old proof
+//! cannot be passed off as a fresh one.
+//!
+//! `HoldingsProof::check` needs no RPC: it checks the signature and that every account's raw
+//! data says what the bundle claims. `verify_holdings_proof` runs that check against the
+//! verifier's own RPC node, then makes sure the proof comes from the same cluster and that
+//! its slot matches its capture time. It then re-reads the accounts. RPC nodes do not serve
+//! account state at a past slot, so the accounts are compared with their current state, and
+//! any change since the proof's slot is reported as drift rather than failure.
+
+use crate::telemetry::readable;
+use crate::token_account::decode_token_account;
+use crate::FinternetClient;
+use anyhow::{anyhow, Result};
+use base64::{engine::general_purpose, Engine};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use solana_account_decoder::{UiAccountData, UiAccountEncoding};
+use solana_client::rpc_config::RpcAccountInfoConfig;
+use solana_client::rpc_request::TokenAccountsFilter;
+use solana_sdk::{account::Account, pubkey::Pubkey, signature::Keypair, signer::Signer};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const PROOF_VERSION: u32 = 1;
+/// The wallet signs this followed by the proof's hash
+const PROOF_SIGNING_PREFIX: &str = "finternet-holdings-proof:";
+/// Most accounts one `getMultipleAccounts` call returns, and so one proof covers
+pub const MAX_PROVEN_ACCOUNTS: usize = 100;
+/// How far the cluster's time for a proof's slot may be from its `captured_at`
+pub const MAX_SLOT_TIME_SKEW_SECS: u64 = 300;
+
+/// One token account as the RPC node returned it at the proof's slot
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProvenAccount {
+    pub address: Pubkey,
+    /// The token program that owns the account
+    pub program: Pubkey,
+    pub mint: Pubkey,
+    pub amount: u64,
+    /// The account's data, base64, as the node returned it
+    pub data: String,
+}
+
+/// What a wallet held in some mints at one slot, signed by the wallet; see the module
+/// documentation
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HoldingsProof {
+    pub version: u32,
+    pub wallet: Pubkey,
+    /// The cluster the accounts were read from
+    pub genesis_hash: String,
+    pub slot: u64,
+    pub captured_at: u64,
+    /// Every mint asked about, including those the wallet holds none of
+    pub mints: Vec<Pubkey>,
+    pub accounts: Vec<ProvenAccount>,
+    /// Supplied by the verifier, so the proof is known to be made for them
+    pub challenge: Option<String>,
+    /// The wallet's off-chain message signature over the hash of every other field
+    pub signature: String,
+}
+
+impl HoldingsProof {
+    pub fn load(path: &Path) -> Result<Self> {
+        let data = fs::read_to_string(path)?;
+        serde_json::from_str(&data).map_err(|e| anyhow!("Invalid holdings proof {}: {}", path.display(), e))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            if !parent.as_os_str().is_empty() {
+                fs::create_dir_all(parent)?;
+            }
+        }
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// What the proof claims `wallet` held of `mint`, in base units
+    pub fn total(&self, mint: &Pubkey) -> u64 {
+        self.accounts
+            .iter()
+            .filter(|account| account.mint == *mint)
+            .fold(0u64, |total, account| total.saturating_add(account.amount))
+    }
+
+    /// The message the wallet signs: the prefix and the hex SHA-256 of the proof without its
+    /// signature
+    fn signing_message(&self) -> Result<String> {
+        let unsigned = HoldingsProof { signature: String::new(), ..self.clone() };
+        Ok(format!("{}{}", PROOF_SIGNING_PREFIX, hex_sha256(serde_json::to_string(&unsigned)?.as_bytes())))
+    }
+
+    /// Everything wrong with the proof that can be told without an RPC node; empty when its
+    /// signature holds and every account's data says what the proof claims
+    pub fn check(&self) -> Vec<ProofFailure> {
+        if self.version != PROOF_VERSION {
+            return vec![ProofFailure::UnsupportedVersion { version: self.version }];
+        }
+        let mut failures = Vec::new();
+        let signed = self
+            .signing_message()
+            .and_then(|message| FinternetClient::verify_offchain_message(&self.wallet, &message, &self.signature));
+        if !signed.unwrap_or(false) {
+            failures.push(ProofFailure::BadSignature);
+        }
+        for account in &self.accounts {
+            if let Err(reason) = check_account(self, account) {
+                failures.push(ProofFailure::AccountMismatch { address: account.address, reason });
+            }
+        }
+        failures
+    }
+}
+
+impl fmt::Display for HoldingsProof {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Holdings of {} at slot {}", self.wallet, self.slot)?;
+        for mint in &self.mints {
+            let accounts = self.accounts.iter().filter(|account| account.mint == *mint).count();
+            writeln!(f, "  {}: {} in {} account(s)", mint, self.total(mint), accounts)?;
+        }
+        if let Some(challenge) = &self.challenge {
+            writeln!(f, "  Challenge: {}", challenge)?;
+        }
+        write!(f, "  Captured:  {}", self.captured_at)
+    }
+}
+
+/// Whether `account`'s raw data decodes to the mint, owner, and amount `proof` claims
+fn check_account(proof: &HoldingsProof, account: &ProvenAccount) -> std::result::Result<(), String> {
+    if account.program != spl_token::id() && account.program != spl_token_2022::id() {
+        return Err(format!("owned by {}, not a token program", account.program));
+    }
+    if !proof.mints.contains(&account.mint) {
+        return Err(format!("mint {} is not one the proof covers", account.mint));
+    }
+    let data = UiAccountData::Binary(account.data.clone(), UiAccountEncoding::Base64);
+    let decoded = decode_token_account(&data).map_err(|e| e.to_string())?;
+    if decoded.owner != proof.wallet {
+        return Err(format!("the data names {} as owner", decoded.owner));
+    }
+    if decoded.mint != account.mint {
+        return Err(format!("the data is for mint {}", decoded.mint));
+    }
+    if decoded.amount != account.amount {
+        return Err(format!("the data holds {}, not {}", decoded.amount, account.amount));
+    }
+    Ok(())
+}
+
+/// Why a holdings proof cannot be relied on
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "failure", rename_all = "snake_case")]
+pub enum ProofFailure {
+    UnsupportedVersion { version: u32 },
+    /// The wallet's signature does not hold over the proof
+    BadSignature,
+    /// An account's raw data does not say what the proof claims about it
+    AccountMismatch { address: Pubkey, reason: String },
+    /// The proof was read from another cluster than the verifier's
+    WrongNetwork { expected: String, actual: String },
+    /// The verifier's node has not reached the proof's slot
+    FutureSlot { slot: u64, current_slot: u64 },
+    /// The cluster's time for the proof's slot is far from when the proof says it was captured
+    SlotTimeMismatch { slot_time: u64, captured_at: u64 },
+    ChallengeMismatch { expected: String, actual: Option<String> },
+}
+
+impl ProofFailure {
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::UnsupportedVersion { .. } => "unsupported_proof_version",
+            Self::BadSignature => "bad_proof_signature",
+            Self::AccountMismatch { .. } => "proof_account_mismatch",
+            Self::WrongNetwork { .. } => "wrong_network",
+            Self::FutureSlot { .. } => "future_slot",
+            Self::SlotTimeMismatch { .. } => "slot_time_mismatch",
+            Self::ChallengeMismatch { .. } => "challenge_mismatch",
+        }
+    }
+}
+
+impl fmt::Display for ProofFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnsupportedVersion { version } => write!(f, "Unsupported holdings proof version {}", version),
+            Self::BadSignature => write!(f, "The wallet's signature does not match the proof"),
+            Self::AccountMismatch { address, reason } => write!(f, "Account {} does not match the proof: {}", address, reason),
+            Self::WrongNetwork { expected, actual } => {
+                write!(f, "The proof is from the cluster with genesis hash {}, not {}", actual, expected)
+            }
+            Self::FutureSlot { slot, current_slot } => {
+                write!(f, "The proof's slot {} is ahead of the cluster's current slot {}", slot, current_slot)
+            }
+            Self::SlotTimeMismatch { slot_time, captured_at } => write!(
+                f,
+                "The proof's slot was produced at {}, but the proof says it was captured at {}",
+                slot_time, captured_at
+            ),
+            Self::ChallengeMismatch { expected, actual: Some(actual) } => {
+                write!(f, "The proof answers challenge '{}', not '{}'", actual, expected)
+            }
+            Self::ChallengeMismatch { expected, actual: None } => {
+                write!(f, "The proof answers no challenge; expected '{}'", expected)
+            }
+        }
+    }
+}
+
+/// How a proven account differs from its current state
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "drift", rename_all = "snake_case")]
+pub enum AccountDrift {
+    /// The account's data is the same as at the proof's slot
+    Unchanged,
+    /// Still the wallet's, but its data changed
+    Changed { amount: u64 },
+    /// The account now belongs to another wallet
+    Transferred { owner: Pubkey },
+    Closed,
+}
+
+/// One proven account, checked against the cluster
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AccountVerification {
+    pub address: Pubkey,
+    pub mint: Pubkey,
+    /// Held at the proof's slot
+    pub proven_amount: u64,
+    pub drift: AccountDrift,
+}
+
+/// What `verify_holdings_proof` found
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProofVerification {
+    pub wallet: Pubkey,
+    pub slot: u64,
+    /// The slot the accounts were read again at
+    pub checked_at_slot: u64,
+    /// The cluster's time for the proof's slot; `None` when the node could not say
+    pub slot_time: Option<u64>,
+    /// Empty for a proof that can be relied on
+    pub failures: Vec<ProofFailure>,
+    pub accounts: Vec<AccountVerification>,
+}
+
+impl ProofVerification {
+    pub fn is_valid(&self) -> bool {
+        self.failures.is_empty()
+    }
+
+    /// Whether the proof is valid and shows at least `amount` base units of `mint` at its slot
+    pub fn proves_at_least(&self, mint: &Pubkey, amount: u64) -> bool {
+        let proven = self
+            .accounts
+            .iter()
+            .filter(|account| account.mint == *mint)
+            .fold(0u64, |total, account| total.saturating_add(account.proven_amount));
+        self.is_valid() && proven >= amount
+    }
+
+    /// Whether any proven account has changed since the proof's slot
+    pub fn has_drift(&self) -> bool {
+        self.accounts.iter().any(|account| account.drift != AccountDrift::Unchanged)
+    }
+}
+
+impl FinternetClient {
+    /// Prove what `wallet` holds of each of `mints` now; see the module documentation
+    pub async fn generate_holdings_proof(&self, wallet: &Keypair, mints: &[Pubkey]) -> Result<HoldingsProof> {
+        self.generate_holdings_proof_for(wallet, mints, None).await
+    }
+
+    /// `generate_holdings_proof`, with a verifier's `challenge` signed into the proof
+    #[tracing::instrument(skip_all, fields(wallet = %readable(&wallet.pubkey()), mints = mints.len()))]
+    pub async fn generate_holdings_proof_for(
+        &self,
+        wallet: &Keypair,
+        mints: &[Pubkey],
+        challenge: Option<&str>,
+    ) -> Result<HoldingsProof> {
+        let owner = wallet.pubkey();
+        let mut addresses = Vec::new();
+        for mint in mints {
+            let accounts = self.client.get_token_accounts_by_owner(&owner, TokenAccountsFilter::Mint(*mint))?;
+            for account in accounts {
+                addresses.push(Pubkey::from_str(&account.pubkey)?);
+            }
+        }
+        if addresses.len() > MAX_PROVEN_ACCOUNTS {
+            return Err(anyhow!(
+                "{} holds {} accounts in these mints; one proof covers at most {}, so prove fewer mints at a time",
+                owner,
+                addresses.len(),
+                MAX_PROVEN_ACCOUNTS
+            ));
+        }
+
+        // One read, so every account comes from the same slot
+        let (slot, accounts) = self.read_accounts(&addresses, None)?;
+        let mut proven = Vec::with_capacity(addresses.len());
+        for (address, account) in addresses.into_iter().zip(accounts) {
+            // Closed or handed over between the two reads
+            let Some(account) = account else { continue };
+            let data = general_purpose::STANDARD.encode(&account.data);
+            let decoded = decode_token_account(&UiAccountData::Binary(data.clone(), UiAccountEncoding::Base64))
+                .map_err(|e| anyhow!("Token account {} could not be read: {}", address, e))?;
+            if decoded.owner != owner {
+                continue;
+            }
+            proven.push(ProvenAccount { address, program: account.owner, mint: decoded.mint, amount: decoded.amount, data });
+        }
+
+        let mut proof = HoldingsProof {
+            version: PROOF_VERSION,
+            wallet: owner,
+            genesis_hash: self.client.get_genesis_hash()?.to_string(),
+            slot,
+            captured_at: unix_now(),
+            mints: mints.to_vec(),
+            accounts: proven,
+            challenge: challenge.map(str::to_string),
+            signature: String::new(),
+        };
+        proof.signature = Self::sign_offchain_message(wallet, &proof.signing_message()?)?;
+        tracing::info!("Holdings of {} proven at slot {} over {} accounts", owner, slot, proof.accounts.len());
+        Ok(proof)
+    }
+
+    /// Check `proof` against this client's cluster; see the module documentation
+    ///
+    /// With `challenge`, the proof must have been made for it.
+    #[tracing::instrument(skip_all, fields(wallet = %readable(&proof.wallet), slot = proof.slot))]
+    pub async fn verify_holdings_proof(&self, proof: &HoldingsProof, challenge: Option<&str>) -> Result<ProofVerification> {
+        let mut failures = proof.check();
+        if let Some(expected) = challenge {
+            if proof.challenge.as_deref() != Some(expected) {
+                failures.push(ProofFailure::ChallengeMismatch { expected: expected.to_string(), actual: proof.challenge.clone() });
+            }
+        }
+
+        let genesis_hash = self.client.get_genesis_hash()?.to_string();
+        if genesis_hash != proof.genesis_hash {
+            failures.push(ProofFailure::WrongNetwork { expected: genesis_hash, actual: proof.genesis_hash.clone() });
+        }
+        let current_slot = self.client.get_slot()?;
+        if proof.slot > current_slot {
+            failures.push(ProofFailure::FutureSlot { slot: proof.slot, current_slot });
+        }
+        let slot_time = match self.slot_to_time(proof.slot).await {
+            Ok(time) => Some(time.timestamp().max(0) as u64),
+            Err(e) => {
+                tracing::warn!("No time for slot {}: {}", proof.slot, e);
+                None
+            }
+        };
+        if let Some(slot_time) = slot_time {
+            if slot_time.abs_diff(proof.captured_at) > MAX_SLOT_TIME_SKEW_SECS {
+                failures.push(ProofFailure::SlotTimeMismatch { slot_time, captured_at: proof.captured_at });
+            }
+        }
+
+        let addresses: Vec<Pubkey> = proof.accounts.iter().map(|account| account.address).collect();
+        let min_context_slot = (proof.slot <= current_slot).then_some(proof.slot);
+        let (checked_at_slot, current) = if addresses.is_empty() {
+            (current_slot, Vec::new())
+        } else {
+            self.read_accounts(&addresses, min_context_slot)?
+        };
+        let accounts = proof
+            .accounts
+            .iter()
+            .zip(current)
+            .map(|(proven, current)| AccountVerification {
+                address: proven.address,
+                mint: proven.mint,
+                proven_amount: proven.amount,
+                drift: drift(proof, proven, current),
+            })
+            .collect();
+
+        Ok(ProofVerification { wallet: proof.wallet, slot: proof.slot, checked_at_slot, slot_time, failures, accounts })
+    }
+
+    /// `addresses` in one `getMultipleAccounts` call, with the slot it was served at
+    fn read_accounts(&self, addresses: &[Pubkey], min_context_slot: Option<u64>) -> Result<(u64, Vec<Option<Account>>)> {
+        let config = RpcAccountInfoConfig {
+            encoding: Some(UiAccountEncoding::Base64),
+            commitment: Some(self.client.commitment()),
+            min_context_slot,
+            ..RpcAccountInfoConfig::default()
+        };
+        let response = self.client.get_multiple_accounts_with_config(addresses, config)?;
+        Ok((response.context.slot, response.value))
+    }
+}
+
+/// How `proven` differs from `current`, the same account read now
+fn drift(proof: &HoldingsProof, proven: &ProvenAccount, current: Option<Account>) -> AccountDrift {
+    let Some(current) = current else {
+        return AccountDrift::Closed;
+    };
+    let data = general_purpose::STANDARD.encode(&current.data);
+    if data == proven.data && current.owner == proven.program {
+        return AccountDrift::Unchanged;
+    }
+    match decode_token_account(&UiAccountData::Binary(data, UiAccountEncoding::Base64)) {
+        Ok(decoded) if decoded.owner != proof.wallet => AccountDrift::Transferred { owner: decoded.owner },
+        Ok(decoded) => AccountDrift::Changed { amount: decoded.amount },
+        // Closed and reused for something else
+        Err(_) => AccountDrift::Closed,
+    }
+}
+
+fn hex_sha256(bytes: &[u8]) -> String {
+    Sha256::digest(bytes).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_cluster::{MockCluster, TempDir};
+    use solana_sdk::hash::Hash;
+
+    /// 12,000 of a 6-decimal dollar token, in base units
+    const HELD: u64 = 12_000_000_000;
+    /// What the lender asks to see
+    const REQUIRED: u64 = 10_000_000_000;
+
+    /// A cluster at the current time where `wallet` holds `HELD` of a dollar token and one
+    /// asset token, and a client of it
+    struct Setup {
+        cluster: MockCluster,
+        client: FinternetClient,
+        wallet: Keypair,
+        dollars: Pubkey,
+        asset: Pubkey,
+    }
+
+    impl Setup {
+        fn new() -> Self {
+            let cluster = MockCluster::new();
+            cluster.set_time(unix_now() as i64);
+            let wallet = Keypair::new();
+            let dollars = cluster.add_mint(&spl_token::id(), 6, &Pubkey::new_unique());
+            let asset = cluster.add_mint(&spl_token_2022::id(), 0, &Pubkey::new_unique());
+            cluster.add_token_account(&wallet.pubkey(), &dollars, HELD);
+            cluster.add_token_account(&wallet.pubkey(), &asset, 1);
+            let client = cluster.default_client();
+            Self { cluster, client, wallet, dollars, asset }
+        }
+
+        async fn prove(&self, challenge: Option<&str>) -> HoldingsProof {
+            self.client.generate_holdings_proof_for(&self.wallet, &[self.dollars, self.asset], challenge).await.unwrap()
+        }
+
+        /// A separate client of the same cluster, as the lender would run
+        fn verifier(&self) -> FinternetClient {
+            self.cluster.default_client()
+        }
+    }
+
+    fn codes(failures: &[ProofFailure]) -> Vec<&'static str> {
+        failures.iter().map(ProofFailure::code).collect()
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn a_third_party_verifies_a_saved_proof_with_its_own_client() {
+        let setup = Setup::new();
+        let dir = TempDir::new("holdings-proof");
+        let proof = setup.prove(Some("lender-nonce-42")).await;
+        assert_eq!(proof.slot, 1_000);
+        assert_eq!((proof.total(&setup.dollars), proof.total(&setup.asset)), (HELD, 1));
+        assert_eq!(proof.accounts.len(), 2);
+        assert!(proof.check().is_empty());
+        assert!(proof.to_string().contains("Challenge: lender-nonce-42"));
+        // Every account came from the single read
+        assert_eq!(setup.cluster.call_count("getMultipleAccounts"), 1);
+
+        proof.save(&dir.join("proof.json")).unwrap();
+        let received = HoldingsProof::load(&dir.join("proof.json")).unwrap();
+        assert_eq!(received, proof);
+
+        let verification = setup.verifier().verify_holdings_proof(&received, Some("lender-nonce-42")).await.unwrap();
+        assert!(verification.is_valid(), "{:?}", verification.failures);
+        assert!(verification.proves_at_least(&setup.dollars, REQUIRED));
+        assert!(verification.proves_at_least(&setup.asset, 1));
+        assert!(!verification.proves_at_least(&setup.dollars, HELD + 1));
+        assert!(!verification.has_drift());
+        assert_eq!((verification.wallet, verification.slot), (setup.wallet.pubkey(), 1_000));
+        assert!(verification.slot_time.is_some());
+
+        // A proof made for someone else's challenge, or none, is not taken as fresh
+        let verification = setup.verifier().verify_holdings_proof(&received, Some("another-nonce")).await.unwrap();
+        assert_eq!(codes(&verification.failures), ["challenge_mismatch"]);
+        assert!(!verification.proves_at_least(&setup.dollars, REQUIRED));
+        let unchallenged = setup.prove(None).await;
+        let verification = setup.verifier().verify_holdings_proof(&unchallenged, Some("lender-nonce-42")).await.unwrap();
+        assert_eq!(
+            verification.failures,
+            [ProofFailure::ChallengeMismatch { expected: "lender-nonce-42".to_string(), actual: None }]
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn every_edit_to_the_bundle_is_caught() {
+        let setup = Setup::new();
+        let proof = setup.prove(None).await;
+        let dollars = proof.accounts.iter().position(|account| account.mint == setup.dollars).unwrap();
+        let asset = 1 - dollars;
+
+        // Claiming more than the raw data holds
+        let mut inflated = proof.clone();
+        inflated.accounts[dollars].amount = 50_000_000_000;
+        assert_eq!(codes(&inflated.check()), ["bad_proof_signature", "proof_account_mismatch"]);
+        assert!(inflated.check()[1].to_string().contains(&format!("the data holds {}, not 50000000000", HELD)));
+
+        // ...even with the raw data rewritten to match, the signature no longer holds
+        let mut rewritten = proof.clone();
+        let mut data = general_purpose::STANDARD.decode(&rewritten.accounts[dollars].data).unwrap();
+        data[64..72].copy_from_slice(&50_000_000_000u64.to_le_bytes());
+        rewritten.accounts[dollars].data = general_purpose::STANDARD.encode(&data);
+        rewritten.accounts[dollars].amount = 50_000_000_000;
+        assert_eq!(codes(&rewritten.check()), ["bad_proof_signature"]);
+
+        // Passing off another wallet's accounts
+        let mut borrowed = proof.clone();
+        borrowed.wallet = Keypair::new().pubkey();
+        assert_eq!(codes(&borrowed.check()), ["bad_proof_signature", "proof_account_mismatch", "proof_account_mismatch"]);
+
+        // Hiding an account, relabelling one, or moving the slot
+        let mut hidden = proof.clone();
+        hidden.accounts.remove(asset);
+        assert_eq!(codes(&hidden.check()), ["bad_proof_signature"]);
+        let mut relabelled = proof.clone();
+        relabelled.accounts[asset].mint = setup.dollars;
+        assert_eq!(codes(&relabelled.check()), ["bad_proof_signature", "proof_account_mismatch"]);
+        let mut later = proof.clone();
+        later.slot = 5_000;
+        let verification = setup.verifier().verify_holdings_proof(&later, None).await.unwrap();
+        assert_eq!(codes(&verification.failures), ["bad_proof_signature", "future_slot", "slot_time_mismatch"]);
+
+        // Signed by a key that is not the wallet's
+        let mut forged = proof.clone();
+        forged.signature = FinternetClient::sign_offchain_message(&Keypair::new(), &proof.signing_message().unwrap()).unwrap();
+        assert_eq!(codes(&forged.check()), ["bad_proof_signature"]);
+
+        let mut unknown = proof.clone();
+        unknown.version = 2;
+        assert_eq!(unknown.check(), [ProofFailure::UnsupportedVersion { version: 2 }]);
+
+        for tampered in [inflated, rewritten, borrowed, hidden, relabelled, forged] {
+            let verification = setup.verifier().verify_holdings_proof(&tampered, None).await.unwrap();
+            assert!(!verification.is_valid());
+            assert!(!verification.proves_at_least(&setup.dollars, REQUIRED));
+        }
+        assert!(setup.verifier().verify_holdings_proof(&proof, None).await.unwrap().is_valid());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn later_changes_are_drift_but_another_cluster_or_time_fails() {
+        let setup = Setup::new();
+        let proof = setup.prove(None).await;
+        let address = |mint: &Pubkey| proof.accounts.iter().find(|account| account.mint == *mint).unwrap().address;
+
+        // Spending after the proof, or handing the asset on, does not undo what was held then
+        setup.cluster.set_token_account(address(&setup.dollars), &setup.wallet.pubkey(), &setup.dollars, 2_000_000_000);
+        let buyer = Keypair::new().pubkey();
+        setup.cluster.set_token_account(address(&setup.asset), &buyer, &setup.asset, 1);
+        let verification = setup.verifier().verify_holdings_proof(&proof, None).await.unwrap();
+        assert!(verification.is_valid());
+        assert!(verification.has_drift());
+        assert!(verification.proves_at_least(&setup.dollars, REQUIRED));
+        let drift = |mint: &Pubkey| verification.accounts.iter().find(|account| account.mint == *mint).unwrap().drift.clone();
+        assert_eq!(drift(&setup.dollars), AccountDrift::Changed { amount: 2_000_000_000 });
+        assert_eq!(drift(&setup.asset), AccountDrift::Transferred { owner: buyer });
+
+        setup.cluster.set_account(address(&setup.asset), Account::default());
+        let verification = setup.verifier().verify_holdings_proof(&proof, None).await.unwrap();
+        assert_eq!(verification.accounts.iter().find(|account| account.mint == setup.asset).unwrap().drift, AccountDrift::Closed);
+
+        // A cluster whose clock puts the proof's slot far from its capture time
+        setup.cluster.set_time(proof.captured_at as i64 + 3_600);
+        let verification = setup.verifier().verify_holdings_proof(&proof, None).await.unwrap();
+        assert_eq!(codes(&verification.failures), ["slot_time_mismatch"]);
+
+        // The same accounts read from another cluster prove nothing there
+        let other = MockCluster::new();
+        other.set_time(unix_now() as i64);
+        let genesis_hash = Hash::new_unique();
+        other.set_genesis_hash(genesis_hash);
+        let verification = other.default_client().verify_holdings_proof(&proof, None).await.unwrap();
+        assert_eq!(
+            verification.failures,
+            [ProofFailure::WrongNetwork { expected: genesis_hash.to_string(), actual: proof.genesis_hash.clone() }]
+        );
+    }
+}
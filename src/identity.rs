@@ -1,6 +1,15 @@
-use crate::FinternetClient;
+use crate::credential::SignedCredential;
+use crate::events::SdkEvent;
+use crate::ledger::TokenAccountDetail;
+use crate::redaction::Redactions;
+use crate::{Currency, FinternetClient};
+use crate::telemetry::readable;
+use crate::token_account::UndecodableAccount;
+use crate::validation::{self, validate_identity};
 use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
 use solana_sdk::{
+    offchain_message::OffchainMessage,
     pubkey::Pubkey,
     signature::{Keypair, Signature},
     signer::Signer,
@@ -9,11 +18,19 @@ use std::collections::HashMap;
 use std::fs;
 use std::path::Path;
 
-#[derive(Debug, Clone)]
+pub(crate) const REGISTER_IDENTITY_ACTION: &str = "register_identity";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FinternetIdentity {
     pub pubkey: Pubkey,
     pub display_name: Option<String>,
     pub metadata: HashMap<String, String>,
+    /// Credentials the holder has presented; check each with `verify_credential`
+    #[serde(default)]
+    pub credentials: Vec<SignedCredential>,
+    /// Fields the holder redacted since registering; their values are left out
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub redacted_fields: Vec<String>,
 }
 
 impl FinternetIdentity {
@@ -22,9 +39,27 @@ impl FinternetIdentity {
             pubkey,
             display_name: None,
             metadata: HashMap::new(),
+            credentials: Vec::new(),
+            redacted_fields: Vec::new(),
         }
     }
     
+    /// Attach a credential presented by the holder; it must be about this identity
+    pub fn present_credential(&mut self, credential: SignedCredential) -> Result<()> {
+        if credential.subject != self.pubkey {
+            return Err(anyhow!(
+                "Credential {} is about {}, not {}",
+                credential.id,
+                credential.subject,
+                self.pubkey
+            ));
+        }
+        if !self.credentials.iter().any(|c| c.id == credential.id) {
+            self.credentials.push(credential);
+        }
+        Ok(())
+    }
+    
     pub fn with_display_name(mut self, name: &str) -> Self {
         self.display_name = Some(name.to_string());
         self
@@ -52,6 +87,7 @@ impl FinternetClient {
     }
     
     /// Load wallet from a specific file path
+    #[tracing::instrument(skip_all, fields(path = %path.display()))]
     pub fn load_wallet_from_file(path: &Path) -> Result<Keypair> {
         if !path.exists() {
             return Err(anyhow!("Wallet file does not exist: {}", path.display()));
@@ -73,6 +109,7 @@ impl FinternetClient {
     }
     
     /// Save wallet to a file
+    #[tracing::instrument(skip_all, fields(wallet = %readable(&wallet.pubkey()), path = %path.display()))]
     pub fn save_wallet_to_file(wallet: &Keypair, path: &Path) -> Result<()> {
         let wallet_bytes = wallet.to_bytes();
         let wallet_json = serde_json::to_string_pretty(&wallet_bytes.to_vec())?;
@@ -83,32 +120,34 @@ impl FinternetClient {
         }
         
         fs::write(path, wallet_json)?;
-        log::info!("Wallet saved to: {}", path.display());
+        tracing::info!("Wallet saved to: {}", path.display());
         Ok(())
     }
     
     /// Get identity information for a public key
+    #[tracing::instrument(skip_all, fields(wallet = %readable(pubkey), rpc = %self.rpc_endpoint()))]
     pub async fn get_identity(&self, pubkey: &Pubkey) -> Result<FinternetIdentity> {
-        log::info!("Getting identity for: {}", pubkey);
-        
-        // In a real implementation, this might query a DID registry or identity service
-        // For now, we'll create a basic identity from the public key
-        let mut identity = FinternetIdentity::new(*pubkey);
+        tracing::info!("Getting identity for: {}", pubkey);
         
-        // Try to get SOL balance as basic account verification
-        match self.client.get_balance(pubkey) {
-            Ok(balance) => {
-                identity = identity.with_metadata("sol_balance", &balance.to_string());
-                if balance > 0 {
-                    identity = identity.with_metadata("account_status", "active");
-                } else {
-                    identity = identity.with_metadata("account_status", "inactive");
-                }
-            }
-            Err(_) => {
-                identity = identity.with_metadata("account_status", "not_found");
+        // Start from whatever the address registered on the ledger, if anything
+        let mut identity = match self.get_registered_identity(pubkey).await {
+            Ok(Some(registered)) => registered,
+            Ok(None) => FinternetIdentity::new(*pubkey),
+            Err(e) => {
+                tracing::warn!("Could not read registered identity for {}: {}", pubkey, e);
+                FinternetIdentity::new(*pubkey)
             }
+        };
+        
+        // An unreachable RPC node must surface as an error, not as a missing account
+        let status = self.get_account_status(pubkey).await?.require_available()?;
+        if let Some(lamports) = status.lamports() {
+            identity = identity.with_metadata("sol_balance", &lamports.to_string());
+        }
+        if let AccountStatus::ProgramOwned { owner, .. } = &status {
+            identity = identity.with_metadata("owner_program", &owner.to_string());
         }
+        identity = identity.with_metadata("account_status", status.label());
         
         // Check if this is a known system account
         if pubkey == &solana_sdk::system_program::id() {
@@ -120,29 +159,123 @@ impl FinternetClient {
         Ok(identity)
     }
     
+    /// The most recent `register_identity` entry the address itself signed, if any
+    #[tracing::instrument(skip_all, fields(wallet = %readable(pubkey)))]
+    pub async fn get_registered_identity(&self, pubkey: &Pubkey) -> Result<Option<FinternetIdentity>> {
+        let entries = self.read_ledger_entries(pubkey, None).await?;
+        
+        // Entries are newest first; anyone can write a memo naming this address, so only
+        // registrations signed by the address count
+        let Some((position, value)) = entries
+            .iter()
+            .enumerate()
+            .filter(|(_, entry)| entry.signer == Some(*pubkey))
+            .filter_map(|(position, entry)| entry.json().map(|value| (position, value)))
+            .find(|(_, value)| {
+                value.get("action").and_then(|a| a.as_str()) == Some(REGISTER_IDENTITY_ACTION)
+                    && value.get("pubkey").and_then(|p| p.as_str()) == Some(pubkey.to_string().as_str())
+            })
+        else {
+            return Ok(None);
+        };
+        // Only redactions written after the registration cover it
+        let redactions = Redactions::from_entries(pubkey, &entries[..position]);
+        
+        let mut identity = FinternetIdentity::new(*pubkey);
+        identity.display_name = value
+            .get("display_name")
+            .and_then(|n| n.as_str())
+            .map(str::to_string);
+        if let Some(metadata) = value.get("metadata").and_then(|m| m.as_object()) {
+            for (key, value) in metadata {
+                if let Some(value) = value.as_str() {
+                    identity.metadata.insert(key.clone(), value.to_string());
+                }
+            }
+        }
+        if let Some(timestamp) = value.get("timestamp").and_then(|t| t.as_u64()) {
+            identity.metadata.insert("registered_at".to_string(), timestamp.to_string());
+        }
+        // Countersigned from an attestation made by `register_identities_bulk`
+        if let Some(authority) = value.get("attested_by").and_then(|a| a.as_str()) {
+            identity.metadata.insert("attested_by".to_string(), authority.to_string());
+        }
+        redactions.apply_to_identity(&mut identity);
+        Ok(Some(identity))
+    }
+    
     /// Register an identity with metadata (using memo transactions for simple on-chain storage)
+    #[tracing::instrument(skip_all, fields(wallet = %readable(&wallet.pubkey()), metadata_keys = metadata.len()))]
     pub async fn register_identity(
         &self,
         wallet: &Keypair,
         display_name: &str,
         metadata: HashMap<String, String>,
     ) -> Result<Signature> {
-        log::info!("Registering identity for: {}", wallet.pubkey());
+        tracing::info!("Registering identity for: {}", wallet.pubkey());
+        let parameters = serde_json::json!({
+            "pubkey": wallet.pubkey().to_string(),
+            "display_name": display_name,
+            "metadata": metadata,
+        });
+        let outcome = self.register_identity_unlogged(wallet, display_name, metadata).await;
+        if let Ok(signature) = &outcome {
+            self.emit(|| SdkEvent::IdentityRegistered { pubkey: wallet.pubkey(), signature: *signature });
+        }
+        self.log_action("register_identity", parameters, None, outcome.as_ref(), Signature::to_string);
+        outcome
+    }
+    
+    async fn register_identity_unlogged(
+        &self,
+        wallet: &Keypair,
+        display_name: &str,
+        metadata: HashMap<String, String>,
+    ) -> Result<Signature> {
+        validation::check(validate_identity(display_name, &metadata))?;
         
-        // Create identity registration data
         let identity_data = serde_json::json!({
-            "action": "register_identity",
+            "action": REGISTER_IDENTITY_ACTION,
             "pubkey": wallet.pubkey().to_string(),
             "display_name": display_name,
             "metadata": metadata,
-            "timestamp": std::time::SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)
-                .unwrap()
-                .as_secs()
+            "timestamp": self.registration_timestamp().await?
         });
+        self.write_identity_registration(wallet, identity_data).await
+    }
+    
+    /// Unix time for a registration; the cluster's clock, so it agrees with the block time,
+    /// unless `fixed_timestamp` is set
+    pub(crate) async fn registration_timestamp(&self) -> Result<u64> {
+        if let Some(timestamp) = self.config.fixed_timestamp {
+            return Ok(timestamp);
+        }
+        match self.get_chain_time().await {
+            Ok(chain_time) => Ok(chain_time.unix_timestamp()),
+            Err(e) => {
+                tracing::warn!("Chain time unavailable, using local clock: {}", e);
+                Ok(std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)?
+                    .as_secs())
+            }
+        }
+    }
+    
+    /// Write a `register_identity` entry signed by `wallet`, returning its first signature
+    pub(crate) async fn write_identity_registration(
+        &self,
+        wallet: &Keypair,
+        identity_data: serde_json::Value,
+    ) -> Result<Signature> {
+        // Write to ledger using memo, chunked and compressed when the metadata is large
+        let entry = identity_data.to_string();
+        let compress = entry.len() > crate::chunking::MAX_SINGLE_ENTRY_LEN;
+        let signatures = self.write_ledger_entry_chunked(wallet, &entry, compress).await?;
         
-        // Write to ledger using memo
-        self.write_ledger_entry(wallet, &identity_data.to_string()).await
+        signatures
+            .first()
+            .copied()
+            .ok_or_else(|| anyhow!("Identity registration produced no transactions"))
     }
     
     /// Verify wallet ownership by signing a challenge
@@ -160,18 +293,53 @@ impl FinternetClient {
         Ok(signature.verify(pubkey.as_ref(), challenge_bytes))
     }
     
+    /// Sign a message using the Solana off-chain message format (what wallets show users)
+    pub fn sign_offchain_message(wallet: &Keypair, message: &str) -> Result<String> {
+        let offchain = OffchainMessage::new(0, message.as_bytes())
+            .map_err(|e| anyhow!("Invalid off-chain message: {}", e))?;
+        let signature = offchain
+            .sign(wallet)
+            .map_err(|e| anyhow!("Failed to sign off-chain message: {}", e))?;
+        Ok(signature.to_string())
+    }
+    
+    /// Verify a signature produced over a message in the Solana off-chain message format
+    pub fn verify_offchain_message(pubkey: &Pubkey, message: &str, signature_str: &str) -> Result<bool> {
+        let signature: Signature = signature_str.parse()?;
+        let offchain = OffchainMessage::new(0, message.as_bytes())
+            .map_err(|e| anyhow!("Invalid off-chain message: {}", e))?;
+        
+        offchain
+            .verify(pubkey, &signature)
+            .map_err(|e| anyhow!("Failed to verify off-chain message: {}", e))
+    }
+    
     /// Get wallet information including balances
+    #[tracing::instrument(skip_all, fields(wallet = %readable(pubkey)))]
     pub async fn get_wallet_info(&self, pubkey: &Pubkey) -> Result<WalletInfo> {
-        log::info!("Getting wallet info for: {}", pubkey);
+        tracing::info!("Getting wallet info for: {}", pubkey);
         
-        let sol_balance = self.client.get_balance(pubkey)?;
-        let token_accounts = self.get_token_accounts(pubkey).await?;
+        let epoch = self.cache.wallets.epoch();
+        let status = self.get_account_status(pubkey).await?.require_available()?;
+        let sol_balance = status.lamports().unwrap_or(0);
+        let scan = self.get_token_account_scan(pubkey).await?;
+        let token_accounts = scan.accounts;
+        
+        let mut token_balances = HashMap::new();
+        for account in &token_accounts {
+            *token_balances.entry(account.mint).or_insert(0u64) += account.amount;
+        }
         
-        Ok(WalletInfo {
+        let info = WalletInfo {
             pubkey: *pubkey,
             sol_balance,
-            token_balances: token_accounts,
-        })
+            token_balances,
+            token_accounts,
+            undecodable_accounts: scan.undecodable,
+            status,
+        };
+        self.cache.wallets.store(&info, epoch);
+        Ok(info)
     }
     
     /// Create a human-readable address from a public key
@@ -183,11 +351,110 @@ impl FinternetClient {
         )
     }
     
-    /// Check if an account is initialized and active
-    pub async fn is_account_active(&self, pubkey: &Pubkey) -> Result<bool> {
-        match self.client.get_account(pubkey) {
-            Ok(account) => Ok(account.lamports > 0),
-            Err(_) => Ok(false),
+    /// Classify an account by existence, owner, and balance
+    ///
+    /// RPC failures are reported as `AccountStatus::RpcUnavailable` rather than folded into
+    /// `NotFound`; use `require_available` to turn that case into an error.
+    #[tracing::instrument(skip_all, fields(wallet = %readable(pubkey)))]
+    pub async fn get_account_status(&self, pubkey: &Pubkey) -> Result<AccountStatus> {
+        let account = match self.client.get_account_with_commitment(pubkey, self.client.commitment()) {
+            Ok(response) => response.value,
+            Err(e) => {
+                tracing::warn!("Account lookup for {} failed: {}", pubkey, e);
+                return Ok(AccountStatus::RpcUnavailable { reason: e.to_string() });
+            }
+        };
+        
+        let Some(account) = account else {
+            return Ok(AccountStatus::NotFound);
+        };
+        
+        if account.owner != solana_sdk::system_program::id() {
+            return Ok(AccountStatus::ProgramOwned {
+                owner: account.owner,
+                lamports: account.lamports,
+            });
+        }
+        
+        let rent_exempt_minimum = match self
+            .client
+            .get_minimum_balance_for_rent_exemption(account.data.len())
+        {
+            Ok(minimum) => minimum,
+            Err(e) => return Ok(AccountStatus::RpcUnavailable { reason: e.to_string() }),
+        };
+        
+        if account.lamports <= rent_exempt_minimum {
+            Ok(AccountStatus::RentExemptReserve { lamports: account.lamports })
+        } else {
+            Ok(AccountStatus::SystemAccountActive { lamports: account.lamports })
+        }
+    }
+}
+
+/// What an address looks like on-chain
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum AccountStatus {
+    /// No account exists at this address
+    NotFound,
+    /// Wallet-style account holding more than its rent-exempt minimum
+    SystemAccountActive { lamports: u64 },
+    /// Token account, mint, program, or other account owned by a non-system program
+    ProgramOwned { owner: Pubkey, lamports: u64 },
+    /// System account holding no more than its rent-exempt minimum
+    RentExemptReserve { lamports: u64 },
+    /// The RPC node could not be reached or refused the lookup; the account state is unknown
+    RpcUnavailable { reason: String },
+}
+
+impl AccountStatus {
+    /// Turn `RpcUnavailable` into an error so an unknown state is never mistaken for a real one
+    pub fn require_available(self) -> Result<Self> {
+        match self {
+            Self::RpcUnavailable { reason } => Err(anyhow!("RPC unavailable, account state unknown: {}", reason)),
+            status => Ok(status),
+        }
+    }
+    
+    pub fn exists(&self) -> bool {
+        !matches!(self, Self::NotFound | Self::RpcUnavailable { .. })
+    }
+    
+    pub fn lamports(&self) -> Option<u64> {
+        match self {
+            Self::SystemAccountActive { lamports }
+            | Self::ProgramOwned { lamports, .. }
+            | Self::RentExemptReserve { lamports } => Some(*lamports),
+            Self::NotFound => Some(0),
+            Self::RpcUnavailable { .. } => None,
+        }
+    }
+    
+    /// Short machine-readable name, matching the serialized `status` tag
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::NotFound => "not_found",
+            Self::SystemAccountActive { .. } => "system_account_active",
+            Self::ProgramOwned { .. } => "program_owned",
+            Self::RentExemptReserve { .. } => "rent_exempt_reserve",
+            Self::RpcUnavailable { .. } => "rpc_unavailable",
+        }
+    }
+}
+
+impl std::fmt::Display for AccountStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NotFound => write!(f, "Not found (no account at this address)"),
+            Self::SystemAccountActive { lamports } => write!(f, "Active ({} lamports)", lamports),
+            Self::ProgramOwned { owner, lamports } => {
+                write!(f, "Program-owned by {} ({} lamports)", owner, lamports)
+            }
+            Self::RentExemptReserve { lamports } => {
+                write!(f, "Rent-exempt reserve only ({} lamports)", lamports)
+            }
+            Self::RpcUnavailable { reason } => write!(f, "Unknown (RPC unavailable: {})", reason),
         }
     }
 }
@@ -197,6 +464,10 @@ pub struct WalletInfo {
     pub pubkey: Pubkey,
     pub sol_balance: u64,
     pub token_balances: HashMap<Pubkey, u64>,
+    pub token_accounts: Vec<TokenAccountDetail>,
+    /// Accounts the RPC node returned that could not be decoded; their balances are missing
+    pub undecodable_accounts: Vec<UndecodableAccount>,
+    pub status: AccountStatus,
 }
 
 impl WalletInfo {
@@ -211,4 +482,25 @@ impl WalletInfo {
     pub fn total_token_types(&self) -> usize {
         self.token_balances.len()
     }
+    
+    /// SOL first, then every mint held, each with the currency needed to display it
+    pub fn balances(&self) -> Vec<(Currency, u64)> {
+        let mut balances = vec![(Currency::Sol, self.sol_balance)];
+        let mut mints: Vec<&Pubkey> = self.token_balances.keys().collect();
+        mints.sort();
+        for mint in mints {
+            let currency = self
+                .token_accounts
+                .iter()
+                .find(|account| account.mint == *mint)
+                .map(TokenAccountDetail::currency)
+                .unwrap_or_else(|| Currency::from_mint(*mint));
+            balances.push((currency, self.token_balances[mint]));
+        }
+        balances
+    }
+
+    pub fn frozen_accounts(&self) -> impl Iterator<Item = &TokenAccountDetail> {
+        self.token_accounts.iter().filter(|account| account.is_frozen())
+    }
 } 
\ No newline at end of file
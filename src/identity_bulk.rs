@@ -0,0 +1,654 @@
+//! Registering many identities at once, e.g. when onboarding an organization's wallets
+//!
+//! Wallets the authority can sign for are registered directly. For any other wallet the
+//! authority records an `IdentityAttestation` on the ledger instead, which only becomes a
+//! registration once the wallet's owner countersigns it with `confirm_identity_attestation`;
+//! `get_registered_identity` never trusts an identity the address did not sign itself.
+
+use crate::identity::REGISTER_IDENTITY_ACTION;
+use crate::ledger::{extract_memos, fee_payer_of};
+use crate::reconcile::split_csv_line;
+use crate::telemetry::readable;
+use crate::validation::{self, validate_identity};
+use crate::FinternetClient;
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use solana_client::rpc_config::RpcTransactionConfig;
+use solana_sdk::{
+    commitment_config::CommitmentConfig,
+    pubkey::Pubkey,
+    signature::{Keypair, Signature},
+    signer::Signer,
+};
+use solana_transaction_status::UiTransactionEncoding;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Ledger entry action of an authority vouching for another wallet's identity
+pub const IDENTITY_ATTESTATION_ACTION: &str = "identity_attestation";
+
+/// One identity of `register_identities_bulk`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IdentityEntry {
+    pub pubkey: Pubkey,
+    pub display_name: String,
+    #[serde(default)]
+    pub metadata: HashMap<String, String>,
+    /// Keypair file of a wallet the authority controls, so the entry is registered directly
+    ///
+    /// Entries for the authority itself need none.
+    #[serde(default)]
+    pub wallet_path: Option<PathBuf>,
+}
+
+impl IdentityEntry {
+    /// Entries from a CSV with `pubkey` and `display_name` columns; an optional `keypair` column
+    /// names the keypair file of a controlled wallet, and every other column becomes metadata
+    pub fn from_csv(csv: &str) -> Result<Vec<Self>> {
+        let mut lines = csv.lines().enumerate().filter(|(_, line)| !line.trim().is_empty());
+        let (_, header) = lines.next().ok_or_else(|| anyhow!("Identity CSV is empty"))?;
+        let header: Vec<String> = split_csv_line(header).into_iter().map(|h| h.trim().to_lowercase()).collect();
+        let column = |name: &str| header.iter().position(|h| h == name);
+        let pubkey_column = column("pubkey").ok_or_else(|| anyhow!("Identity CSV has no pubkey column"))?;
+        let name_column = column("display_name")
+            .or_else(|| column("name"))
+            .ok_or_else(|| anyhow!("Identity CSV has no display_name column"))?;
+        let keypair_column = column("keypair");
+
+        let mut entries = Vec::new();
+        for (line_index, line) in lines {
+            let line_number = line_index + 1;
+            let fields: Vec<String> = split_csv_line(line).into_iter().map(|f| f.trim().to_string()).collect();
+            let field = |index: usize| fields.get(index).map(String::as_str).filter(|value| !value.is_empty());
+
+            let pubkey = field(pubkey_column).ok_or_else(|| anyhow!("Line {}: missing pubkey", line_number))?;
+            let pubkey =
+                Pubkey::from_str(pubkey).map_err(|_| anyhow!("Line {}: invalid pubkey '{}'", line_number, pubkey))?;
+            let display_name = field(name_column)
+                .ok_or_else(|| anyhow!("Line {}: missing display_name", line_number))?
+                .to_string();
+            let metadata = header
+                .iter()
+                .enumerate()
+                .filter(|(index, _)| ![Some(pubkey_column), Some(name_column), keypair_column].contains(&Some(*index)))
+                .filter_map(|(index, key)| field(index).map(|value| (key.clone(), value.to_string())))
+                .collect();
+            entries.push(IdentityEntry {
+                pubkey,
+                display_name,
+                metadata,
+                wallet_path: keypair_column.and_then(field).map(PathBuf::from),
+            });
+        }
+        Ok(entries)
+    }
+}
+
+/// An authority's signed claim about another wallet's identity, awaiting its countersignature
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IdentityAttestation {
+    pub id: String,
+    pub authority: Pubkey,
+    pub subject: Pubkey,
+    pub display_name: String,
+    #[serde(default)]
+    pub metadata: HashMap<String, String>,
+    pub issued_at: u64,
+    /// Transaction carrying the authority's ledger entry
+    pub signature: Signature,
+}
+
+impl IdentityAttestation {
+    pub fn load(path: &Path) -> Result<Self> {
+        let data = fs::read_to_string(path)?;
+        serde_json::from_str(&data).map_err(|e| anyhow!("Invalid identity attestation {}: {}", path.display(), e))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            if !parent.as_os_str().is_empty() {
+                fs::create_dir_all(parent)?;
+            }
+        }
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// The ledger entry the authority writes, naming both parties
+    fn ledger_entry(&self) -> serde_json::Value {
+        attestation_entry(
+            &self.id,
+            &self.authority,
+            &self.subject,
+            &self.display_name,
+            &self.metadata,
+            self.issued_at,
+        )
+    }
+}
+
+fn attestation_entry(
+    id: &str,
+    authority: &Pubkey,
+    subject: &Pubkey,
+    display_name: &str,
+    metadata: &HashMap<String, String>,
+    issued_at: u64,
+) -> serde_json::Value {
+    serde_json::json!({
+        "action": IDENTITY_ATTESTATION_ACTION,
+        "id": id,
+        "authority": authority.to_string(),
+        "subject": subject.to_string(),
+        "display_name": display_name,
+        "metadata": metadata,
+        "timestamp": issued_at,
+    })
+}
+
+/// Why an attestation could not be countersigned
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AttestationError {
+    /// Only the attested wallet can countersign
+    WrongWallet { subject: Pubkey, wallet: Pubkey },
+    /// The authority's ledger entry is missing, altered, or was not signed by the authority
+    NotOnLedger { signature: Signature },
+}
+
+impl AttestationError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::WrongWallet { .. } => "wrong_wallet",
+            Self::NotOnLedger { .. } => "attestation_not_on_ledger",
+        }
+    }
+}
+
+impl fmt::Display for AttestationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::WrongWallet { subject, wallet } => {
+                write!(f, "Attestation is about {}, it cannot be confirmed by {}", subject, wallet)
+            }
+            Self::NotOnLedger { signature } => {
+                write!(f, "Transaction {} does not carry this attestation from its authority", signature)
+            }
+        }
+    }
+}
+
+impl std::error::Error for AttestationError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BulkIdentityStatus {
+    Pending,
+    /// Registration signed by the wallet itself
+    Registered,
+    /// Attestation recorded; registered once the wallet countersigns it
+    Attested,
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BulkIdentityItem {
+    pub entry: IdentityEntry,
+    pub status: BulkIdentityStatus,
+    pub signature: Option<Signature>,
+    pub attestation: Option<IdentityAttestation>,
+    pub error: Option<String>,
+    pub updated_at: u64,
+}
+
+impl BulkIdentityItem {
+    fn set(&mut self, status: BulkIdentityStatus, signature: Option<Signature>, error: Option<String>) {
+        self.status = status;
+        self.signature = signature;
+        self.error = error;
+        self.updated_at = now_secs();
+    }
+}
+
+/// Per-entry outcome of `register_identities_bulk`, written to disk after every entry
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BulkResult {
+    pub id: String,
+    pub authority: Pubkey,
+    pub created_at: u64,
+    pub items: Vec<BulkIdentityItem>,
+}
+
+impl BulkResult {
+    /// Start a result with every entry `Pending`; each wallet may appear only once
+    pub fn new(authority: Pubkey, entries: Vec<IdentityEntry>) -> Result<Self> {
+        let mut seen = HashSet::new();
+        let mut items = Vec::with_capacity(entries.len());
+        for entry in entries {
+            if !seen.insert(entry.pubkey) {
+                return Err(anyhow!("{} is listed more than once", entry.pubkey));
+            }
+            items.push(BulkIdentityItem {
+                entry,
+                status: BulkIdentityStatus::Pending,
+                signature: None,
+                attestation: None,
+                error: None,
+                updated_at: now_secs(),
+            });
+        }
+        Ok(Self {
+            id: uuid::Uuid::new_v4().to_string(),
+            authority,
+            created_at: now_secs(),
+            items,
+        })
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let data = fs::read_to_string(path)?;
+        serde_json::from_str(&data).map_err(|e| anyhow!("Corrupt identity journal {}: {}", path.display(), e))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            if !parent.as_os_str().is_empty() {
+                fs::create_dir_all(parent)?;
+            }
+        }
+        // Write-then-rename so an interrupted run never leaves a truncated journal
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, serde_json::to_string_pretty(self)?)?;
+        fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    pub fn count(&self, status: BulkIdentityStatus) -> usize {
+        self.items.iter().filter(|item| item.status == status).count()
+    }
+
+    /// Every entry was registered, attested, or failed
+    pub fn is_complete(&self) -> bool {
+        self.count(BulkIdentityStatus::Pending) == 0
+    }
+
+    fn persist(&self, path: Option<&Path>) -> Result<()> {
+        match path {
+            Some(path) => self.save(path),
+            None => Ok(()),
+        }
+    }
+}
+
+impl FinternetClient {
+    /// Register every entry, journaling progress to `journal_path` if given
+    ///
+    /// One entry failing does not stop the rest; check the returned result for per-entry
+    /// signatures and errors, and hand each `attestation` to its wallet's owner.
+    #[tracing::instrument(skip_all, fields(wallet = %readable(&authority.pubkey()), entries = entries.len()))]
+    pub async fn register_identities_bulk(
+        &self,
+        authority: &Keypair,
+        entries: Vec<IdentityEntry>,
+        journal_path: Option<&Path>,
+    ) -> Result<BulkResult> {
+        let mut result = BulkResult::new(authority.pubkey(), entries)?;
+        self.run_identity_registration(authority, &mut result, journal_path).await?;
+        Ok(result)
+    }
+
+    /// Carry on with the entries an interrupted `register_identities_bulk` left `Pending`
+    pub async fn resume_identity_registration(&self, authority: &Keypair, journal_path: &Path) -> Result<BulkResult> {
+        let mut result = BulkResult::load(journal_path)?;
+        if result.authority != authority.pubkey() {
+            return Err(anyhow!(
+                "Identity journal {} belongs to authority {}, not {}",
+                journal_path.display(),
+                result.authority,
+                authority.pubkey()
+            ));
+        }
+        self.run_identity_registration(authority, &mut result, Some(journal_path)).await?;
+        Ok(result)
+    }
+
+    async fn run_identity_registration(
+        &self,
+        authority: &Keypair,
+        result: &mut BulkResult,
+        journal_path: Option<&Path>,
+    ) -> Result<()> {
+        result.persist(journal_path)?;
+        let pending: Vec<usize> = (0..result.items.len())
+            .filter(|&i| result.items[i].status == BulkIdentityStatus::Pending)
+            .collect();
+        tracing::info!("Registering {} of {} identities", pending.len(), result.items.len());
+
+        for index in pending {
+            let entry = result.items[index].entry.clone();
+            let outcome = match validation::check(validate_identity(&entry.display_name, &entry.metadata)) {
+                Err(e) => Err(e),
+                Ok(()) if entry.pubkey == authority.pubkey() => self
+                    .register_identity(authority, &entry.display_name, entry.metadata.clone())
+                    .await
+                    .map(|signature| (BulkIdentityStatus::Registered, signature, None)),
+                Ok(()) => match &entry.wallet_path {
+                    Some(path) => self.register_controlled_identity(&entry, path).await,
+                    None => self
+                        .attest_identity(authority, &entry)
+                        .await
+                        .map(|attestation| (BulkIdentityStatus::Attested, attestation.signature, Some(attestation))),
+                },
+            };
+
+            let item = &mut result.items[index];
+            match outcome {
+                Ok((status, signature, attestation)) => {
+                    item.attestation = attestation;
+                    item.set(status, Some(signature), None);
+                }
+                Err(e) => {
+                    tracing::warn!("Identity for {} failed: {}", entry.pubkey, e);
+                    item.set(BulkIdentityStatus::Failed, None, Some(e.to_string()));
+                }
+            }
+            result.persist(journal_path)?;
+        }
+
+        tracing::info!(
+            "Identities: {} registered, {} attested, {} failed",
+            result.count(BulkIdentityStatus::Registered),
+            result.count(BulkIdentityStatus::Attested),
+            result.count(BulkIdentityStatus::Failed)
+        );
+        Ok(())
+    }
+
+    async fn register_controlled_identity(
+        &self,
+        entry: &IdentityEntry,
+        wallet_path: &Path,
+    ) -> Result<(BulkIdentityStatus, Signature, Option<IdentityAttestation>)> {
+        let wallet = Self::load_wallet_from_file(wallet_path)?;
+        if wallet.pubkey() != entry.pubkey {
+            return Err(anyhow!("{} holds the keypair of {}, not {}", wallet_path.display(), wallet.pubkey(), entry.pubkey));
+        }
+        let signature = self.register_identity(&wallet, &entry.display_name, entry.metadata.clone()).await?;
+        Ok((BulkIdentityStatus::Registered, signature, None))
+    }
+
+    /// Record on the ledger that `authority` vouches for `entry`, for its wallet to countersign
+    async fn attest_identity(&self, authority: &Keypair, entry: &IdentityEntry) -> Result<IdentityAttestation> {
+        let id = uuid::Uuid::new_v4().to_string();
+        let issued_at = self.registration_timestamp().await?;
+        let ledger_entry = attestation_entry(
+            &id,
+            &authority.pubkey(),
+            &entry.pubkey,
+            &entry.display_name,
+            &entry.metadata,
+            issued_at,
+        )
+        .to_string();
+        // Countersigning looks the entry up by signature, so it must fit a single memo
+        if ledger_entry.len() > crate::chunking::MAX_SINGLE_ENTRY_LEN {
+            return Err(anyhow!(
+                "Attestation for {} is {} bytes, at most {} fit in one ledger entry",
+                entry.pubkey,
+                ledger_entry.len(),
+                crate::chunking::MAX_SINGLE_ENTRY_LEN
+            ));
+        }
+        let signature = self.write_ledger_entry(authority, &ledger_entry).await?;
+        Ok(IdentityAttestation {
+            id,
+            authority: authority.pubkey(),
+            subject: entry.pubkey,
+            display_name: entry.display_name.clone(),
+            metadata: entry.metadata.clone(),
+            issued_at,
+            signature,
+        })
+    }
+
+    /// Countersign an attestation as the wallet it is about, registering the identity it names
+    ///
+    /// The authority's ledger entry is checked first, so an attestation that was edited, or
+    /// never recorded, is refused with an `AttestationError`.
+    #[tracing::instrument(skip_all, fields(wallet = %readable(&wallet.pubkey()), authority = %readable(&attestation.authority)))]
+    pub async fn confirm_identity_attestation(&self, wallet: &Keypair, attestation: &IdentityAttestation) -> Result<Signature> {
+        if attestation.subject != wallet.pubkey() {
+            return Err(AttestationError::WrongWallet {
+                subject: attestation.subject,
+                wallet: wallet.pubkey(),
+            }
+            .into());
+        }
+        validation::check(validate_identity(&attestation.display_name, &attestation.metadata))?;
+        self.verify_identity_attestation(attestation)?;
+
+        let identity_data = serde_json::json!({
+            "action": REGISTER_IDENTITY_ACTION,
+            "pubkey": wallet.pubkey().to_string(),
+            "display_name": attestation.display_name,
+            "metadata": attestation.metadata,
+            "timestamp": self.registration_timestamp().await?,
+            "attested_by": attestation.authority.to_string(),
+            "attestation": attestation.id,
+        });
+        let signature = self.write_identity_registration(wallet, identity_data).await?;
+        tracing::info!("Attestation {} confirmed by {}: {}", attestation.id, wallet.pubkey(), signature);
+        Ok(signature)
+    }
+
+    /// Check that `attestation.signature` is a transaction paid for by the authority whose
+    /// memo is exactly this attestation
+    pub fn verify_identity_attestation(&self, attestation: &IdentityAttestation) -> Result<()> {
+        let not_on_ledger = || AttestationError::NotOnLedger { signature: attestation.signature };
+        let transaction = self
+            .client
+            .get_transaction_with_config(
+                &attestation.signature,
+                RpcTransactionConfig {
+                    encoding: Some(UiTransactionEncoding::Json),
+                    commitment: Some(CommitmentConfig::confirmed()),
+                    max_supported_transaction_version: Some(0),
+                },
+            )
+            .map_err(|e| {
+                tracing::warn!("Attestation transaction {} unavailable: {}", attestation.signature, e);
+                not_on_ledger()
+            })?;
+        let failed = transaction.transaction.meta.as_ref().is_some_and(|meta| meta.err.is_some());
+        if failed || fee_payer_of(&transaction) != Some(attestation.authority) {
+            return Err(not_on_ledger().into());
+        }
+
+        let expected = attestation.ledger_entry();
+        let recorded = extract_memos(&transaction)
+            .iter()
+            .filter_map(|memo| serde_json::from_str::<serde_json::Value>(self.entry_body(memo)?).ok())
+            .any(|value| value == expected);
+        if !recorded {
+            return Err(not_on_ledger().into());
+        }
+        Ok(())
+    }
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_cluster::{MockCluster, TempDir};
+
+    fn entry(pubkey: Pubkey, name: &str) -> IdentityEntry {
+        IdentityEntry {
+            pubkey,
+            display_name: name.to_string(),
+            metadata: HashMap::from([("team".to_string(), "Treasury".to_string())]),
+            wallet_path: None,
+        }
+    }
+
+    fn funded(cluster: &MockCluster) -> Keypair {
+        let wallet = Keypair::new();
+        cluster.set_balance(wallet.pubkey(), 1_000_000_000);
+        wallet
+    }
+
+    fn attestation_error(error: anyhow::Error) -> AttestationError {
+        error.downcast::<AttestationError>().unwrap()
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn external_wallets_are_attested_and_registered_once_they_countersign() {
+        let cluster = MockCluster::new();
+        let client = cluster.default_client();
+        let dir = TempDir::new("identity-bulk");
+        let (authority, controlled, external) = (funded(&cluster), funded(&cluster), funded(&cluster));
+        let wallet_path = dir.join("controlled.json");
+        FinternetClient::save_wallet_to_file(&controlled, &wallet_path).unwrap();
+        let entries = vec![
+            entry(authority.pubkey(), "Acme Treasury"),
+            IdentityEntry { wallet_path: Some(wallet_path), ..entry(controlled.pubkey(), "Acme Payroll") },
+            entry(external.pubkey(), "Dana Ortiz"),
+        ];
+
+        let result = client.register_identities_bulk(&authority, entries, None).await.unwrap();
+        let statuses: Vec<BulkIdentityStatus> = result.items.iter().map(|item| item.status).collect();
+        assert_eq!(statuses, [BulkIdentityStatus::Registered, BulkIdentityStatus::Registered, BulkIdentityStatus::Attested]);
+        assert!(result.items.iter().all(|item| item.signature.is_some() && item.error.is_none()));
+        let attestation = result.items[2].attestation.clone().unwrap();
+        assert_eq!((attestation.authority, attestation.subject), (authority.pubkey(), external.pubkey()));
+        assert_eq!(result.items[2].signature, Some(attestation.signature));
+
+        // The directly registered wallets resolve straight away, the attested one only once it signs
+        let name = |pubkey: Pubkey| {
+            let client = &client;
+            async move { client.get_registered_identity(&pubkey).await.unwrap().and_then(|identity| identity.display_name) }
+        };
+        assert_eq!(name(authority.pubkey()).await.as_deref(), Some("Acme Treasury"));
+        assert_eq!(name(controlled.pubkey()).await.as_deref(), Some("Acme Payroll"));
+        assert_eq!(name(external.pubkey()).await, None);
+
+        let saved = dir.join("attestation.json");
+        attestation.save(&saved).unwrap();
+        let signature = client.confirm_identity_attestation(&external, &IdentityAttestation::load(&saved).unwrap()).await.unwrap();
+        let confirmation = cluster.sent().into_iter().find(|tx| tx.signatures[0] == signature).unwrap();
+        assert_eq!(confirmation.message.account_keys[0], external.pubkey());
+        let identity = client.get_registered_identity(&external.pubkey()).await.unwrap().unwrap();
+        assert_eq!(identity.display_name.as_deref(), Some("Dana Ortiz"));
+        assert_eq!(identity.metadata.get("team").map(String::as_str), Some("Treasury"));
+        assert_eq!(identity.metadata.get("attested_by"), Some(&authority.pubkey().to_string()));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn an_attestation_is_only_countersigned_by_its_subject_and_as_recorded() {
+        let cluster = MockCluster::new();
+        let client = cluster.default_client();
+        let (authority, external, forger) = (funded(&cluster), funded(&cluster), funded(&cluster));
+        let result = client
+            .register_identities_bulk(&authority, vec![entry(external.pubkey(), "Dana Ortiz")], None)
+            .await
+            .unwrap();
+        let attestation = result.items[0].attestation.clone().unwrap();
+        client.verify_identity_attestation(&attestation).unwrap();
+        let sent = cluster.sent().len();
+
+        let error = attestation_error(client.confirm_identity_attestation(&forger, &attestation).await.unwrap_err());
+        assert_eq!(error, AttestationError::WrongWallet { subject: external.pubkey(), wallet: forger.pubkey() });
+
+        // Any edit to what the authority recorded breaks the match with its ledger entry
+        let edits: [fn(&mut IdentityAttestation); 5] = [
+            |a| a.display_name = "Dana Ortiz (CFO)".to_string(),
+            |a| {
+                a.metadata.insert("role".to_string(), "admin".to_string());
+            },
+            |a| a.issued_at += 1,
+            |a| a.id = uuid::Uuid::new_v4().to_string(),
+            |a| a.authority = Pubkey::new_unique(),
+        ];
+        for edit in edits {
+            let mut edited = attestation.clone();
+            edit(&mut edited);
+            let error = attestation_error(client.confirm_identity_attestation(&external, &edited).await.unwrap_err());
+            assert_eq!(error.code(), "attestation_not_on_ledger");
+        }
+
+        // The same entry written by someone else, or a signature the cluster never saw, proves nothing
+        let copied = client.write_ledger_entry(&forger, &attestation.ledger_entry().to_string()).await.unwrap();
+        let forged = IdentityAttestation { signature: copied, ..attestation.clone() };
+        let error = attestation_error(client.confirm_identity_attestation(&external, &forged).await.unwrap_err());
+        assert_eq!(error, AttestationError::NotOnLedger { signature: copied });
+        let unknown = IdentityAttestation { signature: Signature::new_unique(), ..attestation.clone() };
+        assert_eq!(attestation_error(client.verify_identity_attestation(&unknown).unwrap_err()).code(), "attestation_not_on_ledger");
+
+        // Only the forger's copy went out; no refused countersignature was sent
+        assert_eq!(cluster.sent().len(), sent + 1);
+        assert!(client.get_registered_identity(&external.pubkey()).await.unwrap().is_none());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn a_resumed_run_only_handles_what_is_still_pending() {
+        let cluster = MockCluster::new();
+        let client = cluster.default_client();
+        let dir = TempDir::new("identity-resume");
+        let journal = dir.join("journal.json");
+        let authority = funded(&cluster);
+        let (first, second) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let entries = vec![entry(first, "Dana Ortiz"), entry(second, ""), entry(Pubkey::new_unique(), "Lee Park")];
+
+        let result = client.register_identities_bulk(&authority, entries, Some(&journal)).await.unwrap();
+        assert!(result.is_complete());
+        assert_eq!(result.count(BulkIdentityStatus::Attested), 2);
+        assert_eq!(result.items[1].status, BulkIdentityStatus::Failed);
+        assert!(result.items[1].error.as_deref().unwrap().contains("display_name"), "{:?}", result.items[1].error);
+
+        // As if the run had stopped before the last entry
+        let mut interrupted = BulkResult::load(&journal).unwrap();
+        interrupted.items[2].status = BulkIdentityStatus::Pending;
+        interrupted.save(&journal).unwrap();
+        let sent = cluster.sent().len();
+
+        let resumed = client.resume_identity_registration(&authority, &journal).await.unwrap();
+        assert_eq!(cluster.sent().len(), sent + 1);
+        assert_eq!(resumed.items[0].signature, result.items[0].signature);
+        assert_eq!(resumed.items[1].status, BulkIdentityStatus::Failed);
+        assert_eq!(resumed.items[2].status, BulkIdentityStatus::Attested);
+        assert_ne!(resumed.items[2].signature, result.items[2].signature);
+
+        let error = client.resume_identity_registration(&funded(&cluster), &journal).await.unwrap_err();
+        assert!(error.to_string().contains("belongs to authority"), "{}", error);
+        assert!(client
+            .register_identities_bulk(&authority, vec![entry(first, "A"), entry(first, "B")], None)
+            .await
+            .is_err());
+    }
+
+    #[test]
+    fn csv_columns_become_entries_with_metadata() {
+        let (dana, lee) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let csv = format!("pubkey,Name,team,keypair\n{},Dana Ortiz,Treasury,\n\n{},\"Park, Lee\",,keys/lee.json\n", dana, lee);
+        let entries = IdentityEntry::from_csv(&csv).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0], entry(dana, "Dana Ortiz"));
+        assert_eq!(
+            entries[1],
+            IdentityEntry { pubkey: lee, display_name: "Park, Lee".to_string(), metadata: HashMap::new(), wallet_path: Some(PathBuf::from("keys/lee.json")) }
+        );
+
+        let error = IdentityEntry::from_csv(&format!("pubkey,display_name\n{},\n", dana)).unwrap_err();
+        assert_eq!(error.to_string(), "Line 2: missing display_name");
+        assert!(IdentityEntry::from_csv("display_name\nDana\n").is_err());
+    }
+}
@@ -0,0 +1,244 @@
+use crate::FinternetClient;
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use solana_client::rpc_client::GetConfirmedSignaturesForAddress2Config;
+use solana_sdk::{pubkey::Pubkey, signature::Signature};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+/// Addresses resolved at once while building or refreshing an index
+const RESOLVE_CONCURRENCY: usize = 8;
+
+/// What an address registered on the ledger, plus where the scan stopped
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IndexedIdentity {
+    pub pubkey: Pubkey,
+    pub display_name: Option<String>,
+    pub metadata: HashMap<String, String>,
+    /// Fields the holder redacted; their values are not indexed
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub redacted_fields: Vec<String>,
+    /// Newest signature seen for the address when it was last resolved
+    pub last_signature: Option<Signature>,
+}
+
+/// Reverse lookup from registered names and metadata to the addresses that claimed them
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct IdentityIndex {
+    #[serde(with = "crate::pubkey_map")]
+    identities: HashMap<Pubkey, IndexedIdentity>,
+}
+
+impl IdentityIndex {
+    pub fn load(path: &Path) -> Result<Self> {
+        let data = fs::read_to_string(path)?;
+        serde_json::from_str(&data).map_err(|e| anyhow!("Corrupt identity index {}: {}", path.display(), e))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            if !parent.as_os_str().is_empty() {
+                fs::create_dir_all(parent)?;
+            }
+        }
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, serde_json::to_string_pretty(self)?)?;
+        fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.identities.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.identities.is_empty()
+    }
+
+    pub fn get(&self, pubkey: &Pubkey) -> Option<&IndexedIdentity> {
+        self.identities.get(pubkey)
+    }
+
+    pub fn display_name(&self, pubkey: &Pubkey) -> Option<&str> {
+        self.identities.get(pubkey)?.display_name.as_deref()
+    }
+
+    #[cfg(test)]
+    pub(crate) fn insert(&mut self, identity: IndexedIdentity) {
+        self.identities.insert(identity.pubkey, identity);
+    }
+
+    /// Every address that registered `name`, compared case-insensitively
+    ///
+    /// More than one result means several addresses claim the same name; callers must decide
+    /// which to trust rather than pick one.
+    pub fn lookup_by_name(&self, name: &str) -> Vec<Pubkey> {
+        let wanted = name.trim().to_lowercase();
+        self.matching(|identity| {
+            identity
+                .display_name
+                .as_deref()
+                .map(|n| n.trim().to_lowercase() == wanted)
+                .unwrap_or(false)
+        })
+    }
+
+    /// Every address whose registered metadata has `key` set to `value` (value case-insensitive)
+    pub fn lookup_by_metadata(&self, key: &str, value: &str) -> Vec<Pubkey> {
+        let wanted = value.trim().to_lowercase();
+        self.matching(|identity| {
+            identity
+                .metadata
+                .get(key)
+                .map(|v| v.trim().to_lowercase() == wanted)
+                .unwrap_or(false)
+        })
+    }
+
+    fn matching(&self, predicate: impl Fn(&IndexedIdentity) -> bool) -> Vec<Pubkey> {
+        let mut found: Vec<Pubkey> = self
+            .identities
+            .values()
+            .filter(|identity| predicate(identity))
+            .map(|identity| identity.pubkey)
+            .collect();
+        found.sort();
+        found
+    }
+}
+
+impl FinternetClient {
+    /// Resolve the registered identity of every address into a searchable index
+    #[tracing::instrument(skip_all, fields(addresses = addresses.len()))]
+    pub async fn build_identity_index(&self, addresses: &[Pubkey]) -> Result<IdentityIndex> {
+        let mut index = IdentityIndex::default();
+        for identity in self.resolve_indexed(addresses)? {
+            index.identities.insert(identity.pubkey, identity);
+        }
+        tracing::info!("Identity index built with {} addresses", index.len());
+        Ok(index)
+    }
+
+    /// Re-resolve only the addresses with new signatures since the index last saw them,
+    /// adding any of `addresses` not yet indexed. Returns how many addresses were rescanned.
+    #[tracing::instrument(skip_all, fields(indexed = index.len(), addresses = addresses.len()))]
+    pub async fn refresh_identity_index(&self, index: &mut IdentityIndex, addresses: &[Pubkey]) -> Result<usize> {
+        let mut candidates: Vec<Pubkey> = index.identities.keys().copied().collect();
+        for address in addresses {
+            if !index.identities.contains_key(address) {
+                candidates.push(*address);
+            }
+        }
+
+        let mut stale = Vec::new();
+        for address in candidates {
+            let known = index.identities.get(&address).and_then(|identity| identity.last_signature);
+            let newest = self.newest_signature(&address)?;
+            if known.is_none() || newest != known {
+                stale.push(address);
+            }
+        }
+
+        for identity in self.resolve_indexed(&stale)? {
+            index.identities.insert(identity.pubkey, identity);
+        }
+        tracing::info!("Identity index refreshed: {} of {} addresses rescanned", stale.len(), index.len());
+        Ok(stale.len())
+    }
+
+    fn resolve_indexed(&self, addresses: &[Pubkey]) -> Result<Vec<IndexedIdentity>> {
+        if addresses.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        // RPC calls block, so spread the addresses over a few threads
+        let chunk_size = addresses.len().div_ceil(RESOLVE_CONCURRENCY);
+        let results: Vec<Result<IndexedIdentity>> = std::thread::scope(|scope| {
+            let workers: Vec<_> = addresses
+                .chunks(chunk_size)
+                .map(|chunk| {
+                    scope.spawn(move || {
+                        chunk
+                            .iter()
+                            .map(|address| self.resolve_one(address))
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect();
+            workers
+                .into_iter()
+                .flat_map(|worker| worker.join().unwrap_or_default())
+                .collect()
+        });
+
+        let mut resolved = Vec::new();
+        for result in results {
+            match result {
+                Ok(identity) => resolved.push(identity),
+                Err(e) => tracing::warn!("Skipping address in identity index: {}", e),
+            }
+        }
+        Ok(resolved)
+    }
+
+    fn resolve_one(&self, address: &Pubkey) -> Result<IndexedIdentity> {
+        // Note the newest signature first so anything landing mid-scan triggers a rescan next time
+        let last_signature = self.newest_signature(address)?;
+        let registered = futures::executor::block_on(self.get_registered_identity(address))?;
+        Ok(IndexedIdentity {
+            pubkey: *address,
+            display_name: registered.as_ref().and_then(|identity| identity.display_name.clone()),
+            redacted_fields: registered.as_ref().map(|identity| identity.redacted_fields.clone()).unwrap_or_default(),
+            metadata: registered.map(|identity| identity.metadata).unwrap_or_default(),
+            last_signature,
+        })
+    }
+
+    fn newest_signature(&self, address: &Pubkey) -> Result<Option<Signature>> {
+        let newest = self.client.get_signatures_for_address_with_config(
+            address,
+            GetConfirmedSignaturesForAddress2Config {
+                limit: Some(1),
+                ..GetConfirmedSignaturesForAddress2Config::default()
+            },
+        )?;
+        newest
+            .first()
+            .map(|info| Signature::from_str(&info.signature).map_err(Into::into))
+            .transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_cluster::TempDir;
+
+    #[test]
+    fn a_saved_index_loads_back_keyed_by_address() {
+        let dir = TempDir::new("identity-index");
+        let path = dir.join("index.json");
+        let pubkey = Pubkey::new_unique();
+        let mut index = IdentityIndex::default();
+        index.insert(IndexedIdentity {
+            pubkey,
+            display_name: Some("Acme Treasury".to_string()),
+            metadata: HashMap::from([("team".to_string(), "Treasury".to_string())]),
+            redacted_fields: Vec::new(),
+            last_signature: Some(Signature::new_unique()),
+        });
+        index.save(&path).unwrap();
+
+        let saved: serde_json::Value = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(saved["identities"][pubkey.to_string()]["display_name"], "Acme Treasury");
+        let loaded = IdentityIndex::load(&path).unwrap();
+        assert_eq!(loaded.lookup_by_name("acme treasury"), [pubkey]);
+        assert_eq!(loaded.lookup_by_metadata("team", "TREASURY"), [pubkey]);
+        assert_eq!(loaded.get(&pubkey).unwrap().last_signature, index.get(&pubkey).unwrap().last_signature);
+
+        fs::write(&path, r#"{"identities":{"not-an-address":{}}}"#).unwrap();
+        assert!(IdentityIndex::load(&path).is_err());
+    }
+}
@@ -0,0 +1,241 @@
+//! Raw instruction builders behind the high-level client methods
+//!
+//! Everything here is pure: no RPC calls and no signing. Callers can mix these instructions
+//! with their own, prepend compute budget instructions, and sign however they like.
+
+use crate::asset::TokenizeOptions;
+use crate::validation::MAX_ASSET_NAME_BYTES;
+use anyhow::{anyhow, Result};
+use mpl_token_metadata::{
+    accounts::Metadata,
+    instructions::{CreateMetadataAccountV3, CreateMetadataAccountV3InstructionArgs},
+    types::DataV2,
+};
+use solana_sdk::{instruction::{AccountMeta, Instruction}, program_pack::Pack, pubkey::Pubkey, rent::Rent, system_instruction};
+use spl_associated_token_account::instruction as ata_instruction;
+use spl_token::instruction as token_instruction;
+
+/// Pay `amount` base units of `mint` from `from`'s ATA to the wallet `to`, with an optional memo
+///
+/// The recipient ATA is created idempotently, so the instructions are valid whether or not it
+/// already exists. `from` signs the transfer and memo and pays for the ATA if it is created.
+pub fn payment_instructions(
+    from: &Pubkey,
+    to: &Pubkey,
+    amount: u64,
+    mint: &Pubkey,
+    memo: Option<&str>,
+) -> Result<Vec<Instruction>> {
+    let to_ata = spl_associated_token_account::get_associated_token_address(to, mint);
+    let mut instructions = vec![ata_instruction::create_associated_token_account_idempotent(
+        from,
+        to,
+        mint,
+        &spl_token::id(),
+    )];
+    instructions.push(token_transfer_instruction(from, &to_ata, amount, mint)?);
+    instructions.extend(memo.map(|memo| memo_instruction(from, memo)));
+    Ok(instructions)
+}
+
+/// Transfer from `from`'s ATA straight into an existing token account of `mint`
+pub fn token_transfer_instruction(
+    from: &Pubkey,
+    to_token_account: &Pubkey,
+    amount: u64,
+    mint: &Pubkey,
+) -> Result<Instruction> {
+    let from_ata = spl_associated_token_account::get_associated_token_address(from, mint);
+    Ok(token_instruction::transfer(
+        &spl_token::id(),
+        &from_ata,
+        to_token_account,
+        from,
+        &[from],
+        amount,
+    )?)
+}
+
+/// `token_transfer_instruction` that also has the token program check the mint and its decimals
+pub fn token_transfer_checked_instruction(
+    from: &Pubkey,
+    to_token_account: &Pubkey,
+    amount: u64,
+    mint: &Pubkey,
+    decimals: u8,
+) -> Result<Instruction> {
+    let from_ata = spl_associated_token_account::get_associated_token_address(from, mint);
+    Ok(token_instruction::transfer_checked(
+        &spl_token::id(),
+        &from_ata,
+        mint,
+        to_token_account,
+        from,
+        &[from],
+        amount,
+        decimals,
+    )?)
+}
+
+/// Memo signed by `signer`
+pub fn memo_instruction(signer: &Pubkey, memo: &str) -> Instruction {
+    ledger_entry_instruction(signer, memo.as_bytes())
+}
+
+/// Ledger entry as written by `write_ledger_entry`: a memo carrying `data`, signed by `signer`
+///
+/// `data` is written as given; prefix it with `namespace::namespaced` for clients to read it back.
+pub fn ledger_entry_instruction(signer: &Pubkey, data: &[u8]) -> Instruction {
+    spl_memo::build_memo(data, &[signer])
+}
+
+/// A zero-lamport transfer from `payer` to itself that lists `references` as read-only accounts
+///
+/// The memo program requires every account it is given to sign, so a ledger entry cannot name
+/// other addresses itself. The system program ignores the extra accounts; listing them puts the
+/// transaction in each reference's signature history, where `read_ledger_entries` finds it.
+pub fn reference_instruction(payer: &Pubkey, references: &[Pubkey]) -> Instruction {
+    let mut instruction = system_instruction::transfer(payer, payer, 0);
+    instruction
+        .accounts
+        .extend(references.iter().map(|reference| AccountMeta::new_readonly(*reference, false)));
+    instruction
+}
+
+/// Longest metadata memo `tokenize_asset_ledger_instructions` accepts; the mint instructions
+/// and two signatures take up the rest of the 1232-byte transaction
+pub const MAX_LEDGER_METADATA_BYTES: usize = 600;
+
+/// Rent-exempt balance for a mint account on clusters using the default rent parameters
+pub fn mint_rent_lamports() -> u64 {
+    Rent::default().minimum_balance(spl_token::state::Mint::LEN)
+}
+
+/// Create the 1-of-1 asset mint `mint`, mint the token to `payer`'s ATA, and attach Metaplex
+/// metadata pointing at `uri`
+///
+/// `payer` is the update authority and must sign, as must `mint` (a fresh keypair). `payer`
+/// holds the mint authority while the token and metadata are created, then hands it to
+/// `options.mint_authority`; the freeze authority is `options.freeze_authority` from the start.
+/// `mint_rent_lamports` funds the mint account; `mint_rent_lamports()` gives the usual value.
+/// Symbol, creators, and royalty come from `options`; its RPC and publishing fields are unused.
+pub fn tokenize_asset_instructions(
+    payer: &Pubkey,
+    mint: &Pubkey,
+    name: &str,
+    uri: &str,
+    mint_rent_lamports: u64,
+    options: &TokenizeOptions,
+) -> Result<Vec<Instruction>> {
+    options.validate()?;
+    if name.len() > MAX_ASSET_NAME_BYTES {
+        return Err(anyhow!("Asset name is longer than {} bytes", MAX_ASSET_NAME_BYTES));
+    }
+
+    let data = DataV2 {
+        name: name.to_string(),
+        symbol: options.symbol().to_string(),
+        uri: uri.to_string(),
+        seller_fee_basis_points: options.seller_fee_basis_points,
+        creators: Some(options.metaplex_creators(payer)),
+        collection: None,
+        uses: None,
+    };
+    let create_metadata_ix = CreateMetadataAccountV3 {
+        metadata: Metadata::find_pda(mint).0,
+        mint: *mint,
+        mint_authority: *payer,
+        payer: *payer,
+        update_authority: (*payer, true),
+        system_program: solana_sdk::system_program::id(),
+        rent: None,
+    }
+    .instruction(CreateMetadataAccountV3InstructionArgs {
+        data,
+        is_mutable: true,
+        collection_details: None,
+    });
+
+    let mut instructions = mint_asset_instructions(payer, mint, mint_rent_lamports, options)?;
+    instructions.push(create_metadata_ix);
+    instructions.extend(mint_authority_handover(payer, mint, options)?);
+    Ok(instructions)
+}
+
+/// `tokenize_asset_instructions` for clusters without Token Metadata: the same mint, with
+/// `metadata_entry` (a JSON ledger entry describing the asset) as a memo signed by `payer`
+/// in place of the Metaplex metadata account
+pub fn tokenize_asset_ledger_instructions(
+    payer: &Pubkey,
+    mint: &Pubkey,
+    metadata_entry: &str,
+    mint_rent_lamports: u64,
+    options: &TokenizeOptions,
+) -> Result<Vec<Instruction>> {
+    options.validate()?;
+    if metadata_entry.len() > MAX_LEDGER_METADATA_BYTES {
+        return Err(anyhow!(
+            "Asset metadata is {} bytes as a ledger memo, at most {} fit in the mint transaction; shorten the description",
+            metadata_entry.len(),
+            MAX_LEDGER_METADATA_BYTES
+        ));
+    }
+
+    let mut instructions = mint_asset_instructions(payer, mint, mint_rent_lamports, options)?;
+    instructions.push(ledger_entry_instruction(payer, metadata_entry.as_bytes()));
+    instructions.extend(mint_authority_handover(payer, mint, options)?);
+    Ok(instructions)
+}
+
+/// Create `mint` with no decimals, `payer` as mint authority and the configured freeze
+/// authority, and mint one token to `payer`'s ATA
+fn mint_asset_instructions(
+    payer: &Pubkey,
+    mint: &Pubkey,
+    mint_rent_lamports: u64,
+    options: &TokenizeOptions,
+) -> Result<Vec<Instruction>> {
+    let create_mint_account_ix = system_instruction::create_account(
+        payer,
+        mint,
+        mint_rent_lamports,
+        spl_token::state::Mint::LEN as u64,
+        &spl_token::id(),
+    );
+    let init_mint_ix = token_instruction::initialize_mint(
+        &spl_token::id(),
+        mint,
+        payer,
+        options.freeze_authority.resolve(payer).as_ref(),
+        0, // decimals
+    )?;
+
+    let associated_token_account = spl_associated_token_account::get_associated_token_address(payer, mint);
+    let create_ata_ix = ata_instruction::create_associated_token_account(payer, payer, mint, &spl_token::id());
+    let mint_to_ix = token_instruction::mint_to(
+        &spl_token::id(),
+        mint,
+        &associated_token_account,
+        payer,
+        &[payer],
+        1,
+    )?;
+
+    Ok(vec![create_mint_account_ix, init_mint_ix, create_ata_ix, mint_to_ix])
+}
+
+/// Move the mint authority from `payer` to `options.mint_authority`, if that is someone else
+fn mint_authority_handover(payer: &Pubkey, mint: &Pubkey, options: &TokenizeOptions) -> Result<Option<Instruction>> {
+    let new_authority = options.mint_authority.resolve(payer);
+    if new_authority == Some(*payer) {
+        return Ok(None);
+    }
+    Ok(Some(token_instruction::set_authority(
+        &spl_token::id(),
+        mint,
+        new_authority.as_ref(),
+        token_instruction::AuthorityType::MintTokens,
+        payer,
+        &[payer],
+    )?))
+}
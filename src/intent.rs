@@ -0,0 +1,393 @@
+//! Payment intents signed by end users, for API payments submitted from untrusted frontends
+//!
+//! A frontend submits a `PaymentIntent` the user signed with their own wallet in the Solana
+//! off-chain message format, naming the recipient, amount, mint, a nonce, and an expiry.
+//! `FinternetClient::verify_payment_intent` checks the signature against the user it names.
+//! `IntentStore::reserve` then refuses an expired intent, a nonce the user already spent, and an
+//! amount over the user's daily limit for the mint, and otherwise burns the nonce and reserves the
+//! amount in one step. A payment that then fails gives its allowance back with `release`; its
+//! nonce stays spent. Each refusal is its own `IntentError`, which the API server answers with its
+//! own 4xx status.
+
+use crate::state_store::{FileStateStore, Namespace, StateStore};
+use crate::FinternetClient;
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use solana_sdk::pubkey::Pubkey;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// A payment an end user authorized by signing it with their own wallet
+///
+/// The signed message is the JSON serialization of this struct, wrapped in the Solana
+/// off-chain message format.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PaymentIntent {
+    /// Wallet that signed the intent and whose limits apply
+    pub user: Pubkey,
+    pub recipient: Pubkey,
+    /// Amount in the mint's base units
+    pub amount: u64,
+    pub mint: Pubkey,
+    /// Unique per user; a nonce can be spent exactly once
+    pub nonce: String,
+    /// Unix timestamp after which the intent is no longer honored
+    pub expires_at: u64,
+    pub memo: Option<String>,
+}
+
+/// Why a signed payment intent was rejected
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IntentError {
+    Malformed(String),
+    InvalidSignature,
+    Expired { expired_at: u64 },
+    ReplayedNonce { nonce: String },
+    LimitExceeded { limit: u64, spent: u64, requested: u64 },
+}
+
+impl IntentError {
+    /// Stable machine-readable identifier for API responses
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::Malformed(_) => "malformed_intent",
+            Self::InvalidSignature => "invalid_signature",
+            Self::Expired { .. } => "intent_expired",
+            Self::ReplayedNonce { .. } => "nonce_replayed",
+            Self::LimitExceeded { .. } => "daily_limit_exceeded",
+        }
+    }
+}
+
+impl fmt::Display for IntentError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Malformed(reason) => write!(f, "Malformed payment intent: {}", reason),
+            Self::InvalidSignature => write!(f, "Intent signature does not match the user's wallet"),
+            Self::Expired { expired_at } => write!(f, "Intent expired at {}", expired_at),
+            Self::ReplayedNonce { nonce } => write!(f, "Nonce {} has already been used", nonce),
+            Self::LimitExceeded { limit, spent, requested } => write!(
+                f,
+                "Daily limit of {} exceeded: {} already spent, {} requested",
+                limit, spent, requested
+            ),
+        }
+    }
+}
+
+impl std::error::Error for IntentError {}
+
+impl FinternetClient {
+    /// Parse a signed intent message and check its off-chain signature against the user it names
+    pub fn verify_payment_intent(message: &str, signature: &str) -> Result<PaymentIntent, IntentError> {
+        let intent: PaymentIntent =
+            serde_json::from_str(message).map_err(|e| IntentError::Malformed(e.to_string()))?;
+        if intent.nonce.is_empty() {
+            return Err(IntentError::Malformed("nonce must not be empty".to_string()));
+        }
+        if intent.amount == 0 {
+            return Err(IntentError::Malformed("amount must be greater than zero".to_string()));
+        }
+
+        match Self::verify_offchain_message(&intent.user, message, signature) {
+            Ok(true) => Ok(intent),
+            Ok(false) => Err(IntentError::InvalidSignature),
+            Err(e) => Err(IntentError::Malformed(e.to_string())),
+        }
+    }
+}
+
+/// Namespace of burned nonces, keyed `user:nonce`, each kept until its intent expires
+pub const INTENT_NONCES_NAMESPACE: &str = "intent_nonces";
+/// Namespace of base units spent, keyed `user:mint:YYYY-MM-DD`
+pub const INTENT_DAILY_TOTALS_NAMESPACE: &str = "intent_daily_totals";
+/// How long a day's total is kept; long enough to release a reservation made just before midnight
+const DAILY_TOTAL_TTL: Duration = Duration::from_secs(2 * 24 * 60 * 60);
+
+/// The file `IntentStore` wrote before it moved onto a `StateStore`
+#[derive(Debug, Default, Deserialize)]
+struct LegacyIntentLedger {
+    /// user -> nonce -> expiry
+    nonces: HashMap<String, HashMap<String, u64>>,
+    /// "user:mint:YYYY-MM-DD" -> base units spent that UTC day
+    daily_totals: HashMap<String, u64>,
+}
+
+fn import_legacy_intents(raw: serde_json::Value) -> Result<Vec<(String, String, serde_json::Value)>> {
+    let ledger: LegacyIntentLedger = serde_json::from_value(raw)?;
+    let nonces = ledger.nonces.into_iter().flat_map(|(user, nonces)| {
+        nonces.into_iter().map(move |(nonce, expires_at)| {
+            (INTENT_NONCES_NAMESPACE.to_string(), format!("{}:{}", user, nonce), expires_at.into())
+        })
+    });
+    let totals = ledger
+        .daily_totals
+        .into_iter()
+        .map(|(key, spent)| (INTENT_DAILY_TOTALS_NAMESPACE.to_string(), key, spent.into()));
+    Ok(nonces.chain(totals).collect())
+}
+
+/// Persistent record of used nonces and per-user daily spend
+pub struct IntentStore {
+    /// Burned nonce -> expiry of its intent
+    nonces: Namespace<u64>,
+    daily_totals: Namespace<u64>,
+    /// Held across each check-then-reserve so two intents cannot both pass the same check
+    lock: Mutex<()>,
+}
+
+impl IntentStore {
+    /// Keep nonces and totals in their own file at `path`
+    pub fn open(path: &Path) -> Result<Self> {
+        let store = FileStateStore::open_registry(path, &import_legacy_intents)?;
+        Ok(Self::new(Arc::new(store)))
+    }
+
+    pub fn new(store: Arc<dyn StateStore>) -> Self {
+        Self {
+            nonces: Namespace::new(Arc::clone(&store), INTENT_NONCES_NAMESPACE),
+            daily_totals: Namespace::new(store, INTENT_DAILY_TOTALS_NAMESPACE),
+            lock: Mutex::new(()),
+        }
+    }
+
+    /// Atomically check expiry, nonce, and daily limit, then burn the nonce and reserve the amount
+    pub fn reserve(&self, intent: &PaymentIntent, daily_limit: u64, now: u64) -> Result<()> {
+        if intent.expires_at <= now {
+            return Err(IntentError::Expired { expired_at: intent.expires_at }.into());
+        }
+
+        let _guard = self.lock.lock().map_err(|_| anyhow!("Intent store lock poisoned"))?;
+        let nonce_key = format!("{}:{}", intent.user, intent.nonce);
+        // Nonces expire with their intents, which can no longer be submitted anyway
+        if self.nonces.get(&nonce_key)?.is_some_and(|expires_at| expires_at > now) {
+            return Err(IntentError::ReplayedNonce { nonce: intent.nonce.clone() }.into());
+        }
+
+        let key = daily_key(intent, now);
+        let spent = self.daily_totals.get(&key)?.unwrap_or(0);
+        if spent.saturating_add(intent.amount) > daily_limit {
+            return Err(IntentError::LimitExceeded {
+                limit: daily_limit,
+                spent,
+                requested: intent.amount,
+            }
+            .into());
+        }
+
+        self.nonces
+            .put_with_ttl(&nonce_key, &intent.expires_at, Duration::from_secs(intent.expires_at - now))?;
+        self.daily_totals.put_with_ttl(&key, &(spent + intent.amount), DAILY_TOTAL_TTL)
+    }
+
+    /// Give back the daily allowance of an intent whose payment failed; the nonce stays burned
+    pub fn release(&self, intent: &PaymentIntent, reserved_at: u64) -> Result<()> {
+        let _guard = self.lock.lock().map_err(|_| anyhow!("Intent store lock poisoned"))?;
+        let key = daily_key(intent, reserved_at);
+        if let Some(total) = self.daily_totals.get(&key)? {
+            self.daily_totals.put_with_ttl(&key, &total.saturating_sub(intent.amount), DAILY_TOTAL_TTL)?;
+        }
+        Ok(())
+    }
+}
+
+fn daily_key(intent: &PaymentIntent, now: u64) -> String {
+    let day = chrono::DateTime::from_timestamp(now as i64, 0)
+        .map(|dt| dt.format("%Y-%m-%d").to_string())
+        .unwrap_or_default();
+    format!("{}:{}:{}", intent.user, intent.mint, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_cluster::TempDir;
+    use crate::state_store::MemoryStateStore;
+    use solana_sdk::signature::{Keypair, Signer};
+
+    /// 2024-05-10 12:00:00 UTC
+    const NOW: u64 = 1_715_342_400;
+    const DAY: u64 = 24 * 60 * 60;
+
+    fn intent(user: &Keypair, amount: u64, nonce: &str) -> PaymentIntent {
+        PaymentIntent {
+            user: user.pubkey(),
+            recipient: Pubkey::new_from_array([2; 32]),
+            amount,
+            mint: Pubkey::new_from_array([3; 32]),
+            nonce: nonce.to_string(),
+            expires_at: NOW + 600,
+            memo: None,
+        }
+    }
+
+    fn signed(user: &Keypair, intent: &PaymentIntent) -> (String, String) {
+        let message = serde_json::to_string(intent).unwrap();
+        let signature = FinternetClient::sign_offchain_message(user, &message).unwrap();
+        (message, signature)
+    }
+
+    fn refusal(result: Result<()>) -> IntentError {
+        result.unwrap_err().downcast::<IntentError>().expect("an intent error")
+    }
+
+    fn memory_store() -> IntentStore {
+        IntentStore::new(Arc::new(MemoryStateStore::new()))
+    }
+
+    #[test]
+    fn only_the_named_user_can_sign_an_intent() {
+        let user = Keypair::new();
+        let (message, signature) = signed(&user, &intent(&user, 100, "n-1"));
+        let verified = FinternetClient::verify_payment_intent(&message, &signature).unwrap();
+        assert_eq!((verified.user, verified.amount, verified.nonce.as_str()), (user.pubkey(), 100, "n-1"));
+
+        // Someone else signing an intent in the user's name
+        let (_, forged) = signed(&Keypair::new(), &intent(&user, 100, "n-1"));
+        assert_eq!(FinternetClient::verify_payment_intent(&message, &forged).unwrap_err(), IntentError::InvalidSignature);
+
+        // The frontend raising the amount after the user signed
+        let tampered = message.replace("\"amount\":100", "\"amount\":100000");
+        assert_ne!(tampered, message);
+        assert_eq!(FinternetClient::verify_payment_intent(&tampered, &signature).unwrap_err(), IntentError::InvalidSignature);
+    }
+
+    #[test]
+    fn malformed_intents_are_refused_before_the_signature_is_checked() {
+        let user = Keypair::new();
+        for bad in [intent(&user, 0, "n-1"), intent(&user, 100, "")] {
+            let (message, signature) = signed(&user, &bad);
+            let error = FinternetClient::verify_payment_intent(&message, &signature).unwrap_err();
+            assert_eq!(error.code(), "malformed_intent", "{}", error);
+        }
+        let error = FinternetClient::verify_payment_intent("{\"user\": 1}", "sig").unwrap_err();
+        assert_eq!(error.code(), "malformed_intent");
+        let (message, _) = signed(&user, &intent(&user, 100, "n-1"));
+        let error = FinternetClient::verify_payment_intent(&message, "not-a-signature").unwrap_err();
+        assert_eq!(error.code(), "malformed_intent");
+    }
+
+    #[test]
+    fn a_replayed_nonce_is_refused_for_as_long_as_its_intent_could_be_submitted() {
+        let (user, other) = (Keypair::new(), Keypair::new());
+        let store = memory_store();
+        store.reserve(&intent(&user, 10, "n-1"), 1_000, NOW).unwrap();
+
+        let replay = refusal(store.reserve(&intent(&user, 10, "n-1"), 1_000, NOW + 599));
+        assert_eq!(replay, IntentError::ReplayedNonce { nonce: "n-1".to_string() });
+        // A replay with a later expiry is still the same nonce
+        let mut extended = intent(&user, 10, "n-1");
+        extended.expires_at = NOW + DAY;
+        assert_eq!(refusal(store.reserve(&extended, 1_000, NOW + 599)).code(), "nonce_replayed");
+
+        // Nonces are per user
+        store.reserve(&intent(&other, 10, "n-1"), 1_000, NOW).unwrap();
+        // Once the first intent has expired, replaying it is refused as expired
+        assert_eq!(refusal(store.reserve(&intent(&user, 10, "n-1"), 1_000, NOW + 600)).code(), "intent_expired");
+    }
+
+    #[test]
+    fn an_intent_at_or_past_its_expiry_is_refused_and_burns_nothing() {
+        let user = Keypair::new();
+        let store = memory_store();
+        let expired = intent(&user, 10, "n-1");
+        assert_eq!(
+            refusal(store.reserve(&expired, 1_000, expired.expires_at)),
+            IntentError::Expired { expired_at: expired.expires_at }
+        );
+        // The refused intent neither spent its nonce nor counted against the limit
+        store.reserve(&expired, 10, expired.expires_at - 1).unwrap();
+    }
+
+    #[test]
+    fn the_daily_limit_is_per_user_mint_and_utc_day() {
+        let user = Keypair::new();
+        let store = memory_store();
+        store.reserve(&intent(&user, 60, "n-1"), 100, NOW).unwrap();
+        store.reserve(&intent(&user, 40, "n-2"), 100, NOW).unwrap();
+        assert_eq!(
+            refusal(store.reserve(&intent(&user, 1, "n-3"), 100, NOW)),
+            IntentError::LimitExceeded { limit: 100, spent: 100, requested: 1 }
+        );
+        assert_eq!(refusal(store.reserve(&intent(&user, u64::MAX, "n-3"), 100, NOW)).code(), "daily_limit_exceeded");
+
+        // The refused nonce was not burned, and another mint has its own allowance
+        let mut other_mint = intent(&user, 100, "n-3");
+        other_mint.mint = Pubkey::new_from_array([4; 32]);
+        store.reserve(&other_mint, 100, NOW).unwrap();
+
+        // The next UTC day starts from zero
+        let tomorrow = NOW - NOW % DAY + DAY;
+        let mut next_day = intent(&user, 100, "n-4");
+        next_day.expires_at = tomorrow + 600;
+        store.reserve(&next_day, 100, tomorrow).unwrap();
+    }
+
+    #[test]
+    fn a_failed_payment_gives_back_its_allowance_but_not_its_nonce() {
+        let user = Keypair::new();
+        let store = memory_store();
+        let first = intent(&user, 80, "n-1");
+        store.reserve(&first, 100, NOW).unwrap();
+        store.release(&first, NOW).unwrap();
+
+        assert_eq!(refusal(store.reserve(&first, 100, NOW + 1)).code(), "nonce_replayed");
+        store.reserve(&intent(&user, 100, "n-2"), 100, NOW + 1).unwrap();
+    }
+
+    #[test]
+    fn concurrent_intents_cannot_both_take_the_last_of_the_limit() {
+        let user = Keypair::new();
+        let store = memory_store();
+        let admitted = std::sync::atomic::AtomicUsize::new(0);
+        std::thread::scope(|scope| {
+            for thread in 0..16 {
+                let (store, admitted, user) = (&store, &admitted, &user);
+                scope.spawn(move || {
+                    if store.reserve(&intent(user, 10, &format!("n-{}", thread)), 100, NOW).is_ok() {
+                        admitted.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                    }
+                });
+            }
+        });
+        assert_eq!(admitted.into_inner(), 10);
+    }
+
+    #[test]
+    fn spent_nonces_and_totals_survive_a_restart() {
+        let dir = TempDir::new("intents");
+        let path = dir.join("intents.json");
+        let user = Keypair::new();
+        IntentStore::open(&path).unwrap().reserve(&intent(&user, 70, "n-1"), 100, NOW).unwrap();
+
+        let reopened = IntentStore::open(&path).unwrap();
+        assert_eq!(refusal(reopened.reserve(&intent(&user, 10, "n-1"), 100, NOW)).code(), "nonce_replayed");
+        assert_eq!(
+            refusal(reopened.reserve(&intent(&user, 31, "n-2"), 100, NOW)),
+            IntentError::LimitExceeded { limit: 100, spent: 70, requested: 31 }
+        );
+    }
+
+    #[test]
+    fn the_file_written_before_the_state_store_is_imported() {
+        let dir = TempDir::new("intents");
+        let path = dir.join("intents.json");
+        let user = Keypair::new();
+        let mint = Pubkey::new_from_array([3; 32]);
+        let legacy = serde_json::json!({
+            "nonces": { user.pubkey().to_string(): { "n-1": NOW + 600 } },
+            "daily_totals": { format!("{}:{}:2024-05-10", user.pubkey(), mint): 90 },
+        });
+        std::fs::write(&path, legacy.to_string()).unwrap();
+
+        let store = IntentStore::open(&path).unwrap();
+        assert_eq!(refusal(store.reserve(&intent(&user, 1, "n-1"), 100, NOW)).code(), "nonce_replayed");
+        assert_eq!(
+            refusal(store.reserve(&intent(&user, 11, "n-2"), 100, NOW)),
+            IntentError::LimitExceeded { limit: 100, spent: 90, requested: 11 }
+        );
+    }
+}
@@ -0,0 +1,224 @@
+use crate::resources::{approximate_bytes, CacheLimit, CacheUsage, LruMap};
+use crate::telemetry::readable;
+use crate::FinternetClient;
+use anyhow::{anyhow, Result};
+use mpl_token_metadata::accounts::Metadata;
+use serde::{Deserialize, Serialize};
+use solana_account_decoder::UiAccountEncoding;
+use solana_client::rpc_client::GetConfirmedSignaturesForAddress2Config;
+use solana_client::rpc_config::{RpcAccountInfoConfig, RpcProgramAccountsConfig};
+use solana_client::rpc_filter::{Memcmp, RpcFilterType};
+use solana_sdk::{pubkey::Pubkey, signature::Signature};
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Byte offset of the first creator's address in a puffed Metadata V1 account:
+/// key + update authority + mint + name(4+32) + symbol(4+10) + uri(4+200) + fee + option + vec len
+const FIRST_CREATOR_OFFSET: usize = 1 + 32 + 32 + 36 + 14 + 204 + 2 + 1 + 4;
+/// `Key::MetadataV1` discriminator
+const METADATA_V1_KEY: u8 = 4;
+/// How long an issuer's mint list is reused before the program accounts are scanned again
+const ISSUER_CACHE_TTL: Duration = Duration::from_secs(300);
+const SIGNATURE_PAGE_LIMIT: usize = 1000;
+const MAX_SIGNATURE_PAGES: usize = 10;
+
+/// A mint whose first verified creator is the issuer
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IssuedAsset {
+    pub mint: Pubkey,
+    pub name: String,
+    /// Oldest transaction touching the mint, i.e. the one that created it
+    pub creation_signature: Option<Signature>,
+    pub created_at: Option<u64>,
+    pub current_supply: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IssuedAssetPage {
+    pub assets: Vec<IssuedAsset>,
+    /// Total mints issued, across all pages
+    pub total: usize,
+    pub offset: usize,
+    pub limit: usize,
+}
+
+/// Mint and name of each asset an issuer created, with when the list was fetched
+type CachedMints = (Instant, Vec<(Pubkey, String)>);
+
+/// Issuer mint lists and mint creation records, shared by every call on a client
+///
+/// Each map keeps up to `ResourceLimits::issuance_cache` entries, least recently used first out.
+#[derive(Default)]
+pub(crate) struct IssuanceCache {
+    issuers: Mutex<LruMap<Pubkey, CachedMints>>,
+    /// Creation never changes, so these are only dropped to make room
+    creations: Mutex<LruMap<Pubkey, (Signature, Option<u64>)>>,
+}
+
+impl IssuanceCache {
+    pub(crate) fn new(limit: CacheLimit) -> Self {
+        Self {
+            issuers: Mutex::new(LruMap::new(limit)),
+            creations: Mutex::new(LruMap::new(limit)),
+        }
+    }
+
+    /// Usage of the issuer and the creation maps
+    pub(crate) fn usage(&self) -> (CacheUsage, CacheUsage) {
+        (
+            self.issuers.lock().map(|issuers| issuers.usage()).unwrap_or_default(),
+            self.creations.lock().map(|creations| creations.usage()).unwrap_or_default(),
+        )
+    }
+}
+
+impl FinternetClient {
+    /// Every asset ever issued by `issuer`, including ones no longer held
+    pub async fn get_issued_assets(&self, issuer: &Pubkey) -> Result<Vec<IssuedAsset>> {
+        Ok(self.get_issued_assets_page(issuer, 0, usize::MAX).await?.assets)
+    }
+
+    /// One page of `get_issued_assets`, ordered by name then mint
+    ///
+    /// The mint list comes from the metadata program, filtered on the first creator, and is
+    /// cached for a few minutes. Creation details and supply are only looked up for the page.
+    #[tracing::instrument(skip_all, fields(wallet = %readable(issuer), offset, limit, rpc = %self.rpc_endpoint()))]
+    pub async fn get_issued_assets_page(
+        &self,
+        issuer: &Pubkey,
+        offset: usize,
+        limit: usize,
+    ) -> Result<IssuedAssetPage> {
+        let mints = self.issued_mints(issuer)?;
+        let total = mints.len();
+
+        let mut assets = Vec::new();
+        for (mint, name) in mints.into_iter().skip(offset).take(limit) {
+            let (creation_signature, created_at) = match self.mint_creation(&mint) {
+                Ok(Some((signature, created_at))) => (Some(signature), created_at),
+                Ok(None) => (None, None),
+                Err(e) => {
+                    tracing::warn!("Could not find creation transaction of {}: {}", mint, e);
+                    (None, None)
+                }
+            };
+            let current_supply = self
+                .client
+                .get_token_supply(&mint)
+                .ok()
+                .and_then(|supply| supply.amount.parse().ok())
+                .unwrap_or(0);
+
+            assets.push(IssuedAsset {
+                mint,
+                name,
+                creation_signature,
+                created_at,
+                current_supply,
+            });
+        }
+
+        tracing::info!("Returning {} of {} issued assets", assets.len(), total);
+        Ok(IssuedAssetPage { assets, total, offset, limit })
+    }
+
+    pub(crate) fn issued_mints(&self, issuer: &Pubkey) -> Result<Vec<(Pubkey, String)>> {
+        {
+            let mut cache = self.issuance_cache.issuers.lock().map_err(|_| anyhow!("Issuance cache lock poisoned"))?;
+            if let Some((fetched_at, mints)) = cache.get(issuer) {
+                if fetched_at.elapsed() < ISSUER_CACHE_TTL {
+                    return Ok(mints.clone());
+                }
+            }
+        }
+
+        let config = RpcProgramAccountsConfig {
+            filters: Some(vec![
+                RpcFilterType::Memcmp(Memcmp::new_raw_bytes(0, vec![METADATA_V1_KEY])),
+                RpcFilterType::Memcmp(Memcmp::new_raw_bytes(FIRST_CREATOR_OFFSET, issuer.to_bytes().to_vec())),
+            ]),
+            account_config: RpcAccountInfoConfig {
+                encoding: Some(UiAccountEncoding::Base64),
+                ..RpcAccountInfoConfig::default()
+            },
+            ..RpcProgramAccountsConfig::default()
+        };
+        let accounts = self
+            .client
+            .get_program_accounts_with_config(&mpl_token_metadata::ID, config)?;
+
+        let mut mints: Vec<(Pubkey, String)> = accounts
+            .into_iter()
+            .filter_map(|(address, account)| match Metadata::from_bytes(&account.data) {
+                Ok(metadata) => Some(metadata),
+                Err(e) => {
+                    tracing::debug!("Skipping undecodable metadata account {}: {}", address, e);
+                    None
+                }
+            })
+            // The memcmp only matches the address; the creator must also have signed
+            .filter(|metadata| {
+                metadata
+                    .creators
+                    .as_ref()
+                    .and_then(|creators| creators.first())
+                    .map(|creator| creator.verified && creator.address == *issuer)
+                    .unwrap_or(false)
+            })
+            .map(|metadata| (metadata.mint, metadata.name.trim_matches('\0').to_string()))
+            .collect();
+        mints.sort_by(|a, b| a.1.cmp(&b.1).then(a.0.cmp(&b.0)));
+
+        self.issuance_cache
+            .issuers
+            .lock()
+            .map_err(|_| anyhow!("Issuance cache lock poisoned"))?
+            .insert(*issuer, (Instant::now(), mints.clone()), approximate_bytes(&mints));
+        Ok(mints)
+    }
+
+    /// Walk the mint's signature history back to its oldest transaction
+    fn mint_creation(&self, mint: &Pubkey) -> Result<Option<(Signature, Option<u64>)>> {
+        if let Some(found) = self
+            .issuance_cache
+            .creations
+            .lock()
+            .map_err(|_| anyhow!("Issuance cache lock poisoned"))?
+            .get(mint)
+        {
+            return Ok(Some(*found));
+        }
+
+        // Mints rarely see more than a handful of transactions; very busy ones are capped and
+        // report the oldest signature within reach
+        let mut before = None;
+        let mut oldest = None;
+        for _ in 0..MAX_SIGNATURE_PAGES {
+            let page = self.client.get_signatures_for_address_with_config(
+                mint,
+                GetConfirmedSignaturesForAddress2Config {
+                    before,
+                    limit: Some(SIGNATURE_PAGE_LIMIT),
+                    ..GetConfirmedSignaturesForAddress2Config::default()
+                },
+            )?;
+            let Some(last) = page.last() else { break };
+            let signature = Signature::from_str(&last.signature)?;
+            oldest = Some((signature, last.block_time.map(|t| t.max(0) as u64)));
+            if page.len() < SIGNATURE_PAGE_LIMIT {
+                break;
+            }
+            before = Some(signature);
+        }
+
+        if let Some(found) = oldest {
+            self.issuance_cache
+                .creations
+                .lock()
+                .map_err(|_| anyhow!("Issuance cache lock poisoned"))?
+                .insert(*mint, found, std::mem::size_of::<(Pubkey, (Signature, Option<u64>))>());
+        }
+        Ok(oldest)
+    }
+}
@@ -0,0 +1,301 @@
+//! Where the time of a send, a history read, or a token discovery goes
+//!
+//! Each phase is timed as it runs, and the totals come back on the result as `PhaseTimings`:
+//! `SendReceipt::timings`, `TransactionHistory::timings`, and `TokenDiscovery::timings`. A phase
+//! whose average call exceeds its `Phase::budget` is logged as a warning when the operation
+//! finishes. Built with the `metrics` feature, the client also keeps a `PhaseHistograms` of every
+//! operation's phases, which the API server serves at `/metrics`.
+
+use crate::FinternetClient;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// One step of an operation, timed separately
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Phase {
+    /// Send middleware: validation, policies, screening, and the hooks of later stages
+    Checks,
+    /// Building the instructions, including reads of the accounts they touch
+    Build,
+    Blockhash,
+    /// Estimating the priority fee
+    Fees,
+    Simulation,
+    /// Handing the transaction to the node, including its preflight simulation
+    Broadcast,
+    Confirmation,
+    /// Listing an account's signatures
+    Signatures,
+    /// Fetching the transactions behind them
+    Transactions,
+    /// Reading token accounts
+    Accounts,
+    /// Reading mint and metadata accounts
+    Metadata,
+}
+
+impl Phase {
+    pub const ALL: [Phase; 11] = [
+        Self::Checks,
+        Self::Build,
+        Self::Blockhash,
+        Self::Fees,
+        Self::Simulation,
+        Self::Broadcast,
+        Self::Confirmation,
+        Self::Signatures,
+        Self::Transactions,
+        Self::Accounts,
+        Self::Metadata,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Checks => "checks",
+            Self::Build => "build",
+            Self::Blockhash => "blockhash",
+            Self::Fees => "fees",
+            Self::Simulation => "simulation",
+            Self::Broadcast => "broadcast",
+            Self::Confirmation => "confirmation",
+            Self::Signatures => "signatures",
+            Self::Transactions => "transactions",
+            Self::Accounts => "accounts",
+            Self::Metadata => "metadata",
+        }
+    }
+
+    /// How long one call of the phase is expected to take against a healthy RPC node
+    ///
+    /// Confirmation waits for the cluster, not the node, so its budget covers a `confirmed`
+    /// commitment a few slots out.
+    pub fn budget(&self) -> Duration {
+        let millis = match self {
+            Self::Checks => 250,
+            Self::Build => 500,
+            Self::Blockhash => 300,
+            Self::Fees => 500,
+            Self::Simulation => 800,
+            Self::Broadcast => 1_000,
+            Self::Confirmation => 15_000,
+            Self::Signatures => 1_000,
+            Self::Transactions => 500,
+            Self::Accounts => 1_000,
+            Self::Metadata => 500,
+        };
+        Duration::from_millis(millis)
+    }
+}
+
+impl fmt::Display for Phase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Time spent in one phase of an operation
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct PhaseTiming {
+    pub elapsed_ms: f64,
+    /// Times the phase ran, e.g. one per transaction fetched
+    pub calls: u32,
+}
+
+impl PhaseTiming {
+    pub fn mean_ms(&self) -> f64 {
+        if self.calls == 0 {
+            0.0
+        } else {
+            self.elapsed_ms / f64::from(self.calls)
+        }
+    }
+
+    /// Whether the average call took longer than `phase`'s budget
+    pub fn over_budget(&self, phase: Phase) -> bool {
+        self.mean_ms() > phase.budget().as_secs_f64() * 1000.0
+    }
+}
+
+/// The phases one operation ran, each with its total time; phases that did not run are absent
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct PhaseTimings {
+    phases: BTreeMap<Phase, PhaseTiming>,
+}
+
+impl PhaseTimings {
+    pub fn get(&self, phase: Phase) -> Option<PhaseTiming> {
+        self.phases.get(&phase).copied()
+    }
+
+    /// Phases in pipeline order
+    pub fn iter(&self) -> impl Iterator<Item = (Phase, PhaseTiming)> + '_ {
+        self.phases.iter().map(|(phase, timing)| (*phase, *timing))
+    }
+
+    pub fn total_ms(&self) -> f64 {
+        self.phases.values().map(|timing| timing.elapsed_ms).sum()
+    }
+
+    /// Phases whose average call exceeded their budget
+    pub fn over_budget(&self) -> Vec<(Phase, PhaseTiming)> {
+        self.iter().filter(|(phase, timing)| timing.over_budget(*phase)).collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.phases.is_empty()
+    }
+
+    pub(crate) fn add(&mut self, phase: Phase, elapsed: Duration) {
+        let timing = self.phases.entry(phase).or_default();
+        timing.elapsed_ms += elapsed.as_secs_f64() * 1000.0;
+        timing.calls += 1;
+    }
+
+    /// Run `f`, adding its time to `phase`
+    pub(crate) fn time<T>(&mut self, phase: Phase, f: impl FnOnce() -> T) -> T {
+        let started = Instant::now();
+        let value = f();
+        self.add(phase, started.elapsed());
+        value
+    }
+}
+
+/// `PhaseTimings` shared by the steps of a send, carried down to them on `RpcCallOptions`
+#[derive(Debug, Clone, Default)]
+pub(crate) struct PhaseClock(Arc<Mutex<PhaseTimings>>);
+
+impl PhaseClock {
+    pub(crate) fn add(&self, phase: Phase, elapsed: Duration) {
+        if let Ok(mut timings) = self.0.lock() {
+            timings.add(phase, elapsed);
+        }
+    }
+
+    pub(crate) fn timings(&self) -> PhaseTimings {
+        self.0.lock().map(|timings| timings.clone()).unwrap_or_default()
+    }
+}
+
+/// Run `f`, adding its time to `phase` on `clock` when the call is being timed
+pub(crate) fn timed<T>(clock: Option<&PhaseClock>, phase: Phase, f: impl FnOnce() -> T) -> T {
+    let started = Instant::now();
+    let value = f();
+    if let Some(clock) = clock {
+        clock.add(phase, started.elapsed());
+    }
+    value
+}
+
+/// Upper bounds, in milliseconds, of the histogram buckets; slower calls land in `+Inf`
+#[cfg(feature = "metrics")]
+pub const HISTOGRAM_BUCKETS_MS: [f64; 12] =
+    [5.0, 10.0, 25.0, 50.0, 100.0, 250.0, 500.0, 1_000.0, 2_500.0, 5_000.0, 10_000.0, 30_000.0];
+
+/// Observations of one phase, one per operation that ran it, of its average call
+#[cfg(feature = "metrics")]
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PhaseHistogram {
+    /// Observations at or under each of `HISTOGRAM_BUCKETS_MS`, not cumulative
+    pub buckets: Vec<u64>,
+    pub count: u64,
+    pub sum_ms: f64,
+}
+
+#[cfg(feature = "metrics")]
+impl PhaseHistogram {
+    fn observe(&mut self, millis: f64) {
+        if self.buckets.is_empty() {
+            self.buckets = vec![0; HISTOGRAM_BUCKETS_MS.len()];
+        }
+        if let Some(bucket) = HISTOGRAM_BUCKETS_MS.iter().position(|bound| millis <= *bound) {
+            self.buckets[bucket] += 1;
+        }
+        self.count += 1;
+        self.sum_ms += millis;
+    }
+}
+
+/// Latency histograms per operation and phase, for the life of the client
+#[cfg(feature = "metrics")]
+#[derive(Debug, Default)]
+pub struct PhaseHistograms {
+    histograms: Mutex<BTreeMap<(String, Phase), PhaseHistogram>>,
+}
+
+#[cfg(feature = "metrics")]
+impl PhaseHistograms {
+    fn observe(&self, operation: &str, timings: &PhaseTimings) {
+        let Ok(mut histograms) = self.histograms.lock() else {
+            return;
+        };
+        for (phase, timing) in timings.iter() {
+            histograms.entry((operation.to_string(), phase)).or_default().observe(timing.mean_ms());
+        }
+    }
+
+    pub fn get(&self, operation: &str, phase: Phase) -> Option<PhaseHistogram> {
+        self.histograms.lock().ok()?.get(&(operation.to_string(), phase)).cloned()
+    }
+
+    /// The histograms in the Prometheus text exposition format, as
+    /// `finternet_phase_duration_seconds` labelled by operation and phase
+    pub fn render_prometheus(&self) -> String {
+        let mut out = String::from(
+            "# HELP finternet_phase_duration_seconds Time spent in each phase of an SDK operation\n\
+             # TYPE finternet_phase_duration_seconds histogram\n",
+        );
+        let Ok(histograms) = self.histograms.lock() else {
+            return out;
+        };
+        for ((operation, phase), histogram) in histograms.iter() {
+            let labels = format!("operation=\"{}\",phase=\"{}\"", operation, phase);
+            let mut cumulative = 0;
+            for (bound, count) in HISTOGRAM_BUCKETS_MS.iter().zip(&histogram.buckets) {
+                cumulative += count;
+                out.push_str(&format!(
+                    "finternet_phase_duration_seconds_bucket{{{},le=\"{}\"}} {}\n",
+                    labels,
+                    bound / 1000.0,
+                    cumulative
+                ));
+            }
+            out.push_str(&format!("finternet_phase_duration_seconds_bucket{{{},le=\"+Inf\"}} {}\n", labels, histogram.count));
+            out.push_str(&format!("finternet_phase_duration_seconds_sum{{{}}} {}\n", labels, histogram.sum_ms / 1000.0));
+            out.push_str(&format!("finternet_phase_duration_seconds_count{{{}}} {}\n", labels, histogram.count));
+        }
+        out
+    }
+}
+
+impl FinternetClient {
+    /// Latency histograms of every operation this client timed
+    #[cfg(feature = "metrics")]
+    pub fn phase_histograms(&self) -> &PhaseHistograms {
+        &self.phase_histograms
+    }
+
+    /// Report `operation`'s timings: a warning per phase over budget, and the histograms
+    pub(crate) fn record_timings(&self, operation: &str, timings: &PhaseTimings) {
+        for (phase, timing) in timings.over_budget() {
+            tracing::warn!(
+                operation,
+                phase = %phase,
+                elapsed_ms = timing.elapsed_ms,
+                calls = timing.calls,
+                "{} spent {:.0}ms per call in {}, over its {}ms budget",
+                operation,
+                timing.mean_ms(),
+                phase,
+                phase.budget().as_millis()
+            );
+        }
+        tracing::debug!(operation, total_ms = timings.total_ms(), "{} timed", operation);
+        #[cfg(feature = "metrics")]
+        self.phase_histograms.observe(operation, timings);
+    }
+}
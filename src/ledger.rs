@@ -1,4 +1,13 @@
-use crate::{FinternetClient, TransactionRecord};
+use crate::chunking::{self, ChunkInfo};
+use crate::confirmation::OperationKind;
+use crate::history::HistoryCompleteness;
+use crate::latency::{Phase, PhaseTimings};
+use crate::instructions;
+use crate::progress::{Operation, ProgressEvent};
+use crate::rpc_options::RpcCallOptions;
+use crate::{Currency, FinternetClient, TransactionRecord};
+use crate::telemetry::readable;
+use crate::token_account::TokenAccountScan;
 use anyhow::Result;
 use solana_client::rpc_config::RpcTransactionConfig;
 use solana_client::rpc_client::GetConfirmedSignaturesForAddress2Config;
@@ -7,14 +16,35 @@ use solana_sdk::{
     commitment_config::CommitmentConfig,
     pubkey::Pubkey,
     signature::{Signature, Signer},
-    program_pack::Pack,
 };
 use solana_transaction_status::{
-    UiTransactionEncoding, EncodedConfirmedTransactionWithStatusMeta,
-    option_serializer::OptionSerializer
+    UiTransactionEncoding, EncodedConfirmedTransactionWithStatusMeta, EncodedTransaction,
+    UiMessage, option_serializer::OptionSerializer
 };
-use solana_account_decoder::UiAccountData;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::time::Instant;
+
+/// Transaction records with how much of the owner's history they cover
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TransactionHistory {
+    pub records: Vec<TransactionRecord>,
+    /// `Complete` or `TruncatedAt` only when the read reached the oldest signature the node has
+    pub completeness: HistoryCompleteness,
+    /// Time spent listing signatures and fetching their transactions
+    #[serde(default)]
+    pub timings: PhaseTimings,
+}
+
+/// Tokens a wallet holds a positive balance of, from `discover_tokens`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TokenDiscovery {
+    /// Mint, balance in base units, and the token's name when one could be read
+    pub tokens: Vec<(Pubkey, u64, Option<String>)>,
+    /// Time spent reading token accounts and each token's metadata
+    #[serde(default)]
+    pub timings: PhaseTimings,
+}
 
 impl FinternetClient {
     /// Get transaction history for a given wallet address
@@ -23,35 +53,83 @@ impl FinternetClient {
         owner: &Pubkey,
         limit: Option<usize>,
     ) -> Result<Vec<TransactionRecord>> {
+        self.get_transaction_history_with_options(owner, limit, &RpcCallOptions::default())
+            .await
+    }
+    
+    /// `get_transaction_history` at a per-call commitment and timeout
+    ///
+    /// History is only served at `confirmed` or above, so `processed` reads as `confirmed`.
+    pub async fn get_transaction_history_with_options(
+        &self,
+        owner: &Pubkey,
+        limit: Option<usize>,
+        options: &RpcCallOptions,
+    ) -> Result<Vec<TransactionRecord>> {
+        let history = self.get_transaction_history_with_completeness(owner, limit, options).await?;
+        if let Some(slot) = history.completeness.truncated_at() {
+            tracing::warn!("History of {} is truncated at slot {}; older transactions are not served", readable(owner), slot);
+        }
+        Ok(history.records)
+    }
+
+    /// `get_transaction_history_with_options` with whether the records reach the account's creation
+    ///
+    /// Completeness is only known when fewer than `limit` signatures came back, so the oldest one
+    /// the node has was read; its transaction is then checked for being the first funding.
+    #[tracing::instrument(skip_all, fields(wallet = %readable(owner), limit, rpc = %self.rpc_endpoint()))]
+    pub async fn get_transaction_history_with_completeness(
+        &self,
+        owner: &Pubkey,
+        limit: Option<usize>,
+        options: &RpcCallOptions,
+    ) -> Result<TransactionHistory> {
         let limit = limit.unwrap_or(10);
-        log::info!("Fetching transaction history for: {} (limit: {})", owner, limit);
+        tracing::info!("Fetching transaction history for: {} (limit: {})", owner, limit);
+        let commitment = self.history_commitment_for(options);
+        let scoped = self.scoped_rpc(options);
+        let rpc = scoped.as_ref().unwrap_or(&self.client);
+        let mut timings = PhaseTimings::default();
         
         // Get recent signatures for the account
-        let signatures = self.client.get_signatures_for_address_with_config(
-            owner,
-            GetConfirmedSignaturesForAddress2Config {
-                before: None,
-                until: None,
-                limit: Some(limit),
-                commitment: Some(CommitmentConfig::confirmed()),
-            },
-        )?;
+        let signatures = timings.time(Phase::Signatures, || {
+            rpc.get_signatures_for_address_with_config(
+                owner,
+                GetConfirmedSignaturesForAddress2Config {
+                    before: None,
+                    until: None,
+                    limit: Some(limit),
+                    commitment: Some(commitment),
+                },
+            )
+            .map_err(anyhow::Error::from)
+        })?;
         
         let mut transaction_records = Vec::new();
+        let total = signatures.len().min(limit);
+        self.report_progress(|| ProgressEvent::Started { operation: Operation::TransactionHistory, total: Some(total) });
         
         // Process up to the limit of transactions
-        for sig_info in signatures.iter().take(limit) {
+        for (completed, sig_info) in signatures.iter().take(limit).enumerate() {
+            if completed > 0 {
+                self.report_progress(|| ProgressEvent::Advanced { operation: Operation::TransactionHistory, completed, total });
+            }
             let signature: Signature = sig_info.signature.parse()?;
+            self.report_progress(|| ProgressEvent::Processing { operation: Operation::TransactionHistory, signature });
             
             // Get transaction details
-            if let Ok(transaction) = self.client.get_transaction_with_config(
-                &signature,
-                RpcTransactionConfig {
-                    encoding: Some(UiTransactionEncoding::Json),
-                    commitment: Some(CommitmentConfig::confirmed()),
-                    max_supported_transaction_version: Some(0),
-                },
-            ) {
+            let fetched = timings.time(Phase::Transactions, || {
+                rpc.get_transaction_with_config(
+                    &signature,
+                    RpcTransactionConfig {
+                        encoding: Some(UiTransactionEncoding::Json),
+                        commitment: Some(commitment),
+                        max_supported_transaction_version: Some(0),
+                    },
+                )
+                .ok()
+            });
+            if let Some(transaction) = fetched {
                 if let Some(meta) = &transaction.transaction.meta {
                     // Extract token transfers from the transaction
                     if let OptionSerializer::Some(ref pre_token_balances) = meta.pre_token_balances {
@@ -66,13 +144,9 @@ impl FinternetClient {
                                     
                                     if pre_amount != post_amount {
                                         // Determine if this is a send or receive
-                                        let amount = if post_amount > pre_amount {
-                                            post_amount - pre_amount
-                                        } else {
-                                            pre_amount - post_amount
-                                        };
+                                        let amount = post_amount.abs_diff(pre_amount);
                                         
-                                        let token_mint = pre_balance.mint.parse()?;
+                                        let currency = Currency::spl(pre_balance.mint.parse()?, pre_balance.ui_token_amount.decimals);
                                         
                                         // Extract memo if present
                                         let memo = self.extract_memo_from_transaction(&transaction);
@@ -82,9 +156,12 @@ impl FinternetClient {
                                             from: *owner, // Simplified - would need more logic to determine actual from/to
                                             to: *owner,
                                             amount,
-                                            token_mint,
+                                            currency,
                                             timestamp: sig_info.block_time.unwrap_or(0) as u64,
                                             memo,
+                                            fee: meta.fee,
+                                            slot: transaction.slot,
+                                            memo_fields: HashMap::new(),
                                         };
                                         
                                         transaction_records.push(record);
@@ -97,14 +174,22 @@ impl FinternetClient {
                 }
             }
         }
+        if total > 0 {
+            self.report_progress(|| ProgressEvent::Advanced { operation: Operation::TransactionHistory, completed: total, total });
+        }
+        self.report_progress(|| ProgressEvent::Finished { operation: Operation::TransactionHistory, completed: total });
         
-        log::info!("Found {} transaction records", transaction_records.len());
-        Ok(transaction_records)
+        self.attach_memo_fields(&mut transaction_records);
+        tracing::info!("Found {} transaction records", transaction_records.len());
+        let completeness = self.completeness_of_signatures(owner, &signatures, signatures.len() < limit);
+        self.record_timings("get_transaction_history", &timings);
+        Ok(TransactionHistory { records: transaction_records, completeness, timings })
     }
     
     /// Get detailed transaction information by signature
+    #[tracing::instrument(skip_all, fields(signature = %signature, rpc = %self.rpc_endpoint()))]
     pub async fn get_transaction_details(&self, signature: &Signature) -> Result<Option<TransactionRecord>> {
-        log::info!("Fetching transaction details for: {}", signature);
+        tracing::info!("Fetching transaction details for: {}", signature);
         
         let transaction = self.client.get_transaction_with_config(
             signature,
@@ -127,13 +212,9 @@ impl FinternetClient {
                             let post_amount = post_balance.ui_token_amount.amount.parse::<u64>().unwrap_or(0);
                             
                             if pre_amount != post_amount {
-                                let amount = if post_amount > pre_amount {
-                                    post_amount - pre_amount
-                                } else {
-                                    pre_amount - post_amount
-                                };
+                                let amount = post_amount.abs_diff(pre_amount);
                                 
-                                let token_mint = pre_balance.mint.parse()?;
+                                let currency = Currency::spl(pre_balance.mint.parse()?, pre_balance.ui_token_amount.decimals);
                                 let memo = self.extract_memo_from_transaction(&transaction);
                                 
                                 let record = TransactionRecord {
@@ -141,9 +222,12 @@ impl FinternetClient {
                                     from: Pubkey::default(), // Would need more complex logic
                                     to: Pubkey::default(),
                                     amount,
-                                    token_mint,
+                                    currency,
                                     timestamp: transaction.block_time.unwrap_or(0) as u64,
                                     memo,
+                                    fee: meta.fee,
+                                    slot: transaction.slot,
+                                    memo_fields: HashMap::new(),
                                 };
                                 
                                 return Ok(Some(record));
@@ -157,120 +241,60 @@ impl FinternetClient {
         Ok(None)
     }
     
-    /// Get all token accounts owned by a wallet
+    /// Get all token accounts owned by a wallet, as total balance per mint
+    #[tracing::instrument(skip_all, fields(wallet = %readable(owner), rpc = %self.rpc_endpoint()))]
     pub async fn get_token_accounts(&self, owner: &Pubkey) -> Result<HashMap<Pubkey, u64>> {
-        log::info!("Fetching token accounts for: {}", owner);
+        let details = self.get_token_account_details(owner).await?;
+        
+        let mut balances = HashMap::new();
+        for detail in details {
+            *balances.entry(detail.mint).or_insert(0u64) += detail.amount;
+        }
+        
+        tracing::info!("Successfully found {} token accounts with balances", balances.len());
+        Ok(balances)
+    }
+    
+    /// Get every token account owned by a wallet, including freeze and delegate state
+    ///
+    /// Accounts that cannot be decoded are left out; `get_token_account_scan` reports them.
+    pub async fn get_token_account_details(&self, owner: &Pubkey) -> Result<Vec<TokenAccountDetail>> {
+        let scan = self.get_token_account_scan(owner).await?;
+        if !scan.undecodable.is_empty() {
+            tracing::warn!("{} token accounts of {} could not be decoded", scan.undecodable.len(), readable(owner));
+        }
+        Ok(scan.accounts)
+    }
+    
+    /// Every token account owned by a wallet, with those that could not be decoded and why
+    #[tracing::instrument(skip_all, fields(wallet = %readable(owner), rpc = %self.rpc_endpoint()))]
+    pub async fn get_token_account_scan(&self, owner: &Pubkey) -> Result<TokenAccountScan> {
+        tracing::info!("Fetching token accounts for: {}", owner);
         
         let token_accounts = self.client.get_token_accounts_by_owner(
             owner,
             TokenAccountsFilter::ProgramId(spl_token::id()),
         )?;
         
-        log::info!("Raw RPC response: {} token accounts found", token_accounts.len());
-        
-        let mut balances = HashMap::new();
-        
-        for (i, account) in token_accounts.iter().enumerate() {
-            log::debug!("Processing account {}: pubkey={}", i, account.pubkey);
-            log::debug!("Account data type: {:?}", account.account.data);
-            
-            // Decode the account data properly - handle both Binary and JSON formats
-            match &account.account.data {
-                UiAccountData::Binary(data, encoding) => {
-                    log::debug!("Account {} has encoding: {:?}, data length: {}", i, encoding, data.len());
-                    
-                    let decoded_data = match encoding {
-                        solana_account_decoder::UiAccountEncoding::Base64 => {
-                            // Use the modern base64 engine instead of deprecated function
-                            use base64::{Engine, engine::general_purpose};
-                            match general_purpose::STANDARD.decode(data) {
-                                Ok(decoded) => {
-                                    log::debug!("Successfully decoded base64 data, length: {}", decoded.len());
-                                    decoded
-                                }
-                                Err(e) => {
-                                    log::warn!("Failed to decode base64 for account {}: {}", i, e);
-                                    continue;
-                                }
-                            }
-                        }
-                        solana_account_decoder::UiAccountEncoding::Base58 => {
-                            match bs58::decode(data).into_vec() {
-                                Ok(decoded) => {
-                                    log::debug!("Successfully decoded base58 data, length: {}", decoded.len());
-                                    decoded
-                                }
-                                Err(e) => {
-                                    log::warn!("Failed to decode base58 for account {}: {}", i, e);
-                                    continue;
-                                }
-                            }
-                        }
-                        _ => {
-                            log::warn!("Skipping account {} with unsupported encoding: {:?}", i, encoding);
-                            continue;
-                        }
-                    };
-                    
-                    match spl_token::state::Account::unpack(&decoded_data) {
-                        Ok(token_account) => {
-                            log::info!("✅ Successfully unpacked token account {}: mint={}, amount={}", 
-                                      i, token_account.mint, token_account.amount);
-                            balances.insert(token_account.mint, token_account.amount);
-                        }
-                        Err(e) => {
-                            log::warn!("Failed to unpack token account {}: {}", i, e);
-                        }
-                    }
-                }
-                UiAccountData::Json(parsed_account) => {
-                    log::debug!("Account {} is in JSON format", i);
-                    
-                    // Handle JSON parsed account data
-                    let parsed = &parsed_account.parsed;
-                    if let Some(info) = parsed.get("info") {
-                        // Extract mint and token amount from JSON
-                        if let (Some(mint_str), Some(token_amount)) = (
-                            info.get("mint").and_then(|v| v.as_str()),
-                            info.get("tokenAmount")
-                        ) {
-                            if let Some(amount_str) = token_amount.get("amount").and_then(|v| v.as_str()) {
-                                match (mint_str.parse::<Pubkey>(), amount_str.parse::<u64>()) {
-                                    (Ok(mint), Ok(amount)) => {
-                                        log::info!("✅ Successfully parsed JSON token account {}: mint={}, amount={}", 
-                                                  i, mint, amount);
-                                        balances.insert(mint, amount);
-                                    }
-                                    (Err(e), _) => {
-                                        log::warn!("Failed to parse mint for account {}: {}", i, e);
-                                    }
-                                    (_, Err(e)) => {
-                                        log::warn!("Failed to parse amount for account {}: {}", i, e);
-                                    }
-                                }
-                            } else {
-                                log::warn!("No amount found in tokenAmount for account {}", i);
-                            }
-                        } else {
-                            log::warn!("Missing mint or tokenAmount in account {} info", i);
-                        }
-                    } else {
-                        log::warn!("No info field in parsed account {}", i);
-                    }
-                }
-                _ => {
-                    log::debug!("Account {} data format not supported", i);
-                }
-            }
+        let mut scan = TokenAccountScan::default();
+        for account in &token_accounts {
+            scan.push(&account.pubkey, &account.account.data);
         }
-        
-        log::info!("Successfully found {} token accounts with balances", balances.len());
-        Ok(balances)
+        for undecodable in &scan.undecodable {
+            tracing::debug!("Token account {} skipped: {}", undecodable.address, undecodable.reason);
+        }
+        tracing::info!(
+            "{} token accounts returned, {} decoded",
+            token_accounts.len(),
+            scan.accounts.len()
+        );
+        Ok(scan)
     }
     
     /// Get all assets (tokens) owned by a wallet with their metadata
+    #[tracing::instrument(skip_all, fields(wallet = %readable(owner)))]
     pub async fn get_owned_assets(&self, owner: &Pubkey) -> Result<Vec<(Pubkey, u64)>> {
-        log::info!("Fetching owned assets for: {}", owner);
+        tracing::info!("Fetching owned assets for: {}", owner);
         
         let token_accounts = self.get_token_accounts(owner).await?;
         let mut assets = Vec::new();
@@ -282,11 +306,12 @@ impl FinternetClient {
             }
         }
         
-        log::info!("Found {} owned assets", assets.len());
+        tracing::info!("Found {} owned assets", assets.len());
         Ok(assets)
     }
     
     /// Check the status of a transaction
+    #[tracing::instrument(skip_all, fields(signature = %signature))]
     pub async fn get_transaction_status(&self, signature: &Signature) -> Result<String> {
         match self.client.get_signature_status(signature)? {
             Some(status) => {
@@ -299,56 +324,214 @@ impl FinternetClient {
         }
     }
     
-    /// Get the current slot and block time (for timestamping)
-    pub async fn get_current_slot_and_time(&self) -> Result<(u64, u64)> {
-        let slot = self.client.get_slot()?;
-        let block_time = self.client.get_block_time(slot)? as u64;
-        
-        Ok((slot, block_time))
-    }
-    
-    /// Write a custom log entry to the ledger (using a memo transaction)
+    /// Write a custom log entry to the ledger (using a memo transaction), under the client's
+    /// namespace
+    #[tracing::instrument(skip_all, fields(wallet = %readable(&wallet.pubkey()), entry_len = entry_data.len(), rpc = %self.rpc_endpoint(), signature = tracing::field::Empty))]
     pub async fn write_ledger_entry(
         &self,
         wallet: &solana_sdk::signature::Keypair,
         entry_data: &str,
     ) -> Result<Signature> {
-        log::info!("Writing ledger entry: {}", entry_data);
-        
-        let memo_ix = solana_sdk::instruction::Instruction {
-            program_id: "MemoSq4gqABAXKb96qnH8TysNcWxMyWCqXgDLGmfcHr".parse()?,
-            accounts: vec![solana_sdk::instruction::AccountMeta::new_readonly(
-                wallet.pubkey(),
-                true,
-            )],
-            data: entry_data.as_bytes().to_vec(),
-        };
+        tracing::info!("Writing ledger entry: {}", entry_data);
+        
+        let entry = self.namespaced_entry(entry_data)?;
+        let memo_ix = instructions::ledger_entry_instruction(&wallet.pubkey(), entry.as_bytes());
         
-        let recent_blockhash = self.client.get_latest_blockhash()?;
         let mut transaction = solana_sdk::transaction::Transaction::new_with_payer(
             &[memo_ix],
             Some(&wallet.pubkey()),
         );
-        transaction.sign(&[wallet], recent_blockhash);
+        self.sign_with_fresh_blockhash(&mut transaction, &[wallet])?;
         
-        let signature = self.client.send_and_confirm_transaction(&transaction)?;
-        log::info!("Ledger entry written with signature: {}", signature);
+        let signature = self.send_and_confirm_resigning(OperationKind::LedgerEntry, "write_ledger_entry", transaction, &[wallet])?;
+        tracing::Span::current().record("signature", tracing::field::display(&signature));
+        tracing::info!("Ledger entry written with signature: {}", signature);
         
         Ok(signature)
     }
     
+    /// Write a single-memo ledger entry that also shows up in the history of each of `references`
+    pub async fn write_ledger_entry_referencing(
+        &self,
+        wallet: &solana_sdk::signature::Keypair,
+        entry_data: &str,
+        references: &[Pubkey],
+    ) -> Result<Signature> {
+        self.write_ledger_entry_alongside(wallet, entry_data, references, Vec::new()).await
+    }
+    
+    /// `write_ledger_entry_referencing` with `extra` instructions in the same transaction, so the
+    /// entry is only written if they succeed
+    pub(crate) async fn write_ledger_entry_alongside(
+        &self,
+        wallet: &solana_sdk::signature::Keypair,
+        entry_data: &str,
+        references: &[Pubkey],
+        extra: Vec<solana_sdk::instruction::Instruction>,
+    ) -> Result<Signature> {
+        let entry = self.namespaced_entry(entry_data)?;
+        if entry.len() > chunking::MAX_SINGLE_ENTRY_LEN {
+            return Err(anyhow::anyhow!(
+                "Ledger entry of {} bytes is too long to carry references; the limit is {}",
+                entry.len(),
+                chunking::MAX_SINGLE_ENTRY_LEN
+            ));
+        }
+        let mut instructions = vec![instructions::reference_instruction(&wallet.pubkey(), references)];
+        instructions.extend(extra);
+        instructions.push(instructions::ledger_entry_instruction(&wallet.pubkey(), entry.as_bytes()));
+        let mut transaction = solana_sdk::transaction::Transaction::new_with_payer(&instructions, Some(&wallet.pubkey()));
+        self.sign_with_fresh_blockhash(&mut transaction, &[wallet])?;
+        
+        let signature = self.send_and_confirm_resigning(OperationKind::LedgerEntry, "write_ledger_entry", transaction, &[wallet])?;
+        tracing::info!("Ledger entry referencing {} accounts written with signature: {}", references.len(), signature);
+        Ok(signature)
+    }
+    
+    /// Write a ledger entry of any size, splitting it across memos and transactions as needed
+    ///
+    /// Small uncompressed payloads go out as a single plain memo. Larger ones (or any payload with
+    /// `compress` set) are optionally gzipped, split into `finternet-chunk i/n:<id>:<data>` memos,
+    /// and written a few chunks per transaction. Returns every signature in write order.
+    #[tracing::instrument(skip_all, fields(wallet = %readable(&wallet.pubkey()), entry_len = entry_data.len(), compress, rpc = %self.rpc_endpoint()))]
+    pub async fn write_ledger_entry_chunked(
+        &self,
+        wallet: &solana_sdk::signature::Keypair,
+        entry_data: &str,
+        compress: bool,
+    ) -> Result<Vec<Signature>> {
+        let entry = self.namespaced_entry(entry_data)?;
+        if !compress && entry.len() <= chunking::MAX_SINGLE_ENTRY_LEN {
+            return Ok(vec![self.write_ledger_entry(wallet, entry_data).await?]);
+        }
+        
+        // The namespace goes inside the payload, so it is read back once the chunks are joined
+        let payload = if compress {
+            chunking::compress(&entry)?
+        } else {
+            entry
+        };
+        
+        let id = uuid::Uuid::new_v4().simple().to_string();
+        let chunks = chunking::split_into_chunks(&id, &payload);
+        tracing::info!(
+            "Writing ledger entry {} as {} chunks ({} -> {} bytes)",
+            id,
+            chunks.len(),
+            entry_data.len(),
+            payload.len()
+        );
+        
+        let mut signatures = Vec::new();
+        for batch in chunks.chunks(chunking::CHUNKS_PER_TRANSACTION) {
+            let instructions: Vec<_> = batch
+                .iter()
+                .map(|chunk| instructions::ledger_entry_instruction(&wallet.pubkey(), chunk.as_bytes()))
+                .collect();
+            
+            let mut transaction = solana_sdk::transaction::Transaction::new_with_payer(
+                &instructions,
+                Some(&wallet.pubkey()),
+            );
+            self.sign_with_fresh_blockhash(&mut transaction, &[wallet])?;
+            
+            let signature = self.send_and_confirm_resigning(OperationKind::LedgerEntry, "write_ledger_entry", transaction, &[wallet])?;
+            tracing::debug!("Ledger entry {} chunk batch written: {}", id, signature);
+            signatures.push(signature);
+        }
+        
+        tracing::info!("Ledger entry {} written in {} transactions", id, signatures.len());
+        Ok(signatures)
+    }
+    
+    /// Read memo-based ledger entries touching an address, newest first
+    ///
+    /// Only entries of the client's namespace, or a trusted one, are returned, with the prefix
+    /// stripped from `data`; see `namespace`.
+    pub async fn read_ledger_entries(&self, address: &Pubkey, limit: Option<usize>) -> Result<Vec<LedgerEntry>> {
+        let entries = self.read_raw_ledger_entries(address, limit).await?;
+        let total = entries.len();
+        let entries: Vec<LedgerEntry> = entries.into_iter().filter_map(|entry| self.accept_entry(entry)).collect();
+        if entries.len() < total {
+            tracing::debug!("Ignored {} ledger entries outside namespace {}", total - entries.len(), self.namespace());
+        }
+        Ok(entries)
+    }
+    
+    /// Every memo touching an address, chunks reassembled, whatever its namespace
+    #[tracing::instrument(skip_all, fields(wallet = %readable(address), limit, rpc = %self.rpc_endpoint()))]
+    pub(crate) async fn read_raw_ledger_entries(&self, address: &Pubkey, limit: Option<usize>) -> Result<Vec<LedgerEntry>> {
+        let limit = limit.unwrap_or(100);
+        tracing::info!("Reading ledger entries for: {} (limit: {})", address, limit);
+        
+        let signatures = self.client.get_signatures_for_address_with_config(
+            address,
+            GetConfirmedSignaturesForAddress2Config {
+                before: None,
+                until: None,
+                limit: Some(limit),
+                commitment: Some(CommitmentConfig::confirmed()),
+            },
+        )?;
+        
+        let mut entries = Vec::new();
+        
+        for sig_info in signatures.iter() {
+            // getSignaturesForAddress already tells us which transactions carry a memo
+            if sig_info.memo.is_none() || sig_info.err.is_some() {
+                continue;
+            }
+            let signature: Signature = sig_info.signature.parse()?;
+            
+            let transaction = match self.client.get_transaction_with_config(
+                &signature,
+                RpcTransactionConfig {
+                    encoding: Some(UiTransactionEncoding::Json),
+                    commitment: Some(CommitmentConfig::confirmed()),
+                    max_supported_transaction_version: Some(0),
+                },
+            ) {
+                Ok(transaction) => transaction,
+                Err(e) => {
+                    tracing::warn!("Skipping ledger entry {}: {}", signature, e);
+                    continue;
+                }
+            };
+            
+            let signer = fee_payer_of(&transaction);
+            for memo in extract_memos(&transaction) {
+                entries.push(LedgerEntry {
+                    signature,
+                    signer,
+                    slot: transaction.slot,
+                    timestamp: transaction.block_time.unwrap_or(0) as u64,
+                    data: memo,
+                    chunk: None,
+                    namespace: None,
+                });
+            }
+        }
+        
+        let entries = chunking::reassemble(entries);
+        tracing::info!("Found {} ledger entries", entries.len());
+        Ok(entries)
+    }
+    
     /// Helper function to extract memo from transaction
     fn extract_memo_from_transaction(
         &self,
-        _transaction: &EncodedConfirmedTransactionWithStatusMeta,
+        transaction: &EncodedConfirmedTransactionWithStatusMeta,
     ) -> Option<String> {
-        // Simplified memo extraction - for now just return None
-        // In a full implementation, we would parse the transaction instructions
-        // to find memo program calls, but this requires complex parsing
-        None
+        let memos = extract_memos(transaction);
+        if memos.is_empty() {
+            None
+        } else {
+            Some(memos.join("; "))
+        }
     }
     
     /// Request devnet USDC airdrop for testing
+    #[tracing::instrument(skip_all, fields(wallet = %readable(wallet_pubkey)))]
     pub async fn request_devnet_usdc(&self, wallet_pubkey: &Pubkey) -> Result<String> {
         use crate::payment::usdc;
         
@@ -359,7 +542,7 @@ impl FinternetClient {
             &usdc_mint,
         );
         
-        log::info!("USDC associated token account for {}: {}", wallet_pubkey, ata);
+        tracing::info!("USDC associated token account for {}: {}", wallet_pubkey, ata);
         
         // Check if ATA exists
         match self.client.get_token_account_balance(&ata) {
@@ -378,27 +561,46 @@ impl FinternetClient {
     
     /// Enhanced asset discovery that includes all token accounts
     pub async fn discover_all_tokens(&self, wallet_pubkey: &Pubkey) -> Result<Vec<(Pubkey, u64, Option<String>)>> {
-        log::info!("Starting enhanced token discovery for: {}", wallet_pubkey);
+        Ok(self.discover_tokens(wallet_pubkey).await?.tokens)
+    }
+    
+    /// `discover_all_tokens` with where its time went
+    #[tracing::instrument(skip_all, fields(wallet = %readable(wallet_pubkey)))]
+    pub async fn discover_tokens(&self, wallet_pubkey: &Pubkey) -> Result<TokenDiscovery> {
+        tracing::info!("Starting enhanced token discovery for: {}", wallet_pubkey);
         
         let mut discovered_tokens = Vec::new();
+        let mut timings = PhaseTimings::default();
         
         // Use our enhanced get_token_accounts method
+        let started = Instant::now();
         let token_accounts = self.get_token_accounts(wallet_pubkey).await?;
+        timings.add(Phase::Accounts, started.elapsed());
         
-        log::info!("Processing {} token accounts for metadata", token_accounts.len());
+        tracing::info!("Processing {} token accounts for metadata", token_accounts.len());
         
         for (mint, balance) in token_accounts {
             if balance > 0 {
-                log::debug!("Processing token: mint={}, balance={}", mint, balance);
+                tracing::debug!("Processing token: mint={}, balance={}", mint, balance);
                 
                 // Try to get metadata for this token
-                let metadata_name = match self.get_asset_info(&mint).await {
-                    Ok(metadata) => {
-                        log::debug!("Found metadata for {}: {}", mint, metadata.name);
+                let started = Instant::now();
+                let asset_info = self.get_asset_info(&mint).await;
+                timings.add(Phase::Metadata, started.elapsed());
+                let metadata_name = match asset_info {
+                    Ok(metadata) if metadata.has_metadata => {
+                        tracing::debug!("Found metadata for {}: {}", mint, metadata.name);
                         Some(metadata.name)
                     }
+                    Ok(mint_only) => {
+                        // No Metaplex metadata, so describe the mint itself
+                        Some(format!(
+                            "Unnamed token ({} decimals, supply {})",
+                            mint_only.decimals, mint_only.supply
+                        ))
+                    }
                     Err(e) => {
-                        log::debug!("No metadata found for {}: {}", mint, e);
+                        tracing::debug!("No metadata found for {}: {}", mint, e);
                         None
                     }
                 };
@@ -407,13 +609,135 @@ impl FinternetClient {
             }
         }
         
-        log::info!("Discovery complete: found {} tokens with positive balances", discovered_tokens.len());
-        Ok(discovered_tokens)
+        tracing::info!("Discovery complete: found {} tokens with positive balances", discovered_tokens.len());
+        self.record_timings("discover_tokens", &timings);
+        Ok(TokenDiscovery { tokens: discovered_tokens, timings })
     }
     
     /// Get SOL balance for a wallet (returns amount in SOL, not lamports)
+    #[tracing::instrument(skip_all, fields(wallet = %readable(wallet_pubkey)))]
     pub async fn get_sol_balance(&self, wallet_pubkey: &Pubkey) -> Result<f64> {
         let balance_lamports = self.client.get_balance(wallet_pubkey)?;
         Ok(balance_lamports as f64 / 1_000_000_000.0)
     }
-} 
\ No newline at end of file
+    
+    /// `get_sol_balance` at a per-call commitment, e.g. `processed` for a fast balance display
+    pub async fn get_sol_balance_with_options(&self, wallet_pubkey: &Pubkey, options: &RpcCallOptions) -> Result<f64> {
+        let scoped = self.scoped_rpc(options);
+        let rpc = scoped.as_ref().unwrap_or(&self.client);
+        let balance_lamports = rpc
+            .get_balance_with_commitment(wallet_pubkey, self.commitment_for(options))?
+            .value;
+        Ok(balance_lamports as f64 / 1_000_000_000.0)
+    }
+}
+
+/// A memo written to the ledger, e.g. by `write_ledger_entry`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LedgerEntry {
+    pub signature: Signature,
+    /// Fee payer of the transaction carrying the memo
+    pub signer: Option<Pubkey>,
+    pub slot: u64,
+    pub timestamp: u64,
+    pub data: String,
+    /// Set when the entry was reassembled from chunks written by `write_ledger_entry_chunked`
+    #[serde(default)]
+    pub chunk: Option<ChunkInfo>,
+    /// Namespace the entry was written under, stripped from `data`; `None` for legacy entries
+    #[serde(default)]
+    pub namespace: Option<String>,
+}
+
+impl LedgerEntry {
+    /// True when some chunks of this entry could not be found
+    pub fn is_partial(&self) -> bool {
+        self.chunk.as_ref().map(|c| !c.missing.is_empty()).unwrap_or(false)
+    }
+    
+    /// Parse the memo as a JSON ledger record (`{"action": ...}`), if it is one
+    pub fn json(&self) -> Option<serde_json::Value> {
+        serde_json::from_str::<serde_json::Value>(&self.data)
+            .ok()
+            .filter(|value| value.is_object())
+    }
+    
+    pub fn action(&self) -> Option<String> {
+        self.json()
+            .and_then(|value| value.get("action").and_then(|a| a.as_str()).map(str::to_string))
+    }
+}
+
+/// Decode every memo instruction (both memo program versions) in a JSON-encoded transaction
+pub(crate) fn extract_memos(transaction: &EncodedConfirmedTransactionWithStatusMeta) -> Vec<String> {
+    let EncodedTransaction::Json(ui_transaction) = &transaction.transaction.transaction else {
+        return Vec::new();
+    };
+    let UiMessage::Raw(message) = &ui_transaction.message else {
+        return Vec::new();
+    };
+    
+    let memo_programs = [spl_memo::id().to_string(), spl_memo::v1::id().to_string()];
+    message
+        .instructions
+        .iter()
+        .filter(|ix| {
+            message
+                .account_keys
+                .get(ix.program_id_index as usize)
+                .map(|key| memo_programs.contains(key))
+                .unwrap_or(false)
+        })
+        .filter_map(|ix| bs58::decode(&ix.data).into_vec().ok())
+        .filter_map(|data| String::from_utf8(data).ok())
+        .collect()
+}
+
+pub(crate) fn fee_payer_of(transaction: &EncodedConfirmedTransactionWithStatusMeta) -> Option<Pubkey> {
+    match &transaction.transaction.transaction {
+        EncodedTransaction::Json(ui_transaction) => match &ui_transaction.message {
+            UiMessage::Raw(message) => message.account_keys.first().and_then(|key| key.parse().ok()),
+            UiMessage::Parsed(message) => message.account_keys.first().and_then(|key| key.pubkey.parse().ok()),
+        },
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TokenAccountState {
+    Uninitialized,
+    Initialized,
+    /// Frozen by the mint's freeze authority; the balance cannot move
+    Frozen,
+}
+
+/// A single token account with the state `get_token_accounts` folds away
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TokenAccountDetail {
+    pub address: Pubkey,
+    pub mint: Pubkey,
+    pub owner: Pubkey,
+    pub amount: u64,
+    /// Only known when the RPC node returned jsonParsed data
+    pub decimals: Option<u8>,
+    pub state: TokenAccountState,
+    pub delegate: Option<Pubkey>,
+    pub delegated_amount: u64,
+    pub close_authority: Option<Pubkey>,
+}
+
+impl TokenAccountDetail {
+    pub fn is_frozen(&self) -> bool {
+        self.state == TokenAccountState::Frozen
+    }
+
+    /// The account's mint as a currency; decimals fall back to the well-known ones when the
+    /// RPC node did not return them
+    pub fn currency(&self) -> Currency {
+        match self.decimals {
+            Some(decimals) => Currency::spl(self.mint, decimals),
+            None => Currency::from_mint(self.mint),
+        }
+    }
+}
@@ -1,10 +1,112 @@
+pub mod action_log;
+pub mod alerts;
+pub mod amount_format;
+pub mod anomaly;
+pub mod api_keys;
+pub mod approval;
 pub mod asset;
+pub mod asset_policy;
+pub mod batch;
+pub mod blockhash;
+pub mod bootstrap;
+pub mod cache;
+pub mod capabilities;
+pub mod chain_time;
+pub mod chunking;
+pub mod cold_sweep;
+pub mod claim;
+pub mod coalesce;
+pub mod confirmation;
+pub mod costs;
+pub mod counterparty;
+pub mod credential;
+pub mod currency;
+pub mod deadline;
+pub mod demo;
+pub mod diagnostics;
+pub mod document;
+pub mod dry_run;
+pub mod error_code;
+pub mod deposit;
+pub mod events;
+pub mod export;
+pub mod fees;
+pub mod finality;
+pub mod freshness;
+pub mod history;
+pub mod holdings_proof;
+pub mod instructions;
+pub mod latency;
 pub mod ledger;
+pub mod listing;
+#[cfg(feature = "sqlite")]
+pub mod local_ledger;
+pub mod localnet;
+pub mod memo_template;
+pub mod metadata;
+pub mod metadata_watch;
+pub mod mint_registry;
+#[cfg(test)]
+pub(crate) mod mock_cluster;
+pub mod namespace;
 pub mod payment;
+pub mod payment_link;
+pub mod payment_queue;
+pub mod pipeline;
+pub mod policy;
+pub mod portfolio;
+pub mod precheck;
+pub mod preview;
+pub mod profile;
+pub mod progress;
+mod pubkey_map;
+pub mod qr;
+pub mod gate;
 pub mod identity;
+pub mod identity_bulk;
+pub mod identity_index;
+pub mod intent;
+pub mod issuance;
+pub mod organization;
+pub mod outbox;
+pub mod owned_assets;
+pub mod recording;
+pub mod redaction;
+pub mod reconcile;
+pub mod redemption;
+pub mod remediation;
+pub mod resources;
+pub mod response_signing;
+pub mod rpc_options;
+pub mod rpc_select;
+pub mod send_context;
+pub mod server_config;
+pub mod solana_pay;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+pub mod state_bundle;
+pub mod state_store;
+#[cfg(feature = "sqlite")]
+pub mod statement;
+pub mod sweep;
+pub mod tasks;
+pub mod telemetry;
+pub mod timelock;
+pub mod token_account;
+pub mod tokenization_draft;
+pub mod usage;
+pub mod validation;
+pub mod webhook;
 
 use serde::{Deserialize, Serialize};
+use solana_client::rpc_client::{RpcClient, RpcClientConfig};
+use solana_client::rpc_sender::RpcSender;
+use solana_rpc_client::http_sender::HttpSender;
 use solana_sdk::{pubkey::Pubkey, signature::Signature};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock};
+use telemetry::InstrumentedSender;
 
 /// Core types and structures used throughout the SDK
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -13,9 +115,191 @@ pub struct AssetMetadata {
     pub description: String,
     pub value: u64,
     pub issuer: Pubkey,
-    pub asset_type: String,
+    pub asset_type: AssetType,
     pub created_at: u64,
     pub token_mint: Option<Pubkey>,
+    /// Metaplex creators with their royalty shares, in on-chain order
+    #[serde(default)]
+    pub creators: Vec<AssetCreator>,
+    #[serde(default)]
+    pub seller_fee_basis_points: u16,
+    /// Mint-level data, read from the mint account of either token program
+    #[serde(default)]
+    pub supply: u64,
+    #[serde(default)]
+    pub decimals: u8,
+    #[serde(default)]
+    pub mint_authority: Option<Pubkey>,
+    #[serde(default)]
+    pub freeze_authority: Option<Pubkey>,
+    #[serde(default)]
+    pub is_initialized: bool,
+    /// False for mints without metadata in either backend; name, issuer, and type are then unknown
+    #[serde(default)]
+    pub has_metadata: bool,
+    /// Where the metadata was read from or written to; `None` when there is none
+    #[serde(default)]
+    pub metadata_backend: Option<MetadataBackend>,
+    /// Metaplex symbol and off-chain JSON URI, with their on-chain padding removed
+    #[serde(default)]
+    pub symbol: String,
+    #[serde(default)]
+    pub uri: String,
+    /// As recorded in the metadata, or inferred the way Metaplex does for older tokens that predate it
+    #[serde(default)]
+    pub token_standard: Option<TokenStandard>,
+    #[serde(default)]
+    pub primary_sale_happened: bool,
+    /// Token Metadata rule set governing transfers of a programmable NFT, if one is configured
+    #[serde(default)]
+    pub rule_set: Option<Pubkey>,
+    /// Master or print edition, for NFTs that have an edition account
+    #[serde(default)]
+    pub edition: Option<Edition>,
+    /// Documents the issuer anchored with `attach_document`, oldest first; only read for 1-of-1 mints
+    #[serde(default)]
+    pub documents: Vec<document::DocumentAnchor>,
+}
+
+/// Metaplex token standard
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TokenStandard {
+    NonFungible,
+    /// A print of a master edition
+    NonFungibleEdition,
+    /// Supply of more than one, with no decimals; what `tokenize_asset` mints
+    FungibleAsset,
+    Fungible,
+    /// NFT whose transfers go through Token Metadata and its rule set
+    ProgrammableNonFungible,
+    ProgrammableNonFungibleEdition,
+}
+
+/// Where an asset's name, issuer, and other descriptive fields are recorded
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MetadataBackend {
+    /// A Metaplex Token Metadata account
+    Metaplex,
+    /// A JSON memo in the mint's creation transaction, for clusters without Token Metadata
+    LedgerMemo,
+}
+
+impl std::str::FromStr for MetadataBackend {
+    type Err = anyhow::Error;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input.trim().to_lowercase().replace('-', "_").as_str() {
+            "metaplex" => Ok(Self::Metaplex),
+            "ledger_memo" | "memo" => Ok(Self::LedgerMemo),
+            _ => Err(anyhow::anyhow!("Unknown metadata backend '{}', expected metaplex or ledger_memo", input)),
+        }
+    }
+}
+
+/// What a mint's edition account says about its printing
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Edition {
+    /// An original; `max_supply` of `None` means unlimited prints
+    Master { supply: u64, max_supply: Option<u64> },
+    /// Print number `number` of the master edition at `parent`
+    Print { parent: Pubkey, number: u64 },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AssetCreator {
+    pub address: Pubkey,
+    /// Whether the creator has signed the metadata; unverified entries are only claims
+    pub verified: bool,
+    /// Percentage of royalties, all creators summing to 100
+    pub share: u8,
+}
+
+/// Category of a tokenized asset
+///
+/// Serializes to the same snake_case strings the SDK has always stored, and any unknown string
+/// deserializes into `Custom` so older metadata keeps loading.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
+pub enum AssetType {
+    RealEstate,
+    Invoice,
+    Commodity,
+    Security,
+    Receivable,
+    Custom(String),
+}
+
+impl AssetType {
+    /// Names accepted by `FromStr`, besides `custom:<name>`
+    pub const ACCEPTED: &'static [&'static str] = &["real_estate", "invoice", "commodity", "security", "receivable"];
+
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::RealEstate => "real_estate",
+            Self::Invoice => "invoice",
+            Self::Commodity => "commodity",
+            Self::Security => "security",
+            Self::Receivable => "receivable",
+            Self::Custom(name) => name,
+        }
+    }
+
+    fn known(normalized: &str) -> Option<Self> {
+        match normalized {
+            "real_estate" | "realestate" => Some(Self::RealEstate),
+            "invoice" => Some(Self::Invoice),
+            "commodity" => Some(Self::Commodity),
+            "security" => Some(Self::Security),
+            "receivable" => Some(Self::Receivable),
+            _ => None,
+        }
+    }
+}
+
+fn normalize_asset_type(input: &str) -> String {
+    input.trim().to_lowercase().replace(['-', ' '], "_")
+}
+
+impl std::fmt::Display for AssetType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Strict parse for user input: case-insensitive and hyphen/underscore tolerant, but unknown
+/// names must be spelled `custom:<name>` so typos are caught
+impl std::str::FromStr for AssetType {
+    type Err = anyhow::Error;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let normalized = normalize_asset_type(input);
+        if let Some(known) = Self::known(&normalized) {
+            return Ok(known);
+        }
+        match normalized.strip_prefix("custom:") {
+            Some(name) if !name.is_empty() => Ok(Self::Custom(name.to_string())),
+            _ => Err(anyhow::anyhow!(
+                "Unknown asset type '{}'. Accepted values: {}, or custom:<name>",
+                input,
+                Self::ACCEPTED.join(", ")
+            )),
+        }
+    }
+}
+
+impl From<String> for AssetType {
+    fn from(stored: String) -> Self {
+        Self::known(&normalize_asset_type(&stored)).unwrap_or(Self::Custom(stored))
+    }
+}
+
+impl From<AssetType> for String {
+    fn from(asset_type: AssetType) -> Self {
+        asset_type.as_str().to_string()
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -23,16 +307,65 @@ pub struct TransactionRecord {
     pub signature: Signature,
     pub from: Pubkey,
     pub to: Pubkey,
+    /// In base units of `currency`
     pub amount: u64,
-    pub token_mint: Pubkey,
+    /// Read from the older `token_mint` field, a bare mint address, when present
+    #[serde(alias = "token_mint")]
+    pub currency: Currency,
     pub timestamp: u64,
     pub memo: Option<String>,
+    /// Network fee in lamports, paid by the transaction's fee payer; 0 when unknown
+    #[serde(default)]
+    pub fee: u64,
+    /// Slot the transaction landed in; 0 when unknown
+    #[serde(default)]
+    pub slot: u64,
+    /// Values parsed out of `memo` by the client's memo template, if it matched
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub memo_fields: HashMap<String, String>,
 }
 
 #[derive(Debug, Clone)]
 pub struct FinternetConfig {
     pub rpc_url: String,
     pub commitment_level: String,
+    /// Capture every RPC request/response pair to this JSONL file for later replay
+    pub record_rpc_to: Option<PathBuf>,
+    /// Journal signed transactions here before broadcast so `recover_outbox` can resolve them after a crash
+    pub outbox_path: Option<PathBuf>,
+    /// Base URL for token metadata JSON (`<base>/<mint>.json`) when no `MetadataPublisher` is set
+    pub metadata_uri_base: Option<String>,
+    /// How long each class of send is waited on; see `with_confirmation_policy`
+    pub confirmation: confirmation::ConfirmationPolicies,
+    /// Force where `tokenize_asset` records metadata; `None` uses Metaplex wherever the Token
+    /// Metadata program is deployed and ledger memos elsewhere
+    pub metadata_backend: Option<MetadataBackend>,
+    /// Append a hash-chained record of every payment, tokenization, and registration here
+    pub action_log_path: Option<PathBuf>,
+    /// Endpoints `auto_select_rpc` and `reselect_rpc` choose among
+    pub rpc_candidates: Vec<String>,
+    /// Replace `rpc_url` with the fastest healthy candidate when the client is built
+    pub auto_select: bool,
+    /// Share one upstream request among identical concurrent reads; see `coalesce`
+    pub coalesce_reads: bool,
+    /// Unix time stamped on ledger entries instead of the chain clock, for replayable runs
+    pub fixed_timestamp: Option<u64>,
+    /// Priority fee for sends whose confirmation policy sets none; see `fees`
+    pub fee_strategy: fees::FeeStrategy,
+    /// Build and simulate every transaction but send none; see `dry_run`
+    pub dry_run: bool,
+    /// How amounts are written for people to read; see `amount_format`
+    pub amount_format: amount_format::AmountFormatter,
+    /// Prefix of every ledger entry written, and the only one read; see `namespace`
+    pub namespace: String,
+    /// Other namespaces whose entries are read as this client's own, e.g. while migrating
+    pub trusted_namespaces: Vec<String>,
+    /// Also read un-prefixed entries written before namespaces existed
+    pub accept_legacy_entries: bool,
+    /// Compare every payment against the wallet's recent activity; see `anomaly`
+    pub anomaly_detection: Option<anomaly::AnomalyRules>,
+    /// Cache sizes, action log rotation, and outbox archival; see `resources`
+    pub resource_limits: resources::ResourceLimits,
 }
 
 impl Default for FinternetConfig {
@@ -40,6 +373,24 @@ impl Default for FinternetConfig {
         Self {
             rpc_url: "https://api.devnet.solana.com".to_string(),
             commitment_level: "confirmed".to_string(),
+            record_rpc_to: None,
+            outbox_path: None,
+            metadata_uri_base: None,
+            confirmation: Default::default(),
+            metadata_backend: None,
+            action_log_path: None,
+            rpc_candidates: Vec::new(),
+            auto_select: false,
+            coalesce_reads: true,
+            fixed_timestamp: None,
+            fee_strategy: Default::default(),
+            dry_run: false,
+            amount_format: Default::default(),
+            namespace: namespace::DEFAULT_NAMESPACE.to_string(),
+            trusted_namespaces: Vec::new(),
+            accept_legacy_entries: false,
+            anomaly_detection: None,
+            resource_limits: Default::default(),
         }
     }
 }
@@ -47,20 +398,158 @@ impl Default for FinternetConfig {
 /// Main SDK client
 pub struct FinternetClient {
     pub config: FinternetConfig,
-    pub client: solana_client::rpc_client::RpcClient,
+    pub client: RpcClient,
+    pub(crate) outbox: Option<outbox::Outbox>,
+    pub(crate) action_log: Option<Arc<action_log::ActionLog>>,
+    pub(crate) metadata_publisher: Option<Arc<dyn metadata::MetadataPublisher>>,
+    pub(crate) issuance_cache: issuance::IssuanceCache,
+    pub(crate) cache: cache::ClientCache,
+    pub(crate) spending: Option<policy::SpendingGuard>,
+    pub(crate) anomaly: Option<anomaly::AnomalyDetector>,
+    pub(crate) asset_policies: HashMap<Pubkey, asset_policy::AssetPolicy>,
+    pub(crate) blockhash: blockhash::BlockhashProvider,
+    /// Whether Token Metadata is deployed on the cluster, checked on first tokenization
+    pub(crate) metaplex_deployed: OnceLock<bool>,
+    /// Which public cluster the RPC node serves, read on first use
+    pub(crate) network: OnceLock<Option<mint_registry::Network>>,
+    pub(crate) fee_samples: fees::FeeSamples,
+    pub(crate) progress: Option<progress::ProgressSubscriber>,
+    pub(crate) memo_template: Option<memo_template::MemoTemplate>,
+    pub(crate) events: events::EventBus,
+    pub(crate) send_pipeline: pipeline::SendPipeline,
+    /// Background tasks, aborted when the client is dropped; see `tasks`
+    pub(crate) tasks: tasks::TaskManager,
+    #[cfg(feature = "metrics")]
+    pub(crate) phase_histograms: latency::PhaseHistograms,
 }
 
 impl FinternetClient {
-    pub fn new(config: FinternetConfig) -> Self {
-        let client = solana_client::rpc_client::RpcClient::new(&config.rpc_url);
-        Self { config, client }
+    pub fn new(mut config: FinternetConfig) -> Self {
+        if config.auto_select && !config.rpc_candidates.is_empty() {
+            config.rpc_url = Self::auto_select_rpc(&config.rpc_candidates);
+        }
+        let client = rpc_client_for(&config);
+        Self::over_rpc(config, client)
+    }
+
+    /// A client that reaches the cluster through `sender` instead of over HTTP to `rpc_url`
+    ///
+    /// `sender` is instrumented, and its reads coalesced, as the HTTP transport would be.
+    /// `record_rpc_to` and `auto_select` are ignored.
+    pub fn with_rpc_sender<S: RpcSender + Send + Sync + 'static>(config: FinternetConfig, sender: S) -> Self {
+        let client = rpc_client_over(sender, &config);
+        Self::over_rpc(config, client)
+    }
+
+    fn over_rpc(config: FinternetConfig, client: RpcClient) -> Self {
+        let limits = config.resource_limits;
+        let outbox = config
+            .outbox_path
+            .as_deref()
+            .map(|path| outbox::Outbox::new(path).with_archival(limits.outbox_archive_after_days));
+        let action_log = config
+            .action_log_path
+            .as_deref()
+            .map(|path| Arc::new(action_log::ActionLog::new(path).with_rotation(limits.action_log_rotation)));
+        let anomaly = config
+            .anomaly_detection
+            .clone()
+            .map(|rules| anomaly::AnomalyDetector::new(rules, config.outbox_path.as_deref()));
+        let client = Self {
+            config,
+            client,
+            outbox,
+            action_log,
+            metadata_publisher: None,
+            issuance_cache: issuance::IssuanceCache::new(limits.issuance_cache),
+            cache: cache::ClientCache::new(&limits),
+            spending: None,
+            anomaly,
+            asset_policies: HashMap::new(),
+            blockhash: Default::default(),
+            metaplex_deployed: OnceLock::new(),
+            network: OnceLock::new(),
+            fee_samples: Default::default(),
+            progress: None,
+            memo_template: None,
+            events: Default::default(),
+            send_pipeline: Default::default(),
+            tasks: Default::default(),
+            #[cfg(feature = "metrics")]
+            phase_histograms: Default::default(),
+        };
+        if let Some(log) = &client.action_log {
+            client.on_event(events::action_log_subscriber(log.clone()));
+        }
+        client
     }
     
     pub fn new_devnet() -> Self {
         Self::new(FinternetConfig::default())
     }
+
+    /// Publish off-chain metadata through `publisher` whenever an asset is tokenized
+    pub fn with_metadata_publisher(mut self, publisher: impl metadata::MetadataPublisher + 'static) -> Self {
+        self.metadata_publisher = Some(Arc::new(publisher));
+        self
+    }
+
+    /// Stamp ledger entries with `timestamp` instead of the chain clock; see `fixed_timestamp`
+    pub fn with_fixed_timestamp(mut self, timestamp: u64) -> Self {
+        self.config.fixed_timestamp = Some(timestamp);
+        self
+    }
+
+    /// Build an offline client that replays a recording captured via `record_rpc_to`
+    pub fn from_recording(path: &Path) -> anyhow::Result<Self> {
+        Self::from_recording_with(path, FinternetConfig::default())
+    }
+
+    /// Replay a recording under `config`, keeping its resource limits and formatting.
+    /// A replay never journals to an outbox, appends to the action log, or records again.
+    pub fn from_recording_with(path: &Path, config: FinternetConfig) -> anyhow::Result<Self> {
+        Ok(Self::replaying(recording::ReplaySender::from_file(path)?, config))
+    }
+
+    fn replaying(sender: recording::ReplaySender, config: FinternetConfig) -> Self {
+        let config = FinternetConfig {
+            rpc_url: format!("replay://{}", sender.path().display()),
+            record_rpc_to: None,
+            outbox_path: None,
+            action_log_path: None,
+            ..config
+        };
+        Self::with_rpc_sender(config, sender)
+    }
+}
+
+/// The RPC client `config` describes, recording its traffic if asked to
+pub(crate) fn rpc_client_for(config: &FinternetConfig) -> RpcClient {
+    match &config.record_rpc_to {
+        Some(path) => match recording::RecordingSender::new(&config.rpc_url, path) {
+            Ok(sender) => rpc_client_over(sender, config),
+            Err(e) => {
+                tracing::warn!("RPC recording disabled, could not open {}: {}", path.display(), e);
+                rpc_client_over(HttpSender::new(&config.rpc_url), config)
+            }
+        },
+        None => rpc_client_over(HttpSender::new(&config.rpc_url), config),
+    }
+}
+
+/// Instrument `sender`, and coalesce identical reads above it so telemetry and recordings see
+/// only the calls that went upstream
+fn rpc_client_over<S: RpcSender + Send + Sync + 'static>(sender: S, config: &FinternetConfig) -> RpcClient {
+    let rpc_config = RpcClientConfig::with_commitment(config.commitment());
+    let sender = InstrumentedSender::new(sender);
+    if config.coalesce_reads {
+        RpcClient::new_sender(coalesce::CoalescingSender::new(sender), rpc_config)
+    } else {
+        RpcClient::new_sender(sender, rpc_config)
+    }
 }
 
 // Re-export main functionality
 pub use payment::*;
-pub use identity::*; 
\ No newline at end of file
+pub use identity::*;
+pub use currency::Currency; 
\ No newline at end of file
@@ -0,0 +1,893 @@
+//! Tokenized assets listed for sale and settled by an atomic swap, with no custom program
+//!
+//! A listing is a ledger entry signed by the seller that offers their whole holding of a mint
+//! for a price in an SPL token until an expiry. It references the mint and the asset's issuer,
+//! so `get_listings` finds it in either one's history. Cancelling, and a new price, are further
+//! entries from the seller: a new price supersedes the listing with one under a fresh id.
+//!
+//! Settlement is delivery versus payment in one transaction: the asset moves from seller to
+//! buyer and the price from buyer to seller, or neither moves. With no program to hold the
+//! seller's consent, the listing transaction also approves a settlement authority, named in the
+//! listing, as delegate for the listed amount of the seller's token account. `fulfill_listing`
+//! is signed by the buyer and that authority, so the seller need not be online to sell, and
+//! goes through the send pipeline like any other payment. Cancelling revokes the approval. Both
+//! legs use the token program that owns each mint, SPL Token or Token-2022. Expiry is enforced
+//! by this SDK against the cluster clock, not by the chain.
+
+use crate::pipeline::{SendBody, SendRequest};
+use crate::telemetry::readable;
+use crate::{Currency, FinternetClient};
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use solana_sdk::{
+    instruction::Instruction,
+    pubkey::Pubkey,
+    signature::{Keypair, Signature},
+    signer::Signer,
+};
+use spl_associated_token_account::get_associated_token_address_with_program_id;
+use spl_associated_token_account::instruction as ata_instruction;
+use spl_token_2022::extension::StateWithExtensions;
+use spl_token_2022::instruction as token_instruction;
+use std::collections::HashMap;
+use std::fmt;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Ledger entry action offering an asset for sale, or replacing an earlier offer
+pub const LISTING_ACTION: &str = "listing";
+pub const LISTING_CANCEL_ACTION: &str = "listing_cancel";
+/// Links a listing to the swap that settled it
+pub const LISTING_FULFILLMENT_ACTION: &str = "listing_fulfillment";
+/// Signatures of an address's history searched for listings and what became of them
+const LISTING_SCAN_LIMIT: usize = 1000;
+
+/// An offer to sell `amount` of `mint` for `price`, signed by the seller
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Listing {
+    pub id: String,
+    pub seller: Pubkey,
+    pub mint: Pubkey,
+    /// `Pubkey::default()` when the asset has no issuer on record
+    pub issuer: Pubkey,
+    /// Base units of `mint` for sale
+    pub amount: u64,
+    pub decimals: u8,
+    /// Base units of `currency`
+    pub price: u64,
+    pub currency: Currency,
+    pub created_at: u64,
+    /// Unix seconds, judged against the cluster clock
+    pub expires_at: u64,
+    /// The listing this one replaced with a new price
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub supersedes: Option<String>,
+    /// Delegate the listing approved to move the asset; it countersigns the swap
+    pub settlement_authority: Pubkey,
+    /// Ledger transaction carrying the listing
+    pub signature: Signature,
+}
+
+impl Listing {
+    /// Addresses the listing's entries reference, so they show up in their histories
+    fn references(&self) -> Vec<Pubkey> {
+        let mut references = vec![self.mint];
+        if self.issuer != Pubkey::default() {
+            references.push(self.issuer);
+        }
+        references
+    }
+
+    fn entry(&self) -> serde_json::Value {
+        serde_json::json!({
+            "action": LISTING_ACTION,
+            "id": self.id,
+            "seller": self.seller.to_string(),
+            "mint": self.mint.to_string(),
+            "issuer": self.issuer.to_string(),
+            "amount": self.amount,
+            "decimals": self.decimals,
+            "price": self.price,
+            "currency": self.currency,
+            "created_at": self.created_at,
+            "expires_at": self.expires_at,
+            "supersedes": self.supersedes,
+            "settlement_authority": self.settlement_authority.to_string(),
+        })
+    }
+}
+
+impl fmt::Display for Listing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let asset = Currency::spl(self.mint, self.decimals);
+        writeln!(f, "Listing {}", self.id)?;
+        writeln!(f, "  Asset:   {} ({})", asset.format_amount(self.amount), self.mint)?;
+        writeln!(f, "  Price:   {}", self.currency.format_amount(self.price))?;
+        writeln!(f, "  Seller:  {}", self.seller)?;
+        writeln!(f, "  Settled by: {}", self.settlement_authority)?;
+        if let Some(supersedes) = &self.supersedes {
+            writeln!(f, "  Replaces: {}", supersedes)?;
+        }
+        write!(f, "  Expires: {}", self.expires_at)
+    }
+}
+
+/// Where a listing stands, from the entries its seller signed
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum ListingState {
+    Active,
+    Expired,
+    Cancelled,
+    /// Replaced by listing `by` with a new price
+    Superseded { by: String },
+    Fulfilled { settlement: Signature },
+}
+
+/// A settled listing: the swap, and the entry linking it to the listing
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ListingFulfillment {
+    pub listing_id: String,
+    pub seller: Pubkey,
+    pub buyer: Pubkey,
+    pub mint: Pubkey,
+    pub amount: u64,
+    pub price: u64,
+    pub currency: Currency,
+    /// The swap that moved the asset and the payment
+    pub settlement_signature: Signature,
+    /// The fulfillment entry, written by the settlement authority; `None` when writing it
+    /// failed after the swap settled
+    pub record_signature: Option<Signature>,
+}
+
+/// Why a listing could not be cancelled, repriced, or fulfilled
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ListingError {
+    /// No listing with this id signed by its seller was found
+    NotFound { id: String },
+    Expired { expires_at: u64 },
+    Cancelled,
+    Superseded { by: String },
+    AlreadyFulfilled { settlement: Signature },
+    /// Only the seller can cancel or reprice a listing
+    NotSeller { seller: Pubkey },
+    /// Only the settlement authority the listing names can countersign its swap
+    NotSettlementAuthority { authority: Pubkey },
+    /// The seller sold or moved the asset since listing it
+    SellerNoLongerHolds { held: u64, listed: u64 },
+    /// The seller withdrew or changed the approval that lets the swap move the asset
+    NotApproved { delegated: u64, listed: u64 },
+    /// The recorded listing's price differs from the one the buyer agreed to
+    PriceChanged { listed: u64, expected: u64 },
+}
+
+impl ListingError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::NotFound { .. } => "listing_not_found",
+            Self::Expired { .. } => "listing_expired",
+            Self::Cancelled => "listing_cancelled",
+            Self::Superseded { .. } => "listing_superseded",
+            Self::AlreadyFulfilled { .. } => "listing_fulfilled",
+            Self::NotSeller { .. } => "not_listing_seller",
+            Self::NotSettlementAuthority { .. } => "not_settlement_authority",
+            Self::SellerNoLongerHolds { .. } => "seller_no_longer_holds",
+            Self::NotApproved { .. } => "listing_not_approved",
+            Self::PriceChanged { .. } => "listing_price_changed",
+        }
+    }
+}
+
+impl fmt::Display for ListingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotFound { id } => write!(f, "No listing {} signed by its seller was found", id),
+            Self::Expired { expires_at } => write!(f, "The listing expired at {}", expires_at),
+            Self::Cancelled => write!(f, "The listing was cancelled by its seller"),
+            Self::Superseded { by } => write!(f, "The listing was replaced by listing {} at a new price", by),
+            Self::AlreadyFulfilled { settlement } => write!(f, "The listing was already settled by {}", settlement),
+            Self::NotSeller { seller } => write!(f, "Only the seller {} can do this", seller),
+            Self::NotSettlementAuthority { authority } => {
+                write!(f, "Only the listing's settlement authority {} can countersign its swap", authority)
+            }
+            Self::SellerNoLongerHolds { held, listed } => write!(
+                f,
+                "The seller no longer holds the listed asset: {} listed, {} held",
+                listed, held
+            ),
+            Self::NotApproved { delegated, listed } => write!(
+                f,
+                "The seller no longer approves the settlement authority for the listed asset: {} listed, {} approved",
+                listed, delegated
+            ),
+            Self::PriceChanged { listed, expected } => write!(
+                f,
+                "The recorded price is {} base units, not the {} agreed to",
+                listed, expected
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ListingError {}
+
+/// Listings touching an address and what their sellers recorded about them since
+#[derive(Default)]
+struct ListingBook {
+    listings: HashMap<String, Listing>,
+    /// Listing id -> the signers of entries about it; only the seller's count, and for
+    /// fulfillment also the settlement authority's
+    cancelled_by: HashMap<String, Vec<Pubkey>>,
+    superseded_by: HashMap<String, Vec<(Pubkey, String)>>,
+    fulfilled_by: HashMap<String, Vec<(Pubkey, Signature)>>,
+}
+
+impl ListingBook {
+    fn state(&self, listing: &Listing, now: u64) -> ListingState {
+        let by_seller = |signer: &Pubkey| *signer == listing.seller;
+        let by_settler = |signer: &Pubkey| by_seller(signer) || *signer == listing.settlement_authority;
+        if let Some((_, settlement)) = self.fulfilled_by.get(&listing.id).into_iter().flatten().find(|(s, _)| by_settler(s)) {
+            return ListingState::Fulfilled { settlement: *settlement };
+        }
+        if self.cancelled_by.get(&listing.id).into_iter().flatten().any(by_seller) {
+            return ListingState::Cancelled;
+        }
+        if let Some((_, by)) = self.superseded_by.get(&listing.id).into_iter().flatten().find(|(s, _)| by_seller(s)) {
+            return ListingState::Superseded { by: by.clone() };
+        }
+        if now >= listing.expires_at {
+            return ListingState::Expired;
+        }
+        ListingState::Active
+    }
+
+    /// The listing and its state, refusing anything but an active one
+    fn active(&self, id: &str, now: u64) -> Result<&Listing> {
+        let listing = self.listings.get(id).ok_or_else(|| ListingError::NotFound { id: id.to_string() })?;
+        match self.state(listing, now) {
+            ListingState::Active => Ok(listing),
+            ListingState::Expired => Err(ListingError::Expired { expires_at: listing.expires_at }.into()),
+            ListingState::Cancelled => Err(ListingError::Cancelled.into()),
+            ListingState::Superseded { by } => Err(ListingError::Superseded { by }.into()),
+            ListingState::Fulfilled { settlement } => Err(ListingError::AlreadyFulfilled { settlement }.into()),
+        }
+    }
+}
+
+impl FinternetClient {
+    /// List the seller's whole holding of `mint` for `price` base units of `currency` until
+    /// `expiry`, approving `settlement_authority` to deliver it to a buyer
+    pub async fn create_listing(
+        &self,
+        seller_wallet: &Keypair,
+        mint: &Pubkey,
+        price: u64,
+        currency: &Currency,
+        expiry: u64,
+        settlement_authority: &Pubkey,
+    ) -> Result<Listing> {
+        let outcome = self.write_listing(seller_wallet, mint, price, currency, expiry, settlement_authority, None).await;
+        let parameters = serde_json::json!({
+            "seller": seller_wallet.pubkey().to_string(),
+            "mint": mint.to_string(),
+            "price": price,
+            "currency": currency,
+            "expiry": expiry,
+            "settlement_authority": settlement_authority.to_string(),
+            "id": outcome.as_ref().ok().map(|listing| listing.id.clone()),
+        });
+        self.log_action("create_listing", parameters, None, outcome.as_ref(), |listing| listing.signature.to_string());
+        outcome
+    }
+
+    /// Replace an active listing with one at `price`, keeping its asset, currency, expiry, and
+    /// settlement authority
+    pub async fn update_listing_price(&self, seller_wallet: &Keypair, listing_id: &str, price: u64) -> Result<Listing> {
+        let outcome = async {
+            let listing = self.own_active_listing(seller_wallet, listing_id).await?;
+            self.write_listing(
+                seller_wallet,
+                &listing.mint,
+                price,
+                &listing.currency,
+                listing.expires_at,
+                &listing.settlement_authority,
+                Some(listing.id),
+            )
+            .await
+        }
+        .await;
+        let parameters = serde_json::json!({
+            "seller": seller_wallet.pubkey().to_string(),
+            "listing_id": listing_id,
+            "price": price,
+            "id": outcome.as_ref().ok().map(|listing| listing.id.clone()),
+        });
+        self.log_action("update_listing_price", parameters, None, outcome.as_ref(), |listing| listing.signature.to_string());
+        outcome
+    }
+
+    /// Active listings in the history of an asset's issuer, or of its mint, oldest first
+    #[tracing::instrument(skip_all, fields(address = %readable(issuer_or_collection)))]
+    pub async fn get_listings(&self, issuer_or_collection: &Pubkey) -> Result<Vec<Listing>> {
+        let book = self.listing_book(issuer_or_collection).await?;
+        let now = self.get_chain_time().await?.unix_timestamp();
+        let mut listings: Vec<Listing> = book
+            .listings
+            .values()
+            .filter(|listing| book.state(listing, now) == ListingState::Active)
+            .cloned()
+            .collect();
+        listings.sort_by(|a, b| a.created_at.cmp(&b.created_at).then_with(|| a.id.cmp(&b.id)));
+        tracing::info!("Found {} active listings", listings.len());
+        Ok(listings)
+    }
+
+    /// Withdraw an active listing, revoking its settlement authority's approval
+    pub async fn cancel_listing(&self, seller_wallet: &Keypair, listing_id: &str) -> Result<Signature> {
+        let outcome = self.cancel_listing_unlogged(seller_wallet, listing_id).await;
+        let parameters = serde_json::json!({
+            "seller": seller_wallet.pubkey().to_string(),
+            "listing_id": listing_id,
+        });
+        self.log_action("cancel_listing", parameters, None, outcome.as_ref(), Signature::to_string);
+        outcome
+    }
+
+    #[tracing::instrument(skip_all, fields(wallet = %readable(&seller_wallet.pubkey()), listing_id))]
+    async fn cancel_listing_unlogged(&self, seller_wallet: &Keypair, listing_id: &str) -> Result<Signature> {
+        let listing = self.own_active_listing(seller_wallet, listing_id).await?;
+        let entry = serde_json::json!({
+            "action": LISTING_CANCEL_ACTION,
+            "listing_id": listing.id,
+            "seller": listing.seller.to_string(),
+            "mint": listing.mint.to_string(),
+        });
+        // Only the listing's own approval is revoked; one the seller gave since is theirs to keep
+        let program = self.token_program_of(&listing.mint)?;
+        let mut revoke = Vec::new();
+        if let Some((address, holding)) = self.listed_holding(&listing.seller, &listing.mint, &program)? {
+            if Option::<Pubkey>::from(holding.delegate) == Some(listing.settlement_authority) {
+                revoke.push(token_instruction::revoke(&program, &address, &listing.seller, &[])?);
+            }
+        }
+        let signature = self
+            .write_ledger_entry_alongside(seller_wallet, &entry.to_string(), &listing.references(), revoke)
+            .await?;
+        tracing::info!("Listing {} cancelled: {}", listing.id, signature);
+        Ok(signature)
+    }
+
+    /// Buy a listing: swap the asset for its price in one transaction, then record the fulfillment
+    ///
+    /// The listing is re-read from the ledger, and refused unless it is still active at the price
+    /// in `listing`, and the seller still holds the asset and approves the listing's settlement
+    /// authority for it. `settlement_authority` countersigns the swap in the seller's place. The
+    /// buyer's payment goes through the send pipeline, with the same middleware, asset policy,
+    /// and spending limits as `send_payment`.
+    pub async fn fulfill_listing(
+        &self,
+        buyer_wallet: &Keypair,
+        settlement_authority: &Keypair,
+        listing: &Listing,
+    ) -> Result<ListingFulfillment> {
+        let outcome = self.fulfill_listing_unlogged(buyer_wallet, settlement_authority, listing).await;
+        let parameters = serde_json::json!({
+            "buyer": buyer_wallet.pubkey().to_string(),
+            "seller": listing.seller.to_string(),
+            "settlement_authority": settlement_authority.pubkey().to_string(),
+            "listing_id": listing.id,
+            "mint": listing.mint.to_string(),
+            "amount": listing.amount,
+            "price": listing.price,
+        });
+        self.log_action("fulfill_listing", parameters, listing.currency.mint().as_ref(), outcome.as_ref(), |fulfillment| {
+            fulfillment.settlement_signature.to_string()
+        });
+        outcome
+    }
+
+    #[tracing::instrument(skip_all, fields(wallet = %readable(&buyer_wallet.pubkey()), listing_id = %listing.id, signature = tracing::field::Empty))]
+    async fn fulfill_listing_unlogged(
+        &self,
+        buyer_wallet: &Keypair,
+        settlement_authority: &Keypair,
+        listing: &Listing,
+    ) -> Result<ListingFulfillment> {
+        let buyer = buyer_wallet.pubkey();
+        if buyer == listing.seller {
+            return Err(anyhow!("The buyer and seller must be different wallets"));
+        }
+
+        // Settle what the seller signed, not what was handed in
+        let book = self.listing_book(&listing.mint).await?;
+        let now = self.get_chain_time().await?.unix_timestamp();
+        let recorded = book.active(&listing.id, now)?.clone();
+        if recorded.price != listing.price {
+            return Err(ListingError::PriceChanged { listed: recorded.price, expected: listing.price }.into());
+        }
+        if settlement_authority.pubkey() != recorded.settlement_authority {
+            return Err(ListingError::NotSettlementAuthority { authority: recorded.settlement_authority }.into());
+        }
+        let seller = recorded.seller;
+        let asset_program = self.token_program_of(&recorded.mint)?;
+        let holding = self.listed_holding(&seller, &recorded.mint, &asset_program)?.map(|(_, holding)| holding);
+        let held = holding.map_or(0, |holding| holding.amount);
+        if held < recorded.amount {
+            return Err(ListingError::SellerNoLongerHolds { held, listed: recorded.amount }.into());
+        }
+        let delegated = holding
+            .filter(|holding| Option::<Pubkey>::from(holding.delegate) == Some(recorded.settlement_authority))
+            .map_or(0, |holding| holding.delegated_amount);
+        if delegated < recorded.amount {
+            return Err(ListingError::NotApproved { delegated, listed: recorded.amount }.into());
+        }
+        let payment_mint = recorded
+            .currency
+            .mint()
+            .ok_or_else(|| anyhow!("Listing {} is priced in SOL; listings settle in SPL tokens", recorded.id))?;
+        let payment_program = self.token_program_of(&payment_mint)?;
+        // The pipeline checks the payment leg; the asset leg is not the buyer's spend
+        self.check_asset_policy(&buyer, &recorded.mint).await?;
+
+        let instructions = swap_instructions(&recorded, &buyer, &payment_mint, &asset_program, &payment_program)?;
+        let request = SendRequest::payment("fulfill_listing", buyer, seller, recorded.price, payment_mint, None, serde_json::Value::Null)
+            .with_body(SendBody::Instructions(instructions))
+            .unrecorded();
+        let receipt = self.send_through_pipeline(request, &[buyer_wallet, settlement_authority]).await?;
+        let settlement_signature = receipt.signature;
+        tracing::Span::current().record("signature", tracing::field::display(&settlement_signature));
+        tracing::info!("Listing {} settled: {}", recorded.id, settlement_signature);
+
+        let entry = serde_json::json!({
+            "action": LISTING_FULFILLMENT_ACTION,
+            "listing_id": recorded.id,
+            "seller": seller.to_string(),
+            "buyer": buyer.to_string(),
+            "mint": recorded.mint.to_string(),
+            "settlement_signature": settlement_signature.to_string(),
+        });
+        // The swap already moved both legs, so a failed record is reported, not returned
+        let record_signature = match self
+            .write_ledger_entry_referencing(settlement_authority, &entry.to_string(), &recorded.references())
+            .await
+        {
+            Ok(signature) => Some(signature),
+            Err(e) => {
+                tracing::warn!("Listing {} settled by {} but its fulfillment was not recorded: {}", recorded.id, settlement_signature, e);
+                None
+            }
+        };
+
+        Ok(ListingFulfillment {
+            listing_id: recorded.id,
+            seller,
+            buyer,
+            mint: recorded.mint,
+            amount: recorded.amount,
+            price: recorded.price,
+            currency: recorded.currency,
+            settlement_signature,
+            record_signature,
+        })
+    }
+
+    #[tracing::instrument(skip_all, fields(wallet = %readable(&seller_wallet.pubkey()), mint = %mint, price, expiry))]
+    #[allow(clippy::too_many_arguments)]
+    async fn write_listing(
+        &self,
+        seller_wallet: &Keypair,
+        mint: &Pubkey,
+        price: u64,
+        currency: &Currency,
+        expiry: u64,
+        settlement_authority: &Pubkey,
+        supersedes: Option<String>,
+    ) -> Result<Listing> {
+        let seller = seller_wallet.pubkey();
+        if *settlement_authority == seller {
+            return Err(anyhow!("The settlement authority must be a wallet other than the seller's"));
+        }
+        if price == 0 {
+            return Err(anyhow!("Listing price must be greater than zero"));
+        }
+        if currency.mint().is_none() {
+            return Err(anyhow!("Listings are priced in an SPL token, not SOL"));
+        }
+        let now = self.get_chain_time().await?.unix_timestamp();
+        if expiry <= now {
+            return Err(anyhow!("Listing expiry {} has already passed (cluster time {})", expiry, now));
+        }
+        let program = self.token_program_of(mint)?;
+        let holding = self.listed_holding(&seller, mint, &program)?;
+        let (address, amount) = match holding {
+            Some((address, holding)) if holding.amount > 0 => (address, holding.amount),
+            _ => return Err(anyhow!("{} holds none of {} to list", seller, mint)),
+        };
+        let asset = self.get_asset_info(mint).await?;
+
+        let mut listing = Listing {
+            id: uuid::Uuid::new_v4().to_string(),
+            seller,
+            mint: *mint,
+            issuer: asset.issuer,
+            amount,
+            decimals: asset.decimals,
+            price,
+            currency: currency.clone(),
+            created_at: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+            expires_at: expiry,
+            supersedes,
+            settlement_authority: *settlement_authority,
+            signature: Signature::default(),
+        };
+        // The entry and the approval land together, so no listing is recorded that cannot settle
+        let approve = token_instruction::approve_checked(
+            &program,
+            &address,
+            mint,
+            settlement_authority,
+            &seller,
+            &[],
+            amount,
+            listing.decimals,
+        )?;
+        listing.signature = self
+            .write_ledger_entry_alongside(seller_wallet, &listing.entry().to_string(), &listing.references(), vec![approve])
+            .await?;
+        tracing::info!("Listing {} written: {}", listing.id, listing.signature);
+        Ok(listing)
+    }
+
+    /// One of the seller's own listings, refused unless it is active
+    async fn own_active_listing(&self, seller_wallet: &Keypair, listing_id: &str) -> Result<Listing> {
+        let book = self.listing_book(&seller_wallet.pubkey()).await?;
+        let now = self.get_chain_time().await?.unix_timestamp();
+        let listing = book.active(listing_id, now)?;
+        if listing.seller != seller_wallet.pubkey() {
+            return Err(ListingError::NotSeller { seller: listing.seller }.into());
+        }
+        Ok(listing.clone())
+    }
+
+    /// The token program that owns `mint`: SPL Token or Token-2022
+    fn token_program_of(&self, mint: &Pubkey) -> Result<Pubkey> {
+        let program = self.client.get_account(mint)?.owner;
+        if program != spl_token::id() && program != spl_token_2022::id() {
+            return Err(anyhow!("{} is not a mint of SPL Token or Token-2022", mint));
+        }
+        Ok(program)
+    }
+
+    /// `owner`'s associated token account for `mint` under `program`, if it exists
+    fn listed_holding(
+        &self,
+        owner: &Pubkey,
+        mint: &Pubkey,
+        program: &Pubkey,
+    ) -> Result<Option<(Pubkey, spl_token_2022::state::Account)>> {
+        let address = get_associated_token_address_with_program_id(owner, mint, program);
+        let Some(account) = self.client.get_account_with_commitment(&address, self.client.commitment())?.value else {
+            return Ok(None);
+        };
+        let holding = StateWithExtensions::<spl_token_2022::state::Account>::unpack(&account.data)
+            .map_err(|e| anyhow!("{} is not a token account: {}", address, e))?
+            .base;
+        Ok(Some((address, holding)))
+    }
+
+    /// Every listing entry in `address`'s history, keeping only what its signer may record
+    async fn listing_book(&self, address: &Pubkey) -> Result<ListingBook> {
+        let entries = self.read_ledger_entries(address, Some(LISTING_SCAN_LIMIT)).await?;
+        let mut book = ListingBook::default();
+        for entry in &entries {
+            let Some(value) = entry.json() else { continue };
+            let Some(signer) = entry.signer else { continue };
+            let text = |field: &str| value.get(field).and_then(|v| v.as_str()).map(str::to_string);
+            match value.get("action").and_then(|a| a.as_str()) {
+                Some(LISTING_ACTION) => match parse_listing(&value, entry.signature) {
+                    // Only the seller a listing names can offer the asset
+                    Some(listing) if listing.seller == signer => {
+                        if let Some(old) = &listing.supersedes {
+                            book.superseded_by.entry(old.clone()).or_default().push((signer, listing.id.clone()));
+                        }
+                        book.listings.insert(listing.id.clone(), listing);
+                    }
+                    Some(_) => {}
+                    None => tracing::warn!("Ignoring malformed listing in {}", entry.signature),
+                },
+                Some(LISTING_CANCEL_ACTION) => {
+                    if let Some(id) = text("listing_id") {
+                        book.cancelled_by.entry(id).or_default().push(signer);
+                    }
+                }
+                Some(LISTING_FULFILLMENT_ACTION) => {
+                    let settlement = text("settlement_signature").and_then(|s| s.parse::<Signature>().ok());
+                    if let (Some(id), Some(settlement)) = (text("listing_id"), settlement) {
+                        book.fulfilled_by.entry(id).or_default().push((signer, settlement));
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(book)
+    }
+}
+
+/// Create both receiving accounts if needed, move the asset to the buyer under the settlement
+/// authority's approval and the price to the seller, and mark the swap with the listing id
+fn swap_instructions(
+    listing: &Listing,
+    buyer: &Pubkey,
+    payment_mint: &Pubkey,
+    asset_program: &Pubkey,
+    payment_program: &Pubkey,
+) -> Result<Vec<Instruction>> {
+    let seller = &listing.seller;
+    let authority = &listing.settlement_authority;
+    let memo = format!("listing-swap:{}", listing.id);
+    Ok(vec![
+        ata_instruction::create_associated_token_account_idempotent(buyer, buyer, &listing.mint, asset_program),
+        ata_instruction::create_associated_token_account_idempotent(buyer, seller, payment_mint, payment_program),
+        token_instruction::transfer_checked(
+            asset_program,
+            &get_associated_token_address_with_program_id(seller, &listing.mint, asset_program),
+            &listing.mint,
+            &get_associated_token_address_with_program_id(buyer, &listing.mint, asset_program),
+            authority,
+            &[],
+            listing.amount,
+            listing.decimals,
+        )?,
+        token_instruction::transfer_checked(
+            payment_program,
+            &get_associated_token_address_with_program_id(buyer, payment_mint, payment_program),
+            payment_mint,
+            &get_associated_token_address_with_program_id(seller, payment_mint, payment_program),
+            buyer,
+            &[],
+            listing.price,
+            listing.currency.decimals(),
+        )?,
+        spl_memo::build_memo(memo.as_bytes(), &[buyer, authority]),
+    ])
+}
+
+fn parse_listing(value: &serde_json::Value, signature: Signature) -> Option<Listing> {
+    let pubkey = |field: &str| value.get(field)?.as_str()?.parse::<Pubkey>().ok();
+    let number = |field: &str| value.get(field)?.as_u64();
+
+    Some(Listing {
+        id: value.get("id")?.as_str()?.to_string(),
+        seller: pubkey("seller")?,
+        mint: pubkey("mint")?,
+        issuer: pubkey("issuer").unwrap_or_default(),
+        amount: number("amount")?,
+        decimals: u8::try_from(number("decimals")?).ok()?,
+        price: number("price")?,
+        currency: serde_json::from_value(value.get("currency")?.clone()).ok()?,
+        created_at: number("created_at").unwrap_or(0),
+        expires_at: number("expires_at")?,
+        supersedes: value.get("supersedes").and_then(|v| v.as_str()).map(str::to_string),
+        // Listings from before settlement authorities name none, so no swap can settle them
+        settlement_authority: pubkey("settlement_authority").unwrap_or_default(),
+        signature,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_cluster::{MockCluster, TempDir};
+    use crate::pipeline::{SendMiddleware, SendRefused, SendRequest, SendStage};
+    use crate::FinternetConfig;
+    use solana_sdk::transaction::Transaction;
+    use spl_token_2022::instruction::TokenInstruction;
+
+    const NOW: u64 = 1_700_000_000;
+
+    /// A seller holding 5 of a Token-2022 asset, a buyer holding 100 of a 6-decimal payment
+    /// token, and a settlement authority, all funded
+    struct Market {
+        cluster: MockCluster,
+        client: FinternetClient,
+        seller: Keypair,
+        buyer: Keypair,
+        authority: Keypair,
+        asset: Pubkey,
+        currency: Currency,
+    }
+
+    fn market(dir: &TempDir) -> Market {
+        let cluster = MockCluster::new();
+        let (seller, buyer, authority) = (Keypair::new(), Keypair::new(), Keypair::new());
+        for wallet in [&seller, &buyer, &authority] {
+            cluster.set_balance(wallet.pubkey(), 1_000_000_000);
+        }
+        let asset = cluster.add_mint(&spl_token_2022::id(), 0, &Pubkey::new_unique());
+        let payment_mint = cluster.add_mint(&spl_token::id(), 6, &Pubkey::new_unique());
+        cluster.add_token_account(&seller.pubkey(), &asset, 5);
+        cluster.add_token_account(&buyer.pubkey(), &payment_mint, 100_000_000);
+        let client = cluster.client(FinternetConfig {
+            outbox_path: Some(dir.join("outbox.json")),
+            ..FinternetConfig::default()
+        });
+        Market { cluster, client, seller, buyer, authority, asset, currency: Currency::spl(payment_mint, 6) }
+    }
+
+    impl Market {
+        async fn list(&self) -> Listing {
+            self.client
+                .create_listing(&self.seller, &self.asset, 25_000_000, &self.currency, NOW + 3_600, &self.authority.pubkey())
+                .await
+                .unwrap()
+        }
+
+        /// What the listing's approval did on-chain, which the mock does not execute
+        fn approve(&self, delegate: Option<Pubkey>, amount: u64) {
+            let address = get_associated_token_address_with_program_id(&self.seller.pubkey(), &self.asset, &spl_token_2022::id());
+            self.cluster.set_token_delegate(&address, delegate, amount);
+        }
+    }
+
+    /// Each token instruction of `transaction`, with the program that runs it
+    fn token_instructions(transaction: &Transaction) -> Vec<(Pubkey, TokenInstruction<'_>, Vec<Pubkey>)> {
+        let keys = &transaction.message.account_keys;
+        transaction
+            .message
+            .instructions
+            .iter()
+            .filter(|instruction| [spl_token::id(), spl_token_2022::id()].contains(&keys[instruction.program_id_index as usize]))
+            .map(|instruction| {
+                let accounts = instruction.accounts.iter().map(|index| keys[*index as usize]).collect();
+                (keys[instruction.program_id_index as usize], TokenInstruction::unpack(&instruction.data).unwrap(), accounts)
+            })
+            .collect()
+    }
+
+    fn signers(transaction: &Transaction) -> Vec<Pubkey> {
+        transaction.message.account_keys[..transaction.message.header.num_required_signatures as usize].to_vec()
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn a_listing_approves_its_settlement_authority_on_the_mints_program() {
+        let dir = TempDir::new("listing");
+        let market = market(&dir);
+
+        let listing = market.list().await;
+        let sent = market.cluster.sent();
+        let instructions = token_instructions(sent.last().unwrap());
+        assert_eq!(instructions.len(), 1);
+        let (program, instruction, accounts) = &instructions[0];
+        assert_eq!(*program, spl_token_2022::id());
+        assert!(matches!(instruction, TokenInstruction::ApproveChecked { amount: 5, decimals: 0 }));
+        assert_eq!(accounts[2], market.authority.pubkey());
+        assert_eq!(accounts[3], market.seller.pubkey());
+
+        assert_eq!(listing.settlement_authority, market.authority.pubkey());
+        assert_eq!(market.client.get_listings(&market.asset).await.unwrap(), vec![listing]);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn the_buyer_settles_with_the_settlement_authority_and_not_the_seller() {
+        let dir = TempDir::new("listing");
+        let market = market(&dir);
+        let listing = market.list().await;
+        market.approve(Some(market.authority.pubkey()), 5);
+
+        let fulfillment = market.client.fulfill_listing(&market.buyer, &market.authority, &listing).await.unwrap();
+        let sent = market.cluster.sent();
+        let swap = sent.iter().find(|transaction| transaction.signatures[0] == fulfillment.settlement_signature).unwrap();
+        assert_eq!(signers(swap), vec![market.buyer.pubkey(), market.authority.pubkey()]);
+        let transfers: Vec<_> = token_instructions(swap)
+            .into_iter()
+            .map(|(program, instruction, accounts)| match instruction {
+                TokenInstruction::TransferChecked { amount, .. } => (program, amount, accounts[3]),
+                other => panic!("unexpected {:?}", other),
+            })
+            .collect();
+        assert_eq!(
+            transfers,
+            vec![
+                (spl_token_2022::id(), 5, market.authority.pubkey()),
+                (spl_token::id(), 25_000_000, market.buyer.pubkey()),
+            ]
+        );
+
+        // The authority's fulfillment entry closes the listing
+        let record = sent.last().unwrap();
+        assert_eq!(Some(record.signatures[0]), fulfillment.record_signature);
+        assert_eq!(signers(record), vec![market.authority.pubkey()]);
+        assert!(market.client.get_listings(&market.asset).await.unwrap().is_empty());
+        let error = market.client.fulfill_listing(&market.buyer, &market.authority, &listing).await.unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<ListingError>(),
+            Some(&ListingError::AlreadyFulfilled { settlement: fulfillment.settlement_signature })
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn a_fulfillment_recorded_by_anyone_else_leaves_the_listing_active() {
+        let dir = TempDir::new("listing");
+        let market = market(&dir);
+        let listing = market.list().await;
+
+        let entry = serde_json::json!({
+            "action": LISTING_FULFILLMENT_ACTION,
+            "listing_id": listing.id,
+            "settlement_signature": Signature::new_unique().to_string(),
+        });
+        market.client.write_ledger_entry_referencing(&market.buyer, &entry.to_string(), &[market.asset]).await.unwrap();
+
+        assert_eq!(market.client.get_listings(&market.asset).await.unwrap(), vec![listing]);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn a_swap_is_refused_without_the_listed_approval_and_authority() {
+        let dir = TempDir::new("listing");
+        let market = market(&dir);
+        let listing = market.list().await;
+        let sent = market.cluster.sent().len();
+
+        // Approved for less than listed, or for someone else
+        market.approve(Some(market.authority.pubkey()), 4);
+        let error = market.client.fulfill_listing(&market.buyer, &market.authority, &listing).await.unwrap_err();
+        assert_eq!(error.downcast_ref::<ListingError>(), Some(&ListingError::NotApproved { delegated: 4, listed: 5 }));
+        market.approve(Some(Pubkey::new_unique()), 5);
+        let error = market.client.fulfill_listing(&market.buyer, &market.authority, &listing).await.unwrap_err();
+        assert_eq!(error.downcast_ref::<ListingError>(), Some(&ListingError::NotApproved { delegated: 0, listed: 5 }));
+
+        market.approve(Some(market.authority.pubkey()), 5);
+        let error = market.client.fulfill_listing(&market.buyer, &Keypair::new(), &listing).await.unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<ListingError>(),
+            Some(&ListingError::NotSettlementAuthority { authority: market.authority.pubkey() })
+        );
+        assert_eq!(market.cluster.sent().len(), sent);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn cancelling_revokes_the_listings_approval() {
+        let dir = TempDir::new("listing");
+        let market = market(&dir);
+        let listing = market.list().await;
+        market.approve(Some(market.authority.pubkey()), 5);
+
+        let signature = market.client.cancel_listing(&market.seller, &listing.id).await.unwrap();
+        let sent = market.cluster.sent();
+        let cancel = sent.iter().find(|transaction| transaction.signatures[0] == signature).unwrap();
+        let instructions = token_instructions(cancel);
+        assert_eq!(instructions.len(), 1);
+        assert_eq!(instructions[0].0, spl_token_2022::id());
+        assert!(matches!(instructions[0].1, TokenInstruction::Revoke));
+        assert!(market.client.get_listings(&market.asset).await.unwrap().is_empty());
+    }
+
+    struct Refuse;
+
+    #[async_trait::async_trait]
+    impl SendMiddleware for Refuse {
+        fn name(&self) -> &str {
+            "refuse"
+        }
+
+        async fn before_send(&self, _client: &FinternetClient, _request: &mut SendRequest) -> Result<()> {
+            Err(SendRefused::new("refuse", "test", "refused for the test").into())
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn the_swap_passes_the_send_middleware() {
+        let dir = TempDir::new("listing");
+        let Market { cluster, client, seller, buyer, authority, asset, currency } = market(&dir);
+        let client = client.with_send_middleware(SendStage::Screen, Refuse);
+        let market = Market { cluster, client, seller, buyer, authority, asset, currency };
+        let listing = market.list().await;
+        market.approve(Some(market.authority.pubkey()), 5);
+        let sent = market.cluster.sent().len();
+
+        let error = market.client.fulfill_listing(&market.buyer, &market.authority, &listing).await.unwrap_err();
+        assert!(error.downcast_ref::<SendRefused>().is_some());
+        assert_eq!(market.cluster.sent().len(), sent);
+    }
+}
@@ -0,0 +1,898 @@
+//! A local copy of an owner's token transfers, kept in a SQLite database
+//!
+//! `sync_local_ledger` pulls only the signatures newer than the last sync and stores the
+//! transfers they made, so history, per-mint, and monthly queries run without RPC. Each sync is
+//! written in one transaction. History the node could not serve is recorded as a gap rather
+//! than left out silently.
+
+pub use crate::history::HistoryGap;
+use crate::history::{HistoryCompleteness, HistoryFilter, MAX_SYNC_PAGES, SIGNATURE_PAGE_LIMIT};
+use crate::progress::{Operation, ProgressEvent};
+use crate::resources::{file_bytes, StoreUsage};
+use crate::sqlite::{Connection, SqlValue};
+use crate::telemetry::readable;
+use crate::{Currency, FinternetClient, TransactionRecord};
+use anyhow::{anyhow, Result};
+use chrono::{TimeZone, Utc};
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use solana_sdk::{pubkey::Pubkey, signature::Signature};
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Schema version written by this build, kept as the database's `user_version`
+const LEDGER_SCHEMA_VERSION: u32 = 1;
+
+/// Newest first; among records of the same slot and time, the later sync and the earlier
+/// history position come first
+const NEWEST_FIRST: &str = "ORDER BY slot DESC, timestamp DESC, rowid DESC";
+const OLDEST_FIRST: &str = "ORDER BY slot ASC, timestamp ASC, rowid ASC";
+
+/// Outcome of one `sync_ledger` run
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SyncReport {
+    pub new_signatures: usize,
+    pub new_records: usize,
+    /// Gaps recorded during this run
+    pub gaps: Vec<HistoryGap>,
+    /// Whether the ledger now reaches back to the account's creation
+    #[serde(default)]
+    pub completeness: HistoryCompleteness,
+}
+
+/// Sent, received, and fee totals for one calendar month (UTC) and currency
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MonthlyTotal {
+    /// `YYYY-MM`
+    pub month: String,
+    #[serde(alias = "mint")]
+    pub currency: Currency,
+    pub sent: u64,
+    pub received: u64,
+    /// Fees the owner paid on these transactions, in lamports
+    pub fees: u64,
+    pub transactions: usize,
+}
+
+/// What `LocalLedger::compact` may drop beyond bookkeeping it can rebuild
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LedgerRetention {
+    /// Newest records kept per owner; older ones are dropped and recorded as a gap
+    pub max_records_per_owner: Option<usize>,
+}
+
+/// What one `LocalLedger::compact` run removed
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LedgerCompaction {
+    pub records_dropped: usize,
+    /// Processed signatures with no record left, which later syncs no longer need
+    pub signatures_forgotten: usize,
+    /// Gaps recorded more than once for the same stretch of history
+    pub duplicate_gaps: usize,
+    pub bytes_before: u64,
+    pub bytes_after: u64,
+}
+
+/// What one sync found, written to the ledger in one transaction
+struct SyncBatch {
+    newest: Option<Signature>,
+    processed: Vec<Signature>,
+    /// Newest first
+    records: Vec<TransactionRecord>,
+    gaps: Vec<HistoryGap>,
+    completeness: Option<HistoryCompleteness>,
+}
+
+/// Local copy of an owner's token transfers, synced incrementally from RPC and queried offline
+pub struct LocalLedger {
+    path: PathBuf,
+    /// One connection, so each call's statements run as a unit
+    db: Mutex<Connection>,
+}
+
+impl LocalLedger {
+    /// Open the ledger database at `path`, creating it if needed
+    pub fn open(path: &Path) -> Result<Self> {
+        let connection = Connection::open(path)?;
+        let version = connection
+            .query("PRAGMA user_version", &[])?
+            .first()
+            .and_then(|row| row.first())
+            .and_then(SqlValue::as_i64)
+            .unwrap_or(0) as u32;
+        if version > LEDGER_SCHEMA_VERSION {
+            return Err(anyhow!(
+                "Local ledger {} is at schema version {}, newer than this build's {}",
+                path.display(),
+                version,
+                LEDGER_SCHEMA_VERSION
+            ));
+        }
+        if version < LEDGER_SCHEMA_VERSION {
+            connection
+                .transaction(|db| migrate(db, version))
+                .map_err(|e| e.context(format!("Migrating {}", path.display())))?;
+        }
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            db: Mutex::new(connection),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Records held across every owner, and the database's size
+    pub fn usage(&self) -> Result<StoreUsage> {
+        let db = self.lock()?;
+        let entries = single_i64(&db, "SELECT COUNT(*) FROM records", &[])?.unwrap_or(0) as usize;
+        Ok(StoreUsage {
+            path: self.path.clone(),
+            entries,
+            bytes: file_bytes(&self.path),
+            archived_files: 0,
+            archived_bytes: 0,
+        })
+    }
+
+    /// Shrink the ledger: forget processed signatures that left no record, merge repeated
+    /// gaps, and drop records beyond `retention`
+    ///
+    /// Syncs only read history newer than the newest signature seen, so forgotten signatures are
+    /// not read again unless a dropped fork rolls that signature back. Dropped records leave a
+    /// gap, and the owner's history is then reported as truncated at the oldest slot kept, so
+    /// statements do not treat what remains as complete. When anything was removed the database
+    /// is vacuumed afterwards, so the file itself shrinks and `bytes_after` reports its new size.
+    pub fn compact(&self, retention: &LedgerRetention) -> Result<LedgerCompaction> {
+        let db = self.lock()?;
+        let mut report = LedgerCompaction { bytes_before: file_bytes(&self.path), ..LedgerCompaction::default() };
+        db.transaction(|db| {
+            if let Some(max) = retention.max_records_per_owner {
+                let over: Vec<String> = db
+                    .query("SELECT owner FROM records GROUP BY owner HAVING COUNT(*) > ?", &[(max as i64).into()])?
+                    .iter()
+                    .filter_map(|row| row.first().and_then(SqlValue::as_str).map(str::to_string))
+                    .collect();
+                for owner in over {
+                    report.records_dropped += drop_oldest_records(db, &owner, max)?;
+                }
+            }
+            report.signatures_forgotten = db.execute(
+                "DELETE FROM seen WHERE NOT EXISTS
+                    (SELECT 1 FROM records WHERE records.owner = seen.owner AND records.signature = seen.signature)",
+                &[],
+            )?;
+            db.execute(
+                "DELETE FROM finalized WHERE NOT EXISTS
+                    (SELECT 1 FROM records WHERE records.owner = finalized.owner AND records.signature = finalized.signature)",
+                &[],
+            )?;
+            // Keep the newest of each, so its reason and time are current
+            report.duplicate_gaps = db.execute(
+                "DELETE FROM gaps WHERE EXISTS (SELECT 1 FROM gaps AS newer WHERE newer.owner = gaps.owner
+                    AND newer.before_signature IS gaps.before_signature AND newer.after_slot = gaps.after_slot
+                    AND newer.id > gaps.id)",
+                &[],
+            )?;
+            Ok(())
+        })?;
+        // VACUUM cannot run inside a transaction, and rewrites the whole file, so only pay for it
+        // when the transaction freed something
+        if report.records_dropped + report.signatures_forgotten + report.duplicate_gaps > 0 {
+            db.execute_batch("VACUUM")?;
+        }
+        report.bytes_after = file_bytes(&self.path);
+        Ok(report)
+    }
+
+    /// Most recent records for `owner`, newest first
+    pub fn query_history(&self, owner: &Pubkey, limit: usize, offset: usize) -> Result<Vec<TransactionRecord>> {
+        let db = self.lock()?;
+        read_records(
+            &db,
+            &format!("SELECT record FROM records WHERE owner = ? {} LIMIT ? OFFSET ?", NEWEST_FIRST),
+            &[owner_key(owner), (limit.min(i64::MAX as usize) as i64).into(), (offset.min(i64::MAX as usize) as i64).into()],
+        )
+    }
+
+    /// Every record for `owner` moving `mint`, newest first
+    pub fn query_by_mint(&self, owner: &Pubkey, mint: &Pubkey) -> Result<Vec<TransactionRecord>> {
+        let db = self.lock()?;
+        read_records(
+            &db,
+            &format!("SELECT record FROM records WHERE owner = ? AND mint = ? {}", NEWEST_FIRST),
+            &[owner_key(owner), mint.to_string().into()],
+        )
+    }
+
+    /// Records for `owner` with `from_ts <= timestamp <= to_ts`, oldest first
+    pub fn query_between(&self, owner: &Pubkey, from_ts: u64, to_ts: u64) -> Result<Vec<TransactionRecord>> {
+        let db = self.lock()?;
+        read_records(
+            &db,
+            &format!("SELECT record FROM records WHERE owner = ? AND timestamp BETWEEN ? AND ? {}", OLDEST_FIRST),
+            &[owner_key(owner), SqlValue::try_from(from_ts)?, SqlValue::try_from(to_ts)?],
+        )
+    }
+
+    /// Per-month, per-mint totals for `owner`, oldest month first
+    pub fn monthly_totals(&self, owner: &Pubkey, mint: Option<&Pubkey>) -> Result<Vec<MonthlyTotal>> {
+        let records = {
+            let db = self.lock()?;
+            match mint {
+                Some(mint) => read_records(
+                    &db,
+                    &format!("SELECT record FROM records WHERE owner = ? AND mint = ? {}", NEWEST_FIRST),
+                    &[owner_key(owner), mint.to_string().into()],
+                )?,
+                None => read_records(&db, &format!("SELECT record FROM records WHERE owner = ? {}", NEWEST_FIRST), &[owner_key(owner)])?,
+            }
+        };
+        let mut totals: BTreeMap<(String, Option<Pubkey>), MonthlyTotal> = BTreeMap::new();
+        let mut fee_counted: HashSet<Signature> = HashSet::new();
+        for record in &records {
+            let month = month_of(record.timestamp);
+            let total = totals
+                .entry((month.clone(), record.currency.mint()))
+                .or_insert_with(|| MonthlyTotal {
+                    month,
+                    currency: record.currency.clone(),
+                    sent: 0,
+                    received: 0,
+                    fees: 0,
+                    transactions: 0,
+                });
+            if record.from == *owner && record.to != *owner {
+                total.sent += record.amount;
+            } else {
+                total.received += record.amount;
+            }
+            // A transaction moving several mints pays its fee once
+            if record.from == *owner && fee_counted.insert(record.signature) {
+                total.fees += record.fee;
+            }
+            total.transactions += 1;
+        }
+        Ok(totals.into_values().collect())
+    }
+
+    /// Known holes in `owner`'s history, oldest recorded first
+    pub fn gaps(&self, owner: &Pubkey) -> Result<Vec<HistoryGap>> {
+        let db = self.lock()?;
+        db.query(
+            "SELECT before_signature, after_slot, reason, recorded_at FROM gaps WHERE owner = ? ORDER BY id",
+            &[owner_key(owner)],
+        )?
+        .iter()
+        .map(|row| {
+            Ok(HistoryGap {
+                before: row[0].as_str().map(parse_signature).transpose()?,
+                after_slot: row[1].as_i64().unwrap_or(0) as u64,
+                reason: row[2].as_str().unwrap_or_default().to_string(),
+                recorded_at: row[3].as_i64().unwrap_or(0) as u64,
+            })
+        })
+        .collect()
+    }
+
+    /// Whether `owner`'s synced history reaches back to the account's creation
+    pub fn completeness(&self, owner: &Pubkey) -> Result<HistoryCompleteness> {
+        let db = self.lock()?;
+        owner_completeness(&db, owner)
+    }
+
+    /// Unix time of the last completed sync for `owner`, if any
+    pub fn last_synced_at(&self, owner: &Pubkey) -> Result<Option<u64>> {
+        let db = self.lock()?;
+        let synced = single_i64(&db, "SELECT last_synced_at FROM owners WHERE owner = ?", &[owner_key(owner)])?;
+        Ok(synced.map(|t| t as u64).filter(|t| *t > 0))
+    }
+
+    /// Signatures of `owner`'s records not yet seen finalized, oldest first
+    pub(crate) fn unfinalized(&self, owner: &Pubkey) -> Result<Vec<(Signature, u64)>> {
+        let db = self.lock()?;
+        let rows = db.query(
+            &format!(
+                "SELECT signature, slot FROM records WHERE owner = ? AND NOT EXISTS
+                    (SELECT 1 FROM finalized WHERE finalized.owner = records.owner AND finalized.signature = records.signature)
+                 {}",
+                OLDEST_FIRST
+            ),
+            &[owner_key(owner)],
+        )?;
+        let mut listed = HashSet::new();
+        let mut unfinalized = Vec::new();
+        for row in rows {
+            let signature = parse_signature(row[0].as_str().unwrap_or_default())?;
+            if listed.insert(signature) {
+                unfinalized.push((signature, row[1].as_i64().unwrap_or(0) as u64));
+            }
+        }
+        Ok(unfinalized)
+    }
+
+    pub(crate) fn mark_finalized(&self, owner: &Pubkey, signatures: &[Signature]) -> Result<()> {
+        if signatures.is_empty() {
+            return Ok(());
+        }
+        let db = self.lock()?;
+        db.transaction(|db| {
+            if single_i64(db, "SELECT COUNT(*) FROM owners WHERE owner = ?", &[owner_key(owner)])? == Some(0) {
+                return Ok(());
+            }
+            for signature in signatures {
+                db.execute(
+                    "INSERT OR IGNORE INTO finalized (owner, signature) VALUES (?, ?)",
+                    &[owner_key(owner), signature.to_string().into()],
+                )?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Drop every record of a transaction the chain no longer has, returning what was removed
+    ///
+    /// The signature is forgotten too, so a later sync picks the transaction up again if it
+    /// lands on the surviving fork after all.
+    pub(crate) fn invalidate(&self, owner: &Pubkey, signature: &Signature) -> Result<Vec<TransactionRecord>> {
+        let db = self.lock()?;
+        db.transaction(|db| {
+            let params = [owner_key(owner), signature.to_string().into()];
+            let removed = read_records(
+                db,
+                &format!("SELECT record FROM records WHERE owner = ? AND signature = ? {}", NEWEST_FIRST),
+                &params,
+            )?;
+            db.execute("DELETE FROM records WHERE owner = ? AND signature = ?", &params)?;
+            db.execute("DELETE FROM seen WHERE owner = ? AND signature = ?", &params)?;
+            let newest = db
+                .query("SELECT newest_signature FROM owners WHERE owner = ?", &[owner_key(owner)])?
+                .first()
+                .and_then(|row| row[0].as_str().map(str::to_string));
+            if newest.as_deref() == Some(signature.to_string().as_str()) {
+                // Syncing `until` a signature the node has never heard of would re-read everything
+                let replacement = db
+                    .query(&format!("SELECT signature FROM records WHERE owner = ? {} LIMIT 1", NEWEST_FIRST), &[owner_key(owner)])?
+                    .first()
+                    .and_then(|row| row[0].as_str().map(str::to_string));
+                db.execute(
+                    "UPDATE owners SET newest_signature = ? WHERE owner = ?",
+                    &[replacement.into(), owner_key(owner)],
+                )?;
+            }
+            Ok(removed)
+        })
+    }
+
+    /// Store `records` (newest first) for `owner` as if a sync had found them
+    #[cfg(test)]
+    pub(crate) fn insert_records(&self, owner: &Pubkey, records: Vec<TransactionRecord>) -> Result<()> {
+        let batch = SyncBatch {
+            newest: records.first().map(|record| record.signature),
+            processed: records.iter().map(|record| record.signature).collect(),
+            records,
+            gaps: Vec::new(),
+            completeness: None,
+        };
+        self.store_sync(owner, batch).map(|_| ())
+    }
+
+    /// Where the next sync of `owner` starts, and every signature it already processed
+    fn sync_start(&self, owner: &Pubkey) -> Result<(Option<Signature>, HashSet<Signature>)> {
+        let db = self.lock()?;
+        let until = db
+            .query("SELECT newest_signature FROM owners WHERE owner = ?", &[owner_key(owner)])?
+            .first()
+            .and_then(|row| row[0].as_str().map(parse_signature))
+            .transpose()?;
+        let seen = db
+            .query("SELECT signature FROM seen WHERE owner = ?", &[owner_key(owner)])?
+            .iter()
+            .map(|row| parse_signature(row[0].as_str().unwrap_or_default()))
+            .collect::<Result<_>>()?;
+        Ok((until, seen))
+    }
+
+    /// Write what a sync found, returning the owner's completeness afterwards
+    fn store_sync(&self, owner: &Pubkey, batch: SyncBatch) -> Result<HistoryCompleteness> {
+        let db = self.lock()?;
+        db.transaction(|db| {
+            db.execute(
+                "INSERT OR IGNORE INTO owners (owner, completeness) VALUES (?, ?)",
+                &[owner_key(owner), serde_json::to_string(&HistoryCompleteness::default())?.into()],
+            )?;
+            if let Some(newest) = batch.newest {
+                db.execute("UPDATE owners SET newest_signature = ? WHERE owner = ?", &[newest.to_string().into(), owner_key(owner)])?;
+            }
+            for signature in &batch.processed {
+                db.execute("INSERT OR IGNORE INTO seen (owner, signature) VALUES (?, ?)", &[owner_key(owner), signature.to_string().into()])?;
+            }
+            if let Some(completeness) = batch.completeness {
+                set_completeness(db, owner, completeness)?;
+            }
+            // Oldest first, so the rowid order among equal slots follows the history
+            for record in batch.records.iter().rev() {
+                insert_record(db, owner, record)?;
+            }
+            for gap in &batch.gaps {
+                insert_gap(db, owner, gap)?;
+            }
+            db.execute("UPDATE owners SET last_synced_at = ? WHERE owner = ?", &[SqlValue::try_from(now_secs())?, owner_key(owner)])?;
+            owner_completeness(db, owner)
+        })
+    }
+
+    fn lock(&self) -> Result<std::sync::MutexGuard<'_, Connection>> {
+        self.db.lock().map_err(|_| anyhow!("Local ledger lock poisoned"))
+    }
+}
+
+/// Bring the database from `version` up to `LEDGER_SCHEMA_VERSION`
+///
+/// Version 0 is a new, empty database. Each future schema change adds an arm taking the
+/// database from the version before it.
+fn migrate(db: &Connection, version: u32) -> Result<()> {
+    for from in version..LEDGER_SCHEMA_VERSION {
+        match from {
+            0 => db.execute_batch(
+                "CREATE TABLE owners (
+                    owner TEXT PRIMARY KEY,
+                    newest_signature TEXT,
+                    last_synced_at INTEGER NOT NULL DEFAULT 0,
+                    completeness TEXT NOT NULL
+                );
+                CREATE TABLE seen (owner TEXT NOT NULL, signature TEXT NOT NULL, PRIMARY KEY (owner, signature));
+                CREATE TABLE finalized (owner TEXT NOT NULL, signature TEXT NOT NULL, PRIMARY KEY (owner, signature));
+                CREATE TABLE records (
+                    owner TEXT NOT NULL,
+                    signature TEXT NOT NULL,
+                    mint TEXT NOT NULL,
+                    slot INTEGER NOT NULL,
+                    timestamp INTEGER NOT NULL,
+                    record TEXT NOT NULL,
+                    PRIMARY KEY (owner, signature, mint)
+                );
+                CREATE INDEX records_newest ON records (owner, slot DESC, timestamp DESC);
+                CREATE TABLE gaps (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    owner TEXT NOT NULL,
+                    before_signature TEXT,
+                    after_slot INTEGER NOT NULL,
+                    reason TEXT NOT NULL,
+                    recorded_at INTEGER NOT NULL
+                );",
+            )?,
+            other => return Err(anyhow!("No migration from local ledger schema version {}", other)),
+        }
+    }
+    db.execute_batch(&format!("PRAGMA user_version = {}", LEDGER_SCHEMA_VERSION))
+}
+
+/// Delete all but `owner`'s newest `max` records, leaving a gap where they were
+fn drop_oldest_records(db: &Connection, owner: &str, max: usize) -> Result<usize> {
+    let params = [SqlValue::from(owner), (max as i64).into()];
+    let dropped = db.query(
+        &format!("SELECT signature, slot FROM records WHERE owner = ? {} LIMIT -1 OFFSET ?", NEWEST_FIRST),
+        &params,
+    )?;
+    let Some(newest_dropped) = dropped.first() else {
+        return Ok(0);
+    };
+    let newest_dropped_slot = newest_dropped[1].as_i64().unwrap_or(0) as u64;
+    let oldest_kept_slot = match max {
+        0 => newest_dropped_slot,
+        max => single_i64(
+            db,
+            &format!("SELECT slot FROM records WHERE owner = ? {} LIMIT 1 OFFSET ?", NEWEST_FIRST),
+            &[owner.into(), (max as i64 - 1).into()],
+        )?
+        .map_or(newest_dropped_slot, |slot| slot as u64),
+    };
+    db.execute(
+        &format!(
+            "DELETE FROM records WHERE rowid IN (SELECT rowid FROM records WHERE owner = ? {} LIMIT -1 OFFSET ?)",
+            NEWEST_FIRST
+        ),
+        &params,
+    )?;
+    let owner_key = Pubkey::from_str(owner).map_err(|e| anyhow!("Corrupt owner {} in local ledger: {}", owner, e))?;
+    insert_gap(
+        db,
+        &owner_key,
+        &HistoryGap {
+            before: Some(parse_signature(newest_dropped[0].as_str().unwrap_or_default())?),
+            after_slot: oldest_kept_slot,
+            reason: format!("Compacted: {} older records dropped to keep {} per owner", dropped.len(), max),
+            recorded_at: now_secs(),
+        },
+    )?;
+    set_completeness(db, &owner_key, HistoryCompleteness::TruncatedAt(oldest_kept_slot))?;
+    Ok(dropped.len())
+}
+
+fn insert_record(db: &Connection, owner: &Pubkey, record: &TransactionRecord) -> Result<()> {
+    db.execute(
+        "INSERT OR IGNORE INTO records (owner, signature, mint, slot, timestamp, record) VALUES (?, ?, ?, ?, ?, ?)",
+        &[
+            owner_key(owner),
+            record.signature.to_string().into(),
+            record.currency.mint().map(|mint| mint.to_string()).unwrap_or_default().into(),
+            SqlValue::try_from(record.slot)?,
+            SqlValue::try_from(record.timestamp)?,
+            serde_json::to_string(record)?.into(),
+        ],
+    )?;
+    Ok(())
+}
+
+fn insert_gap(db: &Connection, owner: &Pubkey, gap: &HistoryGap) -> Result<()> {
+    db.execute(
+        "INSERT INTO gaps (owner, before_signature, after_slot, reason, recorded_at) VALUES (?, ?, ?, ?, ?)",
+        &[
+            owner_key(owner),
+            gap.before.map(|signature| signature.to_string()).into(),
+            SqlValue::try_from(gap.after_slot)?,
+            gap.reason.as_str().into(),
+            SqlValue::try_from(gap.recorded_at)?,
+        ],
+    )?;
+    Ok(())
+}
+
+fn set_completeness(db: &Connection, owner: &Pubkey, completeness: HistoryCompleteness) -> Result<()> {
+    db.execute(
+        "UPDATE owners SET completeness = ? WHERE owner = ?",
+        &[serde_json::to_string(&completeness)?.into(), owner_key(owner)],
+    )?;
+    Ok(())
+}
+
+fn owner_completeness(db: &Connection, owner: &Pubkey) -> Result<HistoryCompleteness> {
+    let stored = db.query("SELECT completeness FROM owners WHERE owner = ?", &[owner_key(owner)])?;
+    match stored.first().and_then(|row| row[0].as_str()) {
+        Some(text) => serde_json::from_str(text).map_err(|e| anyhow!("Corrupt completeness for {}: {}", owner, e)),
+        None => Ok(HistoryCompleteness::default()),
+    }
+}
+
+fn read_records(db: &Connection, sql: &str, params: &[SqlValue]) -> Result<Vec<TransactionRecord>> {
+    db.query(sql, params)?
+        .iter()
+        .map(|row| {
+            let text = row[0].as_str().unwrap_or_default();
+            serde_json::from_str(text).map_err(|e| anyhow!("Corrupt record in local ledger: {}", e))
+        })
+        .collect()
+}
+
+fn single_i64(db: &Connection, sql: &str, params: &[SqlValue]) -> Result<Option<i64>> {
+    Ok(db.query(sql, params)?.first().and_then(|row| row.first()).and_then(SqlValue::as_i64))
+}
+
+fn owner_key(owner: &Pubkey) -> SqlValue {
+    owner.to_string().into()
+}
+
+fn parse_signature(text: &str) -> Result<Signature> {
+    Signature::from_str(text).map_err(|e| anyhow!("Corrupt signature {} in local ledger: {}", text, e))
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+fn month_of(timestamp: u64) -> String {
+    Utc.timestamp_opt(timestamp as i64, 0)
+        .single()
+        .map(|t| t.format("%Y-%m").to_string())
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+impl FinternetClient {
+    /// Pull `owner`'s signatures newer than the last sync into the ledger file at `db_path`
+    pub async fn sync_ledger(&self, owner: &Pubkey, db_path: &Path) -> Result<SyncReport> {
+        let ledger = LocalLedger::open(db_path)?;
+        self.sync_local_ledger(&ledger, owner).await
+    }
+
+    /// `sync_ledger` against an already-open ledger, e.g. one shared with a server
+    ///
+    /// Re-running is idempotent: signatures already stored are skipped. History the node can no
+    /// longer serve, or that lies beyond this run's page budget, is recorded as a gap.
+    #[tracing::instrument(skip_all, fields(wallet = %readable(owner), db = %ledger.path.display(), rpc = %self.rpc_endpoint()))]
+    pub async fn sync_local_ledger(&self, ledger: &LocalLedger, owner: &Pubkey) -> Result<SyncReport> {
+        let (until, seen) = ledger.sync_start(owner)?;
+
+        // Signatures come newest first; stop at the newest one already synced
+        let history = self.iter_history(owner, HistoryFilter { until, ..HistoryFilter::default() });
+        futures::pin_mut!(history);
+        let budget = MAX_SYNC_PAGES * SIGNATURE_PAGE_LIMIT;
+        let mut report = SyncReport::default();
+        let mut records = Vec::new();
+        let mut processed = Vec::new();
+        let mut scanned = 0;
+        let mut newest = None;
+        let mut oldest_slot = None;
+        let mut oldest_completeness = None;
+        let mut unsynced = None;
+        self.report_progress(|| ProgressEvent::Started { operation: Operation::SyncLedger, total: None });
+        while let Some(entry) = history.next().await {
+            let entry = entry?;
+            if scanned == budget {
+                unsynced = Some(entry.signature);
+                break;
+            }
+            scanned += 1;
+            newest.get_or_insert(entry.signature);
+            oldest_slot = Some(entry.slot);
+            if entry.oldest_available {
+                oldest_completeness = Some(self.history_completeness(owner, &entry));
+            }
+            self.report_progress(|| ProgressEvent::Scanning { operation: Operation::SyncLedger, signatures_found: scanned });
+
+            let signature = entry.signature;
+            if seen.contains(&signature) {
+                continue;
+            }
+            self.report_progress(|| ProgressEvent::Processing { operation: Operation::SyncLedger, signature });
+            report.new_signatures += 1;
+            match &entry.transaction {
+                // Failed on chain, so it moved nothing
+                None => processed.push(signature),
+                Some(Ok(_)) => {
+                    records.extend(entry.transfer_records(owner));
+                    processed.push(signature);
+                }
+                Some(Err(e)) => {
+                    // Pruned or unreachable: say so rather than pretend the history is complete
+                    tracing::warn!("Transaction {} unavailable, recording gap: {}", signature, e);
+                    report.gaps.push(HistoryGap {
+                        before: Some(signature),
+                        after_slot: entry.slot,
+                        reason: format!("Transaction could not be fetched: {}", e),
+                        recorded_at: now_secs(),
+                    });
+                }
+            }
+        }
+        self.report_progress(|| ProgressEvent::Finished { operation: Operation::SyncLedger, completed: scanned });
+
+        // Only a fresh sync sees the oldest end of the history
+        let mut completeness = None;
+        if unsynced.is_some() {
+            if until.is_none() {
+                completeness = Some(HistoryCompleteness::Unknown);
+            }
+            report.gaps.push(HistoryGap {
+                before: unsynced,
+                after_slot: oldest_slot.unwrap_or(0),
+                reason: format!(
+                    "Stopped after {} signatures; older {}history was not synced",
+                    scanned,
+                    if until.is_some() { "unsynced " } else { "" }
+                ),
+                recorded_at: now_secs(),
+            });
+        } else if until.is_none() {
+            // A fresh sync that ran out of signatures has everything the node still serves
+            let first_available = self.client.get_first_available_block().unwrap_or(0);
+            let found = oldest_completeness.unwrap_or(if scanned == 0 && first_available == 0 {
+                HistoryCompleteness::Complete
+            } else {
+                HistoryCompleteness::Unknown
+            });
+            completeness = Some(found);
+            // Reaching the first funding means nothing older was pruned
+            if first_available > 0 && !found.is_complete() {
+                report.gaps.push(HistoryGap {
+                    before: None,
+                    after_slot: first_available,
+                    reason: format!("RPC node serves no history before slot {}", first_available),
+                    recorded_at: now_secs(),
+                });
+            }
+        }
+        report.new_records = records.len();
+        records.sort_by(|a, b| b.slot.cmp(&a.slot).then(b.timestamp.cmp(&a.timestamp)));
+        report.completeness = ledger.store_sync(
+            owner,
+            SyncBatch { newest, processed, records, gaps: report.gaps.clone(), completeness },
+        )?;
+
+        tracing::info!(
+            "Ledger sync: {} new signatures, {} new records, {} gaps, history {}",
+            report.new_signatures,
+            report.new_records,
+            report.gaps.len(),
+            report.completeness
+        );
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_cluster::{MockCluster, TempDir};
+    use solana_sdk::signature::{Keypair, Signer};
+    use std::collections::HashMap;
+
+    /// 2024-06-15 and 2024-07-15, UTC
+    const JUNE: u64 = 1_718_409_600;
+    const JULY: u64 = 1_721_001_600;
+
+    fn record(owner: &Pubkey, incoming: bool, mint: &Pubkey, amount: u64, slot: u64, timestamp: u64) -> TransactionRecord {
+        let counterparty = Pubkey::new_unique();
+        let (from, to) = if incoming { (counterparty, *owner) } else { (*owner, counterparty) };
+        TransactionRecord {
+            signature: Signature::new_unique(),
+            from,
+            to,
+            amount,
+            currency: Currency::spl(*mint, 6),
+            timestamp,
+            memo: None,
+            fee: 5_000,
+            slot,
+            memo_fields: HashMap::new(),
+        }
+    }
+
+    fn batch(records: Vec<TransactionRecord>) -> SyncBatch {
+        SyncBatch {
+            newest: records.first().map(|record| record.signature),
+            processed: records.iter().map(|record| record.signature).collect(),
+            records,
+            gaps: Vec::new(),
+            completeness: Some(HistoryCompleteness::Complete),
+        }
+    }
+
+    fn signatures(records: &[TransactionRecord]) -> Vec<Signature> {
+        records.iter().map(|record| record.signature).collect()
+    }
+
+    #[test]
+    fn synced_records_are_queried_from_the_database_after_reopening() {
+        let dir = TempDir::new("local-ledger");
+        let path = dir.join("ledger.db");
+        let (owner, usdc, other) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        // Newest first, as a sync hands them over
+        let records = vec![
+            record(&owner, false, &usdc, 4_000_000, 300, JULY),
+            record(&owner, true, &other, 9, 200, JUNE + 60),
+            record(&owner, true, &usdc, 10_000_000, 100, JUNE),
+        ];
+        {
+            let ledger = LocalLedger::open(&path).unwrap();
+            ledger.store_sync(&owner, batch(records.clone())).unwrap();
+        }
+        assert!(crate::state_bundle::is_sqlite_database(&path));
+
+        let ledger = LocalLedger::open(&path).unwrap();
+        assert_eq!(signatures(&ledger.query_history(&owner, 10, 0).unwrap()), signatures(&records));
+        assert_eq!(signatures(&ledger.query_history(&owner, 1, 1).unwrap()), vec![records[1].signature]);
+        assert_eq!(signatures(&ledger.query_by_mint(&owner, &usdc).unwrap()), vec![records[0].signature, records[2].signature]);
+        assert_eq!(signatures(&ledger.query_between(&owner, JUNE, JUNE + 60).unwrap()), vec![records[2].signature, records[1].signature]);
+
+        let totals: Vec<_> = ledger
+            .monthly_totals(&owner, Some(&usdc))
+            .unwrap()
+            .into_iter()
+            .map(|total| (total.month, total.sent, total.received, total.fees))
+            .collect();
+        assert_eq!(totals, vec![("2024-06".to_string(), 0, 10_000_000, 0), ("2024-07".to_string(), 4_000_000, 0, 5_000)]);
+        assert_eq!(ledger.completeness(&owner).unwrap(), HistoryCompleteness::Complete);
+        assert!(ledger.last_synced_at(&owner).unwrap().is_some());
+        assert_eq!(ledger.usage().unwrap().entries, 3);
+        assert!(ledger.query_history(&Pubkey::new_unique(), 10, 0).unwrap().is_empty());
+    }
+
+    #[test]
+    fn storing_the_same_sync_twice_adds_nothing() {
+        let dir = TempDir::new("local-ledger");
+        let ledger = LocalLedger::open(&dir.join("ledger.db")).unwrap();
+        let (owner, mint) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let records = vec![record(&owner, true, &mint, 1, 20, JUNE), record(&owner, true, &mint, 2, 10, JUNE)];
+
+        ledger.store_sync(&owner, batch(records.clone())).unwrap();
+        ledger.store_sync(&owner, batch(records.clone())).unwrap();
+
+        assert_eq!(signatures(&ledger.query_history(&owner, 10, 0).unwrap()), signatures(&records));
+        let (until, seen) = ledger.sync_start(&owner).unwrap();
+        assert_eq!(until, Some(records[0].signature));
+        assert_eq!(seen, signatures(&records).into_iter().collect());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn a_resync_against_the_cluster_skips_what_it_already_processed() {
+        let dir = TempDir::new("local-ledger");
+        let cluster = MockCluster::new();
+        let client = cluster.default_client();
+        let wallet = Keypair::new();
+        cluster.set_balance(wallet.pubkey(), 1_000_000_000);
+        for entry in ["first", "second"] {
+            client.write_ledger_entry(&wallet, entry).await.unwrap();
+            cluster.advance_blockhash();
+        }
+
+        let path = dir.join("ledger.db");
+        let first = client.sync_ledger(&wallet.pubkey(), &path).await.unwrap();
+        assert_eq!(first.new_signatures, 2);
+        let again = client.sync_ledger(&wallet.pubkey(), &path).await.unwrap();
+        assert_eq!(again.new_signatures, 0);
+        assert_eq!(LocalLedger::open(&path).unwrap().sync_start(&wallet.pubkey()).unwrap().1.len(), 2);
+    }
+
+    #[test]
+    fn an_invalidated_record_is_forgotten_and_finality_is_tracked_per_signature() {
+        let dir = TempDir::new("local-ledger");
+        let ledger = LocalLedger::open(&dir.join("ledger.db")).unwrap();
+        let (owner, mint) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let records: Vec<_> = [30, 20, 10].into_iter().map(|slot| record(&owner, true, &mint, 1, slot, JUNE)).collect();
+        ledger.store_sync(&owner, batch(records.clone())).unwrap();
+
+        ledger.mark_finalized(&owner, &[records[2].signature]).unwrap();
+        let unfinalized: Vec<_> = ledger.unfinalized(&owner).unwrap().into_iter().map(|(signature, _)| signature).collect();
+        assert_eq!(unfinalized, vec![records[1].signature, records[0].signature]);
+
+        let removed = ledger.invalidate(&owner, &records[0].signature).unwrap();
+        assert_eq!(signatures(&removed), vec![records[0].signature]);
+        let (until, seen) = ledger.sync_start(&owner).unwrap();
+        assert_eq!(until, Some(records[1].signature));
+        assert!(!seen.contains(&records[0].signature));
+    }
+
+    #[test]
+    fn compaction_keeps_the_newest_records_and_marks_the_history_truncated() {
+        let dir = TempDir::new("local-ledger");
+        let ledger = LocalLedger::open(&dir.join("ledger.db")).unwrap();
+        let (owner, mint) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let records: Vec<_> = (0..5).rev().map(|n| record(&owner, true, &mint, n, 100 + n * 10, JUNE + n)).collect();
+        let mut first = batch(records.clone());
+        let repeated = HistoryGap { before: None, after_slot: 90, reason: "pruned".to_string(), recorded_at: JUNE };
+        first.gaps = vec![repeated.clone(), repeated];
+        first.processed.push(Signature::new_unique());
+        ledger.store_sync(&owner, first).unwrap();
+
+        let report = ledger.compact(&LedgerRetention { max_records_per_owner: Some(2) }).unwrap();
+        assert_eq!((report.records_dropped, report.signatures_forgotten, report.duplicate_gaps), (3, 4, 1));
+        assert_eq!(signatures(&ledger.query_history(&owner, 10, 0).unwrap()), signatures(&records[..2]));
+        assert_eq!(ledger.completeness(&owner).unwrap(), HistoryCompleteness::TruncatedAt(130));
+        let gaps = ledger.gaps(&owner).unwrap();
+        assert_eq!(gaps.len(), 2);
+        assert_eq!((gaps[1].before, gaps[1].after_slot), (Some(records[2].signature), 130));
+
+        let again = ledger.compact(&LedgerRetention { max_records_per_owner: Some(2) }).unwrap();
+        assert_eq!((again.records_dropped, again.signatures_forgotten, again.duplicate_gaps), (0, 0, 0));
+    }
+
+    #[test]
+    fn compaction_gives_freed_pages_back_to_the_file_system() {
+        let dir = TempDir::new("local-ledger");
+        let path = dir.join("ledger.db");
+        let ledger = LocalLedger::open(&path).unwrap();
+        let (owner, mint) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let records: Vec<_> = (0..2_000).rev().map(|n| record(&owner, true, &mint, n, n, JUNE + n)).collect();
+        ledger.store_sync(&owner, batch(records)).unwrap();
+        let before = file_bytes(&path);
+
+        let report = ledger.compact(&LedgerRetention { max_records_per_owner: Some(10) }).unwrap();
+        assert_eq!(report.records_dropped, 1_990);
+        assert_eq!(report.bytes_before, before);
+        assert_eq!(report.bytes_after, file_bytes(&path));
+        assert!(report.bytes_after < before / 10, "{} -> {}", before, report.bytes_after);
+        assert_eq!(ledger.query_history(&owner, 100, 0).unwrap().len(), 10);
+
+        let idle = ledger.compact(&LedgerRetention::default()).unwrap();
+        assert_eq!(idle.bytes_after, report.bytes_after);
+    }
+}
@@ -0,0 +1,481 @@
+//! A local validator ready for development: programs, a funded wallet, and a USDC stand-in
+//!
+//! `Localnet::up` starts `solana-test-validator` with the Metaplex Token Metadata program,
+//! deployed from a `.so` file when one is given and cloned from mainnet otherwise. The SPL Memo,
+//! Token, Token-2022, and Associated Token Account programs are built into the test validator;
+//! `up` only checks they are there. It then funds the workspace wallet, creates a 6-decimal mint
+//! standing in for USDC, registers it with `mint_registry`, and writes the `localnet` profile.
+//! Every step finds what an earlier run left in place, so `up` against a running validator only
+//! tops up. The wallet and mint keypairs outlive `reset`, so the profile stays valid across one.
+
+use crate::instructions::mint_rent_lamports;
+use crate::mint_registry::{self, Network};
+use crate::profile::{Profile, Profiles, DEFAULT_PROFILES_PATH};
+use crate::{Currency, FinternetClient, FinternetConfig};
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::program_pack::Pack;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{Keypair, Signature};
+use solana_sdk::signer::Signer;
+use solana_sdk::system_instruction;
+use solana_sdk::transaction::Transaction;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+/// Name of the profile `up` writes
+pub const PROFILE_NAME: &str = "localnet";
+/// Clone source for programs not given as `.so` files
+pub const MAINNET_RPC_URL: &str = "https://api.mainnet-beta.solana.com";
+/// Marks a directory as a localnet workspace; `state_bundle` skips such directories
+pub const STATE_FILE: &str = "localnet.json";
+
+const VALIDATOR_BINARY: &str = "solana-test-validator";
+const USDC_DECIMALS: u8 = 6;
+const AIRDROP_CONFIRM_SECS: u64 = 30;
+
+#[derive(Debug, Clone)]
+pub struct LocalnetOptions {
+    /// Ledger, log, state file, wallet, and mint keypair all live here
+    pub dir: PathBuf,
+    pub rpc_port: u16,
+    /// Token Metadata program to deploy; cloned from `clone_url` when unset
+    pub token_metadata_program: Option<PathBuf>,
+    pub clone_url: String,
+    /// SOL the wallet is topped up to
+    pub wallet_lamports: u64,
+    /// USDC stand-in the wallet is topped up to, in base units
+    pub usdc_balance: u64,
+    pub profiles_path: PathBuf,
+    /// How long the validator may take to answer after starting
+    pub startup_timeout: Duration,
+}
+
+impl Default for LocalnetOptions {
+    fn default() -> Self {
+        Self {
+            dir: PathBuf::from(".finternet/localnet"),
+            rpc_port: 8899,
+            token_metadata_program: None,
+            clone_url: MAINNET_RPC_URL.to_string(),
+            wallet_lamports: 100_000_000_000,
+            usdc_balance: 1_000_000_000_000,
+            profiles_path: PathBuf::from(DEFAULT_PROFILES_PATH),
+            startup_timeout: Duration::from_secs(60),
+        }
+    }
+}
+
+/// What `up` left running, kept in the workspace's `localnet.json`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LocalnetState {
+    pub rpc_url: String,
+    /// `None` for a validator `up` found already running and did not start
+    pub pid: Option<u32>,
+    pub wallet: String,
+    pub usdc_mint: String,
+}
+
+/// What `up` did, for the CLI to print
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LocalnetReport {
+    pub state: LocalnetState,
+    /// Whether this run started the validator rather than finding it running
+    pub started: bool,
+    pub wallet_lamports: u64,
+    pub usdc_balance: u64,
+    /// Transactions this run sent: airdrop, then mint creation or top-up
+    pub signatures: Vec<String>,
+    pub profile: String,
+    pub profiles_path: PathBuf,
+}
+
+pub struct Localnet {
+    options: LocalnetOptions,
+}
+
+impl Localnet {
+    pub fn new(options: LocalnetOptions) -> Self {
+        Self { options }
+    }
+
+    pub fn rpc_url(&self) -> String {
+        format!("http://127.0.0.1:{}", self.options.rpc_port)
+    }
+
+    pub fn ledger_dir(&self) -> PathBuf {
+        self.options.dir.join("ledger")
+    }
+
+    pub fn wallet_path(&self) -> PathBuf {
+        self.options.dir.join("wallet.json")
+    }
+
+    fn mint_keypair_path(&self) -> PathBuf {
+        self.options.dir.join("usdc-mint.json")
+    }
+
+    fn state_path(&self) -> PathBuf {
+        self.options.dir.join(STATE_FILE)
+    }
+
+    fn log_path(&self) -> PathBuf {
+        self.options.dir.join("validator.log")
+    }
+
+    /// Whether a validator answers on the configured port
+    pub fn is_running(&self) -> bool {
+        RpcClient::new(self.rpc_url()).get_health().is_ok()
+    }
+
+    /// Start the validator unless one is running, then fund the wallet, create the USDC stand-in,
+    /// and write the profile
+    pub async fn up(&self) -> Result<LocalnetReport> {
+        fs::create_dir_all(&self.options.dir)?;
+        let wallet = load_or_create_keypair(&self.wallet_path())?;
+        let mint = load_or_create_keypair(&self.mint_keypair_path())?;
+
+        let previous = self.load_state()?;
+        let (pid, started) = if self.is_running() {
+            (previous.and_then(|state| state.pid), false)
+        } else {
+            (Some(self.start_validator().await?), true)
+        };
+        let state = LocalnetState {
+            rpc_url: self.rpc_url(),
+            pid,
+            wallet: wallet.pubkey().to_string(),
+            usdc_mint: mint.pubkey().to_string(),
+        };
+        self.save_state(&state)?;
+
+        let client = FinternetClient::new(FinternetConfig {
+            rpc_url: self.rpc_url(),
+            ..FinternetConfig::default()
+        });
+        check_programs(&client)?;
+        if client.network()? != Some(Network::Localnet) {
+            return Err(anyhow!("{} does not serve a local cluster", self.rpc_url()));
+        }
+
+        let mut signatures = Vec::new();
+        let wallet_lamports = fund_wallet(&client, &wallet.pubkey(), self.options.wallet_lamports, &mut signatures).await?;
+        let usdc_balance = ensure_usdc(&client, &wallet, &mint, self.options.usdc_balance, &mut signatures).await?;
+        mint_registry::register_localnet_mint(mint_registry::USDC, mint.pubkey());
+
+        let mut profiles = Profiles::load(&self.options.profiles_path)?;
+        profiles.set(
+            PROFILE_NAME,
+            Profile {
+                rpc_url: self.rpc_url(),
+                wallet: Some(self.wallet_path()),
+                network: Some(Network::Localnet),
+                usdc_mint: Some(mint.pubkey().to_string()),
+            },
+        );
+        profiles.save(&self.options.profiles_path)?;
+        tracing::info!("Localnet ready at {}; profile '{}' written", self.rpc_url(), PROFILE_NAME);
+
+        Ok(LocalnetReport {
+            state,
+            started,
+            wallet_lamports,
+            usdc_balance,
+            signatures,
+            profile: PROFILE_NAME.to_string(),
+            profiles_path: self.options.profiles_path.clone(),
+        })
+    }
+
+    /// Stop the validator `up` started, returning its pid; `None` when none was recorded
+    ///
+    /// The ledger is kept, so the next `up` resumes it.
+    pub async fn down(&self) -> Result<Option<u32>> {
+        let Some(mut state) = self.load_state()? else {
+            return Ok(None);
+        };
+        let Some(pid) = state.pid.take() else {
+            return Ok(None);
+        };
+        let status = Command::new("kill").arg(pid.to_string()).status()?;
+        if !status.success() {
+            tracing::warn!("Validator {} was not running", pid);
+        }
+        let deadline = Instant::now() + self.options.startup_timeout;
+        while self.is_running() {
+            if Instant::now() > deadline {
+                return Err(anyhow!("Validator {} still answers on {} after being stopped", pid, self.rpc_url()));
+            }
+            tokio::time::sleep(Duration::from_millis(500)).await;
+        }
+        self.save_state(&state)?;
+        Ok(Some(pid))
+    }
+
+    /// Stop the validator, wipe its ledger, and bring it back up with fresh programs and mint
+    pub async fn reset(&self) -> Result<LocalnetReport> {
+        self.down().await?;
+        if self.is_running() {
+            return Err(anyhow!(
+                "A validator not started by localnet up answers on {}; stop it before resetting",
+                self.rpc_url()
+            ));
+        }
+        let ledger = self.ledger_dir();
+        if ledger.exists() {
+            fs::remove_dir_all(&ledger)?;
+        }
+        self.up().await
+    }
+
+    async fn start_validator(&self) -> Result<u32> {
+        let log = fs::File::create(self.log_path())?;
+        let mut command = Command::new(VALIDATOR_BINARY);
+        command
+            .arg("--ledger")
+            .arg(self.ledger_dir())
+            .arg("--rpc-port")
+            .arg(self.options.rpc_port.to_string())
+            .arg("--quiet");
+        match &self.options.token_metadata_program {
+            Some(program) => {
+                command.arg("--bpf-program").arg(mpl_token_metadata::ID.to_string()).arg(program);
+            }
+            None => {
+                command
+                    .arg("--clone-upgradeable-program")
+                    .arg(mpl_token_metadata::ID.to_string())
+                    .arg("--url")
+                    .arg(&self.options.clone_url);
+            }
+        }
+        let mut child = command
+            .stdin(Stdio::null())
+            .stdout(log.try_clone()?)
+            .stderr(log)
+            .spawn()
+            .map_err(|e| anyhow!("Could not start {} ({}); install the Solana CLI tools", VALIDATOR_BINARY, e))?;
+
+        let deadline = Instant::now() + self.options.startup_timeout;
+        while !self.is_running() {
+            if let Some(status) = child.try_wait()? {
+                return Err(anyhow!(
+                    "{} exited with {} before answering; see {}",
+                    VALIDATOR_BINARY,
+                    status,
+                    self.log_path().display()
+                ));
+            }
+            if Instant::now() > deadline {
+                let _ = child.kill();
+                return Err(anyhow!(
+                    "{} did not answer on {} within {:?}; see {}",
+                    VALIDATOR_BINARY,
+                    self.rpc_url(),
+                    self.options.startup_timeout,
+                    self.log_path().display()
+                ));
+            }
+            tokio::time::sleep(Duration::from_millis(500)).await;
+        }
+        Ok(child.id())
+    }
+
+    fn load_state(&self) -> Result<Option<LocalnetState>> {
+        let path = self.state_path();
+        if !path.exists() {
+            return Ok(None);
+        }
+        let data = fs::read_to_string(&path)?;
+        serde_json::from_str(&data)
+            .map(Some)
+            .map_err(|e| anyhow!("Corrupt localnet state {}: {}", path.display(), e))
+    }
+
+    fn save_state(&self, state: &LocalnetState) -> Result<()> {
+        let path = self.state_path();
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, serde_json::to_string_pretty(state)?)?;
+        fs::rename(&tmp, &path)?;
+        Ok(())
+    }
+}
+
+/// Whether `dir` is a localnet workspace, holding a ledger and keypairs rather than client state
+pub fn is_workspace(dir: &Path) -> bool {
+    dir.join(STATE_FILE).is_file()
+}
+
+fn load_or_create_keypair(path: &Path) -> Result<Keypair> {
+    if path.exists() {
+        return FinternetClient::load_wallet_from_file(path);
+    }
+    let keypair = FinternetClient::create_new_wallet();
+    FinternetClient::save_wallet_to_file(&keypair, path)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+    }
+    Ok(keypair)
+}
+
+/// Refuse a validator missing a program the SDK calls
+fn check_programs(client: &FinternetClient) -> Result<()> {
+    let programs = [
+        ("Token Metadata", mpl_token_metadata::ID),
+        ("SPL Memo", spl_memo::id()),
+        ("SPL Token", spl_token::id()),
+        ("SPL Token-2022", spl_token_2022::id()),
+        ("Associated Token Account", spl_associated_token_account::id()),
+    ];
+    for (name, program) in programs {
+        match client.client.get_account(&program) {
+            Ok(account) if account.executable => {}
+            _ => {
+                return Err(anyhow!(
+                    "The {} program ({}) is not deployed on {}; reset the localnet to deploy it",
+                    name,
+                    program,
+                    client.config.rpc_url
+                ))
+            }
+        }
+    }
+    Ok(())
+}
+
+async fn fund_wallet(client: &FinternetClient, wallet: &Pubkey, target: u64, signatures: &mut Vec<String>) -> Result<u64> {
+    let balance = client.client.get_balance(wallet)?;
+    if balance >= target {
+        return Ok(balance);
+    }
+    let airdrop: Signature = client.client.request_airdrop(wallet, target - balance)?;
+    for _ in 0..AIRDROP_CONFIRM_SECS {
+        if client.client.confirm_transaction(&airdrop)? {
+            break;
+        }
+        tokio::time::sleep(Duration::from_secs(1)).await;
+    }
+    signatures.push(airdrop.to_string());
+    let balance = client.client.get_balance(wallet)?;
+    if balance < target {
+        return Err(anyhow!(
+            "Wallet {} holds {} after airdrop {}, expected {}",
+            wallet,
+            Currency::Sol.format_amount(balance),
+            airdrop,
+            Currency::Sol.format_amount(target)
+        ));
+    }
+    Ok(balance)
+}
+
+/// Create the USDC stand-in with `wallet` as mint authority on first use, and top up its balance
+async fn ensure_usdc(
+    client: &FinternetClient,
+    wallet: &Keypair,
+    mint: &Keypair,
+    target: u64,
+    signatures: &mut Vec<String>,
+) -> Result<u64> {
+    let owner = wallet.pubkey();
+    let ata = spl_associated_token_account::get_associated_token_address(&owner, &mint.pubkey());
+    let mut instructions = Vec::new();
+    let mut signers = vec![wallet];
+    if client.client.get_account(&mint.pubkey()).is_err() {
+        instructions.push(system_instruction::create_account(
+            &owner,
+            &mint.pubkey(),
+            mint_rent_lamports(),
+            spl_token::state::Mint::LEN as u64,
+            &spl_token::id(),
+        ));
+        instructions.push(spl_token::instruction::initialize_mint2(
+            &spl_token::id(),
+            &mint.pubkey(),
+            &owner,
+            None,
+            USDC_DECIMALS,
+        )?);
+        signers.push(mint);
+    }
+    let balance = client.get_token_balance(&owner, &mint.pubkey()).await.unwrap_or(0);
+    if balance < target {
+        instructions.push(spl_associated_token_account::instruction::create_associated_token_account_idempotent(
+            &owner,
+            &owner,
+            &mint.pubkey(),
+            &spl_token::id(),
+        ));
+        instructions.push(spl_token::instruction::mint_to(
+            &spl_token::id(),
+            &mint.pubkey(),
+            &ata,
+            &owner,
+            &[],
+            target - balance,
+        )?);
+    }
+    if !instructions.is_empty() {
+        let mut transaction = Transaction::new_with_payer(&instructions, Some(&owner));
+        client.sign_with_fresh_blockhash(&mut transaction, &signers)?;
+        signatures.push(client.send_and_confirm_untracked("localnet_usdc", &transaction)?.to_string());
+    }
+    client.get_token_balance(&owner, &mint.pubkey()).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::demo::{DemoOptions, DEFAULT_DEMO_SEED, DEMO_TIMESTAMP};
+
+    fn client_for(report: &LocalnetReport, fixed_timestamp: Option<u64>) -> FinternetClient {
+        FinternetClient::new(FinternetConfig {
+            rpc_url: report.state.rpc_url.clone(),
+            fixed_timestamp,
+            ..FinternetConfig::default()
+        })
+    }
+
+    /// Brings up the localnet `finternet-cli localnet up` and the examples use, reusing one that
+    /// is already running, and runs the demo and a run of USDC payments against it. A validator
+    /// this test started is stopped again at the end:
+    /// `cargo test --lib localnet::tests::two_suites_share_one_localnet -- --ignored`
+    #[tokio::test(flavor = "multi_thread")]
+    #[ignore = "starts solana-test-validator, which clones Token Metadata from mainnet"]
+    async fn two_suites_share_one_localnet() {
+        let localnet = Localnet::new(LocalnetOptions::default());
+        let first = localnet.up().await.unwrap();
+
+        let client = client_for(&first, Some(DEMO_TIMESTAMP));
+        let options = DemoOptions::self_contained(DEFAULT_DEMO_SEED);
+        let demo = client.run_demo(&options.payer().unwrap(), &options).await.unwrap();
+        assert!(demo.passed, "{:#?}", demo.stages);
+
+        let second = localnet.up().await.unwrap();
+        assert!(!second.started);
+        assert_eq!(second.state, first.state);
+
+        let client = client_for(&second, None);
+        let wallet = FinternetClient::load_wallet_from_file(&localnet.wallet_path()).unwrap();
+        let usdc = client.usdc_mint().unwrap();
+        assert_eq!(usdc.to_string(), second.state.usdc_mint);
+        let recipients: Vec<Pubkey> = (0..3).map(|_| Keypair::new().pubkey()).collect();
+        let context = client.prepare_send_context(&usdc).unwrap();
+        client.prefetch_recipients(&context, &recipients).unwrap();
+        for (i, to) in recipients.iter().enumerate() {
+            client.send_payment_with_context(&context, &wallet, to, 1_000_000 + i as u64, None).await.unwrap();
+        }
+        for (i, to) in recipients.iter().enumerate() {
+            assert_eq!(client.get_token_balance(to, &usdc).await.unwrap(), 1_000_000 + i as u64);
+        }
+
+        if first.started {
+            assert_eq!(localnet.down().await.unwrap(), first.state.pid);
+        }
+    }
+}
@@ -0,0 +1,249 @@
+use crate::validation::MAX_MEMO_BYTES;
+use crate::FinternetClient;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{Keypair, Signature};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::str::FromStr;
+
+/// Why a memo template could not be parsed or filled in
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MemoTemplateError {
+    /// The template itself is malformed
+    InvalidTemplate(String),
+    MissingVariable(String),
+    /// A value contains text that would make the memo parse back differently
+    AmbiguousValue { name: String, value: String },
+    TooLong { bytes: usize },
+}
+
+impl MemoTemplateError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::InvalidTemplate(_) => "invalid_memo_template",
+            Self::MissingVariable(_) => "missing_memo_variable",
+            Self::AmbiguousValue { .. } => "ambiguous_memo_variable",
+            Self::TooLong { .. } => "memo_too_long",
+        }
+    }
+}
+
+impl fmt::Display for MemoTemplateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidTemplate(reason) => write!(f, "Invalid memo template: {}", reason),
+            Self::MissingVariable(name) => write!(f, "Memo template variable '{}' has no value", name),
+            Self::AmbiguousValue { name, value } => write!(
+                f,
+                "Value '{}' for memo variable '{}' contains the template's separator text and would not parse back",
+                value, name
+            ),
+            Self::TooLong { bytes } => {
+                write!(f, "Memo is {} bytes, at most {} allowed (memo program limit)", bytes, MAX_MEMO_BYTES)
+            }
+        }
+    }
+}
+
+impl std::error::Error for MemoTemplateError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Literal(String),
+    Placeholder(String),
+}
+
+/// A structured memo format such as `INV-{invoice_id}|{customer}|{period}`
+///
+/// Placeholder names are letters, digits, and `_`; write `{{` or `}}` for a literal brace.
+/// Two placeholders must be separated by literal text so the memo can be parsed back.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct MemoTemplate {
+    source: String,
+    segments: Vec<Segment>,
+}
+
+impl MemoTemplate {
+    pub fn parse(template: &str) -> Result<Self, MemoTemplateError> {
+        let invalid = |reason: String| MemoTemplateError::InvalidTemplate(reason);
+        let mut segments = Vec::new();
+        let mut literal = String::new();
+        let mut seen = HashSet::new();
+        let mut chars = template.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '}' => return Err(invalid("unmatched '}'; write '}}' for a literal brace".to_string())),
+                '{' => {
+                    let mut name = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) if c.is_ascii_alphanumeric() || c == '_' => name.push(c),
+                            Some(c) => return Err(invalid(format!("'{}' is not allowed in a placeholder name", c))),
+                            None => return Err(invalid(format!("placeholder '{{{}' is never closed", name))),
+                        }
+                    }
+                    if name.is_empty() {
+                        return Err(invalid("empty placeholder '{}'".to_string()));
+                    }
+                    if !seen.insert(name.clone()) {
+                        return Err(invalid(format!("placeholder '{}' appears more than once", name)));
+                    }
+                    if literal.is_empty() && matches!(segments.last(), Some(Segment::Placeholder(_))) {
+                        return Err(invalid(format!("placeholder '{}' directly follows another placeholder", name)));
+                    }
+                    if !literal.is_empty() {
+                        segments.push(Segment::Literal(std::mem::take(&mut literal)));
+                    }
+                    segments.push(Segment::Placeholder(name));
+                }
+                c => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            segments.push(Segment::Literal(literal));
+        }
+        Ok(Self { source: template.to_string(), segments })
+    }
+
+    /// Placeholder names in template order
+    pub fn variables(&self) -> Vec<&str> {
+        self.segments
+            .iter()
+            .filter_map(|segment| match segment {
+                Segment::Placeholder(name) => Some(name.as_str()),
+                Segment::Literal(_) => None,
+            })
+            .collect()
+    }
+
+    /// Fill in every placeholder from `vars`; unused entries in `vars` are ignored
+    ///
+    /// Fails if a value is missing, if the result would not extract back to the same values,
+    /// or if it exceeds the memo program's size limit.
+    pub fn render(&self, vars: &HashMap<String, String>) -> Result<String, MemoTemplateError> {
+        let mut memo = String::new();
+        for segment in &self.segments {
+            match segment {
+                Segment::Literal(text) => memo.push_str(text),
+                Segment::Placeholder(name) => {
+                    let value = vars.get(name).ok_or_else(|| MemoTemplateError::MissingVariable(name.clone()))?;
+                    memo.push_str(value);
+                }
+            }
+        }
+        if memo.len() > MAX_MEMO_BYTES {
+            return Err(MemoTemplateError::TooLong { bytes: memo.len() });
+        }
+        let extracted = self.extract(&memo).unwrap_or_default();
+        for name in self.variables() {
+            if extracted.get(name) != vars.get(name) {
+                return Err(MemoTemplateError::AmbiguousValue {
+                    name: name.to_string(),
+                    value: vars[name].clone(),
+                });
+            }
+        }
+        Ok(memo)
+    }
+
+    /// The placeholder values in `memo`, or `None` if it was not produced by this template
+    pub fn extract(&self, memo: &str) -> Option<HashMap<String, String>> {
+        let mut fields = HashMap::new();
+        let mut rest = memo;
+        let mut segments = self.segments.iter().peekable();
+        while let Some(segment) = segments.next() {
+            match segment {
+                Segment::Literal(text) => rest = rest.strip_prefix(text.as_str())?,
+                Segment::Placeholder(name) => {
+                    let end = match segments.peek() {
+                        Some(Segment::Literal(next)) => rest.find(next.as_str())?,
+                        _ => rest.len(),
+                    };
+                    fields.insert(name.clone(), rest[..end].to_string());
+                    rest = &rest[end..];
+                }
+            }
+        }
+        rest.is_empty().then_some(fields)
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.source
+    }
+}
+
+impl fmt::Display for MemoTemplate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+impl FromStr for MemoTemplate {
+    type Err = MemoTemplateError;
+
+    fn from_str(s: &str) -> Result<Self, MemoTemplateError> {
+        Self::parse(s)
+    }
+}
+
+impl TryFrom<String> for MemoTemplate {
+    type Error = MemoTemplateError;
+
+    fn try_from(s: String) -> Result<Self, MemoTemplateError> {
+        Self::parse(&s)
+    }
+}
+
+impl From<MemoTemplate> for String {
+    fn from(template: MemoTemplate) -> Self {
+        template.source
+    }
+}
+
+impl FinternetClient {
+    /// Attach `memo_fields` extracted with `template` to every record `get_transaction_history` returns
+    pub fn with_memo_template(mut self, template: MemoTemplate) -> Self {
+        self.memo_template = Some(template);
+        self
+    }
+
+    /// `send_payment` with the memo rendered from `template` and `vars`
+    ///
+    /// Nothing is sent if the template cannot be filled in; the error is a `MemoTemplateError`.
+    pub async fn send_templated_payment(
+        &self,
+        from_wallet: &Keypair,
+        to_pubkey: &Pubkey,
+        amount: u64,
+        token_mint: &Pubkey,
+        template: &MemoTemplate,
+        vars: &HashMap<String, String>,
+    ) -> Result<Signature> {
+        let memo = template.render(vars)?;
+        self.send_payment(from_wallet, to_pubkey, amount, token_mint, Some(&memo)).await
+    }
+
+    /// Fill `memo_fields` on records whose memo matches the registered template
+    pub(crate) fn attach_memo_fields(&self, records: &mut [crate::TransactionRecord]) {
+        let Some(template) = &self.memo_template else {
+            return;
+        };
+        for record in records {
+            if let Some(fields) = record.memo.as_deref().and_then(|memo| template.extract(memo)) {
+                record.memo_fields = fields;
+            }
+        }
+    }
+}
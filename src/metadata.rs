@@ -0,0 +1,373 @@
+use crate::AssetMetadata;
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use solana_sdk::pubkey::Pubkey;
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+
+const HTTP_TIMEOUT: Duration = Duration::from_secs(20);
+/// Directory or key prefix, under the publisher's own, that drafts' metadata is staged in
+const STAGING_PREFIX: &str = "staging";
+
+/// Off-chain token metadata in the Metaplex JSON standard
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OffchainMetadata {
+    pub name: String,
+    pub symbol: String,
+    pub description: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image: Option<String>,
+    #[serde(default)]
+    pub attributes: Vec<MetadataAttribute>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MetadataAttribute {
+    pub trait_type: String,
+    pub value: serde_json::Value,
+}
+
+impl OffchainMetadata {
+    pub fn from_asset(asset: &AssetMetadata, symbol: &str) -> Self {
+        let attribute = |trait_type: &str, value: serde_json::Value| MetadataAttribute {
+            trait_type: trait_type.to_string(),
+            value,
+        };
+        Self {
+            name: asset.name.clone(),
+            symbol: symbol.to_string(),
+            description: asset.description.clone(),
+            image: None,
+            attributes: vec![
+                attribute("value", asset.value.into()),
+                attribute("asset_type", asset.asset_type.to_string().into()),
+                attribute("created_at", asset.created_at.into()),
+                attribute("issuer", asset.issuer.to_string().into()),
+            ],
+        }
+    }
+
+    pub fn attribute(&self, trait_type: &str) -> Option<&serde_json::Value> {
+        self.attributes
+            .iter()
+            .find(|a| a.trait_type == trait_type)
+            .map(|a| &a.value)
+    }
+}
+
+/// Where `stage_metadata` put a draft's metadata, and where the mint will point once committed
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StagedMetadata {
+    /// Readable by reviewers before the mint exists
+    pub staging_uri: String,
+    /// What `promote_staged` returns, and so what goes on-chain
+    pub final_uri: String,
+}
+
+/// Somewhere to host off-chain metadata JSON so the on-chain URI resolves
+#[async_trait]
+pub trait MetadataPublisher: Send + Sync {
+    /// Store the metadata for `mint` and return the public URL to put on-chain
+    async fn publish(&self, mint: &Pubkey, metadata: &OffchainMetadata) -> Result<String>;
+
+    /// Store a tokenization draft's metadata for review, before `mint` is created
+    ///
+    /// By default the metadata is published for real, which suits content-addressed stores:
+    /// the staging copy is the final one, and discarding leaves it in place.
+    async fn stage_metadata(&self, mint: &Pubkey, metadata: &OffchainMetadata) -> Result<StagedMetadata> {
+        let uri = self.publish(mint, metadata).await?;
+        Ok(StagedMetadata { staging_uri: uri.clone(), final_uri: uri })
+    }
+
+    /// Publish the reviewed metadata at its final location when the draft is committed
+    async fn promote_staged(&self, mint: &Pubkey, metadata: &OffchainMetadata) -> Result<String> {
+        self.publish(mint, metadata).await
+    }
+
+    /// Remove the staging copy of a discarded or committed draft's metadata
+    async fn discard_staged(&self, mint: &Pubkey) -> Result<()> {
+        let _ = mint;
+        Ok(())
+    }
+
+    /// Store a document attached to `mint` under its SHA-256 and return its public URL
+    ///
+    /// `None` by default, for publishers that only host metadata JSON; the document is then
+    /// anchored by hash alone.
+    async fn publish_document(&self, mint: &Pubkey, sha256: &str, bytes: &[u8]) -> Result<Option<String>> {
+        let _ = (mint, sha256, bytes);
+        Ok(None)
+    }
+}
+
+/// Writes `<mint>.json` into a directory served by a static web server
+pub struct DirectoryPublisher {
+    pub directory: PathBuf,
+    /// Public URL the directory is served at, e.g. `https://assets.example.com/metadata`
+    pub public_base_url: String,
+}
+
+impl DirectoryPublisher {
+    pub fn new(directory: impl Into<PathBuf>, public_base_url: &str) -> Self {
+        Self {
+            directory: directory.into(),
+            public_base_url: public_base_url.trim_end_matches('/').to_string(),
+        }
+    }
+}
+
+#[async_trait]
+impl MetadataPublisher for DirectoryPublisher {
+    async fn publish(&self, mint: &Pubkey, metadata: &OffchainMetadata) -> Result<String> {
+        fs::create_dir_all(&self.directory)?;
+        let file_name = format!("{}.json", mint);
+        let path = self.directory.join(&file_name);
+        fs::write(&path, serde_json::to_string_pretty(metadata)?)
+            .map_err(|e| anyhow!("Failed to write metadata to {}: {}", path.display(), e))?;
+        Ok(format!("{}/{}", self.public_base_url, file_name))
+    }
+
+    /// Writes `staging/<mint>.json`; the final URL is the one `publish` will return
+    async fn stage_metadata(&self, mint: &Pubkey, metadata: &OffchainMetadata) -> Result<StagedMetadata> {
+        let directory = self.directory.join(STAGING_PREFIX);
+        fs::create_dir_all(&directory)?;
+        let file_name = format!("{}.json", mint);
+        let path = directory.join(&file_name);
+        fs::write(&path, serde_json::to_string_pretty(metadata)?)
+            .map_err(|e| anyhow!("Failed to write staged metadata to {}: {}", path.display(), e))?;
+        Ok(StagedMetadata {
+            staging_uri: format!("{}/{}/{}", self.public_base_url, STAGING_PREFIX, file_name),
+            final_uri: format!("{}/{}", self.public_base_url, file_name),
+        })
+    }
+
+    async fn promote_staged(&self, mint: &Pubkey, metadata: &OffchainMetadata) -> Result<String> {
+        let uri = self.publish(mint, metadata).await?;
+        self.discard_staged(mint).await?;
+        Ok(uri)
+    }
+
+    async fn discard_staged(&self, mint: &Pubkey) -> Result<()> {
+        let path = self.directory.join(STAGING_PREFIX).join(format!("{}.json", mint));
+        match fs::remove_file(&path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                Err(anyhow!("Failed to remove staged metadata {}: {}", path.display(), e))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Writes `documents/<mint>/<sha256>` under the directory
+    async fn publish_document(&self, mint: &Pubkey, sha256: &str, bytes: &[u8]) -> Result<Option<String>> {
+        let directory = self.directory.join("documents").join(mint.to_string());
+        fs::create_dir_all(&directory)?;
+        let path = directory.join(sha256);
+        fs::write(&path, bytes).map_err(|e| anyhow!("Failed to write document to {}: {}", path.display(), e))?;
+        Ok(Some(format!("{}/documents/{}/{}", self.public_base_url, mint, sha256)))
+    }
+}
+
+/// Uploads `<prefix><mint>.json` to an S3-compatible bucket (AWS, R2, MinIO, ...) with SigV4
+pub struct S3Publisher {
+    /// e.g. `https://s3.us-east-1.amazonaws.com` or `https://<account>.r2.cloudflarestorage.com`
+    pub endpoint: String,
+    pub bucket: String,
+    pub region: String,
+    pub access_key: String,
+    pub secret_key: String,
+    pub key_prefix: String,
+    /// Public URL objects are readable at, e.g. a CDN in front of the bucket
+    pub public_base_url: String,
+}
+
+impl S3Publisher {
+    fn sign(&self, key: &[u8], data: &str) -> Vec<u8> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any length");
+        mac.update(data.as_bytes());
+        mac.finalize().into_bytes().to_vec()
+    }
+
+    /// PUT `body` at `object_key` and return its public URL
+    async fn put_object(&self, object_key: &str, body: Vec<u8>, content_type: &str) -> Result<String> {
+        self.object_request(reqwest::Method::PUT, object_key, body, Some(content_type)).await?;
+        Ok(self.public_url(object_key))
+    }
+
+    fn public_url(&self, object_key: &str) -> String {
+        format!("{}/{}", self.public_base_url.trim_end_matches('/'), object_key)
+    }
+
+    /// Send a SigV4-signed `method` request for `object_key`
+    async fn object_request(
+        &self,
+        method: reqwest::Method,
+        object_key: &str,
+        body: Vec<u8>,
+        content_type: Option<&str>,
+    ) -> Result<()> {
+        let url = reqwest::Url::parse(&format!(
+            "{}/{}/{}",
+            self.endpoint.trim_end_matches('/'),
+            self.bucket,
+            object_key
+        ))?;
+        let host = match (url.host_str(), url.port()) {
+            (Some(host), Some(port)) => format!("{}:{}", host, port),
+            (Some(host), None) => host.to_string(),
+            _ => return Err(anyhow!("Invalid S3 endpoint: {}", self.endpoint)),
+        };
+
+        let now = chrono::Utc::now();
+        let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let date_stamp = now.format("%Y%m%d").to_string();
+        let payload_hash = hex(&Sha256::digest(&body));
+
+        let signed_headers = "host;x-amz-content-sha256;x-amz-date";
+        let canonical_request = format!(
+            "{}\n{}\n\nhost:{}\nx-amz-content-sha256:{}\nx-amz-date:{}\n\n{}\n{}",
+            method,
+            url.path(),
+            host,
+            payload_hash,
+            amz_date,
+            signed_headers,
+            payload_hash
+        );
+        let scope = format!("{}/{}/s3/aws4_request", date_stamp, self.region);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            amz_date,
+            scope,
+            hex(&Sha256::digest(canonical_request.as_bytes()))
+        );
+
+        let k_date = self.sign(format!("AWS4{}", self.secret_key).as_bytes(), &date_stamp);
+        let k_region = self.sign(&k_date, &self.region);
+        let k_service = self.sign(&k_region, "s3");
+        let k_signing = self.sign(&k_service, "aws4_request");
+        let signature = hex(&self.sign(&k_signing, &string_to_sign));
+
+        let authorization = format!(
+            "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+            self.access_key, scope, signed_headers, signature
+        );
+
+        let mut request = reqwest::Client::new()
+            .request(method.clone(), url)
+            .timeout(HTTP_TIMEOUT)
+            .header("host", host)
+            .header("x-amz-content-sha256", payload_hash)
+            .header("x-amz-date", amz_date)
+            .header("authorization", authorization);
+        if let Some(content_type) = content_type {
+            request = request.header("content-type", content_type);
+        }
+        let response = request.body(body).send().await?;
+        if !response.status().is_success() {
+            return Err(anyhow!(
+                "S3 {} of {} failed with {}: {}",
+                method,
+                object_key,
+                response.status(),
+                response.text().await.unwrap_or_default()
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl MetadataPublisher for S3Publisher {
+    async fn publish(&self, mint: &Pubkey, metadata: &OffchainMetadata) -> Result<String> {
+        let object_key = format!("{}{}.json", self.key_prefix, mint);
+        self.put_object(&object_key, serde_json::to_vec(metadata)?, "application/json").await
+    }
+
+    /// Uploads `<prefix>staging/<mint>.json`
+    async fn stage_metadata(&self, mint: &Pubkey, metadata: &OffchainMetadata) -> Result<StagedMetadata> {
+        let object_key = format!("{}{}/{}.json", self.key_prefix, STAGING_PREFIX, mint);
+        let staging_uri = self.put_object(&object_key, serde_json::to_vec(metadata)?, "application/json").await?;
+        Ok(StagedMetadata { staging_uri, final_uri: self.public_url(&format!("{}{}.json", self.key_prefix, mint)) })
+    }
+
+    async fn promote_staged(&self, mint: &Pubkey, metadata: &OffchainMetadata) -> Result<String> {
+        let uri = self.publish(mint, metadata).await?;
+        self.discard_staged(mint).await?;
+        Ok(uri)
+    }
+
+    async fn discard_staged(&self, mint: &Pubkey) -> Result<()> {
+        let object_key = format!("{}{}/{}.json", self.key_prefix, STAGING_PREFIX, mint);
+        self.object_request(reqwest::Method::DELETE, &object_key, Vec::new(), None).await
+    }
+
+    /// Uploads `<prefix>documents/<mint>/<sha256>`
+    async fn publish_document(&self, mint: &Pubkey, sha256: &str, bytes: &[u8]) -> Result<Option<String>> {
+        let object_key = format!("{}documents/{}/{}", self.key_prefix, mint, sha256);
+        Ok(Some(self.put_object(&object_key, bytes.to_vec(), "application/octet-stream").await?))
+    }
+}
+
+/// Pins metadata JSON through a Pinata-compatible pinning API and returns its gateway URL
+pub struct IpfsPinningPublisher {
+    /// e.g. `https://api.pinata.cloud/pinning/pinJSONToIPFS`
+    pub api_url: String,
+    pub jwt: String,
+    /// e.g. `https://gateway.pinata.cloud`
+    pub gateway_url: String,
+}
+
+#[derive(Deserialize)]
+struct PinResponse {
+    #[serde(rename = "IpfsHash")]
+    ipfs_hash: String,
+}
+
+#[async_trait]
+impl MetadataPublisher for IpfsPinningPublisher {
+    async fn publish(&self, mint: &Pubkey, metadata: &OffchainMetadata) -> Result<String> {
+        let request = serde_json::json!({
+            "pinataContent": metadata,
+            "pinataMetadata": { "name": format!("{}.json", mint) },
+        });
+
+        let response = reqwest::Client::new()
+            .post(&self.api_url)
+            .timeout(HTTP_TIMEOUT)
+            .bearer_auth(&self.jwt)
+            .json(&request)
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(anyhow!(
+                "IPFS pinning failed with {}: {}",
+                response.status(),
+                response.text().await.unwrap_or_default()
+            ));
+        }
+
+        let pinned: PinResponse = response.json().await?;
+        Ok(format!("{}/ipfs/{}", self.gateway_url.trim_end_matches('/'), pinned.ipfs_hash))
+    }
+}
+
+/// Download and parse the off-chain metadata an on-chain URI points at
+pub async fn fetch_offchain_metadata(uri: &str) -> Result<OffchainMetadata> {
+    let response = reqwest::Client::new()
+        .get(uri)
+        .timeout(HTTP_TIMEOUT)
+        .send()
+        .await?;
+    if !response.status().is_success() {
+        return Err(anyhow!("Metadata fetch from {} failed with {}", uri, response.status()));
+    }
+    Ok(response.json().await?)
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
@@ -0,0 +1,373 @@
+//! Noticing when the metadata of assets a wallet holds is changed under it
+//!
+//! A mutable Metaplex metadata account can be rewritten by its update authority at any time,
+//! and the JSON its URI points at can change without any on-chain trace. A `MetadataSnapshot`
+//! records, for every mint the owner holds, the on-chain fields worth watching, a hash of the
+//! whole metadata account, and a hash of whatever the URI served. `diff_asset_metadata`
+//! compares two snapshots field by field; `watch_asset_metadata` takes one every interval and
+//! reports what changed since the last. Assets tokenized as ledger memos have no metadata
+//! account and nothing that can change, so they appear only as having no metadata.
+
+use crate::telemetry::readable;
+use crate::FinternetClient;
+use anyhow::{anyhow, Result};
+use futures::stream::{self, StreamExt};
+use mpl_token_metadata::accounts::Metadata;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use solana_sdk::pubkey::Pubkey;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Metadata accounts read per `getMultipleAccounts` call, the RPC maximum
+const METADATA_PER_FETCH: usize = 100;
+/// URIs downloaded at once while taking a snapshot
+const URI_FETCH_CONCURRENCY: usize = 8;
+const URI_FETCH_TIMEOUT: Duration = Duration::from_secs(20);
+
+/// A royalty creator as recorded in the metadata account
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SnapshotCreator {
+    pub address: Pubkey,
+    pub verified: bool,
+    pub share: u8,
+}
+
+impl fmt::Display for SnapshotCreator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}%{}", self.address, self.share, if self.verified { "" } else { " (unverified)" })
+    }
+}
+
+/// The watched metadata of one held mint at the time of a snapshot
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MintMetadataSnapshot {
+    pub mint: Pubkey,
+    /// False for mints with no Metaplex metadata account; every field below is then empty
+    pub has_metadata: bool,
+    pub name: Option<String>,
+    pub symbol: Option<String>,
+    pub uri: Option<String>,
+    pub seller_fee_basis_points: Option<u16>,
+    #[serde(default)]
+    pub creators: Vec<SnapshotCreator>,
+    pub update_authority: Option<Pubkey>,
+    pub is_mutable: Option<bool>,
+    /// SHA-256 of the whole metadata account, catching changes to fields not listed here
+    pub onchain_hash: Option<String>,
+    /// SHA-256 of the body the URI served
+    pub uri_content_hash: Option<String>,
+    /// Why the URI could not be fetched, when it could not; its content hash is then unknown
+    pub uri_error: Option<String>,
+}
+
+impl MintMetadataSnapshot {
+    fn without_metadata(mint: Pubkey) -> Self {
+        Self {
+            mint,
+            has_metadata: false,
+            name: None,
+            symbol: None,
+            uri: None,
+            seller_fee_basis_points: None,
+            creators: Vec::new(),
+            update_authority: None,
+            is_mutable: None,
+            onchain_hash: None,
+            uri_content_hash: None,
+            uri_error: None,
+        }
+    }
+
+    fn from_account(mint: Pubkey, data: &[u8]) -> Result<Self> {
+        let metadata = Metadata::from_bytes(data).map_err(|e| anyhow!("Unreadable metadata for {}: {}", mint, e))?;
+        Ok(Self {
+            mint,
+            has_metadata: true,
+            name: Some(trim_padding(&metadata.name)),
+            symbol: Some(trim_padding(&metadata.symbol)),
+            uri: Some(trim_padding(&metadata.uri)),
+            seller_fee_basis_points: Some(metadata.seller_fee_basis_points),
+            creators: metadata
+                .creators
+                .unwrap_or_default()
+                .into_iter()
+                .map(|creator| SnapshotCreator {
+                    address: creator.address,
+                    verified: creator.verified,
+                    share: creator.share,
+                })
+                .collect(),
+            update_authority: Some(metadata.update_authority),
+            is_mutable: Some(metadata.is_mutable),
+            onchain_hash: Some(sha256_hex(data)),
+            uri_content_hash: None,
+            uri_error: None,
+        })
+    }
+}
+
+/// Metadata of every mint `owner` held a non-zero balance of at `taken_at`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MetadataSnapshot {
+    pub owner: Pubkey,
+    /// Unix seconds
+    pub taken_at: u64,
+    /// Sorted by mint
+    pub assets: Vec<MintMetadataSnapshot>,
+}
+
+impl MetadataSnapshot {
+    pub fn load(path: &Path) -> Result<Self> {
+        let data = fs::read_to_string(path)?;
+        serde_json::from_str(&data).map_err(|e| anyhow!("Corrupt metadata snapshot {}: {}", path.display(), e))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            if !parent.as_os_str().is_empty() {
+                fs::create_dir_all(parent)?;
+            }
+        }
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, serde_json::to_string_pretty(self)?)?;
+        fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    pub fn asset(&self, mint: &Pubkey) -> Option<&MintMetadataSnapshot> {
+        self.assets.iter().find(|asset| asset.mint == *mint)
+    }
+}
+
+/// How much a metadata change should worry the holder
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeSeverity {
+    /// Cosmetic: name, symbol, or the content behind an unchanged URI
+    Low,
+    /// Changes what the asset points at or who is paid for it: URI, royalty, or creators
+    Medium,
+    /// Changes who controls the metadata from now on: update authority or mutability
+    High,
+}
+
+impl fmt::Display for ChangeSeverity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Low => "low",
+            Self::Medium => "medium",
+            Self::High => "high",
+        })
+    }
+}
+
+/// One field of one mint's metadata that differs between two snapshots
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MetadataChange {
+    pub mint: Pubkey,
+    /// A `MintMetadataSnapshot` field name, or `held` when the mint entered or left the holdings
+    pub field: String,
+    pub old: Option<String>,
+    pub new: Option<String>,
+    pub severity: ChangeSeverity,
+}
+
+impl fmt::Display for MetadataChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "[{}] {} {}: {} -> {}",
+            self.severity,
+            self.mint,
+            self.field,
+            self.old.as_deref().unwrap_or("-"),
+            self.new.as_deref().unwrap_or("-")
+        )
+    }
+}
+
+impl FinternetClient {
+    /// Snapshot the metadata of every mint `owner` holds a non-zero balance of
+    ///
+    /// Each metadata URI is downloaded and hashed; a URI that cannot be fetched is recorded in
+    /// `uri_error` rather than failing the snapshot.
+    pub async fn snapshot_asset_metadata(&self, owner: &Pubkey) -> Result<MetadataSnapshot> {
+        let mints: BTreeSet<Pubkey> = self
+            .get_token_account_details(owner)
+            .await?
+            .into_iter()
+            .filter(|account| account.amount > 0)
+            .map(|account| account.mint)
+            .collect();
+        let mints: Vec<Pubkey> = mints.into_iter().collect();
+
+        let mut assets = Vec::with_capacity(mints.len());
+        for chunk in mints.chunks(METADATA_PER_FETCH) {
+            let addresses: Vec<Pubkey> = chunk.iter().map(|mint| Metadata::find_pda(mint).0).collect();
+            let accounts = self.client.get_multiple_accounts_with_commitment(&addresses, self.client.commitment())?.value;
+            for (mint, account) in chunk.iter().zip(accounts) {
+                assets.push(match account {
+                    Some(account) if account.owner == mpl_token_metadata::ID => {
+                        MintMetadataSnapshot::from_account(*mint, &account.data)?
+                    }
+                    _ => MintMetadataSnapshot::without_metadata(*mint),
+                });
+            }
+        }
+
+        let http = reqwest::Client::new();
+        let hashes: BTreeMap<String, Result<String, String>> = stream::iter(
+            assets
+                .iter()
+                .filter_map(|asset| asset.uri.clone())
+                .filter(|uri| !uri.is_empty())
+                .collect::<BTreeSet<_>>(),
+        )
+        .map(|uri| {
+            let http = http.clone();
+            async move {
+                let hash = uri_content_hash(&http, &uri).await.map_err(|e| e.to_string());
+                (uri, hash)
+            }
+        })
+        .buffer_unordered(URI_FETCH_CONCURRENCY)
+        .collect()
+        .await;
+        for asset in &mut assets {
+            match asset.uri.as_ref().and_then(|uri| hashes.get(uri)) {
+                Some(Ok(hash)) => asset.uri_content_hash = Some(hash.clone()),
+                Some(Err(e)) => asset.uri_error = Some(e.clone()),
+                None => {}
+            }
+        }
+
+        Ok(MetadataSnapshot {
+            owner: *owner,
+            taken_at: unix_now(),
+            assets,
+        })
+    }
+
+    /// Every field that differs between `old` and `new`, ordered by mint, most severe first
+    ///
+    /// A content hash that is unknown in either snapshot, because its URI could not be fetched,
+    /// is not reported as a change.
+    pub fn diff_asset_metadata(old: &MetadataSnapshot, new: &MetadataSnapshot) -> Vec<MetadataChange> {
+        let mints: BTreeSet<Pubkey> = old.assets.iter().chain(&new.assets).map(|asset| asset.mint).collect();
+        let mut changes = Vec::new();
+        for mint in mints {
+            let mut mint_changes = diff_mint(&mint, old.asset(&mint), new.asset(&mint));
+            mint_changes.sort_by_key(|change| std::cmp::Reverse(change.severity));
+            changes.extend(mint_changes);
+        }
+        changes
+    }
+
+    /// Snapshot `owner` every `interval` and call `handler` with each change since the last
+    ///
+    /// The first snapshot is the baseline and reports nothing. Runs until the task is dropped
+    /// or the client shuts down; failed snapshots are logged and retried on the next tick, keeping the last good one.
+    pub async fn watch_asset_metadata(
+        &self,
+        owner: &Pubkey,
+        interval: Duration,
+        mut handler: impl FnMut(MetadataChange),
+    ) {
+        let mut last: Option<MetadataSnapshot> = None;
+        let shutdown = self.tasks.signal();
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            tokio::select! {
+                _ = ticker.tick() => {}
+                _ = shutdown.cancelled() => return,
+            }
+            match self.snapshot_asset_metadata(owner).await {
+                Ok(snapshot) => {
+                    if let Some(previous) = &last {
+                        Self::diff_asset_metadata(previous, &snapshot).into_iter().for_each(&mut handler);
+                    }
+                    last = Some(snapshot);
+                }
+                Err(e) => tracing::warn!("Metadata snapshot for {} failed: {}", readable(owner), e),
+            }
+        }
+    }
+}
+
+fn diff_mint(mint: &Pubkey, old: Option<&MintMetadataSnapshot>, new: Option<&MintMetadataSnapshot>) -> Vec<MetadataChange> {
+    let change = |field: &str, old: Option<String>, new: Option<String>, severity| MetadataChange {
+        mint: *mint,
+        field: field.to_string(),
+        old,
+        new,
+        severity,
+    };
+    let (old, new) = match (old, new) {
+        (Some(old), Some(new)) => (old, new),
+        (old, new) => {
+            let held = |asset: Option<&MintMetadataSnapshot>| Some(asset.is_some().to_string());
+            return vec![change("held", held(old), held(new), ChangeSeverity::Low)];
+        }
+    };
+
+    let mut changes = Vec::new();
+    let mut compare = |field: &str, old: Option<String>, new: Option<String>, severity| {
+        if old != new {
+            changes.push(change(field, old, new, severity));
+        }
+    };
+    let creators = |asset: &MintMetadataSnapshot| {
+        Some(asset.creators.iter().map(ToString::to_string).collect::<Vec<_>>().join(", "))
+    };
+    compare("has_metadata", Some(old.has_metadata.to_string()), Some(new.has_metadata.to_string()), ChangeSeverity::High);
+    compare(
+        "update_authority",
+        old.update_authority.map(|a| a.to_string()),
+        new.update_authority.map(|a| a.to_string()),
+        ChangeSeverity::High,
+    );
+    compare("is_mutable", old.is_mutable.map(|m| m.to_string()), new.is_mutable.map(|m| m.to_string()), ChangeSeverity::High);
+    compare("uri", old.uri.clone(), new.uri.clone(), ChangeSeverity::Medium);
+    compare(
+        "seller_fee_basis_points",
+        old.seller_fee_basis_points.map(|b| b.to_string()),
+        new.seller_fee_basis_points.map(|b| b.to_string()),
+        ChangeSeverity::Medium,
+    );
+    compare("creators", creators(old), creators(new), ChangeSeverity::Medium);
+    compare("name", old.name.clone(), new.name.clone(), ChangeSeverity::Low);
+    compare("symbol", old.symbol.clone(), new.symbol.clone(), ChangeSeverity::Low);
+    if old.uri_content_hash.is_some() && new.uri_content_hash.is_some() {
+        compare("uri_content_hash", old.uri_content_hash.clone(), new.uri_content_hash.clone(), ChangeSeverity::Low);
+    }
+    // The account hash only matters when none of the fields above explain the difference
+    if changes.is_empty() && old.onchain_hash != new.onchain_hash {
+        changes.push(change("onchain_hash", old.onchain_hash.clone(), new.onchain_hash.clone(), ChangeSeverity::Low));
+    }
+    changes
+}
+
+async fn uri_content_hash(http: &reqwest::Client, uri: &str) -> Result<String> {
+    let response = http.get(uri).timeout(URI_FETCH_TIMEOUT).send().await?;
+    if !response.status().is_success() {
+        return Err(anyhow!("Fetch from {} failed with {}", uri, response.status()));
+    }
+    Ok(sha256_hex(&response.bytes().await?))
+}
+
+/// Metaplex pads name, symbol, and URI with NUL bytes to their maximum lengths
+fn trim_padding(value: &str) -> String {
+    value.trim_end_matches('\0').to_string()
+}
+
+fn sha256_hex(bytes: &[u8]) -> String {
+    Sha256::digest(bytes).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
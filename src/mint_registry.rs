@@ -0,0 +1,352 @@
+//! Which cluster well-known mints live on, so a mint pasted from the wrong one is caught
+//!
+//! The cluster is read once from the RPC node's genesis hash. Payments in a mint the registry
+//! places on another cluster are refused with `MintNetworkError::MintNetworkMismatch`; a mint
+//! it does not know at all is only warned about, since tokenized assets are never listed.
+//! A validator on this machine is `Network::Localnet`; its mints are created by
+//! `localnet::Localnet::up` and added with `register_localnet_mint`.
+
+use crate::payment::usdc;
+use crate::FinternetClient;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use solana_sdk::pubkey::Pubkey;
+use std::fmt;
+use std::sync::RwLock;
+
+const MAINNET_GENESIS_HASH: &str = "5eykt4UsFv8P8NJdTREpY1vzqKqZKvdpKuc147dw2N9d";
+const DEVNET_GENESIS_HASH: &str = "EtWTRABZaYq6iMfeYKouRu166VU2xqa1wcaWoxPkrZBG";
+const TESTNET_GENESIS_HASH: &str = "4uhcVJyU9pJkvQyS88uRDiswHXSCkY3zQawwpjk2NsNY";
+
+/// USDC mint name in the registry
+pub const USDC: &str = "USDC";
+
+/// A public Solana cluster, or a validator on this machine
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Network {
+    Mainnet,
+    Devnet,
+    Testnet,
+    /// `solana-test-validator` or another validator reached through localhost
+    Localnet,
+}
+
+impl Network {
+    /// The public cluster with this genesis hash; `None` for local validators and private clusters
+    pub fn from_genesis_hash(hash: &str) -> Option<Self> {
+        match hash {
+            MAINNET_GENESIS_HASH => Some(Self::Mainnet),
+            DEVNET_GENESIS_HASH => Some(Self::Devnet),
+            TESTNET_GENESIS_HASH => Some(Self::Testnet),
+            _ => None,
+        }
+    }
+
+    /// The cluster behind `rpc_url` with genesis hash `hash`: a public one, `Localnet` for an
+    /// unknown genesis served from localhost, `None` otherwise
+    pub fn detect(hash: &str, rpc_url: &str) -> Option<Self> {
+        Self::from_genesis_hash(hash).or_else(|| is_local_endpoint(rpc_url).then_some(Self::Localnet))
+    }
+}
+
+/// Whether `rpc_url` points at this machine
+pub fn is_local_endpoint(rpc_url: &str) -> bool {
+    let authority = rpc_url.split("://").nth(1).unwrap_or(rpc_url);
+    let authority = authority.split('/').next().unwrap_or_default();
+    // An IPv6 host is bracketed, and its colons are not the port separator
+    let host = match authority.find(']') {
+        Some(end) if authority.starts_with('[') => &authority[..=end],
+        _ => authority.split(':').next().unwrap_or_default(),
+    };
+    matches!(host, "localhost" | "127.0.0.1" | "[::1]")
+}
+
+impl fmt::Display for Network {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Mainnet => write!(f, "mainnet"),
+            Self::Devnet => write!(f, "devnet"),
+            Self::Testnet => write!(f, "testnet"),
+            Self::Localnet => write!(f, "localnet"),
+        }
+    }
+}
+
+/// A mint the registry knows
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KnownMint {
+    pub mint: Pubkey,
+    pub name: &'static str,
+    /// `None` for mints that exist on every cluster, such as wrapped SOL
+    pub network: Option<Network>,
+}
+
+impl KnownMint {
+    /// The name with the cluster it belongs to, e.g. `USDC (mainnet!)` when that is not `current`
+    pub fn describe(&self, current: Option<Network>) -> String {
+        match self.network {
+            Some(network) if Some(network) != current => format!("{} ({}!)", self.name, network),
+            Some(network) => format!("{} ({})", self.name, network),
+            None => self.name.to_string(),
+        }
+    }
+}
+
+/// Mints created on the local validator; they change with every fresh ledger, so they are
+/// registered at runtime rather than listed
+static LOCALNET_MINTS: RwLock<Vec<KnownMint>> = RwLock::new(Vec::new());
+
+fn registry() -> Vec<KnownMint> {
+    let mut known = vec![
+        KnownMint { mint: usdc::mainnet_mint(), name: USDC, network: Some(Network::Mainnet) },
+        KnownMint { mint: usdc::devnet_mint(), name: USDC, network: Some(Network::Devnet) },
+        KnownMint { mint: spl_token::native_mint::id(), name: "wSOL", network: None },
+    ];
+    known.extend(LOCALNET_MINTS.read().unwrap_or_else(|e| e.into_inner()).iter().copied());
+    known
+}
+
+/// Add `mint` as the local validator's `name`, replacing an earlier one of that name
+///
+/// `localnet::Localnet::up` registers its USDC stand-in this way, and so does
+/// `profile::Profile::register_mints` for a profile that records one.
+pub fn register_localnet_mint(name: &'static str, mint: Pubkey) {
+    let mut mints = LOCALNET_MINTS.write().unwrap_or_else(|e| e.into_inner());
+    mints.retain(|known| known.name != name);
+    mints.push(KnownMint { mint, name, network: Some(Network::Localnet) });
+}
+
+/// The registry entry for `mint`, if it has one
+pub fn lookup(mint: &Pubkey) -> Option<KnownMint> {
+    registry().into_iter().find(|known| known.mint == *mint)
+}
+
+/// The mint called `name` on `network`, e.g. to suggest devnet USDC for a pasted mainnet one
+pub fn find(name: &str, network: Network) -> Option<KnownMint> {
+    registry()
+        .into_iter()
+        .find(|known| known.name == name && known.network.is_none_or(|n| n == network))
+}
+
+/// Every mint usable on `network`: its own and those on every cluster; only the latter for `None`
+pub fn on_network(network: Option<Network>) -> Vec<KnownMint> {
+    registry()
+        .into_iter()
+        .filter(|known| known.network.is_none() || known.network == network)
+        .collect()
+}
+
+/// Solana Explorer link to `path` on `network`; see `FinternetClient::explorer_url`
+pub fn explorer_url(network: Option<Network>, rpc_url: &str, path: &str) -> String {
+    let base = format!("https://explorer.solana.com/{}", path.trim_start_matches('/'));
+    match network {
+        Some(Network::Mainnet) => base,
+        Some(Network::Devnet) => format!("{}?cluster=devnet", base),
+        Some(Network::Testnet) => format!("{}?cluster=testnet", base),
+        Some(Network::Localnet) | None => {
+            let custom_url: String = form_urlencoded::byte_serialize(rpc_url.as_bytes()).collect();
+            format!("{}?cluster=custom&customUrl={}", base, custom_url)
+        }
+    }
+}
+
+/// Why a mint was refused for the cluster the client is connected to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MintNetworkError {
+    /// `mint` is the registry's `name` on `network`, but the client is on `expected_network`
+    MintNetworkMismatch {
+        mint: Pubkey,
+        name: &'static str,
+        network: Network,
+        expected_network: Network,
+    },
+}
+
+impl MintNetworkError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::MintNetworkMismatch { .. } => "mint_network_mismatch",
+        }
+    }
+}
+
+impl fmt::Display for MintNetworkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MintNetworkMismatch { mint, name, network, expected_network } => write!(
+                f,
+                "{} is {} ({}!), but this client is connected to {}",
+                mint, name, network, expected_network
+            ),
+        }
+    }
+}
+
+impl std::error::Error for MintNetworkError {}
+
+impl FinternetClient {
+    /// The cluster the RPC node serves, read once from its genesis hash; see `Network::detect`
+    pub fn network(&self) -> Result<Option<Network>> {
+        if let Some(network) = self.network.get() {
+            return Ok(*network);
+        }
+        let genesis = self.client.get_genesis_hash()?;
+        Ok(*self.network.get_or_init(|| Network::detect(&genesis.to_string(), &self.config.rpc_url)))
+    }
+
+    /// USDC on the client's cluster, or devnet USDC on a cluster the registry has none for
+    ///
+    /// On `Network::Localnet` this is the stand-in `localnet::Localnet::up` created, once it or
+    /// a profile recording it has been registered.
+    pub fn usdc_mint(&self) -> Result<Pubkey> {
+        let known = self.network()?.and_then(|network| find(USDC, network));
+        Ok(known.map_or_else(usdc::devnet_mint, |known| known.mint))
+    }
+
+    /// Solana Explorer link to `path` (`tx/<signature>` or `address/<pubkey>`) on the client's cluster
+    ///
+    /// Local and private clusters use the explorer's custom-cluster form, pointed at the RPC URL.
+    pub fn explorer_url(&self, path: &str) -> String {
+        let network = self.network().unwrap_or_else(|e| {
+            tracing::warn!("Could not read the cluster for an explorer link: {}", e);
+            None
+        });
+        explorer_url(network, &self.config.rpc_url, path)
+    }
+
+    /// Refuse `mint` if the registry places it on another cluster than the client's
+    ///
+    /// Returns the registry entry, or `None` for a mint it does not know, which is logged as a
+    /// warning. On a private cluster nothing can be told apart, and a local validator may hold
+    /// clones of any cluster's mints, so on either nothing is refused.
+    pub fn check_mint_network(&self, mint: &Pubkey) -> Result<Option<KnownMint>> {
+        let known = lookup(mint);
+        let current = self.network()?.filter(|network| *network != Network::Localnet);
+        match (known, current) {
+            (Some(KnownMint { network: Some(network), name, .. }), Some(expected_network)) if network != expected_network => {
+                Err(MintNetworkError::MintNetworkMismatch { mint: *mint, name, network, expected_network }.into())
+            }
+            (None, Some(network)) => {
+                tracing::warn!("Mint {} is not in the token registry for {}", mint, network);
+                Ok(None)
+            }
+            (known, _) => Ok(known),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_cluster::MockCluster;
+    use solana_sdk::{hash::Hash, signature::Keypair, signer::Signer};
+    use std::str::FromStr;
+
+    /// A cluster whose node reports the genesis hash of `network`
+    fn cluster_on(genesis_hash: &str) -> MockCluster {
+        let cluster = MockCluster::new();
+        cluster.set_genesis_hash(Hash::from_str(genesis_hash).unwrap());
+        cluster
+    }
+
+    fn funded(cluster: &MockCluster, mint: &Pubkey) -> Keypair {
+        let payer = Keypair::new();
+        cluster.set_balance(payer.pubkey(), 1_000_000_000);
+        cluster.add_token_account(&payer.pubkey(), mint, 100_000_000);
+        payer
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn a_devnet_client_refuses_mainnet_usdc_before_sending() {
+        let cluster = cluster_on(DEVNET_GENESIS_HASH);
+        let client = cluster.default_client();
+        let payer = funded(&cluster, &usdc::mainnet_mint());
+
+        let error = client
+            .send_payment(&payer, &Keypair::new().pubkey(), 1_000_000, &usdc::mainnet_mint(), None)
+            .await
+            .unwrap_err();
+
+        assert!(error.to_string().contains("USDC (mainnet!)"), "{}", error);
+        assert_eq!(
+            error.downcast_ref::<MintNetworkError>(),
+            Some(&MintNetworkError::MintNetworkMismatch {
+                mint: usdc::mainnet_mint(),
+                name: USDC,
+                network: Network::Mainnet,
+                expected_network: Network::Devnet,
+            })
+        );
+        assert!(cluster.sent().is_empty());
+        // The registry can name the mint the user most likely meant
+        assert_eq!(find(USDC, Network::Devnet).unwrap().mint, usdc::devnet_mint());
+        assert_eq!(client.usdc_mint().unwrap(), usdc::devnet_mint());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn a_mainnet_client_refuses_devnet_usdc() {
+        let cluster = cluster_on(MAINNET_GENESIS_HASH);
+        let client = cluster.default_client();
+        let error = client.check_mint_network(&usdc::devnet_mint()).unwrap_err();
+        assert_eq!(error.downcast_ref::<MintNetworkError>().unwrap().code(), "mint_network_mismatch");
+        assert_eq!(client.check_mint_network(&usdc::mainnet_mint()).unwrap().unwrap().name, USDC);
+        // Wrapped SOL is the same mint everywhere
+        assert_eq!(client.check_mint_network(&spl_token::native_mint::id()).unwrap().unwrap().name, "wSOL");
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn an_unknown_mint_is_only_warned_about_and_the_payment_goes_ahead() {
+        let cluster = cluster_on(DEVNET_GENESIS_HASH);
+        let client = cluster.default_client();
+        let mint = cluster.add_mint(&spl_token::id(), 6, &Pubkey::new_unique());
+        let payer = funded(&cluster, &mint);
+
+        assert_eq!(client.check_mint_network(&mint).unwrap(), None);
+        client.send_payment(&payer, &Keypair::new().pubkey(), 1_000_000, &mint, None).await.unwrap();
+        assert_eq!(cluster.sent().len(), 1);
+        // The genesis hash was read once for every check
+        assert_eq!(cluster.call_count("getGenesisHash"), 1);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn nothing_is_refused_on_a_private_cluster() {
+        let cluster = cluster_on(&Hash::new_unique().to_string());
+        let client = cluster.default_client();
+        assert_eq!(client.network().unwrap(), None);
+        assert_eq!(client.check_mint_network(&usdc::mainnet_mint()).unwrap().unwrap().network, Some(Network::Mainnet));
+        assert_eq!(client.check_mint_network(&usdc::devnet_mint()).unwrap().unwrap().network, Some(Network::Devnet));
+    }
+
+    #[test]
+    fn the_cluster_is_told_from_the_genesis_hash_or_a_local_endpoint() {
+        assert_eq!(Network::detect(MAINNET_GENESIS_HASH, "https://rpc.example"), Some(Network::Mainnet));
+        assert_eq!(Network::detect(TESTNET_GENESIS_HASH, "http://localhost:8899"), Some(Network::Testnet));
+        let unknown = Hash::new_unique().to_string();
+        for local in ["http://localhost:8899", "http://127.0.0.1:8899/", "http://[::1]:8899", "[::1]"] {
+            assert_eq!(Network::detect(&unknown, local), Some(Network::Localnet), "{}", local);
+        }
+        assert_eq!(Network::detect(&unknown, "https://localhost.example.com"), None);
+        assert_eq!(Network::detect(&unknown, "http://[2001:db8::1]:8899"), None);
+    }
+
+    #[test]
+    fn mints_are_described_with_the_cluster_they_belong_to() {
+        let usdc = lookup(&usdc::mainnet_mint()).unwrap();
+        assert_eq!(usdc.describe(Some(Network::Devnet)), "USDC (mainnet!)");
+        assert_eq!(usdc.describe(None), "USDC (mainnet!)");
+        assert_eq!(usdc.describe(Some(Network::Mainnet)), "USDC (mainnet)");
+        assert_eq!(lookup(&spl_token::native_mint::id()).unwrap().describe(Some(Network::Devnet)), "wSOL");
+        assert_eq!(lookup(&Pubkey::new_unique()), None);
+    }
+
+    #[test]
+    fn explorer_links_name_the_cluster() {
+        assert_eq!(explorer_url(Some(Network::Mainnet), "", "tx/abc"), "https://explorer.solana.com/tx/abc");
+        assert_eq!(explorer_url(Some(Network::Devnet), "", "/tx/abc"), "https://explorer.solana.com/tx/abc?cluster=devnet");
+        assert_eq!(
+            explorer_url(Some(Network::Localnet), "http://localhost:8899", "address/xyz"),
+            "https://explorer.solana.com/address/xyz?cluster=custom&customUrl=http%3A%2F%2Flocalhost%3A8899"
+        );
+    }
+}
@@ -0,0 +1,877 @@
+//! An in-memory cluster served through `RpcSender`, for tests
+//!
+//! `MockCluster` holds accounts, blockhashes, and the status of every transaction sent to it,
+//! and answers the RPC methods the SDK's send and read paths use, including the history and
+//! transaction lookups `read_ledger_entries` makes. It does not execute instructions: what
+//! happens to a sent transaction is decided by its `Landing`, so tests can make sends confirm,
+//! fail on-chain, vanish, or be refused, and then look at what was sent. A test that needs
+//! accounts to change, such as the end-to-end demo, can turn on `execute_sends`.
+//! Methods it does not serve fail, unless a test answers them with `respond`.
+
+// Each test module uses the part of the cluster it needs
+#![allow(dead_code)]
+
+use crate::{FinternetClient, FinternetConfig};
+use solana_account_decoder::{UiAccount, UiAccountEncoding};
+use solana_client::client_error::{ClientError, ClientErrorKind, Result as ClientResult};
+use solana_client::rpc_request::{RpcError, RpcRequest, RpcResponseErrorData};
+use solana_client::rpc_sender::{RpcSender, RpcTransportStats};
+use solana_sdk::{
+    account::Account,
+    hash::Hash,
+    instruction::InstructionError,
+    nonce::state::{Data as NonceData, DurableNonce, State as NonceState, Versions as NonceVersions},
+    program_pack::{IsInitialized, Pack},
+    pubkey::Pubkey,
+    rent::Rent,
+    signature::Signature,
+    system_instruction::SystemInstruction,
+    transaction::{Transaction, TransactionError},
+};
+use spl_token::instruction::{AuthorityType, TokenInstruction};
+use solana_transaction_status::{
+    Encodable, EncodedConfirmedTransactionWithStatusMeta, EncodedTransactionWithStatusMeta, TransactionConfirmationStatus,
+    TransactionStatus, UiTransactionEncoding,
+};
+use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// Blocks a blockhash stays valid for, as on a real cluster
+const BLOCKHASH_LIFETIME: u64 = 150;
+const SLOTS_PER_EPOCH: u64 = 432_000;
+const MS_PER_SLOT: i64 = 400;
+
+/// What happens to a transaction once it is sent
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Landing {
+    /// Lands, finalized, without error
+    Confirmed,
+    /// Lands, finalized, and fails with this error
+    Failed(TransactionError),
+    /// Accepted but never lands, until `land` says otherwise
+    Pending,
+    /// The node refuses the transaction with this message; nothing is recorded as sent
+    Refused(String),
+}
+
+type Responder = Box<dyn Fn(&Value) -> Option<Result<Value, String>> + Send + Sync>;
+type Lander = Box<dyn Fn(&Transaction) -> Landing + Send + Sync>;
+
+struct ClusterState {
+    accounts: HashMap<Pubkey, Account>,
+    blockhash: Hash,
+    valid_blockhashes: HashSet<Hash>,
+    slot: u64,
+    /// Block time of `slot`; earlier slots are a slot's length earlier each
+    unix_time: i64,
+    genesis_hash: Hash,
+    sent: Vec<Transaction>,
+    statuses: HashMap<Signature, TransactionStatus>,
+    /// Status meta `getTransaction` reports for executed transactions
+    metas: HashMap<Signature, Value>,
+    calls: Vec<String>,
+    landing: Landing,
+    lander: Option<Lander>,
+    responders: HashMap<String, Responder>,
+    executes: bool,
+}
+
+/// A fake cluster shared by every client and sender made from it
+#[derive(Clone)]
+pub(crate) struct MockCluster {
+    state: Arc<Mutex<ClusterState>>,
+}
+
+impl MockCluster {
+    pub fn new() -> Self {
+        let blockhash = Hash::new_unique();
+        Self {
+            state: Arc::new(Mutex::new(ClusterState {
+                accounts: HashMap::new(),
+                blockhash,
+                valid_blockhashes: HashSet::from([blockhash]),
+                slot: 1_000,
+                unix_time: 1_700_000_000,
+                genesis_hash: Hash::new_unique(),
+                sent: Vec::new(),
+                statuses: HashMap::new(),
+                metas: HashMap::new(),
+                calls: Vec::new(),
+                landing: Landing::Confirmed,
+                lander: None,
+                responders: HashMap::new(),
+                executes: false,
+            })),
+        }
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, ClusterState> {
+        self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// A transport to this cluster
+    pub fn sender(&self) -> MockSender {
+        MockSender { cluster: self.clone() }
+    }
+
+    /// A client of this cluster built from `config`, whose `rpc_url` is only a label
+    pub fn client(&self, config: FinternetConfig) -> FinternetClient {
+        FinternetClient::with_rpc_sender(
+            FinternetConfig {
+                rpc_url: "mock://cluster".to_string(),
+                ..config
+            },
+            self.sender(),
+        )
+    }
+
+    /// A client of this cluster with the default configuration
+    pub fn default_client(&self) -> FinternetClient {
+        self.client(FinternetConfig::default())
+    }
+
+    /// Report this genesis hash, e.g. devnet's to make the client think it is on devnet
+    pub fn set_genesis_hash(&self, hash: Hash) {
+        self.state().genesis_hash = hash;
+    }
+
+    pub fn set_account(&self, address: Pubkey, account: Account) {
+        self.state().accounts.insert(address, account);
+    }
+
+    pub fn account(&self, address: &Pubkey) -> Option<Account> {
+        self.state().accounts.get(address).cloned()
+    }
+
+    pub fn set_balance(&self, address: Pubkey, lamports: u64) {
+        self.state()
+            .accounts
+            .entry(address)
+            .or_insert_with(|| Account::new(0, 0, &solana_sdk::system_program::id()))
+            .lamports = lamports;
+    }
+
+    /// Create a mint with `decimals` owned by `program` (SPL Token or Token-2022)
+    pub fn add_mint(&self, program: &Pubkey, decimals: u8, authority: &Pubkey) -> Pubkey {
+        let mint = Pubkey::new_unique();
+        let state = spl_token::state::Mint {
+            mint_authority: Some(*authority).into(),
+            supply: 0,
+            decimals,
+            is_initialized: true,
+            freeze_authority: None.into(),
+        };
+        let mut data = vec![0; spl_token::state::Mint::LEN];
+        spl_token::state::Mint::pack(state, &mut data).expect("mint packs");
+        self.set_account(mint, packed_account(data, program));
+        mint
+    }
+
+    /// Give `owner` a token account at its ATA holding `amount` of `mint`, returning the ATA
+    pub fn add_token_account(&self, owner: &Pubkey, mint: &Pubkey, amount: u64) -> Pubkey {
+        let program = self.account(mint).map(|account| account.owner).unwrap_or_else(spl_token::id);
+        let address = spl_associated_token_account::get_associated_token_address_with_program_id(owner, mint, &program);
+        self.set_token_account(address, owner, mint, amount);
+        address
+    }
+
+    /// Create or overwrite the token account at `address`
+    pub fn set_token_account(&self, address: Pubkey, owner: &Pubkey, mint: &Pubkey, amount: u64) {
+        let program = self.account(mint).map(|account| account.owner).unwrap_or_else(spl_token::id);
+        let state = spl_token::state::Account {
+            mint: *mint,
+            owner: *owner,
+            amount,
+            state: spl_token::state::AccountState::Initialized,
+            ..Default::default()
+        };
+        let mut data = vec![0; spl_token::state::Account::LEN];
+        spl_token::state::Account::pack(state, &mut data).expect("token account packs");
+        self.set_account(address, packed_account(data, &program));
+    }
+
+    /// Approve `delegate` to move `amount` out of the token account at `address`
+    pub fn set_token_delegate(&self, address: &Pubkey, delegate: Option<Pubkey>, amount: u64) {
+        let mut state = self.state();
+        let account = state.accounts.get_mut(address).expect("token account exists");
+        let mut token = spl_token::state::Account::unpack(&account.data).expect("token account unpacks");
+        token.delegate = delegate.into();
+        token.delegated_amount = amount;
+        spl_token::state::Account::pack(token, &mut account.data).expect("token account packs");
+    }
+
+    /// Create a durable nonce account controlled by `authority`, holding a fresh nonce
+    pub fn add_nonce_account(&self, authority: &Pubkey) -> Pubkey {
+        let address = Pubkey::new_unique();
+        self.advance_nonce(&address, authority);
+        address
+    }
+
+    /// Store a fresh nonce in the nonce account at `address`, as advancing it on-chain would,
+    /// and return it
+    pub fn advance_nonce(&self, address: &Pubkey, authority: &Pubkey) -> Hash {
+        let durable = DurableNonce::from_blockhash(&Hash::new_unique());
+        let state = NonceVersions::new(NonceState::Initialized(NonceData::new(*authority, durable, 5_000)));
+        let data = bincode::serialize(&state).expect("nonce state serializes");
+        self.set_account(*address, packed_account(data, &solana_sdk::system_program::id()));
+        *durable.as_hash()
+    }
+
+    /// What happens to transactions sent from now on
+    pub fn set_landing(&self, landing: Landing) {
+        let mut state = self.state();
+        state.landing = landing;
+        state.lander = None;
+    }
+
+    /// Decide what happens to each transaction sent from now on by looking at it
+    pub fn land_with(&self, lander: impl Fn(&Transaction) -> Landing + Send + Sync + 'static) {
+        self.state().lander = Some(Box::new(lander));
+    }
+
+    /// Apply what each transaction that lands from now on does to the accounts it touches
+    ///
+    /// Only system account creation and transfers, SPL Token mints, mint-tos, transfers, and
+    /// authority changes, and associated token account creation are applied; memos and other
+    /// programs change nothing. A transaction that cannot apply, e.g. for want of funds, fails
+    /// on-chain and changes nothing but the fee.
+    pub fn execute_sends(&self) {
+        self.state().executes = true;
+    }
+
+    /// Give `signature` a finalized status now, e.g. for a send left `Pending`
+    pub fn land(&self, signature: &Signature, error: Option<TransactionError>) {
+        let mut state = self.state();
+        let slot = state.slot;
+        state.statuses.insert(*signature, landed_status(slot, error));
+    }
+
+    /// Answer `method` with `respond` whenever it returns `Some`; `Err` is an RPC error message
+    pub fn respond(&self, method: &str, respond: impl Fn(&Value) -> Option<Result<Value, String>> + Send + Sync + 'static) {
+        self.state().responders.insert(method.to_string(), Box::new(respond));
+    }
+
+    /// Move to a new blockhash and stop accepting every earlier one
+    pub fn expire_blockhashes(&self) {
+        let mut state = self.state();
+        let blockhash = Hash::new_unique();
+        state.blockhash = blockhash;
+        state.valid_blockhashes = HashSet::from([blockhash]);
+        state.slot += BLOCKHASH_LIFETIME + 1;
+    }
+
+    /// Move to a new blockhash, keeping earlier ones valid
+    pub fn advance_blockhash(&self) -> Hash {
+        let mut state = self.state();
+        let blockhash = Hash::new_unique();
+        state.blockhash = blockhash;
+        state.valid_blockhashes.insert(blockhash);
+        state.slot += 1;
+        blockhash
+    }
+
+    /// Make the current slot's block time `unix_time`
+    pub fn set_time(&self, unix_time: i64) {
+        self.state().unix_time = unix_time;
+    }
+
+    /// The blockhash the cluster currently hands out
+    pub fn blockhash(&self) -> Hash {
+        self.state().blockhash
+    }
+
+    /// Every transaction the cluster accepted, in the order it was sent
+    pub fn sent(&self) -> Vec<Transaction> {
+        self.state().sent.clone()
+    }
+
+    /// Every RPC method called, in order
+    pub fn calls(&self) -> Vec<String> {
+        self.state().calls.clone()
+    }
+
+    /// How many times `method` was called
+    pub fn call_count(&self, method: &str) -> usize {
+        self.state().calls.iter().filter(|call| *call == method).count()
+    }
+
+    fn handle(&self, method: &str, params: &Value) -> Result<Value, String> {
+        let mut state = self.state();
+        state.calls.push(method.to_string());
+        if let Some(answer) = state.responders.get(method).and_then(|respond| respond(params)) {
+            return answer;
+        }
+        let context = json!({ "slot": state.slot });
+        let response = |value: Value| Ok(json!({ "context": context, "value": value }));
+        match method {
+            "getLatestBlockhash" => response(json!({
+                "blockhash": state.blockhash.to_string(),
+                "lastValidBlockHeight": state.slot + BLOCKHASH_LIFETIME,
+            })),
+            "isBlockhashValid" => {
+                let hash: Hash = parse_param(params, 0)?;
+                response(json!(state.valid_blockhashes.contains(&hash)))
+            }
+            "getSlot" | "getBlockHeight" => Ok(json!(state.slot)),
+            "getEpochInfo" => Ok(json!({
+                "absoluteSlot": state.slot,
+                "blockHeight": state.slot,
+                "epoch": state.slot / SLOTS_PER_EPOCH,
+                "slotIndex": state.slot % SLOTS_PER_EPOCH,
+                "slotsInEpoch": SLOTS_PER_EPOCH,
+                "transactionCount": null,
+            })),
+            "getBlockTime" => {
+                let slot = params.get(0).and_then(Value::as_u64).ok_or("getBlockTime expects a slot")?;
+                if slot > state.slot {
+                    return Err(format!("Block not available for slot {}", slot));
+                }
+                Ok(json!(state.unix_time - (state.slot - slot) as i64 * MS_PER_SLOT / 1000))
+            }
+            "getSignaturesForAddress" => {
+                let address: Pubkey = parse_param(params, 0)?;
+                let signatures: Vec<Value> = state
+                    .sent
+                    .iter()
+                    .rev()
+                    .filter(|transaction| transaction.message.account_keys.contains(&address))
+                    .filter_map(|transaction| {
+                        let signature = transaction.signatures.first()?;
+                        let status = state.statuses.get(signature)?;
+                        Some(json!({
+                            "signature": signature.to_string(),
+                            "slot": status.slot,
+                            "err": status.err,
+                            "memo": memo_of(transaction),
+                            "blockTime": state.unix_time,
+                            "confirmationStatus": "finalized",
+                        }))
+                    })
+                    .collect();
+                Ok(Value::Array(signatures))
+            }
+            "getTransaction" => {
+                let signature: Signature = parse_param(params, 0)?;
+                let landed = state
+                    .sent
+                    .iter()
+                    .find(|transaction| transaction.signatures.first() == Some(&signature))
+                    .zip(state.statuses.get(&signature));
+                let Some((transaction, status)) = landed else {
+                    return Ok(Value::Null);
+                };
+                let encoded = EncodedConfirmedTransactionWithStatusMeta {
+                    slot: status.slot,
+                    transaction: EncodedTransactionWithStatusMeta {
+                        transaction: transaction.encode(UiTransactionEncoding::Json),
+                        meta: None,
+                        version: None,
+                    },
+                    block_time: Some(state.unix_time - (state.slot - status.slot) as i64 * MS_PER_SLOT / 1000),
+                };
+                let mut encoded = serde_json::to_value(encoded).map_err(|e| e.to_string())?;
+                if let Some(meta) = state.metas.get(&signature) {
+                    encoded["meta"] = meta.clone();
+                }
+                Ok(encoded)
+            }
+            "getGenesisHash" => Ok(json!(state.genesis_hash.to_string())),
+            "getVersion" => Ok(json!({ "solana-core": "1.18.26", "feature-set": 0 })),
+            "getBalance" => {
+                let address: Pubkey = parse_param(params, 0)?;
+                response(json!(state.accounts.get(&address).map_or(0, |account| account.lamports)))
+            }
+            "getAccountInfo" => {
+                let address: Pubkey = parse_param(params, 0)?;
+                response(encode_account(&address, state.accounts.get(&address)))
+            }
+            "getMultipleAccounts" => {
+                let addresses = params
+                    .get(0)
+                    .and_then(Value::as_array)
+                    .ok_or("getMultipleAccounts expects a list of addresses")?;
+                let mut accounts = Vec::with_capacity(addresses.len());
+                for address in addresses {
+                    let address: Pubkey = address.as_str().and_then(|a| a.parse().ok()).ok_or("invalid address")?;
+                    accounts.push(encode_account(&address, state.accounts.get(&address)));
+                }
+                response(Value::Array(accounts))
+            }
+            "getProgramAccounts" => {
+                let program: Pubkey = parse_param(params, 0)?;
+                let filters = params.get(1).and_then(|config| config.get("filters")).and_then(Value::as_array);
+                let mut matching: Vec<(&Pubkey, &Account)> = state
+                    .accounts
+                    .iter()
+                    .filter(|(_, account)| account.owner == program)
+                    .filter(|(_, account)| filters.is_none_or(|filters| filters.iter().all(|filter| filter_matches(filter, &account.data))))
+                    .collect();
+                matching.sort_by_key(|(address, _)| **address);
+                let accounts: Vec<Value> = matching
+                    .into_iter()
+                    .map(|(address, account)| json!({ "pubkey": address.to_string(), "account": encode_account(address, Some(account)) }))
+                    .collect();
+                Ok(Value::Array(accounts))
+            }
+            "getTokenAccountsByOwner" => {
+                let owner: Pubkey = parse_param(params, 0)?;
+                let filter = params.get(1).ok_or("getTokenAccountsByOwner expects a mint or program filter")?;
+                let by_address = |key: &str| filter.get(key).and_then(Value::as_str).and_then(|a| a.parse::<Pubkey>().ok());
+                let (mint, program) = match (by_address("mint"), by_address("programId")) {
+                    (Some(mint), _) => (Some(mint), state.accounts.get(&mint).map_or(spl_token::id(), |account| account.owner)),
+                    (None, Some(program)) => (None, program),
+                    (None, None) => return Err("getTokenAccountsByOwner expects a mint or program filter".to_string()),
+                };
+                let mut matching: Vec<(&Pubkey, &Account)> = state
+                    .accounts
+                    .iter()
+                    .filter(|(_, account)| account.owner == program && account.data.len() >= spl_token::state::Account::LEN)
+                    .filter(|(_, account)| {
+                        spl_token::state::Account::unpack_unchecked(&account.data[..spl_token::state::Account::LEN])
+                            .is_ok_and(|token| token.owner == owner && mint.is_none_or(|mint| token.mint == mint))
+                    })
+                    .collect();
+                matching.sort_by_key(|(address, _)| **address);
+                let accounts: Vec<Value> = matching
+                    .into_iter()
+                    .map(|(address, account)| json!({ "pubkey": address.to_string(), "account": encode_account(address, Some(account)) }))
+                    .collect();
+                response(Value::Array(accounts))
+            }
+            "getMinimumBalanceForRentExemption" => {
+                let size = params.get(0).and_then(Value::as_u64).unwrap_or(0);
+                Ok(json!(Rent::default().minimum_balance(size as usize)))
+            }
+            "getFeeForMessage" => response(json!(5_000)),
+            "getRecentPrioritizationFees" => Ok(json!([])),
+            "getTokenAccountBalance" => {
+                let address: Pubkey = parse_param(params, 0)?;
+                let token = state
+                    .accounts
+                    .get(&address)
+                    .and_then(|account| spl_token::state::Account::unpack_from_slice(&account.data).ok())
+                    .ok_or_else(|| format!("Invalid param: could not find account {}", address))?;
+                let decimals = state
+                    .accounts
+                    .get(&token.mint)
+                    .and_then(|account| spl_token::state::Mint::unpack_from_slice(&account.data).ok())
+                    .map_or(0, |mint| mint.decimals);
+                response(ui_token_amount(token.amount, decimals))
+            }
+            "getTokenSupply" => {
+                let address: Pubkey = parse_param(params, 0)?;
+                let mint = state
+                    .accounts
+                    .get(&address)
+                    .and_then(|account| spl_token::state::Mint::unpack_from_slice(&account.data).ok())
+                    .ok_or_else(|| format!("Invalid param: not a Token mint {}", address))?;
+                response(ui_token_amount(mint.supply, mint.decimals))
+            }
+            "simulateTransaction" => response(json!({ "err": null, "logs": [], "accounts": null, "unitsConsumed": 0 })),
+            "getSignatureStatuses" => {
+                let signatures = params
+                    .get(0)
+                    .and_then(Value::as_array)
+                    .ok_or("getSignatureStatuses expects a list of signatures")?;
+                if signatures.len() > 256 {
+                    return Err("Too many inputs provided; max 256".to_string());
+                }
+                let mut statuses = Vec::with_capacity(signatures.len());
+                for signature in signatures {
+                    let signature: Signature =
+                        signature.as_str().and_then(|s| s.parse().ok()).ok_or("invalid signature")?;
+                    statuses.push(serde_json::to_value(state.statuses.get(&signature)).map_err(|e| e.to_string())?);
+                }
+                response(Value::Array(statuses))
+            }
+            "sendTransaction" => {
+                let transaction = decode_transaction(params)?;
+                let signature = transaction.signatures.first().copied().ok_or("transaction is unsigned")?;
+                if transaction.verify().is_err() {
+                    return Err("Transaction signature verification failure".to_string());
+                }
+                if !state.valid_blockhashes.contains(&transaction.message.recent_blockhash)
+                    && !state.accounts.values().any(|account| nonce_matches(account, &transaction.message.recent_blockhash))
+                {
+                    return Err("Transaction simulation failed: Blockhash not found".to_string());
+                }
+                let landing = match &state.lander {
+                    Some(lander) => lander(&transaction),
+                    None => state.landing.clone(),
+                };
+                let slot = state.slot;
+                match landing {
+                    Landing::Refused(message) => return Err(message),
+                    Landing::Confirmed if state.executes => {
+                        let keys = &transaction.message.account_keys;
+                        let before = balances(&state.accounts, keys);
+                        let error = execute(&mut state.accounts, &transaction).err();
+                        let meta = executed_meta(before, balances(&state.accounts, keys), error.as_ref());
+                        state.metas.insert(signature, meta);
+                        state.statuses.insert(signature, landed_status(slot, error));
+                    }
+                    Landing::Confirmed => {
+                        state.statuses.insert(signature, landed_status(slot, None));
+                    }
+                    Landing::Failed(error) => {
+                        state.statuses.insert(signature, landed_status(slot, Some(error)));
+                    }
+                    Landing::Pending => {}
+                }
+                state.sent.push(transaction);
+                Ok(json!(signature.to_string()))
+            }
+            "requestAirdrop" => {
+                let address: Pubkey = parse_param(params, 0)?;
+                let lamports = params.get(1).and_then(Value::as_u64).unwrap_or(0);
+                state
+                    .accounts
+                    .entry(address)
+                    .or_insert_with(|| Account::new(0, 0, &solana_sdk::system_program::id()))
+                    .lamports += lamports;
+                let signature = Signature::new_unique();
+                let slot = state.slot;
+                state.statuses.insert(signature, landed_status(slot, None));
+                Ok(json!(signature.to_string()))
+            }
+            other => Err(format!("Mock cluster does not serve {}", other)),
+        }
+    }
+}
+
+impl Default for MockCluster {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// RPC transport to a `MockCluster`
+pub(crate) struct MockSender {
+    cluster: MockCluster,
+}
+
+#[async_trait::async_trait]
+impl RpcSender for MockSender {
+    async fn send(&self, request: RpcRequest, params: Value) -> ClientResult<Value> {
+        self.cluster.handle(&request.to_string(), &params).map_err(|message| {
+            ClientError::new_with_request(
+                ClientErrorKind::RpcError(RpcError::RpcResponseError {
+                    code: -32002,
+                    message,
+                    data: RpcResponseErrorData::Empty,
+                }),
+                request,
+            )
+        })
+    }
+
+    fn get_transport_stats(&self) -> RpcTransportStats {
+        RpcTransportStats::default()
+    }
+
+    fn url(&self) -> String {
+        "mock://cluster".to_string()
+    }
+}
+
+/// Fee `execute` charges the fee payer of every transaction
+const FEE_LAMPORTS: u64 = 5_000;
+
+/// Apply `transaction` to `accounts`, all of it or, when an instruction fails, only its fee
+fn execute(accounts: &mut HashMap<Pubkey, Account>, transaction: &Transaction) -> Result<(), TransactionError> {
+    let keys = &transaction.message.account_keys;
+    let payer = accounts.get_mut(&keys[0]).filter(|payer| payer.lamports >= FEE_LAMPORTS);
+    payer.ok_or(TransactionError::InsufficientFundsForFee)?.lamports -= FEE_LAMPORTS;
+
+    let mut after = accounts.clone();
+    for (index, instruction) in transaction.message.instructions.iter().enumerate() {
+        let program = keys[instruction.program_id_index as usize];
+        let metas: Vec<Pubkey> = instruction.accounts.iter().map(|account| keys[*account as usize]).collect();
+        execute_instruction(&mut after, &program, &metas, &instruction.data)
+            .map_err(|error| TransactionError::InstructionError(index as u8, error))?;
+    }
+    *accounts = after;
+    Ok(())
+}
+
+/// Lamports of each of `keys`, and the token balances of those that are token accounts
+fn balances(accounts: &HashMap<Pubkey, Account>, keys: &[Pubkey]) -> (Vec<u64>, Vec<Value>) {
+    let lamports = keys.iter().map(|key| accounts.get(key).map_or(0, |account| account.lamports)).collect();
+    let tokens = keys
+        .iter()
+        .enumerate()
+        .filter_map(|(index, key)| {
+            let account = accounts.get(key).filter(|account| account.owner == spl_token::id())?;
+            let token = spl_token::state::Account::unpack(&account.data).ok()?;
+            let decimals = accounts
+                .get(&token.mint)
+                .and_then(|mint| spl_token::state::Mint::unpack(&mint.data).ok())
+                .map_or(0, |mint| mint.decimals);
+            Some(json!({
+                "accountIndex": index,
+                "mint": token.mint.to_string(),
+                "owner": token.owner.to_string(),
+                "programId": spl_token::id().to_string(),
+                "uiTokenAmount": ui_token_amount(token.amount, decimals),
+            }))
+        })
+        .collect();
+    (lamports, tokens)
+}
+
+fn executed_meta(before: (Vec<u64>, Vec<Value>), after: (Vec<u64>, Vec<Value>), error: Option<&TransactionError>) -> Value {
+    json!({
+        "err": error,
+        "status": match error {
+            Some(error) => json!({ "Err": error }),
+            None => json!({ "Ok": null }),
+        },
+        "fee": FEE_LAMPORTS,
+        "preBalances": before.0,
+        "postBalances": after.0,
+        "preTokenBalances": before.1,
+        "postTokenBalances": after.1,
+    })
+}
+
+fn execute_instruction(
+    accounts: &mut HashMap<Pubkey, Account>,
+    program: &Pubkey,
+    metas: &[Pubkey],
+    data: &[u8],
+) -> Result<(), InstructionError> {
+    let account = |index: usize| metas.get(index).copied().ok_or(InstructionError::NotEnoughAccountKeys);
+    if *program == solana_sdk::system_program::id() {
+        match bincode::deserialize(data).map_err(|_| InstructionError::InvalidInstructionData)? {
+            SystemInstruction::CreateAccount { lamports, space, owner } => {
+                let address = account(1)?;
+                if accounts.get(&address).is_some_and(|existing| !existing.data.is_empty() || existing.owner != solana_sdk::system_program::id()) {
+                    return Err(InstructionError::AccountAlreadyInitialized);
+                }
+                move_lamports(accounts, &account(0)?, &address, lamports)?;
+                let created = accounts.get_mut(&address).expect("credited account exists");
+                created.data = vec![0; space as usize];
+                created.owner = owner;
+            }
+            SystemInstruction::Transfer { lamports } => move_lamports(accounts, &account(0)?, &account(1)?, lamports)?,
+            _ => {}
+        }
+    } else if *program == spl_token::id() {
+        match TokenInstruction::unpack(data).map_err(|_| InstructionError::InvalidInstructionData)? {
+            TokenInstruction::InitializeMint { decimals, mint_authority, freeze_authority }
+            | TokenInstruction::InitializeMint2 { decimals, mint_authority, freeze_authority } => {
+                update_packed(accounts, &account(0)?, |mint: &mut spl_token::state::Mint| {
+                    if mint.is_initialized {
+                        return Err(InstructionError::AccountAlreadyInitialized);
+                    }
+                    *mint = spl_token::state::Mint {
+                        mint_authority: Some(mint_authority).into(),
+                        supply: 0,
+                        decimals,
+                        is_initialized: true,
+                        freeze_authority,
+                    };
+                    Ok(())
+                })?;
+            }
+            TokenInstruction::MintTo { amount } | TokenInstruction::MintToChecked { amount, .. } => {
+                update_packed(accounts, &account(0)?, |mint: &mut spl_token::state::Mint| {
+                    mint.supply = mint.supply.checked_add(amount).ok_or(InstructionError::ArithmeticOverflow)?;
+                    Ok(())
+                })?;
+                update_packed(accounts, &account(1)?, |token: &mut spl_token::state::Account| {
+                    token.amount += amount;
+                    Ok(())
+                })?;
+            }
+            TokenInstruction::Transfer { amount } => move_tokens(accounts, &account(0)?, &account(1)?, amount)?,
+            TokenInstruction::TransferChecked { amount, .. } => move_tokens(accounts, &account(0)?, &account(2)?, amount)?,
+            TokenInstruction::SetAuthority { authority_type, new_authority } => {
+                update_packed(accounts, &account(0)?, |mint: &mut spl_token::state::Mint| {
+                    match authority_type {
+                        AuthorityType::MintTokens => mint.mint_authority = new_authority,
+                        AuthorityType::FreezeAccount => mint.freeze_authority = new_authority,
+                        _ => return Err(InstructionError::InvalidArgument),
+                    }
+                    Ok(())
+                })?;
+            }
+            _ => {}
+        }
+    } else if *program == spl_associated_token_account::id() {
+        // Create and CreateIdempotent both take payer, account, wallet, mint, system, token program
+        let (payer, address, wallet, mint) = (account(0)?, account(1)?, account(2)?, account(3)?);
+        if accounts.get(&address).is_some_and(|existing| !existing.data.is_empty()) {
+            return if data.first() == Some(&1) { Ok(()) } else { Err(InstructionError::AccountAlreadyInitialized) };
+        }
+        let token = packed_account(vec![0; spl_token::state::Account::LEN], &account(5)?);
+        move_lamports(accounts, &payer, &address, token.lamports)?;
+        let created = accounts.get_mut(&address).expect("credited account exists");
+        created.data = token.data;
+        created.owner = token.owner;
+        update_packed(accounts, &address, |token: &mut spl_token::state::Account| {
+            *token = spl_token::state::Account {
+                mint,
+                owner: wallet,
+                state: spl_token::state::AccountState::Initialized,
+                ..Default::default()
+            };
+            Ok(())
+        })?;
+    }
+    Ok(())
+}
+
+fn move_lamports(accounts: &mut HashMap<Pubkey, Account>, from: &Pubkey, to: &Pubkey, lamports: u64) -> Result<(), InstructionError> {
+    let source = accounts.get_mut(from).filter(|source| source.lamports >= lamports);
+    source.ok_or(InstructionError::Custom(1))?.lamports -= lamports;
+    accounts
+        .entry(*to)
+        .or_insert_with(|| Account::new(0, 0, &solana_sdk::system_program::id()))
+        .lamports += lamports;
+    Ok(())
+}
+
+fn move_tokens(accounts: &mut HashMap<Pubkey, Account>, from: &Pubkey, to: &Pubkey, amount: u64) -> Result<(), InstructionError> {
+    update_packed(accounts, from, |token: &mut spl_token::state::Account| {
+        token.amount = token.amount.checked_sub(amount).ok_or(InstructionError::Custom(1))?;
+        Ok(())
+    })?;
+    update_packed(accounts, to, |token: &mut spl_token::state::Account| {
+        token.amount += amount;
+        Ok(())
+    })
+}
+
+/// Change the packed state of the account at `address` through `update`
+fn update_packed<T: Pack + IsInitialized>(
+    accounts: &mut HashMap<Pubkey, Account>,
+    address: &Pubkey,
+    update: impl FnOnce(&mut T) -> Result<(), InstructionError>,
+) -> Result<(), InstructionError> {
+    let account = accounts.get_mut(address).ok_or(InstructionError::UninitializedAccount)?;
+    let mut state = T::unpack_unchecked(&account.data).map_err(|_| InstructionError::InvalidAccountData)?;
+    update(&mut state)?;
+    T::pack(state, &mut account.data).map_err(|_| InstructionError::InvalidAccountData)
+}
+
+fn packed_account(data: Vec<u8>, owner: &Pubkey) -> Account {
+    Account {
+        lamports: Rent::default().minimum_balance(data.len()),
+        data,
+        owner: *owner,
+        executable: false,
+        rent_epoch: 0,
+    }
+}
+
+fn landed_status(slot: u64, err: Option<TransactionError>) -> TransactionStatus {
+    TransactionStatus {
+        slot,
+        confirmations: None,
+        status: err.clone().map_or(Ok(()), Err),
+        err,
+        confirmation_status: Some(TransactionConfirmationStatus::Finalized),
+    }
+}
+
+pub(crate) fn encode_account(address: &Pubkey, account: Option<&Account>) -> Value {
+    match account {
+        Some(account) => json!(UiAccount::encode(address, account, UiAccountEncoding::Base64, None, None)),
+        None => Value::Null,
+    }
+}
+
+fn ui_token_amount(amount: u64, decimals: u8) -> Value {
+    let ui_amount = amount as f64 / 10f64.powi(decimals as i32);
+    json!({
+        "amount": amount.to_string(),
+        "decimals": decimals,
+        "uiAmount": ui_amount,
+        "uiAmountString": ui_amount.to_string(),
+    })
+}
+
+/// Memo text as `getSignaturesForAddress` reports it, when the transaction carries one
+fn memo_of(transaction: &Transaction) -> Option<String> {
+    let memos: Vec<String> = transaction
+        .message
+        .instructions
+        .iter()
+        .filter(|instruction| transaction.message.account_keys[instruction.program_id_index as usize] == spl_memo::id())
+        .map(|instruction| format!("[{}] {}", instruction.data.len(), String::from_utf8_lossy(&instruction.data)))
+        .collect();
+    (!memos.is_empty()).then(|| memos.join("; "))
+}
+
+/// Whether account `data` passes one `getProgramAccounts` filter (`dataSize` or `memcmp`)
+fn filter_matches(filter: &Value, data: &[u8]) -> bool {
+    if let Some(size) = filter.get("dataSize").and_then(Value::as_u64) {
+        return data.len() as u64 == size;
+    }
+    let Some(memcmp) = filter.get("memcmp") else {
+        return false;
+    };
+    let offset = memcmp.get("offset").and_then(Value::as_u64).unwrap_or(0) as usize;
+    let encoded = memcmp.get("bytes").and_then(Value::as_str).unwrap_or_default();
+    let bytes = match memcmp.get("encoding").and_then(Value::as_str) {
+        Some("base64") => base64::Engine::decode(&base64::engine::general_purpose::STANDARD, encoded).ok(),
+        _ => bs58::decode(encoded).into_vec().ok(),
+    };
+    bytes.is_some_and(|bytes| data.get(offset..offset + bytes.len()) == Some(bytes.as_slice()))
+}
+
+/// Whether `account` is a nonce account whose stored value is `blockhash`
+fn nonce_matches(account: &Account, blockhash: &Hash) -> bool {
+    let account = solana_sdk::account::AccountSharedData::from(account.clone());
+    solana_sdk::nonce_account::verify_nonce_account(&account, blockhash).is_some()
+}
+
+fn parse_param<T: std::str::FromStr>(params: &Value, index: usize) -> Result<T, String> {
+    params
+        .get(index)
+        .and_then(Value::as_str)
+        .and_then(|text| text.parse().ok())
+        .ok_or_else(|| format!("Invalid param at position {}", index))
+}
+
+pub(crate) fn decode_transaction(params: &Value) -> Result<Transaction, String> {
+    use base64::{engine::general_purpose, Engine};
+
+    let encoded = params.get(0).and_then(Value::as_str).ok_or("sendTransaction expects a transaction")?;
+    let encoding = params.get(1).and_then(|config| config.get("encoding")).and_then(Value::as_str);
+    let bytes = match encoding {
+        Some("base64") => general_purpose::STANDARD.decode(encoded).map_err(|e| e.to_string())?,
+        _ => bs58::decode(encoded).into_vec().map_err(|e| e.to_string())?,
+    };
+    bincode::deserialize(&bytes).map_err(|e| e.to_string())
+}
+
+/// A directory under the system temp dir, removed when dropped
+pub(crate) struct TempDir {
+    path: PathBuf,
+}
+
+impl TempDir {
+    pub fn new(label: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("finternet-{}-{}", label, uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&path).expect("temp dir is writable");
+        Self { path }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn join(&self, name: &str) -> PathBuf {
+        self.path.join(name)
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.path);
+    }
+}
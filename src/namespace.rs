@@ -0,0 +1,254 @@
+//! Memo namespaces, so other apps' entries on the same addresses are never read as ours
+//!
+//! Every ledger entry the client writes is prefixed `<namespace>/v1:`, `finternet/v1:` by
+//! default, before any chunking or compression. `read_ledger_entries`, and every state rebuilt
+//! from it (identities, credentials, policies, organizations, escrows, claims, redemptions,
+//! ledger metadata), keeps only entries of the configured namespace or a trusted one, with the
+//! prefix stripped. Entries of any other namespace are dropped unread. Un-prefixed entries from
+//! earlier releases are dropped too unless `accept_legacy_entries` is set, and
+//! `migrate_legacy_entries` writes a wallet's own legacy entries again under the namespace.
+
+use crate::chunking;
+use crate::ledger::LedgerEntry;
+use crate::telemetry::readable;
+use crate::{FinternetClient, FinternetConfig};
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use solana_sdk::{
+    pubkey::Pubkey,
+    signature::{Keypair, Signature},
+    signer::Signer,
+};
+use std::collections::HashSet;
+
+pub const DEFAULT_NAMESPACE: &str = "finternet";
+/// Format version written after the namespace; entries of other versions are not read
+pub const ENTRY_VERSION: u32 = 1;
+
+/// What a memo's prefix says about who wrote it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntryPrefix<'a> {
+    Namespaced {
+        namespace: &'a str,
+        version: u32,
+        body: &'a str,
+    },
+    /// No namespace prefix: written before namespaces existed, or by an app without them
+    Legacy(&'a str),
+}
+
+/// Split a memo into its namespace, version, and body
+///
+/// A prefix is `<namespace>/v<version>:`, where the namespace is lowercase ASCII letters,
+/// digits, `.`, `_`, or `-`. Anything else is `Legacy`.
+pub fn parse_entry(data: &str) -> EntryPrefix<'_> {
+    let namespaced = data.split_once(':').and_then(|(prefix, body)| {
+        let (namespace, version) = prefix.split_once("/v")?;
+        if !is_valid_namespace(namespace) || version.is_empty() || !version.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        Some(EntryPrefix::Namespaced {
+            namespace,
+            version: version.parse().ok()?,
+            body,
+        })
+    });
+    namespaced.unwrap_or(EntryPrefix::Legacy(data))
+}
+
+/// `body` prefixed for `namespace` at the current version
+pub fn namespaced(namespace: &str, body: &str) -> Result<String> {
+    if !is_valid_namespace(namespace) {
+        return Err(anyhow!(
+            "Invalid ledger namespace '{}'; use lowercase letters, digits, '.', '_', or '-'",
+            namespace
+        ));
+    }
+    Ok(format!("{}/v{}:{}", namespace, ENTRY_VERSION, body))
+}
+
+fn is_valid_namespace(namespace: &str) -> bool {
+    !namespace.is_empty()
+        && namespace
+            .bytes()
+            .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || matches!(b, b'.' | b'_' | b'-'))
+}
+
+impl FinternetConfig {
+    /// Write and read ledger entries under `namespace` instead of `finternet`
+    pub fn with_namespace(mut self, namespace: &str) -> Self {
+        self.namespace = namespace.to_string();
+        self
+    }
+
+    /// Read entries of these namespaces too, e.g. another deployment's while moving off it
+    pub fn with_trusted_namespaces(mut self, namespaces: Vec<String>) -> Self {
+        self.trusted_namespaces = namespaces;
+        self
+    }
+
+    /// Read un-prefixed entries written before namespaces existed, until they are migrated
+    pub fn with_legacy_entries(mut self, accept: bool) -> Self {
+        self.accept_legacy_entries = accept;
+        self
+    }
+}
+
+/// One legacy entry `migrate_legacy_entries` wrote again
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MigratedEntry {
+    /// The transaction carrying the legacy entry
+    pub original: Signature,
+    pub signatures: Vec<Signature>,
+}
+
+/// A legacy entry `migrate_legacy_entries` left alone, and why
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SkippedEntry {
+    pub signature: Signature,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LegacyMigration {
+    pub address: Pubkey,
+    pub namespace: String,
+    /// Oldest first, the order they were written again in
+    pub migrated: Vec<MigratedEntry>,
+    pub skipped: Vec<SkippedEntry>,
+}
+
+impl FinternetClient {
+    pub fn namespace(&self) -> &str {
+        &self.config.namespace
+    }
+
+    /// `body` as the ledger entry this client writes
+    pub(crate) fn namespaced_entry(&self, body: &str) -> Result<String> {
+        namespaced(&self.config.namespace, body)
+    }
+
+    /// The body of `data` if it is one of this client's entries
+    pub(crate) fn entry_body<'a>(&self, data: &'a str) -> Option<&'a str> {
+        match parse_entry(data) {
+            EntryPrefix::Namespaced { namespace, version, body } if self.trusts_namespace(namespace) => {
+                if version == ENTRY_VERSION {
+                    Some(body)
+                } else {
+                    tracing::debug!("Ignoring {} entry of unsupported version {}", namespace, version);
+                    None
+                }
+            }
+            EntryPrefix::Namespaced { .. } => None,
+            EntryPrefix::Legacy(body) => self.config.accept_legacy_entries.then_some(body),
+        }
+    }
+
+    /// `entry` with its prefix stripped, or `None` when it belongs to someone else
+    pub(crate) fn accept_entry(&self, mut entry: LedgerEntry) -> Option<LedgerEntry> {
+        let namespace = match parse_entry(&entry.data) {
+            EntryPrefix::Namespaced { namespace, .. } => Some(namespace.to_string()),
+            EntryPrefix::Legacy(_) => None,
+        };
+        entry.data = self.entry_body(&entry.data)?.to_string();
+        entry.namespace = namespace;
+        Some(entry)
+    }
+
+    fn trusts_namespace(&self, namespace: &str) -> bool {
+        namespace == self.config.namespace || self.config.trusted_namespaces.iter().any(|trusted| trusted == namespace)
+    }
+
+    /// Write `wallet`'s un-prefixed JSON entries on `address` again under the namespace
+    ///
+    /// Only entries `wallet` signed can be written again with the same meaning, so entries of
+    /// other signers, and plain-text memos, are skipped. So is any entry whose body this
+    /// namespace already holds, which makes running it twice harmless. The new entries reference
+    /// `address` so they appear where the old ones did; run it before writing new entries there,
+    /// since readers that replay history see the migrated entries as the newest.
+    pub async fn migrate_legacy_entries(
+        &self,
+        wallet: &Keypair,
+        address: &Pubkey,
+        limit: Option<usize>,
+    ) -> Result<LegacyMigration> {
+        let outcome = self.migrate_legacy_entries_unlogged(wallet, address, limit).await;
+        let parameters = serde_json::json!({
+            "wallet": wallet.pubkey().to_string(),
+            "address": address.to_string(),
+            "namespace": self.config.namespace,
+            "migrated": outcome.as_ref().ok().map(|migration| migration.migrated.len()),
+        });
+        self.log_action("migrate_legacy_entries", parameters, None, outcome.as_ref(), |migration| {
+            let signatures: Vec<String> = migration
+                .migrated
+                .iter()
+                .flat_map(|entry| &entry.signatures)
+                .map(Signature::to_string)
+                .collect();
+            signatures.join(",")
+        });
+        outcome
+    }
+
+    #[tracing::instrument(skip_all, fields(wallet = %readable(&wallet.pubkey()), address = %readable(address), namespace = %self.config.namespace))]
+    async fn migrate_legacy_entries_unlogged(
+        &self,
+        wallet: &Keypair,
+        address: &Pubkey,
+        limit: Option<usize>,
+    ) -> Result<LegacyMigration> {
+        let entries = self.read_raw_ledger_entries(address, limit).await?;
+        let migrated_bodies: HashSet<&str> = entries
+            .iter()
+            .filter_map(|entry| match parse_entry(&entry.data) {
+                EntryPrefix::Namespaced { namespace, version: ENTRY_VERSION, body } if namespace == self.config.namespace => {
+                    Some(body)
+                }
+                _ => None,
+            })
+            .collect();
+
+        let mut migration = LegacyMigration {
+            address: *address,
+            namespace: self.config.namespace.clone(),
+            migrated: Vec::new(),
+            skipped: Vec::new(),
+        };
+        // Oldest first, so entries replayed in order keep their order
+        for entry in entries.iter().rev() {
+            let EntryPrefix::Legacy(body) = parse_entry(&entry.data) else { continue };
+            let skip = |reason: &str| SkippedEntry { signature: entry.signature, reason: reason.to_string() };
+            if entry.is_partial() {
+                migration.skipped.push(skip("some of its chunks are missing"));
+                continue;
+            }
+            let is_record = serde_json::from_str::<serde_json::Value>(body)
+                .is_ok_and(|value| value.get("action").is_some_and(|action| action.is_string()));
+            if !is_record {
+                migration.skipped.push(skip("not a JSON ledger record"));
+                continue;
+            }
+            if entry.signer != Some(wallet.pubkey()) {
+                migration.skipped.push(skip("signed by another wallet"));
+                continue;
+            }
+            if migrated_bodies.contains(body) {
+                migration.skipped.push(skip("already migrated"));
+                continue;
+            }
+
+            let signatures = if address == &wallet.pubkey() {
+                self.write_ledger_entry_chunked(wallet, body, false).await?
+            } else if self.namespaced_entry(body)?.len() <= chunking::MAX_SINGLE_ENTRY_LEN {
+                vec![self.write_ledger_entry_referencing(wallet, body, &[*address]).await?]
+            } else {
+                migration.skipped.push(skip("too long to reference another address"));
+                continue;
+            };
+            tracing::info!("Migrated legacy entry {} under {}", entry.signature, self.config.namespace);
+            migration.migrated.push(MigratedEntry { original: entry.signature, signatures });
+        }
+        Ok(migration)
+    }
+}
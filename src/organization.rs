@@ -0,0 +1,585 @@
+//! Organizations: wallets that operate a shared treasury, each with a role
+//!
+//! An organization lives on the ledger like an identity does. Its id is an address derived from
+//! the creating wallet and the name, and every org entry lists that address as a reference, so
+//! `get_organization` finds them all in its history. Anyone can write an entry that names an
+//! organization; replaying the entries oldest first accepts only membership changes signed by
+//! a wallet that was an admin at that point, and reports the rest as rejected.
+
+use crate::ledger::LedgerEntry;
+use crate::policy::SpendRequest;
+use crate::telemetry::readable;
+use crate::FinternetClient;
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use solana_sdk::{
+    pubkey::Pubkey,
+    signature::{Keypair, Signature},
+    signer::Signer,
+};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+pub(crate) const ORG_CREATE_ACTION: &str = "org_create";
+pub(crate) const ORG_ADD_MEMBER_ACTION: &str = "org_add_member";
+pub(crate) const ORG_REMOVE_MEMBER_ACTION: &str = "org_remove_member";
+
+/// Most org entries `get_organization` replays
+const MAX_ORG_ENTRIES: usize = 1000;
+
+/// What a member may do, each role including the ones before it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OrgRole {
+    /// Sees balances and history
+    Viewer,
+    /// Also sends payments
+    Operator,
+    /// Also changes the membership
+    Admin,
+}
+
+impl OrgRole {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Viewer => "viewer",
+            Self::Operator => "operator",
+            Self::Admin => "admin",
+        }
+    }
+}
+
+impl FromStr for OrgRole {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "viewer" => Ok(Self::Viewer),
+            "operator" => Ok(Self::Operator),
+            "admin" => Ok(Self::Admin),
+            other => Err(anyhow!("Unknown organization role '{}': use viewer, operator, or admin", other)),
+        }
+    }
+}
+
+impl fmt::Display for OrgRole {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// The id of the organization `creator` creates as `name`
+pub fn organization_id(creator: &Pubkey, name: &str) -> Pubkey {
+    let mut hasher = Sha256::new();
+    hasher.update(b"finternet-org-v1:");
+    hasher.update(creator.as_ref());
+    hasher.update(name.as_bytes());
+    Pubkey::new_from_array(hasher.finalize().into())
+}
+
+/// An org entry that replay did not apply
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RejectedOrgChange {
+    pub signature: Signature,
+    pub signer: Option<Pubkey>,
+    pub action: String,
+    pub reason: String,
+}
+
+/// An organization's membership as the ledger has it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Organization {
+    pub id: Pubkey,
+    pub name: String,
+    pub creator: Pubkey,
+    pub created_at: u64,
+    /// Keyed by member address
+    #[serde(with = "crate::pubkey_map")]
+    pub members: BTreeMap<Pubkey, OrgRole>,
+    /// Entries naming the organization that were not applied, oldest first
+    pub rejected: Vec<RejectedOrgChange>,
+}
+
+impl Organization {
+    pub fn role_of(&self, member: &Pubkey) -> Option<OrgRole> {
+        self.members.get(member).copied()
+    }
+
+    /// Whether `member` holds `role` or a higher one
+    pub fn has_role(&self, member: &Pubkey, role: OrgRole) -> bool {
+        self.role_of(member).is_some_and(|held| held >= role)
+    }
+
+    fn admin_count(&self) -> usize {
+        self.members.values().filter(|role| **role == OrgRole::Admin).count()
+    }
+
+    /// Why `signer` may not give `member` the role `role` (`None` removes them), if they may not
+    fn check_change(&self, signer: &Pubkey, member: &Pubkey, role: Option<OrgRole>) -> Option<OrganizationError> {
+        if !self.has_role(signer, OrgRole::Admin) {
+            return Some(OrganizationError::NotAdmin { org: self.id, signer: *signer });
+        }
+        let current = self.role_of(member);
+        if role.is_none() && current.is_none() {
+            return Some(OrganizationError::NotMember { org: self.id, member: *member });
+        }
+        if current == Some(OrgRole::Admin) && role != Some(OrgRole::Admin) && self.admin_count() == 1 {
+            return Some(OrganizationError::LastAdmin { org: self.id });
+        }
+        None
+    }
+
+    /// Replay the entries naming `id`, newest first as `read_ledger_entries` returns them
+    pub(crate) fn from_entries(id: &Pubkey, entries: &[LedgerEntry]) -> Option<Self> {
+        let id_str = id.to_string();
+        let mut org: Option<Organization> = None;
+        let mut rejected = Vec::new();
+        let mut ordered: Vec<&LedgerEntry> = entries.iter().rev().collect();
+        ordered.sort_by_key(|entry| entry.slot);
+
+        for entry in ordered {
+            let Some(value) = entry.json() else { continue };
+            let action = value.get("action").and_then(|a| a.as_str()).unwrap_or_default();
+            if ![ORG_CREATE_ACTION, ORG_ADD_MEMBER_ACTION, ORG_REMOVE_MEMBER_ACTION].contains(&action)
+                || value.get("org").and_then(|o| o.as_str()) != Some(id_str.as_str())
+            {
+                continue;
+            }
+            let mut reject = |reason: String| {
+                rejected.push(RejectedOrgChange {
+                    signature: entry.signature,
+                    signer: entry.signer,
+                    action: action.to_string(),
+                    reason,
+                })
+            };
+            let Some(signer) = entry.signer else {
+                reject("Entry has no signer".to_string());
+                continue;
+            };
+
+            if action == ORG_CREATE_ACTION {
+                let name = value.get("name").and_then(|n| n.as_str()).unwrap_or_default();
+                if org.is_some() {
+                    reject("Organization already created".to_string());
+                } else if organization_id(&signer, name) != *id {
+                    // Only the wallet the id was derived from can create it
+                    reject(format!("{} did not create this organization", signer));
+                } else {
+                    org = Some(Organization {
+                        id: *id,
+                        name: name.to_string(),
+                        creator: signer,
+                        created_at: entry.timestamp,
+                        members: BTreeMap::from([(signer, OrgRole::Admin)]),
+                        rejected: Vec::new(),
+                    });
+                }
+                continue;
+            }
+
+            let Some(current) = org.as_mut() else {
+                reject("Organization not created yet".to_string());
+                continue;
+            };
+            let Some(member) = value.get("member").and_then(|m| m.as_str()).and_then(|m| Pubkey::from_str(m).ok()) else {
+                reject("Missing or invalid member".to_string());
+                continue;
+            };
+            let role = if action == ORG_ADD_MEMBER_ACTION {
+                match value.get("role").and_then(|r| r.as_str()).map(OrgRole::from_str) {
+                    Some(Ok(role)) => Some(role),
+                    _ => {
+                        reject("Missing or invalid role".to_string());
+                        continue;
+                    }
+                }
+            } else {
+                None
+            };
+            match (current.check_change(&signer, &member, role), role) {
+                (Some(error), _) => reject(error.to_string()),
+                (None, Some(role)) => {
+                    current.members.insert(member, role);
+                }
+                (None, None) => {
+                    current.members.remove(&member);
+                }
+            }
+        }
+
+        let mut org = org?;
+        org.rejected = rejected;
+        Some(org)
+    }
+}
+
+/// Why an organization change was refused
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OrganizationError {
+    InvalidName { reason: String },
+    NotFound { org: Pubkey },
+    /// Only admins change the membership
+    NotAdmin { org: Pubkey, signer: Pubkey },
+    NotMember { org: Pubkey, member: Pubkey },
+    /// The change would leave the organization without an admin
+    LastAdmin { org: Pubkey },
+}
+
+impl OrganizationError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::InvalidName { .. } => "org_invalid_name",
+            Self::NotFound { .. } => "org_not_found",
+            Self::NotAdmin { .. } => "org_not_admin",
+            Self::NotMember { .. } => "org_not_member",
+            Self::LastAdmin { .. } => "org_last_admin",
+        }
+    }
+}
+
+impl fmt::Display for OrganizationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidName { reason } => write!(f, "Invalid organization name: {}", reason),
+            Self::NotFound { org } => write!(f, "No organization {} on the ledger", org),
+            Self::NotAdmin { org, signer } => write!(f, "{} is not an admin of organization {}", signer, org),
+            Self::NotMember { org, member } => write!(f, "{} is not a member of organization {}", member, org),
+            Self::LastAdmin { org } => write!(f, "Organization {} must keep at least one admin", org),
+        }
+    }
+}
+
+impl std::error::Error for OrganizationError {}
+
+/// A spending-policy rule: transfers of `mint` at or above `threshold` need a signer holding
+/// at least `role` in `org`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RoleRequirement {
+    pub org: Pubkey,
+    pub role: OrgRole,
+    /// Base units of the mint
+    pub threshold: u64,
+}
+
+impl FinternetClient {
+    /// Create an organization named `name` with `admin_wallet` as its first admin
+    ///
+    /// Returns the organization's id, which members and `get_organization` go by.
+    #[tracing::instrument(skip_all, fields(admin = %readable(&admin_wallet.pubkey()), name = %name))]
+    pub async fn create_organization(&self, admin_wallet: &Keypair, name: &str) -> Result<(Pubkey, Signature)> {
+        let id = organization_id(&admin_wallet.pubkey(), name);
+        let parameters = serde_json::json!({ "org": id.to_string(), "name": name });
+        let outcome = self.create_organization_unlogged(admin_wallet, name, &id).await;
+        self.log_action(ORG_CREATE_ACTION, parameters, None, outcome.as_ref(), Signature::to_string);
+        Ok((id, outcome?))
+    }
+
+    async fn create_organization_unlogged(&self, admin_wallet: &Keypair, name: &str, id: &Pubkey) -> Result<Signature> {
+        if name.trim().is_empty() || name.trim() != name {
+            let reason = "must be non-empty without leading or trailing spaces".to_string();
+            return Err(OrganizationError::InvalidName { reason }.into());
+        }
+        if self.get_organization(id).await?.is_some() {
+            return Err(anyhow!("{} already created organization '{}' as {}", admin_wallet.pubkey(), name, id));
+        }
+        let entry = serde_json::json!({
+            "action": ORG_CREATE_ACTION,
+            "org": id.to_string(),
+            "name": name,
+            "timestamp": self.registration_timestamp().await?,
+        });
+        let signature = self.write_ledger_entry_referencing(admin_wallet, &entry.to_string(), &[*id]).await?;
+        tracing::info!("Organization '{}' created as {}: {}", name, id, signature);
+        Ok(signature)
+    }
+
+    /// Give `member` the role `role` in `org`, or change the role they have
+    pub async fn add_member(&self, admin_wallet: &Keypair, org: &Pubkey, member: &Pubkey, role: OrgRole) -> Result<Signature> {
+        self.change_membership(admin_wallet, org, member, Some(role)).await
+    }
+
+    pub async fn remove_member(&self, admin_wallet: &Keypair, org: &Pubkey, member: &Pubkey) -> Result<Signature> {
+        self.change_membership(admin_wallet, org, member, None).await
+    }
+
+    #[tracing::instrument(skip_all, fields(org = %readable(org), member = %readable(member), role = ?role))]
+    async fn change_membership(
+        &self,
+        admin_wallet: &Keypair,
+        org: &Pubkey,
+        member: &Pubkey,
+        role: Option<OrgRole>,
+    ) -> Result<Signature> {
+        let action = if role.is_some() { ORG_ADD_MEMBER_ACTION } else { ORG_REMOVE_MEMBER_ACTION };
+        let parameters = serde_json::json!({
+            "org": org.to_string(),
+            "member": member.to_string(),
+            "role": role.map(|role| role.as_str()),
+        });
+        let outcome = self.change_membership_unlogged(admin_wallet, org, member, role, action).await;
+        self.log_action(action, parameters, None, outcome.as_ref(), Signature::to_string);
+        outcome
+    }
+
+    async fn change_membership_unlogged(
+        &self,
+        admin_wallet: &Keypair,
+        org: &Pubkey,
+        member: &Pubkey,
+        role: Option<OrgRole>,
+        action: &str,
+    ) -> Result<Signature> {
+        let current = self.get_organization(org).await?.ok_or(OrganizationError::NotFound { org: *org })?;
+        // Readers would reject the entry anyway; refusing here saves the fee
+        if let Some(error) = current.check_change(&admin_wallet.pubkey(), member, role) {
+            return Err(error.into());
+        }
+        let mut entry = serde_json::json!({
+            "action": action,
+            "org": org.to_string(),
+            "member": member.to_string(),
+            "timestamp": self.registration_timestamp().await?,
+        });
+        if let Some(role) = role {
+            entry["role"] = serde_json::Value::String(role.as_str().to_string());
+        }
+        let signature = self.write_ledger_entry_referencing(admin_wallet, &entry.to_string(), &[*org]).await?;
+        tracing::info!("Organization {} membership of {} set to {:?}: {}", org, member, role, signature);
+        Ok(signature)
+    }
+
+    /// The organization's membership, replayed from the entries naming it
+    #[tracing::instrument(skip_all, fields(org = %readable(org)))]
+    pub async fn get_organization(&self, org: &Pubkey) -> Result<Option<Organization>> {
+        let entries = self.read_ledger_entries(org, Some(MAX_ORG_ENTRIES)).await?;
+        let organization = Organization::from_entries(org, &entries);
+        if let Some(organization) = &organization {
+            if !organization.rejected.is_empty() {
+                tracing::warn!("Organization {}: {} entries rejected", org, organization.rejected.len());
+            }
+        }
+        Ok(organization)
+    }
+
+    /// Check the spending policy's role requirements for `signer` sending `requests`
+    pub(crate) async fn check_signer_role(&self, signer: &Pubkey, requests: &[SpendRequest]) -> Result<()> {
+        let Some(guard) = &self.spending else {
+            return Ok(());
+        };
+        for (mint, requirement, requested) in guard.role_requirements_for(requests)? {
+            let organization = self.get_organization(&requirement.org).await?;
+            if !organization.is_some_and(|org| org.has_role(signer, requirement.role)) {
+                return Err(crate::policy::PolicyViolation::RoleRequired {
+                    org: requirement.org,
+                    role: requirement.role,
+                    signer: *signer,
+                    mint,
+                    threshold: requirement.threshold,
+                    requested,
+                }
+                .into());
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_cluster::MockCluster;
+    use crate::policy::{PolicyViolation, SpendingPolicy};
+
+    fn funded(cluster: &MockCluster) -> Keypair {
+        let wallet = Keypair::new();
+        cluster.set_balance(wallet.pubkey(), 1_000_000_000);
+        wallet
+    }
+
+    fn org_error(error: anyhow::Error) -> OrganizationError {
+        error.downcast::<OrganizationError>().unwrap()
+    }
+
+    /// Write an org entry as `signer` without the SDK's own admin check, as a forger would
+    async fn write_raw(client: &FinternetClient, signer: &Keypair, org: &Pubkey, entry: serde_json::Value) {
+        client.write_ledger_entry_referencing(signer, &entry.to_string(), &[*org]).await.unwrap();
+    }
+
+    #[test]
+    fn each_role_includes_the_ones_below_it() {
+        let (admin, operator, viewer, outsider) =
+            (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let org = Organization {
+            id: Pubkey::new_unique(),
+            name: "Acme".to_string(),
+            creator: admin,
+            created_at: 0,
+            members: BTreeMap::from([(admin, OrgRole::Admin), (operator, OrgRole::Operator), (viewer, OrgRole::Viewer)]),
+            rejected: Vec::new(),
+        };
+        let held = |member: &Pubkey| {
+            [OrgRole::Viewer, OrgRole::Operator, OrgRole::Admin].map(|role| org.has_role(member, role))
+        };
+        assert_eq!(held(&admin), [true, true, true]);
+        assert_eq!(held(&operator), [true, true, false]);
+        assert_eq!(held(&viewer), [true, false, false]);
+        assert_eq!(held(&outsider), [false, false, false]);
+
+        assert_eq!(" Operator ".parse::<OrgRole>().unwrap(), OrgRole::Operator);
+        assert!("owner".parse::<OrgRole>().is_err());
+        let json = serde_json::to_value(&org).unwrap();
+        assert_eq!(json["members"][operator.to_string()], "operator");
+        let read: Organization = serde_json::from_value(json).unwrap();
+        assert_eq!(read.members, org.members);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn the_membership_is_replayed_from_the_ledger() {
+        let cluster = MockCluster::new();
+        let client = cluster.default_client();
+        let admin = funded(&cluster);
+        let (operator, viewer) = (Pubkey::new_unique(), Pubkey::new_unique());
+
+        let (org, _) = client.create_organization(&admin, "Acme Treasury").await.unwrap();
+        assert_eq!(org, organization_id(&admin.pubkey(), "Acme Treasury"));
+        client.add_member(&admin, &org, &operator, OrgRole::Operator).await.unwrap();
+        client.add_member(&admin, &org, &viewer, OrgRole::Operator).await.unwrap();
+        client.add_member(&admin, &org, &viewer, OrgRole::Viewer).await.unwrap();
+
+        let organization = client.get_organization(&org).await.unwrap().unwrap();
+        assert_eq!((organization.name.as_str(), organization.creator), ("Acme Treasury", admin.pubkey()));
+        assert_eq!(
+            organization.members,
+            BTreeMap::from([(admin.pubkey(), OrgRole::Admin), (operator, OrgRole::Operator), (viewer, OrgRole::Viewer)])
+        );
+        assert!(organization.rejected.is_empty());
+
+        client.remove_member(&admin, &org, &viewer).await.unwrap();
+        assert_eq!(client.get_organization(&org).await.unwrap().unwrap().role_of(&viewer), None);
+        assert!(client.get_organization(&Pubkey::new_unique()).await.unwrap().is_none());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn the_sdk_refuses_changes_readers_would_reject_before_sending_them() {
+        let cluster = MockCluster::new();
+        let client = cluster.default_client();
+        let (admin, operator) = (funded(&cluster), funded(&cluster));
+        let (org, _) = client.create_organization(&admin, "Acme").await.unwrap();
+        client.add_member(&admin, &org, &operator.pubkey(), OrgRole::Operator).await.unwrap();
+        let sent = cluster.sent().len();
+
+        let error = client.add_member(&operator, &org, &operator.pubkey(), OrgRole::Admin).await.unwrap_err();
+        assert_eq!(org_error(error), OrganizationError::NotAdmin { org, signer: operator.pubkey() });
+        let stranger = Pubkey::new_unique();
+        let error = client.remove_member(&admin, &org, &stranger).await.unwrap_err();
+        assert_eq!(org_error(error), OrganizationError::NotMember { org, member: stranger });
+        let error = client.remove_member(&admin, &org, &admin.pubkey()).await.unwrap_err();
+        assert_eq!(org_error(error), OrganizationError::LastAdmin { org });
+        let error = client.add_member(&admin, &Pubkey::new_unique(), &stranger, OrgRole::Viewer).await.unwrap_err();
+        assert!(matches!(org_error(error), OrganizationError::NotFound { .. }));
+        let error = client.create_organization(&admin, " Acme").await.unwrap_err();
+        assert!(matches!(org_error(error), OrganizationError::InvalidName { .. }));
+        assert_eq!(cluster.sent().len(), sent);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn forged_membership_changes_are_rejected_on_read() {
+        let cluster = MockCluster::new();
+        let client = cluster.default_client();
+        let (admin, operator, forger) = (funded(&cluster), funded(&cluster), funded(&cluster));
+        let (org, _) = client.create_organization(&admin, "Acme").await.unwrap();
+        client.add_member(&admin, &org, &operator.pubkey(), OrgRole::Operator).await.unwrap();
+
+        // An operator promoting themself, an outsider adding themself, and a second creation
+        let change = |action: &str, member: &Pubkey, role: Option<&str>| {
+            serde_json::json!({ "action": action, "org": org.to_string(), "member": member.to_string(), "role": role })
+        };
+        write_raw(&client, &operator, &org, change(ORG_ADD_MEMBER_ACTION, &operator.pubkey(), Some("admin"))).await;
+        write_raw(&client, &forger, &org, change(ORG_ADD_MEMBER_ACTION, &forger.pubkey(), Some("admin"))).await;
+        write_raw(&client, &operator, &org, change(ORG_REMOVE_MEMBER_ACTION, &admin.pubkey(), None)).await;
+        write_raw(&client, &forger, &org, serde_json::json!({ "action": ORG_CREATE_ACTION, "org": org.to_string(), "name": "Acme" })).await;
+
+        let organization = client.get_organization(&org).await.unwrap().unwrap();
+        assert_eq!(
+            organization.members,
+            BTreeMap::from([(admin.pubkey(), OrgRole::Admin), (operator.pubkey(), OrgRole::Operator)])
+        );
+        assert_eq!(organization.creator, admin.pubkey());
+        let rejected: Vec<(Option<Pubkey>, &str)> =
+            organization.rejected.iter().map(|change| (change.signer, change.action.as_str())).collect();
+        assert_eq!(
+            rejected,
+            [
+                (Some(operator.pubkey()), ORG_ADD_MEMBER_ACTION),
+                (Some(forger.pubkey()), ORG_ADD_MEMBER_ACTION),
+                (Some(operator.pubkey()), ORG_REMOVE_MEMBER_ACTION),
+                (Some(forger.pubkey()), ORG_CREATE_ACTION),
+            ]
+        );
+        assert_eq!(organization.rejected[0].reason, OrganizationError::NotAdmin { org, signer: operator.pubkey() }.to_string());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn a_removed_admin_can_no_longer_change_the_membership() {
+        let cluster = MockCluster::new();
+        let client = cluster.default_client();
+        let (founder, successor) = (funded(&cluster), funded(&cluster));
+        let (org, _) = client.create_organization(&founder, "Acme").await.unwrap();
+        client.add_member(&founder, &org, &successor.pubkey(), OrgRole::Admin).await.unwrap();
+        client.remove_member(&successor, &org, &founder.pubkey()).await.unwrap();
+
+        let late = Pubkey::new_unique();
+        let entry = serde_json::json!({
+            "action": ORG_ADD_MEMBER_ACTION,
+            "org": org.to_string(),
+            "member": late.to_string(),
+            "role": "operator",
+        });
+        write_raw(&client, &founder, &org, entry).await;
+
+        let organization = client.get_organization(&org).await.unwrap().unwrap();
+        assert_eq!(organization.members, BTreeMap::from([(successor.pubkey(), OrgRole::Admin)]));
+        assert_eq!(organization.rejected.len(), 1);
+        assert_eq!(organization.rejected[0].signer, Some(founder.pubkey()));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn payments_above_the_threshold_need_the_required_role() {
+        let cluster = MockCluster::new();
+        let admin = funded(&cluster);
+        let (operator, viewer) = (funded(&cluster), funded(&cluster));
+        let mint = cluster.add_mint(&spl_token::id(), 6, &Pubkey::new_unique());
+        for wallet in [&operator, &viewer] {
+            cluster.add_token_account(&wallet.pubkey(), &mint, 100_000_000);
+        }
+        let (org, _) = cluster.default_client().create_organization(&admin, "Acme").await.unwrap();
+        let client = cluster
+            .default_client()
+            .with_spending_policy(SpendingPolicy::new().require_org_role(mint, org, OrgRole::Operator, 1_000_000))
+            .unwrap();
+        client.add_member(&admin, &org, &operator.pubkey(), OrgRole::Operator).await.unwrap();
+        client.add_member(&admin, &org, &viewer.pubkey(), OrgRole::Viewer).await.unwrap();
+        let to = Keypair::new().pubkey();
+
+        client.send_payment(&operator, &to, 1_000_000, &mint, None).await.unwrap();
+        client.send_payment(&viewer, &to, 999_999, &mint, None).await.unwrap();
+        let sent = cluster.sent().len();
+        let error = client.send_payment(&viewer, &to, 1_000_000, &mint, None).await.unwrap_err();
+        let violation = error.downcast::<PolicyViolation>().unwrap();
+        assert_eq!(
+            violation,
+            PolicyViolation::RoleRequired {
+                org,
+                role: OrgRole::Operator,
+                signer: viewer.pubkey(),
+                mint,
+                threshold: 1_000_000,
+                requested: 1_000_000,
+            }
+        );
+        assert_eq!(cluster.sent().len(), sent);
+    }
+}
@@ -0,0 +1,661 @@
+use crate::confirmation::OperationKind;
+use crate::events::SdkEvent;
+use crate::latency::{timed, Phase};
+use crate::resources::{file_bytes, StoreUsage};
+use crate::rpc_options::{durable_nonce_account, RpcCallOptions};
+use crate::FinternetClient;
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use solana_client::client_error::ClientError;
+use solana_sdk::{
+    commitment_config::CommitmentConfig,
+    signature::Signature,
+    transaction::Transaction,
+};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OutboxStatus {
+    /// Signed and handed to the RPC node, outcome not yet known
+    Submitted,
+    Confirmed,
+    /// Landed on-chain but the transaction itself failed
+    Failed,
+    /// Never landed and its blockhash has expired, or its durable nonce moved on, so it can no
+    /// longer land
+    Expired,
+    /// Was confirmed, then vanished from the chain with the fork it landed on
+    Dropped,
+    /// Journaled, then refused by send middleware before it was broadcast
+    Refused,
+}
+
+/// A signed transaction persisted before broadcast
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OutboxEntry {
+    pub signature: Signature,
+    /// What produced the transaction, e.g. `send_payment`
+    pub label: String,
+    pub transaction: Transaction,
+    pub status: OutboxStatus,
+    pub created_at: u64,
+    pub updated_at: u64,
+    pub error: Option<String>,
+}
+
+impl OutboxStatus {
+    /// Whether the outcome is final, so the entry is kept only for the record
+    pub fn is_settled(&self) -> bool {
+        matches!(self, Self::Confirmed | Self::Failed | Self::Expired | Self::Refused)
+    }
+}
+
+/// A settled entry moved to the outbox archive, without its transaction
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArchivedOutboxEntry {
+    pub signature: Signature,
+    pub label: String,
+    pub status: OutboxStatus,
+    pub created_at: u64,
+    pub updated_at: u64,
+    pub error: Option<String>,
+}
+
+impl From<OutboxEntry> for ArchivedOutboxEntry {
+    fn from(entry: OutboxEntry) -> Self {
+        Self {
+            signature: entry.signature,
+            label: entry.label,
+            status: entry.status,
+            created_at: entry.created_at,
+            updated_at: entry.updated_at,
+            error: entry.error,
+        }
+    }
+}
+
+/// Outcome of `recover_outbox` for every entry that was still `Submitted`
+#[derive(Debug, Clone, Default)]
+pub struct OutboxRecovery {
+    pub confirmed: Vec<Signature>,
+    pub failed: Vec<Signature>,
+    pub expired: Vec<Signature>,
+    pub rebroadcast: Vec<Signature>,
+    /// Landed, but not yet at the client's commitment; left `Submitted` for the next recovery
+    pub landing: Vec<Signature>,
+}
+
+impl OutboxRecovery {
+    pub fn is_empty(&self) -> bool {
+        self.confirmed.is_empty()
+            && self.failed.is_empty()
+            && self.expired.is_empty()
+            && self.rebroadcast.is_empty()
+            && self.landing.is_empty()
+    }
+}
+
+/// Small JSON-file store of signed transactions, written atomically on every change
+///
+/// With archival on, every change also moves confirmed, failed, and expired entries that
+/// settled long enough ago to `<path>.archive.jsonl`, one line each without the transaction.
+/// `Submitted` and `Dropped` entries are never moved. A crash between the two writes can leave
+/// an entry in the archive twice, never in neither file.
+pub struct Outbox {
+    path: PathBuf,
+    lock: Mutex<()>,
+    archive_after_days: Option<u64>,
+}
+
+impl Outbox {
+    pub fn new(path: &Path) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            lock: Mutex::new(()),
+            archive_after_days: None,
+        }
+    }
+
+    /// Wait for a write already under way; every change is written through, so none is buffered
+    pub fn flush(&self) -> Result<()> {
+        let _guard = self.lock.lock().map_err(|_| anyhow!("Outbox lock poisoned"))?;
+        Ok(())
+    }
+
+    /// Archive settled entries this many days after their last update; `None` keeps them all
+    pub fn with_archival(mut self, after_days: Option<u64>) -> Self {
+        self.archive_after_days = after_days;
+        self
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn archive_path(&self) -> PathBuf {
+        let mut name = self.path.as_os_str().to_owned();
+        name.push(".archive.jsonl");
+        PathBuf::from(name)
+    }
+
+    /// Archive what is due now rather than on the next change, returning how many entries moved
+    pub fn archive_settled(&self) -> Result<usize> {
+        let _guard = self.lock.lock().map_err(|_| anyhow!("Outbox lock poisoned"))?;
+        let mut entries = self.load()?;
+        let archived = self.archive_due(&mut entries)?;
+        if archived > 0 {
+            self.store(&entries)?;
+        }
+        Ok(archived)
+    }
+
+    pub fn usage(&self) -> Result<StoreUsage> {
+        let archive = self.archive_path();
+        Ok(StoreUsage {
+            path: self.path.clone(),
+            entries: self.entries()?.len(),
+            bytes: file_bytes(&self.path),
+            archived_files: usize::from(archive.exists()),
+            archived_bytes: file_bytes(&archive),
+        })
+    }
+
+    pub fn entries(&self) -> Result<Vec<OutboxEntry>> {
+        let _guard = self.lock.lock().map_err(|_| anyhow!("Outbox lock poisoned"))?;
+        self.load()
+    }
+
+    /// Record a signed transaction as `Submitted`; must happen before it is broadcast
+    pub fn record_submitted(&self, label: &str, transaction: &Transaction) -> Result<Signature> {
+        let signature = *transaction
+            .signatures
+            .first()
+            .ok_or_else(|| anyhow!("Cannot record an unsigned transaction in the outbox"))?;
+
+        self.update(|entries| {
+            if !entries.iter().any(|e| e.signature == signature) {
+                let now = now_secs();
+                entries.push(OutboxEntry {
+                    signature,
+                    label: label.to_string(),
+                    transaction: transaction.clone(),
+                    status: OutboxStatus::Submitted,
+                    created_at: now,
+                    updated_at: now,
+                    error: None,
+                });
+            }
+        })?;
+        Ok(signature)
+    }
+
+    pub fn mark(&self, signature: &Signature, status: OutboxStatus, error: Option<String>) -> Result<()> {
+        self.mark_all(vec![(*signature, status, error)])
+    }
+
+    /// `mark` several entries in one write
+    pub fn mark_all(&self, marks: Vec<(Signature, OutboxStatus, Option<String>)>) -> Result<()> {
+        if marks.is_empty() {
+            return Ok(());
+        }
+        self.update(|entries| {
+            let now = now_secs();
+            for (signature, status, error) in marks {
+                if let Some(entry) = entries.iter_mut().find(|e| e.signature == signature) {
+                    entry.status = status;
+                    entry.error = error;
+                    entry.updated_at = now;
+                }
+            }
+        })
+    }
+
+    fn update(&self, apply: impl FnOnce(&mut Vec<OutboxEntry>)) -> Result<()> {
+        let _guard = self.lock.lock().map_err(|_| anyhow!("Outbox lock poisoned"))?;
+        let mut entries = self.load()?;
+        apply(&mut entries);
+        self.archive_due(&mut entries)?;
+        self.store(&entries)
+    }
+
+    /// Move entries due for archival out of `entries` and onto the archive
+    fn archive_due(&self, entries: &mut Vec<OutboxEntry>) -> Result<usize> {
+        let Some(days) = self.archive_after_days else {
+            return Ok(0);
+        };
+        let cutoff = now_secs().saturating_sub(days.saturating_mul(86_400));
+        let (due, kept): (Vec<OutboxEntry>, Vec<OutboxEntry>) =
+            entries.drain(..).partition(|entry| entry.status.is_settled() && entry.updated_at < cutoff);
+        *entries = kept;
+        if due.is_empty() {
+            return Ok(0);
+        }
+        let archive = self.archive_path();
+        let count = due.len();
+        let mut lines = String::new();
+        for entry in due {
+            lines.push_str(&serde_json::to_string(&ArchivedOutboxEntry::from(entry))?);
+            lines.push('\n');
+        }
+        let mut file = OpenOptions::new().create(true).append(true).open(&archive)?;
+        file.write_all(lines.as_bytes())
+            .and_then(|_| file.sync_data())
+            .map_err(|e| anyhow!("Could not append to outbox archive {}: {}", archive.display(), e))?;
+        tracing::info!("Archived {} settled outbox entries to {}", count, archive.display());
+        Ok(count)
+    }
+
+    fn load(&self) -> Result<Vec<OutboxEntry>> {
+        if !self.path.exists() {
+            return Ok(Vec::new());
+        }
+        let data = fs::read_to_string(&self.path)?;
+        if data.trim().is_empty() {
+            return Ok(Vec::new());
+        }
+        serde_json::from_str(&data)
+            .map_err(|e| anyhow!("Corrupt outbox file {}: {}", self.path.display(), e))
+    }
+
+    fn store(&self, entries: &[OutboxEntry]) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            if !parent.as_os_str().is_empty() {
+                fs::create_dir_all(parent)?;
+            }
+        }
+        // Write-then-rename so a crash mid-write never leaves a truncated outbox
+        let tmp = self.path.with_extension("tmp");
+        fs::write(&tmp, serde_json::to_string_pretty(entries)?)?;
+        fs::rename(&tmp, &self.path)?;
+        Ok(())
+    }
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// One send of a signed transaction, taken a step at a time: `journal`, `broadcast`, `confirm`,
+/// and `finish` with the outcome, whichever step it came from
+pub(crate) struct TrackedSend<'a> {
+    client: &'a FinternetClient,
+    label: String,
+    transaction: &'a Transaction,
+    options: RpcCallOptions,
+    /// The signature, when the send is a payment and so raises the payment events
+    payment: Option<Signature>,
+    /// Whether `PaymentSubmitted` went out, so the outcome is announced too
+    announced: bool,
+    /// Whether the outbox has the transaction, so the outcome is recorded there too
+    journaled: bool,
+    /// Whether the transaction was handed to the node, so it may land whatever the outcome says
+    broadcast: bool,
+}
+
+impl TrackedSend<'_> {
+    /// Raise `PaymentSubmitted` and record the transaction in the outbox before it is broadcast
+    pub(crate) fn journal(&mut self) -> Result<()> {
+        if let Some(signature) = self.payment {
+            self.client.emit(|| SdkEvent::PaymentSubmitted { operation: self.label.clone(), signature });
+            self.announced = true;
+        }
+        let Some(outbox) = &self.client.outbox else {
+            if self.options.wait == Some(false) {
+                tracing::warn!("{} sent without waiting and no outbox is configured to reconcile it", self.label);
+            }
+            return Ok(());
+        };
+        outbox.record_submitted(&self.label, self.transaction)?;
+        self.journaled = true;
+        Ok(())
+    }
+
+    pub(crate) fn broadcast(&mut self) -> Result<Signature> {
+        self.broadcast = true;
+        self.client.broadcast_with_options(self.transaction, &self.options)
+    }
+
+    /// Wait for `signature` as the send's confirmation policy says
+    pub(crate) fn confirm(&self, signature: &Signature) -> Result<()> {
+        self.client.await_with_options(self.transaction, signature, &self.options)
+    }
+
+    /// Record `outcome` in the outbox, announce it, and drop cached balances the send touched
+    pub(crate) fn finish(self, outcome: Result<Signature>) -> Result<Signature> {
+        let outcome = match (&self.client.outbox, outcome) {
+            (Some(outbox), outcome) if self.journaled => self.record_outcome(outbox, outcome),
+            (_, outcome) => outcome,
+        };
+        if let (Some(signature), true) = (self.payment, self.announced) {
+            let operation = || self.label.clone();
+            match &outcome {
+                Ok(_) if self.options.wait == Some(false) => {}
+                Ok(_) => self.client.emit(|| SdkEvent::PaymentConfirmed { operation: operation(), signature }),
+                Err(e) => self.client.emit(|| SdkEvent::PaymentFailed { operation: operation(), signature, error: e.to_string() }),
+            }
+        }
+        self.client.cache.wallets.invalidate(&self.transaction.message.account_keys);
+        outcome
+    }
+
+    fn record_outcome(&self, outbox: &Outbox, outcome: Result<Signature>) -> Result<Signature> {
+        match outcome {
+            Ok(sent) if self.options.wait == Some(false) => Ok(sent),
+            Ok(confirmed) => {
+                outbox.mark(&confirmed, OutboxStatus::Confirmed, None)?;
+                Ok(confirmed)
+            }
+            Err(e) if !self.broadcast => {
+                // Recovery would otherwise send what the middleware refused
+                if let Some(signature) = self.transaction.signatures.first() {
+                    outbox.mark(signature, OutboxStatus::Refused, Some(e.to_string()))?;
+                }
+                Err(e)
+            }
+            Err(e) => {
+                // On-chain execution errors are final; anything else (timeouts, dropped
+                // connections) stays `Submitted` so recovery can find out what happened
+                let tx_err = e.downcast_ref::<ClientError>().and_then(|c| c.get_transaction_error());
+                if let (Some(tx_err), Some(signature)) = (tx_err, self.transaction.signatures.first()) {
+                    outbox.mark(signature, OutboxStatus::Failed, Some(tx_err.to_string()))?;
+                }
+                Err(e)
+            }
+        }
+    }
+}
+
+impl FinternetClient {
+    /// Send and confirm a signed transaction, journaling it in the outbox when one is configured
+    ///
+    /// Waits as `kind`'s `ConfirmationPolicy` says. A send that does not wait stays `Submitted`
+    /// in the outbox until `recover_outbox` resolves it.
+    pub(crate) fn send_and_confirm_tracked(
+        &self,
+        kind: OperationKind,
+        label: &str,
+        transaction: &Transaction,
+    ) -> Result<Signature> {
+        self.send_and_confirm_tracked_with(kind, label, transaction, &RpcCallOptions::default())
+    }
+
+    /// `send_and_confirm_tracked` with per-call send and confirmation settings
+    ///
+    /// Payments raise `PaymentSubmitted`, then `PaymentConfirmed` once waited on, or
+    /// `PaymentFailed`. Cached balances of every account the transaction names are dropped,
+    /// whatever the outcome. Nothing is journaled or broadcast once `options.deadline` is too
+    /// close; see `deadline`.
+    pub(crate) fn send_and_confirm_tracked_with(
+        &self,
+        kind: OperationKind,
+        label: &str,
+        transaction: &Transaction,
+        options: &RpcCallOptions,
+    ) -> Result<Signature> {
+        let mut send = self.begin_tracked_send(kind, label, transaction, options)?;
+        let outcome = match send.journal() {
+            Ok(()) => send.broadcast().and_then(|signature| send.confirm(&signature).map(|()| signature)),
+            Err(e) => Err(e),
+        };
+        send.finish(outcome)
+    }
+
+    /// The steps of `send_and_confirm_tracked_with`, for callers that act between them
+    ///
+    /// In dry-run mode the transaction is simulated instead and the simulation returned as the
+    /// error, as it is once the deadline is too close.
+    pub(crate) fn begin_tracked_send<'a>(
+        &'a self,
+        kind: OperationKind,
+        label: &str,
+        transaction: &'a Transaction,
+        options: &RpcCallOptions,
+    ) -> Result<TrackedSend<'a>> {
+        if self.is_dry_run() {
+            return Err(timed(options.phase_clock.as_ref(), Phase::Simulation, || self.simulate_instead(label, transaction)));
+        }
+        self.check_deadline(label, options)?;
+        Ok(TrackedSend {
+            client: self,
+            label: label.to_string(),
+            transaction,
+            options: self.confirmation_options(kind, options),
+            payment: transaction.signatures.first().copied().filter(|_| kind == OperationKind::Payment),
+            announced: false,
+            journaled: false,
+            broadcast: false,
+        })
+    }
+
+    /// Resolve every outbox entry left `Submitted` by a previous run
+    ///
+    /// Entries that reached the client's commitment are marked confirmed or failed; entries
+    /// that landed but have not reached it yet are left for a later run. Unknown entries are
+    /// rebroadcast while they can still land (the signature is unchanged, so this can never
+    /// double-send) and marked expired once they cannot: once the blockhash expires, or for a
+    /// durable-nonce transaction, once its nonce account no longer holds the nonce it was
+    /// signed with.
+    #[tracing::instrument(skip_all)]
+    pub async fn recover_outbox(&self) -> Result<OutboxRecovery> {
+        let mut recovery = OutboxRecovery::default();
+        let Some(outbox) = &self.outbox else {
+            return Ok(recovery);
+        };
+
+        let pending: Vec<OutboxEntry> = outbox
+            .entries()?
+            .into_iter()
+            .filter(|e| e.status == OutboxStatus::Submitted)
+            .collect();
+        if pending.is_empty() {
+            return Ok(recovery);
+        }
+        tracing::info!("Recovering {} submitted outbox entries from {}", pending.len(), outbox.path().display());
+
+        let commitment = self.client.commitment();
+        // getSignatureStatuses accepts at most 256 signatures per call; each chunk's outcomes are
+        // written at once, and a crash before that leaves them `Submitted` for the next run
+        for chunk in pending.chunks(256) {
+            let signatures: Vec<Signature> = chunk.iter().map(|e| e.signature).collect();
+            let statuses = self.client.get_signature_statuses_with_history(&signatures)?.value;
+            let mut marks = Vec::new();
+            for (entry, status) in chunk.iter().zip(statuses) {
+                match status {
+                    Some(status) if !status.satisfies_commitment(commitment) => {
+                        tracing::info!("Outbox entry {} landed but is not {:?} yet", entry.signature, commitment.commitment);
+                        recovery.landing.push(entry.signature);
+                    }
+                    Some(status) => match status.err {
+                        None => {
+                            marks.push((entry.signature, OutboxStatus::Confirmed, None));
+                            recovery.confirmed.push(entry.signature);
+                        }
+                        Some(err) => {
+                            marks.push((entry.signature, OutboxStatus::Failed, Some(err.to_string())));
+                            recovery.failed.push(entry.signature);
+                        }
+                    },
+                    None if self.is_dry_run() => {
+                        tracing::info!("Dry run: not rebroadcasting outbox entry {}", entry.signature);
+                    }
+                    None => {
+                        if self.can_still_land(&entry.transaction)? {
+                            tracing::info!("Rebroadcasting outbox entry {}", entry.signature);
+                            self.client.send_transaction(&entry.transaction)?;
+                            recovery.rebroadcast.push(entry.signature);
+                        } else {
+                            marks.push((entry.signature, OutboxStatus::Expired, None));
+                            recovery.expired.push(entry.signature);
+                        }
+                    }
+                }
+            }
+            outbox.mark_all(marks)?;
+        }
+
+        tracing::info!(
+            "Outbox recovery: {} confirmed, {} failed, {} expired, {} rebroadcast, {} still landing",
+            recovery.confirmed.len(),
+            recovery.failed.len(),
+            recovery.expired.len(),
+            recovery.rebroadcast.len(),
+            recovery.landing.len()
+        );
+        Ok(recovery)
+    }
+
+    /// Whether an unlanded transaction could still land: its blockhash is valid, or it is built
+    /// on a durable nonce that its nonce account still holds
+    fn can_still_land(&self, transaction: &Transaction) -> Result<bool> {
+        let blockhash = transaction.message.recent_blockhash;
+        match durable_nonce_account(transaction) {
+            Some(nonce_account) => Ok(self.stored_nonce(&nonce_account)? == Some(blockhash)),
+            None => Ok(self.client.is_blockhash_valid(&blockhash, CommitmentConfig::processed())?),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_cluster::{Landing, MockCluster, TempDir};
+    use crate::FinternetConfig;
+    use serde_json::json;
+    use solana_sdk::hash::Hash;
+    use solana_sdk::signature::Keypair;
+    use solana_sdk::signer::Signer;
+    use solana_sdk::system_instruction;
+    use std::collections::HashSet;
+
+    fn client(cluster: &MockCluster, dir: &TempDir) -> FinternetClient {
+        cluster.client(FinternetConfig {
+            outbox_path: Some(dir.join("outbox.json")),
+            ..FinternetConfig::default()
+        })
+    }
+
+    /// A signed transfer of `lamports` from `payer`, distinct per amount
+    fn transfer(payer: &Keypair, lamports: u64, blockhash: Hash) -> Transaction {
+        let instruction = system_instruction::transfer(&payer.pubkey(), &Keypair::new().pubkey(), lamports);
+        Transaction::new_signed_with_payer(&[instruction], Some(&payer.pubkey()), &[payer], blockhash)
+    }
+
+    fn distinct_sent(cluster: &MockCluster) -> HashSet<Signature> {
+        cluster.sent().iter().map(|transaction| transaction.signatures[0]).collect()
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn a_restart_settles_what_a_crashed_run_sent_without_sending_anything_new() {
+        let (cluster, dir, payer) = (MockCluster::new(), TempDir::new("outbox"), Keypair::new());
+        cluster.set_landing(Landing::Pending);
+        let (landed, in_flight, unsent) =
+            (transfer(&payer, 1, cluster.blockhash()), transfer(&payer, 2, cluster.blockhash()), transfer(&payer, 3, Hash::new_unique()));
+        {
+            // The crashed run journaled all three, broadcast two, and saw none confirm
+            let crashed = client(&cluster, &dir);
+            let outbox = crashed.outbox.as_ref().unwrap();
+            for transaction in [&landed, &in_flight, &unsent] {
+                outbox.record_submitted("send_payment", transaction).unwrap();
+            }
+            crashed.client.send_transaction(&landed).unwrap();
+            crashed.client.send_transaction(&in_flight).unwrap();
+        }
+        cluster.land(&landed.signatures[0], None);
+
+        let restarted = client(&cluster, &dir);
+        let recovery = restarted.recover_outbox().await.unwrap();
+        assert_eq!(recovery.confirmed, vec![landed.signatures[0]]);
+        assert_eq!(recovery.rebroadcast, vec![in_flight.signatures[0]]);
+        assert_eq!(recovery.expired, vec![unsent.signatures[0]]);
+
+        cluster.land(&in_flight.signatures[0], None);
+        let recovery = restarted.recover_outbox().await.unwrap();
+        assert_eq!(recovery.confirmed, vec![in_flight.signatures[0]]);
+        assert!(restarted.recover_outbox().await.unwrap().is_empty());
+
+        // Only the journaled signatures ever reached the cluster; the rebroadcast was the same one
+        assert_eq!(distinct_sent(&cluster), HashSet::from([landed.signatures[0], in_flight.signatures[0]]));
+        let statuses: Vec<OutboxStatus> =
+            restarted.outbox.as_ref().unwrap().entries().unwrap().iter().map(|entry| entry.status).collect();
+        assert_eq!(statuses, vec![OutboxStatus::Confirmed, OutboxStatus::Confirmed, OutboxStatus::Expired]);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn statuses_are_asked_for_at_most_256_at_a_time() {
+        let (cluster, dir, payer) = (MockCluster::new(), TempDir::new("outbox"), Keypair::new());
+        let client = client(&cluster, &dir);
+        let entries: Vec<OutboxEntry> = (1..=300)
+            .map(|lamports| {
+                let transaction = transfer(&payer, lamports, cluster.blockhash());
+                cluster.land(&transaction.signatures[0], None);
+                OutboxEntry {
+                    signature: transaction.signatures[0],
+                    label: "send_payment".to_string(),
+                    transaction,
+                    status: OutboxStatus::Submitted,
+                    created_at: 0,
+                    updated_at: 0,
+                    error: None,
+                }
+            })
+            .collect();
+        // Journaled in one write; one each would make the test slow
+        client.outbox.as_ref().unwrap().store(&entries).unwrap();
+
+        let recovery = client.recover_outbox().await.unwrap();
+
+        assert_eq!(recovery.confirmed.len(), 300);
+        assert_eq!(cluster.call_count("getSignatureStatuses"), 2);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn an_entry_short_of_the_commitment_is_left_submitted() {
+        let (cluster, dir, payer) = (MockCluster::new(), TempDir::new("outbox"), Keypair::new());
+        let client = client(&cluster, &dir);
+        let transaction = transfer(&payer, 1, cluster.blockhash());
+        client.outbox.as_ref().unwrap().record_submitted("send_payment", &transaction).unwrap();
+        cluster.respond("getSignatureStatuses", |_| {
+            Some(Ok(json!({
+                "context": { "slot": 1_000 },
+                "value": [{ "slot": 1_000, "confirmations": 0, "status": { "Ok": null }, "err": null, "confirmationStatus": "processed" }],
+            })))
+        });
+
+        let recovery = client.recover_outbox().await.unwrap();
+
+        assert_eq!(recovery.landing, vec![transaction.signatures[0]]);
+        assert!(recovery.confirmed.is_empty() && recovery.rebroadcast.is_empty());
+        assert_eq!(client.outbox.as_ref().unwrap().entries().unwrap()[0].status, OutboxStatus::Submitted);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn a_durable_nonce_entry_lives_as_long_as_its_nonce() {
+        let (cluster, dir, payer) = (MockCluster::new(), TempDir::new("outbox"), Keypair::new());
+        cluster.set_landing(Landing::Pending);
+        let client = client(&cluster, &dir);
+        let nonce_account = cluster.add_nonce_account(&payer.pubkey());
+        let nonce = cluster.advance_nonce(&nonce_account, &payer.pubkey());
+        let instructions = [
+            system_instruction::advance_nonce_account(&nonce_account, &payer.pubkey()),
+            system_instruction::transfer(&payer.pubkey(), &Keypair::new().pubkey(), 1),
+        ];
+        let transaction = Transaction::new_signed_with_payer(&instructions, Some(&payer.pubkey()), &[&payer], nonce);
+        client.outbox.as_ref().unwrap().record_submitted("approve_payment", &transaction).unwrap();
+
+        // The nonce is no blockhash the cluster knows, yet the transaction can still land
+        cluster.expire_blockhashes();
+        let recovery = client.recover_outbox().await.unwrap();
+        assert_eq!(recovery.rebroadcast, vec![transaction.signatures[0]]);
+
+        cluster.advance_nonce(&nonce_account, &payer.pubkey());
+        let recovery = client.recover_outbox().await.unwrap();
+        assert_eq!(recovery.expired, vec![transaction.signatures[0]]);
+    }
+}
@@ -0,0 +1,361 @@
+//! Paging through a wallet's holdings without resolving metadata it will not return
+//!
+//! A wallet's token accounts come back in one RPC call, but resolving metadata costs an
+//! account read and an off-chain fetch per mint. `get_owned_assets_page` filters and sorts on
+//! what is already known and resolves metadata only for the page it returns. Sorting by name
+//! reads the metadata accounts of every candidate in batches but fetches no off-chain JSON;
+//! filtering by asset type has to resolve full metadata for every candidate, from the client's
+//! metadata cache where it can.
+
+use crate::asset::{asset_from_accounts, fill_offchain_metadata};
+use crate::telemetry::readable;
+use crate::{AssetMetadata, AssetType, FinternetClient, MetadataBackend};
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use solana_sdk::pubkey::Pubkey;
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+/// Order of a page of owned assets
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OwnedAssetSort {
+    /// Largest balance first
+    #[default]
+    Balance,
+    /// By on-chain name, unnamed mints last
+    Name,
+}
+
+impl fmt::Display for OwnedAssetSort {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Balance => "balance",
+            Self::Name => "name",
+        })
+    }
+}
+
+impl FromStr for OwnedAssetSort {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "balance" => Ok(Self::Balance),
+            "name" => Ok(Self::Name),
+            _ => Err(anyhow!("Unknown sort '{}'; expected balance or name", s)),
+        }
+    }
+}
+
+/// Which owned assets `get_owned_assets_page` returns, and in what order
+#[derive(Debug, Clone, Default)]
+pub struct OwnedAssetsQuery {
+    pub offset: usize,
+    /// Page size; 0 returns only the total count
+    pub limit: usize,
+    pub sort: OwnedAssetSort,
+    /// Only assets whose metadata has this type
+    pub asset_type: Option<AssetType>,
+    /// Only balances of at least this many base units; balances of 0 are never listed
+    pub min_balance: u64,
+}
+
+/// One held mint with its metadata, when that could be read
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OwnedAsset {
+    pub mint: Pubkey,
+    /// Base units
+    pub balance: u64,
+    pub metadata: Option<AssetMetadata>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OwnedAssetPage {
+    pub assets: Vec<OwnedAsset>,
+    /// Assets matching the filters, across all pages
+    pub total_count: usize,
+    pub offset: usize,
+    /// Offset of the following page, when there is one
+    pub next_offset: Option<usize>,
+}
+
+impl FinternetClient {
+    /// One page of the assets `owner` holds, filtered and sorted before metadata is resolved
+    #[tracing::instrument(skip_all, fields(wallet = %readable(owner), offset = query.offset, limit = query.limit, sort = %query.sort))]
+    pub async fn get_owned_assets_page(&self, owner: &Pubkey, query: &OwnedAssetsQuery) -> Result<OwnedAssetPage> {
+        let mut candidates: Vec<(Pubkey, u64)> = self
+            .get_owned_assets(owner)
+            .await?
+            .into_iter()
+            .filter(|(_, balance)| *balance >= query.min_balance.max(1))
+            .collect();
+
+        // Resolved up front only when the filter needs it; the page reuses it
+        let mut resolved = HashMap::new();
+        if let Some(asset_type) = &query.asset_type {
+            let mints: Vec<Pubkey> = candidates.iter().map(|(mint, _)| *mint).collect();
+            resolved = self.resolve_owned_metadata(&mints).await?;
+            candidates.retain(|(mint, _)| {
+                resolved
+                    .get(mint)
+                    .is_some_and(|metadata| metadata.has_metadata && &metadata.asset_type == asset_type)
+            });
+        }
+
+        match query.sort {
+            OwnedAssetSort::Balance => candidates.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0))),
+            OwnedAssetSort::Name => {
+                let names = self.owned_asset_names(&candidates, &resolved)?;
+                // Empty names sort after every named mint
+                candidates.sort_by(|a, b| {
+                    let (a_name, b_name) = (names.get(&a.0), names.get(&b.0));
+                    a_name
+                        .is_none()
+                        .cmp(&b_name.is_none())
+                        .then_with(|| a_name.cmp(&b_name))
+                        .then(a.0.cmp(&b.0))
+                });
+            }
+        }
+
+        let total_count = candidates.len();
+        let page: Vec<(Pubkey, u64)> = candidates.into_iter().skip(query.offset).take(query.limit).collect();
+        let missing: Vec<Pubkey> = page
+            .iter()
+            .map(|(mint, _)| *mint)
+            .filter(|mint| !resolved.contains_key(mint))
+            .collect();
+        resolved.extend(self.resolve_owned_metadata(&missing).await?);
+
+        let end = query.offset.saturating_add(page.len());
+        Ok(OwnedAssetPage {
+            assets: page
+                .into_iter()
+                .map(|(mint, balance)| OwnedAsset {
+                    mint,
+                    balance,
+                    metadata: resolved.remove(&mint),
+                })
+                .collect(),
+            total_count,
+            offset: query.offset,
+            next_offset: (end < total_count && query.limit > 0).then_some(end),
+        })
+    }
+
+    /// Full metadata for each of `mints` that has readable metadata, as `get_asset_info` gives
+    ///
+    /// Metaplex metadata comes from the client's cache in batched reads; mints without it fall
+    /// back to their ledger memo, one history scan each.
+    async fn resolve_owned_metadata(&self, mints: &[Pubkey]) -> Result<HashMap<Pubkey, AssetMetadata>> {
+        let mut resolved = HashMap::with_capacity(mints.len());
+        for (mint, asset) in mints.iter().zip(self.get_assets_metadata(mints).await?) {
+            let mut metadata = match asset {
+                Ok(asset) => asset.metadata,
+                Err(e) => {
+                    tracing::debug!("No readable metadata for {}: {}", mint, e);
+                    continue;
+                }
+            };
+            if let Err(e) = self.fill_ledger_metadata(mint, &mut metadata).await {
+                tracing::debug!("Could not read ledger metadata for {}: {}", mint, e);
+            }
+            if metadata.metadata_backend == Some(MetadataBackend::LedgerMemo) && !metadata.uri.is_empty() {
+                let uri = metadata.uri.clone();
+                fill_offchain_metadata(&mut metadata, &uri).await;
+            }
+            resolved.insert(*mint, metadata);
+        }
+        Ok(resolved)
+    }
+
+    /// On-chain names of `candidates`, from `resolved` or the metadata accounts alone
+    fn owned_asset_names(
+        &self,
+        candidates: &[(Pubkey, u64)],
+        resolved: &HashMap<Pubkey, AssetMetadata>,
+    ) -> Result<HashMap<Pubkey, String>> {
+        let mut names: HashMap<Pubkey, String> = resolved
+            .iter()
+            .map(|(mint, metadata)| (*mint, metadata.name.clone()))
+            .collect();
+        let unread: Vec<Pubkey> = candidates
+            .iter()
+            .map(|(mint, _)| *mint)
+            .filter(|mint| !names.contains_key(mint))
+            .collect();
+        for (mint, accounts) in unread.iter().zip(self.asset_accounts(&unread)?) {
+            if let Ok(metadata) = asset_from_accounts(mint, &accounts) {
+                names.insert(*mint, metadata.name);
+            }
+        }
+        names.retain(|_, name| !name.is_empty());
+        Ok(names)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_cluster::MockCluster;
+    use mpl_token_metadata::accounts::Metadata;
+    use solana_sdk::account::Account;
+    use std::time::{Duration, Instant};
+
+    const ACCOUNTS: u64 = 500;
+    const PAGE: usize = 20;
+
+    /// A wallet holding `ACCOUNTS` fungible mints with balances 1..=500 in mint order, plus an
+    /// emptied account; returns the owner and the mints
+    fn whale(cluster: &MockCluster) -> (Pubkey, Vec<Pubkey>) {
+        let (owner, authority) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let mints: Vec<Pubkey> = (1..=ACCOUNTS)
+            .map(|balance| {
+                let mint = cluster.add_mint(&spl_token::id(), 6, &authority);
+                cluster.add_token_account(&owner, &mint, balance);
+                mint
+            })
+            .collect();
+        let emptied = cluster.add_mint(&spl_token::id(), 6, &authority);
+        cluster.add_token_account(&owner, &emptied, 0);
+        (owner, mints)
+    }
+
+    /// Give `mint` a Token Metadata account named `name`
+    fn name_mint(cluster: &MockCluster, mint: &Pubkey, name: &str) {
+        fn string(data: &mut Vec<u8>, text: &str) {
+            data.extend((text.len() as u32).to_le_bytes());
+            data.extend(text.as_bytes());
+        }
+        let mut data = vec![4];
+        data.extend(Pubkey::new_unique().to_bytes());
+        data.extend(mint.to_bytes());
+        string(&mut data, name);
+        string(&mut data, "");
+        string(&mut data, "");
+        // No fee or creators, mutable, and none of the optional fields
+        data.extend([0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0]);
+        let account = Account { lamports: 5_616_720, data, owner: mpl_token_metadata::ID, executable: false, rent_epoch: 0 };
+        cluster.set_account(Metadata::find_pda(mint).0, account);
+    }
+
+    fn query(offset: usize, limit: usize) -> OwnedAssetsQuery {
+        OwnedAssetsQuery { offset, limit, ..OwnedAssetsQuery::default() }
+    }
+
+    fn balances(page: &OwnedAssetPage) -> Vec<u64> {
+        page.assets.iter().map(|asset| asset.balance).collect()
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn a_page_of_twenty_from_500_accounts_reads_metadata_for_the_page_only() {
+        let cluster = MockCluster::new();
+        let (owner, mints) = whale(&cluster);
+        let client = cluster.default_client();
+
+        let started = Instant::now();
+        let page = client.get_owned_assets_page(&owner, &query(0, PAGE)).await.unwrap();
+        assert!(started.elapsed() < Duration::from_secs(1), "took {:?}", started.elapsed());
+
+        assert_eq!(balances(&page), (481..=500).rev().collect::<Vec<u64>>());
+        assert_eq!(page.assets[0].mint, mints[499]);
+        assert_eq!((page.total_count, page.offset, page.next_offset), (500, 0, Some(20)));
+        assert!(page.assets.iter().all(|asset| asset.metadata.as_ref().is_some_and(|m| m.decimals == 6)));
+        // One listing, one batched read of the page's mint, metadata, and edition accounts, and
+        // no history scans for fungible mints
+        assert_eq!(cluster.call_count("getTokenAccountsByOwner"), 1);
+        assert_eq!(cluster.call_count("getMultipleAccounts"), 1);
+        assert_eq!(cluster.call_count("getAccountInfo"), 0);
+        assert_eq!(cluster.call_count("getSignaturesForAddress"), 0);
+
+        let next = client.get_owned_assets_page(&owner, &query(20, PAGE)).await.unwrap();
+        assert_eq!(balances(&next), (461..=480).rev().collect::<Vec<u64>>());
+        assert_eq!(next.next_offset, Some(40));
+        assert_eq!(cluster.call_count("getMultipleAccounts"), 2);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn the_last_page_has_no_next_offset_and_a_zero_limit_only_counts() {
+        let cluster = MockCluster::new();
+        let (owner, _) = whale(&cluster);
+        let client = cluster.default_client();
+
+        let last = client.get_owned_assets_page(&owner, &query(490, PAGE)).await.unwrap();
+        assert_eq!(balances(&last), (1..=10).rev().collect::<Vec<u64>>());
+        assert_eq!((last.total_count, last.next_offset), (500, None));
+
+        let past_the_end = client.get_owned_assets_page(&owner, &query(600, PAGE)).await.unwrap();
+        assert!(past_the_end.assets.is_empty());
+        assert_eq!(past_the_end.next_offset, None);
+
+        let reads = cluster.call_count("getMultipleAccounts");
+        let count = client.get_owned_assets_page(&owner, &query(0, 0)).await.unwrap();
+        assert!(count.assets.is_empty());
+        assert_eq!((count.total_count, count.next_offset), (500, None));
+        assert_eq!(cluster.call_count("getMultipleAccounts"), reads);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn min_balance_filters_before_paging_and_empty_accounts_are_never_listed() {
+        let cluster = MockCluster::new();
+        let (owner, _) = whale(&cluster);
+        let client = cluster.default_client();
+
+        let all = client.get_owned_assets_page(&owner, &query(0, 1000)).await.unwrap();
+        assert_eq!(all.total_count, 500);
+        assert!(all.assets.iter().all(|asset| asset.balance > 0));
+
+        let large = OwnedAssetsQuery { min_balance: 451, ..query(40, PAGE) };
+        let page = client.get_owned_assets_page(&owner, &large).await.unwrap();
+        assert_eq!(page.total_count, 50);
+        assert_eq!(balances(&page), (451..=460).rev().collect::<Vec<u64>>());
+        assert_eq!(page.next_offset, None);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn name_sort_puts_named_mints_first_and_reads_metadata_accounts_in_batches() {
+        let cluster = MockCluster::new();
+        let (owner, mints) = whale(&cluster);
+        name_mint(&cluster, &mints[10], "Zinc Receipt");
+        name_mint(&cluster, &mints[200], "Aluminium Receipt");
+        name_mint(&cluster, &mints[400], "Copper Receipt");
+        let client = cluster.default_client();
+
+        let by_name = OwnedAssetsQuery { sort: OwnedAssetSort::Name, ..query(0, PAGE) };
+        let page = client.get_owned_assets_page(&owner, &by_name).await.unwrap();
+        let names: Vec<&str> = page.assets[..3]
+            .iter()
+            .map(|asset| asset.metadata.as_ref().unwrap().name.as_str())
+            .collect();
+        assert_eq!(names, ["Aluminium Receipt", "Copper Receipt", "Zinc Receipt"]);
+        // Unnamed mints follow in address order
+        let unnamed: Vec<Pubkey> = page.assets[3..].iter().map(|asset| asset.mint).collect();
+        let named = [mints[10], mints[200], mints[400]];
+        let expected: Vec<Pubkey> = mints.iter().filter(|mint| !named.contains(mint)).take(17).copied().collect();
+        assert_eq!(unnamed, expected);
+        assert_eq!(page.total_count, 500);
+        // 1500 accounts for the names in batches of 100, then one read for the page
+        assert_eq!(cluster.call_count("getMultipleAccounts"), 16);
+        assert_eq!(cluster.call_count("getSignaturesForAddress"), 0);
+
+        let tokenized = OwnedAssetsQuery {
+            asset_type: Some(AssetType::Custom("tokenized_asset".to_string())),
+            ..query(0, PAGE)
+        };
+        let page = client.get_owned_assets_page(&owner, &tokenized).await.unwrap();
+        assert_eq!(page.total_count, 3);
+        assert_eq!(balances(&page), [401, 201, 11]);
+        assert!(page.assets.iter().all(|asset| asset.metadata.as_ref().unwrap().has_metadata));
+    }
+
+    #[test]
+    fn sort_parses_case_insensitively_and_round_trips() {
+        assert_eq!("Name".parse::<OwnedAssetSort>().unwrap(), OwnedAssetSort::Name);
+        assert_eq!(OwnedAssetSort::Balance.to_string().parse::<OwnedAssetSort>().unwrap(), OwnedAssetSort::Balance);
+        assert_eq!(serde_json::to_string(&OwnedAssetSort::Name).unwrap(), "\"name\"");
+        let error = "newest".parse::<OwnedAssetSort>().unwrap_err();
+        assert!(error.to_string().contains("expected balance or name"), "{}", error);
+    }
+}
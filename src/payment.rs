@@ -1,13 +1,19 @@
-use crate::{FinternetClient, TransactionRecord};
-use anyhow::Result;
+use crate::instructions::{memo_instruction, payment_instructions, token_transfer_instruction};
+use crate::pipeline::{SendReceipt, SendRequest};
+use crate::precheck::as_token_account;
+use crate::rpc_options::RpcCallOptions;
+use crate::{Currency, FinternetClient, TransactionRecord};
+use crate::telemetry::readable;
+use anyhow::{anyhow, Result};
+use std::collections::HashMap;
 use solana_sdk::{
+    account::Account,
+    instruction::{AccountMeta, Instruction},
     pubkey::Pubkey,
     signature::{Keypair, Signature},
     signer::Signer,
-    transaction::Transaction,
 };
-use spl_associated_token_account::instruction as ata_instruction;
-use spl_token::instruction as token_instruction;
+use std::fmt;
 use std::time::{SystemTime, UNIX_EPOCH};
 
 /// Common USDC mint addresses for different networks
@@ -29,7 +35,372 @@ pub mod usdc {
     }
 }
 
+/// Why a payment recipient address was refused before anything was sent
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RecipientError {
+    /// `account` is a token account, but for another mint than the one being sent
+    RecipientIsTokenAccountForDifferentMint {
+        account: Pubkey,
+        account_mint: Pubkey,
+        owner: Pubkey,
+        expected_mint: Pubkey,
+    },
+    /// Off-curve and not a token account: nobody holds a key for an ATA derived from it
+    RecipientOffCurve { address: Pubkey },
+}
+
+impl RecipientError {
+    /// Stable machine-readable identifier for API responses
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::RecipientIsTokenAccountForDifferentMint { .. } => "recipient_token_account_wrong_mint",
+            Self::RecipientOffCurve { .. } => "recipient_off_curve",
+        }
+    }
+}
+
+impl fmt::Display for RecipientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::RecipientIsTokenAccountForDifferentMint { account, account_mint, owner, expected_mint } => write!(
+                f,
+                "{} is a token account for mint {}, not {}; it belongs to wallet {}",
+                account, account_mint, expected_mint, owner
+            ),
+            Self::RecipientOffCurve { address } => write!(
+                f,
+                "{} is an off-curve address and not a token account, so tokens sent to it would be unreachable",
+                address
+            ),
+        }
+    }
+}
+
+impl std::error::Error for RecipientError {}
+
+/// Why typed-in text is not an amount of the currency it was meant for
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AmountError {
+    Empty,
+    Negative { input: String },
+    InvalidCharacter { input: String, character: char },
+    /// Separators that could be read more than one way, e.g. `1,000` or `1.000,000.5`
+    Ambiguous { input: String, reason: String },
+    /// Thousands separators not between groups of three digits
+    Misgrouped { input: String },
+    /// A currency symbol or ticker for another currency than the one being sent
+    SymbolMismatch { symbol: String, currency: String },
+    TooManyDecimals { input: String, currency: String, decimals: u8 },
+    Overflow { input: String },
+}
+
+impl AmountError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::Empty => "amount_empty",
+            Self::Negative { .. } => "amount_negative",
+            Self::InvalidCharacter { .. } => "amount_invalid_character",
+            Self::Ambiguous { .. } => "amount_ambiguous",
+            Self::Misgrouped { .. } => "amount_misgrouped",
+            Self::SymbolMismatch { .. } => "amount_symbol_mismatch",
+            Self::TooManyDecimals { .. } => "amount_too_many_decimals",
+            Self::Overflow { .. } => "amount_overflow",
+        }
+    }
+}
+
+impl fmt::Display for AmountError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Empty => write!(f, "Amount is empty"),
+            Self::Negative { input } => write!(f, "Amount '{}' is negative", input),
+            Self::InvalidCharacter { input, character } => {
+                write!(f, "Amount '{}' contains '{}', which is not part of a number", input, character)
+            }
+            Self::Ambiguous { input, reason } => write!(f, "Amount '{}' is ambiguous: {}", input, reason),
+            Self::Misgrouped { input } => {
+                write!(f, "Amount '{}' has thousands separators that do not separate groups of three digits", input)
+            }
+            Self::SymbolMismatch { symbol, currency } => write!(f, "'{}' is not a symbol for {}", symbol, currency),
+            Self::TooManyDecimals { input, currency, decimals } => {
+                write!(f, "{} has at most {} decimal places, got '{}'", currency, decimals, input)
+            }
+            Self::Overflow { input } => write!(f, "Amount '{}' is too large", input),
+        }
+    }
+}
+
+impl std::error::Error for AmountError {}
+
+/// An exact amount of a currency, read from what a person typed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Amount {
+    pub base_units: u64,
+    pub currency: Currency,
+}
+
+impl Amount {
+    /// Read `input` as a decimal amount of `currency`, without going through floats
+    ///
+    /// Accepts thousands separators (`1,000.50`, or `1.000,50` in the European style), a
+    /// decimal comma on its own (`1000,50`), a `k` or `m` suffix (`0.5k`), and a leading or
+    /// trailing symbol as long as it names `currency`: its ticker, or `$` for USDC and `◎` for
+    /// SOL. A lone comma or dot between one to three digits and exactly three more (`1,000` or
+    /// `1.000`) could be either a thousands separator or a decimal separator, and is refused;
+    /// a leading `0` (`0.125`) can only start a fraction, so it is read as one.
+    pub fn parse(input: &str, currency: &Currency) -> Result<Self, AmountError> {
+        let base_units = parse_base_units(input, currency)?;
+        Ok(Self { base_units, currency: currency.clone() })
+    }
+
+    /// The amount as a plain decimal, e.g. `1000.5`, which `parse` reads back to the same amount
+    ///
+    /// A fraction of exactly three digits after a whole part of one to three gets a trailing
+    /// zero (`1.0050`), so it cannot be read as a thousands separator.
+    pub fn to_decimal_string(&self) -> String {
+        let decimals = self.currency.decimals() as usize;
+        if decimals == 0 {
+            return self.base_units.to_string();
+        }
+        let scale = 10u64.pow(decimals as u32);
+        let fraction = format!("{:0width$}", self.base_units % scale, width = decimals);
+        let fraction = fraction.trim_end_matches('0');
+        if fraction.is_empty() {
+            (self.base_units / scale).to_string()
+        } else {
+            unambiguous_decimal(format!("{}.{}", self.base_units / scale, fraction))
+        }
+    }
+
+}
+
+impl fmt::Display for Amount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.currency.format_amount(self.base_units))
+    }
+}
+
+fn parse_base_units(input: &str, currency: &Currency) -> Result<u64, AmountError> {
+    let original = input;
+    let text = strip_symbol(input.trim(), currency)?;
+    if text.is_empty() {
+        return Err(AmountError::Empty);
+    }
+    if text.starts_with('-') {
+        return Err(AmountError::Negative { input: original.to_string() });
+    }
+    let text = text.strip_prefix('+').unwrap_or(text);
+
+    // A magnitude suffix moves the decimal point; `k` and `m` are never digits or symbols
+    let (text, shift) = match text.chars().last().map(|c| c.to_ascii_lowercase()) {
+        Some('k') => (text[..text.len() - 1].trim_end(), 3),
+        Some('m') => (text[..text.len() - 1].trim_end(), 6),
+        _ => (text, 0),
+    };
+    if let Some(character) = text.chars().find(|c| !c.is_ascii_digit() && *c != ',' && *c != '.') {
+        return Err(AmountError::InvalidCharacter { input: original.to_string(), character });
+    }
+
+    let (whole, fraction) = split_decimal(text, original)?;
+    if whole.is_empty() && fraction.is_empty() {
+        return Err(AmountError::Empty);
+    }
+
+    // Shift the decimal point right for the suffix, then split off the currency's decimals
+    let mut digits = format!("{}{}", whole, fraction);
+    let mut fraction_len = fraction.len();
+    if shift > fraction_len {
+        digits.push_str(&"0".repeat(shift - fraction_len));
+        fraction_len = 0;
+    } else {
+        fraction_len -= shift;
+    }
+    let decimals = currency.decimals() as usize;
+    let significant = digits.trim_end_matches('0').len().max(digits.len() - fraction_len);
+    if significant > digits.len() - fraction_len + decimals {
+        return Err(AmountError::TooManyDecimals {
+            input: original.to_string(),
+            currency: currency.label(),
+            decimals: currency.decimals(),
+        });
+    }
+    if fraction_len > decimals {
+        digits.truncate(digits.len() - (fraction_len - decimals));
+    } else {
+        digits.push_str(&"0".repeat(decimals - fraction_len));
+    }
+    let digits = digits.trim_start_matches('0');
+    if digits.is_empty() {
+        return Ok(0);
+    }
+    digits.parse::<u64>().map_err(|_| AmountError::Overflow { input: original.to_string() })
+}
+
+/// Drop a leading or trailing symbol, refusing one that names another currency
+fn strip_symbol<'a>(text: &'a str, currency: &Currency) -> Result<&'a str, AmountError> {
+    let label = currency.label();
+    let is_usdc = label == "USDC";
+    let is_sol = *currency == Currency::Sol;
+    for symbol in ["$", "€", "£", "◎"] {
+        let stripped = text.strip_prefix(symbol).or_else(|| text.strip_suffix(symbol));
+        if let Some(stripped) = stripped {
+            let matches = (symbol == "$" && is_usdc) || (symbol == "◎" && is_sol);
+            if !matches {
+                return Err(AmountError::SymbolMismatch { symbol: symbol.to_string(), currency: label });
+            }
+            return Ok(stripped.trim());
+        }
+    }
+    // A ticker, e.g. `25 USDC` or `USDC 25`
+    let alphabetic_end = text.trim_end_matches(|c: char| c.is_ascii_alphabetic());
+    let alphabetic_start = text.trim_start_matches(|c: char| c.is_ascii_alphabetic());
+    for (rest, ticker) in [
+        (alphabetic_end, &text[alphabetic_end.len()..]),
+        (alphabetic_start, &text[..text.len() - alphabetic_start.len()]),
+    ] {
+        // A bare magnitude suffix is not a ticker
+        if ticker.len() > 1 {
+            if !ticker.eq_ignore_ascii_case(&label) {
+                return Err(AmountError::SymbolMismatch { symbol: ticker.to_string(), currency: label });
+            }
+            return Ok(rest.trim());
+        }
+    }
+    Ok(text)
+}
+
+/// Split `text` into whole digits and fraction digits, removing thousands separators
+fn split_decimal<'a>(text: &'a str, original: &str) -> Result<(String, &'a str), AmountError> {
+    let ambiguous = |reason: &str| AmountError::Ambiguous { input: original.to_string(), reason: reason.to_string() };
+    let commas = text.matches(',').count();
+    let dots = text.matches('.').count();
+
+    let decimal_separator = match (commas, dots) {
+        (0, 0) => None,
+        // Both present: the last one is the decimal separator, and it may appear only once,
+        // after every thousands separator
+        (_, _) if commas > 0 && dots > 0 => {
+            let last = if text.rfind(',') > text.rfind('.') { ',' } else { '.' };
+            let count = if last == ',' { commas } else { dots };
+            let other = if last == ',' { '.' } else { ',' };
+            if count > 1 || text.rfind(other) > text.rfind(last) {
+                return Err(ambiguous("both ',' and '.' are used as separators in conflicting positions"));
+            }
+            Some(last)
+        }
+        (0, 1) | (1, 0) => {
+            let separator = if commas == 1 { ',' } else { '.' };
+            if could_be_grouping(text, separator) {
+                return Err(ambiguous(&format!(
+                    "'{}' followed by three digits may be a thousands separator or a decimal separator",
+                    separator
+                )));
+            }
+            Some(separator)
+        }
+        // Repeated separators of one kind are only thousands separators
+        _ => None,
+    };
+
+    let (whole, fraction) = match decimal_separator {
+        Some(separator) => text.rsplit_once(separator).unwrap_or((text, "")),
+        None => (text, ""),
+    };
+    let thousands = match decimal_separator {
+        Some(',') => '.',
+        Some(_) => ',',
+        None if commas > 0 => ',',
+        None => '.',
+    };
+    if fraction.contains([',', '.']) {
+        return Err(ambiguous("separators after the decimal point"));
+    }
+    if !whole.contains(thousands) {
+        return Ok((whole.to_string(), fraction));
+    }
+    let mut groups = whole.split(thousands);
+    let first = groups.next().unwrap_or_default();
+    if first.is_empty() || first.len() > 3 || groups.clone().any(|group| group.len() != 3) {
+        return Err(AmountError::Misgrouped { input: original.to_string() });
+    }
+    Ok((whole.replace(thousands, ""), fraction))
+}
+
+/// Whether the one `separator` in `text` could be a thousands separator: one to three digits
+/// before it, not starting with `0`, and exactly three after
+fn could_be_grouping(text: &str, separator: char) -> bool {
+    match text.split_once(separator) {
+        Some((before, after)) => (1..=3).contains(&before.len()) && !before.starts_with('0') && after.len() == 3,
+        None => false,
+    }
+}
+
+/// `text`, written with a decimal point, with a trailing zero added when `parse` would
+/// otherwise refuse the point as possibly a thousands separator
+fn unambiguous_decimal(mut text: String) -> String {
+    if could_be_grouping(&text, '.') {
+        text.push('0');
+    }
+    text
+}
+
+/// Deserialize an amount given as a JSON number or string into its decimal text
+///
+/// Request structs keep the text and read it with `Amount::parse` once they know the currency.
+/// Numbers are taken in their shortest decimal form, so `12.5` arrives as `"12.5"`. A number's
+/// dot is always a decimal point, so `1.005` arrives as `"1.0050"` rather than being refused.
+pub fn deserialize_amount_text<'de, D>(deserializer: D) -> std::result::Result<String, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    use serde::Deserialize;
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum AmountText {
+        Number(serde_json::Number),
+        Text(String),
+    }
+    Ok(match AmountText::deserialize(deserializer)? {
+        AmountText::Number(number) => unambiguous_decimal(number.to_string()),
+        AmountText::Text(text) => text,
+    })
+}
+
+/// Where a payment leg's tokens go
+pub(crate) enum RecipientAccount {
+    /// An owner wallet; tokens go to its associated token account
+    Wallet,
+    /// Already a token account of the right mint
+    TokenAccount(Pubkey),
+}
+
+/// Classify `to` from its account, `None` when it does not exist
+pub(crate) fn classify_recipient(to: &Pubkey, token_mint: &Pubkey, account: Option<&Account>) -> Result<RecipientAccount> {
+    if let Some(token) = account.and_then(as_token_account) {
+        if token.mint == *token_mint {
+            return Ok(RecipientAccount::TokenAccount(*to));
+        }
+        return Err(RecipientError::RecipientIsTokenAccountForDifferentMint {
+            account: *to,
+            account_mint: token.mint,
+            owner: token.owner,
+            expected_mint: *token_mint,
+        }
+        .into());
+    }
+    if !to.is_on_curve() {
+        return Err(RecipientError::RecipientOffCurve { address: *to }.into());
+    }
+    Ok(RecipientAccount::Wallet)
+}
+
 impl FinternetClient {
+    /// Classify `to` as a wallet or as a token account that can be paid directly
+    pub(crate) fn resolve_recipient(&self, to: &Pubkey, token_mint: &Pubkey) -> Result<RecipientAccount> {
+        let account = self.client.get_account_with_commitment(to, self.client.commitment())?.value;
+        classify_recipient(to, token_mint, account.as_ref())
+    }
+    
     /// Send SPL token payment (e.g., USDC) between wallets
     pub async fn send_payment(
         &self,
@@ -39,7 +410,89 @@ impl FinternetClient {
         token_mint: &Pubkey,
         memo: Option<&str>,
     ) -> Result<Signature> {
-        log::info!(
+        self.send_payment_with_options(from_wallet, to_pubkey, amount, token_mint, memo, &RpcCallOptions::default())
+            .await
+    }
+    
+    /// `send_payment` with per-call commitment, confirmation timeout, retries, and preflight
+    pub async fn send_payment_with_options(
+        &self,
+        from_wallet: &Keypair,
+        to_pubkey: &Pubkey,
+        amount: u64,
+        token_mint: &Pubkey,
+        memo: Option<&str>,
+        options: &RpcCallOptions,
+    ) -> Result<Signature> {
+        self.send_referenced_payment(from_wallet, to_pubkey, amount, token_mint, memo, &[], options)
+            .await
+    }
+    
+    /// `send_payment` whose transfer also carries `reference` as a read-only key, the Solana Pay
+    /// convention, so `find_payment_by_reference` can find it later
+    pub async fn send_payment_with_reference(
+        &self,
+        from_wallet: &Keypair,
+        to_pubkey: &Pubkey,
+        amount: u64,
+        token_mint: &Pubkey,
+        memo: Option<&str>,
+        reference: &Pubkey,
+    ) -> Result<Signature> {
+        self.send_referenced_payment(
+            from_wallet,
+            to_pubkey,
+            amount,
+            token_mint,
+            memo,
+            std::slice::from_ref(reference),
+            &RpcCallOptions::default(),
+        )
+        .await
+    }
+    
+    /// `send_payment` returning the `SendReceipt`, with whatever send middleware attached to it
+    pub async fn send_payment_with_receipt(
+        &self,
+        from_wallet: &Keypair,
+        to_pubkey: &Pubkey,
+        amount: u64,
+        token_mint: &Pubkey,
+        memo: Option<&str>,
+    ) -> Result<SendReceipt> {
+        self.send_referenced_payment_receipt(from_wallet, to_pubkey, amount, token_mint, memo, &[], &RpcCallOptions::default())
+            .await
+    }
+    
+    #[allow(clippy::too_many_arguments)]
+    async fn send_referenced_payment(
+        &self,
+        from_wallet: &Keypair,
+        to_pubkey: &Pubkey,
+        amount: u64,
+        token_mint: &Pubkey,
+        memo: Option<&str>,
+        references: &[Pubkey],
+        options: &RpcCallOptions,
+    ) -> Result<Signature> {
+        self.send_referenced_payment_receipt(from_wallet, to_pubkey, amount, token_mint, memo, references, options)
+            .await
+            .map(|receipt| receipt.signature)
+    }
+    
+    #[allow(clippy::too_many_arguments)]
+    #[tracing::instrument(skip_all, fields(wallet = %readable(&from_wallet.pubkey()), to = %readable(to_pubkey), mint = %token_mint, amount, rpc = %self.rpc_endpoint(), signature = tracing::field::Empty))]
+    async fn send_referenced_payment_receipt(
+        &self,
+        from_wallet: &Keypair,
+        to_pubkey: &Pubkey,
+        amount: u64,
+        token_mint: &Pubkey,
+        memo: Option<&str>,
+        references: &[Pubkey],
+        options: &RpcCallOptions,
+    ) -> Result<SendReceipt> {
+        tracing::info!(
             "Sending payment: {} tokens from {} to {} (mint: {})",
             amount,
             from_wallet.pubkey(),
@@ -47,82 +500,146 @@ impl FinternetClient {
             token_mint
         );
         
-        // Get source associated token account
-        let from_ata = spl_associated_token_account::get_associated_token_address(
-            &from_wallet.pubkey(),
-            token_mint,
-        );
+        let parameters = serde_json::json!({
+            "from": from_wallet.pubkey().to_string(),
+            "to": to_pubkey.to_string(),
+            "amount": amount,
+            "mint": token_mint.to_string(),
+            "memo": memo,
+            "references": references.iter().map(Pubkey::to_string).collect::<Vec<_>>(),
+        });
+        let request = SendRequest::payment("send_payment", from_wallet.pubkey(), *to_pubkey, amount, *token_mint, memo, parameters)
+            .with_references(references)
+            .with_options(options);
+        let receipt = self.send_through_pipeline(request, &[from_wallet]).await?;
+        tracing::Span::current().record("signature", tracing::field::display(&receipt.signature));
         
-        // Get destination associated token account
-        let to_ata = spl_associated_token_account::get_associated_token_address(
-            to_pubkey,
-            token_mint,
-        );
+        tracing::info!("Payment sent successfully! Signature: {}", receipt.signature);
+        Ok(receipt)
+    }
+    
+    /// Pay a primary recipient and route basis-point cuts of the amount to other parties,
+    /// all in a single transaction so either everyone is paid or nobody is
+    ///
+    /// Each split receives `amount * bps / 10000` rounded down; the rounding remainder and
+    /// everything not split off goes to the primary recipient.
+    #[tracing::instrument(skip_all, fields(wallet = %readable(&from_wallet.pubkey()), to = %readable(&primary.0), mint = %token_mint, amount = primary.1, splits = splits.len(), signature = tracing::field::Empty))]
+    pub async fn send_split_payment(
+        &self,
+        from_wallet: &Keypair,
+        primary: (Pubkey, u64),
+        splits: &[(Pubkey, u16)],
+        token_mint: &Pubkey,
+        memo: Option<&str>,
+    ) -> Result<Signature> {
+        let parameters = serde_json::json!({
+            "from": from_wallet.pubkey().to_string(),
+            "to": primary.0.to_string(),
+            "amount": primary.1,
+            "splits": splits.iter().map(|(to, bps)| serde_json::json!({ "to": to.to_string(), "bps": bps })).collect::<Vec<_>>(),
+            "mint": token_mint.to_string(),
+            "memo": memo,
+        });
+        let request = SendRequest::payment("send_split_payment", from_wallet.pubkey(), primary.0, primary.1, *token_mint, memo, parameters)
+            .with_splits(splits);
+        let signature = self.send_through_pipeline(request, &[from_wallet]).await?.signature;
+        tracing::Span::current().record("signature", tracing::field::display(&signature));
         
+        tracing::info!("Split payment sent successfully! Signature: {}", signature);
+        Ok(signature)
+    }
+    
+    /// Instructions of a split payment, shared by `send_split_payment` and its preview
+    pub(crate) fn split_payment_instructions(
+        &self,
+        from: &Pubkey,
+        legs: &[(Pubkey, u64)],
+        token_mint: &Pubkey,
+        memo: Option<&str>,
+    ) -> Result<Vec<Instruction>> {
         let mut instructions = Vec::new();
-        
-        // Check if destination ATA exists, create if not
-        if self.client.get_account(&to_ata).is_err() {
-            log::info!("Creating associated token account for recipient");
-            let create_ata_ix = ata_instruction::create_associated_token_account(
-                &from_wallet.pubkey(),
-                to_pubkey,
-                token_mint,
-                &spl_token::id(),
-            );
-            instructions.push(create_ata_ix);
+        for (recipient, amount) in legs {
+            self.push_transfer_instructions(&mut instructions, from, recipient, *amount, token_mint)?;
         }
         
-        // Create transfer instruction
-        let transfer_ix = token_instruction::transfer(
-            &spl_token::id(),
-            &from_ata,
-            &to_ata,
-            &from_wallet.pubkey(),
-            &[&from_wallet.pubkey()],
-            amount,
-        )?;
-        instructions.push(transfer_ix);
+        if let Some(memo_text) = memo {
+            instructions.push(memo_instruction(from, memo_text));
+        }
+        Ok(instructions)
+    }
+    
+    /// Instructions of a `send_payment` transfer, shared with its preview so the two cannot diverge
+    pub(crate) fn payment_transaction_instructions(
+        &self,
+        from: &Pubkey,
+        to_pubkey: &Pubkey,
+        amount: u64,
+        token_mint: &Pubkey,
+        memo: Option<&str>,
+        references: &[Pubkey],
+    ) -> Result<Vec<Instruction>> {
+        let mut instructions = Vec::new();
+        self.push_transfer_instructions(&mut instructions, from, to_pubkey, amount, token_mint)?;
+        if let Some(transfer) = instructions.last_mut() {
+            transfer
+                .accounts
+                .extend(references.iter().map(|reference| AccountMeta::new_readonly(*reference, false)));
+        }
         
         // Add memo instruction if provided
         if let Some(memo_text) = memo {
-            let memo_ix = spl_memo::build_memo(memo_text.as_bytes(), &[&from_wallet.pubkey()]);
-            instructions.push(memo_ix);
+            instructions.push(memo_instruction(from, memo_text));
         }
-        
-        // Get recent blockhash and build transaction
-        let recent_blockhash = self.client.get_latest_blockhash()?;
-        let mut transaction = Transaction::new_with_payer(&instructions, Some(&from_wallet.pubkey()));
-        transaction.sign(&[from_wallet], recent_blockhash);
-        
-        // Send and confirm transaction
-        let signature = self.client.send_and_confirm_transaction(&transaction)?;
-        
-        log::info!("Payment sent successfully! Signature: {}", signature);
-        Ok(signature)
+        Ok(instructions)
+    }
+    
+    /// Append the (optional) recipient ATA creation and the transfer for one payment leg
+    ///
+    /// A recipient that is itself a token account of `token_mint` is paid directly instead of
+    /// through an ATA derived from it.
+    fn push_transfer_instructions(
+        &self,
+        instructions: &mut Vec<Instruction>,
+        from: &Pubkey,
+        to: &Pubkey,
+        amount: u64,
+        token_mint: &Pubkey,
+    ) -> Result<()> {
+        match self.resolve_recipient(to, token_mint)? {
+            RecipientAccount::TokenAccount(account) => {
+                tracing::info!("Recipient {} is a token account of the mint, paying it directly", to);
+                instructions.push(token_transfer_instruction(from, &account, amount, token_mint)?);
+            }
+            RecipientAccount::Wallet => {
+                instructions.extend(payment_instructions(from, to, amount, token_mint, None)?);
+            }
+        }
+        Ok(())
     }
     
     /// Send USDC payment using the devnet USDC mint
+    ///
+    /// `amount` is in base units (millionths of a USDC); read typed text such as `"10.50"`
+    /// with `Amount::parse` first.
+    #[tracing::instrument(skip_all, fields(wallet = %readable(&from_wallet.pubkey()), to = %readable(to_pubkey), amount))]
     pub async fn send_usdc_payment(
         &self,
         from_wallet: &Keypair,
         to_pubkey: &Pubkey,
-        amount_usdc: f64, // Amount in USDC (e.g., 10.50)
+        amount: u64,
         memo: Option<&str>,
     ) -> Result<Signature> {
-        // Convert USDC amount to lamports (USDC has 6 decimals)
-        let amount_lamports = (amount_usdc * 1_000_000.0) as u64;
-        
         self.send_payment(
             from_wallet,
             to_pubkey,
-            amount_lamports,
+            amount,
             &usdc::devnet_mint(),
             memo,
         ).await
     }
     
     /// Get token balance for a wallet
+    #[tracing::instrument(skip_all, fields(wallet = %readable(wallet_pubkey), mint = %token_mint, rpc = %self.rpc_endpoint()))]
     pub async fn get_token_balance(
         &self,
         wallet_pubkey: &Pubkey,
@@ -136,7 +653,7 @@ impl FinternetClient {
         match self.client.get_token_account_balance(&ata) {
             Ok(balance) => {
                 let amount = balance.amount.parse::<u64>().unwrap_or(0);
-                log::info!(
+                tracing::info!(
                     "Token balance for {} (mint: {}): {}",
                     wallet_pubkey,
                     token_mint,
@@ -145,7 +662,7 @@ impl FinternetClient {
                 Ok(amount)
             }
             Err(_) => {
-                log::warn!(
+                tracing::warn!(
                     "No token account found for {} with mint {}",
                     wallet_pubkey,
                     token_mint
@@ -156,6 +673,7 @@ impl FinternetClient {
     }
     
     /// Get USDC balance for a wallet (returns amount in USDC, not lamports)
+    #[tracing::instrument(skip_all, fields(wallet = %readable(wallet_pubkey)))]
     pub async fn get_usdc_balance(&self, wallet_pubkey: &Pubkey) -> Result<f64> {
         let balance_lamports = self.get_token_balance(wallet_pubkey, &usdc::devnet_mint()).await?;
         Ok(balance_lamports as f64 / 1_000_000.0)
@@ -168,7 +686,7 @@ impl FinternetClient {
         from: Pubkey,
         to: Pubkey,
         amount: u64,
-        token_mint: Pubkey,
+        currency: Currency,
         memo: Option<String>,
     ) -> TransactionRecord {
         TransactionRecord {
@@ -176,16 +694,20 @@ impl FinternetClient {
             from,
             to,
             amount,
-            token_mint,
+            currency,
             timestamp: SystemTime::now()
                 .duration_since(UNIX_EPOCH)
                 .unwrap_or_default()
                 .as_secs(),
             memo,
+            fee: 0,
+            slot: 0,
+            memo_fields: HashMap::new(),
         }
     }
     
     /// Check if a wallet has sufficient balance for a payment
+    #[tracing::instrument(skip_all, fields(wallet = %readable(wallet_pubkey), mint = %token_mint, amount))]
     pub async fn can_afford_payment(
         &self,
         wallet_pubkey: &Pubkey,
@@ -197,27 +719,437 @@ impl FinternetClient {
     }
 }
 
-// Include memo program for transaction memos
-mod spl_memo {
-    use solana_sdk::{
-        instruction::{AccountMeta, Instruction},
-        pubkey::Pubkey,
-    };
+/// Work out how much each party of a split payment receives
+///
+/// Returns the primary recipient first, followed by the splits in order.
+pub fn compute_split_amounts(primary: (Pubkey, u64), splits: &[(Pubkey, u16)]) -> Result<Vec<(Pubkey, u64)>> {
+    let total_bps: u32 = splits.iter().map(|(_, bps)| *bps as u32).sum();
+    if total_bps > 10_000 {
+        return Err(anyhow!("Split basis points sum to {}, which exceeds 10000", total_bps));
+    }
+    
+    let (primary_recipient, amount) = primary;
+    let split_amounts: Vec<(Pubkey, u64)> = splits
+        .iter()
+        .map(|(recipient, bps)| (*recipient, (amount as u128 * *bps as u128 / 10_000) as u64))
+        .collect();
+    let split_total: u64 = split_amounts.iter().map(|(_, share)| share).sum();
     
-    pub fn build_memo(memo: &[u8], signer_pubkeys: &[&Pubkey]) -> Instruction {
-        Instruction {
-            program_id: memo_program_id(),
-            accounts: signer_pubkeys
-                .iter()
-                .map(|&pubkey| AccountMeta::new_readonly(*pubkey, true))
-                .collect(),
-            data: memo.to_vec(),
+    let mut payouts = Vec::with_capacity(splits.len() + 1);
+    payouts.push((primary_recipient, amount - split_total));
+    payouts.extend(split_amounts);
+    Ok(payouts)
+}
+
+/// The transfers a split payment makes: one per distinct recipient with a nonzero share
+///
+/// A recipient listed twice gets one combined transfer (and at most one ATA creation).
+pub(crate) fn split_payment_legs(primary: (Pubkey, u64), splits: &[(Pubkey, u16)]) -> Result<Vec<(Pubkey, u64)>> {
+    let payouts = compute_split_amounts(primary, splits)?;
+    let mut merged: Vec<(Pubkey, u64)> = Vec::with_capacity(payouts.len());
+    for (recipient, amount) in payouts {
+        match merged.iter_mut().find(|(existing, _)| *existing == recipient) {
+            Some(entry) => entry.1 += amount,
+            None => merged.push((recipient, amount)),
         }
     }
-    
-    fn memo_program_id() -> Pubkey {
-        "MemoSq4gqABAXKb96qnH8TysNcWxMyWCqXgDLGmfcHr"
-            .parse()
-            .unwrap()
+    Ok(merged.into_iter().filter(|(_, amount)| *amount > 0).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_cluster::{Landing, MockCluster};
+    use solana_sdk::transaction::{Transaction, TransactionError};
+    use spl_token::instruction::TokenInstruction;
+
+    /// xorshift64*, so every run checks the same inputs and a failure reproduces
+    struct Inputs(u64);
+
+    impl Inputs {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 >> 12;
+            self.0 ^= self.0 << 25;
+            self.0 ^= self.0 >> 27;
+            self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+        }
+
+        fn below(&mut self, bound: u64) -> u64 {
+            self.next() % bound
+        }
+
+        fn digits(&mut self, len: u64) -> String {
+            (0..len).map(|_| char::from(b'0' + self.below(10) as u8)).collect()
+        }
     }
-} 
\ No newline at end of file
+
+    fn currency(decimals: u8) -> Currency {
+        if decimals == 9 {
+            Currency::Sol
+        } else {
+            Currency::spl(Pubkey::new_unique(), decimals)
+        }
+    }
+
+    /// `whole` with `separator` between each group of three digits
+    fn grouped(whole: &str, separator: char) -> String {
+        let mut out = String::new();
+        for (i, digit) in whole.chars().enumerate() {
+            if i > 0 && (whole.len() - i).is_multiple_of(3) {
+                out.push(separator);
+            }
+            out.push(digit);
+        }
+        out
+    }
+
+    /// What `whole.fraction` is in base units, by integer arithmetic alone; `None` when it has
+    /// more significant decimals than the currency or does not fit a `u64`
+    fn reading(whole: &str, fraction: &str, decimals: usize) -> Option<u64> {
+        let significant = fraction.trim_end_matches('0');
+        if significant.len() > decimals {
+            return None;
+        }
+        let scaled = format!("{}{}{}", whole, significant, "0".repeat(decimals - significant.len()));
+        let value = scaled.parse::<u128>().unwrap_or(u128::MAX);
+        u64::try_from(value).ok()
+    }
+
+    #[test]
+    fn every_amount_reads_back_from_its_decimal_text() {
+        let mut inputs = Inputs(0x9e37_79b9_7f4a_7c15);
+        for _ in 0..20_000 {
+            let decimals = inputs.below(10) as u8;
+            let base_units = match inputs.below(3) {
+                0 => inputs.below(1_000),
+                1 => inputs.below(10u64.pow(12)),
+                _ => inputs.next(),
+            };
+            let currency = currency(decimals);
+            let amount = Amount { base_units, currency: currency.clone() };
+            let text = amount.to_decimal_string();
+            let parsed = Amount::parse(&text, &currency).unwrap_or_else(|e| panic!("{} ({} decimals): {}", text, decimals, e));
+            assert_eq!(parsed, amount, "{}", text);
+            assert_eq!(parsed.to_decimal_string(), text);
+        }
+    }
+
+    #[test]
+    fn no_input_reads_as_anything_but_its_decimal_value() {
+        let mut inputs = Inputs(0xd1b5_4a32_d192_ed03);
+        for _ in 0..20_000 {
+            let decimals = inputs.below(10) as usize;
+            let currency = currency(decimals as u8);
+            let whole_len = inputs.below(16);
+            let whole = inputs.digits(whole_len).trim_start_matches('0').to_string();
+            let whole = if whole.is_empty() { "0".to_string() } else { whole };
+            let fraction_len = inputs.below(decimals as u64 + 3);
+            let fraction = inputs.digits(fraction_len);
+            let expected = reading(&whole, &fraction, decimals);
+
+            let mut forms = vec![];
+            if fraction.is_empty() {
+                forms.push(whole.clone());
+            } else {
+                // A lone separator between a nonzero whole part of up to three digits and three more
+                // could be a thousands separator, so it is refused
+                if whole.len() <= 3 && whole != "0" && fraction.len() == 3 {
+                    for separator in ['.', ','] {
+                        let form = format!("{}{}{}", whole, separator, fraction);
+                        assert!(matches!(Amount::parse(&form, &currency), Err(AmountError::Ambiguous { .. })), "{}", form);
+                    }
+                } else {
+                    forms.push(format!("{}.{}", whole, fraction));
+                    forms.push(format!("{},{}", whole, fraction));
+                    forms.push(format!("{}.{}", grouped(&whole, ','), fraction));
+                    forms.push(format!("{},{}", grouped(&whole, '.'), fraction));
+                }
+            }
+            for form in forms {
+                match (Amount::parse(&form, &currency), expected) {
+                    (Ok(amount), Some(expected)) => assert_eq!(amount.base_units, expected, "{} ({} decimals)", form, decimals),
+                    (Err(AmountError::TooManyDecimals { .. }), None) if fraction.trim_end_matches('0').len() > decimals => {}
+                    (Err(AmountError::Overflow { .. }), None) => {}
+                    (result, expected) => panic!("{} ({} decimals): {:?}, expected {:?}", form, decimals, result, expected),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn suffixes_and_symbols_scale_exactly() {
+        let usdc = Currency::usdc_devnet();
+        let mut inputs = Inputs(0x2545_f491_4f6c_dd1d);
+        for _ in 0..5_000 {
+            let whole = inputs.below(1_000_000);
+            // Three fraction digits after a nonzero whole part of up to three would be ambiguous
+            let fraction_len = [1, 2, 4][inputs.below(3) as usize];
+            let fraction = inputs.digits(fraction_len);
+            let units = reading(&whole.to_string(), &fraction, 6).unwrap();
+            assert_eq!(Amount::parse(&format!("{}.{}k", whole, fraction), &usdc).unwrap().base_units, units * 1_000);
+            assert_eq!(Amount::parse(&format!("{}.{}M", whole, fraction), &usdc).unwrap().base_units, units * 1_000_000);
+            assert_eq!(Amount::parse(&format!("${}.{}", whole, fraction), &usdc).unwrap().base_units, units);
+            assert_eq!(Amount::parse(&format!("{}.{} USDC", whole, fraction), &usdc).unwrap().base_units, units);
+        }
+    }
+
+    #[test]
+    fn arbitrary_text_is_refused_or_read_without_panicking() {
+        let alphabet: Vec<char> = "0123456789.,+-$€◎kmK USDCx ".chars().collect();
+        let mut inputs = Inputs(0x6a09_e667_f3bc_c909);
+        for _ in 0..20_000 {
+            let len = inputs.below(12);
+            let text: String = (0..len).map(|_| alphabet[inputs.below(alphabet.len() as u64) as usize]).collect();
+            for decimals in [0, 6, 9] {
+                if let Ok(amount) = Amount::parse(&text, &currency(decimals)) {
+                    let again = Amount::parse(&amount.to_decimal_string(), &amount.currency).unwrap();
+                    assert_eq!(again, amount, "{:?}", text);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn decimals_a_float_would_round_are_read_exactly() {
+        let usdc = Currency::usdc_devnet();
+        // Each of these comes out a base unit off through `(x * 1e6) as u64`
+        for (text, units) in [("2.01", 2_010_000), ("4.1", 4_100_000), ("8.2", 8_200_000), ("9007199254.740993", 9_007_199_254_740_993)] {
+            assert_eq!(Amount::parse(text, &usdc).unwrap().base_units, units, "{}", text);
+        }
+        assert_eq!(Amount::parse("0.1", &Currency::Sol).unwrap().base_units, 100_000_000);
+    }
+
+    #[test]
+    fn a_lone_dot_before_three_digits_is_as_ambiguous_as_a_lone_comma() {
+        let usdc = Currency::usdc_devnet();
+        for text in ["1.000", "1,000", "25.500", "$999.999"] {
+            assert!(matches!(Amount::parse(text, &usdc), Err(AmountError::Ambiguous { .. })), "{}", text);
+        }
+        for (text, units) in [("0.125", 125_000), ("0,125", 125_000), ("1.0050", 1_005_000), ("1234.567", 1_234_567_000)] {
+            assert_eq!(Amount::parse(text, &usdc).unwrap().base_units, units, "{}", text);
+        }
+
+        // A JSON number's dot is a decimal point whatever follows it
+        #[derive(serde::Deserialize)]
+        struct Body {
+            #[serde(deserialize_with = "deserialize_amount_text")]
+            amount: String,
+        }
+        let body: Body = serde_json::from_str(r#"{ "amount": 1.005 }"#).unwrap();
+        assert_eq!(body.amount, "1.0050");
+        assert_eq!(Amount::parse(&body.amount, &usdc).unwrap().base_units, 1_005_000);
+        assert_eq!(Amount { base_units: 1_005_000, currency: usdc }.to_decimal_string(), "1.0050");
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn a_usdc_payment_sends_the_exact_base_units() {
+        let cluster = MockCluster::new();
+        let mint = cluster.add_mint(&spl_token::id(), 6, &Pubkey::new_unique());
+        cluster.set_account(crate::usdc::devnet_mint(), cluster.account(&mint).unwrap());
+        let payer = Keypair::new();
+        cluster.set_balance(payer.pubkey(), 1_000_000_000);
+        cluster.add_token_account(&payer.pubkey(), &crate::usdc::devnet_mint(), 10_000_000);
+        let client = cluster.default_client();
+
+        let amount = Amount::parse("2.01", &Currency::usdc_devnet()).unwrap();
+        client.send_usdc_payment(&payer, &Keypair::new().pubkey(), amount.base_units, None).await.unwrap();
+        let sent = cluster.sent();
+        let message = &sent.last().unwrap().message;
+        let amounts: Vec<u64> = message
+            .instructions
+            .iter()
+            .filter(|instruction| message.account_keys[instruction.program_id_index as usize] == spl_token::id())
+            .filter_map(|instruction| match TokenInstruction::unpack(&instruction.data).ok()? {
+                TokenInstruction::Transfer { amount } | TokenInstruction::TransferChecked { amount, .. } => Some(amount),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(amounts, vec![2_010_000]);
+    }
+
+    #[test]
+    fn split_shares_round_down_and_the_remainder_goes_to_the_primary() {
+        let (primary, a, b, c) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let payouts = compute_split_amounts((primary, 101), &[(a, 3_333), (b, 3_333), (c, 3_333)]).unwrap();
+        assert_eq!(payouts, vec![(primary, 2), (a, 33), (b, 33), (c, 33)]);
+
+        // A 2% commission of an amount that does not divide evenly
+        let payouts = compute_split_amounts((primary, 1_234_567), &[(a, 200)]).unwrap();
+        assert_eq!(payouts, vec![(primary, 1_209_876), (a, 24_691)]);
+
+        assert_eq!(compute_split_amounts((primary, 500), &[(a, 10_000)]).unwrap(), vec![(primary, 0), (a, 500)]);
+        assert_eq!(compute_split_amounts((primary, 500), &[]).unwrap(), vec![(primary, 500)]);
+        let error = compute_split_amounts((primary, 500), &[(a, 9_000), (b, 1_001)]).unwrap_err();
+        assert!(error.to_string().contains("10001"), "{}", error);
+    }
+
+    #[test]
+    fn split_payouts_always_add_up_to_the_amount() {
+        let mut inputs = Inputs(0x5eed_0000_0000_2369);
+        for _ in 0..5_000 {
+            let amount = match inputs.below(3) {
+                0 => inputs.below(1_000),
+                1 => inputs.next(),
+                _ => u64::MAX - inputs.below(1_000),
+            };
+            let mut budget = 10_000;
+            let splits: Vec<(Pubkey, u16)> = (0..inputs.below(6))
+                .map(|_| {
+                    let bps = inputs.below(budget + 1);
+                    budget -= bps;
+                    (Pubkey::new_unique(), bps as u16)
+                })
+                .collect();
+            let primary = Pubkey::new_unique();
+            let payouts = compute_split_amounts((primary, amount), &splits).unwrap();
+            assert_eq!(payouts, compute_split_amounts((primary, amount), &splits).unwrap());
+            assert_eq!(payouts.iter().map(|(_, share)| *share as u128).sum::<u128>(), amount as u128);
+            assert_eq!(payouts[0].0, primary);
+            for ((recipient, share), (split, bps)) in payouts[1..].iter().zip(&splits) {
+                assert_eq!(recipient, split);
+                assert_eq!(*share as u128, amount as u128 * *bps as u128 / 10_000);
+            }
+            // Rounding never takes more than one base unit per split from the primary's due
+            let primary_due = amount as u128 * budget as u128 / 10_000;
+            assert!((payouts[0].1 as u128) >= primary_due);
+            assert!((payouts[0].1 as u128) <= primary_due + splits.len() as u128 + 1);
+        }
+    }
+
+    #[test]
+    fn a_recipient_listed_twice_gets_one_leg_and_empty_shares_none() {
+        let (primary, a, b) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let legs = split_payment_legs((primary, 1_000), &[(a, 100), (b, 0), (a, 150), (primary, 250)]).unwrap();
+        assert_eq!(legs, vec![(primary, 975), (a, 25)]);
+    }
+
+    fn transfer_amounts(transaction: &Transaction) -> Vec<u64> {
+        let message = &transaction.message;
+        message
+            .instructions
+            .iter()
+            .filter(|instruction| message.account_keys[instruction.program_id_index as usize] == spl_token::id())
+            .filter_map(|instruction| match TokenInstruction::unpack(&instruction.data).ok()? {
+                TokenInstruction::Transfer { amount } | TokenInstruction::TransferChecked { amount, .. } => Some(amount),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn a_split_payment_pays_everyone_in_one_transaction_or_nobody() {
+        let cluster = MockCluster::new();
+        let mint = cluster.add_mint(&spl_token::id(), 6, &Pubkey::new_unique());
+        let payer = Keypair::new();
+        cluster.set_balance(payer.pubkey(), 1_000_000_000);
+        cluster.add_token_account(&payer.pubkey(), &mint, 10_000_000);
+        let (seller, marketplace, referrer) = (Keypair::new().pubkey(), Keypair::new().pubkey(), Keypair::new().pubkey());
+        let splits = [(marketplace, 200), (referrer, 50)];
+        let client = cluster.default_client();
+
+        client.send_split_payment(&payer, (seller, 1_000_001), &splits, &mint, Some("order 88")).await.unwrap();
+        let sent = cluster.sent();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(transfer_amounts(&sent[0]), vec![975_001, 20_000, 5_000]);
+        let creates = sent[0]
+            .message
+            .instructions
+            .iter()
+            .filter(|instruction| sent[0].message.account_keys[instruction.program_id_index as usize] == spl_associated_token_account::id())
+            .count();
+        assert_eq!(creates, 3);
+
+        // When that one transaction fails on-chain, no leg is retried or sent on its own
+        cluster.set_landing(Landing::Failed(TransactionError::InstructionError(
+            1,
+            solana_sdk::instruction::InstructionError::Custom(1),
+        )));
+        client.send_split_payment(&payer, (seller, 1_000_001), &splits, &mint, None).await.unwrap_err();
+        let sent = cluster.sent();
+        assert_eq!(sent.len(), 2);
+        assert_eq!(transfer_amounts(&sent[1]), vec![975_001, 20_000, 5_000]);
+
+        // Over-allocated splits are refused before anything is signed
+        client.send_split_payment(&payer, (seller, 100), &[(marketplace, 10_001)], &mint, None).await.unwrap_err();
+        assert_eq!(cluster.sent().len(), 2);
+    }
+
+    /// Where each transfer of `transaction` sends its tokens, and how many ATAs it creates
+    fn destinations(transaction: &Transaction) -> (Vec<Pubkey>, usize) {
+        let message = &transaction.message;
+        let program = |instruction: &solana_sdk::instruction::CompiledInstruction| message.account_keys[instruction.program_id_index as usize];
+        let destinations = message
+            .instructions
+            .iter()
+            .filter(|instruction| program(instruction) == spl_token::id())
+            .filter_map(|instruction| {
+                let index = match TokenInstruction::unpack(&instruction.data).ok()? {
+                    TokenInstruction::Transfer { .. } => 1,
+                    TokenInstruction::TransferChecked { .. } => 2,
+                    _ => return None,
+                };
+                Some(message.account_keys[instruction.accounts[index] as usize])
+            })
+            .collect();
+        let creates = message
+            .instructions
+            .iter()
+            .filter(|instruction| program(instruction) == spl_associated_token_account::id())
+            .count();
+        (destinations, creates)
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn a_recipient_is_paid_at_its_ata_or_directly_as_a_token_account() {
+        let cluster = MockCluster::new();
+        let mint = cluster.add_mint(&spl_token::id(), 6, &Pubkey::new_unique());
+        let payer = Keypair::new();
+        cluster.set_balance(payer.pubkey(), 1_000_000_000);
+        cluster.add_token_account(&payer.pubkey(), &mint, 10_000_000);
+        let client = cluster.default_client();
+
+        // An owner wallet is paid at its ATA, created on the way
+        let wallet = Keypair::new().pubkey();
+        client.send_payment(&payer, &wallet, 1_000, &mint, None).await.unwrap();
+        let ata = spl_associated_token_account::get_associated_token_address(&wallet, &mint);
+        assert_eq!(destinations(&cluster.sent()[0]), (vec![ata], 1));
+
+        // A pasted token account of the right mint, ATA or not, is paid as is: no ATA of an ATA
+        let account = Pubkey::new_unique();
+        cluster.set_token_account(account, &Keypair::new().pubkey(), &mint, 0);
+        client.send_payment(&payer, &account, 1_000, &mint, None).await.unwrap();
+        assert_eq!(destinations(&cluster.sent()[1]), (vec![account], 0));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn a_token_account_of_another_mint_or_an_off_curve_address_is_refused_before_sending() {
+        let cluster = MockCluster::new();
+        let mint = cluster.add_mint(&spl_token::id(), 6, &Pubkey::new_unique());
+        let other_mint = cluster.add_mint(&spl_token::id(), 6, &Pubkey::new_unique());
+        let payer = Keypair::new();
+        cluster.set_balance(payer.pubkey(), 1_000_000_000);
+        cluster.add_token_account(&payer.pubkey(), &mint, 10_000_000);
+        let client = cluster.default_client();
+
+        let owner = Keypair::new().pubkey();
+        let account = cluster.add_token_account(&owner, &other_mint, 0);
+        let error = client.send_payment(&payer, &account, 1_000, &mint, None).await.unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<RecipientError>(),
+            Some(&RecipientError::RecipientIsTokenAccountForDifferentMint {
+                account,
+                account_mint: other_mint,
+                owner,
+                expected_mint: mint,
+            })
+        );
+        assert!(error.to_string().contains(&owner.to_string()), "{}", error);
+
+        // A PDA that holds no token account: an ATA derived from it could never be spent
+        let (pda, _) = Pubkey::find_program_address(&[b"vault"], &Pubkey::new_unique());
+        let error = client.send_payment(&payer, &pda, 1_000, &mint, None).await.unwrap_err();
+        assert_eq!(error.downcast_ref::<RecipientError>(), Some(&RecipientError::RecipientOffCurve { address: pda }));
+        assert_eq!(error.downcast_ref::<RecipientError>().unwrap().code(), "recipient_off_curve");
+        assert!(cluster.sent().is_empty());
+    }
+}
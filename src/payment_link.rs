@@ -0,0 +1,391 @@
+use crate::telemetry::readable;
+use crate::validation::{self, validate_payment};
+use crate::{Currency, FinternetClient};
+use anyhow::Result;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use solana_sdk::{
+    pubkey::Pubkey,
+    signature::{Keypair, Signature},
+    signer::Signer,
+};
+use std::fmt;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Every payment link starts with this, followed by `<base64url payload>.<signature>`
+pub const PAYMENT_LINK_PREFIX: &str = "finternet-pay:";
+const PAYMENT_LINK_VERSION: u32 = 1;
+
+/// "Pay me this much for this", signed by the wallet that gets paid
+///
+/// The signature covers the recipient, so a link cannot be edited to pay someone else.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "LinkPayload", into = "LinkPayload")]
+pub struct PaymentLink {
+    pub version: u32,
+    pub id: String,
+    pub recipient: Pubkey,
+    pub currency: Currency,
+    /// Base units of `currency`
+    pub amount: u64,
+    pub memo: Option<String>,
+    pub created_at: u64,
+    pub expires_at: u64,
+}
+
+/// What is actually signed and encoded: addresses as base58 so the link stays short
+#[derive(Serialize, Deserialize)]
+struct LinkPayload {
+    v: u32,
+    id: String,
+    to: String,
+    mint: String,
+    decimals: u8,
+    amount: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    memo: Option<String>,
+    created_at: u64,
+    expires_at: u64,
+}
+
+impl From<PaymentLink> for LinkPayload {
+    fn from(link: PaymentLink) -> Self {
+        Self {
+            v: link.version,
+            id: link.id,
+            to: link.recipient.to_string(),
+            mint: link.currency.mint().map(|mint| mint.to_string()).unwrap_or_default(),
+            decimals: link.currency.decimals(),
+            amount: link.amount,
+            memo: link.memo,
+            created_at: link.created_at,
+            expires_at: link.expires_at,
+        }
+    }
+}
+
+impl TryFrom<LinkPayload> for PaymentLink {
+    type Error = String;
+
+    fn try_from(payload: LinkPayload) -> Result<Self, String> {
+        let address = |value: &str| Pubkey::from_str(value).map_err(|_| format!("invalid address '{}'", value));
+        Ok(Self {
+            version: payload.v,
+            id: payload.id,
+            recipient: address(&payload.to)?,
+            currency: Currency::spl(address(&payload.mint)?, payload.decimals),
+            amount: payload.amount,
+            memo: payload.memo,
+            created_at: payload.created_at,
+            expires_at: payload.expires_at,
+        })
+    }
+}
+
+impl PaymentLink {
+    /// Solana Pay reference derived from the link id; every payment of the link carries it
+    pub fn reference(&self) -> Pubkey {
+        let digest = Sha256::digest(format!("finternet-payment-link:{}", self.id).as_bytes());
+        Pubkey::new_from_array(digest.into())
+    }
+
+    /// Decode a link and check its signature against the recipient it names
+    ///
+    /// Expiry is not checked here, so an expired link can still be shown.
+    pub fn decode(link: &str) -> Result<Self, PaymentLinkError> {
+        let malformed = |reason: &str| PaymentLinkError::Malformed(reason.to_string());
+        let body = link
+            .trim()
+            .strip_prefix(PAYMENT_LINK_PREFIX)
+            .ok_or_else(|| malformed("missing the finternet-pay: prefix"))?;
+        let (payload, signature) = body.split_once('.').ok_or_else(|| malformed("missing the signature"))?;
+        let message = base64::engine::general_purpose::URL_SAFE_NO_PAD
+            .decode(payload)
+            .ok()
+            .and_then(|bytes| String::from_utf8(bytes).ok())
+            .ok_or_else(|| malformed("payload is not valid base64url"))?;
+        let decoded: PaymentLink =
+            serde_json::from_str(&message).map_err(|e| PaymentLinkError::Malformed(e.to_string()))?;
+        if decoded.version != PAYMENT_LINK_VERSION {
+            return Err(PaymentLinkError::Malformed(format!("unsupported version {}", decoded.version)));
+        }
+
+        match FinternetClient::verify_offchain_message(&decoded.recipient, &message, signature) {
+            Ok(true) => Ok(decoded),
+            Ok(false) => Err(PaymentLinkError::InvalidSignature),
+            Err(e) => Err(PaymentLinkError::Malformed(e.to_string())),
+        }
+    }
+
+    pub fn is_expired(&self, now: u64) -> bool {
+        now > self.expires_at
+    }
+}
+
+impl fmt::Display for PaymentLink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Payment link {}", self.id)?;
+        writeln!(f, "  Pay:     {}", self.currency.format_amount(self.amount))?;
+        writeln!(f, "  To:      {}", self.recipient)?;
+        if let Some(memo) = &self.memo {
+            writeln!(f, "  Memo:    {}", memo)?;
+        }
+        write!(f, "  Expires: {}", self.expires_at)
+    }
+}
+
+/// Why a payment link was refused
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PaymentLinkError {
+    Malformed(String),
+    /// The payload was altered after the recipient signed it
+    InvalidSignature,
+    Expired { expires_at: u64 },
+    /// A payment carrying this link's reference has already landed
+    AlreadyPaid { signature: Signature },
+    /// Payment links are for SPL tokens; `send_payment` cannot pay native SOL
+    UnsupportedCurrency,
+}
+
+impl PaymentLinkError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::Malformed(_) => "malformed_payment_link",
+            Self::InvalidSignature => "invalid_signature",
+            Self::Expired { .. } => "payment_link_expired",
+            Self::AlreadyPaid { .. } => "payment_link_already_paid",
+            Self::UnsupportedCurrency => "unsupported_currency",
+        }
+    }
+}
+
+impl fmt::Display for PaymentLinkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Malformed(reason) => write!(f, "Malformed payment link: {}", reason),
+            Self::InvalidSignature => write!(f, "Payment link signature does not match its recipient"),
+            Self::Expired { expires_at } => write!(f, "Payment link expired at {}", expires_at),
+            Self::AlreadyPaid { signature } => write!(f, "Payment link already paid by {}", signature),
+            Self::UnsupportedCurrency => write!(f, "Payment links only support SPL tokens"),
+        }
+    }
+}
+
+impl std::error::Error for PaymentLinkError {}
+
+impl FinternetClient {
+    /// A link asking for `amount` of `currency` to be paid to `recipient`, signed by it
+    pub fn create_payment_link(
+        recipient: &Keypair,
+        amount: u64,
+        currency: &Currency,
+        memo: Option<&str>,
+        expires_at: u64,
+    ) -> Result<String> {
+        let mint = currency.mint().ok_or(PaymentLinkError::UnsupportedCurrency)?;
+        validation::check(validate_payment(&recipient.pubkey(), amount, &mint, memo))?;
+
+        let link = PaymentLink {
+            version: PAYMENT_LINK_VERSION,
+            id: uuid::Uuid::new_v4().to_string(),
+            recipient: recipient.pubkey(),
+            currency: currency.clone(),
+            amount,
+            memo: memo.map(str::to_string),
+            created_at: unix_now(),
+            expires_at,
+        };
+        let message = serde_json::to_string(&link)?;
+        let signature = Self::sign_offchain_message(recipient, &message)?;
+        Ok(format!(
+            "{}{}.{}",
+            PAYMENT_LINK_PREFIX,
+            base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(message.as_bytes()),
+            signature
+        ))
+    }
+
+    /// Pay a link after checking its signature and expiry, refusing one that was already paid
+    ///
+    /// The payment carries the link's `reference`, which is how an earlier payment is found.
+    #[tracing::instrument(skip_all, fields(wallet = %readable(&wallet.pubkey())))]
+    pub async fn execute_payment_link(&self, wallet: &Keypair, link: &str) -> Result<Signature> {
+        let link = PaymentLink::decode(link)?;
+        if link.is_expired(unix_now()) {
+            return Err(PaymentLinkError::Expired { expires_at: link.expires_at }.into());
+        }
+        let mint = link.currency.mint().ok_or(PaymentLinkError::UnsupportedCurrency)?;
+        let reference = link.reference();
+        if let Some((signature, _)) = self.find_payment_by_reference(&reference, &link.recipient).await? {
+            return Err(PaymentLinkError::AlreadyPaid { signature }.into());
+        }
+
+        tracing::info!(
+            "Paying link {}: {} to {}",
+            link.id,
+            link.currency.format_amount(link.amount),
+            readable(&link.recipient)
+        );
+        self.send_payment_with_reference(wallet, &link.recipient, link.amount, &mint, link.memo.as_deref(), &reference)
+            .await
+    }
+}
+
+impl FromStr for PaymentLink {
+    type Err = PaymentLinkError;
+
+    fn from_str(s: &str) -> Result<Self, PaymentLinkError> {
+        Self::decode(s)
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_cluster::MockCluster;
+
+    /// The link's payload and signature, split apart
+    fn parts(link: &str) -> (LinkPayload, String) {
+        let (payload, signature) = link.strip_prefix(PAYMENT_LINK_PREFIX).unwrap().split_once('.').unwrap();
+        let json = base64::engine::general_purpose::URL_SAFE_NO_PAD.decode(payload).unwrap();
+        (serde_json::from_slice(&json).unwrap(), signature.to_string())
+    }
+
+    /// A link of `payload` serialized as it was signed, with `signature` attached
+    fn reassemble(payload: &LinkPayload, signature: &str) -> String {
+        with_json(&serde_json::to_string(payload).unwrap(), signature)
+    }
+
+    fn with_json(json: &str, signature: &str) -> String {
+        let payload = base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(json);
+        format!("{}{}.{}", PAYMENT_LINK_PREFIX, payload, signature)
+    }
+
+    type Edit = fn(&mut LinkPayload);
+
+    fn invoice(recipient: &Keypair, mint: Pubkey, expires_at: u64) -> String {
+        FinternetClient::create_payment_link(recipient, 125_000_000, &Currency::spl(mint, 6), Some("invoice 884"), expires_at)
+            .unwrap()
+    }
+
+    #[test]
+    fn a_link_decodes_to_what_its_recipient_signed() {
+        let (recipient, mint) = (Keypair::new(), Pubkey::new_unique());
+        let link = invoice(&recipient, mint, u64::MAX);
+        let decoded: PaymentLink = link.parse().unwrap();
+
+        assert_eq!(decoded.recipient, recipient.pubkey());
+        assert_eq!(decoded.currency, Currency::spl(mint, 6));
+        assert_eq!((decoded.amount, decoded.memo.as_deref()), (125_000_000, Some("invoice 884")));
+        assert_eq!(decoded.version, PAYMENT_LINK_VERSION);
+        assert!(decoded.to_string().contains("125"), "{}", decoded);
+        // Surrounding whitespace from a copy and paste is fine
+        assert_eq!(PaymentLink::decode(&format!("  {}\n", link)).unwrap(), decoded);
+
+        // Each link has its own id, so its own reference
+        let again = PaymentLink::decode(&invoice(&recipient, mint, u64::MAX)).unwrap();
+        assert_ne!(again.id, decoded.id);
+        assert_ne!(again.reference(), decoded.reference());
+        assert_eq!(decoded.reference(), PaymentLink::decode(&link).unwrap().reference());
+    }
+
+    #[test]
+    fn any_edit_to_the_payload_breaks_the_signature() {
+        let (recipient, mint) = (Keypair::new(), Pubkey::new_unique());
+        let link = invoice(&recipient, mint, u64::MAX);
+        let (payload, signature) = parts(&link);
+        assert_eq!(reassemble(&payload, &signature), link);
+
+        let edits: [(&str, Edit); 5] = [
+            ("to", |payload| payload.to = Keypair::new().pubkey().to_string()),
+            ("amount", |payload| payload.amount *= 10),
+            ("mint", |payload| payload.mint = Pubkey::new_unique().to_string()),
+            ("memo", |payload| payload.memo = Some("invoice 885".to_string())),
+            ("expires_at", |payload| payload.expires_at = 0),
+        ];
+        for (field, edit) in edits {
+            let (mut edited, _) = parts(&link);
+            edit(&mut edited);
+            assert_eq!(
+                PaymentLink::decode(&reassemble(&edited, &signature)),
+                Err(PaymentLinkError::InvalidSignature),
+                "{} edited",
+                field
+            );
+        }
+
+        // Re-signing with another key does not help: the signature must be the named recipient's
+        let mut swapped = payload;
+        swapped.to = Keypair::new().pubkey().to_string();
+        let forged = FinternetClient::sign_offchain_message(&Keypair::new(), &serde_json::to_string(&swapped).unwrap()).unwrap();
+        assert_eq!(PaymentLink::decode(&reassemble(&swapped, &forged)), Err(PaymentLinkError::InvalidSignature));
+    }
+
+    #[test]
+    fn broken_links_are_malformed_rather_than_misread() {
+        let link = invoice(&Keypair::new(), Pubkey::new_unique(), u64::MAX);
+        let (mut future, signature) = parts(&link);
+        future.v = 2;
+
+        let broken = [
+            link.trim_start_matches(PAYMENT_LINK_PREFIX).to_string(),
+            link.split_once('.').unwrap().0.to_string(),
+            format!("{}!!!.{}", PAYMENT_LINK_PREFIX, signature),
+            format!("{}.{}", link.split_once('.').unwrap().0, "not-a-signature"),
+            with_json(r#"{"v":1}"#, &signature),
+            reassemble(&future, &signature),
+        ];
+        for link in &broken {
+            let error = PaymentLink::decode(link).unwrap_err();
+            assert!(matches!(error, PaymentLinkError::Malformed(_)), "{}: {:?}", link, error);
+            assert_eq!(error.code(), "malformed_payment_link");
+        }
+    }
+
+    #[test]
+    fn only_spl_tokens_can_be_requested() {
+        let error = FinternetClient::create_payment_link(&Keypair::new(), 1, &Currency::Sol, None, u64::MAX).unwrap_err();
+        assert_eq!(error.downcast_ref::<PaymentLinkError>(), Some(&PaymentLinkError::UnsupportedCurrency));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn an_expired_link_is_refused_and_a_live_one_pays_with_its_reference() {
+        let cluster = MockCluster::new();
+        let mint = cluster.add_mint(&spl_token::id(), 6, &Pubkey::new_unique());
+        let (payer, recipient) = (Keypair::new(), Keypair::new());
+        cluster.set_balance(payer.pubkey(), 1_000_000_000);
+        cluster.add_token_account(&payer.pubkey(), &mint, 200_000_000);
+        let client = cluster.default_client();
+
+        let expires_at = unix_now() - 1;
+        let expired = invoice(&recipient, mint, expires_at);
+        let decoded = PaymentLink::decode(&expired).unwrap();
+        assert!(decoded.is_expired(expires_at + 1) && !decoded.is_expired(expires_at));
+        let error = client.execute_payment_link(&payer, &expired).await.unwrap_err();
+        assert_eq!(error.downcast_ref::<PaymentLinkError>(), Some(&PaymentLinkError::Expired { expires_at }));
+
+        let tampered = {
+            let (mut payload, signature) = parts(&invoice(&recipient, mint, u64::MAX));
+            payload.to = payer.pubkey().to_string();
+            reassemble(&payload, &signature)
+        };
+        let error = client.execute_payment_link(&payer, &tampered).await.unwrap_err();
+        assert_eq!(error.downcast_ref::<PaymentLinkError>(), Some(&PaymentLinkError::InvalidSignature));
+        assert!(cluster.sent().is_empty());
+
+        let link = invoice(&recipient, mint, u64::MAX);
+        let signature = client.execute_payment_link(&payer, &link).await.unwrap();
+        let sent = cluster.sent();
+        assert_eq!((sent.len(), sent[0].signatures[0]), (1, signature));
+        let keys = &sent[0].message.account_keys;
+        assert!(keys.contains(&PaymentLink::decode(&link).unwrap().reference()));
+        let ata = spl_associated_token_account::get_associated_token_address(&recipient.pubkey(), &mint);
+        assert!(keys.contains(&ata));
+    }
+}
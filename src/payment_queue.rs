@@ -0,0 +1,681 @@
+//! Payments queued for a worker to send at the pace the cluster can take
+//!
+//! `PaymentQueue::enqueue` records a payment with a priority and, optionally, a time before
+//! which it must not go out. `PaymentQueue::run` drains the queue with a client and wallet: it
+//! picks the highest priority first and the oldest among equals, keeps at most
+//! `max_in_flight` payments unsettled at once, and never has two unsettled payments to one
+//! recipient, so each recipient is paid in the order it was picked.
+//!
+//! Each payment goes through the client's send pipeline like `send_payment`, so validation,
+//! policies, and any added middleware apply to it. The queue lives in a `StateStore`. As in a
+//! batch journal, a payment's signature and blockhash are stored at `PersistIntent`, before it
+//! is broadcast. After a restart, each `Submitted` payment is
+//! looked up on the cluster: it is `Sent` if it landed and `Failed` if it failed on-chain. It
+//! goes back to `Queued` once its blockhash has expired without it landing. A payment that
+//! keeps expiring is failed after `MAX_SEND_ATTEMPTS`.
+
+use crate::confirmation::OperationKind;
+use crate::pipeline::{SendBody, SendMiddleware, SendRequest, SendStage};
+use crate::send_context::SendContext;
+use crate::state_store::{legacy_map, FileStateStore, Namespace, StateStore};
+use crate::telemetry::readable;
+use crate::validation::{self, validate_payment};
+use crate::FinternetClient;
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use solana_client::client_error::ClientError;
+use solana_sdk::{
+    commitment_config::CommitmentConfig,
+    hash::Hash,
+    pubkey::Pubkey,
+    signature::{Keypair, Signature},
+    signer::Signer,
+};
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Namespace queued payments are kept under in a `StateStore`
+pub const PAYMENT_QUEUE_NAMESPACE: &str = "payment_queue";
+/// Sends of one payment that may expire without landing before it is failed
+pub const MAX_SEND_ATTEMPTS: u32 = 5;
+
+/// A payment to queue, in the mint's base units
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PaymentJob {
+    pub to: Pubkey,
+    pub amount: u64,
+    pub mint: Pubkey,
+    #[serde(default)]
+    pub memo: Option<String>,
+    /// Higher goes first; 0 by default
+    #[serde(default)]
+    pub priority: i32,
+    /// Unix time before which the payment is not sent
+    #[serde(default)]
+    pub not_before: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QueuedPaymentStatus {
+    /// Waiting for its turn, or for `not_before`
+    Queued,
+    /// Signed and possibly broadcast; its signature is known
+    Submitted,
+    /// Confirmed on-chain
+    Sent,
+    Failed,
+}
+
+/// A payment in the queue
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QueuedPayment {
+    pub id: String,
+    #[serde(flatten)]
+    pub job: PaymentJob,
+    /// Enqueue order, which breaks ties in priority
+    pub sequence: u64,
+    pub status: QueuedPaymentStatus,
+    pub enqueued_at: u64,
+    pub updated_at: u64,
+    /// Times it has been signed and handed to the cluster
+    pub attempts: u32,
+    /// Set before the payment is broadcast
+    pub signature: Option<Signature>,
+    pub recent_blockhash: Option<Hash>,
+    pub error: Option<String>,
+}
+
+impl QueuedPayment {
+    fn set(&mut self, status: QueuedPaymentStatus, error: Option<String>) {
+        self.status = status;
+        self.error = error;
+        self.updated_at = unix_now();
+    }
+
+    /// Whether the worker may pick it at `now`
+    fn is_ready(&self, now: u64) -> bool {
+        self.status == QueuedPaymentStatus::Queued && self.job.not_before.is_none_or(|not_before| not_before <= now)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PaymentQueueConfig {
+    /// Most payments sent and not yet settled at once
+    pub max_in_flight: usize,
+    /// How often the worker looks for scheduled payments that have come due and for
+    /// submitted ones to settle
+    pub poll_interval: Duration,
+}
+
+impl Default for PaymentQueueConfig {
+    fn default() -> Self {
+        Self { max_in_flight: 4, poll_interval: Duration::from_secs(1) }
+    }
+}
+
+/// How deep and how old the queue is, for dashboards and alerts
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QueueMetrics {
+    /// Queued and due now
+    pub ready: usize,
+    /// Queued with a `not_before` still ahead
+    pub scheduled: usize,
+    /// Being sent, or submitted and not yet settled
+    pub in_flight: usize,
+    pub sent: usize,
+    pub failed: usize,
+    /// Seconds the oldest ready payment has waited since it was enqueued or came due
+    pub oldest_ready_age_secs: Option<u64>,
+}
+
+impl QueueMetrics {
+    /// Gauges in the Prometheus text exposition format, `finternet_payment_queue_*`
+    pub fn render_prometheus(&self) -> String {
+        let mut out = String::from(
+            "# HELP finternet_payment_queue_payments Queued payments by state\n\
+             # TYPE finternet_payment_queue_payments gauge\n",
+        );
+        for (state, count) in [
+            ("ready", self.ready),
+            ("scheduled", self.scheduled),
+            ("in_flight", self.in_flight),
+            ("sent", self.sent),
+            ("failed", self.failed),
+        ] {
+            out.push_str(&format!("finternet_payment_queue_payments{{state=\"{}\"}} {}\n", state, count));
+        }
+        out.push_str(
+            "# HELP finternet_payment_queue_oldest_ready_seconds Seconds the oldest ready payment has waited\n\
+             # TYPE finternet_payment_queue_oldest_ready_seconds gauge\n",
+        );
+        out.push_str(&format!(
+            "finternet_payment_queue_oldest_ready_seconds {}\n",
+            self.oldest_ready_age_secs.unwrap_or(0)
+        ));
+        out
+    }
+}
+
+/// What the worker holds in memory between polls
+#[derive(Default)]
+struct QueueState {
+    /// Next enqueue sequence number
+    sequence: u64,
+    /// Payments picked and not yet back from the send, by id, with their recipient
+    in_flight: HashMap<String, Pubkey>,
+}
+
+/// Persistent queue of outgoing payments; see the module documentation
+pub struct PaymentQueue {
+    payments: Namespace<QueuedPayment>,
+    config: PaymentQueueConfig,
+    /// Held across each read-then-write so the worker and enqueuers see each other
+    state: Mutex<QueueState>,
+    wake: tokio::sync::Notify,
+}
+
+impl PaymentQueue {
+    /// Keep the queue in its own file at `path`
+    pub fn open(path: &Path, config: PaymentQueueConfig) -> Result<Self> {
+        let store = FileStateStore::open_registry(path, &legacy_map(PAYMENT_QUEUE_NAMESPACE))?;
+        Self::new(Arc::new(store), config)
+    }
+
+    pub fn new(store: Arc<dyn StateStore>, config: PaymentQueueConfig) -> Result<Self> {
+        let payments: Namespace<QueuedPayment> = Namespace::new(store, PAYMENT_QUEUE_NAMESPACE);
+        let sequence = payments.list()?.iter().map(|(_, payment)| payment.sequence + 1).max().unwrap_or(0);
+        Ok(Self {
+            payments,
+            config,
+            state: Mutex::new(QueueState { sequence, in_flight: HashMap::new() }),
+            wake: tokio::sync::Notify::new(),
+        })
+    }
+
+    pub fn config(&self) -> PaymentQueueConfig {
+        self.config
+    }
+
+    fn lock(&self) -> Result<std::sync::MutexGuard<'_, QueueState>> {
+        self.state.lock().map_err(|_| anyhow!("Payment queue lock poisoned"))
+    }
+
+    /// Add `job` to the queue and wake the worker; the job is validated first
+    pub fn enqueue(&self, job: PaymentJob) -> Result<QueuedPayment> {
+        validation::check(validate_payment(&job.to, job.amount, &job.mint, job.memo.as_deref()))?;
+        let mut state = self.lock()?;
+        let now = unix_now();
+        let payment = QueuedPayment {
+            id: uuid::Uuid::new_v4().to_string(),
+            job,
+            sequence: state.sequence,
+            status: QueuedPaymentStatus::Queued,
+            enqueued_at: now,
+            updated_at: now,
+            attempts: 0,
+            signature: None,
+            recent_blockhash: None,
+            error: None,
+        };
+        self.payments.put(&payment.id, &payment)?;
+        state.sequence += 1;
+        drop(state);
+        self.wake.notify_one();
+        tracing::info!("Payment {} to {} queued at priority {}", payment.id, readable(&payment.job.to), payment.job.priority);
+        Ok(payment)
+    }
+
+    pub fn get(&self, id: &str) -> Result<Option<QueuedPayment>> {
+        self.payments.get(id)
+    }
+
+    /// Every payment in the queue, in the order the worker would pick them
+    pub fn list(&self) -> Result<Vec<QueuedPayment>> {
+        let mut payments: Vec<QueuedPayment> = self.payments.list()?.into_iter().map(|(_, payment)| payment).collect();
+        payments.sort_by_key(|payment| (std::cmp::Reverse(payment.job.priority), payment.sequence));
+        Ok(payments)
+    }
+
+    pub fn metrics(&self) -> Result<QueueMetrics> {
+        let picked: HashSet<String> = self.lock()?.in_flight.keys().cloned().collect();
+        let now = unix_now();
+        let mut metrics = QueueMetrics::default();
+        for payment in self.list()? {
+            match payment.status {
+                // Picked but not yet signed, so still stored as queued
+                QueuedPaymentStatus::Queued if picked.contains(&payment.id) => metrics.in_flight += 1,
+                QueuedPaymentStatus::Queued if payment.is_ready(now) => {
+                    metrics.ready += 1;
+                    let since = payment.job.not_before.map_or(payment.enqueued_at, |due| due.max(payment.enqueued_at));
+                    let age = now.saturating_sub(since);
+                    metrics.oldest_ready_age_secs = Some(metrics.oldest_ready_age_secs.map_or(age, |oldest| oldest.max(age)));
+                }
+                QueuedPaymentStatus::Queued => metrics.scheduled += 1,
+                QueuedPaymentStatus::Submitted => metrics.in_flight += 1,
+                QueuedPaymentStatus::Sent => metrics.sent += 1,
+                QueuedPaymentStatus::Failed => metrics.failed += 1,
+            }
+        }
+        Ok(metrics)
+    }
+
+    /// Pick the payments to send now and mark them in flight
+    ///
+    /// A recipient with a payment in flight or submitted is skipped, so its next payment waits
+    /// until the earlier one has settled.
+    fn claim_ready(&self) -> Result<Vec<QueuedPayment>> {
+        let mut state = self.lock()?;
+        let payments = self.list()?;
+        let submitted: Vec<&QueuedPayment> =
+            payments.iter().filter(|payment| payment.status == QueuedPaymentStatus::Submitted).collect();
+        let mut busy: HashSet<Pubkey> = state.in_flight.values().copied().collect();
+        busy.extend(submitted.iter().map(|payment| payment.job.to));
+        let unsettled = state.in_flight.len()
+            + submitted.iter().filter(|payment| !state.in_flight.contains_key(&payment.id)).count();
+        let free = self.config.max_in_flight.saturating_sub(unsettled);
+        let now = unix_now();
+
+        let mut claimed = Vec::new();
+        for payment in payments.iter() {
+            if claimed.len() >= free {
+                break;
+            }
+            if !payment.is_ready(now) || state.in_flight.contains_key(&payment.id) || !busy.insert(payment.job.to) {
+                continue;
+            }
+            state.in_flight.insert(payment.id.clone(), payment.job.to);
+            claimed.push(payment.clone());
+        }
+        Ok(claimed)
+    }
+
+    /// The worker is done with `id`, whatever became of it
+    fn release(&self, id: &str) {
+        if let Ok(mut state) = self.state.lock() {
+            state.in_flight.remove(id);
+        }
+        self.wake.notify_one();
+    }
+
+    /// Apply `change` to the stored payment `id`
+    fn update(&self, id: &str, change: impl FnOnce(&mut QueuedPayment)) -> Result<QueuedPayment> {
+        let _state = self.lock()?;
+        let mut payment = self.payments.get(id)?.ok_or_else(|| anyhow!("No queued payment {}", id))?;
+        change(&mut payment);
+        self.payments.put(id, &payment)?;
+        Ok(payment)
+    }
+
+    /// Settle submitted payments the worker is not sending right now against the cluster
+    fn reconcile(&self, client: &FinternetClient) -> Result<()> {
+        let in_flight: HashSet<String> = self.lock()?.in_flight.keys().cloned().collect();
+        let submitted: Vec<QueuedPayment> = self
+            .list()?
+            .into_iter()
+            .filter(|payment| payment.status == QueuedPaymentStatus::Submitted && !in_flight.contains(&payment.id))
+            .collect();
+        if submitted.is_empty() {
+            return Ok(());
+        }
+        let signatures: Vec<Signature> = submitted.iter().map(|payment| payment.signature.unwrap_or_default()).collect();
+        let mut statuses = Vec::with_capacity(signatures.len());
+        // getSignatureStatuses accepts at most 256 signatures per call
+        for chunk in signatures.chunks(256) {
+            statuses.extend(client.client.get_signature_statuses_with_history(chunk)?.value);
+        }
+
+        for (payment, status) in submitted.into_iter().zip(statuses) {
+            match status {
+                Some(status) => {
+                    let outcome = status.err.map(|err| err.to_string());
+                    self.update(&payment.id, |payment| match outcome {
+                        None => payment.set(QueuedPaymentStatus::Sent, None),
+                        Some(err) => payment.set(QueuedPaymentStatus::Failed, Some(err)),
+                    })?;
+                }
+                None => {
+                    let still_valid = match payment.recent_blockhash {
+                        Some(blockhash) => client.client.is_blockhash_valid(&blockhash, CommitmentConfig::processed())?,
+                        None => false,
+                    };
+                    if still_valid {
+                        continue;
+                    }
+                    let payment = self.update(&payment.id, |payment| {
+                        payment.signature = None;
+                        payment.recent_blockhash = None;
+                        if payment.attempts >= MAX_SEND_ATTEMPTS {
+                            let error = format!("Expired without landing {} times", payment.attempts);
+                            payment.set(QueuedPaymentStatus::Failed, Some(error));
+                        } else {
+                            payment.set(QueuedPaymentStatus::Queued, Some("Expired without landing".to_string()));
+                        }
+                    })?;
+                    tracing::warn!("Queued payment {} expired without landing; now {:?}", payment.id, payment.status);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Drain the queue with `client` and `wallet` until the task is dropped or the client
+    /// shuts down
+    ///
+    /// Submitted payments left by an earlier run are settled first, then on every poll. Each
+    /// send runs as one of the client's tasks, so shutting down waits for sends under way and
+    /// starts no new ones; unsent payments stay queued for the next run.
+    pub async fn run(self: Arc<Self>, client: Arc<FinternetClient>, wallet: Arc<Keypair>) {
+        // One send context per mint, prepared on reaching its first payment
+        let mut contexts: HashMap<Pubkey, SendContext> = HashMap::new();
+        let shutdown = client.tasks().signal();
+        loop {
+            if shutdown.is_shutdown() {
+                return;
+            }
+            if let Err(e) = self.reconcile(&client) {
+                tracing::warn!("Payment queue reconciliation failed: {}", e);
+            }
+            let claimed = self.claim_ready().unwrap_or_else(|e| {
+                tracing::warn!("Payment queue could not pick payments: {}", e);
+                Vec::new()
+            });
+            for payment in claimed {
+                let context = match contexts.get(&payment.job.mint) {
+                    Some(context) => context.clone(),
+                    None => match client.prepare_send_context(&payment.job.mint) {
+                        Ok(context) => contexts.entry(payment.job.mint).or_insert(context).clone(),
+                        Err(e) => {
+                            let failed = self.update(&payment.id, |payment| {
+                                payment.set(QueuedPaymentStatus::Failed, Some(e.to_string()))
+                            });
+                            if let Err(e) = failed {
+                                tracing::warn!("Could not fail queued payment {}: {}", payment.id, e);
+                            }
+                            self.release(&payment.id);
+                            continue;
+                        }
+                    },
+                };
+                let id = payment.id.clone();
+                let (queue, task_client, wallet) = (Arc::clone(&self), Arc::clone(&client), Arc::clone(&wallet));
+                let started = client.tasks().spawn("queued_payment", async move {
+                    if let Err(e) = task_client.send_queued_payment(&wallet, &queue, &payment, &context).await {
+                        tracing::warn!("Queued payment {} could not be recorded: {}", payment.id, e);
+                    }
+                    queue.release(&payment.id);
+                });
+                if let Err(e) = started {
+                    tracing::info!("Leaving queued payment {} for the next run: {}", id, e);
+                    self.release(&id);
+                }
+            }
+            tokio::select! {
+                _ = self.wake.notified() => {}
+                _ = tokio::time::sleep(self.config.poll_interval) => {}
+                _ = shutdown.cancelled() => return,
+            }
+        }
+    }
+}
+
+/// Names a queued payment's signature in the queue before anything is broadcast
+///
+/// Runs at `PersistIntent` on every attempt the send makes, counting each one.
+struct RecordQueuedSignature<'a> {
+    queue: &'a PaymentQueue,
+    id: &'a str,
+    recorded: AtomicBool,
+}
+
+#[async_trait]
+impl SendMiddleware for RecordQueuedSignature<'_> {
+    fn name(&self) -> &str {
+        "record_queued_signature"
+    }
+
+    async fn before_send(&self, _client: &FinternetClient, request: &mut SendRequest) -> Result<()> {
+        self.queue.update(self.id, |payment| {
+            payment.signature = request.signature;
+            payment.recent_blockhash = request.recent_blockhash;
+            payment.attempts += 1;
+            payment.set(QueuedPaymentStatus::Submitted, None);
+        })?;
+        self.recorded.store(true, Ordering::SeqCst);
+        Ok(())
+    }
+}
+
+impl FinternetClient {
+    /// Send one queued payment through the send pipeline and store what became of it
+    ///
+    /// Fails only when the queue cannot be written; the payment's own failure is stored on it.
+    async fn send_queued_payment(
+        &self,
+        wallet: &Keypair,
+        queue: &PaymentQueue,
+        payment: &QueuedPayment,
+        context: &SendContext,
+    ) -> Result<()> {
+        let PaymentJob { to, amount, mint, memo, .. } = payment.job.clone();
+        let parameters = serde_json::json!({
+            "queue_id": payment.id,
+            "from": wallet.pubkey().to_string(),
+            "to": to.to_string(),
+            "amount": amount,
+            "mint": mint.to_string(),
+            "memo": memo,
+        });
+        let request = SendRequest::payment("queued_payment", wallet.pubkey(), to, amount, mint, memo.as_deref(), parameters)
+            .with_body(SendBody::Context(context.clone()));
+        let recording = RecordQueuedSignature { queue, id: &payment.id, recorded: AtomicBool::new(false) };
+        let outcome = self
+            .send_through_pipeline_with(request, &[wallet], &[(SendStage::PersistIntent, &recording)])
+            .await
+            .map(|receipt| receipt.signature);
+
+        match outcome {
+            Ok(_) if self.config.confirmation.get(OperationKind::Payment).wait => {
+                queue.update(&payment.id, |payment| payment.set(QueuedPaymentStatus::Sent, None))?;
+            }
+            // Sent without waiting, so it is settled by the worker's reconciliation
+            Ok(_) => {}
+            // Refused, simulated, or failed before the queue named a signature: nothing went out
+            Err(e) if !recording.recorded.load(Ordering::SeqCst) => {
+                tracing::warn!("Queued payment {} was not sent: {}", payment.id, e);
+                queue.update(&payment.id, |payment| payment.set(QueuedPaymentStatus::Failed, Some(e.to_string())))?;
+            }
+            Err(e) => {
+                // Only on-chain failures are final; anything else is settled by reconciliation
+                // once the blockhash has expired
+                let final_failure = e.downcast_ref::<ClientError>().and_then(|c| c.get_transaction_error()).is_some();
+                tracing::warn!("Queued payment {} did not confirm: {}", payment.id, e);
+                queue.update(&payment.id, |payment| {
+                    let status = if final_failure { QueuedPaymentStatus::Failed } else { QueuedPaymentStatus::Submitted };
+                    payment.set(status, Some(e.to_string()));
+                })?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::confirmation::ConfirmationPolicy;
+    use crate::mock_cluster::{Landing, MockCluster, TempDir};
+    use crate::FinternetConfig;
+    use solana_sdk::transaction::Transaction;
+    use spl_token::instruction::TokenInstruction;
+    use std::time::Instant;
+
+    fn funded(cluster: &MockCluster) -> (Keypair, Pubkey) {
+        let payer = Keypair::new();
+        let mint = cluster.add_mint(&spl_token::id(), 6, &Pubkey::new_unique());
+        cluster.set_balance(payer.pubkey(), 1_000_000_000);
+        cluster.add_token_account(&payer.pubkey(), &mint, 10_000_000);
+        (payer, mint)
+    }
+
+    fn job(to: Pubkey, amount: u64, mint: Pubkey) -> PaymentJob {
+        PaymentJob { to, amount, mint, memo: None, priority: 0, not_before: None }
+    }
+
+    fn config(max_in_flight: usize) -> PaymentQueueConfig {
+        PaymentQueueConfig { max_in_flight, poll_interval: Duration::from_millis(20) }
+    }
+
+    async fn wait_until(what: &str, mut done: impl FnMut() -> bool) {
+        let started = Instant::now();
+        while !done() {
+            assert!(started.elapsed() < Duration::from_secs(10), "timed out waiting for {}", what);
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    }
+
+    fn status(queue: &PaymentQueue, id: &str) -> QueuedPaymentStatus {
+        queue.get(id).unwrap().unwrap().status
+    }
+
+    /// The recipient wallet and amount of the token transfer in `transaction`
+    fn transfer(transaction: &Transaction, mint: &Pubkey, recipients: &[Pubkey]) -> (Pubkey, u64) {
+        let message = &transaction.message;
+        message
+            .instructions
+            .iter()
+            .filter(|instruction| message.account_keys[usize::from(instruction.program_id_index)] == spl_token::id())
+            .find_map(|instruction| {
+                let (amount, destination) = match TokenInstruction::unpack(&instruction.data).ok()? {
+                    TokenInstruction::Transfer { amount } => (amount, instruction.accounts[1]),
+                    TokenInstruction::TransferChecked { amount, .. } => (amount, instruction.accounts[2]),
+                    _ => return None,
+                };
+                let destination = message.account_keys[usize::from(destination)];
+                let to = recipients
+                    .iter()
+                    .find(|to| spl_associated_token_account::get_associated_token_address(to, mint) == destination)?;
+                Some((*to, amount))
+            })
+            .expect("a token transfer to a known recipient")
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn restart_settles_what_was_submitted_without_sending_it_again() {
+        let dir = TempDir::new("payment-queue");
+        let path = dir.join("queue.json");
+        let cluster = MockCluster::new();
+        let (payer, mint) = funded(&cluster);
+        let client = Arc::new(cluster.default_client());
+        let (landed_to, queued_to, expired_to) = (Keypair::new().pubkey(), Keypair::new().pubkey(), Keypair::new().pubkey());
+
+        // The first run sent one payment and crashed before recording that it landed, signed
+        // another whose blockhash then expired, and never reached the third
+        let queue = PaymentQueue::open(&path, config(4)).unwrap();
+        let landed = queue.enqueue(job(landed_to, 1_000, mint)).unwrap();
+        let expired = queue.enqueue(job(expired_to, 3_000, mint)).unwrap();
+        let queued = queue.enqueue(job(queued_to, 2_000, mint)).unwrap();
+        let signature = client.send_payment(&payer, &landed_to, 1_000, &mint, None).await.unwrap();
+        let stale_blockhash = cluster.blockhash();
+        queue
+            .update(&landed.id, |payment| {
+                payment.signature = Some(signature);
+                payment.recent_blockhash = Some(stale_blockhash);
+                payment.attempts = 1;
+                payment.set(QueuedPaymentStatus::Submitted, None);
+            })
+            .unwrap();
+        queue
+            .update(&expired.id, |payment| {
+                payment.signature = Some(Signature::new_unique());
+                payment.recent_blockhash = Some(stale_blockhash);
+                payment.attempts = 1;
+                payment.set(QueuedPaymentStatus::Submitted, None);
+            })
+            .unwrap();
+        drop(queue);
+        cluster.expire_blockhashes();
+
+        let queue = Arc::new(PaymentQueue::open(&path, config(4)).unwrap());
+        tokio::spawn(Arc::clone(&queue).run(Arc::clone(&client), Arc::new(payer)));
+        wait_until("every payment to settle", || {
+            [&landed.id, &queued.id, &expired.id].iter().all(|id| status(&queue, id) == QueuedPaymentStatus::Sent)
+        })
+        .await;
+        client.shutdown_with_timeout(Duration::from_secs(5)).await;
+
+        let sent = cluster.sent();
+        assert_eq!(sent.len(), 3, "the landed payment must not be sent again");
+        let recipients = [landed_to, queued_to, expired_to];
+        let paid: Vec<Pubkey> = sent.iter().map(|transaction| transfer(transaction, &mint, &recipients).0).collect();
+        assert_eq!(paid.iter().filter(|to| **to == landed_to).count(), 1);
+        let landed = queue.get(&landed.id).unwrap().unwrap();
+        assert_eq!((landed.signature, landed.attempts), (Some(signature), 1));
+        let expired = queue.get(&expired.id).unwrap().unwrap();
+        // Its stale attempt, then at least one more once it was queued again
+        assert!(expired.attempts >= 2, "{}", expired.attempts);
+        let resent = sent.iter().find(|t| transfer(t, &mint, &recipients).0 == expired_to).unwrap();
+        assert_eq!(expired.signature, resent.signatures.first().copied());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn each_recipient_is_paid_in_the_order_its_payments_were_picked() {
+        let cluster = MockCluster::new();
+        let (payer, mint) = funded(&cluster);
+        let client = Arc::new(cluster.default_client());
+        let dir = TempDir::new("payment-queue");
+        let queue = Arc::new(PaymentQueue::open(&dir.join("queue.json"), config(4)).unwrap());
+        let (first, second) = (Keypair::new().pubkey(), Keypair::new().pubkey());
+
+        let mut ids = Vec::new();
+        for amount in [1, 2, 3] {
+            ids.push(queue.enqueue(job(first, amount, mint)).unwrap().id);
+        }
+        let urgent = PaymentJob { priority: 10, ..job(second, 9, mint) };
+        ids.push(queue.enqueue(urgent).unwrap().id);
+        tokio::spawn(Arc::clone(&queue).run(Arc::clone(&client), Arc::new(payer)));
+        wait_until("every payment to be sent", || ids.iter().all(|id| status(&queue, id) == QueuedPaymentStatus::Sent)).await;
+        client.shutdown_with_timeout(Duration::from_secs(5)).await;
+
+        let paid: Vec<(Pubkey, u64)> = cluster.sent().iter().map(|t| transfer(t, &mint, &[first, second])).collect();
+        let to_first: Vec<u64> = paid.iter().filter(|(to, _)| *to == first).map(|(_, amount)| *amount).collect();
+        assert_eq!(to_first, [1, 2, 3]);
+        assert_eq!(paid.len(), 4);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn no_more_than_max_in_flight_are_unsettled_at_once() {
+        let cluster = MockCluster::new();
+        let (payer, mint) = funded(&cluster);
+        cluster.set_landing(Landing::Pending);
+        let config_without_waiting = FinternetConfig::default()
+            .with_confirmation_policy(OperationKind::Payment, ConfirmationPolicy::fire_and_forget());
+        let client = Arc::new(cluster.client(config_without_waiting));
+        let dir = TempDir::new("payment-queue");
+        let queue = Arc::new(PaymentQueue::open(&dir.join("queue.json"), config(2)).unwrap());
+        let ids: Vec<String> =
+            (0..5).map(|i| queue.enqueue(job(Keypair::new().pubkey(), 100 + i, mint)).unwrap().id).collect();
+        tokio::spawn(Arc::clone(&queue).run(Arc::clone(&client), Arc::new(payer)));
+
+        for (expected, unsettled) in [(2, 2), (4, 2), (5, 1)] {
+            wait_until("the next payments to go out", || cluster.sent().len() == expected).await;
+            // Several polls pass with nothing settled; nothing more may go out meanwhile
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            assert_eq!(cluster.sent().len(), expected);
+            assert_eq!(queue.metrics().unwrap().in_flight, unsettled);
+            for transaction in cluster.sent() {
+                cluster.land(&transaction.signatures[0], None);
+            }
+        }
+        wait_until("every payment to settle", || ids.iter().all(|id| status(&queue, id) == QueuedPaymentStatus::Sent)).await;
+        client.shutdown_with_timeout(Duration::from_secs(5)).await;
+    }
+}
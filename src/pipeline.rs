@@ -0,0 +1,836 @@
+//! The ordered pipeline every payment passes through on its way to the chain
+//!
+//! Every payment the client signs and sends is described as a `SendRequest` and handed to the
+//! client's `SendMiddleware`, ordered by `SendStage`. Each middleware's `before_send` runs at the
+//! point of the send its stage names, and may change the request, attach metadata for the
+//! `SendReceipt`, or refuse the payment with an error, which is returned as-is and stops the
+//! rest. `Validate`, `Policy`, and `Screen` run before anything is built. The transaction is then
+//! built from the request, `EnrichFees` runs, and the transaction is priced by the fee strategy
+//! unless a middleware set `compute_unit_price`, and signed. `PersistIntent` runs before the
+//! outbox journals it, `Broadcast` before it is handed to the node, and `Confirm` before the wait
+//! for its confirmation. A transaction that expires unlanded is re-signed and goes through those
+//! three stages again. Last, every middleware's `after_send` sees the outcome, in stage order,
+//! whether the payment went out or was refused. How long each step took comes back on the
+//! receipt as `timings`; see `latency`. A request whose `options` carry a deadline is refused
+//! between steps once the deadline is too close to make; see `deadline`.
+//!
+//! The client registers the built-in middleware: `ValidatePayment` at `Validate`,
+//! `PaymentPolicies` (asset policies, organization roles, the spending policy, and anomaly
+//! detection) at `Policy`, and `RecordAction` (the action log and `ActionCompleted`) at
+//! `Record`. `with_send_middleware` adds others after those already at the same stage.
+//! `send_payment`, `send_split_payment`, `SendContext`, batch, queued, time-locked, and
+//! claimable payments, and listing swaps, all send through the pipeline. Settling a time lock
+//! or a claim moves funds already in escrow rather than spending the signer's, and is sent
+//! directly.
+
+use crate::confirmation::OperationKind;
+use crate::fees::{FeeStrategy, PriorityFee};
+use crate::latency::{timed, Phase, PhaseClock, PhaseTimings};
+use crate::payment::split_payment_legs;
+use crate::policy::{SpendRequest, SpendReservation};
+use crate::rpc_options::RpcCallOptions;
+use crate::send_context::SendContext;
+use crate::validation::{self, validate_payment};
+use crate::FinternetClient;
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use solana_sdk::{
+    compute_budget::ComputeBudgetInstruction,
+    hash::Hash,
+    instruction::Instruction,
+    pubkey::Pubkey,
+    signature::{Keypair, Signature},
+    transaction::Transaction,
+};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::ops::RangeInclusive;
+use std::sync::Arc;
+use std::time::Instant;
+
+/// Where in the pipeline a middleware runs; middleware runs in this order
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SendStage {
+    Validate,
+    Policy,
+    /// Checks on who is paid, e.g. sanctions screening; no built-in middleware
+    Screen,
+    /// Once the transaction is built, before the fee strategy prices it; set
+    /// `compute_unit_price` to override it
+    EnrichFees,
+    /// Once the transaction is signed, before the outbox journals it; `signature` is set
+    PersistIntent,
+    /// Before the transaction is handed to the node
+    Broadcast,
+    /// Once the node has the transaction, before waiting for it to confirm
+    Confirm,
+    /// After everything else has seen the outcome, e.g. for audit trails; there is nothing
+    /// left to send, so only `after_send` runs here
+    Record,
+}
+
+/// One payment as it moves through the pipeline
+#[derive(Debug)]
+pub struct SendRequest {
+    /// SDK method that started the payment, e.g. `send_payment`; also the transport label
+    pub operation: String,
+    pub kind: OperationKind,
+    /// The paying wallet
+    pub signer: Pubkey,
+    pub mint: Pubkey,
+    /// Who the caller asked to pay, and how much, before any split
+    pub to: Pubkey,
+    pub amount: u64,
+    /// Every transfer the transaction makes, as the spending policy sees them
+    pub transfers: Vec<SpendRequest>,
+    pub memo: Option<String>,
+    /// Read-only keys carried on the transfer, for `find_payment_by_reference`
+    pub references: Vec<Pubkey>,
+    pub options: RpcCallOptions,
+    /// Micro-lamports per compute unit to pay instead of what the fee strategy would choose
+    pub compute_unit_price: Option<u64>,
+    /// Attached by middleware and returned on the `SendReceipt`
+    pub metadata: BTreeMap<String, serde_json::Value>,
+    /// The signed transaction's signature and blockhash, from `PersistIntent` on; a re-signed
+    /// transaction replaces them
+    pub signature: Option<Signature>,
+    pub recent_blockhash: Option<Hash>,
+    /// What the transaction is built from
+    pub(crate) body: SendBody,
+    /// Basis-point splits of `amount`; `ValidatePayment` turns them into `transfers`
+    pub(crate) splits: Option<Vec<(Pubkey, u16)>>,
+    /// What the action log records as the call's parameters
+    pub(crate) parameters: serde_json::Value,
+    pub(crate) reservation: Option<SpendReservation>,
+    /// Whether the transaction was handed to the transport, so its outcome says what moved
+    pub(crate) broadcast: bool,
+    /// Whether `RecordAction` logs the call; off for calls that log what they return themselves
+    pub(crate) recorded: bool,
+}
+
+/// How a `SendRequest` becomes instructions
+#[derive(Debug, Clone)]
+pub(crate) enum SendBody {
+    /// A transfer to `to`, or one per split, reading each recipient's accounts
+    Payment,
+    /// A transfer to `to` using what the context knows of the recipient
+    Context(SendContext),
+    /// Instructions the caller built, moving what `transfers` says
+    Instructions(Vec<Instruction>),
+}
+
+impl SendRequest {
+    pub(crate) fn payment(
+        operation: &str,
+        signer: Pubkey,
+        to: Pubkey,
+        amount: u64,
+        mint: Pubkey,
+        memo: Option<&str>,
+        parameters: serde_json::Value,
+    ) -> Self {
+        Self {
+            operation: operation.to_string(),
+            kind: OperationKind::Payment,
+            signer,
+            mint,
+            to,
+            amount,
+            transfers: vec![SpendRequest { to, mint, amount }],
+            memo: memo.map(str::to_string),
+            references: Vec::new(),
+            options: RpcCallOptions::default(),
+            compute_unit_price: None,
+            metadata: BTreeMap::new(),
+            signature: None,
+            recent_blockhash: None,
+            body: SendBody::Payment,
+            splits: None,
+            parameters,
+            reservation: None,
+            broadcast: false,
+            recorded: true,
+        }
+    }
+
+    pub(crate) fn with_body(mut self, body: SendBody) -> Self {
+        self.body = body;
+        self
+    }
+
+    /// Leave the action log to the caller
+    pub(crate) fn unrecorded(mut self) -> Self {
+        self.recorded = false;
+        self
+    }
+
+    pub(crate) fn with_references(mut self, references: &[Pubkey]) -> Self {
+        self.references = references.to_vec();
+        self
+    }
+
+    pub(crate) fn with_options(mut self, options: &RpcCallOptions) -> Self {
+        self.options = options.clone();
+        self
+    }
+
+    pub(crate) fn with_splits(mut self, splits: &[(Pubkey, u16)]) -> Self {
+        self.splits = Some(splits.to_vec());
+        self
+    }
+}
+
+/// What a payment that went out returns, with whatever middleware attached to it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SendReceipt {
+    pub operation: String,
+    pub signature: Signature,
+    pub metadata: BTreeMap<String, serde_json::Value>,
+    /// Where the send's time went, from the first middleware to confirmation
+    #[serde(default)]
+    pub timings: PhaseTimings,
+}
+
+/// A payment refused by a middleware that has no error type of its own
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SendRefused {
+    pub middleware: String,
+    /// Stable machine-readable reason, e.g. `sanctioned_recipient`
+    pub reason: String,
+    pub message: String,
+}
+
+impl SendRefused {
+    pub fn new(middleware: &str, reason: &str, message: impl Into<String>) -> Self {
+        Self {
+            middleware: middleware.to_string(),
+            reason: reason.to_string(),
+            message: message.into(),
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        "send_refused"
+    }
+}
+
+impl fmt::Display for SendRefused {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} refused the payment ({}): {}", self.middleware, self.reason, self.message)
+    }
+}
+
+impl std::error::Error for SendRefused {}
+
+/// A step of the send pipeline; see the module documentation
+#[async_trait]
+pub trait SendMiddleware: Send + Sync {
+    fn name(&self) -> &str;
+
+    /// Runs at the middleware's stage; an error refuses the payment
+    ///
+    /// An error at `Confirm` stops the wait, not the transaction, which may still land and is
+    /// left `Submitted` in the outbox for `recover_outbox`.
+    async fn before_send(&self, _client: &FinternetClient, _request: &mut SendRequest) -> Result<()> {
+        Ok(())
+    }
+
+    /// Runs once the payment was sent or refused, on every middleware
+    async fn after_send(&self, _client: &FinternetClient, _request: &mut SendRequest, _outcome: &Result<Signature>) {}
+}
+
+/// Checks the payment is well-formed and its mint belongs to the cluster
+pub struct ValidatePayment;
+
+#[async_trait]
+impl SendMiddleware for ValidatePayment {
+    fn name(&self) -> &str {
+        "validate_payment"
+    }
+
+    async fn before_send(&self, client: &FinternetClient, request: &mut SendRequest) -> Result<()> {
+        validation::check(validate_payment(&request.to, request.amount, &request.mint, request.memo.as_deref()))?;
+        client.check_mint_network(&request.mint)?;
+        if let Some(splits) = &request.splits {
+            let legs = split_payment_legs((request.to, request.amount), splits)?;
+            request.transfers =
+                legs.into_iter().map(|(to, amount)| SpendRequest { to, mint: request.mint, amount }).collect();
+        }
+        Ok(())
+    }
+}
+
+/// Asset policies, organization roles, the spending policy, and anomaly detection
+///
+/// Spends are reserved here and given back once the outcome shows the transaction moved
+/// nothing, as `settle_spend` decides.
+pub struct PaymentPolicies;
+
+#[async_trait]
+impl SendMiddleware for PaymentPolicies {
+    fn name(&self) -> &str {
+        "payment_policies"
+    }
+
+    async fn before_send(&self, client: &FinternetClient, request: &mut SendRequest) -> Result<()> {
+        for transfer in &request.transfers {
+            client.check_asset_policy(&transfer.to, &transfer.mint).await?;
+        }
+        client.check_signer_role(&request.signer, &request.transfers).await?;
+        request.reservation = Some(client.reserve_spend(request.transfers.clone())?);
+        Ok(())
+    }
+
+    async fn after_send(&self, client: &FinternetClient, request: &mut SendRequest, outcome: &Result<Signature>) {
+        let Some(reservation) = request.reservation.take() else {
+            return;
+        };
+        if request.broadcast {
+            client.settle_spend(reservation, outcome);
+        } else if outcome.is_err() {
+            client.release_spend(reservation);
+        }
+    }
+}
+
+/// Records the call in the action log and raises `ActionCompleted`
+pub struct RecordAction;
+
+#[async_trait]
+impl SendMiddleware for RecordAction {
+    fn name(&self) -> &str {
+        "record_action"
+    }
+
+    async fn after_send(&self, client: &FinternetClient, request: &mut SendRequest, outcome: &Result<Signature>) {
+        if !request.recorded {
+            return;
+        }
+        client.log_action(
+            &request.operation,
+            request.parameters.clone(),
+            Some(&request.mint),
+            outcome.as_ref(),
+            Signature::to_string,
+        );
+    }
+}
+
+/// The client's middleware, in the order it runs
+#[derive(Clone)]
+pub(crate) struct SendPipeline {
+    middleware: Vec<(SendStage, Arc<dyn SendMiddleware>)>,
+}
+
+impl Default for SendPipeline {
+    fn default() -> Self {
+        let mut pipeline = Self { middleware: Vec::new() };
+        pipeline.insert(SendStage::Validate, Arc::new(ValidatePayment));
+        pipeline.insert(SendStage::Policy, Arc::new(PaymentPolicies));
+        pipeline.insert(SendStage::Record, Arc::new(RecordAction));
+        pipeline
+    }
+}
+
+impl SendPipeline {
+    /// Add `middleware` after everything already at `stage` or before it
+    fn insert(&mut self, stage: SendStage, middleware: Arc<dyn SendMiddleware>) {
+        let index = self.middleware.partition_point(|(existing, _)| *existing <= stage);
+        self.middleware.insert(index, (stage, middleware));
+    }
+}
+
+impl FinternetClient {
+    /// Run `middleware` at `stage` of every payment this client sends; see `pipeline`
+    pub fn with_send_middleware(mut self, stage: SendStage, middleware: impl SendMiddleware + 'static) -> Self {
+        self.send_pipeline.insert(stage, Arc::new(middleware));
+        self
+    }
+
+    /// Name and stage of each middleware, in the order it runs
+    pub fn send_middleware(&self) -> Vec<(SendStage, String)> {
+        self.send_pipeline
+            .middleware
+            .iter()
+            .map(|(stage, middleware)| (*stage, middleware.name().to_string()))
+            .collect()
+    }
+
+    /// Pass `request` through every middleware and, unless one refuses it, send it signed by
+    /// `signers`, the payer first
+    pub(crate) async fn send_through_pipeline(&self, request: SendRequest, signers: &[&Keypair]) -> Result<SendReceipt> {
+        self.send_through_pipeline_with(request, signers, &[]).await
+    }
+
+    /// `send_through_pipeline` with middleware for this send only, run after the client's own
+    /// at each stage
+    pub(crate) async fn send_through_pipeline_with(
+        &self,
+        mut request: SendRequest,
+        signers: &[&Keypair],
+        local: &[(SendStage, &dyn SendMiddleware)],
+    ) -> Result<SendReceipt> {
+        let mut middleware: Vec<(SendStage, &dyn SendMiddleware)> =
+            self.send_pipeline.middleware.iter().map(|(stage, middleware)| (*stage, middleware.as_ref())).collect();
+        for (stage, extra) in local {
+            let index = middleware.partition_point(|(existing, _)| existing <= stage);
+            middleware.insert(index, (*stage, *extra));
+        }
+
+        let clock = PhaseClock::default();
+        request.options.phase_clock = Some(clock.clone());
+        let outcome = self.run_send_stages(&mut request, signers, &middleware, &clock).await;
+        if let SendBody::Context(context) = &request.body {
+            context.settle(&request.to, &outcome);
+        }
+        for (_, middleware) in &middleware {
+            middleware.after_send(self, &mut request, &outcome).await;
+        }
+        // Refused and failed sends are timed too; they are often the slow ones
+        let timings = clock.timings();
+        self.record_timings(&request.operation, &timings);
+        Ok(SendReceipt {
+            signature: outcome?,
+            operation: request.operation,
+            metadata: request.metadata,
+            timings,
+        })
+    }
+
+    /// Run the `before_send` of every middleware at `stages`
+    async fn run_stages(
+        &self,
+        middleware: &[(SendStage, &dyn SendMiddleware)],
+        stages: RangeInclusive<SendStage>,
+        request: &mut SendRequest,
+        clock: &PhaseClock,
+    ) -> Result<()> {
+        let started = Instant::now();
+        for (stage, middleware) in middleware.iter().filter(|(stage, _)| stages.contains(stage)) {
+            if let Err(e) = middleware.before_send(self, request).await {
+                tracing::info!("{} stopped {} at {:?}: {}", middleware.name(), request.operation, stage, e);
+                clock.add(Phase::Checks, started.elapsed());
+                return Err(e);
+            }
+        }
+        clock.add(Phase::Checks, started.elapsed());
+        Ok(())
+    }
+
+    async fn run_send_stages(
+        &self,
+        request: &mut SendRequest,
+        signers: &[&Keypair],
+        middleware: &[(SendStage, &dyn SendMiddleware)],
+        clock: &PhaseClock,
+    ) -> Result<Signature> {
+        self.run_stages(middleware, SendStage::Validate..=SendStage::Screen, request, clock).await?;
+        self.check_deadline(&request.operation, &request.options)?;
+
+        let instructions = timed(Some(clock), Phase::Build, || self.send_instructions(request))?;
+        self.run_stages(middleware, SendStage::EnrichFees..=SendStage::EnrichFees, request, clock).await?;
+        let (mut transaction, fee) = timed(Some(clock), Phase::Fees, || self.priced_transaction(request, instructions))?;
+        timed(Some(clock), Phase::Blockhash, || self.sign_with_fresh_blockhash(&mut transaction, signers))?;
+        if let Some(fee) = fee {
+            self.report_priority_fee(&request.operation, &transaction, fee);
+        }
+
+        let mut resigns = 0;
+        loop {
+            request.signature = transaction.signatures.first().copied();
+            request.recent_blockhash = Some(transaction.message.recent_blockhash);
+            let (outcome, broadcast) = self.send_attempt(request, &transaction, middleware, clock).await;
+            let error = match outcome {
+                Ok(signature) => return Ok(signature),
+                Err(e) => e,
+            };
+            // A middleware refusing the attempt refuses the payment; only sends can expire
+            if !broadcast
+                || !self.resign_if_expired(&request.operation, &mut transaction, signers, &error, &mut resigns, &request.options)?
+            {
+                return Err(error);
+            }
+        }
+    }
+
+    /// One attempt at sending the signed `transaction`, and whether it reached the node
+    async fn send_attempt(
+        &self,
+        request: &mut SendRequest,
+        transaction: &Transaction,
+        middleware: &[(SendStage, &dyn SendMiddleware)],
+        clock: &PhaseClock,
+    ) -> (Result<Signature>, bool) {
+        let mut send = match self.begin_tracked_send(request.kind, &request.operation, transaction, &request.options) {
+            Ok(send) => send,
+            Err(e) => return (Err(e), false),
+        };
+        let mut broadcast = false;
+        let outcome = async {
+            self.run_stages(middleware, SendStage::PersistIntent..=SendStage::PersistIntent, request, clock).await?;
+            send.journal()?;
+            self.run_stages(middleware, SendStage::Broadcast..=SendStage::Broadcast, request, clock).await?;
+            request.broadcast = true;
+            broadcast = true;
+            let signature = send.broadcast()?;
+            self.run_stages(middleware, SendStage::Confirm..=SendStage::Confirm, request, clock).await?;
+            send.confirm(&signature)?;
+            Ok(signature)
+        }
+        .await;
+        (send.finish(outcome), broadcast)
+    }
+
+    /// The instructions `request` describes, before any priority fee
+    fn send_instructions(&self, request: &SendRequest) -> Result<Vec<Instruction>> {
+        match &request.body {
+            SendBody::Payment if request.splits.is_some() => {
+                let legs: Vec<(Pubkey, u64)> = request.transfers.iter().map(|transfer| (transfer.to, transfer.amount)).collect();
+                tracing::info!(
+                    "Sending split payment of {} tokens across {} recipients (mint: {})",
+                    request.amount,
+                    legs.len(),
+                    request.mint
+                );
+                self.split_payment_instructions(&request.signer, &legs, &request.mint, request.memo.as_deref())
+            }
+            SendBody::Payment => self.payment_transaction_instructions(
+                &request.signer,
+                &request.to,
+                request.amount,
+                &request.mint,
+                request.memo.as_deref(),
+                &request.references,
+            ),
+            SendBody::Context(context) => {
+                self.context_payment_instructions(context, &request.signer, &request.to, request.amount, request.memo.as_deref())
+            }
+            SendBody::Instructions(instructions) => Ok(instructions.clone()),
+        }
+    }
+
+    /// `instructions` as an unsigned transaction paid by the signer, at the compute unit price
+    /// a middleware set or the fee strategy picks
+    fn priced_transaction(
+        &self,
+        request: &SendRequest,
+        mut instructions: Vec<Instruction>,
+    ) -> Result<(Transaction, Option<PriorityFee>)> {
+        if let Some(price) = request.compute_unit_price {
+            instructions.insert(0, ComputeBudgetInstruction::set_compute_unit_price(price));
+            let fee = PriorityFee { strategy: FeeStrategy::Fixed(price), compute_unit_price: price, clamped_from: None };
+            return Ok((Transaction::new_with_payer(&instructions, Some(&request.signer)), Some(fee)));
+        }
+        let mut transaction = Transaction::new_with_payer(&instructions, Some(&request.signer));
+        let sampled = match &request.body {
+            SendBody::Context(context) => Some(context.fee_accounts(&request.signer)),
+            _ => None,
+        };
+        let fee = self.price_transaction(request.kind, &request.operation, &mut transaction, sampled.as_ref().map(|accounts| accounts.as_slice()))?;
+        Ok((transaction, fee))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_cluster::{Landing, MockCluster, TempDir};
+    use crate::outbox::OutboxStatus;
+    use crate::FinternetConfig;
+    use solana_sdk::signer::Signer;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::sync::Mutex;
+
+    /// What had happened to a send when a middleware saw it
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    struct Seen {
+        stage: SendStage,
+        signature: Option<Signature>,
+        fees_sampled: bool,
+        journaled: usize,
+        sent: usize,
+        statuses_polled: bool,
+    }
+
+    /// Notes what it sees at its stage, and after the send
+    struct Probe {
+        stage: SendStage,
+        cluster: MockCluster,
+        seen: Arc<Mutex<Vec<Seen>>>,
+    }
+
+    impl Probe {
+        fn note(&self, client: &FinternetClient, request: &SendRequest, stage: SendStage) {
+            let journaled = client.outbox.as_ref().map_or(0, |outbox| outbox.entries().unwrap().len());
+            self.seen.lock().unwrap().push(Seen {
+                stage,
+                signature: request.signature,
+                fees_sampled: self.cluster.call_count("getRecentPrioritizationFees") > 0,
+                journaled,
+                sent: self.cluster.sent().len(),
+                statuses_polled: self.cluster.call_count("getSignatureStatuses") > 0,
+            });
+        }
+    }
+
+    #[async_trait]
+    impl SendMiddleware for Probe {
+        fn name(&self) -> &str {
+            "probe"
+        }
+
+        async fn before_send(&self, client: &FinternetClient, request: &mut SendRequest) -> Result<()> {
+            self.note(client, request, self.stage);
+            Ok(())
+        }
+    }
+
+    struct Refuse;
+
+    #[async_trait]
+    impl SendMiddleware for Refuse {
+        fn name(&self) -> &str {
+            "refuse"
+        }
+
+        async fn before_send(&self, _client: &FinternetClient, _request: &mut SendRequest) -> Result<()> {
+            Err(SendRefused::new("refuse", "test", "refused for the test").into())
+        }
+    }
+
+    struct FixPrice(u64);
+
+    #[async_trait]
+    impl SendMiddleware for FixPrice {
+        fn name(&self) -> &str {
+            "fix_price"
+        }
+
+        async fn before_send(&self, _client: &FinternetClient, request: &mut SendRequest) -> Result<()> {
+            request.compute_unit_price = Some(self.0);
+            Ok(())
+        }
+    }
+
+    /// Expires every blockhash the first time it runs, so the attempt underway cannot land
+    struct ExpireOnce {
+        cluster: MockCluster,
+        done: AtomicBool,
+    }
+
+    #[async_trait]
+    impl SendMiddleware for ExpireOnce {
+        fn name(&self) -> &str {
+            "expire_once"
+        }
+
+        async fn before_send(&self, _client: &FinternetClient, _request: &mut SendRequest) -> Result<()> {
+            if !self.done.swap(true, Ordering::SeqCst) {
+                self.cluster.expire_blockhashes();
+            }
+            Ok(())
+        }
+    }
+
+    const STAGES: [SendStage; 7] = [
+        SendStage::Validate,
+        SendStage::Policy,
+        SendStage::Screen,
+        SendStage::EnrichFees,
+        SendStage::PersistIntent,
+        SendStage::Broadcast,
+        SendStage::Confirm,
+    ];
+
+    /// A cluster with a payer holding 10 tokens of a 6-decimal mint, and a client of it that
+    /// journals in an outbox and samples priority fees
+    fn setup(dir: &TempDir) -> (MockCluster, FinternetClient, Keypair, Pubkey) {
+        let cluster = MockCluster::new();
+        let payer = Keypair::new();
+        let mint = cluster.add_mint(&spl_token::id(), 6, &Pubkey::new_unique());
+        cluster.set_balance(payer.pubkey(), 1_000_000_000);
+        cluster.add_token_account(&payer.pubkey(), &mint, 10_000_000);
+        let config = FinternetConfig {
+            outbox_path: Some(dir.join("outbox.json")),
+            ..FinternetConfig::default()
+        }
+        .with_fee_strategy(FeeStrategy::Dynamic { percentile: 50, max: 10_000 });
+        let client = cluster.client(config);
+        (cluster, client, payer, mint)
+    }
+
+    fn probed(mut client: FinternetClient, cluster: &MockCluster) -> (FinternetClient, Arc<Mutex<Vec<Seen>>>) {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        for stage in STAGES {
+            client = client.with_send_middleware(stage, Probe { stage, cluster: cluster.clone(), seen: Arc::clone(&seen) });
+        }
+        (client, seen)
+    }
+
+    fn seen_at(seen: &[Seen], stage: SendStage) -> Vec<Seen> {
+        seen.iter().filter(|seen| seen.stage == stage).cloned().collect()
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn each_stage_runs_at_its_point_in_the_send() {
+        let dir = TempDir::new("pipeline");
+        let (cluster, client, payer, mint) = setup(&dir);
+        let (client, seen) = probed(client, &cluster);
+
+        let signature = client.send_payment(&payer, &Keypair::new().pubkey(), 1_000_000, &mint, None).await.unwrap();
+
+        let seen = seen.lock().unwrap().clone();
+        assert_eq!(seen.iter().map(|seen| seen.stage).collect::<Vec<_>>(), STAGES);
+        for checks in &seen[..3] {
+            assert_eq!(
+                (checks.signature, checks.fees_sampled, checks.journaled, checks.sent),
+                (None, false, 0, 0),
+                "{:?}",
+                checks.stage
+            );
+        }
+        let enrich = &seen[3];
+        assert_eq!((enrich.signature, enrich.fees_sampled), (None, false), "EnrichFees runs before pricing and signing");
+        let persist = &seen[4];
+        assert_eq!(persist.signature, Some(signature));
+        assert!(persist.fees_sampled);
+        assert_eq!((persist.journaled, persist.sent), (0, 0), "PersistIntent runs before the outbox journals");
+        let broadcast = &seen[5];
+        assert_eq!((broadcast.journaled, broadcast.sent), (1, 0), "Broadcast runs after journaling, before the send");
+        let confirm = &seen[6];
+        assert_eq!((confirm.sent, confirm.statuses_polled), (1, false), "Confirm runs after the send, before the wait");
+        assert_eq!(client.outbox.as_ref().unwrap().entries().unwrap()[0].status, OutboxStatus::Confirmed);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn enrich_fees_price_replaces_the_fee_strategy() {
+        let dir = TempDir::new("pipeline");
+        let (cluster, client, payer, mint) = setup(&dir);
+        let client = client.with_send_middleware(SendStage::EnrichFees, FixPrice(7));
+
+        client.send_payment(&payer, &Keypair::new().pubkey(), 1_000_000, &mint, None).await.unwrap();
+
+        assert_eq!(cluster.call_count("getRecentPrioritizationFees"), 0);
+        let sent = cluster.sent();
+        let message = &sent[0].message;
+        let first = &message.instructions[0];
+        assert_eq!(message.account_keys[usize::from(first.program_id_index)], solana_sdk::compute_budget::id());
+        assert_eq!(first.data, ComputeBudgetInstruction::set_compute_unit_price(7).data);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn refusal_at_broadcast_is_journaled_but_never_sent() {
+        let dir = TempDir::new("pipeline");
+        let (cluster, client, payer, mint) = setup(&dir);
+        let client = client.with_send_middleware(SendStage::Broadcast, Refuse);
+
+        let error = client.send_payment(&payer, &Keypair::new().pubkey(), 1_000_000, &mint, None).await.unwrap_err();
+
+        assert!(error.is::<SendRefused>(), "{}", error);
+        assert!(cluster.sent().is_empty());
+        let entries = client.outbox.as_ref().unwrap().entries().unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].status, OutboxStatus::Refused);
+        // Recovery must not send what the middleware refused
+        client.recover_outbox().await.unwrap();
+        assert!(cluster.sent().is_empty());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn refusal_before_build_reads_nothing() {
+        let dir = TempDir::new("pipeline");
+        let (cluster, client, payer, mint) = setup(&dir);
+        let client = client.with_send_middleware(SendStage::Screen, Refuse);
+        let calls = cluster.calls().len();
+
+        client.send_payment(&payer, &Keypair::new().pubkey(), 1_000_000, &mint, None).await.unwrap_err();
+
+        // Only the mint's cluster check may have read anything
+        let read: Vec<String> = cluster.calls()[calls..].iter().filter(|call| *call != "getGenesisHash").cloned().collect();
+        assert!(read.is_empty(), "{:?}", read);
+        assert!(client.outbox.as_ref().unwrap().entries().unwrap().is_empty());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn a_re_signed_attempt_goes_through_the_send_stages_again() {
+        let dir = TempDir::new("pipeline");
+        let (cluster, client, payer, mint) = setup(&dir);
+        let (client, seen) = probed(client, &cluster);
+        let client = client.with_send_middleware(
+            SendStage::Confirm,
+            ExpireOnce { cluster: cluster.clone(), done: AtomicBool::new(false) },
+        );
+        let attempts = Arc::new(AtomicUsize::new(0));
+        let counted = Arc::clone(&attempts);
+        cluster.land_with(move |_| match counted.fetch_add(1, Ordering::SeqCst) {
+            0 => Landing::Pending,
+            _ => Landing::Confirmed,
+        });
+
+        let signature = client.send_payment(&payer, &Keypair::new().pubkey(), 1_000_000, &mint, None).await.unwrap();
+
+        let seen = seen.lock().unwrap().clone();
+        for stage in &STAGES[..4] {
+            assert_eq!(seen_at(&seen, *stage).len(), 1, "{:?}", stage);
+        }
+        let persisted = seen_at(&seen, SendStage::PersistIntent);
+        assert_eq!(persisted.len(), 2);
+        assert_eq!(seen_at(&seen, SendStage::Broadcast).len(), 2);
+        assert_eq!(seen_at(&seen, SendStage::Confirm).len(), 2);
+        assert_ne!(persisted[0].signature, persisted[1].signature);
+        assert_eq!(persisted[1].signature, Some(signature));
+
+        let entries = client.outbox.as_ref().unwrap().entries().unwrap();
+        let status = |signature: Option<Signature>| entries.iter().find(|e| Some(e.signature) == signature).unwrap().status;
+        assert_eq!(status(persisted[0].signature), OutboxStatus::Expired);
+        assert_eq!(status(persisted[1].signature), OutboxStatus::Confirmed);
+    }
+
+    /// Notes the operation of every payment it sees
+    struct Operations(Arc<Mutex<Vec<String>>>);
+
+    #[async_trait]
+    impl SendMiddleware for Operations {
+        fn name(&self) -> &str {
+            "operations"
+        }
+
+        async fn before_send(&self, _client: &FinternetClient, request: &mut SendRequest) -> Result<()> {
+            self.0.lock().unwrap().push(request.operation.clone());
+            Ok(())
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn every_kind_of_payment_passes_the_pipeline() {
+        let dir = TempDir::new("pipeline");
+        let (cluster, client, payer, mint) = setup(&dir);
+        let operations = Arc::new(Mutex::new(Vec::new()));
+        let client = client.with_send_middleware(SendStage::Screen, Operations(Arc::clone(&operations)));
+        let recipient = Keypair::new().pubkey();
+
+        let context = client.prepare_send_context(&mint).unwrap();
+        client.send_payment_with_context(&context, &payer, &recipient, 1_000, None).await.unwrap();
+        let mut journal = crate::batch::BatchJournal::new(vec![(
+            "first".to_string(),
+            crate::batch::BatchAction::Payment { to: recipient, amount: 1_000, mint, memo: None },
+        )])
+        .unwrap();
+        client.run_batch(&payer, &mut journal, None).await.unwrap();
+        let now = 1_700_000_000;
+        cluster.set_time(now as i64);
+        client.send_timelocked_payment(&payer, &recipient, 1_000, &mint, now + 60, now + 3_600).await.unwrap();
+        let secret = crate::claim::generate_claim_secret();
+        client.send_claimable_payment(&payer, 1_000, &mint, &secret, now + 3_600).await.unwrap();
+
+        assert_eq!(
+            *operations.lock().unwrap(),
+            ["send_payment_with_context", "batch_payment", "send_timelocked_payment", "send_claimable_payment"]
+        );
+        assert_eq!(cluster.sent().len(), 4);
+        assert_eq!(journal.items[0].status, crate::batch::BatchItemStatus::Confirmed);
+        assert_eq!(journal.items[0].signature, cluster.sent()[1].signatures.first().copied());
+    }
+}
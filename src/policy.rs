@@ -0,0 +1,645 @@
+use crate::anomaly::Anomaly;
+use crate::deadline::DeadlineError;
+use crate::dry_run::DryRunOutcome;
+use crate::organization::{OrgRole, RoleRequirement};
+use crate::FinternetClient;
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use solana_client::client_error::ClientError;
+use solana_sdk::pubkey::Pubkey;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Length of the rolling spend window
+const WINDOW_SECS: u64 = 24 * 60 * 60;
+
+/// One outgoing transfer as the policy sees it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SpendRequest {
+    pub to: Pubkey,
+    pub mint: Pubkey,
+    /// Base units of `mint`
+    pub amount: u64,
+}
+
+/// Asked to approve a transaction whose amount of some mint reaches its approval threshold;
+/// returning `false` refuses it
+pub type ApprovalCallback = Arc<dyn Fn(&[SpendRequest]) -> bool + Send + Sync>;
+
+/// Guardrails checked before any payment transaction is built
+///
+/// Limits are per mint, in base units, and apply to the sum of every transfer of that mint in
+/// one transaction. Mints without a configured limit are unrestricted.
+#[derive(Clone, Default)]
+pub struct SpendingPolicy {
+    pub max_per_transaction: HashMap<Pubkey, u64>,
+    /// Cap on the total sent in any rolling 24 hours
+    pub max_per_day: HashMap<Pubkey, u64>,
+    /// When set, only these recipients may be paid
+    pub allowed_recipients: Option<HashSet<Pubkey>>,
+    pub denied_recipients: HashSet<Pubkey>,
+    /// Amounts at or above these need the approval callback to say yes
+    pub approval_thresholds: HashMap<Pubkey, u64>,
+    approval: Option<ApprovalCallback>,
+    /// Amounts at or above a requirement's threshold need a signer with its organization role
+    pub role_requirements: HashMap<Pubkey, RoleRequirement>,
+}
+
+impl fmt::Debug for SpendingPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SpendingPolicy")
+            .field("max_per_transaction", &self.max_per_transaction)
+            .field("max_per_day", &self.max_per_day)
+            .field("allowed_recipients", &self.allowed_recipients)
+            .field("denied_recipients", &self.denied_recipients)
+            .field("approval_thresholds", &self.approval_thresholds)
+            .field("approval", &self.approval.is_some())
+            .field("role_requirements", &self.role_requirements)
+            .finish()
+    }
+}
+
+impl SpendingPolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_max_per_transaction(mut self, mint: Pubkey, amount: u64) -> Self {
+        self.max_per_transaction.insert(mint, amount);
+        self
+    }
+
+    pub fn with_max_per_day(mut self, mint: Pubkey, amount: u64) -> Self {
+        self.max_per_day.insert(mint, amount);
+        self
+    }
+
+    /// Restrict payments to an allowlist; call once per allowed recipient
+    pub fn allow_recipient(mut self, recipient: Pubkey) -> Self {
+        self.allowed_recipients.get_or_insert_with(HashSet::new).insert(recipient);
+        self
+    }
+
+    pub fn deny_recipient(mut self, recipient: Pubkey) -> Self {
+        self.denied_recipients.insert(recipient);
+        self
+    }
+
+    /// Require `approve` to accept any transaction sending at least `threshold` of `mint`
+    pub fn with_approval(
+        mut self,
+        mint: Pubkey,
+        threshold: u64,
+        approve: impl Fn(&[SpendRequest]) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.approval_thresholds.insert(mint, threshold);
+        self.approval = Some(Arc::new(approve));
+        self
+    }
+
+    /// Require the signing wallet to hold at least `role` in `org` to send `threshold` or more
+    /// of `mint` in one transaction
+    pub fn require_org_role(mut self, mint: Pubkey, org: Pubkey, role: OrgRole, threshold: u64) -> Self {
+        self.role_requirements.insert(mint, RoleRequirement { org, role, threshold });
+        self
+    }
+
+    /// Every rule that does not depend on past spending, in order: denylist, allowlist,
+    /// per-transaction limit, then approval
+    pub fn check(&self, requests: &[SpendRequest]) -> std::result::Result<(), PolicyViolation> {
+        for request in requests {
+            if self.denied_recipients.contains(&request.to) {
+                return Err(PolicyViolation::RecipientDenied { recipient: request.to });
+            }
+            if let Some(allowed) = &self.allowed_recipients {
+                if !allowed.contains(&request.to) {
+                    return Err(PolicyViolation::RecipientNotAllowed { recipient: request.to });
+                }
+            }
+        }
+
+        let totals = totals_by_mint(requests);
+        for (mint, requested) in &totals {
+            if let Some(limit) = self.max_per_transaction.get(mint) {
+                if requested > limit {
+                    return Err(PolicyViolation::PerTransactionLimit {
+                        mint: *mint,
+                        limit: *limit,
+                        requested: *requested,
+                    });
+                }
+            }
+        }
+        for (mint, requested) in &totals {
+            if let Some(threshold) = self.approval_thresholds.get(mint) {
+                if requested >= threshold {
+                    let approved = self.approval.as_ref().map(|approve| approve(requests)).unwrap_or(false);
+                    if !approved {
+                        return Err(PolicyViolation::ApprovalDenied {
+                            mint: *mint,
+                            threshold: *threshold,
+                            requested: *requested,
+                        });
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The specific spending rule a payment broke
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PolicyViolation {
+    RecipientDenied { recipient: Pubkey },
+    RecipientNotAllowed { recipient: Pubkey },
+    PerTransactionLimit { mint: Pubkey, limit: u64, requested: u64 },
+    /// Sending `requested` would take the last 24 hours' total of `mint` past `limit`
+    RollingLimit { mint: Pubkey, limit: u64, spent: u64, requested: u64 },
+    /// The amount needs approval and the callback refused it (or none is configured)
+    ApprovalDenied { mint: Pubkey, threshold: u64, requested: u64 },
+    /// The amount needs a signer holding `role` in `org`, and `signer` does not
+    RoleRequired { org: Pubkey, role: OrgRole, signer: Pubkey, mint: Pubkey, threshold: u64, requested: u64 },
+    /// Anomaly detection in enforce mode refused the payment
+    Anomaly(Anomaly),
+}
+
+impl PolicyViolation {
+    /// Stable machine-readable identifier for API responses
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::RecipientDenied { .. } => "recipient_denied",
+            Self::RecipientNotAllowed { .. } => "recipient_not_allowed",
+            Self::PerTransactionLimit { .. } => "per_transaction_limit",
+            Self::RollingLimit { .. } => "rolling_limit",
+            Self::ApprovalDenied { .. } => "approval_denied",
+            Self::RoleRequired { .. } => "role_required",
+            Self::Anomaly(_) => "anomaly_detected",
+        }
+    }
+}
+
+impl fmt::Display for PolicyViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::RecipientDenied { recipient } => write!(f, "Recipient {} is on the denylist", recipient),
+            Self::RecipientNotAllowed { recipient } => write!(f, "Recipient {} is not on the allowlist", recipient),
+            Self::PerTransactionLimit { mint, limit, requested } => write!(
+                f,
+                "{} of mint {} exceeds the per-transaction limit of {}",
+                requested, mint, limit
+            ),
+            Self::RollingLimit { mint, limit, spent, requested } => write!(
+                f,
+                "{} of mint {} would exceed the 24h limit of {} ({} already spent)",
+                requested, mint, limit, spent
+            ),
+            Self::ApprovalDenied { mint, threshold, requested } => write!(
+                f,
+                "{} of mint {} is at or above the approval threshold of {} and was not approved",
+                requested, mint, threshold
+            ),
+            Self::RoleRequired { org, role, signer, mint, threshold, requested } => write!(
+                f,
+                "{} of mint {} is at or above {} and needs a signer with at least the {} role in organization {}; {} does not have it",
+                requested, mint, threshold, role, org, signer
+            ),
+            Self::Anomaly(anomaly) => write!(f, "Refused as anomalous ({}): {}", anomaly.code(), anomaly),
+        }
+    }
+}
+
+impl std::error::Error for PolicyViolation {}
+
+fn totals_by_mint(requests: &[SpendRequest]) -> BTreeMap<Pubkey, u64> {
+    let mut totals = BTreeMap::new();
+    for request in requests {
+        let total: &mut u64 = totals.entry(request.mint).or_default();
+        *total = total.saturating_add(request.amount);
+    }
+    totals
+}
+
+/// One reserved spend inside the rolling window
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct SpendRecord {
+    mint: Pubkey,
+    amount: u64,
+    at: u64,
+}
+
+/// A policy plus the spends counted against its rolling limits
+///
+/// Spends are reserved before a transaction is built and released only when it certainly never
+/// moved funds, so an ambiguous failure still counts against the limit.
+pub(crate) struct SpendingGuard {
+    /// Swapped whole by `replace_spending_policy`; a send already checked keeps the old one
+    policy: RwLock<Arc<SpendingPolicy>>,
+    /// JSON file of recent spends; in memory only when `None`
+    path: Option<PathBuf>,
+    window: Mutex<Vec<SpendRecord>>,
+}
+
+impl SpendingGuard {
+    pub(crate) fn open(policy: SpendingPolicy, path: Option<&Path>) -> Result<Self> {
+        let window = match path {
+            Some(path) if path.exists() => {
+                let data = fs::read_to_string(path)?;
+                if data.trim().is_empty() {
+                    Vec::new()
+                } else {
+                    serde_json::from_str(&data)
+                        .map_err(|e| anyhow!("Corrupt spending window {}: {}", path.display(), e))?
+                }
+            }
+            _ => Vec::new(),
+        };
+        Ok(Self {
+            policy: RwLock::new(Arc::new(policy)),
+            path: path.map(Path::to_path_buf),
+            window: Mutex::new(window),
+        })
+    }
+
+    fn policy(&self) -> Result<Arc<SpendingPolicy>> {
+        Ok(Arc::clone(&*self.policy.read().map_err(|_| anyhow!("Spending policy lock poisoned"))?))
+    }
+
+    /// Each role requirement `requests` reach, with its mint and the total requested
+    pub(crate) fn role_requirements_for(&self, requests: &[SpendRequest]) -> Result<Vec<(Pubkey, RoleRequirement, u64)>> {
+        let policy = self.policy()?;
+        Ok(totals_by_mint(requests)
+            .into_iter()
+            .filter_map(|(mint, requested)| {
+                let requirement = policy.role_requirements.get(&mint)?;
+                (requested >= requirement.threshold).then_some((mint, *requirement, requested))
+            })
+            .collect())
+    }
+
+    /// Swap in `policy`, keeping the spends already counted in the rolling window
+    pub(crate) fn replace(&self, policy: SpendingPolicy) -> Result<()> {
+        *self.policy.write().map_err(|_| anyhow!("Spending policy lock poisoned"))? = Arc::new(policy);
+        Ok(())
+    }
+
+    /// Check every rule and count the spend against the rolling window
+    pub(crate) fn reserve(&self, requests: &[SpendRequest], now: u64) -> Result<()> {
+        // Approval may block on a human, so it runs before the window is locked
+        let policy = self.policy()?;
+        policy.check(requests)?;
+
+        let mut window = self.window.lock().map_err(|_| anyhow!("Spending window lock poisoned"))?;
+        window.retain(|record| record.at + WINDOW_SECS > now);
+
+        let totals = totals_by_mint(requests);
+        for (mint, requested) in &totals {
+            if let Some(limit) = policy.max_per_day.get(mint) {
+                let spent = window
+                    .iter()
+                    .filter(|record| record.mint == *mint)
+                    .fold(0u64, |sum, record| sum.saturating_add(record.amount));
+                if spent.saturating_add(*requested) > *limit {
+                    return Err(PolicyViolation::RollingLimit {
+                        mint: *mint,
+                        limit: *limit,
+                        spent,
+                        requested: *requested,
+                    }
+                    .into());
+                }
+            }
+        }
+
+        window.extend(totals.into_iter().map(|(mint, amount)| SpendRecord { mint, amount, at: now }));
+        self.persist(&window)
+    }
+
+    /// Undo a `reserve` made at `now` for a transaction that was never sent or failed on-chain
+    pub(crate) fn release(&self, requests: &[SpendRequest], now: u64) -> Result<()> {
+        let mut window = self.window.lock().map_err(|_| anyhow!("Spending window lock poisoned"))?;
+        for (mint, amount) in totals_by_mint(requests) {
+            if let Some(index) = window
+                .iter()
+                .position(|record| record.mint == mint && record.amount == amount && record.at == now)
+            {
+                window.remove(index);
+            }
+        }
+        self.persist(&window)
+    }
+
+    fn persist(&self, window: &[SpendRecord]) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if let Some(parent) = path.parent() {
+            if !parent.as_os_str().is_empty() {
+                fs::create_dir_all(parent)?;
+            }
+        }
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, serde_json::to_string_pretty(window)?)?;
+        fs::rename(&tmp, path)?;
+        Ok(())
+    }
+}
+
+/// Proof that a spend was reserved, handed back to `release_spend` if the transaction fails
+#[derive(Debug)]
+pub(crate) struct SpendReservation {
+    requests: Vec<SpendRequest>,
+    at: u64,
+}
+
+impl FinternetClient {
+    /// Enforce `policy` on every payment this client sends
+    ///
+    /// The rolling 24h window is kept in `spending-window.json` next to the outbox file, so
+    /// restarts do not reset it. Without an `outbox_path` it only lives in memory.
+    pub fn with_spending_policy(mut self, policy: SpendingPolicy) -> Result<Self> {
+        let path = self
+            .config
+            .outbox_path
+            .as_ref()
+            .map(|outbox| outbox.with_file_name("spending-window.json"));
+        if path.is_none() {
+            tracing::warn!("No outbox_path configured; the spending window will reset on restart");
+        }
+        self.spending = Some(SpendingGuard::open(policy, path.as_deref())?);
+        Ok(self)
+    }
+
+    /// Enforce `policy` from the next payment on, e.g. after a config reload
+    ///
+    /// The spends already counted against the rolling window still count, so lowering a daily
+    /// limit takes effect at once. Fails unless `with_spending_policy` attached one first.
+    pub fn replace_spending_policy(&self, policy: SpendingPolicy) -> Result<()> {
+        let guard = self
+            .spending
+            .as_ref()
+            .ok_or_else(|| anyhow!("No spending policy attached; use with_spending_policy first"))?;
+        guard.replace(policy)
+    }
+
+    /// Check the spending policy and anomaly rules and reserve the amounts; a no-op without
+    /// either
+    pub(crate) fn reserve_spend(&self, requests: Vec<SpendRequest>) -> Result<SpendReservation> {
+        let at = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        if let Some(guard) = &self.spending {
+            guard.reserve(&requests, at)?;
+        }
+        if let Err(e) = self.check_anomalies(&requests, at) {
+            if let Some(guard) = &self.spending {
+                if let Err(e) = guard.release(&requests, at) {
+                    tracing::warn!("Failed to release spending reservation: {}", e);
+                }
+            }
+            return Err(e);
+        }
+        Ok(SpendReservation { requests, at })
+    }
+
+    /// Release the reservation if `outcome` failed on-chain, was only simulated, or was refused
+    /// at its deadline before broadcast, so the transaction moved nothing; timeouts and other
+    /// ambiguous failures keep counting against the limit
+    pub(crate) fn settle_spend<T>(&self, reservation: SpendReservation, outcome: &Result<T>) {
+        if let Err(e) = outcome {
+            let moved_nothing = e.is::<DryRunOutcome>()
+                || matches!(e.downcast_ref::<DeadlineError>(), Some(DeadlineError::TooLateToSend { .. }))
+                || e.downcast_ref::<ClientError>().and_then(|c| c.get_transaction_error()).is_some();
+            if moved_nothing {
+                self.release_spend(reservation);
+            }
+        }
+    }
+
+    /// Give back a reservation whose transaction certainly moved no funds
+    pub(crate) fn release_spend(&self, reservation: SpendReservation) {
+        if let Some(guard) = &self.spending {
+            if let Err(e) = guard.release(&reservation.requests, reservation.at) {
+                tracing::warn!("Failed to release spending reservation: {}", e);
+            }
+        }
+        self.forget_anomaly_sends(&reservation.requests, reservation.at);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_cluster::{Landing, MockCluster, TempDir};
+    use crate::FinternetConfig;
+    use solana_sdk::instruction::InstructionError;
+    use solana_sdk::signature::Keypair;
+    use solana_sdk::signer::Signer;
+    use solana_sdk::transaction::TransactionError;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    const NOW: u64 = 1_700_000_000;
+
+    fn spend(to: Pubkey, mint: Pubkey, amount: u64) -> SpendRequest {
+        SpendRequest { to, mint, amount }
+    }
+
+    fn violation(result: Result<()>) -> PolicyViolation {
+        result.unwrap_err().downcast::<PolicyViolation>().unwrap()
+    }
+
+    #[test]
+    fn the_denylist_wins_over_the_allowlist_and_unlisted_recipients_are_refused() {
+        let (friend, stranger, mint) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let policy = SpendingPolicy::new().allow_recipient(friend).deny_recipient(friend);
+        assert_eq!(policy.check(&[spend(friend, mint, 1)]), Err(PolicyViolation::RecipientDenied { recipient: friend }));
+
+        let policy = SpendingPolicy::new().allow_recipient(friend);
+        assert_eq!(policy.check(&[spend(friend, mint, 1)]), Ok(()));
+        // One unlisted leg refuses the whole transaction
+        assert_eq!(
+            policy.check(&[spend(friend, mint, 1), spend(stranger, mint, 1)]),
+            Err(PolicyViolation::RecipientNotAllowed { recipient: stranger })
+        );
+        assert_eq!(SpendingPolicy::new().check(&[spend(stranger, mint, u64::MAX)]), Ok(()));
+    }
+
+    #[test]
+    fn the_per_transaction_limit_counts_every_leg_of_its_mint() {
+        let (usdc, other) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let to = Pubkey::new_unique();
+        let policy = SpendingPolicy::new().with_max_per_transaction(usdc, 100);
+
+        assert_eq!(policy.check(&[spend(to, usdc, 100)]), Ok(()));
+        assert_eq!(
+            policy.check(&[spend(to, usdc, 60), spend(Pubkey::new_unique(), usdc, 41)]),
+            Err(PolicyViolation::PerTransactionLimit { mint: usdc, limit: 100, requested: 101 })
+        );
+        // Other mints are unrestricted, and totals saturate rather than wrap
+        assert_eq!(policy.check(&[spend(to, usdc, 100), spend(to, other, 1_000)]), Ok(()));
+        assert_eq!(
+            policy.check(&[spend(to, usdc, u64::MAX), spend(to, usdc, 2)]),
+            Err(PolicyViolation::PerTransactionLimit { mint: usdc, limit: 100, requested: u64::MAX })
+        );
+    }
+
+    #[test]
+    fn amounts_from_the_threshold_up_need_the_callback_to_approve() {
+        let (mint, to) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let asked = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&asked);
+        let policy = SpendingPolicy::new().with_approval(mint, 500, move |requests| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            requests.iter().map(|r| r.amount).sum::<u64>() < 1_000
+        });
+
+        assert_eq!(policy.check(&[spend(to, mint, 499)]), Ok(()));
+        assert_eq!(asked.load(Ordering::SeqCst), 0);
+        assert_eq!(policy.check(&[spend(to, mint, 500)]), Ok(()));
+        assert_eq!(
+            policy.check(&[spend(to, mint, 1_000)]),
+            Err(PolicyViolation::ApprovalDenied { mint, threshold: 500, requested: 1_000 })
+        );
+        assert_eq!(asked.load(Ordering::SeqCst), 2);
+
+        // The per-transaction limit is checked first, so nobody is asked to approve a refusal
+        let policy = policy.with_max_per_transaction(mint, 800);
+        assert!(matches!(policy.check(&[spend(to, mint, 900)]), Err(PolicyViolation::PerTransactionLimit { .. })));
+        assert_eq!(asked.load(Ordering::SeqCst), 2);
+
+        // A threshold with no callback to ask refuses
+        let mut unapproved = SpendingPolicy::new();
+        unapproved.approval_thresholds.insert(mint, 10);
+        assert!(matches!(unapproved.check(&[spend(to, mint, 10)]), Err(PolicyViolation::ApprovalDenied { .. })));
+    }
+
+    #[test]
+    fn the_daily_limit_rolls_over_the_last_24_hours() {
+        let (mint, to) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let guard = SpendingGuard::open(SpendingPolicy::new().with_max_per_day(mint, 100), None).unwrap();
+
+        guard.reserve(&[spend(to, mint, 60)], NOW).unwrap();
+        guard.reserve(&[spend(to, mint, 40)], NOW + 3_600).unwrap();
+        assert_eq!(
+            violation(guard.reserve(&[spend(to, mint, 1)], NOW + 7_200)),
+            PolicyViolation::RollingLimit { mint, limit: 100, spent: 100, requested: 1 }
+        );
+        // A refused spend is not counted
+        assert_eq!(
+            violation(guard.reserve(&[spend(to, mint, 1)], NOW + WINDOW_SECS - 1)),
+            PolicyViolation::RollingLimit { mint, limit: 100, spent: 100, requested: 1 }
+        );
+
+        // Exactly 24 hours on, the first spend has left the window and the second has not
+        guard.reserve(&[spend(to, mint, 60)], NOW + WINDOW_SECS).unwrap();
+        assert_eq!(
+            violation(guard.reserve(&[spend(to, mint, 1)], NOW + WINDOW_SECS)),
+            PolicyViolation::RollingLimit { mint, limit: 100, spent: 100, requested: 1 }
+        );
+        guard.reserve(&[spend(to, mint, 40)], NOW + WINDOW_SECS + 3_600).unwrap();
+
+        // Other mints have their own window, or none
+        guard.reserve(&[spend(to, Pubkey::new_unique(), 1_000_000)], NOW + WINDOW_SECS + 3_600).unwrap();
+    }
+
+    #[test]
+    fn releasing_gives_back_only_that_reservation() {
+        let (mint, to) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let guard = SpendingGuard::open(SpendingPolicy::new().with_max_per_day(mint, 100), None).unwrap();
+
+        guard.reserve(&[spend(to, mint, 50)], NOW).unwrap();
+        guard.reserve(&[spend(to, mint, 50)], NOW + 1).unwrap();
+        guard.release(&[spend(to, mint, 50)], NOW + 1).unwrap();
+        // Releasing what was never reserved, or releasing twice, changes nothing
+        guard.release(&[spend(to, mint, 50)], NOW + 1).unwrap();
+        guard.release(&[spend(to, mint, 30)], NOW).unwrap();
+
+        guard.reserve(&[spend(to, mint, 50)], NOW + 2).unwrap();
+        assert_eq!(
+            violation(guard.reserve(&[spend(to, mint, 1)], NOW + 3)),
+            PolicyViolation::RollingLimit { mint, limit: 100, spent: 100, requested: 1 }
+        );
+    }
+
+    #[test]
+    fn the_window_survives_a_restart_and_a_corrupt_file_is_refused() {
+        let dir = TempDir::new("policy");
+        let path = dir.join("spending-window.json");
+        let (mint, to) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let policy = || SpendingPolicy::new().with_max_per_day(mint, 100);
+
+        SpendingGuard::open(policy(), Some(&path)).unwrap().reserve(&[spend(to, mint, 90)], NOW).unwrap();
+        let restarted = SpendingGuard::open(policy(), Some(&path)).unwrap();
+        assert_eq!(
+            violation(restarted.reserve(&[spend(to, mint, 11)], NOW + 60)),
+            PolicyViolation::RollingLimit { mint, limit: 100, spent: 90, requested: 11 }
+        );
+
+        // Replacing the policy keeps what was spent
+        restarted.replace(SpendingPolicy::new().with_max_per_day(mint, 95)).unwrap();
+        assert_eq!(
+            violation(restarted.reserve(&[spend(to, mint, 6)], NOW + 60)),
+            PolicyViolation::RollingLimit { mint, limit: 95, spent: 90, requested: 6 }
+        );
+
+        fs::write(&path, "").unwrap();
+        SpendingGuard::open(policy(), Some(&path)).unwrap().reserve(&[spend(to, mint, 100)], NOW).unwrap();
+        fs::write(&path, "{not json").unwrap();
+        let error = SpendingGuard::open(policy(), Some(&path)).err().unwrap();
+        assert!(error.to_string().contains("Corrupt spending window"), "{}", error);
+    }
+
+    fn funded(cluster: &MockCluster, dir: &TempDir, policy: SpendingPolicy) -> (FinternetClient, Keypair, Pubkey) {
+        let mint = cluster.add_mint(&spl_token::id(), 6, &Pubkey::new_unique());
+        let payer = Keypair::new();
+        cluster.set_balance(payer.pubkey(), 1_000_000_000);
+        cluster.add_token_account(&payer.pubkey(), &mint, 10_000_000);
+        let client = cluster
+            .client(FinternetConfig { outbox_path: Some(dir.join("outbox.json")), ..FinternetConfig::default() })
+            .with_spending_policy(policy.with_max_per_day(mint, 1_000))
+            .unwrap();
+        (client, payer, mint)
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn an_on_chain_failure_is_released_but_an_ambiguous_one_keeps_counting() {
+        let (cluster, dir) = (MockCluster::new(), TempDir::new("policy"));
+        let (client, payer, mint) = funded(&cluster, &dir, SpendingPolicy::new());
+        let to = Keypair::new().pubkey();
+
+        cluster.set_landing(Landing::Failed(TransactionError::InstructionError(1, InstructionError::Custom(1))));
+        client.send_payment(&payer, &to, 1_000, &mint, None).await.unwrap_err();
+        // The failed payment moved nothing, so the whole day's limit is still there
+        cluster.set_landing(Landing::Refused("Node is behind".to_string()));
+        let error = client.send_payment(&payer, &to, 600, &mint, None).await.unwrap_err();
+        assert!(error.downcast_ref::<PolicyViolation>().is_none(), "{}", error);
+
+        // The refused broadcast may yet have gone out, so it still counts
+        cluster.set_landing(Landing::Confirmed);
+        let sent = cluster.sent().len();
+        let error = client.send_payment(&payer, &to, 401, &mint, None).await.unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<PolicyViolation>(),
+            Some(&PolicyViolation::RollingLimit { mint, limit: 1_000, spent: 600, requested: 401 })
+        );
+        assert_eq!(cluster.sent().len(), sent);
+        client.send_payment(&payer, &to, 400, &mint, None).await.unwrap();
+        assert!(dir.join("spending-window.json").exists());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn a_violation_is_refused_before_anything_is_built() {
+        let (cluster, dir) = (MockCluster::new(), TempDir::new("policy"));
+        let denied = Keypair::new().pubkey();
+        let (client, payer, mint) = funded(&cluster, &dir, SpendingPolicy::new().deny_recipient(denied));
+        let calls = cluster.calls().len();
+
+        let error = client.send_payment(&payer, &denied, 1, &mint, None).await.unwrap_err();
+        assert_eq!(error.downcast_ref::<PolicyViolation>(), Some(&PolicyViolation::RecipientDenied { recipient: denied }));
+        assert_eq!(error.downcast_ref::<PolicyViolation>().unwrap().code(), "recipient_denied");
+        assert!(!cluster.calls()[calls..].iter().any(|call| call == "getLatestBlockhash" || call == "sendTransaction"));
+        assert!(cluster.sent().is_empty());
+    }
+}
@@ -0,0 +1,272 @@
+//! One consolidated view of what several wallets hold
+//!
+//! `get_portfolio` reads every wallet's SOL with batched `getMultipleAccounts` calls and its token
+//! accounts on parallel threads, then names each mint from the client's metadata cache. Holdings
+//! of the same mint are summed across wallets. A wallet whose reads fail carries its error instead
+//! of failing the whole snapshot, and the totals cover only the wallets that were read.
+
+use crate::telemetry::readable;
+use crate::{Currency, FinternetClient};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use solana_sdk::pubkey::Pubkey;
+use std::collections::{BTreeMap, HashMap};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// `getMultipleAccounts` accepts at most this many addresses per call
+const ACCOUNTS_PER_CALL: usize = 100;
+/// Wallets whose token accounts are read at once
+const WALLET_CONCURRENCY: usize = 8;
+
+/// One wallet's base-unit balances, or why they could not be read
+type WalletRead = Result<Vec<(Currency, u64)>, String>;
+
+/// USD price of one whole unit of each currency, for `Portfolio::with_usd_prices`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct UsdPrices {
+    pub sol: Option<f64>,
+    /// Keyed by mint address
+    #[serde(default, with = "crate::pubkey_map")]
+    pub tokens: HashMap<Pubkey, f64>,
+}
+
+impl UsdPrices {
+    fn price_of(&self, currency: &Currency) -> Option<f64> {
+        match currency.mint() {
+            None => self.sol,
+            Some(mint) => self.tokens.get(&mint).copied(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Holding {
+    pub currency: Currency,
+    /// Token name from its metadata, when it has any
+    pub name: Option<String>,
+    /// Base units of `currency`
+    pub amount: u64,
+    pub usd_value: Option<f64>,
+}
+
+impl Holding {
+    fn price(&mut self, prices: &UsdPrices) {
+        self.usd_value = prices
+            .price_of(&self.currency)
+            .map(|price| self.amount as f64 / 10f64.powi(self.currency.decimals() as i32) * price);
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WalletHoldings {
+    pub address: Pubkey,
+    /// SOL first, then tokens by mint
+    pub holdings: Vec<Holding>,
+    /// Sum of the holdings that have a USD price
+    pub usd_value: Option<f64>,
+    /// Why this wallet could not be read; its holdings are then empty
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Portfolio {
+    /// One entry per requested address, in request order
+    pub wallets: Vec<WalletHoldings>,
+    /// Every currency summed across the wallets that were read
+    pub totals: Vec<Holding>,
+    pub usd_value: Option<f64>,
+    /// Unix time the balances were read
+    pub snapshot_at: u64,
+}
+
+impl Portfolio {
+    pub fn failed_wallets(&self) -> usize {
+        self.wallets.iter().filter(|wallet| wallet.error.is_some()).count()
+    }
+
+    /// Fill in the USD value of every holding that `prices` has a price for
+    pub fn with_usd_prices(mut self, prices: &UsdPrices) -> Self {
+        for wallet in &mut self.wallets {
+            wallet.holdings.iter_mut().for_each(|holding| holding.price(prices));
+            wallet.usd_value = sum_usd(&wallet.holdings);
+        }
+        self.totals.iter_mut().for_each(|holding| holding.price(prices));
+        self.usd_value = sum_usd(&self.totals);
+        self
+    }
+}
+
+fn sum_usd(holdings: &[Holding]) -> Option<f64> {
+    let priced: Vec<f64> = holdings.iter().filter_map(|holding| holding.usd_value).collect();
+    (!priced.is_empty()).then(|| priced.iter().sum())
+}
+
+impl FinternetClient {
+    /// Balances of every wallet in `addresses`, per wallet and consolidated
+    ///
+    /// Zero token balances are left out. USD values stay empty until `with_usd_prices`.
+    #[tracing::instrument(skip_all, fields(wallets = addresses.len(), rpc = %self.rpc_endpoint()))]
+    pub async fn get_portfolio(&self, addresses: &[Pubkey]) -> Result<Portfolio> {
+        let snapshot_at = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        let sol = self.sol_balances(addresses);
+        let tokens = self.token_balances(addresses);
+
+        let mut raw: Vec<(Pubkey, WalletRead)> = Vec::with_capacity(addresses.len());
+        for ((address, sol), tokens) in addresses.iter().zip(sol).zip(tokens) {
+            let holdings = sol.and_then(|lamports| {
+                let mut holdings = vec![(Currency::Sol, lamports)];
+                holdings.extend(tokens?);
+                Ok(holdings)
+            });
+            raw.push((*address, holdings));
+        }
+
+        let mut mints: Vec<Pubkey> = raw
+            .iter()
+            .filter_map(|(_, holdings)| holdings.as_ref().ok())
+            .flatten()
+            .filter_map(|(currency, _)| currency.mint())
+            .collect();
+        mints.sort();
+        mints.dedup();
+        let names = self.token_names(&mints).await;
+
+        let name_of = |currency: &Currency| currency.mint().and_then(|mint| names.get(&mint).cloned());
+        let mut totals: BTreeMap<Option<Pubkey>, Holding> = BTreeMap::new();
+        let mut wallets = Vec::with_capacity(raw.len());
+        for (address, holdings) in raw {
+            match holdings {
+                Ok(holdings) => {
+                    let holdings: Vec<Holding> = holdings
+                        .into_iter()
+                        .map(|(currency, amount)| Holding { name: name_of(&currency), currency, amount, usd_value: None })
+                        .collect();
+                    for holding in &holdings {
+                        totals
+                            .entry(holding.currency.mint())
+                            .or_insert_with(|| Holding { amount: 0, ..holding.clone() })
+                            .amount += holding.amount;
+                    }
+                    wallets.push(WalletHoldings { address, holdings, usd_value: None, error: None });
+                }
+                Err(error) => {
+                    tracing::warn!("Portfolio: could not read {}: {}", readable(&address), error);
+                    wallets.push(WalletHoldings { address, holdings: Vec::new(), usd_value: None, error: Some(error) });
+                }
+            }
+        }
+
+        let portfolio = Portfolio { wallets, totals: totals.into_values().collect(), usd_value: None, snapshot_at };
+        tracing::info!(
+            "Portfolio of {} wallets read, {} failed, {} currencies",
+            portfolio.wallets.len(),
+            portfolio.failed_wallets(),
+            portfolio.totals.len()
+        );
+        Ok(portfolio)
+    }
+
+    /// Lamports of each address, in order; a failed batch fails only its own addresses
+    fn sol_balances(&self, addresses: &[Pubkey]) -> Vec<Result<u64, String>> {
+        let mut balances = Vec::with_capacity(addresses.len());
+        for chunk in addresses.chunks(ACCOUNTS_PER_CALL) {
+            match self.client.get_multiple_accounts(chunk) {
+                Ok(accounts) => {
+                    balances.extend(accounts.iter().map(|account| Ok(account.as_ref().map_or(0, |a| a.lamports))))
+                }
+                Err(e) => balances.extend(chunk.iter().map(|_| Err(format!("SOL balance unavailable: {}", e)))),
+            }
+        }
+        balances
+    }
+
+    /// Non-zero token balances of each address, in order, with mints merged per wallet
+    fn token_balances(&self, addresses: &[Pubkey]) -> Vec<WalletRead> {
+        if addresses.is_empty() {
+            return Vec::new();
+        }
+        // RPC calls block, so spread the wallets over a few threads
+        let chunk_size = addresses.len().div_ceil(WALLET_CONCURRENCY);
+        std::thread::scope(|scope| {
+            let workers: Vec<_> = addresses
+                .chunks(chunk_size)
+                .map(|chunk| {
+                    scope.spawn(move || {
+                        chunk
+                            .iter()
+                            .map(|address| self.wallet_tokens(address).map_err(|e| format!("Token balances unavailable: {}", e)))
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect();
+            workers
+                .into_iter()
+                .zip(addresses.chunks(chunk_size))
+                .flat_map(|(worker, chunk)| {
+                    worker
+                        .join()
+                        .unwrap_or_else(|_| chunk.iter().map(|_| Err("Token balance read panicked".to_string())).collect())
+                })
+                .collect()
+        })
+    }
+
+    fn wallet_tokens(&self, address: &Pubkey) -> Result<Vec<(Currency, u64)>> {
+        let accounts = futures::executor::block_on(self.get_token_account_details(address))?;
+        let mut by_mint: BTreeMap<Pubkey, (Option<u8>, u64)> = BTreeMap::new();
+        for account in accounts.iter().filter(|account| account.amount > 0) {
+            let entry = by_mint.entry(account.mint).or_insert((account.decimals, 0));
+            entry.0 = entry.0.or(account.decimals);
+            entry.1 += account.amount;
+        }
+        Ok(by_mint
+            .into_iter()
+            .map(|(mint, (decimals, amount))| {
+                let currency = match decimals {
+                    Some(decimals) => Currency::spl(mint, decimals),
+                    None => Currency::from_mint(mint),
+                };
+                (currency, amount)
+            })
+            .collect())
+    }
+
+    /// Names of the mints that have metadata; the rest are shown by their currency label
+    async fn token_names(&self, mints: &[Pubkey]) -> HashMap<Pubkey, String> {
+        match self.get_assets_metadata(mints).await {
+            Ok(assets) => mints
+                .iter()
+                .zip(assets)
+                .filter_map(|(mint, asset)| asset.ok().map(|asset| (*mint, asset.metadata.name)))
+                .filter(|(_, name)| !name.is_empty())
+                .collect(),
+            Err(e) => {
+                tracing::warn!("Portfolio: token names unavailable: {}", e);
+                HashMap::new()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn token_prices_are_read_keyed_by_mint_address() {
+        let mint = Pubkey::new_unique();
+        let prices: UsdPrices = serde_json::from_value(serde_json::json!({
+            "sol": 150.0,
+            "tokens": { mint.to_string(): 1.0 },
+        }))
+        .unwrap();
+        assert_eq!(prices.price_of(&Currency::spl(mint, 6)), Some(1.0));
+        assert_eq!(prices.price_of(&Currency::Sol), Some(150.0));
+        assert_eq!(prices.price_of(&Currency::spl(Pubkey::new_unique(), 6)), None);
+        assert_eq!(serde_json::to_value(&prices).unwrap()["tokens"][mint.to_string()], 1.0);
+
+        let unpriced: UsdPrices = serde_json::from_str(r#"{ "sol": null }"#).unwrap();
+        assert!(unpriced.tokens.is_empty());
+        assert!(serde_json::from_str::<UsdPrices>(r#"{ "sol": null, "tokens": { "USDC": 1.0 } }"#).is_err());
+    }
+}
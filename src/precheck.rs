@@ -0,0 +1,208 @@
+use crate::mint_registry::{self, KnownMint};
+use crate::telemetry::readable;
+use crate::FinternetClient;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use solana_client::rpc_client::GetConfirmedSignaturesForAddress2Config;
+use solana_sdk::{account::Account, program_pack::Pack, pubkey::Pubkey};
+use spl_token_2022::extension::StateWithExtensions;
+
+const LAMPORTS_PER_SOL: f64 = 1_000_000_000.0;
+
+/// What kind of account a payment recipient address holds
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum RecipientKind {
+    /// Nothing on-chain yet; fine for a fresh wallet
+    NotFound,
+    /// An ordinary wallet
+    SystemAccount,
+    /// An SPL token account rather than the wallet that owns it
+    TokenAccount { mint: Pubkey, owner: Pubkey },
+    /// An executable program
+    Program,
+    /// Data account of some other program (mint, metadata, PDA state, ...)
+    ProgramOwned { owner: Pubkey },
+}
+
+/// Everything a payment form should know about a recipient before submitting
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecipientPrecheck {
+    pub address: Pubkey,
+    pub mint: Pubkey,
+    /// Off-curve addresses (PDAs) have no private key, so nobody can sign for their ATA
+    pub on_curve: bool,
+    pub kind: RecipientKind,
+    /// Associated token account the payment would be sent to
+    pub associated_token_account: Pubkey,
+    pub ata_exists: bool,
+    /// Rent the sender pays to create the ATA, 0 when it already exists
+    pub extra_rent_lamports: u64,
+    pub has_history: bool,
+    /// The token registry's name for the mint, e.g. `USDC (devnet)`
+    #[serde(default)]
+    pub mint_name: Option<String>,
+    /// The client is on a public cluster whose registry does not list the mint
+    #[serde(default)]
+    pub mint_unlisted: bool,
+    /// Human-readable problems worth confirming before sending, most serious first
+    pub warnings: Vec<String>,
+}
+
+impl RecipientPrecheck {
+    pub fn is_clean(&self) -> bool {
+        self.warnings.is_empty()
+    }
+
+    /// The address is probably not what the sender meant (not a wallet, or off-curve);
+    /// a missing ATA or empty history alone is not reason enough to stop, and neither is a
+    /// token account of the mint being sent, which is paid directly
+    ///
+    /// A mint the registry does not list is asked about too, since it may be a lookalike.
+    pub fn needs_confirmation(&self) -> bool {
+        if self.mint_unlisted {
+            return true;
+        }
+        match &self.kind {
+            RecipientKind::TokenAccount { mint, .. } => *mint != self.mint,
+            RecipientKind::NotFound | RecipientKind::SystemAccount => !self.on_curve,
+            RecipientKind::Program | RecipientKind::ProgramOwned { .. } => true,
+        }
+    }
+}
+
+/// Mint, owner, and program of an SPL token account
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct TokenAccountOwner {
+    pub mint: Pubkey,
+    pub owner: Pubkey,
+    pub program: Pubkey,
+}
+
+/// Decode `account` as a token account of either token program
+pub(crate) fn as_token_account(account: &Account) -> Option<TokenAccountOwner> {
+    if account.owner != spl_token::id() && account.owner != spl_token_2022::id() {
+        return None;
+    }
+    // Mints share the token programs but are shorter than any token account
+    if account.data.len() < spl_token_2022::state::Account::LEN {
+        return None;
+    }
+    let state = StateWithExtensions::<spl_token_2022::state::Account>::unpack(&account.data).ok()?;
+    Some(TokenAccountOwner {
+        mint: state.base.mint,
+        owner: state.base.owner,
+        program: account.owner,
+    })
+}
+
+impl FinternetClient {
+    /// Inspect `to` before paying it in `mint`: account kind, ATA state, extra rent, and history
+    #[tracing::instrument(skip_all, fields(to = %readable(to), mint = %mint, rpc = %self.rpc_endpoint()))]
+    pub async fn precheck_recipient(&self, to: &Pubkey, mint: &Pubkey) -> Result<RecipientPrecheck> {
+        let on_curve = to.is_on_curve();
+        let account = self.client.get_account_with_commitment(to, self.client.commitment())?.value;
+
+        let kind = match &account {
+            None => RecipientKind::NotFound,
+            Some(account) if account.owner == solana_sdk::system_program::id() => RecipientKind::SystemAccount,
+            Some(account) if account.executable => RecipientKind::Program,
+            Some(account) => match as_token_account(account) {
+                Some(token) => RecipientKind::TokenAccount {
+                    mint: token.mint,
+                    owner: token.owner,
+                },
+                None => RecipientKind::ProgramOwned { owner: account.owner },
+            },
+        };
+
+        let associated_token_account = spl_associated_token_account::get_associated_token_address(to, mint);
+        let ata_exists = self
+            .client
+            .get_account_with_commitment(&associated_token_account, self.client.commitment())?
+            .value
+            .is_some();
+        let extra_rent_lamports = if ata_exists {
+            0
+        } else {
+            self.client
+                .get_minimum_balance_for_rent_exemption(spl_token::state::Account::LEN)?
+        };
+
+        let has_history = !self
+            .client
+            .get_signatures_for_address_with_config(
+                to,
+                GetConfirmedSignaturesForAddress2Config {
+                    limit: Some(1),
+                    ..GetConfirmedSignaturesForAddress2Config::default()
+                },
+            )?
+            .is_empty();
+
+        let network = self.network()?;
+        let known_mint = mint_registry::lookup(mint);
+        let mint_unlisted = known_mint.is_none() && network.is_some();
+
+        let mut warnings = Vec::new();
+        if let (Some(KnownMint { network: Some(mint_network), name, .. }), Some(network)) = (known_mint, network) {
+            if mint_network != network {
+                warnings.push(format!("{} is {} ({}!), but this client is connected to {}.", mint, name, mint_network, network));
+            }
+        }
+        if let (true, Some(network)) = (mint_unlisted, network) {
+            warnings.push(format!(
+                "{} is not in the token registry for {}; make sure it is the token you meant and not a lookalike.",
+                mint, network
+            ));
+        }
+        match &kind {
+            RecipientKind::TokenAccount { mint: account_mint, owner } if account_mint == mint => warnings.push(format!(
+                "{} is a token account, not a wallet; the payment goes straight into it. Its owner is {}.",
+                to, owner
+            )),
+            RecipientKind::TokenAccount { mint: account_mint, owner } => warnings.push(format!(
+                "{} is a token account for a different mint ({}), not a wallet. Its owner is {}.",
+                to, account_mint, owner
+            )),
+            RecipientKind::Program => warnings.push(format!(
+                "{} is a program. Tokens sent to it are almost certainly unrecoverable.",
+                to
+            )),
+            RecipientKind::ProgramOwned { owner } => warnings.push(format!(
+                "{} is a data account owned by program {}, not a wallet.",
+                to, owner
+            )),
+            RecipientKind::NotFound | RecipientKind::SystemAccount => {}
+        }
+        if !on_curve && !matches!(kind, RecipientKind::TokenAccount { .. }) {
+            warnings.push(format!(
+                "{} is off-curve (a program-derived address); only its program can move tokens sent to it.",
+                to
+            ));
+        }
+        if !ata_exists {
+            warnings.push(format!(
+                "Recipient has never held this token; you'll pay ~{:.4} SOL extra to create their account.",
+                extra_rent_lamports as f64 / LAMPORTS_PER_SOL
+            ));
+        }
+        if !has_history {
+            warnings.push(format!("{} has no transaction history; double-check the address.", to));
+        }
+
+        Ok(RecipientPrecheck {
+            address: *to,
+            mint: *mint,
+            on_curve,
+            kind,
+            associated_token_account,
+            ata_exists,
+            extra_rent_lamports,
+            has_history,
+            mint_name: known_mint.map(|known| known.describe(network)),
+            mint_unlisted,
+            warnings,
+        })
+    }
+}
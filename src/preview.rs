@@ -0,0 +1,521 @@
+//! Dry runs of payments and tokenizations, for showing their effects before the user confirms
+//!
+//! A preview builds the exact instructions the real call would send, through the same builder,
+//! and simulates them unsigned against the cluster. Nothing is signed or broadcast. The result
+//! lists the fee, every balance the transaction would move, and the accounts it would create.
+//! A simulation that fails comes back as a `PreviewWarning` instead of an error, with the
+//! failure verbatim and a hint when one is known.
+
+use crate::asset::{new_asset_metadata, TokenizeOptions};
+use crate::payment::split_payment_legs;
+use crate::remediation::remediation_for_message;
+use crate::telemetry::readable;
+use crate::validation::{self, validate_payment};
+use crate::{AssetMetadata, AssetType, Currency, FinternetClient};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use solana_account_decoder::UiAccountEncoding;
+use solana_client::rpc_config::{RpcSimulateTransactionAccountsConfig, RpcSimulateTransactionConfig};
+use solana_sdk::{
+    account::Account,
+    instruction::Instruction,
+    program_pack::Pack,
+    pubkey::Pubkey,
+    signer::Signer,
+    transaction::Transaction,
+};
+use std::collections::HashMap;
+
+/// SOL balances are reported with the native mint's 9 decimals
+const SOL_DECIMALS: u8 = 9;
+
+/// A balance the transaction would change, in base units
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BalanceChange {
+    pub account: Pubkey,
+    /// Wallet owning the token account; the account itself for SOL balances
+    pub owner: Pubkey,
+    /// `None` for SOL
+    pub mint: Option<Pubkey>,
+    pub decimals: u8,
+    pub before: u64,
+    pub after: u64,
+}
+
+impl BalanceChange {
+    pub fn change(&self) -> i128 {
+        self.after as i128 - self.before as i128
+    }
+
+    pub fn currency(&self) -> Currency {
+        match self.mint {
+            Some(mint) => Currency::spl(mint, self.decimals),
+            None => Currency::Sol,
+        }
+    }
+}
+
+/// An account the transaction would bring into existence
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreatedAccount {
+    pub address: Pubkey,
+    /// Program that would own it
+    pub program: Pubkey,
+    /// Rent deposited into it
+    pub lamports: u64,
+}
+
+/// Something that would go wrong, or that the user should know before confirming
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PreviewWarning {
+    pub code: String,
+    pub message: String,
+    pub hint: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TransactionPreview {
+    /// Network fee in lamports
+    pub fee: u64,
+    pub balance_changes: Vec<BalanceChange>,
+    pub created_accounts: Vec<CreatedAccount>,
+    /// The simulation's failure, verbatim
+    pub simulation_error: Option<String>,
+    pub logs: Vec<String>,
+    pub units_consumed: Option<u64>,
+    pub warnings: Vec<PreviewWarning>,
+}
+
+impl TransactionPreview {
+    /// Whether the simulation succeeded and nothing else was flagged
+    pub fn would_succeed(&self) -> bool {
+        self.simulation_error.is_none() && self.warnings.is_empty()
+    }
+}
+
+/// What `preview_tokenize` would mint
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TokenizePreview {
+    /// Placeholder mint; the real call generates a fresh one
+    pub mint: Pubkey,
+    pub metadata: AssetMetadata,
+    pub transaction: TransactionPreview,
+}
+
+impl FinternetClient {
+    /// Simulate `send_payment` from `from` without signing or sending anything
+    ///
+    /// Input validation and the mint's cluster check fail as they would for the real call. Asset policy refusals are reported
+    /// as warnings. Spending limits are not consulted, since checking them may ask for approval.
+    #[tracing::instrument(skip_all, fields(wallet = %readable(from), to = %readable(to), mint = %mint, amount))]
+    pub async fn preview_payment(
+        &self,
+        from: &Pubkey,
+        to: &Pubkey,
+        amount: u64,
+        mint: &Pubkey,
+        memo: Option<&str>,
+    ) -> Result<TransactionPreview> {
+        validation::check(validate_payment(to, amount, mint, memo))?;
+        self.check_mint_network(mint)?;
+        let mut warnings = self.asset_policy_warnings(&[*to], mint).await;
+        let instructions = self.payment_transaction_instructions(from, to, amount, mint, memo, &[])?;
+        let mut preview = self.simulate_instructions(from, &instructions)?;
+        warnings.append(&mut preview.warnings);
+        preview.warnings = warnings;
+        Ok(preview)
+    }
+
+    /// Simulate `send_split_payment` from `from` without signing or sending anything
+    #[tracing::instrument(skip_all, fields(wallet = %readable(from), to = %readable(&primary.0), mint = %mint, amount = primary.1, splits = splits.len()))]
+    pub async fn preview_split_payment(
+        &self,
+        from: &Pubkey,
+        primary: (Pubkey, u64),
+        splits: &[(Pubkey, u16)],
+        mint: &Pubkey,
+        memo: Option<&str>,
+    ) -> Result<TransactionPreview> {
+        validation::check(validate_payment(&primary.0, primary.1, mint, memo))?;
+        self.check_mint_network(mint)?;
+        let legs = split_payment_legs(primary, splits)?;
+        let recipients: Vec<Pubkey> = legs.iter().map(|(to, _)| *to).collect();
+        let mut warnings = self.asset_policy_warnings(&recipients, mint).await;
+        let instructions = self.split_payment_instructions(from, &legs, mint, memo)?;
+        let mut preview = self.simulate_instructions(from, &instructions)?;
+        warnings.append(&mut preview.warnings);
+        preview.warnings = warnings;
+        Ok(preview)
+    }
+
+    /// Simulate `tokenize_asset_with_options` for `issuer` without signing or sending anything
+    ///
+    /// No metadata is published: the URI is the one derived from the configured URI base, or
+    /// empty when the client publishes through a `MetadataPublisher`.
+    #[tracing::instrument(skip_all, fields(wallet = %readable(issuer), asset_type = %asset_type, value))]
+    pub async fn preview_tokenize(
+        &self,
+        name: &str,
+        description: &str,
+        value: u64,
+        asset_type: &AssetType,
+        issuer: &Pubkey,
+        options: &TokenizeOptions,
+    ) -> Result<TokenizePreview> {
+        validation::check(validation::validate_asset_spec(name, description, value, asset_type, options))?;
+        let mint = options.new_mint_keypair().pubkey();
+        let mut metadata = new_asset_metadata(name, description, value, asset_type, issuer, &mint, options)?;
+        let mut warnings = Vec::new();
+        metadata.uri = match (&self.metadata_publisher, self.configured_metadata_uri(&mint, options)) {
+            (None, Some(uri)) => uri,
+            (Some(_), _) => String::new(),
+            (None, None) => {
+                warnings.push(PreviewWarning {
+                    code: "no_metadata_uri".to_string(),
+                    message: "No metadata publisher or URI base is configured; the asset would be minted without a URI"
+                        .to_string(),
+                    hint: None,
+                });
+                String::new()
+            }
+        };
+        let instructions = self.tokenize_transaction_instructions(issuer, &mut metadata, options)?;
+        let mut transaction = self.simulate_instructions(issuer, &instructions)?;
+        warnings.append(&mut transaction.warnings);
+        transaction.warnings = warnings;
+        Ok(TokenizePreview { mint, metadata, transaction })
+    }
+
+    async fn asset_policy_warnings(&self, recipients: &[Pubkey], mint: &Pubkey) -> Vec<PreviewWarning> {
+        let mut warnings = Vec::new();
+        for recipient in recipients {
+            if let Err(e) = self.check_asset_policy(recipient, mint).await {
+                let code = e
+                    .downcast_ref::<crate::asset_policy::AssetPolicyViolation>()
+                    .map(|violation| violation.code())
+                    .unwrap_or("asset_policy_unavailable");
+                warnings.push(PreviewWarning { code: code.to_string(), message: e.to_string(), hint: None });
+            }
+        }
+        warnings
+    }
+
+    /// Simulate `instructions` paid by `payer`, unsigned, and diff every account they touch
+    pub(crate) fn simulate_instructions(&self, payer: &Pubkey, instructions: &[Instruction]) -> Result<TransactionPreview> {
+        let transaction = Transaction::new_with_payer(instructions, Some(payer));
+        let keys = transaction.message.account_keys.clone();
+        let fee = self.client.get_fee_for_message(&transaction.message)?;
+        let before = self.client.get_multiple_accounts(&keys)?;
+
+        let config = RpcSimulateTransactionConfig {
+            sig_verify: false,
+            replace_recent_blockhash: true,
+            commitment: Some(self.client.commitment()),
+            accounts: Some(RpcSimulateTransactionAccountsConfig {
+                encoding: Some(UiAccountEncoding::Base64),
+                addresses: keys.iter().map(Pubkey::to_string).collect(),
+            }),
+            ..Default::default()
+        };
+        let result = self.client.simulate_transaction_with_config(&transaction, config)?.value;
+        let logs = result.logs.unwrap_or_default();
+
+        let mut preview = TransactionPreview {
+            fee,
+            balance_changes: Vec::new(),
+            created_accounts: Vec::new(),
+            simulation_error: result.err.as_ref().map(ToString::to_string),
+            logs,
+            units_consumed: result.units_consumed,
+            warnings: Vec::new(),
+        };
+        if let Some(error) = &preview.simulation_error {
+            let text = format!("{}\n{}", error, preview.logs.join("\n"));
+            preview.warnings.push(PreviewWarning {
+                code: simulation_warning_code(&text).to_string(),
+                message: format!("The transaction would fail: {}", error),
+                hint: remediation_for_message(&text),
+            });
+            // A failed simulation returns no account states worth diffing
+            return Ok(preview);
+        }
+
+        let after: Vec<Option<Account>> = result
+            .accounts
+            .unwrap_or_default()
+            .into_iter()
+            .map(|account| account.and_then(|account| account.decode::<Account>()))
+            .collect();
+        let mut decimals: HashMap<Pubkey, u8> = HashMap::new();
+        for ((address, before), after) in keys.iter().zip(before).zip(after) {
+            let Some(after) = after else { continue };
+            let before_lamports = before.as_ref().map_or(0, |account| account.lamports);
+            if before_lamports == 0 && after.lamports > 0 {
+                preview.created_accounts.push(CreatedAccount {
+                    address: *address,
+                    program: after.owner,
+                    lamports: after.lamports,
+                });
+            }
+            if before_lamports != after.lamports {
+                preview.balance_changes.push(BalanceChange {
+                    account: *address,
+                    owner: *address,
+                    mint: None,
+                    decimals: SOL_DECIMALS,
+                    before: before_lamports,
+                    after: after.lamports,
+                });
+            }
+
+            let Some(after_state) = token_state(&after) else { continue };
+            let before_amount = before.as_ref().and_then(token_state).map_or(0, |state| state.amount);
+            if before_amount == after_state.amount {
+                continue;
+            }
+            let mint_decimals = match decimals.get(&after_state.mint) {
+                Some(decimals) => *decimals,
+                None => {
+                    let fetched = self.client.get_token_supply(&after_state.mint)?.decimals;
+                    decimals.insert(after_state.mint, fetched);
+                    fetched
+                }
+            };
+            preview.balance_changes.push(BalanceChange {
+                account: *address,
+                owner: after_state.owner,
+                mint: Some(after_state.mint),
+                decimals: mint_decimals,
+                before: before_amount,
+                after: after_state.amount,
+            });
+        }
+        Ok(preview)
+    }
+}
+
+fn token_state(account: &Account) -> Option<spl_token::state::Account> {
+    if account.owner != spl_token::id() || account.data.len() != spl_token::state::Account::LEN {
+        return None;
+    }
+    spl_token::state::Account::unpack(&account.data).ok()
+}
+
+/// Stable code for a simulation failure, for clients that branch on it
+fn simulation_warning_code(text: &str) -> &'static str {
+    let text = text.to_ascii_lowercase();
+    if text.contains("insufficient funds for fee")
+        || text.contains("insufficient lamports")
+        || text.contains("no record of a prior credit")
+    {
+        "insufficient_sol"
+    } else if text.contains("insufficient funds") {
+        // The token program's `Error: insufficient funds` log
+        "insufficient_funds"
+    } else {
+        "simulation_failed"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_cluster::{decode_transaction, encode_account, MockCluster};
+    use crate::MetadataBackend;
+    use serde_json::{json, Value};
+    use solana_sdk::compute_budget;
+    use solana_sdk::rent::Rent;
+    use solana_sdk::signature::Keypair;
+    use std::sync::{Arc, Mutex};
+
+    const FEE: u64 = 5_000;
+
+    fn token_account(owner: &Pubkey, mint: &Pubkey, amount: u64) -> Account {
+        let state = spl_token::state::Account {
+            mint: *mint,
+            owner: *owner,
+            amount,
+            state: spl_token::state::AccountState::Initialized,
+            ..Default::default()
+        };
+        let mut data = vec![0; spl_token::state::Account::LEN];
+        spl_token::state::Account::pack(state, &mut data).unwrap();
+        Account { lamports: rent(), data, owner: spl_token::id(), executable: false, rent_epoch: 0 }
+    }
+
+    fn rent() -> u64 {
+        Rent::default().minimum_balance(spl_token::state::Account::LEN)
+    }
+
+    type Simulated = Arc<Mutex<Vec<(Transaction, Value)>>>;
+
+    /// Answer every simulation with `after` for the accounts it asks about (unlisted ones read
+    /// as unchanged), keeping what was simulated and how
+    fn simulate_to(cluster: &MockCluster, after: HashMap<Pubkey, Account>) -> Simulated {
+        let simulated = Simulated::default();
+        let seen = Arc::clone(&simulated);
+        cluster.respond("simulateTransaction", move |params| {
+            seen.lock().unwrap().push((decode_transaction(params).unwrap(), params[1].clone()));
+            let accounts: Vec<Value> = params[1]["accounts"]["addresses"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|address| {
+                    let address: Pubkey = address.as_str().unwrap().parse().unwrap();
+                    encode_account(&address, after.get(&address))
+                })
+                .collect();
+            Some(Ok(json!({ "context": { "slot": 1_000 }, "value": { "err": null, "logs": ["Program log: ok"], "accounts": accounts, "unitsConsumed": 6_200 } })))
+        });
+        simulated
+    }
+
+    /// Answer every simulation with `err` and `logs`
+    fn simulate_failure(cluster: &MockCluster, err: Value, logs: &[&str]) {
+        let logs: Vec<String> = logs.iter().map(|line| line.to_string()).collect();
+        cluster.respond("simulateTransaction", move |_| {
+            Some(Ok(json!({ "context": { "slot": 1_000 }, "value": { "err": err, "logs": logs, "accounts": null, "unitsConsumed": 3_100 } })))
+        });
+    }
+
+    /// Instructions without the compute budget ones the real send adds for its priority fee
+    fn effective(transaction: &Transaction) -> Vec<Instruction> {
+        let keys = &transaction.message.account_keys;
+        transaction
+            .message
+            .instructions
+            .iter()
+            .filter(|ix| *ix.program_id(keys) != compute_budget::id())
+            .map(|ix| Instruction {
+                program_id: *ix.program_id(keys),
+                accounts: ix.accounts.iter().map(|i| solana_sdk::instruction::AccountMeta::new_readonly(keys[*i as usize], false)).collect(),
+                data: ix.data.clone(),
+            })
+            .collect()
+    }
+
+    /// A cluster where `payer` holds 100 USDC-like tokens and 1 SOL, and a recipient with no
+    /// token account yet
+    fn setup() -> (MockCluster, FinternetClient, Keypair, Pubkey, Pubkey) {
+        let cluster = MockCluster::new();
+        let payer = Keypair::new();
+        let mint = cluster.add_mint(&spl_token::id(), 6, &Pubkey::new_unique());
+        cluster.set_balance(payer.pubkey(), 1_000_000_000);
+        cluster.add_token_account(&payer.pubkey(), &mint, 100_000_000);
+        let client = cluster.default_client();
+        (cluster, client, payer, mint, Keypair::new().pubkey())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn a_payment_preview_reports_the_fee_balances_and_new_accounts_without_sending() {
+        let (cluster, client, payer, mint, recipient) = setup();
+        let source = spl_associated_token_account::get_associated_token_address(&payer.pubkey(), &mint);
+        let destination = spl_associated_token_account::get_associated_token_address(&recipient, &mint);
+        let paid = Account { lamports: 1_000_000_000 - FEE - rent(), ..cluster.account(&payer.pubkey()).unwrap() };
+        let simulated = simulate_to(
+            &cluster,
+            HashMap::from([
+                (payer.pubkey(), paid),
+                (source, token_account(&payer.pubkey(), &mint, 75_500_000)),
+                (destination, token_account(&recipient, &mint, 24_500_000)),
+            ]),
+        );
+
+        let preview = client.preview_payment(&payer.pubkey(), &recipient, 24_500_000, &mint, Some("INV-7")).await.unwrap();
+        assert!(preview.would_succeed());
+        assert_eq!((preview.fee, preview.units_consumed), (FEE, Some(6_200)));
+        let change = |account: &Pubkey, mint: Option<Pubkey>| {
+            let change = preview.balance_changes.iter().find(|c| c.account == *account && c.mint == mint).unwrap();
+            (change.owner, change.decimals, change.change())
+        };
+        assert_eq!(change(&payer.pubkey(), None), (payer.pubkey(), 9, -((FEE + rent()) as i128)));
+        assert_eq!(change(&source, Some(mint)), (payer.pubkey(), 6, -24_500_000));
+        assert_eq!(change(&destination, Some(mint)), (recipient, 6, 24_500_000));
+        // The new token account's rent shows as a SOL change too
+        assert_eq!(preview.balance_changes.len(), 4);
+        assert_eq!(preview.created_accounts.len(), 1);
+        let created = &preview.created_accounts[0];
+        assert_eq!((created.address, created.program, created.lamports), (destination, spl_token::id(), rent()));
+
+        // Simulated unsigned, against the latest blockhash, and never sent
+        let (transaction, config) = simulated.lock().unwrap()[0].clone();
+        assert_eq!((config["sigVerify"].clone(), config["replaceRecentBlockhash"].clone()), (json!(false), json!(true)));
+        assert!(transaction.signatures.iter().all(|signature| *signature == Default::default()));
+        assert!(cluster.sent().is_empty());
+        assert_eq!(cluster.call_count("sendTransaction"), 0);
+
+        // The real send goes out with the very instructions that were simulated
+        client.send_payment(&payer, &recipient, 24_500_000, &mint, Some("INV-7")).await.unwrap();
+        assert_eq!(effective(&cluster.sent()[0]), effective(&transaction));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn a_simulated_failure_comes_back_as_a_warning_not_an_error() {
+        let (cluster, client, payer, mint, recipient) = setup();
+        let token = spl_token::id().to_string();
+        simulate_failure(
+            &cluster,
+            json!({ "InstructionError": [1, { "Custom": 1 }] }),
+            &[
+                &format!("Program {} invoke [1]", token),
+                "Program log: Error: insufficient funds",
+                &format!("Program {} failed: custom program error: 0x1", token),
+            ],
+        );
+
+        let preview = client.preview_payment(&payer.pubkey(), &recipient, 500_000_000, &mint, None).await.unwrap();
+        assert!(!preview.would_succeed());
+        assert_eq!(preview.simulation_error.as_deref(), Some("Error processing Instruction 1: custom program error: 0x1"));
+        assert_eq!(preview.logs.len(), 3);
+        assert!(preview.balance_changes.is_empty() && preview.created_accounts.is_empty());
+        assert_eq!(preview.warnings.len(), 1);
+        let warning = &preview.warnings[0];
+        assert_eq!(warning.code, "insufficient_funds");
+        assert_eq!(warning.message, "The transaction would fail: Error processing Instruction 1: custom program error: 0x1");
+        assert!(warning.hint.as_deref().unwrap().starts_with("The source token account balance is too low"), "{:?}", warning.hint);
+
+        simulate_failure(&cluster, json!("InsufficientFundsForFee"), &[]);
+        let preview = client.preview_payment(&payer.pubkey(), &recipient, 1_000_000, &mint, None).await.unwrap();
+        assert_eq!(preview.warnings[0].code, "insufficient_sol");
+        assert!(preview.warnings[0].hint.as_deref().unwrap().starts_with("The fee payer cannot cover the network fee"));
+        assert!(cluster.sent().is_empty());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn invalid_input_fails_like_the_real_call_and_policy_refusals_are_warnings() {
+        let (cluster, client, payer, mint, recipient) = setup();
+        simulate_to(&cluster, HashMap::new());
+
+        assert!(client.preview_payment(&payer.pubkey(), &recipient, 0, &mint, None).await.is_err());
+        assert_eq!(cluster.call_count("simulateTransaction"), 0);
+
+        let policy = crate::asset_policy::AssetPolicy::new(mint).allow_owner(Pubkey::new_unique());
+        let client = client.with_asset_policy(policy);
+        let preview = client.preview_payment(&payer.pubkey(), &recipient, 1_000_000, &mint, None).await.unwrap();
+        assert_eq!(preview.warnings.len(), 1);
+        assert_eq!(preview.warnings[0].code, "owner_not_allowed");
+        assert!(!preview.would_succeed());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn a_tokenize_preview_mints_nothing_and_flags_a_missing_uri() {
+        let (cluster, client, payer, _, _) = setup();
+        let simulated = simulate_to(&cluster, HashMap::new());
+
+        let preview = client
+            .preview_tokenize("Warehouse Receipt", "Berth 12", 25_000, &AssetType::Commodity, &payer.pubkey(), &TokenizeOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(preview.metadata.token_mint, Some(preview.mint));
+        assert_eq!(preview.metadata.metadata_backend, Some(MetadataBackend::LedgerMemo));
+        let codes: Vec<&str> = preview.transaction.warnings.iter().map(|w| w.code.as_str()).collect();
+        assert_eq!(codes, ["no_metadata_uri"]);
+        assert!(cluster.sent().is_empty());
+
+        // The same builder as the real mint, down to the programs it calls in order
+        let (transaction, _) = simulated.lock().unwrap()[0].clone();
+        client.tokenize_asset("Warehouse Receipt", "Berth 12", 25_000, &AssetType::Commodity, &payer).await.unwrap();
+        let programs = |tx: &Transaction| effective(tx).into_iter().map(|ix| ix.program_id).collect::<Vec<_>>();
+        assert_eq!(programs(&cluster.sent()[0]), programs(&transaction));
+    }
+}
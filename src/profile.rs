@@ -0,0 +1,83 @@
+//! Named connection settings, picked on the command line with `--profile`
+//!
+//! A profile holds what it takes to talk to one cluster: its RPC URL, the wallet to sign with,
+//! and, for a local validator, the mints created there. `localnet::Localnet::up` writes the
+//! `localnet` profile; any other profile is written by hand.
+
+use crate::mint_registry::{self, Network};
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use solana_sdk::pubkey::Pubkey;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Where the CLI looks for profiles
+pub const DEFAULT_PROFILES_PATH: &str = ".finternet/profiles.json";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Profile {
+    pub rpc_url: String,
+    /// Keypair file to sign with
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub wallet: Option<PathBuf>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub network: Option<Network>,
+    /// USDC stand-in on a local validator, as base58
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usdc_mint: Option<String>,
+}
+
+impl Profile {
+    /// Register the profile's local mints with `mint_registry`, so USDC resolves to them
+    pub fn register_mints(&self) -> Result<()> {
+        if let Some(mint) = &self.usdc_mint {
+            let mint = Pubkey::from_str(mint).map_err(|e| anyhow!("Invalid usdc_mint '{}': {}", mint, e))?;
+            mint_registry::register_localnet_mint(mint_registry::USDC, mint);
+        }
+        Ok(())
+    }
+}
+
+/// The profiles file: profiles by name
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Profiles {
+    #[serde(default)]
+    pub profiles: BTreeMap<String, Profile>,
+}
+
+impl Profiles {
+    /// Profiles in `path`; none when it does not exist
+    pub fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let data = fs::read_to_string(path)?;
+        serde_json::from_str(&data).map_err(|e| anyhow!("Corrupt profiles file {}: {}", path.display(), e))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            if !parent.as_os_str().is_empty() {
+                fs::create_dir_all(parent)?;
+            }
+        }
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, serde_json::to_string_pretty(self)?)?;
+        fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Result<&Profile> {
+        self.profiles.get(name).ok_or_else(|| {
+            let known: Vec<&str> = self.profiles.keys().map(String::as_str).collect();
+            anyhow!("No profile named '{}'; known profiles: {}", name, if known.is_empty() { "none".to_string() } else { known.join(", ") })
+        })
+    }
+
+    /// Add or replace the profile called `name`
+    pub fn set(&mut self, name: &str, profile: Profile) {
+        self.profiles.insert(name.to_string(), profile);
+    }
+}
@@ -0,0 +1,69 @@
+use crate::FinternetClient;
+use serde::Serialize;
+use solana_sdk::signature::Signature;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Long-running client operations that report progress
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Operation {
+    Batch,
+    SyncLedger,
+    TransactionHistory,
+}
+
+/// What a long-running operation is doing right now
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum ProgressEvent {
+    /// `total` is unknown until the operation has counted its work
+    Started { operation: Operation, total: Option<usize> },
+    /// Signature pages read so far, before the amount of work is known
+    Scanning { operation: Operation, signatures_found: usize },
+    /// The transaction the operation is working on now
+    Processing { operation: Operation, signature: Signature },
+    /// Still waiting for `signature` to reach the requested commitment
+    AwaitingConfirmation { signature: Signature, waited_secs: u64 },
+    Advanced { operation: Operation, completed: usize, total: usize },
+    Finished { operation: Operation, completed: usize },
+}
+
+/// Receives every `ProgressEvent`; must return quickly, as it runs on the operation's thread
+pub type ProgressCallback = Arc<dyn Fn(ProgressEvent) + Send + Sync>;
+
+/// A subscriber plus whether it has panicked, after which it is no longer called
+#[derive(Clone)]
+pub(crate) struct ProgressSubscriber {
+    callback: ProgressCallback,
+    broken: Arc<AtomicBool>,
+}
+
+impl FinternetClient {
+    /// Call `callback` with progress from batches, ledger syncs, and history fetches
+    pub fn with_progress(mut self, callback: impl Fn(ProgressEvent) + Send + Sync + 'static) -> Self {
+        self.progress = Some(ProgressSubscriber {
+            callback: Arc::new(callback),
+            broken: Arc::new(AtomicBool::new(false)),
+        });
+        self
+    }
+
+    /// Hand an event to the subscriber, building it only if there is one
+    ///
+    /// A panicking callback is caught, logged, and dropped; the operation carries on.
+    pub(crate) fn report_progress(&self, event: impl FnOnce() -> ProgressEvent) {
+        let Some(subscriber) = &self.progress else {
+            return;
+        };
+        if subscriber.broken.load(Ordering::Relaxed) {
+            return;
+        }
+        let event = event();
+        if catch_unwind(AssertUnwindSafe(|| (subscriber.callback)(event))).is_err() {
+            subscriber.broken.store(true, Ordering::Relaxed);
+            tracing::warn!("Progress callback panicked; no further progress will be reported");
+        }
+    }
+}
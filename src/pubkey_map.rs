@@ -0,0 +1,37 @@
+//! Serde helpers for maps keyed by `Pubkey`
+//!
+//! `Pubkey` serializes as a byte array, which JSON cannot use as an object key. Use
+//! `#[serde(with = "crate::pubkey_map")]` on such a field to key it by the base58 address instead.
+
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use solana_sdk::pubkey::Pubkey;
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+pub fn serialize<'a, M, V, S>(map: &'a M, serializer: S) -> Result<S::Ok, S::Error>
+where
+    &'a M: IntoIterator<Item = (&'a Pubkey, &'a V)>,
+    V: Serialize + 'a,
+    S: Serializer,
+{
+    // Sorted by address, so the same map always serializes the same way
+    let keyed: BTreeMap<String, &V> = map.into_iter().map(|(key, value)| (key.to_string(), value)).collect();
+    keyed.serialize(serializer)
+}
+
+pub fn deserialize<'de, M, V, D>(deserializer: D) -> Result<M, D::Error>
+where
+    M: FromIterator<(Pubkey, V)>,
+    V: Deserialize<'de>,
+    D: Deserializer<'de>,
+{
+    BTreeMap::<String, V>::deserialize(deserializer)?
+        .into_iter()
+        .map(|(key, value)| {
+            Pubkey::from_str(&key)
+                .map(|key| (key, value))
+                .map_err(|e| D::Error::custom(format!("Invalid address '{}': {}", key, e)))
+        })
+        .collect()
+}
@@ -0,0 +1,653 @@
+//! Minimal QR code encoder with PNG output, enough for Solana Pay links
+//!
+//! Byte-mode segments at error correction level M, versions 1-40. Follows the ISO/IEC 18004
+//! construction: data codewords, Reed-Solomon blocks, interleaving, function patterns, and the
+//! mask with the lowest penalty.
+
+use anyhow::{anyhow, Result};
+use flate2::{write::ZlibEncoder, Compression, Crc};
+use std::io::Write;
+
+/// Error correction codewords per block at level M, indexed by version
+const ECC_CODEWORDS_PER_BLOCK: [usize; 41] = [
+    0, 10, 16, 26, 18, 24, 16, 18, 22, 22, 26, 30, 22, 22, 24, 24, 28, 28, 26, 26, 26, 26, 28, 28, 28, 28, 28,
+    28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28,
+];
+
+/// Error correction blocks at level M, indexed by version
+const NUM_ERROR_CORRECTION_BLOCKS: [usize; 41] = [
+    0, 1, 1, 1, 2, 2, 4, 4, 4, 5, 5, 5, 8, 9, 9, 10, 10, 11, 13, 14, 16, 17, 17, 18, 20, 21, 23, 25, 26, 28, 29,
+    31, 33, 35, 37, 38, 40, 43, 45, 47, 49,
+];
+
+/// Format-information bits for level M
+const ECC_FORMAT_BITS: u32 = 0;
+
+/// A square grid of dark (`true`) and light modules
+#[derive(Debug, Clone)]
+pub struct QrCode {
+    size: usize,
+    modules: Vec<bool>,
+    is_function: Vec<bool>,
+}
+
+impl QrCode {
+    /// Encode `data` as bytes in the smallest version that fits
+    pub fn encode(data: &[u8]) -> Result<Self> {
+        let version = (1..=40)
+            .find(|&version| {
+                let count_bits = if version <= 9 { 8 } else { 16 };
+                4 + count_bits + data.len() * 8 <= num_data_codewords(version) * 8
+            })
+            .ok_or_else(|| anyhow!("{} bytes do not fit in a QR code", data.len()))?;
+
+        // Mode indicator, character count, data, terminator, then pad to capacity
+        let count_bits = if version <= 9 { 8 } else { 16 };
+        let capacity_bits = num_data_codewords(version) * 8;
+        let mut bits = BitBuffer::default();
+        bits.append(0b0100, 4);
+        bits.append(data.len() as u32, count_bits);
+        for &byte in data {
+            bits.append(byte as u32, 8);
+        }
+        bits.append(0, (capacity_bits - bits.len()).min(4));
+        bits.append(0, (8 - bits.len() % 8) % 8);
+        for pad in [0xEC, 0x11].iter().cycle() {
+            if bits.len() >= capacity_bits {
+                break;
+            }
+            bits.append(*pad, 8);
+        }
+        let codewords = interleave_with_ecc(version, &bits.to_bytes());
+
+        let size = version * 4 + 17;
+        let mut qr = QrCode {
+            size,
+            modules: vec![false; size * size],
+            is_function: vec![false; size * size],
+        };
+        qr.draw_function_patterns(version);
+        qr.draw_codewords(&codewords);
+
+        let mut best = None;
+        for mask in 0..8u8 {
+            qr.apply_mask(mask);
+            qr.draw_format_bits(mask);
+            let penalty = qr.penalty();
+            if best.is_none_or(|(_, lowest)| penalty < lowest) {
+                best = Some((mask, penalty));
+            }
+            qr.apply_mask(mask); // XOR again to undo
+        }
+        let (mask, _) = best.expect("eight masks were scored");
+        qr.apply_mask(mask);
+        qr.draw_format_bits(mask);
+        Ok(qr)
+    }
+
+    /// Modules per side, excluding the quiet zone
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Whether the module at column `x`, row `y` is dark
+    pub fn module(&self, x: usize, y: usize) -> bool {
+        self.modules[y * self.size + x]
+    }
+
+    /// Grayscale PNG with `scale` pixels per module and a 4-module quiet zone
+    pub fn to_png(&self, scale: usize) -> Result<Vec<u8>> {
+        let scale = scale.max(1);
+        let border = 4;
+        let width = (self.size + 2 * border) * scale;
+
+        let mut raw = Vec::with_capacity((width + 1) * width);
+        for py in 0..width {
+            raw.push(0); // filter: none
+            let y = (py / scale).checked_sub(border).filter(|y| *y < self.size);
+            for px in 0..width {
+                let x = (px / scale).checked_sub(border).filter(|x| *x < self.size);
+                let dark = matches!((x, y), (Some(x), Some(y)) if self.module(x, y));
+                raw.push(if dark { 0 } else { 255 });
+            }
+        }
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&raw)?;
+        let idat = encoder.finish()?;
+
+        let mut ihdr = Vec::with_capacity(13);
+        ihdr.extend_from_slice(&(width as u32).to_be_bytes());
+        ihdr.extend_from_slice(&(width as u32).to_be_bytes());
+        ihdr.extend_from_slice(&[8, 0, 0, 0, 0]); // 8-bit grayscale, deflate, no interlace
+
+        let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+        write_chunk(&mut png, b"IHDR", &ihdr);
+        write_chunk(&mut png, b"IDAT", &idat);
+        write_chunk(&mut png, b"IEND", &[]);
+        Ok(png)
+    }
+
+    fn set_function(&mut self, x: usize, y: usize, dark: bool) {
+        self.modules[y * self.size + x] = dark;
+        self.is_function[y * self.size + x] = true;
+    }
+
+    fn draw_function_patterns(&mut self, version: usize) {
+        let size = self.size;
+        for i in 0..size {
+            self.set_function(6, i, i % 2 == 0);
+            self.set_function(i, 6, i % 2 == 0);
+        }
+
+        for (cx, cy) in [(3, 3), (size - 4, 3), (3, size - 4)] {
+            for dy in -4i32..=4 {
+                for dx in -4i32..=4 {
+                    let (x, y) = (cx as i32 + dx, cy as i32 + dy);
+                    if (0..size as i32).contains(&x) && (0..size as i32).contains(&y) {
+                        let distance = dx.abs().max(dy.abs());
+                        self.set_function(x as usize, y as usize, distance != 2 && distance != 4);
+                    }
+                }
+            }
+        }
+
+        let positions = alignment_positions(version);
+        let last = positions.len().saturating_sub(1);
+        for (i, &cx) in positions.iter().enumerate() {
+            for (j, &cy) in positions.iter().enumerate() {
+                // Skip the three corners taken by finder patterns
+                if (i == 0 && (j == 0 || j == last)) || (i == last && j == 0) {
+                    continue;
+                }
+                for dy in -2i32..=2 {
+                    for dx in -2i32..=2 {
+                        let dark = dx.abs().max(dy.abs()) != 1;
+                        self.set_function((cx as i32 + dx) as usize, (cy as i32 + dy) as usize, dark);
+                    }
+                }
+            }
+        }
+
+        // Reserve the format areas; real bits are drawn once the mask is chosen
+        self.draw_format_bits(0);
+
+        if version >= 7 {
+            let mut remainder = version as u32;
+            for _ in 0..12 {
+                remainder = (remainder << 1) ^ ((remainder >> 11) * 0x1F25);
+            }
+            let bits = (version as u32) << 12 | remainder;
+            for i in 0..18 {
+                let dark = (bits >> i) & 1 != 0;
+                let a = size - 11 + i % 3;
+                let b = i / 3;
+                self.set_function(a, b, dark);
+                self.set_function(b, a, dark);
+            }
+        }
+    }
+
+    fn draw_format_bits(&mut self, mask: u8) {
+        let data = ECC_FORMAT_BITS << 3 | mask as u32;
+        let mut remainder = data;
+        for _ in 0..10 {
+            remainder = (remainder << 1) ^ ((remainder >> 9) * 0x537);
+        }
+        let bits = (data << 10 | remainder) ^ 0x5412;
+        let bit = |i: usize| (bits >> i) & 1 != 0;
+        let size = self.size;
+
+        for i in 0..=5 {
+            self.set_function(8, i, bit(i));
+        }
+        self.set_function(8, 7, bit(6));
+        self.set_function(8, 8, bit(7));
+        self.set_function(7, 8, bit(8));
+        for i in 9..15 {
+            self.set_function(14 - i, 8, bit(i));
+        }
+
+        for i in 0..8 {
+            self.set_function(size - 1 - i, 8, bit(i));
+        }
+        for i in 8..15 {
+            self.set_function(8, size - 15 + i, bit(i));
+        }
+        self.set_function(8, size - 8, true);
+    }
+
+    /// Fill non-function modules in the zigzag order, two columns at a time from the right
+    fn draw_codewords(&mut self, codewords: &[u8]) {
+        let size = self.size;
+        let total_bits = codewords.len() * 8;
+        let mut i = 0;
+        let mut right = size as i32 - 1;
+        while right >= 1 {
+            if right == 6 {
+                right = 5;
+            }
+            for vertical in 0..size {
+                for j in 0..2 {
+                    let x = (right - j) as usize;
+                    let upward = (right + 1) & 2 == 0;
+                    let y = if upward { size - 1 - vertical } else { vertical };
+                    if !self.is_function[y * size + x] && i < total_bits {
+                        self.modules[y * size + x] = (codewords[i >> 3] >> (7 - (i & 7))) & 1 != 0;
+                        i += 1;
+                    }
+                }
+            }
+            right -= 2;
+        }
+    }
+
+    fn apply_mask(&mut self, mask: u8) {
+        for y in 0..self.size {
+            for x in 0..self.size {
+                let invert = match mask {
+                    0 => (x + y) % 2 == 0,
+                    1 => y % 2 == 0,
+                    2 => x % 3 == 0,
+                    3 => (x + y) % 3 == 0,
+                    4 => (x / 3 + y / 2) % 2 == 0,
+                    5 => x * y % 2 + x * y % 3 == 0,
+                    6 => (x * y % 2 + x * y % 3) % 2 == 0,
+                    _ => ((x + y) % 2 + x * y % 3) % 2 == 0,
+                };
+                let index = y * self.size + x;
+                if invert && !self.is_function[index] {
+                    self.modules[index] = !self.modules[index];
+                }
+            }
+        }
+    }
+
+    /// Mask score from long runs, 2x2 blocks, finder-like patterns, and dark/light balance
+    fn penalty(&self) -> usize {
+        let size = self.size;
+        let mut penalty = 0;
+
+        for transpose in [false, true] {
+            for a in 0..size {
+                let line: Vec<bool> = (0..size)
+                    .map(|b| if transpose { self.module(a, b) } else { self.module(b, a) })
+                    .collect();
+
+                let mut run = 1;
+                for b in 1..size {
+                    if line[b] == line[b - 1] {
+                        run += 1;
+                    } else {
+                        if run >= 5 {
+                            penalty += run - 2;
+                        }
+                        run = 1;
+                    }
+                }
+                if run >= 5 {
+                    penalty += run - 2;
+                }
+
+                // 1:1:3:1:1 dark-light pattern with four light modules on one side
+                for b in 0..size.saturating_sub(10) {
+                    let window = &line[b..b + 11];
+                    let core = [true, false, true, true, true, false, true];
+                    let light = |range: std::ops::Range<usize>| window[range].iter().all(|dark| !dark);
+                    if (window[4..].iter().eq(core.iter()) && light(0..4))
+                        || (window[..7].iter().eq(core.iter()) && light(7..11))
+                    {
+                        penalty += 40;
+                    }
+                }
+            }
+        }
+
+        for y in 0..size - 1 {
+            for x in 0..size - 1 {
+                let dark = self.module(x, y);
+                if dark == self.module(x + 1, y) && dark == self.module(x, y + 1) && dark == self.module(x + 1, y + 1) {
+                    penalty += 3;
+                }
+            }
+        }
+
+        let dark = self.modules.iter().filter(|dark| **dark).count();
+        let total = size * size;
+        // Smallest k with (45 - 5k)% <= dark share <= (55 + 5k)%
+        let k = (dark * 20).abs_diff(total * 10).div_ceil(total).saturating_sub(1);
+        penalty + k * 10
+    }
+}
+
+/// Bits accumulated most significant first
+#[derive(Default)]
+struct BitBuffer(Vec<bool>);
+
+impl BitBuffer {
+    fn append(&mut self, value: u32, count: usize) {
+        for i in (0..count).rev() {
+            self.0.push((value >> i) & 1 != 0);
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        self.0
+            .chunks(8)
+            .map(|chunk| chunk.iter().fold(0u8, |byte, bit| byte << 1 | *bit as u8))
+            .collect()
+    }
+}
+
+/// Modules available for data and error correction once function patterns are placed
+fn num_raw_data_modules(version: usize) -> usize {
+    let mut modules = (16 * version + 128) * version + 64;
+    if version >= 2 {
+        let num_align = version / 7 + 2;
+        modules -= (25 * num_align - 10) * num_align - 55;
+        if version >= 7 {
+            modules -= 36;
+        }
+    }
+    modules
+}
+
+fn num_data_codewords(version: usize) -> usize {
+    num_raw_data_modules(version) / 8 - ECC_CODEWORDS_PER_BLOCK[version] * NUM_ERROR_CORRECTION_BLOCKS[version]
+}
+
+fn alignment_positions(version: usize) -> Vec<usize> {
+    if version == 1 {
+        return Vec::new();
+    }
+    let num_align = version / 7 + 2;
+    let step = if version == 32 {
+        26
+    } else {
+        (version * 4 + num_align * 2 + 1) / (num_align * 2 - 2) * 2
+    };
+    let size = version * 4 + 17;
+    let mut positions = vec![6];
+    let mut position = size - 7;
+    for _ in 0..num_align - 1 {
+        positions.insert(1, position);
+        position -= step;
+    }
+    positions
+}
+
+/// Split data into blocks, append each block's Reed-Solomon codewords, and interleave
+fn interleave_with_ecc(version: usize, data: &[u8]) -> Vec<u8> {
+    let num_blocks = NUM_ERROR_CORRECTION_BLOCKS[version];
+    let ecc_len = ECC_CODEWORDS_PER_BLOCK[version];
+    let raw_codewords = num_raw_data_modules(version) / 8;
+    let num_short_blocks = num_blocks - raw_codewords % num_blocks;
+    let short_block_len = raw_codewords / num_blocks;
+    let divisor = reed_solomon_divisor(ecc_len);
+
+    let mut blocks = Vec::with_capacity(num_blocks);
+    let mut offset = 0;
+    for i in 0..num_blocks {
+        let data_len = short_block_len - ecc_len + usize::from(i >= num_short_blocks);
+        let mut block = data[offset..offset + data_len].to_vec();
+        offset += data_len;
+        let ecc = reed_solomon_remainder(&block, &divisor);
+        if i < num_short_blocks {
+            block.push(0); // placeholder so every block has the same length
+        }
+        block.extend(ecc);
+        blocks.push(block);
+    }
+
+    let mut result = Vec::with_capacity(raw_codewords);
+    for i in 0..blocks[0].len() {
+        for (j, block) in blocks.iter().enumerate() {
+            if i != short_block_len - ecc_len || j >= num_short_blocks {
+                result.push(block[i]);
+            }
+        }
+    }
+    result
+}
+
+fn reed_solomon_divisor(degree: usize) -> Vec<u8> {
+    let mut result = vec![0u8; degree];
+    result[degree - 1] = 1;
+    let mut root = 1u8;
+    for _ in 0..degree {
+        for j in 0..degree {
+            result[j] = gf_multiply(result[j], root);
+            if j + 1 < degree {
+                result[j] ^= result[j + 1];
+            }
+        }
+        root = gf_multiply(root, 0x02);
+    }
+    result
+}
+
+fn reed_solomon_remainder(data: &[u8], divisor: &[u8]) -> Vec<u8> {
+    let mut result = vec![0u8; divisor.len()];
+    for &byte in data {
+        let factor = byte ^ result.remove(0);
+        result.push(0);
+        for (value, &coefficient) in result.iter_mut().zip(divisor) {
+            *value ^= gf_multiply(coefficient, factor);
+        }
+    }
+    result
+}
+
+/// Multiplication in GF(2^8) modulo x^8 + x^4 + x^3 + x^2 + 1
+fn gf_multiply(x: u8, y: u8) -> u8 {
+    let mut z: u32 = 0;
+    for i in (0..8).rev() {
+        z = (z << 1) ^ ((z >> 7) * 0x11D);
+        z ^= ((y as u32 >> i) & 1) * x as u32;
+    }
+    z as u8
+}
+
+fn write_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    let mut crc = Crc::new();
+    crc.update(kind);
+    crc.update(data);
+    png.extend_from_slice(&crc.sum().to_be_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Amount, Currency};
+    use crate::solana_pay::TransferRequest;
+    use flate2::read::ZlibDecoder;
+    use solana_sdk::pubkey::Pubkey;
+    use std::io::Read;
+
+    /// Read the bytes back out of `qr` the way a scanner would, checking every block's
+    /// error correction on the way
+    fn decode(qr: &QrCode) -> Vec<u8> {
+        let size = qr.size();
+        let version = (size - 17) / 4;
+
+        // First copy of the format information, around the top-left finder
+        let mut positions: Vec<(usize, usize)> = (0..=5).map(|i| (8, i)).collect();
+        positions.extend([(8, 7), (8, 8), (7, 8)]);
+        positions.extend((9..15).map(|i| (14 - i, 8)));
+        let format = positions
+            .iter()
+            .enumerate()
+            .fold(0u32, |bits, (i, &(x, y))| bits | (qr.module(x, y) as u32) << i)
+            ^ 0x5412;
+        assert_eq!(format >> 13, ECC_FORMAT_BITS, "error correction level is not M");
+        let mask = (format >> 10 & 7) as u8;
+
+        // Which modules carry data, from the function patterns of a blank code of this version
+        let mut blank = QrCode { size, modules: vec![false; size * size], is_function: vec![false; size * size] };
+        blank.draw_function_patterns(version);
+        let mut unmasked = QrCode { modules: qr.modules.clone(), ..blank.clone() };
+        unmasked.apply_mask(mask);
+
+        let mut bits = Vec::new();
+        let mut right = size as i32 - 1;
+        while right >= 1 {
+            if right == 6 {
+                right = 5;
+            }
+            for vertical in 0..size {
+                for j in 0..2 {
+                    let x = (right - j) as usize;
+                    let y = if (right + 1) & 2 == 0 { size - 1 - vertical } else { vertical };
+                    if !blank.is_function[y * size + x] {
+                        bits.push(unmasked.module(x, y));
+                    }
+                }
+            }
+            right -= 2;
+        }
+        let codewords: Vec<u8> =
+            bits.chunks_exact(8).map(|byte| byte.iter().fold(0u8, |b, bit| b << 1 | *bit as u8)).collect();
+
+        // Undo the interleaving, then check each block against its Reed-Solomon codewords
+        let num_blocks = NUM_ERROR_CORRECTION_BLOCKS[version];
+        let ecc_len = ECC_CODEWORDS_PER_BLOCK[version];
+        let raw_codewords = num_raw_data_modules(version) / 8;
+        let num_short_blocks = num_blocks - raw_codewords % num_blocks;
+        let short_data_len = raw_codewords / num_blocks - ecc_len;
+        let data_len = |block: usize| short_data_len + usize::from(block >= num_short_blocks);
+        let mut blocks = vec![Vec::new(); num_blocks];
+        let mut next = codewords.iter().copied();
+        for i in 0..=short_data_len {
+            for (block, data) in blocks.iter_mut().enumerate() {
+                if i < data_len(block) {
+                    data.push(next.next().unwrap());
+                }
+            }
+        }
+        let mut ecc = vec![Vec::new(); num_blocks];
+        for _ in 0..ecc_len {
+            for block in ecc.iter_mut() {
+                block.push(next.next().unwrap());
+            }
+        }
+        let divisor = reed_solomon_divisor(ecc_len);
+        for (data, ecc) in blocks.iter().zip(&ecc) {
+            assert_eq!(&reed_solomon_remainder(data, &divisor), ecc);
+        }
+
+        // One byte-mode segment
+        let data: Vec<u8> = blocks.concat();
+        let bit = |i: usize| (data[i / 8] >> (7 - i % 8)) & 1;
+        let read = |from: usize, count: usize| (from..from + count).fold(0usize, |value, i| value << 1 | bit(i) as usize);
+        assert_eq!(read(0, 4), 0b0100, "not a byte-mode segment");
+        let count_bits = if version <= 9 { 8 } else { 16 };
+        let len = read(4, count_bits);
+        (0..len).map(|i| read(4 + count_bits + i * 8, 8) as u8).collect()
+    }
+
+    fn transfer_request() -> TransferRequest {
+        let mint = Pubkey::new_unique();
+        let mut request = TransferRequest::new(Pubkey::new_unique());
+        request.amount = Some(Amount::parse("12.5", &Currency::spl(mint, 6)).unwrap());
+        request.spl_token = Some(mint);
+        request.references = vec![Pubkey::new_unique(), Pubkey::new_unique()];
+        request.label = Some("Corner Café".to_string());
+        request.message = Some("Order #42 & a coffee".to_string());
+        request
+    }
+
+    #[test]
+    fn a_solana_pay_url_reads_back_from_its_qr_code() {
+        let request = transfer_request();
+        let url = request.to_url();
+        let qr = QrCode::encode(url.as_bytes()).unwrap();
+        assert_eq!(decode(&qr), url.as_bytes());
+
+        let (recipient, query) = url.strip_prefix("solana:").unwrap().split_once('?').unwrap();
+        assert_eq!(recipient, request.recipient.to_string());
+        assert!(!query.contains('+'), "spaces must be %20: {}", query);
+        let params: Vec<(String, String)> = form_urlencoded::parse(query.as_bytes()).into_owned().collect();
+        let values = |key: &str| params.iter().filter(|(k, _)| k == key).map(|(_, v)| v.clone()).collect::<Vec<_>>();
+        assert_eq!(values("amount"), vec!["12.5"]);
+        assert_eq!(values("spl-token"), vec![request.spl_token.unwrap().to_string()]);
+        assert_eq!(values("reference"), request.references.iter().map(Pubkey::to_string).collect::<Vec<_>>());
+        assert_eq!(values("label"), vec!["Corner Café"]);
+        assert_eq!(values("message"), vec!["Order #42 & a coffee"]);
+        assert!(values("memo").is_empty());
+    }
+
+    #[test]
+    fn a_bare_recipient_is_just_the_address() {
+        let recipient = Pubkey::new_unique();
+        let url = TransferRequest::new(recipient).to_url();
+        assert_eq!(url, format!("solana:{}", recipient));
+        assert_eq!(decode(&QrCode::encode(url.as_bytes()).unwrap()), url.as_bytes());
+    }
+
+    #[test]
+    fn every_length_picks_the_smallest_version_that_fits_and_decodes() {
+        // Across the 8-bit and 16-bit character count boundary and several block layouts
+        for len in [0, 1, 14, 15, 16, 100, 229, 230, 231, 600, 2331] {
+            let data: Vec<u8> = (0..len).map(|i| (i * 7 + 3) as u8).collect();
+            let qr = QrCode::encode(&data).unwrap();
+            let version = (qr.size() - 17) / 4;
+            let count_bits = |version: usize| if version <= 9 { 8 } else { 16 };
+            assert!(4 + count_bits(version) + len * 8 <= num_data_codewords(version) * 8, "{} bytes", len);
+            if version > 1 {
+                assert!(4 + count_bits(version - 1) + len * 8 > num_data_codewords(version - 1) * 8, "{} bytes", len);
+            }
+            assert_eq!(decode(&qr), data, "{} bytes in version {}", len, version);
+        }
+        assert!(QrCode::encode(&[0; 2332]).is_err());
+    }
+
+    #[test]
+    fn the_png_is_a_valid_grayscale_image_of_the_code() {
+        let qr = QrCode::encode(transfer_request().to_url().as_bytes()).unwrap();
+        let scale = 3;
+        let png = qr.to_png(scale).unwrap();
+        assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+
+        // Walk the chunks, checking each CRC
+        let mut chunks = Vec::new();
+        let mut rest = &png[8..];
+        while !rest.is_empty() {
+            let len = u32::from_be_bytes(rest[..4].try_into().unwrap()) as usize;
+            let (kind, data) = (&rest[4..8], &rest[8..8 + len]);
+            let mut crc = Crc::new();
+            crc.update(kind);
+            crc.update(data);
+            assert_eq!(rest[8 + len..12 + len], crc.sum().to_be_bytes());
+            chunks.push((kind.to_vec(), data.to_vec()));
+            rest = &rest[12 + len..];
+        }
+        let kinds: Vec<&[u8]> = chunks.iter().map(|(kind, _)| kind.as_slice()).collect();
+        assert_eq!(kinds, vec![b"IHDR".as_slice(), b"IDAT", b"IEND"]);
+
+        let width = (qr.size() + 8) * scale;
+        let ihdr = &chunks[0].1;
+        assert_eq!(u32::from_be_bytes(ihdr[..4].try_into().unwrap()) as usize, width);
+        assert_eq!(u32::from_be_bytes(ihdr[4..8].try_into().unwrap()) as usize, width);
+        let mut raw = Vec::new();
+        ZlibDecoder::new(chunks[1].1.as_slice()).read_to_end(&mut raw).unwrap();
+        assert_eq!(raw.len(), (width + 1) * width);
+
+        // Every pixel matches its module, with a light quiet zone around the code
+        for py in 0..width {
+            let row = &raw[py * (width + 1)..(py + 1) * (width + 1)];
+            assert_eq!(row[0], 0, "filter type");
+            for px in 0..width {
+                let (x, y) = ((px / scale) as i64 - 4, (py / scale) as i64 - 4);
+                let inside = (0..qr.size() as i64).contains(&x) && (0..qr.size() as i64).contains(&y);
+                let dark = inside && qr.module(x as usize, y as usize);
+                assert_eq!(row[px + 1], if dark { 0 } else { 255 }, "pixel {},{}", px, py);
+            }
+        }
+    }
+}
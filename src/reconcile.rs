@@ -0,0 +1,476 @@
+use crate::history::{HistoryCompleteness, HistoryFilter};
+use crate::history::{transfer_records, MAX_SYNC_PAGES, SIGNATURE_PAGE_LIMIT};
+use crate::telemetry::readable;
+use crate::{Currency, FinternetClient, TransactionRecord};
+use anyhow::{anyhow, Result};
+use chrono::{NaiveDate, Utc};
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use solana_sdk::{pubkey::Pubkey, signature::Signature};
+use solana_transaction_status::{EncodedConfirmedTransactionWithStatusMeta, EncodedTransaction, UiMessage};
+use std::collections::HashMap;
+use std::ops::Range;
+use std::str::FromStr;
+
+/// A payment the billing system expects to receive
+///
+/// Serializes with addresses as base58 and the due date as `YYYY-MM-DD`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "ExpectedRow", into = "ExpectedRow")]
+pub struct ExpectedPayment {
+    /// The billing system's own id, e.g. an invoice number
+    pub id: String,
+    pub payer: Option<Pubkey>,
+    pub mint: Pubkey,
+    /// Base units of `mint`
+    pub amount: u64,
+    /// Solana Pay reference the payment should carry
+    pub reference: Option<Pubkey>,
+    /// Text the payment's memo should contain as a whole token, so `INV-1` is not found in
+    /// `INV-10`
+    pub memo: Option<String>,
+    pub due_date: Option<NaiveDate>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ExpectedRow {
+    id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    payer: Option<String>,
+    mint: String,
+    amount: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    reference: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    memo: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    due_date: Option<NaiveDate>,
+}
+
+impl From<ExpectedPayment> for ExpectedRow {
+    fn from(expected: ExpectedPayment) -> Self {
+        Self {
+            id: expected.id,
+            payer: expected.payer.map(|payer| payer.to_string()),
+            mint: expected.mint.to_string(),
+            amount: expected.amount,
+            reference: expected.reference.map(|reference| reference.to_string()),
+            memo: expected.memo,
+            due_date: expected.due_date,
+        }
+    }
+}
+
+impl TryFrom<ExpectedRow> for ExpectedPayment {
+    type Error = String;
+
+    fn try_from(row: ExpectedRow) -> Result<Self, String> {
+        let address = |value: &str| Pubkey::from_str(value).map_err(|_| format!("invalid address '{}'", value));
+        Ok(Self {
+            id: row.id,
+            payer: row.payer.as_deref().map(address).transpose()?,
+            mint: address(&row.mint)?,
+            amount: row.amount,
+            reference: row.reference.as_deref().map(address).transpose()?,
+            memo: row.memo.filter(|memo| !memo.is_empty()),
+            due_date: row.due_date,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReconciliationStatus {
+    Matched,
+    PartiallyPaid,
+    Overpaid,
+    Unmatched,
+}
+
+/// How a payment was tied to its expectation, strongest first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MatchTier {
+    /// The transaction carried the expected Solana Pay reference
+    Reference,
+    /// The memo contained the expected text, not as part of a longer token
+    Memo,
+    /// Same mint from the expected payer, preferring the exact amount; a guess, worth a look
+    AmountAndPayer,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReconciledPayment {
+    pub expected: ExpectedPayment,
+    pub status: ReconciliationStatus,
+    pub tier: Option<MatchTier>,
+    /// Base units received across every matched payment
+    pub received: u64,
+    pub signatures: Vec<Signature>,
+    /// Past its due date and not fully paid
+    pub overdue: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReconciliationReport {
+    pub owner: Pubkey,
+    /// Unix seconds, `to` exclusive
+    pub from: u64,
+    pub to: u64,
+    pub generated_at: u64,
+    pub items: Vec<ReconciledPayment>,
+    /// Inbound payments in the window that matched no expectation
+    pub unexpected: Vec<TransactionRecord>,
+    /// False when the scan did not reach `from`: it stopped at its page budget, or the node's
+    /// history of the owner ends inside the window
+    pub complete: bool,
+    /// `TruncatedAt` when the node serves nothing older within the window, though the account
+    /// existed before; payments older than that slot cannot be matched
+    #[serde(default)]
+    pub completeness: HistoryCompleteness,
+}
+
+impl ReconciliationReport {
+    pub fn count(&self, status: ReconciliationStatus) -> usize {
+        self.items.iter().filter(|item| item.status == status).count()
+    }
+}
+
+/// Unix-second window covering the whole of `from` through the whole of `to`, in UTC
+pub fn day_window(from: NaiveDate, to: NaiveDate) -> Range<u64> {
+    let secs = |date: NaiveDate| date.and_hms_opt(0, 0, 0).unwrap_or_default().and_utc().timestamp().max(0) as u64;
+    secs(from)..secs(to.succ_opt().unwrap_or(to))
+}
+
+/// An incoming transfer and the accounts its transaction touched
+struct Inbound {
+    record: TransactionRecord,
+    accounts: Vec<Pubkey>,
+    claimed: bool,
+}
+
+impl FinternetClient {
+    /// Match `expected` payments against what `owner` actually received during `window`
+    ///
+    /// Each expectation is tried by reference first, then by memo text, then by payer and
+    /// amount, and every tier runs across all expectations before the next one starts, so a
+    /// weak guess never takes a payment a stronger match would have claimed.
+    #[tracing::instrument(skip_all, fields(wallet = %readable(owner), expected = expected.len(), rpc = %self.rpc_endpoint()))]
+    pub async fn reconcile(
+        &self,
+        expected: Vec<ExpectedPayment>,
+        owner: &Pubkey,
+        window: Range<u64>,
+    ) -> Result<ReconciliationReport> {
+        let (mut inbound, completeness) = self.inbound_transfers(owner, &window).await?;
+        tracing::info!("Reconciling {} expectations against {} inbound transfers", expected.len(), inbound.len());
+
+        let mut matched: Vec<(Option<MatchTier>, Vec<usize>)> = vec![(None, Vec::new()); expected.len()];
+        for tier in [MatchTier::Reference, MatchTier::Memo, MatchTier::AmountAndPayer] {
+            for (index, expectation) in expected.iter().enumerate() {
+                if matched[index].0.is_some() {
+                    continue;
+                }
+                let claims = match_tier(tier, expectation, &inbound);
+                if !claims.is_empty() {
+                    for &claim in &claims {
+                        inbound[claim].claimed = true;
+                    }
+                    matched[index] = (Some(tier), claims);
+                }
+            }
+        }
+
+        let today = Utc::now().date_naive();
+        let items = expected
+            .into_iter()
+            .zip(matched)
+            .map(|(expected, (tier, claims))| {
+                let received: u64 = claims.iter().map(|&claim| inbound[claim].record.amount).sum();
+                let status = match (claims.is_empty(), received.cmp(&expected.amount)) {
+                    (true, _) => ReconciliationStatus::Unmatched,
+                    (false, std::cmp::Ordering::Less) => ReconciliationStatus::PartiallyPaid,
+                    (false, std::cmp::Ordering::Equal) => ReconciliationStatus::Matched,
+                    (false, std::cmp::Ordering::Greater) => ReconciliationStatus::Overpaid,
+                };
+                let unpaid = matches!(status, ReconciliationStatus::Unmatched | ReconciliationStatus::PartiallyPaid);
+                ReconciledPayment {
+                    overdue: unpaid && expected.due_date.map(|due| due < today).unwrap_or(false),
+                    signatures: claims.iter().map(|&claim| inbound[claim].record.signature).collect(),
+                    expected,
+                    status,
+                    tier,
+                    received,
+                }
+            })
+            .collect();
+
+        Ok(ReconciliationReport {
+            owner: *owner,
+            from: window.start,
+            to: window.end,
+            generated_at: Utc::now().timestamp().max(0) as u64,
+            items,
+            unexpected: inbound
+                .into_iter()
+                .filter(|transfer| !transfer.claimed)
+                .map(|transfer| transfer.record)
+                .collect(),
+            complete: completeness.is_complete(),
+            completeness,
+        })
+    }
+
+    /// Every transfer into `owner` within `window`, oldest first, and whether the scan covered
+    /// the whole window
+    async fn inbound_transfers(&self, owner: &Pubkey, window: &Range<u64>) -> Result<(Vec<Inbound>, HistoryCompleteness)> {
+        let history = self.iter_history(owner, HistoryFilter { time_range: Some(window.clone()), ..HistoryFilter::default() });
+        futures::pin_mut!(history);
+        let budget = MAX_SYNC_PAGES * SIGNATURE_PAGE_LIMIT;
+        let mut scanned = 0;
+        // Unless the node's history runs out inside the window, the scan ends past its start
+        let mut completeness = HistoryCompleteness::Complete;
+        let mut inbound = Vec::new();
+        while let Some(entry) = history.next().await {
+            let entry = entry?;
+            if scanned == budget {
+                completeness = HistoryCompleteness::Unknown;
+                break;
+            }
+            scanned += 1;
+            if entry.oldest_available {
+                completeness = self.history_completeness(owner, &entry);
+            }
+            let transaction = match entry.transaction {
+                None => continue,
+                Some(Ok(transaction)) => transaction,
+                Some(Err(e)) => return Err(anyhow!("Transaction {} could not be fetched: {}", entry.signature, e)),
+            };
+            let accounts = account_keys(&transaction);
+            inbound.extend(
+                transfer_records(owner, entry.signature, &transaction)
+                    .into_iter()
+                    .filter(|record| record.to == *owner)
+                    .map(|record| Inbound {
+                        record,
+                        accounts: accounts.clone(),
+                        claimed: false,
+                    }),
+            );
+        }
+        match completeness {
+            HistoryCompleteness::Complete => {}
+            HistoryCompleteness::TruncatedAt(slot) => {
+                tracing::warn!("History of {} is truncated at slot {}, inside the reconciliation window", owner, slot)
+            }
+            HistoryCompleteness::Unknown => {
+                tracing::warn!("Reconciliation scan for {} stopped before reaching the window start", owner)
+            }
+        }
+
+        // Matched oldest first
+        inbound.reverse();
+        Ok((inbound, completeness))
+    }
+
+    /// Read a billing export as `ExpectedPayment`s
+    ///
+    /// The CSV needs a header row with `mint` and `amount` columns; `id`, `payer`, `reference`,
+    /// `memo`, and `due_date` (`YYYY-MM-DD`) are optional. Amounts are in whole units, e.g.
+    /// `125.50`, converted with each mint's on-chain decimals.
+    pub fn expected_payments_from_csv(&self, csv: &str) -> Result<Vec<ExpectedPayment>> {
+        let mut lines = csv.lines().enumerate().filter(|(_, line)| !line.trim().is_empty());
+        let (_, header) = lines.next().ok_or_else(|| anyhow!("Expected payments CSV is empty"))?;
+        let header: Vec<String> = split_csv_line(header).into_iter().map(|h| h.trim().to_lowercase()).collect();
+        let column = |name: &str| header.iter().position(|h| h == name);
+        if column("mint").is_none() {
+            return Err(anyhow!("Expected payments CSV has no mint column"));
+        }
+        let amount_column = column("amount").ok_or_else(|| anyhow!("Expected payments CSV has no amount column"))?;
+
+        let mut decimals: HashMap<Pubkey, u8> = HashMap::new();
+        let mut expected = Vec::new();
+        for (line_index, line) in lines {
+            let line_number = line_index + 1;
+            let fields = split_csv_line(line);
+            let field = |index: Option<usize>| {
+                index
+                    .and_then(|index| fields.get(index))
+                    .map(|value| value.trim())
+                    .filter(|value| !value.is_empty())
+            };
+            let address = |name: &str| {
+                field(column(name))
+                    .map(|value| {
+                        Pubkey::from_str(value).map_err(|_| anyhow!("Line {}: invalid {} '{}'", line_number, name, value))
+                    })
+                    .transpose()
+            };
+
+            let mint = address("mint")?.ok_or_else(|| anyhow!("Line {}: missing mint", line_number))?;
+            let mint_decimals = match decimals.get(&mint) {
+                Some(known) => *known,
+                None => {
+                    let fetched = self.client.get_token_supply(&mint)?.decimals;
+                    decimals.insert(mint, fetched);
+                    fetched
+                }
+            };
+            let amount = field(Some(amount_column)).ok_or_else(|| anyhow!("Line {}: missing amount", line_number))?;
+            let amount = Currency::spl(mint, mint_decimals)
+                .parse_amount(amount)
+                .map_err(|e| anyhow!("Line {}: {}", line_number, e))?;
+            let due_date = field(column("due_date"))
+                .map(|value| {
+                    NaiveDate::parse_from_str(value, "%Y-%m-%d")
+                        .map_err(|_| anyhow!("Line {}: due_date '{}' is not YYYY-MM-DD", line_number, value))
+                })
+                .transpose()?;
+
+            expected.push(ExpectedPayment {
+                id: field(column("id")).map(str::to_string).unwrap_or_else(|| format!("line-{}", line_number)),
+                payer: address("payer")?,
+                mint,
+                amount,
+                reference: address("reference")?,
+                memo: field(column("memo")).map(str::to_string),
+                due_date,
+            });
+        }
+        Ok(expected)
+    }
+}
+
+/// Indices of the unclaimed transfers `tier` ties to `expected`; empty when it finds none
+fn match_tier(tier: MatchTier, expected: &ExpectedPayment, inbound: &[Inbound]) -> Vec<usize> {
+    let candidates = inbound
+        .iter()
+        .enumerate()
+        .filter(|(_, transfer)| !transfer.claimed && transfer.record.currency.mint() == Some(expected.mint));
+    match tier {
+        MatchTier::Reference => match expected.reference {
+            Some(reference) => candidates
+                .filter(|(_, transfer)| transfer.accounts.contains(&reference))
+                .map(|(index, _)| index)
+                .collect(),
+            None => Vec::new(),
+        },
+        MatchTier::Memo => match &expected.memo {
+            Some(memo) => candidates
+                .filter(|(_, transfer)| transfer.record.memo.as_deref().is_some_and(|m| contains_token(m, memo.trim())))
+                .map(|(index, _)| index)
+                .collect(),
+            None => Vec::new(),
+        },
+        MatchTier::AmountAndPayer => {
+            let candidates: Vec<(usize, &Inbound)> = candidates
+                .filter(|(_, transfer)| expected.payer.map(|payer| transfer.record.from == payer).unwrap_or(true))
+                .collect();
+            let exact: Vec<usize> = candidates
+                .iter()
+                .filter(|(_, transfer)| transfer.record.amount == expected.amount)
+                .map(|(index, _)| *index)
+                .collect();
+            match expected.payer {
+                // The oldest exact payment from the payer, else its oldest payment, paid short or over
+                Some(_) => exact
+                    .first()
+                    .or(candidates.first().map(|(index, _)| index))
+                    .map(|index| vec![*index])
+                    .unwrap_or_default(),
+                // Without a payer, an amount alone only counts when it is unambiguous
+                None if exact.len() == 1 => exact,
+                None => Vec::new(),
+            }
+        }
+    }
+}
+
+/// Whether `token` occurs in `text` with no letter, digit, `-`, or `_` right before or after it
+fn contains_token(text: &str, token: &str) -> bool {
+    let joins = |c: Option<char>| c.is_some_and(|c| c.is_alphanumeric() || c == '-' || c == '_');
+    !token.is_empty()
+        && text
+            .match_indices(token)
+            .any(|(start, _)| !joins(text[..start].chars().next_back()) && !joins(text[start + token.len()..].chars().next()))
+}
+
+fn account_keys(transaction: &EncodedConfirmedTransactionWithStatusMeta) -> Vec<Pubkey> {
+    let EncodedTransaction::Json(ui_transaction) = &transaction.transaction.transaction else {
+        return Vec::new();
+    };
+    let UiMessage::Raw(message) = &ui_transaction.message else {
+        return Vec::new();
+    };
+    message.account_keys.iter().filter_map(|key| Pubkey::from_str(key).ok()).collect()
+}
+
+/// Fields of one CSV line, honoring double quotes and `""` escapes
+pub(crate) fn split_csv_line(line: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(std::mem::take(&mut field)),
+            _ => field.push(c),
+        }
+    }
+    fields.push(field);
+    fields
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn inbound(mint: &Pubkey, memo: &str) -> Inbound {
+        Inbound {
+            record: TransactionRecord {
+                signature: Signature::new_unique(),
+                from: Pubkey::new_unique(),
+                to: Pubkey::new_unique(),
+                amount: 1_000_000,
+                currency: Currency::spl(*mint, 6),
+                timestamp: 0,
+                memo: Some(memo.to_string()),
+                fee: 0,
+                slot: 0,
+                memo_fields: HashMap::new(),
+            },
+            accounts: Vec::new(),
+            claimed: false,
+        }
+    }
+
+    #[test]
+    fn a_memo_matches_a_whole_invoice_number_and_not_a_longer_one() {
+        let mint = Pubkey::new_unique();
+        let expected = |memo: &str| ExpectedPayment {
+            id: memo.to_string(),
+            payer: None,
+            mint,
+            amount: 1_000_000,
+            reference: None,
+            memo: Some(memo.to_string()),
+            due_date: None,
+        };
+        let received = [
+            inbound(&mint, "INV-10"),
+            inbound(&mint, "Payment for INV-1."),
+            inbound(&mint, "INV-1-B"),
+            inbound(&mint, "xINV-1"),
+        ];
+
+        assert_eq!(match_tier(MatchTier::Memo, &expected("INV-1"), &received), vec![1]);
+        assert_eq!(match_tier(MatchTier::Memo, &expected("INV-10"), &received), vec![0]);
+        assert_eq!(match_tier(MatchTier::Memo, &expected(" INV-1-B "), &received), vec![2]);
+        assert!(match_tier(MatchTier::Memo, &expected("INV"), &received).is_empty());
+    }
+}
@@ -0,0 +1,455 @@
+//! Suppressing personal data that was written to the immutable ledger
+//!
+//! A holder cannot delete an identity registration or a memo from the chain. What they can do is
+//! write a `redact` entry naming the identity fields and memos readers must stop showing. The
+//! entry carries only field keys and signatures, never the data itself. Every SDK read path
+//! honors it: `get_registered_identity` (and so the identity index), statements, exports, and
+//! action log pages. Redacted values are dropped or replaced with `REDACTED`, and a `redacted`
+//! marker is set on what was changed.
+
+use crate::action_log::ActionLogPage;
+use crate::identity::{FinternetIdentity, REGISTER_IDENTITY_ACTION};
+use crate::ledger::LedgerEntry;
+use crate::telemetry::readable;
+use crate::{FinternetClient, TransactionRecord};
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use solana_sdk::{
+    pubkey::Pubkey,
+    signature::{Keypair, Signature},
+    signer::Signer,
+};
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+
+pub(crate) const REDACT_ACTION: &str = "redact";
+
+/// What a redacted value reads as wherever it cannot simply be left out
+pub const REDACTED: &str = "[redacted]";
+
+/// Field key that redacts an identity's display name rather than a metadata entry
+pub const DISPLAY_NAME_FIELD: &str = "display_name";
+
+/// Everything one holder has asked readers to suppress
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Redactions {
+    /// Metadata keys, or `display_name`
+    pub fields: HashSet<String>,
+    /// Transactions whose memo must not be shown
+    pub memos: HashSet<Signature>,
+}
+
+impl Redactions {
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty() && self.memos.is_empty()
+    }
+
+    /// Union of the `redact` entries `holder` signed among `entries`
+    pub(crate) fn from_entries(holder: &Pubkey, entries: &[LedgerEntry]) -> Self {
+        let mut redactions = Self::default();
+        let holder_str = holder.to_string();
+        // Anyone can write a memo naming this address, so only entries the holder signed count
+        for value in entries.iter().filter(|entry| entry.signer == Some(*holder)).filter_map(LedgerEntry::json) {
+            if value.get("action").and_then(|a| a.as_str()) != Some(REDACT_ACTION)
+                || value.get("pubkey").and_then(|p| p.as_str()) != Some(holder_str.as_str())
+            {
+                continue;
+            }
+            let strings = |key: &str| {
+                value
+                    .get(key)
+                    .and_then(|list| list.as_array())
+                    .map(|list| list.iter().filter_map(|item| item.as_str()).map(str::to_string).collect::<Vec<_>>())
+                    .unwrap_or_default()
+            };
+            redactions.fields.extend(strings("fields"));
+            redactions
+                .memos
+                .extend(strings("memos").iter().filter_map(|signature| Signature::from_str(signature).ok()));
+        }
+        redactions
+    }
+
+    /// Drop the redacted fields from `identity`, listing them in `redacted_fields`
+    pub fn apply_to_identity(&self, identity: &mut FinternetIdentity) {
+        for field in &self.fields {
+            let removed = if field == DISPLAY_NAME_FIELD {
+                identity.display_name.take().is_some()
+            } else {
+                identity.metadata.remove(field).is_some()
+            };
+            if removed && !identity.redacted_fields.contains(field) {
+                identity.redacted_fields.push(field.clone());
+            }
+        }
+        identity.redacted_fields.sort();
+    }
+
+    /// Replace the memo of every redacted transaction among `records` with `REDACTED`
+    pub fn apply_to_records(&self, records: &mut [TransactionRecord]) {
+        for record in records.iter_mut().filter(|record| self.memos.contains(&record.signature)) {
+            if record.memo.is_some() {
+                record.memo = Some(REDACTED.to_string());
+            }
+            record.memo_fields.clear();
+        }
+    }
+
+    /// Whether the memo of `signature` is redacted
+    pub fn hides_memo(&self, signature: &Signature) -> bool {
+        self.memos.contains(signature)
+    }
+}
+
+impl FinternetClient {
+    /// Ask every reader to stop showing `keys` of `wallet`'s registered identity
+    ///
+    /// Keys are metadata keys, or `display_name`. The registration itself stays on the chain;
+    /// registering a field again afterwards publishes it again.
+    pub async fn redact_identity_fields(&self, wallet: &Keypair, keys: &[&str]) -> Result<Signature> {
+        self.redact(wallet, keys, &[]).await
+    }
+
+    /// Ask every reader to stop showing the memos of `signatures` in `wallet`'s history
+    pub async fn redact_memos(&self, wallet: &Keypair, signatures: &[Signature]) -> Result<Signature> {
+        self.redact(wallet, &[], signatures).await
+    }
+
+    /// Everything `holder` has redacted so far
+    #[tracing::instrument(skip_all, fields(wallet = %readable(holder)))]
+    pub async fn get_redactions(&self, holder: &Pubkey) -> Result<Redactions> {
+        let entries = self.read_ledger_entries(holder, None).await?;
+        Ok(Redactions::from_entries(holder, &entries))
+    }
+
+    /// Mask what each entry's own wallet has redacted in a page of the action log
+    ///
+    /// Identity registrations lose their redacted fields and payments their redacted memos; each
+    /// entry changed gets `"redacted": true` in its parameters. Masked entries no longer match
+    /// their hashes, so verify the file on disk rather than a served page.
+    pub async fn redact_action_page(&self, page: &mut ActionLogPage) -> Result<()> {
+        let mut by_holder: HashMap<Pubkey, Redactions> = HashMap::new();
+        for entry in page.entries.iter_mut() {
+            let is_registration = entry.operation == REGISTER_IDENTITY_ACTION;
+            let holder_key = if is_registration { "pubkey" } else { "from" };
+            let Some(holder) = entry
+                .parameters
+                .get(holder_key)
+                .and_then(|holder| holder.as_str())
+                .and_then(|holder| Pubkey::from_str(holder).ok())
+            else {
+                continue;
+            };
+            let redactions = match by_holder.entry(holder) {
+                Entry::Occupied(known) => known.into_mut(),
+                Entry::Vacant(slot) => slot.insert(self.get_redactions(&holder).await?),
+            };
+            let Some(parameters) = entry.parameters.as_object_mut() else { continue };
+
+            let mut changed = false;
+            if is_registration {
+                for field in &redactions.fields {
+                    let target = if field == DISPLAY_NAME_FIELD {
+                        parameters.get_mut(DISPLAY_NAME_FIELD)
+                    } else {
+                        parameters.get_mut("metadata").and_then(|metadata| metadata.get_mut(field))
+                    };
+                    if let Some(value) = target {
+                        *value = serde_json::Value::String(REDACTED.to_string());
+                        changed = true;
+                    }
+                }
+            }
+            let memo_redacted = entry
+                .signature
+                .as_deref()
+                .and_then(|signature| Signature::from_str(signature).ok())
+                .is_some_and(|signature| redactions.hides_memo(&signature));
+            if memo_redacted {
+                if let Some(memo) = parameters.get_mut("memo").filter(|memo| !memo.is_null()) {
+                    *memo = serde_json::Value::String(REDACTED.to_string());
+                    changed = true;
+                }
+            }
+            if changed {
+                parameters.insert("redacted".to_string(), serde_json::Value::Bool(true));
+            }
+        }
+        Ok(())
+    }
+
+    /// Redact identity fields and memos in one ledger entry
+    #[tracing::instrument(skip_all, fields(wallet = %readable(&wallet.pubkey()), fields = fields.len(), memos = memos.len()))]
+    pub async fn redact(&self, wallet: &Keypair, fields: &[&str], memos: &[Signature]) -> Result<Signature> {
+        let parameters = serde_json::json!({
+            "pubkey": wallet.pubkey().to_string(),
+            "fields": fields,
+            "memos": memos.iter().map(Signature::to_string).collect::<Vec<_>>(),
+        });
+        let outcome = self.write_redaction_unlogged(wallet, fields, memos).await;
+        self.log_action("redact", parameters, None, outcome.as_ref(), Signature::to_string);
+        outcome
+    }
+
+    async fn write_redaction_unlogged(&self, wallet: &Keypair, fields: &[&str], memos: &[Signature]) -> Result<Signature> {
+        if fields.is_empty() && memos.is_empty() {
+            return Err(anyhow!("Name at least one identity field or memo to redact"));
+        }
+        if let Some(field) = fields.iter().find(|field| field.trim().is_empty()) {
+            return Err(anyhow!("Invalid field to redact: '{}'", field));
+        }
+        let entry = serde_json::json!({
+            "action": REDACT_ACTION,
+            "pubkey": wallet.pubkey().to_string(),
+            "fields": fields,
+            "memos": memos.iter().map(Signature::to_string).collect::<Vec<_>>(),
+            "timestamp": self.registration_timestamp().await?,
+        })
+        .to_string();
+        let compress = entry.len() > crate::chunking::MAX_SINGLE_ENTRY_LEN;
+        let signatures = self.write_ledger_entry_chunked(wallet, &entry, compress).await?;
+        let signature = signatures
+            .first()
+            .copied()
+            .ok_or_else(|| anyhow!("Redaction produced no transactions"))?;
+        tracing::info!("Redaction written for {}: {}", wallet.pubkey(), signature);
+        Ok(signature)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_cluster::{MockCluster, TempDir};
+    use crate::FinternetConfig;
+    use serde_json::{json, Value};
+
+    const EMAIL: &str = "dana.ortiz@example.com";
+
+    /// 3 March 2024, inside the statement month the tests generate
+    const IN_MARCH: u64 = 1_709_500_000;
+
+    fn funded(cluster: &MockCluster) -> Keypair {
+        let wallet = Keypair::new();
+        cluster.set_balance(wallet.pubkey(), 1_000_000_000);
+        wallet
+    }
+
+    fn profile() -> HashMap<String, String> {
+        HashMap::from([("email".to_string(), EMAIL.to_string()), ("team".to_string(), "Treasury".to_string())])
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn a_redacted_email_is_gone_from_every_identity_read_while_other_fields_survive() {
+        let cluster = MockCluster::new();
+        let client = cluster.default_client();
+        let holder = funded(&cluster);
+        client.register_identity(&holder, "Dana Ortiz", profile()).await.unwrap();
+        let before = client.get_registered_identity(&holder.pubkey()).await.unwrap().unwrap();
+        assert_eq!(before.metadata.get("email").map(String::as_str), Some(EMAIL));
+
+        client.redact_identity_fields(&holder, &["email"]).await.unwrap();
+        let redactions = client.get_redactions(&holder.pubkey()).await.unwrap();
+        assert_eq!(redactions.fields, HashSet::from(["email".to_string()]));
+        assert!(redactions.memos.is_empty());
+
+        let registered = client.get_registered_identity(&holder.pubkey()).await.unwrap().unwrap();
+        let resolved = client.get_identity(&holder.pubkey()).await.unwrap();
+        for identity in [&registered, &resolved] {
+            assert_eq!(identity.display_name.as_deref(), Some("Dana Ortiz"));
+            assert_eq!(identity.metadata.get("team").map(String::as_str), Some("Treasury"));
+            assert!(!identity.metadata.contains_key("email"));
+            assert_eq!(identity.redacted_fields, ["email"]);
+            assert!(!serde_json::to_string(identity).unwrap().contains(EMAIL));
+        }
+
+        let index = client.build_identity_index(&[holder.pubkey()]).await.unwrap();
+        let indexed = index.get(&holder.pubkey()).unwrap();
+        assert_eq!(indexed.redacted_fields, ["email"]);
+        assert!(!serde_json::to_string(indexed).unwrap().contains(EMAIL));
+        assert!(index.lookup_by_metadata("email", EMAIL).is_empty());
+        assert_eq!(index.lookup_by_metadata("team", "treasury"), [holder.pubkey()]);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn the_display_name_is_redacted_by_its_own_field_key() {
+        let cluster = MockCluster::new();
+        let client = cluster.default_client();
+        let holder = funded(&cluster);
+        client.register_identity(&holder, "Dana Ortiz", profile()).await.unwrap();
+        client.redact_identity_fields(&holder, &[DISPLAY_NAME_FIELD, "not-registered"]).await.unwrap();
+
+        let identity = client.get_registered_identity(&holder.pubkey()).await.unwrap().unwrap();
+        assert_eq!(identity.display_name, None);
+        assert_eq!(identity.metadata.get("email").map(String::as_str), Some(EMAIL));
+        // Only fields that were actually dropped are listed
+        assert_eq!(identity.redacted_fields, [DISPLAY_NAME_FIELD]);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn action_log_pages_mask_redacted_registrations() {
+        let cluster = MockCluster::new();
+        let dir = TempDir::new("redaction");
+        let client = cluster.client(FinternetConfig {
+            action_log_path: Some(dir.join("actions.jsonl")),
+            ..FinternetConfig::default()
+        });
+        let holder = funded(&cluster);
+        client.register_identity(&holder, "Dana Ortiz", profile()).await.unwrap();
+        client.redact_identity_fields(&holder, &["email"]).await.unwrap();
+
+        let log = client.action_log().unwrap();
+        let mut page = log.page(0, 10).unwrap();
+        assert!(serde_json::to_string(&page).unwrap().contains(EMAIL));
+        client.redact_action_page(&mut page).await.unwrap();
+
+        let registration = page.entries.iter().find(|entry| entry.operation == REGISTER_IDENTITY_ACTION).unwrap();
+        assert_eq!(registration.parameters["metadata"]["email"], REDACTED);
+        assert_eq!(registration.parameters["metadata"]["team"], "Treasury");
+        assert_eq!(registration.parameters["display_name"], "Dana Ortiz");
+        assert_eq!(registration.parameters["redacted"], true);
+        // The redaction itself names fields, not values, and is left alone
+        let redaction = page.entries.iter().find(|entry| entry.operation == "redact").unwrap();
+        assert_eq!(redaction.parameters.get("redacted"), None::<&Value>);
+        assert!(!serde_json::to_string(&page).unwrap().contains(EMAIL));
+    }
+
+    #[test]
+    fn only_redactions_the_holder_signed_count() {
+        let holder = Pubkey::new_unique();
+        let entry = |signer: Pubkey, pubkey: Pubkey, fields: &[&str]| LedgerEntry {
+            signature: Signature::new_unique(),
+            signer: Some(signer),
+            slot: 900,
+            timestamp: IN_MARCH,
+            data: json!({ "action": REDACT_ACTION, "pubkey": pubkey.to_string(), "fields": fields, "memos": [] }).to_string(),
+            chunk: None,
+            namespace: None,
+        };
+        let forger = Pubkey::new_unique();
+        let entries = [
+            entry(holder, holder, &["email"]),
+            entry(holder, holder, &["phone"]),
+            // Someone else naming the holder, and the holder naming someone else
+            entry(forger, holder, &["team"]),
+            entry(holder, forger, &["display_name"]),
+        ];
+        let redactions = Redactions::from_entries(&holder, &entries);
+        assert_eq!(redactions.fields, HashSet::from(["email".to_string(), "phone".to_string()]));
+        assert!(Redactions::from_entries(&forger, &entries).is_empty());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn an_empty_or_blank_redaction_is_refused_before_anything_is_sent() {
+        let cluster = MockCluster::new();
+        let client = cluster.default_client();
+        let holder = funded(&cluster);
+        assert!(client.redact(&holder, &[], &[]).await.is_err());
+        assert!(client.redact_identity_fields(&holder, &[" "]).await.is_err());
+        assert!(cluster.sent().is_empty());
+    }
+
+    /// Statements are built from the SQLite-backed `LocalLedger`
+    #[cfg(feature = "sqlite")]
+    mod statements {
+        use super::*;
+        use crate::export::{export_history, ExportFormat};
+        use crate::local_ledger::LocalLedger;
+        use crate::statement::YearMonth;
+        use crate::Currency;
+        use base64::{engine::general_purpose::STANDARD, Engine};
+        use solana_sdk::transaction::Transaction;
+
+        /// An outgoing payment from `owner` carrying `memo`
+        fn payment(owner: &Pubkey, mint: Pubkey, memo: &str) -> TransactionRecord {
+            TransactionRecord {
+                signature: Signature::new_unique(),
+                from: *owner,
+                to: Pubkey::new_unique(),
+                amount: 1_000_000,
+                currency: Currency::spl(mint, 6),
+                timestamp: IN_MARCH,
+                memo: Some(memo.to_string()),
+                fee: 5_000,
+                slot: 900,
+                memo_fields: HashMap::from([("ref".to_string(), memo.to_string())]),
+            }
+        }
+
+        /// Answer `getTransaction` for each of `records` with `owner` holding `balance` of `mint` afterwards
+        fn serve_balance(cluster: &MockCluster, records: &[&TransactionRecord], owner: Pubkey, mint: Pubkey, balance: u64) {
+            let signatures: HashSet<String> = records.iter().map(|record| record.signature.to_string()).collect();
+            cluster.respond("getTransaction", move |params| {
+                if !signatures.contains(params[0].as_str()?) {
+                    return None;
+                }
+                // The transaction and its meta sit beside the slot, not under a key of their own
+                Some(Ok(json!({
+                    "slot": 900,
+                    "blockTime": IN_MARCH,
+                    "transaction": [STANDARD.encode(bincode::serialize(&Transaction::default()).unwrap()), "base64"],
+                    "meta": {
+                        "err": null,
+                        "status": { "Ok": null },
+                        "fee": 5_000,
+                        "preBalances": [],
+                        "postBalances": [],
+                        "postTokenBalances": [{
+                            "accountIndex": 1,
+                            "mint": mint.to_string(),
+                            "owner": owner.to_string(),
+                            "uiTokenAmount": {
+                                "uiAmount": null,
+                                "decimals": 6,
+                                "amount": balance.to_string(),
+                                "uiAmountString": balance.to_string(),
+                            },
+                        }],
+                    },
+                })))
+            });
+        }
+
+        #[tokio::test(flavor = "multi_thread")]
+        async fn a_redacted_memo_is_masked_in_statements_and_exports() {
+            let cluster = MockCluster::new();
+            let client = cluster.default_client();
+            let dir = TempDir::new("redaction");
+            let holder = funded(&cluster);
+            let mint = Pubkey::new_unique();
+            let private = payment(&holder.pubkey(), mint, EMAIL);
+            let public = payment(&holder.pubkey(), mint, "invoice 42");
+            let ledger = LocalLedger::open(&dir.join("ledger.db")).unwrap();
+            ledger.insert_records(&holder.pubkey(), vec![private.clone(), public.clone()]).unwrap();
+            serve_balance(&cluster, &[&private, &public], holder.pubkey(), mint, 0);
+
+            client.redact_memos(&holder, &[private.signature]).await.unwrap();
+
+            let statement = client
+                .generate_statement(&ledger, &holder.pubkey(), YearMonth::new(2024, 3).unwrap(), None, None, false)
+                .await
+                .unwrap();
+            let lines = &statement.mints[0].lines;
+            let line = |signature: Signature| lines.iter().find(|line| line.signature == signature).unwrap();
+            assert!(line(private.signature).redacted);
+            assert_eq!(line(private.signature).memo.as_deref(), Some(REDACTED));
+            assert!(!line(public.signature).redacted);
+            assert_eq!(line(public.signature).memo.as_deref(), Some("invoice 42"));
+            assert!(!statement.to_markdown().contains(EMAIL));
+            assert!(!serde_json::to_string(&statement).unwrap().contains(EMAIL));
+
+            let redactions = client.get_redactions(&holder.pubkey()).await.unwrap();
+            for format in [ExportFormat::Ofx, ExportFormat::Qif] {
+                let exported = export_history(format, &holder.pubkey(), &[private.clone(), public.clone()], None, &redactions, 0);
+                assert!(!exported.contains(EMAIL), "{:?}", format);
+                assert!(exported.contains("invoice 42"), "{:?}", format);
+            }
+
+            let mut records = vec![private.clone()];
+            redactions.apply_to_records(&mut records);
+            assert_eq!(records[0].memo.as_deref(), Some(REDACTED));
+            assert!(records[0].memo_fields.is_empty());
+        }
+    }
+}
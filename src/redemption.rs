@@ -0,0 +1,294 @@
+use crate::confirmation::OperationKind;
+use crate::telemetry::readable;
+use crate::FinternetClient;
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use solana_sdk::{
+    instruction::Instruction,
+    pubkey::Pubkey,
+    signature::{Keypair, Signature},
+    signer::Signer,
+    system_instruction,
+    transaction::Transaction,
+};
+use spl_associated_token_account::instruction as ata_instruction;
+use spl_token::instruction as token_instruction;
+use std::collections::HashSet;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const REDEMPTION_REQUEST_ACTION: &str = "redemption_request";
+const REDEMPTION_SETTLEMENT_ACTION: &str = "redemption_settlement";
+
+/// A holder's request to cash out tokens with their issuer
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RedemptionRequest {
+    pub id: String,
+    pub holder: Pubkey,
+    pub issuer: Pubkey,
+    pub mint: Pubkey,
+    pub amount: u64,
+    /// Free-form payout instructions, e.g. a bank reference or a USDC address
+    pub payout_details: String,
+    /// Where the redeemed tokens were sent, if they were returned with the request
+    pub redemption_address: Option<Pubkey>,
+    pub timestamp: u64,
+    /// Ledger transaction carrying the request
+    pub signature: Option<Signature>,
+}
+
+/// Issuer-side record linking a payout to the request it settles
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RedemptionSettlement {
+    pub request_id: String,
+    pub payment_signature: Signature,
+    pub burned: u64,
+    pub signature: Signature,
+}
+
+impl FinternetClient {
+    /// Ask the issuer of `mint` to redeem `amount` tokens
+    ///
+    /// The request is written to the ledger and a zero-lamport notification transfer to the issuer
+    /// makes it show up in the issuer's history. When `redemption_address` is given the tokens are
+    /// transferred there in the same transaction.
+    #[tracing::instrument(skip_all, fields(wallet = %readable(&holder_wallet.pubkey()), mint = %mint, amount))]
+    pub async fn request_redemption(
+        &self,
+        holder_wallet: &Keypair,
+        mint: &Pubkey,
+        amount: u64,
+        payout_details: &str,
+        redemption_address: Option<&Pubkey>,
+    ) -> Result<RedemptionRequest> {
+        if amount == 0 {
+            return Err(anyhow!("Redemption amount must be greater than zero"));
+        }
+
+        let issuer = self.get_asset_info(mint).await?.issuer;
+        if issuer == Pubkey::default() {
+            return Err(anyhow!("Asset {} has no issuer on record", mint));
+        }
+
+        let holder = holder_wallet.pubkey();
+        let balance = self.get_token_balance(&holder, mint).await?;
+        if balance < amount {
+            return Err(anyhow!(
+                "Insufficient balance to redeem: have {}, requested {}",
+                balance,
+                amount
+            ));
+        }
+
+        let mut request = RedemptionRequest {
+            id: uuid::Uuid::new_v4().to_string(),
+            holder,
+            issuer,
+            mint: *mint,
+            amount,
+            payout_details: payout_details.to_string(),
+            redemption_address: redemption_address.copied(),
+            timestamp: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+            signature: None,
+        };
+
+        let entry = serde_json::json!({
+            "action": REDEMPTION_REQUEST_ACTION,
+            "id": request.id,
+            "holder": holder.to_string(),
+            "issuer": issuer.to_string(),
+            "mint": mint.to_string(),
+            "amount": amount,
+            "payout_details": payout_details,
+            "redemption_address": redemption_address.map(|a| a.to_string()),
+            "timestamp": request.timestamp,
+        });
+
+        let mut instructions = vec![
+            spl_memo::build_memo(self.namespaced_entry(&entry.to_string())?.as_bytes(), &[&holder]),
+            system_instruction::transfer(&holder, &issuer, 0),
+        ];
+
+        if let Some(destination) = redemption_address {
+            let from_ata = spl_associated_token_account::get_associated_token_address(&holder, mint);
+            let to_ata = spl_associated_token_account::get_associated_token_address(destination, mint);
+            if self.client.get_account(&to_ata).is_err() {
+                instructions.push(ata_instruction::create_associated_token_account(
+                    &holder,
+                    destination,
+                    mint,
+                    &spl_token::id(),
+                ));
+            }
+            instructions.push(token_instruction::transfer(
+                &spl_token::id(),
+                &from_ata,
+                &to_ata,
+                &holder,
+                &[&holder],
+                amount,
+            )?);
+        }
+
+        let signature = self.send_redemption_transaction(holder_wallet, &instructions, "request_redemption")?;
+        tracing::info!("Redemption request {} submitted: {}", request.id, signature);
+
+        request.signature = Some(signature);
+        Ok(request)
+    }
+
+    /// Redemption requests addressed to an issuer that have not been settled yet, oldest first
+    #[tracing::instrument(skip_all, fields(wallet = %readable(issuer)))]
+    pub async fn list_redemption_requests(&self, issuer: &Pubkey) -> Result<Vec<RedemptionRequest>> {
+        let entries = self.read_ledger_entries(issuer, Some(1000)).await?;
+
+        let mut settled = HashSet::new();
+        let mut requests = Vec::new();
+
+        for entry in &entries {
+            let Some(value) = entry.json() else { continue };
+            match value.get("action").and_then(|a| a.as_str()) {
+                Some(REDEMPTION_SETTLEMENT_ACTION) => {
+                    if let Some(id) = value.get("request_id").and_then(|v| v.as_str()) {
+                        settled.insert(id.to_string());
+                    }
+                }
+                Some(REDEMPTION_REQUEST_ACTION) => {
+                    match parse_request(&value, entry.signature) {
+                        // Only trust requests actually signed by the holder they name
+                        Some(request) if request.issuer == *issuer && entry.signer == Some(request.holder) => {
+                            requests.push(request)
+                        }
+                        Some(_) => {}
+                        None => tracing::warn!("Ignoring malformed redemption request in {}", entry.signature),
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        requests.retain(|request| !settled.contains(&request.id));
+        requests.sort_by_key(|request| request.timestamp);
+
+        tracing::info!("Found {} pending redemption requests", requests.len());
+        Ok(requests)
+    }
+
+    /// Record the payout for a redemption request and burn the returned tokens
+    pub async fn settle_redemption(
+        &self,
+        issuer_wallet: &Keypair,
+        request: &RedemptionRequest,
+        payment_signature: &Signature,
+    ) -> Result<RedemptionSettlement> {
+        let outcome = self.settle_redemption_unlogged(issuer_wallet, request, payment_signature).await;
+        let parameters = serde_json::json!({
+            "issuer": issuer_wallet.pubkey().to_string(),
+            "request_id": request.id,
+            "holder": request.holder.to_string(),
+            "mint": request.mint.to_string(),
+            "amount": request.amount,
+            "payment_signature": payment_signature.to_string(),
+            "burned": outcome.as_ref().ok().map(|settlement| settlement.burned),
+        });
+        self.log_action("settle_redemption", parameters, None, outcome.as_ref(), |settlement| settlement.signature.to_string());
+        outcome
+    }
+    
+    #[tracing::instrument(skip_all, fields(wallet = %readable(&issuer_wallet.pubkey()), request_id = %request.id))]
+    async fn settle_redemption_unlogged(
+        &self,
+        issuer_wallet: &Keypair,
+        request: &RedemptionRequest,
+        payment_signature: &Signature,
+    ) -> Result<RedemptionSettlement> {
+        let issuer = issuer_wallet.pubkey();
+        if request.issuer != issuer {
+            return Err(anyhow!(
+                "Request {} is addressed to issuer {}, not {}",
+                request.id,
+                request.issuer,
+                issuer
+            ));
+        }
+
+        let burned = match request.redemption_address {
+            Some(address) if address == issuer => request.amount,
+            Some(address) => {
+                return Err(anyhow!(
+                    "Tokens for request {} were returned to {}; settle with that wallet to burn them",
+                    request.id,
+                    address
+                ))
+            }
+            None => 0,
+        };
+
+        let entry = serde_json::json!({
+            "action": REDEMPTION_SETTLEMENT_ACTION,
+            "request_id": request.id,
+            "holder": request.holder.to_string(),
+            "mint": request.mint.to_string(),
+            "amount": request.amount,
+            "payment_signature": payment_signature.to_string(),
+            "timestamp": SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+        });
+
+        let mut instructions = vec![
+            spl_memo::build_memo(self.namespaced_entry(&entry.to_string())?.as_bytes(), &[&issuer]),
+            system_instruction::transfer(&issuer, &request.holder, 0),
+        ];
+
+        if burned > 0 {
+            let token_account = spl_associated_token_account::get_associated_token_address(&issuer, &request.mint);
+            instructions.push(token_instruction::burn(
+                &spl_token::id(),
+                &token_account,
+                &request.mint,
+                &issuer,
+                &[&issuer],
+                burned,
+            )?);
+        }
+
+        let signature = self.send_redemption_transaction(issuer_wallet, &instructions, "settle_redemption")?;
+        tracing::info!("Redemption {} settled: {}", request.id, signature);
+
+        Ok(RedemptionSettlement {
+            request_id: request.id.clone(),
+            payment_signature: *payment_signature,
+            burned,
+            signature,
+        })
+    }
+
+    fn send_redemption_transaction(
+        &self,
+        wallet: &Keypair,
+        instructions: &[Instruction],
+        label: &str,
+    ) -> Result<Signature> {
+        let mut transaction = Transaction::new_with_payer(instructions, Some(&wallet.pubkey()));
+        self.sign_with_fresh_blockhash(&mut transaction, &[wallet])?;
+        self.send_and_confirm_resigning(OperationKind::Other, label, transaction, &[wallet])
+    }
+}
+
+fn parse_request(value: &serde_json::Value, signature: Signature) -> Option<RedemptionRequest> {
+    let pubkey = |field: &str| value.get(field)?.as_str()?.parse::<Pubkey>().ok();
+
+    Some(RedemptionRequest {
+        id: value.get("id")?.as_str()?.to_string(),
+        holder: pubkey("holder")?,
+        issuer: pubkey("issuer")?,
+        mint: pubkey("mint")?,
+        amount: value.get("amount")?.as_u64()?,
+        payout_details: value
+            .get("payout_details")
+            .and_then(|v| v.as_str())
+            .unwrap_or_default()
+            .to_string(),
+        redemption_address: pubkey("redemption_address"),
+        timestamp: value.get("timestamp").and_then(|v| v.as_u64()).unwrap_or(0),
+        signature: Some(signature),
+    })
+}
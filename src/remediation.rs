@@ -0,0 +1,306 @@
+//! What to do about a failed call, for errors that show up in support tickets
+//!
+//! Raw Solana failures such as `custom program error: 0x1` say what broke but not how to fix
+//! it. `remediation` matches an error against a table of known RPC messages and SPL error
+//! codes and returns a plain-language hint. Unknown errors get none.
+
+use crate::blockhash::MAX_EXPIRY_RESIGNS;
+use crate::Currency;
+use solana_client::client_error::{ClientError, ClientErrorKind};
+use solana_client::rpc_request::{RpcError, RpcResponseErrorData};
+use solana_sdk::{program_pack::Pack, rent::Rent};
+
+/// Signature fee per signer, in lamports
+const LAMPORTS_PER_SIGNATURE: u64 = 5_000;
+
+/// Errors whose text contains `pattern` (lowercase) get the hint `hint` builds from that text
+struct HintRule {
+    pattern: &'static str,
+    hint: fn(&str) -> Option<String>,
+}
+
+/// Checked in order; the first rule that matches and produces a hint wins
+const HINT_RULES: &[HintRule] = &[
+    HintRule { pattern: "insufficient lamports", hint: lamport_shortfall },
+    HintRule { pattern: "no record of a prior credit", hint: |_| Some(no_sol()) },
+    HintRule { pattern: "insufficient funds for fee", hint: |_| Some(fee_shortfall()) },
+    HintRule { pattern: "insufficient funds for rent", hint: |_| Some(rent_shortfall()) },
+    HintRule { pattern: "custom program error: 0x", hint: program_error },
+    HintRule { pattern: "blockhash not found", hint: |_| Some(expired()) },
+    HintRule { pattern: "block height exceeded", hint: |_| Some(expired()) },
+    HintRule { pattern: "not confirmed by the deadline", hint: |_| Some(past_deadline()) },
+    HintRule { pattern: "too many requests", hint: |_| Some(rate_limited()) },
+    HintRule { pattern: "accountnotfound", hint: |_| Some(account_not_found()) },
+    HintRule { pattern: "account not found", hint: |_| Some(account_not_found()) },
+    HintRule { pattern: "could not find account", hint: |_| Some(account_not_found()) },
+    HintRule { pattern: "error sending request", hint: |_| Some(unreachable()) },
+    HintRule { pattern: "connection refused", hint: |_| Some(unreachable()) },
+    HintRule { pattern: "operation timed out", hint: |_| Some(unreachable()) },
+];
+
+/// SPL Token error codes and what to do about each
+const TOKEN_ERROR_HINTS: &[(u32, &str)] = &[
+    (0x0, "The token account would not be rent exempt; fund it with at least the rent minimum for a token account"),
+    (0x1, "The source token account balance is too low for this transfer; check it with `finternet-cli wallet-info`"),
+    (0x2, "The mint address is not a token mint; check the mint"),
+    (0x3, "A token account belongs to a different mint than the one being sent; check the recipient and the mint"),
+    (0x4, "The signer does not own the source token account and is not its delegate"),
+    (0x5, "The mint has no mint authority, so its supply is fixed"),
+    (0x6, "The account is already initialized; use the existing account instead of creating it"),
+    (0x9, "The token account does not exist yet; create the recipient's associated token account first"),
+    (0x11, "The token account is frozen by the mint's freeze authority; ask the issuer to thaw it"),
+    (0x12, "The amount's decimals do not match the mint; check the mint's decimals before converting"),
+];
+
+/// A hint on how to fix `error`, from any error in its chain
+pub fn remediation(error: &anyhow::Error) -> Option<String> {
+    remediation_for_message(&error_text(error))
+}
+
+/// Every message in `error`'s chain, with the program logs of any preflight failure
+pub(crate) fn error_text(error: &anyhow::Error) -> String {
+    let mut text = String::new();
+    for cause in error.chain() {
+        text.push_str(&cause.to_string());
+        text.push('\n');
+        if let Some(logs) = cause.downcast_ref::<ClientError>().and_then(preflight_logs) {
+            text.push_str(&logs.join("\n"));
+            text.push('\n');
+        }
+    }
+    text
+}
+
+/// A hint for an error that only survives as text, such as a logged message
+pub fn remediation_for_message(message: &str) -> Option<String> {
+    let message = message.to_ascii_lowercase();
+    HINT_RULES
+        .iter()
+        .filter(|rule| message.contains(rule.pattern))
+        .find_map(|rule| (rule.hint)(&message))
+}
+
+/// Program logs of a send refused in simulation
+fn preflight_logs(error: &ClientError) -> Option<&Vec<String>> {
+    match error.kind() {
+        ClientErrorKind::RpcError(RpcError::RpcResponseError {
+            data: RpcResponseErrorData::SendTransactionPreflightFailure(result),
+            ..
+        }) => result.logs.as_ref(),
+        _ => None,
+    }
+}
+
+fn sol(lamports: u64) -> String {
+    Currency::Sol.format_amount(lamports)
+}
+
+/// `Transfer: insufficient lamports 1000, need 5000` from the System Program
+fn lamport_shortfall(message: &str) -> Option<String> {
+    let numbers = message.split("insufficient lamports").nth(1)?;
+    let mut numbers = numbers
+        .split(|c: char| !c.is_ascii_digit())
+        .filter(|part| !part.is_empty())
+        .map(|part| part.parse::<u64>());
+    let (Some(Ok(have)), Some(Ok(need))) = (numbers.next(), numbers.next()) else {
+        return Some("The paying account does not hold enough SOL; top it up and try again".to_string());
+    };
+    Some(format!(
+        "The paying account holds {} but needs {}; top it up with at least {}",
+        sol(have),
+        sol(need),
+        sol(need.saturating_sub(have))
+    ))
+}
+
+fn no_sol() -> String {
+    format!(
+        "The fee payer has no SOL; top it up with at least {} (on devnet: `solana airdrop 1`)",
+        sol(Rent::default().minimum_balance(0) + LAMPORTS_PER_SIGNATURE)
+    )
+}
+
+fn fee_shortfall() -> String {
+    format!(
+        "The fee payer cannot cover the network fee; top it up with at least {} per signature",
+        sol(LAMPORTS_PER_SIGNATURE)
+    )
+}
+
+fn rent_shortfall() -> String {
+    let rent = Rent::default();
+    format!(
+        "An account would be left below its rent-exempt minimum: {} for a token account, {} for a plain SOL account. Top up the payer, or leave at least that much behind",
+        sol(rent.minimum_balance(spl_token::state::Account::LEN)),
+        sol(rent.minimum_balance(0))
+    )
+}
+
+/// Hint for a custom program error, by the program that failed
+///
+/// The program is read from the simulation logs when they were captured. Without them the
+/// error is taken to be the token program's, where almost every failure in this SDK comes from.
+fn program_error(message: &str) -> Option<String> {
+    let code = message.split("custom program error: 0x").nth(1)?;
+    let code: String = code.chars().take_while(|c| c.is_ascii_hexdigit()).collect();
+    let code = u32::from_str_radix(&code, 16).ok()?;
+
+    let failed = message
+        .lines()
+        .find_map(|line| line.strip_prefix("program ")?.split_once(" failed: custom program error"))
+        .map(|(program, _)| program.to_string());
+    // The logs were lowercased with everything else, so compare program ids case-insensitively
+    let program = match failed {
+        Some(program) => [spl_token::id(), spl_associated_token_account::id()]
+            .into_iter()
+            .find(|id| id.to_string().eq_ignore_ascii_case(&program))?,
+        None => spl_token::id(),
+    };
+
+    if program == spl_token::id() {
+        let hint = TOKEN_ERROR_HINTS.iter().find(|(known, _)| *known == code)?.1;
+        if code == 0x0 {
+            return Some(format!(
+                "{} ({})",
+                hint,
+                sol(Rent::default().minimum_balance(spl_token::state::Account::LEN))
+            ));
+        }
+        return Some(hint.to_string());
+    }
+    if program == spl_associated_token_account::id() && code == 0x0 {
+        return Some("The associated token account address does not match the wallet and mint; derive it again".to_string());
+    }
+    None
+}
+
+fn past_deadline() -> String {
+    "The transaction was sent but had not confirmed by the deadline, and may still land. Look up its signature \
+     before paying again; with an outbox configured, `recover_outbox` records how it ended"
+        .to_string()
+}
+
+fn expired() -> String {
+    format!(
+        "The transaction expired before it landed. SDK sends re-sign with a fresh blockhash and retry up to {} times on their own; if this keeps happening the RPC node is lagging or congested, so try again or switch RPC endpoints",
+        MAX_EXPIRY_RESIGNS
+    )
+}
+
+fn rate_limited() -> String {
+    "The RPC node is rate limiting requests; slow down, or use a dedicated RPC endpoint instead of the public one".to_string()
+}
+
+fn account_not_found() -> String {
+    "The account does not exist on this cluster; check the address and that the RPC URL points at the right network".to_string()
+}
+
+fn unreachable() -> String {
+    "The RPC node could not be reached; check the network connection and the RPC URL".to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error_code::ErrorEnvelope;
+    use anyhow::anyhow;
+    use solana_client::rpc_response::RpcSimulateTransactionResult;
+    use solana_sdk::instruction::InstructionError;
+    use solana_sdk::pubkey::Pubkey;
+    use solana_sdk::transaction::TransactionError;
+
+    /// Raw messages as nodes and programs report them, each with the start of its hint
+    const KNOWN: &[(&str, &str)] = &[
+        (
+            "Transfer: insufficient lamports 1000, need 2039280",
+            "The paying account holds 0.000001 SOL but needs 0.00203928 SOL; top it up with at least 0.00203828 SOL",
+        ),
+        ("Transfer: insufficient lamports", "The paying account does not hold enough SOL"),
+        ("Attempt to debit an account but found no record of a prior credit.", "The fee payer has no SOL; top it up with at least 0.00089588 SOL"),
+        ("Transaction results in an account (1) with insufficient funds for fee", "The fee payer cannot cover the network fee"),
+        ("Transaction results in an account (2) with insufficient funds for rent", "An account would be left below its rent-exempt minimum: 0.00203928 SOL"),
+        ("Error processing Instruction 2: custom program error: 0x1", "The source token account balance is too low"),
+        ("custom program error: 0x0", "The token account would not be rent exempt; fund it with at least the rent minimum for a token account (0.00203928 SOL)"),
+        ("custom program error: 0x11", "The token account is frozen"),
+        ("custom program error: 0x12", "The amount's decimals do not match the mint"),
+        ("Transaction simulation failed: Blockhash not found", "The transaction expired before it landed. SDK sends re-sign with a fresh blockhash and retry up to 2 times"),
+        ("block height exceeded", "The transaction expired before it landed"),
+        ("5Vf...Qz was not confirmed by the deadline", "The transaction was sent but had not confirmed by the deadline"),
+        ("HTTP status client error (429 Too Many Requests)", "The RPC node is rate limiting requests"),
+        ("AccountNotFound: pubkey=9xQ...", "The account does not exist on this cluster"),
+        ("error sending request for url (https://api.devnet.solana.com/)", "The RPC node could not be reached"),
+        ("tcp connect error: Connection refused (os error 111)", "The RPC node could not be reached"),
+    ];
+
+    /// A send refused in simulation, with the program logs the node returned
+    fn preflight(err: TransactionError, logs: &[&str]) -> anyhow::Error {
+        ClientError::from(RpcError::RpcResponseError {
+            code: -32002,
+            message: format!("Transaction simulation failed: {}", err),
+            data: RpcResponseErrorData::SendTransactionPreflightFailure(RpcSimulateTransactionResult {
+                err: Some(err),
+                logs: Some(logs.iter().map(|line| line.to_string()).collect()),
+                accounts: None,
+                units_consumed: None,
+                return_data: None,
+                inner_instructions: None,
+            }),
+        })
+        .into()
+    }
+
+    #[test]
+    fn each_known_error_gets_its_hint() {
+        for (raw, hint) in KNOWN {
+            let got = remediation_for_message(raw).unwrap_or_else(|| panic!("no hint for {}", raw));
+            assert!(got.starts_with(hint), "{}\n  got {}", raw, got);
+        }
+    }
+
+    #[test]
+    fn unknown_errors_and_unlisted_codes_get_none() {
+        for raw in ["Invalid param: WrongSize", "custom program error: 0x7", "custom program error: 0xzz", "something else broke"] {
+            assert_eq!(remediation_for_message(raw), None, "{}", raw);
+        }
+    }
+
+    #[test]
+    fn the_failing_program_is_read_from_the_preflight_logs() {
+        let failed = |program: &Pubkey, code: u32| {
+            preflight(
+                TransactionError::InstructionError(1, InstructionError::Custom(code)),
+                &[
+                    &format!("Program {} invoke [1]", program),
+                    &format!("Program {} failed: custom program error: {:#x}", program, code),
+                ],
+            )
+        };
+
+        let hint = remediation(&failed(&spl_token::id(), 0x1)).unwrap();
+        assert!(hint.starts_with("The source token account balance is too low"), "{}", hint);
+        let hint = remediation(&failed(&spl_associated_token_account::id(), 0x0)).unwrap();
+        assert!(hint.starts_with("The associated token account address does not match"), "{}", hint);
+        // An error code of some other program means something else entirely
+        assert_eq!(remediation(&failed(&Pubkey::new_unique(), 0x1)), None);
+    }
+
+    #[test]
+    fn any_error_in_the_chain_counts_and_the_first_matching_rule_wins() {
+        let error = anyhow!("Blockhash not found").context("Payment failed").context("Payroll run stopped");
+        assert!(remediation(&error).unwrap().starts_with("The transaction expired"));
+
+        // A lamport shortfall is more specific than the rent rule it also matches
+        let both = "insufficient funds for rent: Transfer: insufficient lamports 10, need 20";
+        assert!(remediation_for_message(both).unwrap().starts_with("The paying account holds"));
+    }
+
+    #[test]
+    fn the_cli_error_envelope_carries_the_hint() {
+        let error = preflight(TransactionError::InsufficientFundsForFee, &["Program log: Error: insufficient funds for fee"]);
+        let envelope = serde_json::to_value(ErrorEnvelope::new(&error.context("send_payment failed"))).unwrap();
+        assert!(envelope["hint"].as_str().unwrap().starts_with("The fee payer cannot cover the network fee"), "{}", envelope);
+
+        let envelope = serde_json::to_value(ErrorEnvelope::new(&anyhow!("something else broke"))).unwrap();
+        assert_eq!(envelope["hint"], serde_json::Value::Null);
+    }
+}
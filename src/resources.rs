@@ -0,0 +1,630 @@
+//! Bounds on what a long-running client keeps in memory and on disk
+//!
+//! In-memory caches are `LruMap`s capped by entry count and, where entries vary in size, by
+//! approximate bytes; the least recently used entry goes first. On disk, the action log rotates
+//! into verified segments (see `action_log::RotationPolicy`), the outbox moves settled entries
+//! to an archive once they are old enough, and `LocalLedger::compact` trims the local ledger.
+//! Nothing that still matters is evicted: `Submitted` and `Dropped` outbox entries stay put, and
+//! an action log segment is only rotated out or pruned after its hash chain verifies.
+//!
+//! The coalescing map in `coalesce` is not listed: it only holds reads while they are in
+//! flight, so it is already bounded by concurrency.
+
+use crate::action_log::RotationPolicy;
+use crate::FinternetClient;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::hash::Hash;
+use std::path::{Path, PathBuf};
+
+/// Most entries, and optionally most bytes, one cache holds
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CacheLimit {
+    pub max_entries: usize,
+    /// Approximate, from each entry's serialized size
+    pub max_bytes: Option<usize>,
+}
+
+impl CacheLimit {
+    pub fn entries(max_entries: usize) -> Self {
+        Self { max_entries, max_bytes: None }
+    }
+
+    pub fn with_max_bytes(mut self, max_bytes: usize) -> Self {
+        self.max_bytes = Some(max_bytes);
+        self
+    }
+}
+
+impl Default for CacheLimit {
+    fn default() -> Self {
+        Self::entries(10_000)
+    }
+}
+
+/// The client's caches and stores, as `FinternetConfig::resource_limits`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResourceLimits {
+    /// Asset metadata, including off-chain JSON; default 10,000 entries or 64 MiB
+    pub asset_cache: CacheLimit,
+    /// Cached wallet balances; default 10,000 wallets
+    pub wallet_cache: CacheLimit,
+    /// Issuer mint lists and mint creation records, each; default 1,000 entries
+    pub issuance_cache: CacheLimit,
+    /// Recipients each `SendContext` remembers; default 100,000
+    pub send_context_recipients: CacheLimit,
+    /// Rotate the action log by size; it grows without bound when unset
+    pub action_log_rotation: Option<RotationPolicy>,
+    /// Archive confirmed, failed, and expired outbox entries this many days after they settled
+    pub outbox_archive_after_days: Option<u64>,
+}
+
+impl Default for ResourceLimits {
+    fn default() -> Self {
+        Self {
+            asset_cache: CacheLimit::entries(10_000).with_max_bytes(64 * 1024 * 1024),
+            wallet_cache: CacheLimit::entries(10_000),
+            issuance_cache: CacheLimit::entries(1_000),
+            send_context_recipients: CacheLimit::entries(100_000),
+            action_log_rotation: None,
+            outbox_archive_after_days: None,
+        }
+    }
+}
+
+/// A map that evicts its least recently used entries to stay within a `CacheLimit`
+pub struct LruMap<K, V> {
+    limit: CacheLimit,
+    entries: HashMap<K, LruSlot<V>>,
+    /// Keys by last use, oldest first
+    recency: BTreeMap<u64, K>,
+    clock: u64,
+    bytes: usize,
+    evictions: u64,
+}
+
+struct LruSlot<V> {
+    value: V,
+    used: u64,
+    bytes: usize,
+}
+
+impl<K: Eq + Hash + Clone, V> LruMap<K, V> {
+    pub fn new(limit: CacheLimit) -> Self {
+        Self {
+            limit,
+            entries: HashMap::new(),
+            recency: BTreeMap::new(),
+            clock: 0,
+            bytes: 0,
+            evictions: 0,
+        }
+    }
+
+    /// The value for `key`, marking it as just used
+    pub fn get(&mut self, key: &K) -> Option<&V> {
+        let slot = self.entries.get_mut(key)?;
+        self.recency.remove(&slot.used);
+        self.clock += 1;
+        slot.used = self.clock;
+        self.recency.insert(self.clock, key.clone());
+        Some(&slot.value)
+    }
+
+    /// Insert `value`, `bytes` in size, then evict until the map is within its limit
+    ///
+    /// A value larger than `max_bytes` on its own is evicted straight away.
+    pub fn insert(&mut self, key: K, value: V, bytes: usize) {
+        self.remove(&key);
+        self.clock += 1;
+        self.recency.insert(self.clock, key.clone());
+        self.entries.insert(key, LruSlot { value, used: self.clock, bytes });
+        self.bytes += bytes;
+        while self.entries.len() > self.limit.max_entries || self.limit.max_bytes.is_some_and(|max| self.bytes > max) {
+            let Some((_, oldest)) = self.recency.pop_first() else { break };
+            if let Some(slot) = self.entries.remove(&oldest) {
+                self.bytes -= slot.bytes;
+                self.evictions += 1;
+            }
+        }
+    }
+
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let slot = self.entries.remove(key)?;
+        self.recency.remove(&slot.used);
+        self.bytes -= slot.bytes;
+        Some(slot.value)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn usage(&self) -> CacheUsage {
+        CacheUsage {
+            entries: self.entries.len(),
+            bytes: self.bytes,
+            max_entries: self.limit.max_entries,
+            max_bytes: self.limit.max_bytes,
+            evictions: self.evictions,
+        }
+    }
+}
+
+impl<K, V> fmt::Debug for LruMap<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LruMap").field("entries", &self.entries.len()).field("limit", &self.limit).finish()
+    }
+}
+
+impl<K: Eq + Hash + Clone, V> Default for LruMap<K, V> {
+    fn default() -> Self {
+        Self::new(CacheLimit::default())
+    }
+}
+
+/// Serialized size of `value`, as the byte count an `LruMap` entry is charged
+pub fn approximate_bytes<T: Serialize>(value: &T) -> usize {
+    serde_json::to_vec(value).map(|bytes| bytes.len()).unwrap_or(0)
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CacheUsage {
+    pub entries: usize,
+    pub bytes: usize,
+    pub max_entries: usize,
+    pub max_bytes: Option<usize>,
+    /// Entries dropped to stay within the limit, since the client was built
+    pub evictions: u64,
+}
+
+/// Size of one file-backed store
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StoreUsage {
+    pub path: PathBuf,
+    /// Entries in the live file
+    pub entries: usize,
+    pub bytes: u64,
+    /// Rotated action log segments or the outbox archive
+    pub archived_files: usize,
+    pub archived_bytes: u64,
+}
+
+/// What `FinternetClient::resource_usage` found
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ResourceUsage {
+    pub asset_cache: CacheUsage,
+    pub wallet_cache: CacheUsage,
+    pub issuer_cache: CacheUsage,
+    pub mint_creation_cache: CacheUsage,
+    pub outbox: Option<StoreUsage>,
+    pub action_log: Option<StoreUsage>,
+    /// Only set by callers that hold a `LocalLedger`; the client does not own one
+    pub local_ledger: Option<StoreUsage>,
+}
+
+impl ResourceUsage {
+    /// Gauges in the Prometheus text exposition format: `finternet_cache_*` labelled by cache
+    /// and `finternet_store_*` labelled by store
+    pub fn render_prometheus(&self) -> String {
+        let caches = [
+            ("asset", &self.asset_cache),
+            ("wallet", &self.wallet_cache),
+            ("issuer", &self.issuer_cache),
+            ("mint_creation", &self.mint_creation_cache),
+        ];
+        let mut out = String::from(
+            "# HELP finternet_cache_entries Entries held in each in-memory cache\n\
+             # TYPE finternet_cache_entries gauge\n",
+        );
+        for (cache, usage) in caches {
+            out.push_str(&format!("finternet_cache_entries{{cache=\"{}\"}} {}\n", cache, usage.entries));
+        }
+        out.push_str("# HELP finternet_cache_bytes Approximate bytes held in each in-memory cache\n# TYPE finternet_cache_bytes gauge\n");
+        for (cache, usage) in caches {
+            out.push_str(&format!("finternet_cache_bytes{{cache=\"{}\"}} {}\n", cache, usage.bytes));
+        }
+        out.push_str("# HELP finternet_cache_evictions_total Entries evicted from each in-memory cache\n# TYPE finternet_cache_evictions_total counter\n");
+        for (cache, usage) in caches {
+            out.push_str(&format!("finternet_cache_evictions_total{{cache=\"{}\"}} {}\n", cache, usage.evictions));
+        }
+
+        let stores = [("outbox", &self.outbox), ("action_log", &self.action_log), ("local_ledger", &self.local_ledger)];
+        let stores: Vec<(&str, &StoreUsage)> = stores.into_iter().filter_map(|(name, usage)| Some((name, usage.as_ref()?))).collect();
+        out.push_str("# HELP finternet_store_entries Entries in each store's live file\n# TYPE finternet_store_entries gauge\n");
+        for (store, usage) in &stores {
+            out.push_str(&format!("finternet_store_entries{{store=\"{}\"}} {}\n", store, usage.entries));
+        }
+        out.push_str("# HELP finternet_store_bytes Size of each store's live file\n# TYPE finternet_store_bytes gauge\n");
+        for (store, usage) in &stores {
+            out.push_str(&format!("finternet_store_bytes{{store=\"{}\"}} {}\n", store, usage.bytes));
+        }
+        out.push_str("# HELP finternet_store_archived_bytes Size of each store's rotated or archived files\n# TYPE finternet_store_archived_bytes gauge\n");
+        for (store, usage) in &stores {
+            out.push_str(&format!("finternet_store_archived_bytes{{store=\"{}\"}} {}\n", store, usage.archived_bytes));
+        }
+        out
+    }
+}
+
+impl crate::FinternetConfig {
+    /// Bound the client's caches and stores; see `resources`
+    pub fn with_resource_limits(mut self, limits: ResourceLimits) -> Self {
+        self.resource_limits = limits;
+        self
+    }
+}
+
+/// Size of `path`, 0 when it does not exist
+pub(crate) fn file_bytes(path: &Path) -> u64 {
+    std::fs::metadata(path).map(|metadata| metadata.len()).unwrap_or(0)
+}
+
+impl FinternetClient {
+    /// How full each cache is and how large the outbox and action log have grown
+    pub fn resource_usage(&self) -> ResourceUsage {
+        let (issuer_cache, mint_creation_cache) = self.issuance_cache.usage();
+        let store = |name: &str, usage: anyhow::Result<StoreUsage>| {
+            usage
+                .map_err(|e| tracing::warn!("Could not measure the {}: {}", name, e))
+                .ok()
+        };
+        ResourceUsage {
+            asset_cache: self.cache.asset_usage(),
+            wallet_cache: self.cache.wallets.usage(),
+            issuer_cache,
+            mint_creation_cache,
+            outbox: self.outbox.as_ref().and_then(|outbox| store("outbox", outbox.usage())),
+            action_log: self.action_log.as_ref().and_then(|log| store("action log", log.usage())),
+            local_ledger: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::action_log::{verify_action_log, ActionLog};
+    use crate::freshness::Freshness;
+    use crate::mock_cluster::{encode_account, MockCluster, TempDir};
+    use crate::outbox::{ArchivedOutboxEntry, Outbox, OutboxStatus};
+    use crate::recording::RecordingSender;
+    use crate::FinternetConfig;
+    use solana_sdk::account::Account;
+    use solana_sdk::hash::Hash;
+    use solana_sdk::pubkey::Pubkey;
+    use solana_sdk::signature::{Keypair, Signature, Signer};
+    use solana_sdk::system_instruction;
+    use solana_sdk::transaction::Transaction;
+    use std::sync::Arc;
+
+    #[test]
+    fn the_least_recently_used_entry_goes_first() {
+        let mut map = LruMap::new(CacheLimit::entries(3));
+        for key in 0..3 {
+            map.insert(key, key, 1);
+        }
+        map.get(&0);
+        map.insert(3, 3, 1);
+        assert!(map.get(&1).is_none(), "the least recently used key is evicted");
+        assert!(map.get(&0).is_some());
+        assert_eq!(map.usage(), CacheUsage { entries: 3, bytes: 3, max_entries: 3, max_bytes: None, evictions: 1 });
+
+        for key in 10..1_010 {
+            map.insert(key, key, 1);
+        }
+        assert_eq!(map.len(), 3);
+        assert_eq!(map.usage().evictions, 1_001);
+    }
+
+    #[test]
+    fn a_byte_limit_evicts_until_the_map_fits() {
+        let mut map = LruMap::new(CacheLimit::entries(100).with_max_bytes(10));
+        map.insert("a", (), 4);
+        map.insert("b", (), 4);
+        map.insert("c", (), 4);
+        assert_eq!((map.len(), map.usage().bytes), (2, 8));
+        assert!(map.get(&"a").is_none());
+
+        map.insert("huge", (), 11);
+        assert!(map.is_empty(), "an entry over the byte limit on its own is evicted straight away");
+        assert_eq!(map.usage().bytes, 0);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn a_replayed_client_keeps_the_configured_limits_and_writes_nothing() {
+        let dir = TempDir::new("resources");
+        let recording = dir.join("rpc.jsonl");
+        let cluster = MockCluster::new();
+        let recorder = RecordingSender::over(cluster.sender(), &recording).unwrap();
+        FinternetClient::with_rpc_sender(FinternetConfig::default(), recorder).client.get_slot().unwrap();
+
+        let limits = ResourceLimits {
+            asset_cache: CacheLimit::entries(5).with_max_bytes(1_024),
+            wallet_cache: CacheLimit::entries(3),
+            issuance_cache: CacheLimit::entries(7),
+            ..ResourceLimits::default()
+        };
+        let config = FinternetConfig {
+            outbox_path: Some(dir.join("outbox.json")),
+            action_log_path: Some(dir.join("actions.jsonl")),
+            ..FinternetConfig::default()
+        }
+        .with_resource_limits(limits);
+        let replay = FinternetClient::from_recording_with(&recording, config).unwrap();
+
+        assert_eq!(replay.config.resource_limits, limits);
+        let usage = replay.resource_usage();
+        assert_eq!((usage.asset_cache.max_entries, usage.asset_cache.max_bytes), (5, Some(1_024)));
+        assert_eq!(usage.wallet_cache.max_entries, 3);
+        assert_eq!((usage.issuer_cache.max_entries, usage.mint_creation_cache.max_entries), (7, 7));
+        assert!(usage.outbox.is_none() && usage.action_log.is_none());
+        assert!(replay.client.get_slot().is_ok());
+    }
+
+    #[test]
+    fn the_action_log_rotates_and_prunes_only_verified_segments() {
+        let dir = TempDir::new("resources");
+        let path = dir.join("actions.jsonl");
+        let log = ActionLog::new(&path).with_rotation(Some(RotationPolicy { max_bytes: 1, retained_segments: 2 }));
+        for n in 0..6 {
+            log.append("op", serde_json::json!({ "n": n }), None, None, None, Vec::new()).unwrap();
+        }
+
+        // Each append past the first rotates the one before it out of the live file
+        let usage = log.usage().unwrap();
+        assert_eq!((usage.entries, usage.archived_files), (1, 2));
+        let verification = verify_action_log(&path).unwrap();
+        assert_eq!((verification.entries, verification.pruned, verification.segments), (3, 3, 2));
+        assert_eq!(log.page(0, 10).unwrap().entries.iter().map(|entry| entry.seq).collect::<Vec<_>>(), vec![3, 4, 5]);
+
+        // An altered live file is neither rotated nor pruned, so the change stays in sight
+        let altered = std::fs::read_to_string(&path).unwrap().replace("\"n\":5", "\"n\":50");
+        std::fs::write(&path, altered).unwrap();
+        log.append("op", serde_json::json!({ "n": 6 }), None, None, None, Vec::new()).unwrap();
+        let usage = log.usage().unwrap();
+        assert_eq!((usage.entries, usage.archived_files), (2, 2));
+        assert!(verify_action_log(&path).is_err());
+    }
+
+    #[test]
+    fn settled_outbox_entries_move_to_the_archive_once_old_enough() {
+        let dir = TempDir::new("resources");
+        let path = dir.join("outbox.json");
+        let outbox = Outbox::new(&path);
+        let payer = Keypair::new();
+        let statuses = [OutboxStatus::Confirmed, OutboxStatus::Failed, OutboxStatus::Submitted, OutboxStatus::Dropped];
+        let mut signatures = Vec::new();
+        for (n, status) in statuses.into_iter().enumerate() {
+            let transfer = system_instruction::transfer(&payer.pubkey(), &Keypair::new().pubkey(), n as u64 + 1);
+            let transaction = Transaction::new_signed_with_payer(&[transfer], Some(&payer.pubkey()), &[&payer], Hash::new_unique());
+            let signature = outbox.record_submitted("test", &transaction).unwrap();
+            outbox.mark(&signature, status, None).unwrap();
+            signatures.push(signature);
+        }
+        // A week ago, except the last confirmed one
+        let mut entries = outbox.entries().unwrap();
+        for entry in &mut entries {
+            entry.updated_at -= 7 * 86_400;
+        }
+        entries[1].updated_at += 7 * 86_400;
+        std::fs::write(&path, serde_json::to_string(&entries).unwrap()).unwrap();
+
+        let outbox = Outbox::new(&path).with_archival(Some(3));
+        assert_eq!(outbox.archive_settled().unwrap(), 1);
+        let kept: Vec<_> = outbox.entries().unwrap().iter().map(|entry| entry.signature).collect();
+        assert_eq!(kept, signatures[1..].to_vec());
+        let archive = std::fs::read_to_string(outbox.archive_path()).unwrap();
+        let archived: Vec<ArchivedOutboxEntry> = archive.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert_eq!(archived.len(), 1);
+        assert_eq!((archived[0].signature, archived[0].status), (signatures[0], OutboxStatus::Confirmed));
+        assert!(!archive.contains("\"transaction\""));
+        assert_eq!(outbox.archive_settled().unwrap(), 0);
+        let usage = outbox.usage().unwrap();
+        assert_eq!((usage.entries, usage.archived_files), (3, 1));
+    }
+
+    #[test]
+    fn a_send_context_forgets_its_least_recently_used_recipients() {
+        let cluster = MockCluster::new();
+        let mint = cluster.add_mint(&spl_token::id(), 6, &Pubkey::new_unique());
+        let limits = ResourceLimits { send_context_recipients: CacheLimit::entries(3), ..ResourceLimits::default() };
+        let client = cluster.client(FinternetConfig::default().with_resource_limits(limits));
+        let context = client.prepare_send_context(&mint).unwrap();
+        let recipients: Vec<Pubkey> = (0..10).map(|_| Keypair::new().pubkey()).collect();
+
+        client.prefetch_recipients(&context, &recipients).unwrap();
+        assert_eq!(context.known_recipients(), 3);
+        assert_eq!(context.recipient_usage().evictions, 7);
+        // The newest are kept, so prefetching them again reads nothing
+        let lookups = context.lookups();
+        client.prefetch_recipients(&context, &recipients[7..]).unwrap();
+        assert_eq!(context.lookups(), lookups);
+    }
+
+    /// Records each structure takes in the soak
+    const SOAK_RECORDS: usize = 100_000;
+    /// Sampled this often to show usage levelling off rather than climbing
+    const SOAK_SAMPLE_EVERY: usize = 10_000;
+
+    /// A puffed Token Metadata account naming `issuer` as its verified first creator, as
+    /// `getProgramAccounts` returns it
+    fn issued_metadata(issuer: &Pubkey, mint: &Pubkey) -> Account {
+        fn padded(data: &mut Vec<u8>, text: &str, len: usize) {
+            data.extend((len as u32).to_le_bytes());
+            data.extend(text.as_bytes());
+            data.extend(std::iter::repeat_n(0, len - text.len()));
+        }
+        let mut data = vec![4];
+        data.extend(issuer.to_bytes());
+        data.extend(mint.to_bytes());
+        padded(&mut data, "Soak", 32);
+        padded(&mut data, "SOAK", 10);
+        padded(&mut data, "", 200);
+        data.extend(0u16.to_le_bytes());
+        data.extend([1, 1, 0, 0, 0]);
+        data.extend(issuer.to_bytes());
+        data.extend([1, 100, 0, 1, 0, 1, 2, 0, 0, 0, 0]);
+        Account { lamports: 5_616_720, data, owner: mpl_token_metadata::ID, executable: false, rent_epoch: 0 }
+    }
+
+    /// Push `SOAK_RECORDS` records through every bounded cache and store and check each stays
+    /// within its limit while it runs, printing usage every `SOAK_SAMPLE_EVERY` records
+    ///
+    /// Covers a send context's recipients, the issuer and mint creation caches, the wallet
+    /// freshness cache, the rotating action log, the archiving outbox, and with `--features
+    /// sqlite` the compacted local ledger. Runs against the mock cluster in a temporary
+    /// directory; about a minute in release mode, and ten with the ledger, which commits each
+    /// record on its own:
+    ///
+    ///     cargo test --release --features sqlite --lib resources::tests::soak -- --ignored --nocapture
+    #[tokio::test(flavor = "multi_thread")]
+    #[ignore = "soak test; run in release mode"]
+    async fn soak_every_bounded_cache_and_store_levels_off() {
+        let cluster = MockCluster::new();
+        let dir = TempDir::new("soak");
+        let mint = cluster.add_mint(&spl_token::id(), 6, &Pubkey::new_unique());
+        // Each issuer has issued one mint, each with one transaction
+        cluster.respond("getProgramAccounts", |params| {
+            // The creator filter's raw bytes
+            let creator = params[1]["filters"].as_array()?.iter().find_map(|filter| {
+                let bytes: Vec<u8> = serde_json::from_value(filter["memcmp"]["bytes"].clone()).ok()?;
+                Pubkey::try_from(bytes.as_slice()).ok()
+            })?;
+            let address = Pubkey::new_unique();
+            let account = issued_metadata(&creator, &Pubkey::new_unique());
+            Some(Ok(serde_json::json!([{ "pubkey": address.to_string(), "account": encode_account(&address, Some(&account)) }])))
+        });
+        cluster.respond("getSignaturesForAddress", |_| {
+            Some(Ok(serde_json::json!([{
+                "signature": Signature::new_unique().to_string(),
+                "slot": 1,
+                "err": null,
+                "memo": null,
+                "blockTime": 1_700_000_000,
+                "confirmationStatus": "finalized",
+            }])))
+        });
+
+        let rotation = RotationPolicy { max_bytes: 2 * 1024 * 1024, retained_segments: 3 };
+        let limits = ResourceLimits {
+            wallet_cache: CacheLimit::entries(2_000),
+            issuance_cache: CacheLimit::entries(1_000).with_max_bytes(256 * 1024),
+            send_context_recipients: CacheLimit::entries(5_000),
+            action_log_rotation: Some(rotation),
+            outbox_archive_after_days: Some(0),
+            ..ResourceLimits::default()
+        };
+        let client = Arc::new(cluster.client(
+            FinternetConfig {
+                outbox_path: Some(dir.join("outbox.json")),
+                action_log_path: Some(dir.join("actions.jsonl")),
+                ..FinternetConfig::default()
+            }
+            .with_resource_limits(limits),
+        ));
+        let context = client.prepare_send_context(&mint).unwrap();
+        let (outbox, log) = (client.outbox.as_ref().unwrap(), client.action_log.as_ref().unwrap());
+        #[cfg(feature = "sqlite")]
+        let (ledger, retention, owners) = (
+            crate::local_ledger::LocalLedger::open(&dir.join("ledger.db")).unwrap(),
+            crate::local_ledger::LedgerRetention { max_records_per_owner: Some(1_000) },
+            [Pubkey::new_unique(), Pubkey::new_unique()],
+        );
+
+        let started = std::time::Instant::now();
+        let payer = Keypair::new();
+        let mut recipients = Vec::new();
+        for i in 0..SOAK_RECORDS {
+            recipients.push(Keypair::new().pubkey());
+            if recipients.len() == 50 {
+                client.prefetch_recipients(&context, &recipients).unwrap();
+                recipients.clear();
+            }
+            client.get_issued_assets_page(&Pubkey::new_unique(), 0, 1).await.unwrap();
+            client.get_wallet_info_with_freshness(&Pubkey::new_unique(), Freshness::Cached).await.unwrap();
+            log.append("soak_payment", serde_json::json!({ "i": i }), Some(format!("soak-{}", i)), None, None, Vec::new())
+                .unwrap();
+            let transfer = system_instruction::transfer(&payer.pubkey(), &Pubkey::new_unique(), i as u64 + 1);
+            let transaction = Transaction::new_signed_with_payer(&[transfer], Some(&payer.pubkey()), &[&payer], Hash::new_unique());
+            let signature = outbox.record_submitted("soak", &transaction).unwrap();
+            outbox.mark(&signature, OutboxStatus::Confirmed, None).unwrap();
+            #[cfg(feature = "sqlite")]
+            {
+                let owner = owners[i % owners.len()];
+                let record = crate::TransactionRecord {
+                    signature,
+                    from: owner,
+                    to: Pubkey::new_unique(),
+                    amount: i as u64 + 1,
+                    currency: crate::Currency::Sol,
+                    timestamp: 1_700_000_000 + i as u64,
+                    memo: None,
+                    fee: 5_000,
+                    slot: i as u64,
+                    memo_fields: HashMap::new(),
+                };
+                ledger.insert_records(&owner, vec![record]).unwrap();
+            }
+
+            if (i + 1) % SOAK_SAMPLE_EVERY != 0 {
+                continue;
+            }
+            let usage = client.resource_usage();
+            let recipients = context.recipient_usage();
+            let (log_usage, outbox_usage) = (usage.action_log.unwrap(), usage.outbox.unwrap());
+            println!(
+                "{:>7} | recipients {:>5} | issuers {:>5} {:>7}B | creations {:>5} | wallets {:>5} | log {:>8}B + {} segments {:>8}B | outbox {:>4} live, {:>9}B archived",
+                i + 1,
+                recipients.entries,
+                usage.issuer_cache.entries,
+                usage.issuer_cache.bytes,
+                usage.mint_creation_cache.entries,
+                usage.wallet_cache.entries,
+                log_usage.bytes,
+                log_usage.archived_files,
+                log_usage.archived_bytes,
+                outbox_usage.entries,
+                outbox_usage.archived_bytes,
+            );
+            for (cache, usage, limit) in [
+                ("recipients", recipients, limits.send_context_recipients),
+                ("issuers", usage.issuer_cache, limits.issuance_cache),
+                ("creations", usage.mint_creation_cache, limits.issuance_cache),
+                ("wallets", usage.wallet_cache, limits.wallet_cache),
+            ] {
+                assert!(usage.entries <= limit.max_entries, "{} holds {} entries", cache, usage.entries);
+                assert!(limit.max_bytes.is_none_or(|max| usage.bytes <= max), "{} holds {} bytes", cache, usage.bytes);
+                assert!(usage.evictions > 0, "{} never reached its limit", cache);
+            }
+            // A segment is rotated out once it passes `max_bytes`, so it ends at most one entry over
+            let disk_bound = rotation.max_bytes * (rotation.retained_segments as u64 + 1) * 2;
+            assert!(log_usage.bytes + log_usage.archived_bytes <= disk_bound, "action log at {} bytes", log_usage.bytes + log_usage.archived_bytes);
+            assert!(log_usage.archived_files <= rotation.retained_segments);
+            // Only entries settled within the current second are still live
+            assert!(outbox_usage.entries < SOAK_SAMPLE_EVERY, "outbox holds {} live entries", outbox_usage.entries);
+            #[cfg(feature = "sqlite")]
+            {
+                ledger.compact(&retention).unwrap();
+                let kept = ledger.usage().unwrap().entries;
+                println!("        | ledger {} records, {} bytes", kept, file_bytes(&dir.join("ledger.db")));
+                assert!(kept <= owners.len() * retention.max_records_per_owner.unwrap());
+            }
+        }
+        println!("⏱️  {:.1?}", started.elapsed());
+
+        // Nothing was lost on the way: every action verifies, and every settled payment is archived
+        let verification = verify_action_log(&dir.join("actions.jsonl")).unwrap();
+        assert_eq!(verification.entries + verification.pruned, SOAK_RECORDS as u64);
+        std::thread::sleep(std::time::Duration::from_millis(1_100));
+        outbox.archive_settled().unwrap();
+        assert!(outbox.entries().unwrap().is_empty());
+        let archived = std::fs::read_to_string(outbox.archive_path()).unwrap();
+        assert_eq!(archived.lines().count(), SOAK_RECORDS);
+    }
+}
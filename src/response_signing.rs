@@ -0,0 +1,180 @@
+//! Signatures over API response bodies, so partners can prove what the server returned
+//!
+//! The server signs the SHA-256 of a canonical serialization of each JSON body, not the bytes
+//! it sent, so a body that is re-indented or has its keys reordered on the way still verifies.
+//! The signed text is `finternet-api-response-v1:<hex digest>`, signed with the server wallet in
+//! the Solana off-chain message format, which keeps it under the format's length limit however
+//! large the body is. The canonical form is:
+//!
+//! - no whitespace between tokens
+//! - object keys sorted by their UTF-8 bytes
+//! - integers in plain decimal, other numbers in the shortest form that reads back to the same
+//!   `f64`, never with an exponent (`0.1`, `100`, `1000000000000000000000`)
+//! - strings escaped only where JSON requires it: `"` and `\`, `\b \f \n \r \t`, other control
+//!   characters as lowercase `\u00xx`, and everything else, non-ASCII included, as raw UTF-8
+
+use crate::FinternetClient;
+use anyhow::{anyhow, Result};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use solana_sdk::{pubkey::Pubkey, signature::Keypair};
+
+pub const SIGNATURE_HEADER: &str = "X-Finternet-Signature";
+pub const SIGNER_HEADER: &str = "X-Finternet-Signer";
+
+const MESSAGE_PREFIX: &str = "finternet-api-response-v1:";
+
+/// `value` in the canonical form described above
+pub fn canonical_json(value: &Value) -> String {
+    let mut out = String::new();
+    write_canonical(value, &mut out);
+    out
+}
+
+fn write_canonical(value: &Value, out: &mut String) {
+    match value {
+        Value::Null => out.push_str("null"),
+        Value::Bool(b) => out.push_str(if *b { "true" } else { "false" }),
+        Value::Number(n) => {
+            if let Some(i) = n.as_u64() {
+                out.push_str(&i.to_string());
+            } else if let Some(i) = n.as_i64() {
+                out.push_str(&i.to_string());
+            } else {
+                // serde_json never holds a NaN or an infinity
+                out.push_str(&n.as_f64().unwrap_or(0.0).to_string());
+            }
+        }
+        Value::String(s) => out.push_str(&Value::String(s.clone()).to_string()),
+        Value::Array(items) => {
+            out.push('[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_canonical(item, out);
+            }
+            out.push(']');
+        }
+        Value::Object(fields) => {
+            let mut keys: Vec<&String> = fields.keys().collect();
+            keys.sort();
+            out.push('{');
+            for (i, key) in keys.into_iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                out.push_str(&Value::String(key.clone()).to_string());
+                out.push(':');
+                write_canonical(&fields[key], out);
+            }
+            out.push('}');
+        }
+    }
+}
+
+/// The text that is signed for a response body
+pub fn response_message(body: &Value) -> String {
+    let digest: String = Sha256::digest(canonical_json(body).as_bytes()).iter().map(|b| format!("{:02x}", b)).collect();
+    format!("{}{}", MESSAGE_PREFIX, digest)
+}
+
+impl FinternetClient {
+    /// Sign a response body for the `X-Finternet-Signature` header
+    pub fn sign_api_response(wallet: &Keypair, body: &Value) -> Result<String> {
+        Self::sign_offchain_message(wallet, &response_message(body))
+    }
+
+    /// Check the `X-Finternet-Signature` and `X-Finternet-Signer` headers against the body received
+    ///
+    /// An error means the body is not JSON or the signature is malformed; `Ok(false)` means it
+    /// does not match.
+    pub fn verify_api_response(body: &[u8], signature: &str, signer: &Pubkey) -> Result<bool> {
+        let body: Value = serde_json::from_slice(body).map_err(|e| anyhow!("Response body is not JSON: {}", e))?;
+        Self::verify_offchain_message(signer, &response_message(&body), signature)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use solana_sdk::signer::Signer;
+
+    #[test]
+    fn keys_are_sorted_by_their_bytes_at_every_depth_without_whitespace() {
+        let value: Value = serde_json::from_str(
+            r#"{ "b": 1, "a": { "z": [3, { "y": null, "x": true }], "Z": false }, "é": "", "A": [] }"#,
+        )
+        .unwrap();
+        assert_eq!(canonical_json(&value), r#"{"A":[],"a":{"Z":false,"z":[3,{"x":true,"y":null}]},"b":1,"é":""}"#);
+    }
+
+    #[test]
+    fn numbers_have_one_form_without_exponents() {
+        let cases = [
+            ("0", "0"),
+            ("-42", "-42"),
+            ("18446744073709551615", "18446744073709551615"),
+            ("-9223372036854775808", "-9223372036854775808"),
+            ("0.1", "0.1"),
+            ("1.50", "1.5"),
+            ("2.0", "2"),
+            ("1e21", "1000000000000000000000"),
+            ("1E-7", "0.0000001"),
+            ("-0.25", "-0.25"),
+        ];
+        for (written, canonical) in cases {
+            let value: Value = serde_json::from_str(written).unwrap();
+            assert_eq!(canonical_json(&value), canonical, "{}", written);
+        }
+    }
+
+    #[test]
+    fn strings_escape_only_what_json_requires_and_keep_unicode_raw() {
+        let memo = "Invoice №42 — café ☕ 支払い 🚀 \"quoted\" back\\slash\ttab\nline\u{1}\u{7f}";
+        assert_eq!(
+            canonical_json(&json!({ "memo": memo })),
+            "{\"memo\":\"Invoice №42 — café ☕ 支払い 🚀 \\\"quoted\\\" back\\\\slash\\ttab\\nline\\u0001\u{7f}\"}"
+        );
+        // However the sender escaped it, the same text canonicalizes the same way
+        let escaped: Value = serde_json::from_str(r#"{"memo":"café 🚀 \/"}"#).unwrap();
+        assert_eq!(canonical_json(&escaped), "{\"memo\":\"café 🚀 /\"}");
+    }
+
+    #[test]
+    fn a_signed_body_verifies_however_it_is_reformatted_but_not_once_changed() {
+        let server = Keypair::new();
+        let body = json!({
+            "transactions": [{ "signature": "5w2P", "amount": 1.5, "memo": "Zahlung für März ✓" }],
+            "total": 1,
+        });
+        let signature = FinternetClient::sign_api_response(&server, &body).unwrap();
+
+        let compact = serde_json::to_vec(&body).unwrap();
+        // Reordered, re-indented, and with the memo written as escapes
+        let reordered = r#"{ "total" : 1,
+            "transactions" : [ { "memo" : "Zahlung f\u00fcr M\u00e4rz \u2713", "amount" : 1.50, "signature" : "5w2P" } ] }"#;
+        assert!(FinternetClient::verify_api_response(&compact, &signature, &server.pubkey()).unwrap());
+        assert!(FinternetClient::verify_api_response(reordered.as_bytes(), &signature, &server.pubkey()).unwrap());
+
+        let tampered = serde_json::to_vec(&json!({
+            "transactions": [{ "signature": "5w2P", "amount": 15, "memo": "Zahlung für März ✓" }],
+            "total": 1,
+        }))
+        .unwrap();
+        assert!(!FinternetClient::verify_api_response(&tampered, &signature, &server.pubkey()).unwrap());
+        assert!(!FinternetClient::verify_api_response(&compact, &signature, &Keypair::new().pubkey()).unwrap());
+
+        assert!(FinternetClient::verify_api_response(b"not json", &signature, &server.pubkey()).is_err());
+        assert!(FinternetClient::verify_api_response(&compact, "not-a-signature", &server.pubkey()).is_err());
+    }
+
+    #[test]
+    fn the_signed_message_is_the_prefixed_digest_of_the_canonical_body() {
+        let body = json!({ "b": [1, 2], "a": "x" });
+        let digest = Sha256::digest(br#"{"a":"x","b":[1,2]}"#);
+        let hex: String = digest.iter().map(|b| format!("{:02x}", b)).collect();
+        assert_eq!(response_message(&body), format!("finternet-api-response-v1:{}", hex));
+    }
+}
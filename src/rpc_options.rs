@@ -0,0 +1,310 @@
+use crate::latency::{timed, Phase, PhaseClock};
+use crate::progress::ProgressEvent;
+use crate::telemetry::InstrumentedSender;
+use crate::{FinternetClient, FinternetConfig};
+use anyhow::{anyhow, Result};
+use solana_client::client_error::ClientError;
+use solana_rpc_client::http_sender::HttpSender;
+use solana_client::rpc_client::{RpcClient, RpcClientConfig};
+use solana_client::rpc_config::RpcSendTransactionConfig;
+use solana_client::rpc_request::RpcError;
+use solana_client::nonce_utils;
+use solana_sdk::{
+    commitment_config::{CommitmentConfig, CommitmentLevel},
+    hash::Hash,
+    pubkey::Pubkey,
+    signature::Signature,
+    transaction::{self, Transaction},
+};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+/// How long `wait_for_confirmation` polls when no timeout is given
+const DEFAULT_CONFIRMATION_TIMEOUT: Duration = Duration::from_secs(60);
+const CONFIRMATION_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Per-call overrides of the client's RPC defaults
+///
+/// Unset fields fall back to `FinternetConfig`. Build with `RpcCallOptions::default()` and the
+/// `with_*` methods, e.g. `RpcCallOptions::default().with_commitment(CommitmentConfig::finalized())`.
+#[derive(Debug, Clone, Default)]
+pub struct RpcCallOptions {
+    /// Commitment to read at, or to wait for when confirming a send
+    pub commitment: Option<CommitmentConfig>,
+    /// Bound on each RPC request for reads, and on the confirmation wait for sends
+    pub timeout: Option<Duration>,
+    /// How many times the RPC node rebroadcasts a send; `None` leaves it to the node
+    pub max_retries: Option<usize>,
+    /// Broadcast without simulating first, saving a round trip for high-throughput sends
+    pub skip_preflight: bool,
+    /// Whether sends wait for confirmation; `None` follows the operation's `ConfirmationPolicy`
+    pub wait: Option<bool>,
+    /// Sends confirm by then or are not broadcast at all; see `deadline`
+    pub deadline: Option<Instant>,
+    /// Where a send pipeline call records the time of its steps
+    pub(crate) phase_clock: Option<PhaseClock>,
+}
+
+impl RpcCallOptions {
+    pub fn with_commitment(mut self, commitment: CommitmentConfig) -> Self {
+        self.commitment = Some(commitment);
+        self
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    pub fn with_max_retries(mut self, max_retries: usize) -> Self {
+        self.max_retries = Some(max_retries);
+        self
+    }
+
+    pub fn with_skip_preflight(mut self, skip_preflight: bool) -> Self {
+        self.skip_preflight = skip_preflight;
+        self
+    }
+
+    pub fn with_wait(mut self, wait: bool) -> Self {
+        self.wait = Some(wait);
+        self
+    }
+
+    pub fn with_deadline(mut self, deadline: Instant) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// Options with only the commitment set, parsed from `processed`, `confirmed`, or `finalized`
+    pub fn from_commitment_str(commitment: &str) -> Result<Self> {
+        Ok(Self::default().with_commitment(parse_commitment(commitment)?))
+    }
+
+    /// Whether sends can take the plain send-and-confirm path
+    fn is_default_send(&self) -> bool {
+        self.commitment.is_none()
+            && self.timeout.is_none()
+            && self.max_retries.is_none()
+            && !self.skip_preflight
+            && self.wait != Some(false)
+            && self.deadline.is_none()
+    }
+}
+
+fn parse_commitment(commitment: &str) -> Result<CommitmentConfig> {
+    let level = CommitmentLevel::from_str(commitment.trim())
+        .map_err(|_| anyhow!("Unknown commitment '{}', expected processed, confirmed, or finalized", commitment))?;
+    Ok(CommitmentConfig { commitment: level })
+}
+
+impl FinternetConfig {
+    /// `commitment_level` as a `CommitmentConfig`, falling back to `confirmed` when unparseable
+    pub fn commitment(&self) -> CommitmentConfig {
+        parse_commitment(&self.commitment_level).unwrap_or_else(|e| {
+            tracing::warn!("{}; using confirmed", e);
+            CommitmentConfig::confirmed()
+        })
+    }
+}
+
+impl FinternetClient {
+    /// The commitment a call should use: its override, else the configured level
+    pub(crate) fn commitment_for(&self, options: &RpcCallOptions) -> CommitmentConfig {
+        options.commitment.unwrap_or_else(|| self.config.commitment())
+    }
+
+    /// Commitment for RPC methods that reject `processed` (transactions, signature lists)
+    pub(crate) fn history_commitment_for(&self, options: &RpcCallOptions) -> CommitmentConfig {
+        let commitment = self.commitment_for(options);
+        if commitment.is_at_least_confirmed() {
+            commitment
+        } else {
+            CommitmentConfig::confirmed()
+        }
+    }
+
+    /// A short-lived RPC client honoring `options.timeout`, when one can be built
+    ///
+    /// Recording and replay clients are never swapped out, so the timeout is ignored for them.
+    pub(crate) fn scoped_rpc(&self, options: &RpcCallOptions) -> Option<RpcClient> {
+        let timeout = options.timeout?;
+        if self.config.record_rpc_to.is_some() || !self.config.rpc_url.starts_with("http") {
+            tracing::debug!("Ignoring per-call RPC timeout for recorded or replayed client");
+            return None;
+        }
+        Some(RpcClient::new_sender(
+            InstrumentedSender::new(HttpSender::new_with_timeout(&self.config.rpc_url, timeout)),
+            RpcClientConfig::with_commitment(self.commitment_for(options)),
+        ))
+    }
+
+    /// Send and confirm a transaction the outbox does not journal; simulated in dry-run mode
+    pub(crate) fn send_and_confirm_untracked(&self, label: &str, transaction: &Transaction) -> Result<Signature> {
+        if self.is_dry_run() {
+            return Err(self.simulate_instead(label, transaction));
+        }
+        Ok(self.client.send_and_confirm_transaction(transaction)?)
+    }
+
+    /// Hand a signed transaction to the node with the call's send settings
+    pub(crate) fn broadcast_with_options(&self, transaction: &Transaction, options: &RpcCallOptions) -> Result<Signature> {
+        let clock = options.phase_clock.as_ref();
+        if options.is_default_send() {
+            return timed(clock, Phase::Broadcast, || self.client.send_transaction(transaction).map_err(anyhow::Error::from));
+        }
+
+        let commitment = self.commitment_for(options);
+        timed(clock, Phase::Broadcast, || {
+            self.client.send_transaction_with_config(
+                transaction,
+                RpcSendTransactionConfig {
+                    skip_preflight: options.skip_preflight,
+                    preflight_commitment: Some(commitment.commitment),
+                    max_retries: options.max_retries,
+                    ..RpcSendTransactionConfig::default()
+                },
+            )
+            .map_err(anyhow::Error::from)
+        })
+    }
+
+    /// Wait for a transaction `broadcast_with_options` sent to reach the call's commitment,
+    /// unless `options.wait` is `Some(false)`
+    pub(crate) fn await_with_options(
+        &self,
+        transaction: &Transaction,
+        signature: &Signature,
+        options: &RpcCallOptions,
+    ) -> Result<()> {
+        let clock = options.phase_clock.as_ref();
+        if options.is_default_send() {
+            return timed(clock, Phase::Confirmation, || self.await_landing(transaction, signature));
+        }
+        if options.wait == Some(false) {
+            tracing::debug!("Not waiting for {} to confirm", signature);
+            return Ok(());
+        }
+        timed(clock, Phase::Confirmation, || self.confirm_signature(signature, options))
+    }
+
+    /// The wait of `RpcClient::send_and_confirm_transaction`, apart from its send so the two can
+    /// be timed separately: poll until `signature` has a status or the blockhash expires
+    ///
+    /// A transaction on a durable nonce, such as an approved proposal, has no blockhash to
+    /// expire, so it is watched by its nonce instead; see `await_nonce_landing`.
+    fn await_landing(&self, transaction: &Transaction, signature: &Signature) -> Result<()> {
+        if let Some(nonce_account) = durable_nonce_account(transaction) {
+            return self.await_nonce_landing(transaction, signature, &nonce_account);
+        }
+        let blockhash = transaction.message.recent_blockhash;
+        loop {
+            if let Some(result) = self.client.get_signature_status(signature)? {
+                return result.map_err(|e| ClientError::from(e).into());
+            }
+            if !self.client.is_blockhash_valid(&blockhash, CommitmentConfig::processed())? {
+                return Err(ClientError::from(RpcError::ForUser(
+                    "unable to confirm transaction. This can happen in situations such as transaction expiration \
+                     and insufficient fee-payer funds"
+                        .to_string(),
+                ))
+                .into());
+            }
+            std::thread::sleep(CONFIRMATION_POLL_INTERVAL);
+        }
+    }
+
+    /// Poll until `signature` has a status, its nonce moves on without it, or the default
+    /// confirmation timeout passes
+    ///
+    /// A nonce that no longer holds the transaction's blockhash was used by this transaction or
+    /// another one; the status is read once more so a landing between the two reads is not
+    /// taken for a lost transaction.
+    fn await_nonce_landing(&self, transaction: &Transaction, signature: &Signature, nonce_account: &Pubkey) -> Result<()> {
+        let nonce = transaction.message.recent_blockhash;
+        let started = Instant::now();
+        loop {
+            if let Some(result) = self.client.get_signature_status(signature)? {
+                return result.map_err(|e| ClientError::from(e).into());
+            }
+            if self.stored_nonce(nonce_account)? != Some(nonce) {
+                if let Some(result) = self.client.get_signature_status(signature)? {
+                    return result.map_err(|e| ClientError::from(e).into());
+                }
+                return Err(anyhow!(
+                    "Nonce account {} advanced without transaction {}, which can no longer land",
+                    nonce_account,
+                    signature
+                ));
+            }
+            if started.elapsed() >= DEFAULT_CONFIRMATION_TIMEOUT {
+                return Err(anyhow!(
+                    "Transaction {} did not land within {}s; its nonce is unused, so it may still land",
+                    signature,
+                    DEFAULT_CONFIRMATION_TIMEOUT.as_secs()
+                ));
+            }
+            std::thread::sleep(CONFIRMATION_POLL_INTERVAL);
+        }
+    }
+
+    /// The blockhash held by the nonce account, or `None` if it is gone or no longer a nonce
+    pub(crate) fn stored_nonce(&self, nonce_account: &Pubkey) -> Result<Option<Hash>> {
+        let Some(account) = self.client.get_account_with_commitment(nonce_account, CommitmentConfig::processed())?.value else {
+            return Ok(None);
+        };
+        Ok(nonce_utils::data_from_account(&account).ok().map(|data| data.blockhash()))
+    }
+
+    /// Wait until `signature` reaches the configured commitment, failing if it errors on-chain
+    pub async fn wait_for_confirmation(&self, signature: &Signature) -> Result<()> {
+        self.wait_for_confirmation_with_options(signature, &RpcCallOptions::default())
+            .await
+    }
+
+    /// `wait_for_confirmation` with a per-call commitment and timeout (60s by default)
+    #[tracing::instrument(skip_all, fields(signature = %signature, rpc = %self.rpc_endpoint()))]
+    pub async fn wait_for_confirmation_with_options(
+        &self,
+        signature: &Signature,
+        options: &RpcCallOptions,
+    ) -> Result<()> {
+        self.confirm_signature(signature, options)
+    }
+
+    fn confirm_signature(&self, signature: &Signature, options: &RpcCallOptions) -> Result<()> {
+        let commitment = self.commitment_for(options);
+        let timeout = options.timeout.unwrap_or(DEFAULT_CONFIRMATION_TIMEOUT);
+        let started = Instant::now();
+
+        loop {
+            if let Some(result) = self.client.get_signature_status_with_commitment(signature, commitment)? {
+                // Kept as a `ClientError` so callers can still tell on-chain failures apart
+                return result.map_err(|e| ClientError::from(e).into());
+            }
+            self.report_progress(|| ProgressEvent::AwaitingConfirmation {
+                signature: *signature,
+                waited_secs: started.elapsed().as_secs(),
+            });
+            if options.deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                return Err(self.deadline_exceeded(signature));
+            }
+            if started.elapsed() >= timeout {
+                return Err(anyhow!(
+                    "Transaction {} not {:?} after {}s",
+                    signature,
+                    commitment.commitment,
+                    timeout.as_secs()
+                ));
+            }
+            std::thread::sleep(CONFIRMATION_POLL_INTERVAL);
+        }
+    }
+}
+
+/// The nonce account a transaction advances first, when it is built on a durable nonce
+pub(crate) fn durable_nonce_account(transaction: &Transaction) -> Option<Pubkey> {
+    let instruction = transaction::uses_durable_nonce(transaction)?;
+    let index = *instruction.accounts.first()?;
+    transaction.message.account_keys.get(index as usize).copied()
+}
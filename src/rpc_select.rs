@@ -0,0 +1,173 @@
+//! Picking the fastest healthy RPC endpoint from a list of candidates
+//!
+//! The public devnet endpoint is slow from far away. `probe_rpc_endpoints` calls `getHealth`
+//! and `getSlot` on every candidate in parallel. It rejects nodes that fail either call or lag
+//! more than `MAX_SLOT_LAG` slots behind the best one. `auto_select_rpc` picks the fastest of
+//! the rest.
+
+use crate::events::SdkEvent;
+use crate::recording::{redact_secrets, redact_url};
+use crate::FinternetClient;
+use serde::{Deserialize, Serialize};
+use solana_client::rpc_client::{RpcClient, RpcClientConfig};
+use solana_rpc_client::http_sender::HttpSender;
+use solana_sdk::commitment_config::CommitmentConfig;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// Slots a node may trail the most advanced candidate by and still be chosen
+pub const MAX_SLOT_LAG: u64 = 50;
+
+/// Bound on each probe request; a node slower than this is no use anyway
+const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// What probing one candidate endpoint found
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RpcProbe {
+    pub url: String,
+    /// Round trip of the `getSlot` call, in milliseconds
+    pub latency_ms: Option<u64>,
+    pub slot: Option<u64>,
+    /// Slots behind the most advanced candidate
+    pub slot_lag: Option<u64>,
+    /// Why the endpoint was rejected, if it was
+    pub error: Option<String>,
+}
+
+impl RpcProbe {
+    pub fn is_usable(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+impl fmt::Display for RpcProbe {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let url = redact_url(&self.url);
+        match (&self.error, self.latency_ms, self.slot) {
+            (Some(error), _, _) => write!(f, "{}: rejected ({})", url, error),
+            (None, Some(latency), Some(slot)) => write!(
+                f,
+                "{}: {} ms, slot {} ({} behind)",
+                url,
+                latency,
+                slot,
+                self.slot_lag.unwrap_or(0)
+            ),
+            _ => write!(f, "{}: no data", url),
+        }
+    }
+}
+
+impl FinternetClient {
+    /// Probe every candidate in parallel, fastest usable endpoint first
+    pub fn probe_rpc_endpoints(candidates: &[String]) -> Vec<RpcProbe> {
+        let mut probes: Vec<RpcProbe> = std::thread::scope(|scope| {
+            let handles: Vec<_> = candidates
+                .iter()
+                .map(|url| scope.spawn(move || probe(url)))
+                .collect();
+            handles
+                .into_iter()
+                .zip(candidates)
+                .map(|(handle, url)| {
+                    handle.join().unwrap_or_else(|_| RpcProbe {
+                        url: url.clone(),
+                        latency_ms: None,
+                        slot: None,
+                        slot_lag: None,
+                        error: Some("probe panicked".to_string()),
+                    })
+                })
+                .collect()
+        });
+
+        if let Some(best) = probes.iter().filter_map(|probe| probe.slot).max() {
+            for probe in probes.iter_mut() {
+                let Some(slot) = probe.slot else { continue };
+                let lag = best.saturating_sub(slot);
+                probe.slot_lag = Some(lag);
+                if lag > MAX_SLOT_LAG && probe.error.is_none() {
+                    probe.error = Some(format!("{} slots behind the best node", lag));
+                }
+            }
+        }
+        probes.sort_by_key(|probe| (!probe.is_usable(), probe.latency_ms.unwrap_or(u64::MAX)));
+        probes
+    }
+
+    /// The fastest healthy, up-to-date candidate
+    ///
+    /// Falls back to the first candidate when none is usable, and to the default devnet endpoint
+    /// when the list is empty, so a construction-time selection never leaves the client without
+    /// a URL.
+    pub fn auto_select_rpc(candidates: &[String]) -> String {
+        let probes = Self::probe_rpc_endpoints(candidates);
+        match probes.iter().find(|probe| probe.is_usable()) {
+            Some(best) => {
+                tracing::info!("Selected RPC endpoint {}", best);
+                best.url.clone()
+            }
+            None => {
+                let fallback = candidates
+                    .first()
+                    .cloned()
+                    .unwrap_or_else(|| crate::FinternetConfig::default().rpc_url);
+                tracing::warn!("No candidate RPC endpoint is healthy; using {}", redact_url(&fallback));
+                fallback
+            }
+        }
+    }
+
+    /// Probe `config.rpc_candidates` again and move the client to the winner if it changed
+    ///
+    /// Meant for callers that see a run of RPC failures: the node chosen at startup may have
+    /// degraded since. Returns the URL now in use. Recording and replay clients are left alone.
+    pub fn reselect_rpc(&mut self) -> String {
+        if self.config.rpc_candidates.is_empty()
+            || self.config.record_rpc_to.is_some()
+            || !self.config.rpc_url.starts_with("http")
+        {
+            return self.config.rpc_url.clone();
+        }
+        let selected = Self::auto_select_rpc(&self.config.rpc_candidates);
+        if selected != self.config.rpc_url {
+            tracing::info!(
+                "Switching RPC endpoint from {} to {}",
+                redact_url(&self.config.rpc_url),
+                redact_url(&selected)
+            );
+            let from = redact_url(&self.config.rpc_url);
+            self.config.rpc_url = selected.clone();
+            self.client = crate::rpc_client_for(&self.config);
+            self.emit(|| SdkEvent::RpcFailover { from, to: redact_url(&selected) });
+        }
+        selected
+    }
+}
+
+fn probe(url: &str) -> RpcProbe {
+    let client = RpcClient::new_sender(
+        HttpSender::new_with_timeout(url, PROBE_TIMEOUT),
+        RpcClientConfig::with_commitment(CommitmentConfig::confirmed()),
+    );
+    let mut result = RpcProbe {
+        url: url.to_string(),
+        latency_ms: None,
+        slot: None,
+        slot_lag: None,
+        error: None,
+    };
+    if let Err(e) = client.get_health() {
+        result.error = Some(format!("unhealthy: {}", redact_secrets(&e.to_string(), url)));
+        return result;
+    }
+    let started = Instant::now();
+    match client.get_slot() {
+        Ok(slot) => {
+            result.latency_ms = Some(started.elapsed().as_millis() as u64);
+            result.slot = Some(slot);
+        }
+        Err(e) => result.error = Some(format!("getSlot failed: {}", redact_secrets(&e.to_string(), url))),
+    }
+    result
+}
@@ -0,0 +1,317 @@
+//! Sending many payments of one mint without repeating the lookups behind each one
+//!
+//! `send_payment` reads the recipient's account before every transfer, to tell a wallet from a
+//! token account. A `SendContext` from `prepare_send_context` reads the mint once and remembers
+//! each recipient it has seen, along with whether the recipient's ATA exists. Once a recipient
+//! is known, a payment to it costs only the broadcast and its confirmation. The blockhash and
+//! priority fee samples come from the client's shared caches, which are refetched every few
+//! seconds rather than once per send. `prefetch_recipients` reads a whole list of recipients in
+//! a few `getMultipleAccounts` calls. Batch payments and sweeps both send through a context,
+//! and every payment sent with one goes through the send pipeline.
+
+use crate::instructions::{memo_instruction, token_transfer_checked_instruction};
+use crate::payment::{classify_recipient, RecipientAccount};
+use crate::pipeline::{SendBody, SendRequest};
+use crate::resources::{CacheUsage, LruMap};
+use crate::telemetry::readable;
+use crate::FinternetClient;
+use anyhow::{anyhow, Result};
+use solana_sdk::{
+    account::Account,
+    instruction::Instruction,
+    program_pack::Pack,
+    pubkey::Pubkey,
+    signature::{Keypair, Signature},
+    signer::Signer,
+};
+use spl_associated_token_account::{get_associated_token_address, instruction as ata_instruction};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Recipients read per `getMultipleAccounts` call, each with its ATA, within the RPC maximum of 100
+const RECIPIENTS_PER_FETCH: usize = 50;
+
+/// What a context knows about one recipient
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum KnownRecipient {
+    /// Already a token account of the mint, paid directly
+    TokenAccount,
+    /// A wallet, paid through its ATA, which may need creating first
+    Wallet { ata_exists: bool },
+}
+
+/// Lookups for payments of one mint, done once and shared by every payment sent with them
+///
+/// A context can be used from several tasks at once, and clones share what they know. It is
+/// never refreshed on its own: a recipient whose payment fails is forgotten and read again next
+/// time, and an ATA the context created is remembered as existing. Past
+/// `ResourceLimits::send_context_recipients`, the least recently paid recipients are forgotten.
+#[derive(Debug, Clone)]
+pub struct SendContext {
+    mint: Pubkey,
+    decimals: u8,
+    recipients: Arc<Mutex<LruMap<Pubkey, KnownRecipient>>>,
+    lookups: Arc<AtomicU64>,
+}
+
+impl SendContext {
+    pub fn mint(&self) -> Pubkey {
+        self.mint
+    }
+
+    pub fn decimals(&self) -> u8 {
+        self.decimals
+    }
+
+    /// RPC reads the context has made, preparing it included; blockhash and fee reads are not counted
+    pub fn lookups(&self) -> u64 {
+        self.lookups.load(Ordering::Relaxed)
+    }
+
+    /// Recipients whose accounts the context has read and not forgotten
+    pub fn known_recipients(&self) -> usize {
+        self.recipients.lock().map(|recipients| recipients.len()).unwrap_or(0)
+    }
+
+    pub fn recipient_usage(&self) -> CacheUsage {
+        self.recipients.lock().map(|recipients| recipients.usage()).unwrap_or_default()
+    }
+
+    fn known(&self, to: &Pubkey) -> Result<Option<KnownRecipient>> {
+        let mut recipients = self.recipients.lock().map_err(|_| anyhow!("Send context lock poisoned"))?;
+        Ok(recipients.get(to).copied())
+    }
+
+    fn remember(&self, to: Pubkey, recipient: KnownRecipient) -> Result<()> {
+        let mut recipients = self.recipients.lock().map_err(|_| anyhow!("Send context lock poisoned"))?;
+        recipients.insert(to, recipient, std::mem::size_of::<(Pubkey, KnownRecipient)>());
+        Ok(())
+    }
+
+    /// The accounts every payment from `from` in the context's mint writes, so one priority fee
+    /// sample serves them all
+    pub(crate) fn fee_accounts(&self, from: &Pubkey) -> [Pubkey; 2] {
+        [*from, get_associated_token_address(from, &self.mint)]
+    }
+
+    /// Update what is known about `to` once a payment to it has been sent
+    pub(crate) fn settle<T>(&self, to: &Pubkey, outcome: &Result<T>) {
+        let Ok(mut recipients) = self.recipients.lock() else {
+            return;
+        };
+        match outcome {
+            Ok(_) => {
+                if let Some(KnownRecipient::Wallet { ata_exists: false }) = recipients.get(to) {
+                    let created = KnownRecipient::Wallet { ata_exists: true };
+                    recipients.insert(*to, created, std::mem::size_of::<(Pubkey, KnownRecipient)>());
+                }
+            }
+            Err(_) => {
+                recipients.remove(to);
+            }
+        }
+    }
+
+    /// `to` read from its account and its ATA's, neither of which need exist
+    fn classify(&self, to: &Pubkey, account: Option<&Account>, ata: Option<&Account>) -> Result<KnownRecipient> {
+        Ok(match classify_recipient(to, &self.mint, account)? {
+            RecipientAccount::TokenAccount(_) => KnownRecipient::TokenAccount,
+            RecipientAccount::Wallet => KnownRecipient::Wallet { ata_exists: ata.is_some() },
+        })
+    }
+}
+
+impl FinternetClient {
+    /// Read `mint` once for a run of payments in it; see `send_payment_with_context`
+    ///
+    /// The mint is checked against the client's cluster as `send_payment` would.
+    pub fn prepare_send_context(&self, mint: &Pubkey) -> Result<SendContext> {
+        self.check_mint_network(mint)?;
+        let account = self
+            .client
+            .get_account_with_commitment(mint, self.client.commitment())?
+            .value
+            .ok_or_else(|| anyhow!("Mint {} does not exist", mint))?;
+        if account.owner != spl_token::id() {
+            return Err(anyhow!("{} is not an SPL Token mint", mint));
+        }
+        let decimals = spl_token::state::Mint::unpack(&account.data)
+            .map_err(|e| anyhow!("{} is not an SPL Token mint: {}", mint, e))?
+            .decimals;
+        Ok(SendContext {
+            mint: *mint,
+            decimals,
+            recipients: Arc::new(Mutex::new(LruMap::new(self.config.resource_limits.send_context_recipients))),
+            lookups: Arc::new(AtomicU64::new(1)),
+        })
+    }
+
+    /// Read every recipient in `recipients` the context does not know yet, a few calls for all
+    ///
+    /// Recipients that could not be paid, such as token accounts of another mint, are left
+    /// unknown; their payment reports why.
+    pub fn prefetch_recipients(&self, context: &SendContext, recipients: &[Pubkey]) -> Result<()> {
+        let mut unknown = Vec::new();
+        for to in recipients {
+            if context.known(to)?.is_none() && !unknown.contains(to) {
+                unknown.push(*to);
+            }
+        }
+        for chunk in unknown.chunks(RECIPIENTS_PER_FETCH) {
+            let keys: Vec<Pubkey> = chunk
+                .iter()
+                .flat_map(|to| [*to, get_associated_token_address(to, &context.mint)])
+                .collect();
+            let accounts = self.client.get_multiple_accounts_with_commitment(&keys, self.client.commitment())?.value;
+            context.lookups.fetch_add(1, Ordering::Relaxed);
+            for (to, pair) in chunk.iter().zip(accounts.chunks(2)) {
+                match context.classify(to, pair[0].as_ref(), pair.get(1).and_then(Option::as_ref)) {
+                    Ok(recipient) => context.remember(*to, recipient)?,
+                    Err(e) => tracing::debug!("Not caching recipient {}: {}", readable(to), e),
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// What `context` knows about `to`, read now if it knows nothing yet
+    pub(crate) fn context_recipient(&self, context: &SendContext, to: &Pubkey) -> Result<KnownRecipient> {
+        if let Some(recipient) = context.known(to)? {
+            return Ok(recipient);
+        }
+        let keys = [*to, get_associated_token_address(to, &context.mint)];
+        let accounts = self.client.get_multiple_accounts_with_commitment(&keys, self.client.commitment())?.value;
+        context.lookups.fetch_add(1, Ordering::Relaxed);
+        let recipient = context.classify(to, accounts[0].as_ref(), accounts.get(1).and_then(Option::as_ref))?;
+        context.remember(*to, recipient)?;
+        Ok(recipient)
+    }
+
+    /// The token account payments to `to` land in, and the instruction creating it if needed
+    pub(crate) fn context_destination(
+        &self,
+        context: &SendContext,
+        payer: &Pubkey,
+        to: &Pubkey,
+    ) -> Result<(Pubkey, Option<Instruction>)> {
+        Ok(match self.context_recipient(context, to)? {
+            KnownRecipient::TokenAccount => (*to, None),
+            KnownRecipient::Wallet { ata_exists: true } => (get_associated_token_address(to, &context.mint), None),
+            KnownRecipient::Wallet { ata_exists: false } => (
+                get_associated_token_address(to, &context.mint),
+                Some(ata_instruction::create_associated_token_account_idempotent(payer, to, &context.mint, &spl_token::id())),
+            ),
+        })
+    }
+
+    /// The instructions of a payment of `amount` base units to `to`, before any priority fee
+    pub(crate) fn context_payment_instructions(
+        &self,
+        context: &SendContext,
+        from: &Pubkey,
+        to: &Pubkey,
+        amount: u64,
+        memo: Option<&str>,
+    ) -> Result<Vec<Instruction>> {
+        let (destination, create) = self.context_destination(context, from, to)?;
+        let mut instructions: Vec<Instruction> = create.into_iter().collect();
+        instructions.push(token_transfer_checked_instruction(from, &destination, amount, &context.mint, context.decimals)?);
+        instructions.extend(memo.map(|memo| memo_instruction(from, memo)));
+        Ok(instructions)
+    }
+
+    /// `send_payment` in the context's mint, reusing what `context` already knows
+    ///
+    /// Goes through the send pipeline like `send_payment`, so spending and asset policies are
+    /// checked for every payment.
+    #[tracing::instrument(skip_all, fields(wallet = %readable(&from_wallet.pubkey()), to = %readable(to_pubkey), mint = %context.mint, amount, signature = tracing::field::Empty))]
+    pub async fn send_payment_with_context(
+        &self,
+        context: &SendContext,
+        from_wallet: &Keypair,
+        to_pubkey: &Pubkey,
+        amount: u64,
+        memo: Option<&str>,
+    ) -> Result<Signature> {
+        let parameters = serde_json::json!({
+            "from": from_wallet.pubkey().to_string(),
+            "to": to_pubkey.to_string(),
+            "amount": amount,
+            "mint": context.mint.to_string(),
+            "memo": memo,
+        });
+        let request = SendRequest::payment(
+            "send_payment_with_context",
+            from_wallet.pubkey(),
+            *to_pubkey,
+            amount,
+            context.mint,
+            memo,
+            parameters,
+        )
+        .with_body(SendBody::Context(context.clone()));
+        let signature = self.send_through_pipeline(request, &[from_wallet]).await?.signature;
+        tracing::Span::current().record("signature", tracing::field::display(&signature));
+        Ok(signature)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_cluster::MockCluster;
+    use std::collections::BTreeMap;
+
+    /// RPC calls by method made while `run` sends
+    async fn calls_during<F: std::future::Future<Output = ()>>(cluster: &MockCluster, run: F) -> BTreeMap<String, usize> {
+        let before = cluster.calls().len();
+        run.await;
+        let mut counts = BTreeMap::new();
+        for method in &cluster.calls()[before..] {
+            *counts.entry(method.clone()).or_default() += 1;
+        }
+        counts
+    }
+
+    /// The benchmark example's run, counted rather than timed: 20 payments cycling through 4
+    /// new recipients, sent one by one and then through a context
+    #[tokio::test(flavor = "multi_thread")]
+    async fn a_known_recipient_costs_only_the_broadcast_and_its_confirmation() {
+        let cluster = MockCluster::new();
+        cluster.execute_sends();
+        let payer = Keypair::new();
+        let mint = cluster.add_mint(&spl_token::id(), 6, &Pubkey::new_unique());
+        cluster.set_balance(payer.pubkey(), 1_000_000_000);
+        cluster.add_token_account(&payer.pubkey(), &mint, 10_000_000);
+        let client = cluster.default_client();
+
+        let recipients: Vec<Pubkey> = (0..4).map(|_| Keypair::new().pubkey()).collect();
+        let plain = calls_during(&cluster, async {
+            for i in 0..20 {
+                client.send_payment(&payer, &recipients[i % 4], i as u64 + 1, &mint, None).await.unwrap();
+            }
+        })
+        .await;
+        // One recipient read per payment; the first also reads the genesis hash, version, and blockhash
+        assert_eq!((plain["getAccountInfo"], plain["sendTransaction"], plain["getSignatureStatuses"]), (20, 20, 20));
+        assert_eq!(plain.values().sum::<usize>(), 63);
+
+        let recipients: Vec<Pubkey> = (0..4).map(|_| Keypair::new().pubkey()).collect();
+        let with_context = calls_during(&cluster, async {
+            let context = client.prepare_send_context(&mint).unwrap();
+            client.prefetch_recipients(&context, &recipients).unwrap();
+            for i in 0..20 {
+                client.send_payment_with_context(&context, &payer, &recipients[i % 4], i as u64 + 1, None).await.unwrap();
+            }
+        })
+        .await;
+        let expected = [("getAccountInfo", 1), ("getMultipleAccounts", 1), ("getSignatureStatuses", 20), ("sendTransaction", 20)];
+        assert_eq!(with_context, expected.map(|(method, count)| (method.to_string(), count)).into());
+        // Each new recipient's ATA is created by its first payment only
+        let creates = cluster.sent()[20..]
+            .iter()
+            .filter(|transaction| transaction.message.account_keys.contains(&spl_associated_token_account::id()))
+            .count();
+        assert_eq!(creates, 4);
+    }
+}
@@ -0,0 +1,199 @@
+//! The API server's settings file, re-read while it runs
+//!
+//! `ServerConfig::load` parses and validates the whole file before anything uses it, so a
+//! reload either applies every setting or none. `bind_address` and `wallet_path` are read once
+//! at startup; a reload that changes them keeps the running values and reports them through
+//! `ignored_changes`.
+
+use crate::policy::SpendingPolicy;
+use crate::validation::Violation;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use solana_sdk::pubkey::Pubkey;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Settings only read at startup, in the order `ignored_changes` reports them
+pub const NON_RELOADABLE: &[&str] = &["bind_address", "wallet_path"];
+
+/// Per-mint limits in base units, with mints and recipients as base58
+///
+/// Approval callbacks and organization roles need code, so only `SpendingPolicy` builders set
+/// them.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SpendingLimits {
+    #[serde(default)]
+    pub max_per_transaction: BTreeMap<String, u64>,
+    #[serde(default)]
+    pub max_per_day: BTreeMap<String, u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub allowed_recipients: Option<Vec<String>>,
+    #[serde(default)]
+    pub denied_recipients: Vec<String>,
+}
+
+/// Requests each API key, or each unauthenticated caller as one, may make per minute
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RateLimit {
+    pub requests_per_minute: u32,
+}
+
+/// Where the server POSTs each kind of webhook; unset ones fall back to their environment variable
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WebhookTargets {
+    pub invalidation: Option<String>,
+    pub deposit: Option<String>,
+    pub dead_letter_alert: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ServerConfig {
+    /// e.g. `127.0.0.1:3001`; not reloadable
+    pub bind_address: Option<String>,
+    /// Keypair file of the server wallet; not reloadable
+    pub wallet_path: Option<PathBuf>,
+    /// No limits when unset
+    pub spending: Option<SpendingLimits>,
+    /// Origins allowed to call the API from a browser; any origin when empty
+    #[serde(default)]
+    pub cors_origins: Vec<String>,
+    /// Unlimited when unset
+    pub rate_limit: Option<RateLimit>,
+    #[serde(default)]
+    pub webhooks: WebhookTargets,
+}
+
+/// Every problem found in a settings file; the file was not applied
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServerConfigError {
+    pub path: PathBuf,
+    pub violations: Vec<Violation>,
+}
+
+impl ServerConfigError {
+    pub fn code(&self) -> &'static str {
+        "invalid_server_config"
+    }
+}
+
+impl fmt::Display for ServerConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid server config {}: ", self.path.display())?;
+        for (i, violation) in self.violations.iter().enumerate() {
+            if i > 0 {
+                write!(f, "; ")?;
+            }
+            write!(f, "{}", violation)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ServerConfigError {}
+
+impl ServerConfig {
+    /// Read and validate `path`; a file that does not parse is one violation, `file`
+    pub fn load(path: &Path) -> Result<Self> {
+        let invalid = |message: String| ServerConfigError {
+            path: path.to_path_buf(),
+            violations: vec![Violation { field: "file".to_string(), message }],
+        };
+        let data = fs::read_to_string(path).map_err(|e| invalid(e.to_string()))?;
+        let config: Self = serde_json::from_str(&data).map_err(|e| invalid(e.to_string()))?;
+        let violations = config.violations();
+        if !violations.is_empty() {
+            return Err(ServerConfigError { path: path.to_path_buf(), violations }.into());
+        }
+        Ok(config)
+    }
+
+    /// Everything wrong with the settings, so one edit can fix them all
+    pub fn violations(&self) -> Vec<Violation> {
+        let mut violations = Vec::new();
+        let mut violate = |field: String, message: String| violations.push(Violation { field, message });
+
+        if let Some(address) = &self.bind_address {
+            if SocketAddr::from_str(address).is_err() {
+                violate("bind_address".to_string(), format!("'{}' is not a host:port socket address", address));
+            }
+        }
+        if let Some(spending) = &self.spending {
+            for (field, limits) in [("max_per_transaction", &spending.max_per_transaction), ("max_per_day", &spending.max_per_day)] {
+                for (mint, limit) in limits {
+                    if Pubkey::from_str(mint).is_err() {
+                        violate(format!("spending.{}", field), format!("'{}' is not a mint address", mint));
+                    }
+                    if *limit == 0 {
+                        violate(format!("spending.{}.{}", field, mint), "must be above 0; remove the mint to lift its limit".to_string());
+                    }
+                }
+            }
+            let recipients = spending.allowed_recipients.iter().flatten().map(|r| ("allowed_recipients", r));
+            for (field, recipient) in recipients.chain(spending.denied_recipients.iter().map(|r| ("denied_recipients", r))) {
+                if Pubkey::from_str(recipient).is_err() {
+                    violate(format!("spending.{}", field), format!("'{}' is not a wallet address", recipient));
+                }
+            }
+        }
+        for origin in &self.cors_origins {
+            let scheme = origin.strip_prefix("https://").or_else(|| origin.strip_prefix("http://"));
+            if !scheme.is_some_and(|host| !host.is_empty() && !host.contains('/')) {
+                violate("cors_origins".to_string(), format!("'{}' is not an origin such as https://app.example.com", origin));
+            }
+        }
+        if self.rate_limit.is_some_and(|limit| limit.requests_per_minute == 0) {
+            violate("rate_limit.requests_per_minute".to_string(), "must be above 0; remove rate_limit to lift it".to_string());
+        }
+        let webhooks = [
+            ("webhooks.invalidation", &self.webhooks.invalidation),
+            ("webhooks.deposit", &self.webhooks.deposit),
+            ("webhooks.dead_letter_alert", &self.webhooks.dead_letter_alert),
+        ];
+        for (field, url) in webhooks {
+            if let Some(url) = url {
+                if !(url.starts_with("https://") || url.starts_with("http://")) {
+                    violate(field.to_string(), format!("'{}' is not an http(s) URL", url));
+                }
+            }
+        }
+        violations
+    }
+
+    /// The `SpendingPolicy` the `spending` section describes; an empty one when it is unset
+    pub fn spending_policy(&self) -> Result<SpendingPolicy> {
+        let mut policy = SpendingPolicy::new();
+        let Some(spending) = &self.spending else {
+            return Ok(policy);
+        };
+        for (mint, limit) in &spending.max_per_transaction {
+            policy = policy.with_max_per_transaction(Pubkey::from_str(mint)?, *limit);
+        }
+        for (mint, limit) in &spending.max_per_day {
+            policy = policy.with_max_per_day(Pubkey::from_str(mint)?, *limit);
+        }
+        if let Some(allowed) = &spending.allowed_recipients {
+            // An empty list allows nobody, not everybody
+            policy.allowed_recipients = Some(allowed.iter().map(|r| Pubkey::from_str(r)).collect::<Result<_, _>>()?);
+        }
+        for recipient in &spending.denied_recipients {
+            policy = policy.deny_recipient(Pubkey::from_str(recipient)?);
+        }
+        Ok(policy)
+    }
+
+    /// Names of the `NON_RELOADABLE` settings that differ from `running`
+    pub fn ignored_changes(&self, running: &Self) -> Vec<&'static str> {
+        let mut changed = Vec::new();
+        if self.bind_address != running.bind_address {
+            changed.push(NON_RELOADABLE[0]);
+        }
+        if self.wallet_path != running.wallet_path {
+            changed.push(NON_RELOADABLE[1]);
+        }
+        changed
+    }
+}
@@ -0,0 +1,219 @@
+use crate::history::transfer_records;
+use crate::state_store::{legacy_map, FileStateStore, Namespace, StateStore};
+use crate::telemetry::readable;
+use crate::{Amount, FinternetClient, TransactionRecord};
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use solana_client::rpc_client::GetConfirmedSignaturesForAddress2Config;
+use solana_client::rpc_config::RpcTransactionConfig;
+use solana_sdk::{commitment_config::CommitmentConfig, pubkey::Pubkey, signature::Signature};
+use solana_transaction_status::UiTransactionEncoding;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+
+/// Solana Pay transfer request, rendered as a `solana:` URL for wallets to scan
+#[derive(Debug, Clone, PartialEq)]
+pub struct TransferRequest {
+    pub recipient: Pubkey,
+    /// Amount to pay, written to the URL in whole units of the token (e.g. `12.5` USDC)
+    pub amount: Option<Amount>,
+    /// SPL mint to pay in; native SOL when `None`
+    pub spl_token: Option<Pubkey>,
+    /// Extra read-only keys the paying transaction must include, used to find it afterwards
+    pub references: Vec<Pubkey>,
+    pub label: Option<String>,
+    pub message: Option<String>,
+    pub memo: Option<String>,
+}
+
+impl TransferRequest {
+    pub fn new(recipient: Pubkey) -> Self {
+        Self {
+            recipient,
+            amount: None,
+            spl_token: None,
+            references: Vec::new(),
+            label: None,
+            message: None,
+            memo: None,
+        }
+    }
+
+    pub fn to_url(&self) -> String {
+        let mut params = form_urlencoded::Serializer::new(String::new());
+        if let Some(amount) = &self.amount {
+            params.append_pair("amount", &amount.to_decimal_string());
+        }
+        if let Some(mint) = &self.spl_token {
+            params.append_pair("spl-token", &mint.to_string());
+        }
+        for reference in &self.references {
+            params.append_pair("reference", &reference.to_string());
+        }
+        for (key, value) in [("label", &self.label), ("message", &self.message), ("memo", &self.memo)] {
+            if let Some(value) = value {
+                params.append_pair(key, value);
+            }
+        }
+        let query = params.finish();
+
+        if query.is_empty() {
+            format!("solana:{}", self.recipient)
+        } else {
+            // Solana Pay wants %20 rather than the form encoding's `+`
+            format!("solana:{}?{}", self.recipient, query.replace('+', "%20"))
+        }
+    }
+}
+
+impl FinternetClient {
+    /// Earliest successful transaction carrying `reference`, as seen from `recipient`'s side
+    ///
+    /// Returns one record per mint the recipient's balance moved in; `None` until a transaction
+    /// with the reference has confirmed.
+    #[tracing::instrument(skip_all, fields(reference = %reference, wallet = %readable(recipient), rpc = %self.rpc_endpoint()))]
+    pub async fn find_payment_by_reference(
+        &self,
+        reference: &Pubkey,
+        recipient: &Pubkey,
+    ) -> Result<Option<(Signature, Vec<TransactionRecord>)>> {
+        let signatures = self.client.get_signatures_for_address_with_config(
+            reference,
+            GetConfirmedSignaturesForAddress2Config {
+                commitment: Some(CommitmentConfig::confirmed()),
+                ..GetConfirmedSignaturesForAddress2Config::default()
+            },
+        )?;
+
+        // Newest first; the first payment is the one that settles the request
+        for status in signatures.iter().rev().filter(|status| status.err.is_none()) {
+            let signature = Signature::from_str(&status.signature)?;
+            let transaction = self.client.get_transaction_with_config(
+                &signature,
+                RpcTransactionConfig {
+                    encoding: Some(UiTransactionEncoding::Json),
+                    commitment: Some(CommitmentConfig::confirmed()),
+                    max_supported_transaction_version: Some(0),
+                },
+            )?;
+            let records = transfer_records(recipient, signature, &transaction);
+            if !records.is_empty() {
+                tracing::info!("Reference {} settled by {}", reference, signature);
+                return Ok(Some((signature, records)));
+            }
+        }
+        Ok(None)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PaymentRequestStatus {
+    Pending,
+    Paid,
+    Expired,
+}
+
+/// A point-of-sale payment request awaiting a wallet payment to `recipient`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PaymentRequest {
+    pub id: String,
+    /// Unique key the paying transaction must reference
+    pub reference: Pubkey,
+    pub recipient: Pubkey,
+    pub mint: Pubkey,
+    /// Whole-unit amount shown to the payer, as exact decimal text such as `12.5`
+    #[serde(deserialize_with = "crate::payment::deserialize_amount_text")]
+    pub amount: String,
+    /// Amount in the mint's base units that counts as fully paid
+    pub base_units: u64,
+    pub currency: String,
+    pub label: Option<String>,
+    pub message: Option<String>,
+    pub url: String,
+    pub created_at: u64,
+    pub expires_at: u64,
+    pub status: PaymentRequestStatus,
+    /// Transfer that paid the request
+    pub settlement: Option<TransactionRecord>,
+}
+
+impl PaymentRequest {
+    /// Whether `record` pays this request in full in the right token
+    pub fn is_settled_by(&self, record: &TransactionRecord) -> bool {
+        record.to == self.recipient && record.currency.mint() == Some(self.mint) && record.amount >= self.base_units
+    }
+}
+
+/// Namespace payment requests are kept under in a `StateStore`
+pub const PAYMENT_REQUESTS_NAMESPACE: &str = "payment_requests";
+
+/// Persistent set of payment requests, keyed by id
+pub struct PaymentRequestStore {
+    requests: Namespace<PaymentRequest>,
+    /// Held across each read-then-write so concurrent transitions see each other
+    lock: Mutex<()>,
+}
+
+impl PaymentRequestStore {
+    /// Keep requests in their own file at `path`
+    pub fn open(path: &Path) -> Result<Self> {
+        let store = FileStateStore::open_registry(path, &legacy_map(PAYMENT_REQUESTS_NAMESPACE))?;
+        Ok(Self::new(Arc::new(store)))
+    }
+
+    pub fn new(store: Arc<dyn StateStore>) -> Self {
+        Self {
+            requests: Namespace::new(store, PAYMENT_REQUESTS_NAMESPACE),
+            lock: Mutex::new(()),
+        }
+    }
+
+    pub fn insert(&self, request: PaymentRequest) -> Result<()> {
+        let _guard = self.lock.lock().map_err(|_| anyhow!("Payment request store lock poisoned"))?;
+        self.requests.put(&request.id, &request)
+    }
+
+    pub fn get(&self, id: &str) -> Result<Option<PaymentRequest>> {
+        self.requests.get(id)
+    }
+
+    /// Pending requests whose expiry is at or before `now`
+    pub fn stale(&self, now: u64) -> Result<Vec<PaymentRequest>> {
+        Ok(self
+            .requests
+            .list()?
+            .into_iter()
+            .map(|(_, request)| request)
+            .filter(|request| request.status == PaymentRequestStatus::Pending && request.expires_at <= now)
+            .collect())
+    }
+
+    /// Record the settling transfer; a request that is no longer pending is left as it is
+    pub fn mark_paid(&self, id: &str, settlement: TransactionRecord) -> Result<Option<PaymentRequest>> {
+        self.transition(id, PaymentRequestStatus::Paid, Some(settlement))
+    }
+
+    pub fn mark_expired(&self, id: &str) -> Result<Option<PaymentRequest>> {
+        self.transition(id, PaymentRequestStatus::Expired, None)
+    }
+
+    fn transition(
+        &self,
+        id: &str,
+        status: PaymentRequestStatus,
+        settlement: Option<TransactionRecord>,
+    ) -> Result<Option<PaymentRequest>> {
+        let _guard = self.lock.lock().map_err(|_| anyhow!("Payment request store lock poisoned"))?;
+        let Some(mut request) = self.requests.get(id)? else {
+            return Ok(None);
+        };
+        if request.status == PaymentRequestStatus::Pending {
+            request.status = status;
+            request.settlement = settlement;
+            self.requests.put(id, &request)?;
+        }
+        Ok(Some(request))
+    }
+}
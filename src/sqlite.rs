@@ -0,0 +1,385 @@
+//! A small binding to the system SQLite library, for the stores that keep their data in it
+//!
+//! Built only with the `sqlite` feature, which links `libsqlite3` from the system. `Connection`
+//! runs statements with positional parameters and hands back every row at once, which is all
+//! the SDK's stores need. Connections are opened in serialized mode (`SQLITE_OPEN_FULLMUTEX`),
+//! and a library built without thread support (`SQLITE_THREADSAFE=0`) is refused, because the
+//! `Send` and `Sync` impls below rely on SQLite's own mutex. Each store still keeps its
+//! connection behind a lock so a read-then-write runs as one unit.
+
+use anyhow::{anyhow, Result};
+use std::ffi::{c_char, c_int, c_void, CStr, CString};
+use std::path::Path;
+use std::ptr;
+
+#[repr(C)]
+struct Sqlite3 {
+    _private: [u8; 0],
+}
+
+#[repr(C)]
+struct Sqlite3Stmt {
+    _private: [u8; 0],
+}
+
+const SQLITE_OK: c_int = 0;
+const SQLITE_ROW: c_int = 100;
+const SQLITE_DONE: c_int = 101;
+
+const SQLITE_INTEGER: c_int = 1;
+const SQLITE_FLOAT: c_int = 2;
+const SQLITE_TEXT: c_int = 3;
+const SQLITE_BLOB: c_int = 4;
+
+const SQLITE_OPEN_READWRITE: c_int = 0x0000_0002;
+const SQLITE_OPEN_CREATE: c_int = 0x0000_0004;
+const SQLITE_OPEN_FULLMUTEX: c_int = 0x0001_0000;
+
+/// SQLite copies the bound value before the call returns
+const SQLITE_TRANSIENT: isize = -1;
+
+/// How long a statement waits on another connection's lock before failing
+const BUSY_TIMEOUT_MS: c_int = 5_000;
+
+#[link(name = "sqlite3")]
+extern "C" {
+    fn sqlite3_threadsafe() -> c_int;
+    fn sqlite3_open_v2(filename: *const c_char, db: *mut *mut Sqlite3, flags: c_int, vfs: *const c_char) -> c_int;
+    fn sqlite3_close_v2(db: *mut Sqlite3) -> c_int;
+    fn sqlite3_errmsg(db: *mut Sqlite3) -> *const c_char;
+    fn sqlite3_busy_timeout(db: *mut Sqlite3, ms: c_int) -> c_int;
+    fn sqlite3_exec(
+        db: *mut Sqlite3,
+        sql: *const c_char,
+        callback: *const c_void,
+        arg: *mut c_void,
+        errmsg: *mut *mut c_char,
+    ) -> c_int;
+    fn sqlite3_free(ptr: *mut c_void);
+    fn sqlite3_changes(db: *mut Sqlite3) -> c_int;
+    fn sqlite3_prepare_v2(
+        db: *mut Sqlite3,
+        sql: *const c_char,
+        len: c_int,
+        stmt: *mut *mut Sqlite3Stmt,
+        tail: *mut *const c_char,
+    ) -> c_int;
+    fn sqlite3_bind_null(stmt: *mut Sqlite3Stmt, index: c_int) -> c_int;
+    fn sqlite3_bind_int64(stmt: *mut Sqlite3Stmt, index: c_int, value: i64) -> c_int;
+    fn sqlite3_bind_double(stmt: *mut Sqlite3Stmt, index: c_int, value: f64) -> c_int;
+    fn sqlite3_bind_text(stmt: *mut Sqlite3Stmt, index: c_int, value: *const c_char, len: c_int, destructor: isize) -> c_int;
+    fn sqlite3_step(stmt: *mut Sqlite3Stmt) -> c_int;
+    fn sqlite3_column_count(stmt: *mut Sqlite3Stmt) -> c_int;
+    fn sqlite3_column_type(stmt: *mut Sqlite3Stmt, column: c_int) -> c_int;
+    fn sqlite3_column_int64(stmt: *mut Sqlite3Stmt, column: c_int) -> i64;
+    fn sqlite3_column_double(stmt: *mut Sqlite3Stmt, column: c_int) -> f64;
+    fn sqlite3_column_text(stmt: *mut Sqlite3Stmt, column: c_int) -> *const u8;
+    fn sqlite3_column_bytes(stmt: *mut Sqlite3Stmt, column: c_int) -> c_int;
+    fn sqlite3_finalize(stmt: *mut Sqlite3Stmt) -> c_int;
+}
+
+/// A value bound to a parameter or read from a column
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum SqlValue {
+    Null,
+    Integer(i64),
+    Real(f64),
+    Text(String),
+}
+
+impl SqlValue {
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            Self::Integer(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Self::Text(value) => Some(value),
+            _ => None,
+        }
+    }
+}
+
+impl From<i64> for SqlValue {
+    fn from(value: i64) -> Self {
+        Self::Integer(value)
+    }
+}
+
+impl TryFrom<u64> for SqlValue {
+    type Error = anyhow::Error;
+
+    /// SQLite integers are signed 64-bit, so values past `i64::MAX` are refused, not wrapped
+    fn try_from(value: u64) -> Result<Self> {
+        i64::try_from(value)
+            .map(Self::Integer)
+            .map_err(|_| anyhow!("{} is past the largest integer SQLite stores", value))
+    }
+}
+
+impl From<&str> for SqlValue {
+    fn from(value: &str) -> Self {
+        Self::Text(value.to_string())
+    }
+}
+
+impl From<String> for SqlValue {
+    fn from(value: String) -> Self {
+        Self::Text(value)
+    }
+}
+
+impl<T: Into<SqlValue>> From<Option<T>> for SqlValue {
+    fn from(value: Option<T>) -> Self {
+        value.map_or(Self::Null, Into::into)
+    }
+}
+
+/// One open database file
+pub(crate) struct Connection {
+    db: *mut Sqlite3,
+}
+
+// SAFETY: `open` refuses a library built single-threaded and opens every connection with
+// SQLITE_OPEN_FULLMUTEX, so SQLite serializes all use of `db` behind its own mutex and the
+// handle may be used from, and moved to, any thread. Statements never outlive the `&self` call
+// that prepared them.
+unsafe impl Send for Connection {}
+// SAFETY: as for `Send`; every method takes `&self` and goes through SQLite's serialized mode
+unsafe impl Sync for Connection {}
+
+impl Connection {
+    /// Open the database at `path`, creating the file if it does not exist
+    pub fn open(path: &Path) -> Result<Self> {
+        if let Some(parent) = path.parent() {
+            if !parent.as_os_str().is_empty() {
+                std::fs::create_dir_all(parent)?;
+            }
+        }
+        // SAFETY: takes no arguments and only reports how the library was compiled
+        if unsafe { sqlite3_threadsafe() } == 0 {
+            return Err(anyhow!("The system SQLite library was built without thread support"));
+        }
+        let filename = CString::new(path.to_string_lossy().as_bytes())
+            .map_err(|_| anyhow!("Database path {} contains a NUL byte", path.display()))?;
+        let mut db = ptr::null_mut();
+        let flags = SQLITE_OPEN_READWRITE | SQLITE_OPEN_CREATE | SQLITE_OPEN_FULLMUTEX;
+        // SAFETY: `filename` is NUL-terminated and outlives the call, `db` is a valid out
+        // pointer, and a null VFS selects the default one. SQLite may set `db` even on failure,
+        // so it is wrapped right away for `Drop` to close.
+        let code = unsafe { sqlite3_open_v2(filename.as_ptr(), &mut db, flags, ptr::null()) };
+        let connection = Self { db };
+        if code != SQLITE_OK {
+            return Err(anyhow!("Cannot open database {}: {}", path.display(), connection.last_error()));
+        }
+        // SAFETY: `db` is the open handle returned above
+        unsafe { sqlite3_busy_timeout(db, BUSY_TIMEOUT_MS) };
+        Ok(connection)
+    }
+
+    /// Run one or more statements that take no parameters, e.g. a schema or `VACUUM`
+    pub fn execute_batch(&self, sql: &str) -> Result<()> {
+        let sql = CString::new(sql).map_err(|_| anyhow!("SQL contains a NUL byte"))?;
+        let mut message: *mut c_char = ptr::null_mut();
+        // SAFETY: `self.db` is open, `sql` is NUL-terminated and outlives the call, no callback
+        // is passed, and `message` is a valid out pointer SQLite fills with a string it allocated
+        let code = unsafe { sqlite3_exec(self.db, sql.as_ptr(), ptr::null(), ptr::null_mut(), &mut message) };
+        if code == SQLITE_OK {
+            return Ok(());
+        }
+        let error = if message.is_null() {
+            self.last_error()
+        } else {
+            // SAFETY: `message` is the NUL-terminated string `sqlite3_exec` allocated; it is
+            // copied out before being freed once, with `sqlite3_free` as the API requires
+            let text = unsafe { CStr::from_ptr(message) }.to_string_lossy().into_owned();
+            unsafe { sqlite3_free(message as *mut c_void) };
+            text
+        };
+        Err(anyhow!("SQLite error: {}", error))
+    }
+
+    /// Run one statement and return how many rows it changed
+    pub fn execute(&self, sql: &str, params: &[SqlValue]) -> Result<usize> {
+        self.run(sql, params, |_| Ok(()))?;
+        // SAFETY: `self.db` is open; the count is per connection and the caller holds the
+        // store's lock, so it belongs to the statement just run
+        Ok(unsafe { sqlite3_changes(self.db) }.max(0) as usize)
+    }
+
+    /// Run one statement and return every row it produced
+    pub fn query(&self, sql: &str, params: &[SqlValue]) -> Result<Vec<Vec<SqlValue>>> {
+        let mut rows = Vec::new();
+        self.run(sql, params, |row| {
+            rows.push(row);
+            Ok(())
+        })?;
+        Ok(rows)
+    }
+
+    /// Run `work` inside one transaction, committed if it succeeds and rolled back if not
+    pub fn transaction<T>(&self, work: impl FnOnce(&Self) -> Result<T>) -> Result<T> {
+        self.execute_batch("BEGIN IMMEDIATE")?;
+        match work(self) {
+            Ok(value) => {
+                self.execute_batch("COMMIT")?;
+                Ok(value)
+            }
+            Err(e) => {
+                let _ = self.execute_batch("ROLLBACK");
+                Err(e)
+            }
+        }
+    }
+
+    fn run(&self, sql: &str, params: &[SqlValue], mut on_row: impl FnMut(Vec<SqlValue>) -> Result<()>) -> Result<()> {
+        let statement = Statement::prepare(self, sql)?;
+        for (index, param) in params.iter().enumerate() {
+            statement.bind(self, index as c_int + 1, param)?;
+        }
+        loop {
+            // SAFETY: `statement` is prepared on this connection and fully bound
+            match unsafe { sqlite3_step(statement.stmt) } {
+                SQLITE_ROW => on_row(statement.row())?,
+                SQLITE_DONE => return Ok(()),
+                _ => return Err(anyhow!("SQLite error: {}", self.last_error())),
+            }
+        }
+    }
+
+    fn last_error(&self) -> String {
+        if self.db.is_null() {
+            return "out of memory".to_string();
+        }
+        // SAFETY: `self.db` is non-null, and `sqlite3_errmsg` returns a NUL-terminated string
+        // SQLite owns, valid until the next call on the connection; it is copied at once
+        unsafe { CStr::from_ptr(sqlite3_errmsg(self.db)) }.to_string_lossy().into_owned()
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        // SAFETY: closed once, here; `sqlite3_close_v2` accepts null and defers the close until
+        // any statement still alive is finalized
+        unsafe { sqlite3_close_v2(self.db) };
+    }
+}
+
+/// A prepared statement, finalized when dropped
+struct Statement {
+    stmt: *mut Sqlite3Stmt,
+}
+
+impl Statement {
+    fn prepare(connection: &Connection, sql: &str) -> Result<Self> {
+        let sql = CString::new(sql).map_err(|_| anyhow!("SQL contains a NUL byte"))?;
+        let mut stmt = ptr::null_mut();
+        // SAFETY: `connection.db` is open, `sql` is NUL-terminated (length -1) and outlives the
+        // call, and `stmt` is a valid out pointer; no tail pointer is asked for
+        let code = unsafe { sqlite3_prepare_v2(connection.db, sql.as_ptr(), -1, &mut stmt, ptr::null_mut()) };
+        if code != SQLITE_OK || stmt.is_null() {
+            return Err(anyhow!("SQLite error: {}", connection.last_error()));
+        }
+        Ok(Self { stmt })
+    }
+
+    fn bind(&self, connection: &Connection, index: c_int, value: &SqlValue) -> Result<()> {
+        let len = match value {
+            SqlValue::Text(value) => c_int::try_from(value.len()).map_err(|_| anyhow!("Parameter {} is too long for SQLite", index))?,
+            _ => 0,
+        };
+        // SAFETY: `self.stmt` is a live prepared statement. Text is passed with its exact byte
+        // length, so no NUL terminator is needed, and with SQLITE_TRANSIENT, so SQLite copies
+        // the bytes before returning; the borrowed `value` only has to live for this call.
+        let code = unsafe {
+            match value {
+                SqlValue::Null => sqlite3_bind_null(self.stmt, index),
+                SqlValue::Integer(value) => sqlite3_bind_int64(self.stmt, index, *value),
+                SqlValue::Real(value) => sqlite3_bind_double(self.stmt, index, *value),
+                SqlValue::Text(value) => sqlite3_bind_text(
+                    self.stmt,
+                    index,
+                    value.as_ptr() as *const c_char,
+                    len,
+                    SQLITE_TRANSIENT,
+                ),
+            }
+        };
+        if code != SQLITE_OK {
+            return Err(anyhow!("SQLite error binding parameter {}: {}", index, connection.last_error()));
+        }
+        Ok(())
+    }
+
+    /// The current row; only called right after `sqlite3_step` returned SQLITE_ROW
+    fn row(&self) -> Vec<SqlValue> {
+        // SAFETY: `self.stmt` is a live prepared statement positioned on a row
+        let columns = unsafe { sqlite3_column_count(self.stmt) };
+        (0..columns)
+            // SAFETY: `column` is within the row. The text pointer stays valid until the next
+            // step, reset, or finalize, or another conversion of the same column; `bytes` is read
+            // after `text` as SQLite advises, and the bytes are copied out before anything else.
+            .map(|column| unsafe {
+                match sqlite3_column_type(self.stmt, column) {
+                    SQLITE_INTEGER => SqlValue::Integer(sqlite3_column_int64(self.stmt, column)),
+                    SQLITE_FLOAT => SqlValue::Real(sqlite3_column_double(self.stmt, column)),
+                    SQLITE_TEXT | SQLITE_BLOB => {
+                        let text = sqlite3_column_text(self.stmt, column);
+                        let len = sqlite3_column_bytes(self.stmt, column).max(0) as usize;
+                        if text.is_null() {
+                            SqlValue::Text(String::new())
+                        } else {
+                            SqlValue::Text(String::from_utf8_lossy(std::slice::from_raw_parts(text, len)).into_owned())
+                        }
+                    }
+                    _ => SqlValue::Null,
+                }
+            })
+            .collect()
+    }
+}
+
+impl Drop for Statement {
+    fn drop(&mut self) {
+        // SAFETY: finalized once, here, and `prepare` only builds a `Statement` around a
+        // non-null handle
+        unsafe { sqlite3_finalize(self.stmt) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_cluster::TempDir;
+
+    #[test]
+    fn rows_round_trip_and_a_failed_transaction_rolls_back() {
+        let dir = TempDir::new("sqlite");
+        let connection = Connection::open(&dir.join("nested/test.db")).unwrap();
+        connection.execute_batch("CREATE TABLE t (k TEXT PRIMARY KEY, n INTEGER, note TEXT)").unwrap();
+        let changed = connection
+            .execute("INSERT INTO t VALUES (?, ?, ?)", &["a".into(), 7i64.into(), SqlValue::from(None::<&str>)])
+            .unwrap();
+        assert_eq!(changed, 1);
+
+        let failed: Result<()> = connection.transaction(|tx| {
+            tx.execute("INSERT INTO t VALUES (?, ?, ?)", &["b".into(), 1i64.into(), "it's".into()])?;
+            Err(anyhow!("abandoned"))
+        });
+        assert!(failed.is_err());
+
+        let rows = connection.query("SELECT k, n, note FROM t ORDER BY k", &[]).unwrap();
+        assert_eq!(rows, vec![vec![SqlValue::Text("a".to_string()), SqlValue::Integer(7), SqlValue::Null]]);
+        assert!(connection.execute("INSERT INTO t VALUES (?, ?, ?)", &["a".into(), 1i64.into(), SqlValue::Null]).is_err());
+    }
+
+    #[test]
+    fn integers_past_i64_max_are_refused_rather_than_wrapped() {
+        assert_eq!(SqlValue::try_from(i64::MAX as u64).unwrap(), SqlValue::Integer(i64::MAX));
+        assert!(SqlValue::try_from(i64::MAX as u64 + 1).is_err());
+        assert!(SqlValue::try_from(u64::MAX).is_err());
+    }
+}
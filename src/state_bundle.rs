@@ -0,0 +1,536 @@
+//! Portable bundle of local SDK state, for moving an integration between environments
+//!
+//! A bundle holds JSON state files (identity indexes, spending policy windows, asset policies,
+//! webhook and payment request stores, and so on) under their relative paths, plus a manifest
+//! with a checksum of each. Wallets never travel in a bundle: exporting one fails outright.
+
+use anyhow::{anyhow, Result};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use solana_sdk::signature::Keypair;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io::{Read, Write};
+use std::path::{Component, Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub const STATE_BUNDLE_VERSION: u32 = 1;
+
+/// JSON keys that only ever hold key material
+const SECRET_KEYS: &[&str] = &["secret_key", "private_key", "secretkey", "privatekey", "seed_phrase", "mnemonic"];
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    pub path: String,
+    /// Hex SHA-256 of the contents
+    pub sha256: String,
+    pub size: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StateManifest {
+    pub version: u32,
+    /// SDK version that wrote the bundle
+    pub sdk_version: String,
+    pub created_at: u64,
+    pub files: Vec<ManifestEntry>,
+}
+
+/// Local state files and their manifest, written to disk as gzipped JSON
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StateBundle {
+    pub manifest: StateManifest,
+    /// File contents by relative path, `/`-separated
+    pub files: BTreeMap<String, String>,
+}
+
+/// What applying a bundle does to one file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StateChangeKind {
+    Added,
+    Modified,
+    Unchanged,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StateChange {
+    pub path: String,
+    pub kind: StateChangeKind,
+}
+
+/// Why a bundle could not be exported or applied
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StateBundleError {
+    /// The file holds key material, e.g. a wallet keypair
+    SecretDetected { path: String, reason: String },
+    /// Absolute, or escaping the working directory with `..`
+    UnsafePath { path: String },
+    UnsupportedVersion { version: u32 },
+    /// Listed in the manifest but missing from the bundle, or the other way round
+    ManifestMismatch { path: String },
+    ChecksumMismatch { path: String },
+    /// A bundled file is not valid JSON
+    InvalidFile { path: String, reason: String },
+}
+
+impl StateBundleError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::SecretDetected { .. } => "secret_detected",
+            Self::UnsafePath { .. } => "unsafe_path",
+            Self::UnsupportedVersion { .. } => "unsupported_version",
+            Self::ManifestMismatch { .. } => "manifest_mismatch",
+            Self::ChecksumMismatch { .. } => "checksum_mismatch",
+            Self::InvalidFile { .. } => "invalid_file",
+        }
+    }
+}
+
+impl fmt::Display for StateBundleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::SecretDetected { path, reason } => {
+                write!(f, "Refusing to bundle {}: {}; state bundles never carry private keys", path, reason)
+            }
+            Self::UnsafePath { path } => write!(f, "{} must be a relative path inside the working directory", path),
+            Self::UnsupportedVersion { version } => write!(
+                f,
+                "State bundle version {} is not supported, expected {}",
+                version, STATE_BUNDLE_VERSION
+            ),
+            Self::ManifestMismatch { path } => write!(f, "{} does not match the bundle manifest", path),
+            Self::ChecksumMismatch { path } => write!(f, "{} does not match its manifest checksum", path),
+            Self::InvalidFile { path, reason } => write!(f, "{} is not valid JSON: {}", path, reason),
+        }
+    }
+}
+
+impl std::error::Error for StateBundleError {}
+
+impl StateBundle {
+    /// Check the version, paths, checksums, and contents of every file
+    pub fn validate(&self) -> Result<(), StateBundleError> {
+        if self.manifest.version != STATE_BUNDLE_VERSION {
+            return Err(StateBundleError::UnsupportedVersion { version: self.manifest.version });
+        }
+        if let Some(extra) = self
+            .files
+            .keys()
+            .find(|path| !self.manifest.files.iter().any(|entry| &entry.path == *path))
+        {
+            return Err(StateBundleError::ManifestMismatch { path: extra.clone() });
+        }
+        for entry in &self.manifest.files {
+            relative_path(&entry.path)?;
+            let contents = self
+                .files
+                .get(&entry.path)
+                .ok_or_else(|| StateBundleError::ManifestMismatch { path: entry.path.clone() })?;
+            if sha256_hex(contents) != entry.sha256 {
+                return Err(StateBundleError::ChecksumMismatch { path: entry.path.clone() });
+            }
+            check_contents(&entry.path, contents)?;
+        }
+        Ok(())
+    }
+
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(serde_json::to_string(self)?.as_bytes())?;
+        fs::write(path, encoder.finish()?)?;
+        Ok(())
+    }
+
+    pub fn read(path: &Path) -> Result<Self> {
+        let mut json = String::new();
+        GzDecoder::new(fs::File::open(path)?)
+            .read_to_string(&mut json)
+            .map_err(|e| anyhow!("{} is not a gzipped state bundle: {}", path.display(), e))?;
+        serde_json::from_str(&json).map_err(|e| anyhow!("Corrupt state bundle {}: {}", path.display(), e))
+    }
+}
+
+/// Bundle every file under `paths` (files, or directories walked recursively)
+///
+/// Paths must be relative to the working directory, and are restored under the same paths.
+/// Leftover `.tmp` files from interrupted writes are skipped, and so are localnet workspaces,
+/// which hold a validator ledger and keypairs, and SQLite databases such as the local ledger,
+/// which `sync` rebuilds from the chain. Fails on the first file that looks like it holds a
+/// private key.
+pub fn export_client_state(paths: &[PathBuf]) -> Result<StateBundle> {
+    let mut files = BTreeMap::new();
+    for path in paths {
+        collect_files(path, &mut files)?;
+    }
+
+    let manifest = StateManifest {
+        version: STATE_BUNDLE_VERSION,
+        sdk_version: env!("CARGO_PKG_VERSION").to_string(),
+        created_at: SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0),
+        files: files
+            .iter()
+            .map(|(path, contents)| ManifestEntry {
+                path: path.clone(),
+                sha256: sha256_hex(contents),
+                size: contents.len() as u64,
+            })
+            .collect(),
+    };
+    tracing::info!("Exported {} state files", manifest.files.len());
+    Ok(StateBundle { manifest, files })
+}
+
+/// What `apply_client_state` would do to each file, without writing anything
+pub fn plan_client_state(bundle: &StateBundle) -> Result<Vec<StateChange>> {
+    bundle.validate()?;
+    bundle
+        .files
+        .iter()
+        .map(|(path, contents)| {
+            let kind = match fs::read_to_string(relative_path(path)?) {
+                Ok(existing) if existing == *contents => StateChangeKind::Unchanged,
+                Ok(_) => StateChangeKind::Modified,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => StateChangeKind::Added,
+                Err(e) => return Err(anyhow!("Could not read {}: {}", path, e)),
+            };
+            Ok(StateChange { path: path.clone(), kind })
+        })
+        .collect()
+}
+
+/// Validate `bundle` and write its added and modified files, returning what changed
+///
+/// Files on disk that the bundle does not mention are left alone.
+pub fn apply_client_state(bundle: &StateBundle) -> Result<Vec<StateChange>> {
+    let changes = plan_client_state(bundle)?;
+    for change in changes.iter().filter(|c| c.kind != StateChangeKind::Unchanged) {
+        let target = relative_path(&change.path)?;
+        if let Some(parent) = target.parent() {
+            if !parent.as_os_str().is_empty() {
+                fs::create_dir_all(parent)?;
+            }
+        }
+        let tmp = target.with_extension("tmp");
+        fs::write(&tmp, &bundle.files[&change.path])?;
+        fs::rename(&tmp, &target)?;
+        tracing::info!("Restored {} ({:?})", change.path, change.kind);
+    }
+    Ok(changes)
+}
+
+fn collect_files(path: &Path, files: &mut BTreeMap<String, String>) -> Result<()> {
+    let key = bundle_key(path)?;
+    if path.is_dir() {
+        if crate::localnet::is_workspace(path) {
+            tracing::info!("Skipping localnet workspace {}", path.display());
+            return Ok(());
+        }
+        let mut entries = fs::read_dir(path)?.collect::<std::io::Result<Vec<_>>>()?;
+        entries.sort_by_key(|entry| entry.path());
+        for entry in entries {
+            collect_files(&entry.path(), files)?;
+        }
+        return Ok(());
+    }
+    if path.extension().is_some_and(|ext| ext == "tmp") {
+        return Ok(());
+    }
+    if is_sqlite_database(path) {
+        tracing::info!("Skipping database {}", path.display());
+        return Ok(());
+    }
+
+    let contents =
+        fs::read_to_string(path).map_err(|e| anyhow!("Could not read state file {}: {}", path.display(), e))?;
+    check_contents(&key, &contents)?;
+    files.insert(key, contents);
+    Ok(())
+}
+
+/// Whether `path` holds a SQLite database, going by its header; checked whether or not this
+/// build has the `sqlite` feature, since another build may have written it
+pub(crate) fn is_sqlite_database(path: &Path) -> bool {
+    let mut header = [0u8; 16];
+    let read = fs::File::open(path).and_then(|mut file| file.read_exact(&mut header));
+    read.is_ok() && &header == b"SQLite format 3\0"
+}
+
+/// `path` as a bundle key: relative, `/`-separated, without `.` components
+fn bundle_key(path: &Path) -> Result<String, StateBundleError> {
+    let unsafe_path = || StateBundleError::UnsafePath { path: path.display().to_string() };
+    let mut parts = Vec::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => parts.push(part.to_str().ok_or_else(unsafe_path)?.to_string()),
+            Component::CurDir => {}
+            _ => return Err(unsafe_path()),
+        }
+    }
+    if parts.is_empty() {
+        return Err(unsafe_path());
+    }
+    Ok(parts.join("/"))
+}
+
+/// The on-disk path of a bundle key, refusing anything outside the working directory
+fn relative_path(key: &str) -> Result<PathBuf, StateBundleError> {
+    let path = PathBuf::from(key);
+    if bundle_key(&path)? != key {
+        return Err(StateBundleError::UnsafePath { path: key.to_string() });
+    }
+    Ok(path)
+}
+
+/// Reject contents that are not JSON or that carry key material
+fn check_contents(path: &str, contents: &str) -> Result<(), StateBundleError> {
+    let secret = |reason: &str| StateBundleError::SecretDetected {
+        path: path.to_string(),
+        reason: reason.to_string(),
+    };
+    let trimmed = contents.trim();
+    if bs58::decode(trimmed).into_vec().is_ok_and(|bytes| bytes.len() == 64) {
+        return Err(secret("it is a base58 keypair"));
+    }
+
+    let value: serde_json::Value = serde_json::from_str(trimmed).map_err(|e| StateBundleError::InvalidFile {
+        path: path.to_string(),
+        reason: e.to_string(),
+    })?;
+    if let Ok(bytes) = serde_json::from_value::<Vec<u8>>(value.clone()) {
+        if bytes.len() == 64 && Keypair::from_bytes(&bytes).is_ok() {
+            return Err(secret("it is a wallet keypair file"));
+        }
+    }
+    if let Some(key) = find_secret_key(&value) {
+        return Err(secret(&format!("it has a '{}' field", key)));
+    }
+    Ok(())
+}
+
+fn find_secret_key(value: &serde_json::Value) -> Option<String> {
+    match value {
+        serde_json::Value::Object(map) => map.iter().find_map(|(key, value)| {
+            if SECRET_KEYS.contains(&key.to_ascii_lowercase().as_str()) {
+                Some(key.clone())
+            } else {
+                find_secret_key(value)
+            }
+        }),
+        serde_json::Value::Array(items) => items.iter().find_map(find_secret_key),
+        _ => None,
+    }
+}
+
+fn sha256_hex(contents: &str) -> String {
+    Sha256::digest(contents.as_bytes()).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asset_policy::AssetPolicy;
+    use crate::FinternetClient;
+    use solana_sdk::pubkey::Pubkey;
+    use solana_sdk::signer::Signer;
+
+    /// A directory under `target/`, given relative to the working directory since bundles only
+    /// hold relative paths, and removed when dropped
+    struct WorkDir(PathBuf);
+
+    impl WorkDir {
+        fn new() -> Self {
+            let path = PathBuf::from("target").join(format!("state-bundle-{}", uuid::Uuid::new_v4()));
+            fs::create_dir_all(&path).unwrap();
+            Self(path)
+        }
+
+        fn join(&self, name: &str) -> PathBuf {
+            self.0.join(name)
+        }
+
+        fn key(&self, name: &str) -> String {
+            bundle_key(&self.join(name)).unwrap()
+        }
+
+        fn write(&self, name: &str, contents: &str) {
+            let path = self.join(name);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        }
+    }
+
+    impl Drop for WorkDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    /// A config directory as a running integration leaves it, with an asset policy saved
+    /// through its own API
+    fn populated(dir: &WorkDir) -> AssetPolicy {
+        dir.write("config/profiles.json", r#"{"staging":{"rpc_url":"https://api.devnet.solana.com"}}"#);
+        dir.write("config/address_book.json", r#"{"treasury":"11111111111111111111111111111111"}"#);
+        dir.write("config/webhooks/endpoints.json", r#"[{"url":"https://example.com/hook","events":["payment"]}]"#);
+        dir.write("config/schedules.json", r#"{"payroll":{"cron":"0 9 1 * *","amount":1000}}"#);
+        dir.write("config/tokens.json", r#"{"USDC":{"decimals":6}}"#);
+        let policy = AssetPolicy::new(Pubkey::new_unique()).allow_owner(Pubkey::new_unique()).max_holders(10);
+        policy.save(&dir.join("config/asset_policy.json")).unwrap();
+        policy
+    }
+
+    fn snapshot(dir: &WorkDir, names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| fs::read_to_string(dir.join(name)).unwrap()).collect()
+    }
+
+    const FILES: [&str; 6] = [
+        "config/profiles.json",
+        "config/address_book.json",
+        "config/webhooks/endpoints.json",
+        "config/schedules.json",
+        "config/tokens.json",
+        "config/asset_policy.json",
+    ];
+
+    #[test]
+    fn a_populated_config_directory_round_trips_through_a_bundle_file() {
+        let source = WorkDir::new();
+        let policy = populated(&source);
+        let before = snapshot(&source, &FILES);
+
+        let bundle = export_client_state(&[source.join("config")]).unwrap();
+        assert_eq!(bundle.manifest.version, STATE_BUNDLE_VERSION);
+        assert_eq!(bundle.manifest.files.len(), FILES.len());
+        let archive = source.join("state.tar.gz");
+        bundle.write(&archive).unwrap();
+        let restored = StateBundle::read(&archive).unwrap();
+        assert_eq!(restored, bundle);
+        restored.validate().unwrap();
+
+        fs::remove_dir_all(source.join("config")).unwrap();
+        let changes = apply_client_state(&restored).unwrap();
+        assert!(changes.iter().all(|change| change.kind == StateChangeKind::Added));
+        assert_eq!(snapshot(&source, &FILES), before);
+        assert_eq!(AssetPolicy::load(&source.join("config/asset_policy.json")).unwrap(), policy);
+
+        // Applying the same bundle again changes nothing
+        let again = apply_client_state(&restored).unwrap();
+        assert!(again.iter().all(|change| change.kind == StateChangeKind::Unchanged));
+    }
+
+    #[test]
+    fn the_plan_reports_what_would_change_without_writing() {
+        let dir = WorkDir::new();
+        populated(&dir);
+        let bundle = export_client_state(&[dir.join("config")]).unwrap();
+
+        dir.write("config/tokens.json", r#"{"USDC":{"decimals":9}}"#);
+        fs::remove_file(dir.join("config/schedules.json")).unwrap();
+        dir.write("config/local_only.json", "{}");
+        let plan = plan_client_state(&bundle).unwrap();
+
+        let kind = |name: &str| plan.iter().find(|change| change.path == dir.key(name)).unwrap().kind;
+        assert_eq!(kind("config/tokens.json"), StateChangeKind::Modified);
+        assert_eq!(kind("config/schedules.json"), StateChangeKind::Added);
+        assert_eq!(kind("config/profiles.json"), StateChangeKind::Unchanged);
+        assert!(!dir.join("config/schedules.json").exists());
+        assert_eq!(fs::read_to_string(dir.join("config/tokens.json")).unwrap(), r#"{"USDC":{"decimals":9}}"#);
+
+        assert_eq!(apply_client_state(&bundle).unwrap(), plan);
+        assert_eq!(fs::read_to_string(dir.join("config/tokens.json")).unwrap(), r#"{"USDC":{"decimals":6}}"#);
+        // Files the bundle does not mention are left alone
+        assert!(dir.join("config/local_only.json").exists());
+    }
+
+    #[test]
+    fn a_wallet_anywhere_in_the_exported_paths_fails_the_export() {
+        let wallet = solana_sdk::signature::Keypair::new();
+        let secret = |dir: &WorkDir| {
+            let error = export_client_state(&[dir.join("config")]).unwrap_err();
+            match error.downcast::<StateBundleError>().unwrap() {
+                StateBundleError::SecretDetected { reason, .. } => reason,
+                other => panic!("{:?}", other),
+            }
+        };
+
+        let dir = WorkDir::new();
+        populated(&dir);
+        FinternetClient::save_wallet_to_file(&wallet, &dir.join("config/keys/id.json")).unwrap();
+        assert_eq!(secret(&dir), "it is a wallet keypair file");
+
+        let dir = WorkDir::new();
+        dir.write("config/wallet.txt", &bs58::encode(wallet.to_bytes()).into_string());
+        assert_eq!(secret(&dir), "it is a base58 keypair");
+
+        let dir = WorkDir::new();
+        dir.write("config/profiles.json", r#"{"prod":{"signer":{"Private_Key":"..."}}}"#);
+        assert_eq!(secret(&dir), "it has a 'Private_Key' field");
+
+        // A public key alone is fine
+        let dir = WorkDir::new();
+        dir.write("config/profiles.json", &format!(r#"{{"prod":{{"pubkey":"{}"}}}}"#, wallet.pubkey()));
+        assert_eq!(export_client_state(&[dir.join("config")]).unwrap().files.len(), 1);
+    }
+
+    #[test]
+    fn leftovers_databases_and_localnet_workspaces_are_skipped() {
+        let dir = WorkDir::new();
+        dir.write("config/tokens.json", "{}");
+        dir.write("config/tokens.tmp", "{");
+        dir.write("config/ledger.db", "SQLite format 3\0 and pages");
+        dir.write("config/localnet/localnet.json", "{}");
+        dir.write("config/localnet/payer.json", "[]");
+
+        let bundle = export_client_state(&[dir.join("config")]).unwrap();
+        assert_eq!(bundle.files.keys().cloned().collect::<Vec<_>>(), vec![dir.key("config/tokens.json")]);
+    }
+
+    #[test]
+    fn a_tampered_or_mismatched_bundle_is_refused_before_anything_is_written() {
+        let dir = WorkDir::new();
+        populated(&dir);
+        let bundle = export_client_state(&[dir.join("config")]).unwrap();
+        fs::remove_dir_all(dir.join("config")).unwrap();
+        let tokens = dir.key("config/tokens.json");
+        let refused = |bundle: StateBundle| {
+            let code = apply_client_state(&bundle).unwrap_err().downcast::<StateBundleError>().unwrap().code();
+            assert!(!dir.join("config").exists(), "{} wrote files", code);
+            code
+        };
+
+        let mut tampered = bundle.clone();
+        tampered.files.insert(tokens.clone(), r#"{"USDC":{"decimals":0}}"#.to_string());
+        assert_eq!(refused(tampered), "checksum_mismatch");
+
+        let mut newer = bundle.clone();
+        newer.manifest.version = STATE_BUNDLE_VERSION + 1;
+        assert_eq!(refused(newer), "unsupported_version");
+
+        let mut missing = bundle.clone();
+        missing.files.remove(&tokens);
+        assert_eq!(refused(missing), "manifest_mismatch");
+
+        let mut unlisted = bundle.clone();
+        unlisted.files.insert(dir.key("config/extra.json"), "{}".to_string());
+        assert_eq!(refused(unlisted), "manifest_mismatch");
+
+        for path in ["../escape.json", "/etc/escape.json", "config/./escape.json"] {
+            let mut escaping = bundle.clone();
+            escaping.files.insert(path.to_string(), "{}".to_string());
+            escaping.manifest.files.insert(0, ManifestEntry { path: path.to_string(), sha256: sha256_hex("{}"), size: 2 });
+            assert_eq!(refused(escaping), "unsafe_path", "{}", path);
+        }
+
+        let mut secret = bundle.clone();
+        let contents = r#"{"mnemonic":"abandon abandon"}"#.to_string();
+        secret.manifest.files.iter_mut().find(|entry| entry.path == tokens).unwrap().sha256 = sha256_hex(&contents);
+        secret.files.insert(tokens, contents);
+        assert_eq!(refused(secret), "secret_detected");
+
+        assert!(export_client_state(&[PathBuf::from("/etc/hosts")]).is_err());
+    }
+}
@@ -0,0 +1,575 @@
+//! Where server-side registries keep their records
+//!
+//! A `StateStore` holds JSON values by namespace and key, each optionally expiring. Expired
+//! entries are never returned and are dropped on the next write. `MemoryStateStore` keeps
+//! everything in process, for tests and throwaway servers; `FileStateStore` keeps it in one
+//! JSON file, rewritten atomically on every change, so it survives a restart; `SqliteStateStore`,
+//! built with the `sqlite` feature, keeps it in a SQLite database, writing only the entry that
+//! changed. Registries reach the
+//! store through a typed `Namespace`, and one store can back any number of them.
+//!
+//! The file records its `schema_version`. Opening an older file runs each migration after its
+//! version in turn and writes the result back. Version 0 is the bare per-registry file the
+//! registries wrote before they were moved onto a store; `FileStateStore::open_registry` imports
+//! one into its namespaces. A database records its version as SQLite's `user_version` and is
+//! migrated the same way, one step per version, inside a transaction.
+
+#[cfg(feature = "sqlite")]
+use crate::sqlite::{Connection, SqlValue};
+use anyhow::{anyhow, Result};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fs;
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Version written by this build; files from later builds are refused
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Turns a version-0 registry file into `(namespace, key, value)` entries
+pub type LegacyImport<'a> = &'a dyn Fn(Value) -> Result<Vec<(String, String, Value)>>;
+
+/// Namespaced, expiring key-value storage shared by the server's registries
+///
+/// Every call is atomic on its own. Registries that read and then write hold their own lock
+/// across both.
+pub trait StateStore: Send + Sync {
+    fn get(&self, namespace: &str, key: &str) -> Result<Option<Value>>;
+
+    /// Store `value`, replacing any entry under `key`; with a `ttl` it expires after that long
+    fn put(&self, namespace: &str, key: &str, value: Value, ttl: Option<Duration>) -> Result<()>;
+
+    /// Whether there was a live entry to remove
+    fn delete(&self, namespace: &str, key: &str) -> Result<bool>;
+
+    /// Every live entry in `namespace`, by key
+    fn list(&self, namespace: &str) -> Result<Vec<(String, Value)>>;
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct StoredEntry {
+    value: Value,
+    /// Unix time after which the entry is gone
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    expires_at: Option<u64>,
+}
+
+impl StoredEntry {
+    fn new(value: Value, ttl: Option<Duration>) -> Self {
+        Self { value, expires_at: ttl.map(|ttl| unix_now().saturating_add(ttl.as_secs())) }
+    }
+
+    fn is_live(&self, now: u64) -> bool {
+        self.expires_at.is_none_or(|expires_at| expires_at > now)
+    }
+}
+
+type Namespaces = BTreeMap<String, BTreeMap<String, StoredEntry>>;
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct StoreFile {
+    schema_version: u32,
+    namespaces: Namespaces,
+}
+
+fn get_live(namespaces: &Namespaces, namespace: &str, key: &str) -> Option<Value> {
+    let now = unix_now();
+    namespaces
+        .get(namespace)
+        .and_then(|entries| entries.get(key))
+        .filter(|entry| entry.is_live(now))
+        .map(|entry| entry.value.clone())
+}
+
+fn list_live(namespaces: &Namespaces, namespace: &str) -> Vec<(String, Value)> {
+    let now = unix_now();
+    namespaces
+        .get(namespace)
+        .map(|entries| {
+            entries
+                .iter()
+                .filter(|(_, entry)| entry.is_live(now))
+                .map(|(key, entry)| (key.clone(), entry.value.clone()))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Drop every expired entry and any namespace left empty
+fn purge_expired(namespaces: &mut Namespaces) {
+    let now = unix_now();
+    for entries in namespaces.values_mut() {
+        entries.retain(|_, entry| entry.is_live(now));
+    }
+    namespaces.retain(|_, entries| !entries.is_empty());
+}
+
+/// Entries held in process only; gone when it exits
+#[derive(Default)]
+pub struct MemoryStateStore {
+    namespaces: Mutex<Namespaces>,
+}
+
+impl MemoryStateStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn lock(&self) -> Result<std::sync::MutexGuard<'_, Namespaces>> {
+        self.namespaces.lock().map_err(|_| anyhow!("State store lock poisoned"))
+    }
+}
+
+impl StateStore for MemoryStateStore {
+    fn get(&self, namespace: &str, key: &str) -> Result<Option<Value>> {
+        let namespaces = self.lock()?;
+        Ok(get_live(&namespaces, namespace, key))
+    }
+
+    fn put(&self, namespace: &str, key: &str, value: Value, ttl: Option<Duration>) -> Result<()> {
+        let mut namespaces = self.lock()?;
+        purge_expired(&mut namespaces);
+        namespaces.entry(namespace.to_string()).or_default().insert(key.to_string(), StoredEntry::new(value, ttl));
+        Ok(())
+    }
+
+    fn delete(&self, namespace: &str, key: &str) -> Result<bool> {
+        let mut namespaces = self.lock()?;
+        let removed = namespaces.get_mut(namespace).and_then(|entries| entries.remove(key));
+        purge_expired(&mut namespaces);
+        Ok(removed.is_some_and(|entry| entry.is_live(unix_now())))
+    }
+
+    fn list(&self, namespace: &str) -> Result<Vec<(String, Value)>> {
+        let namespaces = self.lock()?;
+        Ok(list_live(&namespaces, namespace))
+    }
+}
+
+/// Entries kept in one JSON file, rewritten on every change
+pub struct FileStateStore {
+    path: PathBuf,
+    namespaces: Mutex<Namespaces>,
+}
+
+impl FileStateStore {
+    /// Open the store at `path`, creating it on the first write
+    pub fn open(path: &Path) -> Result<Self> {
+        Self::open_with(path, None)
+    }
+
+    /// Open a registry's own file, importing it with `legacy` if it predates the store
+    pub fn open_registry(path: &Path, legacy: LegacyImport<'_>) -> Result<Self> {
+        Self::open_with(path, Some(legacy))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn open_with(path: &Path, legacy: Option<LegacyImport<'_>>) -> Result<Self> {
+        let data = if path.exists() { fs::read_to_string(path)? } else { String::new() };
+        let (namespaces, migrated) = if data.trim().is_empty() {
+            (Namespaces::new(), false)
+        } else {
+            let raw: Value =
+                serde_json::from_str(&data).map_err(|e| anyhow!("Corrupt state store {}: {}", path.display(), e))?;
+            let version = raw.get("schema_version").and_then(Value::as_u64).unwrap_or(0) as u32;
+            if version > SCHEMA_VERSION {
+                return Err(anyhow!(
+                    "State store {} is at schema version {}, newer than this build's {}",
+                    path.display(),
+                    version,
+                    SCHEMA_VERSION
+                ));
+            }
+            let file = migrate(raw, version, legacy).map_err(|e| e.context(format!("Migrating {}", path.display())))?;
+            (file.namespaces, version < SCHEMA_VERSION)
+        };
+
+        let store = Self { path: path.to_path_buf(), namespaces: Mutex::new(namespaces) };
+        if migrated {
+            let namespaces = store.lock()?;
+            store.persist(&namespaces)?;
+            tracing::info!("Migrated state store {} to schema version {}", path.display(), SCHEMA_VERSION);
+        }
+        Ok(store)
+    }
+
+    fn lock(&self) -> Result<std::sync::MutexGuard<'_, Namespaces>> {
+        self.namespaces.lock().map_err(|_| anyhow!("State store lock poisoned"))
+    }
+
+    fn persist(&self, namespaces: &Namespaces) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            if !parent.as_os_str().is_empty() {
+                fs::create_dir_all(parent)?;
+            }
+        }
+        let file = StoreFileRef { schema_version: SCHEMA_VERSION, namespaces };
+        let tmp = self.path.with_extension("tmp");
+        fs::write(&tmp, serde_json::to_string_pretty(&file)?)?;
+        fs::rename(&tmp, &self.path)?;
+        Ok(())
+    }
+}
+
+#[derive(Serialize)]
+struct StoreFileRef<'a> {
+    schema_version: u32,
+    namespaces: &'a Namespaces,
+}
+
+impl StateStore for FileStateStore {
+    fn get(&self, namespace: &str, key: &str) -> Result<Option<Value>> {
+        let namespaces = self.lock()?;
+        Ok(get_live(&namespaces, namespace, key))
+    }
+
+    fn put(&self, namespace: &str, key: &str, value: Value, ttl: Option<Duration>) -> Result<()> {
+        let mut namespaces = self.lock()?;
+        purge_expired(&mut namespaces);
+        namespaces.entry(namespace.to_string()).or_default().insert(key.to_string(), StoredEntry::new(value, ttl));
+        self.persist(&namespaces)
+    }
+
+    fn delete(&self, namespace: &str, key: &str) -> Result<bool> {
+        let mut namespaces = self.lock()?;
+        let removed = namespaces.get_mut(namespace).and_then(|entries| entries.remove(key));
+        purge_expired(&mut namespaces);
+        self.persist(&namespaces)?;
+        Ok(removed.is_some_and(|entry| entry.is_live(unix_now())))
+    }
+
+    fn list(&self, namespace: &str) -> Result<Vec<(String, Value)>> {
+        let namespaces = self.lock()?;
+        Ok(list_live(&namespaces, namespace))
+    }
+}
+
+/// Entries kept in a SQLite database, one row each
+#[cfg(feature = "sqlite")]
+pub struct SqliteStateStore {
+    path: PathBuf,
+    /// One connection, so each call's statements run as a unit
+    connection: Mutex<Connection>,
+}
+
+#[cfg(feature = "sqlite")]
+impl SqliteStateStore {
+    /// Open the database at `path`, creating and migrating it as needed
+    pub fn open(path: &Path) -> Result<Self> {
+        let connection = Connection::open(path)?;
+        let version = connection
+            .query("PRAGMA user_version", &[])?
+            .first()
+            .and_then(|row| row.first())
+            .and_then(SqlValue::as_i64)
+            .unwrap_or(0) as u32;
+        if version > SCHEMA_VERSION {
+            return Err(anyhow!(
+                "State store {} is at schema version {}, newer than this build's {}",
+                path.display(),
+                version,
+                SCHEMA_VERSION
+            ));
+        }
+        if version < SCHEMA_VERSION {
+            connection
+                .transaction(|connection| migrate_sqlite(connection, version))
+                .map_err(|e| e.context(format!("Migrating {}", path.display())))?;
+            if version > 0 {
+                tracing::info!("Migrated state store {} to schema version {}", path.display(), SCHEMA_VERSION);
+            }
+        }
+        Ok(Self { path: path.to_path_buf(), connection: Mutex::new(connection) })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn lock(&self) -> Result<std::sync::MutexGuard<'_, Connection>> {
+        self.connection.lock().map_err(|_| anyhow!("State store lock poisoned"))
+    }
+}
+
+#[cfg(feature = "sqlite")]
+impl StateStore for SqliteStateStore {
+    fn get(&self, namespace: &str, key: &str) -> Result<Option<Value>> {
+        let connection = self.lock()?;
+        let rows = connection.query(
+            "SELECT value FROM entries WHERE namespace = ? AND key = ? AND (expires_at IS NULL OR expires_at > ?)",
+            &[namespace.into(), key.into(), SqlValue::try_from(unix_now())?],
+        )?;
+        rows.first().and_then(|row| row.first()).map(|value| decode_stored(namespace, key, value)).transpose()
+    }
+
+    fn put(&self, namespace: &str, key: &str, value: Value, ttl: Option<Duration>) -> Result<()> {
+        let entry = StoredEntry::new(value, ttl);
+        let connection = self.lock()?;
+        connection.transaction(|connection| {
+            purge_expired_rows(connection)?;
+            connection.execute(
+                "INSERT OR REPLACE INTO entries (namespace, key, value, expires_at) VALUES (?, ?, ?, ?)",
+                &[namespace.into(), key.into(), serde_json::to_string(&entry.value)?.into(), entry.expires_at.map(SqlValue::try_from).transpose()?.into()],
+            )?;
+            Ok(())
+        })
+    }
+
+    fn delete(&self, namespace: &str, key: &str) -> Result<bool> {
+        let connection = self.lock()?;
+        connection.transaction(|connection| {
+            let removed = connection.execute(
+                "DELETE FROM entries WHERE namespace = ? AND key = ? AND (expires_at IS NULL OR expires_at > ?)",
+                &[namespace.into(), key.into(), SqlValue::try_from(unix_now())?],
+            )?;
+            purge_expired_rows(connection)?;
+            Ok(removed > 0)
+        })
+    }
+
+    fn list(&self, namespace: &str) -> Result<Vec<(String, Value)>> {
+        let connection = self.lock()?;
+        connection
+            .query(
+                "SELECT key, value FROM entries WHERE namespace = ? AND (expires_at IS NULL OR expires_at > ?) ORDER BY key",
+                &[namespace.into(), SqlValue::try_from(unix_now())?],
+            )?
+            .iter()
+            .map(|row| {
+                let key = row.first().and_then(SqlValue::as_str).unwrap_or_default().to_string();
+                let value = decode_stored(namespace, &key, row.get(1).unwrap_or(&SqlValue::Null))?;
+                Ok((key, value))
+            })
+            .collect()
+    }
+}
+
+#[cfg(feature = "sqlite")]
+fn purge_expired_rows(connection: &Connection) -> Result<()> {
+    connection.execute("DELETE FROM entries WHERE expires_at IS NOT NULL AND expires_at <= ?", &[SqlValue::try_from(unix_now())?])?;
+    Ok(())
+}
+
+#[cfg(feature = "sqlite")]
+fn decode_stored(namespace: &str, key: &str, value: &SqlValue) -> Result<Value> {
+    let text = value.as_str().ok_or_else(|| anyhow!("Corrupt {} entry '{}': not text", namespace, key))?;
+    serde_json::from_str(text).map_err(|e| anyhow!("Corrupt {} entry '{}': {}", namespace, key, e))
+}
+
+/// Bring a database at `version` up to `SCHEMA_VERSION`
+///
+/// Version 0 is a new, empty database. Each future schema change adds an arm taking the
+/// database from the version before it.
+#[cfg(feature = "sqlite")]
+fn migrate_sqlite(connection: &Connection, version: u32) -> Result<()> {
+    for from in version..SCHEMA_VERSION {
+        match from {
+            0 => connection.execute_batch(
+                "CREATE TABLE IF NOT EXISTS entries (
+                    namespace TEXT NOT NULL,
+                    key TEXT NOT NULL,
+                    value TEXT NOT NULL,
+                    expires_at INTEGER,
+                    PRIMARY KEY (namespace, key)
+                );
+                CREATE INDEX IF NOT EXISTS entries_expiry ON entries (expires_at) WHERE expires_at IS NOT NULL;",
+            )?,
+            other => return Err(anyhow!("No migration from schema version {}", other)),
+        }
+    }
+    connection.execute_batch(&format!("PRAGMA user_version = {}", SCHEMA_VERSION))
+}
+
+/// Bring a file read at `version` up to `SCHEMA_VERSION`
+///
+/// Each future schema change adds an arm taking the file from the version before it.
+fn migrate(mut raw: Value, version: u32, legacy: Option<LegacyImport<'_>>) -> Result<StoreFile> {
+    for from in version..SCHEMA_VERSION {
+        raw = match from {
+            0 => {
+                let legacy = legacy.ok_or_else(|| anyhow!("File has no schema_version and is not a registry file"))?;
+                let mut namespaces = Namespaces::new();
+                for (namespace, key, value) in legacy(raw)? {
+                    namespaces.entry(namespace).or_default().insert(key, StoredEntry::new(value, None));
+                }
+                serde_json::to_value(StoreFile { schema_version: 1, namespaces })?
+            }
+            other => return Err(anyhow!("No migration from schema version {}", other)),
+        };
+    }
+    serde_json::from_value(raw).map_err(|e| anyhow!("Invalid state store contents: {}", e))
+}
+
+/// A version-0 file holding one JSON object of records by key, imported into `namespace`
+pub fn legacy_map(namespace: &str) -> impl Fn(Value) -> Result<Vec<(String, String, Value)>> + '_ {
+    move |raw| match raw {
+        Value::Object(records) => {
+            Ok(records.into_iter().map(|(key, value)| (namespace.to_string(), key, value)).collect())
+        }
+        other => Err(anyhow!("Expected an object of records, found {}", other)),
+    }
+}
+
+/// One registry's records of type `T` in a store
+pub struct Namespace<T> {
+    store: Arc<dyn StateStore>,
+    name: &'static str,
+    _records: PhantomData<fn() -> T>,
+}
+
+impl<T: Serialize + DeserializeOwned> Namespace<T> {
+    pub fn new(store: Arc<dyn StateStore>, name: &'static str) -> Self {
+        Self { store, name, _records: PhantomData }
+    }
+
+    pub fn get(&self, key: &str) -> Result<Option<T>> {
+        self.store.get(self.name, key)?.map(|value| self.decode(key, value)).transpose()
+    }
+
+    pub fn put(&self, key: &str, record: &T) -> Result<()> {
+        self.store.put(self.name, key, serde_json::to_value(record)?, None)
+    }
+
+    /// Store `record` until `ttl` has passed
+    pub fn put_with_ttl(&self, key: &str, record: &T, ttl: Duration) -> Result<()> {
+        self.store.put(self.name, key, serde_json::to_value(record)?, Some(ttl))
+    }
+
+    pub fn delete(&self, key: &str) -> Result<bool> {
+        self.store.delete(self.name, key)
+    }
+
+    /// Every live record, by key
+    pub fn list(&self) -> Result<Vec<(String, T)>> {
+        self.store
+            .list(self.name)?
+            .into_iter()
+            .map(|(key, value)| self.decode(&key, value).map(|record| (key, record)))
+            .collect()
+    }
+
+    fn decode(&self, key: &str, value: Value) -> Result<T> {
+        serde_json::from_value(value).map_err(|e| anyhow!("Corrupt {} entry '{}': {}", self.name, key, e))
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_cluster::TempDir;
+    #[cfg(feature = "sqlite")]
+    use crate::solana_pay::{PaymentRequest, PaymentRequestStatus, PaymentRequestStore};
+    #[cfg(feature = "sqlite")]
+    use crate::webhook::{DeadLetterQueue, DeliveryFailure};
+    #[cfg(feature = "sqlite")]
+    use solana_sdk::pubkey::Pubkey;
+
+    #[cfg(feature = "sqlite")]
+    fn payment_request(id: &str) -> PaymentRequest {
+        PaymentRequest {
+            id: id.to_string(),
+            reference: Pubkey::new_unique(),
+            recipient: Pubkey::new_unique(),
+            mint: Pubkey::new_unique(),
+            amount: "12.5".to_string(),
+            base_units: 12_500_000,
+            currency: "USDC".to_string(),
+            label: Some("Till 3".to_string()),
+            message: None,
+            url: "solana:example".to_string(),
+            created_at: unix_now(),
+            expires_at: unix_now() + 600,
+            status: PaymentRequestStatus::Pending,
+            settlement: None,
+        }
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn dead_letters_and_pending_payment_requests_survive_a_restart_on_sqlite() {
+        let dir = TempDir::new("state-store");
+        let path = dir.join("state.db");
+        let (letter, request) = {
+            let store: Arc<dyn StateStore> = Arc::new(SqliteStateStore::open(&path).unwrap());
+            let failure = DeliveryFailure { attempts: 5, last_error: "connection refused".to_string() };
+            let (letter, _) = DeadLetterQueue::new(Arc::clone(&store))
+                .push("deposit", "https://example.com/hook", &serde_json::json!({ "amount": 1 }), &failure)
+                .unwrap();
+            let request = payment_request("pr-1");
+            PaymentRequestStore::new(store).insert(request.clone()).unwrap();
+            (letter, request)
+        };
+
+        let store: Arc<dyn StateStore> = Arc::new(SqliteStateStore::open(&path).unwrap());
+        assert_eq!(DeadLetterQueue::new(Arc::clone(&store)).list().unwrap(), vec![letter]);
+        let restored = PaymentRequestStore::new(store).get("pr-1").unwrap().unwrap();
+        assert_eq!(restored.status, PaymentRequestStatus::Pending);
+        assert_eq!((restored.reference, restored.base_units), (request.reference, request.base_units));
+    }
+
+    #[test]
+    fn expired_entries_are_gone_from_every_backend() {
+        let dir = TempDir::new("state-store");
+        let stores: Vec<Box<dyn StateStore>> = vec![
+            Box::new(MemoryStateStore::new()),
+            Box::new(FileStateStore::open(&dir.join("ttl.json")).unwrap()),
+            #[cfg(feature = "sqlite")]
+            Box::new(SqliteStateStore::open(&dir.join("ttl.db")).unwrap()),
+        ];
+        for store in stores {
+            store.put("nonces", "kept", Value::from(1), Some(Duration::from_secs(3_600))).unwrap();
+            store.put("nonces", "spent", Value::from(2), Some(Duration::ZERO)).unwrap();
+            store.put("nonces", "forever", Value::from(3), None).unwrap();
+
+            assert_eq!(store.get("nonces", "spent").unwrap(), None);
+            assert!(!store.delete("nonces", "spent").unwrap());
+            let keys: Vec<String> = store.list("nonces").unwrap().into_iter().map(|(key, _)| key).collect();
+            assert_eq!(keys, vec!["forever", "kept"]);
+            assert!(store.delete("nonces", "kept").unwrap());
+            assert_eq!(store.list("other").unwrap(), vec![]);
+        }
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn concurrent_writers_all_land_in_sqlite() {
+        let dir = TempDir::new("state-store");
+        let store = Arc::new(SqliteStateStore::open(&dir.join("concurrent.db")).unwrap());
+        let writers: Vec<_> = (0..8)
+            .map(|writer| {
+                let store = Arc::clone(&store);
+                std::thread::spawn(move || {
+                    for n in 0..25 {
+                        store.put("jobs", &format!("{}-{}", writer, n), Value::from(n), None).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for writer in writers {
+            writer.join().unwrap();
+        }
+        assert_eq!(store.list("jobs").unwrap().len(), 200);
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn a_database_from_a_newer_build_is_refused() {
+        let dir = TempDir::new("state-store");
+        let path = dir.join("newer.db");
+        Connection::open(&path).unwrap().execute_batch(&format!("PRAGMA user_version = {}", SCHEMA_VERSION + 1)).unwrap();
+
+        let error = SqliteStateStore::open(&path).err().unwrap();
+        assert!(error.to_string().contains("newer than this build"), "{}", error);
+    }
+}
@@ -0,0 +1,379 @@
+use crate::amount_format::AmountFormatter;
+use crate::history::{HistoryCompleteness, TruncatedHistory};
+use crate::identity_index::IdentityIndex;
+use crate::local_ledger::{HistoryGap, LocalLedger};
+use crate::telemetry::readable;
+use crate::{Currency, FinternetClient, TransactionRecord};
+use anyhow::{anyhow, Result};
+use chrono::{NaiveDate, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use solana_client::rpc_config::RpcTransactionConfig;
+use solana_sdk::{commitment_config::CommitmentConfig, pubkey::Pubkey, signature::Signature};
+use solana_transaction_status::{option_serializer::OptionSerializer, UiTransactionEncoding};
+use std::collections::HashSet;
+use std::fmt;
+use std::fmt::Write as _;
+use std::str::FromStr;
+
+/// A calendar month in UTC, written `YYYY-MM`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct YearMonth {
+    pub year: i32,
+    pub month: u32,
+}
+
+impl YearMonth {
+    pub fn new(year: i32, month: u32) -> Result<Self> {
+        if !(1..=12).contains(&month) {
+            return Err(anyhow!("Month must be 1-12, got {}", month));
+        }
+        Ok(Self { year, month })
+    }
+
+    /// Unix seconds of the first instant of the month and of the month after
+    pub fn bounds(&self) -> (u64, u64) {
+        let start = NaiveDate::from_ymd_opt(self.year, self.month, 1).unwrap_or_default();
+        let (next_year, next_month) = if self.month == 12 { (self.year + 1, 1) } else { (self.year, self.month + 1) };
+        let end = NaiveDate::from_ymd_opt(next_year, next_month, 1).unwrap_or_default();
+        let secs = |date: NaiveDate| date.and_hms_opt(0, 0, 0).unwrap_or_default().and_utc().timestamp().max(0) as u64;
+        (secs(start), secs(end))
+    }
+}
+
+impl FromStr for YearMonth {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (year, month) = s
+            .trim()
+            .split_once('-')
+            .ok_or_else(|| anyhow!("Expected a month as YYYY-MM, got '{}'", s))?;
+        Self::new(
+            year.parse().map_err(|_| anyhow!("Invalid year in '{}'", s))?,
+            month.parse().map_err(|_| anyhow!("Invalid month in '{}'", s))?,
+        )
+    }
+}
+
+impl TryFrom<String> for YearMonth {
+    type Error = anyhow::Error;
+
+    fn try_from(s: String) -> Result<Self> {
+        s.parse()
+    }
+}
+
+impl From<YearMonth> for String {
+    fn from(month: YearMonth) -> Self {
+        month.to_string()
+    }
+}
+
+impl fmt::Display for YearMonth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04}-{:02}", self.year, self.month)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StatementLine {
+    pub signature: Signature,
+    pub timestamp: u64,
+    /// Other side of the transfer; the default pubkey for mints and burns
+    pub counterparty: Pubkey,
+    pub counterparty_name: Option<String>,
+    pub memo: Option<String>,
+    /// Positive for money in, negative for money out, in base units
+    pub amount: i128,
+    pub running_balance: i128,
+    /// Network fee in lamports, when the owner sent the transaction
+    pub fee: u64,
+    /// The owner redacted this line's memo
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub redacted: bool,
+}
+
+/// One mint's activity over the statement period
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MintStatement {
+    pub currency: Currency,
+    /// On-chain balance after the last transaction before the period
+    pub opening_balance: u64,
+    /// No transaction before the period was synced and the history is not known to be
+    /// complete, so the opening balance of 0 is assumed rather than read from the chain
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub opening_balance_unverified: bool,
+    pub lines: Vec<StatementLine>,
+    /// Opening balance plus every line
+    pub closing_balance: i128,
+    /// On-chain balance after the last transaction in the period
+    pub on_chain_closing_balance: u64,
+    pub fees: u64,
+}
+
+impl MintStatement {
+    /// The ledger's lines do not add up to what the chain says the balance became
+    pub fn is_mismatched(&self) -> bool {
+        self.closing_balance != self.on_chain_closing_balance as i128
+    }
+}
+
+/// Opening balance, every transfer, and closing balance for one owner over one month
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Statement {
+    pub owner: Pubkey,
+    pub owner_name: Option<String>,
+    pub month: YearMonth,
+    pub generated_at: u64,
+    pub mints: Vec<MintStatement>,
+    /// Known holes in the synced history; any statement built over them may be incomplete
+    pub history_gaps: Vec<HistoryGap>,
+    /// Whether the synced history reaches back to the account's creation
+    #[serde(default)]
+    pub completeness: HistoryCompleteness,
+}
+
+impl Statement {
+    pub fn has_mismatch(&self) -> bool {
+        self.mints.iter().any(MintStatement::is_mismatched)
+    }
+
+    /// Markdown tables, one per mint, with mismatches and gaps called out before anything else
+    pub fn to_markdown(&self) -> String {
+        self.to_markdown_with(&AmountFormatter::default())
+    }
+
+    /// `to_markdown`, with amounts written by `formatter`
+    pub fn to_markdown_with(&self, formatter: &AmountFormatter) -> String {
+        let mut out = String::new();
+        let owner = match &self.owner_name {
+            Some(name) => format!("{} ({})", name, self.owner),
+            None => self.owner.to_string(),
+        };
+        let _ = writeln!(out, "# Statement for {}: {}\n", owner, self.month);
+
+        for mint in self.mints.iter().filter(|m| m.is_mismatched()) {
+            let _ = writeln!(
+                out,
+                "> **⚠️ BALANCE MISMATCH for {}**: transactions add up to {} but the chain reports {}. \
+                 This statement is missing activity; do not rely on it.\n",
+                mint.currency,
+                formatter.format_signed(&mint.currency, mint.closing_balance),
+                formatter.format(&mint.currency, mint.on_chain_closing_balance)
+            );
+        }
+        for mint in self.mints.iter().filter(|m| m.opening_balance_unverified) {
+            let _ = writeln!(
+                out,
+                "> **⚠️ UNVERIFIED OPENING BALANCE for {}**: history is {}, so earlier activity may be missing.\n",
+                mint.currency, self.completeness
+            );
+        }
+        if !self.history_gaps.is_empty() {
+            let _ = writeln!(
+                out,
+                "> **⚠️ {} gap(s) in synced history**; older or skipped transactions may be absent.\n",
+                self.history_gaps.len()
+            );
+        }
+
+        for mint in &self.mints {
+            let _ = writeln!(out, "## {}\n", mint.currency);
+            let _ = writeln!(out, "Opening balance: {}\n", formatter.format(&mint.currency, mint.opening_balance));
+            let _ = writeln!(out, "| Date (UTC) | Counterparty | Memo | In | Out | Balance | Transaction |");
+            let _ = writeln!(out, "|---|---|---|---:|---:|---:|---|");
+            for line in &mint.lines {
+                let date = Utc
+                    .timestamp_opt(line.timestamp as i64, 0)
+                    .single()
+                    .map(|t| t.format("%Y-%m-%d %H:%M").to_string())
+                    .unwrap_or_default();
+                let counterparty = if line.counterparty == Pubkey::default() {
+                    "mint/burn".to_string()
+                } else {
+                    match &line.counterparty_name {
+                        Some(name) => format!("{} ({})", name, readable(&line.counterparty)),
+                        None => readable(&line.counterparty),
+                    }
+                };
+                let (amount_in, amount_out) = if line.amount >= 0 {
+                    (formatter.format_units(&mint.currency, line.amount), String::new())
+                } else {
+                    (String::new(), formatter.format_units(&mint.currency, -line.amount))
+                };
+                let memo = line.memo.as_deref().unwrap_or("").replace('|', "\\|");
+                let signature = line.signature.to_string();
+                let _ = writeln!(
+                    out,
+                    "| {} | {} | {} | {} | {} | {} | {}… |",
+                    date,
+                    counterparty,
+                    memo,
+                    amount_in,
+                    amount_out,
+                    formatter.format_units(&mint.currency, line.running_balance),
+                    &signature[..12.min(signature.len())]
+                );
+            }
+            let _ = writeln!(out);
+            let _ = writeln!(out, "Closing balance: {}", formatter.format_signed(&mint.currency, mint.closing_balance));
+            let _ = writeln!(out, "On-chain closing balance: {}", formatter.format(&mint.currency, mint.on_chain_closing_balance));
+            let _ = writeln!(out, "Network fees paid: {}\n", formatter.format(&Currency::Sol, mint.fees));
+        }
+        out
+    }
+}
+
+impl FinternetClient {
+    /// Build `owner`'s statement for `month` from the local ledger
+    ///
+    /// Opening and closing balances come from the token balances the chain recorded on the
+    /// transactions either side of the period, so a ledger missing transfers shows up as a
+    /// mismatch. With `mint` unset, every mint the owner has moved gets its own section.
+    ///
+    /// A mint with nothing synced before the period opens at 0. When the ledger's history is
+    /// truncated that is a guess, so the statement fails with `TruncatedHistory` unless
+    /// `allow_partial` is set, in which case the section is flagged `opening_balance_unverified`.
+    #[tracing::instrument(skip_all, fields(wallet = %readable(owner), month = %month, rpc = %self.rpc_endpoint()))]
+    pub async fn generate_statement(
+        &self,
+        ledger: &LocalLedger,
+        owner: &Pubkey,
+        month: YearMonth,
+        mint: Option<Pubkey>,
+        names: Option<&IdentityIndex>,
+        allow_partial: bool,
+    ) -> Result<Statement> {
+        let (start, end) = month.bounds();
+        let completeness = ledger.completeness(owner)?;
+        let mut records = ledger.query_between(owner, 0, end.saturating_sub(1))?;
+        let redactions = self.get_redactions(owner).await?;
+        redactions.apply_to_records(&mut records);
+
+        let mut mints: Vec<Pubkey> = match mint {
+            Some(mint) => vec![mint],
+            None => {
+                let mut seen = HashSet::new();
+                records.iter().filter_map(|r| r.currency.mint()).filter(|m| seen.insert(*m)).collect()
+            }
+        };
+        mints.sort();
+
+        let mut sections = Vec::new();
+        for mint in mints {
+            let for_mint: Vec<&TransactionRecord> = records.iter().filter(|r| r.currency.mint() == Some(mint)).collect();
+            let before = for_mint.iter().rev().find(|r| r.timestamp < start);
+            let in_period: Vec<&TransactionRecord> = for_mint.iter().copied().filter(|r| r.timestamp >= start).collect();
+
+            let opening_balance = match before {
+                Some(record) => self.balance_after(owner, &mint, &record.signature)?,
+                None => 0,
+            };
+            if in_period.is_empty() && opening_balance == 0 {
+                continue;
+            }
+            let opening_balance_unverified = before.is_none() && !completeness.is_complete();
+            if opening_balance_unverified {
+                if let (Some(slot), false) = (completeness.truncated_at(), allow_partial) {
+                    return Err(TruncatedHistory {
+                        owner: *owner,
+                        slot,
+                        needed_for: format!("opening balance of {}", mint),
+                    }
+                    .into());
+                }
+            }
+
+            let mut running = opening_balance as i128;
+            let mut fees = 0;
+            let mut fee_counted = HashSet::new();
+            let mut lines = Vec::with_capacity(in_period.len());
+            for record in &in_period {
+                let incoming = record.to == *owner;
+                let counterparty = if incoming { record.from } else { record.to };
+                let amount = if incoming { record.amount as i128 } else { -(record.amount as i128) };
+                running += amount;
+                let fee = if record.from == *owner && fee_counted.insert(record.signature) { record.fee } else { 0 };
+                fees += fee;
+                lines.push(StatementLine {
+                    signature: record.signature,
+                    timestamp: record.timestamp,
+                    counterparty,
+                    counterparty_name: names.and_then(|n| n.display_name(&counterparty)).map(str::to_string),
+                    memo: record.memo.clone(),
+                    amount,
+                    running_balance: running,
+                    fee,
+                    redacted: redactions.hides_memo(&record.signature),
+                });
+            }
+
+            let on_chain_closing_balance = match in_period.last() {
+                Some(record) => self.balance_after(owner, &mint, &record.signature)?,
+                None => opening_balance,
+            };
+            // Records synced before decimals were stored carry 0, so ask the mint
+            let recorded = for_mint.last().map(|r| r.currency.decimals()).unwrap_or(0);
+            let decimals = self.client.get_token_supply(&mint).map(|s| s.decimals).unwrap_or(recorded);
+
+            let section = MintStatement {
+                currency: Currency::spl(mint, decimals),
+                opening_balance,
+                opening_balance_unverified,
+                lines,
+                closing_balance: running,
+                on_chain_closing_balance,
+                fees,
+            };
+            if section.is_mismatched() {
+                tracing::error!(
+                    "Statement for {} in {} does not reconcile for mint {}: computed {} vs on-chain {}",
+                    owner,
+                    month,
+                    mint,
+                    section.closing_balance,
+                    section.on_chain_closing_balance
+                );
+            }
+            sections.push(section);
+        }
+
+        Ok(Statement {
+            owner: *owner,
+            owner_name: names.and_then(|n| n.display_name(owner)).map(str::to_string),
+            month,
+            generated_at: Utc::now().timestamp().max(0) as u64,
+            mints: sections,
+            history_gaps: ledger.gaps(owner)?,
+            completeness,
+        })
+    }
+
+    /// What the chain recorded as `owner`'s total `mint` balance right after `signature`
+    fn balance_after(&self, owner: &Pubkey, mint: &Pubkey, signature: &Signature) -> Result<u64> {
+        let transaction = self.client.get_transaction_with_config(
+            signature,
+            RpcTransactionConfig {
+                encoding: Some(UiTransactionEncoding::Json),
+                commitment: Some(CommitmentConfig::confirmed()),
+                max_supported_transaction_version: Some(0),
+            },
+        )?;
+        let meta = transaction
+            .transaction
+            .meta
+            .ok_or_else(|| anyhow!("Transaction {} has no status metadata", signature))?;
+        let OptionSerializer::Some(balances) = meta.post_token_balances else {
+            return Err(anyhow!("Transaction {} has no token balances", signature));
+        };
+
+        let owner = owner.to_string();
+        let mint = mint.to_string();
+        Ok(balances
+            .iter()
+            .filter(|b| b.mint == mint && matches!(&b.owner, OptionSerializer::Some(o) if *o == owner))
+            .map(|b| b.ui_token_amount.amount.parse::<u64>().unwrap_or(0))
+            .sum())
+    }
+}
@@ -0,0 +1,298 @@
+//! Consolidating small token balances spread over many wallets into one account
+//!
+//! Each swept wallet signs its own transfer while the fee payer covers every fee, so the
+//! wallets need no SOL of their own. As many sweeps as fit are packed into each transaction,
+//! all sent through one `SendContext`; each transaction's fee is worked out locally.
+//! Balances are always swept in full, so sweeping again right away finds nothing new.
+
+use crate::confirmation::OperationKind;
+use crate::fees::{compute_unit_price_instruction, transaction_fee, PriorityFee};
+use crate::instructions::token_transfer_instruction;
+use crate::rpc_options::RpcCallOptions;
+use crate::telemetry::readable;
+use crate::FinternetClient;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use solana_sdk::{
+    instruction::Instruction,
+    message::Message,
+    packet::PACKET_DATA_SIZE,
+    program_pack::Pack,
+    pubkey::Pubkey,
+    signature::{Keypair, Signature},
+    signer::Signer,
+    transaction::Transaction,
+};
+use spl_token::instruction as token_instruction;
+
+/// Accounts fetched per `getMultipleAccounts` call, the RPC maximum
+const ACCOUNTS_PER_FETCH: usize = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SweepOptions {
+    /// Balances below this many base units are left alone, as not worth the fee
+    pub min_amount: u64,
+    /// Close each emptied token account, and any already empty one, returning its rent to
+    /// the fee payer
+    pub close_empty_accounts: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SweepAccountStatus {
+    /// Balance moved to the destination (and the account closed, if requested)
+    Swept,
+    /// Nothing to sweep, but the empty account was closed
+    Closed,
+    BelowMinimum,
+    /// Empty and left open
+    Empty,
+    /// The wallet has no token account for the mint
+    NoAccount,
+    /// The transaction carrying this sweep failed
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SweptAccount {
+    pub wallet: Pubkey,
+    pub token_account: Pubkey,
+    /// Base units moved, or the balance left behind when not swept
+    pub amount: u64,
+    pub status: SweepAccountStatus,
+    pub signature: Option<Signature>,
+    pub rent_reclaimed: u64,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SweepTransaction {
+    pub signature: Signature,
+    pub accounts: usize,
+    pub fee_lamports: u64,
+}
+
+/// What `sweep_balances` did to every wallet, and the transactions it took
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SweepReport {
+    pub mint: Pubkey,
+    /// Token account the balances went to
+    pub destination: Pubkey,
+    pub accounts: Vec<SweptAccount>,
+    pub transactions: Vec<SweepTransaction>,
+}
+
+impl SweepReport {
+    pub fn count(&self, status: SweepAccountStatus) -> usize {
+        self.accounts.iter().filter(|account| account.status == status).count()
+    }
+
+    pub fn total_swept(&self) -> u64 {
+        self.accounts
+            .iter()
+            .filter(|account| account.status == SweepAccountStatus::Swept)
+            .map(|account| account.amount)
+            .sum()
+    }
+
+    pub fn total_fees(&self) -> u64 {
+        self.transactions.iter().map(|transaction| transaction.fee_lamports).sum()
+    }
+
+    pub fn total_rent_reclaimed(&self) -> u64 {
+        self.accounts.iter().map(|account| account.rent_reclaimed).sum()
+    }
+}
+
+impl FinternetClient {
+    /// Move every balance of `mint` held by `wallets` to `destination`, a wallet or a token
+    /// account of the mint
+    ///
+    /// `fee_payer` pays all fees, creates the destination's ATA if needed, and receives the
+    /// rent of closed accounts. A failed transaction marks only its own accounts `Failed`; the
+    /// remaining transactions are still sent.
+    pub async fn sweep_balances(
+        &self,
+        fee_payer: &Keypair,
+        wallets: &[Keypair],
+        destination: &Pubkey,
+        mint: &Pubkey,
+        options: &SweepOptions,
+    ) -> Result<SweepReport> {
+        let outcome = self.sweep_balances_unlogged(fee_payer, wallets, destination, mint, options).await;
+        let parameters = serde_json::json!({
+            "fee_payer": fee_payer.pubkey().to_string(),
+            "wallets": wallets.iter().map(|wallet| wallet.pubkey().to_string()).collect::<Vec<_>>(),
+            "to": destination.to_string(),
+            "mint": mint.to_string(),
+            "min_amount": options.min_amount,
+            "close_empty_accounts": options.close_empty_accounts,
+            "swept": outcome.as_ref().ok().map(SweepReport::total_swept),
+        });
+        self.log_action("sweep_balances", parameters, None, outcome.as_ref(), |report| {
+            let signatures: Vec<String> = report.transactions.iter().map(|tx| tx.signature.to_string()).collect();
+            signatures.join(",")
+        });
+        outcome
+    }
+
+    #[tracing::instrument(skip_all, fields(wallet = %readable(&fee_payer.pubkey()), to = %readable(destination), mint = %mint, wallets = wallets.len()))]
+    async fn sweep_balances_unlogged(
+        &self,
+        fee_payer: &Keypair,
+        wallets: &[Keypair],
+        destination: &Pubkey,
+        mint: &Pubkey,
+        options: &SweepOptions,
+    ) -> Result<SweepReport> {
+        let context = self.prepare_send_context(mint)?;
+        let (destination_account, mut create_destination) =
+            self.context_destination(&context, &fee_payer.pubkey(), destination)?;
+        let fee = self.priority_fee_for(OperationKind::Payment, "sweep_balances", &[fee_payer.pubkey(), destination_account])?;
+
+        let token_accounts: Vec<Pubkey> = wallets
+            .iter()
+            .map(|wallet| spl_associated_token_account::get_associated_token_address(&wallet.pubkey(), mint))
+            .collect();
+        let mut fetched = Vec::with_capacity(token_accounts.len());
+        for chunk in token_accounts.chunks(ACCOUNTS_PER_FETCH) {
+            fetched.extend(self.client.get_multiple_accounts(chunk)?);
+        }
+
+        let mut report = SweepReport {
+            mint: *mint,
+            destination: destination_account,
+            accounts: Vec::with_capacity(wallets.len()),
+            transactions: Vec::new(),
+        };
+        // Index into `wallets` and the instructions sweeping it, for each account to touch
+        let mut sweeps: Vec<(usize, Vec<Instruction>)> = Vec::new();
+        for (index, (wallet, (token_account, account))) in
+            wallets.iter().zip(token_accounts.iter().zip(fetched)).enumerate()
+        {
+            let state = account
+                .as_ref()
+                .filter(|account| account.owner == spl_token::id() && *token_account != destination_account)
+                .and_then(|account| spl_token::state::Account::unpack(&account.data).ok());
+            let mut swept = SweptAccount {
+                wallet: wallet.pubkey(),
+                token_account: *token_account,
+                amount: state.map(|state| state.amount).unwrap_or(0),
+                status: SweepAccountStatus::NoAccount,
+                signature: None,
+                rent_reclaimed: 0,
+                error: None,
+            };
+            let Some(state) = state else {
+                report.accounts.push(swept);
+                continue;
+            };
+
+            let mut instructions = Vec::new();
+            if state.amount > 0 && state.amount >= options.min_amount {
+                swept.status = SweepAccountStatus::Swept;
+                instructions.push(token_transfer_instruction(&wallet.pubkey(), &destination_account, state.amount, mint)?);
+            } else if state.amount > 0 {
+                swept.status = SweepAccountStatus::BelowMinimum;
+            } else {
+                swept.status = SweepAccountStatus::Empty;
+            }
+            if options.close_empty_accounts && swept.status != SweepAccountStatus::BelowMinimum {
+                if swept.status == SweepAccountStatus::Empty {
+                    swept.status = SweepAccountStatus::Closed;
+                }
+                swept.rent_reclaimed = account.map(|account| account.lamports).unwrap_or(0);
+                instructions.push(token_instruction::close_account(
+                    &spl_token::id(),
+                    token_account,
+                    &fee_payer.pubkey(),
+                    &wallet.pubkey(),
+                    &[],
+                )?);
+            }
+            if !instructions.is_empty() {
+                sweeps.push((index, instructions));
+            }
+            report.accounts.push(swept);
+        }
+        tracing::info!("Sweeping {} of {} wallets into {}", sweeps.len(), wallets.len(), destination_account);
+
+        let mut remaining = sweeps.into_iter().peekable();
+        while remaining.peek().is_some() {
+            let mut instructions: Vec<Instruction> = fee.iter().map(compute_unit_price_instruction).collect();
+            instructions.extend(create_destination.take());
+            let mut batch = Vec::new();
+            while let Some((index, sweep)) = remaining.peek() {
+                let candidate: Vec<Instruction> = instructions.iter().chain(sweep).cloned().collect();
+                if !batch.is_empty() && transaction_size(&candidate, &fee_payer.pubkey()) > PACKET_DATA_SIZE {
+                    break;
+                }
+                instructions = candidate;
+                batch.push(*index);
+                remaining.next();
+            }
+
+            let mut signers: Vec<&Keypair> = vec![fee_payer];
+            signers.extend(batch.iter().map(|&index| &wallets[index]));
+            let outcome = self.send_sweep(fee_payer, &instructions, &signers, batch.len(), fee);
+            context.settle(destination, &outcome);
+            for &index in &batch {
+                let swept = &mut report.accounts[index];
+                match &outcome {
+                    Ok(transaction) => swept.signature = Some(transaction.signature),
+                    Err(e) => {
+                        swept.status = SweepAccountStatus::Failed;
+                        swept.rent_reclaimed = 0;
+                        swept.error = Some(e.to_string());
+                    }
+                }
+            }
+            match outcome {
+                Ok(transaction) => report.transactions.push(transaction),
+                Err(e) => tracing::warn!("Sweep of {} wallets failed: {}", batch.len(), e),
+            }
+        }
+
+        tracing::info!(
+            "Swept {} base units from {} wallets in {} transactions",
+            report.total_swept(),
+            report.count(SweepAccountStatus::Swept),
+            report.transactions.len()
+        );
+        Ok(report)
+    }
+
+    fn send_sweep(
+        &self,
+        fee_payer: &Keypair,
+        instructions: &[Instruction],
+        signers: &[&Keypair],
+        accounts: usize,
+        fee: Option<PriorityFee>,
+    ) -> Result<SweepTransaction> {
+        let mut transaction = Transaction::new_with_payer(instructions, Some(&fee_payer.pubkey()));
+        self.sign_with_fresh_blockhash(&mut transaction, signers)?;
+        let compute_unit_price = fee.map_or(0, |fee| fee.compute_unit_price);
+        let fee_lamports = transaction_fee(&transaction.message, compute_unit_price);
+        if let Some(fee) = fee {
+            self.report_priority_fee("sweep_balances", &transaction, fee);
+        }
+        let signature = self.send_and_confirm_resigning_as_priced(
+            OperationKind::Payment,
+            "sweep_balances",
+            transaction,
+            signers,
+            &RpcCallOptions::default(),
+        )?;
+        Ok(SweepTransaction { signature, accounts, fee_lamports })
+    }
+}
+
+/// Serialized size of a transaction carrying `instructions`, signatures included
+pub(crate) fn transaction_size(instructions: &[Instruction], payer: &Pubkey) -> usize {
+    let message = Message::new(instructions, Some(payer));
+    let signatures = message.header.num_required_signatures as usize;
+    // The signature count is a compact-u16, one byte below 128
+    let count_len = if signatures < 0x80 { 1 } else { 2 };
+    count_len + signatures * 64 + message.serialize().len()
+}
@@ -0,0 +1,438 @@
+//! Background tasks owned by the client, and shutting them down without leaving writes behind
+//!
+//! Everything the SDK runs in the background registers with the client's `TaskManager`: the
+//! watchers (`watch_balance_alerts`, `watch_deposits`, `watch_asset_metadata`,
+//! `watch_ledger_finality`), the payment queue and the sends it starts, and any task an
+//! application hands to `spawn`. `FinternetClient::shutdown` raises the shutdown signal, which
+//! ends the watchers and history streams at their next tick or page, waits for every task up
+//! to a timeout, aborts and awaits what is left, and then runs the flush hooks. Once it
+//! returns, no task can still be writing to a store. Dropping the manager aborts whatever is
+//! still running, so a dropped client does not leak tasks.
+
+use crate::FinternetClient;
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::sync::{Mutex, MutexGuard};
+use std::time::Duration;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+
+/// How long `FinternetClient::shutdown` waits for tasks before aborting them
+pub const DEFAULT_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Raised once when the client starts shutting down; clone one into each task
+#[derive(Debug, Clone)]
+pub struct ShutdownSignal {
+    receiver: watch::Receiver<bool>,
+}
+
+impl ShutdownSignal {
+    pub fn is_shutdown(&self) -> bool {
+        *self.receiver.borrow()
+    }
+
+    /// Resolves once shutdown starts, or at once if it already has
+    pub async fn cancelled(&self) {
+        let mut receiver = self.receiver.clone();
+        // The sender only goes away with the manager, which is as final as a shutdown
+        let _ = receiver.wait_for(|shutdown| *shutdown).await;
+    }
+}
+
+/// What `shutdown` did with each task, by the name it was spawned under
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ShutdownReport {
+    /// Finished on their own once signalled, or had already finished
+    pub stopped: Vec<String>,
+    /// Still running at the timeout, so aborted
+    pub aborted: Vec<String>,
+    /// Flush hooks that failed, with their errors
+    pub flush_errors: Vec<(String, String)>,
+}
+
+impl ShutdownReport {
+    /// Every task ended by itself and every flush succeeded
+    pub fn is_clean(&self) -> bool {
+        self.aborted.is_empty() && self.flush_errors.is_empty()
+    }
+}
+
+struct RegisteredTask {
+    name: String,
+    handle: JoinHandle<()>,
+}
+
+type FlushHook = Box<dyn Fn() -> Result<()> + Send + Sync>;
+
+/// Registry of the client's background tasks and what to flush once they stop
+pub struct TaskManager {
+    shutdown: watch::Sender<bool>,
+    tasks: Mutex<Vec<RegisteredTask>>,
+    flush_hooks: Mutex<Vec<(String, FlushHook)>>,
+}
+
+impl Default for TaskManager {
+    fn default() -> Self {
+        let (shutdown, _) = watch::channel(false);
+        Self {
+            shutdown,
+            tasks: Mutex::new(Vec::new()),
+            flush_hooks: Mutex::new(Vec::new()),
+        }
+    }
+}
+
+impl TaskManager {
+    pub fn signal(&self) -> ShutdownSignal {
+        ShutdownSignal { receiver: self.shutdown.subscribe() }
+    }
+
+    pub fn is_shutting_down(&self) -> bool {
+        *self.shutdown.borrow()
+    }
+
+    /// Run `future` as a tracked task, which should return soon after `signal()` is raised
+    ///
+    /// Refused once shutdown has started, since nothing would wait for the task.
+    pub fn spawn<F>(&self, name: &str, future: F) -> Result<()>
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        if self.is_shutting_down() {
+            return Err(anyhow!("Not starting task {}: the client is shutting down", name));
+        }
+        let handle = tokio::spawn(future);
+        let mut tasks = lock(&self.tasks);
+        tasks.retain(|task| !task.handle.is_finished());
+        tasks.push(RegisteredTask { name: name.to_string(), handle });
+        tracing::debug!("Started background task {}", name);
+        Ok(())
+    }
+
+    /// `spawn` for a future that does not watch the signal: it is dropped at its next await
+    /// once shutdown starts
+    pub fn spawn_until_shutdown<F>(&self, name: &str, future: F) -> Result<()>
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let signal = self.signal();
+        self.spawn(name, async move {
+            tokio::select! {
+                _ = future => {}
+                _ = signal.cancelled() => {}
+            }
+        })
+    }
+
+    /// Run `hook` after every task has stopped, e.g. to write out a checkpoint
+    pub fn on_shutdown(&self, name: &str, hook: impl Fn() -> Result<()> + Send + Sync + 'static) {
+        lock(&self.flush_hooks).push((name.to_string(), Box::new(hook)));
+    }
+
+    /// Names of the tasks still running
+    pub fn running(&self) -> Vec<String> {
+        lock(&self.tasks)
+            .iter()
+            .filter(|task| !task.handle.is_finished())
+            .map(|task| task.name.clone())
+            .collect()
+    }
+
+    /// Signal every task, wait up to `timeout` for all of them, abort the rest, then flush
+    ///
+    /// Aborted tasks are awaited too, so none of them is still between awaits, and possibly
+    /// mid-write, when the flush hooks run. Calling it again only re-runs the hooks.
+    pub async fn shutdown(&self, timeout: Duration) -> ShutdownReport {
+        self.shutdown.send_replace(true);
+        let tasks = std::mem::take(&mut *lock(&self.tasks));
+        tracing::info!("Shutting down {} background tasks", tasks.len());
+
+        let mut report = ShutdownReport::default();
+        let deadline = tokio::time::Instant::now() + timeout;
+        for mut task in tasks {
+            match tokio::time::timeout_at(deadline, &mut task.handle).await {
+                Ok(result) => {
+                    if let Some(e) = result.err().filter(|e| e.is_panic()) {
+                        tracing::warn!("Background task {} had panicked: {}", task.name, e);
+                    }
+                    report.stopped.push(task.name);
+                }
+                Err(_) => {
+                    tracing::warn!("Background task {} did not stop within {:?}; aborting it", task.name, timeout);
+                    task.handle.abort();
+                    let _ = task.handle.await;
+                    report.aborted.push(task.name);
+                }
+            }
+        }
+
+        for (name, hook) in lock(&self.flush_hooks).iter() {
+            if let Err(e) = hook() {
+                tracing::warn!("Flushing {} at shutdown failed: {}", name, e);
+                report.flush_errors.push((name.clone(), e.to_string()));
+            }
+        }
+        report
+    }
+}
+
+impl Drop for TaskManager {
+    fn drop(&mut self) {
+        self.shutdown.send_replace(true);
+        let tasks = std::mem::take(&mut *lock(&self.tasks));
+        if !tasks.is_empty() {
+            tracing::warn!("Client dropped without shutdown; aborting {} background tasks", tasks.len());
+        }
+        for task in tasks {
+            task.handle.abort();
+        }
+    }
+}
+
+impl FinternetClient {
+    /// The client's background tasks, for registering an application's own alongside the SDK's
+    pub fn tasks(&self) -> &TaskManager {
+        &self.tasks
+    }
+
+    /// Stop every background task within `DEFAULT_SHUTDOWN_TIMEOUT` and flush pending state
+    pub async fn shutdown(&self) -> ShutdownReport {
+        self.shutdown_with_timeout(DEFAULT_SHUTDOWN_TIMEOUT).await
+    }
+
+    /// `shutdown`, waiting up to `timeout` before aborting tasks
+    ///
+    /// After the tasks and flush hooks, it waits for any outbox write under way, so the
+    /// outbox on disk is final when this returns. The client still works for direct calls
+    /// afterwards, but refuses to start new background tasks.
+    pub async fn shutdown_with_timeout(&self, timeout: Duration) -> ShutdownReport {
+        let mut report = self.tasks.shutdown(timeout).await;
+        if let Some(outbox) = &self.outbox {
+            if let Err(e) = outbox.flush() {
+                report.flush_errors.push(("outbox".to_string(), e.to_string()));
+            }
+        }
+        tracing::info!(
+            "Client shut down: {} tasks stopped, {} aborted, {} flush errors",
+            report.stopped.len(),
+            report.aborted.len(),
+            report.flush_errors.len()
+        );
+        report
+    }
+}
+
+/// The registries stay usable after a panic elsewhere; they hold no invariants a panic can break
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::deposit::DepositManager;
+    use crate::history::HistoryFilter;
+    use crate::mock_cluster::{MockCluster, TempDir};
+    use crate::state_store::{MemoryStateStore, StateStore};
+    use crate::FinternetConfig;
+    use futures::StreamExt;
+    use serde_json::Value;
+    use solana_sdk::{pubkey::Pubkey, signature::{Keypair, Signature}};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    /// A memory store that counts its writes
+    #[derive(Default)]
+    struct CountingStore {
+        inner: MemoryStateStore,
+        writes: AtomicUsize,
+    }
+
+    impl CountingStore {
+        fn writes(&self) -> usize {
+            self.writes.load(Ordering::SeqCst)
+        }
+    }
+
+    impl StateStore for CountingStore {
+        fn get(&self, namespace: &str, key: &str) -> Result<Option<Value>> {
+            self.inner.get(namespace, key)
+        }
+
+        fn put(&self, namespace: &str, key: &str, value: Value, ttl: Option<Duration>) -> Result<()> {
+            self.writes.fetch_add(1, Ordering::SeqCst);
+            self.inner.put(namespace, key, value, ttl)
+        }
+
+        fn delete(&self, namespace: &str, key: &str) -> Result<bool> {
+            self.writes.fetch_add(1, Ordering::SeqCst);
+            self.inner.delete(namespace, key)
+        }
+
+        fn list(&self, namespace: &str) -> Result<Vec<(String, Value)>> {
+            self.inner.list(namespace)
+        }
+    }
+
+    /// Write a tick to `store` every millisecond, forever
+    async fn tick_forever(store: Arc<CountingStore>, key: &'static str) {
+        for tick in 0u64.. {
+            store.put("ticks", key, Value::from(tick), None).unwrap();
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+    }
+
+    /// Give `owner` a history that never runs out: every page is full of failed transactions,
+    /// which the stream yields without fetching
+    fn endless_history(cluster: &MockCluster, owner: Pubkey) {
+        cluster.respond("getSignaturesForAddress", move |params| {
+            if params[0] != owner.to_string() {
+                return None;
+            }
+            let limit = params[1]["limit"].as_u64().unwrap_or(1_000);
+            let page: Vec<Value> = (0..limit)
+                .map(|_| {
+                    serde_json::json!({
+                        "signature": Signature::new_unique().to_string(),
+                        "slot": 900,
+                        "err": { "InstructionError": [0, "InvalidArgument"] },
+                        "memo": null,
+                        "blockTime": null,
+                        "confirmationStatus": "finalized",
+                    })
+                })
+                .collect();
+            Some(Ok(Value::Array(page)))
+        });
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn after_shutdown_no_task_writes_to_the_store_and_every_stream_ends() {
+        let cluster = MockCluster::new();
+        let dir = TempDir::new("tasks-shutdown");
+        let client = Arc::new(cluster.client(FinternetConfig {
+            outbox_path: Some(dir.join("outbox.json")),
+            ..FinternetConfig::default()
+        }));
+        let store = Arc::new(CountingStore::default());
+        let tasks = client.tasks();
+
+        // An SDK watcher, a task that watches the signal, one that never looks at it, and one
+        // that does not know about shutdown at all
+        let manager = Arc::new(DepositManager::new(store.clone(), &Keypair::new(), Pubkey::new_unique()).unwrap());
+        manager.assign_address("customer-1").unwrap();
+        let (watching, deposits) = (Arc::clone(&client), Arc::clone(&manager));
+        tasks
+            .spawn("deposits", async move {
+                watching.watch_deposits(&deposits, 1, Duration::from_millis(5), |_| {}).await;
+            })
+            .unwrap();
+        let (signal, ticking) = (tasks.signal(), Arc::clone(&store));
+        tasks
+            .spawn("checkpointer", async move {
+                tokio::select! {
+                    _ = tick_forever(ticking, "checkpointer") => {}
+                    _ = signal.cancelled() => {}
+                }
+            })
+            .unwrap();
+        tasks.spawn("stubborn", tick_forever(Arc::clone(&store), "stubborn")).unwrap();
+        tasks.spawn_until_shutdown("unaware", tick_forever(Arc::clone(&store), "unaware")).unwrap();
+        let flushed_at = Arc::new(AtomicUsize::new(usize::MAX));
+        let (flushing, flushed) = (Arc::clone(&store), Arc::clone(&flushed_at));
+        tasks.on_shutdown("checkpoint", move || {
+            flushing.put("ticks", "flushed", Value::Bool(true), None)?;
+            flushed.store(flushing.writes(), Ordering::SeqCst);
+            Ok(())
+        });
+
+        let owner = Pubkey::new_unique();
+        endless_history(&cluster, owner);
+        let mut history = Box::pin(client.iter_history(&owner, HistoryFilter::default()));
+        assert!(history.next().await.unwrap().is_ok());
+
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(tasks.running().len(), 4);
+        assert!(store.writes() > 10);
+
+        let report = client.shutdown_with_timeout(Duration::from_millis(200)).await;
+        assert_eq!(report.stopped, ["deposits", "checkpointer", "unaware"]);
+        assert_eq!(report.aborted, ["stubborn"]);
+        assert!(report.flush_errors.is_empty());
+        assert!(!report.is_clean());
+        assert!(tasks.running().is_empty());
+
+        // The flush hook wrote last, and nothing has written since
+        let writes = store.writes();
+        assert_eq!(flushed_at.load(Ordering::SeqCst), writes);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(store.writes(), writes);
+
+        // The stream hands over the page it had and reads no further
+        let pages = cluster.call_count("getSignaturesForAddress");
+        let rest: Vec<_> = history.collect().await;
+        assert!(rest.iter().all(Result::is_ok));
+        assert!(rest.len() < 1_000);
+        assert_eq!(cluster.call_count("getSignaturesForAddress"), pages);
+        let mut late = Box::pin(client.iter_history(&owner, HistoryFilter::default()));
+        assert!(late.next().await.is_none());
+
+        // Nothing new starts once shutdown has begun
+        let error = tasks.spawn("late", async {}).unwrap_err();
+        assert!(error.to_string().contains("shutting down"), "{}", error);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn a_second_shutdown_only_flushes_and_failures_are_reported() {
+        let manager = TaskManager::default();
+        manager.spawn("finished", async {}).unwrap();
+        manager.spawn("panics", async { panic!("watcher bug") }).unwrap();
+        let flushes = Arc::new(AtomicUsize::new(0));
+        let counted = Arc::clone(&flushes);
+        manager.on_shutdown("checkpoint", move || {
+            counted.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        });
+        manager.on_shutdown("disk", || Err(anyhow!("disk full")));
+
+        let report = manager.shutdown(Duration::from_secs(1)).await;
+        assert_eq!(report.stopped, ["finished", "panics"]);
+        assert!(report.aborted.is_empty());
+        assert_eq!(report.flush_errors, [("disk".to_string(), "disk full".to_string())]);
+        assert!(!report.is_clean());
+        assert!(manager.signal().is_shutdown());
+
+        let again = manager.shutdown(Duration::from_secs(1)).await;
+        assert!(again.stopped.is_empty() && again.aborted.is_empty());
+        assert_eq!(flushes.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn dropping_the_manager_aborts_its_tasks() {
+        let manager = TaskManager::default();
+        let held = Arc::new(());
+        let holding = Arc::clone(&held);
+        manager
+            .spawn("forever", async move {
+                let _held = holding;
+                std::future::pending::<()>().await
+            })
+            .unwrap();
+        let signal = manager.signal();
+        assert!(!signal.is_shutdown());
+        assert_eq!(Arc::strong_count(&held), 2);
+
+        drop(manager);
+        // A dropped task releases what it held once the runtime gets to it
+        for _ in 0..100 {
+            if Arc::strong_count(&held) == 1 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        assert_eq!(Arc::strong_count(&held), 1);
+        assert!(signal.is_shutdown());
+        signal.cancelled().await;
+    }
+}
@@ -0,0 +1,74 @@
+use crate::recording::{redact_secrets, redact_url};
+use crate::FinternetClient;
+use solana_client::rpc_request::RpcRequest;
+use solana_client::rpc_sender::{RpcSender, RpcTransportStats};
+use solana_sdk::pubkey::Pubkey;
+use std::time::Instant;
+
+/// Shorten a public key for log fields (`abcdefgh...12345678`)
+pub(crate) fn readable(pubkey: &Pubkey) -> String {
+    FinternetClient::create_readable_address(pubkey, "")
+}
+
+/// RPC transport wrapper that emits a timing event for every call made through it, and counts it
+/// against the surrounding `usage::metered` future
+pub struct InstrumentedSender<S> {
+    inner: S,
+    url: String,
+    endpoint: String,
+}
+
+impl<S: RpcSender> InstrumentedSender<S> {
+    pub fn new(inner: S) -> Self {
+        let url = inner.url();
+        let endpoint = redact_url(&url);
+        Self { inner, url, endpoint }
+    }
+}
+
+#[async_trait::async_trait]
+impl<S: RpcSender + Send + Sync> RpcSender for InstrumentedSender<S> {
+    async fn send(
+        &self,
+        request: RpcRequest,
+        params: serde_json::Value,
+    ) -> solana_client::client_error::Result<serde_json::Value> {
+        let started = Instant::now();
+        crate::usage::count_rpc_call();
+        let response = self.inner.send(request, params).await;
+        let elapsed_ms = started.elapsed().as_secs_f64() * 1000.0;
+
+        match &response {
+            Ok(_) => tracing::debug!(
+                rpc.method = %request,
+                rpc.endpoint = %self.endpoint,
+                elapsed_ms,
+                "rpc call completed"
+            ),
+            Err(e) => tracing::warn!(
+                rpc.method = %request,
+                rpc.endpoint = %self.endpoint,
+                elapsed_ms,
+                error = %redact_secrets(&e.to_string(), &self.url),
+                "rpc call failed"
+            ),
+        }
+
+        response
+    }
+
+    fn get_transport_stats(&self) -> RpcTransportStats {
+        self.inner.get_transport_stats()
+    }
+
+    fn url(&self) -> String {
+        self.inner.url()
+    }
+}
+
+impl FinternetClient {
+    /// RPC endpoint with credentials stripped, safe to attach to spans
+    pub fn rpc_endpoint(&self) -> String {
+        redact_url(&self.config.rpc_url)
+    }
+}
@@ -0,0 +1,945 @@
+//! Time-locked payments: held in escrow, cancellable until they unlock, refundable once expired
+//!
+//! The funds sit in a token account owned by a fresh 1-of-1 SPL multisig of the sender. The
+//! recipient holds no authority over the escrow, so they cannot take the funds before they
+//! unlock. To claim, the recipient signs a claim transaction against a durable nonce created
+//! with the escrow and hands it to the sender, whose SDK rebuilds it from their own handle and
+//! countersigns it as the escrow's authority, refusing outside the claim window; neither party
+//! ever holds the other's key. The schedule itself (cancel before `unlock_at`, claim until
+//! `expiry`, reclaim after) is enforced by this SDK against the cluster clock, not by the chain,
+//! so it binds the recipient but not a sender who signs transfers by hand. Every transition
+//! empties and closes the escrow, so whoever settles first takes the whole balance and a losing
+//! cancel or claim fails on-chain. Each transition carries a typed ledger entry so either side
+//! can audit it with `get_timelock_status`.
+
+use crate::confirmation::OperationKind;
+use crate::payment::RecipientAccount;
+use crate::pipeline::{SendBody, SendRequest};
+use crate::telemetry::readable;
+use crate::FinternetClient;
+use anyhow::{anyhow, Result};
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use solana_sdk::{
+    hash::Hash,
+    instruction::Instruction,
+    message::Message,
+    nonce::State as NonceState,
+    program_pack::Pack,
+    pubkey::Pubkey,
+    signature::{Keypair, Signature},
+    signer::Signer,
+    system_instruction,
+    transaction::Transaction,
+};
+use spl_associated_token_account::instruction as ata_instruction;
+use spl_token::instruction as token_instruction;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub const TIMELOCK_CREATED_ACTION: &str = "timelock_created";
+pub const TIMELOCK_CANCELLED_ACTION: &str = "timelock_cancelled";
+pub const TIMELOCK_CLAIMED_ACTION: &str = "timelock_claimed";
+pub const TIMELOCK_RECLAIMED_ACTION: &str = "timelock_reclaimed";
+
+/// Ledger entries read back when auditing a time lock; it sees at most four transitions
+const STATUS_ENTRY_LIMIT: usize = 100;
+
+/// Everything either party needs to settle or audit a time-locked payment
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TimelockHandle {
+    pub id: String,
+    pub sender: Pubkey,
+    pub recipient: Pubkey,
+    pub mint: Pubkey,
+    pub decimals: u8,
+    /// Base units held in escrow
+    pub amount: u64,
+    /// 1-of-1 SPL multisig of the sender that owns the escrow; the recipient has no say over it
+    pub multisig: Pubkey,
+    /// The multisig's associated token account for `mint`
+    pub escrow: Pubkey,
+    /// Durable nonce controlled by the sender; it keeps a claim valid while the claim waits for
+    /// the sender's countersignature
+    pub nonce_account: Pubkey,
+    /// Unix seconds from which the recipient may claim and the sender may no longer cancel
+    pub unlock_at: u64,
+    /// Unix seconds from which the sender may reclaim and the recipient may no longer claim
+    pub expiry: u64,
+    pub created_at: u64,
+    /// Transaction that funded the escrow
+    pub signature: Signature,
+}
+
+impl TimelockHandle {
+    pub fn load(path: &Path) -> Result<Self> {
+        let data = fs::read_to_string(path)?;
+        serde_json::from_str(&data).map_err(|e| anyhow!("Invalid time lock handle {}: {}", path.display(), e))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            if !parent.as_os_str().is_empty() {
+                fs::create_dir_all(parent)?;
+            }
+        }
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// Where the funds stand at `now` while still in escrow
+    fn open_state(&self, now: u64) -> TimelockState {
+        if now < self.unlock_at {
+            TimelockState::Locked
+        } else if now < self.expiry {
+            TimelockState::Claimable
+        } else {
+            TimelockState::Expired
+        }
+    }
+}
+
+/// A claim signed by the recipient and waiting for the sender's countersignature
+///
+/// `message` is the exact transaction the recipient signed. The sender rebuilds it from their
+/// own copy of the handle and compares the two before countersigning, so a claim cannot be
+/// pointed at another escrow or payee.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TimelockClaim {
+    /// Id of the time lock claimed
+    pub id: String,
+    pub recipient: Pubkey,
+    /// Cluster time when the recipient signed, recorded in the claim's ledger entry
+    pub claimed_at: u64,
+    pub nonce_blockhash: String,
+    /// Base64 of the serialized transaction message
+    pub message: String,
+    pub recipient_signature: Signature,
+}
+
+impl TimelockClaim {
+    pub fn load(path: &Path) -> Result<Self> {
+        let data = fs::read_to_string(path)?;
+        serde_json::from_str(&data).map_err(|e| anyhow!("Invalid time lock claim {}: {}", path.display(), e))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            if !parent.as_os_str().is_empty() {
+                fs::create_dir_all(parent)?;
+            }
+        }
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TimelockState {
+    /// In escrow before `unlock_at`; the sender may cancel
+    Locked,
+    /// In escrow between `unlock_at` and `expiry`; the recipient may claim with the sender's countersignature
+    Claimable,
+    /// In escrow past `expiry`; the sender may reclaim
+    Expired,
+    Cancelled,
+    Claimed,
+    Reclaimed,
+    /// The escrow was emptied without a matching ledger entry, e.g. by a hand-built transfer
+    ReleasedOutsideSdk,
+}
+
+impl TimelockState {
+    pub fn is_settled(&self) -> bool {
+        !matches!(self, Self::Locked | Self::Claimable | Self::Expired)
+    }
+}
+
+impl fmt::Display for TimelockState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let label = match self {
+            Self::Locked => "locked",
+            Self::Claimable => "claimable",
+            Self::Expired => "expired",
+            Self::Cancelled => "cancelled",
+            Self::Claimed => "claimed",
+            Self::Reclaimed => "reclaimed",
+            Self::ReleasedOutsideSdk => "released outside the SDK",
+        };
+        f.write_str(label)
+    }
+}
+
+/// Why a time lock transition was refused
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TimelockError {
+    InvalidSchedule { reason: String },
+    NotSender { expected: Pubkey, actual: Pubkey },
+    NotRecipient { expected: Pubkey, actual: Pubkey },
+    /// Too late to cancel: the payment has unlocked
+    CancelWindowClosed { unlock_at: u64 },
+    /// Too early to claim
+    StillLocked { unlock_at: u64 },
+    /// Too late to claim: the payment has expired
+    ClaimWindowClosed { expiry: u64 },
+    /// Too early to reclaim
+    NotExpired { expiry: u64 },
+    /// Another transition emptied the escrow first
+    AlreadySettled { state: TimelockState },
+    /// The claim does not match the time lock it is for
+    InvalidClaim { reason: String },
+    /// The time lock's nonce moved on since the claim was signed; the recipient must claim again
+    ClaimNonceAdvanced { nonce_account: Pubkey },
+}
+
+impl TimelockError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::InvalidSchedule { .. } => "timelock_invalid_schedule",
+            Self::NotSender { .. } => "timelock_not_sender",
+            Self::NotRecipient { .. } => "timelock_not_recipient",
+            Self::CancelWindowClosed { .. } => "timelock_cancel_window_closed",
+            Self::StillLocked { .. } => "timelock_still_locked",
+            Self::ClaimWindowClosed { .. } => "timelock_claim_window_closed",
+            Self::NotExpired { .. } => "timelock_not_expired",
+            Self::AlreadySettled { .. } => "timelock_already_settled",
+            Self::InvalidClaim { .. } => "timelock_invalid_claim",
+            Self::ClaimNonceAdvanced { .. } => "timelock_claim_nonce_advanced",
+        }
+    }
+}
+
+impl fmt::Display for TimelockError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidSchedule { reason } => write!(f, "Invalid time lock schedule: {}", reason),
+            Self::NotSender { expected, actual } => {
+                write!(f, "Only the sender {} can do this, not {}", expected, actual)
+            }
+            Self::NotRecipient { expected, actual } => {
+                write!(f, "Only the recipient {} can claim, not {}", expected, actual)
+            }
+            Self::CancelWindowClosed { unlock_at } => {
+                write!(f, "The payment unlocked at {} and can no longer be cancelled", unlock_at)
+            }
+            Self::StillLocked { unlock_at } => write!(f, "The payment is locked until {}", unlock_at),
+            Self::ClaimWindowClosed { expiry } => {
+                write!(f, "The payment expired at {} and can only be reclaimed by the sender", expiry)
+            }
+            Self::NotExpired { expiry } => write!(f, "The payment cannot be reclaimed before it expires at {}", expiry),
+            Self::AlreadySettled { state } => write!(f, "The payment has already been {}", state),
+            Self::InvalidClaim { reason } => write!(f, "Invalid time lock claim: {}", reason),
+            Self::ClaimNonceAdvanced { nonce_account } => write!(
+                f,
+                "Nonce account {} has moved on since the claim was signed; the recipient must claim again",
+                nonce_account
+            ),
+        }
+    }
+}
+
+impl std::error::Error for TimelockError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TimelockAction {
+    Created,
+    Cancelled,
+    Claimed,
+    Reclaimed,
+}
+
+impl TimelockAction {
+    fn ledger_action(&self) -> &'static str {
+        match self {
+            Self::Created => TIMELOCK_CREATED_ACTION,
+            Self::Cancelled => TIMELOCK_CANCELLED_ACTION,
+            Self::Claimed => TIMELOCK_CLAIMED_ACTION,
+            Self::Reclaimed => TIMELOCK_RECLAIMED_ACTION,
+        }
+    }
+
+    fn from_ledger_action(action: &str) -> Option<Self> {
+        [Self::Created, Self::Cancelled, Self::Claimed, Self::Reclaimed]
+            .into_iter()
+            .find(|candidate| candidate.ledger_action() == action)
+    }
+}
+
+/// A transition recorded on the ledger by the party allowed to make it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TimelockEvent {
+    pub action: TimelockAction,
+    pub signer: Pubkey,
+    pub signature: Signature,
+    pub slot: u64,
+    pub timestamp: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TimelockStatus {
+    pub id: String,
+    pub state: TimelockState,
+    /// Base units still in escrow
+    pub escrow_balance: u64,
+    /// Oldest first
+    pub events: Vec<TimelockEvent>,
+    /// Cluster time the schedule was judged against
+    pub checked_at: u64,
+}
+
+impl FinternetClient {
+    /// Move `amount` of `mint` into escrow for `to`, claimable from `unlock_at` until `expiry`
+    ///
+    /// Sent through the send pipeline, so the same validation, asset policy, and spending limits
+    /// apply as to `send_payment`. The returned handle is what both parties pass to the other time lock calls; hand the
+    /// recipient a copy.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn send_timelocked_payment(
+        &self,
+        wallet: &Keypair,
+        to: &Pubkey,
+        amount: u64,
+        mint: &Pubkey,
+        unlock_at: u64,
+        expiry: u64,
+    ) -> Result<TimelockHandle> {
+        let outcome = self.send_timelocked_payment_unlogged(wallet, to, amount, mint, unlock_at, expiry).await;
+        let parameters = serde_json::json!({
+            "from": wallet.pubkey().to_string(),
+            "to": to.to_string(),
+            "amount": amount,
+            "mint": mint.to_string(),
+            "unlock_at": unlock_at,
+            "expiry": expiry,
+            "id": outcome.as_ref().ok().map(|handle| handle.id.clone()),
+        });
+        self.log_action("send_timelocked_payment", parameters, Some(mint), outcome.as_ref(), |handle| {
+            handle.signature.to_string()
+        });
+        outcome
+    }
+
+    #[tracing::instrument(skip_all, fields(wallet = %readable(&wallet.pubkey()), to = %readable(to), mint = %mint, amount, unlock_at, expiry))]
+    async fn send_timelocked_payment_unlogged(
+        &self,
+        wallet: &Keypair,
+        to: &Pubkey,
+        amount: u64,
+        mint: &Pubkey,
+        unlock_at: u64,
+        expiry: u64,
+    ) -> Result<TimelockHandle> {
+        let sender = wallet.pubkey();
+        if *to == sender {
+            return Err(TimelockError::InvalidSchedule { reason: "sender and recipient are the same wallet".to_string() }.into());
+        }
+        if unlock_at >= expiry {
+            return Err(TimelockError::InvalidSchedule {
+                reason: format!("unlock_at {} must come before expiry {}", unlock_at, expiry),
+            }
+            .into());
+        }
+        let now = self.get_chain_time().await?.unix_timestamp();
+        if expiry <= now {
+            return Err(TimelockError::InvalidSchedule {
+                reason: format!("expiry {} has already passed (cluster time {})", expiry, now),
+            }
+            .into());
+        }
+        if let RecipientAccount::TokenAccount(account) = self.resolve_recipient(to, mint)? {
+            return Err(anyhow!(
+                "{} is a token account; time-locked payments go to its owner wallet instead",
+                account
+            ));
+        }
+        let decimals = self.client.get_token_supply(mint)?.decimals;
+
+        let (multisig, nonce) = (Keypair::new(), Keypair::new());
+        let escrow = spl_associated_token_account::get_associated_token_address(&multisig.pubkey(), mint);
+        let mut handle = TimelockHandle {
+            id: uuid::Uuid::new_v4().to_string(),
+            sender,
+            recipient: *to,
+            mint: *mint,
+            decimals,
+            amount,
+            multisig: multisig.pubkey(),
+            escrow,
+            nonce_account: nonce.pubkey(),
+            unlock_at,
+            expiry,
+            created_at: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+            signature: Signature::default(),
+        };
+        let entry = serde_json::json!({
+            "action": TIMELOCK_CREATED_ACTION,
+            "id": handle.id,
+            "sender": sender.to_string(),
+            "recipient": to.to_string(),
+            "mint": mint.to_string(),
+            "amount": amount,
+            "multisig": handle.multisig.to_string(),
+            "escrow": escrow.to_string(),
+            "unlock_at": unlock_at,
+            "expiry": expiry,
+        });
+
+        let multisig_rent = self
+            .client
+            .get_minimum_balance_for_rent_exemption(spl_token::state::Multisig::LEN)?;
+        let nonce_rent = self.client.get_minimum_balance_for_rent_exemption(NonceState::size())?;
+        let mut instructions = system_instruction::create_nonce_account(&sender, &nonce.pubkey(), &sender, nonce_rent);
+        instructions.extend([
+            system_instruction::create_account(
+                &sender,
+                &multisig.pubkey(),
+                multisig_rent,
+                spl_token::state::Multisig::LEN as u64,
+                &spl_token::id(),
+            ),
+            token_instruction::initialize_multisig2(&spl_token::id(), &multisig.pubkey(), &[&sender], 1)?,
+            ata_instruction::create_associated_token_account_idempotent(&sender, &multisig.pubkey(), mint, &spl_token::id()),
+            token_instruction::transfer_checked(
+                &spl_token::id(),
+                &spl_associated_token_account::get_associated_token_address(&sender, mint),
+                mint,
+                &escrow,
+                &sender,
+                &[],
+                amount,
+                decimals,
+            )?,
+            spl_memo::build_memo(self.namespaced_entry(&entry.to_string())?.as_bytes(), &[&sender]),
+            // Zero-lamport notification so the payment shows up in the recipient's history
+            system_instruction::transfer(&sender, to, 0),
+        ]);
+
+        // Logged by `send_timelocked_payment`, with the handle's id
+        let request = SendRequest::payment("send_timelocked_payment", sender, *to, amount, *mint, None, serde_json::Value::Null)
+            .with_body(SendBody::Instructions(instructions))
+            .unrecorded();
+        handle.signature = self.send_through_pipeline(request, &[wallet, &multisig, &nonce]).await?.signature;
+
+        tracing::info!("Time lock {} funded: {}", handle.id, handle.signature);
+        Ok(handle)
+    }
+
+    /// Return the funds to the sender before the payment unlocks
+    pub async fn cancel_timelocked(&self, wallet: &Keypair, handle: &TimelockHandle) -> Result<Signature> {
+        self.settle_timelock_logged(wallet, handle, TimelockAction::Cancelled).await
+    }
+
+    /// Sign a claim to the funds as the recipient, between `unlock_at` and `expiry`
+    ///
+    /// Nothing is broadcast: hand the claim to the sender, whose `countersign_timelock_claim`
+    /// signs for the escrow and sends it. The claim rides on the time lock's durable nonce, so it
+    /// stays valid until the sender gets to it. The recipient pays the fee and signs the claim's
+    /// ledger entry.
+    #[tracing::instrument(skip_all, fields(wallet = %readable(&recipient_wallet.pubkey()), timelock = %handle.id))]
+    pub async fn claim_timelocked(&self, recipient_wallet: &Keypair, handle: &TimelockHandle) -> Result<TimelockClaim> {
+        let status = self
+            .check_transition(&recipient_wallet.pubkey(), handle, TimelockAction::Claimed)
+            .await?;
+        let nonce_blockhash = self.nonce_blockhash(&handle.nonce_account, &handle.sender)?;
+        let bytes = self.claim_message(handle, status.checked_at, &nonce_blockhash)?.serialize();
+
+        tracing::info!("Time lock {} claim signed, awaiting the countersignature of {}", handle.id, handle.sender);
+        Ok(TimelockClaim {
+            id: handle.id.clone(),
+            recipient: handle.recipient,
+            claimed_at: status.checked_at,
+            nonce_blockhash: nonce_blockhash.to_string(),
+            message: base64::engine::general_purpose::STANDARD.encode(&bytes),
+            recipient_signature: recipient_wallet.sign_message(&bytes),
+        })
+    }
+
+    /// Check the recipient's claim against `handle`, countersign it as the escrow's authority,
+    /// and broadcast it
+    ///
+    /// Refuses, with a `TimelockError`, claims outside the claim window, against a settled time
+    /// lock, altered in any way, or whose nonce has moved on.
+    pub async fn countersign_timelock_claim(
+        &self,
+        sender_wallet: &Keypair,
+        handle: &TimelockHandle,
+        claim: &TimelockClaim,
+    ) -> Result<Signature> {
+        let outcome = self.countersign_timelock_claim_unlogged(sender_wallet, handle, claim).await;
+        let parameters = serde_json::json!({
+            "wallet": sender_wallet.pubkey().to_string(),
+            "id": handle.id,
+            "sender": handle.sender.to_string(),
+            "recipient": handle.recipient.to_string(),
+            "mint": handle.mint.to_string(),
+            "amount": handle.amount,
+        });
+        self.log_action("claim_timelocked", parameters, None, outcome.as_ref(), Signature::to_string);
+        outcome
+    }
+
+    /// Return unclaimed funds to the sender once the payment has expired
+    pub async fn reclaim_expired(&self, wallet: &Keypair, handle: &TimelockHandle) -> Result<Signature> {
+        self.settle_timelock_logged(wallet, handle, TimelockAction::Reclaimed).await
+    }
+
+    /// Where a time lock stands, from its escrow balance and the ledger entries of each
+    /// transition; only entries signed by the party entitled to make them are trusted
+    #[tracing::instrument(skip_all, fields(timelock = %handle.id))]
+    pub async fn get_timelock_status(&self, handle: &TimelockHandle) -> Result<TimelockStatus> {
+        let checked_at = self.get_chain_time().await?.unix_timestamp();
+        let escrow_balance = match self.client.get_token_account_balance(&handle.escrow) {
+            Ok(balance) => balance.amount.parse::<u64>().unwrap_or(0),
+            // Closed by a settlement, or never funded
+            Err(_) => 0,
+        };
+
+        let mut events = Vec::new();
+        for entry in self.read_ledger_entries(&handle.multisig, Some(STATUS_ENTRY_LIMIT)).await? {
+            let Some(value) = entry.json() else { continue };
+            if value.get("id").and_then(|id| id.as_str()) != Some(handle.id.as_str()) {
+                continue;
+            }
+            let Some(action) = entry.action().as_deref().and_then(TimelockAction::from_ledger_action) else {
+                continue;
+            };
+            let entitled = match action {
+                TimelockAction::Claimed => handle.recipient,
+                _ => handle.sender,
+            };
+            match entry.signer {
+                Some(signer) if signer == entitled => events.push(TimelockEvent {
+                    action,
+                    signer,
+                    signature: entry.signature,
+                    slot: entry.slot,
+                    timestamp: entry.timestamp,
+                }),
+                _ => tracing::warn!("Ignoring {} entry for time lock {} not signed by {}", action.ledger_action(), handle.id, entitled),
+            }
+        }
+        events.sort_by_key(|event| event.slot);
+
+        let settled = events.iter().find_map(|event| match event.action {
+            TimelockAction::Created => None,
+            TimelockAction::Cancelled => Some(TimelockState::Cancelled),
+            TimelockAction::Claimed => Some(TimelockState::Claimed),
+            TimelockAction::Reclaimed => Some(TimelockState::Reclaimed),
+        });
+        let state = match settled {
+            Some(state) => state,
+            None if escrow_balance >= handle.amount => handle.open_state(checked_at),
+            None => TimelockState::ReleasedOutsideSdk,
+        };
+
+        Ok(TimelockStatus {
+            id: handle.id.clone(),
+            state,
+            escrow_balance,
+            events,
+            checked_at,
+        })
+    }
+
+    async fn settle_timelock_logged(&self, wallet: &Keypair, handle: &TimelockHandle, action: TimelockAction) -> Result<Signature> {
+        let outcome = self.settle_timelock(wallet, handle, action).await;
+        let parameters = serde_json::json!({
+            "wallet": wallet.pubkey().to_string(),
+            "id": handle.id,
+            "sender": handle.sender.to_string(),
+            "recipient": handle.recipient.to_string(),
+            "mint": handle.mint.to_string(),
+            "amount": handle.amount,
+        });
+        let operation = match action {
+            TimelockAction::Cancelled => "cancel_timelocked",
+            _ => "reclaim_expired",
+        };
+        self.log_action(operation, parameters, None, outcome.as_ref(), Signature::to_string);
+        outcome
+    }
+
+    /// Cancel or reclaim as the sender: empty and close the escrow back to them, if the schedule
+    /// allows it now
+    #[tracing::instrument(skip_all, fields(wallet = %readable(&wallet.pubkey()), timelock = %handle.id, action = ?action))]
+    async fn settle_timelock(&self, wallet: &Keypair, handle: &TimelockHandle, action: TimelockAction) -> Result<Signature> {
+        let status = self.check_transition(&wallet.pubkey(), handle, action).await?;
+        let instructions = self.settlement_instructions(handle, action, status.checked_at)?;
+        let label = action.ledger_action();
+        let mut transaction = Transaction::new_with_payer(&instructions, Some(&handle.sender));
+        self.sign_with_fresh_blockhash(&mut transaction, &[wallet])?;
+        match self.send_and_confirm_resigning(OperationKind::Payment, label, transaction, &[wallet]) {
+            Ok(signature) => {
+                tracing::info!("Time lock {} {}: {}", handle.id, status.state, signature);
+                Ok(signature)
+            }
+            Err(e) => Err(self.settled_instead(handle, e).await),
+        }
+    }
+
+    #[tracing::instrument(skip_all, fields(wallet = %readable(&sender_wallet.pubkey()), timelock = %handle.id))]
+    async fn countersign_timelock_claim_unlogged(
+        &self,
+        sender_wallet: &Keypair,
+        handle: &TimelockHandle,
+        claim: &TimelockClaim,
+    ) -> Result<Signature> {
+        if sender_wallet.pubkey() != handle.sender {
+            return Err(TimelockError::NotSender { expected: handle.sender, actual: sender_wallet.pubkey() }.into());
+        }
+        let invalid = |reason: &str| TimelockError::InvalidClaim { reason: reason.to_string() };
+        if claim.id != handle.id || claim.recipient != handle.recipient {
+            return Err(invalid("the claim is for another time lock").into());
+        }
+        let status = self.check_transition(&handle.recipient, handle, TimelockAction::Claimed).await?;
+
+        let stored = base64::engine::general_purpose::STANDARD
+            .decode(&claim.message)
+            .map_err(|_| invalid("message is not valid base64"))?;
+        let nonce_blockhash = Hash::from_str(&claim.nonce_blockhash).map_err(|_| invalid("nonce blockhash is not a hash"))?;
+        let message = self.claim_message(handle, claim.claimed_at, &nonce_blockhash)?;
+        if message.serialize() != stored {
+            return Err(invalid("transaction does not match the time lock's escrow, payee, or amount").into());
+        }
+        if !claim.recipient_signature.verify(handle.recipient.as_ref(), &stored) {
+            return Err(invalid("the recipient's signature does not verify").into());
+        }
+        if self.nonce_blockhash(&handle.nonce_account, &handle.sender)? != nonce_blockhash {
+            return Err(TimelockError::ClaimNonceAdvanced { nonce_account: handle.nonce_account }.into());
+        }
+
+        let mut transaction = Transaction::new_unsigned(message);
+        let recipient_index = transaction
+            .message
+            .account_keys
+            .iter()
+            .position(|key| *key == handle.recipient)
+            .ok_or_else(|| invalid("the recipient does not sign the claim"))?;
+        transaction.signatures[recipient_index] = claim.recipient_signature;
+        transaction.try_partial_sign(&[sender_wallet], nonce_blockhash)?;
+        match self.send_and_confirm_tracked(OperationKind::Payment, TIMELOCK_CLAIMED_ACTION, &transaction) {
+            Ok(signature) => {
+                tracing::info!("Time lock {} {}: {}", handle.id, status.state, signature);
+                Ok(signature)
+            }
+            Err(e) => Err(self.settled_instead(handle, e).await),
+        }
+    }
+
+    /// Refuse `action` by `actor` unless it is theirs to make and the schedule allows it now
+    async fn check_transition(&self, actor: &Pubkey, handle: &TimelockHandle, action: TimelockAction) -> Result<TimelockStatus> {
+        let entitled = match action {
+            TimelockAction::Claimed => handle.recipient,
+            _ => handle.sender,
+        };
+        if *actor != entitled {
+            return Err(match action {
+                TimelockAction::Claimed => TimelockError::NotRecipient { expected: entitled, actual: *actor },
+                _ => TimelockError::NotSender { expected: entitled, actual: *actor },
+            }
+            .into());
+        }
+
+        let status = self.get_timelock_status(handle).await?;
+        if status.state.is_settled() {
+            return Err(TimelockError::AlreadySettled { state: status.state }.into());
+        }
+        let allowed = match (action, status.state) {
+            (TimelockAction::Cancelled, TimelockState::Locked) => Ok(()),
+            (TimelockAction::Cancelled, _) => Err(TimelockError::CancelWindowClosed { unlock_at: handle.unlock_at }),
+            (TimelockAction::Claimed, TimelockState::Claimable) => Ok(()),
+            (TimelockAction::Claimed, TimelockState::Locked) => Err(TimelockError::StillLocked { unlock_at: handle.unlock_at }),
+            (TimelockAction::Claimed, _) => Err(TimelockError::ClaimWindowClosed { expiry: handle.expiry }),
+            (TimelockAction::Reclaimed, TimelockState::Expired) => Ok(()),
+            _ => Err(TimelockError::NotExpired { expiry: handle.expiry }),
+        };
+        allowed?;
+        Ok(status)
+    }
+
+    /// The claim transaction for `handle`: it advances the time lock's nonce, so it needs no
+    /// recent blockhash, and the recipient pays for it
+    fn claim_message(&self, handle: &TimelockHandle, claimed_at: u64, nonce_blockhash: &Hash) -> Result<Message> {
+        let mut instructions = vec![system_instruction::advance_nonce_account(&handle.nonce_account, &handle.sender)];
+        instructions.extend(self.settlement_instructions(handle, TimelockAction::Claimed, claimed_at)?);
+        Ok(Message::new_with_blockhash(&instructions, Some(&handle.recipient), nonce_blockhash))
+    }
+
+    /// Empty and close the escrow towards whoever `action` pays, with the entry its maker signs
+    fn settlement_instructions(&self, handle: &TimelockHandle, action: TimelockAction, timestamp: u64) -> Result<Vec<Instruction>> {
+        let payee = match action {
+            TimelockAction::Claimed => handle.recipient,
+            _ => handle.sender,
+        };
+        let entry = serde_json::json!({
+            "action": action.ledger_action(),
+            "id": handle.id,
+            "amount": handle.amount,
+            "to": payee.to_string(),
+            "timestamp": timestamp,
+        });
+        let payee_account = spl_associated_token_account::get_associated_token_address(&payee, &handle.mint);
+        Ok(vec![
+            ata_instruction::create_associated_token_account_idempotent(&payee, &payee, &handle.mint, &spl_token::id()),
+            token_instruction::transfer_checked(
+                &spl_token::id(),
+                &handle.escrow,
+                &handle.mint,
+                &payee_account,
+                &handle.multisig,
+                &[&handle.sender],
+                handle.amount,
+                handle.decimals,
+            )?,
+            // The sender paid the escrow's rent, so it goes back to them whoever settles. Closing
+            // the escrow is what makes a second settlement fail on-chain.
+            token_instruction::close_account(&spl_token::id(), &handle.escrow, &handle.sender, &handle.multisig, &[&handle.sender])?,
+            spl_memo::build_memo(self.namespaced_entry(&entry.to_string())?.as_bytes(), &[&payee]),
+        ])
+    }
+
+    /// A settlement failed: if the other party lost us the race, report what they did rather
+    /// than the raw failure
+    async fn settled_instead(&self, handle: &TimelockHandle, error: anyhow::Error) -> anyhow::Error {
+        match self.get_timelock_status(handle).await {
+            Ok(after) if after.state.is_settled() => TimelockError::AlreadySettled { state: after.state }.into(),
+            _ => error,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_cluster::{Landing, MockCluster, TempDir};
+    use crate::FinternetConfig;
+    use solana_sdk::instruction::InstructionError;
+    use solana_sdk::transaction::TransactionError;
+    use spl_token::instruction::TokenInstruction;
+    use std::time::Duration;
+
+    const NOW: u64 = 1_700_000_000;
+    const UNLOCK_AT: u64 = NOW + 3_600;
+    const EXPIRY: u64 = NOW + 7_200;
+
+    /// A sender holding 100 of a 6-decimal token and an empty recipient, both funded
+    struct Parties {
+        cluster: MockCluster,
+        sender: Keypair,
+        recipient: Keypair,
+        mint: Pubkey,
+    }
+
+    fn parties() -> Parties {
+        let cluster = MockCluster::new();
+        let (sender, recipient) = (Keypair::new(), Keypair::new());
+        cluster.set_balance(sender.pubkey(), 1_000_000_000);
+        cluster.set_balance(recipient.pubkey(), 1_000_000_000);
+        let mint = cluster.add_mint(&spl_token::id(), 6, &Pubkey::new_unique());
+        cluster.add_token_account(&sender.pubkey(), &mint, 100_000_000);
+        Parties { cluster, sender, recipient, mint }
+    }
+
+    impl Parties {
+        fn client(&self, dir: &TempDir, name: &str) -> FinternetClient {
+            self.cluster.client(FinternetConfig {
+                outbox_path: Some(dir.join(name)),
+                ..FinternetConfig::default()
+            })
+        }
+
+        /// Lock 25 tokens and fund the escrow and its nonce, which the mock does not do on its own
+        async fn lock(&self, client: &FinternetClient) -> TimelockHandle {
+            let handle = client
+                .send_timelocked_payment(&self.sender, &self.recipient.pubkey(), 25_000_000, &self.mint, UNLOCK_AT, EXPIRY)
+                .await
+                .unwrap();
+            self.cluster.set_token_account(handle.escrow, &handle.multisig, &self.mint, handle.amount);
+            self.cluster.advance_nonce(&handle.nonce_account, &self.sender.pubkey());
+            self.cluster.advance_blockhash();
+            handle
+        }
+
+        /// The settlement sent with `payer` as fee payer, waiting until it is sent
+        async fn settlement_from(&self, payer: &Pubkey, after: usize) -> Transaction {
+            for _ in 0..500 {
+                if let Some(transaction) = self.cluster.sent().into_iter().skip(after).find(|tx| tx.message.account_keys[0] == *payer) {
+                    return transaction;
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+            panic!("no settlement sent by {}", payer);
+        }
+    }
+
+    fn token_instructions(transaction: &Transaction) -> Vec<(TokenInstruction<'_>, Vec<Pubkey>)> {
+        let keys = &transaction.message.account_keys;
+        transaction
+            .message
+            .instructions
+            .iter()
+            .filter(|instruction| keys[instruction.program_id_index as usize] == spl_token::id())
+            .map(|instruction| {
+                let accounts = instruction.accounts.iter().map(|index| keys[*index as usize]).collect();
+                (TokenInstruction::unpack(&instruction.data).unwrap(), accounts)
+            })
+            .collect()
+    }
+
+    fn signers(transaction: &Transaction) -> Vec<Pubkey> {
+        transaction.message.account_keys[..transaction.message.header.num_required_signatures as usize].to_vec()
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn the_recipient_claims_without_the_senders_key_and_the_sender_countersigns() {
+        let dir = TempDir::new("timelock");
+        let parties = parties();
+        let client = parties.client(&dir, "outbox.json");
+
+        let handle = parties.lock(&client).await;
+        let funding = parties.cluster.sent().pop().unwrap();
+        let (instruction, accounts) = token_instructions(&funding).remove(0);
+        assert!(matches!(instruction, TokenInstruction::InitializeMultisig2 { m: 1 }));
+        assert_eq!(accounts, vec![handle.multisig, parties.sender.pubkey()]);
+        assert!(signers(&funding).contains(&handle.nonce_account));
+
+        let error = client.claim_timelocked(&parties.recipient, &handle).await.unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<TimelockError>(),
+            Some(&TimelockError::StillLocked { unlock_at: UNLOCK_AT })
+        );
+
+        // The recipient signs alone and nothing is sent until the sender countersigns
+        parties.cluster.set_time(UNLOCK_AT as i64);
+        let sent = parties.cluster.sent().len();
+        let claim = client.claim_timelocked(&parties.recipient, &handle).await.unwrap();
+        assert_eq!(parties.cluster.sent().len(), sent);
+        let path = dir.join("claim.json");
+        claim.save(&path).unwrap();
+        let claim = TimelockClaim::load(&path).unwrap();
+
+        let impostor = Keypair::new();
+        let error = client.countersign_timelock_claim(&impostor, &handle, &claim).await.unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<TimelockError>(),
+            Some(&TimelockError::NotSender { expected: parties.sender.pubkey(), actual: impostor.pubkey() })
+        );
+
+        let signature = client.countersign_timelock_claim(&parties.sender, &handle, &claim).await.unwrap();
+        let sent = parties.cluster.sent().pop().unwrap();
+        assert_eq!(sent.signatures[0], signature);
+        assert_eq!(signers(&sent), vec![parties.recipient.pubkey(), parties.sender.pubkey()]);
+        assert_eq!(sent.message.recent_blockhash.to_string(), claim.nonce_blockhash);
+        for (instruction, accounts) in token_instructions(&sent) {
+            match instruction {
+                TokenInstruction::TransferChecked { amount, .. } => {
+                    assert_eq!(amount, 25_000_000);
+                    assert_eq!(accounts[3..], [handle.multisig, parties.sender.pubkey()]);
+                }
+                TokenInstruction::CloseAccount => assert_eq!(accounts[2..], [handle.multisig, parties.sender.pubkey()]),
+                other => panic!("unexpected {:?}", other),
+            }
+        }
+        assert_eq!(client.get_timelock_status(&handle).await.unwrap().state, TimelockState::Claimed);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn the_sender_refuses_to_countersign_an_altered_or_stale_claim() {
+        let dir = TempDir::new("timelock");
+        let parties = parties();
+        let client = parties.client(&dir, "outbox.json");
+        let handle = parties.lock(&client).await;
+        parties.cluster.set_time(UNLOCK_AT as i64);
+        let claim = client.claim_timelocked(&parties.recipient, &handle).await.unwrap();
+        let sent = parties.cluster.sent().len();
+
+        // Another of the sender's time locks, also claimable, that the claim is pointed at
+        let other = parties.lock(&client).await;
+        let retargeted = TimelockClaim { id: other.id.clone(), ..claim.clone() };
+        let backdated = TimelockClaim { claimed_at: NOW, ..claim.clone() };
+        let forged = TimelockClaim { recipient_signature: Keypair::new().sign_message(b"claim"), ..claim.clone() };
+        let sent = sent + 1;
+        for (handle, claim) in [(&other, &retargeted), (&handle, &backdated), (&handle, &forged)] {
+            let error = client.countersign_timelock_claim(&parties.sender, handle, claim).await.unwrap_err();
+            assert_eq!(error.downcast_ref::<TimelockError>().map(TimelockError::code), Some("timelock_invalid_claim"));
+        }
+
+        parties.cluster.advance_nonce(&handle.nonce_account, &parties.sender.pubkey());
+        let error = client.countersign_timelock_claim(&parties.sender, &handle, &claim).await.unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<TimelockError>(),
+            Some(&TimelockError::ClaimNonceAdvanced { nonce_account: handle.nonce_account })
+        );
+        assert_eq!(parties.cluster.sent().len(), sent);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn a_claim_after_a_landed_cancel_is_refused_without_sending() {
+        let dir = TempDir::new("timelock");
+        let parties = parties();
+        let client = parties.client(&dir, "outbox.json");
+        let handle = parties.lock(&client).await;
+
+        client.cancel_timelocked(&parties.sender, &handle).await.unwrap();
+        parties.cluster.set_time(UNLOCK_AT as i64);
+        let sent = parties.cluster.sent().len();
+
+        let error = client.claim_timelocked(&parties.recipient, &handle).await.unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<TimelockError>(),
+            Some(&TimelockError::AlreadySettled { state: TimelockState::Cancelled })
+        );
+        assert_eq!(parties.cluster.sent().len(), sent);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn a_cancel_and_a_claim_in_flight_together_settle_once() {
+        let dir = TempDir::new("timelock");
+        let parties = parties();
+        let handle = parties.lock(&parties.client(&dir, "outbox.json")).await;
+        let after_funding = parties.cluster.sent().len();
+        parties.cluster.set_landing(Landing::Pending);
+
+        // The sender cancels just before the payment unlocks...
+        let cancel = {
+            let client = parties.client(&dir, "sender.json");
+            let (sender, handle) = (Keypair::from_bytes(&parties.sender.to_bytes()).unwrap(), handle.clone());
+            tokio::spawn(async move { client.cancel_timelocked(&sender, &handle).await })
+        };
+        let cancel_tx = parties.settlement_from(&parties.sender.pubkey(), after_funding).await;
+
+        // ...and the recipient claims just after, countersigned before the cancel has landed
+        parties.cluster.set_time(UNLOCK_AT as i64);
+        let claim = {
+            let client = parties.client(&dir, "recipient.json");
+            let signed = client.claim_timelocked(&parties.recipient, &handle).await.unwrap();
+            let (sender, handle) = (Keypair::from_bytes(&parties.sender.to_bytes()).unwrap(), handle.clone());
+            tokio::spawn(async move { client.countersign_timelock_claim(&sender, &handle, &signed).await })
+        };
+        let claim_tx = parties.settlement_from(&parties.recipient.pubkey(), after_funding).await;
+
+        // The cancel closes the escrow, so the claim fails on-chain
+        parties.cluster.land(&cancel_tx.signatures[0], None);
+        parties.cluster.land(
+            &claim_tx.signatures[0],
+            Some(TransactionError::InstructionError(1, InstructionError::UninitializedAccount)),
+        );
+
+        assert_eq!(cancel.await.unwrap().unwrap(), cancel_tx.signatures[0]);
+        let error = claim.await.unwrap().unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<TimelockError>(),
+            Some(&TimelockError::AlreadySettled { state: TimelockState::Cancelled })
+        );
+
+        let status = parties.client(&dir, "outbox.json").get_timelock_status(&handle).await.unwrap();
+        assert_eq!(status.state, TimelockState::Cancelled);
+        let actions: Vec<_> = status.events.iter().map(|event| event.action).collect();
+        assert_eq!(actions, vec![TimelockAction::Created, TimelockAction::Cancelled]);
+    }
+}
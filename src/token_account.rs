@@ -0,0 +1,379 @@
+//! Decoding token accounts from whatever form the RPC node returns them in
+//!
+//! `getTokenAccountsByOwner` answers with base64 or base58 account data, the legacy base58 form,
+//! or jsonParsed objects, and Token-2022 accounts carry extensions after the base layout.
+//! `decode_token_account` handles every one of these and returns an error, never a panic, for
+//! anything else. Owner reads collect the accounts that fail into a `TokenAccountScan` so callers
+//! can say how many were left out and why.
+
+use crate::ledger::{TokenAccountDetail, TokenAccountState};
+use serde::{Deserialize, Serialize};
+use solana_account_decoder::{UiAccountData, UiAccountEncoding};
+use solana_sdk::{program_pack::Pack, pubkey::Pubkey};
+use spl_token_2022::extension::StateWithExtensions;
+use std::fmt;
+
+/// The fields of a token account, without the account's own address
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecodedTokenAccount {
+    pub mint: Pubkey,
+    pub owner: Pubkey,
+    pub amount: u64,
+    /// Only known when the RPC node returned jsonParsed data
+    pub decimals: Option<u8>,
+    pub state: TokenAccountState,
+    pub delegate: Option<Pubkey>,
+    pub delegated_amount: u64,
+    pub close_authority: Option<Pubkey>,
+}
+
+impl DecodedTokenAccount {
+    pub fn into_detail(self, address: Pubkey) -> TokenAccountDetail {
+        TokenAccountDetail {
+            address,
+            mint: self.mint,
+            owner: self.owner,
+            amount: self.amount,
+            decimals: self.decimals,
+            state: self.state,
+            delegate: self.delegate,
+            delegated_amount: self.delegated_amount,
+            close_authority: self.close_authority,
+        }
+    }
+}
+
+/// Why an account returned as a token account could not be read as one
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TokenAccountDecodeError {
+    /// The RPC node returned an address that is not a public key
+    InvalidAddress { address: String },
+    /// The data does not decode from its stated encoding
+    InvalidEncoding { encoding: String, reason: String },
+    UnsupportedEncoding { encoding: String },
+    /// The bytes are not a token account of either token program
+    InvalidData { reason: String },
+    /// jsonParsed data describing something other than a token account, such as a mint
+    NotTokenAccount { kind: String },
+    MissingField { field: &'static str },
+    InvalidField { field: &'static str, value: String },
+}
+
+impl TokenAccountDecodeError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::InvalidAddress { .. } => "token_account_invalid_address",
+            Self::InvalidEncoding { .. } => "token_account_invalid_encoding",
+            Self::UnsupportedEncoding { .. } => "token_account_unsupported_encoding",
+            Self::InvalidData { .. } => "token_account_invalid_data",
+            Self::NotTokenAccount { .. } => "token_account_not_token_account",
+            Self::MissingField { .. } => "token_account_missing_field",
+            Self::InvalidField { .. } => "token_account_invalid_field",
+        }
+    }
+}
+
+impl fmt::Display for TokenAccountDecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidAddress { address } => write!(f, "Account address '{}' is not a public key", address),
+            Self::InvalidEncoding { encoding, reason } => write!(f, "Account data is not valid {}: {}", encoding, reason),
+            Self::UnsupportedEncoding { encoding } => write!(f, "Account data encoding {} is not supported", encoding),
+            Self::InvalidData { reason } => write!(f, "Account data is not a token account: {}", reason),
+            Self::NotTokenAccount { kind } => write!(f, "Parsed account is a {}, not a token account", kind),
+            Self::MissingField { field } => write!(f, "Parsed token account has no {}", field),
+            Self::InvalidField { field, value } => write!(f, "Parsed token account has an invalid {}: {}", field, value),
+        }
+    }
+}
+
+impl std::error::Error for TokenAccountDecodeError {}
+
+/// An account an owner read returned but could not decode
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UndecodableAccount {
+    /// As the RPC node returned it, which may not be a valid public key
+    pub address: String,
+    pub code: String,
+    pub reason: String,
+}
+
+/// Every token account an owner read returned, decoded or not
+#[derive(Debug, Clone, Default)]
+pub struct TokenAccountScan {
+    pub accounts: Vec<TokenAccountDetail>,
+    pub undecodable: Vec<UndecodableAccount>,
+}
+
+impl TokenAccountScan {
+    /// Decode `address`'s `data` into the scan, recording it as undecodable if it fails
+    pub fn push(&mut self, address: &str, data: &UiAccountData) {
+        let decoded = address
+            .parse::<Pubkey>()
+            .map_err(|_| TokenAccountDecodeError::InvalidAddress { address: address.to_string() })
+            .and_then(|pubkey| decode_token_account(data).map(|account| account.into_detail(pubkey)));
+        match decoded {
+            Ok(detail) => self.accounts.push(detail),
+            Err(e) => self.undecodable.push(UndecodableAccount {
+                address: address.to_string(),
+                code: e.code().to_string(),
+                reason: e.to_string(),
+            }),
+        }
+    }
+}
+
+/// Decode a token account of either token program from any RPC encoding of its data
+pub fn decode_token_account(data: &UiAccountData) -> Result<DecodedTokenAccount, TokenAccountDecodeError> {
+    match data {
+        UiAccountData::LegacyBinary(data) => decode_binary(&decode_base58(data)?),
+        UiAccountData::Binary(data, UiAccountEncoding::Base58) => decode_binary(&decode_base58(data)?),
+        UiAccountData::Binary(data, UiAccountEncoding::Base64) => {
+            use base64::{engine::general_purpose, Engine};
+            let bytes = general_purpose::STANDARD.decode(data).map_err(|e| TokenAccountDecodeError::InvalidEncoding {
+                encoding: "base64".to_string(),
+                reason: e.to_string(),
+            })?;
+            decode_binary(&bytes)
+        }
+        UiAccountData::Binary(_, encoding) => {
+            Err(TokenAccountDecodeError::UnsupportedEncoding { encoding: format!("{:?}", encoding) })
+        }
+        UiAccountData::Json(parsed) => decode_parsed(&parsed.parsed),
+    }
+}
+
+fn decode_base58(data: &str) -> Result<Vec<u8>, TokenAccountDecodeError> {
+    bs58::decode(data).into_vec().map_err(|e| TokenAccountDecodeError::InvalidEncoding {
+        encoding: "base58".to_string(),
+        reason: e.to_string(),
+    })
+}
+
+/// The base layout is the same for both programs; Token-2022 appends its extensions after it
+fn decode_binary(bytes: &[u8]) -> Result<DecodedTokenAccount, TokenAccountDecodeError> {
+    if bytes.len() < spl_token_2022::state::Account::LEN {
+        return Err(TokenAccountDecodeError::InvalidData {
+            reason: format!("{} bytes, a token account has at least {}", bytes.len(), spl_token_2022::state::Account::LEN),
+        });
+    }
+    let account = StateWithExtensions::<spl_token_2022::state::Account>::unpack(bytes)
+        .map_err(|e| TokenAccountDecodeError::InvalidData { reason: e.to_string() })?
+        .base;
+    Ok(DecodedTokenAccount {
+        mint: account.mint,
+        owner: account.owner,
+        amount: account.amount,
+        decimals: None,
+        state: match account.state {
+            spl_token_2022::state::AccountState::Uninitialized => TokenAccountState::Uninitialized,
+            spl_token_2022::state::AccountState::Initialized => TokenAccountState::Initialized,
+            spl_token_2022::state::AccountState::Frozen => TokenAccountState::Frozen,
+        },
+        delegate: account.delegate.into(),
+        delegated_amount: account.delegated_amount,
+        close_authority: account.close_authority.into(),
+    })
+}
+
+/// jsonParsed data: `{"type": "account", "info": {...}}`; Token-2022 extensions are ignored
+fn decode_parsed(parsed: &serde_json::Value) -> Result<DecodedTokenAccount, TokenAccountDecodeError> {
+    use TokenAccountDecodeError::{InvalidField, MissingField};
+
+    if let Some(kind) = parsed.get("type") {
+        if kind.as_str() != Some("account") {
+            return Err(TokenAccountDecodeError::NotTokenAccount { kind: kind.to_string() });
+        }
+    }
+    let info = parsed.get("info").ok_or(MissingField { field: "info" })?;
+    let invalid = |field: &'static str, value: &serde_json::Value| InvalidField { field, value: value.to_string() };
+    let pubkey = |field: &'static str| -> Result<Option<Pubkey>, TokenAccountDecodeError> {
+        match info.get(field) {
+            None | Some(serde_json::Value::Null) => Ok(None),
+            Some(value) => value.as_str().and_then(|v| v.parse().ok()).map(Some).ok_or_else(|| invalid(field, value)),
+        }
+    };
+    let amount = |value: &serde_json::Value, field: &'static str| -> Result<u64, TokenAccountDecodeError> {
+        let amount = value.get("amount").ok_or(MissingField { field })?;
+        amount.as_str().and_then(|a| a.parse().ok()).ok_or_else(|| invalid(field, amount))
+    };
+
+    let mint = pubkey("mint")?.ok_or(MissingField { field: "mint" })?;
+    let owner = pubkey("owner")?.ok_or(MissingField { field: "owner" })?;
+    let token_amount = info.get("tokenAmount").ok_or(MissingField { field: "tokenAmount" })?;
+    let decimals = match token_amount.get("decimals") {
+        None => None,
+        Some(decimals) => {
+            Some(decimals.as_u64().and_then(|d| u8::try_from(d).ok()).ok_or_else(|| invalid("tokenAmount.decimals", decimals))?)
+        }
+    };
+    let state = match info.get("state").and_then(|v| v.as_str()) {
+        Some("frozen") => TokenAccountState::Frozen,
+        Some("uninitialized") => TokenAccountState::Uninitialized,
+        _ => TokenAccountState::Initialized,
+    };
+    let delegated_amount = match info.get("delegatedAmount") {
+        None | Some(serde_json::Value::Null) => 0,
+        Some(delegated) => amount(delegated, "delegatedAmount.amount")?,
+    };
+
+    Ok(DecodedTokenAccount {
+        mint,
+        owner,
+        amount: amount(token_amount, "tokenAmount.amount")?,
+        decimals,
+        state,
+        delegate: pubkey("delegate")?,
+        delegated_amount,
+        close_authority: pubkey("closeAuthority")?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base64::{engine::general_purpose, Engine};
+    use std::path::{Path, PathBuf};
+
+    /// Account dumps in `tests/fixtures/token_accounts`, also the seed corpus of the fuzz target
+    fn fixtures() -> Vec<(PathBuf, serde_json::Value)> {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/token_accounts");
+        let mut paths: Vec<PathBuf> = std::fs::read_dir(&dir).unwrap().map(|entry| entry.unwrap().path()).collect();
+        paths.sort();
+        paths
+            .into_iter()
+            .map(|path| {
+                let fixture = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+                (path, fixture)
+            })
+            .collect()
+    }
+
+    fn data(fixture: &serde_json::Value) -> UiAccountData {
+        serde_json::from_value(fixture["data"].clone()).unwrap()
+    }
+
+    fn pubkey(value: &serde_json::Value) -> Option<Pubkey> {
+        value.as_str().map(|key| key.parse().unwrap())
+    }
+
+    #[test]
+    fn every_fixture_decodes_to_its_expected_fields_or_error() {
+        let fixtures = fixtures();
+        assert!(fixtures.len() >= 15);
+        for (path, fixture) in fixtures {
+            let name = path.file_name().unwrap().to_string_lossy().into_owned();
+            let decoded = decode_token_account(&data(&fixture));
+            if let Some(code) = fixture["error"].as_str() {
+                assert_eq!(decoded.map_err(|e| e.code()).unwrap_err(), code, "{}", name);
+                continue;
+            }
+            let expected = &fixture["expected"];
+            let state = serde_json::from_value(expected["state"].clone()).unwrap();
+            let want = DecodedTokenAccount {
+                mint: pubkey(&expected["mint"]).unwrap(),
+                owner: pubkey(&expected["owner"]).unwrap(),
+                amount: expected["amount"].as_u64().unwrap(),
+                decimals: expected["decimals"].as_u64().map(|decimals| decimals as u8),
+                state,
+                delegate: pubkey(&expected["delegate"]),
+                delegated_amount: expected["delegated_amount"].as_u64().unwrap(),
+                close_authority: pubkey(&expected["close_authority"]),
+            };
+            assert_eq!(decoded.unwrap_or_else(|e| panic!("{}: {}", name, e)), want, "{}", name);
+        }
+    }
+
+    #[test]
+    fn a_scan_keeps_what_decodes_and_says_why_the_rest_did_not() {
+        let mut scan = TokenAccountScan::default();
+        let fixtures = fixtures();
+        for (_, fixture) in &fixtures {
+            scan.push(&Pubkey::new_unique().to_string(), &data(fixture));
+        }
+        scan.push("not-an-address", &data(&fixtures[0].1));
+        let failing = fixtures.iter().filter(|(_, fixture)| fixture.get("error").is_some()).count();
+        assert_eq!(scan.accounts.len(), fixtures.len() - failing);
+        assert_eq!(scan.undecodable.len(), failing + 1);
+        assert_eq!(scan.undecodable.last().unwrap().code, "token_account_invalid_address");
+    }
+
+    /// xorshift64*, so a failing input reproduces
+    fn next(state: &mut u64) -> u64 {
+        *state ^= *state >> 12;
+        *state ^= *state << 25;
+        *state ^= *state >> 27;
+        state.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    #[test]
+    fn truncated_and_corrupted_dumps_are_errors_not_panics() {
+        let mut state = 0x853c_49e6_748f_ea9b;
+        for (path, fixture) in fixtures() {
+            let UiAccountData::Binary(encoded, UiAccountEncoding::Base64) = data(&fixture) else {
+                continue;
+            };
+            let Ok(bytes) = general_purpose::STANDARD.decode(&encoded) else {
+                continue;
+            };
+            for len in 0..bytes.len() {
+                let truncated = UiAccountData::Binary(general_purpose::STANDARD.encode(&bytes[..len]), UiAccountEncoding::Base64);
+                if len < spl_token_2022::state::Account::LEN {
+                    assert!(decode_token_account(&truncated).is_err(), "{} cut to {}", path.display(), len);
+                } else {
+                    let _ = decode_token_account(&truncated);
+                }
+            }
+            for _ in 0..2_000 {
+                let mut corrupted = bytes.clone();
+                for _ in 0..=next(&mut state) % 4 {
+                    let at = (next(&mut state) as usize) % corrupted.len().max(1);
+                    if let Some(byte) = corrupted.get_mut(at) {
+                        *byte = next(&mut state) as u8;
+                    }
+                }
+                let extra = next(&mut state) % 24;
+                corrupted.extend((0..extra).map(|_| next(&mut state) as u8));
+                let _ = decode_token_account(&UiAccountData::Binary(general_purpose::STANDARD.encode(&corrupted), UiAccountEncoding::Base64));
+                let _ = decode_token_account(&UiAccountData::LegacyBinary(bs58::encode(&corrupted).into_string()));
+            }
+        }
+    }
+
+    #[test]
+    fn arbitrary_parsed_json_never_panics() {
+        let values = [
+            serde_json::json!(null),
+            serde_json::json!([]),
+            serde_json::json!("account"),
+            serde_json::json!({ "type": 7 }),
+            serde_json::json!({ "type": "account", "info": [] }),
+            serde_json::json!({ "info": { "mint": 1, "owner": null, "tokenAmount": "1" } }),
+            serde_json::json!({ "info": { "mint": "x", "owner": "y", "tokenAmount": { "amount": "-1" } } }),
+            serde_json::json!({ "info": { "tokenAmount": { "amount": "18446744073709551616", "decimals": 256 } } }),
+        ];
+        let (_, template) = fixtures().into_iter().find(|(path, _)| path.ends_with("spl_token_json_parsed_delegated.json")).unwrap();
+        let mut state = 0x2545_f491_4f6c_dd1d;
+        let mut parsed = values.to_vec();
+        // Drop or replace each field of a good account in turn
+        let info = template["data"]["parsed"]["info"].as_object().unwrap().clone();
+        for field in info.keys() {
+            for replacement in [None, Some(serde_json::json!(null)), Some(serde_json::json!(next(&mut state))), Some(serde_json::json!({}))] {
+                let mut info = info.clone();
+                match replacement {
+                    Some(value) => info.insert(field.clone(), value),
+                    None => info.remove(field),
+                };
+                parsed.push(serde_json::json!({ "type": "account", "info": info }));
+            }
+        }
+        for parsed in parsed {
+            let data = UiAccountData::Json(solana_account_decoder::parse_account_data::ParsedAccount {
+                program: "spl-token".to_string(),
+                parsed: parsed.clone(),
+                space: 165,
+            });
+            let _ = decode_token_account(&data);
+        }
+    }
+}
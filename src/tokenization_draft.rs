@@ -0,0 +1,448 @@
+//! Tokenization in two steps, with the issuer's review in between
+//!
+//! `prepare_tokenization` only stages: it validates the asset, puts its metadata where
+//! reviewers can read it, simulates the mint for its costs, and returns a `TokenizationDraft`
+//! the issuer signed. The draft names the mint address, derived from the issuer's signature over
+//! the draft id so no key needs to live in the file, and the SHA-256 of the exact instructions
+//! that will run. `commit_tokenization` rebuilds those instructions from the draft's fields and
+//! mints only if they hash the same, the draft is unexpired, and its signature still holds.
+//! `discard_tokenization` removes the staged metadata of a draft that will not be committed.
+
+use crate::asset::{new_asset_metadata, TokenizeOptions};
+use crate::confirmation::OperationKind;
+use crate::events::SdkEvent;
+use crate::metadata::OffchainMetadata;
+use crate::preview::{PreviewWarning, TransactionPreview};
+use crate::telemetry::readable;
+use crate::validation;
+use crate::{AssetMetadata, AssetType, FinternetClient, MetadataBackend};
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use solana_sdk::{
+    instruction::Instruction,
+    message::Message,
+    pubkey::Pubkey,
+    signature::{keypair_from_seed, Keypair, Signature},
+    signer::Signer,
+    transaction::Transaction,
+};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const DRAFT_VERSION: u32 = 1;
+/// A week for review
+pub const DEFAULT_DRAFT_VALID_FOR_SECS: u64 = 7 * 24 * 60 * 60;
+/// The issuer signs this followed by the draft id to derive the draft's mint keypair
+const MINT_SEED_PREFIX: &str = "finternet-tokenization-draft:";
+
+/// The asset a draft mints
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AssetSpec {
+    pub name: String,
+    pub description: String,
+    pub value: u64,
+    pub asset_type: AssetType,
+}
+
+/// Why a draft was refused at commit
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TokenizationDraftError {
+    /// The draft's fields no longer match its signature, hashes, or mint
+    Tampered { reason: String },
+    Expired { expires_at: u64 },
+    WrongIssuer { expected: Pubkey, actual: Pubkey },
+    /// The instructions or metadata URI rebuilt at commit differ from the reviewed ones, e.g.
+    /// because the cluster's metadata backend changed; prepare the draft again
+    Diverged { reason: String },
+    /// The draft's mint exists already
+    AlreadyCommitted { mint: Pubkey },
+    UnsupportedVersion(u32),
+}
+
+impl TokenizationDraftError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::Tampered { .. } => "draft_tampered",
+            Self::Expired { .. } => "draft_expired",
+            Self::WrongIssuer { .. } => "wrong_draft_issuer",
+            Self::Diverged { .. } => "draft_diverged",
+            Self::AlreadyCommitted { .. } => "draft_already_committed",
+            Self::UnsupportedVersion(_) => "unsupported_draft_version",
+        }
+    }
+}
+
+impl fmt::Display for TokenizationDraftError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Tampered { reason } => write!(f, "Tokenization draft has been tampered with: {}", reason),
+            Self::Expired { expires_at } => write!(f, "Tokenization draft expired at {}", expires_at),
+            Self::WrongIssuer { expected, actual } => {
+                write!(f, "Tokenization draft must be committed by {}, not {}", expected, actual)
+            }
+            Self::Diverged { reason } => write!(f, "Tokenization no longer matches its draft: {}", reason),
+            Self::AlreadyCommitted { mint } => write!(f, "Tokenization draft was already committed as {}", mint),
+            Self::UnsupportedVersion(version) => write!(f, "Unsupported tokenization draft version {}", version),
+        }
+    }
+}
+
+impl std::error::Error for TokenizationDraftError {}
+
+/// An asset ready to mint, signed by its issuer and waiting for review; see the module
+/// documentation
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TokenizationDraft {
+    pub version: u32,
+    pub id: String,
+    pub issuer: Pubkey,
+    /// Derived from the issuer's signature over `id`
+    pub mint: Pubkey,
+    pub spec: AssetSpec,
+    pub symbol: String,
+    /// As given in the options; empty means the issuer at 100%
+    pub creators: Vec<(Pubkey, u8)>,
+    pub seller_fee_basis_points: u16,
+    pub metadata_backend: MetadataBackend,
+    /// The URI the mint will point at
+    pub metadata_uri: String,
+    /// Where reviewers can read the metadata before the commit; `None` without a publisher
+    pub staging_uri: Option<String>,
+    /// Hex SHA-256 of the off-chain metadata JSON
+    pub metadata_hash: String,
+    /// Hex SHA-256 of the mint transaction's message, without its blockhash or priority fee
+    pub instructions_hash: String,
+    /// Fee, balance changes, and created accounts of the mint, simulated at prepare time
+    pub estimate: TransactionPreview,
+    pub created_at: u64,
+    pub expires_at: u64,
+    /// The issuer's signature over the hash of every other field
+    pub signature: Signature,
+}
+
+/// What `commit_tokenization` minted
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TokenizedAsset {
+    pub mint: Pubkey,
+    pub metadata: AssetMetadata,
+    pub signature: Signature,
+}
+
+impl TokenizationDraft {
+    pub fn load(path: &Path) -> Result<Self> {
+        let data = fs::read_to_string(path)?;
+        serde_json::from_str(&data).map_err(|e| anyhow!("Invalid tokenization draft {}: {}", path.display(), e))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            if !parent.as_os_str().is_empty() {
+                fs::create_dir_all(parent)?;
+            }
+        }
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    pub fn is_expired(&self) -> bool {
+        unix_now() > self.expires_at
+    }
+
+    /// Check the version and the issuer's signature; expiry is checked at commit
+    pub fn verify(&self) -> Result<()> {
+        if self.version != DRAFT_VERSION {
+            return Err(TokenizationDraftError::UnsupportedVersion(self.version).into());
+        }
+        if !self.signature.verify(self.issuer.as_ref(), self.draft_hash()?.as_bytes()) {
+            return Err(TokenizationDraftError::Tampered {
+                reason: "the issuer's signature does not match the draft".to_string(),
+            }
+            .into());
+        }
+        Ok(())
+    }
+
+    /// Hex SHA-256 of the draft serialized with its signature empty
+    fn draft_hash(&self) -> Result<String> {
+        let unsigned = TokenizationDraft { signature: Signature::default(), ..self.clone() };
+        Ok(hex_sha256(serde_json::to_string(&unsigned)?.as_bytes()))
+    }
+
+    fn tokenize_options(&self) -> TokenizeOptions {
+        TokenizeOptions {
+            symbol: Some(self.symbol.clone()),
+            creators: self.creators.clone(),
+            seller_fee_basis_points: self.seller_fee_basis_points,
+            ..TokenizeOptions::default()
+        }
+    }
+
+    /// The asset as it will be minted, with the draft's timestamp and URI
+    fn asset_metadata(&self) -> Result<AssetMetadata> {
+        let mut metadata = new_asset_metadata(
+            &self.spec.name,
+            &self.spec.description,
+            self.spec.value,
+            &self.spec.asset_type,
+            &self.issuer,
+            &self.mint,
+            &self.tokenize_options(),
+        )?;
+        metadata.created_at = self.created_at;
+        metadata.uri = self.metadata_uri.clone();
+        Ok(metadata)
+    }
+}
+
+impl fmt::Display for TokenizationDraft {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Tokenization draft {}", self.id)?;
+        writeln!(f, "  Asset:     {} ({}, value {})", self.spec.name, self.spec.asset_type, self.spec.value)?;
+        writeln!(f, "  Symbol:    {}", self.symbol)?;
+        writeln!(f, "  Issuer:    {}", self.issuer)?;
+        writeln!(f, "  Mint:      {}", self.mint)?;
+        writeln!(f, "  Metadata:  {}", if self.metadata_uri.is_empty() { "(none)" } else { &self.metadata_uri })?;
+        if let Some(staging_uri) = &self.staging_uri {
+            writeln!(f, "  Staged at: {}", staging_uri)?;
+        }
+        writeln!(f, "  Fee:       {} lamports", self.estimate.fee)?;
+        for warning in &self.estimate.warnings {
+            writeln!(f, "  ⚠️  {}", warning.message)?;
+        }
+        writeln!(f, "  Hash:      {}", self.instructions_hash)?;
+        write!(f, "  Expires:   {}", self.expires_at)
+    }
+}
+
+impl FinternetClient {
+    /// Stage `spec` for review and return the draft `wallet` signed; nothing is minted
+    ///
+    /// The configured `MetadataPublisher` stages the metadata. Without one, the URI comes from
+    /// the configured URI base, as for `tokenize_asset`.
+    pub async fn prepare_tokenization(
+        &self,
+        wallet: &Keypair,
+        spec: &AssetSpec,
+        options: &TokenizeOptions,
+        valid_for_secs: u64,
+    ) -> Result<TokenizationDraft> {
+        let outcome = self.prepare_tokenization_unlogged(wallet, spec, options, valid_for_secs).await;
+        let parameters = serde_json::json!({
+            "issuer": wallet.pubkey().to_string(),
+            "name": spec.name,
+            "value": spec.value,
+            "asset_type": spec.asset_type.to_string(),
+            "draft": outcome.as_ref().ok().map(|draft| draft.id.clone()),
+            "mint": outcome.as_ref().ok().map(|draft| draft.mint.to_string()),
+        });
+        self.log_action("prepare_tokenization", parameters, None, outcome.as_ref(), |_| String::new());
+        outcome
+    }
+
+    #[tracing::instrument(skip_all, fields(wallet = %readable(&wallet.pubkey()), asset_type = %spec.asset_type, value = spec.value))]
+    async fn prepare_tokenization_unlogged(
+        &self,
+        wallet: &Keypair,
+        spec: &AssetSpec,
+        options: &TokenizeOptions,
+        valid_for_secs: u64,
+    ) -> Result<TokenizationDraft> {
+        validation::check(validation::validate_asset_spec(
+            &spec.name,
+            &spec.description,
+            spec.value,
+            &spec.asset_type,
+            options,
+        ))?;
+        let issuer = wallet.pubkey();
+        let id = uuid::Uuid::new_v4().to_string();
+        let mint = draft_mint_keypair(wallet, &id)?.pubkey();
+
+        let mut metadata =
+            new_asset_metadata(&spec.name, &spec.description, spec.value, &spec.asset_type, &issuer, &mint, options)?;
+        let offchain = OffchainMetadata::from_asset(&metadata, options.symbol());
+        let mut warnings = Vec::new();
+        let staging_uri = match &self.metadata_publisher {
+            Some(publisher) => {
+                let staged = publisher
+                    .stage_metadata(&mint, &offchain)
+                    .await
+                    .map_err(|e| anyhow!("Staging the draft's metadata failed: {}", e))?;
+                tracing::info!("Staged metadata for {} at {}", mint, staged.staging_uri);
+                metadata.uri = staged.final_uri;
+                Some(staged.staging_uri)
+            }
+            None => {
+                match self.configured_metadata_uri(&mint, options) {
+                    Some(uri) => metadata.uri = uri,
+                    None => warnings.push(PreviewWarning {
+                        code: "no_metadata_uri".to_string(),
+                        message: "No metadata publisher or URI base is configured; the asset would be minted without a URI"
+                            .to_string(),
+                        hint: None,
+                    }),
+                }
+                None
+            }
+        };
+
+        let instructions = self.tokenize_transaction_instructions(&issuer, &mut metadata, options)?;
+        let mut estimate = self.simulate_instructions(&issuer, &instructions)?;
+        warnings.append(&mut estimate.warnings);
+        estimate.warnings = warnings;
+
+        let created_at = metadata.created_at;
+        let mut draft = TokenizationDraft {
+            version: DRAFT_VERSION,
+            id,
+            issuer,
+            mint,
+            spec: spec.clone(),
+            symbol: options.symbol().to_string(),
+            creators: options.creators.clone(),
+            seller_fee_basis_points: options.seller_fee_basis_points,
+            metadata_backend: metadata.metadata_backend.unwrap_or(MetadataBackend::Metaplex),
+            metadata_uri: metadata.uri.clone(),
+            staging_uri,
+            metadata_hash: hex_sha256(&serde_json::to_vec(&offchain)?),
+            instructions_hash: instructions_hash(&issuer, &instructions),
+            estimate,
+            created_at,
+            expires_at: created_at + valid_for_secs,
+            signature: Signature::default(),
+        };
+        draft.signature = wallet.sign_message(draft.draft_hash()?.as_bytes());
+        tracing::info!("Prepared tokenization draft {} for mint {}", draft.id, draft.mint);
+        Ok(draft)
+    }
+
+    /// Check `draft` again, publish its metadata, and mint it
+    pub async fn commit_tokenization(&self, wallet: &Keypair, draft: &TokenizationDraft) -> Result<TokenizedAsset> {
+        let outcome = self.commit_tokenization_unlogged(wallet, draft).await;
+        let parameters = serde_json::json!({
+            "issuer": wallet.pubkey().to_string(),
+            "draft": draft.id,
+            "mint": draft.mint.to_string(),
+            "instructions_hash": draft.instructions_hash,
+        });
+        if let Ok(asset) = &outcome {
+            self.emit(|| SdkEvent::TokenizationCompleted { mint: asset.mint, signature: asset.signature });
+        }
+        self.log_action("commit_tokenization", parameters, None, outcome.as_ref(), |asset| asset.signature.to_string());
+        outcome
+    }
+
+    #[tracing::instrument(skip_all, fields(wallet = %readable(&wallet.pubkey()), draft = %draft.id, mint = %draft.mint, signature = tracing::field::Empty))]
+    async fn commit_tokenization_unlogged(&self, wallet: &Keypair, draft: &TokenizationDraft) -> Result<TokenizedAsset> {
+        draft.verify()?;
+        if wallet.pubkey() != draft.issuer {
+            return Err(TokenizationDraftError::WrongIssuer { expected: draft.issuer, actual: wallet.pubkey() }.into());
+        }
+        if draft.is_expired() {
+            return Err(TokenizationDraftError::Expired { expires_at: draft.expires_at }.into());
+        }
+        let tampered = |reason: &str| TokenizationDraftError::Tampered { reason: reason.to_string() };
+        let mint_keypair = draft_mint_keypair(wallet, &draft.id)?;
+        if mint_keypair.pubkey() != draft.mint {
+            return Err(tampered("the mint is not the one derived from the draft id").into());
+        }
+
+        let options = draft.tokenize_options();
+        let spec = &draft.spec;
+        validation::check(validation::validate_asset_spec(
+            &spec.name,
+            &spec.description,
+            spec.value,
+            &spec.asset_type,
+            &options,
+        ))?;
+        let mut metadata = draft.asset_metadata()?;
+        let offchain = OffchainMetadata::from_asset(&metadata, options.symbol());
+        if hex_sha256(&serde_json::to_vec(&offchain)?) != draft.metadata_hash {
+            return Err(tampered("the metadata does not match its hash").into());
+        }
+        let instructions = self.tokenize_transaction_instructions(&draft.issuer, &mut metadata, &options)?;
+        if instructions_hash(&draft.issuer, &instructions) != draft.instructions_hash {
+            return Err(TokenizationDraftError::Diverged {
+                reason: "the mint instructions differ from the reviewed ones".to_string(),
+            }
+            .into());
+        }
+        if self.client.get_multiple_accounts(&[draft.mint])?.first().is_some_and(Option::is_some) {
+            return Err(TokenizationDraftError::AlreadyCommitted { mint: draft.mint }.into());
+        }
+
+        // Publish before minting, so the URI is live by the time wallets see the mint
+        if let Some(publisher) = &self.metadata_publisher {
+            let uri = publisher
+                .promote_staged(&draft.mint, &offchain)
+                .await
+                .map_err(|e| anyhow!("Metadata publishing failed, nothing was minted: {}", e))?;
+            if uri != draft.metadata_uri {
+                return Err(TokenizationDraftError::Diverged {
+                    reason: format!("the metadata was published at {}, not the reviewed {}", uri, draft.metadata_uri),
+                }
+                .into());
+            }
+        }
+
+        let mut transaction = Transaction::new_with_payer(&instructions, Some(&draft.issuer));
+        let signers = [wallet, &mint_keypair];
+        self.sign_with_fresh_blockhash(&mut transaction, &signers)?;
+        let signature = self.send_and_confirm_resigning_with(
+            OperationKind::Tokenization,
+            "commit_tokenization",
+            transaction,
+            &signers,
+            &Default::default(),
+        )?;
+        tracing::Span::current().record("signature", tracing::field::display(&signature));
+        tracing::info!("Tokenization draft {} committed: mint {}, signature {}", draft.id, draft.mint, signature);
+        Ok(TokenizedAsset { mint: draft.mint, metadata, signature })
+    }
+
+    /// Remove `draft`'s staged metadata; a committed draft keeps its published metadata
+    pub async fn discard_tokenization(&self, draft: &TokenizationDraft) -> Result<()> {
+        let outcome = self.discard_tokenization_unlogged(draft).await;
+        let parameters = serde_json::json!({
+            "issuer": draft.issuer.to_string(),
+            "draft": draft.id,
+            "mint": draft.mint.to_string(),
+        });
+        self.log_action("discard_tokenization", parameters, None, outcome.as_ref(), |_| String::new());
+        outcome
+    }
+
+    #[tracing::instrument(skip_all, fields(draft = %draft.id, mint = %draft.mint))]
+    async fn discard_tokenization_unlogged(&self, draft: &TokenizationDraft) -> Result<()> {
+        draft.verify()?;
+        if let (Some(publisher), Some(_)) = (&self.metadata_publisher, &draft.staging_uri) {
+            publisher.discard_staged(&draft.mint).await?;
+        }
+        tracing::info!("Discarded tokenization draft {}", draft.id);
+        Ok(())
+    }
+}
+
+/// The mint keypair of draft `id`: Ed25519 signatures are deterministic, so the issuer derives
+/// the same keypair at prepare and at commit
+fn draft_mint_keypair(wallet: &Keypair, id: &str) -> Result<Keypair> {
+    let seed = wallet.sign_message(format!("{}{}", MINT_SEED_PREFIX, id).as_bytes());
+    keypair_from_seed(&seed.as_ref()[..32]).map_err(|e| anyhow!("Could not derive the draft's mint: {}", e))
+}
+
+/// Hash of the message `instructions` make, with the default blockhash
+fn instructions_hash(payer: &Pubkey, instructions: &[Instruction]) -> String {
+    hex_sha256(&Message::new(instructions, Some(payer)).serialize())
+}
+
+fn hex_sha256(bytes: &[u8]) -> String {
+    Sha256::digest(bytes).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
@@ -0,0 +1,400 @@
+//! Usage accounting and daily quotas per API key
+//!
+//! `UsageLedger` keeps one `UsageCounts` per key and UTC day in a `StateStore`: requests served,
+//! SDK operations run, RPC calls made, payments sent, and payment volume per currency. RPC calls
+//! and operations are attributed with `metered`, which counts what the SDK does on the task it
+//! wraps. `InstrumentedSender` counts each RPC call and `log_action` each operation, so calls
+//! made from tasks spawned elsewhere (the payment queue worker, watchers) are not attributed.
+//!
+//! A key's `UsageQuota` caps its requests and its volume per currency each day. Checks and
+//! increments happen under one lock, as with intent daily totals, so concurrent requests for
+//! the same key cannot both take the last unit of a quota.
+
+use crate::api_keys::ApiKeyRecord;
+use crate::currency::Currency;
+use crate::state_store::{legacy_map, FileStateStore, Namespace, StateStore};
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::future::Future;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Namespace usage counts are kept under in a `StateStore`
+pub const USAGE_NAMESPACE: &str = "api_usage";
+
+const SECS_PER_DAY: u64 = 86_400;
+
+/// Daily caps for one key; an unset cap is unlimited
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UsageQuota {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub requests_per_day: Option<u64>,
+    /// Base units per day, keyed like `UsageCounts::volume` (`SOL` or the mint address)
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub volume_per_day: BTreeMap<String, u64>,
+}
+
+impl UsageQuota {
+    pub fn is_unlimited(&self) -> bool {
+        self.requests_per_day.is_none() && self.volume_per_day.is_empty()
+    }
+}
+
+/// What one key used over a day, or summed over several
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UsageCounts {
+    pub requests: u64,
+    /// SDK operations by name, as recorded in the action log
+    #[serde(default)]
+    pub operations: BTreeMap<String, u64>,
+    pub rpc_calls: u64,
+    pub payments: u64,
+    /// Base units sent per currency, keyed by `currency_key`
+    #[serde(default)]
+    pub volume: BTreeMap<String, u64>,
+}
+
+impl UsageCounts {
+    pub fn add(&mut self, other: &UsageCounts) {
+        self.requests += other.requests;
+        self.rpc_calls += other.rpc_calls;
+        self.payments += other.payments;
+        for (operation, count) in &other.operations {
+            *self.operations.entry(operation.clone()).or_default() += count;
+        }
+        for (currency, amount) in &other.volume {
+            let total = self.volume.entry(currency.clone()).or_default();
+            *total = total.saturating_add(*amount);
+        }
+    }
+}
+
+/// One key's usage between two days, inclusive
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeyUsage {
+    pub key_id: String,
+    pub total: UsageCounts,
+    /// Per UTC day (`YYYY-MM-DD`); days with no usage are left out
+    pub daily: BTreeMap<String, UsageCounts>,
+}
+
+/// How `currency` is keyed in volumes and quotas: `SOL`, or the mint address
+pub fn currency_key(currency: &Currency) -> String {
+    match currency.mint() {
+        Some(mint) => mint.to_string(),
+        None => "SOL".to_string(),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum QuotaError {
+    /// The key has used every request it has today
+    RequestsExhausted { key_id: String, limit: u64, resets_at: u64 },
+    /// Sending `requested` would take the key past its volume for `currency` today
+    VolumeExhausted {
+        key_id: String,
+        currency: String,
+        limit: u64,
+        used: u64,
+        requested: u64,
+        resets_at: u64,
+    },
+}
+
+impl QuotaError {
+    /// Unix time the quota starts over: the next UTC midnight
+    pub fn resets_at(&self) -> u64 {
+        match self {
+            Self::RequestsExhausted { resets_at, .. } | Self::VolumeExhausted { resets_at, .. } => *resets_at,
+        }
+    }
+}
+
+impl fmt::Display for QuotaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::RequestsExhausted { key_id, limit, .. } => {
+                write!(f, "API key {} has used its {} requests for today", key_id, limit)
+            }
+            Self::VolumeExhausted { key_id, currency, limit, used, requested, .. } => write!(
+                f,
+                "API key {} may send {} of {} a day: {} already sent, {} requested",
+                key_id, limit, currency, used, requested
+            ),
+        }
+    }
+}
+
+impl std::error::Error for QuotaError {}
+
+/// Persistent usage counts per key and day, checked against each key's quota
+pub struct UsageLedger {
+    counts: Namespace<UsageCounts>,
+    /// Held across each check-then-increment so two requests cannot both pass the same check
+    lock: Mutex<()>,
+}
+
+impl UsageLedger {
+    /// Keep usage in its own file at `path`
+    pub fn open(path: &Path) -> Result<Self> {
+        let store = FileStateStore::open_registry(path, &legacy_map(USAGE_NAMESPACE))?;
+        Ok(Self::new(Arc::new(store)))
+    }
+
+    pub fn new(store: Arc<dyn StateStore>) -> Self {
+        Self {
+            counts: Namespace::new(store, USAGE_NAMESPACE),
+            lock: Mutex::new(()),
+        }
+    }
+
+    /// Count one request for `key`, refusing it once the key's daily request quota is used up
+    pub fn admit_request(&self, key: &ApiKeyRecord, now: u64) -> Result<()> {
+        let _guard = self.lock.lock().map_err(|_| anyhow!("Usage ledger lock poisoned"))?;
+        let bucket = bucket_key(&key.id, now);
+        let mut counts = self.counts.get(&bucket)?.unwrap_or_default();
+        if let Some(limit) = key.quota.as_ref().and_then(|quota| quota.requests_per_day) {
+            if counts.requests >= limit {
+                return Err(QuotaError::RequestsExhausted { key_id: key.id.clone(), limit, resets_at: next_day(now) }.into());
+            }
+        }
+        counts.requests += 1;
+        self.counts.put(&bucket, &counts)
+    }
+
+    /// Count a payment of `amount` for `key` before it is sent, refusing it when it would take
+    /// the key past its daily volume for `currency`
+    ///
+    /// Call `release_payment` with the same arguments if the payment then fails.
+    pub fn reserve_payment(&self, key: &ApiKeyRecord, currency: &Currency, amount: u64, now: u64) -> Result<()> {
+        let _guard = self.lock.lock().map_err(|_| anyhow!("Usage ledger lock poisoned"))?;
+        let bucket = bucket_key(&key.id, now);
+        let currency = currency_key(currency);
+        let mut counts = self.counts.get(&bucket)?.unwrap_or_default();
+        let used = counts.volume.get(&currency).copied().unwrap_or(0);
+        if let Some(limit) = key.quota.as_ref().and_then(|quota| quota.volume_per_day.get(&currency)).copied() {
+            if used.saturating_add(amount) > limit {
+                return Err(QuotaError::VolumeExhausted {
+                    key_id: key.id.clone(),
+                    currency,
+                    limit,
+                    used,
+                    requested: amount,
+                    resets_at: next_day(now),
+                }
+                .into());
+            }
+        }
+        counts.payments += 1;
+        counts.volume.insert(currency, used.saturating_add(amount));
+        self.counts.put(&bucket, &counts)
+    }
+
+    /// Give back a reservation whose payment was not sent
+    pub fn release_payment(&self, key_id: &str, currency: &Currency, amount: u64, reserved_at: u64) -> Result<()> {
+        let _guard = self.lock.lock().map_err(|_| anyhow!("Usage ledger lock poisoned"))?;
+        let bucket = bucket_key(key_id, reserved_at);
+        let Some(mut counts) = self.counts.get(&bucket)? else {
+            return Ok(());
+        };
+        counts.payments = counts.payments.saturating_sub(1);
+        if let Some(volume) = counts.volume.get_mut(&currency_key(currency)) {
+            *volume = volume.saturating_sub(amount);
+        }
+        self.counts.put(&bucket, &counts)
+    }
+
+    /// Add what `metered` saw during a request to `key_id`'s day
+    pub fn record_metered(&self, key_id: &str, reading: &MeterReading, now: u64) -> Result<()> {
+        if reading.rpc_calls == 0 && reading.operations.is_empty() {
+            return Ok(());
+        }
+        let _guard = self.lock.lock().map_err(|_| anyhow!("Usage ledger lock poisoned"))?;
+        let bucket = bucket_key(key_id, now);
+        let mut counts = self.counts.get(&bucket)?.unwrap_or_default();
+        counts.add(&UsageCounts {
+            rpc_calls: reading.rpc_calls,
+            operations: reading.operations.clone(),
+            ..UsageCounts::default()
+        });
+        self.counts.put(&bucket, &counts)
+    }
+
+    /// Usage of `key_id`, or of every key, for the UTC days `from` through `to` (`YYYY-MM-DD`)
+    pub fn usage(&self, key_id: Option<&str>, from: &str, to: &str) -> Result<Vec<KeyUsage>> {
+        let mut by_key: BTreeMap<String, KeyUsage> = BTreeMap::new();
+        for (bucket, counts) in self.counts.list()? {
+            let Some((id, day)) = bucket.rsplit_once(':') else {
+                continue;
+            };
+            if key_id.is_some_and(|wanted| wanted != id) || day < from || day > to {
+                continue;
+            }
+            let usage = by_key.entry(id.to_string()).or_insert_with(|| KeyUsage {
+                key_id: id.to_string(),
+                ..KeyUsage::default()
+            });
+            usage.total.add(&counts);
+            usage.daily.insert(day.to_string(), counts);
+        }
+        Ok(by_key.into_values().collect())
+    }
+}
+
+/// The UTC day of `now`, as `YYYY-MM-DD`
+pub fn usage_day(now: u64) -> String {
+    chrono::DateTime::from_timestamp(now as i64, 0)
+        .map(|dt| dt.format("%Y-%m-%d").to_string())
+        .unwrap_or_default()
+}
+
+fn bucket_key(key_id: &str, now: u64) -> String {
+    format!("{}:{}", key_id, usage_day(now))
+}
+
+fn next_day(now: u64) -> u64 {
+    (now / SECS_PER_DAY + 1) * SECS_PER_DAY
+}
+
+/// RPC calls and operations counted while a `metered` future ran
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MeterReading {
+    pub rpc_calls: u64,
+    pub operations: BTreeMap<String, u64>,
+}
+
+#[derive(Default)]
+struct UsageMeter {
+    rpc_calls: AtomicU64,
+    operations: Mutex<BTreeMap<String, u64>>,
+}
+
+tokio::task_local! {
+    static METER: Arc<UsageMeter>;
+}
+
+/// Run `future`, counting the RPC calls and SDK operations it makes on its own task
+pub async fn metered<F: Future>(future: F) -> (F::Output, MeterReading) {
+    let meter = Arc::new(UsageMeter::default());
+    let output = METER.scope(Arc::clone(&meter), future).await;
+    let operations = meter.operations.lock().map(|operations| operations.clone()).unwrap_or_default();
+    (output, MeterReading { rpc_calls: meter.rpc_calls.load(Ordering::Relaxed), operations })
+}
+
+/// Count an RPC call against the surrounding `metered` future, if any
+pub(crate) fn count_rpc_call() {
+    let _ = METER.try_with(|meter| meter.rpc_calls.fetch_add(1, Ordering::Relaxed));
+}
+
+/// Count an SDK operation against the surrounding `metered` future, if any
+pub(crate) fn count_operation(operation: &str) {
+    let _ = METER.try_with(|meter| {
+        if let Ok(mut operations) = meter.operations.lock() {
+            *operations.entry(operation.to_string()).or_default() += 1;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api_keys::ApiScope;
+    use crate::error_code::FinternetError;
+    use crate::mock_cluster::TempDir;
+    use solana_sdk::pubkey::Pubkey;
+    use std::thread;
+
+    /// 2024-06-15 12:00 UTC
+    const NOON: u64 = 1_718_452_800;
+
+    fn key(quota: UsageQuota) -> ApiKeyRecord {
+        ApiKeyRecord {
+            id: "key-1".to_string(),
+            name: "till".to_string(),
+            key_hash: String::new(),
+            scopes: vec![ApiScope::PaymentsSend],
+            member: None,
+            created_at: NOON,
+            revoked_at: None,
+            quota: Some(quota),
+        }
+    }
+
+    /// Run `attempt` on 32 threads at once, returning what each got back
+    fn race(ledger: &Arc<UsageLedger>, attempt: impl Fn(&UsageLedger) -> Result<()> + Send + Sync + 'static) -> Vec<Result<()>> {
+        let attempt = Arc::new(attempt);
+        let start = Arc::new(std::sync::Barrier::new(32));
+        let handles: Vec<_> = (0..32)
+            .map(|_| {
+                let (ledger, attempt, start) = (Arc::clone(ledger), Arc::clone(&attempt), Arc::clone(&start));
+                thread::spawn(move || {
+                    start.wait();
+                    attempt(&ledger)
+                })
+            })
+            .collect();
+        handles.into_iter().map(|handle| handle.join().unwrap()).collect()
+    }
+
+    fn quota_errors(results: Vec<Result<()>>) -> (usize, Vec<QuotaError>) {
+        let admitted = results.iter().filter(|result| result.is_ok()).count();
+        let refused = results
+            .into_iter()
+            .filter_map(Result::err)
+            .map(|e| {
+                assert_eq!(FinternetError::of(&e), FinternetError::QuotaExceeded);
+                e.downcast::<QuotaError>().expect("only quota refusals")
+            })
+            .collect();
+        (admitted, refused)
+    }
+
+    #[test]
+    fn concurrent_requests_at_the_boundary_admit_exactly_the_quota() {
+        let dir = TempDir::new("usage");
+        let ledger = Arc::new(UsageLedger::open(&dir.join("usage.json")).unwrap());
+        let key = key(UsageQuota { requests_per_day: Some(10), ..UsageQuota::default() });
+        ledger.admit_request(&key, NOON).unwrap();
+
+        let racing = key.clone();
+        let (admitted, refused) = quota_errors(race(&ledger, move |ledger| ledger.admit_request(&racing, NOON)));
+        assert_eq!((admitted, refused.len()), (9, 23));
+        let midnight = NOON - NOON % SECS_PER_DAY + SECS_PER_DAY;
+        assert!(refused.iter().all(|e| e == &QuotaError::RequestsExhausted { key_id: key.id.clone(), limit: 10, resets_at: midnight }));
+
+        let usage = ledger.usage(Some("key-1"), "2024-06-15", "2024-06-15").unwrap();
+        assert_eq!(usage[0].total.requests, 10);
+        ledger.admit_request(&key, midnight).unwrap();
+    }
+
+    #[test]
+    fn concurrent_payments_at_the_boundary_never_overspend_the_volume() {
+        let ledger = Arc::new(UsageLedger::new(Arc::new(crate::state_store::MemoryStateStore::new())));
+        let mint = Pubkey::new_unique();
+        let currency = Currency::spl(mint, 6);
+        let quota = UsageQuota { volume_per_day: BTreeMap::from([(mint.to_string(), 1_000)]), ..UsageQuota::default() };
+        let key = key(quota);
+        ledger.reserve_payment(&key, &currency, 299, NOON).unwrap();
+
+        // 7 of 100 fit under the 701 left; the last unit is then refused on its own
+        let (racing, racing_currency) = (key.clone(), currency.clone());
+        let (admitted, refused) =
+            quota_errors(race(&ledger, move |ledger| ledger.reserve_payment(&racing, &racing_currency, 100, NOON)));
+        assert_eq!((admitted, refused.len()), (7, 25));
+        assert!(refused.iter().all(|e| matches!(e, QuotaError::VolumeExhausted { used: 999, requested: 100, .. })));
+        ledger.reserve_payment(&key, &currency, 1, NOON).unwrap();
+        ledger.reserve_payment(&key, &currency, 1, NOON).unwrap_err();
+
+        // A released payment frees exactly its own volume, to one racer
+        ledger.release_payment(&key.id, &currency, 100, NOON).unwrap();
+        let (racing, racing_currency) = (key.clone(), currency.clone());
+        let (admitted, _) = quota_errors(race(&ledger, move |ledger| ledger.reserve_payment(&racing, &racing_currency, 100, NOON)));
+        assert_eq!(admitted, 1);
+        let usage = ledger.usage(None, "2024-06-15", "2024-06-15").unwrap();
+        assert_eq!(usage[0].total.volume[&mint.to_string()], 1_000);
+        assert_eq!(usage[0].total.payments, 9);
+    }
+}
@@ -0,0 +1,233 @@
+use crate::asset::{AuthorityCustody, TokenizeOptions};
+use crate::AssetType;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signer::Signer;
+use std::collections::HashMap;
+use std::fmt;
+
+/// Largest memo the memo program accepts in a single instruction
+pub const MAX_MEMO_BYTES: usize = 566;
+/// Metaplex limits on token metadata
+pub const MAX_ASSET_NAME_BYTES: usize = 32;
+pub const MAX_SYMBOL_BYTES: usize = 10;
+pub const MAX_CREATORS: usize = 5;
+pub const MAX_SELLER_FEE_BPS: u16 = 10_000;
+/// Kept to what fits comfortably in off-chain metadata JSON
+pub const MAX_DESCRIPTION_BYTES: usize = 1_000;
+/// No real asset is worth more than a quadrillion units of account
+pub const MAX_ASSET_VALUE: u64 = 1_000_000_000_000_000;
+pub const MAX_CUSTOM_TYPE_BYTES: usize = 32;
+pub const MAX_DISPLAY_NAME_BYTES: usize = 64;
+pub const MAX_METADATA_KEYS: usize = 32;
+pub const MAX_METADATA_KEY_BYTES: usize = 64;
+pub const MAX_METADATA_VALUE_BYTES: usize = 512;
+
+/// One input field that broke a rule
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Violation {
+    pub field: String,
+    pub message: String,
+}
+
+impl Violation {
+    fn new(field: impl Into<String>, message: impl Into<String>) -> Self {
+        Self { field: field.into(), message: message.into() }
+    }
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.field, self.message)
+    }
+}
+
+/// Every violation found in one input, so callers can fix them all at once
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidationError {
+    pub violations: Vec<Violation>,
+}
+
+impl ValidationError {
+    pub fn code(&self) -> &'static str {
+        "invalid_input"
+    }
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid input: ")?;
+        for (i, violation) in self.violations.iter().enumerate() {
+            if i > 0 {
+                write!(f, "; ")?;
+            }
+            write!(f, "{}", violation)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ValidationError {}
+
+/// `Ok` when there are no violations, otherwise a `ValidationError` carrying all of them
+pub fn check(violations: Vec<Violation>) -> Result<()> {
+    if violations.is_empty() {
+        Ok(())
+    } else {
+        Err(ValidationError { violations }.into())
+    }
+}
+
+/// Name, description, value, and type of an asset to tokenize, plus its Metaplex options
+pub fn validate_asset_spec(
+    name: &str,
+    description: &str,
+    value: u64,
+    asset_type: &AssetType,
+    options: &TokenizeOptions,
+) -> Vec<Violation> {
+    let mut violations = Vec::new();
+    if name.trim().is_empty() {
+        violations.push(Violation::new("name", "must not be empty"));
+    }
+    if name.len() > MAX_ASSET_NAME_BYTES {
+        violations.push(Violation::new(
+            "name",
+            format!("is {} bytes, at most {} allowed (Metaplex limit)", name.len(), MAX_ASSET_NAME_BYTES),
+        ));
+    }
+    if description.len() > MAX_DESCRIPTION_BYTES {
+        violations.push(Violation::new(
+            "description",
+            format!("is {} bytes, at most {} allowed", description.len(), MAX_DESCRIPTION_BYTES),
+        ));
+    }
+    if value == 0 {
+        violations.push(Violation::new("value", "must be greater than 0"));
+    }
+    if value > MAX_ASSET_VALUE {
+        violations.push(Violation::new("value", format!("{} exceeds the maximum of {}", value, MAX_ASSET_VALUE)));
+    }
+    if let AssetType::Custom(custom) = asset_type {
+        if custom.trim().is_empty() {
+            violations.push(Violation::new("asset_type", "custom type name must not be empty"));
+        }
+        if custom.len() > MAX_CUSTOM_TYPE_BYTES {
+            violations.push(Violation::new(
+                "asset_type",
+                format!("custom type is {} bytes, at most {} allowed", custom.len(), MAX_CUSTOM_TYPE_BYTES),
+            ));
+        }
+    }
+    violations.extend(validate_tokenize_options(options));
+    violations
+}
+
+/// Symbol, royalty, and creator shares against the Metaplex rules
+pub fn validate_tokenize_options(options: &TokenizeOptions) -> Vec<Violation> {
+    let mut violations = Vec::new();
+    let symbol = options.symbol();
+    if symbol.len() > MAX_SYMBOL_BYTES {
+        violations.push(Violation::new(
+            "symbol",
+            format!("'{}' is {} bytes, at most {} allowed", symbol, symbol.len(), MAX_SYMBOL_BYTES),
+        ));
+    }
+    if options.seller_fee_basis_points > MAX_SELLER_FEE_BPS {
+        violations.push(Violation::new(
+            "seller_fee_basis_points",
+            format!("{} exceeds the maximum of {}", options.seller_fee_basis_points, MAX_SELLER_FEE_BPS),
+        ));
+    }
+    if let Some(mint) = options.mint_keypair.as_ref().map(|keypair| keypair.pubkey()) {
+        for (field, custody) in [("mint_authority", options.mint_authority), ("freeze_authority", options.freeze_authority)] {
+            if custody == AuthorityCustody::Key(mint) {
+                violations.push(Violation::new(field, "must not be the mint's own address"));
+            }
+        }
+    }
+    if options.creators.is_empty() {
+        return violations;
+    }
+    if options.creators.len() > MAX_CREATORS {
+        violations.push(Violation::new(
+            "creators",
+            format!("{} listed, at most {} allowed", options.creators.len(), MAX_CREATORS),
+        ));
+    }
+    for (i, (address, _)) in options.creators.iter().enumerate() {
+        if options.creators[..i].iter().any(|(other, _)| other == address) {
+            violations.push(Violation::new("creators", format!("{} is listed more than once", address)));
+        }
+    }
+    let total: u32 = options.creators.iter().map(|(_, share)| *share as u32).sum();
+    if total != 100 {
+        violations.push(Violation::new("creators", format!("shares must sum to exactly 100, got {}", total)));
+    }
+    violations
+}
+
+/// A transfer of `amount` base units of `mint` to `to`, with an optional memo
+pub fn validate_payment(to: &Pubkey, amount: u64, mint: &Pubkey, memo: Option<&str>) -> Vec<Violation> {
+    let mut violations = Vec::new();
+    if *to == Pubkey::default() {
+        violations.push(Violation::new("to", "must not be the all-zero (system program) address"));
+    }
+    if amount == 0 {
+        violations.push(Violation::new("amount", "must be greater than 0 base units"));
+    }
+    if *mint == Pubkey::default() {
+        violations.push(Violation::new("mint", "must not be the all-zero address"));
+    }
+    if let Some(memo) = memo {
+        if memo.len() > MAX_MEMO_BYTES {
+            violations.push(Violation::new(
+                "memo",
+                format!("is {} bytes, at most {} allowed (memo program limit)", memo.len(), MAX_MEMO_BYTES),
+            ));
+        }
+    }
+    violations
+}
+
+/// A display name and metadata to register as an on-chain identity
+pub fn validate_identity(display_name: &str, metadata: &HashMap<String, String>) -> Vec<Violation> {
+    let mut violations = Vec::new();
+    if display_name.trim().is_empty() {
+        violations.push(Violation::new("display_name", "must not be empty"));
+    }
+    if display_name.len() > MAX_DISPLAY_NAME_BYTES {
+        violations.push(Violation::new(
+            "display_name",
+            format!("is {} bytes, at most {} allowed", display_name.len(), MAX_DISPLAY_NAME_BYTES),
+        ));
+    }
+    if metadata.len() > MAX_METADATA_KEYS {
+        violations.push(Violation::new(
+            "metadata",
+            format!("has {} keys, at most {} allowed", metadata.len(), MAX_METADATA_KEYS),
+        ));
+    }
+    let mut keys: Vec<&String> = metadata.keys().collect();
+    keys.sort();
+    for key in keys {
+        if key.trim().is_empty() {
+            violations.push(Violation::new("metadata", "keys must not be empty"));
+        }
+        if key.len() > MAX_METADATA_KEY_BYTES {
+            violations.push(Violation::new(
+                format!("metadata.{}", key),
+                format!("key is {} bytes, at most {} allowed", key.len(), MAX_METADATA_KEY_BYTES),
+            ));
+        }
+        let value = &metadata[key];
+        if value.len() > MAX_METADATA_VALUE_BYTES {
+            violations.push(Violation::new(
+                format!("metadata.{}", key),
+                format!("value is {} bytes, at most {} allowed", value.len(), MAX_METADATA_VALUE_BYTES),
+            ));
+        }
+    }
+    violations
+}
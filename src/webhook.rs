@@ -0,0 +1,345 @@
+use crate::state_store::{legacy_map, FileStateStore, Namespace, StateStore};
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Stored in place of anything that looks like a credential
+pub const REDACTED: &str = "[REDACTED]";
+
+/// Object keys whose values are credentials; also matched as a `_`-separated suffix, so
+/// `client_secret` and `x-api-key` are caught but `token_mint` is not
+const AUTH_KEYS: &[&str] = &[
+    "authorization",
+    "auth",
+    "password",
+    "secret",
+    "token",
+    "api_key",
+    "apikey",
+    "jwt",
+    "private_key",
+    "cookie",
+    "credentials",
+];
+
+fn is_auth_key(key: &str) -> bool {
+    let key = key.to_ascii_lowercase().replace('-', "_");
+    AUTH_KEYS
+        .iter()
+        .any(|auth| key == *auth || key.ends_with(&format!("_{}", auth)))
+}
+
+/// How hard to try a webhook before giving up on it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Total attempts, including the first
+    pub max_attempts: u32,
+    /// Wait before the second attempt; doubles after each failure
+    pub initial_backoff: Duration,
+    pub timeout: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_backoff: Duration::from_secs(1),
+            timeout: Duration::from_secs(10),
+        }
+    }
+}
+
+/// Every attempt at a delivery failed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeliveryFailure {
+    pub attempts: u32,
+    pub last_error: String,
+}
+
+impl std::fmt::Display for DeliveryFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Webhook delivery failed after {} attempts: {}", self.attempts, self.last_error)
+    }
+}
+
+impl std::error::Error for DeliveryFailure {}
+
+/// POST `payload` to `url` as JSON until it gets a 2xx or `policy` runs out of attempts
+///
+/// Returns the number of attempts it took.
+pub async fn deliver(
+    http: &reqwest::Client,
+    url: &str,
+    payload: &Value,
+    policy: &RetryPolicy,
+) -> Result<u32, DeliveryFailure> {
+    let mut backoff = policy.initial_backoff;
+    let mut last_error = String::new();
+    for attempt in 1..=policy.max_attempts.max(1) {
+        if attempt > 1 {
+            tokio::time::sleep(backoff).await;
+            backoff = backoff.saturating_mul(2);
+        }
+        let sent = http
+            .post(url)
+            .timeout(policy.timeout)
+            .json(payload)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status());
+        match sent {
+            Ok(_) => return Ok(attempt),
+            Err(e) => {
+                tracing::warn!("Webhook delivery to {} failed (attempt {}): {}", url, attempt, e);
+                last_error = e.to_string();
+            }
+        }
+    }
+    Err(DeliveryFailure {
+        attempts: policy.max_attempts.max(1),
+        last_error,
+    })
+}
+
+/// Copy of `payload` with every credential-looking field replaced by `REDACTED`, at any depth
+pub fn redact_auth(payload: &Value) -> Value {
+    match payload {
+        Value::Object(fields) => Value::Object(
+            fields
+                .iter()
+                .map(|(key, value)| {
+                    let value = if is_auth_key(key) {
+                        Value::String(REDACTED.to_string())
+                    } else {
+                        redact_auth(value)
+                    };
+                    (key.clone(), value)
+                })
+                .collect(),
+        ),
+        Value::Array(items) => Value::Array(items.iter().map(redact_auth).collect()),
+        other => other.clone(),
+    }
+}
+
+/// A webhook delivery that exhausted its retries
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DeadLetter {
+    pub id: String,
+    /// What produced the event, e.g. `balance_alert`
+    pub source: String,
+    pub url: String,
+    /// Redacted with `redact_auth` before it was stored
+    pub payload: Value,
+    /// Across the original delivery and every retry since
+    pub attempts: u32,
+    pub last_error: String,
+    pub failed_at: u64,
+    pub last_attempt_at: u64,
+}
+
+/// Namespace dead letters are kept under in a `StateStore`
+pub const DEAD_LETTERS_NAMESPACE: &str = "dead_letters";
+
+/// Persistent queue of failed webhook deliveries, keyed by id
+pub struct DeadLetterQueue {
+    letters: Namespace<DeadLetter>,
+    /// Held across each read-then-write so a retry and a push do not lose each other's changes
+    lock: Mutex<()>,
+}
+
+impl DeadLetterQueue {
+    /// Keep the queue in its own file at `path`
+    pub fn open(path: &Path) -> Result<Self> {
+        let store = FileStateStore::open_registry(path, &legacy_map(DEAD_LETTERS_NAMESPACE))?;
+        Ok(Self::new(Arc::new(store)))
+    }
+
+    pub fn new(store: Arc<dyn StateStore>) -> Self {
+        Self {
+            letters: Namespace::new(store, DEAD_LETTERS_NAMESPACE),
+            lock: Mutex::new(()),
+        }
+    }
+
+    /// Store a failed delivery, redacting its payload; returns the new entry and the queue length
+    pub fn push(&self, source: &str, url: &str, payload: &Value, failure: &DeliveryFailure) -> Result<(DeadLetter, usize)> {
+        let now = unix_now();
+        let letter = DeadLetter {
+            id: uuid::Uuid::new_v4().to_string(),
+            source: source.to_string(),
+            url: url.to_string(),
+            payload: redact_auth(payload),
+            attempts: failure.attempts,
+            last_error: failure.last_error.clone(),
+            failed_at: now,
+            last_attempt_at: now,
+        };
+        let _guard = self.lock.lock().map_err(|_| anyhow!("Dead-letter queue lock poisoned"))?;
+        self.letters.put(&letter.id, &letter)?;
+        let queued = self.letters.list()?.len();
+        Ok((letter, queued))
+    }
+
+    /// Every dead letter, oldest first
+    pub fn list(&self) -> Result<Vec<DeadLetter>> {
+        let mut letters: Vec<DeadLetter> = self.letters.list()?.into_iter().map(|(_, letter)| letter).collect();
+        letters.sort_by(|a, b| a.failed_at.cmp(&b.failed_at).then_with(|| a.id.cmp(&b.id)));
+        Ok(letters)
+    }
+
+    pub fn get(&self, id: &str) -> Result<Option<DeadLetter>> {
+        self.letters.get(id)
+    }
+
+    /// Re-attempt a dead letter with `policy`; it leaves the queue once delivered
+    ///
+    /// Returns `None` for an unknown id, otherwise the outcome. A failed retry stays queued with
+    /// its attempts and last error updated.
+    pub async fn retry(
+        &self,
+        http: &reqwest::Client,
+        id: &str,
+        policy: &RetryPolicy,
+    ) -> Result<Option<Result<u32, DeliveryFailure>>> {
+        let Some(letter) = self.get(id)? else {
+            return Ok(None);
+        };
+        let outcome = deliver(http, &letter.url, &letter.payload, policy).await;
+
+        let _guard = self.lock.lock().map_err(|_| anyhow!("Dead-letter queue lock poisoned"))?;
+        match &outcome {
+            Ok(_) => {
+                self.letters.delete(id)?;
+            }
+            Err(failure) => {
+                if let Some(mut stored) = self.letters.get(id)? {
+                    stored.attempts += failure.attempts;
+                    stored.last_error = failure.last_error.clone();
+                    stored.last_attempt_at = unix_now();
+                    self.letters.put(id, &stored)?;
+                }
+            }
+        }
+        Ok(Some(outcome))
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_cluster::TempDir;
+    use axum::{extract::State, http::StatusCode, routing::post, Json, Router};
+    use serde_json::json;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    /// A webhook receiver answering 500 while `failing`, recording every body it accepts
+    #[derive(Clone, Default)]
+    struct Receiver {
+        failing: Arc<AtomicBool>,
+        received: Arc<Mutex<Vec<Value>>>,
+    }
+
+    async fn receive(State(receiver): State<Receiver>, Json(body): Json<Value>) -> StatusCode {
+        if receiver.failing.load(Ordering::SeqCst) {
+            return StatusCode::INTERNAL_SERVER_ERROR;
+        }
+        receiver.received.lock().unwrap().push(body);
+        StatusCode::NO_CONTENT
+    }
+
+    async fn serve(receiver: Receiver) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let app = Router::new().route("/hook", post(receive)).with_state(receiver);
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        url
+    }
+
+    fn quick(max_attempts: u32) -> RetryPolicy {
+        RetryPolicy { max_attempts, initial_backoff: Duration::from_millis(1), timeout: Duration::from_secs(5) }
+    }
+
+    fn payload() -> Value {
+        json!({
+            "event": "balance_alert",
+            "token_mint": "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v",
+            "headers": { "Authorization": "Bearer abc", "X-Api-Key": "k-1" },
+            "recipients": [{ "client_secret": "s-1", "amount": 5 }],
+        })
+    }
+
+    #[test]
+    fn credentials_are_redacted_at_any_depth_and_token_mints_are_kept() {
+        let redacted = redact_auth(&payload());
+        assert_eq!(
+            redacted,
+            json!({
+                "event": "balance_alert",
+                "token_mint": "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v",
+                "headers": { "Authorization": REDACTED, "X-Api-Key": REDACTED },
+                "recipients": [{ "client_secret": REDACTED, "amount": 5 }],
+            })
+        );
+        assert_eq!(redact_auth(&json!({ "password": { "nested": 1 } })), json!({ "password": REDACTED }));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn a_delivery_that_keeps_failing_is_dead_lettered_and_replays_once_the_receiver_recovers() {
+        let (receiver, dir) = (Receiver::default(), TempDir::new("webhook"));
+        receiver.failing.store(true, Ordering::SeqCst);
+        let url = serve(receiver.clone()).await;
+        let http = reqwest::Client::new();
+        let queue = DeadLetterQueue::open(&dir.join("dead-letters.json")).unwrap();
+
+        let failure = deliver(&http, &url, &payload(), &quick(3)).await.unwrap_err();
+        assert_eq!(failure.attempts, 3);
+        assert!(failure.last_error.contains("500"), "{}", failure.last_error);
+        let (letter, queued) = queue.push("balance_alert", &url, &payload(), &failure).unwrap();
+        assert_eq!(queued, 1);
+        assert_eq!((letter.attempts, letter.url.as_str()), (3, url.as_str()));
+        assert_eq!(letter.payload, redact_auth(&payload()));
+
+        // Still down: the letter stays, counting the extra attempts
+        let outcome = queue.retry(&http, &letter.id, &quick(2)).await.unwrap().unwrap();
+        assert_eq!(outcome.unwrap_err().attempts, 2);
+        let stored = queue.get(&letter.id).unwrap().unwrap();
+        assert_eq!(stored.attempts, 5);
+        assert!(stored.last_attempt_at >= letter.failed_at);
+
+        // Recovered, and read back after a restart: the redacted payload is delivered once
+        receiver.failing.store(false, Ordering::SeqCst);
+        let queue = DeadLetterQueue::open(&dir.join("dead-letters.json")).unwrap();
+        assert_eq!(queue.list().unwrap(), vec![stored]);
+        assert_eq!(queue.retry(&http, &letter.id, &quick(1)).await.unwrap().unwrap(), Ok(1));
+        assert_eq!(*receiver.received.lock().unwrap(), vec![redact_auth(&payload())]);
+        assert!(queue.list().unwrap().is_empty());
+        assert!(queue.retry(&http, &letter.id, &quick(1)).await.unwrap().is_none());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn the_queue_length_grows_with_each_letter_and_lists_oldest_first() {
+        let queue = DeadLetterQueue::open(&TempDir::new("webhook").join("dead-letters.json")).unwrap();
+        let failure = DeliveryFailure { attempts: 3, last_error: "connection refused".to_string() };
+        let mut ids = Vec::new();
+        for (i, source) in ["balance_alert", "payment_request", "balance_alert"].into_iter().enumerate() {
+            let (letter, queued) = queue.push(source, "http://127.0.0.1:9/hook", &json!({ "n": i }), &failure).unwrap();
+            assert_eq!(queued, i + 1);
+            ids.push(letter.id);
+        }
+        let listed = queue.list().unwrap();
+        assert_eq!(listed.len(), 3);
+        assert!(listed.windows(2).all(|pair| (pair[0].failed_at, &pair[0].id) <= (pair[1].failed_at, &pair[1].id)));
+        let mut listed_ids: Vec<String> = listed.into_iter().map(|letter| letter.id).collect();
+        listed_ids.sort();
+        ids.sort();
+        assert_eq!(listed_ids, ids);
+    }
+}
@@ -4,6 +4,9 @@ import { useState, useEffect, useCallback, useMemo } from 'react';
 import { useWallet } from '@solana/wallet-adapter-react';
 import { Package, RefreshCw, ExternalLink, AlertCircle, TrendingUp } from 'lucide-react';
 
+// Matches the infinite-scroll page size the API is tuned for
+const PAGE_SIZE = 20;
+
 interface AssetListProps {
   refreshTrigger: number;
 }
@@ -22,6 +25,9 @@ export function AssetList({ refreshTrigger }: AssetListProps) {
   const { connected, publicKey } = useWallet();
   const [loading, setLoading] = useState(false);
   const [assets, setAssets] = useState<Asset[]>([]);
+  const [loadingMore, setLoadingMore] = useState(false);
+  const [nextCursor, setNextCursor] = useState<string | null>(null);
+  const [totalCount, setTotalCount] = useState(0);
 
   // Example assets for demonstration (always shown)
   const exampleAssets = useMemo(() => [
@@ -63,16 +69,22 @@ export function AssetList({ refreshTrigger }: AssetListProps) {
     },
   ], []);
 
-  const fetchAssets = useCallback(async () => {
+  const fetchAssets = useCallback(async (cursor?: string) => {
     if (!connected || !publicKey) {
       setAssets(exampleAssets);
+      setNextCursor(null);
       return;
     }
 
-    setLoading(true);
+    const setBusy = cursor ? setLoadingMore : setLoading;
+    setBusy(true);
     try {
-      // Call the real Rust API server to get owned assets
-      const response = await fetch('http://127.0.0.1:3001/api/assets', {
+      // Call the real Rust API server for one page of owned assets
+      const params = new URLSearchParams({ limit: String(PAGE_SIZE), sort: 'balance' });
+      if (cursor) {
+        params.set('cursor', cursor);
+      }
+      const response = await fetch(`http://127.0.0.1:3001/api/assets?${params}`, {
         method: 'GET',
         headers: {
           'Content-Type': 'application/json',
@@ -83,10 +95,10 @@ export function AssetList({ refreshTrigger }: AssetListProps) {
         throw new Error(`API call failed: ${response.status}`);
       }
 
-      const apiAssets = await response.json();
+      const page = await response.json();
       
       // Transform API response to our Asset interface
-      const transformedAssets = apiAssets.map((asset: any) => ({
+      const transformedAssets = page.assets.map((asset: any) => ({
         mint: asset.mint,
         name: asset.metadata?.name || `Token ${asset.mint.slice(0, 8)}...`,
         type: asset.metadata?.asset_type || 'unknown',
@@ -96,8 +108,12 @@ export function AssetList({ refreshTrigger }: AssetListProps) {
         icon: getAssetIcon(asset.metadata?.asset_type || 'unknown'),
       }));
 
-      // If no real assets, show examples with a note
-      if (transformedAssets.length === 0) {
+      setNextCursor(page.next_cursor ?? null);
+      setTotalCount(page.total_count);
+      if (cursor) {
+        setAssets(previous => [...previous, ...transformedAssets]);
+      } else if (transformedAssets.length === 0) {
+        // If no real assets, show examples with a note
         setAssets(exampleAssets);
       } else {
         setAssets(transformedAssets);
@@ -106,9 +122,12 @@ export function AssetList({ refreshTrigger }: AssetListProps) {
     } catch (error) {
       console.error('Failed to fetch assets:', error);
       // Fallback to example data if API fails
-      setAssets(exampleAssets);
+      if (!cursor) {
+        setAssets(exampleAssets);
+        setNextCursor(null);
+      }
     } finally {
-      setLoading(false);
+      setBusy(false);
     }
   }, [connected, publicKey, exampleAssets]);
 
@@ -149,7 +168,7 @@ export function AssetList({ refreshTrigger }: AssetListProps) {
         </div>
         
         <button
-          onClick={fetchAssets}
+          onClick={() => fetchAssets()}
           disabled={loading}
           className="flex items-center space-x-2 px-4 py-2 bg-green-600 text-white rounded-xl hover:bg-green-700 disabled:opacity-50 transition-all duration-200 shadow-sm hover:shadow-md"
         >
@@ -186,7 +205,7 @@ export function AssetList({ refreshTrigger }: AssetListProps) {
                 </p>
                 <p className="text-3xl font-bold text-gray-900">${totalValue.toLocaleString()}</p>
                 <p className="text-sm text-gray-600 mt-1">
-                  {assets.length} asset{assets.length !== 1 ? 's' : ''} • 
+                  {isUsingExamples ? assets.length : totalCount} asset{(isUsingExamples ? assets.length : totalCount) !== 1 ? 's' : ''} • 
                   {assets.reduce((sum, asset) => sum + asset.balance, 0)} token{assets.reduce((sum, asset) => sum + asset.balance, 0) !== 1 ? 's' : ''}
                 </p>
               </div>
@@ -262,6 +281,18 @@ export function AssetList({ refreshTrigger }: AssetListProps) {
             ))}
           </div>
 
+          {!isUsingExamples && nextCursor && (
+            <div className="mt-6 text-center">
+              <button
+                onClick={() => fetchAssets(nextCursor)}
+                disabled={loadingMore}
+                className="px-4 py-2 border border-gray-300 text-gray-700 rounded-xl hover:bg-gray-50 disabled:opacity-50 transition-all duration-200"
+              >
+                {loadingMore ? 'Loading...' : `Load more (${totalCount - assets.length} remaining)`}
+              </button>
+            </div>
+          )}
+
           {isUsingExamples && (
             <div className="mt-8 p-6 bg-gray-50 border border-gray-200 rounded-xl">
               <h4 className="font-semibold text-gray-900 mb-2 flex items-center space-x-2">